@@ -0,0 +1,237 @@
+//! Command-line arguments
+//!
+//! The kernel passes `main(argc, argv)` where `argv` is a null-terminated
+//! array of pointers to null-terminated strings (see `ArgLayout` in the
+//! kernel). [`Args`] decodes that layout without allocating, and [`Parser`]
+//! provides getopt-style iteration over flags and positionals.
+
+use core::ffi::CStr;
+
+/// Arguments passed to the command, including the program name at index 0
+#[derive(Clone, Copy)]
+pub struct Args<'a> {
+    argc: usize,
+    argv: *const *const u8,
+    _marker: core::marker::PhantomData<&'a str>,
+}
+
+impl<'a> Args<'a> {
+    /// Wrap the raw `argc`/`argv` pair handed to `main`
+    ///
+    /// # Safety
+    /// `argv` must point to at least `argc` valid pointers to null-terminated
+    /// strings that outlive `'a`. A negative `argc` is treated as zero.
+    pub unsafe fn from_raw(argc: i32, argv: *const *const u8) -> Self {
+        let argc = if argv.is_null() {
+            0
+        } else {
+            usize::try_from(argc).unwrap_or(0)
+        };
+        Self {
+            argc,
+            argv,
+            _marker: core::marker::PhantomData,
+        }
+    }
+
+    /// Number of arguments (including the program name)
+    pub fn len(&self) -> usize {
+        self.argc
+    }
+
+    /// True if not even a program name was passed
+    pub fn is_empty(&self) -> bool {
+        self.argc == 0
+    }
+
+    /// Argument at `index`, or `None` if out of range or not valid UTF-8
+    pub fn get(&self, index: usize) -> Option<&'a str> {
+        if index >= self.argc {
+            return None;
+        }
+        // SAFETY: index < argc and from_raw's contract guarantees the pointer
+        // array and strings are valid for 'a
+        unsafe {
+            let ptr = *self.argv.add(index);
+            if ptr.is_null() {
+                return None;
+            }
+            CStr::from_ptr(ptr.cast()).to_str().ok()
+        }
+    }
+
+    /// The program name (`argv[0]`), or an empty string
+    pub fn program(&self) -> &'a str {
+        self.get(0).unwrap_or("")
+    }
+
+    /// Iterate over all arguments, skipping any that are not valid UTF-8
+    pub fn iter(&self) -> impl Iterator<Item = &'a str> + '_ {
+        (0..self.argc).filter_map(|i| self.get(i))
+    }
+
+    /// Start option parsing after the program name
+    pub fn parser(&self) -> Parser<'a> {
+        Parser::new(*self)
+    }
+}
+
+/// One item produced by [`Parser`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opt<'a> {
+    /// `-x` (clustered flags like `-abc` yield one `Short` each)
+    Short(char),
+    /// `--name` or `--name=value`
+    Long(&'a str),
+    /// Anything else, including everything after `--`
+    Positional(&'a str),
+}
+
+/// Getopt-style argument parser
+///
+/// ```rust,ignore
+/// let mut p = args.parser();
+/// while let Some(opt) = p.next() {
+///     match opt {
+///         Opt::Short('n') | Opt::Long("lines") => lines = p.value(),
+///         Opt::Short('v') => verbose = true,
+///         Opt::Positional(path) => files.push(path),
+///         _ => return usage(),
+///     }
+/// }
+/// ```
+pub struct Parser<'a> {
+    args: Args<'a>,
+    index: usize,
+    /// Remaining characters of a short-option cluster
+    cluster: &'a str,
+    /// Value attached to the last long option with `=`
+    attached: Option<&'a str>,
+    options_done: bool,
+}
+
+impl<'a> Parser<'a> {
+    fn new(args: Args<'a>) -> Self {
+        Self {
+            args,
+            index: 1,
+            cluster: "",
+            attached: None,
+            options_done: false,
+        }
+    }
+
+    /// Next option or positional argument
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<Opt<'a>> {
+        self.attached = None;
+
+        if let Some(c) = self.cluster.chars().next() {
+            self.cluster = &self.cluster[c.len_utf8()..];
+            return Some(Opt::Short(c));
+        }
+
+        let arg = self.args.get(self.index)?;
+        self.index += 1;
+
+        if self.options_done || arg == "-" || !arg.starts_with('-') {
+            return Some(Opt::Positional(arg));
+        }
+
+        if arg == "--" {
+            self.options_done = true;
+            return self.next();
+        }
+
+        if let Some(long) = arg.strip_prefix("--") {
+            return Some(match long.split_once('=') {
+                Some((name, value)) => {
+                    self.attached = Some(value);
+                    Opt::Long(name)
+                }
+                None => Opt::Long(long),
+            });
+        }
+
+        self.cluster = &arg[1..];
+        self.next()
+    }
+
+    /// Value for the option just returned by [`Parser::next`]
+    ///
+    /// Takes the `=value` of a long option, the rest of a short cluster
+    /// (`-n5`), or else the following argument.
+    pub fn value(&mut self) -> Option<&'a str> {
+        if let Some(v) = self.attached.take() {
+            return Some(v);
+        }
+        if !self.cluster.is_empty() {
+            let v = self.cluster;
+            self.cluster = "";
+            return Some(v);
+        }
+        let v = self.args.get(self.index)?;
+        self.index += 1;
+        Some(v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_args<R>(strs: &[&CStr], f: impl FnOnce(Args) -> R) -> R {
+        let mut ptrs: Vec<*const u8> = strs.iter().map(|s| s.as_ptr().cast()).collect();
+        ptrs.push(core::ptr::null());
+        let args = unsafe { Args::from_raw(strs.len() as i32, ptrs.as_ptr()) };
+        f(args)
+    }
+
+    #[test]
+    fn test_args_basic() {
+        with_args(&[c"cat", c"file.txt"], |args| {
+            assert_eq!(args.len(), 2);
+            assert_eq!(args.program(), "cat");
+            assert_eq!(args.get(1), Some("file.txt"));
+            assert_eq!(args.get(2), None);
+            assert_eq!(args.iter().collect::<Vec<_>>(), ["cat", "file.txt"]);
+        });
+    }
+
+    #[test]
+    fn test_args_null_argv() {
+        let args = unsafe { Args::from_raw(3, core::ptr::null()) };
+        assert!(args.is_empty());
+        assert_eq!(args.program(), "");
+    }
+
+    #[test]
+    fn test_parser_short_cluster_and_value() {
+        with_args(&[c"head", c"-vn5", c"a", c"-c", c"10"], |args| {
+            let mut p = args.parser();
+            assert_eq!(p.next(), Some(Opt::Short('v')));
+            assert_eq!(p.next(), Some(Opt::Short('n')));
+            assert_eq!(p.value(), Some("5"));
+            assert_eq!(p.next(), Some(Opt::Positional("a")));
+            assert_eq!(p.next(), Some(Opt::Short('c')));
+            assert_eq!(p.value(), Some("10"));
+            assert_eq!(p.next(), None);
+        });
+    }
+
+    #[test]
+    fn test_parser_long_and_double_dash() {
+        with_args(
+            &[c"x", c"--lines=3", c"--all", c"--", c"-not-an-opt", c"-"],
+            |args| {
+                let mut p = args.parser();
+                assert_eq!(p.next(), Some(Opt::Long("lines")));
+                assert_eq!(p.value(), Some("3"));
+                assert_eq!(p.next(), Some(Opt::Long("all")));
+                assert_eq!(p.next(), Some(Opt::Positional("-not-an-opt")));
+                assert_eq!(p.next(), Some(Opt::Positional("-")));
+                assert_eq!(p.next(), None);
+            },
+        );
+    }
+}
@@ -0,0 +1,192 @@
+//! Environment variables and working directory
+//!
+//! The kernel passes the environment to `main` as an `envp` block of
+//! `NAME=value` strings (same layout as argv). [`entry!`](crate::entry)
+//! records it with [`init`], after which [`get`], [`vars`], [`home`] and
+//! [`path`] read it without a syscall. Without a block (older kernels) the
+//! lookups fall back to the `getenv` syscall.
+//!
+//! The kernel copies at most `buf.len()` bytes and does not report
+//! truncation, so the allocating helpers use a buffer large enough for any
+//! value the shell will set.
+
+use crate::error::{Result, check};
+use crate::sys;
+use core::ffi::CStr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+/// The envp block handed to `main`, or null
+static ENVP: AtomicPtr<*const u8> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Record the environment block handed to `main`
+///
+/// # Safety
+/// `envp` must be null or point to a null-terminated array of pointers to
+/// null-terminated strings that stay valid for the rest of the command.
+pub unsafe fn init(envp: *const *const u8) {
+    ENVP.store(envp.cast_mut(), Ordering::Relaxed);
+}
+
+/// Iterator over the environment block as `(name, value)` pairs
+///
+/// Entries that are not valid UTF-8 or have no `=` are skipped.
+#[derive(Clone)]
+pub struct Vars {
+    next: *const *const u8,
+}
+
+impl Vars {
+    /// # Safety
+    /// Same contract as [`init`].
+    unsafe fn from_raw(envp: *const *const u8) -> Self {
+        Self { next: envp }
+    }
+}
+
+impl Iterator for Vars {
+    type Item = (&'static str, &'static str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.next.is_null() {
+                return None;
+            }
+            // SAFETY: init's contract guarantees a null-terminated array of
+            // valid string pointers; we stop at the null entry
+            let entry = unsafe {
+                let ptr = *self.next;
+                if ptr.is_null() {
+                    self.next = core::ptr::null();
+                    return None;
+                }
+                self.next = self.next.add(1);
+                CStr::from_ptr(ptr.cast()).to_str()
+            };
+            if let Some(pair) = entry.ok().and_then(|e| e.split_once('=')) {
+                return Some(pair);
+            }
+        }
+    }
+}
+
+/// True if the kernel passed an environment block
+pub fn has_block() -> bool {
+    !ENVP.load(Ordering::Relaxed).is_null()
+}
+
+/// All environment variables from the block (empty without one)
+pub fn vars() -> Vars {
+    // SAFETY: ENVP is only set through init, whose caller upholds its contract
+    unsafe { Vars::from_raw(ENVP.load(Ordering::Relaxed).cast_const()) }
+}
+
+/// Look up an environment variable in the block, without a syscall
+pub fn get(name: &str) -> Option<&'static str> {
+    vars().find(|(k, _)| *k == name).map(|(_, v)| v)
+}
+
+/// The `HOME` directory from the environment block
+pub fn home() -> Option<&'static str> {
+    get("HOME")
+}
+
+/// The directories of `PATH` from the environment block
+pub fn path() -> impl Iterator<Item = &'static str> {
+    get("PATH")
+        .unwrap_or("")
+        .split(':')
+        .filter(|dir| !dir.is_empty())
+}
+
+/// Look up an environment variable, writing its value into `buf`
+///
+/// Returns `None` if the variable is unset or empty, or not valid UTF-8.
+/// Longer values are cut to `buf.len()` bytes.
+pub fn var_into<'b>(name: &str, buf: &'b mut [u8]) -> Option<&'b str> {
+    if has_block() {
+        let value = get(name).filter(|v| !v.is_empty())?;
+        let n = value.len().min(buf.len());
+        buf[..n].copy_from_slice(&value.as_bytes()[..n]);
+        return core::str::from_utf8(&buf[..n]).ok();
+    }
+    // SAFETY: name and buf are valid for the lengths passed
+    let ret = unsafe {
+        sys::getenv(
+            name.as_ptr(),
+            sys::len_i32(name.len()),
+            buf.as_mut_ptr(),
+            sys::len_i32(buf.len()),
+        )
+    };
+    let n = check(ret).ok().filter(|&n| n > 0)?;
+    core::str::from_utf8(&buf[..n.min(buf.len())]).ok()
+}
+
+/// Write the current working directory into `buf`
+pub fn current_dir_into(buf: &mut [u8]) -> Result<&str> {
+    // SAFETY: buf is valid for writes of buf.len() bytes
+    let n = check(unsafe { sys::getcwd(buf.as_mut_ptr(), sys::len_i32(buf.len())) })?;
+    core::str::from_utf8(&buf[..n.min(buf.len())]).map_err(|_| crate::Error::InvalidArgument)
+}
+
+/// Look up an environment variable
+#[cfg(feature = "alloc")]
+pub fn var(name: &str) -> Option<alloc::string::String> {
+    use alloc::string::ToString;
+    if has_block() {
+        return get(name).filter(|v| !v.is_empty()).map(|v| v.to_string());
+    }
+    let mut buf = alloc::vec![0u8; 4096];
+    var_into(name, &mut buf).map(|v| v.to_string())
+}
+
+/// The current working directory
+#[cfg(feature = "alloc")]
+pub fn current_dir() -> Result<alloc::string::String> {
+    use alloc::string::ToString;
+    let mut buf = alloc::vec![0u8; 4096];
+    current_dir_into(&mut buf).map(|v| v.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_block() {
+        assert!(!has_block());
+        assert_eq!(get("HOME"), None);
+
+        let entries = [
+            c"HOME=/home/user",
+            c"junk",
+            c"PATH=/bin::/usr/bin",
+            c"EMPTY=",
+        ];
+        let mut ptrs: Vec<*const u8> = entries.iter().map(|s| s.as_ptr().cast()).collect();
+        ptrs.push(core::ptr::null());
+        // The block must outlive the command; leak it like the kernel's
+        let ptrs = ptrs.leak();
+        unsafe { init(ptrs.as_ptr()) };
+
+        assert!(has_block());
+        assert_eq!(
+            vars().collect::<Vec<_>>(),
+            [
+                ("HOME", "/home/user"),
+                ("PATH", "/bin::/usr/bin"),
+                ("EMPTY", "")
+            ]
+        );
+        assert_eq!(home(), Some("/home/user"));
+        assert_eq!(path().collect::<Vec<_>>(), ["/bin", "/usr/bin"]);
+        assert_eq!(get("EMPTY"), Some(""));
+        assert_eq!(get("NOPE"), None);
+
+        let mut buf = [0u8; 5];
+        assert_eq!(var_into("HOME", &mut buf), Some("/home"));
+        assert_eq!(var_into("EMPTY", &mut buf), None);
+        #[cfg(feature = "alloc")]
+        assert_eq!(var("PATH").as_deref(), Some("/bin::/usr/bin"));
+    }
+}
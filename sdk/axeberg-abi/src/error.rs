@@ -0,0 +1,115 @@
+//! Error codes returned by the kernel
+//!
+//! Mirrors `kernel::wasm::SyscallError` in the main crate. The numeric values
+//! are part of the ABI and must never change.
+
+use core::fmt;
+
+/// Error returned by a failed syscall
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum Error {
+    /// Generic/unknown error
+    Generic = -1,
+    /// File or directory not found
+    NotFound = -2,
+    /// Permission denied
+    PermissionDenied = -3,
+    /// File or directory already exists
+    AlreadyExists = -4,
+    /// Expected directory, got file
+    NotADirectory = -5,
+    /// Expected file, got directory
+    IsADirectory = -6,
+    /// Invalid argument
+    InvalidArgument = -7,
+    /// No space left on device
+    NoSpace = -8,
+    /// I/O error
+    IoError = -9,
+    /// Invalid file descriptor
+    BadFd = -10,
+    /// Directory not empty
+    NotEmpty = -11,
+}
+
+/// Result type used throughout the SDK
+pub type Result<T> = core::result::Result<T, Error>;
+
+impl Error {
+    /// The raw ABI code
+    pub fn code(&self) -> i32 {
+        *self as i32
+    }
+
+    /// Decode a raw ABI code; unknown negative codes become `Generic`
+    pub fn from_code(code: i32) -> Self {
+        match code {
+            -2 => Self::NotFound,
+            -3 => Self::PermissionDenied,
+            -4 => Self::AlreadyExists,
+            -5 => Self::NotADirectory,
+            -6 => Self::IsADirectory,
+            -7 => Self::InvalidArgument,
+            -8 => Self::NoSpace,
+            -9 => Self::IoError,
+            -10 => Self::BadFd,
+            -11 => Self::NotEmpty,
+            _ => Self::Generic,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            Error::Generic => "error",
+            Error::NotFound => "no such file or directory",
+            Error::PermissionDenied => "permission denied",
+            Error::AlreadyExists => "file exists",
+            Error::NotADirectory => "not a directory",
+            Error::IsADirectory => "is a directory",
+            Error::InvalidArgument => "invalid argument",
+            Error::NoSpace => "no space left on device",
+            Error::IoError => "I/O error",
+            Error::BadFd => "bad file descriptor",
+            Error::NotEmpty => "directory not empty",
+        };
+        f.write_str(msg)
+    }
+}
+
+/// Convert a raw syscall return value into a `Result`
+///
+/// Non-negative values are returned as a byte count / descriptor.
+pub fn check(ret: i32) -> Result<usize> {
+    if ret < 0 {
+        Err(Error::from_code(ret))
+    } else {
+        Ok(ret as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_roundtrip() {
+        for code in -11..=-1 {
+            assert_eq!(Error::from_code(code).code(), code);
+        }
+    }
+
+    #[test]
+    fn test_unknown_code_is_generic() {
+        assert_eq!(Error::from_code(-999), Error::Generic);
+    }
+
+    #[test]
+    fn test_check() {
+        assert_eq!(check(5), Ok(5));
+        assert_eq!(check(0), Ok(0));
+        assert_eq!(check(-2), Err(Error::NotFound));
+    }
+}
@@ -0,0 +1,279 @@
+//! Filesystem helpers
+//!
+//! Paths are passed to the kernel as (pointer, length) pairs, so no null
+//! terminators or allocations are needed to call these.
+
+use crate::error::{Error, Result, check};
+use crate::{io, sys};
+use core::ops::BitOr;
+
+/// Flags for [`File::open`], matching the kernel's `OpenFlags`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpenFlags(pub i32);
+
+impl OpenFlags {
+    pub const READ: OpenFlags = OpenFlags(0);
+    pub const WRITE: OpenFlags = OpenFlags(1);
+    pub const READ_WRITE: OpenFlags = OpenFlags(2);
+    pub const CREATE: OpenFlags = OpenFlags(4);
+    pub const TRUNCATE: OpenFlags = OpenFlags(8);
+}
+
+impl BitOr for OpenFlags {
+    type Output = OpenFlags;
+
+    fn bitor(self, rhs: OpenFlags) -> OpenFlags {
+        OpenFlags(self.0 | rhs.0)
+    }
+}
+
+/// An open file, closed on drop
+#[derive(Debug)]
+pub struct File {
+    fd: i32,
+}
+
+impl File {
+    /// Open `path` with the given flags
+    pub fn open(path: &str, flags: OpenFlags) -> Result<File> {
+        // SAFETY: path is a valid byte slice for the duration of the call
+        let fd = check(unsafe { sys::open(path.as_ptr(), sys::len_i32(path.len()), flags.0) })?;
+        Ok(File { fd: fd as i32 })
+    }
+
+    /// Create (or truncate) `path` for writing
+    pub fn create(path: &str) -> Result<File> {
+        Self::open(
+            path,
+            OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE,
+        )
+    }
+
+    /// The raw descriptor
+    pub fn fd(&self) -> i32 {
+        self.fd
+    }
+
+    /// Read up to `buf.len()` bytes; `Ok(0)` means end of file
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        io::read(self.fd, buf)
+    }
+
+    /// Write all of `buf`
+    pub fn write_all(&mut self, buf: &[u8]) -> Result<()> {
+        io::write_all(self.fd, buf)
+    }
+
+    /// Read up to `buf.len()` bytes from `offset`, without moving the
+    /// file's position
+    pub fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        io::pread(self.fd, buf, offset)
+    }
+
+    /// Write some of `buf` at `offset`, without moving the file's position
+    pub fn write_at(&self, buf: &[u8], offset: u64) -> Result<usize> {
+        io::pwrite(self.fd, buf, offset)
+    }
+
+    /// Read the rest of the file
+    #[cfg(feature = "alloc")]
+    pub fn read_to_end(&mut self) -> Result<alloc::vec::Vec<u8>> {
+        io::read_to_end(self.fd)
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        // SAFETY: closing an fd has no memory-safety preconditions
+        unsafe {
+            sys::close(self.fd);
+        }
+    }
+}
+
+/// File metadata decoded from the 32-byte stat buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    /// Size in bytes
+    pub size: u32,
+    /// True for directories
+    pub is_dir: bool,
+    /// Last modification time (unix timestamp)
+    pub modified: u64,
+    /// Creation time (unix timestamp)
+    pub created: u64,
+}
+
+impl Metadata {
+    /// Size of the kernel's stat buffer
+    pub const STAT_SIZE: usize = 32;
+
+    /// Decode a little-endian stat buffer
+    pub fn from_bytes(buf: &[u8; Self::STAT_SIZE]) -> Self {
+        let u32_at = |i: usize| u32::from_le_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]]);
+        let u64_at = |i: usize| u64::from(u32_at(i)) | (u64::from(u32_at(i + 4)) << 32);
+        Self {
+            size: u32_at(0),
+            is_dir: u32_at(4) != 0,
+            modified: u64_at(8),
+            created: u64_at(16),
+        }
+    }
+}
+
+/// Stat a path
+pub fn metadata(path: &str) -> Result<Metadata> {
+    let mut buf = [0u8; Metadata::STAT_SIZE];
+    // SAFETY: buf holds the 32 bytes the kernel writes
+    check(unsafe { sys::stat(path.as_ptr(), sys::len_i32(path.len()), buf.as_mut_ptr()) })?;
+    Ok(Metadata::from_bytes(&buf))
+}
+
+/// True if the path exists
+pub fn exists(path: &str) -> bool {
+    metadata(path).is_ok()
+}
+
+/// Create a directory
+pub fn create_dir(path: &str) -> Result<()> {
+    // SAFETY: path is a valid byte slice for the duration of the call
+    check(unsafe { sys::mkdir(path.as_ptr(), sys::len_i32(path.len())) }).map(|_| ())
+}
+
+/// Remove an empty directory
+pub fn remove_dir(path: &str) -> Result<()> {
+    // SAFETY: path is a valid byte slice for the duration of the call
+    check(unsafe { sys::rmdir(path.as_ptr(), sys::len_i32(path.len())) }).map(|_| ())
+}
+
+/// Remove a file
+pub fn remove_file(path: &str) -> Result<()> {
+    // SAFETY: path is a valid byte slice for the duration of the call
+    check(unsafe { sys::unlink(path.as_ptr(), sys::len_i32(path.len())) }).map(|_| ())
+}
+
+/// Rename or move a file
+pub fn rename(from: &str, to: &str) -> Result<()> {
+    // SAFETY: both paths are valid byte slices for the duration of the call
+    check(unsafe {
+        sys::rename(
+            from.as_ptr(),
+            sys::len_i32(from.len()),
+            to.as_ptr(),
+            sys::len_i32(to.len()),
+        )
+    })
+    .map(|_| ())
+}
+
+/// List a directory into `buf` without allocating
+///
+/// Entries that do not fit in `buf` are dropped by the kernel, so size the
+/// buffer generously (or use [`read_dir`]).
+pub fn read_dir_into<'b>(path: &str, buf: &'b mut [u8]) -> Result<DirEntries<'b>> {
+    // SAFETY: buf is valid for writes of buf.len() bytes
+    let n = check(unsafe {
+        sys::readdir(
+            path.as_ptr(),
+            sys::len_i32(path.len()),
+            buf.as_mut_ptr(),
+            sys::len_i32(buf.len()),
+        )
+    })?;
+    Ok(DirEntries {
+        rest: &buf[..n.min(buf.len())],
+    })
+}
+
+/// Iterator over the null-separated names written by `readdir`
+pub struct DirEntries<'b> {
+    rest: &'b [u8],
+}
+
+impl<'b> Iterator for DirEntries<'b> {
+    type Item = &'b str;
+
+    fn next(&mut self) -> Option<&'b str> {
+        while !self.rest.is_empty() {
+            let end = self
+                .rest
+                .iter()
+                .position(|&b| b == 0)
+                .unwrap_or(self.rest.len());
+            let name = &self.rest[..end];
+            self.rest = self.rest.get(end + 1..).unwrap_or(&[]);
+            if let Ok(name) = core::str::from_utf8(name)
+                && !name.is_empty()
+            {
+                return Some(name);
+            }
+        }
+        None
+    }
+}
+
+/// List a directory
+#[cfg(feature = "alloc")]
+pub fn read_dir(path: &str) -> Result<alloc::vec::Vec<alloc::string::String>> {
+    use alloc::string::ToString;
+    let mut buf = alloc::vec![0u8; 16 * 1024];
+    Ok(read_dir_into(path, &mut buf)?
+        .map(|name| name.to_string())
+        .collect())
+}
+
+/// Read a whole file
+#[cfg(feature = "alloc")]
+pub fn read(path: &str) -> Result<alloc::vec::Vec<u8>> {
+    File::open(path, OpenFlags::READ)?.read_to_end()
+}
+
+/// Read a whole file as UTF-8 text
+#[cfg(feature = "alloc")]
+pub fn read_to_string(path: &str) -> Result<alloc::string::String> {
+    alloc::string::String::from_utf8(read(path)?).map_err(|_| Error::InvalidArgument)
+}
+
+/// Create or replace a file with `data`
+pub fn write(path: &str, data: &[u8]) -> Result<()> {
+    File::create(path)?.write_all(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_flags_combine() {
+        let flags = OpenFlags::WRITE | OpenFlags::CREATE;
+        assert_eq!(flags.0, 5);
+    }
+
+    #[test]
+    fn test_metadata_from_bytes() {
+        let mut buf = [0u8; 32];
+        buf[0..4].copy_from_slice(&1234u32.to_le_bytes());
+        buf[4..8].copy_from_slice(&1u32.to_le_bytes());
+        buf[8..16].copy_from_slice(&1_700_000_000u64.to_le_bytes());
+        buf[16..24].copy_from_slice(&1_600_000_000u64.to_le_bytes());
+
+        let meta = Metadata::from_bytes(&buf);
+        assert_eq!(meta.size, 1234);
+        assert!(meta.is_dir);
+        assert_eq!(meta.modified, 1_700_000_000);
+        assert_eq!(meta.created, 1_600_000_000);
+    }
+
+    #[test]
+    fn test_dir_entries_split() {
+        let entries = DirEntries {
+            rest: b"a.txt\0sub\0\0last",
+        };
+        assert_eq!(entries.collect::<Vec<_>>(), ["a.txt", "sub", "last"]);
+    }
+
+    #[test]
+    fn test_host_stub_fails() {
+        assert_eq!(metadata("/etc").unwrap_err(), Error::Generic);
+    }
+}
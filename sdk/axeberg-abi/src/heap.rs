@@ -0,0 +1,144 @@
+//! Bump allocator for `#![no_std]` commands
+//!
+//! Commands are short-lived, so the allocator never frees: it hands out
+//! memory from `__heap_base` upward and grows linear memory a page at a time
+//! when it runs out. Everything is reclaimed when the instance is dropped.
+//!
+//! ```rust,ignore
+//! #[global_allocator]
+//! static HEAP: axeberg_abi::heap::BumpAllocator = axeberg_abi::heap::BumpAllocator::new();
+//! ```
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// WebAssembly page size
+pub const PAGE_SIZE: usize = 64 * 1024;
+
+/// Never-freeing allocator over the command's linear memory
+pub struct BumpAllocator {
+    /// Next free address (0 until first use)
+    next: AtomicUsize,
+    /// End of currently committed memory
+    end: AtomicUsize,
+}
+
+impl BumpAllocator {
+    pub const fn new() -> Self {
+        Self {
+            next: AtomicUsize::new(0),
+            end: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Default for BumpAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Round `addr` up to a multiple of `align` (a power of two)
+pub fn align_up(addr: usize, align: usize) -> Option<usize> {
+    Some(addr.checked_add(align - 1)? & !(align - 1))
+}
+
+/// Pages needed to extend `end` so that `want` fits
+pub fn pages_needed(end: usize, want: usize) -> usize {
+    want.saturating_sub(end).div_ceil(PAGE_SIZE)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn heap_start() -> usize {
+    unsafe extern "C" {
+        static __heap_base: u8;
+    }
+    // SAFETY: only the address of the linker-provided symbol is taken
+    unsafe { core::ptr::addr_of!(__heap_base) as usize }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn memory_end() -> usize {
+    core::arch::wasm32::memory_size(0) * PAGE_SIZE
+}
+
+#[cfg(target_arch = "wasm32")]
+fn memory_grow(pages: usize) -> bool {
+    core::arch::wasm32::memory_grow(0, pages) != usize::MAX
+}
+
+// Off-target there is no linear memory to manage; every allocation fails.
+#[cfg(not(target_arch = "wasm32"))]
+fn heap_start() -> usize {
+    0
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn memory_end() -> usize {
+    0
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn memory_grow(_pages: usize) -> bool {
+    false
+}
+
+// SAFETY: commands are single-threaded; the atomics only make the static Sync
+unsafe impl GlobalAlloc for BumpAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut next = self.next.load(Ordering::Relaxed);
+        let mut end = self.end.load(Ordering::Relaxed);
+        if next == 0 {
+            next = heap_start();
+            end = memory_end();
+        }
+
+        let Some(start) = align_up(next, layout.align()) else {
+            return core::ptr::null_mut();
+        };
+        let Some(new_next) = start.checked_add(layout.size()) else {
+            return core::ptr::null_mut();
+        };
+
+        if new_next > end {
+            let pages = pages_needed(end, new_next);
+            if !memory_grow(pages) {
+                return core::ptr::null_mut();
+            }
+            end += pages * PAGE_SIZE;
+        }
+
+        self.next.store(new_next, Ordering::Relaxed);
+        self.end.store(end, Ordering::Relaxed);
+        start as *mut u8
+    }
+
+    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_align_up() {
+        assert_eq!(align_up(0, 8), Some(0));
+        assert_eq!(align_up(1, 8), Some(8));
+        assert_eq!(align_up(16, 16), Some(16));
+        assert_eq!(align_up(usize::MAX, 8), None);
+    }
+
+    #[test]
+    fn test_pages_needed() {
+        assert_eq!(pages_needed(PAGE_SIZE, PAGE_SIZE), 0);
+        assert_eq!(pages_needed(PAGE_SIZE, PAGE_SIZE + 1), 1);
+        assert_eq!(pages_needed(0, 3 * PAGE_SIZE), 3);
+    }
+
+    #[test]
+    fn test_host_alloc_fails_cleanly() {
+        let heap = BumpAllocator::new();
+        let ptr = unsafe { heap.alloc(Layout::from_size_align(16, 8).unwrap()) };
+        assert!(ptr.is_null());
+    }
+}
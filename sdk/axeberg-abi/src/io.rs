@@ -0,0 +1,138 @@
+//! Standard streams and raw descriptor I/O
+//!
+//! The `print!` family of macros formats straight into the descriptor with
+//! no intermediate buffer, so they work without an allocator.
+
+use crate::error::{Error, Result, check};
+use crate::sys;
+use core::fmt;
+
+/// Standard input descriptor
+pub const STDIN: i32 = 0;
+/// Standard output descriptor
+pub const STDOUT: i32 = 1;
+/// Standard error descriptor
+pub const STDERR: i32 = 2;
+
+/// Read up to `buf.len()` bytes; `Ok(0)` means end of file
+pub fn read(fd: i32, buf: &mut [u8]) -> Result<usize> {
+    // SAFETY: buf is valid for writes of buf.len() bytes
+    check(unsafe { sys::read(fd, buf.as_mut_ptr(), sys::len_i32(buf.len())) })
+}
+
+/// Write some of `buf`, returning how many bytes were accepted
+pub fn write(fd: i32, buf: &[u8]) -> Result<usize> {
+    // SAFETY: buf is valid for reads of buf.len() bytes
+    check(unsafe { sys::write(fd, buf.as_ptr(), sys::len_i32(buf.len())) })
+}
+
+/// Read up to `buf.len()` bytes from `offset`, leaving the descriptor's
+/// position where it was; `Ok(0)` means `offset` is at or past the end
+pub fn pread(fd: i32, buf: &mut [u8], offset: u64) -> Result<usize> {
+    let offset = i64::try_from(offset).map_err(|_| Error::InvalidArgument)?;
+    // SAFETY: buf is valid for writes of buf.len() bytes
+    check(unsafe { sys::pread(fd, buf.as_mut_ptr(), sys::len_i32(buf.len()), offset) })
+}
+
+/// Write some of `buf` at `offset`, leaving the descriptor's position
+/// where it was
+pub fn pwrite(fd: i32, buf: &[u8], offset: u64) -> Result<usize> {
+    let offset = i64::try_from(offset).map_err(|_| Error::InvalidArgument)?;
+    // SAFETY: buf is valid for reads of buf.len() bytes
+    check(unsafe { sys::pwrite(fd, buf.as_ptr(), sys::len_i32(buf.len()), offset) })
+}
+
+/// Write all of `buf`, retrying short writes
+pub fn write_all(fd: i32, mut buf: &[u8]) -> Result<()> {
+    while !buf.is_empty() {
+        match write(fd, buf)? {
+            0 => return Err(Error::IoError),
+            n => buf = &buf[n.min(buf.len())..],
+        }
+    }
+    Ok(())
+}
+
+/// Read from `fd` until end of file
+#[cfg(feature = "alloc")]
+pub fn read_to_end(fd: i32) -> Result<alloc::vec::Vec<u8>> {
+    let mut out = alloc::vec::Vec::new();
+    let mut chunk = [0u8; 1024];
+    loop {
+        match read(fd, &mut chunk)? {
+            0 => return Ok(out),
+            n => out.extend_from_slice(&chunk[..n]),
+        }
+    }
+}
+
+/// Read all of stdin as UTF-8 text
+#[cfg(feature = "alloc")]
+pub fn read_stdin_to_string() -> Result<alloc::string::String> {
+    alloc::string::String::from_utf8(read_to_end(STDIN)?).map_err(|_| Error::InvalidArgument)
+}
+
+/// `core::fmt::Write` adapter over a descriptor
+#[derive(Debug, Clone, Copy)]
+pub struct FdWriter(pub i32);
+
+impl fmt::Write for FdWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write_all(self.0, s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+/// Writer for standard output
+pub fn stdout() -> FdWriter {
+    FdWriter(STDOUT)
+}
+
+/// Writer for standard error
+pub fn stderr() -> FdWriter {
+    FdWriter(STDERR)
+}
+
+#[doc(hidden)]
+pub fn _print(fd: i32, args: fmt::Arguments) {
+    use fmt::Write;
+    // Like std, output errors are ignored by print!; use write_all to observe them
+    let _ = FdWriter(fd).write_fmt(args);
+}
+
+/// Print to standard output
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => {
+        $crate::io::_print($crate::io::STDOUT, format_args!($($arg)*))
+    };
+}
+
+/// Print to standard output, with a newline
+#[macro_export]
+macro_rules! println {
+    () => {
+        $crate::print!("\n")
+    };
+    ($($arg:tt)*) => {
+        $crate::io::_print($crate::io::STDOUT, format_args!("{}\n", format_args!($($arg)*)))
+    };
+}
+
+/// Print to standard error
+#[macro_export]
+macro_rules! eprint {
+    ($($arg:tt)*) => {
+        $crate::io::_print($crate::io::STDERR, format_args!($($arg)*))
+    };
+}
+
+/// Print to standard error, with a newline
+#[macro_export]
+macro_rules! eprintln {
+    () => {
+        $crate::eprint!("\n")
+    };
+    ($($arg:tt)*) => {
+        $crate::io::_print($crate::io::STDERR, format_args!("{}\n", format_args!($($arg)*)))
+    };
+}
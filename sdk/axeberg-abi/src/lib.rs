@@ -0,0 +1,102 @@
+//! axeberg-abi - Safe wrappers for writing axeberg WASM commands
+//!
+//! The kernel exposes a small set of syscalls to command modules through the
+//! `env` import namespace (see `kernel::wasm` in the main crate). Calling them
+//! directly means raw pointers, manual length bookkeeping and negative return
+//! codes everywhere. This crate wraps those imports once so that commands can
+//! be written in ordinary safe Rust:
+//!
+//! - [`args`]: argc/argv decoding and a small option parser
+//! - [`io`]: stdin/stdout/stderr plus `print!`/`println!`/`eprintln!`
+//! - [`fs`]: files, metadata and directory helpers
+//! - [`env`]: environment variables (from the envp block) and the working directory
+//! - [`error`]: ABI error codes mapped to a Rust [`Error`] type
+//! - [`heap`]: a bump allocator for `#![no_std]` commands (feature `alloc`)
+//!
+//! The crate is `#![no_std]`. On targets other than `wasm32` the raw imports
+//! are replaced by stubs that fail with [`Error::Generic`], so the crate can
+//! be built and unit tested on the host.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! #![no_std]
+//! #![no_main]
+//!
+//! use axeberg_abi::{args::Args, println, eprintln};
+//!
+//! axeberg_abi::entry!(run);
+//! axeberg_abi::panic_handler!();
+//!
+//! #[global_allocator]
+//! static HEAP: axeberg_abi::heap::BumpAllocator = axeberg_abi::heap::BumpAllocator::new();
+//!
+//! fn run(args: Args) -> i32 {
+//!     let Some(path) = args.get(1) else {
+//!         eprintln!("usage: {} <file>", args.program());
+//!         return 1;
+//!     };
+//!     match axeberg_abi::fs::read_to_string(path) {
+//!         Ok(text) => {
+//!             println!("{}", text);
+//!             0
+//!         }
+//!         Err(e) => {
+//!             eprintln!("{}: {}", path, e);
+//!             1
+//!         }
+//!     }
+//! }
+//! ```
+
+#![cfg_attr(not(test), no_std)]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+pub mod args;
+pub mod env;
+pub mod error;
+pub mod fs;
+#[cfg(feature = "alloc")]
+pub mod heap;
+pub mod io;
+pub mod process;
+pub mod sys;
+
+pub use error::{Error, Result};
+
+/// Define the `main` export required by the command ABI
+///
+/// The given function receives the decoded [`args::Args`] and returns the
+/// exit code. The environment block is recorded for [`env::get`] and
+/// friends.
+#[macro_export]
+macro_rules! entry {
+    ($main:path) => {
+        #[unsafe(no_mangle)]
+        pub extern "C" fn main(argc: i32, argv: *const *const u8, envp: *const *const u8) -> i32 {
+            // SAFETY: the kernel lays out argv and envp as described in the
+            // ABI and keeps them alive until main returns
+            let args = unsafe {
+                $crate::env::init(envp);
+                $crate::args::Args::from_raw(argc, argv)
+            };
+            $main(args)
+        }
+    };
+}
+
+/// Define a `#[panic_handler]` that reports to stderr and exits with 101
+///
+/// Only needed by `#![no_std]` commands; std-based commands already have one.
+#[macro_export]
+macro_rules! panic_handler {
+    () => {
+        #[panic_handler]
+        fn panic(info: &core::panic::PanicInfo) -> ! {
+            $crate::eprintln!("panic: {}", info.message());
+            $crate::process::exit(101)
+        }
+    };
+}
@@ -0,0 +1,17 @@
+//! Process control
+
+use crate::sys;
+
+/// Terminate the command with `code`
+///
+/// Output already written is kept; nothing after this call runs.
+pub fn exit(code: i32) -> ! {
+    // SAFETY: exit has no memory-safety preconditions
+    unsafe { sys::exit(code) };
+    // The kernel marks the command terminated when exit is called; trap so
+    // control never returns into the command
+    #[cfg(target_arch = "wasm32")]
+    core::arch::wasm32::unreachable();
+    #[cfg(not(target_arch = "wasm32"))]
+    panic!("exit({}) called outside the axeberg runtime", code);
+}
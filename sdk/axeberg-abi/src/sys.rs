@@ -0,0 +1,121 @@
+//! Raw syscall imports
+//!
+//! These are the exact functions the kernel provides in the `env` namespace.
+//! Prefer the safe wrappers in the other modules; this module exists so
+//! commands can reach a syscall the SDK does not wrap yet.
+//!
+//! All pointers refer to the command's own linear memory. Return values
+//! follow the ABI convention: `>= 0` is success, `< 0` is an error code
+//! (see [`crate::Error`]).
+
+#[cfg(target_arch = "wasm32")]
+#[link(wasm_import_module = "env")]
+unsafe extern "C" {
+    pub fn open(path_ptr: *const u8, path_len: i32, flags: i32) -> i32;
+    pub fn close(fd: i32) -> i32;
+    pub fn read(fd: i32, buf_ptr: *mut u8, len: i32) -> i32;
+    pub fn write(fd: i32, buf_ptr: *const u8, len: i32) -> i32;
+    pub fn pread(fd: i32, buf_ptr: *mut u8, len: i32, offset: i64) -> i32;
+    pub fn pwrite(fd: i32, buf_ptr: *const u8, len: i32, offset: i64) -> i32;
+    pub fn stat(path_ptr: *const u8, path_len: i32, stat_buf: *mut u8) -> i32;
+    pub fn mkdir(path_ptr: *const u8, path_len: i32) -> i32;
+    pub fn readdir(path_ptr: *const u8, path_len: i32, buf_ptr: *mut u8, buf_len: i32) -> i32;
+    pub fn rmdir(path_ptr: *const u8, path_len: i32) -> i32;
+    pub fn unlink(path_ptr: *const u8, path_len: i32) -> i32;
+    pub fn rename(from_ptr: *const u8, from_len: i32, to_ptr: *const u8, to_len: i32) -> i32;
+    pub fn exit(code: i32);
+    pub fn getenv(name_ptr: *const u8, name_len: i32, buf_ptr: *mut u8, buf_len: i32) -> i32;
+    pub fn getcwd(buf_ptr: *mut u8, buf_len: i32) -> i32;
+}
+
+/// Host stand-ins so the SDK builds and tests off-target
+///
+/// Every call fails with the generic error code. They touch no memory, so
+/// they are always safe to call; they are `unsafe` only to match the
+/// imports' signatures.
+#[cfg(not(target_arch = "wasm32"))]
+#[allow(clippy::missing_safety_doc)]
+mod host {
+    const GENERIC: i32 = -1;
+
+    pub unsafe fn open(_path_ptr: *const u8, _path_len: i32, _flags: i32) -> i32 {
+        GENERIC
+    }
+
+    pub unsafe fn close(_fd: i32) -> i32 {
+        GENERIC
+    }
+
+    pub unsafe fn read(_fd: i32, _buf_ptr: *mut u8, _len: i32) -> i32 {
+        GENERIC
+    }
+
+    pub unsafe fn write(_fd: i32, _buf_ptr: *const u8, _len: i32) -> i32 {
+        GENERIC
+    }
+
+    pub unsafe fn pread(_fd: i32, _buf_ptr: *mut u8, _len: i32, _offset: i64) -> i32 {
+        GENERIC
+    }
+
+    pub unsafe fn pwrite(_fd: i32, _buf_ptr: *const u8, _len: i32, _offset: i64) -> i32 {
+        GENERIC
+    }
+
+    pub unsafe fn stat(_path_ptr: *const u8, _path_len: i32, _stat_buf: *mut u8) -> i32 {
+        GENERIC
+    }
+
+    pub unsafe fn mkdir(_path_ptr: *const u8, _path_len: i32) -> i32 {
+        GENERIC
+    }
+
+    pub unsafe fn readdir(
+        _path_ptr: *const u8,
+        _path_len: i32,
+        _buf_ptr: *mut u8,
+        _buf_len: i32,
+    ) -> i32 {
+        GENERIC
+    }
+
+    pub unsafe fn rmdir(_path_ptr: *const u8, _path_len: i32) -> i32 {
+        GENERIC
+    }
+
+    pub unsafe fn unlink(_path_ptr: *const u8, _path_len: i32) -> i32 {
+        GENERIC
+    }
+
+    pub unsafe fn rename(
+        _from_ptr: *const u8,
+        _from_len: i32,
+        _to_ptr: *const u8,
+        _to_len: i32,
+    ) -> i32 {
+        GENERIC
+    }
+
+    pub unsafe fn exit(_code: i32) {}
+
+    pub unsafe fn getenv(
+        _name_ptr: *const u8,
+        _name_len: i32,
+        _buf_ptr: *mut u8,
+        _buf_len: i32,
+    ) -> i32 {
+        GENERIC
+    }
+
+    pub unsafe fn getcwd(_buf_ptr: *mut u8, _buf_len: i32) -> i32 {
+        GENERIC
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use host::*;
+
+/// Length of a slice as the ABI's `i32`, saturating for oversized buffers
+pub(crate) fn len_i32(len: usize) -> i32 {
+    i32::try_from(len).unwrap_or(i32::MAX)
+}
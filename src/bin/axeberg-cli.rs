@@ -45,6 +45,7 @@ fn main() {
                         println!("  pwd     - Print working directory");
                         println!("  ls      - List directory");
                         println!("  cat     - Display file contents");
+                        println!("  pkg new <name> - Scaffold a WASM command project");
                         println!();
                         println!("Note: This is a minimal WASI CLI.");
                     }
@@ -79,6 +80,12 @@ fn main() {
                             Err(e) => eprintln!("cat: {}: {}", path, e),
                         }
                     }
+                    cmd if cmd.starts_with("pkg new") => {
+                        let name = cmd.strip_prefix("pkg new").unwrap().trim();
+                        if let Err(e) = scaffold_project(name) {
+                            eprintln!("pkg new: {}", e);
+                        }
+                    }
                     cmd if cmd.starts_with("echo ") => {
                         println!("{}", cmd.strip_prefix("echo ").unwrap());
                     }
@@ -97,3 +104,26 @@ fn main() {
         }
     }
 }
+
+/// Write a `pkg new` project template onto the host filesystem
+fn scaffold_project(name: &str) -> Result<(), String> {
+    use axeberg::kernel::pkg::ProjectTemplate;
+    use std::path::Path;
+
+    let template = ProjectTemplate::new(name).map_err(|e| e.to_string())?;
+    let root = Path::new(name);
+    if root.exists() {
+        return Err(format!("{}: already exists", name));
+    }
+
+    for dir in template.dirs() {
+        std::fs::create_dir_all(root.join(dir)).map_err(|e| e.to_string())?;
+    }
+    for (rel, content) in template.files() {
+        let path = root.join(rel);
+        std::fs::write(&path, content).map_err(|e| format!("{}: {}", path.display(), e))?;
+        println!("  {}", path.display());
+    }
+    println!("Created command project '{}'", name);
+    Ok(())
+}
@@ -25,7 +25,13 @@ fn main() {
     println!("└─────────────────────────────────────┘");
 
     for request in server.incoming_requests() {
-        let url_path = request.url().to_string();
+        // The page takes parameters (?guest, ?v=VERSION) the files don't
+        let url_path = request
+            .url()
+            .split('?')
+            .next()
+            .unwrap_or_default()
+            .to_string();
         let file_path = if url_path == "/" {
             "index.html".to_string()
         } else {
@@ -6,9 +6,16 @@
 #![cfg(target_arch = "wasm32")]
 
 use crate::console_log;
+use crate::kernel::LogLevel;
+use crate::kernel::mount::{FsType, FstabEntry};
+use crate::kernel::pressure::{self, MemorySample};
 use crate::kernel::syscall::{self, OpenFlags};
+use crate::shell::profile::{self, ProfileSet};
+use crate::shell::{self, backup, cron, indexer, journal, update, widgets};
 use crate::terminal;
 use crate::vfs::Persistence;
+use wasm_bindgen::JsCast;
+use wasm_bindgen::prelude::*;
 
 /// Boot the system
 pub fn boot() {
@@ -16,6 +23,11 @@ pub fn boot() {
     let init_pid = syscall::spawn_process("init");
     syscall::set_current_process(init_pid);
     console_log!("[boot] Created init process: {:?}", init_pid);
+    if let Err(e) = crate::compositor::clipboard::install() {
+        console_log!("[boot] No /dev/clipboard: {}", e);
+    }
+    // Time task polls and syscalls, e.g. for `time`
+    crate::kernel::set_clock(Some(terminal::platform_now));
 
     // Initialize terminal immediately
     console_log!("[boot] Initializing terminal...");
@@ -25,19 +37,45 @@ pub fn boot() {
     }
     console_log!("[boot] Terminal initialized");
 
+    // Heavy background work only runs in ticks with nothing urgent in them
+    let task = crate::kernel::spawn_idle(|| {
+        indexer::tick(js_sys::Date::now());
+    });
+    syscall::name_task(task, "indexer");
+    let task = crate::kernel::spawn_idle(|| {
+        journal::tick(js_sys::Date::now());
+    });
+    syscall::name_task(task, "journal");
+
+    // Caches that can go when memory runs short
+    pressure::on_pressure(indexer::trim);
+    pressure::on_pressure(crate::compositor::terminal::trim_scrollback);
+    pressure::on_pressure(terminal::trim_scrollback);
+
+    // Drive the executor, timers and cron at the power governor's pace
+    setup_visibility_handler();
+    schedule_tick();
+
     // Initialize filesystem asynchronously
     wasm_bindgen_futures::spawn_local(async {
-        match restore_or_init_filesystem().await {
+        // No saved state: run the first-boot setup once the shell is up
+        let fresh = match restore_or_init_filesystem().await {
             Ok(restored) => {
                 if restored {
-                    console_log!("[boot] Restored filesystem from OPFS");
+                    console_log!("[boot] Restored filesystem from browser storage");
                 } else {
                     console_log!("[boot] Initialized fresh filesystem");
                 }
+                !restored
             }
             Err(e) => {
                 // Log to console for debugging
                 console_log!("[boot] Filesystem restore failed: {}", e);
+                syscall::klog(
+                    LogLevel::Err,
+                    "persist",
+                    &format!("restore failed, starting fresh: {}", e),
+                );
                 web_sys::console::warn_1(
                     &format!("[boot] Previous session data could not be restored: {}", e).into(),
                 );
@@ -49,18 +87,168 @@ pub fn boot() {
 
                 // Initialize fresh filesystem
                 init_filesystem();
+                true
             }
+        };
+        boot_system();
+        if let Err(e) = shell::spell::install() {
+            console_log!("[boot] No word list: {}", e);
+        }
+        if let Err(e) = shell::login::install() {
+            console_log!("[boot] No shells list: {}", e);
+        }
+        apply_default_profile();
+        report_swap_files();
+        // The shell is up on this system image, so keep it
+        if let Err(e) = syscall::system_mark_good() {
+            web_sys::console::warn_1(&format!("[boot] Could not confirm system: {}", e).into());
+        }
+        if guest_requested() {
+            start_guest();
+        } else if fresh {
+            terminal::start_setup();
+        } else {
+            terminal::start_unlock(fstab_vaults());
         }
     });
 }
 
-/// Try to restore filesystem from OPFS, or initialize fresh
+/// Encrypted mounts to unlock at boot, from /etc/fstab
+fn fstab_vaults() -> Vec<FstabEntry> {
+    let Ok(fstab) = syscall::read_file("/etc/fstab") else {
+        return Vec::new();
+    };
+    fstab
+        .lines()
+        .filter_map(FstabEntry::parse)
+        .filter(|entry| entry.fstype == FsType::Encrypted)
+        .collect()
+}
+
+/// Check for `?guest` in the page URL
+fn guest_requested() -> bool {
+    web_sys::window()
+        .and_then(|w| w.location().search().ok())
+        .is_some_and(|query| {
+            query
+                .trim_start_matches('?')
+                .split('&')
+                .any(|param| param == "guest")
+        })
+}
+
+/// Boot into a guest session: the filesystem gets a throwaway overlay
+fn start_guest() {
+    match syscall::guest_start() {
+        Ok(()) => {
+            console_log!("[boot] Guest session started");
+            terminal::writeln(
+                "\x1b[33mGuest session: changes are discarded on exit (see 'guest --help')\x1b[0m",
+            );
+        }
+        Err(e) => console_log!("[boot] Guest session failed: {}", e),
+    }
+}
+
+/// Report page visibility to the power governor (`ondemand` saves power
+/// while the tab is hidden)
+fn setup_visibility_handler() {
+    let Some(document) = web_sys::window().and_then(|w| w.document()) else {
+        return;
+    };
+    syscall::set_page_hidden(document.hidden());
+
+    let callback = Closure::wrap(Box::new(move || {
+        if let Some(document) = web_sys::window().and_then(|w| w.document())
+            && syscall::set_page_hidden(document.hidden())
+        {
+            console_log!("[power] Mode: {}", syscall::power().mode().name());
+        }
+    }) as Box<dyn FnMut()>);
+    let _ = document
+        .add_event_listener_with_callback("visibilitychange", callback.as_ref().unchecked_ref());
+    callback.forget();
+}
+
+/// Run the next scheduler tick after the governor's tick interval
+fn schedule_tick() {
+    let callback = Closure::once_into_js(tick);
+    if let Some(window) = web_sys::window() {
+        let _ = window.set_timeout_with_callback_and_timeout_and_arguments_0(
+            callback.unchecked_ref(),
+            syscall::power_tick_interval() as i32,
+        );
+    }
+}
+
+/// One scheduler tick: timers, executor (with the idle work: the file
+/// index and journal compaction), service watchdogs, memory pressure, cron,
+/// remote backups, editor swap files, status bar widgets, update checks and
+/// batched persistence
+fn tick() {
+    let now = terminal::platform_now();
+    syscall::set_time(now);
+    crate::kernel::wake(&syscall::tick_timers());
+    crate::kernel::tick();
+    syscall::check_watchdogs();
+    if let Some(level) = syscall::memory_pressure_tick(js_heap()) {
+        console_log!("[memory] Pressure: {}", level.name());
+    }
+    let wall = js_sys::Date::now();
+    cron::tick(wall);
+    backup::tick(wall);
+    crate::editor::tick(wall);
+    if widgets::tick(wall) {
+        terminal::set_status(&widgets::line());
+    }
+    update::tick(wall);
+    if terminal::can_notify()
+        && let Some(notice) = update::take_notice()
+    {
+        terminal::notify(&notice);
+    }
+    if terminal::can_notify()
+        && let Some(notice) = syscall::take_net_notice()
+    {
+        terminal::notify(&notice);
+    }
+
+    let power = syscall::power();
+    crate::compositor::set_animations_paused(power.animations_paused());
+    terminal::flush_autosave(now);
+    schedule_tick();
+}
+
+/// The JS heap in use and its limit, from `performance.memory` (only some
+/// browsers have it)
+fn js_heap() -> Option<MemorySample> {
+    let performance = web_sys::window()?.performance()?;
+    let memory = js_sys::Reflect::get(&performance, &"memory".into()).ok()?;
+    let field = |name: &str| js_sys::Reflect::get(&memory, &name.into()).ok()?.as_f64();
+    Some(MemorySample {
+        used: field("usedJSHeapSize")? as u64,
+        limit: field("jsHeapSizeLimit")? as u64,
+    })
+}
+
+/// Try to restore filesystem from OPFS or IndexedDB, or initialize fresh
 async fn restore_or_init_filesystem() -> Result<bool, String> {
-    // Try to load from OPFS
-    if let Some(fs) = Persistence::load().await? {
+    // Try to load a snapshot, falling back to a backup if it's corrupt
+    if let Some(restored) = Persistence::load().await? {
         // Restore the VFS
-        let data = fs.to_json().map_err(|e| e.to_string())?;
-        syscall::vfs_restore(&data).map_err(|e| e.to_string())?;
+        let data = restored.value.to_json().map_err(|e| e.to_string())?;
+        let check = syscall::vfs_restore(&data).map_err(|e| e.to_string())?;
+        if restored.is_clean() {
+            syscall::klog(LogLevel::Info, "persist", "restored filesystem snapshot");
+        } else {
+            report_recovery(&Persistence::describe(&restored));
+        }
+        if check.fixed() > 0 {
+            terminal::writeln(&format!(
+                "\x1b[33m⚠ fsck repaired {} problem(s) in the saved filesystem (see 'dmesg')\x1b[0m",
+                check.fixed()
+            ));
+        }
         Ok(true)
     } else {
         // Fresh install - initialize filesystem
@@ -69,6 +257,72 @@ async fn restore_or_init_filesystem() -> Result<bool, String> {
     }
 }
 
+/// Tell the user the saved session was damaged and what was used instead
+fn report_recovery(lines: &[String]) {
+    for line in lines {
+        syscall::klog(LogLevel::Warn, "persist", line);
+        web_sys::console::warn_1(&format!("[boot] {}", line).into());
+    }
+    terminal::writeln(
+        "\x1b[33m⚠ Saved session was damaged - restored from a backup (see 'dmesg')\x1b[0m",
+    );
+}
+
+/// Point out editor buffers a crash or reload left unsaved
+fn report_swap_files() {
+    let count = shell::swap::list().len();
+    if count > 0 {
+        terminal::writeln(&format!(
+            "\x1b[33m⚠ {} unsaved editor buffer(s) from the last session - 'edit -r' lists them\x1b[0m",
+            count
+        ));
+    }
+}
+
+/// Mount the read-only system partition, switching to a staged update or
+/// rolling back one that didn't boot
+fn boot_system() {
+    match syscall::system_boot(&shell::system_image()) {
+        Ok(boot) => {
+            console_log!(
+                "[boot] System {} from slot {}",
+                boot.version,
+                boot.slot.name()
+            );
+            if boot.switched {
+                terminal::writeln(&format!(
+                    "\x1b[32mSystem updated to {}\x1b[0m",
+                    boot.version
+                ));
+            }
+            if let Some(reason) = boot.rolled_back {
+                terminal::writeln(&format!(
+                    "\x1b[33m⚠ System update rolled back: {}\x1b[0m",
+                    reason
+                ));
+            }
+        }
+        Err(e) => {
+            web_sys::console::warn_1(&format!("[boot] System partition failed: {}", e).into());
+        }
+    }
+}
+
+/// Apply the default terminal profile from /etc/terminal/profiles.toml
+fn apply_default_profile() {
+    let profile = match profile::load() {
+        Ok(profiles) => profiles.default_profile(),
+        Err(e) => {
+            terminal::writeln(&format!("\x1b[33m⚠ {} - using built-in profile\x1b[0m", e));
+            return;
+        }
+    };
+    console_log!("[boot] Applying terminal profile '{}'", profile.name);
+    if let Err(e) = terminal::apply_profile(&profile) {
+        web_sys::console::warn_1(&format!("[boot] Terminal profile failed: {:?}", e).into());
+    }
+}
+
 /// Set up initial filesystem structure using syscalls
 fn init_filesystem() {
     // Create user home directory
@@ -88,4 +342,11 @@ fn init_filesystem() {
     let fd = syscall::open("/etc/version", OpenFlags::WRITE).expect("create version");
     syscall::write(fd, b"axeberg 0.1.0\n").expect("write version");
     syscall::close(fd).expect("close version");
+
+    // Write the default terminal profile
+    profile::save(&ProfileSet::default()).expect("write terminal profiles");
+
+    // Write the default status bar widgets
+    syscall::write_file(widgets::CONFIG_PATH, widgets::DEFAULT_CONFIG)
+        .expect("write status bar widgets");
 }
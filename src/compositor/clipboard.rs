@@ -0,0 +1,91 @@
+//! /dev/clipboard
+//!
+//! The desktop clipboard as a device: writing replaces its contents, and
+//! in the browser the host's clipboard too; reading returns them. Terminal
+//! windows copy to it for programs that ask with OSC 52.
+
+use crate::kernel::devfs::{DeviceInfo, DeviceOps};
+use crate::kernel::syscall::{self, Kernel, SyscallError, SyscallResult};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Clipboard contents, shared by every descriptor
+#[derive(Default)]
+struct Clipboard {
+    contents: RefCell<Vec<u8>>,
+}
+
+impl DeviceOps for Clipboard {
+    fn info(&self) -> DeviceInfo {
+        DeviceInfo::char(10, 200, 0o666)
+    }
+
+    fn read(&self, _kernel: &mut Kernel, offset: u64, buf: &mut [u8]) -> SyscallResult<usize> {
+        let contents = self.contents.borrow();
+        let rest = contents.get(offset as usize..).unwrap_or(&[]);
+        let n = rest.len().min(buf.len());
+        buf[..n].copy_from_slice(&rest[..n]);
+        Ok(n)
+    }
+
+    fn write(&self, _kernel: &mut Kernel, offset: u64, buf: &[u8]) -> SyscallResult<usize> {
+        let mut contents = self.contents.borrow_mut();
+        if offset == 0 {
+            contents.clear();
+        }
+        contents.extend_from_slice(buf);
+        if cfg!(target_arch = "wasm32") {
+            copy_to_host(&String::from_utf8_lossy(&contents));
+        }
+        Ok(buf.len())
+    }
+}
+
+/// Add /dev/clipboard unless it is already there
+pub fn install() -> SyscallResult<()> {
+    match syscall::register_device("clipboard", Rc::new(Clipboard::default())) {
+        Err(SyscallError::AlreadyExists) => Ok(()),
+        result => result,
+    }
+}
+
+/// Hand `text` to the browser's clipboard; it may refuse without a user
+/// gesture, which leaves just our copy
+fn copy_to_host(text: &str) {
+    use wasm_bindgen::{JsCast, JsValue};
+
+    let Some(window) = web_sys::window() else {
+        return;
+    };
+    let navigator = window.navigator();
+    let Ok(clipboard) = js_sys::Reflect::get(&navigator, &JsValue::from_str("clipboard")) else {
+        return;
+    };
+    if let Ok(write_text) = js_sys::Reflect::get(&clipboard, &JsValue::from_str("writeText"))
+        && let Ok(write_text) = write_text.dyn_into::<js_sys::Function>()
+    {
+        let _ = write_text.call1(&clipboard, &JsValue::from_str(text));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::syscall::KERNEL;
+
+    #[test]
+    fn test_clipboard_device() {
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        install().unwrap();
+        install().unwrap();
+
+        syscall::write_file("/dev/clipboard", "first").unwrap();
+        syscall::write_file("/dev/clipboard", "copied").unwrap();
+        assert_eq!(syscall::read_file("/dev/clipboard").unwrap(), "copied");
+    }
+}
@@ -0,0 +1,430 @@
+//! Inspector - live kernel object inspector window
+//!
+//! Shows the kernel object table (handles, types, refcounts, owning fds),
+//! each process's fd table and the mount table as expandable trees. The
+//! data comes from `syscall::object_snapshot()` and is refreshed with
+//! [`refresh`].
+//!
+//! The leak detector compares the current snapshot with a baseline taken
+//! by pressing `b`: objects created since then that no fd points at are
+//! flagged as leaks.
+//!
+//! Keys: Up/Down (or k/j) move, Enter/Space expands or collapses, `b` takes
+//! a baseline, `c` clears it.
+
+use super::{COMPOSITOR, WindowId};
+use crate::kernel::syscall;
+use crate::kernel::{Handle, LeakReport, ObjectSnapshot, Pid, TaskId};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+/// Window title
+pub const INSPECTOR_TITLE: &str = "Inspector";
+
+/// An expandable row in the tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Node {
+    /// The object table section
+    Objects,
+    /// One kernel object (expands to its owning fds)
+    Object(Handle),
+    /// The process section
+    Processes,
+    /// One process (expands to its fd table)
+    Process(Pid),
+    /// The mount table section
+    Mounts,
+    /// The leak detector section
+    Leaks,
+}
+
+/// Inspector state for one window
+#[derive(Debug, Clone)]
+pub struct Inspector {
+    snapshot: ObjectSnapshot,
+    baseline: Option<ObjectSnapshot>,
+    expanded: HashSet<Node>,
+    selected: usize,
+}
+
+impl Inspector {
+    /// Create an inspector showing `snapshot`, with the object table expanded
+    pub fn new(snapshot: ObjectSnapshot) -> Self {
+        Self {
+            snapshot,
+            baseline: None,
+            expanded: HashSet::from([Node::Objects]),
+            selected: 0,
+        }
+    }
+
+    /// Replace the displayed snapshot, keeping expansion and selection
+    pub fn refresh(&mut self, snapshot: ObjectSnapshot) {
+        self.snapshot = snapshot;
+        self.selected = self.selected.min(self.rows().len().saturating_sub(1));
+    }
+
+    /// The displayed snapshot
+    pub fn snapshot(&self) -> &ObjectSnapshot {
+        &self.snapshot
+    }
+
+    /// Use the current snapshot as the leak detector baseline
+    pub fn mark_baseline(&mut self) {
+        self.baseline = Some(self.snapshot.clone());
+        self.expanded.insert(Node::Leaks);
+    }
+
+    /// Drop the leak detector baseline
+    pub fn clear_baseline(&mut self) {
+        self.baseline = None;
+    }
+
+    /// Changes since the baseline, if one was taken
+    pub fn leak_report(&self) -> Option<LeakReport> {
+        self.baseline.as_ref().map(|b| b.diff(&self.snapshot))
+    }
+
+    /// Check if a node is expanded
+    pub fn is_expanded(&self, node: Node) -> bool {
+        self.expanded.contains(&node)
+    }
+
+    /// Expand or collapse a node
+    pub fn toggle(&mut self, node: Node) {
+        if !self.expanded.remove(&node) {
+            self.expanded.insert(node);
+        }
+    }
+
+    /// Index of the selected row
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Move the selection down
+    pub fn select_next(&mut self) {
+        let last = self.rows().len().saturating_sub(1);
+        self.selected = (self.selected + 1).min(last);
+    }
+
+    /// Move the selection up
+    pub fn select_prev(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    /// Expand or collapse the selected row, if it is expandable
+    pub fn toggle_selected(&mut self) {
+        if let Some((Some(node), _)) = self.rows().get(self.selected) {
+            self.toggle(*node);
+        }
+    }
+
+    /// Handle a key press (DOM key names); returns true if it was used
+    pub fn handle_key(&mut self, key: &str) -> bool {
+        match key {
+            "ArrowDown" | "j" => self.select_next(),
+            "ArrowUp" | "k" => self.select_prev(),
+            "Enter" | " " => self.toggle_selected(),
+            "b" => self.mark_baseline(),
+            "c" => self.clear_baseline(),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Tree rows, with the node each one expands if any
+    pub fn rows(&self) -> Vec<(Option<Node>, String)> {
+        let mut rows = Vec::new();
+        let snap = &self.snapshot;
+
+        let section = |node: Node, label: String| (Some(node), label);
+        let arrow = |node: Node| {
+            if self.is_expanded(node) { "▾" } else { "▸" }
+        };
+
+        rows.push(section(
+            Node::Objects,
+            format!("{} Objects ({})", arrow(Node::Objects), snap.objects.len()),
+        ));
+        if self.is_expanded(Node::Objects) {
+            for obj in &snap.objects {
+                let node = Node::Object(obj.handle);
+                let orphan = if obj.owners.is_empty() {
+                    "  (no fds)"
+                } else {
+                    ""
+                };
+                rows.push(section(
+                    node,
+                    format!(
+                        "  {} #{:<4} {:9} refs {:<3} fds {:<3} {}{}",
+                        arrow(node),
+                        obj.handle.0,
+                        obj.kind,
+                        obj.refcount,
+                        obj.owners.len(),
+                        obj.detail,
+                        orphan
+                    ),
+                ));
+                if self.is_expanded(node) {
+                    for (pid, fd) in &obj.owners {
+                        rows.push((None, format!("        pid {} fd {}", pid.0, fd.0)));
+                    }
+                }
+            }
+        }
+
+        rows.push(section(
+            Node::Processes,
+            format!(
+                "{} Processes ({})",
+                arrow(Node::Processes),
+                snap.fd_tables.len()
+            ),
+        ));
+        if self.is_expanded(Node::Processes) {
+            for table in &snap.fd_tables {
+                let node = Node::Process(table.pid);
+                rows.push(section(
+                    node,
+                    format!(
+                        "  {} [{}] {}  ({} fds)",
+                        arrow(node),
+                        table.pid.0,
+                        table.name,
+                        table.fds.len()
+                    ),
+                ));
+                if self.is_expanded(node) {
+                    for (fd, handle) in &table.fds {
+                        let kind = snap.object(*handle).map(|o| o.kind).unwrap_or("(stale)");
+                        rows.push((
+                            None,
+                            format!("        fd {:<3} → #{} {}", fd.0, handle.0, kind),
+                        ));
+                    }
+                }
+            }
+        }
+
+        rows.push(section(
+            Node::Mounts,
+            format!("{} Mounts ({})", arrow(Node::Mounts), snap.mounts.len()),
+        ));
+        if self.is_expanded(Node::Mounts) {
+            for m in &snap.mounts {
+                rows.push((
+                    None,
+                    format!(
+                        "      {:16} {:10} {:10} {}",
+                        m.target, m.fstype, m.source, m.options
+                    ),
+                ));
+            }
+        }
+
+        let report = self.leak_report();
+        let status = match &report {
+            None => "no baseline".to_string(),
+            Some(r) if r.is_clean() => "clean".to_string(),
+            Some(r) => format!("{} leaked", r.leaked.len()),
+        };
+        rows.push(section(
+            Node::Leaks,
+            format!("{} Leak detector ({})", arrow(Node::Leaks), status),
+        ));
+        if self.is_expanded(Node::Leaks) {
+            match report {
+                None => rows.push((None, "      press b to take a baseline".to_string())),
+                Some(report) => {
+                    for line in report.render_ascii().lines() {
+                        rows.push((None, format!("      {}", line)));
+                    }
+                }
+            }
+        }
+
+        rows
+    }
+
+    /// Render the tree as window lines, marking the selected row
+    pub fn lines(&self) -> Vec<String> {
+        self.rows()
+            .into_iter()
+            .enumerate()
+            .map(|(i, (_, text))| {
+                let marker = if i == self.selected { '›' } else { ' ' };
+                format!("{}{}", marker, text)
+            })
+            .collect()
+    }
+}
+
+thread_local! {
+    /// Open inspector windows
+    static INSPECTORS: RefCell<HashMap<WindowId, Inspector>> = RefCell::new(HashMap::new());
+}
+
+/// Copy an inspector's lines into its window
+fn draw(id: WindowId, inspector: &Inspector) {
+    COMPOSITOR.with(|c| {
+        if let Some(window) = c.borrow_mut().get_window_mut(id) {
+            window.content = inspector.lines();
+            window.dirty = true;
+        }
+    });
+}
+
+/// Open a new inspector window
+pub fn open(owner: TaskId) -> WindowId {
+    let id = COMPOSITOR.with(|c| c.borrow_mut().create_window(INSPECTOR_TITLE, owner));
+    let inspector = Inspector::new(syscall::object_snapshot());
+    draw(id, &inspector);
+    INSPECTORS.with(|i| i.borrow_mut().insert(id, inspector));
+    id
+}
+
+/// Take a fresh snapshot for every open inspector and redraw
+///
+/// Inspectors whose window has been closed are dropped.
+pub fn refresh() {
+    let open: HashSet<WindowId> =
+        COMPOSITOR.with(|c| c.borrow().window_ids().into_iter().collect());
+    INSPECTORS.with(|i| {
+        let mut inspectors = i.borrow_mut();
+        inspectors.retain(|id, _| open.contains(id));
+        if inspectors.is_empty() {
+            return;
+        }
+        let snapshot = syscall::object_snapshot();
+        for (id, inspector) in inspectors.iter_mut() {
+            inspector.refresh(snapshot.clone());
+            draw(*id, inspector);
+        }
+    });
+}
+
+/// Route a key press to the inspector in `window`; returns true if handled
+pub fn handle_key(window: WindowId, key: &str) -> bool {
+    INSPECTORS.with(|i| {
+        let mut inspectors = i.borrow_mut();
+        let Some(inspector) = inspectors.get_mut(&window) else {
+            return false;
+        };
+        let handled = inspector.handle_key(key);
+        if handled {
+            draw(window, inspector);
+        }
+        handled
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::syscall::{KERNEL, Kernel, OpenFlags};
+
+    fn setup() {
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+    }
+
+    #[test]
+    fn test_tree_expand_and_select() {
+        setup();
+        let fd = syscall::open("/tmp/x.txt", OpenFlags::WRITE).unwrap();
+        let mut inspector = Inspector::new(syscall::object_snapshot());
+
+        let lines = inspector.lines();
+        assert!(lines[0].starts_with("›▾ Objects (2)"));
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.contains("file") && l.contains("/tmp/x.txt"))
+        );
+        assert!(lines.iter().any(|l| l.contains("▸ Processes (1)")));
+
+        // Expand the file object to see its owning fd
+        let row = inspector
+            .rows()
+            .iter()
+            .rposition(|(n, _)| matches!(n, Some(Node::Object(_))))
+            .unwrap();
+        for _ in 0..row {
+            inspector.handle_key("ArrowDown");
+        }
+        inspector.handle_key("Enter");
+        let lines = inspector.lines();
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.contains(&format!("pid 1 fd {}", fd.0)))
+        );
+
+        // Collapse the object table; the selection is clamped on refresh
+        inspector.toggle(Node::Objects);
+        inspector.refresh(syscall::object_snapshot());
+        assert!(inspector.selected() < inspector.rows().len());
+    }
+
+    #[test]
+    fn test_leak_detector() {
+        setup();
+        let mut inspector = Inspector::new(syscall::object_snapshot());
+        assert!(
+            inspector
+                .lines()
+                .iter()
+                .any(|l| l.contains("(no baseline)"))
+        );
+        inspector.handle_key("b");
+
+        // Dropping the fd without releasing the object leaves a reference
+        // nobody can close
+        let fd = syscall::open("/tmp/y.txt", OpenFlags::WRITE).unwrap();
+        let handle = KERNEL.with(|k| {
+            let k = k.borrow();
+            let proc = k.current_process().unwrap();
+            proc.files.get(fd).unwrap()
+        });
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            k.current_process_mut().unwrap().files.remove(fd);
+        });
+        inspector.refresh(syscall::object_snapshot());
+
+        let report = inspector.leak_report().unwrap();
+        assert_eq!(report.leaked.len(), 1);
+        assert_eq!(report.leaked[0].handle, handle);
+        let lines = inspector.lines();
+        assert!(lines.iter().any(|l| l.contains("Leak detector (1 leaked)")));
+        assert!(lines.iter().any(|l| l.contains("LEAK: no fds")));
+
+        inspector.handle_key("c");
+        assert!(inspector.leak_report().is_none());
+    }
+
+    #[test]
+    fn test_window_refresh() {
+        setup();
+        COMPOSITOR.with(|c| *c.borrow_mut() = super::super::Compositor::new());
+        let id = open(TaskId(1));
+        let content =
+            |id| COMPOSITOR.with(|c| c.borrow().get_window(id).map(|w| w.content.clone()));
+        assert!(content(id).unwrap()[0].contains("Objects (1)"));
+
+        syscall::open("/tmp/z.txt", OpenFlags::WRITE).unwrap();
+        refresh();
+        assert!(content(id).unwrap()[0].contains("Objects (2)"));
+
+        assert!(handle_key(id, "j"));
+        assert!(!handle_key(id, "x"));
+        assert!(!handle_key(WindowId(999), "j"));
+    }
+}
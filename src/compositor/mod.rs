@@ -25,9 +25,13 @@
 //! └──────────────────────────────────────────┘
 //! ```
 
+pub mod clipboard;
 mod geometry;
+pub mod inspector;
 mod layout;
+pub mod terminal;
 mod text;
+pub mod timeline;
 mod window;
 
 // Surface module requires web_sys, only available on wasm32
@@ -46,6 +50,8 @@ pub use window::{Window, WindowId};
 pub use surface::Surface;
 
 use crate::kernel::TaskId;
+#[cfg(target_arch = "wasm32")]
+use crate::shell::osc::ProgressState;
 use std::cell::RefCell;
 use std::collections::HashMap;
 
@@ -323,6 +329,16 @@ impl WindowAnimationState {
         !self.animations.is_empty()
     }
 
+    /// Jump every animation to its end value and drop it
+    ///
+    /// Used instead of [`Self::update`] while the power governor pauses
+    /// animations, so windows still end up in their final state.
+    pub fn finish(&mut self) {
+        let end = self.animations.iter().map(|a| a.start_time + a.duration);
+        let end = end.fold(0.0, f64::max);
+        self.update(end);
+    }
+
     /// Create a window open animation
     pub fn window_open(start_time: f64) -> Self {
         let mut state = Self::new();
@@ -466,6 +482,10 @@ pub struct Compositor {
     theme: Theme,
     /// Dirty flag - needs redraw
     dirty: bool,
+    /// Session locked: draw nothing and ignore input
+    locked: bool,
+    /// Power saving: skip animations (see [`WindowAnimationState::finish`])
+    animations_paused: bool,
 }
 
 impl Compositor {
@@ -481,6 +501,8 @@ impl Compositor {
             focused: None,
             theme: Theme::default(),
             dirty: true,
+            locked: false,
+            animations_paused: false,
         }
     }
 
@@ -617,8 +639,34 @@ impl Compositor {
         self.dirty = true;
     }
 
+    /// Blank the screen and ignore input while the session is locked
+    pub fn set_locked(&mut self, locked: bool) {
+        if self.locked != locked {
+            self.locked = locked;
+            self.dirty = true;
+        }
+    }
+
+    /// Check if the session lock is blanking the screen
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Skip animations while the power governor saves power
+    pub fn set_animations_paused(&mut self, paused: bool) {
+        self.animations_paused = paused;
+    }
+
+    /// Check if animations should jump straight to their end state
+    pub fn animations_paused(&self) -> bool {
+        self.animations_paused
+    }
+
     /// Handle a mouse click at (x, y)
     pub fn handle_click(&mut self, x: f64, y: f64, _button: i16) {
+        if self.locked {
+            return;
+        }
         // Find which window was clicked
         for (i, window) in self.windows.iter().enumerate() {
             if window.rect.contains(x, y) {
@@ -751,9 +799,9 @@ impl Compositor {
             // Clear the surface
             surface.clear();
 
-            // Draw each window
+            // Draw each window (none while locked)
             for (i, window) in self.windows.iter().enumerate() {
-                if !window.flags.visible {
+                if !window.flags.visible || self.locked {
                     continue;
                 }
 
@@ -767,10 +815,11 @@ impl Compositor {
                     self.theme.unfocus_border
                 };
 
-                // Draw window with border
+                // Draw window with border (terminals use their profile's
+                // background)
                 surface.draw_rect_with_border(
                     rect,
-                    self.theme.window_bg,
+                    terminal::background(window.id).unwrap_or(self.theme.window_bg),
                     border_color,
                     self.theme.border_width,
                 );
@@ -780,6 +829,22 @@ impl Compositor {
                     let titlebar = window.titlebar_rect();
                     surface.draw_rect(titlebar, self.theme.titlebar_bg);
                 }
+                if let Some((badge, progress)) = window.progress_rect().zip(window.progress) {
+                    let color = match progress.state {
+                        ProgressState::Error => Color::RED,
+                        ProgressState::Paused => self.theme.unfocus_border,
+                        _ => self.theme.focus_border,
+                    };
+                    surface.draw_rect(badge, color);
+                }
+
+                // Timeline shapes (text ops are skipped until the surface
+                // can draw glyphs)
+                for op in timeline::draw_ops(window.id, window.content_rect()) {
+                    if let timeline::DrawOp::Rect { rect, color } = op {
+                        surface.draw_rect(rect, color);
+                    }
+                }
             }
 
             // Submit all queued rectangles to GPU
@@ -807,6 +872,16 @@ pub fn handle_click(x: f64, y: f64, button: i16) {
     COMPOSITOR.with(|c| c.borrow_mut().handle_click(x, y, button));
 }
 
+/// Blank the screen and ignore input while the session is locked
+pub fn set_locked(locked: bool) {
+    COMPOSITOR.with(|c| c.borrow_mut().set_locked(locked));
+}
+
+/// Skip animations while the power governor saves power
+pub fn set_animations_paused(paused: bool) {
+    COMPOSITOR.with(|c| c.borrow_mut().set_animations_paused(paused));
+}
+
 /// Handle resize event
 pub fn handle_resize(width: u32, height: u32) {
     COMPOSITOR.with(|c| c.borrow_mut().resize(width, height));
@@ -892,6 +967,25 @@ mod tests {
         assert_eq!(comp.focused_window_id(), Some(id1));
     }
 
+    #[test]
+    fn test_locked_ignores_clicks() {
+        let mut comp = Compositor::new();
+        comp.resize(800, 600);
+        let id1 = comp.create_window("Window 1", TaskId(1));
+        let id2 = comp.create_window("Window 2", TaskId(2));
+        let rect = comp.get_window(id1).unwrap().rect;
+        comp.mark_clean();
+
+        comp.set_locked(true);
+        assert!(comp.is_dirty());
+        comp.handle_click(rect.x + 10.0, rect.y + 10.0, 0);
+        assert_eq!(comp.focused_window_id(), Some(id2));
+
+        comp.set_locked(false);
+        comp.handle_click(rect.x + 10.0, rect.y + 10.0, 0);
+        assert_eq!(comp.focused_window_id(), Some(id1));
+    }
+
     #[test]
     fn test_multiple_windows_layout() {
         let mut comp = Compositor::new();
@@ -1023,6 +1117,15 @@ mod tests {
         assert_eq!(state.animations.len(), 2); // Opacity and scale
     }
 
+    #[test]
+    fn test_finish_animations() {
+        let mut state = WindowAnimationState::window_close(1000.0);
+        state.finish();
+        assert!(state.animations.is_empty());
+        assert_eq!(state.opacity, 0.0);
+        assert_eq!(state.scale, 0.9);
+    }
+
     #[test]
     fn test_window_close_animation() {
         let state = WindowAnimationState::window_close(0.0);
@@ -0,0 +1,338 @@
+//! Terminal windows
+//!
+//! Each terminal window runs its own shell ([`Terminal`]) set up from a
+//! terminal profile: it starts in the profile's directory, runs its startup
+//! command and paints the window with the profile's background. Pass a
+//! profile name to [`open`], or `None` for the default profile from
+//! `/etc/terminal/profiles.toml`.
+//!
+//! Programs can retitle their window (OSC 0/2) and copy to the clipboard
+//! (OSC 52, through /dev/clipboard); an empty title restores the default.
+//! Progress they report (OSC 9;4) is drawn as a bar above the input line
+//! and as a badge on the title bar until they clear it.
+//! Full-screen programs that turn on mouse reporting get clicks, drags and
+//! the wheel as terminal input; otherwise the mouse selects and scrolls.
+//!
+//! Under memory pressure [`trim_scrollback`] cuts every window's scrollback
+//! down to [`PRESSURE_SCROLLBACK`] lines, or to what is on screen once
+//! pressure is critical.
+
+use super::{COMPOSITOR, Color, WindowId, clipboard};
+use crate::kernel::TaskId;
+use crate::kernel::klog::LogLevel;
+use crate::kernel::pressure::PressureLevel;
+use crate::kernel::syscall;
+use crate::kernel::tty::MouseEvent;
+use crate::shell::Terminal;
+use crate::shell::osc::{self, OscCommand};
+use crate::shell::profile::{self, TerminalProfile};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+/// Window title prefix; the profile name is appended
+pub const TERMINAL_TITLE: &str = "Terminal";
+
+/// Scrollback lines a window keeps under moderate memory pressure
+pub const PRESSURE_SCROLLBACK: usize = 200;
+
+thread_local! {
+    /// Open terminal windows
+    static TERMINALS: RefCell<HashMap<WindowId, Terminal>> = RefCell::new(HashMap::new());
+}
+
+/// Scrollback and input line as window content
+fn lines(terminal: &Terminal) -> Vec<String> {
+    let mut lines: Vec<String> = terminal
+        .visible_lines()
+        .map(|line| line.text.clone())
+        .collect();
+    let (prompt, input, _) = terminal.input_line();
+    lines.push(format!("{}{}", prompt, input));
+    lines
+}
+
+/// Copy a terminal's lines into its window, after carrying out the OSC
+/// requests its commands made
+fn draw(id: WindowId, terminal: &mut Terminal) {
+    let requests = terminal.take_osc();
+    COMPOSITOR.with(|c| {
+        if let Some(window) = c.borrow_mut().get_window_mut(id) {
+            for request in requests {
+                match request {
+                    OscCommand::Title(title) if title.is_empty() => {
+                        window.set_title(default_title(terminal.profile()))
+                    }
+                    OscCommand::Title(title) => window.set_title(title),
+                    OscCommand::Progress(progress) => window.set_progress(progress),
+                    OscCommand::Copy(data) => {
+                        if let Err(e) = osc::copy(&data) {
+                            syscall::klog(
+                                LogLevel::Info,
+                                "terminal",
+                                &format!("clipboard copy refused: {}", e),
+                            );
+                        }
+                    }
+                }
+            }
+            window.content = lines(terminal);
+            // The bar sits above the input line
+            if let Some(progress) = window.progress {
+                let input = window.content.len().saturating_sub(1);
+                window.content.insert(input, progress.render());
+            }
+            window.dirty = true;
+        }
+    });
+}
+
+/// Title of a terminal window nothing has retitled
+fn default_title(profile: Option<&TerminalProfile>) -> String {
+    match profile {
+        Some(profile) => format!("{} ({})", TERMINAL_TITLE, profile.name),
+        None => TERMINAL_TITLE.to_string(),
+    }
+}
+
+/// Open a new terminal window using `profile` (or the default profile)
+pub fn open(owner: TaskId, profile: Option<&str>) -> Result<WindowId, String> {
+    let profile = profile::load()?.resolve(profile)?;
+    let title = default_title(Some(&profile));
+    // Copies go nowhere until the clipboard is there
+    let _ = clipboard::install();
+    let mut terminal = Terminal::with_profile(profile);
+    let id = COMPOSITOR.with(|c| c.borrow_mut().create_window(&title, owner));
+    draw(id, &mut terminal);
+    TERMINALS.with(|t| t.borrow_mut().insert(id, terminal));
+    Ok(id)
+}
+
+/// Cut the scrollback of every terminal window, for a memory pressure
+/// hook
+pub fn trim_scrollback(level: PressureLevel) -> Option<String> {
+    let keep = match level {
+        PressureLevel::Normal => return None,
+        PressureLevel::Moderate => PRESSURE_SCROLLBACK,
+        PressureLevel::Critical => 0,
+    };
+    let dropped: usize = TERMINALS.with(|t| {
+        t.borrow_mut()
+            .iter_mut()
+            .map(|(&id, terminal)| {
+                let dropped = terminal.trim_scrollback(keep);
+                if dropped > 0 {
+                    draw(id, terminal);
+                }
+                dropped
+            })
+            .sum()
+    });
+    (dropped > 0).then(|| format!("{} lines of terminal window scrollback", dropped))
+}
+
+/// Drop terminals whose window has been closed
+pub fn refresh() {
+    let open: HashSet<WindowId> =
+        COMPOSITOR.with(|c| c.borrow().window_ids().into_iter().collect());
+    TERMINALS.with(|t| t.borrow_mut().retain(|id, _| open.contains(id)));
+}
+
+/// Route a key press to the terminal in `window`; returns true if handled
+pub fn handle_key(window: WindowId, key: &str, code: &str, ctrl: bool, alt: bool) -> bool {
+    if COMPOSITOR.with(|c| c.borrow().is_locked()) {
+        return false;
+    }
+    TERMINALS.with(|t| {
+        let mut terminals = t.borrow_mut();
+        let Some(terminal) = terminals.get_mut(&window) else {
+            return false;
+        };
+        let handled = terminal.handle_key(key, code, ctrl, alt);
+        if handled {
+            draw(window, terminal);
+        }
+        handled
+    })
+}
+
+/// Route a mouse event at a 1-based cell to the terminal in `window`;
+/// returns true if handled
+pub fn handle_mouse(window: WindowId, event: &MouseEvent) -> bool {
+    if COMPOSITOR.with(|c| c.borrow().is_locked()) {
+        return false;
+    }
+    TERMINALS.with(|t| {
+        let mut terminals = t.borrow_mut();
+        let Some(terminal) = terminals.get_mut(&window) else {
+            return false;
+        };
+        let handled = terminal.handle_mouse(event);
+        if handled && !terminal.mouse_tracking().is_enabled() {
+            draw(window, terminal);
+        }
+        handled
+    })
+}
+
+/// Profile of the terminal in `window`
+pub fn profile(window: WindowId) -> Option<TerminalProfile> {
+    TERMINALS.with(|t| t.borrow().get(&window)?.profile().cloned())
+}
+
+/// Background color of the terminal in `window`, from its profile
+pub fn background(window: WindowId) -> Option<Color> {
+    let profile = profile(window)?;
+    Color::from_hex(profile.color("background")?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::syscall::{KERNEL, Kernel};
+    use crate::shell::profile::ProfileSet;
+
+    fn setup() {
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        COMPOSITOR.with(|c| *c.borrow_mut() = super::super::Compositor::new());
+    }
+
+    #[test]
+    fn test_open_with_profile() {
+        setup();
+        let mut profiles = ProfileSet::default();
+        profiles.create("ops", None).unwrap();
+        let ops = profiles.get_mut("ops").unwrap();
+        ops.set("cwd", "/tmp").unwrap();
+        ops.set("startup", "echo ready").unwrap();
+        ops.set("colors.background", "#000000").unwrap();
+        profile::save(&profiles).unwrap();
+
+        let id = open(TaskId(1), Some("ops")).unwrap();
+        let window = COMPOSITOR
+            .with(|c| c.borrow().get_window(id).cloned())
+            .unwrap();
+        assert_eq!(window.title, "Terminal (ops)");
+        assert!(window.content.iter().any(|l| l == "ready"));
+        assert_eq!(window.content.last().map(String::as_str), Some("/tmp $ "));
+        assert_eq!(background(id), Some(Color::rgb(0.0, 0.0, 0.0)));
+
+        // Typing goes to the window's own shell
+        for key in ["p", "w", "d"] {
+            handle_key(id, key, key, false, false);
+        }
+        handle_key(id, "Enter", "Enter", false, false);
+        let content = COMPOSITOR.with(|c| c.borrow().get_window(id).map(|w| w.content.clone()));
+        assert!(content.unwrap().iter().any(|l| l == "/tmp"));
+
+        // The default profile has the built-in look
+        let default = open(TaskId(1), None).unwrap();
+        assert_eq!(
+            profile(default).map(|p| p.name),
+            Some(profile::DEFAULT_PROFILE.to_string())
+        );
+        assert!(open(TaskId(1), Some("missing")).is_err());
+
+        COMPOSITOR.with(|c| c.borrow_mut().close_window(id));
+        refresh();
+        assert!(profile(id).is_none());
+    }
+
+    #[test]
+    fn test_trim_scrollback() {
+        setup();
+        let id = open(TaskId(1), None).unwrap();
+        TERMINALS.with(|t| {
+            let mut t = t.borrow_mut();
+            let terminal = t.get_mut(&id).unwrap();
+            for i in 0..PRESSURE_SCROLLBACK * 2 {
+                terminal.print(&format!("line {}", i));
+            }
+        });
+        let lines = || TERMINALS.with(|t| t.borrow()[&id].line_count());
+
+        assert_eq!(trim_scrollback(PressureLevel::Normal), None);
+        assert!(trim_scrollback(PressureLevel::Moderate).is_some());
+        assert_eq!(lines(), PRESSURE_SCROLLBACK);
+        assert_eq!(trim_scrollback(PressureLevel::Moderate), None);
+        assert!(trim_scrollback(PressureLevel::Critical).is_some());
+        assert!(lines() < PRESSURE_SCROLLBACK);
+    }
+
+    #[test]
+    fn test_osc_requests() {
+        setup();
+        let id = open(TaskId(1), None).unwrap();
+        let run = |line: &str| {
+            for c in line.chars() {
+                let key = c.to_string();
+                handle_key(id, &key, &key, false, false);
+            }
+            handle_key(id, "Enter", "Enter", false, false);
+        };
+        let window = || {
+            COMPOSITOR
+                .with(|c| c.borrow().get_window(id).cloned())
+                .unwrap()
+        };
+
+        run("echo -e '\\e]0;build\\a'");
+        assert_eq!(window().title, "build");
+        run("echo -e 'x\\e]52;c;Y29waWVk\\e\\\\y'");
+        assert_eq!(syscall::read_file("/dev/clipboard").unwrap(), "copied");
+        assert!(window().content.iter().any(|l| l == "xy"));
+
+        run("echo -e '\\e]2;\\a'");
+        assert_eq!(
+            window().title,
+            format!("{} ({})", TERMINAL_TITLE, profile::DEFAULT_PROFILE)
+        );
+    }
+
+    #[test]
+    fn test_mouse_reporting() {
+        use crate::kernel::tty::{MouseAction, MouseButton};
+        use std::io::Read;
+
+        setup();
+        let id = open(TaskId(1), None).unwrap();
+        let run = |line: &str| {
+            for c in line.chars() {
+                let key = c.to_string();
+                handle_key(id, &key, &key, false, false);
+            }
+            handle_key(id, "Enter", "Enter", false, false);
+        };
+        let input = || {
+            let mut buf = [0u8; 64];
+            let n = KERNEL.with(|k| {
+                k.borrow_mut()
+                    .console()
+                    .unwrap()
+                    .read(&mut buf)
+                    .unwrap_or(0)
+            });
+            String::from_utf8_lossy(&buf[..n]).into_owned()
+        };
+        let press = MouseEvent::new(MouseAction::Press(MouseButton::Left), 5, 3);
+        let selecting = || TERMINALS.with(|t| t.borrow()[&id].selection().is_some());
+
+        // A program that asks for drags in SGR form gets them as input
+        run("echo -e '\\e[?1002;1006h'");
+        assert!(handle_mouse(id, &press));
+        let drag = MouseEvent::new(MouseAction::Drag(MouseButton::Left), 6, 3);
+        assert!(handle_mouse(id, &drag));
+        assert_eq!(input(), "\x1b[<0;5;3M\x1b[<32;6;3M");
+        assert!(!selecting());
+
+        // Once it turns reporting off, the mouse selects again
+        run("echo -e '\\e[?1002l'");
+        assert!(handle_mouse(id, &press));
+        assert!(selecting());
+        assert_eq!(input(), "");
+    }
+}
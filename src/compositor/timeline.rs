@@ -0,0 +1,791 @@
+//! Timeline - scheduler/compositor timeline window
+//!
+//! Plots the tracer's spans on a shared time axis: one track for frames
+//! (executor ticks), one per task showing its polls, and one per process
+//! showing its syscalls. Spans come from `syscall::trace_events()`, so the
+//! tracer must be enabled and a clock set with `kernel::set_clock`.
+//!
+//! Frames longer than [`FRAME_BUDGET_MS`] are drawn red and shaded across
+//! every track, which makes it easy to see which poll or syscall blew the
+//! budget. Hovering a span shows its details in the window text. Task
+//! tracks are labelled with names from the kernel symbol map.
+//!
+//! Keys: `+`/`=` zoom in, `-` zoom out, Left/Right (or h/l) pan, `0`/`f`
+//! fit everything. The mouse wheel zooms around the pointer.
+
+use super::{
+    COMPOSITOR, Color, FontMetrics, Rect, TextLayout, TextLayoutOptions, TextWrap, WindowId,
+    layout_text,
+};
+use crate::kernel::syscall;
+use crate::kernel::{SymbolMap, TaskId, TraceCategory, TraceEvent};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Window title
+pub const TIMELINE_TITLE: &str = "Timeline";
+
+/// Time budget for one frame at 60 fps, in milliseconds
+pub const FRAME_BUDGET_MS: f64 = 1000.0 / 60.0;
+
+/// Width of the track label column
+const LABEL_WIDTH: f64 = 96.0;
+/// Height of the time axis above the tracks
+const AXIS_HEIGHT: f64 = 16.0;
+/// Height of one track
+const TRACK_HEIGHT: f64 = 18.0;
+/// Gap between tracks
+const TRACK_GAP: f64 = 2.0;
+/// Smallest visible time range, in milliseconds
+const MIN_VIEW_SPAN: f64 = 0.01;
+/// Font size for labels
+const FONT_SIZE: f64 = 11.0;
+/// Number of time axis ticks
+const AXIS_TICKS: usize = 5;
+
+const BACKGROUND: Color = Color::rgb(0.08, 0.08, 0.11);
+const TEXT: Color = Color::rgb(0.85, 0.85, 0.9);
+const TRACK_BG: Color = Color::rgb(0.12, 0.12, 0.16);
+const FRAME_OK: Color = Color::rgb(0.25, 0.65, 0.35);
+const FRAME_SLOW: Color = Color::rgb(0.85, 0.25, 0.25);
+const POLL: Color = Color::rgb(0.3, 0.5, 0.85);
+const SYSCALL: Color = Color::rgb(0.8, 0.6, 0.25);
+const SYSCALL_ERR: Color = Color::rgb(0.85, 0.35, 0.6);
+const HOVER: Color = Color::rgb(1.0, 1.0, 1.0);
+const OVER_BUDGET: Color = Color::new(0.85, 0.2, 0.2, 0.18);
+const BOUNDARY: Color = Color::new(1.0, 1.0, 1.0, 0.15);
+
+/// What a track shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum TrackKind {
+    /// Executor ticks
+    Frames,
+    /// Polls of one executor task
+    Task(u64),
+    /// Syscalls made by one process
+    Process(u32),
+}
+
+/// One span on a track
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub start: f64,
+    pub end: f64,
+    pub name: String,
+    pub detail: Option<String>,
+    pub pid: Option<u32>,
+    pub task: Option<u64>,
+}
+
+impl Span {
+    fn from_event(event: &TraceEvent) -> Self {
+        Self {
+            start: event.timestamp,
+            end: event.timestamp + event.duration.unwrap_or(0.0),
+            name: event.name.clone(),
+            detail: event.detail.clone(),
+            pid: event.pid,
+            task: event.task,
+        }
+    }
+
+    /// Span length in milliseconds
+    pub fn duration(&self) -> f64 {
+        self.end - self.start
+    }
+
+    /// Check if this frame span ran over [`FRAME_BUDGET_MS`]
+    pub fn over_budget(&self) -> bool {
+        self.duration() > FRAME_BUDGET_MS
+    }
+}
+
+/// A row of spans
+#[derive(Debug, Clone, PartialEq)]
+pub struct Track {
+    pub kind: TrackKind,
+    pub spans: Vec<Span>,
+    /// Name of the task, from the symbol map
+    pub name: Option<String>,
+}
+
+impl Track {
+    /// Label drawn in the track's label column
+    pub fn label(&self) -> String {
+        match self.kind {
+            TrackKind::Frames => "frames".to_string(),
+            TrackKind::Task(task) => match (&self.name, self.spans.iter().find_map(|s| s.pid)) {
+                (Some(name), Some(pid)) => format!("{} (pid {})", name, pid),
+                (Some(name), None) => format!("{} (task {})", name, task),
+                (None, Some(pid)) => format!("task {} (pid {})", task, pid),
+                (None, None) => format!("task {}", task),
+            },
+            TrackKind::Process(pid) => format!("pid {} syscalls", pid),
+        }
+    }
+}
+
+/// A shape to draw, in window coordinates
+#[derive(Debug, Clone)]
+pub enum DrawOp {
+    Rect { rect: Rect, color: Color },
+    Text { layout: TextLayout, color: Color },
+}
+
+/// What the pointer is over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HoverTarget {
+    pub track: usize,
+    pub span: usize,
+}
+
+/// Timeline state for one window
+#[derive(Debug, Clone)]
+pub struct Timeline {
+    tracks: Vec<Track>,
+    symbols: SymbolMap,
+    view_start: f64,
+    view_span: f64,
+    bounds: Rect,
+    hover: Option<HoverTarget>,
+}
+
+impl Timeline {
+    /// Build a timeline from trace events, fitted to all of them
+    pub fn new(events: &[TraceEvent]) -> Self {
+        let mut timeline = Self {
+            tracks: Vec::new(),
+            symbols: SymbolMap::new(),
+            view_start: 0.0,
+            view_span: FRAME_BUDGET_MS,
+            bounds: Rect::new(0.0, 0.0, 640.0, 240.0),
+            hover: None,
+        };
+        timeline.load(events);
+        timeline.fit();
+        timeline
+    }
+
+    /// Replace the spans with ones from `events`, keeping the view
+    pub fn load(&mut self, events: &[TraceEvent]) {
+        let mut tracks: BTreeMap<TrackKind, Vec<Span>> = BTreeMap::new();
+        for event in events {
+            let kind = match (event.category, event.task, event.pid) {
+                (TraceCategory::Compositor, _, _) if event.name == "frame" => TrackKind::Frames,
+                (TraceCategory::Scheduler, Some(task), _) => TrackKind::Task(task),
+                (TraceCategory::Syscall, _, Some(pid)) if event.duration.is_some() => {
+                    TrackKind::Process(pid)
+                }
+                _ => continue,
+            };
+            tracks
+                .entry(kind)
+                .or_default()
+                .push(Span::from_event(event));
+        }
+        self.tracks = tracks
+            .into_iter()
+            .map(|(kind, spans)| Track {
+                kind,
+                spans,
+                name: None,
+            })
+            .collect();
+        self.name_tracks();
+        self.hover = None;
+    }
+
+    /// Use `symbols` to name task tracks, now and after each load
+    pub fn set_symbols(&mut self, symbols: SymbolMap) {
+        self.symbols = symbols;
+        self.name_tracks();
+    }
+
+    fn name_tracks(&mut self) {
+        for track in &mut self.tracks {
+            if let TrackKind::Task(task) = track.kind {
+                track.name = self.symbols.task_name(TaskId(task)).map(String::from);
+            }
+        }
+    }
+
+    /// All tracks, frames first
+    pub fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+
+    /// The frame track's spans
+    pub fn frames(&self) -> &[Span] {
+        self.tracks
+            .iter()
+            .find(|t| t.kind == TrackKind::Frames)
+            .map_or(&[], |t| &t.spans)
+    }
+
+    /// Visible time range as (start, end) in milliseconds
+    pub fn view(&self) -> (f64, f64) {
+        (self.view_start, self.view_start + self.view_span)
+    }
+
+    /// Set the area the timeline is drawn in
+    pub fn set_bounds(&mut self, bounds: Rect) {
+        self.bounds = bounds;
+    }
+
+    /// The span area, right of the label column and below the axis
+    fn plot(&self) -> Rect {
+        Rect::new(
+            self.bounds.x + LABEL_WIDTH,
+            self.bounds.y + AXIS_HEIGHT,
+            (self.bounds.width - LABEL_WIDTH).max(1.0),
+            (self.bounds.height - AXIS_HEIGHT).max(0.0),
+        )
+    }
+
+    /// X coordinate of time `t`
+    pub fn x_at(&self, t: f64) -> f64 {
+        let plot = self.plot();
+        plot.x + (t - self.view_start) / self.view_span * plot.width
+    }
+
+    /// Time at X coordinate `x`
+    pub fn time_at(&self, x: f64) -> f64 {
+        let plot = self.plot();
+        self.view_start + (x - plot.x) / plot.width * self.view_span
+    }
+
+    /// Zoom by `factor` (> 1 zooms in), keeping the time under `anchor_x` fixed
+    pub fn zoom(&mut self, factor: f64, anchor_x: f64) {
+        if factor <= 0.0 {
+            return;
+        }
+        let anchor = self.time_at(anchor_x);
+        let plot = self.plot();
+        let frac = (anchor_x - plot.x) / plot.width;
+        self.view_span = (self.view_span / factor).max(MIN_VIEW_SPAN);
+        self.view_start = anchor - frac * self.view_span;
+    }
+
+    /// Pan by a fraction of the visible range (negative pans left)
+    pub fn pan(&mut self, fraction: f64) {
+        self.view_start += fraction * self.view_span;
+    }
+
+    /// Show every span
+    pub fn fit(&mut self) {
+        let spans = || self.tracks.iter().flat_map(|t| &t.spans);
+        let start = spans().map(|s| s.start).fold(f64::INFINITY, f64::min);
+        let end = spans().map(|s| s.end).fold(f64::NEG_INFINITY, f64::max);
+        if start.is_finite() && end.is_finite() {
+            self.view_start = start;
+            self.view_span = (end - start).max(MIN_VIEW_SPAN);
+        }
+    }
+
+    /// Vertical extent of track `index`
+    fn track_rect(&self, index: usize) -> Rect {
+        let plot = self.plot();
+        Rect::new(
+            plot.x,
+            plot.y + index as f64 * (TRACK_HEIGHT + TRACK_GAP),
+            plot.width,
+            TRACK_HEIGHT,
+        )
+    }
+
+    /// Find the span at a point
+    pub fn hit(&self, x: f64, y: f64) -> Option<HoverTarget> {
+        let track = (0..self.tracks.len()).find(|&i| {
+            let r = self.track_rect(i);
+            y >= r.y && y < r.y + r.height
+        })?;
+        if x < self.plot().x {
+            return None;
+        }
+        // Spans narrower than a pixel are drawn one pixel wide; hit them too
+        let t = self.time_at(x);
+        let slop = self.view_span / self.plot().width;
+        let span = self.tracks[track]
+            .spans
+            .iter()
+            .rposition(|s| t >= s.start - slop && t <= s.end + slop)?;
+        Some(HoverTarget { track, span })
+    }
+
+    /// Update the hovered span from a pointer position
+    pub fn hover(&mut self, x: f64, y: f64) -> Option<HoverTarget> {
+        self.hover = self.hit(x, y);
+        self.hover
+    }
+
+    /// The hovered span
+    pub fn hovered(&self) -> Option<&Span> {
+        let target = self.hover?;
+        self.tracks.get(target.track)?.spans.get(target.span)
+    }
+
+    /// Description of the hovered span
+    pub fn hover_text(&self) -> Option<String> {
+        let target = self.hover?;
+        let track = self.tracks.get(target.track)?;
+        let span = track.spans.get(target.span)?;
+        let mut text = format!(
+            "{} {}: {:.3} ms at {:.3} ms",
+            track.label(),
+            span.name,
+            span.duration(),
+            span.start
+        );
+        if track.kind == TrackKind::Frames {
+            let budget = span.duration() / FRAME_BUDGET_MS * 100.0;
+            text.push_str(&format!(" ({:.0}% of budget)", budget));
+        }
+        if let Some(detail) = &span.detail {
+            text.push_str(&format!(" - {}", detail));
+        }
+        Some(text)
+    }
+
+    /// Handle a key press (DOM key names); returns true if it was used
+    pub fn handle_key(&mut self, key: &str) -> bool {
+        let plot = self.plot();
+        let center = plot.x + plot.width / 2.0;
+        match key {
+            "+" | "=" => self.zoom(2.0, center),
+            "-" => self.zoom(0.5, center),
+            "ArrowLeft" | "h" => self.pan(-0.25),
+            "ArrowRight" | "l" => self.pan(0.25),
+            "0" | "f" => self.fit(),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Handle a mouse wheel event at `x`; negative `delta_y` zooms in
+    pub fn handle_wheel(&mut self, x: f64, delta_y: f64) {
+        let factor = if delta_y < 0.0 { 1.25 } else { 0.8 };
+        self.zoom(factor, x);
+    }
+
+    /// Frames in view that ran over budget
+    fn slow_frames(&self) -> impl Iterator<Item = &Span> {
+        let (start, end) = self.view();
+        self.frames()
+            .iter()
+            .filter(move |f| f.over_budget() && f.end >= start && f.start <= end)
+    }
+
+    /// Status lines for the window text
+    pub fn lines(&self) -> Vec<String> {
+        let (start, end) = self.view();
+        let in_view = self
+            .frames()
+            .iter()
+            .filter(|f| f.end >= start && f.start <= end);
+        let frames = in_view.clone().count();
+        let worst = in_view.map(Span::duration).fold(0.0, f64::max);
+        let mut lines = vec![
+            format!(
+                "{:.3}-{:.3} ms ({:.3} ms) | {} tracks",
+                start,
+                end,
+                self.view_span,
+                self.tracks.len()
+            ),
+            format!(
+                "{} frames, {} over budget ({:.1} ms), worst {:.2} ms",
+                frames,
+                self.slow_frames().count(),
+                FRAME_BUDGET_MS,
+                worst
+            ),
+        ];
+        if self.tracks.is_empty() {
+            lines.push("no spans: enable tracing and set a clock".to_string());
+        }
+        if let Some(hover) = self.hover_text() {
+            lines.push(hover);
+        }
+        lines
+    }
+
+    /// Shapes for the current view
+    pub fn draw(&self) -> Vec<DrawOp> {
+        let mut ops = vec![DrawOp::Rect {
+            rect: self.bounds,
+            color: BACKGROUND,
+        }];
+        let plot = self.plot();
+        let (view_start, view_end) = self.view();
+        let text_opts = TextLayoutOptions {
+            font_size: FONT_SIZE,
+            wrap: TextWrap::None,
+            ..TextLayoutOptions::default()
+        };
+        let text = |ops: &mut Vec<DrawOp>, s: &str, rect: Rect| {
+            ops.push(DrawOp::Text {
+                layout: layout_text(s, rect, &text_opts),
+                color: TEXT,
+            });
+        };
+
+        // Time axis
+        let label_width = FontMetrics::monospace(FONT_SIZE).average_width * 10.0;
+        for i in 0..AXIS_TICKS {
+            let t = view_start + self.view_span * i as f64 / (AXIS_TICKS - 1) as f64;
+            let x = self.x_at(t).min(plot.x + plot.width - label_width);
+            text(
+                &mut ops,
+                &format!("{:.2}ms", t),
+                Rect::new(x, self.bounds.y, label_width, AXIS_HEIGHT),
+            );
+        }
+
+        let tracks_height = self.tracks.len() as f64 * (TRACK_HEIGHT + TRACK_GAP);
+        let clip = |start: f64, end: f64| -> Option<(f64, f64)> {
+            if end < view_start || start > view_end {
+                return None;
+            }
+            let x0 = self.x_at(start).max(plot.x);
+            let x1 = self.x_at(end).min(plot.x + plot.width);
+            Some((x0, (x1 - x0).max(1.0)))
+        };
+
+        for (i, track) in self.tracks.iter().enumerate() {
+            let row = self.track_rect(i);
+            text(
+                &mut ops,
+                &track.label(),
+                Rect::new(self.bounds.x, row.y, LABEL_WIDTH, TRACK_HEIGHT),
+            );
+            ops.push(DrawOp::Rect {
+                rect: row,
+                color: TRACK_BG,
+            });
+            for (j, span) in track.spans.iter().enumerate() {
+                let Some((x, width)) = clip(span.start, span.end) else {
+                    continue;
+                };
+                let hovered = self.hover == Some(HoverTarget { track: i, span: j });
+                let color = match track.kind {
+                    _ if hovered => HOVER,
+                    TrackKind::Frames if span.over_budget() => FRAME_SLOW,
+                    TrackKind::Frames => FRAME_OK,
+                    TrackKind::Task(_) => POLL,
+                    TrackKind::Process(_) if span.detail.is_some() => SYSCALL_ERR,
+                    TrackKind::Process(_) => SYSCALL,
+                };
+                ops.push(DrawOp::Rect {
+                    rect: Rect::new(x, row.y + 1.0, width, TRACK_HEIGHT - 2.0),
+                    color,
+                });
+            }
+        }
+
+        // Frame boundaries and the budget overlay span every track
+        for frame in self.frames() {
+            if frame.start >= view_start && frame.start <= view_end {
+                ops.push(DrawOp::Rect {
+                    rect: Rect::new(self.x_at(frame.start), plot.y, 1.0, tracks_height),
+                    color: BOUNDARY,
+                });
+            }
+        }
+        for frame in self.slow_frames() {
+            let over = frame.start + FRAME_BUDGET_MS;
+            if let Some((x, width)) = clip(over, frame.end) {
+                ops.push(DrawOp::Rect {
+                    rect: Rect::new(x, plot.y, width, tracks_height),
+                    color: OVER_BUDGET,
+                });
+            }
+        }
+
+        ops
+    }
+}
+
+thread_local! {
+    /// Open timeline windows
+    static TIMELINES: RefCell<HashMap<WindowId, Timeline>> = RefCell::new(HashMap::new());
+}
+
+/// Copy a timeline's status lines into its window
+fn show(id: WindowId, timeline: &Timeline) {
+    COMPOSITOR.with(|c| {
+        if let Some(window) = c.borrow_mut().get_window_mut(id) {
+            window.content = timeline.lines();
+            window.dirty = true;
+        }
+    });
+}
+
+/// Content area of a window
+fn content_rect(id: WindowId) -> Option<Rect> {
+    COMPOSITOR.with(|c| c.borrow().get_window(id).map(|w| w.content_rect()))
+}
+
+/// Run `f` on the timeline in `window` and refresh its text
+fn with_timeline<R>(window: WindowId, f: impl FnOnce(&mut Timeline) -> R) -> Option<R> {
+    let bounds = content_rect(window);
+    TIMELINES.with(|t| {
+        let mut timelines = t.borrow_mut();
+        let timeline = timelines.get_mut(&window)?;
+        if let Some(bounds) = bounds {
+            timeline.set_bounds(bounds);
+        }
+        let result = f(timeline);
+        show(window, timeline);
+        Some(result)
+    })
+}
+
+/// Open a new timeline window over the tracer's events
+pub fn open(owner: TaskId) -> WindowId {
+    let id = COMPOSITOR.with(|c| c.borrow_mut().create_window(TIMELINE_TITLE, owner));
+    let mut timeline = Timeline::new(&syscall::trace_events());
+    timeline.set_symbols(syscall::ksyms());
+    if let Some(bounds) = content_rect(id) {
+        timeline.set_bounds(bounds);
+    }
+    show(id, &timeline);
+    TIMELINES.with(|t| t.borrow_mut().insert(id, timeline));
+    id
+}
+
+/// Reload the tracer's events into every open timeline
+///
+/// Timelines whose window has been closed are dropped.
+pub fn refresh() {
+    let open: HashSet<WindowId> =
+        COMPOSITOR.with(|c| c.borrow().window_ids().into_iter().collect());
+    TIMELINES.with(|t| {
+        let mut timelines = t.borrow_mut();
+        timelines.retain(|id, _| open.contains(id));
+        if timelines.is_empty() {
+            return;
+        }
+        let events = syscall::trace_events();
+        let symbols = syscall::ksyms();
+        for (id, timeline) in timelines.iter_mut() {
+            timeline.set_symbols(symbols.clone());
+            timeline.load(&events);
+            show(*id, timeline);
+        }
+    });
+}
+
+/// Route a key press to the timeline in `window`; returns true if handled
+pub fn handle_key(window: WindowId, key: &str) -> bool {
+    with_timeline(window, |t| t.handle_key(key)).unwrap_or(false)
+}
+
+/// Update hover details for a pointer at (x, y)
+pub fn handle_mouse_move(window: WindowId, x: f64, y: f64) -> Option<HoverTarget> {
+    with_timeline(window, |t| t.hover(x, y)).flatten()
+}
+
+/// Zoom the timeline in `window` around `x`
+pub fn handle_wheel(window: WindowId, x: f64, delta_y: f64) -> bool {
+    with_timeline(window, |t| t.handle_wheel(x, delta_y)).is_some()
+}
+
+/// Shapes for the timeline in `window`, drawn in `bounds`
+///
+/// Does not touch the compositor, so it can be called while rendering.
+pub fn draw_ops(window: WindowId, bounds: Rect) -> Vec<DrawOp> {
+    TIMELINES.with(|t| {
+        let mut timelines = t.borrow_mut();
+        match timelines.get_mut(&window) {
+            Some(timeline) => {
+                timeline.set_bounds(bounds);
+                timeline.draw()
+            }
+            None => Vec::new(),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::syscall::{KERNEL, Kernel};
+    use crate::kernel::{PollSpan, Priority};
+
+    fn frame(start: f64, len: f64) -> TraceEvent {
+        TraceEvent::instant(start, TraceCategory::Compositor, "frame").with_duration(len)
+    }
+
+    fn poll(task: u64, start: f64, len: f64) -> TraceEvent {
+        TraceEvent::instant(start, TraceCategory::Scheduler, "poll")
+            .with_task(task)
+            .with_duration(len)
+    }
+
+    fn sample() -> Timeline {
+        let events = vec![
+            frame(0.0, 10.0),
+            poll(1, 1.0, 4.0),
+            TraceEvent::instant(2.0, TraceCategory::Syscall, "open")
+                .with_pid(3)
+                .with_duration(1.0),
+            frame(20.0, 30.0),
+            poll(2, 21.0, 25.0),
+            // Instant custom events are not plotted
+            TraceEvent::instant(5.0, TraceCategory::Custom, "mark"),
+        ];
+        let mut timeline = Timeline::new(&events);
+        timeline.set_bounds(Rect::new(0.0, 0.0, LABEL_WIDTH + 500.0, 200.0));
+        timeline
+    }
+
+    #[test]
+    fn test_tracks_and_budget_overlay() {
+        let timeline = sample();
+        let kinds: Vec<_> = timeline.tracks().iter().map(|t| t.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TrackKind::Frames,
+                TrackKind::Task(1),
+                TrackKind::Task(2),
+                TrackKind::Process(3)
+            ]
+        );
+        assert_eq!(timeline.view(), (0.0, 50.0));
+        assert!(timeline.lines()[1].starts_with("2 frames, 1 over budget"));
+
+        let ops = timeline.draw();
+        let rect_of = |color: Color| {
+            ops.iter().find_map(|op| match op {
+                DrawOp::Rect { rect, color: c } if *c == color => Some(*rect),
+                _ => None,
+            })
+        };
+        // The overlay starts where the slow frame exceeds its budget
+        let overlay = rect_of(OVER_BUDGET).unwrap();
+        assert!((overlay.x - timeline.x_at(20.0 + FRAME_BUDGET_MS)).abs() < 1e-9);
+        assert!((overlay.height - 4.0 * (TRACK_HEIGHT + TRACK_GAP)).abs() < 1e-9);
+        assert!(rect_of(FRAME_OK).is_some());
+        assert!(rect_of(FRAME_SLOW).is_some());
+        assert!(ops.iter().any(|op| matches!(
+            op,
+            DrawOp::Text { layout, .. } if layout.text == "task 1"
+        )));
+    }
+
+    #[test]
+    fn test_zoom_pan_and_hover() {
+        let mut timeline = sample();
+        let anchor = timeline.x_at(20.0);
+        timeline.zoom(4.0, anchor);
+        let (start, end) = timeline.view();
+        assert!((end - start - 12.5).abs() < 1e-9);
+        assert!((timeline.time_at(anchor) - 20.0).abs() < 1e-9);
+
+        timeline.pan(-1.0);
+        assert!((timeline.view().0 - (start - 12.5)).abs() < 1e-9);
+        assert!(timeline.handle_key("0"));
+        assert_eq!(timeline.view(), (0.0, 50.0));
+        assert!(!timeline.handle_key("x"));
+
+        // Hover the slow frame
+        let y = timeline.track_rect(0).y + 1.0;
+        let hit = timeline.hover(timeline.x_at(30.0), y).unwrap();
+        assert_eq!(hit, HoverTarget { track: 0, span: 1 });
+        let text = timeline.hover_text().unwrap();
+        assert!(text.starts_with("frames frame: 30.000 ms at 20.000 ms"));
+        assert!(text.contains("180% of budget"));
+        assert_eq!(timeline.lines().last().unwrap(), &text);
+
+        // The label column and gaps between spans hit nothing
+        assert!(timeline.hover(1.0, y).is_none());
+        assert!(timeline.hover(timeline.x_at(15.0), y).is_none());
+        assert!(timeline.hovered().is_none());
+    }
+
+    #[test]
+    fn test_kernel_frame_events() {
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+            k.get_process_mut(pid).unwrap().task = Some(TaskId(7));
+            k.trace_enable();
+        });
+
+        syscall::pipe().unwrap();
+        syscall::trace_frame(
+            100.0,
+            120.0,
+            &[PollSpan {
+                task: TaskId(7),
+                priority: Priority::Normal,
+                start: 101.0,
+                end: 119.0,
+                completed: false,
+            }],
+        );
+
+        let timeline = Timeline::new(&syscall::trace_events());
+        let kinds: Vec<_> = timeline.tracks().iter().map(|t| t.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![TrackKind::Frames, TrackKind::Task(7), TrackKind::Process(1)]
+        );
+        assert_eq!(timeline.tracks()[1].label(), "task 7 (pid 1)");
+        assert_eq!(timeline.tracks()[2].spans[0].name, "pipe");
+        assert!(timeline.frames()[0].over_budget());
+
+        // Named from the symbol map: by process, or by kernel task name
+        syscall::name_task(TaskId(9), "indexer");
+        syscall::trace_frame(
+            130.0,
+            135.0,
+            &[PollSpan {
+                task: TaskId(9),
+                priority: Priority::Idle,
+                start: 131.0,
+                end: 132.0,
+                completed: false,
+            }],
+        );
+        let mut timeline = Timeline::new(&syscall::trace_events());
+        timeline.set_symbols(syscall::ksyms());
+        let labels: Vec<_> = timeline.tracks().iter().map(|t| t.label()).collect();
+        assert_eq!(
+            labels,
+            [
+                "frames",
+                "test (pid 1)",
+                "indexer (task 9)",
+                "pid 1 syscalls"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_window_functions() {
+        KERNEL.with(|k| *k.borrow_mut() = Kernel::new());
+        COMPOSITOR.with(|c| *c.borrow_mut() = super::super::Compositor::new());
+        let id = open(TaskId(1));
+        let content =
+            |id| COMPOSITOR.with(|c| c.borrow().get_window(id).map(|w| w.content.clone()));
+        assert!(content(id).unwrap()[2].starts_with("no spans"));
+
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            k.trace_enable();
+            k.trace_frame(0.0, 5.0, &[]);
+        });
+        refresh();
+        assert!(content(id).unwrap()[1].starts_with("1 frames, 0 over budget"));
+
+        assert!(handle_key(id, "+"));
+        assert!(handle_wheel(id, 0.0, -1.0));
+        assert!(!handle_key(WindowId(999), "+"));
+        assert!(!draw_ops(id, Rect::new(0.0, 0.0, 300.0, 100.0)).is_empty());
+        assert!(draw_ops(WindowId(999), Rect::default()).is_empty());
+    }
+}
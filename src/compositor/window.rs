@@ -4,6 +4,7 @@
 
 use super::geometry::Rect;
 use crate::kernel::TaskId;
+use crate::shell::osc::{Progress, ProgressState};
 
 /// Unique identifier for a window
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -85,6 +86,8 @@ pub struct Window {
     pub scroll_offset: usize,
     /// Needs redraw
     pub dirty: bool,
+    /// Progress a program in the window is reporting, shown as a badge
+    pub progress: Option<Progress>,
 }
 
 impl Window {
@@ -99,6 +102,7 @@ impl Window {
             content: Vec::new(),
             scroll_offset: 0,
             dirty: true,
+            progress: None,
         }
     }
 
@@ -113,6 +117,7 @@ impl Window {
             content: Vec::new(),
             scroll_offset: 0,
             dirty: true,
+            progress: None,
         }
     }
 
@@ -122,6 +127,9 @@ impl Window {
     /// Border width in pixels
     pub const BORDER_WIDTH: f64 = 2.0;
 
+    /// Height of the progress badge in pixels
+    pub const BADGE_HEIGHT: f64 = 3.0;
+
     /// Get the content area (inside decorations)
     pub fn content_rect(&self) -> Rect {
         if self.flags.decorated {
@@ -179,6 +187,31 @@ impl Window {
         self.dirty = true;
     }
 
+    /// Show `progress` as the window's badge, or remove it
+    pub fn set_progress(&mut self, progress: Option<Progress>) {
+        if self.progress != progress {
+            self.progress = progress;
+            self.dirty = true;
+        }
+    }
+
+    /// The badge: a strip along the bottom of the title bar, as wide as
+    /// the share done
+    pub fn progress_rect(&self) -> Option<Rect> {
+        let progress = self.progress.filter(|_| self.flags.decorated)?;
+        let titlebar = self.titlebar_rect();
+        let width = match progress.state {
+            ProgressState::Indeterminate => titlebar.width,
+            _ => titlebar.width * f64::from(progress.percent) / 100.0,
+        };
+        Some(Rect::new(
+            titlebar.x,
+            titlebar.y + titlebar.height - Self::BADGE_HEIGHT,
+            width,
+            Self::BADGE_HEIGHT,
+        ))
+    }
+
     /// Scroll up by n lines
     pub fn scroll_up(&mut self, lines: usize) {
         self.scroll_offset = self.scroll_offset.saturating_sub(lines);
@@ -261,6 +294,23 @@ mod tests {
         assert_eq!(content.width, 200.0 - 2.0 * Window::BORDER_WIDTH);
     }
 
+    #[test]
+    fn test_progress_badge() {
+        let mut window = Window::new(WindowId(1), "Test".to_string(), TaskId(1));
+        window.rect = Rect::new(10.0, 10.0, 200.0, 150.0);
+        assert_eq!(window.progress_rect(), None);
+
+        window.dirty = false;
+        window.set_progress(Some(Progress::new(25)));
+        assert!(window.dirty);
+        let badge = window.progress_rect().unwrap();
+        assert_eq!(badge.width, 50.0);
+        assert_eq!(badge.y + badge.height, 10.0 + Window::TITLEBAR_HEIGHT);
+
+        window.flags.decorated = false;
+        assert_eq!(window.progress_rect(), None);
+    }
+
     #[test]
     fn test_titlebar_detection() {
         let mut window = Window::new(WindowId(1), "Test".to_string(), TaskId(1));
@@ -0,0 +1,133 @@
+//! Editor settings
+//!
+//! Each user can pick the editor's key bindings in `~/.config/editor.toml`:
+//!
+//! ```toml
+//! # "default" for the Ctrl-key bindings, "vi" for modal editing
+//! keymap = "vi"
+//! # Underline misspelled words in Markdown and text files
+//! spell = true
+//! ```
+//!
+//! A missing file means the defaults.
+
+use crate::kernel::syscall;
+use crate::shell::profile::{parse_value, strip_comment};
+
+/// Settings file, relative to the home directory
+pub const CONFIG_FILE: &str = ".config/editor.toml";
+
+/// Key bindings the editor uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Keymap {
+    /// Ctrl-key bindings, always in insert mode
+    #[default]
+    Default,
+    /// vi-style normal, insert and visual modes
+    Vi,
+}
+
+impl Keymap {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "default" => Some(Self::Default),
+            "vi" => Some(Self::Vi),
+            _ => None,
+        }
+    }
+}
+
+/// Editor settings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    pub keymap: Keymap,
+    /// Spell check prose files
+    pub spell: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            keymap: Keymap::default(),
+            spell: true,
+        }
+    }
+}
+
+impl Config {
+    /// Parse a settings file
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let mut config = Self::default();
+        for (i, raw) in content.lines().enumerate() {
+            let line = strip_comment(raw).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let err = |msg: String| format!("line {}: {}", i + 1, msg);
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| err(format!("expected key = value, got '{}'", line)))?;
+            let value = value.trim();
+            match key.trim() {
+                "keymap" => {
+                    let value = parse_value(value).map_err(&err)?;
+                    config.keymap = Keymap::from_name(&value)
+                        .ok_or_else(|| err(format!("unknown keymap '{}'", value)))?;
+                }
+                "spell" => {
+                    config.spell = match value {
+                        "true" => true,
+                        "false" => false,
+                        _ => {
+                            return Err(err(format!("spell must be true or false, got {}", value)));
+                        }
+                    };
+                }
+                key => return Err(err(format!("unknown key '{}'", key))),
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// The current user's settings file
+pub fn config_path() -> String {
+    let home = syscall::getenv("HOME")
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| "/tmp".to_string());
+    format!("{}/{}", home.trim_end_matches('/'), CONFIG_FILE)
+}
+
+/// Load the current user's settings
+pub fn load() -> Result<Config, String> {
+    let path = config_path();
+    match syscall::read_file(&path) {
+        Ok(content) => Config::parse(&content).map_err(|e| format!("{}: {}", path, e)),
+        Err(_) => Ok(Config::default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        assert_eq!(Config::parse("").unwrap().keymap, Keymap::Default);
+        let config = Config::parse("# bindings\nkeymap = \"vi\" # modal\n").unwrap();
+        assert_eq!(config.keymap, Keymap::Vi);
+        assert!(config.spell);
+        assert!(!Config::parse("spell = false").unwrap().spell);
+        assert!(Config::parse("spell = \"maybe\"").is_err());
+        assert_eq!(
+            Config::parse("keymap = \"emacs\""),
+            Err("line 1: unknown keymap 'emacs'".to_string())
+        );
+        assert!(
+            Config::parse("\ntheme = \"dark\"")
+                .unwrap_err()
+                .starts_with("line 2")
+        );
+    }
+}
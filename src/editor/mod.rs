@@ -10,46 +10,91 @@
 //! - Ctrl+G: Go to line
 //! - Ctrl+D: Duplicate line
 //! - Ctrl+K: Delete line
+//! - Ctrl+E: Next misspelled word, with corrections
+//! - Ctrl+Y: Add the word at the cursor to the dictionary
 //! - Arrows: Move cursor
 //! - Ctrl+Arrows: Move by word
 //! - Home/End: Start/end of line
 //! - Page Up/Down: Scroll
+//!
+//! `keymap = "vi"` in `~/.config/editor.toml` switches to vi-style modal
+//! editing instead (see [`vi`]).
+//!
+//! Markdown and text files are spell checked as they are edited (see
+//! [`spell`]).
+//!
+//! Unsaved changes are kept in a swap file (see [`crate::shell::swap`]),
+//! written a couple of seconds after the last edit and when a task
+//! crashes. Opening a file that still has one offers to recover it.
 
 #![cfg(target_arch = "wasm32")]
 
 use std::cell::RefCell;
+use std::sync::Once;
+
+use crate::kernel::{crash, syscall};
+use crate::shell::programs::services::ago;
+use crate::shell::stats;
+use crate::shell::swap::{self, Swap};
 
-use crate::kernel::syscall;
+pub mod keymap;
+pub mod spell;
+pub mod vi;
+
+pub use keymap::Keymap;
+use spell::Spell;
 
 // Global editor state
 thread_local! {
     static EDITOR: RefCell<Option<Editor>> = RefCell::new(None);
-    static EDITOR_ACTIVE: RefCell<bool> = RefCell::new(false);
+    // Virtual console the editor is open on
+    static EDITOR_CONSOLE: RefCell<Option<usize>> = RefCell::new(None);
 }
 
-/// Check if editor is currently active
+/// Check if editor is currently active on the console on screen
 pub fn is_active() -> bool {
-    EDITOR_ACTIVE.with(|a| *a.borrow())
+    EDITOR_CONSOLE.with(|c| *c.borrow() == Some(syscall::active_vt()))
 }
 
 /// Start the editor with a file
 pub fn start(filename: Option<&str>) -> Result<(), String> {
+    if let Some(vt) = EDITOR_CONSOLE.with(|c| *c.borrow()) {
+        return Err(format!("already open on tty{}", vt));
+    }
     let mut editor = Editor::new();
 
     // Get actual terminal size
     let (cols, rows) = crate::terminal::get_size();
     editor.set_screen_size(cols, rows);
 
+    let config = match keymap::load() {
+        Ok(config) => {
+            editor.set_keymap(config.keymap);
+            config
+        }
+        Err(e) => {
+            editor.status_msg = e;
+            keymap::Config::default()
+        }
+    };
+
     if let Some(path) = filename {
         editor.load(path)?;
+        if config.spell {
+            editor.spell = Spell::for_file(path);
+        }
+        editor.check_swap();
     }
 
+    static FAULT_HOOK: Once = Once::new();
+    FAULT_HOOK.call_once(|| crash::on_fault(flush_swap));
+
     EDITOR.with(|e| {
         *e.borrow_mut() = Some(editor);
     });
 
-    EDITOR_ACTIVE.with(|a| {
-        *a.borrow_mut() = true;
+    EDITOR_CONSOLE.with(|c| {
+        *c.borrow_mut() = Some(syscall::active_vt());
     });
 
     // Clear screen and render
@@ -61,14 +106,40 @@ pub fn start(filename: Option<&str>) -> Result<(), String> {
 
 /// Stop the editor and return to shell
 pub fn stop() {
-    EDITOR_ACTIVE.with(|a| {
-        *a.borrow_mut() = false;
+    EDITOR_CONSOLE.with(|c| {
+        *c.borrow_mut() = None;
     });
     EDITOR.with(|e| {
-        *e.borrow_mut() = None;
+        if let Some(editor) = e.borrow_mut().take() {
+            editor.remove_swap();
+        }
     });
 }
 
+/// Write the swap file once the buffer has been left alone long enough
+pub fn tick(now: f64) {
+    let written = EDITOR.with(|e| match e.try_borrow_mut() {
+        Ok(mut editor) => editor
+            .as_mut()
+            .is_some_and(|ed| ed.swap_due.is_some_and(|due| due <= now) && ed.write_swap()),
+        Err(_) => false,
+    });
+    if written {
+        crate::terminal::request_autosave();
+    }
+}
+
+/// Write the swap file now, if there are changes it doesn't have yet
+fn flush_swap() {
+    let written = EDITOR.with(|e| match e.try_borrow_mut() {
+        Ok(mut editor) => editor.as_mut().is_some_and(|ed| ed.write_swap()),
+        Err(_) => false,
+    });
+    if written {
+        crate::terminal::request_autosave();
+    }
+}
+
 /// Refresh the editor display
 pub fn refresh() {
     EDITOR.with(|e| {
@@ -158,10 +229,13 @@ pub fn parse_key(key: &str, key_code: u32, ctrl: bool, alt: bool, _shift: bool)
 pub fn handle_paste(text: &str) {
     EDITOR.with(|e| {
         if let Some(ref mut editor) = *e.borrow_mut() {
+            if editor.prompt_mode == PromptMode::Recover {
+                return;
+            }
             for ch in text.chars() {
                 if ch == '\n' || ch == '\r' {
                     editor.insert_newline();
-                } else if ch.is_ascii_graphic() || ch == ' ' || ch == '\t' {
+                } else if !ch.is_control() || ch == '\t' {
                     editor.insert_char(ch);
                 }
             }
@@ -178,6 +252,8 @@ const CURSOR_HIDE: &str = "\x1b[?25l";
 const CURSOR_SHOW: &str = "\x1b[?25h";
 const INVERT_COLORS: &str = "\x1b[7m";
 const RESET_COLORS: &str = "\x1b[m";
+/// Start and end of a visual selection
+const SELECTED: (&str, &str) = (INVERT_COLORS, RESET_COLORS);
 
 /// Arrow key directions
 #[derive(Clone, Copy, PartialEq)]
@@ -348,6 +424,17 @@ pub enum PromptMode {
     Save(String),
     Find(String),
     GoTo(String),
+    /// A `:` command in the vi keymap
+    Command(String),
+    /// A swap file was found on open
+    Recover,
+}
+
+/// A swap file found on open, waiting for the user to decide on it
+struct Recovery {
+    swap: Swap,
+    /// The file's rows, put aside while the diff is shown
+    file_rows: Option<Vec<Row>>,
 }
 
 /// Editor state
@@ -380,6 +467,18 @@ pub struct Editor {
     copied_row: Option<String>,
     /// Last search match position
     last_match: Option<(usize, usize)>,
+    /// Last query searched for
+    last_search: Option<String>,
+    /// vi state, when using the vi keymap
+    vi: Option<vi::Vi>,
+    /// When to write the swap file, if it is behind the buffer
+    swap_due: Option<f64>,
+    /// Whether the swap file for this file is ours to remove
+    swapped: bool,
+    /// Swap file found on open
+    recovery: Option<Recovery>,
+    /// Spell checking, for prose files
+    spell: Option<Spell>,
 }
 
 impl Editor {
@@ -399,6 +498,12 @@ impl Editor {
             prompt_mode: PromptMode::None,
             copied_row: None,
             last_match: None,
+            last_search: None,
+            vi: None,
+            swap_due: None,
+            swapped: false,
+            recovery: None,
+            spell: None,
         }
     }
 
@@ -406,19 +511,9 @@ impl Editor {
     pub fn load(&mut self, path: &str) -> Result<(), String> {
         match syscall::read_file(path) {
             Ok(content) => {
-                self.rows.clear();
-                for line in content.lines() {
-                    self.rows.push(Row::new(line.to_string()));
-                }
-                if self.rows.is_empty() {
-                    self.rows.push(Row::empty());
-                }
+                self.set_text(&content);
                 self.filename = Some(path.to_string());
                 self.dirty = false;
-                self.cx = 0;
-                self.cy = 0;
-                self.col_offset = 0;
-                self.row_offset = 0;
                 self.status_msg = format!("Loaded: {}", path);
                 Ok(())
             }
@@ -433,18 +528,106 @@ impl Editor {
         }
     }
 
+    /// Switch key bindings
+    pub fn set_keymap(&mut self, keymap: Keymap) {
+        match keymap {
+            Keymap::Default => {
+                self.vi = None;
+                self.status_msg = String::from("Ctrl+S = save | Ctrl+Q = quit | Ctrl+F = find");
+            }
+            Keymap::Vi => {
+                self.vi = Some(vi::Vi::default());
+                self.status_msg = String::from("i = insert | :w = save | :q = quit | / = find");
+            }
+        }
+    }
+
+    /// Replace the document with `text`, cursor at the top
+    fn set_text(&mut self, text: &str) {
+        self.rows = text.lines().map(|l| Row::new(l.to_string())).collect();
+        if self.rows.is_empty() {
+            self.rows.push(Row::empty());
+        }
+        self.cx = 0;
+        self.cy = 0;
+        self.col_offset = 0;
+        self.row_offset = 0;
+    }
+
+    /// The document as text
+    fn text(&self) -> String {
+        self.rows
+            .iter()
+            .map(|r| r.chars.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Note an unsaved change, putting the swap file behind
+    fn mark_dirty(&mut self) {
+        self.dirty = true;
+        self.swap_due = Some(stats::clock() + swap::SWAP_DELAY_MS);
+    }
+
+    /// Offer to recover a swap file left by an earlier session
+    fn check_swap(&mut self) {
+        let Some(path) = &self.filename else {
+            return;
+        };
+        let Some(swap) = swap::read(path) else {
+            return;
+        };
+        if swap.content == self.text() {
+            swap::remove(path);
+            return;
+        }
+        self.recovery = Some(Recovery {
+            swap,
+            file_rows: None,
+        });
+        self.prompt_mode = PromptMode::Recover;
+    }
+
+    /// Write the swap file if it is behind the buffer, returning whether
+    /// it was written
+    fn write_swap(&mut self) -> bool {
+        let Some(path) = self.filename.clone() else {
+            return false;
+        };
+        if !self.dirty || self.swap_due.take().is_none() {
+            return false;
+        }
+        match swap::write(&path, &self.text()) {
+            Ok(()) => {
+                self.swapped = true;
+                true
+            }
+            Err(e) => {
+                self.status_msg = format!("Swap file not written: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Remove our swap file: the changes are saved or given up
+    fn remove_swap(&self) {
+        if self.swapped
+            && let Some(path) = &self.filename
+        {
+            swap::remove(path);
+        }
+    }
+
     /// Save the document
     pub fn save(&mut self) -> Result<(), String> {
         if let Some(ref path) = self.filename {
-            let content: String = self
-                .rows
-                .iter()
-                .map(|r| r.chars.as_str())
-                .collect::<Vec<_>>()
-                .join("\n");
+            let content = self.text();
 
             syscall::write_file(path, &content).map_err(|e| format!("{:?}", e))?;
 
+            self.remove_swap();
+            self.swapped = false;
+            self.swap_due = None;
             self.dirty = false;
             self.status_msg = format!("Saved: {} ({} bytes)", path, content.len());
             Ok(())
@@ -589,7 +772,7 @@ impl Editor {
         if let Some(row) = self.current_row_mut() {
             row.insert_char(cx, ch);
             self.cx += 1;
-            self.dirty = true;
+            self.mark_dirty();
         }
     }
 
@@ -605,7 +788,7 @@ impl Editor {
         }
         self.cy += 1;
         self.cx = 0;
-        self.dirty = true;
+        self.mark_dirty();
     }
 
     /// Delete character (backspace)
@@ -619,12 +802,12 @@ impl Editor {
                 self.cy -= 1;
                 self.cx = self.rows[self.cy].len();
                 self.rows[self.cy].append(&current.chars);
-                self.dirty = true;
+                self.mark_dirty();
             }
         } else {
             self.rows[self.cy].delete_char(self.cx - 1);
             self.cx -= 1;
-            self.dirty = true;
+            self.mark_dirty();
         }
     }
 
@@ -636,12 +819,12 @@ impl Editor {
         let row_len = self.rows[self.cy].len();
         if self.cx < row_len {
             self.rows[self.cy].delete_char(self.cx);
-            self.dirty = true;
+            self.mark_dirty();
         } else if self.cy < self.rows.len() - 1 {
             // Merge with next row
             let next = self.rows.remove(self.cy + 1);
             self.rows[self.cy].append(&next.chars);
-            self.dirty = true;
+            self.mark_dirty();
         }
     }
 
@@ -652,11 +835,11 @@ impl Editor {
             if self.cy >= self.rows.len() {
                 self.cy = self.rows.len() - 1;
             }
-            self.dirty = true;
+            self.mark_dirty();
         } else {
             self.rows[0] = Row::empty();
             self.cx = 0;
-            self.dirty = true;
+            self.mark_dirty();
         }
         let row_len = self.current_row().map(|r| r.len()).unwrap_or(0);
         if self.cx > row_len {
@@ -670,7 +853,7 @@ impl Editor {
             let copy = self.rows[self.cy].clone();
             self.rows.insert(self.cy + 1, copy);
             self.cy += 1;
-            self.dirty = true;
+            self.mark_dirty();
         }
     }
 
@@ -687,7 +870,7 @@ impl Editor {
         if let Some(ref text) = self.copied_row.clone() {
             self.rows.insert(self.cy + 1, Row::new(text.clone()));
             self.cy += 1;
-            self.dirty = true;
+            self.mark_dirty();
         }
     }
 
@@ -721,13 +904,34 @@ impl Editor {
         buf.push_str(CURSOR_HOME);
 
         // Draw rows
+        let selection = self
+            .vi
+            .as_ref()
+            .and_then(|vi| vi.selection((self.cy, self.cx)));
+        let misspelled = self.misspelled_rows(self.row_offset..self.row_offset + self.screen_rows);
         for y in 0..self.screen_rows {
             let file_row = y + self.row_offset;
             if file_row < self.rows.len() {
                 let row = &self.rows[file_row];
-                let len = row.render_len().saturating_sub(self.col_offset);
-                let display_len = len.min(self.screen_cols);
-                buf.push_str(row.render_slice(self.col_offset, display_len));
+                let words = misspelled.get(y).filter(|words| !words.is_empty());
+                let selected = selection.and_then(|sel| selected_columns(row, file_row, sel));
+                match (selected, words) {
+                    (Some(selected), _) => {
+                        self.draw_marked_row(&mut buf, row, &[selected], SELECTED);
+                    }
+                    (None, Some(words)) => {
+                        let columns: Vec<(usize, usize)> = words
+                            .iter()
+                            .map(|word| (row.cx_to_rx(word.start), row.cx_to_rx(word.end)))
+                            .collect();
+                        self.draw_marked_row(&mut buf, row, &columns, spell::MISSPELLED);
+                    }
+                    (None, None) => {
+                        let len = row.render_len().saturating_sub(self.col_offset);
+                        let display_len = len.min(self.screen_cols);
+                        buf.push_str(row.render_slice(self.col_offset, display_len));
+                    }
+                }
             } else {
                 buf.push('~');
             }
@@ -752,6 +956,30 @@ impl Editor {
         buf
     }
 
+    /// Draw the visible part of a row with the render columns in `spans`
+    /// (each `from..to`) between the `(start, end)` escape sequences
+    fn draw_marked_row(
+        &self,
+        buf: &mut String,
+        row: &Row,
+        spans: &[(usize, usize)],
+        (start, end): (&str, &str),
+    ) {
+        let mut marked = false;
+        let visible = row.render.chars().enumerate().skip(self.col_offset);
+        for (x, ch) in visible.take(self.screen_cols) {
+            let inside = spans.iter().any(|&(from, to)| from <= x && x < to);
+            if inside != marked {
+                buf.push_str(if inside { start } else { end });
+                marked = inside;
+            }
+            buf.push(ch);
+        }
+        if marked {
+            buf.push_str(end);
+        }
+    }
+
     /// Draw the status bar
     fn draw_status_bar(&self, buf: &mut String) {
         buf.push_str(INVERT_COLORS);
@@ -762,7 +990,8 @@ impl Editor {
             .map(|s| s.as_str())
             .unwrap_or("[No Name]");
         let modified = if self.dirty { "(modified)" } else { "" };
-        let left = format!("{} {} ", filename, modified);
+        let mode = self.vi.as_ref().map_or("", |vi| vi.mode_label());
+        let left = format!("{} {} {}", filename, modified, mode);
         let right = format!(" {}/{} ", self.cy + 1, self.rows.len());
 
         let width = self.screen_cols;
@@ -799,6 +1028,14 @@ impl Editor {
             PromptMode::Save(input) => format!("Save as: {}", input),
             PromptMode::Find(query) => format!("Find: {} (ESC to cancel)", query),
             PromptMode::GoTo(input) => format!("Go to line: {}", input),
+            PromptMode::Command(input) => format!(":{}", input),
+            PromptMode::Recover => match &self.recovery {
+                Some(recovery) => format!(
+                    "Unsaved changes from {}: (r)ecover, (d)iff, (x) discard, ESC to keep",
+                    ago(stats::clock(), recovery.swap.time)
+                ),
+                None => String::new(),
+            },
         };
 
         let len = msg.chars().count().min(self.screen_cols);
@@ -880,11 +1117,78 @@ impl Editor {
     /// Process a key press, returns true if should quit
     pub fn process_key(&mut self, key: Key) -> bool {
         match &self.prompt_mode {
-            PromptMode::None => self.process_key_normal(key),
-            PromptMode::Save(_) | PromptMode::Find(_) | PromptMode::GoTo(_) => {
-                self.process_key_prompt(key)
+            PromptMode::None => match self.vi.take() {
+                Some(mut vi) => {
+                    let quit = vi.process_key(self, key);
+                    self.vi = Some(vi);
+                    quit
+                }
+                None => self.process_key_normal(key),
+            },
+            PromptMode::Save(_)
+            | PromptMode::Find(_)
+            | PromptMode::GoTo(_)
+            | PromptMode::Command(_) => self.process_key_prompt(key),
+            PromptMode::Recover => self.process_key_recover(key),
+        }
+    }
+
+    /// Process key while asking about a swap file
+    fn process_key_recover(&mut self, key: Key) -> bool {
+        let Some(mut recovery) = self.recovery.take() else {
+            self.prompt_mode = PromptMode::None;
+            return false;
+        };
+        // The diff is shown in place of the file until asked again
+        let file_rows = recovery.file_rows.take();
+        match key {
+            Key::Char('d') => {
+                match file_rows {
+                    Some(rows) => self.rows = rows,
+                    None => {
+                        let diff = swap::diff(&self.text(), &recovery.swap.content);
+                        let rows = diff.into_iter().map(Row::new).collect();
+                        recovery.file_rows = Some(std::mem::replace(&mut self.rows, rows));
+                    }
+                }
+                self.cx = 0;
+                self.cy = 0;
+                self.recovery = Some(recovery);
+                return false;
+            }
+            Key::Arrow(_) | Key::PageUp | Key::PageDown | Key::Home | Key::End => {
+                recovery.file_rows = file_rows;
+                self.recovery = Some(recovery);
+                return self.process_key_normal(key);
+            }
+            Key::Char('r') | Key::Char('x') | Key::Escape => {}
+            _ => {
+                recovery.file_rows = file_rows;
+                self.recovery = Some(recovery);
+                return false;
+            }
+        }
+
+        if let Some(rows) = file_rows {
+            self.rows = rows;
+        }
+        self.cx = 0;
+        self.cy = 0;
+        self.prompt_mode = PromptMode::None;
+        match key {
+            Key::Char('r') => {
+                self.set_text(&recovery.swap.content);
+                self.mark_dirty();
+                self.swapped = true;
+                self.status_msg = String::from("Recovered unsaved changes - Ctrl+S to keep them");
+            }
+            Key::Char('x') => {
+                swap::remove(&recovery.swap.path);
+                self.status_msg = String::from("Swap file discarded");
             }
+            _ => self.status_msg = String::from("Swap file kept - 'edit -r' lists it"),
         }
+        false
     }
 
     /// Process key in normal mode
@@ -927,6 +1231,12 @@ impl Editor {
             Key::Ctrl('v') => {
                 self.paste_line();
             }
+            Key::Ctrl('e') => {
+                self.next_misspelling(true);
+            }
+            Key::Ctrl('y') => {
+                self.spell_add();
+            }
             Key::Arrow(dir) => {
                 self.move_cursor(dir);
             }
@@ -998,22 +1308,34 @@ impl Editor {
                     let q = query.clone();
                     self.prompt_mode = PromptMode::None;
                     self.find(&q, true);
+                    self.last_search = Some(q);
                 }
                 PromptMode::GoTo(input) => {
                     let line = input.clone();
                     self.prompt_mode = PromptMode::None;
                     self.goto_line(&line);
                 }
-                PromptMode::None => {}
+                PromptMode::Command(input) => {
+                    let command = input.clone();
+                    self.prompt_mode = PromptMode::None;
+                    return self.ex_command(&command);
+                }
+                PromptMode::None | PromptMode::Recover => {}
             },
             Key::Backspace => match &mut self.prompt_mode {
-                PromptMode::Save(input) | PromptMode::Find(input) | PromptMode::GoTo(input) => {
+                PromptMode::Save(input)
+                | PromptMode::Find(input)
+                | PromptMode::GoTo(input)
+                | PromptMode::Command(input) => {
                     input.pop();
                 }
-                PromptMode::None => {}
+                PromptMode::None | PromptMode::Recover => {}
             },
             Key::Char(ch) => match &mut self.prompt_mode {
-                PromptMode::Save(input) | PromptMode::Find(input) | PromptMode::GoTo(input) => {
+                PromptMode::Save(input)
+                | PromptMode::Find(input)
+                | PromptMode::GoTo(input)
+                | PromptMode::Command(input) => {
                     input.push(ch);
                     // Live search for Find mode
                     if matches!(self.prompt_mode, PromptMode::Find(_)) {
@@ -1023,7 +1345,7 @@ impl Editor {
                         }
                     }
                 }
-                PromptMode::None => {}
+                PromptMode::None | PromptMode::Recover => {}
             },
             Key::Arrow(Arrow::Up) | Key::Arrow(Arrow::Left) => {
                 if let PromptMode::Find(query) = &self.prompt_mode {
@@ -1043,6 +1365,24 @@ impl Editor {
     }
 }
 
+/// Render columns of `row`, number `y`, inside a visual selection
+fn selected_columns(
+    row: &Row,
+    y: usize,
+    (start, end, line): ((usize, usize), (usize, usize), bool),
+) -> Option<(usize, usize)> {
+    if y < start.0 || y > end.0 {
+        return None;
+    }
+    let from = if line || y != start.0 { 0 } else { start.1 };
+    let to = if line || y != end.0 {
+        row.len()
+    } else {
+        (end.1 + 1).min(row.len())
+    };
+    Some((row.cx_to_rx(from), row.cx_to_rx(to)))
+}
+
 impl Default for Editor {
     fn default() -> Self {
         Self::new()
@@ -1174,4 +1514,43 @@ mod tests {
         assert_eq!(editor.rows[1].chars, "test");
         assert_eq!(editor.cy, 1);
     }
+
+    #[test]
+    fn test_editor_swap_recovery() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        syscall::mkdir("/tmp/me").unwrap();
+        syscall::setenv("HOME", "/tmp/me").unwrap();
+        syscall::write_file("/tmp/notes.txt", "saved").unwrap();
+        swap::write("/tmp/notes.txt", "saved\nunsaved").unwrap();
+
+        let mut editor = Editor::new();
+        editor.load("/tmp/notes.txt").unwrap();
+        editor.check_swap();
+        assert!(editor.prompt_mode == PromptMode::Recover);
+        editor.process_key(Key::Char('d'));
+        assert_eq!(editor.text(), "  saved\n+ unsaved");
+        editor.process_key(Key::Char('d'));
+        assert_eq!(editor.text(), "saved");
+        editor.process_key(Key::Char('r'));
+        assert!(editor.prompt_mode == PromptMode::None);
+        assert_eq!(editor.text(), "saved\nunsaved");
+        assert!(editor.dirty);
+
+        // Edits reach the swap file, and saving removes it
+        editor.insert_char('!');
+        assert!(editor.write_swap());
+        assert!(!editor.write_swap());
+        assert_eq!(
+            swap::read("/tmp/notes.txt").unwrap().content,
+            "!saved\nunsaved"
+        );
+        editor.save().unwrap();
+        assert_eq!(swap::read("/tmp/notes.txt"), None);
+    }
 }
@@ -0,0 +1,235 @@
+//! Spell checking in the editor
+//!
+//! Markdown and text buffers have their misspelled words underlined (see
+//! [`crate::shell::spell`]). Ctrl+E moves to the next one and lists
+//! corrections, and Ctrl+Y adds the word at the cursor to the personal
+//! dictionary; the vi keymap also has `]s` `[s`, `z=` (`Nz=` to take the
+//! Nth correction) and `zg`. `spell = false` in `~/.config/editor.toml`
+//! turns checking off.
+
+use std::ops::Range;
+
+use super::{Editor, Row};
+use crate::shell::spell::{self, Dictionary, Fences};
+
+/// Start and end of the underline for misspelled words
+pub(super) const MISSPELLED: (&str, &str) = ("\x1b[4;31m", "\x1b[24;39m");
+
+/// How many corrections are offered
+const SUGGESTIONS: usize = 5;
+
+/// Spell checking for a buffer
+pub struct Spell {
+    dict: Dictionary,
+    markdown: bool,
+}
+
+impl Spell {
+    /// Checking for `path`, if it holds prose
+    pub fn for_file(path: &str) -> Option<Self> {
+        spell::is_prose(path).then(|| Self {
+            dict: spell::load(),
+            markdown: spell::is_markdown(path),
+        })
+    }
+}
+
+impl Editor {
+    /// Misspelled words in each of `rows`, as character ranges
+    pub(super) fn misspelled_rows(&self, rows: Range<usize>) -> Vec<Vec<Range<usize>>> {
+        let Some(spell) = &self.spell else {
+            return Vec::new();
+        };
+        // Fenced code blocks can start anywhere above
+        let mut fences = Fences::default();
+        self.rows
+            .iter()
+            .take(rows.end)
+            .enumerate()
+            .filter_map(|(y, row)| {
+                let prose = !spell.markdown || fences.prose(&row.chars);
+                (y >= rows.start).then(|| match prose {
+                    true => spell.dict.misspelled(&row.chars, spell.markdown),
+                    false => Vec::new(),
+                })
+            })
+            .collect()
+    }
+
+    /// Move to the next misspelled word, or the previous one, and list
+    /// corrections for it
+    pub(super) fn next_misspelling(&mut self, forward: bool) {
+        if self.spell.is_none() {
+            self.status_msg = String::from("Spell checking is off for this file");
+            return;
+        }
+        let words: Vec<(usize, usize)> = self
+            .misspelled_rows(0..self.rows.len())
+            .iter()
+            .enumerate()
+            .flat_map(|(y, ranges)| ranges.iter().map(move |range| (y, range.start)))
+            .collect();
+        let cursor = (self.cy, self.cx);
+        let target = match forward {
+            true => words.iter().find(|&&pos| pos > cursor).or(words.first()),
+            false => words
+                .iter()
+                .rev()
+                .find(|&&pos| pos < cursor)
+                .or(words.last()),
+        };
+        match target {
+            Some(&(y, x)) => {
+                self.cy = y;
+                self.cx = x;
+                self.spell_suggest(None);
+            }
+            None => self.status_msg = String::from("No misspelled words"),
+        }
+    }
+
+    /// The word under the cursor, as a character range
+    fn word_at_cursor(&self) -> Option<Range<usize>> {
+        let markdown = self.spell.as_ref().is_some_and(|spell| spell.markdown);
+        let row = self.rows.get(self.cy)?;
+        spell::words(&row.chars, markdown)
+            .into_iter()
+            .find(|range| range.contains(&self.cx))
+    }
+
+    fn word(&self, range: &Range<usize>) -> String {
+        self.rows[self.cy]
+            .chars
+            .chars()
+            .skip(range.start)
+            .take(range.len())
+            .collect()
+    }
+
+    /// List corrections for the word under the cursor, or put the `n`th
+    /// in its place
+    pub(super) fn spell_suggest(&mut self, n: Option<usize>) {
+        let (Some(spell), Some(range)) = (&self.spell, self.word_at_cursor()) else {
+            self.status_msg = String::from("No word to check here");
+            return;
+        };
+        let word = self.word(&range);
+        let suggestions = spell.dict.suggest(&word, SUGGESTIONS);
+        match n {
+            Some(n) => {
+                let Some(replacement) = suggestions.get(n.saturating_sub(1)) else {
+                    self.status_msg = format!("No correction {} for '{}'", n, word);
+                    return;
+                };
+                let mut chars: Vec<char> = self.rows[self.cy].chars.chars().collect();
+                chars.splice(range.clone(), replacement.chars());
+                self.rows[self.cy] = Row::new(chars.into_iter().collect());
+                self.cx = range.start;
+                self.mark_dirty();
+                self.status_msg = format!("'{}' -> '{}'", word, replacement);
+            }
+            None if suggestions.is_empty() => {
+                self.status_msg = format!("'{}': no corrections", word);
+            }
+            None => {
+                let list: Vec<String> = suggestions
+                    .iter()
+                    .enumerate()
+                    .map(|(i, suggestion)| format!("{}:{}", i + 1, suggestion))
+                    .collect();
+                self.status_msg = format!("'{}': {}", word, list.join(" "));
+            }
+        }
+    }
+
+    /// Add the word under the cursor to the personal dictionary
+    pub(super) fn spell_add(&mut self) {
+        let Some(range) = self.word_at_cursor() else {
+            self.status_msg = String::from("No word to add here");
+            return;
+        };
+        let word = self.word(&range);
+        match spell::add_personal(&[&word]) {
+            Ok(()) => {
+                if let Some(spell) = &mut self.spell {
+                    spell.dict.add_list(&word);
+                }
+                self.status_msg = format!("Added '{}' to ~/{}", word, spell::PERSONAL_FILE);
+            }
+            Err(e) => self.status_msg = e,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Key, Keymap};
+    use super::*;
+
+    fn editor(text: &str) -> Editor {
+        let mut ed = Editor::new();
+        ed.set_text(text);
+        let mut dict = Dictionary::new();
+        dict.add_list("the\nword\nhere\nwood\ncode\n");
+        ed.spell = Some(Spell {
+            dict,
+            markdown: true,
+        });
+        ed
+    }
+
+    #[test]
+    fn test_misspellings() {
+        let mut ed = editor("the wrod here\n```\nnot chekced\n```\nthe cdoe");
+        let counts: Vec<usize> = ed.misspelled_rows(0..5).iter().map(Vec::len).collect();
+        assert_eq!(counts, [1, 0, 0, 0, 1]);
+        assert_eq!(ed.misspelled_rows(4..5)[0][0], 4..8);
+
+        ed.next_misspelling(true);
+        assert_eq!((ed.cy, ed.cx), (0, 4));
+        assert_eq!(ed.status_msg, "'wrod': 1:wood 2:word");
+        ed.next_misspelling(true);
+        assert_eq!((ed.cy, ed.cx), (4, 4));
+        ed.next_misspelling(true);
+        assert_eq!((ed.cy, ed.cx), (0, 4));
+        ed.next_misspelling(false);
+        assert_eq!((ed.cy, ed.cx), (4, 4));
+
+        ed.spell_suggest(Some(1));
+        assert_eq!(ed.rows[4].chars, "the code");
+        assert!(ed.dirty);
+        ed.spell_suggest(Some(9));
+        assert_eq!(ed.status_msg, "No correction 9 for 'code'");
+
+        ed.spell = None;
+        assert!(ed.misspelled_rows(0..5).is_empty());
+    }
+
+    #[test]
+    fn test_vi_keys() {
+        use crate::kernel::syscall::{self, KERNEL, Kernel};
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        syscall::mkdir("/tmp/me").unwrap();
+        syscall::setenv("HOME", "/tmp/me").unwrap();
+
+        let mut ed = editor("the wrod\nhere cdoe");
+        ed.set_keymap(Keymap::Vi);
+        let keys = |ed: &mut Editor, keys: &str| {
+            for c in keys.chars() {
+                ed.process_key(Key::Char(c));
+            }
+        };
+        keys(&mut ed, "]s2z=");
+        assert_eq!(ed.rows[0].chars, "the word");
+        keys(&mut ed, "]szg");
+        assert_eq!((ed.cy, ed.cx), (1, 5));
+        assert_eq!(ed.status_msg, "Added 'cdoe' to ~/.config/spell/words");
+        keys(&mut ed, "[s");
+        assert_eq!(ed.status_msg, "No misspelled words");
+    }
+}
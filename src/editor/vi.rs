@@ -0,0 +1,846 @@
+//! vi-style modal editing
+//!
+//! Normal mode moves and edits with single keys, insert mode types with the
+//! default bindings, and visual mode selects text to act on. Ctrl-key
+//! bindings work in every mode.
+//!
+//! ```text
+//! motions    h j k l  w b e  0 ^ $  gg G  (and arrows), with counts
+//! operators  d c y + motion, doubled for whole lines (dd cc yy)
+//! edits      x X D C Y s S J r{char} p P
+//! insert     i a I A o O, ESC back to normal
+//! visual     v V, then motions to extend and d x c y to act
+//! commands   :w :w FILE :q :q! :wq :x :N, / to search, n N
+//! spelling   ]s [s to the next or previous misspelling, z= (Nz=) zg
+//! ```
+
+use super::{Arrow, Editor, Key, PromptMode, Row};
+
+/// Current vi mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    #[default]
+    Normal,
+    Insert,
+    /// Selecting from the anchor to the cursor, whole lines if `line`
+    Visual {
+        line: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordForward,
+    WordBackward,
+    WordEnd,
+    LineStart,
+    FirstNonBlank,
+    LineEnd,
+    FirstLine,
+    LastLine,
+}
+
+impl Motion {
+    /// Operators act on whole lines
+    fn linewise(self) -> bool {
+        matches!(
+            self,
+            Self::Up | Self::Down | Self::FirstLine | Self::LastLine
+        )
+    }
+
+    /// Operators include the character the motion lands on
+    fn inclusive(self) -> bool {
+        matches!(self, Self::WordEnd | Self::LineEnd)
+    }
+}
+
+/// Text that was yanked or deleted
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Register {
+    Chars(String),
+    Lines(Vec<String>),
+}
+
+/// A buffer position: (row, column in characters)
+type Pos = (usize, usize);
+
+/// vi keymap state
+#[derive(Debug, Default)]
+pub struct Vi {
+    pub mode: Mode,
+    /// Count typed so far
+    count: Option<usize>,
+    /// Operator waiting for a motion, with the count typed before it
+    operator: Option<(Operator, usize)>,
+    /// `g`, `r`, `z`, `[` or `]` waiting for the next key
+    pending: Option<char>,
+    /// Where the visual selection started
+    anchor: Pos,
+    register: Option<Register>,
+}
+
+impl Vi {
+    /// Label for the status bar
+    pub fn mode_label(&self) -> &'static str {
+        match self.mode {
+            Mode::Normal => "",
+            Mode::Insert => "-- INSERT --",
+            Mode::Visual { line: false } => "-- VISUAL --",
+            Mode::Visual { line: true } => "-- VISUAL LINE --",
+        }
+    }
+
+    /// Selected span, first and last position inclusive, and whether it is
+    /// whole lines
+    pub fn selection(&self, cursor: Pos) -> Option<(Pos, Pos, bool)> {
+        let Mode::Visual { line } = self.mode else {
+            return None;
+        };
+        Some((self.anchor.min(cursor), self.anchor.max(cursor), line))
+    }
+
+    /// Handle a key, returning true if the editor should quit
+    pub fn process_key(&mut self, ed: &mut Editor, key: Key) -> bool {
+        if self.mode == Mode::Insert {
+            if key == Key::Escape {
+                self.mode = Mode::Normal;
+                ed.cx = ed.cx.saturating_sub(1);
+                return false;
+            }
+            return ed.process_key_normal(key);
+        }
+        if matches!(key, Key::Ctrl(_)) {
+            return ed.process_key_normal(key);
+        }
+
+        if let Some(pending) = self.pending.take() {
+            match (pending, &key) {
+                ('g', Key::Char('g')) => self.motion(ed, Motion::FirstLine),
+                ('r', Key::Char(c)) => self.replace(ed, *c),
+                (']' | '[', Key::Char('s')) => {
+                    self.reset();
+                    ed.next_misspelling(pending == ']');
+                }
+                ('z', Key::Char('=')) => ed.spell_suggest(self.count.take()),
+                ('z', Key::Char('g')) => ed.spell_add(),
+                _ => self.reset(),
+            }
+            ed.clamp_normal();
+            return false;
+        }
+
+        let quit = self.key(ed, key);
+        if self.mode != Mode::Insert {
+            ed.clamp_normal();
+        }
+        quit
+    }
+
+    fn key(&mut self, ed: &mut Editor, key: Key) -> bool {
+        let c = match key {
+            Key::Char(c) => c,
+            Key::Arrow(Arrow::Left) | Key::Backspace => 'h',
+            Key::Arrow(Arrow::Right) => 'l',
+            Key::Arrow(Arrow::Up) => 'k',
+            Key::Arrow(Arrow::Down) | Key::Enter => 'j',
+            Key::Home => '0',
+            Key::End => '$',
+            Key::Escape => {
+                if matches!(self.mode, Mode::Visual { .. }) {
+                    self.mode = Mode::Normal;
+                }
+                self.reset();
+                return false;
+            }
+            Key::PageUp | Key::PageDown | Key::CtrlArrow(_) => {
+                return ed.process_key_normal(key);
+            }
+            _ => return false,
+        };
+
+        if let Some(digit) = c.to_digit(10)
+            && (digit != 0 || self.count.is_some())
+        {
+            self.count = Some(self.count.unwrap_or(0) * 10 + digit as usize);
+            return false;
+        }
+
+        if let Some(motion) = motion_for(c) {
+            self.motion(ed, motion);
+            return false;
+        }
+
+        if let Mode::Visual { line } = self.mode {
+            self.visual_key(ed, c, line);
+            return false;
+        }
+
+        // gg, r{char}, z= zg and ]s [s wait for the next key
+        if c == 'g' || (matches!(c, 'r' | 'z' | '[' | ']') && self.operator.is_none()) {
+            self.pending = Some(c);
+            return false;
+        }
+
+        let count = self.count.take().unwrap_or(1);
+        if let Some((op, _)) = self.operator {
+            // A doubled operator acts on whole lines
+            let doubled = matches!(
+                (op, c),
+                (Operator::Delete, 'd') | (Operator::Change, 'c') | (Operator::Yank, 'y')
+            );
+            if doubled {
+                let total = self.operator.take().map_or(1, |(_, n)| n) * count;
+                let last = (ed.cy + total - 1).min(ed.rows.len() - 1);
+                self.apply(ed, op, (ed.cy, 0), (last, 0), true);
+            } else {
+                self.reset();
+            }
+            return false;
+        }
+
+        match c {
+            'd' => self.operator = Some((Operator::Delete, count)),
+            'c' => self.operator = Some((Operator::Change, count)),
+            'y' => self.operator = Some((Operator::Yank, count)),
+            'x' => self.operate(ed, Operator::Delete, Motion::Right, count),
+            'X' => self.operate(ed, Operator::Delete, Motion::Left, count),
+            's' => self.operate(ed, Operator::Change, Motion::Right, count),
+            'D' => self.operate(ed, Operator::Delete, Motion::LineEnd, count),
+            'C' => self.operate(ed, Operator::Change, Motion::LineEnd, count),
+            'Y' | 'S' => {
+                let op = if c == 'Y' {
+                    Operator::Yank
+                } else {
+                    Operator::Change
+                };
+                let last = (ed.cy + count - 1).min(ed.rows.len() - 1);
+                self.apply(ed, op, (ed.cy, 0), (last, 0), true);
+            }
+            'p' | 'P' => self.put(ed, c == 'p', count),
+            'J' => ed.join_lines(count.max(2) - 1),
+            'i' => self.mode = Mode::Insert,
+            'a' => {
+                ed.cx = (ed.cx + 1).min(ed.line_len(ed.cy));
+                self.mode = Mode::Insert;
+            }
+            'I' => {
+                ed.cx = ed.first_non_blank(ed.cy);
+                self.mode = Mode::Insert;
+            }
+            'A' => {
+                ed.cx = ed.line_len(ed.cy);
+                self.mode = Mode::Insert;
+            }
+            'o' | 'O' => {
+                let y = if c == 'o' { ed.cy + 1 } else { ed.cy };
+                ed.rows.insert(y, Row::empty());
+                ed.cy = y;
+                ed.cx = 0;
+                ed.mark_dirty();
+                self.mode = Mode::Insert;
+            }
+            'v' | 'V' => {
+                self.anchor = (ed.cy, ed.cx);
+                self.mode = Mode::Visual { line: c == 'V' };
+            }
+            ':' => ed.prompt_mode = PromptMode::Command(String::new()),
+            '/' => {
+                ed.prompt_mode = PromptMode::Find(String::new());
+                ed.last_match = None;
+            }
+            'n' | 'N' => {
+                if let Some(query) = ed.last_search.clone() {
+                    for _ in 0..count {
+                        ed.find(&query, c == 'n');
+                    }
+                }
+            }
+            _ => {}
+        }
+        false
+    }
+
+    /// A key in visual mode that isn't a motion or count
+    fn visual_key(&mut self, ed: &mut Editor, c: char, line: bool) {
+        self.count = None;
+        let (start, end, _) = self.selection((ed.cy, ed.cx)).unwrap_or_default();
+        let op = match c {
+            'd' | 'x' => Operator::Delete,
+            'c' => Operator::Change,
+            'y' => Operator::Yank,
+            'v' | 'V' => {
+                let line_wanted = c == 'V';
+                self.mode = if line == line_wanted {
+                    Mode::Normal
+                } else {
+                    Mode::Visual { line: line_wanted }
+                };
+                return;
+            }
+            _ => return,
+        };
+        self.mode = Mode::Normal;
+        if line {
+            self.apply(ed, op, start, end, true);
+        } else {
+            let end = ed.step(end).unwrap_or((end.0, ed.line_len(end.0)));
+            self.apply(ed, op, start, end, false);
+        }
+    }
+
+    /// Run a motion: move the cursor, or hand it to the pending operator
+    fn motion(&mut self, ed: &mut Editor, motion: Motion) {
+        let explicit = self.count.take();
+        let count = explicit.unwrap_or(1);
+        match self.operator.take() {
+            Some((op, n)) => {
+                let explicit = explicit.map(|c| c * n).or((n > 1).then_some(n));
+                self.operate_counted(ed, op, motion, explicit);
+            }
+            None => {
+                let (y, x) = ed.motion_target(motion, count, explicit);
+                ed.cy = y;
+                ed.cx = x;
+            }
+        }
+    }
+
+    fn operate(&mut self, ed: &mut Editor, op: Operator, motion: Motion, count: usize) {
+        self.operate_counted(ed, op, motion, Some(count));
+    }
+
+    /// Apply an operator over a motion from the cursor
+    fn operate_counted(
+        &mut self,
+        ed: &mut Editor,
+        op: Operator,
+        motion: Motion,
+        explicit: Option<usize>,
+    ) {
+        let count = explicit.unwrap_or(1);
+        let cursor = (ed.cy, ed.cx);
+        // cw changes to the end of the word, like ce
+        let motion = if op == Operator::Change
+            && motion == Motion::WordForward
+            && ed.char_at(cursor).is_some_and(|c| !c.is_whitespace())
+        {
+            Motion::WordEnd
+        } else {
+            motion
+        };
+        let mut target = ed.motion_target(motion, count, explicit);
+        if motion.linewise() {
+            let (first, last) = (cursor.0.min(target.0), cursor.0.max(target.0));
+            self.apply(ed, op, (first, 0), (last, 0), true);
+            return;
+        }
+        // A word motion from the last word stops at the end of the line
+        if motion == Motion::WordForward && target.0 > cursor.0 {
+            target = (cursor.0, ed.line_len(cursor.0));
+        }
+        let (start, mut end) = (cursor.min(target), cursor.max(target));
+        if motion.inclusive() && ed.char_at(end).is_some() {
+            end.1 += 1;
+        }
+        if start != end {
+            self.apply(ed, op, start, end, false);
+        } else if op == Operator::Change {
+            self.mode = Mode::Insert;
+        }
+    }
+
+    /// Apply an operator to lines `start.0..=end.0` if `linewise`, or the
+    /// characters from `start` up to `end`
+    fn apply(&mut self, ed: &mut Editor, op: Operator, start: Pos, end: Pos, linewise: bool) {
+        self.reset();
+        if linewise {
+            let lines: Vec<String> = ed.rows[start.0..=end.0]
+                .iter()
+                .map(|r| r.chars.clone())
+                .collect();
+            self.register = Some(Register::Lines(lines));
+            match op {
+                Operator::Yank => ed.cy = start.0,
+                Operator::Delete => {
+                    ed.rows.drain(start.0..=end.0);
+                    if ed.rows.is_empty() {
+                        ed.rows.push(Row::empty());
+                    }
+                    ed.cy = start.0.min(ed.rows.len() - 1);
+                    ed.cx = ed.first_non_blank(ed.cy);
+                    ed.mark_dirty();
+                }
+                Operator::Change => {
+                    ed.rows.splice(start.0..=end.0, [Row::empty()]);
+                    ed.cy = start.0;
+                    ed.cx = 0;
+                    ed.mark_dirty();
+                    self.mode = Mode::Insert;
+                }
+            }
+            return;
+        }
+
+        self.register = Some(Register::Chars(ed.text_between(start, end)));
+        if op != Operator::Yank {
+            ed.delete_between(start, end);
+        }
+        ed.cy = start.0;
+        ed.cx = start.1;
+        if op == Operator::Change {
+            self.mode = Mode::Insert;
+        }
+    }
+
+    /// Put the register after the cursor, or before it
+    fn put(&mut self, ed: &mut Editor, after: bool, count: usize) {
+        match &self.register {
+            Some(Register::Lines(lines)) => {
+                let y = if after { ed.cy + 1 } else { ed.cy };
+                let rows = (0..count).flat_map(|_| lines.iter().map(|l| Row::new(l.clone())));
+                ed.rows.splice(y..y, rows);
+                ed.cy = y;
+                ed.cx = ed.first_non_blank(y);
+            }
+            Some(Register::Chars(text)) => {
+                let x = if after {
+                    (ed.cx + 1).min(ed.line_len(ed.cy))
+                } else {
+                    ed.cx
+                };
+                let end = ed.insert_text((ed.cy, x), &text.repeat(count));
+                ed.cy = end.0;
+                ed.cx = end.1.saturating_sub(1);
+            }
+            None => return,
+        }
+        ed.mark_dirty();
+    }
+
+    /// Replace characters under the cursor with `c`
+    fn replace(&mut self, ed: &mut Editor, c: char) {
+        let count = self.count.take().unwrap_or(1);
+        let (y, x) = (ed.cy, ed.cx);
+        if x + count > ed.line_len(y) {
+            return;
+        }
+        let mut chars: Vec<char> = ed.rows[y].chars.chars().collect();
+        chars[x..x + count].fill(c);
+        ed.rows[y] = Row::new(chars.into_iter().collect());
+        ed.cx = x + count - 1;
+        ed.mark_dirty();
+    }
+
+    /// Forget a half-typed command
+    fn reset(&mut self) {
+        self.count = None;
+        self.operator = None;
+        self.pending = None;
+    }
+}
+
+fn motion_for(c: char) -> Option<Motion> {
+    Some(match c {
+        'h' => Motion::Left,
+        'l' | ' ' => Motion::Right,
+        'k' => Motion::Up,
+        'j' => Motion::Down,
+        'w' => Motion::WordForward,
+        'b' => Motion::WordBackward,
+        'e' => Motion::WordEnd,
+        '0' => Motion::LineStart,
+        '^' => Motion::FirstNonBlank,
+        '$' => Motion::LineEnd,
+        'G' => Motion::LastLine,
+        _ => return None,
+    })
+}
+
+/// Character classes for word motions
+#[derive(PartialEq, Eq)]
+enum Class {
+    Blank,
+    Word,
+    Punct,
+}
+
+fn class(c: Option<char>) -> Class {
+    match c {
+        None => Class::Blank,
+        Some(c) if c.is_whitespace() => Class::Blank,
+        Some(c) if c.is_alphanumeric() || c == '_' => Class::Word,
+        Some(_) => Class::Punct,
+    }
+}
+
+impl Editor {
+    pub(super) fn line_len(&self, y: usize) -> usize {
+        self.rows.get(y).map_or(0, |r| r.len())
+    }
+
+    /// Character at a position; the end of a line has none
+    fn char_at(&self, (y, x): Pos) -> Option<char> {
+        self.rows.get(y)?.chars.chars().nth(x)
+    }
+
+    pub(super) fn first_non_blank(&self, y: usize) -> usize {
+        self.rows
+            .get(y)
+            .and_then(|r| r.chars.chars().position(|c| !c.is_whitespace()))
+            .unwrap_or(0)
+    }
+
+    /// Next position, counting the end of each line as one
+    fn step(&self, (y, x): Pos) -> Option<Pos> {
+        if x < self.line_len(y) {
+            Some((y, x + 1))
+        } else if y + 1 < self.rows.len() {
+            Some((y + 1, 0))
+        } else {
+            None
+        }
+    }
+
+    fn step_back(&self, (y, x): Pos) -> Option<Pos> {
+        if x > 0 {
+            Some((y, x - 1))
+        } else if y > 0 {
+            Some((y - 1, self.line_len(y - 1)))
+        } else {
+            None
+        }
+    }
+
+    fn is_empty_line(&self, (y, _): Pos) -> bool {
+        self.line_len(y) == 0
+    }
+
+    /// Start of the next word
+    fn word_forward(&self, from: Pos) -> Pos {
+        let end = (self.rows.len() - 1, self.line_len(self.rows.len() - 1));
+        let mut pos = from;
+        let start = class(self.char_at(pos));
+        if start != Class::Blank {
+            while class(self.char_at(pos)) == start {
+                let Some(next) = self.step(pos) else {
+                    return end;
+                };
+                pos = next;
+            }
+        }
+        while class(self.char_at(pos)) == Class::Blank {
+            if pos != from && self.is_empty_line(pos) {
+                break;
+            }
+            let Some(next) = self.step(pos) else {
+                return end;
+            };
+            pos = next;
+        }
+        pos
+    }
+
+    /// Start of this word, or the one before
+    fn word_backward(&self, from: Pos) -> Pos {
+        let Some(mut pos) = self.step_back(from) else {
+            return from;
+        };
+        while class(self.char_at(pos)) == Class::Blank && !self.is_empty_line(pos) {
+            let Some(prev) = self.step_back(pos) else {
+                return pos;
+            };
+            pos = prev;
+        }
+        let word = class(self.char_at(pos));
+        while let Some(prev) = self.step_back(pos)
+            && word != Class::Blank
+            && class(self.char_at(prev)) == word
+        {
+            pos = prev;
+        }
+        pos
+    }
+
+    /// End of this word, or the next
+    fn word_end(&self, from: Pos) -> Pos {
+        let Some(mut pos) = self.step(from) else {
+            return from;
+        };
+        while class(self.char_at(pos)) == Class::Blank {
+            let Some(next) = self.step(pos) else {
+                return pos;
+            };
+            pos = next;
+        }
+        let word = class(self.char_at(pos));
+        while let Some(next) = self.step(pos)
+            && class(self.char_at(next)) == word
+        {
+            pos = next;
+        }
+        pos
+    }
+
+    /// Where a motion repeated `count` times goes from the cursor;
+    /// `explicit` is the count if one was typed
+    fn motion_target(&self, motion: Motion, count: usize, explicit: Option<usize>) -> Pos {
+        let last = self.rows.len() - 1;
+        let (y, x) = (self.cy, self.cx);
+        let line = |n: usize| n.saturating_sub(1).min(last);
+        let repeat = |f: &dyn Fn(Pos) -> Pos| (0..count).fold((y, x), |pos, _| f(pos));
+        match motion {
+            Motion::Left => (y, x.saturating_sub(count)),
+            Motion::Right => (y, (x + count).min(self.line_len(y))),
+            Motion::Up => (y.saturating_sub(count), x),
+            Motion::Down => ((y + count).min(last), x),
+            Motion::WordForward => repeat(&|pos| self.word_forward(pos)),
+            Motion::WordBackward => repeat(&|pos| self.word_backward(pos)),
+            Motion::WordEnd => repeat(&|pos| self.word_end(pos)),
+            Motion::LineStart => (y, 0),
+            Motion::FirstNonBlank => (y, self.first_non_blank(y)),
+            Motion::LineEnd => {
+                let y = (y + count - 1).min(last);
+                (y, self.line_len(y).saturating_sub(1))
+            }
+            Motion::FirstLine => {
+                let y = explicit.map_or(0, line);
+                (y, self.first_non_blank(y))
+            }
+            Motion::LastLine => {
+                let y = explicit.map_or(last, line);
+                (y, self.first_non_blank(y))
+            }
+        }
+    }
+
+    /// Keep the cursor on a character, as normal mode does
+    fn clamp_normal(&mut self) {
+        self.cy = self.cy.min(self.rows.len() - 1);
+        self.cx = self.cx.min(self.line_len(self.cy).saturating_sub(1));
+    }
+
+    /// Text from `start` up to `end`, lines joined with newlines
+    fn text_between(&self, start: Pos, end: Pos) -> String {
+        let mut text = String::new();
+        for y in start.0..=end.0 {
+            let from = if y == start.0 { start.1 } else { 0 };
+            let to = if y == end.0 { end.1 } else { usize::MAX };
+            text.extend(
+                self.rows[y]
+                    .chars
+                    .chars()
+                    .skip(from)
+                    .take(to.saturating_sub(from)),
+            );
+            if y != end.0 {
+                text.push('\n');
+            }
+        }
+        text
+    }
+
+    /// Remove the text from `start` up to `end`
+    fn delete_between(&mut self, start: Pos, end: Pos) {
+        let head: String = self.rows[start.0].chars.chars().take(start.1).collect();
+        let tail: String = self.rows[end.0].chars.chars().skip(end.1).collect();
+        self.rows.splice(start.0..=end.0, [Row::new(head + &tail)]);
+        self.mark_dirty();
+    }
+
+    /// Insert `text` at `at`, returning the position just after it
+    fn insert_text(&mut self, (y, x): Pos, text: &str) -> Pos {
+        let head: String = self.rows[y].chars.chars().take(x).collect();
+        let tail: String = self.rows[y].chars.chars().skip(x).collect();
+        let mut lines: Vec<String> = text.split('\n').map(String::from).collect();
+        let n = lines.len();
+        let end = (
+            y + n - 1,
+            lines[n - 1].chars().count() + if n == 1 { x } else { 0 },
+        );
+        lines[0] = head + &lines[0];
+        lines[n - 1].push_str(&tail);
+        self.rows.splice(y..=y, lines.into_iter().map(Row::new));
+        end
+    }
+
+    /// Join `count` following lines onto the cursor's line
+    fn join_lines(&mut self, count: usize) {
+        for _ in 0..count {
+            if self.cy + 1 >= self.rows.len() {
+                break;
+            }
+            let next = self.rows.remove(self.cy + 1);
+            let row = &mut self.rows[self.cy];
+            let joined = next.chars.trim_start();
+            self.cx = row.len();
+            if !joined.is_empty() && !row.chars.is_empty() && !row.chars.ends_with(' ') {
+                row.append(" ");
+            }
+            row.append(joined);
+            self.mark_dirty();
+        }
+    }
+
+    /// Run a `:` command, returning true if the editor should quit
+    pub(super) fn ex_command(&mut self, command: &str) -> bool {
+        let command = command.trim();
+        let write = |ed: &mut Self| match ed.save() {
+            Ok(()) => true,
+            Err(e) => {
+                ed.status_msg = format!("Save failed: {}", e);
+                false
+            }
+        };
+        match command {
+            "" => false,
+            "w" => {
+                write(self);
+                false
+            }
+            "q" if self.dirty => {
+                self.status_msg = String::from("No write since last change (add ! to override)");
+                false
+            }
+            "q" | "q!" => true,
+            "wq" => write(self),
+            "x" => !self.dirty || write(self),
+            _ if command.chars().all(|c| c.is_ascii_digit()) => {
+                self.goto_line(command);
+                false
+            }
+            _ => {
+                if let Some(path) = command.strip_prefix("w ") {
+                    self.filename = Some(path.trim().to_string());
+                    write(self);
+                } else {
+                    self.status_msg = format!("Not an editor command: {}", command);
+                }
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn editor(text: &str) -> (Editor, Vi) {
+        let mut ed = Editor::new();
+        ed.set_text(text);
+        (ed, Vi::default())
+    }
+
+    fn keys(ed: &mut Editor, vi: &mut Vi, keys: &str) {
+        for c in keys.chars() {
+            let key = match c {
+                '\x1b' => Key::Escape,
+                '\n' => Key::Enter,
+                c => Key::Char(c),
+            };
+            match ed.prompt_mode {
+                PromptMode::None => {
+                    vi.process_key(ed, key);
+                }
+                _ => {
+                    ed.process_key_prompt(key);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_motions() {
+        let (mut ed, mut vi) = editor("foo bar.baz\n\n  qux end");
+        keys(&mut ed, &mut vi, "w");
+        assert_eq!((ed.cy, ed.cx), (0, 4));
+        keys(&mut ed, &mut vi, "w");
+        assert_eq!((ed.cy, ed.cx), (0, 7));
+        keys(&mut ed, &mut vi, "2w");
+        assert_eq!((ed.cy, ed.cx), (1, 0));
+        keys(&mut ed, &mut vi, "w^e");
+        assert_eq!((ed.cy, ed.cx), (2, 4));
+        keys(&mut ed, &mut vi, "$b");
+        assert_eq!((ed.cy, ed.cx), (2, 6));
+        keys(&mut ed, &mut vi, "gg$");
+        assert_eq!((ed.cy, ed.cx), (0, 10));
+        keys(&mut ed, &mut vi, "3G0l");
+        assert_eq!((ed.cy, ed.cx), (2, 1));
+        keys(&mut ed, &mut vi, "99l");
+        assert_eq!((ed.cy, ed.cx), (2, 8));
+    }
+
+    #[test]
+    fn test_operators() {
+        let (mut ed, mut vi) = editor("one two three\nfour\nfive");
+        keys(&mut ed, &mut vi, "dw");
+        assert_eq!(ed.text(), "two three\nfour\nfive");
+        keys(&mut ed, &mut vi, "wD");
+        assert_eq!(ed.text(), "two \nfour\nfive");
+        keys(&mut ed, &mut vi, "jdd");
+        assert_eq!(ed.text(), "two \nfive");
+        assert_eq!(vi.register, Some(Register::Lines(vec!["four".into()])));
+        keys(&mut ed, &mut vi, "P");
+        assert_eq!(ed.text(), "two \nfour\nfive");
+        keys(&mut ed, &mut vi, "ggcwsix\x1b");
+        assert_eq!(ed.text(), "six \nfour\nfive");
+        keys(&mut ed, &mut vi, "2yyGp");
+        assert_eq!(ed.text(), "six \nfour\nfive\nsix \nfour");
+        keys(&mut ed, &mut vi, "gg3x");
+        assert_eq!(ed.text(), " \nfour\nfive\nsix \nfour");
+        keys(&mut ed, &mut vi, "jjdgg");
+        assert_eq!(ed.text(), "six \nfour");
+        keys(&mut ed, &mut vi, "P");
+        assert_eq!(ed.text(), " \nfour\nfive\nsix \nfour");
+        keys(&mut ed, &mut vi, "d2j");
+        assert_eq!(ed.text(), "six \nfour");
+        keys(&mut ed, &mut vi, "yep");
+        assert_eq!(ed.text(), "ssixix \nfour");
+        keys(&mut ed, &mut vi, "Jrx");
+        assert_eq!(ed.text(), "ssixix xour");
+    }
+
+    #[test]
+    fn test_insert_and_visual() {
+        let (mut ed, mut vi) = editor("abc");
+        keys(&mut ed, &mut vi, "Ax\x1b");
+        assert_eq!(vi.mode, Mode::Normal);
+        assert_eq!((ed.text(), ed.cx), ("abcx".to_string(), 3));
+        keys(&mut ed, &mut vi, "onext\x1bO\x1b");
+        assert_eq!(ed.text(), "abcx\n\nnext");
+        keys(&mut ed, &mut vi, "ggvly");
+        assert_eq!(vi.mode, Mode::Normal);
+        assert_eq!(vi.register, Some(Register::Chars("ab".into())));
+        keys(&mut ed, &mut vi, "lvjd");
+        assert_eq!(ed.text(), "anext");
+        keys(&mut ed, &mut vi, "Vjc");
+        assert_eq!(vi.mode, Mode::Insert);
+        assert_eq!(ed.text(), "");
+    }
+
+    #[test]
+    fn test_ex_commands() {
+        let (mut ed, mut vi) = editor("a\nb\nc");
+        keys(&mut ed, &mut vi, ":3\n");
+        assert_eq!(ed.cy, 2);
+        keys(&mut ed, &mut vi, "x:q\n");
+        assert!(ed.status_msg.starts_with("No write since last change"));
+        assert!(ed.ex_command("q!"));
+        keys(&mut ed, &mut vi, ":bogus\n");
+        assert_eq!(ed.status_msg, "Not an editor command: bogus");
+        keys(&mut ed, &mut vi, "gg/b\nggn");
+        assert_eq!(ed.cy, 1);
+    }
+}
@@ -0,0 +1,307 @@
+//! AES-GCM authenticated encryption
+//!
+//! AES (FIPS 197) with 128- or 256-bit keys, in Galois/Counter Mode (NIST
+//! SP 800-38D) with 96-bit nonces and 128-bit tags. Like the rest of
+//! [`crypto`](super::crypto) it is written out here rather than pulled from
+//! a cipher crate. The S-box is a table lookup, so this is not hardened
+//! against cache-timing attacks by other code on the same machine.
+//!
+//! A nonce must never be used twice with one key; callers draw them from
+//! the kernel CSPRNG.
+
+use super::crypto::CryptoError;
+
+/// Nonce length in bytes
+pub const NONCE_LEN: usize = 12;
+
+/// Tag length in bytes
+pub const TAG_LEN: usize = 16;
+
+const BLOCK_LEN: usize = 16;
+
+const SBOX: [u8; 256] = [
+    0x63, 0x7c, 0x77, 0x7b, 0xf2, 0x6b, 0x6f, 0xc5, 0x30, 0x01, 0x67, 0x2b, 0xfe, 0xd7, 0xab, 0x76,
+    0xca, 0x82, 0xc9, 0x7d, 0xfa, 0x59, 0x47, 0xf0, 0xad, 0xd4, 0xa2, 0xaf, 0x9c, 0xa4, 0x72, 0xc0,
+    0xb7, 0xfd, 0x93, 0x26, 0x36, 0x3f, 0xf7, 0xcc, 0x34, 0xa5, 0xe5, 0xf1, 0x71, 0xd8, 0x31, 0x15,
+    0x04, 0xc7, 0x23, 0xc3, 0x18, 0x96, 0x05, 0x9a, 0x07, 0x12, 0x80, 0xe2, 0xeb, 0x27, 0xb2, 0x75,
+    0x09, 0x83, 0x2c, 0x1a, 0x1b, 0x6e, 0x5a, 0xa0, 0x52, 0x3b, 0xd6, 0xb3, 0x29, 0xe3, 0x2f, 0x84,
+    0x53, 0xd1, 0x00, 0xed, 0x20, 0xfc, 0xb1, 0x5b, 0x6a, 0xcb, 0xbe, 0x39, 0x4a, 0x4c, 0x58, 0xcf,
+    0xd0, 0xef, 0xaa, 0xfb, 0x43, 0x4d, 0x33, 0x85, 0x45, 0xf9, 0x02, 0x7f, 0x50, 0x3c, 0x9f, 0xa8,
+    0x51, 0xa3, 0x40, 0x8f, 0x92, 0x9d, 0x38, 0xf5, 0xbc, 0xb6, 0xda, 0x21, 0x10, 0xff, 0xf3, 0xd2,
+    0xcd, 0x0c, 0x13, 0xec, 0x5f, 0x97, 0x44, 0x17, 0xc4, 0xa7, 0x7e, 0x3d, 0x64, 0x5d, 0x19, 0x73,
+    0x60, 0x81, 0x4f, 0xdc, 0x22, 0x2a, 0x90, 0x88, 0x46, 0xee, 0xb8, 0x14, 0xde, 0x5e, 0x0b, 0xdb,
+    0xe0, 0x32, 0x3a, 0x0a, 0x49, 0x06, 0x24, 0x5c, 0xc2, 0xd3, 0xac, 0x62, 0x91, 0x95, 0xe4, 0x79,
+    0xe7, 0xc8, 0x37, 0x6d, 0x8d, 0xd5, 0x4e, 0xa9, 0x6c, 0x56, 0xf4, 0xea, 0x65, 0x7a, 0xae, 0x08,
+    0xba, 0x78, 0x25, 0x2e, 0x1c, 0xa6, 0xb4, 0xc6, 0xe8, 0xdd, 0x74, 0x1f, 0x4b, 0xbd, 0x8b, 0x8a,
+    0x70, 0x3e, 0xb5, 0x66, 0x48, 0x03, 0xf6, 0x0e, 0x61, 0x35, 0x57, 0xb9, 0x86, 0xc1, 0x1d, 0x9e,
+    0xe1, 0xf8, 0x98, 0x11, 0x69, 0xd9, 0x8e, 0x94, 0x9b, 0x1e, 0x87, 0xe9, 0xce, 0x55, 0x28, 0xdf,
+    0x8c, 0xa1, 0x89, 0x0d, 0xbf, 0xe6, 0x42, 0x68, 0x41, 0x99, 0x2d, 0x0f, 0xb0, 0x54, 0xbb, 0x16,
+];
+
+/// The reduction constant of GHASH's field, in its reflected bit order
+const R: u128 = 0xe1 << 120;
+
+/// Multiply by x in GF(2^8)
+fn xtime(b: u8) -> u8 {
+    (b << 1) ^ if b & 0x80 != 0 { 0x1b } else { 0 }
+}
+
+/// The AES block cipher, encryption direction only (all GCM needs)
+pub struct Aes {
+    round_keys: Vec<[u8; BLOCK_LEN]>,
+}
+
+impl Aes {
+    /// Expand a 16-, 24- or 32-byte key
+    pub fn new(key: &[u8]) -> Option<Self> {
+        let nk = match key.len() {
+            16 | 24 | 32 => key.len() / 4,
+            _ => return None,
+        };
+        let rounds = nk + 6;
+        let mut words: Vec<[u8; 4]> = key
+            .chunks_exact(4)
+            .map(|w| [w[0], w[1], w[2], w[3]])
+            .collect();
+        let mut rcon = 1;
+        for i in nk..4 * (rounds + 1) {
+            let mut t = words[i - 1];
+            if i % nk == 0 {
+                t.rotate_left(1);
+                t = t.map(|b| SBOX[b as usize]);
+                t[0] ^= rcon;
+                rcon = xtime(rcon);
+            } else if nk > 6 && i % nk == 4 {
+                t = t.map(|b| SBOX[b as usize]);
+            }
+            let back = words[i - nk];
+            words.push(std::array::from_fn(|j| back[j] ^ t[j]));
+        }
+        let round_keys = words
+            .chunks_exact(4)
+            .map(|w| std::array::from_fn(|j| w[j / 4][j % 4]))
+            .collect();
+        Some(Self { round_keys })
+    }
+
+    /// Encrypt one block in place
+    pub fn encrypt_block(&self, block: &mut [u8; BLOCK_LEN]) {
+        let last = self.round_keys.len() - 1;
+        add_round_key(block, &self.round_keys[0]);
+        for round_key in &self.round_keys[1..last] {
+            sub_shift(block);
+            mix_columns(block);
+            add_round_key(block, round_key);
+        }
+        sub_shift(block);
+        add_round_key(block, &self.round_keys[last]);
+    }
+}
+
+fn add_round_key(block: &mut [u8; BLOCK_LEN], key: &[u8; BLOCK_LEN]) {
+    for (b, k) in block.iter_mut().zip(key) {
+        *b ^= k;
+    }
+}
+
+/// SubBytes and ShiftRows; the state is stored column by column
+fn sub_shift(block: &mut [u8; BLOCK_LEN]) {
+    let old = *block;
+    for (i, b) in block.iter_mut().enumerate() {
+        let (row, col) = (i % 4, i / 4);
+        *b = SBOX[old[row + 4 * ((col + row) % 4)] as usize];
+    }
+}
+
+fn mix_columns(block: &mut [u8; BLOCK_LEN]) {
+    for col in block.chunks_exact_mut(4) {
+        let [a0, a1, a2, a3] = [col[0], col[1], col[2], col[3]];
+        let all = a0 ^ a1 ^ a2 ^ a3;
+        col[0] ^= all ^ xtime(a0 ^ a1);
+        col[1] ^= all ^ xtime(a1 ^ a2);
+        col[2] ^= all ^ xtime(a2 ^ a3);
+        col[3] ^= all ^ xtime(a3 ^ a0);
+    }
+}
+
+/// Multiply in GHASH's field
+fn gf_mul(x: u128, y: u128) -> u128 {
+    let mut z = 0;
+    let mut v = y;
+    for i in (0..128).rev() {
+        if (x >> i) & 1 == 1 {
+            z ^= v;
+        }
+        v = if v & 1 == 1 { (v >> 1) ^ R } else { v >> 1 };
+    }
+    z
+}
+
+/// AES in Galois/Counter Mode
+pub struct AesGcm {
+    aes: Aes,
+    /// The hash key, the encrypted zero block
+    h: u128,
+}
+
+impl AesGcm {
+    /// GCM with a 16-, 24- or 32-byte key
+    pub fn new(key: &[u8]) -> Option<Self> {
+        let aes = Aes::new(key)?;
+        let mut zero = [0; BLOCK_LEN];
+        aes.encrypt_block(&mut zero);
+        Some(Self {
+            aes,
+            h: u128::from_be_bytes(zero),
+        })
+    }
+
+    /// XOR `data` with the keystream starting at counter block `counter`
+    fn apply_ctr(&self, nonce: &[u8; NONCE_LEN], counter: u32, data: &mut [u8]) {
+        let mut block = [0; BLOCK_LEN];
+        block[..NONCE_LEN].copy_from_slice(nonce);
+        for (i, chunk) in data.chunks_mut(BLOCK_LEN).enumerate() {
+            let mut keystream = block;
+            keystream[NONCE_LEN..].copy_from_slice(&counter.wrapping_add(i as u32).to_be_bytes());
+            self.aes.encrypt_block(&mut keystream);
+            for (d, k) in chunk.iter_mut().zip(keystream) {
+                *d ^= k;
+            }
+        }
+    }
+
+    fn ghash(&self, aad: &[u8], ciphertext: &[u8]) -> u128 {
+        let mut y = 0;
+        for data in [aad, ciphertext] {
+            for chunk in data.chunks(BLOCK_LEN) {
+                let mut block = [0; BLOCK_LEN];
+                block[..chunk.len()].copy_from_slice(chunk);
+                y = gf_mul(y ^ u128::from_be_bytes(block), self.h);
+            }
+        }
+        let lengths = ((aad.len() as u128 * 8) << 64) | (ciphertext.len() as u128 * 8);
+        gf_mul(y ^ lengths, self.h)
+    }
+
+    fn tag(&self, nonce: &[u8; NONCE_LEN], aad: &[u8], ciphertext: &[u8]) -> [u8; TAG_LEN] {
+        let mut tag = self.ghash(aad, ciphertext).to_be_bytes();
+        self.apply_ctr(nonce, 1, &mut tag);
+        tag
+    }
+
+    /// Encrypt `plaintext`, authenticating it and `aad`; returns the
+    /// ciphertext followed by the tag
+    pub fn seal(&self, nonce: &[u8; NONCE_LEN], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+        let mut sealed = plaintext.to_vec();
+        self.apply_ctr(nonce, 2, &mut sealed);
+        let tag = self.tag(nonce, aad, &sealed);
+        sealed.extend_from_slice(&tag);
+        sealed
+    }
+
+    /// Check and decrypt the output of [`seal`](Self::seal)
+    pub fn open(
+        &self,
+        nonce: &[u8; NONCE_LEN],
+        aad: &[u8],
+        sealed: &[u8],
+    ) -> Result<Vec<u8>, CryptoError> {
+        if sealed.len() < TAG_LEN {
+            return Err(CryptoError::Truncated);
+        }
+        let (ciphertext, tag) = sealed.split_at(sealed.len() - TAG_LEN);
+        let expected = self.tag(nonce, aad, ciphertext);
+        // Compare without an early exit
+        if expected
+            .iter()
+            .zip(tag)
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            != 0
+        {
+            return Err(CryptoError::BadPassword);
+        }
+        let mut plaintext = ciphertext.to_vec();
+        self.apply_ctr(nonce, 2, &mut plaintext);
+        Ok(plaintext)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn unhex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_aes_vectors() {
+        // FIPS 197 appendix C.1 and C.3
+        let plaintext: [u8; 16] = unhex("00112233445566778899aabbccddeeff")
+            .try_into()
+            .unwrap();
+        let key: Vec<u8> = (0..32).collect();
+        for (len, expected) in [
+            (16, "69c4e0d86a7b0430d8cdb78070b4c55a"),
+            (32, "8ea2b7ca516745bfeafc49904b496089"),
+        ] {
+            let mut block = plaintext;
+            Aes::new(&key[..len]).unwrap().encrypt_block(&mut block);
+            assert_eq!(hex(&block), expected);
+        }
+        assert!(Aes::new(&key[..20]).is_none());
+    }
+
+    #[test]
+    fn test_gcm_vectors() {
+        // The AES-256 cases from the GCM specification
+        let gcm = AesGcm::new(&[0; 32]).unwrap();
+        assert_eq!(
+            hex(&gcm.seal(&[0; 12], b"", b"")),
+            "530f8afbc74536b9a963b4f1c4cb738b"
+        );
+        assert_eq!(
+            hex(&gcm.seal(&[0; 12], b"", &[0; 16])),
+            "cea7403d4d606b6e074ec5d3baf39d18d0d1c8a799996bf0265b98b5d48ab919"
+        );
+
+        let gcm = AesGcm::new(&unhex(&"feffe9928665731c6d6a8f9467308308".repeat(2))).unwrap();
+        let nonce: [u8; 12] = unhex("cafebabefacedbaddecaf888").try_into().unwrap();
+        let aad = unhex("feedfacedeadbeeffeedfacedeadbeefabaddad2");
+        let plaintext = unhex(
+            "d9313225f88406e5a55909c5aff5269a86a7a9531534f7da2e4c303d8a318a72\
+             1c3c0c95956809532fcf0e2449a6b525b16aedf5aa0de657ba637b39",
+        );
+        let sealed = gcm.seal(&nonce, &aad, &plaintext);
+        assert_eq!(
+            hex(&sealed),
+            "522dc1f099567d07f47f37a32a84427d643a8cdcbfe5c0c97598a2bd2555d1aa\
+             8cb08e48590dbb3da7b08b1056828838c5f61e6393ba7a0abcc9f662\
+             76fc6ece0f4e1768cddf8853bb2d551b"
+        );
+        assert_eq!(gcm.open(&nonce, &aad, &sealed), Ok(plaintext));
+
+        // Any change to the ciphertext, tag or associated data is caught
+        let mut damaged = sealed.clone();
+        damaged[0] ^= 1;
+        assert_eq!(
+            gcm.open(&nonce, &aad, &damaged),
+            Err(CryptoError::BadPassword)
+        );
+        assert_eq!(
+            gcm.open(&nonce, b"other", &sealed),
+            Err(CryptoError::BadPassword)
+        );
+        assert_eq!(
+            gcm.open(&nonce, &aad, &sealed[..8]),
+            Err(CryptoError::Truncated)
+        );
+    }
+}
@@ -0,0 +1,162 @@
+//! Crash reports for tasks that panicked
+//!
+//! The executor catches a panic in a task's poll and drops the task instead
+//! of letting it unwind through the whole OS. The kernel then finds the
+//! process that task ran, if any, and ends it as if it had exited with
+//! [`FAULT_STATUS`]: a zombie its parent can reap, with its locks and
+//! semaphore adjustments released. Every fault produces a [`CrashReport`],
+//! kept in memory and written under [`CRASH_DIR`].
+//!
+//! Kernel state a panicking task was in the middle of changing is not
+//! rolled back; borrows of the kernel are released as the panic unwinds,
+//! so the rest of the system can still get at it.
+//!
+//! Subsystems holding work that isn't on disk yet, like the editor's
+//! unsaved buffers, can register a hook with [`on_fault`] to write it out
+//! once faults have been handled, in case worse follows.
+
+use super::executor::{Priority, TaskFault};
+use super::process::Pid;
+use super::task::TaskId;
+use std::cell::RefCell;
+
+thread_local! {
+    /// Hooks run after task faults
+    static FAULT_HOOKS: RefCell<Vec<fn()>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Run `hook` after every batch of task faults, once crash reports are
+/// written and the kernel is free to call into
+pub fn on_fault(hook: fn()) {
+    FAULT_HOOKS.with(|hooks| hooks.borrow_mut().push(hook));
+}
+
+/// Run the hooks registered with [`on_fault`]
+pub(crate) fn run_fault_hooks() {
+    let hooks = FAULT_HOOKS.with(|hooks| hooks.borrow().clone());
+    for hook in hooks {
+        hook();
+    }
+}
+
+/// Directory crash reports are written to
+pub const CRASH_DIR: &str = "/var/log/crash";
+
+/// Exit status of a process whose task panicked (128 + SIGSEGV, what a
+/// Unix shell reports for a segfault)
+pub const FAULT_STATUS: i32 = 139;
+
+/// Crash reports kept in memory
+pub const MAX_CRASH_REPORTS: usize = 16;
+
+/// What is known about one task fault
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrashReport {
+    /// When it was handled
+    pub time: f64,
+    pub task: TaskId,
+    pub priority: Priority,
+    /// The process the task ran, if it belonged to one
+    pub pid: Option<Pid>,
+    /// Process name, or "kernel" for a task without a process
+    pub name: String,
+    pub message: String,
+    pub location: Option<String>,
+}
+
+impl CrashReport {
+    pub fn new(fault: &TaskFault, pid: Option<Pid>, name: &str, time: f64) -> Self {
+        Self {
+            time,
+            task: fault.task,
+            priority: fault.priority,
+            pid,
+            name: name.to_string(),
+            message: fault.message.clone(),
+            location: fault.location.clone(),
+        }
+    }
+
+    /// File name under [`CRASH_DIR`], e.g. `sh.12.1500`
+    pub fn file_name(&self) -> String {
+        let pid = self.pid.map_or(0, |p| p.0);
+        let name = self.name.replace('/', "_");
+        format!("{}.{}.{:.0}", name, pid, self.time)
+    }
+
+    /// One-line summary for the log and the tracer
+    pub fn summary(&self) -> String {
+        let who = match self.pid {
+            Some(pid) => format!("{} (pid {})", self.name, pid.0),
+            None => format!("{} (task {})", self.name, self.task.0),
+        };
+        match &self.location {
+            Some(location) => format!("{} panicked at {}: {}", who, location, self.message),
+            None => format!("{} panicked: {}", who, self.message),
+        }
+    }
+
+    /// The full report, as written to disk
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        text.push_str(&format!("Time:     {:.3} ms\n", self.time));
+        match self.pid {
+            Some(pid) => text.push_str(&format!("Process:  {} (pid {})\n", self.name, pid.0)),
+            None => text.push_str(&format!("Process:  none ({})\n", self.name)),
+        }
+        text.push_str(&format!(
+            "Task:     {} ({:?})\n",
+            self.task.0, self.priority
+        ));
+        if self.pid.is_some() {
+            text.push_str(&format!("Status:   {} (fault)\n", FAULT_STATUS));
+        }
+        text.push_str(&format!("Panic:    {}\n", self.message));
+        if let Some(location) = &self.location {
+            text.push_str(&format!("Location: {}\n", location));
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_report_text() {
+        let fault = TaskFault {
+            task: TaskId(4),
+            priority: Priority::Normal,
+            message: "index out of bounds".to_string(),
+            location: Some("src/app.rs:10:5".to_string()),
+        };
+        let report = CrashReport::new(&fault, Some(Pid(12)), "edit", 1500.0);
+        assert_eq!(report.file_name(), "edit.12.1500");
+        assert_eq!(
+            report.summary(),
+            "edit (pid 12) panicked at src/app.rs:10:5: index out of bounds"
+        );
+        let text = report.to_text();
+        assert!(text.contains("Process:  edit (pid 12)\n"));
+        assert!(text.contains("Status:   139 (fault)\n"));
+        assert!(text.contains("Location: src/app.rs:10:5\n"));
+
+        let report = CrashReport::new(&fault, None, "kernel", 0.0);
+        assert_eq!(report.file_name(), "kernel.0.0");
+        assert!(report.summary().starts_with("kernel (task 4) panicked at"));
+        assert!(!report.to_text().contains("Status:"));
+    }
+
+    #[test]
+    fn test_fault_hooks() {
+        thread_local! {
+            static RUNS: Cell<u32> = const { Cell::new(0) };
+        }
+        on_fault(|| RUNS.with(|r| r.set(r.get() + 1)));
+        run_fault_hooks();
+        run_fault_hooks();
+        assert_eq!(RUNS.with(Cell::get), 2);
+    }
+}
@@ -0,0 +1,229 @@
+//! Password-based encryption
+//!
+//! Built from SHA-256 alone (see [`Checksum`]), so no
+//! cipher crate is needed:
+//!
+//! - PBKDF2-HMAC-SHA256 turns the password and a random salt into a key
+//! - HMAC-SHA256 of a random nonce and a block counter is the keystream,
+//!   XORed with the plaintext (a PRF in counter mode)
+//! - HMAC-SHA256 of everything before it authenticates the result, so a
+//!   wrong password or a damaged file is detected before anything is used
+//!
+//! Sealed data is laid out as
+//!
+//! ```text
+//! salt (16) | nonce (16) | iterations (u32 LE) | ciphertext | tag (32)
+//! ```
+
+use super::pkg::Checksum;
+use super::syscall::generate_random_bytes;
+use std::fmt;
+
+/// PBKDF2 rounds for new data; the count is stored, so it can change
+pub const KDF_ITERATIONS: u32 = 100_000;
+
+/// Most PBKDF2 rounds stored data may ask for; the count is read before
+/// anything is authenticated, so a damaged or planted header must not be
+/// able to keep the kernel deriving keys for hours
+pub const KDF_MAX_ITERATIONS: u32 = 10 * KDF_ITERATIONS;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 16;
+const HEADER_LEN: usize = SALT_LEN + NONCE_LEN + 4;
+const TAG_LEN: usize = 32;
+const BLOCK_LEN: usize = 64;
+
+/// Why sealed data couldn't be opened
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CryptoError {
+    /// Too short to hold a header and tag
+    Truncated,
+    /// The tag doesn't match: wrong password, or the data was changed
+    BadPassword,
+    /// The stored PBKDF2 round count is 0 or above [`KDF_MAX_ITERATIONS`]
+    BadIterations,
+}
+
+impl fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CryptoError::Truncated => write!(f, "encrypted data is truncated"),
+            CryptoError::BadPassword => write!(f, "wrong password or damaged data"),
+            CryptoError::BadIterations => write!(f, "damaged data (bad key derivation rounds)"),
+        }
+    }
+}
+
+impl std::error::Error for CryptoError {}
+
+fn sha256(data: &[u8]) -> [u8; 32] {
+    *Checksum::compute(data).as_bytes()
+}
+
+/// HMAC-SHA256 (RFC 2104)
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut block = [0u8; BLOCK_LEN];
+    if key.len() > BLOCK_LEN {
+        block[..32].copy_from_slice(&sha256(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner = Vec::with_capacity(BLOCK_LEN + data.len());
+    inner.extend(block.iter().map(|b| b ^ 0x36));
+    inner.extend_from_slice(data);
+    let inner = sha256(&inner);
+
+    let mut outer = Vec::with_capacity(BLOCK_LEN + 32);
+    outer.extend(block.iter().map(|b| b ^ 0x5c));
+    outer.extend_from_slice(&inner);
+    sha256(&outer)
+}
+
+/// PBKDF2-HMAC-SHA256 (RFC 8018), one 32-byte block
+pub fn pbkdf2_sha256(password: &[u8], salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut input = salt.to_vec();
+    input.extend_from_slice(&1u32.to_be_bytes());
+    let mut u = hmac_sha256(password, &input);
+    let mut key = u;
+    for _ in 1..iterations {
+        u = hmac_sha256(password, &u);
+        for (k, b) in key.iter_mut().zip(u) {
+            *k ^= b;
+        }
+    }
+    key
+}
+
+/// Check a PBKDF2 round count read from stored data, before deriving a
+/// key with it
+pub fn check_iterations(iterations: u32) -> Result<u32, CryptoError> {
+    if iterations == 0 || iterations > KDF_MAX_ITERATIONS {
+        return Err(CryptoError::BadIterations);
+    }
+    Ok(iterations)
+}
+
+/// Separate keys for encryption and authentication
+fn derive_keys(password: &str, salt: &[u8], iterations: u32) -> ([u8; 32], [u8; 32]) {
+    let master = pbkdf2_sha256(password.as_bytes(), salt, iterations);
+    (hmac_sha256(&master, b"enc"), hmac_sha256(&master, b"mac"))
+}
+
+/// XOR `data` with the keystream for `nonce`
+fn apply_keystream(key: &[u8; 32], nonce: &[u8], data: &mut [u8]) {
+    let mut input = nonce.to_vec();
+    input.extend_from_slice(&[0u8; 8]);
+    for (counter, chunk) in data.chunks_mut(32).enumerate() {
+        input[NONCE_LEN..].copy_from_slice(&(counter as u64).to_le_bytes());
+        let block = hmac_sha256(key, &input);
+        for (d, k) in chunk.iter_mut().zip(block) {
+            *d ^= k;
+        }
+    }
+}
+
+/// Encrypt `plaintext` with `password`
+pub fn encrypt(password: &str, plaintext: &[u8]) -> Vec<u8> {
+    encrypt_with(password, plaintext, KDF_ITERATIONS)
+}
+
+/// Encrypt with a given number of PBKDF2 rounds
+pub fn encrypt_with(password: &str, plaintext: &[u8], iterations: u32) -> Vec<u8> {
+    let salt = generate_random_bytes(SALT_LEN);
+    let nonce = generate_random_bytes(NONCE_LEN);
+    let (enc_key, mac_key) = derive_keys(password, &salt, iterations);
+
+    let mut sealed = Vec::with_capacity(HEADER_LEN + plaintext.len() + TAG_LEN);
+    sealed.extend_from_slice(&salt);
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&iterations.to_le_bytes());
+    sealed.extend_from_slice(plaintext);
+    apply_keystream(&enc_key, &nonce, &mut sealed[HEADER_LEN..]);
+    let tag = hmac_sha256(&mac_key, &sealed);
+    sealed.extend_from_slice(&tag);
+    sealed
+}
+
+/// Check and decrypt data from [`encrypt`]
+pub fn decrypt(password: &str, sealed: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    if sealed.len() < HEADER_LEN + TAG_LEN {
+        return Err(CryptoError::Truncated);
+    }
+    let (body, tag) = sealed.split_at(sealed.len() - TAG_LEN);
+    let salt = &body[..SALT_LEN];
+    let nonce = &body[SALT_LEN..SALT_LEN + NONCE_LEN];
+    let mut rounds = [0u8; 4];
+    rounds.copy_from_slice(&body[SALT_LEN + NONCE_LEN..HEADER_LEN]);
+    let iterations = check_iterations(u32::from_le_bytes(rounds))?;
+
+    let (enc_key, mac_key) = derive_keys(password, salt, iterations);
+    let expected = hmac_sha256(&mac_key, body);
+    // Compare without an early exit
+    if expected
+        .iter()
+        .zip(tag)
+        .fold(0, |acc, (a, b)| acc | (a ^ b))
+        != 0
+    {
+        return Err(CryptoError::BadPassword);
+    }
+
+    let mut plaintext = body[HEADER_LEN..].to_vec();
+    apply_keystream(&enc_key, nonce, &mut plaintext);
+    Ok(plaintext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn test_hmac_and_pbkdf2_vectors() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        // RFC 7914 section 11
+        assert_eq!(
+            hex(&pbkdf2_sha256(b"passwd", b"salt", 1)),
+            "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc"
+        );
+    }
+
+    #[test]
+    fn test_encrypt_roundtrip() {
+        let plaintext = b"the whole filesystem, and then some more bytes".repeat(3);
+        let sealed = encrypt_with("hunter2", &plaintext, 10);
+        assert_eq!(sealed.len(), HEADER_LEN + plaintext.len() + TAG_LEN);
+        assert!(!sealed.windows(9).any(|w| w == b"the whole"));
+        assert_eq!(decrypt("hunter2", &sealed), Ok(plaintext.clone()));
+
+        // Salt and nonce are fresh each time
+        assert_ne!(encrypt_with("hunter2", &plaintext, 10), sealed);
+
+        assert_eq!(decrypt("hunter3", &sealed), Err(CryptoError::BadPassword));
+        let mut damaged = sealed.clone();
+        damaged[HEADER_LEN] ^= 1;
+        assert_eq!(decrypt("hunter2", &damaged), Err(CryptoError::BadPassword));
+        assert_eq!(
+            decrypt("hunter2", &sealed[..40]),
+            Err(CryptoError::Truncated)
+        );
+
+        // The round count is checked before any key is derived from it
+        for rounds in [0, KDF_MAX_ITERATIONS + 1, u32::MAX] {
+            let mut planted = sealed.clone();
+            planted[SALT_LEN + NONCE_LEN..HEADER_LEN].copy_from_slice(&rounds.to_le_bytes());
+            assert_eq!(
+                decrypt("hunter2", &planted),
+                Err(CryptoError::BadIterations)
+            );
+        }
+    }
+}
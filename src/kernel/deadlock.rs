@@ -0,0 +1,251 @@
+//! Deadlock detection for IPC waits
+//!
+//! Nothing in the kernel sleeps: a blocking lock, semaphore or channel
+//! operation that can't proceed returns and is retried. While it's being
+//! retried the process is waiting, and the wait-for graph records on what
+//! and for whom - the processes that have to act before it can go on.
+//!
+//! When a new wait would close a cycle (A waits for B, B waits for A) no
+//! process in it can ever make progress. The process whose wait closed the
+//! cycle is the one that fails, with `SyscallError::Deadlock`; the others
+//! stay as they are and proceed once it backs off. The cycle is kept in
+//! [`WaitForGraph::reports`] and traced for postmortem analysis.
+//!
+//! A process waits on one thing at a time: a new wait replaces the old one,
+//! and success, exit or giving up ([`WaitForGraph::clear`]) removes it.
+
+use super::process::Pid;
+use super::semaphore::SemId;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+
+/// Detected cycles kept for inspection
+pub const MAX_REPORTS: usize = 16;
+
+/// Something a process can wait on
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum WaitResource {
+    /// A whole-file or byte-range lock on a path
+    FileLock(String),
+    /// One semaphore of a set
+    Semaphore(SemId, usize),
+    /// A bounded channel (full for a sender, empty for a receiver)
+    Channel(u64),
+}
+
+impl fmt::Display for WaitResource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WaitResource::FileLock(path) => write!(f, "lock on {}", path),
+            WaitResource::Semaphore(id, num) => write!(f, "semaphore {}[{}]", id.0, num),
+            WaitResource::Channel(id) => write!(f, "channel {}", id),
+        }
+    }
+}
+
+/// One step of a cycle: `pid` waits on `resource`, held by the next pid
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WaitEdge {
+    pub pid: Pid,
+    pub resource: WaitResource,
+}
+
+/// A detected deadlock
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeadlockReport {
+    /// When it was detected
+    pub time: f64,
+    /// The process that was failed
+    pub victim: Pid,
+    /// The cycle, starting at the victim; the last edge leads back to it
+    pub cycle: Vec<WaitEdge>,
+}
+
+impl fmt::Display for DeadlockReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for edge in &self.cycle {
+            write!(f, "{} -> {} -> ", edge.pid.0, edge.resource)?;
+        }
+        write!(f, "{}", self.victim.0)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Wait {
+    resource: WaitResource,
+    holders: Vec<Pid>,
+}
+
+/// Which process waits on what, and for whom
+#[derive(Debug, Default)]
+pub struct WaitForGraph {
+    waits: HashMap<Pid, Wait>,
+    reports: VecDeque<DeadlockReport>,
+}
+
+impl WaitForGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that `waiter` is waiting on `resource` until `holders` act
+    ///
+    /// If that would close a cycle nothing is recorded, the cycle is kept
+    /// as a report and returned.
+    pub fn wait(
+        &mut self,
+        waiter: Pid,
+        resource: WaitResource,
+        holders: &[Pid],
+        now: f64,
+    ) -> Result<(), DeadlockReport> {
+        let holders: Vec<Pid> = holders.iter().copied().filter(|&p| p != waiter).collect();
+        let wait = Wait { resource, holders };
+
+        if let Some(path) = self.path_to(waiter, &wait.holders) {
+            let mut cycle = vec![WaitEdge {
+                pid: waiter,
+                resource: wait.resource,
+            }];
+            cycle.extend(path);
+            self.waits.remove(&waiter);
+
+            let report = DeadlockReport {
+                time: now,
+                victim: waiter,
+                cycle,
+            };
+            if self.reports.len() >= MAX_REPORTS {
+                self.reports.pop_front();
+            }
+            self.reports.push_back(report.clone());
+            return Err(report);
+        }
+
+        self.waits.insert(waiter, wait);
+        Ok(())
+    }
+
+    /// Stop tracking `pid`'s wait (it got what it waited for or gave up)
+    pub fn clear(&mut self, pid: Pid) {
+        self.waits.remove(&pid);
+    }
+
+    /// Forget an exited process, as a waiter and as a holder
+    pub fn remove(&mut self, pid: Pid) {
+        self.waits.remove(&pid);
+        for wait in self.waits.values_mut() {
+            wait.holders.retain(|&p| p != pid);
+        }
+    }
+
+    /// What `pid` is waiting on, if anything
+    pub fn waiting_on(&self, pid: Pid) -> Option<&WaitResource> {
+        self.waits.get(&pid).map(|w| &w.resource)
+    }
+
+    /// Recently detected deadlocks, oldest first
+    pub fn reports(&self) -> &VecDeque<DeadlockReport> {
+        &self.reports
+    }
+
+    /// Find a chain of waits from one of `from` back to `target`
+    ///
+    /// Returns the edges walked, starting at the first holder.
+    fn path_to(&self, target: Pid, from: &[Pid]) -> Option<Vec<WaitEdge>> {
+        let mut visited = HashSet::new();
+        let mut path = Vec::new();
+        from.iter()
+            .any(|&pid| self.search(pid, target, &mut visited, &mut path))
+            .then_some(path)
+    }
+
+    fn search(
+        &self,
+        pid: Pid,
+        target: Pid,
+        visited: &mut HashSet<Pid>,
+        path: &mut Vec<WaitEdge>,
+    ) -> bool {
+        if pid == target {
+            return true;
+        }
+        if !visited.insert(pid) {
+            return false;
+        }
+        let Some(wait) = self.waits.get(&pid) else {
+            return false;
+        };
+
+        path.push(WaitEdge {
+            pid,
+            resource: wait.resource.clone(),
+        });
+        if wait
+            .holders
+            .iter()
+            .any(|&next| self.search(next, target, visited, path))
+        {
+            return true;
+        }
+        path.pop();
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lock(path: &str) -> WaitResource {
+        WaitResource::FileLock(path.to_string())
+    }
+
+    #[test]
+    fn test_cycle_detection() {
+        let mut graph = WaitForGraph::new();
+
+        // 1 waits for 2, 2 waits for 3: a chain, not a cycle
+        assert!(graph.wait(Pid(1), lock("/a"), &[Pid(2)], 0.0).is_ok());
+        assert!(
+            graph
+                .wait(Pid(2), WaitResource::Semaphore(SemId(0), 1), &[Pid(3)], 0.0)
+                .is_ok()
+        );
+        assert!(graph.reports().is_empty());
+
+        // 3 waiting for 1 closes it; 3 is failed and not recorded
+        let report = graph
+            .wait(Pid(3), WaitResource::Channel(7), &[Pid(4), Pid(1)], 1.0)
+            .unwrap_err();
+        assert_eq!(report.victim, Pid(3));
+        let pids: Vec<Pid> = report.cycle.iter().map(|e| e.pid).collect();
+        assert_eq!(pids, [Pid(3), Pid(1), Pid(2)]);
+        assert_eq!(
+            report.to_string(),
+            "3 -> channel 7 -> 1 -> lock on /a -> 2 -> semaphore 0[1] -> 3"
+        );
+        assert_eq!(graph.waiting_on(Pid(3)), None);
+        assert_eq!(graph.reports().len(), 1);
+
+        // Once 2 stops waiting there is no cycle
+        graph.clear(Pid(2));
+        assert!(graph.wait(Pid(3), lock("/b"), &[Pid(1)], 2.0).is_ok());
+        assert_eq!(graph.waiting_on(Pid(3)), Some(&lock("/b")));
+    }
+
+    #[test]
+    fn test_removed_process_breaks_cycle() {
+        let mut graph = WaitForGraph::new();
+
+        assert!(graph.wait(Pid(1), lock("/a"), &[Pid(2)], 0.0).is_ok());
+        // Waiting on yourself is not a deadlock
+        assert!(graph.wait(Pid(2), lock("/b"), &[Pid(2)], 0.0).is_ok());
+
+        // Once 2 has exited, 1 no longer waits for it
+        graph.remove(Pid(2));
+        assert_eq!(graph.waiting_on(Pid(2)), None);
+        assert!(graph.wait(Pid(2), lock("/b"), &[Pid(1)], 0.0).is_ok());
+        assert!(graph.reports().is_empty());
+    }
+}
@@ -6,12 +6,15 @@
 //! - Register/argument viewing
 //! - Execution history
 //! - Step-through debugging (at syscall boundaries)
+//! - Path watchpoints (break when a process opens, writes or unlinks a path)
 //!
 //! This debugger operates at the syscall level rather than instruction level,
 //! providing practical debugging without requiring WASM bytecode manipulation.
 
+use super::ksyms::SymbolMap;
 use super::process::{Fd, Pid};
 use super::task::TaskId;
+use super::users::Uid;
 use std::collections::{HashMap, HashSet, VecDeque};
 
 /// Maximum execution history entries
@@ -23,6 +26,12 @@ const MAX_WATCHES: usize = 64;
 /// Maximum breakpoints
 const MAX_BREAKPOINTS: usize = 256;
 
+/// Maximum path watchpoints
+const MAX_PATH_WATCHES: usize = 64;
+
+/// Maximum path watch hits waiting for the debugger UI
+const MAX_PATH_HITS: usize = 256;
+
 // ============================================================================
 // Breakpoints
 // ============================================================================
@@ -324,6 +333,114 @@ impl MemoryView {
     }
 }
 
+// ============================================================================
+// Path Watchpoints
+// ============================================================================
+
+/// Filesystem operation a path watchpoint can break on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PathOp {
+    /// The path is opened (any mode)
+    Open,
+    /// An fd open on the path is written to, or the path is replaced by a rename
+    Write,
+    /// The path is unlinked or renamed away
+    Unlink,
+}
+
+impl PathOp {
+    /// Every operation, the default for a new watchpoint
+    pub const ALL: [PathOp; 3] = [PathOp::Open, PathOp::Write, PathOp::Unlink];
+
+    /// Short name, as used by the `debug` command
+    pub fn name(&self) -> &'static str {
+        match self {
+            PathOp::Open => "open",
+            PathOp::Write => "write",
+            PathOp::Unlink => "unlink",
+        }
+    }
+
+    /// Parse a short name
+    pub fn from_name(name: &str) -> Option<PathOp> {
+        PathOp::ALL.into_iter().find(|op| op.name() == name)
+    }
+}
+
+/// A watchpoint on a VFS path
+///
+/// Watching a directory also covers everything below it.
+#[derive(Debug, Clone)]
+pub struct PathWatch {
+    /// Watch identifier
+    pub id: u32,
+    /// Absolute path being watched
+    pub path: String,
+    /// Operations that trigger the watch
+    pub ops: Vec<PathOp>,
+    /// Only processes running as this user trigger the watch (`None` = any)
+    pub uid: Option<Uid>,
+    /// Is this watch enabled?
+    pub enabled: bool,
+    /// Number of times triggered
+    pub hit_count: u64,
+}
+
+impl PathWatch {
+    /// Check whether `op` on `path` by a process running as `uid` triggers this watch
+    pub fn matches(&self, path: &str, op: PathOp, uid: Uid) -> bool {
+        if !self.enabled || !self.ops.contains(&op) {
+            return false;
+        }
+        if self.uid.is_some_and(|owner| owner != uid) {
+            return false;
+        }
+        let watched = self.path.trim_end_matches('/');
+        match path.strip_prefix(watched) {
+            Some(rest) => rest.is_empty() || rest.starts_with('/'),
+            None => false,
+        }
+    }
+}
+
+/// A path watchpoint that fired
+#[derive(Debug, Clone)]
+pub struct PathWatchHit {
+    /// The watch that fired
+    pub watch_id: u32,
+    /// Path that was accessed
+    pub path: String,
+    /// What was done to it
+    pub op: PathOp,
+    /// The triggering syscall
+    pub record: SyscallRecord,
+}
+
+impl std::fmt::Display for PathWatchHit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let args: Vec<String> = self
+            .record
+            .args
+            .iter()
+            .map(|a| a.interpreted.to_string())
+            .collect();
+        write!(
+            f,
+            "watch {}: pid {} {} {}: {}({})",
+            self.watch_id,
+            self.record.pid.0,
+            self.op.name(),
+            self.path,
+            self.record.syscall,
+            args.join(", ")
+        )?;
+        if let Some(result) = &self.record.result {
+            write!(f, " {}", result)?;
+        }
+        Ok(())
+    }
+}
+
 // ============================================================================
 // Execution History
 // ============================================================================
@@ -477,6 +594,14 @@ pub struct WasmDebugger {
     watches: HashMap<u32, MemoryWatch>,
     /// Next watch ID
     next_watch_id: u32,
+    /// Path watchpoints
+    path_watches: HashMap<u32, PathWatch>,
+    /// Next path watch ID
+    next_path_watch_id: u32,
+    /// Path watch hits not yet collected by the debugger UI
+    path_hits: VecDeque<PathWatchHit>,
+    /// Processes paused by a path watchpoint
+    paused: Vec<Pid>,
     /// Execution history
     history: VecDeque<SyscallRecord>,
     /// Next sequence number
@@ -506,6 +631,10 @@ impl WasmDebugger {
             next_breakpoint_id: 1,
             watches: HashMap::new(),
             next_watch_id: 1,
+            path_watches: HashMap::new(),
+            next_path_watch_id: 1,
+            path_hits: VecDeque::new(),
+            paused: Vec::new(),
             history: VecDeque::with_capacity(MAX_HISTORY),
             next_seq: 1,
             ignore_syscalls: HashSet::new(),
@@ -559,8 +688,17 @@ impl WasmDebugger {
     // Breakpoint Management
     // ========================================================================
 
-    /// Add a breakpoint
+    /// Add a breakpoint on a syscall, given by name or by number
     pub fn add_breakpoint(&mut self, syscall: impl Into<String>) -> BreakpointId {
+        let mut syscall = syscall.into();
+        if let Some(name) = syscall
+            .parse()
+            .ok()
+            .and_then(|nr| SymbolMap::new().syscall_name(nr).map(String::from))
+        {
+            syscall = name;
+        }
+
         if self.breakpoints.len() >= MAX_BREAKPOINTS {
             // Remove oldest breakpoint
             if let Some(oldest) = self.breakpoints.keys().min().copied() {
@@ -669,6 +807,96 @@ impl WasmDebugger {
         self.watches.clear();
     }
 
+    // ========================================================================
+    // Path Watchpoints
+    // ========================================================================
+
+    /// Watch `path` for the given operations
+    ///
+    /// With `uid` set, only processes running as that user trigger the watch.
+    pub fn add_path_watch(
+        &mut self,
+        path: impl Into<String>,
+        ops: &[PathOp],
+        uid: Option<Uid>,
+    ) -> u32 {
+        if self.path_watches.len() >= MAX_PATH_WATCHES {
+            // Remove oldest watch
+            if let Some(oldest) = self.path_watches.keys().min().copied() {
+                self.path_watches.remove(&oldest);
+            }
+        }
+
+        let id = self.next_path_watch_id;
+        self.next_path_watch_id += 1;
+
+        let watch = PathWatch {
+            id,
+            path: path.into(),
+            ops: ops.to_vec(),
+            uid,
+            enabled: true,
+            hit_count: 0,
+        };
+
+        self.path_watches.insert(id, watch);
+        id
+    }
+
+    /// Remove a path watch
+    pub fn remove_path_watch(&mut self, id: u32) -> bool {
+        self.path_watches.remove(&id).is_some()
+    }
+
+    /// Get a path watch
+    pub fn path_watch(&self, id: u32) -> Option<&PathWatch> {
+        self.path_watches.get(&id)
+    }
+
+    /// Get all path watches
+    pub fn path_watches(&self) -> impl Iterator<Item = &PathWatch> {
+        self.path_watches.values()
+    }
+
+    /// Check if any path watch is set (cheap test before building a record)
+    pub fn has_path_watches(&self) -> bool {
+        self.enabled && !self.path_watches.is_empty()
+    }
+
+    /// Clear all path watches
+    pub fn clear_path_watches(&mut self) {
+        self.path_watches.clear();
+    }
+
+    /// Collect the hits queued since the last call
+    ///
+    /// With `uid` set, only hits of watches scoped to that user are taken;
+    /// the rest stay queued.
+    pub fn take_path_hits(&mut self, uid: Option<Uid>) -> Vec<PathWatchHit> {
+        let Some(uid) = uid else {
+            return self.path_hits.drain(..).collect();
+        };
+        let (taken, kept): (Vec<_>, Vec<_>) = self.path_hits.drain(..).partition(|hit| {
+            self.path_watches
+                .get(&hit.watch_id)
+                .is_some_and(|w| w.uid == Some(uid))
+        });
+        self.path_hits = kept.into();
+        taken
+    }
+
+    /// Processes currently paused by a path watchpoint
+    pub fn paused(&self) -> &[Pid] {
+        &self.paused
+    }
+
+    /// Forget that `pid` is paused, once the caller has resumed it
+    pub fn release(&mut self, pid: Pid) -> bool {
+        let before = self.paused.len();
+        self.paused.retain(|&p| p != pid);
+        self.paused.len() != before
+    }
+
     // ========================================================================
     // Execution Control
     // ========================================================================
@@ -817,6 +1045,73 @@ impl WasmDebugger {
         self.history.push_back(record);
     }
 
+    /// Called when a process opens, writes or unlinks a path
+    ///
+    /// Returns true if a watchpoint fired; the debugger stops and the caller
+    /// should pause the process. The hit is queued for `take_path_hits`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn on_path_access(
+        &mut self,
+        op: PathOp,
+        path: &str,
+        syscall: &str,
+        pid: Pid,
+        uid: Uid,
+        task_id: TaskId,
+        args: Vec<SyscallArg>,
+        result: SyscallResult,
+        timestamp: f64,
+    ) -> bool {
+        if !self.should_debug(pid, task_id) {
+            return false;
+        }
+
+        let mut fired = None;
+        let mut ids: Vec<u32> = self.path_watches.keys().copied().collect();
+        ids.sort_unstable();
+        for id in ids {
+            if let Some(watch) = self.path_watches.get_mut(&id)
+                && watch.matches(path, op, uid)
+            {
+                watch.hit_count += 1;
+                fired.get_or_insert(id);
+            }
+        }
+        let Some(watch_id) = fired else {
+            return false;
+        };
+
+        let record = SyscallRecord {
+            seq: self.next_seq,
+            timestamp,
+            pid,
+            task_id,
+            syscall: syscall.to_string(),
+            args,
+            result: Some(result),
+            duration: 0.0,
+            memory_before: None,
+            memory_after: None,
+        };
+        self.next_seq += 1;
+
+        if self.path_hits.len() >= MAX_PATH_HITS {
+            self.path_hits.pop_front();
+        }
+        self.path_hits.push_back(PathWatchHit {
+            watch_id,
+            path: path.to_string(),
+            op,
+            record,
+        });
+
+        if !self.paused.contains(&pid) {
+            self.paused.push(pid);
+        }
+        self.mode = DebugMode::Stopped;
+        true
+    }
+
     // ========================================================================
     // History
     // ========================================================================
@@ -881,6 +1176,7 @@ impl WasmDebugger {
             breakpoint_count: self.breakpoints.len(),
             active_breakpoints: self.breakpoints.values().filter(|b| b.enabled).count(),
             watch_count: self.watches.len(),
+            path_watch_count: self.path_watches.len(),
             history_count: self.history.len(),
             call_depth: self.call_depth,
         }
@@ -915,6 +1211,10 @@ impl WasmDebugger {
             status.breakpoint_count, status.active_breakpoints
         ));
         output.push_str(&format!("║ Watches: {:>32} ║\n", status.watch_count));
+        output.push_str(&format!(
+            "║ Path watches: {:>27} ║\n",
+            status.path_watch_count
+        ));
         output.push_str(&format!("║ History: {:>32} ║\n", status.history_count));
         output.push_str(&format!("║ Call depth: {:>29} ║\n", status.call_depth));
 
@@ -961,6 +1261,36 @@ impl WasmDebugger {
         output
     }
 
+    /// Render path watches list
+    pub fn render_path_watches(&self) -> String {
+        let mut output = String::new();
+        output.push_str("=== Path Watches ===\n");
+        output.push_str("ID    Enabled  Ops                Hits    Path\n");
+        output.push_str("────────────────────────────────────────────────────\n");
+
+        if self.path_watches.is_empty() {
+            output.push_str("  (no path watches set)\n");
+        } else {
+            let mut watches: Vec<_> = self.path_watches.values().collect();
+            watches.sort_by_key(|w| w.id);
+
+            for watch in watches {
+                let enabled = if watch.enabled { "✓" } else { " " };
+                let ops: Vec<_> = watch.ops.iter().map(|op| op.name()).collect();
+                output.push_str(&format!(
+                    "{:<5} {:^8} {:18} {:>5}   {}\n",
+                    watch.id,
+                    enabled,
+                    ops.join(","),
+                    watch.hit_count,
+                    watch.path
+                ));
+            }
+        }
+
+        output
+    }
+
     /// Render execution history
     pub fn render_history(&self, count: usize) -> String {
         let mut output = String::new();
@@ -999,6 +1329,7 @@ pub struct DebuggerStatus {
     pub breakpoint_count: usize,
     pub active_breakpoints: usize,
     pub watch_count: usize,
+    pub path_watch_count: usize,
     pub history_count: usize,
     pub call_depth: usize,
 }
@@ -1172,6 +1503,11 @@ mod tests {
         dbg.remove_breakpoint(bp2);
         assert_eq!(dbg.breakpoints().count(), 1);
 
+        // Syscall numbers are resolved to names through the symbol map
+        let bp3 = dbg.add_breakpoint("2");
+        assert_eq!(dbg.breakpoints.get(&bp3).unwrap().syscall, "open");
+        dbg.remove_breakpoint(bp3);
+
         dbg.clear_breakpoints();
         assert_eq!(dbg.breakpoints().count(), 0);
     }
@@ -1248,7 +1584,7 @@ mod tests {
         dbg.add_breakpoint("open");
 
         let should_stop =
-            dbg.on_syscall_enter("open", Pid(1), TaskId(1), &[0x1000, 0, 0644], 100.0);
+            dbg.on_syscall_enter("open", Pid(1), TaskId(1), &[0x1000, 0, 0o644], 100.0);
 
         assert!(should_stop);
         assert_eq!(dbg.mode(), DebugMode::Stopped);
@@ -1351,4 +1687,74 @@ mod tests {
         let should_stop = dbg.on_syscall_enter("getpid", Pid(1), TaskId(1), &[], 100.0);
         assert!(!should_stop);
     }
+
+    #[test]
+    fn test_path_watch_matching() {
+        let mut dbg = WasmDebugger::new();
+        let dir = dbg.add_path_watch("/etc/", &PathOp::ALL, None);
+        let own = dbg.add_path_watch("/home/user/.config", &[PathOp::Write], Some(Uid(1000)));
+
+        let dir = dbg.path_watch(dir).unwrap();
+        assert!(dir.matches("/etc", PathOp::Open, Uid(0)));
+        assert!(dir.matches("/etc/passwd", PathOp::Unlink, Uid(1000)));
+        assert!(!dir.matches("/etcetera", PathOp::Open, Uid(0)));
+
+        let own = dbg.path_watch(own).unwrap();
+        assert!(own.matches("/home/user/.config", PathOp::Write, Uid(1000)));
+        assert!(!own.matches("/home/user/.config", PathOp::Open, Uid(1000)));
+        assert!(!own.matches("/home/user/.config", PathOp::Write, Uid(1001)));
+    }
+
+    #[test]
+    fn test_path_watch_hit() {
+        let mut dbg = WasmDebugger::new();
+        dbg.enable();
+        let id = dbg.add_path_watch("/etc/app.conf", &[PathOp::Write], None);
+
+        let args = vec![SyscallArg {
+            name: "fd".to_string(),
+            value: 3,
+            interpreted: ArgValue::Fd(Fd(3)),
+        }];
+        let hit = dbg.on_path_access(
+            PathOp::Open,
+            "/etc/app.conf",
+            "open",
+            Pid(7),
+            Uid(0),
+            TaskId(1),
+            args.clone(),
+            SyscallResult::Success(3),
+            10.0,
+        );
+        assert!(!hit);
+
+        let hit = dbg.on_path_access(
+            PathOp::Write,
+            "/etc/app.conf",
+            "write",
+            Pid(7),
+            Uid(0),
+            TaskId(1),
+            args,
+            SyscallResult::Success(5),
+            20.0,
+        );
+        assert!(hit);
+        assert_eq!(dbg.mode(), DebugMode::Stopped);
+        assert_eq!(dbg.paused(), &[Pid(7)]);
+        assert_eq!(dbg.path_watch(id).unwrap().hit_count, 1);
+
+        assert!(dbg.take_path_hits(Some(Uid(1000))).is_empty());
+        let hits = dbg.take_path_hits(None);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].record.syscall, "write");
+        assert_eq!(
+            hits[0].to_string(),
+            format!("watch {}: pid 7 write /etc/app.conf: write(fd:3) = 5", id)
+        );
+        assert!(dbg.take_path_hits(None).is_empty());
+        assert!(dbg.release(Pid(7)));
+        assert!(dbg.paused().is_empty());
+    }
 }
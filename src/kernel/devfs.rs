@@ -1,33 +1,97 @@
 //! /dev virtual filesystem
 //!
-//! Provides device file abstractions. Most devices are simulated
-//! for the WASM environment.
+//! Device nodes are registered at runtime with [`DevFs::register`], each
+//! with a [`DeviceOps`] holding its read, write and ioctl handlers. The
+//! standard devices (console, null, zero, ...) are registered the same way
+//! when the kernel starts; subsystems and services add their own, like
+//! /dev/clipboard, and remove them with [`DevFs::unregister`]. A name may
+//! contain `/` (`pts/0`), which makes the directories it implies.
+//!
+//! Descriptors already open on a device keep working after it is
+//! unregistered; only new opens fail.
+
+use super::object::{DeviceObject, KernelObject};
+use super::process::{Fd, Handle, OpenFlags};
+use super::synthfs::SyntheticFs;
+use super::syscall::{IoctlRequest, IoctlResult, Kernel, SyscallError, SyscallResult, getrandom};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+/// Bytes /dev/zero and /dev/random give per open before EOF, so commands
+/// that read a whole file finish
+const STREAM_LIMIT: u64 = 4096;
+
+/// Handlers for a device node
+///
+/// Every handler has a default: reads and writes fail with
+/// `InvalidArgument`, ioctls too, and opening creates a descriptor that
+/// dispatches to this device. `offset` counts the bytes already read or
+/// written through the descriptor.
+pub trait DeviceOps {
+    /// Type, numbers and permissions of the node
+    fn info(&self) -> DeviceInfo {
+        DeviceInfo::char(0, 0, 0o666)
+    }
+
+    /// Handle to use instead of a new device descriptor (e.g. the shared
+    /// console), or `None` for the default
+    fn open(&self, _kernel: &mut Kernel) -> SyscallResult<Option<Handle>> {
+        Ok(None)
+    }
+
+    /// Check if the device is a terminal, for `ttyname`
+    fn is_tty(&self) -> bool {
+        false
+    }
+
+    /// Read from the device; 0 means end of file
+    fn read(&self, _kernel: &mut Kernel, _offset: u64, _buf: &mut [u8]) -> SyscallResult<usize> {
+        Err(SyscallError::InvalidArgument)
+    }
+
+    /// Write to the device
+    fn write(&self, _kernel: &mut Kernel, _offset: u64, _buf: &[u8]) -> SyscallResult<usize> {
+        Err(SyscallError::InvalidArgument)
+    }
 
-use std::collections::HashSet;
+    /// Device-specific control request
+    fn ioctl(&self, _kernel: &mut Kernel, _request: IoctlRequest) -> SyscallResult<IoctlResult> {
+        Err(SyscallError::InvalidArgument)
+    }
+}
 
 /// Device filesystem manager
 pub struct DevFs {
-    /// Available devices
-    devices: HashSet<&'static str>,
+    /// Registered devices by name relative to /dev
+    devices: RefCell<BTreeMap<String, Rc<dyn DeviceOps>>>,
 }
 
 impl DevFs {
+    /// Devfs with the standard devices
     pub fn new() -> Self {
-        let mut devices = HashSet::new();
-        // Standard devices
-        devices.insert("console");
-        devices.insert("null");
-        devices.insert("zero");
-        devices.insert("random");
-        devices.insert("urandom");
-        devices.insert("stdin");
-        devices.insert("stdout");
-        devices.insert("stderr");
-        devices.insert("tty");
-        devices.insert("ptmx");
-        devices.insert("fd"); // Directory - symlinks to /proc/self/fd
-
-        Self { devices }
+        let devfs = Self {
+            devices: RefCell::new(BTreeMap::new()),
+        };
+        let standard: [(&str, Rc<dyn DeviceOps>); 13] = [
+            ("console", Rc::new(Console::new(5, 1, 0o620))),
+            ("tty", Rc::new(Console::new(5, 0, 0o666))),
+            ("tty1", Rc::new(Console::new(4, 1, 0o620))),
+            ("tty2", Rc::new(Console::new(4, 2, 0o620))),
+            ("tty3", Rc::new(Console::new(4, 3, 0o620))),
+            ("tty4", Rc::new(Console::new(4, 4, 0o620))),
+            ("null", Rc::new(Null)),
+            ("zero", Rc::new(Zero)),
+            ("random", Rc::new(Random { minor: 8 })),
+            ("urandom", Rc::new(Random { minor: 9 })),
+            ("stdin", Rc::new(StdStream(Fd::STDIN))),
+            ("stdout", Rc::new(StdStream(Fd::STDOUT))),
+            ("stderr", Rc::new(StdStream(Fd::STDERR))),
+        ];
+        for (name, ops) in standard {
+            let _ = devfs.register(name, ops);
+        }
+        devfs
     }
 
     /// Check if a path is in /dev
@@ -35,93 +99,107 @@ impl DevFs {
         path == "/dev" || path.starts_with("/dev/")
     }
 
+    /// Add the device node /dev/`name`
+    ///
+    /// Fails with `AlreadyExists` if the name (or a directory of that
+    /// name) is taken, and `InvalidArgument` for empty components, `.`
+    /// and `..`.
+    pub fn register(&self, name: &str, ops: Rc<dyn DeviceOps>) -> SyscallResult<()> {
+        if name
+            .split('/')
+            .any(|part| part.is_empty() || part == "." || part == "..")
+        {
+            return Err(SyscallError::InvalidArgument);
+        }
+        let mut devices = self.devices.borrow_mut();
+        let dir = format!("{}/", name);
+        let taken = name == "fd"
+            || devices.contains_key(name)
+            || devices.keys().any(|existing| {
+                existing.starts_with(&dir) || name.starts_with(&format!("{}/", existing))
+            });
+        if taken {
+            return Err(SyscallError::AlreadyExists);
+        }
+        devices.insert(name.to_string(), ops);
+        Ok(())
+    }
+
+    /// Remove the device node /dev/`name`
+    pub fn unregister(&self, name: &str) -> SyscallResult<()> {
+        self.devices
+            .borrow_mut()
+            .remove(name)
+            .map(|_| ())
+            .ok_or(SyscallError::NotFound)
+    }
+
+    /// Handlers of the device at /dev/`name`
+    pub fn device(&self, name: &str) -> Option<Rc<dyn DeviceOps>> {
+        self.devices.borrow().get(name).cloned()
+    }
+
+    /// Names of all registered devices
+    pub fn names(&self) -> Vec<String> {
+        self.devices.borrow().keys().cloned().collect()
+    }
+
     /// List directory contents
     pub fn list_dir(&self, path: &str) -> Option<Vec<String>> {
-        if path == "/dev" {
-            let entries: Vec<String> = self.devices.iter().map(|s| s.to_string()).collect();
-            Some(entries)
-        } else if path == "/dev/fd" {
+        if path == "/dev/fd" {
             // Would list open file descriptors
-            Some(vec!["0".to_string(), "1".to_string(), "2".to_string()])
-        } else {
-            None
+            return Some(vec!["0".to_string(), "1".to_string(), "2".to_string()]);
+        }
+        if !self.is_dir(path) {
+            return None;
+        }
+        let prefix = match path.strip_prefix("/dev") {
+            Some("") => String::new(),
+            Some(rest) => format!("{}/", &rest[1..]),
+            None => return None,
+        };
+        let mut entries: Vec<String> = self
+            .devices
+            .borrow()
+            .keys()
+            .filter_map(|name| name.strip_prefix(&prefix))
+            .map(|rest| rest.split('/').next().unwrap_or(rest).to_string())
+            .collect();
+        if prefix.is_empty() {
+            entries.push("fd".to_string()); // Directory - symlinks to /proc/self/fd
         }
+        entries.dedup();
+        Some(entries)
     }
 
     /// Check if a path exists in /dev
     pub fn exists(&self, path: &str) -> bool {
-        if path == "/dev" {
+        if self.is_dir(path) {
             return true;
         }
-        if let Some(name) = path.strip_prefix("/dev/") {
+        match path.strip_prefix("/dev/") {
             // Handle nested paths like /dev/fd/0
-            if name.starts_with("fd/") {
-                return true; // Simplified - assume fd paths exist
-            }
-            self.devices.contains(name)
-        } else {
-            false
+            Some(name) if name.starts_with("fd/") => true, // Simplified - assume fd paths exist
+            Some(name) => self.devices.borrow().contains_key(name),
+            None => false,
         }
     }
 
     /// Check if a path is a directory
     pub fn is_dir(&self, path: &str) -> bool {
-        path == "/dev" || path == "/dev/fd"
+        match path.strip_prefix("/dev/") {
+            None => path == "/dev",
+            Some("fd") => true,
+            Some(name) => {
+                let dir = format!("{}/", name);
+                self.devices.borrow().keys().any(|d| d.starts_with(&dir))
+            }
+        }
     }
 
     /// Get device info
     pub fn device_info(&self, name: &str) -> Option<DeviceInfo> {
-        match name {
-            "console" => Some(DeviceInfo {
-                dev_type: DeviceType::Char,
-                major: 5,
-                minor: 1,
-                mode: 0o620,
-            }),
-            "null" => Some(DeviceInfo {
-                dev_type: DeviceType::Char,
-                major: 1,
-                minor: 3,
-                mode: 0o666,
-            }),
-            "zero" => Some(DeviceInfo {
-                dev_type: DeviceType::Char,
-                major: 1,
-                minor: 5,
-                mode: 0o666,
-            }),
-            "random" => Some(DeviceInfo {
-                dev_type: DeviceType::Char,
-                major: 1,
-                minor: 8,
-                mode: 0o666,
-            }),
-            "urandom" => Some(DeviceInfo {
-                dev_type: DeviceType::Char,
-                major: 1,
-                minor: 9,
-                mode: 0o666,
-            }),
-            "tty" => Some(DeviceInfo {
-                dev_type: DeviceType::Char,
-                major: 5,
-                minor: 0,
-                mode: 0o666,
-            }),
-            "ptmx" => Some(DeviceInfo {
-                dev_type: DeviceType::Char,
-                major: 5,
-                minor: 2,
-                mode: 0o666,
-            }),
-            "stdin" | "stdout" | "stderr" => Some(DeviceInfo {
-                dev_type: DeviceType::Symlink,
-                major: 0,
-                minor: 0,
-                mode: 0o777,
-            }),
-            _ => None,
-        }
+        self.device(name).map(|ops| ops.info())
     }
 }
 
@@ -131,6 +209,141 @@ impl Default for DevFs {
     }
 }
 
+impl SyntheticFs for DevFs {
+    fn fs_type(&self) -> &'static str {
+        "devfs"
+    }
+
+    fn list_dir(&self, _kernel: &Kernel, path: &str) -> Option<Vec<String>> {
+        DevFs::list_dir(self, path)
+    }
+
+    fn exists(&self, _kernel: &Kernel, path: &str) -> bool {
+        DevFs::exists(self, path)
+    }
+
+    fn is_dir(&self, _kernel: &Kernel, path: &str) -> bool {
+        DevFs::is_dir(self, path)
+    }
+
+    fn mode(&self, is_dir: bool) -> u16 {
+        // Device files are rw for all
+        if is_dir { 0o755 } else { 0o666 }
+    }
+
+    fn open(&self, kernel: &mut Kernel, path: &str, _flags: OpenFlags) -> SyscallResult<Handle> {
+        let name = path.strip_prefix("/dev/").ok_or(SyscallError::NotFound)?;
+        let ops = self.device(name).ok_or(SyscallError::NotFound)?;
+        match ops.open(kernel)? {
+            Some(handle) => Ok(handle),
+            None => Ok(kernel.insert_object(KernelObject::Device(DeviceObject::new(path, ops)))),
+        }
+    }
+}
+
+/// /dev/console, /dev/tty and the virtual consoles /dev/tty1 to /dev/tty4:
+/// the shared console object
+struct Console(DeviceInfo);
+
+impl Console {
+    fn new(major: u32, minor: u32, mode: u16) -> Self {
+        Self(DeviceInfo::char(major, minor, mode))
+    }
+}
+
+impl DeviceOps for Console {
+    fn info(&self) -> DeviceInfo {
+        self.0.clone()
+    }
+
+    fn is_tty(&self) -> bool {
+        true
+    }
+
+    fn open(&self, kernel: &mut Kernel) -> SyscallResult<Option<Handle>> {
+        Ok(Some(kernel.console_handle()))
+    }
+}
+
+/// /dev/null - discard all writes, return EOF on read
+struct Null;
+
+impl DeviceOps for Null {
+    fn info(&self) -> DeviceInfo {
+        DeviceInfo::char(1, 3, 0o666)
+    }
+
+    fn read(&self, _kernel: &mut Kernel, _offset: u64, _buf: &mut [u8]) -> SyscallResult<usize> {
+        Ok(0)
+    }
+
+    fn write(&self, _kernel: &mut Kernel, _offset: u64, buf: &[u8]) -> SyscallResult<usize> {
+        Ok(buf.len())
+    }
+}
+
+/// Bytes left of a per-open stream at `offset`
+fn stream_len(offset: u64, buf: &[u8]) -> usize {
+    let left = STREAM_LIMIT.saturating_sub(offset);
+    buf.len().min(usize::try_from(left).unwrap_or(usize::MAX))
+}
+
+/// /dev/zero - returns zeros
+struct Zero;
+
+impl DeviceOps for Zero {
+    fn info(&self) -> DeviceInfo {
+        DeviceInfo::char(1, 5, 0o666)
+    }
+
+    fn read(&self, _kernel: &mut Kernel, offset: u64, buf: &mut [u8]) -> SyscallResult<usize> {
+        let n = stream_len(offset, buf);
+        buf[..n].fill(0);
+        Ok(n)
+    }
+
+    fn write(&self, _kernel: &mut Kernel, _offset: u64, buf: &[u8]) -> SyscallResult<usize> {
+        Ok(buf.len())
+    }
+}
+
+/// /dev/random and /dev/urandom - bytes from the kernel's CSPRNG
+/// ([`super::random`]), which never blocks once seeded
+struct Random {
+    minor: u32,
+}
+
+impl DeviceOps for Random {
+    fn info(&self) -> DeviceInfo {
+        DeviceInfo::char(1, self.minor, 0o666)
+    }
+
+    fn read(&self, _kernel: &mut Kernel, offset: u64, buf: &mut [u8]) -> SyscallResult<usize> {
+        let n = stream_len(offset, buf);
+        getrandom(&mut buf[..n])?;
+        Ok(n)
+    }
+}
+
+/// /dev/stdin, /dev/stdout and /dev/stderr: the object behind the
+/// opener's descriptor
+struct StdStream(Fd);
+
+impl DeviceOps for StdStream {
+    fn info(&self) -> DeviceInfo {
+        DeviceInfo {
+            dev_type: DeviceType::Symlink,
+            major: 0,
+            minor: 0,
+            mode: 0o777,
+        }
+    }
+
+    fn open(&self, kernel: &mut Kernel) -> SyscallResult<Option<Handle>> {
+        kernel.share_fd_handle(self.0).map(Some)
+    }
+}
+
 /// Device type
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum DeviceType {
@@ -147,3 +360,152 @@ pub struct DeviceInfo {
     pub minor: u32,
     pub mode: u16,
 }
+
+impl DeviceInfo {
+    /// A character device
+    pub fn char(major: u32, minor: u32, mode: u16) -> Self {
+        Self {
+            dev_type: DeviceType::Char,
+            major,
+            minor,
+            mode,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::syscall::{self, KERNEL};
+
+    /// A clipboard device: writes replace the contents, reads return them
+    #[derive(Default)]
+    struct Clipboard {
+        contents: RefCell<Vec<u8>>,
+    }
+
+    impl DeviceOps for Clipboard {
+        fn read(&self, _kernel: &mut Kernel, offset: u64, buf: &mut [u8]) -> SyscallResult<usize> {
+            let contents = self.contents.borrow();
+            let rest = contents.get(offset as usize..).unwrap_or(&[]);
+            let n = rest.len().min(buf.len());
+            buf[..n].copy_from_slice(&rest[..n]);
+            Ok(n)
+        }
+
+        fn write(&self, _kernel: &mut Kernel, offset: u64, buf: &[u8]) -> SyscallResult<usize> {
+            let mut contents = self.contents.borrow_mut();
+            if offset == 0 {
+                contents.clear();
+            }
+            contents.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn ioctl(&self, _kernel: &mut Kernel, request: IoctlRequest) -> SyscallResult<IoctlResult> {
+            match request {
+                IoctlRequest::TcFlush => {
+                    self.contents.borrow_mut().clear();
+                    Ok(IoctlResult::Ok)
+                }
+                _ => Err(SyscallError::InvalidArgument),
+            }
+        }
+    }
+
+    fn setup_kernel() {
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+    }
+
+    #[test]
+    fn test_register_names() {
+        let devfs = DevFs::new();
+        assert!(devfs.register("pts/0", Rc::new(Null)).is_ok());
+        assert!(devfs.register("pts/1", Rc::new(Null)).is_ok());
+        assert_eq!(
+            devfs.register("null", Rc::new(Null)),
+            Err(SyscallError::AlreadyExists)
+        );
+        assert_eq!(
+            devfs.register("pts", Rc::new(Null)),
+            Err(SyscallError::AlreadyExists)
+        );
+        assert_eq!(
+            devfs.register("null/x", Rc::new(Null)),
+            Err(SyscallError::AlreadyExists)
+        );
+        for bad in ["", "a//b", "../etc", "pts/."] {
+            assert_eq!(
+                devfs.register(bad, Rc::new(Null)),
+                Err(SyscallError::InvalidArgument)
+            );
+        }
+
+        assert!(devfs.is_dir("/dev/pts"));
+        assert_eq!(devfs.list_dir("/dev/pts").unwrap(), ["0", "1"]);
+        let root = devfs.list_dir("/dev").unwrap();
+        assert_eq!(root.iter().filter(|e| *e == "pts").count(), 1);
+        assert!(root.contains(&"fd".to_string()));
+
+        devfs.unregister("pts/0").unwrap();
+        devfs.unregister("pts/1").unwrap();
+        assert!(!devfs.exists("/dev/pts"));
+        assert_eq!(devfs.unregister("pts/1"), Err(SyscallError::NotFound));
+    }
+
+    #[test]
+    fn test_device_handlers() {
+        setup_kernel();
+        syscall::register_device("clipboard", Rc::new(Clipboard::default())).unwrap();
+        assert!(
+            syscall::readdir("/dev")
+                .unwrap()
+                .contains(&"clipboard".to_string())
+        );
+
+        syscall::write_file("/dev/clipboard", "copied text").unwrap();
+        assert_eq!(syscall::read_file("/dev/clipboard").unwrap(), "copied text");
+
+        let fd = syscall::open("/dev/clipboard", OpenFlags::READ).unwrap();
+        assert!(matches!(
+            syscall::ioctl(fd, IoctlRequest::TcFlush),
+            Ok(IoctlResult::Ok)
+        ));
+        assert_eq!(
+            syscall::ioctl(fd, IoctlRequest::GetWinSize).unwrap_err(),
+            SyscallError::InvalidArgument
+        );
+
+        // Open descriptors outlive the node
+        syscall::unregister_device("clipboard").unwrap();
+        assert!(!syscall::exists("/dev/clipboard").unwrap());
+        let mut buf = [0u8; 8];
+        assert_eq!(syscall::read(fd, &mut buf), Ok(0));
+        syscall::close(fd).unwrap();
+    }
+
+    #[test]
+    fn test_standard_devices() {
+        setup_kernel();
+        assert_eq!(syscall::read_file("/dev/zero").unwrap().len(), 4096);
+        assert_eq!(syscall::read_file("/dev/null").unwrap(), "");
+
+        // /dev/stdout is the opener's own stdout
+        let (read_fd, write_fd) = syscall::pipe().unwrap();
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            let process = k.current_process_mut().unwrap();
+            let pipe = process.files.get(write_fd).unwrap();
+            process.files.insert(Fd::STDOUT, pipe);
+        });
+        syscall::write_file("/dev/stdout", "via /dev/stdout").unwrap();
+        let mut buf = [0u8; 32];
+        let n = syscall::read(read_fd, &mut buf).unwrap();
+        assert_eq!(&buf[..n], b"via /dev/stdout");
+    }
+}
@@ -0,0 +1,296 @@
+//! Event queues (epoll)
+//!
+//! An [`EventQueue`] is a kernel object reached through a descriptor, made
+//! by `sys_epoll_create`. It keeps a set of interests between waits, so a
+//! daemon watching many sources registers them once with `sys_epoll_ctl`
+//! and then only asks which are ready with `sys_epoll_wait`, rather than
+//! passing the whole set to `sys_poll` each time.
+//!
+//! An interest watches anything `sys_poll` can (descriptors, Unix domain
+//! sockets, FIFOs), the process's pending signals, or a timer the queue
+//! arms itself. Each is registered under a token of the caller's choosing,
+//! which is what a wait reports back. Interests are level-triggered: one
+//! stays ready until its source is drained, unless it is `oneshot`, in
+//! which case it reports once and is then disarmed until it is modified.
+//! `syscall::epoll_wait` waits on the executor's wakers, like `poll`.
+
+use super::poll::{PollEvents, PollSource};
+use std::collections::BTreeMap;
+
+/// What an interest watches
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventSource {
+    /// A descriptor, socket or FIFO, ready as `sys_poll` would see it
+    Poll(PollSource),
+    /// The process's pending signals in a mask (bit N = signal N); readable
+    /// while any is pending
+    Signals(u16),
+    /// A timer that expires `delay` milliseconds after it is registered,
+    /// then every `interval` milliseconds if there is one; readable once
+    /// it has expired, until a wait reports it
+    Timer { delay: f64, interval: Option<f64> },
+}
+
+/// One registration in an [`EventQueue`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Interest {
+    pub source: EventSource,
+    /// Conditions to report; `error`, `hangup` and `invalid` always are
+    pub events: PollEvents,
+    /// Report once, then stay disarmed until modified (EPOLLONESHOT)
+    pub oneshot: bool,
+}
+
+impl Interest {
+    pub fn new(source: EventSource, events: PollEvents) -> Self {
+        Self {
+            source,
+            events,
+            oneshot: false,
+        }
+    }
+
+    /// Readability of a timer
+    pub fn timer(delay: f64, interval: Option<f64>) -> Self {
+        Self::new(EventSource::Timer { delay, interval }, PollEvents::READ)
+    }
+
+    /// The same interest, reporting only once
+    pub fn oneshot(mut self) -> Self {
+        self.oneshot = true;
+        self
+    }
+}
+
+/// A change to an event queue's interests, for `sys_epoll_ctl`
+#[derive(Debug, Clone, PartialEq)]
+pub enum EpollCtl {
+    /// Register an interest under a token not yet in use
+    Add(u64, Interest),
+    /// Replace the interest under a token, re-arming it
+    Modify(u64, Interest),
+    /// Drop the interest under a token
+    Delete(u64),
+}
+
+/// A ready interest, as `sys_epoll_wait` reports it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EpollEvent {
+    /// Token the interest was registered under
+    pub token: u64,
+    pub events: PollEvents,
+    /// Times a timer expired since it was last reported; 0 for other
+    /// sources
+    pub expirations: u64,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    interest: Interest,
+    /// Cleared once a oneshot interest has reported
+    armed: bool,
+    /// Next expiry of a timer
+    deadline: f64,
+}
+
+impl Entry {
+    fn new(interest: Interest, now: f64) -> Self {
+        let deadline = match interest.source {
+            EventSource::Timer { delay, .. } => now + delay.max(0.0),
+            _ => f64::INFINITY,
+        };
+        Self {
+            interest,
+            armed: true,
+            deadline,
+        }
+    }
+
+    /// Conditions to report, given the readiness of its source (ignored
+    /// for timers)
+    fn report(&self, state: PollEvents, now: f64) -> PollEvents {
+        if !self.armed {
+            return PollEvents::NONE;
+        }
+        let state = match self.interest.source {
+            EventSource::Timer { .. } => PollEvents {
+                readable: now >= self.deadline,
+                ..PollEvents::NONE
+            },
+            _ => state,
+        };
+        self.interest.events.report(state)
+    }
+}
+
+/// An epoll instance: interests by token
+#[derive(Debug, Clone, Default)]
+pub struct EventQueue {
+    entries: BTreeMap<u64, Entry>,
+}
+
+impl EventQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Register `interest` under `token`, at kernel time `now`; false if
+    /// the token is taken
+    pub fn add(&mut self, token: u64, interest: Interest, now: f64) -> bool {
+        if self.entries.contains_key(&token) {
+            return false;
+        }
+        self.entries.insert(token, Entry::new(interest, now));
+        true
+    }
+
+    /// Replace the interest under `token`, re-arming it and restarting a
+    /// timer; false if there is none
+    pub fn modify(&mut self, token: u64, interest: Interest, now: f64) -> bool {
+        match self.entries.get_mut(&token) {
+            Some(entry) => {
+                *entry = Entry::new(interest, now);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Drop the interest under `token`; false if there is none
+    pub fn remove(&mut self, token: u64) -> bool {
+        self.entries.remove(&token).is_some()
+    }
+
+    /// The interests, by token
+    pub fn interests(&self) -> impl Iterator<Item = (u64, &Interest)> {
+        self.entries.iter().map(|(&token, e)| (token, &e.interest))
+    }
+
+    /// When the first armed timer expires, if there is one
+    pub fn next_deadline(&self) -> Option<f64> {
+        self.entries
+            .values()
+            .filter(|e| e.armed && e.deadline.is_finite())
+            .map(|e| e.deadline)
+            .min_by(f64::total_cmp)
+    }
+
+    /// Check if any interest has something to report, given the readiness
+    /// of each source by token
+    pub fn is_ready(&self, states: &BTreeMap<u64, PollEvents>, now: f64) -> bool {
+        self.entries.iter().any(|(token, entry)| {
+            let state = states.get(token).copied().unwrap_or_default();
+            !entry.report(state, now).is_empty()
+        })
+    }
+
+    /// Report up to `max` ready interests, in token order, given the
+    /// readiness of each source by token
+    ///
+    /// Reported timers move on to their next expiry, or are disarmed if
+    /// they don't repeat, and so are reported oneshot interests.
+    pub fn collect(
+        &mut self,
+        states: &BTreeMap<u64, PollEvents>,
+        now: f64,
+        max: usize,
+    ) -> Vec<EpollEvent> {
+        let mut events = Vec::new();
+        for (&token, entry) in self.entries.iter_mut() {
+            if events.len() >= max {
+                break;
+            }
+            let state = states.get(&token).copied().unwrap_or_default();
+            let report = entry.report(state, now);
+            if report.is_empty() {
+                continue;
+            }
+            let mut expirations = 0;
+            if let EventSource::Timer { interval, .. } = entry.interest.source {
+                expirations = 1;
+                match interval {
+                    Some(interval) if interval > 0.0 => {
+                        let late = ((now - entry.deadline) / interval).floor() as u64;
+                        expirations += late;
+                        entry.deadline += (expirations as f64) * interval;
+                    }
+                    _ => entry.armed = false,
+                }
+            }
+            if entry.interest.oneshot {
+                entry.armed = false;
+            }
+            events.push(EpollEvent {
+                token,
+                events: report,
+                expirations,
+            });
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::process::Fd;
+
+    #[test]
+    fn test_event_queue() {
+        let mut queue = EventQueue::new();
+        let pipe = Interest::new(EventSource::Poll(PollSource::Fd(Fd(3))), PollEvents::READ);
+        assert!(queue.add(1, pipe.clone(), 0.0));
+        assert!(!queue.add(1, pipe.clone(), 0.0));
+        assert!(queue.add(2, Interest::timer(100.0, Some(50.0)), 0.0));
+        assert!(queue.add(
+            3,
+            Interest::new(EventSource::Signals(1 << 2), PollEvents::READ).oneshot(),
+            0.0
+        ));
+        assert_eq!(queue.next_deadline(), Some(100.0));
+
+        // Level-triggered: the pipe reports until it is drained
+        let mut states = BTreeMap::new();
+        states.insert(1, PollEvents::READ_WRITE);
+        assert!(queue.is_ready(&states, 0.0));
+        let tokens = |events: Vec<EpollEvent>| events.iter().map(|e| e.token).collect::<Vec<_>>();
+        assert_eq!(tokens(queue.collect(&states, 0.0, 8)), [1]);
+        assert_eq!(queue.collect(&states, 0.0, 8)[0].events, PollEvents::READ);
+
+        // The timer counts expirations it was late for
+        states.clear();
+        let events = queue.collect(&states, 230.0, 8);
+        assert_eq!(events.len(), 1);
+        assert_eq!((events[0].token, events[0].expirations), (2, 3));
+        assert_eq!(queue.next_deadline(), Some(250.0));
+        assert!(!queue.is_ready(&states, 240.0));
+
+        // A oneshot interest reports once, until it is modified
+        states.insert(3, PollEvents::READ);
+        assert_eq!(tokens(queue.collect(&states, 240.0, 8)), [3]);
+        assert!(queue.collect(&states, 240.0, 8).is_empty());
+        assert!(queue.modify(
+            3,
+            Interest::new(EventSource::Signals(1 << 2), PollEvents::READ),
+            240.0
+        ));
+        assert_eq!(tokens(queue.collect(&states, 240.0, 8)), [3]);
+
+        // At most `max` are reported
+        states.insert(1, PollEvents::READ);
+        assert_eq!(tokens(queue.collect(&states, 240.0, 1)), [1]);
+
+        assert!(queue.remove(1));
+        assert!(!queue.remove(1));
+        assert!(!queue.modify(1, pipe, 0.0));
+        assert_eq!(queue.len(), 2);
+    }
+}
@@ -3,10 +3,12 @@
 //! Designed for UI work in WASM:
 //! - Tick-based execution (integrates with requestAnimationFrame)
 //! - Task identity (tasks have IDs for event routing)
-//! - Priority levels (compositor runs before apps)
+//! - Priority levels (compositor runs before apps, idle work only when
+//!   nothing more urgent is ready)
 //! - Proper wake semantics (no busy-waiting)
 //! - Timeout support for async operations
 //! - Task groups for hierarchical management
+//! - Fault isolation (a panicking task is dropped, the others keep running)
 //!
 //! Tractability > Complexity, but this is the kernel - it needs to be solid.
 
@@ -14,6 +16,7 @@ use super::task::{BoxFuture, TaskId};
 use std::cell::RefCell;
 use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
 use std::pin::Pin;
 use std::rc::Rc;
 use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
@@ -28,6 +31,9 @@ pub enum Priority {
     Normal = 1,
     /// Background tasks (can be starved)
     Background = 2,
+    /// Idle work (indexing, compaction): polled only in ticks where no
+    /// Critical or Normal task is ready, so it never delays them
+    Idle = 3,
 }
 
 // ============================================================================
@@ -112,6 +118,31 @@ pub fn timeout<F: Future + Unpin>(future: F, timeout_ms: f64, now: f64) -> Timeo
     Timeout::new(future, now + timeout_ms)
 }
 
+/// A task that calls its job on every poll and stays ready, for periodic
+/// work that decides for itself whether it is due
+///
+/// Spawned at [`Priority::Idle`] it runs in every tick with nothing more
+/// urgent to do. It never completes; cancel it to stop it.
+pub struct Recurring<J> {
+    job: J,
+}
+
+impl<J: FnMut() + Unpin> Recurring<J> {
+    pub fn new(job: J) -> Self {
+        Self { job }
+    }
+}
+
+impl<J: FnMut() + Unpin> Future for Recurring<J> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        (self.job)();
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
 /// Default time function - returns 0.0 (use with platform time)
 fn default_now() -> f64 {
     // In WASM, this would use performance.now()
@@ -360,6 +391,70 @@ struct WakerState {
     ready_set: Rc<RefCell<HashSet<TaskId>>>,
 }
 
+/// Maximum poll spans kept between calls to `take_poll_spans`
+const MAX_POLL_SPANS: usize = 4096;
+
+/// One poll of one task, as timed by the executor clock
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PollSpan {
+    pub task: TaskId,
+    pub priority: Priority,
+    pub start: f64,
+    pub end: f64,
+    /// The task finished during this poll
+    pub completed: bool,
+}
+
+/// Maximum faults kept between calls to `take_faults`
+const MAX_FAULTS: usize = 64;
+
+/// A task that panicked while being polled
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaskFault {
+    pub task: TaskId,
+    pub priority: Priority,
+    /// The panic message
+    pub message: String,
+    /// Where it panicked (`file:line:column`), if known
+    pub location: Option<String>,
+}
+
+thread_local! {
+    /// Location of the most recent panic, recorded by the panic hook
+    static PANIC_LOCATION: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Chain a hook in front of the current panic hook that remembers where
+/// the panic happened, so a fault can say more than its message
+fn install_panic_hook() {
+    static INSTALL: std::sync::Once = std::sync::Once::new();
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let location = info
+                .location()
+                .map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column()));
+            let _ = PANIC_LOCATION.try_with(|p| {
+                if let Ok(mut p) = p.try_borrow_mut() {
+                    *p = location;
+                }
+            });
+            previous(info);
+        }));
+    });
+}
+
+/// The message a task panicked with
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
 /// The executor - runs async tasks cooperatively, one tick at a time
 pub struct Executor {
     /// All tasks, indexed by ID
@@ -373,6 +468,15 @@ pub struct Executor {
 
     /// Next task ID
     next_id: u64,
+
+    /// Clock for timing polls (None = polls are not timed)
+    clock: Option<fn() -> f64>,
+
+    /// Polls timed since the last `take_poll_spans`
+    poll_spans: Vec<PollSpan>,
+
+    /// Tasks that panicked since the last `take_faults`
+    faults: Vec<TaskFault>,
 }
 
 impl Executor {
@@ -382,9 +486,32 @@ impl Executor {
             ready: Rc::new(RefCell::new(HashSet::new())),
             pending_spawn: RefCell::new(VecDeque::new()),
             next_id: 0,
+            clock: None,
+            poll_spans: Vec::new(),
+            faults: Vec::new(),
         }
     }
 
+    /// Time every poll with `clock`, or stop timing with `None`
+    pub fn set_clock(&mut self, clock: Option<fn() -> f64>) {
+        self.clock = clock;
+    }
+
+    /// The poll clock, if set
+    pub fn clock(&self) -> Option<fn() -> f64> {
+        self.clock
+    }
+
+    /// Take the poll spans recorded since the last call
+    pub fn take_poll_spans(&mut self) -> Vec<PollSpan> {
+        std::mem::take(&mut self.poll_spans)
+    }
+
+    /// Take the faults recorded since the last call
+    pub fn take_faults(&mut self) -> Vec<TaskFault> {
+        std::mem::take(&mut self.faults)
+    }
+
     /// Spawn a future with default (Normal) priority, returns task ID
     pub fn spawn<F>(&mut self, future: F) -> TaskId
     where
@@ -427,11 +554,19 @@ impl Executor {
 
     /// Run one tick of execution
     ///
-    /// Polls all ready tasks once, in priority order.
+    /// Polls all ready tasks once, in priority order. Idle tasks are left
+    /// ready for a later tick if any Critical or Normal task is ready.
     /// Returns the number of tasks that were polled.
     ///
+    /// A task that panics is dropped and recorded as a [`TaskFault`]; the
+    /// rest of the tick goes on. This relies on panics unwinding - built
+    /// with `panic = "abort"`, as `wasm32-unknown-unknown` is by default, a
+    /// panic still takes everything down.
+    ///
     /// Call this from requestAnimationFrame for UI work.
     pub fn tick(&mut self) -> usize {
+        install_panic_hook();
+
         // First, integrate any tasks spawned since last tick
         self.integrate_pending();
 
@@ -446,6 +581,15 @@ impl Executor {
                 .unwrap_or(Priority::Background)
         });
 
+        // Idle work waits for a tick with nothing urgent in it
+        let priority_of = |id: &TaskId| self.tasks.get(id).map(|t| t.priority);
+        if ready_ids
+            .first()
+            .is_some_and(|id| priority_of(id) <= Some(Priority::Normal))
+        {
+            ready_ids.retain(|id| priority_of(id) != Some(Priority::Idle));
+        }
+
         let mut polled = 0;
 
         for task_id in ready_ids {
@@ -461,7 +605,30 @@ impl Executor {
             let waker = self.create_waker(task_id);
             let mut cx = Context::from_waker(&waker);
 
-            match task.future.as_mut().poll(&mut cx) {
+            let start = self.clock.map(|clock| clock());
+            let poll = panic::catch_unwind(AssertUnwindSafe(|| task.future.as_mut().poll(&mut cx)));
+            if let (Some(clock), Some(start)) = (self.clock, start)
+                && self.poll_spans.len() < MAX_POLL_SPANS
+            {
+                self.poll_spans.push(PollSpan {
+                    task: task_id,
+                    priority: task.priority,
+                    start,
+                    end: clock(),
+                    completed: !matches!(poll, Ok(Poll::Pending)),
+                });
+            }
+
+            let poll = match poll {
+                Ok(poll) => poll,
+                Err(payload) => {
+                    self.fault(task, payload.as_ref());
+                    polled += 1;
+                    continue;
+                }
+            };
+
+            match poll {
                 Poll::Ready(()) => {
                     // Task completed, don't re-insert
                     polled += 1;
@@ -481,6 +648,23 @@ impl Executor {
         polled
     }
 
+    /// Record a task that panicked and drop it
+    fn fault(&mut self, task: ManagedTask, payload: &(dyn std::any::Any + Send)) {
+        let message = panic_message(payload);
+        let location = PANIC_LOCATION.with(|p| p.borrow_mut().take());
+        if self.faults.len() < MAX_FAULTS {
+            self.faults.push(TaskFault {
+                task: task.id,
+                priority: task.priority,
+                message,
+                location,
+            });
+        }
+        // The future's state is suspect; a panic in its drop glue must not
+        // escape either
+        let _ = panic::catch_unwind(AssertUnwindSafe(move || drop(task)));
+    }
+
     /// Run until all tasks complete (for non-UI contexts)
     pub fn run(&mut self) {
         loop {
@@ -687,6 +871,84 @@ mod tests {
         assert!(!exec.has_tasks());
     }
 
+    #[test]
+    fn test_panicking_task_is_isolated() {
+        let mut exec = Executor::new();
+        let counter = Rc::new(Cell::new(0));
+
+        let c = counter.clone();
+        exec.spawn_with_priority(
+            async move {
+                c.set(c.get() + 1);
+            },
+            Priority::Critical,
+        );
+        let bad = exec.spawn(async {
+            panic!("task blew up");
+        });
+        let c = counter.clone();
+        exec.spawn_with_priority(
+            async move {
+                c.set(c.get() + 1);
+            },
+            Priority::Background,
+        );
+
+        assert_eq!(exec.tick(), 3);
+        assert_eq!(counter.get(), 2);
+        assert!(!exec.has_tasks());
+
+        let faults = exec.take_faults();
+        assert_eq!(faults.len(), 1);
+        assert_eq!(faults[0].task, bad);
+        assert_eq!(faults[0].priority, Priority::Normal);
+        assert_eq!(faults[0].message, "task blew up");
+        assert!(
+            faults[0]
+                .location
+                .as_deref()
+                .is_some_and(|l| l.contains("executor.rs"))
+        );
+        assert!(exec.take_faults().is_empty());
+
+        // The executor keeps working
+        let c = counter.clone();
+        exec.spawn(async move {
+            c.set(c.get() + 1);
+        });
+        exec.tick();
+        assert_eq!(counter.get(), 3);
+    }
+
+    #[test]
+    fn test_poll_spans() {
+        thread_local! {
+            static NOW: Cell<f64> = const { Cell::new(0.0) };
+        }
+        fn clock() -> f64 {
+            NOW.with(|n| {
+                n.set(n.get() + 1.0);
+                n.get()
+            })
+        }
+
+        let mut exec = Executor::new();
+        exec.spawn(async {});
+        exec.tick();
+        assert!(exec.take_poll_spans().is_empty());
+
+        exec.set_clock(Some(clock));
+        let id = exec.spawn_with_priority(async {}, Priority::Critical);
+        exec.tick();
+        let spans = exec.take_poll_spans();
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].task, id);
+        assert_eq!(spans[0].priority, Priority::Critical);
+        assert!(spans[0].completed);
+        assert_eq!(spans[0].end - spans[0].start, 1.0);
+        assert!(exec.take_poll_spans().is_empty());
+    }
+
     #[test]
     fn test_priority_order() {
         let mut exec = Executor::new();
@@ -727,6 +989,40 @@ mod tests {
         assert_eq!(result.as_slice(), &["critical", "normal", "background"]);
     }
 
+    #[test]
+    fn test_idle_priority() {
+        let mut exec = Executor::new();
+        let runs = Rc::new(RefCell::new(0));
+        {
+            let runs = runs.clone();
+            exec.spawn_with_priority(
+                Recurring::new(move || *runs.borrow_mut() += 1),
+                Priority::Idle,
+            );
+        }
+        exec.spawn(async {});
+        exec.spawn_with_priority(async {}, Priority::Background);
+
+        // A Normal task is ready: the idle task waits
+        assert_eq!(exec.tick(), 2);
+        assert_eq!(*runs.borrow(), 0);
+
+        // Nothing else is: it runs, and stays ready
+        assert_eq!(exec.tick(), 1);
+        assert_eq!(exec.tick(), 1);
+        assert_eq!(*runs.borrow(), 2);
+
+        // Background work doesn't hold it back
+        exec.spawn_with_priority(async {}, Priority::Background);
+        assert_eq!(exec.tick(), 2);
+        assert_eq!(*runs.borrow(), 3);
+
+        exec.spawn_with_priority(async {}, Priority::Critical);
+        assert_eq!(exec.tick(), 1);
+        assert_eq!(*runs.borrow(), 3);
+        assert_eq!(exec.task_count(), 1);
+    }
+
     #[test]
     fn test_yielding_task_with_run() {
         // run() handles tasks that yield without waking
@@ -3,6 +3,7 @@
 //! FIFOs provide a way for unrelated processes to communicate via
 //! a named entry in the filesystem.
 
+use super::poll::PollEvents;
 use std::cell::RefCell;
 use std::collections::{HashMap, VecDeque};
 use std::rc::Rc;
@@ -80,6 +81,17 @@ impl FifoBuffer {
         self.data.len() < self.capacity && self.readers > 0
     }
 
+    /// What a read or write could do now without blocking
+    pub fn poll_ready(&self) -> PollEvents {
+        PollEvents {
+            readable: self.is_readable(),
+            writable: self.is_writable(),
+            error: self.readers == 0,
+            hangup: self.writers == 0,
+            invalid: false,
+        }
+    }
+
     /// Add a reader
     pub fn add_reader(&mut self) {
         self.readers += 1;
@@ -8,12 +8,33 @@
 //! - Shared (read) locks: Multiple processes can hold
 //! - Exclusive (write) locks: Only one process can hold
 //!
-//! All locks are advisory - they don't prevent actual I/O,
-//! only coordinate between cooperating processes.
+//! Locks are advisory - they only coordinate between cooperating
+//! processes - except on files whose mode has the set-group-ID bit set and
+//! group execute clear (`chmod 2644`, the System V convention). Reads and
+//! writes of those fail with `WouldBlock` while another process holds a
+//! conflicting lock over the bytes involved; see [`FileLockManager::io_conflict`].
 
 use super::process::Pid;
 use std::collections::HashMap;
 
+/// flock: release the lock
+pub const LOCK_UN: i32 = 0;
+/// flock: take a shared lock
+pub const LOCK_SH: i32 = 1;
+/// flock: take an exclusive lock
+pub const LOCK_EX: i32 = 2;
+/// flock: fail instead of waiting (added to `LOCK_SH` or `LOCK_EX`)
+pub const LOCK_NB: i32 = 4;
+
+/// lockf: unlock a section
+pub const F_ULOCK: i32 = 0;
+/// lockf: lock a section, waiting if it's held
+pub const F_LOCK: i32 = 1;
+/// lockf: lock a section, failing if it's held
+pub const F_TLOCK: i32 = 2;
+/// lockf: check whether a section is locked by another process
+pub const F_TEST: i32 = 3;
+
 /// Lock type
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LockType {
@@ -222,6 +243,110 @@ impl FileLockManager {
         None
     }
 
+    /// Check if `pid` reading (`Shared`) or writing (`Exclusive`) bytes
+    /// `start..start + len` of `path` conflicts with another process's lock
+    ///
+    /// Whole-file locks cover every byte. Returns the holder of the first
+    /// conflicting lock.
+    pub fn io_conflict(
+        &self,
+        path: &str,
+        pid: Pid,
+        access: LockType,
+        start: u64,
+        len: u64,
+    ) -> Option<Pid> {
+        let conflicts = |held: LockType| {
+            held == LockType::Exclusive
+                || (access == LockType::Exclusive && held != LockType::Unlock)
+        };
+
+        let file_holder = self
+            .file_locks
+            .get(path)
+            .into_iter()
+            .flatten()
+            .find(|l| l.pid != pid && conflicts(l.lock_type))
+            .map(|l| l.pid);
+        if file_holder.is_some() {
+            return file_holder;
+        }
+
+        // A zero-length access touches nothing
+        if len == 0 {
+            return None;
+        }
+        let range = RangeLock {
+            pid,
+            lock_type: access,
+            start,
+            len,
+            whence: 0,
+        };
+        self.range_locks
+            .get(path)
+            .into_iter()
+            .flatten()
+            .find(|l| l.pid != pid && conflicts(l.lock_type) && ranges_overlap(l, &range))
+            .map(|l| l.pid)
+    }
+
+    /// Holder of a whole-file lock that would stop `pid` taking `lock_type`
+    pub fn flock_holder(&self, path: &str, pid: Pid, lock_type: LockType) -> Option<Pid> {
+        self.flock_blockers(path, pid, lock_type).into_iter().next()
+    }
+
+    /// Every holder of a whole-file lock that stops `pid` taking `lock_type`
+    pub fn flock_blockers(&self, path: &str, pid: Pid, lock_type: LockType) -> Vec<Pid> {
+        self.file_locks
+            .get(path)
+            .into_iter()
+            .flatten()
+            .filter(|l| {
+                l.pid != pid
+                    && (l.lock_type == LockType::Exclusive || lock_type == LockType::Exclusive)
+            })
+            .map(|l| l.pid)
+            .collect()
+    }
+
+    /// Every holder of a byte-range lock that stops `pid` placing `lock`
+    pub fn range_blockers(&self, path: &str, pid: Pid, lock: &RangeLock) -> Vec<Pid> {
+        let mut pids: Vec<Pid> = self
+            .range_locks
+            .get(path)
+            .into_iter()
+            .flatten()
+            .filter(|l| {
+                l.pid != pid
+                    && ranges_overlap(l, lock)
+                    && (l.lock_type == LockType::Exclusive || lock.lock_type == LockType::Exclusive)
+            })
+            .map(|l| l.pid)
+            .collect();
+        pids.dedup();
+        pids
+    }
+
+    /// Check if no locks are held
+    pub fn is_empty(&self) -> bool {
+        self.file_locks.is_empty() && self.range_locks.is_empty()
+    }
+
+    /// Processes holding at least one lock
+    pub fn holders(&self) -> Vec<Pid> {
+        let mut pids: Vec<Pid> = self
+            .file_locks
+            .values()
+            .flatten()
+            .map(|l| l.pid)
+            .chain(self.range_locks.values().flatten().map(|l| l.pid))
+            .collect();
+        pids.sort_by_key(|p| p.0);
+        pids.dedup();
+        pids
+    }
+
     /// Release all locks held by a process (called on process exit)
     pub fn release_all(&mut self, pid: Pid) {
         // Release file locks
@@ -425,4 +550,48 @@ mod tests {
                 .is_ok()
         );
     }
+
+    #[test]
+    fn test_io_conflict() {
+        let mut mgr = FileLockManager::new();
+        let read_lock = RangeLock {
+            pid: Pid(1),
+            lock_type: LockType::Shared,
+            start: 10,
+            len: 10,
+            whence: 0,
+        };
+        mgr.fcntl_lock("/data", Pid(1), read_lock, false).unwrap();
+
+        // Others may read the locked bytes but not write them
+        assert_eq!(
+            mgr.io_conflict("/data", Pid(2), LockType::Shared, 0, 100),
+            None
+        );
+        assert_eq!(
+            mgr.io_conflict("/data", Pid(2), LockType::Exclusive, 15, 1),
+            Some(Pid(1))
+        );
+        assert_eq!(
+            mgr.io_conflict("/data", Pid(2), LockType::Exclusive, 20, 5),
+            None
+        );
+        assert_eq!(
+            mgr.io_conflict("/data", Pid(1), LockType::Exclusive, 15, 1),
+            None
+        );
+
+        // A whole-file exclusive lock blocks any access by others
+        mgr.flock("/other", Pid(3), LockType::Exclusive, false)
+            .unwrap();
+        assert_eq!(
+            mgr.io_conflict("/other", Pid(2), LockType::Shared, 0, 1),
+            Some(Pid(3))
+        );
+        assert_eq!(
+            mgr.flock_holder("/other", Pid(2), LockType::Shared),
+            Some(Pid(3))
+        );
+        assert_eq!(mgr.holders(), [Pid(1), Pid(3)]);
+    }
 }
@@ -0,0 +1,158 @@
+//! Guest sessions
+//!
+//! A guest session puts a throwaway overlay over the root filesystem. The
+//! real filesystem as it was when the session began is the read-only lower
+//! layer; everything written since is the upper layer, held in memory like
+//! a tmpfs. Persistence only ever sees the lower layer, and ending the
+//! session drops the upper layer, so nothing a guest does survives unless
+//! a path is exported first. Exported paths are copied down into the lower
+//! layer when the session ends.
+
+use super::mount::MountEntry;
+use crate::vfs::{FsChange, FsChangeKind, FsSnapshot, MemoryFs};
+use std::collections::BTreeSet;
+use std::io;
+
+/// Mount source shown for the overlay root while a guest session runs
+pub const GUEST_SOURCE: &str = "guest";
+
+/// An active guest session
+#[derive(Clone)]
+pub struct GuestSession {
+    /// The real filesystem when the session began (lower layer)
+    lower: FsSnapshot,
+    /// Paths copied into the real filesystem when the session ends
+    exports: BTreeSet<String>,
+    /// Time the session began
+    since: f64,
+    /// Root mount the overlay replaced
+    root_mount: Option<MountEntry>,
+}
+
+/// Check if `path` is `prefix` or inside it
+fn within(path: &str, prefix: &str) -> bool {
+    prefix == "/"
+        || path == prefix
+        || path
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+impl GuestSession {
+    /// Begin a session over `fs`, remembering the root mount the overlay
+    /// replaces
+    pub fn new(fs: &MemoryFs, now: f64, root_mount: Option<MountEntry>) -> Self {
+        Self {
+            lower: fs.snapshot(),
+            exports: BTreeSet::new(),
+            since: now,
+            root_mount,
+        }
+    }
+
+    /// Root mount to put back when the session ends
+    pub fn root_mount(&self) -> Option<&MountEntry> {
+        self.root_mount.as_ref()
+    }
+
+    /// The real filesystem under the overlay
+    pub fn lower(&self) -> &FsSnapshot {
+        &self.lower
+    }
+
+    pub fn since(&self) -> f64 {
+        self.since
+    }
+
+    /// Paths that will be kept when the session ends
+    pub fn exports(&self) -> impl Iterator<Item = &str> {
+        self.exports.iter().map(String::as_str)
+    }
+
+    /// Keep `path` (and everything under it) when the session ends
+    pub fn export(&mut self, path: &str) {
+        self.exports.insert(path.to_string());
+    }
+
+    /// The upper layer: paths the guest changed
+    pub fn upper(&self, fs: &MemoryFs) -> Vec<FsChange> {
+        fs.changes_since(&self.lower)
+    }
+
+    /// End the session, returning the real filesystem with the exported
+    /// paths copied in from `fs` and every other change discarded
+    pub fn finish(&self, fs: &MemoryFs) -> io::Result<MemoryFs> {
+        let upper = self.upper(fs);
+        let exported = |path: &str| self.exports.iter().any(|e| within(path, e));
+        // Directories the guest created above an exported path come along
+        let needed = |path: &str| self.exports.iter().any(|e| within(e, path));
+
+        // Applying the upper layer is the reverse of undoing it: created
+        // paths are restored from the guest image, deleted ones removed
+        let changes: Vec<FsChange> = upper
+            .into_iter()
+            .filter(|c| exported(&c.path) || (c.kind == FsChangeKind::Created && needed(&c.path)))
+            .map(|c| FsChange {
+                kind: match c.kind {
+                    FsChangeKind::Deleted => FsChangeKind::Created,
+                    _ => FsChangeKind::Modified,
+                },
+                path: c.path,
+            })
+            .collect();
+        let image = fs.snapshot().subset(
+            changes
+                .iter()
+                .filter(|c| c.kind == FsChangeKind::Modified)
+                .map(|c| c.path.as_str()),
+        );
+
+        let mut real = MemoryFs::restore(self.lower.clone())?;
+        real.revert(&image, &changes)?;
+        Ok(real)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::{FileSystem, read_to_string, write_string};
+
+    #[test]
+    fn test_guest_discards_unless_exported() {
+        let mut fs = MemoryFs::new();
+        fs.create_dir("/home").unwrap();
+        write_string(&mut fs, "/home/notes", "real").unwrap();
+        write_string(&mut fs, "/home/old", "old").unwrap();
+
+        let mut guest = GuestSession::new(&fs, 0.0, None);
+        write_string(&mut fs, "/home/notes", "scribbled").unwrap();
+        fs.remove_file("/home/old").unwrap();
+        fs.create_dir("/work").unwrap();
+        fs.create_dir("/work/demo").unwrap();
+        write_string(&mut fs, "/work/demo/keep.txt", "keep").unwrap();
+        write_string(&mut fs, "/work/scratch", "tmp").unwrap();
+        assert_eq!(guest.upper(&fs).len(), 6);
+
+        guest.export("/work/demo");
+        let mut real = guest.finish(&fs).unwrap();
+        assert_eq!(read_to_string(&mut real, "/home/notes").unwrap(), "real");
+        assert!(real.exists("/home/old"));
+        assert_eq!(
+            read_to_string(&mut real, "/work/demo/keep.txt").unwrap(),
+            "keep"
+        );
+        assert!(!real.exists("/work/scratch"));
+    }
+
+    #[test]
+    fn test_export_deletion() {
+        let mut fs = MemoryFs::new();
+        write_string(&mut fs, "/junk", "x").unwrap();
+        let mut guest = GuestSession::new(&fs, 0.0, None);
+        fs.remove_file("/junk").unwrap();
+        guest.export("/junk");
+        let real = guest.finish(&fs).unwrap();
+        assert!(!real.exists("/junk"));
+    }
+}
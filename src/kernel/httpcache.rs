@@ -0,0 +1,293 @@
+//! HTTP cache
+//!
+//! Successful GET responses are kept in [`CACHE_DIR`], one file per URL,
+//! with the validators the server sent (ETag, Last-Modified). The next
+//! request for the URL asks the server whether its copy is still current
+//! (`If-None-Match`, `If-Modified-Since`), and a `304 Not Modified` answer
+//! is served from the cache without the body crossing the network again.
+//! Each file holds a header line (a [`CachedResponse`] as JSON) and then
+//! the body.
+//!
+//! Requests opt in with a [`CachePolicy`]; `curl`, `wget` and the package
+//! registry do. In offline mode (see `syscall::set_offline`) connections
+//! fail at once, and those callers serve what is cached instead.
+
+use crate::kernel::pkg::Checksum;
+use crate::vfs::{FileSystem, MemoryFs, OpenOptions, atomic_write};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+
+/// Where responses are kept
+pub const CACHE_DIR: &str = "/var/cache/http";
+
+/// How a request uses the cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CachePolicy {
+    /// Leave the cache alone
+    #[default]
+    Bypass,
+    /// Ask the server whether the cached copy is current, and keep what
+    /// it sends
+    Revalidate,
+    /// Serve the cached copy without asking, and fetch only what isn't
+    /// cached
+    Prefer,
+}
+
+impl CachePolicy {
+    /// Revalidate while online; serve what is cached, without asking,
+    /// while offline
+    pub fn for_mode(offline: bool) -> Self {
+        if offline {
+            Self::Prefer
+        } else {
+            Self::Revalidate
+        }
+    }
+}
+
+/// A response as the cache keeps it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub content_type: Option<String>,
+    /// When it was fetched or last revalidated, in milliseconds since the
+    /// epoch
+    pub time: f64,
+    #[serde(skip)]
+    pub body: Vec<u8>,
+}
+
+impl CachedResponse {
+    /// The response to keep for a GET of `url` that came back with
+    /// `status` and `headers` (names lowercased), if it may be kept: a
+    /// 200 the server didn't mark `no-store`
+    pub fn from_response(
+        url: &str,
+        status: u16,
+        headers: &HashMap<String, String>,
+        body: Vec<u8>,
+        time: f64,
+    ) -> Option<Self> {
+        let no_store = headers
+            .get("cache-control")
+            .is_some_and(|cc| cc.split(',').any(|d| d.trim() == "no-store"));
+        if status != 200 || no_store {
+            return None;
+        }
+        Some(Self {
+            url: url.to_string(),
+            etag: headers.get("etag").cloned(),
+            last_modified: headers.get("last-modified").cloned(),
+            content_type: headers.get("content-type").cloned(),
+            time,
+            body,
+        })
+    }
+
+    /// Headers that make a request conditional on this copy being stale
+    pub fn validators(&self) -> Vec<(&'static str, String)> {
+        let mut headers = Vec::new();
+        if let Some(etag) = &self.etag {
+            headers.push(("If-None-Match", etag.clone()));
+        }
+        if let Some(date) = &self.last_modified {
+            headers.push(("If-Modified-Since", date.clone()));
+        }
+        headers
+    }
+}
+
+/// The file `url` is cached in
+pub fn path_of(url: &str) -> String {
+    format!(
+        "{}/{}",
+        CACHE_DIR,
+        Checksum::compute(url.as_bytes()).to_hex()
+    )
+}
+
+/// The cached response for `url`, if there is one
+pub fn load(fs: &mut MemoryFs, url: &str) -> Option<CachedResponse> {
+    let data = read(fs, &path_of(url)).ok()?;
+    let (mut response, body) = parse_header(&data)?;
+    if response.url != url {
+        return None;
+    }
+    response.body = data[body..].to_vec();
+    Some(response)
+}
+
+/// The response in a cache file's header line, without its body, and
+/// where the body starts
+fn parse_header(data: &[u8]) -> Option<(CachedResponse, usize)> {
+    let split = data.iter().position(|&b| b == b'\n')?;
+    let response = serde_json::from_slice(&data[..split]).ok()?;
+    Some((response, split + 1))
+}
+
+/// Keep `response`, replacing any copy of the same URL
+pub fn store(fs: &mut MemoryFs, response: &CachedResponse) -> io::Result<()> {
+    for dir in ["/var", "/var/cache", CACHE_DIR] {
+        if !fs.exists(dir) {
+            fs.create_dir(dir)?;
+            fs.chown(dir, Some(0), Some(0))?;
+        }
+    }
+    let mut data = serde_json::to_vec(response).map_err(io::Error::other)?;
+    data.push(b'\n');
+    data.extend_from_slice(&response.body);
+
+    let path = path_of(&response.url);
+    atomic_write(fs, &path, &data)?;
+    fs.chown(&path, Some(0), Some(0))?;
+    fs.chmod(&path, 0o644)?;
+    Ok(())
+}
+
+/// Responses cached, and the bytes they take
+pub fn usage(fs: &MemoryFs) -> (usize, u64) {
+    let Ok(entries) = fs.read_dir(CACHE_DIR) else {
+        return (0, 0);
+    };
+    entries
+        .iter()
+        .filter(|e| !e.is_dir)
+        .filter_map(|e| fs.metadata(&format!("{}/{}", CACHE_DIR, e.name)).ok())
+        .fold((0, 0), |(count, bytes), meta| {
+            (count + 1, bytes + meta.size)
+        })
+}
+
+/// Drop every cached response; returns how many there were
+pub fn clear(fs: &mut MemoryFs) -> io::Result<usize> {
+    remove_where(fs, |_| true)
+}
+
+/// Drop the responses cached from `origin` (see
+/// [`super::netperm::origin`]); returns how many there were
+pub fn clear_origin(fs: &mut MemoryFs, origin: &str) -> io::Result<usize> {
+    remove_where(fs, |url| {
+        url.and_then(super::netperm::origin).as_deref() == Some(origin)
+    })
+}
+
+/// Drop the cached responses whose URL, if it can be read, `remove`
+/// picks
+fn remove_where(fs: &mut MemoryFs, remove: impl Fn(Option<&str>) -> bool) -> io::Result<usize> {
+    let Ok(entries) = fs.read_dir(CACHE_DIR) else {
+        return Ok(0);
+    };
+    let mut removed = 0;
+    for entry in entries.iter().filter(|e| !e.is_dir) {
+        let path = format!("{}/{}", CACHE_DIR, entry.name);
+        let url = read(fs, &path)
+            .ok()
+            .and_then(|data| parse_header(&data))
+            .map(|(response, _)| response.url);
+        if remove(url.as_deref()) {
+            fs.remove_file(&path)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+fn read(fs: &mut MemoryFs, path: &str) -> io::Result<Vec<u8>> {
+    let size = fs.metadata(path)?.size;
+    let handle = fs.open(path, OpenOptions::new().read(true))?;
+    let mut data = vec![0u8; size as usize];
+    let read = fs.read(handle, &mut data);
+    fs.close(handle)?;
+    data.truncate(read?);
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_from_response() {
+        let url = "https://pkg.test/index.json";
+        let response = CachedResponse::from_response(
+            url,
+            200,
+            &headers(&[("etag", "\"v2\""), ("last-modified", "Mon, 01 Jan 2024")]),
+            b"{}".to_vec(),
+            1.0,
+        )
+        .unwrap();
+        assert_eq!(
+            response.validators(),
+            [
+                ("If-None-Match", "\"v2\"".to_string()),
+                ("If-Modified-Since", "Mon, 01 Jan 2024".to_string())
+            ]
+        );
+
+        assert!(CachedResponse::from_response(url, 404, &headers(&[]), vec![], 1.0).is_none());
+        let private = headers(&[("cache-control", "private, no-store")]);
+        assert!(CachedResponse::from_response(url, 200, &private, vec![], 1.0).is_none());
+        let plain = CachedResponse::from_response(url, 200, &headers(&[]), vec![], 1.0);
+        assert!(plain.unwrap().validators().is_empty());
+    }
+
+    #[test]
+    fn test_store_and_load() {
+        let mut fs = MemoryFs::new();
+        let url = "https://pkg.test/index.json";
+        assert_eq!(load(&mut fs, url), None);
+        assert_eq!(usage(&fs), (0, 0));
+
+        let mut response = CachedResponse::from_response(
+            url,
+            200,
+            &headers(&[("etag", "\"a\"")]),
+            b"first\nline".to_vec(),
+            1.0,
+        )
+        .unwrap();
+        store(&mut fs, &response).unwrap();
+        assert_eq!(load(&mut fs, url), Some(response.clone()));
+        assert_eq!(load(&mut fs, "https://pkg.test/other"), None);
+
+        response.body = b"second".to_vec();
+        store(&mut fs, &response).unwrap();
+        assert_eq!(load(&mut fs, url).unwrap().body, b"second");
+        let (count, bytes) = usage(&fs);
+        assert_eq!(count, 1);
+        assert!(bytes > 6);
+        assert_eq!(fs.metadata(&path_of(url)).unwrap().uid, 0);
+
+        assert_eq!(clear(&mut fs).unwrap(), 1);
+        assert_eq!(load(&mut fs, url), None);
+    }
+
+    #[test]
+    fn test_clear_origin() {
+        let mut fs = MemoryFs::new();
+        let urls = [
+            "https://a.test/x",
+            "https://a.test:443/y",
+            "https://b.test/x",
+        ];
+        for url in urls {
+            let response = CachedResponse::from_response(url, 200, &headers(&[]), vec![], 1.0);
+            store(&mut fs, &response.unwrap()).unwrap();
+        }
+        assert_eq!(clear_origin(&mut fs, "https://a.test").unwrap(), 2);
+        assert_eq!(usage(&fs).0, 1);
+        assert!(load(&mut fs, urls[2]).is_some());
+    }
+}
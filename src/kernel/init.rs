@@ -2,9 +2,27 @@
 //!
 //! Provides basic service management and system initialization.
 //! Acts as the first process, spawning and managing services.
+//!
+//! A service with a watchdog (`watchdog_ms`) has to pet it at least that
+//! often through its watchdog descriptor once running. One that stays
+//! silent longer is considered hung: [`InitSystem::check_watchdogs`]
+//! restarts it if its restart policy allows and fails it otherwise, and
+//! reports each such incident.
+//!
+//! Services don't inherit the environment of whoever starts them: each one
+//! runs with [`SERVICE_PATH`] plus exactly the variables its definition
+//! lists, so it behaves the same whether it was started at boot or from a
+//! shell.
 
+use super::process::EnvSpec;
 use std::collections::HashMap;
 
+/// `PATH` of every service, unless its definition sets another
+pub const SERVICE_PATH: &str = "/bin:/usr/bin:/sbin";
+
+/// Where watchdog incidents are recorded
+pub const WATCHDOG_LOG: &str = "/var/log/watchdog";
+
 /// Service state
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ServiceState {
@@ -50,10 +68,12 @@ pub struct ServiceConfig {
     pub wanted_by: Vec<String>,
     /// Restart policy
     pub restart: RestartPolicy,
-    /// Environment variables
+    /// Environment variables, on top of `PATH`; the service gets no others
     pub environment: HashMap<String, String>,
     /// Working directory
     pub working_directory: Option<String>,
+    /// Watchdog timeout in milliseconds, if the service has a watchdog
+    pub watchdog_ms: Option<f64>,
 }
 
 impl ServiceConfig {
@@ -69,8 +89,20 @@ impl ServiceConfig {
             restart: RestartPolicy::No,
             environment: HashMap::new(),
             working_directory: None,
+            watchdog_ms: None,
         }
     }
+
+    /// How the service's environment is made: from nothing, not from the
+    /// environment of whoever starts it
+    pub fn env_spec(&self) -> EnvSpec {
+        let mut vars: Vec<_> = self.environment.iter().collect();
+        vars.sort();
+        vars.into_iter().fold(
+            EnvSpec::clean().set("PATH", SERVICE_PATH),
+            |spec, (name, value)| spec.set(name.as_str(), value.as_str()),
+        )
+    }
 }
 
 /// Restart policy for services
@@ -97,6 +129,10 @@ pub struct Service {
     pub exit_code: Option<i32>,
     /// Number of restarts
     pub restart_count: u32,
+    /// Watchdog bookkeeping
+    pub watchdog: WatchdogState,
+    /// Environment it was last started with
+    pub environ: HashMap<String, String>,
 }
 
 impl Service {
@@ -104,13 +140,70 @@ impl Service {
         Self {
             config,
             state: ServiceState::Stopped,
+            environ: HashMap::new(),
             pid: None,
             exit_code: None,
             restart_count: 0,
+            watchdog: WatchdogState::default(),
         }
     }
 }
 
+/// Watchdog bookkeeping of a service
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WatchdogState {
+    /// Time of the last pet, or of arming; `None` until the first check
+    /// after the service starts
+    pub last_pet: Option<f64>,
+    /// Deadlines missed
+    pub incidents: u32,
+    /// Time of the last missed deadline
+    pub last_incident: Option<f64>,
+}
+
+/// What the init system did about a missed watchdog deadline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchdogAction {
+    /// Restarted, as its restart policy allows
+    Restarted,
+    /// Marked failed
+    Failed,
+}
+
+/// A service that missed its watchdog deadline
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchdogIncident {
+    pub service: String,
+    /// Main PID of the hung instance
+    pub pid: Option<u32>,
+    /// When the miss was noticed
+    pub time: f64,
+    /// Milliseconds since the last pet
+    pub silent_ms: f64,
+    pub timeout_ms: f64,
+    pub action: WatchdogAction,
+}
+
+impl WatchdogIncident {
+    /// The incident as a log line, time in seconds
+    pub fn to_line(&self) -> String {
+        let pid = self.pid.map_or("-".to_string(), |p| p.to_string());
+        let action = match self.action {
+            WatchdogAction::Restarted => "restarted",
+            WatchdogAction::Failed => "failed",
+        };
+        format!(
+            "[{:>10.3}] {}: watchdog timeout, silent {:.0} ms (limit {:.0} ms), pid {}, {}",
+            self.time / 1000.0,
+            self.service,
+            self.silent_ms,
+            self.timeout_ms,
+            pid,
+            action
+        )
+    }
+}
+
 /// System runlevel/target
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Target {
@@ -249,6 +342,8 @@ impl InitSystem {
         if let Some(service) = self.services.get_mut(name) {
             service.state = ServiceState::Running;
             service.pid = Some(pid);
+            service.environ = service.config.env_spec().resolve(&HashMap::new());
+            service.watchdog.last_pet = None;
         } else {
             return Err(format!("Service '{}' disappeared unexpectedly", name));
         }
@@ -271,6 +366,7 @@ impl InitSystem {
         // In a real implementation, we would signal the process
         service.state = ServiceState::Stopped;
         service.pid = None;
+        service.watchdog.last_pet = None;
 
         Ok(())
     }
@@ -323,9 +419,126 @@ impl InitSystem {
             state: s.state,
             pid: s.pid,
             exit_code: s.exit_code,
+            restart_count: s.restart_count,
+            watchdog_ms: s.config.watchdog_ms,
+            watchdog: s.watchdog.clone(),
+            environ: s.environ.clone(),
         })
     }
 
+    /// Set (`Some`) or remove (`None`) a variable in a service's
+    /// environment; it takes effect the next time the service starts
+    pub fn set_environment(
+        &mut self,
+        name: &str,
+        var: &str,
+        value: Option<&str>,
+    ) -> Result<(), String> {
+        let service = self
+            .services
+            .get_mut(name)
+            .ok_or_else(|| format!("Service '{}' not found", name))?;
+        if var.is_empty() || var.contains('=') {
+            return Err(format!("Invalid variable name '{}'", var));
+        }
+        match value {
+            Some(value) => service
+                .config
+                .environment
+                .insert(var.to_string(), value.to_string()),
+            None => service.config.environment.remove(var),
+        };
+        Ok(())
+    }
+
+    /// Set or remove a service's watchdog timeout
+    pub fn set_watchdog(&mut self, name: &str, timeout_ms: Option<f64>) -> Result<(), String> {
+        let service = self
+            .services
+            .get_mut(name)
+            .ok_or_else(|| format!("Service '{}' not found", name))?;
+        if timeout_ms.is_some_and(|t| t.is_nan() || t <= 0.0) {
+            return Err("Watchdog timeout must be positive".to_string());
+        }
+        service.config.watchdog_ms = timeout_ms;
+        service.watchdog.last_pet = None;
+        Ok(())
+    }
+
+    /// Record that a running service is alive
+    pub fn pet_watchdog(&mut self, name: &str, now: f64) -> Result<(), String> {
+        let service = self
+            .services
+            .get_mut(name)
+            .ok_or_else(|| format!("Service '{}' not found", name))?;
+        if service.config.watchdog_ms.is_none() {
+            return Err(format!("Service '{}' has no watchdog", name));
+        }
+        if service.state != ServiceState::Running {
+            return Err(format!("Service '{}' is not running", name));
+        }
+        service.watchdog.last_pet = Some(now);
+        Ok(())
+    }
+
+    /// Arm the watchdogs of newly started services and deal with running
+    /// services that haven't petted theirs in time
+    pub fn check_watchdogs(&mut self, now: f64) -> Vec<WatchdogIncident> {
+        let mut overdue = Vec::new();
+        for service in self.services.values_mut() {
+            let Some(timeout_ms) = service.config.watchdog_ms else {
+                continue;
+            };
+            if service.state != ServiceState::Running {
+                continue;
+            }
+            match service.watchdog.last_pet {
+                None => service.watchdog.last_pet = Some(now),
+                Some(last) if now - last > timeout_ms => {
+                    overdue.push((service.config.name.clone(), now - last, timeout_ms));
+                }
+                Some(_) => {}
+            }
+        }
+        overdue.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut incidents = Vec::new();
+        for (name, silent_ms, timeout_ms) in overdue {
+            let Some(service) = self.services.get_mut(&name) else {
+                continue;
+            };
+            let pid = service.pid;
+            service.watchdog.incidents += 1;
+            service.watchdog.last_incident = Some(now);
+
+            let action = if service.config.restart != RestartPolicy::No
+                && self.restart_service(&name).is_ok()
+            {
+                if let Some(service) = self.services.get_mut(&name) {
+                    service.restart_count += 1;
+                }
+                WatchdogAction::Restarted
+            } else {
+                if let Some(service) = self.services.get_mut(&name) {
+                    service.state = ServiceState::Failed;
+                    service.pid = None;
+                    service.watchdog.last_pet = None;
+                }
+                WatchdogAction::Failed
+            };
+
+            incidents.push(WatchdogIncident {
+                service: name,
+                pid,
+                time: now,
+                silent_ms,
+                timeout_ms,
+                action,
+            });
+        }
+        incidents
+    }
+
     /// Set system target
     pub fn set_target(&mut self, target: Target) {
         self.target = target;
@@ -376,6 +589,17 @@ impl InitSystem {
         self.hostname = hostname.to_string();
     }
 
+    /// Check a hostname: 1-64 letters, digits, `-` and `.`, not starting
+    /// or ending with `-` or `.`
+    pub fn is_valid_hostname(hostname: &str) -> bool {
+        (1..=64).contains(&hostname.len())
+            && hostname
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+            && !hostname.starts_with(['-', '.'])
+            && !hostname.ends_with(['-', '.'])
+    }
+
     /// Get boot time
     pub fn boot_time(&self) -> f64 {
         self.boot_time
@@ -429,6 +653,11 @@ pub struct ServiceStatus {
     pub state: ServiceState,
     pub pid: Option<u32>,
     pub exit_code: Option<i32>,
+    pub restart_count: u32,
+    pub watchdog_ms: Option<f64>,
+    pub watchdog: WatchdogState,
+    /// Environment of the running service
+    pub environ: HashMap<String, String>,
 }
 
 #[cfg(test)]
@@ -478,6 +707,78 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_service_environment() {
+        let mut init = InitSystem::new();
+        let mut config = ServiceConfig::new("web");
+        config
+            .environment
+            .insert("PORT".to_string(), "8080".to_string());
+        init.register_service(config);
+
+        init.start_service("web").unwrap();
+        let env = init.service_status("web").unwrap().environ;
+        let mut vars: Vec<_> = env.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        vars.sort();
+        assert_eq!(vars, [format!("PATH={}", SERVICE_PATH), "PORT=8080".into()]);
+
+        // Changes apply from the next start
+        init.set_environment("web", "PATH", Some("/opt/bin"))
+            .unwrap();
+        init.set_environment("web", "PORT", None).unwrap();
+        assert!(init.set_environment("web", "A=B", Some("c")).is_err());
+        assert_eq!(init.service_status("web").unwrap().environ, env);
+        init.restart_service("web").unwrap();
+        let env = init.service_status("web").unwrap().environ;
+        assert_eq!(env.len(), 1);
+        assert_eq!(env.get("PATH").map(String::as_str), Some("/opt/bin"));
+    }
+
+    #[test]
+    fn test_watchdog() {
+        let mut init = InitSystem::new();
+
+        let mut config = ServiceConfig::new("daemon");
+        config.restart = RestartPolicy::OnFailure;
+        init.register_service(config);
+        init.register_service(ServiceConfig::new("oneshot"));
+        for name in ["daemon", "oneshot"] {
+            init.set_watchdog(name, Some(1000.0)).unwrap();
+            init.start_service(name).unwrap();
+        }
+        assert!(init.set_watchdog("daemon", Some(0.0)).is_err());
+
+        // Armed on the first check, then petted in time
+        assert!(init.check_watchdogs(0.0).is_empty());
+        init.pet_watchdog("daemon", 900.0).unwrap();
+        init.pet_watchdog("oneshot", 900.0).unwrap();
+        assert!(init.check_watchdogs(1500.0).is_empty());
+
+        // Both go quiet: one is restarted, the other fails
+        let incidents = init.check_watchdogs(2000.0);
+        assert_eq!(incidents.len(), 2);
+        assert_eq!(incidents[0].service, "daemon");
+        assert_eq!(incidents[0].action, WatchdogAction::Restarted);
+        assert_eq!(incidents[0].silent_ms, 1100.0);
+        assert_eq!(incidents[1].action, WatchdogAction::Failed);
+        assert!(incidents[1].to_line().ends_with("failed"));
+
+        let daemon = init.service_status("daemon").unwrap();
+        assert_eq!(daemon.state, ServiceState::Running);
+        assert_eq!(daemon.restart_count, 1);
+        assert_eq!(daemon.watchdog.incidents, 1);
+        assert_eq!(daemon.watchdog.last_pet, None);
+        let oneshot = init.service_status("oneshot").unwrap();
+        assert_eq!(oneshot.state, ServiceState::Failed);
+        assert_eq!(oneshot.pid, None);
+        assert!(init.pet_watchdog("oneshot", 2100.0).is_err());
+
+        // The restarted instance gets a fresh deadline
+        assert!(init.check_watchdogs(2100.0).is_empty());
+        assert!(init.check_watchdogs(3000.0).is_empty());
+        assert_eq!(init.check_watchdogs(3200.0).len(), 1);
+    }
+
     #[test]
     fn test_target_parsing() {
         assert_eq!(Target::parse("rescue"), Some(Target::Rescue));
@@ -500,6 +801,11 @@ mod tests {
 
         init.set_hostname("test-host");
         assert_eq!(init.hostname(), "test-host");
+
+        assert!(InitSystem::is_valid_hostname("my-box.local"));
+        assert!(!InitSystem::is_valid_hostname(""));
+        assert!(!InitSystem::is_valid_hostname("-box"));
+        assert!(!InitSystem::is_valid_hostname("my box"));
     }
 
     #[test]
@@ -9,14 +9,26 @@
 //!
 //! Both channel types support waker-based async for efficient wake-up
 //! instead of busy-polling.
-
-use std::cell::RefCell;
+//!
+//! The halves of a bounded channel can be bound to the processes using
+//! them. A bound sender waiting on a full channel then waits for the
+//! receiving process, and a bound receiver on an empty one for the sending
+//! processes, in the kernel's wait-for graph; a wait that would deadlock
+//! fails with `Deadlock` instead of pending forever.
+
+use super::process::Pid;
+use super::syscall::{self, SyscallError};
+use std::cell::{Cell, RefCell};
 use std::collections::VecDeque;
 use std::future::Future;
 use std::pin::Pin;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::task::{Context, Poll, Waker};
 
+/// Source of bounded channel ids
+static NEXT_CHANNEL_ID: AtomicU64 = AtomicU64::new(1);
+
 /// Create a new unbounded channel pair
 pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
     let inner = Rc::new(RefCell::new(ChannelInner {
@@ -41,16 +53,20 @@ pub fn bounded_channel<T>(capacity: usize) -> (BoundedSender<T>, BoundedReceiver
     assert!(capacity > 0, "capacity must be at least 1");
 
     let inner = Rc::new(RefCell::new(BoundedChannelInner {
+        id: NEXT_CHANNEL_ID.fetch_add(1, Ordering::Relaxed),
         queue: VecDeque::with_capacity(capacity),
         capacity,
         closed: false,
         send_wakers: Vec::new(),
         recv_wakers: Vec::new(),
+        senders: Vec::new(),
+        receiver: None,
     }));
 
     (
         BoundedSender {
             inner: inner.clone(),
+            owner: Cell::new(None),
         },
         BoundedReceiver { inner },
     )
@@ -64,6 +80,8 @@ struct ChannelInner<T> {
 }
 
 struct BoundedChannelInner<T> {
+    /// Identifies the channel in the wait-for graph
+    id: u64,
     queue: VecDeque<T>,
     capacity: usize,
     closed: bool,
@@ -71,6 +89,10 @@ struct BoundedChannelInner<T> {
     send_wakers: Vec<Waker>,
     /// Wakers for tasks waiting to receive (channel empty)
     recv_wakers: Vec<Waker>,
+    /// Processes of the bound senders, one entry per sender
+    senders: Vec<Pid>,
+    /// Process of the receiver, if bound
+    receiver: Option<Pid>,
 }
 
 /// Sending half of a channel
@@ -182,9 +204,23 @@ pub enum TryRecvError {
 /// Sending half of a bounded channel
 pub struct BoundedSender<T> {
     inner: Rc<RefCell<BoundedChannelInner<T>>>,
+    owner: Cell<Option<Pid>>,
 }
 
 impl<T> BoundedSender<T> {
+    /// Bind this sender to the process using it (see the module docs)
+    ///
+    /// Clones start out bound to the same process.
+    pub fn bind(&self, pid: Pid) {
+        let mut inner = self.inner.borrow_mut();
+        if let Some(old) = self.owner.replace(Some(pid))
+            && let Some(i) = inner.senders.iter().position(|&p| p == old)
+        {
+            inner.senders.remove(i);
+        }
+        inner.senders.push(pid);
+    }
+
     /// Try to send a value without blocking
     ///
     /// Returns `TrySendError::Full` if the channel is at capacity.
@@ -251,8 +287,24 @@ impl<T> BoundedSender<T> {
 
 impl<T> Clone for BoundedSender<T> {
     fn clone(&self) -> Self {
+        let owner = self.owner.get();
+        if let Some(pid) = owner {
+            self.inner.borrow_mut().senders.push(pid);
+        }
         Self {
             inner: self.inner.clone(),
+            owner: Cell::new(owner),
+        }
+    }
+}
+
+impl<T> Drop for BoundedSender<T> {
+    fn drop(&mut self) {
+        if let Some(pid) = self.owner.get()
+            && let Ok(mut inner) = self.inner.try_borrow_mut()
+            && let Some(i) = inner.senders.iter().position(|&p| p == pid)
+        {
+            inner.senders.remove(i);
         }
     }
 }
@@ -266,16 +318,18 @@ pub struct BoundedSendFuture<'a, T> {
 impl<T> Unpin for BoundedSendFuture<'_, T> {}
 
 impl<T> Future for BoundedSendFuture<'_, T> {
-    type Output = Result<(), SendError<T>>;
+    type Output = Result<(), BoundedSendError<T>>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let this = self.get_mut();
         let value = this.value.take().expect("polled after completion");
+        let owner = this.sender.owner.get();
 
         let mut inner = this.sender.inner.borrow_mut();
 
         if inner.closed {
-            return Poll::Ready(Err(SendError(value)));
+            stop_waiting(owner, inner.id);
+            return Poll::Ready(Err(BoundedSendError::Closed(value)));
         }
 
         if inner.queue.len() < inner.capacity {
@@ -286,10 +340,17 @@ impl<T> Future for BoundedSendFuture<'_, T> {
                 waker.wake();
             }
 
+            stop_waiting(owner, inner.id);
             return Poll::Ready(Ok(()));
         }
 
-        // Channel is full - register our waker for notification
+        // Channel is full: wait for the receiving process
+        let holders: Vec<Pid> = inner.receiver.into_iter().collect();
+        if let Err(SyscallError::Deadlock) = start_waiting(owner, inner.id, &holders) {
+            return Poll::Ready(Err(BoundedSendError::Deadlock(value)));
+        }
+
+        // Register our waker for notification
         let waker = cx.waker().clone();
         if !inner.send_wakers.iter().any(|w| w.will_wake(&waker)) {
             inner.send_wakers.push(waker);
@@ -306,6 +367,11 @@ pub struct BoundedReceiver<T> {
 }
 
 impl<T> BoundedReceiver<T> {
+    /// Bind this receiver to the process using it (see the module docs)
+    pub fn bind(&self, pid: Pid) {
+        self.inner.borrow_mut().receiver = Some(pid);
+    }
+
     /// Try to receive a value without blocking
     pub fn try_recv(&self) -> Result<T, TryRecvError> {
         let mut inner = self.inner.borrow_mut();
@@ -349,10 +415,11 @@ pub struct BoundedRecvFuture<'a, T> {
 }
 
 impl<T> Future for BoundedRecvFuture<'_, T> {
-    type Output = Option<T>;
+    type Output = Result<T, RecvError>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let mut inner = self.receiver.inner.borrow_mut();
+        let owner = inner.receiver;
 
         match inner.queue.pop_front() {
             Some(value) => {
@@ -360,10 +427,20 @@ impl<T> Future for BoundedRecvFuture<'_, T> {
                 for waker in inner.send_wakers.drain(..) {
                     waker.wake();
                 }
-                Poll::Ready(Some(value))
+                stop_waiting(owner, inner.id);
+                Poll::Ready(Ok(value))
+            }
+            None if inner.closed => {
+                stop_waiting(owner, inner.id);
+                Poll::Ready(Err(RecvError::Closed))
             }
-            None if inner.closed => Poll::Ready(None),
             None => {
+                // Empty: wait for the sending processes
+                if let Err(SyscallError::Deadlock) = start_waiting(owner, inner.id, &inner.senders)
+                {
+                    return Poll::Ready(Err(RecvError::Deadlock));
+                }
+
                 // Register waker for notification when data arrives
                 let waker = cx.waker().clone();
                 // Avoid duplicate wakers
@@ -385,6 +462,57 @@ pub enum TrySendError<T> {
     Closed(T),
 }
 
+/// Error when an async send on a bounded channel fails
+#[derive(Debug, PartialEq, Eq)]
+pub enum BoundedSendError<T> {
+    /// Channel is closed
+    Closed(T),
+    /// Waiting for space would deadlock
+    Deadlock(T),
+}
+
+/// Error when an async receive on a bounded channel fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvError {
+    /// Channel is closed and empty
+    Closed,
+    /// Waiting for a value would deadlock
+    Deadlock,
+}
+
+impl<T> From<BoundedSendError<T>> for SyscallError {
+    fn from(e: BoundedSendError<T>) -> Self {
+        match e {
+            BoundedSendError::Closed(_) => SyscallError::BrokenPipe,
+            BoundedSendError::Deadlock(_) => SyscallError::Deadlock,
+        }
+    }
+}
+
+impl From<RecvError> for SyscallError {
+    fn from(e: RecvError) -> Self {
+        match e {
+            RecvError::Closed => SyscallError::BrokenPipe,
+            RecvError::Deadlock => SyscallError::Deadlock,
+        }
+    }
+}
+
+/// Record a bound half's process as waiting on the channel
+fn start_waiting(owner: Option<Pid>, channel: u64, holders: &[Pid]) -> Result<(), SyscallError> {
+    match owner {
+        Some(pid) => syscall::channel_wait(pid, channel, holders),
+        None => Ok(()),
+    }
+}
+
+/// Clear a bound half's process's wait on the channel, if it was waiting
+fn stop_waiting(owner: Option<Pid>, channel: u64) {
+    if let Some(pid) = owner {
+        syscall::channel_done(pid, channel);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -599,4 +727,54 @@ mod tests {
         tx.try_send(2).unwrap();
         assert_eq!(rx.try_recv(), Ok(2));
     }
+
+    #[test]
+    fn test_bounded_deadlock() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+
+        let (a, b) = KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            (k.spawn_process("a", None), k.spawn_process("b", None))
+        });
+        let mut cx = Context::from_waker(Waker::noop());
+
+        // a sends to b and b to a, over full channels
+        let (to_b, from_a) = bounded_channel::<i32>(1);
+        let (to_a, from_b) = bounded_channel::<i32>(1);
+        to_b.bind(a);
+        from_a.bind(b);
+        to_a.bind(b);
+        from_b.bind(a);
+        to_b.try_send(0).unwrap();
+        to_a.try_send(0).unwrap();
+
+        let mut send = to_b.send(1);
+        assert!(Pin::new(&mut send).poll(&mut cx).is_pending());
+        let mut send_back = to_a.send(2);
+        assert_eq!(
+            Pin::new(&mut send_back).poll(&mut cx),
+            Poll::Ready(Err(BoundedSendError::Deadlock(2)))
+        );
+        assert_eq!(
+            KERNEL.with(|k| k.borrow().deadlocks().len()),
+            1,
+            "deadlock not recorded"
+        );
+
+        // Once b drains its channel, a's send goes through
+        assert_eq!(from_a.try_recv(), Ok(0));
+        assert_eq!(Pin::new(&mut send).poll(&mut cx), Poll::Ready(Ok(())));
+        assert!(KERNEL.with(|k| k.borrow().waits().waiting_on(a).is_none()));
+
+        // Unbound halves aren't tracked
+        let (tx, rx) = bounded_channel::<i32>(1);
+        let mut recv = rx.recv();
+        assert!(Pin::new(&mut recv).poll(&mut cx).is_pending());
+        tx.close();
+        assert_eq!(
+            Pin::new(&mut recv).poll(&mut cx),
+            Poll::Ready(Err(RecvError::Closed))
+        );
+    }
 }
@@ -0,0 +1,228 @@
+//! Persistence of keyed message queues and semaphore sets
+//!
+//! A queue or semaphore set created with a key is how unrelated programs
+//! find each other, so it survives a reboot the way a file does. Before the
+//! VFS is snapshotted the keyed objects are written to [`STATE_PATH`], and
+//! restoring a snapshot recreates them. Keys, owners, modes, semaphore
+//! values and queued messages are kept; ids are assigned afresh. Private
+//! objects (negative keys) can't be found again after a reboot, so they
+//! aren't saved.
+
+use super::msgqueue::{Message, MsgQueueManager};
+use super::semaphore::SemaphoreManager;
+use crate::vfs::{FileSystem, MemoryFs, write_string};
+use serde::{Deserialize, Serialize};
+use std::io;
+
+/// Directory holding the IPC state
+pub const STATE_DIR: &str = "/var/lib/ipc";
+
+/// Keyed IPC objects, as JSON
+pub const STATE_PATH: &str = "/var/lib/ipc/state";
+
+/// A saved message queue
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueueState {
+    pub key: i32,
+    pub uid: u32,
+    pub gid: u32,
+    pub mode: u16,
+    pub max_bytes: usize,
+    /// (type, data) in queue order
+    pub messages: Vec<(i64, Vec<u8>)>,
+}
+
+/// A saved semaphore set
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SemaphoreState {
+    pub key: i32,
+    pub uid: u32,
+    pub gid: u32,
+    pub mode: u16,
+    pub values: Vec<i32>,
+}
+
+/// All keyed IPC objects
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct IpcState {
+    pub queues: Vec<QueueState>,
+    pub semaphores: Vec<SemaphoreState>,
+}
+
+impl IpcState {
+    /// Collect the keyed objects from the managers
+    pub fn capture(queues: &MsgQueueManager, semaphores: &SemaphoreManager) -> Self {
+        let queues = queues
+            .list()
+            .into_iter()
+            .filter_map(|id| queues.get(id))
+            .filter_map(|q| {
+                Some(QueueState {
+                    key: q.key?,
+                    uid: q.uid,
+                    gid: q.gid,
+                    mode: q.mode,
+                    max_bytes: q.stats().msg_qbytes,
+                    messages: q.messages().map(|m| (m.mtype, m.data.clone())).collect(),
+                })
+            })
+            .collect();
+
+        let semaphores = semaphores
+            .list()
+            .into_iter()
+            .filter_map(|id| semaphores.get_set(id))
+            .filter_map(|set| {
+                Some(SemaphoreState {
+                    key: set.key?,
+                    uid: set.uid,
+                    gid: set.gid,
+                    mode: set.mode,
+                    values: set.getall(),
+                })
+            })
+            .collect();
+
+        Self { queues, semaphores }
+    }
+
+    /// Check if there is nothing to save
+    pub fn is_empty(&self) -> bool {
+        self.queues.is_empty() && self.semaphores.is_empty()
+    }
+
+    /// Recreate the saved objects; keys already in use are left alone
+    pub fn apply(
+        &self,
+        queues: &mut MsgQueueManager,
+        semaphores: &mut SemaphoreManager,
+        now: f64,
+    ) -> io::Result<()> {
+        for q in &self.queues {
+            if q.key < 0 || queues.find_key(q.key).is_some() {
+                continue;
+            }
+            let id = queues
+                .msgget(q.key, q.uid, q.gid, true)
+                .map_err(|e| invalid(format!("queue {}: {:?}", q.key, e)))?;
+            queues
+                .msgctl_set(id, None, None, Some(q.mode), Some(q.max_bytes))
+                .map_err(|e| invalid(format!("queue {}: {:?}", q.key, e)))?;
+            for (mtype, data) in &q.messages {
+                queues
+                    .msgsnd(id, Message::new(*mtype, data.clone()), now)
+                    .map_err(|e| invalid(format!("queue {}: {:?}", q.key, e)))?;
+            }
+        }
+
+        for s in &self.semaphores {
+            if s.key < 0 || semaphores.find_key(s.key).is_some() {
+                continue;
+            }
+            let id = semaphores
+                .semget(s.key, s.values.len(), s.uid, s.gid, true, now)
+                .map_err(|e| invalid(format!("semaphore set {}: {:?}", s.key, e)))?;
+            semaphores
+                .semctl_set(id, None, None, Some(s.mode))
+                .and_then(|()| semaphores.semctl_setall(id, &s.values, 0, now))
+                .map_err(|e| invalid(format!("semaphore set {}: {:?}", s.key, e)))?;
+        }
+
+        Ok(())
+    }
+}
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// Write the keyed objects to [`STATE_PATH`], removing it when there are none
+pub fn save(
+    fs: &mut MemoryFs,
+    queues: &MsgQueueManager,
+    semaphores: &SemaphoreManager,
+) -> io::Result<()> {
+    let state = IpcState::capture(queues, semaphores);
+    if state.is_empty() {
+        if fs.exists(STATE_PATH) {
+            fs.remove_file(STATE_PATH)?;
+        }
+        return Ok(());
+    }
+
+    let json = serde_json::to_string(&state).map_err(|e| invalid(e.to_string()))?;
+    let mut dir = String::new();
+    for part in STATE_DIR.split('/').filter(|p| !p.is_empty()) {
+        dir.push('/');
+        dir.push_str(part);
+        if !fs.exists(&dir) {
+            fs.create_dir(&dir)?;
+        }
+    }
+    fs.chown(STATE_DIR, Some(0), Some(0))?;
+    write_string(fs, STATE_PATH, &json)?;
+    fs.chown(STATE_PATH, Some(0), Some(0))?;
+    fs.chmod(STATE_PATH, 0o600)
+}
+
+/// Recreate the objects saved in [`STATE_PATH`]; returns false if there is
+/// no saved state
+pub fn load(
+    fs: &mut MemoryFs,
+    queues: &mut MsgQueueManager,
+    semaphores: &mut SemaphoreManager,
+    now: f64,
+) -> io::Result<bool> {
+    if !fs.exists(STATE_PATH) {
+        return Ok(false);
+    }
+    let content = crate::vfs::read_to_string(fs, STATE_PATH)?;
+    let state: IpcState = serde_json::from_str(&content).map_err(|e| invalid(e.to_string()))?;
+    state.apply(queues, semaphores, now)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_and_load() {
+        let mut queues = MsgQueueManager::new();
+        let mut semaphores = SemaphoreManager::new();
+
+        let q = queues.msgget(42, 1000, 1000, true).unwrap();
+        queues.msgctl_set(q, None, None, Some(0o600), None).unwrap();
+        queues
+            .msgsnd(q, Message::new(2, b"job".to_vec()), 0.0)
+            .unwrap();
+        queues.msgget(-1, 1000, 1000, true).unwrap();
+        let s = semaphores.semget(7, 2, 0, 0, true, 0.0).unwrap();
+        semaphores.semctl_setall(s, &[3, 1], 0, 0.0).unwrap();
+
+        let mut fs = MemoryFs::new();
+        save(&mut fs, &queues, &semaphores).unwrap();
+
+        let mut queues = MsgQueueManager::new();
+        let mut semaphores = SemaphoreManager::new();
+        assert!(load(&mut fs, &mut queues, &mut semaphores, 5.0).unwrap());
+
+        // Only the keyed queue comes back
+        assert_eq!(queues.list().len(), 1);
+        let q = queues.find_key(42).unwrap();
+        let queue = queues.get(q).unwrap();
+        assert_eq!((queue.uid, queue.mode), (1000, 0o600));
+        let msg = queues.msgrcv(q, 0, 5.0).unwrap();
+        assert_eq!((msg.mtype, msg.data), (2, b"job".to_vec()));
+
+        let s = semaphores.find_key(7).unwrap();
+        assert_eq!(semaphores.semctl_getall(s).unwrap(), [3, 1]);
+
+        // Nothing left to save removes the file
+        queues.msgctl_rmid(q).unwrap();
+        semaphores.semctl_rmid(s).unwrap();
+        save(&mut fs, &queues, &semaphores).unwrap();
+        assert!(!fs.exists(STATE_PATH));
+        assert!(!load(&mut fs, &mut queues, &mut semaphores, 5.0).unwrap());
+    }
+}
@@ -0,0 +1,132 @@
+//! Kernel message log (what `dmesg` prints)
+//!
+//! Things the system wants an administrator to see later - a restore that
+//! fell back to a backup, a task that crashed, a service the watchdog had
+//! to restart - go here rather than only to the browser console. The log is
+//! a ring buffer of the last [`MAX_ENTRIES`] messages; it lives in memory
+//! and starts empty on every boot.
+
+use std::collections::VecDeque;
+use std::fmt;
+
+/// Messages kept before the oldest are dropped
+pub const MAX_ENTRIES: usize = 512;
+
+/// How serious a message is, most serious first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogLevel {
+    Err,
+    Warn,
+    Notice,
+    Info,
+}
+
+impl LogLevel {
+    pub fn name(self) -> &'static str {
+        match self {
+            LogLevel::Err => "err",
+            LogLevel::Warn => "warn",
+            LogLevel::Notice => "notice",
+            LogLevel::Info => "info",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "err" | "error" => Some(LogLevel::Err),
+            "warn" | "warning" => Some(LogLevel::Warn),
+            "notice" => Some(LogLevel::Notice),
+            "info" => Some(LogLevel::Info),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// One message
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogEntry {
+    /// Kernel time in milliseconds
+    pub time: f64,
+    pub level: LogLevel,
+    /// Who logged it, e.g. `persist` or `watchdog`
+    pub facility: String,
+    pub message: String,
+}
+
+impl LogEntry {
+    /// `[    1.500000] persist: message`, like dmesg
+    pub fn to_line(&self) -> String {
+        format!(
+            "[{:>12.6}] {}: {}",
+            self.time / 1000.0,
+            self.facility,
+            self.message
+        )
+    }
+}
+
+/// The kernel's ring buffer of messages
+#[derive(Debug, Default)]
+pub struct KernelLog {
+    entries: VecDeque<LogEntry>,
+}
+
+impl KernelLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a message, dropping the oldest when full
+    pub fn log(&mut self, time: f64, level: LogLevel, facility: &str, message: &str) {
+        if self.entries.len() >= MAX_ENTRIES {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(LogEntry {
+            time,
+            level,
+            facility: facility.to_string(),
+            message: message.to_string(),
+        });
+    }
+
+    /// All messages, oldest first
+    pub fn entries(&self) -> &VecDeque<LogEntry> {
+        &self.entries
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring_buffer() {
+        let mut log = KernelLog::new();
+        log.log(1500.0, LogLevel::Warn, "persist", "snapshot corrupt");
+        assert_eq!(
+            log.entries()[0].to_line(),
+            "[    1.500000] persist: snapshot corrupt"
+        );
+
+        for i in 0..MAX_ENTRIES {
+            log.log(i as f64, LogLevel::Info, "test", &i.to_string());
+        }
+        assert_eq!(log.entries().len(), MAX_ENTRIES);
+        assert_eq!(log.entries()[0].message, "0");
+
+        log.clear();
+        assert!(log.entries().is_empty());
+        assert_eq!(LogLevel::from_name("warning"), Some(LogLevel::Warn));
+        assert!(LogLevel::Err < LogLevel::Info);
+    }
+}
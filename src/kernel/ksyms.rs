@@ -0,0 +1,189 @@
+//! Kernel symbol map
+//!
+//! Maps the raw numbers the kernel records to names: syscall numbers,
+//! the WASM command ABI's export and import names, and executor task IDs.
+//! The tracer, profiler and debugger use it to print `sh (task 7)` rather
+//! than `7`, and `/proc/kallsyms` lists it for tooling.
+//!
+//! Syscalls and ABI names come from tables fixed at build time
+//! (`SyscallNr::ALL`, generated by `syscall_names!`, and the `abi`
+//! constants), so the map can't drift from the code. Tasks have no name of
+//! their own; a task is named after the process running on it when the map
+//! is taken (see `Kernel::ksyms`).
+
+use super::syscall::SyscallNr;
+use super::task::TaskId;
+use super::wasm::{exports, syscalls};
+
+/// What a symbol names
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SymbolKind {
+    /// A syscall; the value is its number
+    Syscall,
+    /// An export the kernel looks for in a WASM command; the value is its
+    /// index in the ABI
+    Export,
+    /// A syscall import a WASM command may use; the value is the number of
+    /// the syscall it runs
+    Import,
+    /// An executor task; the value is its ID
+    Task,
+}
+
+impl SymbolKind {
+    /// Type letter shown in `/proc/kallsyms`
+    pub fn letter(self) -> char {
+        match self {
+            Self::Syscall => 's',
+            Self::Export => 'e',
+            Self::Import => 'i',
+            Self::Task => 't',
+        }
+    }
+}
+
+/// One named value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub kind: SymbolKind,
+    pub value: u64,
+    pub name: String,
+}
+
+/// Symbols sorted by kind, then value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolMap {
+    symbols: Vec<Symbol>,
+}
+
+impl SymbolMap {
+    /// The build-time symbols: syscalls and WASM ABI names, with no tasks
+    pub fn new() -> Self {
+        let mut map = Self {
+            symbols: Vec::new(),
+        };
+        for nr in SyscallNr::ALL {
+            map.insert(SymbolKind::Syscall, nr.num() as u64, nr.name());
+        }
+        for (index, name) in exports::ALL.iter().enumerate() {
+            map.insert(SymbolKind::Export, index as u64, name);
+        }
+        for name in syscalls::ALL {
+            if let Some(nr) = SyscallNr::from_name(name) {
+                map.insert(SymbolKind::Import, nr.num() as u64, name);
+            }
+        }
+        map
+    }
+
+    /// Add a symbol, or rename the one with the same kind and value
+    pub fn insert(&mut self, kind: SymbolKind, value: u64, name: &str) {
+        let key = (kind, value);
+        match self
+            .symbols
+            .binary_search_by(|s| (s.kind, s.value).cmp(&key))
+        {
+            Ok(i) => self.symbols[i].name = name.to_string(),
+            Err(i) => self.symbols.insert(
+                i,
+                Symbol {
+                    kind,
+                    value,
+                    name: name.to_string(),
+                },
+            ),
+        }
+    }
+
+    /// Name `task`
+    pub fn add_task(&mut self, task: TaskId, name: &str) {
+        self.insert(SymbolKind::Task, task.0, name);
+    }
+
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    /// Name of the symbol with this kind and value
+    pub fn name(&self, kind: SymbolKind, value: u64) -> Option<&str> {
+        let key = (kind, value);
+        self.symbols
+            .binary_search_by(|s| (s.kind, s.value).cmp(&key))
+            .ok()
+            .map(|i| self.symbols[i].name.as_str())
+    }
+
+    /// Value of the symbol of this kind called `name`
+    pub fn value(&self, kind: SymbolKind, name: &str) -> Option<u64> {
+        self.symbols
+            .iter()
+            .find(|s| s.kind == kind && s.name == name)
+            .map(|s| s.value)
+    }
+
+    /// Name of syscall number `nr`
+    pub fn syscall_name(&self, nr: u32) -> Option<&str> {
+        self.name(SymbolKind::Syscall, nr as u64)
+    }
+
+    /// Name of the process running on `task`
+    pub fn task_name(&self, task: TaskId) -> Option<&str> {
+        self.name(SymbolKind::Task, task.0)
+    }
+
+    /// `task` for display: `name (task N)`, or `task N` if it is unnamed
+    pub fn task_label(&self, task: TaskId) -> String {
+        match self.task_name(task) {
+            Some(name) => format!("{} (task {})", name, task.0),
+            None => format!("task {}", task.0),
+        }
+    }
+
+    /// The map as `/proc/kallsyms` shows it: `VALUE TYPE NAME` per line,
+    /// the value in hex
+    pub fn render(&self) -> String {
+        self.symbols
+            .iter()
+            .map(|s| format!("{:08x} {} {}\n", s.value, s.kind.letter(), s.name))
+            .collect()
+    }
+}
+
+impl Default for SymbolMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_symbol_map() {
+        let mut map = SymbolMap::new();
+        assert_eq!(map.syscall_name(SyscallNr::Open.num()), Some("open"));
+        assert_eq!(map.syscall_name(9999), None);
+        assert_eq!(
+            map.value(SymbolKind::Syscall, "getpid"),
+            Some(SyscallNr::Getpid.num() as u64)
+        );
+        assert_eq!(map.name(SymbolKind::Export, 1), Some(exports::MAIN));
+        // Every ABI import runs a kernel syscall of the same name
+        for name in syscalls::ALL {
+            let nr = map.value(SymbolKind::Import, name).unwrap();
+            assert_eq!(map.syscall_name(nr as u32), Some(*name));
+        }
+
+        assert_eq!(map.task_label(TaskId(7)), "task 7");
+        map.add_task(TaskId(7), "sh");
+        map.add_task(TaskId(3), "init");
+        map.add_task(TaskId(7), "cat");
+        assert_eq!(map.task_label(TaskId(7)), "cat (task 7)");
+
+        let text = map.render();
+        assert!(text.starts_with(&format!("{:08x} s read\n", SyscallNr::Read.num())));
+        assert!(text.ends_with("00000003 t init\n00000007 t cat\n"));
+        assert!(text.contains("00000000 e memory\n"));
+    }
+}
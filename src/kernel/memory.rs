@@ -581,6 +581,12 @@ impl ProcessMemory {
         self.peak
     }
 
+    /// Start peak tracking over from what is allocated now, returning
+    /// the old peak
+    pub fn reset_peak(&mut self) -> usize {
+        std::mem::replace(&mut self.peak, self.allocated)
+    }
+
     pub fn region_count(&self) -> usize {
         self.regions.len()
     }
@@ -11,27 +11,49 @@
 //! - KernelObject: file, pipe, console, window, etc.
 //! - Syscall: the interface between user code and the kernel
 
+pub mod aes;
+pub mod calendar;
+pub mod crash;
+pub mod crypto;
+pub mod deadlock;
 pub mod debugger;
 pub mod devfs;
+pub mod epoll;
 pub mod events;
 pub mod executor;
 pub mod fifo;
 pub mod flock;
+pub mod guest;
+pub mod httpcache;
 pub mod init;
 pub mod ipc;
+pub mod ipc_persist;
+pub mod klog;
+pub mod ksyms;
 pub mod memory;
 pub mod memory_persist;
 pub mod mount;
 pub mod msgqueue;
+pub mod netperm;
+pub mod ninep;
 pub mod object;
 pub mod pkg;
+pub mod poll;
+pub mod power;
+pub mod pressure;
 pub mod process;
 pub mod procfs;
 pub mod profiler;
+pub mod ptrace;
+pub mod random;
+pub mod ratelimit;
 pub mod semaphore;
+pub mod session;
 pub mod signal;
+pub mod synthfs;
 pub mod syscall;
 pub mod sysfs;
+pub mod system;
 pub mod task;
 pub mod timer;
 pub mod trace;
@@ -48,20 +70,27 @@ pub mod network;
 #[cfg(test)]
 mod invariants_test;
 
+pub use crash::{CrashReport, FAULT_STATUS};
+pub use deadlock::{DeadlockReport, WaitForGraph, WaitResource};
 pub use debugger::{
     Breakpoint, BreakpointAction, BreakpointCondition, BreakpointId, DebugMode, DebugTarget,
-    DebuggerStatus, MemoryView, MemoryWatch, SyscallArg, SyscallRecord, WasmDebugger, WatchType,
+    DebuggerStatus, MemoryView, MemoryWatch, PathOp, PathWatch, PathWatchHit, SyscallArg,
+    SyscallRecord, WasmDebugger, WatchType,
 };
-pub use executor::{Executor, Priority};
+pub use epoll::{EpollCtl, EpollEvent, EventQueue, EventSource, Interest};
+pub use executor::{Executor, PollSpan, Priority, Recurring, TaskFault};
 pub use fifo::{FifoBuffer, FifoError, FifoRegistry};
 pub use flock::{FileLockManager, LockError, LockType, RangeLock};
+pub use guest::GuestSession;
 pub use init::{
     InitSystem, RestartPolicy, Service, ServiceConfig, ServiceState, ServiceStatus, Target,
 };
 pub use ipc::{
-    BoundedReceiver, BoundedRecvFuture, BoundedSendFuture, BoundedSender, Receiver, SendError,
-    Sender, TryRecvError, TrySendError, bounded_channel, channel,
+    BoundedReceiver, BoundedRecvFuture, BoundedSendError, BoundedSendFuture, BoundedSender,
+    Receiver, RecvError, SendError, Sender, TryRecvError, TrySendError, bounded_channel, channel,
 };
+pub use klog::{KernelLog, LogEntry, LogLevel};
+pub use ksyms::{Symbol, SymbolKind, SymbolMap};
 pub use memory::{
     CowStats, MemoryError, MemoryStats, PAGE_SIZE, ProcessCowStats, Protection, RegionId, ShmId,
     ShmInfo, SystemMemoryStats,
@@ -76,30 +105,40 @@ pub use pkg::{
     PackageManager, PackageManifest, PackageRegistry, PkgError, PkgResult, RegistryEntry,
     ResolvedPackage, Version, VersionReq,
 };
+pub use poll::{PollEvents, PollFd, PollSource};
+pub use power::{Governor, PowerGovernor, PowerMode};
+pub use pressure::{MemorySample, PressureLevel, PressureMonitor};
 pub use process::{Fd, Handle, OpenFlags, Pid};
 pub use profiler::{
     AllocationEvent, AllocationSizeDistribution, CpuProfile, FlameGraphBuilder, FlameNode,
     MemoryProfile, MemorySnapshot, ProcessMemorySnapshot, ProfileSummary, Profiler, ProfilerState,
     SyscallProfile, TaskSample, TaskSampleState,
 };
+pub use ptrace::{PtraceError, PtraceEvent, PtraceOptions, PtraceTable, SyscallFilter};
+pub use ratelimit::RateLimiter;
 pub use semaphore::{
     SemAdj, SemError, SemId, SemOpResult, SemSetStats, SemaphoreManager, SemaphoreSet,
 };
+pub use session::{LockInfo, LockReason, SessionLock, UnlockError};
 pub use signal::{Signal, SignalAction, SignalError};
 pub use syscall::{SyscallError, SyscallResult};
+pub use system::{Slot, SlotState, SystemBoot, SystemImage};
 pub use task::{Task, TaskId, TaskState};
 pub use timer::TimerId;
 pub use trace::{TraceCategory, TraceEvent, TraceSummary, Tracer};
-pub use tty::{Termios, Tty, TtyManager};
+pub use tty::{
+    MouseAction, MouseButton, MouseEvent, MouseMode, MouseTracking, Termios, Tty, TtyManager,
+};
 pub use uds::{
     SockAddr, SocketError, SocketId, SocketResult, SocketState, SocketType, UnixSocket,
     UnixSocketManager,
 };
-pub use users::{FileMode, Gid, Group, Uid, User, UserDb};
+pub use users::{FileMode, Gid, Group, Uid, User, UserChange, UserDb};
 pub use visualizer::{
-    KernelSnapshot, MemoryRegionType, MemoryRegionView, ProcessMemoryLayout, ProcessTree,
-    ProcessTreeNode, ResourceDashboard, ResourceLimitView, SchedulerView, SyscallActivity,
-    SyscallMonitor, SystemMemoryView, TaskView, TaskViewState,
+    FdTableView, KernelSnapshot, LeakReport, MemoryRegionType, MemoryRegionView, MountView,
+    ObjectSnapshot, ObjectView, ProcessMemoryLayout, ProcessTree, ProcessTreeNode,
+    ResourceDashboard, ResourceLimitView, SchedulerView, SyscallActivity, SyscallMonitor,
+    SystemMemoryView, TaskView, TaskViewState,
 };
 pub use work_stealing::{
     Config as WorkStealingConfig, Injector, StealResult, Stealer, TaskHandle, WorkStealingExecutor,
@@ -130,14 +169,59 @@ where
     EXECUTOR.with(|e| e.borrow_mut().spawn_with_priority(future, priority))
 }
 
+/// Call `job` in every tick with nothing more urgent to do, as a
+/// [`Priority::Idle`] task; returns the task ID, to cancel it
+pub fn spawn_idle<J>(job: J) -> TaskId
+where
+    J: FnMut() + Unpin + 'static,
+{
+    spawn_with_priority(Recurring::new(job), Priority::Idle)
+}
+
 /// Run one tick of execution (call from requestAnimationFrame)
+///
+/// With a clock set, each tick is recorded in the tracer as a frame, along
+/// with the poll spans of the tasks it ran. Tasks that panicked are handed
+/// to the kernel, which ends their processes and writes crash reports.
 pub fn tick() -> usize {
-    EXECUTOR.with(|e| e.borrow_mut().tick())
+    let clock = EXECUTOR.with(|e| e.borrow().clock());
+    let start = clock.map(|clock| clock());
+    let (polled, spans, faults) = EXECUTOR.with(|e| {
+        let mut executor = e.borrow_mut();
+        let polled = executor.tick();
+        (polled, executor.take_poll_spans(), executor.take_faults())
+    });
+    if let (Some(clock), Some(start)) = (clock, start) {
+        syscall::trace_frame(start, clock(), &spans);
+    }
+    if !faults.is_empty() {
+        syscall::task_faults(&faults);
+    }
+    polled
+}
+
+/// Wake tasks, e.g. those whose timers fired in [`syscall::tick_timers`]
+pub fn wake(tasks: &[TaskId]) {
+    EXECUTOR.with(|e| e.borrow().wake_tasks(tasks));
+}
+
+/// Set the high-resolution clock (e.g. `performance.now`) used to time
+/// frames, task polls and syscalls for the tracer
+pub fn set_clock(clock: Option<fn() -> f64>) {
+    EXECUTOR.with(|e| e.borrow_mut().set_clock(clock));
+    syscall::KERNEL.with(|k| k.borrow_mut().tracer_mut().set_clock(clock));
 }
 
 /// Run the executor until all tasks complete (for non-UI contexts)
 pub fn run() {
-    EXECUTOR.with(|e| e.borrow_mut().run())
+    let faults = EXECUTOR.with(|e| {
+        let mut executor = e.borrow_mut();
+        executor.run();
+        executor.take_faults()
+    });
+    if !faults.is_empty() {
+        syscall::task_faults(&faults);
+    }
 }
 
 /// Check if there are active tasks
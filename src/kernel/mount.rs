@@ -3,7 +3,14 @@
 //! Provides mount/umount operations and tracks mounted filesystems.
 //! In this WASM environment, we support virtual filesystem mounts
 //! like /proc, /sys, /dev, and tmpfs.
+//!
+//! Most entries are bookkeeping over the root `MemoryFs` or one of the
+//! synthetic filesystems. A mount made with [`MountTable::attach`] brings
+//! its own [`FileSystem`] (an overlay, say), and the kernel hands every
+//! path below the mount point to it; see [`MountTable::filesystem_for`].
 
+use crate::vfs::FileSystem;
+use std::borrow::Cow;
 use std::collections::HashMap;
 
 /// Filesystem type
@@ -19,6 +26,14 @@ pub enum FsType {
     Tmpfs,
     /// Memory filesystem (our main VFS)
     MemoryFs,
+    /// Writable layer over a read-only lower filesystem
+    Overlay,
+    /// Read-only view of a tar archive
+    Tar,
+    /// Vault kept encrypted in a file (see [`crate::vfs::encrypted`])
+    Encrypted,
+    /// Remote share (see [`crate::vfs::webdav`])
+    WebDav,
     /// Unknown/custom filesystem
     Other(String),
 }
@@ -31,6 +46,10 @@ impl FsType {
             "devfs" | "devtmpfs" => FsType::Devfs,
             "tmpfs" => FsType::Tmpfs,
             "memoryfs" | "ramfs" => FsType::MemoryFs,
+            "overlay" | "overlayfs" => FsType::Overlay,
+            "tar" | "tarfs" => FsType::Tar,
+            "encrypted" | "vault" => FsType::Encrypted,
+            "webdav" | "davfs" => FsType::WebDav,
             other => FsType::Other(other.to_string()),
         }
     }
@@ -42,9 +61,19 @@ impl FsType {
             FsType::Devfs => "devfs",
             FsType::Tmpfs => "tmpfs",
             FsType::MemoryFs => "memoryfs",
+            FsType::Overlay => "overlay",
+            FsType::Tar => "tar",
+            FsType::Encrypted => "encrypted",
+            FsType::WebDav => "webdav",
             FsType::Other(s) => s,
         }
     }
+
+    /// Whether the `casefold` option applies: only mounts served by the
+    /// main `MemoryFs` can look paths up regardless of case
+    pub fn folds_case(&self) -> bool {
+        matches!(self, FsType::MemoryFs | FsType::Tmpfs)
+    }
 }
 
 /// Mount options
@@ -62,6 +91,15 @@ pub struct MountOptions {
     pub nodev: bool,
     /// Size limit for tmpfs (in bytes, 0 = no limit)
     pub size_limit: usize,
+    /// Write saves through the persistence journal first, so an
+    /// interrupted save is replayed at boot (only meaningful on `/`)
+    pub journal: bool,
+    /// Deflate saved snapshots (only meaningful on `/`, see
+    /// [`crate::vfs::compress`])
+    pub compress: bool,
+    /// Look paths up regardless of case, keeping the case names were
+    /// created with (only for mounts served by the main `MemoryFs`)
+    pub casefold: bool,
 }
 
 impl MountOptions {
@@ -72,24 +110,40 @@ impl MountOptions {
     /// Parse mount options from a comma-separated string
     pub fn parse(options: &str) -> Self {
         let mut opts = MountOptions::new();
+        opts.apply(options);
+        opts
+    }
+
+    /// Change the options named in a comma-separated string, keeping the
+    /// others, as a remount does
+    pub fn apply(&mut self, options: &str) {
         for opt in options.split(',') {
             let opt = opt.trim();
             match opt {
-                "ro" | "readonly" => opts.read_only = true,
-                "rw" | "readwrite" => opts.read_only = false,
-                "noatime" => opts.noatime = true,
-                "noexec" => opts.noexec = true,
-                "nosuid" => opts.nosuid = true,
-                "nodev" => opts.nodev = true,
+                "ro" | "readonly" => self.read_only = true,
+                "rw" | "readwrite" => self.read_only = false,
+                "noatime" => self.noatime = true,
+                "atime" => self.noatime = false,
+                "noexec" => self.noexec = true,
+                "exec" => self.noexec = false,
+                "nosuid" => self.nosuid = true,
+                "suid" => self.nosuid = false,
+                "nodev" => self.nodev = true,
+                "dev" => self.nodev = false,
+                "journal" => self.journal = true,
+                "nojournal" => self.journal = false,
+                "compress" => self.compress = true,
+                "nocompress" => self.compress = false,
+                "casefold" => self.casefold = true,
+                "nocasefold" => self.casefold = false,
                 s if s.starts_with("size=") => {
                     if let Ok(size) = parse_size(&s[5..]) {
-                        opts.size_limit = size;
+                        self.size_limit = size;
                     }
                 }
                 _ => {} // Unknown options ignored
             }
         }
-        opts
     }
 }
 
@@ -113,6 +167,15 @@ impl std::fmt::Display for MountOptions {
         if self.nodev {
             parts.push("nodev");
         }
+        if self.journal {
+            parts.push("journal");
+        }
+        if self.compress {
+            parts.push("compress");
+        }
+        if self.casefold {
+            parts.push("casefold");
+        }
         if self.size_limit > 0 {
             return write!(f, "{},size={}", parts.join(","), self.size_limit);
         }
@@ -121,7 +184,7 @@ impl std::fmt::Display for MountOptions {
 }
 
 /// Parse size string (e.g., "1G", "512M", "1024K", "4096")
-fn parse_size(s: &str) -> Result<usize, ()> {
+pub(crate) fn parse_size(s: &str) -> Result<usize, ()> {
     let s = s.trim();
     if s.is_empty() {
         return Err(());
@@ -246,12 +309,15 @@ pub enum MountError {
 pub struct MountTable {
     /// Active mounts (target path -> entry)
     mounts: HashMap<String, MountEntry>,
+    /// Filesystems serving attached mounts (target path -> filesystem)
+    attached: HashMap<String, Box<dyn FileSystem>>,
 }
 
 impl MountTable {
     pub fn new() -> Self {
         Self {
             mounts: HashMap::new(),
+            attached: HashMap::new(),
         }
     }
 
@@ -259,8 +325,18 @@ impl MountTable {
     pub fn with_defaults(now: f64) -> Self {
         let mut table = Self::new();
 
-        // Root filesystem
-        let _ = table.mount("rootfs", "/", FsType::MemoryFs, MountOptions::new(), now);
+        // Root filesystem, the one that is saved
+        let _ = table.mount(
+            "rootfs",
+            "/",
+            FsType::MemoryFs,
+            MountOptions {
+                journal: true,
+                compress: true,
+                ..Default::default()
+            },
+            now,
+        );
 
         // Virtual filesystems
         let _ = table.mount(
@@ -302,40 +378,120 @@ impl MountTable {
         now: f64,
     ) -> Result<(), MountError> {
         // Normalize target path
-        let target = normalize_path(target);
+        let target = normalize_path(target).into_owned();
 
         // Check if already mounted
         if self.mounts.contains_key(&target) {
             return Err(MountError::AlreadyMounted);
         }
+        if options.casefold && !fstype.folds_case() {
+            return Err(MountError::InvalidOptions);
+        }
 
         let entry = MountEntry::new(source, &target, fstype, options, now);
         self.mounts.insert(target, entry);
         Ok(())
     }
 
+    /// Mount `fs` at `target`; paths below it are served by `fs`
+    pub fn attach(
+        &mut self,
+        source: &str,
+        target: &str,
+        fstype: FsType,
+        options: MountOptions,
+        fs: Box<dyn FileSystem>,
+        now: f64,
+    ) -> Result<(), MountError> {
+        let target = normalize_path(target).into_owned();
+        if target == "/" {
+            return Err(MountError::Busy);
+        }
+        self.mount(source, &target, fstype, options, now)?;
+        self.attached.insert(target, fs);
+        Ok(())
+    }
+
     /// Unmount a filesystem
+    ///
+    /// An attached filesystem is dropped along with its entry.
     pub fn umount(&mut self, target: &str) -> Result<MountEntry, MountError> {
-        let target = normalize_path(target);
+        let target = normalize_path(target).into_owned();
 
         // Can't unmount root
         if target == "/" {
             return Err(MountError::Busy);
         }
 
-        self.mounts.remove(&target).ok_or(MountError::NotMounted)
+        let entry = self.mounts.remove(&target).ok_or(MountError::NotMounted)?;
+        self.attached.remove(&target);
+        Ok(entry)
+    }
+
+    /// Check if the mount at `target` has its own filesystem
+    pub fn is_attached(&self, target: &str) -> bool {
+        self.attached.contains_key(normalize_path(target).as_ref())
+    }
+
+    /// The attached mount point that contains `path`, by longest match
+    pub fn attached_mount(&self, path: &str) -> Option<&str> {
+        self.attached
+            .keys()
+            .filter(|m| {
+                path.strip_prefix(m.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+            .max_by_key(|m| m.len())
+            .map(String::as_str)
+    }
+
+    /// The attached filesystem serving `path` (absolute and resolved), and
+    /// the path within it; `None` if the path isn't under one
+    pub fn filesystem_for(&self, path: &str) -> Option<(&dyn FileSystem, String)> {
+        let mount = self.attached_mount(path)?;
+        let inner = format!("/{}", path[mount.len()..].trim_start_matches('/'));
+        Some((self.attached.get(mount)?.as_ref(), inner))
+    }
+
+    /// Mutable version of [`MountTable::filesystem_for`]
+    pub fn filesystem_for_mut(&mut self, path: &str) -> Option<(&mut dyn FileSystem, String)> {
+        let mount = self.attached_mount(path)?.to_string();
+        let inner = format!("/{}", path[mount.len()..].trim_start_matches('/'));
+        Some((self.attached.get_mut(&mount)?.as_mut(), inner))
+    }
+
+    /// Change some options of the mount at `target`, keeping the rest
+    pub fn remount(&mut self, target: &str, options: &str) -> Result<(), MountError> {
+        let entry = self
+            .mounts
+            .get_mut(normalize_path(target).as_ref())
+            .ok_or(MountError::NotMounted)?;
+        let mut changed = entry.options.clone();
+        changed.apply(options);
+        if changed.casefold && !entry.fstype.folds_case() {
+            return Err(MountError::InvalidOptions);
+        }
+        entry.options = changed;
+        Ok(())
+    }
+
+    /// Put `entry` in place of whatever is mounted at its target (even
+    /// the root), returning the entry it replaced
+    pub fn replace(&mut self, entry: MountEntry) -> Option<MountEntry> {
+        self.mounts
+            .insert(normalize_path(&entry.target).into_owned(), entry)
     }
 
     /// Check if a path is a mount point
     pub fn is_mount_point(&self, path: &str) -> bool {
         let path = normalize_path(path);
-        self.mounts.contains_key(&path)
+        self.mounts.contains_key(path.as_ref())
     }
 
     /// Get mount entry for a path
     pub fn get_mount(&self, path: &str) -> Option<&MountEntry> {
         let path = normalize_path(path);
-        self.mounts.get(&path)
+        self.mounts.get(path.as_ref())
     }
 
     /// Get the mount entry that contains a given path
@@ -352,7 +508,8 @@ impl MountTable {
                 true
             } else {
                 // Non-root: exact match or path starts with mount_point/
-                path == *mount_point || path.starts_with(&format!("{}/", mount_point))
+                path.strip_prefix(mount_point.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
             };
 
             if matches {
@@ -396,23 +553,18 @@ impl Default for MountTable {
 }
 
 /// Normalize a path (remove trailing slash, handle . and ..)
-fn normalize_path(path: &str) -> String {
-    let path = path.trim();
-    if path.is_empty() {
-        return "/".to_string();
-    }
-
+fn normalize_path(path: &str) -> Cow<'_, str> {
     // Remove trailing slashes (except for root)
-    let path = path.trim_end_matches('/');
+    let path = path.trim().trim_end_matches('/');
     if path.is_empty() {
-        return "/".to_string();
+        return Cow::Borrowed("/");
     }
 
     // Ensure leading slash
     if !path.starts_with('/') {
-        format!("/{}", path)
+        Cow::Owned(format!("/{}", path))
     } else {
-        path.to_string()
+        Cow::Borrowed(path)
     }
 }
 
@@ -486,11 +638,61 @@ mod tests {
         assert_eq!(opts.size_limit, 1024 * 1024 * 1024);
     }
 
+    #[test]
+    fn test_remount_journal() {
+        let mut table = MountTable::with_defaults(0.0);
+        let root = |t: &MountTable| t.get_mount("/").unwrap().options.clone();
+        assert!(root(&table).journal);
+        assert_eq!(root(&table).to_string(), "rw,journal,compress");
+
+        // Only the options named change
+        table.remount("/", "nojournal,noatime").unwrap();
+        assert!(!root(&table).journal);
+        assert!(root(&table).noatime);
+        table.remount("/", "journal").unwrap();
+        assert!(root(&table).journal && root(&table).noatime);
+
+        table.remount("/", "nocompress").unwrap();
+        assert!(!root(&table).compress);
+        assert_eq!(root(&table).to_string(), "rw,noatime,journal");
+
+        assert_eq!(table.remount("/mnt", "ro"), Err(MountError::NotMounted));
+    }
+
+    #[test]
+    fn test_casefold_option() {
+        let opts = MountOptions::parse("casefold,noatime");
+        assert!(opts.casefold);
+        assert_eq!(opts.to_string(), "rw,noatime,casefold");
+
+        let mut table = MountTable::with_defaults(0.0);
+        table
+            .mount("tmpfs", "/mnt/shared", FsType::Tmpfs, opts, 0.0)
+            .unwrap();
+        table.remount("/mnt/shared", "nocasefold").unwrap();
+        assert!(!table.get_mount("/mnt/shared").unwrap().options.casefold);
+
+        // Filesystems other than the main MemoryFs don't fold case
+        let err = table.mount(
+            "x",
+            "/mnt/tar",
+            FsType::Tar,
+            MountOptions::parse("casefold"),
+            0.0,
+        );
+        assert_eq!(err, Err(MountError::InvalidOptions));
+        assert_eq!(
+            table.remount("/proc", "casefold"),
+            Err(MountError::InvalidOptions)
+        );
+    }
+
     #[test]
     fn test_fstype_parse() {
         assert_eq!(FsType::parse("proc"), FsType::Proc);
         assert_eq!(FsType::parse("SYSFS"), FsType::Sysfs);
         assert_eq!(FsType::parse("tmpfs"), FsType::Tmpfs);
+        assert_eq!(FsType::parse("encrypted"), FsType::Encrypted);
         assert_eq!(FsType::parse("ext4"), FsType::Other("ext4".to_string()));
     }
 
@@ -527,6 +729,37 @@ mod tests {
         assert_eq!(parse_size("1G"), Ok(1024 * 1024 * 1024));
     }
 
+    #[test]
+    fn test_attached_filesystems() {
+        use crate::vfs::{MemoryFs, write_string};
+
+        let mut fs = MemoryFs::new();
+        write_string(&mut fs, "/hello", "hi").unwrap();
+        let mut table = MountTable::with_defaults(0.0);
+        table
+            .attach(
+                "none",
+                "/mnt/",
+                FsType::MemoryFs,
+                MountOptions::new(),
+                Box::new(fs),
+                1.0,
+            )
+            .unwrap();
+        assert!(table.is_attached("/mnt"));
+        assert!(!table.is_attached("/tmp"));
+
+        let (fs, inner) = table.filesystem_for("/mnt/hello").unwrap();
+        assert_eq!(inner, "/hello");
+        assert!(fs.exists(&inner));
+        assert_eq!(table.filesystem_for("/mnt").unwrap().1, "/");
+        assert!(table.filesystem_for("/mntx/hello").is_none());
+        assert!(table.filesystem_for("/tmp").is_none());
+
+        table.umount("/mnt").unwrap();
+        assert!(table.filesystem_for("/mnt/hello").is_none());
+    }
+
     #[test]
     fn test_cant_umount_root() {
         let mut table = MountTable::with_defaults(0.0);
@@ -25,6 +25,8 @@ impl Message {
 pub struct MessageQueue {
     /// Queue ID
     pub id: MsgQueueId,
+    /// Key the queue was created with (None for private queues)
+    pub key: Option<i32>,
     /// Messages in the queue
     messages: VecDeque<Message>,
     /// Maximum number of bytes in queue
@@ -51,6 +53,7 @@ impl MessageQueue {
     pub fn new(id: MsgQueueId, uid: u32, gid: u32) -> Self {
         Self {
             id,
+            key: None,
             messages: VecDeque::new(),
             max_bytes: 16384, // 16KB default
             current_bytes: 0,
@@ -127,6 +130,11 @@ impl MessageQueue {
         }
     }
 
+    /// Messages in queue order
+    pub fn messages(&self) -> impl Iterator<Item = &Message> {
+        self.messages.iter()
+    }
+
     /// Get queue stats
     pub fn stats(&self) -> MsgQueueStats {
         MsgQueueStats {
@@ -237,7 +245,8 @@ impl MsgQueueManager {
         // Create new
         let id = MsgQueueId(self.next_id);
         self.next_id += 1;
-        let queue = MessageQueue::new(id, uid, gid);
+        let mut queue = MessageQueue::new(id, uid, gid);
+        queue.key = Some(key);
         self.queues.insert(id, queue);
         self.key_map.insert(key, id);
         Ok(id)
@@ -309,9 +318,16 @@ impl MsgQueueManager {
         self.queues.get(&id)
     }
 
-    /// List all queue IDs
+    /// Queue created with `key`, if any
+    pub fn find_key(&self, key: i32) -> Option<MsgQueueId> {
+        self.key_map.get(&key).copied()
+    }
+
+    /// List all queue IDs, in ascending order
     pub fn list(&self) -> Vec<MsgQueueId> {
-        self.queues.keys().copied().collect()
+        let mut ids: Vec<_> = self.queues.keys().copied().collect();
+        ids.sort_by_key(|id| id.0);
+        ids
     }
 }
 
@@ -0,0 +1,347 @@
+//! Network permissions
+//!
+//! Outbound connections (fetches, WebSockets, 9P exports) may only go to an
+//! origin allowed in [`ALLOWLIST_PATH`]. The first request to an origin the
+//! list doesn't mention waits while the user is asked, by a notice at the
+//! prompt, to decide with `netperm allow` or `netperm deny`; if nobody
+//! answers within [`PROMPT_TIMEOUT`] it is refused, and the next request
+//! asks again. Relative URLs go to the page's own origin and need no
+//! permission.
+//!
+//! The list is root's, so a package running as an ordinary user can't let
+//! itself out: it can only make the user be asked.
+//!
+//! ```text
+//! # Network permissions (see netperm)
+//! allow https://pkg.axeberg.dev
+//! deny http://tracker.example
+//! ```
+
+use std::collections::{BTreeMap, VecDeque};
+use std::task::Waker;
+
+/// Where decisions are kept
+pub const ALLOWLIST_PATH: &str = "/etc/net/allowlist";
+
+/// How long a request waits for a decision, in milliseconds of kernel time
+pub const PROMPT_TIMEOUT: f64 = 60_000.0;
+
+/// What the user decided for an origin
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetDecision {
+    Allow,
+    Deny,
+}
+
+impl NetDecision {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Allow => "allow",
+            Self::Deny => "deny",
+        }
+    }
+
+    pub fn parse(word: &str) -> Option<Self> {
+        match word {
+            "allow" => Some(Self::Allow),
+            "deny" => Some(Self::Deny),
+            _ => None,
+        }
+    }
+}
+
+/// The origin a request to `url` goes to: scheme, host and any port that
+/// isn't the scheme's default, lowercased, with WebSockets counted as the
+/// HTTP they start as
+///
+/// `None` for a relative URL, which stays on the page's own origin, and
+/// for one without a host.
+pub fn origin(url: &str) -> Option<String> {
+    let url = clean(url);
+    let (scheme, rest) = url.split_once("://")?;
+    let scheme = match scheme.to_ascii_lowercase().as_str() {
+        "ws" => "http".to_string(),
+        "wss" => "https".to_string(),
+        other => other.to_string(),
+    };
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or("");
+    let host = authority
+        .rsplit('@')
+        .next()
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    let host = match (scheme.as_str(), host.rsplit_once(':')) {
+        ("http", Some((name, "80"))) | ("https", Some((name, "443"))) => name.to_string(),
+        _ => host,
+    };
+    if host.is_empty() || host.starts_with(':') {
+        return None;
+    }
+    Some(format!("{}://{}", scheme, host))
+}
+
+/// Whether `url` is a path on the page's own origin: it has no scheme and
+/// isn't protocol-relative (`//host/...`), which the browser would send to
+/// another host
+pub fn is_relative(url: &str) -> bool {
+    let url = clean(url);
+    let slash = |c: char| c == '/' || c == '\\';
+    let mut chars = url.chars();
+    let protocol_relative = chars.next().is_some_and(slash) && chars.next().is_some_and(slash);
+    let scheme_end = url.find(['/', '\\', '?', '#']).unwrap_or(url.len());
+    !protocol_relative && !url[..scheme_end].contains(':')
+}
+
+/// `url` as the browser reads it: without surrounding spaces and control
+/// characters, or tabs and line breaks anywhere
+fn clean(url: &str) -> String {
+    url.trim_matches(|c: char| c <= ' ')
+        .chars()
+        .filter(|c| !matches!(c, '\t' | '\n' | '\r'))
+        .collect()
+}
+
+/// `/etc/net/allowlist`: a decision per origin
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Allowlist {
+    entries: BTreeMap<String, NetDecision>,
+}
+
+impl Allowlist {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read the list from its file, skipping comments and lines that
+    /// don't parse
+    pub fn parse(content: &str) -> Self {
+        let mut list = Self::new();
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            if let (Some(decision), Some(origin), None) = (
+                words.next().and_then(NetDecision::parse),
+                words.next(),
+                words.next(),
+            ) {
+                list.set(origin, decision);
+            }
+        }
+        list
+    }
+
+    /// The list as its file holds it
+    pub fn render(&self) -> String {
+        let mut out = String::from("# Network permissions (see netperm)\n");
+        for (origin, decision) in &self.entries {
+            out.push_str(&format!("{} {}\n", decision.name(), origin));
+        }
+        out
+    }
+
+    pub fn get(&self, origin: &str) -> Option<NetDecision> {
+        self.entries.get(origin).copied()
+    }
+
+    pub fn set(&mut self, origin: &str, decision: NetDecision) {
+        self.entries.insert(origin.to_string(), decision);
+    }
+
+    /// Forget the decision for `origin`; returns whether there was one
+    pub fn remove(&mut self, origin: &str) -> bool {
+        self.entries.remove(origin).is_some()
+    }
+
+    /// The decisions, by origin
+    pub fn entries(&self) -> impl Iterator<Item = (&str, NetDecision)> {
+        self.entries.iter().map(|(o, &d)| (o.as_str(), d))
+    }
+}
+
+/// An origin requests are waiting to reach
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetPrompt {
+    pub origin: String,
+    /// Kernel time of the first request
+    pub since: f64,
+    /// Requests made since, the first included
+    pub requests: u32,
+}
+
+/// Requests waiting for a decision, and the notices asking for one
+#[derive(Debug, Default)]
+pub struct NetPermissions {
+    prompts: BTreeMap<String, NetPrompt>,
+    /// Futures waiting in `syscall::net_access`
+    waiters: Vec<Waker>,
+    /// Notices not yet shown at the prompt, with the origin each is about
+    notices: VecDeque<(String, String)>,
+}
+
+impl NetPermissions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Note a request to `origin`, which has no decision; the first one
+    /// queues a notice asking for it
+    pub fn ask(&mut self, origin: &str, now: f64) {
+        if let Some(prompt) = self.prompts.get_mut(origin) {
+            prompt.requests += 1;
+            return;
+        }
+        self.prompts.insert(
+            origin.to_string(),
+            NetPrompt {
+                origin: origin.to_string(),
+                since: now,
+                requests: 1,
+            },
+        );
+        let notice = format!(
+            "netperm: something wants to connect to {0}; run 'netperm allow {0}' as root to let it, or 'netperm deny {0}'",
+            origin
+        );
+        self.notices.push_back((origin.to_string(), notice));
+    }
+
+    /// Origins waiting for a decision, by origin
+    pub fn prompts(&self) -> impl Iterator<Item = &NetPrompt> {
+        self.prompts.values()
+    }
+
+    /// Wake `waker` when a decision is made or a prompt times out
+    pub fn wait(&mut self, waker: Waker) {
+        self.waiters.push(waker);
+    }
+
+    /// A decision was made for `origin`: stop asking and wake the requests
+    pub fn decided(&mut self, origin: &str) {
+        self.prompts.remove(origin);
+        self.notices.retain(|(o, _)| o != origin);
+        self.wake();
+    }
+
+    /// Drop prompts nobody answered within [`PROMPT_TIMEOUT`], waking
+    /// their requests to give up; returns the origins dropped
+    pub fn expire(&mut self, now: f64) -> Vec<String> {
+        let expired: Vec<String> = self
+            .prompts
+            .values()
+            .filter(|p| now - p.since >= PROMPT_TIMEOUT)
+            .map(|p| p.origin.clone())
+            .collect();
+        if !expired.is_empty() {
+            for origin in &expired {
+                self.prompts.remove(origin);
+            }
+            self.wake();
+        }
+        expired
+    }
+
+    /// Drop every prompt and notice, waking the requests, which have
+    /// nothing left to wait for (offline mode was turned on)
+    pub fn clear(&mut self) {
+        self.prompts.clear();
+        self.notices.clear();
+        self.wake();
+    }
+
+    /// The oldest notice not yet shown
+    pub fn take_notice(&mut self) -> Option<String> {
+        self.notices.pop_front().map(|(_, notice)| notice)
+    }
+
+    fn wake(&mut self) {
+        for waker in self.waiters.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_origin() {
+        assert_eq!(
+            origin("https://Example.com/a/b?c").as_deref(),
+            Some("https://example.com")
+        );
+        assert_eq!(
+            origin("wss://user@relay.test:443/9p").as_deref(),
+            Some("https://relay.test")
+        );
+        assert_eq!(
+            origin("http://localhost:8080").as_deref(),
+            Some("http://localhost:8080")
+        );
+        assert_eq!(origin("/packages/index.json"), None);
+        assert_eq!(origin("https:///nohost"), None);
+        assert_eq!(
+            origin(" ht\ttps://b.test/").as_deref(),
+            Some("https://b.test")
+        );
+    }
+
+    #[test]
+    fn test_is_relative() {
+        for url in ["/packages/index.json", "index.json?a=b:c", "./a:b", "#x"] {
+            assert!(is_relative(url), "{}", url);
+        }
+        for url in [
+            "//other.test/x",
+            "\\\\other.test",
+            " /\\other.test",
+            "http:other.test",
+        ] {
+            assert!(!is_relative(url), "{}", url);
+        }
+    }
+
+    #[test]
+    fn test_allowlist() {
+        let mut list = Allowlist::parse(
+            "# comment\nallow https://a.test\ndeny http://b.test\nmaybe https://c.test\nallow\n",
+        );
+        assert_eq!(list.get("https://a.test"), Some(NetDecision::Allow));
+        assert_eq!(list.get("http://b.test"), Some(NetDecision::Deny));
+        assert_eq!(list.get("https://c.test"), None);
+        assert_eq!(list.entries().count(), 2);
+
+        list.set("https://a.test", NetDecision::Deny);
+        assert!(list.remove("http://b.test"));
+        assert!(!list.remove("http://b.test"));
+        assert_eq!(Allowlist::parse(&list.render()), list);
+    }
+
+    #[test]
+    fn test_prompts() {
+        let mut perms = NetPermissions::new();
+        perms.ask("https://a.test", 0.0);
+        perms.ask("https://a.test", 10.0);
+        perms.ask("https://b.test", 100.0);
+        assert_eq!(perms.prompts().next().unwrap().requests, 2);
+        assert!(
+            perms
+                .take_notice()
+                .unwrap()
+                .contains("netperm allow https://a.test")
+        );
+
+        perms.decided("https://b.test");
+        assert!(perms.take_notice().is_none());
+        assert!(perms.expire(PROMPT_TIMEOUT - 1.0).is_empty());
+        assert_eq!(perms.expire(PROMPT_TIMEOUT), ["https://a.test"]);
+        assert_eq!(perms.prompts().count(), 0);
+
+        perms.ask("https://c.test", 0.0);
+        perms.clear();
+        assert_eq!(perms.prompts().count(), 0);
+        assert!(perms.take_notice().is_none());
+    }
+}
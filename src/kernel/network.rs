@@ -1,8 +1,14 @@
 //! Network subsystem for WASM
 //!
 //! Provides networking capabilities using browser APIs:
-//! - HTTP client via Fetch API
+//! - HTTP client via Fetch API, with download progress
+//! - Chunked uploads paced by a [`RateLimiter`]
 //! - WebSocket support for bidirectional communication
+//! - GETs answered from the HTTP cache when asked to (see
+//!   [`super::httpcache`])
+//!
+//! Every connection needs the user's permission for its origin first
+//! (see [`super::netperm`]), and fails at once in offline mode.
 //!
 //! Limitations (browser sandbox):
 //! - No raw TCP/UDP sockets
@@ -11,6 +17,8 @@
 
 #![cfg(target_arch = "wasm32")]
 
+use super::httpcache::{CachePolicy, CachedResponse};
+use super::ratelimit::RateLimiter;
 use std::collections::HashMap;
 use wasm_bindgen::JsCast;
 use wasm_bindgen::prelude::*;
@@ -25,6 +33,11 @@ pub enum HttpMethod {
     Delete,
     Head,
     Patch,
+    // WebDAV (RFC 4918)
+    Propfind,
+    Mkcol,
+    Move,
+    Copy,
 }
 
 impl HttpMethod {
@@ -36,6 +49,10 @@ impl HttpMethod {
             HttpMethod::Delete => "DELETE",
             HttpMethod::Head => "HEAD",
             HttpMethod::Patch => "PATCH",
+            HttpMethod::Propfind => "PROPFIND",
+            HttpMethod::Mkcol => "MKCOL",
+            HttpMethod::Move => "MOVE",
+            HttpMethod::Copy => "COPY",
         }
     }
 }
@@ -50,6 +67,27 @@ pub struct HttpResponse {
 }
 
 impl HttpResponse {
+    /// A response served from the cache, marked `x-cache: HIT`
+    fn from_cache(cached: CachedResponse) -> Self {
+        let mut headers = HashMap::new();
+        headers.insert("x-cache".to_string(), "HIT".to_string());
+        for (name, value) in [
+            ("content-type", cached.content_type),
+            ("etag", cached.etag),
+            ("last-modified", cached.last_modified),
+        ] {
+            if let Some(value) = value {
+                headers.insert(name.to_string(), value);
+            }
+        }
+        Self {
+            status: 200,
+            status_text: "OK".to_string(),
+            headers,
+            body: cached.body,
+        }
+    }
+
     /// Get body as UTF-8 string
     pub fn text(&self) -> Result<String, std::string::FromUtf8Error> {
         String::from_utf8(self.body.clone())
@@ -62,6 +100,7 @@ pub struct HttpRequest {
     method: HttpMethod,
     headers: HashMap<String, String>,
     body: Option<Vec<u8>>,
+    cache: CachePolicy,
 }
 
 impl HttpRequest {
@@ -72,6 +111,7 @@ impl HttpRequest {
             method: HttpMethod::Get,
             headers: HashMap::new(),
             body: None,
+            cache: CachePolicy::Bypass,
         }
     }
 
@@ -82,6 +122,7 @@ impl HttpRequest {
             method: HttpMethod::Post,
             headers: HashMap::new(),
             body: None,
+            cache: CachePolicy::Bypass,
         }
     }
 
@@ -92,6 +133,7 @@ impl HttpRequest {
             method,
             headers: HashMap::new(),
             body: None,
+            cache: CachePolicy::Bypass,
         }
     }
 
@@ -107,6 +149,12 @@ impl HttpRequest {
         self
     }
 
+    /// Use the HTTP cache this way; only GETs are cached
+    pub fn cache(mut self, policy: CachePolicy) -> Self {
+        self.cache = policy;
+        self
+    }
+
     /// Set JSON body
     pub fn json(mut self, json: &str) -> Self {
         self.headers
@@ -117,6 +165,134 @@ impl HttpRequest {
 
     /// Execute the request
     pub async fn send(self) -> Result<HttpResponse, String> {
+        let cached = self.cached();
+        if let (CachePolicy::Prefer, Some(cached)) = (self.cache, &cached) {
+            return Ok(HttpResponse::from_cache(cached.clone()));
+        }
+        let resp = self.fetch(cached.as_ref()).await?;
+        if let Some(response) = self.not_modified(&resp, cached) {
+            return Ok(response);
+        }
+
+        // Get body
+        let array_buffer = JsFuture::from(
+            resp.array_buffer()
+                .map_err(|e| format!("Failed to get body: {:?}", e))?,
+        )
+        .await
+        .map_err(|e| format!("Failed to read body: {:?}", e))?;
+
+        let uint8_array = js_sys::Uint8Array::new(&array_buffer);
+        let body = uint8_array.to_vec();
+
+        Ok(self.keep(HttpResponse {
+            status: resp.status(),
+            status_text: resp.status_text(),
+            headers: response_headers(&resp),
+            body,
+        }))
+    }
+
+    /// Execute the request, reading the body as it streams in
+    ///
+    /// `progress` is called after every chunk with the bytes received so
+    /// far and the Content-Length, if the server sent one.
+    pub async fn send_with_progress(
+        self,
+        mut progress: impl FnMut(u64, Option<u64>),
+    ) -> Result<HttpResponse, String> {
+        let cached = self.cached();
+        if let (CachePolicy::Prefer, Some(cached)) = (self.cache, &cached) {
+            let len = cached.body.len() as u64;
+            progress(len, Some(len));
+            return Ok(HttpResponse::from_cache(cached.clone()));
+        }
+        let resp = self.fetch(cached.as_ref()).await?;
+        if let Some(response) = self.not_modified(&resp, cached) {
+            let len = response.body.len() as u64;
+            progress(len, Some(len));
+            return Ok(response);
+        }
+        let headers = response_headers(&resp);
+        let total = headers
+            .get("content-length")
+            .and_then(|len| len.parse::<u64>().ok());
+
+        let mut body = Vec::new();
+        if let Some(stream) = resp.body() {
+            let reader: web_sys::ReadableStreamDefaultReader = stream
+                .get_reader()
+                .dyn_into()
+                .map_err(|_| "Failed to get body reader")?;
+            loop {
+                let chunk = JsFuture::from(reader.read())
+                    .await
+                    .map_err(|e| format!("Failed to read body: {:?}", e))?;
+                let done = js_sys::Reflect::get(&chunk, &JsValue::from_str("done"))
+                    .map(|d| d.is_truthy())
+                    .unwrap_or(true);
+                if done {
+                    break;
+                }
+                let value = js_sys::Reflect::get(&chunk, &JsValue::from_str("value"))
+                    .map_err(|e| format!("Failed to read body: {:?}", e))?;
+                body.extend(js_sys::Uint8Array::new(&value).to_vec());
+                progress(body.len() as u64, total);
+            }
+        }
+
+        Ok(self.keep(HttpResponse {
+            status: resp.status(),
+            status_text: resp.status_text(),
+            headers,
+            body,
+        }))
+    }
+
+    /// The cached copy of what this request asks for, if it uses the cache
+    fn cached(&self) -> Option<CachedResponse> {
+        if self.cache == CachePolicy::Bypass || self.method != HttpMethod::Get {
+            return None;
+        }
+        super::syscall::http_cache_get(&self.url)
+    }
+
+    /// The cached copy, if the server says it is still current
+    fn not_modified(
+        &self,
+        resp: &web_sys::Response,
+        cached: Option<CachedResponse>,
+    ) -> Option<HttpResponse> {
+        let mut cached = cached.filter(|_| resp.status() == 304)?;
+        cached.time = js_sys::Date::now();
+        let _ = super::syscall::http_cache_put(&cached);
+        Some(HttpResponse::from_cache(cached))
+    }
+
+    /// Keep `response` in the cache if this request uses it
+    fn keep(&self, response: HttpResponse) -> HttpResponse {
+        if self.cache != CachePolicy::Bypass
+            && self.method == HttpMethod::Get
+            && let Some(cached) = CachedResponse::from_response(
+                &self.url,
+                response.status,
+                &response.headers,
+                response.body.clone(),
+                js_sys::Date::now(),
+            )
+        {
+            let _ = super::syscall::http_cache_put(&cached);
+        }
+        response
+    }
+
+    /// Send the request and wait for the response headers, once the user
+    /// lets it reach its origin (see [`super::netperm`]), asking only for
+    /// a newer copy than `cached`
+    async fn fetch(&self, cached: Option<&CachedResponse>) -> Result<web_sys::Response, String> {
+        super::syscall::net_access(&self.url)
+            .await
+            .map_err(|e| net_refused(&self.url, e))?;
         let window = web_sys::window().ok_or("No window object")?;
 
         // Create request init
@@ -141,56 +317,62 @@ impl HttpRequest {
                 .set(name, value)
                 .map_err(|e| format!("Failed to set header: {:?}", e))?;
         }
+        for (name, value) in cached.map(CachedResponse::validators).unwrap_or_default() {
+            headers
+                .set(name, &value)
+                .map_err(|e| format!("Failed to set header: {:?}", e))?;
+        }
 
         // Execute fetch
         let resp_value = JsFuture::from(window.fetch_with_request(&request))
             .await
             .map_err(|e| format!("Fetch failed: {:?}", e))?;
 
-        let resp: web_sys::Response = resp_value
+        resp_value
             .dyn_into()
-            .map_err(|_| "Failed to cast response")?;
-
-        // Get status
-        let status = resp.status();
-        let status_text = resp.status_text();
-
-        // Get headers
-        let mut response_headers = HashMap::new();
-        let header_entries = resp.headers();
-        // Note: Headers iteration is limited in web-sys, we get common ones
-        for name in [
-            "content-type",
-            "content-length",
-            "cache-control",
-            "date",
-            "server",
-        ]
-        .iter()
-        {
-            if let Ok(Some(value)) = header_entries.get(*name) {
-                response_headers.insert(name.to_string(), value);
-            }
-        }
-
-        // Get body
-        let array_buffer = JsFuture::from(
-            resp.array_buffer()
-                .map_err(|e| format!("Failed to get body: {:?}", e))?,
-        )
-        .await
-        .map_err(|e| format!("Failed to read body: {:?}", e))?;
+            .map_err(|_| "Failed to cast response".to_string())
+    }
+}
 
-        let uint8_array = js_sys::Uint8Array::new(&array_buffer);
-        let body = uint8_array.to_vec();
+/// Why a connection to `url` may not be made
+pub fn net_refused(url: &str, e: super::syscall::SyscallError) -> String {
+    let origin = super::netperm::origin(url).unwrap_or_else(|| url.to_string());
+    match e {
+        super::syscall::SyscallError::NetworkDown => {
+            format!(
+                "{}: offline mode is on; run 'offline off' to connect",
+                origin
+            )
+        }
+        super::syscall::SyscallError::WouldBlock => format!(
+            "{}: waiting for permission; run 'netperm allow {}' and try again",
+            origin, origin
+        ),
+        e => format!("{}: connection not permitted: {}", origin, e),
+    }
+}
 
-        Ok(HttpResponse {
-            status,
-            status_text,
-            headers: response_headers,
-            body,
-        })
+/// The headers of a response worth keeping
+fn response_headers(resp: &web_sys::Response) -> HashMap<String, String> {
+    let mut response_headers = HashMap::new();
+    let header_entries = resp.headers();
+    // Note: Headers iteration is limited in web-sys, we get common ones
+    for name in [
+        "content-type",
+        "content-length",
+        "cache-control",
+        "etag",
+        "last-modified",
+        "date",
+        "server",
+    ]
+    .iter()
+    {
+        if let Ok(Some(value)) = header_entries.get(*name) {
+            response_headers.insert(name.to_string(), value);
+        }
     }
+    response_headers
 }
 
 /// WebSocket connection state
@@ -223,6 +405,7 @@ impl WebSocketManager {
 
     /// Connect to a WebSocket server
     pub fn connect(&mut self, url: &str) -> Result<WsId, String> {
+        super::syscall::net_check(url).map_err(|e| net_refused(url, e))?;
         let ws = web_sys::WebSocket::new(url)
             .map_err(|e| format!("WebSocket creation failed: {:?}", e))?;
 
@@ -317,6 +500,55 @@ pub async fn fetch_with_method(
     req.send().await
 }
 
+/// Wait `ms` milliseconds without blocking the page
+pub async fn sleep(ms: f64) {
+    let promise = js_sys::Promise::new(&mut |resolve, _reject| {
+        let scheduled = web_sys::window().and_then(|window| {
+            window
+                .set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, ms as i32)
+                .ok()
+        });
+        if scheduled.is_none() {
+            let _ = resolve.call0(&JsValue::NULL);
+        }
+    });
+    let _ = JsFuture::from(promise).await;
+}
+
+/// PUT `data` as a series of objects of at most `chunk_size` bytes, the
+/// `n`th to `url_for(n)`, no faster than `limiter` allows
+///
+/// `on_sent` is told how many chunks are up, of how many, after each one.
+/// Stops at the first request that fails or gets a non-2xx status.
+/// Returns the number of chunks.
+pub async fn put_chunked(
+    url_for: impl Fn(usize) -> String,
+    data: &[u8],
+    chunk_size: usize,
+    headers: &HashMap<String, String>,
+    limiter: &mut RateLimiter,
+    mut on_sent: impl FnMut(usize, usize),
+) -> Result<usize, String> {
+    let chunks: Vec<&[u8]> = data.chunks(chunk_size.max(1)).collect();
+    for (n, chunk) in chunks.iter().enumerate() {
+        let wait = limiter.reserve(js_sys::Date::now(), chunk.len());
+        if wait > 0.0 {
+            sleep(wait).await;
+        }
+        let url = url_for(n);
+        let response =
+            fetch_with_method(HttpMethod::Put, &url, headers.clone(), Some(chunk.to_vec())).await?;
+        if !(200..300).contains(&response.status) {
+            return Err(format!(
+                "PUT {}: HTTP {} {}",
+                url, response.status, response.status_text
+            ));
+        }
+        on_sent(n + 1, chunks.len());
+    }
+    Ok(chunks.len())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -327,6 +559,7 @@ mod tests {
         assert_eq!(HttpMethod::Post.as_str(), "POST");
         assert_eq!(HttpMethod::Put.as_str(), "PUT");
         assert_eq!(HttpMethod::Delete.as_str(), "DELETE");
+        assert_eq!(HttpMethod::Propfind.as_str(), "PROPFIND");
     }
 
     #[test]
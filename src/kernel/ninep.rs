@@ -0,0 +1,1224 @@
+//! 9P2000 file server
+//!
+//! Serves the VFS over the Plan 9 file protocol, so another machine can
+//! mount it: `mount -t 9p` on Linux, `9pfuse`, or Plan 9 itself.
+//!
+//! [`Server`] is the protocol: it takes one T-message and returns its
+//! R-message. Every operation goes through the syscall layer as the
+//! process that started the export, so its permissions apply and the
+//! `uname` a client attaches with is not trusted. A server can be limited
+//! to a subtree and made read-only.
+//!
+//! The transports are thin. [`serve`] answers whole messages arriving on
+//! an IPC channel pair; in the browser, [`serve_websocket`] dials out to a
+//! WebSocket relay (a page can't listen for connections) and answers the
+//! binary messages it relays, split into 9P messages by a [`Framer`].
+//!
+//! Only the base 9P2000 dialect is spoken, so symbolic links are followed
+//! rather than shown, and times can't be set through `wstat`.
+
+use super::ipc::{Receiver, Sender};
+use super::syscall::{self, Fd, OpenFlags, Pid, SyscallError};
+use super::users::{Gid, Uid};
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::io::SeekFrom;
+
+/// The protocol version spoken
+pub const VERSION: &str = "9P2000";
+
+/// Largest message accepted or sent
+pub const MAX_MSIZE: u32 = 64 * 1024;
+
+/// Bytes of a Rread or Twrite that aren't data
+const IOHDRSZ: u32 = 24;
+
+const TVERSION: u8 = 100;
+const TAUTH: u8 = 102;
+const TATTACH: u8 = 104;
+const RERROR: u8 = 107;
+const TFLUSH: u8 = 108;
+const TWALK: u8 = 110;
+const TOPEN: u8 = 112;
+const TCREATE: u8 = 114;
+const TREAD: u8 = 116;
+const TWRITE: u8 = 118;
+const TCLUNK: u8 = 120;
+const TREMOVE: u8 = 122;
+const TSTAT: u8 = 124;
+const TWSTAT: u8 = 126;
+
+const NOTAG: u16 = 0xffff;
+const MAXWELEM: usize = 16;
+
+/// Directory bit of a mode
+const DMDIR: u32 = 0x8000_0000;
+const QTDIR: u8 = 0x80;
+const QTFILE: u8 = 0x00;
+
+const OREAD: u8 = 0;
+const OWRITE: u8 = 1;
+const ORDWR: u8 = 2;
+const OEXEC: u8 = 3;
+const OTRUNC: u8 = 0x10;
+const ORCLOSE: u8 = 0x40;
+
+/// A file the client has a handle on
+struct Fid {
+    path: String,
+    open: Option<Open>,
+}
+
+/// How a fid was opened
+struct Open {
+    /// Descriptor of an open file; directories have none
+    fd: Option<Fd>,
+    read: bool,
+    write: bool,
+    remove_on_clunk: bool,
+    /// Entries left of a directory being read
+    dir: Option<DirRead>,
+}
+
+/// A directory read in progress
+#[derive(Default)]
+struct DirRead {
+    /// Where the next read must start
+    offset: u64,
+    entries: VecDeque<Vec<u8>>,
+}
+
+/// A 9P2000 server for part of the VFS
+pub struct Server {
+    root: String,
+    read_only: bool,
+    msize: u32,
+    fids: HashMap<u32, Fid>,
+    pid: Option<Pid>,
+}
+
+impl Server {
+    /// Serve the tree under `root`
+    pub fn new(root: &str) -> Self {
+        let root = match root.trim_end_matches('/') {
+            "" => "/".to_string(),
+            root => root.to_string(),
+        };
+        Self {
+            root,
+            read_only: false,
+            msize: MAX_MSIZE,
+            fids: HashMap::new(),
+            pid: None,
+        }
+    }
+
+    /// Refuse every change to the tree
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    /// Act as `pid` rather than whichever process is current
+    pub fn set_process(&mut self, pid: Pid) {
+        self.pid = Some(pid);
+    }
+
+    /// The tree being served
+    pub fn root(&self) -> &str {
+        &self.root
+    }
+
+    /// Check if changes are refused
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Fids the client holds
+    pub fn fid_count(&self) -> usize {
+        self.fids.len()
+    }
+
+    /// Answer one T-message
+    pub fn handle(&mut self, msg: &[u8]) -> Vec<u8> {
+        let previous = syscall::getpid().ok();
+        if let Some(pid) = self.pid {
+            syscall::set_current_process(pid);
+        }
+
+        let mut r = Reader::new(msg);
+        let reply = match (r.u32(), r.u8(), r.u16()) {
+            (Ok(size), Ok(kind), Ok(tag)) if size as usize == msg.len() => {
+                match self.dispatch(kind, &mut r) {
+                    Ok(body) => message(kind + 1, tag, &body),
+                    Err(e) => error(tag, &e),
+                }
+            }
+            (_, _, Ok(tag)) => error(tag, "malformed message"),
+            _ => error(NOTAG, "malformed message"),
+        };
+
+        if let (Some(_), Some(previous)) = (self.pid, previous) {
+            syscall::set_current_process(previous);
+        }
+        reply
+    }
+
+    /// Close everything the client had open
+    pub fn close_all(&mut self) {
+        for (_, fid) in self.fids.drain() {
+            if let Some(fd) = fid.open.and_then(|o| o.fd) {
+                let _ = syscall::close(fd);
+            }
+        }
+    }
+
+    fn dispatch(&mut self, kind: u8, r: &mut Reader) -> Result<Vec<u8>, String> {
+        match kind {
+            TVERSION => self.version(r),
+            TAUTH => Err("authentication not required".to_string()),
+            TATTACH => self.attach(r),
+            TFLUSH => Ok(Vec::new()),
+            TWALK => self.walk(r),
+            TOPEN => self.open(r),
+            TCREATE => self.create(r),
+            TREAD => self.read(r),
+            TWRITE => self.write(r),
+            TCLUNK => self.clunk(r),
+            TREMOVE => self.remove(r),
+            TSTAT => self.stat(r),
+            TWSTAT => self.wstat(r),
+            _ => Err("unknown message type".to_string()),
+        }
+    }
+
+    fn version(&mut self, r: &mut Reader) -> Result<Vec<u8>, String> {
+        let msize = r.u32()?;
+        let version = r.string()?;
+        // A new session: whatever was open goes
+        self.close_all();
+        self.msize = msize.clamp(IOHDRSZ + 1, MAX_MSIZE);
+
+        let mut w = Writer::default();
+        w.u32(self.msize);
+        w.string(if version.starts_with(VERSION) {
+            VERSION
+        } else {
+            "unknown"
+        });
+        Ok(w.0)
+    }
+
+    fn attach(&mut self, r: &mut Reader) -> Result<Vec<u8>, String> {
+        let fid = r.u32()?;
+        let _afid = r.u32()?;
+        let _uname = r.string()?;
+        let aname = r.string()?;
+        if self.fids.contains_key(&fid) {
+            return Err("fid already in use".to_string());
+        }
+
+        let mut path = self.root.clone();
+        for part in aname.split('/').filter(|p| !p.is_empty() && *p != ".") {
+            if part == ".." {
+                return Err("invalid attach name".to_string());
+            }
+            path = join(&path, part);
+        }
+        let meta = syscall::metadata(&path).map_err(errstr)?;
+        if !meta.is_dir {
+            return Err("not a directory".to_string());
+        }
+
+        let mut w = Writer::default();
+        w.qid(&path, &meta);
+        self.fids.insert(fid, Fid { path, open: None });
+        Ok(w.0)
+    }
+
+    fn walk(&mut self, r: &mut Reader) -> Result<Vec<u8>, String> {
+        let fid = r.u32()?;
+        let newfid = r.u32()?;
+        let count = r.u16()? as usize;
+        if count > MAXWELEM {
+            return Err("too many names in walk".to_string());
+        }
+        let mut names = Vec::with_capacity(count);
+        for _ in 0..count {
+            names.push(r.string()?);
+        }
+
+        let start = self.fid(fid)?;
+        if start.open.is_some() {
+            return Err("cannot walk an open fid".to_string());
+        }
+        if newfid != fid && self.fids.contains_key(&newfid) {
+            return Err("fid already in use".to_string());
+        }
+
+        let mut path = start.path.clone();
+        let mut w = Writer::default();
+        w.u16(0);
+        let mut walked = 0u16;
+        for name in &names {
+            // Only directories can be walked from
+            let found = syscall::metadata(&path)
+                .map_err(errstr)
+                .and_then(|m| match m.is_dir {
+                    true => self.step(&path, name),
+                    false => Err("not a directory".to_string()),
+                })
+                .and_then(|next| match syscall::metadata(&next) {
+                    Ok(meta) => Ok((next, meta)),
+                    Err(e) => Err(errstr(e)),
+                });
+            let (next, meta) = match found {
+                Ok(found) => found,
+                // Only the first name failing is an error
+                Err(e) if walked == 0 => return Err(e),
+                Err(_) => break,
+            };
+            path = next;
+            w.qid(&path, &meta);
+            walked += 1;
+        }
+
+        if walked as usize == names.len() {
+            self.fids.insert(newfid, Fid { path, open: None });
+        }
+        w.0[..2].copy_from_slice(&walked.to_le_bytes());
+        Ok(w.0)
+    }
+
+    fn open(&mut self, r: &mut Reader) -> Result<Vec<u8>, String> {
+        let fid = r.u32()?;
+        let mode = r.u8()?;
+        let path = self.unopened(fid)?.path.clone();
+        let meta = syscall::metadata(&path).map_err(errstr)?;
+
+        let (read, write) = access(mode);
+        if write && self.read_only {
+            return Err(errstr(SyscallError::ReadOnlyFs));
+        }
+        let open = if meta.is_dir {
+            if write || mode & OTRUNC != 0 {
+                return Err("is a directory".to_string());
+            }
+            Open {
+                fd: None,
+                read: true,
+                write: false,
+                remove_on_clunk: false,
+                dir: Some(DirRead::default()),
+            }
+        } else {
+            let flags = OpenFlags {
+                read,
+                write,
+                create: false,
+                truncate: write && mode & OTRUNC != 0,
+                append: false,
+                nonblock: false,
+            };
+            Open {
+                fd: Some(syscall::open(&path, flags).map_err(errstr)?),
+                read,
+                write,
+                remove_on_clunk: false,
+                dir: None,
+            }
+        };
+        self.opened(fid, &path, mode, open)
+    }
+
+    fn create(&mut self, r: &mut Reader) -> Result<Vec<u8>, String> {
+        let fid = r.u32()?;
+        let name = r.string()?;
+        let perm = r.u32()?;
+        let mode = r.u8()?;
+        let dir = self.unopened(fid)?.path.clone();
+        if self.read_only {
+            return Err(errstr(SyscallError::ReadOnlyFs));
+        }
+        if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+            return Err("invalid file name".to_string());
+        }
+        if !syscall::metadata(&dir).map_err(errstr)?.is_dir {
+            return Err("not a directory".to_string());
+        }
+        let path = join(&dir, &name);
+        if syscall::exists(&path).unwrap_or(false) {
+            return Err(errstr(SyscallError::AlreadyExists));
+        }
+
+        let open = if perm & DMDIR != 0 {
+            syscall::mkdir(&path).map_err(errstr)?;
+            syscall::chmod(&path, (perm & 0o777) as u16).map_err(errstr)?;
+            Open {
+                fd: None,
+                read: true,
+                write: false,
+                remove_on_clunk: mode & ORCLOSE != 0,
+                dir: Some(DirRead::default()),
+            }
+        } else {
+            let fd = syscall::open(&path, OpenFlags::WRITE).map_err(errstr)?;
+            syscall::close(fd).map_err(errstr)?;
+            syscall::chmod(&path, (perm & 0o777) as u16).map_err(errstr)?;
+            let (read, write) = access(mode);
+            let flags = OpenFlags {
+                read,
+                write,
+                create: false,
+                truncate: false,
+                append: false,
+                nonblock: false,
+            };
+            Open {
+                fd: Some(syscall::open(&path, flags).map_err(errstr)?),
+                read,
+                write,
+                remove_on_clunk: false,
+                dir: None,
+            }
+        };
+        self.opened(fid, &path, mode, open)
+    }
+
+    /// Make `fid` refer to `path`, opened, and answer with its qid
+    fn opened(
+        &mut self,
+        fid: u32,
+        path: &str,
+        mode: u8,
+        mut open: Open,
+    ) -> Result<Vec<u8>, String> {
+        open.remove_on_clunk |= mode & ORCLOSE != 0;
+        let meta = syscall::metadata(path).map_err(errstr)?;
+        let mut w = Writer::default();
+        w.qid(path, &meta);
+        w.u32(self.msize - IOHDRSZ);
+        self.fids.insert(
+            fid,
+            Fid {
+                path: path.to_string(),
+                open: Some(open),
+            },
+        );
+        Ok(w.0)
+    }
+
+    fn read(&mut self, r: &mut Reader) -> Result<Vec<u8>, String> {
+        let fid = r.u32()?;
+        let offset = r.u64()?;
+        let count = r.u32()?.min(self.msize - IOHDRSZ) as usize;
+        let fid = self.fids.get_mut(&fid).ok_or("unknown fid")?;
+        let open = fid.open.as_mut().ok_or("fid not open")?;
+        if !open.read {
+            return Err("fid not open for reading".to_string());
+        }
+
+        let mut w = Writer::default();
+        w.u32(0);
+        if let Some(dir) = open.dir.as_mut() {
+            if offset == 0 {
+                dir.offset = 0;
+                dir.entries = dir_entries(&fid.path).map_err(errstr)?;
+            } else if offset != dir.offset {
+                return Err("bad offset in directory read".to_string());
+            }
+            let start = w.0.len();
+            while let Some(entry) = dir.entries.front() {
+                if w.0.len() - start + entry.len() > count {
+                    break;
+                }
+                w.0.extend_from_slice(entry);
+                dir.entries.pop_front();
+            }
+            if w.0.len() == start && !dir.entries.is_empty() {
+                return Err("directory entry too large for read".to_string());
+            }
+            dir.offset += (w.0.len() - start) as u64;
+        } else if let Some(fd) = open.fd {
+            syscall::seek(fd, SeekFrom::Start(offset)).map_err(errstr)?;
+            let start = w.0.len();
+            w.0.resize(start + count, 0);
+            let mut filled = 0;
+            while filled < count {
+                match syscall::read(fd, &mut w.0[start + filled..]) {
+                    Ok(0) => break,
+                    Ok(n) => filled += n,
+                    Err(e) => return Err(errstr(e)),
+                }
+            }
+            w.0.truncate(start + filled);
+        }
+        let len = (w.0.len() - 4) as u32;
+        w.0[..4].copy_from_slice(&len.to_le_bytes());
+        Ok(w.0)
+    }
+
+    fn write(&mut self, r: &mut Reader) -> Result<Vec<u8>, String> {
+        let fid = r.u32()?;
+        let offset = r.u64()?;
+        let count = r.u32()? as usize;
+        let data = r.bytes(count)?;
+        let fid = self.fids.get(&fid).ok_or("unknown fid")?;
+        let open = fid.open.as_ref().ok_or("fid not open")?;
+        let fd = match open.fd {
+            Some(fd) if open.write => fd,
+            _ => return Err("fid not open for writing".to_string()),
+        };
+
+        syscall::seek(fd, SeekFrom::Start(offset)).map_err(errstr)?;
+        let written = syscall::write(fd, data).map_err(errstr)?;
+        let mut w = Writer::default();
+        w.u32(written as u32);
+        Ok(w.0)
+    }
+
+    fn clunk(&mut self, r: &mut Reader) -> Result<Vec<u8>, String> {
+        let fid = r.u32()?;
+        let fid = self.fids.remove(&fid).ok_or("unknown fid")?;
+        if let Some(open) = fid.open {
+            if let Some(fd) = open.fd {
+                let _ = syscall::close(fd);
+            }
+            if open.remove_on_clunk {
+                remove_path(&fid.path).map_err(errstr)?;
+            }
+        }
+        Ok(Vec::new())
+    }
+
+    fn remove(&mut self, r: &mut Reader) -> Result<Vec<u8>, String> {
+        let fid = r.u32()?;
+        // The fid goes whether or not the file does
+        let fid = self.fids.remove(&fid).ok_or("unknown fid")?;
+        if let Some(fd) = fid.open.and_then(|o| o.fd) {
+            let _ = syscall::close(fd);
+        }
+        if self.read_only {
+            return Err(errstr(SyscallError::ReadOnlyFs));
+        }
+        if fid.path == self.root {
+            return Err("cannot remove the root".to_string());
+        }
+        remove_path(&fid.path).map_err(errstr)?;
+        Ok(Vec::new())
+    }
+
+    fn stat(&mut self, r: &mut Reader) -> Result<Vec<u8>, String> {
+        let fid = r.u32()?;
+        let path = &self.fid(fid)?.path;
+        let name = if *path == self.root {
+            "/"
+        } else {
+            path.rsplit('/').next().unwrap_or("/")
+        };
+        let entry = stat_entry(path, name).map_err(errstr)?;
+        let mut w = Writer::default();
+        w.u16(entry.len() as u16);
+        w.0.extend_from_slice(&entry);
+        Ok(w.0)
+    }
+
+    fn wstat(&mut self, r: &mut Reader) -> Result<Vec<u8>, String> {
+        let fid = r.u32()?;
+        let _len = r.u16()?;
+        let _size = r.u16()?;
+        let _kind = r.u16()?;
+        let _dev = r.u32()?;
+        let _qid = r.bytes(13)?;
+        let mode = r.u32()?;
+        let _atime = r.u32()?;
+        let _mtime = r.u32()?;
+        let length = r.u64()?;
+        let name = r.string()?;
+        let _uid = r.string()?;
+        let gid = r.string()?;
+        let _muid = r.string()?;
+
+        let path = self.fid(fid)?.path.clone();
+        let meta = syscall::metadata(&path).map_err(errstr)?;
+        let current_name = path.rsplit('/').next().unwrap_or_default();
+        let renaming = !name.is_empty() && name != current_name;
+        let changes = mode != u32::MAX || length != u64::MAX || !gid.is_empty() || renaming;
+        if !changes {
+            // "Make it stable": there's nothing to flush
+            return Ok(Vec::new());
+        }
+        if self.read_only {
+            return Err(errstr(SyscallError::ReadOnlyFs));
+        }
+
+        if mode != u32::MAX {
+            if (mode & DMDIR != 0) != meta.is_dir {
+                return Err("cannot change the directory bit".to_string());
+            }
+            let mode = (meta.mode & !0o777) | (mode & 0o777) as u16;
+            syscall::chmod(&path, mode).map_err(errstr)?;
+        }
+        if length != u64::MAX && length != meta.size {
+            if meta.is_dir {
+                return Err("is a directory".to_string());
+            }
+            set_length(&path, length, meta.size).map_err(errstr)?;
+        }
+        if !gid.is_empty() {
+            let group = syscall::get_group_by_name(&gid).ok_or("unknown group")?;
+            if group.gid.0 != meta.gid {
+                syscall::chown(&path, None, Some(group.gid.0)).map_err(errstr)?;
+            }
+        }
+        if renaming {
+            if path == self.root {
+                return Err("cannot rename the root".to_string());
+            }
+            if name.contains('/') || name == "." || name == ".." {
+                return Err("invalid file name".to_string());
+            }
+            let (parent, _) = path.rsplit_once('/').unwrap_or_default();
+            let to = join(if parent.is_empty() { "/" } else { parent }, &name);
+            syscall::rename(&path, &to).map_err(errstr)?;
+            if let Some(fid) = self.fids.get_mut(&fid) {
+                fid.path = to;
+            }
+        }
+        Ok(Vec::new())
+    }
+
+    fn fid(&self, fid: u32) -> Result<&Fid, String> {
+        self.fids.get(&fid).ok_or_else(|| "unknown fid".to_string())
+    }
+
+    fn unopened(&self, fid: u32) -> Result<&Fid, String> {
+        let found = self.fid(fid)?;
+        if found.open.is_some() {
+            return Err("fid already open".to_string());
+        }
+        Ok(found)
+    }
+
+    /// The path one walk step from `path`, never above the root
+    fn step(&self, path: &str, name: &str) -> Result<String, String> {
+        match name {
+            "" | "." => Ok(path.to_string()),
+            ".." if path == self.root => Ok(path.to_string()),
+            ".." => Ok(match path.rsplit_once('/') {
+                Some(("", _)) | None => "/".to_string(),
+                Some((parent, _)) => parent.to_string(),
+            }),
+            name if name.contains('/') => Err("invalid file name".to_string()),
+            name => Ok(join(path, name)),
+        }
+    }
+}
+
+/// Read and write wanted by an open mode
+fn access(mode: u8) -> (bool, bool) {
+    match mode & 3 {
+        OWRITE => (false, true),
+        ORDWR => (true, true),
+        OREAD | OEXEC => (true, false),
+        _ => (true, false),
+    }
+}
+
+fn join(dir: &str, name: &str) -> String {
+    if dir == "/" {
+        format!("/{}", name)
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+fn remove_path(path: &str) -> Result<(), SyscallError> {
+    if syscall::metadata(path)?.is_dir {
+        syscall::rmdir(path)
+    } else {
+        syscall::remove_file(path)
+    }
+}
+
+/// Cut or extend a file to `length` bytes
+fn set_length(path: &str, length: u64, size: u64) -> Result<(), SyscallError> {
+    if length == 0 {
+        let fd = syscall::open(path, OpenFlags::WRITE)?;
+        return syscall::close(fd);
+    }
+    if length < size {
+        let mut kept = Vec::with_capacity(length as usize);
+        syscall::read_at_with(path, 0, length as usize, &mut |chunk| {
+            kept.extend_from_slice(chunk)
+        })?;
+        let fd = syscall::open(path, OpenFlags::WRITE)?;
+        let written = syscall::write(fd, &kept);
+        syscall::close(fd)?;
+        return written.map(|_| ());
+    }
+    // Growing leaves a hole
+    let fd = syscall::open(path, OpenFlags::RDWR)?;
+    let grown =
+        syscall::seek(fd, SeekFrom::Start(length - 1)).and_then(|_| syscall::write(fd, &[0]));
+    syscall::close(fd)?;
+    grown.map(|_| ())
+}
+
+/// The stat entries of everything in `dir`
+fn dir_entries(dir: &str) -> Result<VecDeque<Vec<u8>>, SyscallError> {
+    let mut names = syscall::readdir(dir)?;
+    names.sort_unstable();
+    let mut entries = VecDeque::with_capacity(names.len());
+    for name in names {
+        // Skip entries that vanish or can't be looked at
+        if let Ok(entry) = stat_entry(&join(dir, &name), &name) {
+            entries.push_back(entry);
+        }
+    }
+    Ok(entries)
+}
+
+/// A file's 9P stat structure, its size prefix included
+fn stat_entry(path: &str, name: &str) -> Result<Vec<u8>, SyscallError> {
+    let meta = syscall::metadata(path)?;
+    let uid = syscall::get_user_by_uid(Uid(meta.uid))
+        .map(|u| u.name)
+        .unwrap_or_else(|| meta.uid.to_string());
+    let gid = syscall::get_group_by_gid(Gid(meta.gid))
+        .map(|g| g.name)
+        .unwrap_or_else(|| meta.gid.to_string());
+    let mode = (meta.mode as u32 & 0o777) | if meta.is_dir { DMDIR } else { 0 };
+
+    let mut w = Writer::default();
+    w.u16(0);
+    w.u16(0); // type
+    w.u32(0); // dev
+    w.qid(path, &meta);
+    w.u32(mode);
+    w.u32((meta.atime / 1000.0) as u32);
+    w.u32((meta.mtime / 1000.0) as u32);
+    w.u64(if meta.is_dir { 0 } else { meta.size });
+    w.string(name);
+    w.string(&uid);
+    w.string(&gid);
+    w.string(&uid);
+    let size = (w.0.len() - 2) as u16;
+    w.0[..2].copy_from_slice(&size.to_le_bytes());
+    Ok(w.0)
+}
+
+/// A message with its size, type and tag
+fn message(kind: u8, tag: u16, body: &[u8]) -> Vec<u8> {
+    let mut w = Writer::default();
+    w.u32((7 + body.len()) as u32);
+    w.u8(kind);
+    w.u16(tag);
+    w.0.extend_from_slice(body);
+    w.0
+}
+
+fn error(tag: u16, text: &str) -> Vec<u8> {
+    let mut w = Writer::default();
+    w.string(text);
+    message(RERROR, tag, &w.0)
+}
+
+/// The error strings 9P clients know
+fn errstr(e: SyscallError) -> String {
+    match e {
+        SyscallError::NotFound => "file does not exist".to_string(),
+        SyscallError::AlreadyExists => "file already exists".to_string(),
+        e => e.to_string(),
+    }
+}
+
+#[derive(Default)]
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, v: u8) {
+        self.0.push(v);
+    }
+
+    fn u16(&mut self, v: u16) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn u32(&mut self, v: u32) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn u64(&mut self, v: u64) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn string(&mut self, s: &str) {
+        let len = s.len().min(u16::MAX as usize);
+        self.u16(len as u16);
+        self.0.extend_from_slice(&s.as_bytes()[..len]);
+    }
+
+    /// A file's qid: kind, version from its time, path from its name
+    fn qid(&mut self, path: &str, meta: &syscall::FileMetadata) {
+        self.u8(if meta.is_dir { QTDIR } else { QTFILE });
+        self.u32(meta.mtime as u64 as u32);
+        // FNV-1a; paths are all the VFS has to tell files apart
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for byte in path.bytes() {
+            hash = (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+        self.u64(hash);
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.data.len());
+        let end = end.ok_or("message too short")?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        let b = self.bytes(8)?;
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(b);
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.u16()? as usize;
+        let bytes = self.bytes(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| "string is not UTF-8".to_string())
+    }
+}
+
+/// Splits a byte stream into 9P messages
+#[derive(Default)]
+pub struct Framer {
+    pending: Vec<u8>,
+}
+
+impl Framer {
+    /// Add bytes, returning the messages they complete
+    pub fn push(&mut self, data: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+        self.pending.extend_from_slice(data);
+        let mut messages = Vec::new();
+        while self.pending.len() >= 4 {
+            let size = u32::from_le_bytes([
+                self.pending[0],
+                self.pending[1],
+                self.pending[2],
+                self.pending[3],
+            ]);
+            if !(7..=MAX_MSIZE).contains(&size) {
+                return Err(format!("bad message size {}", size));
+            }
+            if self.pending.len() < size as usize {
+                break;
+            }
+            let rest = self.pending.split_off(size as usize);
+            messages.push(std::mem::replace(&mut self.pending, rest));
+        }
+        Ok(messages)
+    }
+}
+
+/// Answer the messages on `requests` until it closes, replying on `replies`
+pub async fn serve(mut server: Server, requests: Receiver<Vec<u8>>, replies: Sender<Vec<u8>>) {
+    while let Some(msg) = requests.recv().await {
+        if replies.send(server.handle(&msg)).is_err() {
+            break;
+        }
+    }
+    server.close_all();
+}
+
+/// An export running over a WebSocket
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportInfo {
+    pub id: u32,
+    pub url: String,
+    pub root: String,
+    pub read_only: bool,
+}
+
+struct Export {
+    info: ExportInfo,
+    #[cfg(target_arch = "wasm32")]
+    socket: web_sys::WebSocket,
+}
+
+thread_local! {
+    static EXPORTS: RefCell<Vec<Export>> = const { RefCell::new(Vec::new()) };
+    static NEXT_EXPORT: RefCell<u32> = const { RefCell::new(1) };
+}
+
+/// The exports running now
+pub fn exports() -> Vec<ExportInfo> {
+    EXPORTS.with(|e| e.borrow().iter().map(|e| e.info.clone()).collect())
+}
+
+/// Stop export `id`, or every export; returns how many stopped
+pub fn stop(id: Option<u32>) -> usize {
+    let stopped: Vec<Export> = EXPORTS.with(|e| {
+        let mut exports = e.borrow_mut();
+        let (stopped, kept) = exports
+            .drain(..)
+            .partition(|e| id.is_none_or(|id| e.info.id == id));
+        *exports = kept;
+        stopped
+    });
+    #[cfg(target_arch = "wasm32")]
+    for export in &stopped {
+        let _ = export.socket.close();
+    }
+    stopped.len()
+}
+
+/// Dial `url` and serve 9P to whatever the relay there connects us to
+///
+/// The relay passes binary WebSocket messages through to a 9P client,
+/// such as a `websockify` bridging to a TCP port `mount -t 9p` can reach.
+#[cfg(target_arch = "wasm32")]
+pub fn serve_websocket(url: &str, server: Server) -> Result<u32, String> {
+    use std::rc::Rc;
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen::prelude::*;
+
+    super::syscall::net_check(url).map_err(|e| super::network::net_refused(url, e))?;
+    let socket = web_sys::WebSocket::new(url).map_err(|e| format!("{:?}", e))?;
+    socket.set_binary_type(web_sys::BinaryType::Arraybuffer);
+    let id = NEXT_EXPORT.with(|n| {
+        let mut next = n.borrow_mut();
+        *next += 1;
+        *next - 1
+    });
+    let info = ExportInfo {
+        id,
+        url: url.to_string(),
+        root: server.root().to_string(),
+        read_only: server.is_read_only(),
+    };
+
+    let server = Rc::new(RefCell::new(server));
+    let framer = RefCell::new(Framer::default());
+    let reply_to = socket.clone();
+    let serving = server.clone();
+    let on_message = Closure::wrap(Box::new(move |e: web_sys::MessageEvent| {
+        let Ok(buffer) = e.data().dyn_into::<js_sys::ArrayBuffer>() else {
+            return;
+        };
+        let data = js_sys::Uint8Array::new(&buffer).to_vec();
+        match framer.borrow_mut().push(&data) {
+            Ok(messages) => {
+                for msg in messages {
+                    let reply = serving.borrow_mut().handle(&msg);
+                    let _ = reply_to.send_with_u8_array(&reply);
+                }
+            }
+            Err(e) => {
+                crate::console_log!("[9p] {}: closing", e);
+                let _ = reply_to.close();
+            }
+        }
+    }) as Box<dyn FnMut(_)>);
+    socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+    on_message.forget();
+
+    let on_close = Closure::wrap(Box::new(move |_e: web_sys::CloseEvent| {
+        server.borrow_mut().close_all();
+        EXPORTS.with(|e| e.borrow_mut().retain(|e| e.info.id != id));
+    }) as Box<dyn FnMut(_)>);
+    socket.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+    on_close.forget();
+
+    EXPORTS.with(|e| e.borrow_mut().push(Export { info, socket }));
+    Ok(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::syscall::{KERNEL, Kernel};
+
+    fn setup() {
+        KERNEL.with(|k| {
+            *k.borrow_mut() = Kernel::new();
+            let pid = k.borrow_mut().spawn_process("test", None);
+            k.borrow_mut().set_current(pid);
+        });
+        syscall::mkdir("/tmp/export").unwrap();
+        syscall::write_file("/tmp/export/hello", "hello, world\n").unwrap();
+        syscall::mkdir("/tmp/export/sub").unwrap();
+    }
+
+    /// Build a T-message from its body
+    fn t(kind: u8, body: impl FnOnce(&mut Writer)) -> Vec<u8> {
+        let mut w = Writer::default();
+        body(&mut w);
+        message(kind, 1, &w.0)
+    }
+
+    /// Send a message, expecting success, and return the reply's body
+    fn ok(server: &mut Server, msg: Vec<u8>) -> Vec<u8> {
+        let reply = server.handle(&msg);
+        if reply[4] == RERROR {
+            let text = Reader::new(&reply[7..]).string().unwrap();
+            panic!("Rerror: {}", text);
+        }
+        assert_eq!(reply[4], msg[4] + 1);
+        reply[7..].to_vec()
+    }
+
+    fn err(server: &mut Server, msg: Vec<u8>) -> String {
+        let reply = server.handle(&msg);
+        assert_eq!(reply[4], RERROR);
+        Reader::new(&reply[7..]).string().unwrap()
+    }
+
+    fn walk(fid: u32, newfid: u32, names: &[&str]) -> Vec<u8> {
+        t(TWALK, |w| {
+            w.u32(fid);
+            w.u32(newfid);
+            w.u16(names.len() as u16);
+            for name in names {
+                w.string(name);
+            }
+        })
+    }
+
+    fn attached() -> Server {
+        setup();
+        let mut server = Server::new("/tmp/export");
+        let reply = ok(
+            &mut server,
+            t(TVERSION, |w| {
+                w.u32(8192);
+                w.string("9P2000.L");
+            }),
+        );
+        assert_eq!(Reader::new(&reply[4..]).string().unwrap(), "9P2000");
+        ok(
+            &mut server,
+            t(TATTACH, |w| {
+                w.u32(0);
+                w.u32(u32::MAX);
+                w.string("ignored");
+                w.string("");
+            }),
+        );
+        server
+    }
+
+    #[test]
+    fn test_read_and_list() {
+        let mut server = attached();
+
+        let reply = ok(&mut server, walk(0, 1, &["hello"]));
+        assert_eq!(u16::from_le_bytes([reply[0], reply[1]]), 1);
+        ok(
+            &mut server,
+            t(TOPEN, |w| {
+                w.u32(1);
+                w.u8(OREAD);
+            }),
+        );
+        let reply = ok(
+            &mut server,
+            t(TREAD, |w| {
+                w.u32(1);
+                w.u64(7);
+                w.u32(100);
+            }),
+        );
+        assert_eq!(&reply[4..], b"world\n");
+
+        // The directory lists as stat entries, in name order
+        ok(&mut server, walk(0, 2, &[]));
+        ok(
+            &mut server,
+            t(TOPEN, |w| {
+                w.u32(2);
+                w.u8(OREAD);
+            }),
+        );
+        let reply = ok(
+            &mut server,
+            t(TREAD, |w| {
+                w.u32(2);
+                w.u64(0);
+                w.u32(4096);
+            }),
+        );
+        let mut r = Reader::new(&reply[4..]);
+        let mut names = Vec::new();
+        while r.pos < r.data.len() {
+            let size = r.u16().unwrap() as usize;
+            let mut entry = Reader::new(r.bytes(size).unwrap());
+            entry.bytes(2 + 4 + 13).unwrap();
+            let mode = entry.u32().unwrap();
+            entry.bytes(4 + 4 + 8).unwrap();
+            names.push((entry.string().unwrap(), mode & DMDIR != 0));
+        }
+        assert_eq!(
+            names,
+            vec![("hello".to_string(), false), ("sub".to_string(), true)]
+        );
+
+        // Can't walk above the root, or to what isn't there
+        let reply = ok(&mut server, walk(0, 3, &["..", "..", "hello"]));
+        assert_eq!(u16::from_le_bytes([reply[0], reply[1]]), 3);
+        assert_eq!(
+            err(&mut server, walk(0, 4, &["missing"])),
+            "file does not exist"
+        );
+        let reply = ok(&mut server, walk(0, 4, &["sub", "missing"]));
+        assert_eq!(u16::from_le_bytes([reply[0], reply[1]]), 1);
+        assert_eq!(err(&mut server, walk(4, 5, &[])), "unknown fid");
+    }
+
+    #[test]
+    fn test_create_write_remove() {
+        let mut server = attached();
+
+        ok(&mut server, walk(0, 1, &["sub"]));
+        ok(
+            &mut server,
+            t(TCREATE, |w| {
+                w.u32(1);
+                w.string("notes");
+                w.u32(0o600);
+                w.u8(ORDWR);
+            }),
+        );
+        let reply = ok(
+            &mut server,
+            t(TWRITE, |w| {
+                w.u32(1);
+                w.u64(0);
+                w.u32(5);
+                w.0.extend_from_slice(b"draft");
+            }),
+        );
+        assert_eq!(reply, 5u32.to_le_bytes());
+        ok(&mut server, t(TCLUNK, |w| w.u32(1)));
+        assert_eq!(
+            syscall::read_file("/tmp/export/sub/notes").unwrap(),
+            "draft"
+        );
+        assert_eq!(
+            syscall::metadata("/tmp/export/sub/notes").unwrap().mode & 0o777,
+            0o600
+        );
+
+        // Rename and cut short with wstat
+        ok(&mut server, walk(0, 2, &["sub", "notes"]));
+        ok(
+            &mut server,
+            t(TWSTAT, |w| {
+                let mut stat = Writer::default();
+                stat.u16(0);
+                stat.u16(u16::MAX);
+                stat.u32(u32::MAX);
+                stat.0.extend_from_slice(&[0xff; 13]);
+                stat.u32(u32::MAX);
+                stat.u32(u32::MAX);
+                stat.u32(u32::MAX);
+                stat.u64(3);
+                stat.string("final");
+                stat.string("");
+                stat.string("");
+                stat.string("");
+                w.u32(2);
+                w.u16(stat.0.len() as u16);
+                w.0.extend_from_slice(&stat.0);
+            }),
+        );
+        assert_eq!(syscall::read_file("/tmp/export/sub/final").unwrap(), "dra");
+        ok(&mut server, t(TREMOVE, |w| w.u32(2)));
+        assert!(!syscall::exists("/tmp/export/sub/final").unwrap());
+        assert_eq!(server.fid_count(), 1);
+    }
+
+    #[test]
+    fn test_read_only() {
+        let mut server = attached();
+        server.set_read_only(true);
+
+        ok(&mut server, walk(0, 1, &["hello"]));
+        let open = t(TOPEN, |w| {
+            w.u32(1);
+            w.u8(OWRITE | OTRUNC);
+        });
+        assert_eq!(err(&mut server, open), "read-only file system");
+        assert_eq!(
+            err(&mut server, t(TREMOVE, |w| w.u32(1))),
+            "read-only file system"
+        );
+        assert_eq!(
+            syscall::read_file("/tmp/export/hello").unwrap(),
+            "hello, world\n"
+        );
+
+        assert_eq!(
+            err(&mut server, t(TAUTH, |_| {})),
+            "authentication not required"
+        );
+        let reply = server.handle(&[3, 0, 0]);
+        assert_eq!(reply[4], RERROR);
+    }
+
+    #[test]
+    fn test_framer_and_channel() {
+        let mut framer = Framer::default();
+        let one = message(TCLUNK, 1, &[1, 0, 0, 0]);
+        let two = message(TCLUNK, 2, &[2, 0, 0, 0]);
+        let mut stream = one.clone();
+        stream.extend_from_slice(&two);
+        assert_eq!(framer.push(&stream[..5]).unwrap(), Vec::<Vec<u8>>::new());
+        assert_eq!(framer.push(&stream[5..]).unwrap(), vec![one, two]);
+        assert!(framer.push(&[2, 0, 0, 0]).is_err());
+
+        setup();
+        let (requests, from_client) = crate::kernel::ipc::channel();
+        let (to_client, replies) = crate::kernel::ipc::channel();
+        requests
+            .send(t(TVERSION, |w| {
+                w.u32(MAX_MSIZE * 2);
+                w.string(VERSION);
+            }))
+            .unwrap();
+        requests.close();
+        futures::executor::block_on(serve(Server::new("/"), from_client, to_client));
+        let reply = replies.try_recv().unwrap();
+        assert_eq!(Reader::new(&reply[7..]).u32().unwrap(), MAX_MSIZE);
+    }
+}
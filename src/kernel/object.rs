@@ -4,8 +4,12 @@
 //! them through handles (file descriptors). This provides isolation -
 //! a process can only access objects it has handles to.
 
+use super::devfs::DeviceOps;
+use super::epoll::EventQueue;
+use super::poll::PollEvents;
 use super::process::Handle;
 use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
 
 /// Window identifier for kernel window objects
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -33,6 +37,18 @@ pub enum KernelObject {
 
     /// A directory (for readdir)
     Directory(DirectoryObject),
+
+    /// A device node registered with devfs
+    Device(DeviceObject),
+
+    /// Signals of the reading process, as records (see `sys_signalfd`)
+    SignalFd(SignalFdObject),
+
+    /// A service's watchdog; writes pet it (see `sys_watchdog_open`)
+    Watchdog(WatchdogObject),
+
+    /// An epoll instance (see `sys_epoll_create`)
+    EventQueue(EventQueue),
 }
 
 impl KernelObject {
@@ -50,6 +66,23 @@ impl KernelObject {
                 io::ErrorKind::InvalidInput,
                 "cannot read from directory",
             )),
+            // Device handlers need the kernel; sys_read calls them
+            KernelObject::Device(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "device reads go through the kernel",
+            )),
+            KernelObject::SignalFd(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "signalfd reads go through the kernel",
+            )),
+            KernelObject::Watchdog(_) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot read from watchdog",
+            )),
+            KernelObject::EventQueue(_) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot read from event queue",
+            )),
         }
     }
 
@@ -64,6 +97,22 @@ impl KernelObject {
                 io::ErrorKind::InvalidInput,
                 "cannot write to directory",
             )),
+            KernelObject::Device(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "device writes go through the kernel",
+            )),
+            KernelObject::SignalFd(_) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot write to signalfd",
+            )),
+            KernelObject::Watchdog(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "watchdog writes go through the kernel",
+            )),
+            KernelObject::EventQueue(_) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot write to event queue",
+            )),
         }
     }
 
@@ -71,6 +120,8 @@ impl KernelObject {
     pub fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
         match self {
             KernelObject::File(f) => f.seek(pos),
+            // Devices are streams; seeking is a no-op like on a tty
+            KernelObject::Device(_) => Ok(0),
             _ => Err(io::Error::new(
                 io::ErrorKind::InvalidInput,
                 "object does not support seeking",
@@ -86,6 +137,25 @@ impl KernelObject {
             KernelObject::Console(_) => "console",
             KernelObject::Window(_) => "window",
             KernelObject::Directory(_) => "directory",
+            KernelObject::Device(_) => "device",
+            KernelObject::SignalFd(_) => "signalfd",
+            KernelObject::Watchdog(_) => "watchdog",
+            KernelObject::EventQueue(_) => "eventqueue",
+        }
+    }
+
+    /// Short description of what the object refers to, for inspectors
+    pub fn describe(&self) -> String {
+        match self {
+            KernelObject::File(f) => format!("{} ({} bytes)", f.path.display(), f.data.len()),
+            KernelObject::Pipe(p) => format!("{} bytes buffered", p.buffered()),
+            KernelObject::Console(_) => String::new(),
+            KernelObject::Window(w) => format!("window {}", w.window_id.0),
+            KernelObject::Directory(d) => d.path.display().to_string(),
+            KernelObject::Device(d) => d.path.display().to_string(),
+            KernelObject::SignalFd(s) => format!("mask {:#06x}", s.mask),
+            KernelObject::Watchdog(w) => w.service.clone(),
+            KernelObject::EventQueue(q) => format!("{} interests", q.len()),
         }
     }
 }
@@ -105,6 +175,10 @@ pub struct FileObject {
 }
 
 impl FileObject {
+    /// The largest a file may grow to; writes that would take it past
+    /// this fail with `FileTooLarge`
+    pub const MAX_SIZE: u64 = 1 << 30;
+
     pub fn new(path: PathBuf, data: Vec<u8>, readable: bool, writable: bool) -> Self {
         Self {
             path,
@@ -125,10 +199,10 @@ impl Read for FileObject {
             ));
         }
 
-        let pos = self.position as usize;
-        if pos >= self.data.len() {
+        if self.position >= self.data.len() as u64 {
             return Ok(0); // EOF
         }
+        let pos = self.position as usize;
 
         let available = &self.data[pos..];
         let to_read = buf.len().min(available.len());
@@ -147,14 +221,19 @@ impl Write for FileObject {
             ));
         }
 
-        let pos = self.position as usize;
+        let end = self
+            .position
+            .checked_add(buf.len() as u64)
+            .filter(|&end| end <= Self::MAX_SIZE)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::FileTooLarge, "file too large"))?;
+        let (pos, end) = (self.position as usize, end as usize);
 
         // Extend file if needed
-        if pos + buf.len() > self.data.len() {
-            self.data.resize(pos + buf.len(), 0);
+        if end > self.data.len() {
+            self.data.resize(end, 0);
         }
 
-        self.data[pos..pos + buf.len()].copy_from_slice(buf);
+        self.data[pos..end].copy_from_slice(buf);
         self.position += buf.len() as u64;
         Ok(buf.len())
     }
@@ -217,6 +296,22 @@ impl PipeObject {
     pub fn is_closed(&self) -> bool {
         self.write_closed && self.read_closed
     }
+
+    /// Bytes written but not yet read
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// What a read or write could do now without blocking
+    pub fn poll_ready(&self) -> PollEvents {
+        PollEvents {
+            readable: !self.buffer.is_empty() || self.write_closed,
+            writable: !self.read_closed && !self.write_closed && self.buffer.len() < self.capacity,
+            error: self.read_closed,
+            hangup: self.write_closed,
+            invalid: false,
+        }
+    }
 }
 
 impl Read for PipeObject {
@@ -304,6 +399,14 @@ impl ConsoleObject {
         self.input.clear();
     }
 
+    /// Readable once there is typed input; output never blocks
+    pub fn poll_ready(&self) -> PollEvents {
+        PollEvents {
+            readable: !self.input.is_empty(),
+            ..PollEvents::WRITE
+        }
+    }
+
     /// Clear the output buffer
     pub fn clear_output(&mut self) {
         self.output.clear();
@@ -417,10 +520,111 @@ impl DirectoryObject {
     }
 }
 
+/// An open device node - reads, writes and ioctls go to its handlers
+pub struct DeviceObject {
+    /// Path the device was opened at
+    pub path: PathBuf,
+    /// The device's handlers
+    pub ops: Rc<dyn DeviceOps>,
+    /// Bytes read or written so far
+    pub position: u64,
+}
+
+impl DeviceObject {
+    pub fn new(path: impl Into<PathBuf>, ops: Rc<dyn DeviceOps>) -> Self {
+        Self {
+            path: path.into(),
+            ops,
+            position: 0,
+        }
+    }
+}
+
+/// A signalfd - reads take pending signals in `mask` from the reader
+pub struct SignalFdObject {
+    /// Signals to read (bit N = signal N)
+    pub mask: u16,
+}
+
+/// A service's watchdog - each write pets it
+pub struct WatchdogObject {
+    /// Name of the service
+    pub service: String,
+}
+
+/// Spare file buffers, kept so opening a file can reuse the memory of one
+/// closed earlier instead of allocating for its contents
+///
+/// Only a few buffers are kept, and none bigger than [`Self::MAX_CAPACITY`],
+/// so the pool never holds on to much.
+#[derive(Default)]
+pub struct BufferPool {
+    free: Vec<Vec<u8>>,
+}
+
+impl BufferPool {
+    /// Most buffers kept
+    pub const MAX_BUFFERS: usize = 16;
+    /// Largest buffer kept, in bytes
+    pub const MAX_CAPACITY: usize = 1024 * 1024;
+
+    /// A zeroed buffer of `len` bytes, reusing a spare one if it fits
+    pub fn take(&mut self, len: usize) -> Vec<u8> {
+        let best = self
+            .free
+            .iter()
+            .enumerate()
+            .filter(|(_, buf)| buf.capacity() >= len)
+            .min_by_key(|(_, buf)| buf.capacity())
+            .map(|(i, _)| i);
+        let mut buf = match best {
+            Some(i) => self.free.swap_remove(i),
+            None => Vec::with_capacity(len),
+        };
+        buf.resize(len, 0);
+        buf
+    }
+
+    /// Keep `buf` for a later [`Self::take`], unless the pool is full or
+    /// it is too big to be worth holding
+    pub fn give(&mut self, mut buf: Vec<u8>) {
+        let capacity = buf.capacity();
+        if capacity == 0 || capacity > Self::MAX_CAPACITY || self.free.len() >= Self::MAX_BUFFERS {
+            return;
+        }
+        buf.clear();
+        self.free.push(buf);
+    }
+
+    /// Number of spare buffers
+    pub fn len(&self) -> usize {
+        self.free.len()
+    }
+
+    /// Whether there are no spare buffers
+    pub fn is_empty(&self) -> bool {
+        self.free.is_empty()
+    }
+
+    /// Bytes held by the spare buffers
+    pub fn held(&self) -> usize {
+        self.free.iter().map(Vec::capacity).sum()
+    }
+
+    /// Drop every spare buffer, returning the bytes freed
+    pub fn clear(&mut self) -> usize {
+        let held = self.held();
+        self.free = Vec::new();
+        held
+    }
+}
+
 /// An entry in the object table with reference count
 struct ObjectEntry {
     object: KernelObject,
     refcount: usize,
+    /// O_NONBLOCK, shared by every descriptor for the object
+    nonblock: bool,
 }
 
 /// The object table - maps handles to reference-counted objects
@@ -433,6 +637,8 @@ struct ObjectEntry {
 pub struct ObjectTable {
     next_id: u64,
     objects: HashMap<Handle, ObjectEntry>,
+    /// Buffers of released files, for the next file opened
+    pub buffers: BufferPool,
 }
 
 impl ObjectTable {
@@ -440,6 +646,7 @@ impl ObjectTable {
         Self {
             next_id: 1, // 0 is Handle::NULL
             objects: HashMap::new(),
+            buffers: BufferPool::default(),
         }
     }
 
@@ -452,6 +659,7 @@ impl ObjectTable {
             ObjectEntry {
                 object: obj,
                 refcount: 1,
+                nonblock: false,
             },
         );
         handle
@@ -488,6 +696,14 @@ impl ObjectTable {
         }
     }
 
+    /// Take back what a released object can lend to the next one: a
+    /// file's buffer goes to the pool
+    pub fn recycle(&mut self, object: KernelObject) {
+        if let KernelObject::File(file) = object {
+            self.buffers.give(file.data);
+        }
+    }
+
     /// Get the current reference count for a handle
     pub fn refcount(&self, handle: Handle) -> usize {
         self.objects.get(&handle).map(|e| e.refcount).unwrap_or(0)
@@ -503,11 +719,35 @@ impl ObjectTable {
         self.objects.get_mut(&handle).map(|e| &mut e.object)
     }
 
+    /// Check if reads through `handle` are non-blocking (O_NONBLOCK)
+    pub fn nonblocking(&self, handle: Handle) -> bool {
+        self.objects.get(&handle).is_some_and(|e| e.nonblock)
+    }
+
+    /// Make reads through `handle` non-blocking or not
+    /// Returns false if the handle doesn't exist
+    pub fn set_nonblocking(&mut self, handle: Handle, nonblock: bool) -> bool {
+        match self.objects.get_mut(&handle) {
+            Some(entry) => {
+                entry.nonblock = nonblock;
+                true
+            }
+            None => false,
+        }
+    }
+
     /// Check if a handle exists
     pub fn contains(&self, handle: Handle) -> bool {
         self.objects.contains_key(&handle)
     }
 
+    /// Iterate over all objects with their reference counts
+    pub fn iter(&self) -> impl Iterator<Item = (Handle, &KernelObject, usize)> {
+        self.objects
+            .iter()
+            .map(|(handle, e)| (*handle, &e.object, e.refcount))
+    }
+
     /// Get total number of objects
     pub fn len(&self) -> usize {
         self.objects.len()
@@ -681,4 +921,40 @@ mod tests {
         assert!(table.release(Handle(999)).is_none());
         assert!(table.release(Handle::NULL).is_none());
     }
+
+    #[test]
+    fn test_buffer_pool_reuses_buffers() {
+        let mut table = ObjectTable::new();
+        let data = b"secret".to_vec();
+        let h = table.insert(KernelObject::File(FileObject::new(
+            PathBuf::from("/a"),
+            data,
+            true,
+            false,
+        )));
+        let object = table.release(h).unwrap();
+        table.recycle(object);
+        assert_eq!(table.buffers.len(), 1);
+
+        // The reused buffer comes back zeroed, at the size asked for
+        let buf = table.buffers.take(4);
+        assert_eq!(buf, vec![0; 4]);
+        assert!(table.buffers.is_empty());
+
+        // Nothing fits: a fresh one
+        table.buffers.give(buf);
+        assert_eq!(table.buffers.take(100).len(), 100);
+        assert_eq!(table.buffers.len(), 1);
+
+        // Too big, or the pool is full: dropped
+        table.buffers.give(vec![0; BufferPool::MAX_CAPACITY + 1]);
+        for _ in 0..BufferPool::MAX_BUFFERS + 4 {
+            table.buffers.give(vec![0; 8]);
+        }
+        assert_eq!(table.buffers.len(), BufferPool::MAX_BUFFERS);
+        let held = table.buffers.held();
+        assert_eq!(held, 6 + 8 * (BufferPool::MAX_BUFFERS - 1));
+        assert_eq!(table.buffers.clear(), held);
+        assert!(table.buffers.is_empty());
+    }
 }
@@ -440,16 +440,11 @@ fn read_file(path: &str) -> PkgResult<String> {
     String::from_utf8(content).map_err(|_| PkgError::IoError(format!("{}: invalid UTF-8", path)))
 }
 
+/// Replace a database file in one step, so an interrupted install can't
+/// leave it half-written
 fn write_file(path: &str, content: &str) -> PkgResult<()> {
-    let fd = syscall::open(path, syscall::OpenFlags::WRITE)
-        .map_err(|e| PkgError::IoError(format!("{}: {}", path, e)))?;
-
-    syscall::write(fd, content.as_bytes())
-        .map_err(|e| PkgError::IoError(format!("{}: {}", path, e)))?;
-
-    syscall::close(fd).map_err(|e| PkgError::IoError(format!("{}: {}", path, e)))?;
-
-    Ok(())
+    syscall::atomic_write(path, content.as_bytes())
+        .map_err(|e| PkgError::IoError(format!("{}: {}", path, e)))
 }
 
 fn parse_array(s: &str) -> Vec<String> {
@@ -528,6 +523,7 @@ mod tests {
                 name: "test".to_string(),
                 path: "bin/test.wasm".to_string(),
                 checksum: None,
+                setuid: false,
             }],
             dependencies: vec![],
             dev_dependencies: vec![],
@@ -29,6 +29,7 @@ use super::database::{InstalledPackage, PackageDatabase};
 use super::error::{PkgError, PkgResult};
 use super::manifest::PackageManifest;
 use super::paths;
+use super::pipeline;
 use super::registry::PackageRegistry;
 use super::resolver::ResolvedPackage;
 use crate::kernel::syscall;
@@ -42,6 +43,8 @@ pub struct PackageInstaller {
     verify_checksums: bool,
     /// Whether to keep cached archives
     keep_cache: bool,
+    /// Archives downloaded at once
+    parallel_downloads: usize,
 }
 
 impl PackageInstaller {
@@ -50,6 +53,7 @@ impl PackageInstaller {
         Self {
             verify_checksums: true,
             keep_cache: true,
+            parallel_downloads: pipeline::DEFAULT_PARALLEL,
         }
     }
 
@@ -63,6 +67,16 @@ impl PackageInstaller {
         self.keep_cache = keep;
     }
 
+    /// Set how many archives are downloaded at once (at least one)
+    pub fn set_parallel_downloads(&mut self, parallel: usize) {
+        self.parallel_downloads = parallel.max(1);
+    }
+
+    /// How many archives are downloaded at once
+    pub fn parallel_downloads(&self) -> usize {
+        self.parallel_downloads
+    }
+
     /// Install a resolved package from the registry
     #[cfg(target_arch = "wasm32")]
     pub async fn install(
@@ -87,7 +101,7 @@ impl PackageInstaller {
 
     /// Cache archive to disk (WASM only)
     #[cfg(target_arch = "wasm32")]
-    fn cache_archive(&self, _id: &super::PackageId, _data: &[u8]) -> PkgResult<()> {
+    pub(super) fn cache_archive(&self, _id: &super::PackageId, _data: &[u8]) -> PkgResult<()> {
         // Placeholder - would write to VFS cache directory
         Ok(())
     }
@@ -123,6 +137,13 @@ impl PackageInstaller {
 
     /// Install from archive bytes
     fn install_from_archive(&self, data: &[u8]) -> PkgResult<PackageManifest> {
+        let archive = self.open_archive(data)?;
+        self.install_archive(&archive)?;
+        Ok(archive.manifest)
+    }
+
+    /// Parse an archive and verify its checksums, if enabled
+    pub(super) fn open_archive(&self, data: &[u8]) -> PkgResult<PackageArchive> {
         // Parse the archive
         let archive = self.parse_archive(data)?;
 
@@ -142,6 +163,11 @@ impl PackageInstaller {
             }
         }
 
+        Ok(archive)
+    }
+
+    /// Install the binaries of an opened archive
+    pub(super) fn install_archive(&self, archive: &PackageArchive) -> PkgResult<()> {
         // Install binaries to /bin
         for bin_entry in &archive.manifest.binaries {
             // Find the binary data
@@ -154,12 +180,13 @@ impl PackageInstaller {
                 let dest_path = format!("{}/{}.wasm", paths::BIN_DIR, bin_entry.name);
                 write_file_bytes(&dest_path, data)?;
 
-                // Make executable
-                let _ = syscall::chmod(&dest_path, 0o755);
+                // Make executable, and setuid if the manifest asks
+                let mode = if bin_entry.setuid { 0o4755 } else { 0o755 };
+                let _ = syscall::chmod(&dest_path, mode);
             }
         }
 
-        Ok(archive.manifest)
+        Ok(())
     }
 
     /// Parse a package archive
@@ -263,6 +290,7 @@ impl PackageInstaller {
                     name: "unknown".to_string(),
                     path: "bin/unknown.wasm".to_string(),
                     checksum: Some(Checksum::compute(data)),
+                    setuid: false,
                 }],
                 dependencies: vec![],
                 dev_dependencies: vec![],
@@ -368,18 +396,18 @@ impl Default for PackageInstaller {
 }
 
 /// Parsed package archive
-struct PackageArchive {
-    manifest: PackageManifest,
+pub(super) struct PackageArchive {
+    pub(super) manifest: PackageManifest,
     files: Vec<(String, Vec<u8>)>,
 }
 
 // Helper functions
 
-fn path_exists(path: &str) -> bool {
+pub(super) fn path_exists(path: &str) -> bool {
     syscall::exists(path).unwrap_or(false)
 }
 
-fn mkdir_recursive(path: &str) -> PkgResult<()> {
+pub(super) fn mkdir_recursive(path: &str) -> PkgResult<()> {
     let parts: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
     let mut current = String::new();
 
@@ -422,7 +450,7 @@ fn read_file_bytes(path: &str) -> PkgResult<Vec<u8>> {
     Ok(content)
 }
 
-fn write_file_bytes(path: &str, data: &[u8]) -> PkgResult<()> {
+pub(super) fn write_file_bytes(path: &str, data: &[u8]) -> PkgResult<()> {
     // Ensure parent directory exists
     if let Some(pos) = path.rfind('/') {
         let parent = &path[..pos];
@@ -450,6 +478,7 @@ mod tests {
         let installer = PackageInstaller::new();
         assert!(installer.verify_checksums);
         assert!(installer.keep_cache);
+        assert_eq!(installer.parallel_downloads(), pipeline::DEFAULT_PARALLEL);
     }
 
     #[test]
@@ -16,6 +16,7 @@
 //! [[bin]]
 //! name = "hello"
 //! path = "bin/hello.wasm"
+//! # setuid = true   # run as the owner (root), like chmod u+s
 //!
 //! [dependencies]
 //! utils = "^1.0"
@@ -62,6 +63,9 @@ pub struct BinaryEntry {
     pub path: String,
     /// SHA-256 checksum
     pub checksum: Option<Checksum>,
+    /// Installed setuid: runs with the effective uid of its owner, root,
+    /// for commands such as `passwd` that change files users can't
+    pub setuid: bool,
 }
 
 /// A package dependency
@@ -126,6 +130,9 @@ impl PackageManifest {
             if let Some(ref checksum) = bin.checksum {
                 output.push_str(&format!("checksum = \"{}\"\n", checksum));
             }
+            if bin.setuid {
+                output.push_str("setuid = true\n");
+            }
         }
 
         // [dependencies] section
@@ -427,12 +434,14 @@ impl<'a> TomlParser<'a> {
         let mut name = None;
         let mut path = None;
         let mut checksum = None;
+        let mut setuid = false;
 
         self.parse_section(|key, value| {
             match key {
                 "name" => name = Some(value.to_string()),
                 "path" => path = Some(value.to_string()),
                 "checksum" => checksum = Some(Checksum::from_hex(value)?),
+                "setuid" => setuid = value == "true",
                 _ => {}
             }
             Ok(())
@@ -447,6 +456,7 @@ impl<'a> TomlParser<'a> {
             name,
             path,
             checksum,
+            setuid,
         })
     }
 
@@ -585,6 +595,26 @@ path = "bin/hello.wasm"
         assert_eq!(manifest.binaries[0].path, "bin/hello.wasm");
     }
 
+    #[test]
+    fn test_parse_setuid_bin() {
+        let toml = r#"
+[package]
+name = "passwd"
+version = "1.0.0"
+
+[[bin]]
+name = "passwd"
+path = "bin/passwd.wasm"
+setuid = true
+"#;
+
+        let manifest = PackageManifest::parse(toml).unwrap();
+        assert!(manifest.binaries[0].setuid);
+        assert!(manifest.to_toml().contains("setuid = true\n"));
+        let again = PackageManifest::parse(&manifest.to_toml()).unwrap();
+        assert!(again.binaries[0].setuid);
+    }
+
     #[test]
     fn test_parse_manifest_with_dependencies() {
         let toml = r#"
@@ -641,6 +671,7 @@ optional-dep = { version = "^1.0.0", optional = true }
                 name: "test".to_string(),
                 path: "bin/test.wasm".to_string(),
                 checksum: None,
+                setuid: false,
             }],
             dependencies: vec![Dependency {
                 name: "dep1".to_string(),
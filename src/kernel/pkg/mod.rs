@@ -8,6 +8,7 @@
 //! - **Semantic versioning**: Full semver support with version constraints
 //! - **Dependency resolution**: Automatic resolution with conflict detection
 //! - **Package registry**: Download packages from remote registries
+//! - **Parallel downloads**: Fetch several archives at once, with progress
 //! - **Security**: SHA-256 checksums for integrity verification
 //! - **Local database**: Track installed packages and their metadata
 //!
@@ -78,8 +79,10 @@ mod database;
 mod error;
 mod installer;
 mod manifest;
+pub mod pipeline;
 mod registry;
 mod resolver;
+mod template;
 mod version;
 
 pub use checksum::{Checksum, verify_checksum};
@@ -87,11 +90,17 @@ pub use database::{InstalledPackage, PackageDatabase};
 pub use error::{PkgError, PkgResult};
 pub use installer::PackageInstaller;
 pub use manifest::{BinaryEntry, Dependency, PackageManifest};
+pub use pipeline::{Progress, Stage, Tracker};
 pub use registry::{PackageRegistry, RegistryEntry};
 pub use resolver::{DependencyResolver, ResolvedPackage};
+pub use template::{ProjectTemplate, validate_name};
 pub use version::{Version, VersionReq};
 
 use std::collections::HashMap;
+use std::future::Future;
+#[cfg(target_arch = "wasm32")]
+use std::pin::Pin;
+use std::rc::Rc;
 
 /// Package manager paths
 pub mod paths {
@@ -161,6 +170,8 @@ pub struct PackageManager {
     pub installer: PackageInstaller,
     /// Dependency resolver
     pub resolver: DependencyResolver,
+    /// Called as downloads and installs move along
+    progress: Rc<dyn Fn(&Progress)>,
 }
 
 impl PackageManager {
@@ -171,9 +182,15 @@ impl PackageManager {
             registry: PackageRegistry::new(),
             installer: PackageInstaller::new(),
             resolver: DependencyResolver::new(),
+            progress: Rc::new(|_| {}),
         }
     }
 
+    /// Call `f` with the progress of every package as installs move along
+    pub fn on_progress(&mut self, f: impl Fn(&Progress) + 'static) {
+        self.progress = Rc::new(f);
+    }
+
     /// Initialize package manager directories
     pub fn init(&self) -> PkgResult<()> {
         self.database.init()
@@ -218,16 +235,15 @@ impl PackageManager {
         // Resolve dependencies
         let resolved = self.resolver.resolve(&pkg_id, &self.registry).await?;
 
-        // Install all resolved packages
-        for pkg in resolved {
-            if !self
-                .database
-                .is_installed(&pkg.id.name, Some(&pkg.id.version))?
-            {
-                self.installer.install(&pkg, &self.registry).await?;
-                self.database.record_installed(&pkg.id, &pkg.manifest)?;
-            }
-        }
+        // Download in parallel, install in order
+        install_plan(
+            &mut self.database,
+            &self.installer,
+            &self.progress,
+            resolved,
+            registry_fetch(&self.registry),
+        )
+        .await?;
 
         Ok(pkg_id)
     }
@@ -244,6 +260,31 @@ impl PackageManager {
         self.installer.install_local(path, &mut self.database)
     }
 
+    /// Install resolved packages, downloading them with `fetch`
+    ///
+    /// Up to [`PackageInstaller::parallel_downloads`] archives are fetched at
+    /// once and each is verified as it arrives; once all are in, they are
+    /// installed in dependency order. A package already installed at another
+    /// version is replaced. Nothing is installed if any download fails.
+    pub async fn install_with<F, Fut>(
+        &mut self,
+        plan: Vec<ResolvedPackage>,
+        fetch: F,
+    ) -> PkgResult<()>
+    where
+        F: Fn(usize, &ResolvedPackage, Tracker) -> Fut,
+        Fut: Future<Output = PkgResult<Vec<u8>>>,
+    {
+        install_plan(
+            &mut self.database,
+            &self.installer,
+            &self.progress,
+            plan,
+            fetch,
+        )
+        .await
+    }
+
     /// Remove an installed package
     pub fn remove(&mut self, name: &str) -> PkgResult<()> {
         // Find installed package
@@ -312,26 +353,36 @@ impl PackageManager {
     }
 
     /// Upgrade all installed packages to latest versions
+    ///
+    /// Every upgrade and the dependencies it needs are downloaded together,
+    /// then installed in dependency order.
     #[cfg(target_arch = "wasm32")]
     pub async fn upgrade_all(&mut self) -> PkgResult<Vec<PackageId>> {
         let installed = self.list_installed()?;
         let mut upgraded = Vec::new();
+        let mut plan = Vec::new();
 
         for pkg in installed {
             // Check for newer version
-            if let Ok(entry) = self.registry.fetch_package(&pkg.name).await {
-                if let Some(latest) = entry.versions.iter().max() {
-                    if latest > &pkg.version {
-                        // Remove old version
-                        self.remove(&pkg.name)?;
-                        // Install new version
-                        let new_id = self.install(&pkg.name, None).await?;
-                        upgraded.push(new_id);
-                    }
-                }
+            if let Ok(entry) = self.registry.fetch_package(&pkg.name).await
+                && let Some(latest) = entry.versions.iter().max()
+                && latest > &pkg.version
+            {
+                let id = PackageId::new(&pkg.name, latest.clone());
+                let mut resolver = DependencyResolver::new();
+                plan = merge_plans(plan, resolver.resolve(&id, &self.registry).await?);
+                upgraded.push(id);
             }
         }
 
+        install_plan(
+            &mut self.database,
+            &self.installer,
+            &self.progress,
+            plan,
+            registry_fetch(&self.registry),
+        )
+        .await?;
         Ok(upgraded)
     }
 
@@ -366,6 +417,109 @@ impl Default for PackageManager {
     }
 }
 
+/// Download, verify and install `plan`
+///
+/// Takes the manager's parts rather than the manager, so `fetch` can
+/// borrow the registry.
+async fn install_plan<F, Fut>(
+    database: &mut PackageDatabase,
+    installer: &PackageInstaller,
+    progress: &Rc<dyn Fn(&Progress)>,
+    plan: Vec<ResolvedPackage>,
+    fetch: F,
+) -> PkgResult<()>
+where
+    F: Fn(usize, &ResolvedPackage, Tracker) -> Fut,
+    Fut: Future<Output = PkgResult<Vec<u8>>>,
+{
+    let mut pending = Vec::new();
+    for pkg in plan {
+        if !database.is_installed(&pkg.id.name, Some(&pkg.id.version))? {
+            pending.push(pkg);
+        }
+    }
+    pending.sort_by_key(|pkg| pkg.order);
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    let ids = pending.iter().map(|pkg| pkg.id.clone()).collect();
+    let progress = progress.clone();
+    let tracker = Tracker::new(ids, move |p| progress(p));
+    let archives = pipeline::download_all(
+        &pending,
+        installer.parallel_downloads(),
+        &tracker,
+        fetch,
+        |_, data| installer.open_archive(data),
+    )
+    .await?;
+
+    for (index, (pkg, archive)) in pending.iter().zip(&archives).enumerate() {
+        tracker.set_stage(index, Stage::Installing);
+        let installed = replace_package(database, installer, pkg, archive);
+        if let Err(e) = installed {
+            tracker.set_stage(index, Stage::Failed(e.to_string()));
+            return Err(e);
+        }
+        tracker.set_stage(index, Stage::Installed);
+    }
+    Ok(())
+}
+
+/// Install one package, removing any other version of it first
+fn replace_package(
+    database: &mut PackageDatabase,
+    installer: &PackageInstaller,
+    pkg: &ResolvedPackage,
+    archive: &installer::PackageArchive,
+) -> PkgResult<()> {
+    // An upgrade swaps the package under its dependents
+    if let Some(old) = database.get_installed(&pkg.id.name)? {
+        installer.remove(&old)?;
+        database.remove_installed(&pkg.id.name)?;
+    }
+    installer.install_archive(archive)?;
+    database.record_installed(&pkg.id, &pkg.manifest)
+}
+
+/// Fetch archives from `registry`, reporting bytes as they arrive
+#[cfg(target_arch = "wasm32")]
+fn registry_fetch<'a>(
+    registry: &'a PackageRegistry,
+) -> impl Fn(usize, &ResolvedPackage, Tracker) -> Pin<Box<dyn Future<Output = PkgResult<Vec<u8>>> + 'a>>
++ 'a {
+    move |index, pkg, tracker| {
+        let id = pkg.id.clone();
+        Box::pin(async move {
+            registry
+                .download_package_with_progress(&id.name, &id.version, |received, total| {
+                    tracker.set_received(index, received, total)
+                })
+                .await
+        })
+    }
+}
+
+/// Join resolved plans, keeping the first of each package
+///
+/// Each plan lists dependencies before what needs them, and so does the
+/// result.
+#[cfg(target_arch = "wasm32")]
+fn merge_plans(mut plan: Vec<ResolvedPackage>, more: Vec<ResolvedPackage>) -> Vec<ResolvedPackage> {
+    let mut sorted = more;
+    sorted.sort_by_key(|pkg| pkg.order);
+    for pkg in sorted {
+        if !plan.iter().any(|p| p.id.name == pkg.id.name) {
+            plan.push(pkg);
+        }
+    }
+    for (order, pkg) in plan.iter_mut().enumerate() {
+        pkg.order = order;
+    }
+    plan
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -403,4 +557,91 @@ mod tests {
         let id = PackageId::new("hello", Version::new(1, 2, 3));
         assert_eq!(format!("{}", id), "hello-1.2.3");
     }
+
+    /// A package with one binary, as the registry would serve it
+    fn package(name: &str, version: &str, order: usize, wasm: &[u8]) -> (ResolvedPackage, Vec<u8>) {
+        let manifest = PackageManifest::parse(&format!(
+            "[package]\nname = \"{name}\"\nversion = \"{version}\"\n\n\
+             [[bin]]\nname = \"{name}\"\npath = \"bin/{name}.wasm\"\nchecksum = \"{}\"\n",
+            Checksum::compute(wasm)
+        ))
+        .unwrap();
+        let toml = manifest.to_toml();
+        let path = format!("bin/{}.wasm", name);
+
+        let mut archive = b"AXEPKG\x00\x01".to_vec();
+        archive.extend((toml.len() as u32).to_le_bytes());
+        archive.extend(1u32.to_le_bytes());
+        archive.extend(toml.as_bytes());
+        archive.extend((path.len() as u16).to_le_bytes());
+        archive.extend(path.as_bytes());
+        archive.extend((wasm.len() as u32).to_le_bytes());
+        archive.extend(wasm);
+
+        let resolved = ResolvedPackage {
+            id: PackageId::new(name, manifest.version.clone()),
+            manifest,
+            dependencies: vec![],
+            order,
+        };
+        (resolved, archive)
+    }
+
+    #[test]
+    fn test_install_with() {
+        use crate::kernel::Uid;
+        use crate::kernel::syscall::{self, KERNEL, Kernel};
+        use std::cell::RefCell;
+
+        KERNEL.with(|k| {
+            *k.borrow_mut() = Kernel::new();
+            let pid = k.borrow_mut().spawn_process("test", None);
+            k.borrow_mut().set_current(pid);
+            k.borrow_mut().current_process_mut().unwrap().euid = Uid::ROOT;
+        });
+        let mut pm = PackageManager::new();
+        pm.init().unwrap();
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let record = seen.clone();
+        pm.on_progress(move |p| record.borrow_mut().push(p.render()));
+
+        // Listed out of order; the library goes in first
+        let (app, app_archive) = package("app", "1.0.0", 1, b"\0asm app");
+        let (lib, lib_archive) = package("lib", "1.0.0", 0, b"\0asm lib");
+        let archives = [lib_archive, app_archive.clone()];
+        futures::executor::block_on(pm.install_with(vec![app, lib], |index, _, _| {
+            let data = archives[index].clone();
+            async move { Ok(data) }
+        }))
+        .unwrap();
+        assert_eq!(syscall::read_file("/bin/app.wasm").unwrap(), "\0asm app");
+        assert!(pm.database.is_installed("lib", None).unwrap());
+        let last = seen.borrow().last().cloned().unwrap();
+        assert!(last[0].starts_with("lib-1.0.0") && last[0].ends_with("installed"));
+        assert!(last[1].starts_with("app-1.0.0"));
+
+        // A newer version replaces the old one
+        let (app2, app2_archive) = package("app", "2.0.0", 0, b"\0asm app 2");
+        futures::executor::block_on(pm.install_with(vec![app2.clone()], |_, _, _| {
+            let data = app2_archive.clone();
+            async move { Ok(data) }
+        }))
+        .unwrap();
+        assert_eq!(syscall::read_file("/bin/app.wasm").unwrap(), "\0asm app 2");
+        let installed = pm.database.get_installed("app").unwrap().unwrap();
+        assert_eq!(installed.version, Version::new(2, 0, 0));
+
+        // A bad checksum stops everything before installing
+        let (lib2, _) = package("lib", "2.0.0", 0, b"\0asm lib 2");
+        let result = futures::executor::block_on(pm.install_with(vec![lib2], |_, _, _| {
+            let (_, tampered) = package("lib", "2.0.0", 0, b"\0asm evil");
+            let mut data = tampered;
+            let len = data.len();
+            data[len - 1] ^= 1;
+            async move { Ok(data) }
+        }));
+        assert!(matches!(result, Err(PkgError::ChecksumMismatch { .. })));
+        let installed = pm.database.get_installed("lib").unwrap().unwrap();
+        assert_eq!(installed.version, Version::new(1, 0, 0));
+    }
 }
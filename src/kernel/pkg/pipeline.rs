@@ -0,0 +1,414 @@
+//! Parallel download pipeline
+//!
+//! Installing a package with dependencies, or upgrading everything,
+//! downloads several archives. [`download_all`] keeps up to a set number of
+//! them in flight at once and verifies each one as soon as it arrives, so a
+//! bad checksum stops the run before anything is installed. The caller then
+//! installs the verified archives in dependency order.
+//!
+//! A [`Tracker`] follows every package through the run and calls back
+//! whenever what [`Progress::render`] would show changes, which is how the
+//! shell draws one progress bar per package.
+
+use super::PackageId;
+use super::error::PkgResult;
+use super::resolver::ResolvedPackage;
+use futures::StreamExt;
+use std::cell::RefCell;
+use std::future::Future;
+use std::rc::Rc;
+
+/// Downloads in flight at once unless told otherwise
+pub const DEFAULT_PARALLEL: usize = 4;
+
+/// Width of a progress bar, brackets excluded
+const BAR_WIDTH: usize = 20;
+
+/// Where a package is in the pipeline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stage {
+    /// Waiting for a download slot
+    Queued,
+    /// Being downloaded
+    Downloading,
+    /// Downloaded and checked, waiting to be installed
+    Verified,
+    /// Being installed
+    Installing,
+    /// Done
+    Installed,
+    /// Stopped, with the reason
+    Failed(String),
+}
+
+/// One package's progress
+#[derive(Debug, Clone)]
+pub struct Transfer {
+    /// The package
+    pub id: PackageId,
+    /// Where it is
+    pub stage: Stage,
+    /// Bytes downloaded so far
+    pub received: u64,
+    /// Size of the archive, once the server has said
+    pub total: Option<u64>,
+}
+
+impl Transfer {
+    /// How far along the package is, from 0 to 100
+    fn percent(&self) -> usize {
+        match self.stage {
+            Stage::Queued | Stage::Failed(_) => 0,
+            Stage::Downloading => match self.total {
+                Some(0) | None => 0,
+                Some(total) => (self.received.min(total) * 100 / total) as usize,
+            },
+            Stage::Verified | Stage::Installing | Stage::Installed => 100,
+        }
+    }
+}
+
+/// Every package in a run
+#[derive(Debug, Clone, Default)]
+pub struct Progress {
+    /// In the order the packages were given
+    pub transfers: Vec<Transfer>,
+}
+
+impl Progress {
+    /// Render one line per package: name, bar, percentage and stage
+    pub fn render(&self) -> Vec<String> {
+        let width = self
+            .transfers
+            .iter()
+            .map(|t| t.id.to_string().len())
+            .max()
+            .unwrap_or(0);
+        self.transfers
+            .iter()
+            .map(|t| {
+                let percent = t.percent();
+                let filled = percent * BAR_WIDTH / 100;
+                let status = match &t.stage {
+                    Stage::Queued => "queued".to_string(),
+                    Stage::Downloading => match t.total {
+                        Some(total) => {
+                            format!("{}/{}", format_size(t.received), format_size(total))
+                        }
+                        None => format_size(t.received),
+                    },
+                    Stage::Verified => "verified".to_string(),
+                    Stage::Installing => "installing".to_string(),
+                    Stage::Installed => "installed".to_string(),
+                    Stage::Failed(reason) => format!("failed: {}", reason),
+                };
+                format!(
+                    "{:<width$} [{}{}] {:>3}% {}",
+                    t.id.to_string(),
+                    "#".repeat(filled),
+                    "-".repeat(BAR_WIDTH - filled),
+                    percent,
+                    status,
+                    width = width
+                )
+            })
+            .collect()
+    }
+
+    /// Check if every package is installed
+    pub fn is_done(&self) -> bool {
+        self.transfers.iter().all(|t| t.stage == Stage::Installed)
+    }
+
+    /// Check if any package failed
+    pub fn is_failed(&self) -> bool {
+        self.transfers
+            .iter()
+            .any(|t| matches!(t.stage, Stage::Failed(_)))
+    }
+
+    /// How far along the whole run is, from 0 to 100
+    pub fn percent(&self) -> u8 {
+        let total: usize = self.transfers.iter().map(Transfer::percent).sum();
+        (total / self.transfers.len().max(1)) as u8
+    }
+}
+
+/// Shared handle on a run's [`Progress`]
+///
+/// Clones share the same progress, so each download can hold one.
+#[derive(Clone)]
+pub struct Tracker {
+    progress: Rc<RefCell<Progress>>,
+    on_change: Rc<dyn Fn(&Progress)>,
+}
+
+impl Tracker {
+    /// Track `ids`, calling `on_change` whenever the rendering changes
+    pub fn new(ids: Vec<PackageId>, on_change: impl Fn(&Progress) + 'static) -> Self {
+        let transfers = ids
+            .into_iter()
+            .map(|id| Transfer {
+                id,
+                stage: Stage::Queued,
+                received: 0,
+                total: None,
+            })
+            .collect();
+        Self {
+            progress: Rc::new(RefCell::new(Progress { transfers })),
+            on_change: Rc::new(on_change),
+        }
+    }
+
+    /// Track `ids` without showing anything
+    pub fn quiet(ids: Vec<PackageId>) -> Self {
+        Self::new(ids, |_| {})
+    }
+
+    /// Move package `index` to `stage`
+    pub fn set_stage(&self, index: usize, stage: Stage) {
+        self.update(index, |t| t.stage = stage);
+    }
+
+    /// Record that package `index` has `received` of `total` bytes
+    pub fn set_received(&self, index: usize, received: u64, total: Option<u64>) {
+        self.update(index, |t| {
+            t.received = received;
+            t.total = total;
+        });
+    }
+
+    /// A copy of the progress as it stands
+    pub fn snapshot(&self) -> Progress {
+        self.progress.borrow().clone()
+    }
+
+    fn update(&self, index: usize, change: impl FnOnce(&mut Transfer)) {
+        let changed = {
+            let mut progress = self.progress.borrow_mut();
+            let Some(transfer) = progress.transfers.get_mut(index) else {
+                return;
+            };
+            // Only redraw when the line would look different
+            let before = (transfer.stage.clone(), transfer.percent(), shown(transfer));
+            change(transfer);
+            before != (transfer.stage.clone(), transfer.percent(), shown(transfer))
+        };
+        if changed {
+            (self.on_change)(&self.progress.borrow());
+        }
+    }
+}
+
+/// The byte counts as rendered, to tell whether a line changed
+fn shown(transfer: &Transfer) -> (String, Option<String>) {
+    (
+        format_size(transfer.received),
+        transfer.total.map(format_size),
+    )
+}
+
+/// Download and verify `packages`, at most `parallel` at a time
+///
+/// `fetch` downloads package `index`, reporting bytes to the tracker it is
+/// handed, and `verify` checks what came back. The results are returned in the
+/// order of `packages`. On the first failure the downloads still running
+/// are dropped, marked failed, and the error is returned.
+pub async fn download_all<T, F, Fut, V>(
+    packages: &[ResolvedPackage],
+    parallel: usize,
+    tracker: &Tracker,
+    fetch: F,
+    verify: V,
+) -> PkgResult<Vec<T>>
+where
+    F: Fn(usize, &ResolvedPackage, Tracker) -> Fut,
+    Fut: Future<Output = PkgResult<Vec<u8>>>,
+    V: Fn(&ResolvedPackage, &[u8]) -> PkgResult<T>,
+{
+    let mut results: Vec<Option<T>> = packages.iter().map(|_| None).collect();
+    let mut downloads = futures::stream::iter(packages.iter().enumerate())
+        .map(|(index, package)| {
+            let download = fetch(index, package, tracker.clone());
+            async move {
+                tracker.set_stage(index, Stage::Downloading);
+                (index, download.await)
+            }
+        })
+        .buffer_unordered(parallel.max(1));
+
+    while let Some((index, downloaded)) = downloads.next().await {
+        match downloaded.and_then(|data| verify(&packages[index], &data)) {
+            Ok(verified) => {
+                tracker.set_stage(index, Stage::Verified);
+                results[index] = Some(verified);
+            }
+            Err(e) => {
+                tracker.set_stage(index, Stage::Failed(e.to_string()));
+                drop(downloads);
+                for (other, result) in results.iter().enumerate() {
+                    if other != index && result.is_none() {
+                        tracker.set_stage(other, Stage::Failed("cancelled".to_string()));
+                    }
+                }
+                return Err(e);
+            }
+        }
+    }
+    Ok(results.into_iter().flatten().collect())
+}
+
+/// A byte count in K, M or G
+fn format_size(bytes: u64) -> String {
+    let bytes = bytes as f64;
+    if bytes >= 1024.0 * 1024.0 * 1024.0 {
+        format!("{:.1}G", bytes / (1024.0 * 1024.0 * 1024.0))
+    } else if bytes >= 1024.0 * 1024.0 {
+        format!("{:.1}M", bytes / (1024.0 * 1024.0))
+    } else if bytes >= 1024.0 {
+        format!("{:.1}K", bytes / 1024.0)
+    } else {
+        format!("{}B", bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::pkg::{PackageManifest, PkgError, Version};
+    use std::cell::Cell;
+
+    fn package(name: &str, order: usize) -> ResolvedPackage {
+        let manifest = PackageManifest::parse(&format!(
+            "[package]\nname = \"{}\"\nversion = \"1.0.0\"\n",
+            name
+        ))
+        .unwrap();
+        ResolvedPackage {
+            id: PackageId::new(name, Version::new(1, 0, 0)),
+            manifest,
+            dependencies: vec![],
+            order,
+        }
+    }
+
+    /// Let the other downloads run
+    async fn yield_now() {
+        let mut yielded = false;
+        futures::future::poll_fn(|cx| {
+            if yielded {
+                std::task::Poll::Ready(())
+            } else {
+                yielded = true;
+                cx.waker().wake_by_ref();
+                std::task::Poll::Pending
+            }
+        })
+        .await
+    }
+
+    fn ids(packages: &[ResolvedPackage]) -> Vec<PackageId> {
+        packages.iter().map(|p| p.id.clone()).collect()
+    }
+
+    #[test]
+    fn test_download_all_parallel() {
+        let packages: Vec<_> = (0..6).map(|i| package(&format!("p{}", i), i)).collect();
+        let tracker = Tracker::quiet(ids(&packages));
+        let in_flight = Cell::new(0);
+        let most = Cell::new(0);
+
+        let fetch = |index: usize, _: &ResolvedPackage, tracker: Tracker| {
+            let (in_flight, most) = (&in_flight, &most);
+            async move {
+                in_flight.set(in_flight.get() + 1);
+                most.set(most.get().max(in_flight.get()));
+                // Later packages finish first
+                for _ in 0..(6 - index) {
+                    tracker.set_received(index, 50, Some(100));
+                    yield_now().await;
+                }
+                in_flight.set(in_flight.get() - 1);
+                Ok(vec![index as u8])
+            }
+        };
+        let verified =
+            futures::executor::block_on(download_all(&packages, 3, &tracker, fetch, |_, data| {
+                Ok(data[0])
+            }))
+            .unwrap();
+
+        assert_eq!(verified, vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(most.get(), 3);
+        let progress = tracker.snapshot();
+        assert!(
+            progress
+                .transfers
+                .iter()
+                .all(|t| t.stage == Stage::Verified)
+        );
+        assert!(!progress.is_done());
+    }
+
+    #[test]
+    fn test_download_all_verify_fails() {
+        let packages: Vec<_> = (0..4).map(|i| package(&format!("p{}", i), i)).collect();
+        let tracker = Tracker::quiet(ids(&packages));
+        let result = futures::executor::block_on(download_all(
+            &packages,
+            2,
+            &tracker,
+            |index, _, _| async move { Ok(vec![index as u8]) },
+            |package, data| {
+                if data[0] == 1 {
+                    Err(PkgError::ChecksumMismatch {
+                        expected: "a".to_string(),
+                        actual: "b".to_string(),
+                    })
+                } else {
+                    Ok(package.order)
+                }
+            },
+        ));
+        assert!(matches!(result, Err(PkgError::ChecksumMismatch { .. })));
+
+        let stages: Vec<Stage> = tracker
+            .snapshot()
+            .transfers
+            .into_iter()
+            .map(|t| t.stage)
+            .collect();
+        assert_eq!(stages[0], Stage::Verified);
+        assert!(matches!(&stages[1], Stage::Failed(r) if r.contains("checksum")));
+        assert_eq!(stages[3], Stage::Failed("cancelled".to_string()));
+    }
+
+    #[test]
+    fn test_progress_render() {
+        let packages = vec![package("hello", 0), package("utils-extra", 1)];
+        let redraws = Rc::new(Cell::new(0));
+        let counted = redraws.clone();
+        let tracker = Tracker::new(ids(&packages), move |_| counted.set(counted.get() + 1));
+
+        tracker.set_stage(0, Stage::Downloading);
+        tracker.set_received(0, 512, Some(2048));
+        // Nothing on screen changes
+        tracker.set_received(0, 512, Some(2048));
+        tracker.set_stage(1, Stage::Installed);
+        assert_eq!(redraws.get(), 3);
+
+        let progress = tracker.snapshot();
+        assert_eq!(progress.percent(), 62);
+        assert!(!progress.is_failed());
+        let lines = progress.render();
+        assert_eq!(
+            lines[0],
+            "hello-1.0.0       [#####---------------]  25% 512B/2.0K"
+        );
+        assert_eq!(
+            lines[1],
+            "utils-extra-1.0.0 [####################] 100% installed"
+        );
+    }
+}
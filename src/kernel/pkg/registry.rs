@@ -56,6 +56,13 @@ mod urlencoding {
     }
 }
 
+/// How registry metadata uses the HTTP cache: revalidated while online,
+/// served from the cache while offline
+#[cfg(target_arch = "wasm32")]
+fn registry_cache() -> crate::kernel::httpcache::CachePolicy {
+    crate::kernel::httpcache::CachePolicy::for_mode(crate::kernel::syscall::offline())
+}
+
 /// Default registry URL
 pub const DEFAULT_REGISTRY: &str = "https://pkg.axeberg.dev";
 
@@ -180,6 +187,7 @@ impl PackageRegistry {
         let url = format!("{}/index.json", self.registry_url);
 
         let response = HttpRequest::get(&url)
+            .cache(registry_cache())
             .send()
             .await
             .map_err(|e| PkgError::NetworkError(e))?;
@@ -224,6 +232,7 @@ impl PackageRegistry {
         let url = format!("{}/packages/{}.json", self.registry_url, name);
 
         let response = HttpRequest::get(&url)
+            .cache(registry_cache())
             .send()
             .await
             .map_err(|e| PkgError::NetworkError(e))?;
@@ -254,14 +263,25 @@ impl PackageRegistry {
     }
 
     /// Download a package archive
-    #[cfg(target_arch = "wasm32")]
     pub async fn download_package(&self, name: &str, version: &Version) -> PkgResult<Vec<u8>> {
+        self.download_package_with_progress(name, version, |_, _| {})
+            .await
+    }
+
+    /// Download a package archive, reporting bytes received and the size
+    #[cfg(target_arch = "wasm32")]
+    pub async fn download_package_with_progress(
+        &self,
+        name: &str,
+        version: &Version,
+        progress: impl FnMut(u64, Option<u64>),
+    ) -> PkgResult<Vec<u8>> {
         use crate::kernel::network::HttpRequest;
 
         let url = format!("{}/packages/{}/{}.axepkg", self.registry_url, name, version);
 
         let response = HttpRequest::get(&url)
-            .send()
+            .send_with_progress(progress)
             .await
             .map_err(|e| PkgError::NetworkError(e))?;
 
@@ -279,10 +299,43 @@ impl PackageRegistry {
         Ok(response.body)
     }
 
-    /// Download package (non-WASM stub)
+    /// Download package with progress (non-WASM stub)
     #[cfg(not(target_arch = "wasm32"))]
-    pub async fn download_package(&self, name: &str, version: &Version) -> PkgResult<Vec<u8>> {
-        let _ = (name, version);
+    pub async fn download_package_with_progress(
+        &self,
+        name: &str,
+        version: &Version,
+        progress: impl FnMut(u64, Option<u64>),
+    ) -> PkgResult<Vec<u8>> {
+        let _ = (name, version, progress);
+        Err(PkgError::NotAvailable("WASM required".to_string()))
+    }
+
+    /// Download the latest system image
+    #[cfg(target_arch = "wasm32")]
+    pub async fn download_system_image(&self) -> PkgResult<Vec<u8>> {
+        use crate::kernel::network::HttpRequest;
+
+        let url = format!("{}/system/latest.sysimg", self.registry_url);
+
+        let response = HttpRequest::get(&url)
+            .send()
+            .await
+            .map_err(|e| PkgError::NetworkError(e))?;
+
+        if response.status != 200 {
+            return Err(PkgError::RegistryError(format!(
+                "HTTP {}: {}",
+                response.status, response.status_text
+            )));
+        }
+
+        Ok(response.body)
+    }
+
+    /// Download system image (non-WASM stub)
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn download_system_image(&self) -> PkgResult<Vec<u8>> {
         Err(PkgError::NotAvailable("WASM required".to_string()))
     }
 
@@ -243,6 +243,7 @@ impl DependencyResolver {
                 name: name.to_string(),
                 path: format!("bin/{}.wasm", name),
                 checksum: None,
+                setuid: false,
             }],
             dependencies: vec![], // Would need to fetch from registry
             dev_dependencies: vec![],
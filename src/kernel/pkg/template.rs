@@ -0,0 +1,302 @@
+//! Project templates
+//!
+//! Generates the skeleton of a new WASM command crate for `pkg new`. The
+//! template is plain data (a list of relative paths and contents) so it can
+//! be written into the VFS by the shell or onto the host filesystem by the
+//! CLI.
+//!
+//! # Layout
+//!
+//! ```text
+//! <name>/
+//! ├── Cargo.toml        # cdylib crate depending on axeberg-abi
+//! ├── package.toml      # pkg manifest with one [[bin]] entry
+//! ├── README.md         # build and install notes
+//! ├── bin/              # place the built <name>.wasm here
+//! └── src/
+//!     └── lib.rs        # entry point written against the SDK
+//! ```
+
+use super::error::{PkgError, PkgResult};
+use super::installer::{mkdir_recursive, path_exists, write_file_bytes};
+use super::manifest::{BinaryEntry, PackageManifest};
+use super::version::Version;
+
+/// Longest accepted project name
+pub const MAX_NAME_LEN: usize = 64;
+
+/// A command project to be generated
+#[derive(Debug, Clone)]
+pub struct ProjectTemplate {
+    /// Project, package and command name
+    pub name: String,
+    /// Initial package version
+    pub version: Version,
+    /// Optional author for the manifest
+    pub author: Option<String>,
+}
+
+impl ProjectTemplate {
+    /// Create a template for `name`, validating it as a command name
+    pub fn new(name: &str) -> PkgResult<Self> {
+        validate_name(name)?;
+        Ok(Self {
+            name: name.to_string(),
+            version: Version::new(0, 1, 0),
+            author: None,
+        })
+    }
+
+    /// Set the author recorded in the manifests
+    pub fn with_author(mut self, author: &str) -> Self {
+        self.author = Some(author.to_string());
+        self
+    }
+
+    /// Rust crate name (hyphens are not valid in identifiers)
+    fn crate_ident(&self) -> String {
+        self.name.replace('-', "_")
+    }
+
+    /// The `package.toml` manifest for the project
+    pub fn manifest(&self) -> PackageManifest {
+        PackageManifest {
+            name: self.name.clone(),
+            version: self.version.clone(),
+            description: Some(format!("The {} command", self.name)),
+            authors: self.author.iter().cloned().collect(),
+            license: Some("MIT".to_string()),
+            repository: None,
+            homepage: None,
+            keywords: Vec::new(),
+            binaries: vec![BinaryEntry {
+                name: self.name.clone(),
+                path: format!("bin/{}.wasm", self.name),
+                checksum: None,
+                setuid: false,
+            }],
+            dependencies: Vec::new(),
+            dev_dependencies: Vec::new(),
+        }
+    }
+
+    /// All generated files as (relative path, contents)
+    pub fn files(&self) -> Vec<(String, String)> {
+        vec![
+            ("Cargo.toml".to_string(), self.cargo_toml()),
+            ("package.toml".to_string(), self.manifest().to_toml()),
+            ("src/lib.rs".to_string(), self.lib_rs()),
+            ("README.md".to_string(), self.readme()),
+        ]
+    }
+
+    /// Directories that must exist besides those implied by `files()`
+    pub fn dirs(&self) -> Vec<&'static str> {
+        vec!["src", "bin"]
+    }
+
+    /// Write the project into the VFS under `dir`
+    ///
+    /// Fails if `dir` already exists so an existing project is never
+    /// overwritten. Returns the paths of the files written.
+    pub fn write_to(&self, dir: &str) -> PkgResult<Vec<String>> {
+        let dir = dir.trim_end_matches('/');
+        if path_exists(dir) {
+            return Err(PkgError::IoError(format!("{}: already exists", dir)));
+        }
+
+        for sub in self.dirs() {
+            mkdir_recursive(&format!("{}/{}", dir, sub))?;
+        }
+
+        let mut written = Vec::new();
+        for (rel, content) in self.files() {
+            let path = format!("{}/{}", dir, rel);
+            write_file_bytes(&path, content.as_bytes())?;
+            written.push(path);
+        }
+        Ok(written)
+    }
+
+    fn cargo_toml(&self) -> String {
+        let authors = match &self.author {
+            Some(a) => format!("authors = [\"{}\"]\n", a),
+            None => String::new(),
+        };
+        format!(
+            r#"[package]
+name = "{name}"
+version = "{version}"
+edition = "2024"
+{authors}
+[lib]
+crate-type = ["cdylib"]
+
+[dependencies]
+# Point this at your checkout of axeberg
+axeberg-abi = {{ path = "../axeberg/sdk/axeberg-abi" }}
+
+[profile.release]
+opt-level = "s"
+lto = true
+panic = "abort"
+"#,
+            name = self.name,
+            version = self.version,
+            authors = authors,
+        )
+    }
+
+    fn lib_rs(&self) -> String {
+        format!(
+            r#"//! {name} - an axeberg command
+
+#![no_std]
+#![no_main]
+
+use axeberg_abi::args::{{Args, Opt}};
+use axeberg_abi::{{eprintln, println}};
+
+axeberg_abi::entry!(run);
+axeberg_abi::panic_handler!();
+
+#[global_allocator]
+static HEAP: axeberg_abi::heap::BumpAllocator = axeberg_abi::heap::BumpAllocator::new();
+
+fn run(args: Args) -> i32 {{
+    let mut parser = args.parser();
+    while let Some(opt) = parser.next() {{
+        match opt {{
+            Opt::Short('h') | Opt::Long("help") => {{
+                println!("Usage: {name} [NAME]");
+                return 0;
+            }}
+            Opt::Positional(who) => {{
+                println!("Hello, {{}}!", who);
+                return 0;
+            }}
+            other => {{
+                eprintln!("{name}: unexpected argument {{:?}}", other);
+                return 2;
+            }}
+        }}
+    }}
+
+    println!("Hello from {name}!");
+    0
+}}
+"#,
+            name = self.name,
+        )
+    }
+
+    fn readme(&self) -> String {
+        format!(
+            r#"# {name}
+
+An axeberg WASM command, generated by `pkg new`.
+
+## Building
+
+On the host, with the `wasm32-unknown-unknown` target installed:
+
+```sh
+cargo build --target wasm32-unknown-unknown --release
+cp target/wasm32-unknown-unknown/release/{ident}.wasm bin/{name}.wasm
+```
+
+## Installing
+
+Copy `bin/{name}.wasm` into the OS and either drop it into `/bin`:
+
+```sh
+cp bin/{name}.wasm /bin/{name}.wasm
+{name}
+```
+
+or install it as a package. `package.toml` already lists the binary under
+`[[bin]]`, so an `.axepkg` archive of this directory (manifest plus
+`bin/{name}.wasm`) can be installed with `pkg install-local`, which also
+records it in the package database for `pkg list`/`pkg remove`.
+"#,
+            name = self.name,
+            ident = self.crate_ident(),
+        )
+    }
+}
+
+/// Check that `name` is usable as a package, crate and command name
+///
+/// Allowed: ASCII lowercase letters, digits, `-` and `_`, starting with a
+/// letter.
+pub fn validate_name(name: &str) -> PkgResult<()> {
+    let invalid = |why: &str| Err(PkgError::InvalidPackageId(format!("{}: {}", name, why)));
+
+    if name.is_empty() {
+        return invalid("name is empty");
+    }
+    if name.len() > MAX_NAME_LEN {
+        return invalid("name is too long");
+    }
+    if !name.starts_with(|c: char| c.is_ascii_lowercase()) {
+        return invalid("name must start with a lowercase letter");
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+    {
+        return invalid("only a-z, 0-9, '-' and '_' are allowed");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name("hello").is_ok());
+        assert!(validate_name("my-tool_2").is_ok());
+        assert!(validate_name("").is_err());
+        assert!(validate_name("Hello").is_err());
+        assert!(validate_name("2fast").is_err());
+        assert!(validate_name("../etc").is_err());
+        assert!(validate_name(&"a".repeat(MAX_NAME_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn test_template_files() {
+        let tpl = ProjectTemplate::new("my-tool").unwrap();
+        let files = tpl.files();
+        let names: Vec<_> = files.iter().map(|(p, _)| p.as_str()).collect();
+        assert_eq!(
+            names,
+            ["Cargo.toml", "package.toml", "src/lib.rs", "README.md"]
+        );
+
+        let readme = &files[3].1;
+        assert!(readme.contains("release/my_tool.wasm bin/my-tool.wasm"));
+    }
+
+    #[test]
+    fn test_template_manifest_roundtrip() {
+        let tpl = ProjectTemplate::new("hello").unwrap().with_author("me");
+        let toml = tpl.manifest().to_toml();
+        let parsed = PackageManifest::parse(&toml).unwrap();
+        assert_eq!(parsed.name, "hello");
+        assert_eq!(parsed.version, Version::new(0, 1, 0));
+        assert_eq!(parsed.authors, vec!["me".to_string()]);
+        assert_eq!(parsed.binaries[0].path, "bin/hello.wasm");
+    }
+
+    #[test]
+    fn test_template_sources_use_sdk() {
+        let tpl = ProjectTemplate::new("hello").unwrap();
+        let files = tpl.files();
+        assert!(files[0].1.contains("axeberg-abi"));
+        assert!(files[0].1.contains("cdylib"));
+        assert!(files[2].1.contains("axeberg_abi::entry!(run);"));
+        assert!(files[2].1.contains("Usage: hello"));
+    }
+}
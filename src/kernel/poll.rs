@@ -0,0 +1,146 @@
+//! I/O readiness for poll
+//!
+//! `sys_poll` reports which of a set of sources could be read or written
+//! without blocking: descriptors (pipes, the console, signalfds, files),
+//! Unix domain sockets and FIFOs. Sockets and FIFOs have no descriptors in
+//! this kernel, so a [`PollFd`] names its source rather than holding a
+//! raw fd. `syscall::poll` wraps the syscall in a future that waits for
+//! readiness, a timeout or a signal.
+
+use super::process::Fd;
+use super::uds::SocketId;
+
+/// Readiness conditions, requested in [`PollFd::events`] and reported in
+/// [`PollFd::revents`]
+///
+/// `error`, `hangup` and `invalid` are always reported, whether requested
+/// or not.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PollEvents {
+    /// Data can be read, or a read would return end of file (POLLIN)
+    pub readable: bool,
+    /// Data can be written (POLLOUT)
+    pub writable: bool,
+    /// Writing would fail, e.g. the reading end has gone (POLLERR)
+    pub error: bool,
+    /// The other end has closed (POLLHUP)
+    pub hangup: bool,
+    /// The source does not exist (POLLNVAL)
+    pub invalid: bool,
+}
+
+impl PollEvents {
+    pub const NONE: PollEvents = PollEvents {
+        readable: false,
+        writable: false,
+        error: false,
+        hangup: false,
+        invalid: false,
+    };
+
+    pub const READ: PollEvents = PollEvents {
+        readable: true,
+        ..Self::NONE
+    };
+
+    pub const WRITE: PollEvents = PollEvents {
+        writable: true,
+        ..Self::NONE
+    };
+
+    pub const READ_WRITE: PollEvents = PollEvents {
+        readable: true,
+        writable: true,
+        ..Self::NONE
+    };
+
+    pub fn is_empty(self) -> bool {
+        self == Self::NONE
+    }
+
+    /// The conditions of `ready` worth reporting for a request of `self`
+    pub fn report(self, ready: PollEvents) -> PollEvents {
+        PollEvents {
+            readable: self.readable && ready.readable,
+            writable: self.writable && ready.writable,
+            ..ready
+        }
+    }
+}
+
+/// What a [`PollFd`] watches
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PollSource {
+    /// A descriptor of the polling process
+    Fd(Fd),
+    /// A Unix domain socket
+    Socket(SocketId),
+    /// A FIFO, by path
+    Fifo(String),
+}
+
+/// One entry of a poll set
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PollFd {
+    pub source: PollSource,
+    /// Conditions to wait for
+    pub events: PollEvents,
+    /// Conditions found, filled in by `sys_poll`
+    pub revents: PollEvents,
+}
+
+impl PollFd {
+    pub fn new(source: PollSource, events: PollEvents) -> Self {
+        Self {
+            source,
+            events,
+            revents: PollEvents::NONE,
+        }
+    }
+
+    pub fn fd(fd: Fd, events: PollEvents) -> Self {
+        Self::new(PollSource::Fd(fd), events)
+    }
+
+    pub fn socket(id: SocketId, events: PollEvents) -> Self {
+        Self::new(PollSource::Socket(id), events)
+    }
+
+    pub fn fifo(path: impl Into<String>, events: PollEvents) -> Self {
+        Self::new(PollSource::Fifo(path.into()), events)
+    }
+
+    /// Whether `sys_poll` found anything to report
+    pub fn is_ready(&self) -> bool {
+        !self.revents.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let ready = PollEvents {
+            readable: true,
+            writable: true,
+            hangup: true,
+            ..PollEvents::NONE
+        };
+        assert_eq!(
+            PollEvents::READ.report(ready),
+            PollEvents {
+                readable: true,
+                hangup: true,
+                ..PollEvents::NONE
+            }
+        );
+        assert!(PollEvents::NONE.report(PollEvents::READ).is_empty());
+
+        let mut pfd = PollFd::fd(Fd(3), PollEvents::WRITE);
+        assert!(!pfd.is_ready());
+        pfd.revents = PollEvents::WRITE.report(ready);
+        assert!(pfd.is_ready());
+    }
+}
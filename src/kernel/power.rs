@@ -0,0 +1,226 @@
+//! Power governor
+//!
+//! Decides whether the system runs at full speed or saves power. The
+//! governor is chosen through `/sys/power/governor`:
+//!
+//! - `ondemand` (default): full speed while the page is visible, power
+//!   saving while it is hidden
+//! - `performance`: always full speed
+//! - `powersave`: always power saving
+//!
+//! While saving power the executor ticks less often, compositor animations
+//! are skipped, cron jobs marked `@batch` wait for full speed, and
+//! persistence writes are coalesced into at most one per
+//! [`PERSIST_BATCH_MS`]. The platform reports page visibility with
+//! [`PowerGovernor::set_hidden`] and asks the governor how to behave.
+
+/// Milliseconds between executor ticks at full speed (one frame)
+pub const FULL_TICK_MS: f64 = 16.0;
+
+/// Milliseconds between executor ticks while saving power
+pub const SAVING_TICK_MS: f64 = 250.0;
+
+/// Minimum time between persistence writes while saving power
+pub const PERSIST_BATCH_MS: f64 = 60_000.0;
+
+/// Governor selected through `/sys/power/governor`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Governor {
+    /// Save power only while the page is hidden
+    #[default]
+    Ondemand,
+    /// Always run at full speed
+    Performance,
+    /// Always save power
+    Powersave,
+}
+
+impl Governor {
+    /// All governors, in `available_governors` order
+    pub const ALL: [Governor; 3] = [
+        Governor::Ondemand,
+        Governor::Performance,
+        Governor::Powersave,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Governor::Ondemand => "ondemand",
+            Governor::Performance => "performance",
+            Governor::Powersave => "powersave",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|g| g.name() == name.trim())
+    }
+}
+
+/// Effective power mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerMode {
+    Full,
+    Saving,
+}
+
+impl PowerMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            PowerMode::Full => "full",
+            PowerMode::Saving => "saving",
+        }
+    }
+}
+
+/// Governor state
+#[derive(Debug, Clone, Default)]
+pub struct PowerGovernor {
+    governor: Governor,
+    /// The page (or window) is hidden
+    hidden: bool,
+    /// A persistence write was requested and held back
+    persist_pending: bool,
+    /// Time of the last persistence write
+    last_persist: Option<f64>,
+}
+
+impl PowerGovernor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn governor(&self) -> Governor {
+        self.governor
+    }
+
+    pub fn set_governor(&mut self, governor: Governor) {
+        self.governor = governor;
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.hidden
+    }
+
+    /// Report page visibility; returns true if the mode changed
+    pub fn set_hidden(&mut self, hidden: bool) -> bool {
+        let before = self.mode();
+        self.hidden = hidden;
+        self.mode() != before
+    }
+
+    /// The effective mode for the governor and visibility
+    pub fn mode(&self) -> PowerMode {
+        match (self.governor, self.hidden) {
+            (Governor::Performance, _) | (Governor::Ondemand, false) => PowerMode::Full,
+            (Governor::Powersave, _) | (Governor::Ondemand, true) => PowerMode::Saving,
+        }
+    }
+
+    pub fn is_saving(&self) -> bool {
+        self.mode() == PowerMode::Saving
+    }
+
+    /// Milliseconds the platform should wait between executor ticks
+    pub fn tick_interval(&self) -> f64 {
+        match self.mode() {
+            PowerMode::Full => FULL_TICK_MS,
+            PowerMode::Saving => SAVING_TICK_MS,
+        }
+    }
+
+    /// Whether compositor animations should be skipped
+    pub fn animations_paused(&self) -> bool {
+        self.is_saving()
+    }
+
+    /// Whether cron jobs marked `@batch` should wait
+    pub fn defer_batch(&self) -> bool {
+        self.is_saving()
+    }
+
+    /// Ask to write persistent state; returns true if it should be written
+    /// now. Otherwise the write is held back until [`Self::persist_due`].
+    pub fn request_persist(&mut self, now: f64) -> bool {
+        let due = match self.mode() {
+            PowerMode::Full => true,
+            PowerMode::Saving => self
+                .last_persist
+                .is_none_or(|last| now - last >= PERSIST_BATCH_MS),
+        };
+        if due {
+            self.persist_pending = false;
+            self.last_persist = Some(now);
+        } else {
+            self.persist_pending = true;
+        }
+        due
+    }
+
+    /// Check if a held-back write should happen now: at once on full speed,
+    /// otherwise when [`PERSIST_BATCH_MS`] has passed since the last one
+    pub fn persist_due(&mut self, now: f64) -> bool {
+        self.persist_pending && self.request_persist(now)
+    }
+
+    /// Whether a persistence write is being held back
+    pub fn persist_pending(&self) -> bool {
+        self.persist_pending
+    }
+
+    /// Content of a /sys/power attribute
+    pub fn attr(&self, name: &str) -> Option<String> {
+        match name {
+            "governor" => Some(format!("{}\n", self.governor.name())),
+            "available_governors" => {
+                let names: Vec<_> = Governor::ALL.iter().map(|g| g.name()).collect();
+                Some(format!("{}\n", names.join(" ")))
+            }
+            "mode" => Some(format!("{}\n", self.mode().name())),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_modes() {
+        let mut power = PowerGovernor::new();
+        assert_eq!(power.mode(), PowerMode::Full);
+        assert!(power.set_hidden(true));
+        assert_eq!(power.tick_interval(), SAVING_TICK_MS);
+        assert!(power.defer_batch());
+
+        power.set_governor(Governor::Performance);
+        assert_eq!(power.mode(), PowerMode::Full);
+        assert!(!power.set_hidden(false));
+
+        power.set_governor(Governor::Powersave);
+        assert!(power.animations_paused());
+        assert_eq!(Governor::parse("powersave\n"), Some(Governor::Powersave));
+        assert_eq!(Governor::parse("turbo"), None);
+    }
+
+    #[test]
+    fn test_persist_batching() {
+        let mut power = PowerGovernor::new();
+        assert!(power.request_persist(0.0));
+        assert!(power.request_persist(10.0));
+
+        power.set_governor(Governor::Powersave);
+        assert!(!power.request_persist(20.0));
+        assert!(!power.request_persist(30.0));
+        assert!(power.persist_pending());
+        assert!(!power.persist_due(1000.0));
+        assert!(power.persist_due(PERSIST_BATCH_MS + 10.0));
+        assert!(!power.persist_pending());
+
+        // Held-back writes flush as soon as full speed returns
+        assert!(!power.request_persist(PERSIST_BATCH_MS + 20.0));
+        power.set_governor(Governor::Ondemand);
+        assert!(power.persist_due(PERSIST_BATCH_MS + 30.0));
+        assert!(!power.persist_due(PERSIST_BATCH_MS + 40.0));
+    }
+}
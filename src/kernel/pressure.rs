@@ -0,0 +1,209 @@
+//! Memory pressure
+//!
+//! The monitor compares memory in use with what is available, from two
+//! sources: the kernel's own accounting (process regions and shared memory
+//! against the system limit, when one is set) and the browser's
+//! `performance.memory` heap figures, where the platform reports them.
+//! The fuller of the two decides the [`PressureLevel`].
+//!
+//! When the level rises the kernel logs it to dmesg, sends
+//! `SIGMEMPRESSURE` to processes that handle or block it, and runs the
+//! hooks registered with [`on_pressure`]. Those drop what can be rebuilt
+//! later: the file search index, old terminal scrollback. A level only
+//! falls once usage is [`HYSTERESIS_PERCENT`] below its threshold, so
+//! usage hovering at a threshold doesn't flap.
+
+use std::cell::RefCell;
+
+/// Percent of memory in use at which pressure is moderate
+pub const MODERATE_PERCENT: u64 = 70;
+
+/// Percent of memory in use at which pressure is critical
+pub const CRITICAL_PERCENT: u64 = 90;
+
+/// How far below a threshold usage must fall before the level drops
+pub const HYSTERESIS_PERCENT: u64 = 5;
+
+/// Minimum time between checks, in milliseconds
+pub const CHECK_INTERVAL_MS: f64 = 5_000.0;
+
+/// How short memory is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum PressureLevel {
+    #[default]
+    Normal,
+    Moderate,
+    Critical,
+}
+
+impl PressureLevel {
+    pub fn name(self) -> &'static str {
+        match self {
+            PressureLevel::Normal => "normal",
+            PressureLevel::Moderate => "moderate",
+            PressureLevel::Critical => "critical",
+        }
+    }
+
+    /// Level for usage at `percent`, without hysteresis
+    fn for_percent(percent: u64) -> Self {
+        if percent >= CRITICAL_PERCENT {
+            PressureLevel::Critical
+        } else if percent >= MODERATE_PERCENT {
+            PressureLevel::Moderate
+        } else {
+            PressureLevel::Normal
+        }
+    }
+}
+
+/// Memory in use out of a limit, in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemorySample {
+    pub used: u64,
+    pub limit: u64,
+}
+
+impl MemorySample {
+    /// Percent of the limit in use; `None` if there is no limit
+    pub fn percent(&self) -> Option<u64> {
+        (self.limit > 0).then(|| self.used.saturating_mul(100) / self.limit)
+    }
+}
+
+/// Monitor state
+#[derive(Debug, Clone, Default)]
+pub struct PressureMonitor {
+    level: PressureLevel,
+    /// Usage at the last check, if any source had a limit
+    percent: Option<u64>,
+    /// When the last check was due
+    checked: Option<f64>,
+    /// Times the level has risen
+    rises: u64,
+}
+
+impl PressureMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn level(&self) -> PressureLevel {
+        self.level
+    }
+
+    /// Percent of memory in use at the last check
+    pub fn percent(&self) -> Option<u64> {
+        self.percent
+    }
+
+    /// Times the level has risen
+    pub fn rises(&self) -> u64 {
+        self.rises
+    }
+
+    /// Check if a check is due at `now`, at most every
+    /// [`CHECK_INTERVAL_MS`]; counts as the check if it is
+    pub fn due(&mut self, now: f64) -> bool {
+        if self.checked.is_some_and(|t| now - t < CHECK_INTERVAL_MS) {
+            return false;
+        }
+        self.checked = Some(now);
+        true
+    }
+
+    /// Take a round of samples; returns the new level if it changed
+    pub fn update(&mut self, samples: &[MemorySample]) -> Option<PressureLevel> {
+        self.percent = samples.iter().filter_map(MemorySample::percent).max();
+        let percent = self.percent.unwrap_or(0);
+        let level = match PressureLevel::for_percent(percent) {
+            rising if rising >= self.level => rising,
+            _ => PressureLevel::for_percent(percent + HYSTERESIS_PERCENT).min(self.level),
+        };
+        if level == self.level {
+            return None;
+        }
+        if level > self.level {
+            self.rises += 1;
+        }
+        self.level = level;
+        Some(level)
+    }
+}
+
+/// Frees what it can at a pressure level, and says what it freed
+pub type TrimHook = fn(PressureLevel) -> Option<String>;
+
+thread_local! {
+    /// Hooks run when pressure rises
+    static TRIM_HOOKS: RefCell<Vec<TrimHook>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Run `hook` whenever pressure rises, once the kernel is free to call into
+pub fn on_pressure(hook: TrimHook) {
+    TRIM_HOOKS.with(|hooks| hooks.borrow_mut().push(hook));
+}
+
+/// Run the hooks registered with [`on_pressure`]; returns what they freed
+pub(crate) fn run_trim_hooks(level: PressureLevel) -> Vec<String> {
+    let hooks = TRIM_HOOKS.with(|hooks| hooks.borrow().clone());
+    hooks.into_iter().filter_map(|hook| hook(level)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(used: u64) -> MemorySample {
+        MemorySample { used, limit: 100 }
+    }
+
+    #[test]
+    fn test_levels() {
+        let mut monitor = PressureMonitor::new();
+        assert_eq!(monitor.update(&[sample(50)]), None);
+        assert_eq!(monitor.update(&[sample(75)]), Some(PressureLevel::Moderate));
+        assert_eq!(monitor.update(&[sample(95)]), Some(PressureLevel::Critical));
+        assert_eq!(monitor.rises(), 2);
+
+        // Held until usage is well below the threshold
+        assert_eq!(monitor.update(&[sample(87)]), None);
+        assert_eq!(monitor.update(&[sample(80)]), Some(PressureLevel::Moderate));
+        assert_eq!(monitor.update(&[sample(67)]), None);
+        assert_eq!(monitor.update(&[sample(10)]), Some(PressureLevel::Normal));
+        assert_eq!(monitor.rises(), 2);
+        assert_eq!(monitor.percent(), Some(10));
+    }
+
+    #[test]
+    fn test_fullest_source_wins() {
+        let mut monitor = PressureMonitor::new();
+        let unlimited = MemorySample {
+            used: 1 << 30,
+            limit: 0,
+        };
+        assert_eq!(monitor.update(&[unlimited]), None);
+        assert_eq!(monitor.percent(), None);
+
+        assert_eq!(
+            monitor.update(&[unlimited, sample(20), sample(92)]),
+            Some(PressureLevel::Critical)
+        );
+        assert_eq!(monitor.percent(), Some(92));
+    }
+
+    #[test]
+    fn test_due() {
+        let mut monitor = PressureMonitor::new();
+        assert!(monitor.due(0.0));
+        assert!(!monitor.due(CHECK_INTERVAL_MS - 1.0));
+        assert!(monitor.due(CHECK_INTERVAL_MS));
+    }
+
+    #[test]
+    fn test_trim_hooks() {
+        on_pressure(|level| (level == PressureLevel::Critical).then(|| "everything".into()));
+        assert!(run_trim_hooks(PressureLevel::Moderate).is_empty());
+        assert_eq!(run_trim_hooks(PressureLevel::Critical), vec!["everything"]);
+    }
+}
@@ -141,6 +141,8 @@ pub struct OpenFlags {
     pub create: bool,
     pub truncate: bool,
     pub append: bool,
+    /// Reads that would wait fail with `WouldBlock` instead (O_NONBLOCK)
+    pub nonblock: bool,
 }
 
 impl OpenFlags {
@@ -150,6 +152,7 @@ impl OpenFlags {
         create: false,
         truncate: false,
         append: false,
+        nonblock: false,
     };
 
     pub const WRITE: OpenFlags = OpenFlags {
@@ -158,6 +161,7 @@ impl OpenFlags {
         create: true,
         truncate: true,
         append: false,
+        nonblock: false,
     };
 
     pub const RDWR: OpenFlags = OpenFlags {
@@ -166,6 +170,7 @@ impl OpenFlags {
         create: false,
         truncate: false,
         append: false,
+        nonblock: false,
     };
 
     pub const APPEND: OpenFlags = OpenFlags {
@@ -174,7 +179,14 @@ impl OpenFlags {
         create: true,
         truncate: false,
         append: true,
+        nonblock: false,
     };
+
+    /// The same flags, opening the descriptor in non-blocking mode
+    pub fn nonblocking(mut self) -> Self {
+        self.nonblock = true;
+        self
+    }
 }
 
 /// Session identifier (for session management like Linux SID)
@@ -324,6 +336,105 @@ impl ResourceLimits {
     }
 }
 
+/// Resources a process has used, like `getrusage(2)`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ResourceUsage {
+    /// Syscalls made, as strace and ptrace see them
+    pub syscalls: u64,
+    /// Milliseconds spent in those syscalls; only counted while the kernel
+    /// has a clock (see `kernel::set_clock`)
+    pub sys_ms: f64,
+    /// Most memory allocated at once, in bytes
+    pub peak_memory: usize,
+}
+
+impl ResourceUsage {
+    /// Count one syscall that took `ms` (NaN if it wasn't timed)
+    pub fn charge_syscall(&mut self, ms: f64) {
+        self.syscalls += 1;
+        if ms.is_finite() && ms > 0.0 {
+            self.sys_ms += ms;
+        }
+    }
+}
+
+/// Which of the parent's environment variables a child starts with
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum EnvInherit {
+    /// All of them
+    #[default]
+    All,
+    /// None (`env -i`)
+    Nothing,
+    /// Only the named ones
+    Only(Vec<String>),
+}
+
+/// The environment a child is spawned with, relative to its parent's
+///
+/// The inherited variables are picked first, then `unset` removes and
+/// `set` adds or overrides, like `env -i -u NAME NAME=VALUE`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EnvSpec {
+    pub inherit: EnvInherit,
+    pub unset: Vec<String>,
+    pub set: Vec<(String, String)>,
+}
+
+impl EnvSpec {
+    /// Inherit everything
+    pub fn inherit() -> Self {
+        Self::default()
+    }
+
+    /// Start from an empty environment
+    pub fn clean() -> Self {
+        Self {
+            inherit: EnvInherit::Nothing,
+            ..Self::default()
+        }
+    }
+
+    /// Inherit only `names`
+    pub fn allow<S: Into<String>>(names: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            inherit: EnvInherit::Only(names.into_iter().map(Into::into).collect()),
+            ..Self::default()
+        }
+    }
+
+    /// Also set `name` to `value`
+    pub fn set(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.set.push((name.into(), value.into()));
+        self
+    }
+
+    /// Leave `name` out
+    pub fn unset(mut self, name: impl Into<String>) -> Self {
+        self.unset.push(name.into());
+        self
+    }
+
+    /// The child's environment, given the parent's
+    pub fn resolve(&self, parent: &HashMap<String, String>) -> HashMap<String, String> {
+        let mut environ: HashMap<String, String> = match &self.inherit {
+            EnvInherit::All => parent.clone(),
+            EnvInherit::Nothing => HashMap::new(),
+            EnvInherit::Only(names) => names
+                .iter()
+                .filter_map(|name| Some((name.clone(), parent.get(name)?.clone())))
+                .collect(),
+        };
+        for name in &self.unset {
+            environ.remove(name);
+        }
+        for (name, value) in &self.set {
+            environ.insert(name.clone(), value.clone());
+        }
+        environ
+    }
+}
+
 /// A process in the system
 pub struct Process {
     /// Unique process identifier
@@ -419,6 +530,10 @@ pub struct Process {
     /// Range: -20 (highest priority) to +19 (lowest priority), 0 is default
     /// Like POSIX nice(2) / setpriority(2)
     pub nice: i8,
+
+    /// Resources used so far; `peak_memory` is kept in `memory` and
+    /// filled in by `getrusage`
+    pub usage: ResourceUsage,
 }
 
 /// Builder pattern for creating Process instances
@@ -645,6 +760,7 @@ impl ProcessBuilder {
             umask: self.umask,
             was_continued: false,
             nice: self.nice,
+            usage: ResourceUsage::default(),
         }
     }
 }
@@ -698,6 +814,7 @@ impl Process {
             umask: 0o022,            // Default umask (files=644, dirs=755)
             was_continued: false,
             nice: 0, // Default priority
+            usage: ResourceUsage::default(),
         }
     }
 
@@ -744,6 +861,7 @@ impl Process {
             umask: 0o022,
             was_continued: false,
             nice: 0,
+            usage: ResourceUsage::default(),
         }
     }
 
@@ -790,6 +908,7 @@ impl Process {
             umask: 0o022,
             was_continued: false,
             nice: 0,
+            usage: ResourceUsage::default(),
         }
     }
 
@@ -852,6 +971,7 @@ impl Process {
             umask: 0o022,
             was_continued: false,
             nice: 0,
+            usage: ResourceUsage::default(),
         }
     }
 
@@ -1012,10 +1132,11 @@ impl Process {
             name: self.name.clone(),
             children: Vec::new(), // No children yet
             ctty: self.ctty.clone(),
-            is_session_leader: false, // Child is not session leader
-            umask: self.umask,        // Inherit umask
-            was_continued: false,     // Child starts fresh
-            nice: self.nice,          // Inherit scheduling priority
+            is_session_leader: false,        // Child is not session leader
+            umask: self.umask,               // Inherit umask
+            was_continued: false,            // Child starts fresh
+            nice: self.nice,                 // Inherit scheduling priority
+            usage: ResourceUsage::default(), // Child starts with no usage
         };
 
         (child, region_mapping)
@@ -1025,6 +1146,17 @@ impl Process {
 /// Maximum file descriptors per process (POSIX default is often 1024)
 pub const MAX_FDS_PER_PROCESS: usize = 1024;
 
+/// fcntl: get the descriptor flags ([`FdFlags`])
+pub const F_GETFD: i32 = 1;
+/// fcntl: set the descriptor flags
+pub const F_SETFD: i32 = 2;
+/// fcntl: get the file status flags (`O_NONBLOCK`)
+pub const F_GETFL: i32 = 3;
+/// fcntl: set the file status flags
+pub const F_SETFL: i32 = 4;
+/// File status flag: reads that would wait fail with `WouldBlock`
+pub const O_NONBLOCK: u32 = 0o4000;
+
 /// File descriptor flags (for fcntl F_GETFD/F_SETFD)
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub struct FdFlags {
@@ -1218,6 +1350,30 @@ mod tests {
         assert_eq!(proc.cwd, PathBuf::from("/"));
     }
 
+    #[test]
+    fn test_env_spec() {
+        let parent = Process::new(Pid(1), "sh".to_string(), None).environ;
+
+        assert_eq!(EnvSpec::inherit().resolve(&parent), parent);
+        assert!(EnvSpec::clean().resolve(&parent).is_empty());
+
+        let env = EnvSpec::allow(["PATH", "NOPE"])
+            .set("LANG", "C")
+            .resolve(&parent);
+        let mut names: Vec<_> = env.keys().map(String::as_str).collect();
+        names.sort();
+        assert_eq!(names, ["LANG", "PATH"]);
+
+        // Unsetting comes before setting, so an override wins
+        let env = EnvSpec::inherit()
+            .unset("HOME")
+            .unset("USER")
+            .set("USER", "svc")
+            .resolve(&parent);
+        assert!(!env.contains_key("HOME"));
+        assert_eq!(env.get("USER").map(String::as_str), Some("svc"));
+    }
+
     #[test]
     fn test_file_table_alloc() {
         let mut ft = FileTable::new();
@@ -3,6 +3,10 @@
 //! Provides a dynamic view into kernel and process state.
 //! Files are generated on-demand when read.
 
+use super::process::{Handle, OpenFlags, Pid, ProcessState};
+use super::synthfs::SyntheticFs;
+use super::syscall::{Kernel, SyscallError, SyscallResult};
+use super::users::Uid;
 use std::collections::HashMap;
 
 /// Content generator for /proc files
@@ -38,6 +42,7 @@ impl ProcFs {
                 "loadavg".to_string(),
                 "stat".to_string(),
                 "mounts".to_string(),
+                "kallsyms".to_string(),
             ]);
             Some(entries)
         } else if let Some(pid_str) = path.strip_prefix("/proc/") {
@@ -82,6 +87,7 @@ impl ProcFs {
         // Special files at /proc root
         let special_files = [
             "self", "uptime", "meminfo", "cpuinfo", "version", "loadavg", "stat", "mounts",
+            "kallsyms",
         ];
         if special_files.contains(&rest) {
             return true;
@@ -169,6 +175,115 @@ impl Default for ProcFs {
     }
 }
 
+/// PIDs of all processes, for listing /proc
+fn pids(kernel: &Kernel) -> Vec<u32> {
+    kernel.proc().processes.keys().map(|p| p.0).collect()
+}
+
+impl SyntheticFs for ProcFs {
+    fn fs_type(&self) -> &'static str {
+        "proc"
+    }
+
+    fn list_dir(&self, kernel: &Kernel, path: &str) -> Option<Vec<String>> {
+        ProcFs::list_dir(self, path, &pids(kernel))
+    }
+
+    fn exists(&self, kernel: &Kernel, path: &str) -> bool {
+        ProcFs::exists(self, path, &pids(kernel))
+    }
+
+    fn is_dir(&self, kernel: &Kernel, path: &str) -> bool {
+        ProcFs::is_dir(self, path, &pids(kernel))
+    }
+
+    fn open(&self, kernel: &mut Kernel, path: &str, _flags: OpenFlags) -> SyscallResult<Handle> {
+        let current_pid = kernel.proc().current.ok_or(SyscallError::NoProcess)?;
+
+        // The symbol map needs the whole kernel, not just the contexts below
+        if path == "/proc/kallsyms" {
+            let content = kernel.ksyms().render().into_bytes();
+            return Ok(kernel.create_file_object(path.into(), content, true, false));
+        }
+
+        // Generate system context
+        let sys_stats = kernel.memory().system_stats();
+        let sys_ctx = SystemContext {
+            uptime_secs: kernel.now(),
+            total_memory: 64 * 1024 * 1024, // 64MB simulated
+            used_memory: sys_stats.total_allocated as u64,
+            free_memory: 64 * 1024 * 1024 - sys_stats.total_allocated as u64,
+            num_processes: kernel.proc().processes.len(),
+        };
+
+        // Determine which PID the path refers to
+        let target_pid = if path.starts_with("/proc/self/") {
+            Some(current_pid)
+        } else if let Some(rest) = path.strip_prefix("/proc/") {
+            let parts: Vec<&str> = rest.split('/').collect();
+            if !parts.is_empty() {
+                parts[0].parse::<u32>().ok().map(Pid)
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+
+        // SEC-010: Permission check for /proc/[pid]/* access
+        // Only allow process to read its own /proc/[pid]/* files, or root can read any
+        if let Some(target) = target_pid
+            && target != current_pid
+        {
+            // Accessing another process's /proc entries
+            let current_process = kernel.current_process().ok_or(SyscallError::NoProcess)?;
+            let is_root = current_process.euid == Uid::ROOT;
+
+            if !is_root {
+                // Check if this is a sensitive file that requires ownership
+                let sensitive_files = ["environ", "cmdline", "maps", "fd", "cwd", "exe"];
+                let is_sensitive = sensitive_files.iter().any(|f| path.contains(f));
+
+                if is_sensitive {
+                    return Err(SyscallError::PermissionDenied);
+                }
+            }
+        }
+
+        // Generate process context if needed
+        let proc_ctx = target_pid.and_then(|pid| {
+            kernel.get_process(pid).map(|p| {
+                ProcContext {
+                    pid: p.pid.0,
+                    ppid: p.parent.map(|pp| pp.0),
+                    name: &p.name,
+                    state: match &p.state {
+                        ProcessState::Running => "R (running)",
+                        ProcessState::Sleeping => "S (sleeping)",
+                        ProcessState::Stopped => "T (stopped)",
+                        ProcessState::Zombie(_) => "Z (zombie)",
+                        ProcessState::Blocked(_) => "D (blocked)",
+                    },
+                    uid: p.uid.0,
+                    gid: p.gid.0,
+                    cwd: p.cwd.to_str().unwrap_or("/"),
+                    cmdline: &p.name,
+                    environ: &[], // Will be filled from snapshot
+                    memory_used: p.memory.stats().allocated as u64,
+                    memory_limit: p.memory.stats().limit as u64,
+                }
+            })
+        });
+
+        // Generate file content
+        let content = generate_proc_content(path, current_pid.0, proc_ctx.as_ref(), &sys_ctx)
+            .ok_or(SyscallError::NotFound)?;
+
+        // Create a file object with the generated content
+        Ok(kernel.create_file_object(path.into(), content, true, false))
+    }
+}
+
 /// Information needed to generate /proc content
 pub struct ProcContext<'a> {
     pub pid: u32,
@@ -10,6 +10,7 @@
 //! The profiler builds on the existing trace infrastructure to provide
 //! deeper insights into system performance.
 
+use super::ksyms::SymbolMap;
 use super::process::Pid;
 use super::task::TaskId;
 use super::trace::{PerfCounters, TraceCategory};
@@ -209,6 +210,23 @@ impl CpuProfile {
         (task_time / self.total_time) * 100.0
     }
 
+    /// The `n` busiest tasks, labelled from `symbols`, with their CPU usage
+    /// percentage
+    pub fn top_tasks(&self, n: usize, symbols: &SymbolMap) -> Vec<(String, f64)> {
+        let mut tasks: Vec<_> = self.task_time.keys().copied().collect();
+        tasks.sort_by(|a, b| {
+            self.task_cpu_percent(*b)
+                .partial_cmp(&self.task_cpu_percent(*a))
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(a.cmp(b))
+        });
+        tasks
+            .into_iter()
+            .take(n)
+            .map(|task| (symbols.task_label(task), self.task_cpu_percent(task)))
+            .collect()
+    }
+
     /// Get CPU usage percentage for a process
     pub fn process_cpu_percent(&self, pid: Pid) -> f64 {
         if self.total_time == 0.0 {
@@ -226,7 +244,7 @@ impl CpuProfile {
     /// Get top N syscalls by call count
     pub fn top_syscalls_by_count(&self, n: usize) -> Vec<(&String, &SyscallProfile)> {
         let mut profiles: Vec<_> = self.syscall_profiles.iter().collect();
-        profiles.sort_by(|a, b| b.1.counters.count.cmp(&a.1.counters.count));
+        profiles.sort_by_key(|p| std::cmp::Reverse(p.1.counters.count));
         profiles.into_iter().take(n).collect()
     }
 
@@ -315,7 +333,7 @@ impl MemorySnapshot {
     /// Find top N processes by memory usage
     pub fn top_by_memory(&self, n: usize) -> Vec<&ProcessMemorySnapshot> {
         let mut procs: Vec<_> = self.processes.iter().collect();
-        procs.sort_by(|a, b| b.allocated.cmp(&a.allocated));
+        procs.sort_by_key(|p| std::cmp::Reverse(p.allocated));
         procs.into_iter().take(n).collect()
     }
 }
@@ -1057,6 +1075,29 @@ mod tests {
         assert!((cpu.task_cpu_percent(TaskId(1)) - 100.0).abs() < 0.001);
     }
 
+    #[test]
+    fn test_top_tasks() {
+        let mut cpu = CpuProfile::new();
+        cpu.set_sample_interval(10.0);
+        for task in [2, 5, 5, 5] {
+            cpu.record_sample(TaskSample {
+                timestamp: 100.0,
+                task_id: TaskId(task),
+                pid: None,
+                state: TaskSampleState::Running,
+                stack: Vec::new(),
+            });
+        }
+
+        let mut symbols = SymbolMap::new();
+        symbols.add_task(TaskId(5), "indexer");
+        let top = cpu.top_tasks(5, &symbols);
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[0].0, "indexer (task 5)");
+        assert!((top[0].1 - 75.0).abs() < 0.001);
+        assert_eq!(top[1].0, "task 2");
+    }
+
     #[test]
     fn test_profiler_summary() {
         let mut profiler = Profiler::new();
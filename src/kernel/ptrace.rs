@@ -0,0 +1,462 @@
+//! Process tracing (ptrace-like attach)
+//!
+//! A tracer process attaches to a tracee and receives an event for every
+//! syscall the tracee enters and leaves, much like `PTRACE_SYSCALL` on
+//! Linux. Tracing never stops the tracee: events are queued for the tracer,
+//! which collects them whenever it next runs (e.g. `strace -p PID`).
+//!
+//! Design:
+//! - Each tracee has at most one tracer
+//! - Tracing can follow children spawned or forked by the tracee (`-f`)
+//! - A filter restricts which syscalls are reported (`-e trace=file`)
+//! - Queues are bounded; overflowing drops the oldest events
+
+use super::process::Pid;
+use super::syscall::SyscallNr;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// Maximum events queued per tracer before the oldest are dropped
+pub const MAX_QUEUED_EVENTS: usize = 4096;
+
+/// Syscall classes accepted by `trace=`, after strace's `%file` etc.
+const TRACE_CLASSES: &[(&str, &[SyscallNr])] = &[
+    (
+        "file",
+        &[
+            SyscallNr::Open,
+            SyscallNr::Mkdir,
+            SyscallNr::Readdir,
+            SyscallNr::Unlink,
+            SyscallNr::Rmdir,
+            SyscallNr::Rename,
+            SyscallNr::Symlink,
+            SyscallNr::Readlink,
+            SyscallNr::Stat,
+            SyscallNr::Copy,
+            SyscallNr::AtomicWrite,
+            SyscallNr::Setxattr,
+            SyscallNr::Getxattr,
+            SyscallNr::Listxattr,
+            SyscallNr::Removexattr,
+            SyscallNr::Utimes,
+            SyscallNr::Chdir,
+            SyscallNr::Chmod,
+            SyscallNr::Chown,
+            SyscallNr::Chroot,
+        ],
+    ),
+    (
+        "desc",
+        &[
+            SyscallNr::Read,
+            SyscallNr::Write,
+            SyscallNr::Open,
+            SyscallNr::Close,
+            SyscallNr::Seek,
+            SyscallNr::Pread,
+            SyscallNr::Pwrite,
+            SyscallNr::Fsync,
+            SyscallNr::Fdatasync,
+            SyscallNr::Pipe,
+            SyscallNr::Dup,
+            SyscallNr::Ioctl,
+            SyscallNr::Flock,
+            SyscallNr::Fcntl,
+            SyscallNr::Lockf,
+        ],
+    ),
+    (
+        "process",
+        &[SyscallNr::Exit, SyscallNr::Spawn, SyscallNr::Waitpid],
+    ),
+    (
+        "signal",
+        &[
+            SyscallNr::Kill,
+            SyscallNr::Signal,
+            SyscallNr::Sigblock,
+            SyscallNr::Sigunblock,
+            SyscallNr::Sigpending,
+            SyscallNr::Alarm,
+        ],
+    ),
+    (
+        "ipc",
+        &[
+            SyscallNr::Shmget,
+            SyscallNr::Shmat,
+            SyscallNr::Shmdt,
+            SyscallNr::Msgget,
+            SyscallNr::Msgsnd,
+            SyscallNr::Msgrcv,
+            SyscallNr::Msgctl,
+            SyscallNr::Semget,
+            SyscallNr::Semop,
+        ],
+    ),
+    (
+        "memory",
+        &[
+            SyscallNr::MemAlloc,
+            SyscallNr::MemFree,
+            SyscallNr::MemRead,
+            SyscallNr::MemWrite,
+        ],
+    ),
+    (
+        "env",
+        &[
+            SyscallNr::Getenv,
+            SyscallNr::Setenv,
+            SyscallNr::Unsetenv,
+            SyscallNr::Environ,
+        ],
+    ),
+];
+
+/// Which syscalls a tracer wants to see
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyscallFilter {
+    /// `None` traces everything
+    allowed: Option<HashSet<SyscallNr>>,
+}
+
+impl SyscallFilter {
+    /// Trace every syscall
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// Parse an strace-style set: `file`, `trace=file,process`, `open,read`
+    ///
+    /// Classes may be written with or without a leading `%`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let list = spec.strip_prefix("trace=").unwrap_or(spec);
+        let mut allowed = HashSet::new();
+        for item in list.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            if item == "all" || item == "%all" {
+                return Ok(Self::all());
+            }
+            let class = item.strip_prefix('%').unwrap_or(item);
+            if let Some((_, members)) = TRACE_CLASSES.iter().find(|(name, _)| *name == class) {
+                allowed.extend(members.iter().copied());
+            } else if let Some(nr) = SyscallNr::from_name(item) {
+                allowed.insert(nr);
+            } else {
+                return Err(format!("invalid system call '{}'", item));
+            }
+        }
+        if allowed.is_empty() {
+            return Err("empty syscall set".to_string());
+        }
+        Ok(Self {
+            allowed: Some(allowed),
+        })
+    }
+
+    pub fn matches(&self, nr: SyscallNr) -> bool {
+        self.allowed.as_ref().is_none_or(|set| set.contains(&nr))
+    }
+}
+
+/// Options given when attaching
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PtraceOptions {
+    /// Also trace children the tracee spawns or forks
+    pub follow_children: bool,
+    /// Syscalls to report
+    pub filter: SyscallFilter,
+}
+
+/// Something that happened to a traced process
+#[derive(Debug, Clone, PartialEq)]
+pub enum PtraceEvent {
+    /// Syscall entry with its formatted arguments
+    SyscallEnter {
+        pid: Pid,
+        nr: SyscallNr,
+        args: String,
+    },
+    /// Syscall exit with its formatted return value or error
+    SyscallExit {
+        pid: Pid,
+        nr: SyscallNr,
+        ret: Result<String, String>,
+    },
+    /// A followed child was attached automatically
+    Attached { pid: Pid, parent: Pid },
+    /// The tracee exited; tracing of it has ended
+    Exited { pid: Pid, code: i32 },
+}
+
+impl PtraceEvent {
+    pub fn pid(&self) -> Pid {
+        match self {
+            PtraceEvent::SyscallEnter { pid, .. }
+            | PtraceEvent::SyscallExit { pid, .. }
+            | PtraceEvent::Attached { pid, .. }
+            | PtraceEvent::Exited { pid, .. } => *pid,
+        }
+    }
+}
+
+/// Error from attach/detach
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PtraceError {
+    /// The tracee is already traced by another process
+    AlreadyTraced(Pid),
+    /// The process is not traced by the caller
+    NotTraced,
+}
+
+impl std::fmt::Display for PtraceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PtraceError::AlreadyTraced(tracer) => {
+                write!(f, "already traced by process {}", tracer.0)
+            }
+            PtraceError::NotTraced => write!(f, "not traced by this process"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Attachment {
+    tracer: Pid,
+    options: PtraceOptions,
+}
+
+#[derive(Debug, Default)]
+struct EventQueue {
+    events: VecDeque<PtraceEvent>,
+    dropped: u64,
+}
+
+/// All active trace attachments, owned by the kernel
+#[derive(Debug, Default)]
+pub struct PtraceTable {
+    /// Tracee -> who traces it and how
+    attachments: HashMap<Pid, Attachment>,
+    /// Tracer -> events waiting to be collected
+    queues: HashMap<Pid, EventQueue>,
+}
+
+impl PtraceTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start tracing `tracee`, or update the options of an existing attach
+    pub fn attach(
+        &mut self,
+        tracer: Pid,
+        tracee: Pid,
+        options: PtraceOptions,
+    ) -> Result<(), PtraceError> {
+        if let Some(existing) = self.attachments.get(&tracee)
+            && existing.tracer != tracer
+        {
+            return Err(PtraceError::AlreadyTraced(existing.tracer));
+        }
+        self.attachments
+            .insert(tracee, Attachment { tracer, options });
+        self.queues.entry(tracer).or_default();
+        Ok(())
+    }
+
+    /// Stop tracing `tracee`
+    pub fn detach(&mut self, tracer: Pid, tracee: Pid) -> Result<(), PtraceError> {
+        match self.attachments.get(&tracee) {
+            Some(a) if a.tracer == tracer => {
+                self.attachments.remove(&tracee);
+                if !self.attachments.values().any(|a| a.tracer == tracer) {
+                    self.queues.remove(&tracer);
+                }
+                Ok(())
+            }
+            _ => Err(PtraceError::NotTraced),
+        }
+    }
+
+    /// Who traces `pid`, if anyone
+    pub fn tracer_of(&self, pid: Pid) -> Option<Pid> {
+        self.attachments.get(&pid).map(|a| a.tracer)
+    }
+
+    /// Processes traced by `tracer`, sorted by PID
+    pub fn tracees(&self, tracer: Pid) -> Vec<Pid> {
+        let mut pids: Vec<Pid> = self
+            .attachments
+            .iter()
+            .filter(|(_, a)| a.tracer == tracer)
+            .map(|(pid, _)| *pid)
+            .collect();
+        pids.sort_by_key(|p| p.0);
+        pids
+    }
+
+    /// Whether syscall `nr` made by `pid` should be reported
+    pub fn wants(&self, pid: Pid, nr: SyscallNr) -> bool {
+        self.attachments
+            .get(&pid)
+            .is_some_and(|a| a.options.filter.matches(nr))
+    }
+
+    /// Queue an event for whoever traces its process
+    pub fn record(&mut self, event: PtraceEvent) {
+        let Some(attachment) = self.attachments.get(&event.pid()) else {
+            return;
+        };
+        let queue = self.queues.entry(attachment.tracer).or_default();
+        if queue.events.len() >= MAX_QUEUED_EVENTS {
+            queue.events.pop_front();
+            queue.dropped += 1;
+        }
+        queue.events.push_back(event);
+    }
+
+    /// A process was created; attach it if its parent is followed
+    pub fn on_spawn(&mut self, parent: Pid, child: Pid) {
+        let Some(attachment) = self.attachments.get(&parent) else {
+            return;
+        };
+        if !attachment.options.follow_children {
+            return;
+        }
+        let attachment = attachment.clone();
+        self.attachments.insert(child, attachment);
+        self.record(PtraceEvent::Attached { pid: child, parent });
+    }
+
+    /// A process exited: report it to its tracer and drop anything it traced
+    pub fn on_exit(&mut self, pid: Pid, code: i32) {
+        self.record(PtraceEvent::Exited { pid, code });
+        self.attachments.remove(&pid);
+        self.attachments.retain(|_, a| a.tracer != pid);
+        self.queues.remove(&pid);
+    }
+
+    /// Take the queued events for `tracer`, with the number dropped since
+    /// the last call
+    pub fn take_events(&mut self, tracer: Pid) -> (Vec<PtraceEvent>, u64) {
+        match self.queues.get_mut(&tracer) {
+            Some(queue) => {
+                let dropped = std::mem::take(&mut queue.dropped);
+                (queue.events.drain(..).collect(), dropped)
+            }
+            None => (Vec::new(), 0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enter(pid: u32, nr: SyscallNr) -> PtraceEvent {
+        PtraceEvent::SyscallEnter {
+            pid: Pid(pid),
+            nr,
+            args: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_filter_parse() {
+        let filter = SyscallFilter::parse("trace=file").unwrap();
+        assert!(filter.matches(SyscallNr::Open));
+        assert!(filter.matches(SyscallNr::Unlink));
+        assert!(!filter.matches(SyscallNr::Write));
+
+        let filter = SyscallFilter::parse("%process,write").unwrap();
+        assert!(filter.matches(SyscallNr::Exit));
+        assert!(filter.matches(SyscallNr::Write));
+        assert!(!filter.matches(SyscallNr::Read));
+
+        assert!(
+            SyscallFilter::parse("all")
+                .unwrap()
+                .matches(SyscallNr::Read)
+        );
+        assert!(SyscallFilter::parse("trace=bogus").is_err());
+        assert!(SyscallFilter::parse("trace=").is_err());
+    }
+
+    #[test]
+    fn test_attach_record_take() {
+        let mut table = PtraceTable::new();
+        table
+            .attach(Pid(1), Pid(2), PtraceOptions::default())
+            .unwrap();
+        assert_eq!(
+            table.attach(Pid(3), Pid(2), PtraceOptions::default()),
+            Err(PtraceError::AlreadyTraced(Pid(1)))
+        );
+
+        table.record(enter(2, SyscallNr::Open));
+        // Untraced processes are ignored
+        table.record(enter(5, SyscallNr::Open));
+
+        let (events, dropped) = table.take_events(Pid(1));
+        assert_eq!(events, vec![enter(2, SyscallNr::Open)]);
+        assert_eq!(dropped, 0);
+        assert!(table.take_events(Pid(1)).0.is_empty());
+
+        table.detach(Pid(1), Pid(2)).unwrap();
+        assert_eq!(table.tracer_of(Pid(2)), None);
+        assert_eq!(table.detach(Pid(1), Pid(2)), Err(PtraceError::NotTraced));
+    }
+
+    #[test]
+    fn test_follow_children_and_exit() {
+        let mut table = PtraceTable::new();
+        let options = PtraceOptions {
+            follow_children: true,
+            filter: SyscallFilter::parse("file").unwrap(),
+        };
+        table.attach(Pid(1), Pid(2), options).unwrap();
+
+        table.on_spawn(Pid(2), Pid(7));
+        assert_eq!(table.tracer_of(Pid(7)), Some(Pid(1)));
+        assert!(table.wants(Pid(7), SyscallNr::Open));
+        assert!(!table.wants(Pid(7), SyscallNr::Read));
+
+        table.on_exit(Pid(7), 3);
+        assert_eq!(table.tracer_of(Pid(7)), None);
+        let (events, _) = table.take_events(Pid(1));
+        assert_eq!(
+            events,
+            vec![
+                PtraceEvent::Attached {
+                    pid: Pid(7),
+                    parent: Pid(2)
+                },
+                PtraceEvent::Exited {
+                    pid: Pid(7),
+                    code: 3
+                },
+            ]
+        );
+
+        // Without -f children are not traced
+        table
+            .attach(Pid(1), Pid(2), PtraceOptions::default())
+            .unwrap();
+        table.on_spawn(Pid(2), Pid(8));
+        assert_eq!(table.tracer_of(Pid(8)), None);
+    }
+
+    #[test]
+    fn test_queue_is_bounded() {
+        let mut table = PtraceTable::new();
+        table
+            .attach(Pid(1), Pid(2), PtraceOptions::default())
+            .unwrap();
+        for _ in 0..MAX_QUEUED_EVENTS + 5 {
+            table.record(enter(2, SyscallNr::Read));
+        }
+        let (events, dropped) = table.take_events(Pid(1));
+        assert_eq!(events.len(), MAX_QUEUED_EVENTS);
+        assert_eq!(dropped, 5);
+    }
+}
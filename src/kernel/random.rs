@@ -0,0 +1,256 @@
+//! Kernel random number generator
+//!
+//! A ChaCha20 CSPRNG. The key is seeded from the host's entropy source
+//! ([`crate::platform::entropy`], `crypto.getRandomValues` in the browser)
+//! and ChaCha20 blocks (RFC 8439) under it are the output. Each refill
+//! takes a new key from the front of the keystream before handing anything
+//! out and the bytes handed out are wiped, so state captured later can't
+//! reproduce earlier output ("fast key erasure"). Fresh entropy is mixed
+//! into the key every [`RESEED_BYTES`].
+//!
+//! /dev/random, /dev/urandom, [`getrandom`](super::syscall::getrandom),
+//! salts and nonces all draw from one generator per thread.
+
+use std::cell::RefCell;
+
+use super::crypto::hmac_sha256;
+use crate::platform::{self, PlatformResult};
+
+/// Bytes handed out between reseeds from the entropy source
+pub const RESEED_BYTES: u64 = 1 << 20;
+
+/// ChaCha20 blocks generated per refill
+const BLOCKS: usize = 16;
+
+const BLOCK_LEN: usize = 64;
+
+/// "expand 32-byte k"
+const SIGMA: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+
+thread_local! {
+    static RNG: RefCell<Option<ChaChaRng>> = const { RefCell::new(None) };
+}
+
+fn quarter_round(s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(16);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(12);
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(8);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(7);
+}
+
+fn le_words<const N: usize>(bytes: &[u8]) -> [u32; N] {
+    let mut words = [0; N];
+    for (word, chunk) in words.iter_mut().zip(bytes.chunks_exact(4)) {
+        *word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    }
+    words
+}
+
+/// The ChaCha20 block for `key`, block `counter` and `nonce` (RFC 8439)
+pub fn chacha20_block(key: &[u8; 32], counter: u32, nonce: &[u8; 12]) -> [u8; BLOCK_LEN] {
+    let key: [u32; 8] = le_words(key);
+    let nonce: [u32; 3] = le_words(nonce);
+    let mut state = [0u32; 16];
+    state[..4].copy_from_slice(&SIGMA);
+    state[4..12].copy_from_slice(&key);
+    state[12] = counter;
+    state[13..].copy_from_slice(&nonce);
+
+    let mut working = state;
+    for _ in 0..10 {
+        quarter_round(&mut working, 0, 4, 8, 12);
+        quarter_round(&mut working, 1, 5, 9, 13);
+        quarter_round(&mut working, 2, 6, 10, 14);
+        quarter_round(&mut working, 3, 7, 11, 15);
+        quarter_round(&mut working, 0, 5, 10, 15);
+        quarter_round(&mut working, 1, 6, 11, 12);
+        quarter_round(&mut working, 2, 7, 8, 13);
+        quarter_round(&mut working, 3, 4, 9, 14);
+    }
+
+    let mut out = [0u8; BLOCK_LEN];
+    for (i, chunk) in out.chunks_exact_mut(4).enumerate() {
+        chunk.copy_from_slice(&working[i].wrapping_add(state[i]).to_le_bytes());
+    }
+    out
+}
+
+/// ChaCha20 keystream generator with fast key erasure
+pub struct ChaChaRng {
+    key: [u8; 32],
+    buf: [u8; BLOCKS * BLOCK_LEN],
+    /// Next unused byte of `buf`
+    pos: usize,
+    /// Bytes handed out since the last reseed
+    since_reseed: u64,
+}
+
+impl ChaChaRng {
+    pub fn from_seed(seed: [u8; 32]) -> Self {
+        Self {
+            key: seed,
+            buf: [0; BLOCKS * BLOCK_LEN],
+            pos: BLOCKS * BLOCK_LEN,
+            since_reseed: 0,
+        }
+    }
+
+    /// Generate more keystream, replacing the key with its first 32 bytes
+    fn refill(&mut self) {
+        for (counter, block) in self.buf.chunks_exact_mut(BLOCK_LEN).enumerate() {
+            block.copy_from_slice(&chacha20_block(&self.key, counter as u32, &[0; 12]));
+        }
+        self.key.copy_from_slice(&self.buf[..32]);
+        self.buf[..32].fill(0);
+        self.pos = 32;
+    }
+
+    pub fn fill(&mut self, out: &mut [u8]) {
+        let mut done = 0;
+        while done < out.len() {
+            if self.pos == self.buf.len() {
+                self.refill();
+            }
+            let n = (out.len() - done).min(self.buf.len() - self.pos);
+            let bytes = &mut self.buf[self.pos..self.pos + n];
+            out[done..done + n].copy_from_slice(bytes);
+            bytes.fill(0);
+            self.pos += n;
+            done += n;
+        }
+        self.since_reseed += out.len() as u64;
+    }
+
+    /// Mix `entropy` into the key, dropping any keystream already made
+    pub fn reseed(&mut self, entropy: &[u8]) {
+        self.key = hmac_sha256(&self.key, entropy);
+        self.buf.fill(0);
+        self.pos = self.buf.len();
+        self.since_reseed = 0;
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0; 8];
+        self.fill(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    /// A uniform number below `bound` (which must not be 0), without
+    /// modulo bias
+    pub fn below(&mut self, bound: u64) -> u64 {
+        // Reject the top partial copy of 0..bound in the u64 range
+        let zone = u64::MAX - (u64::MAX % bound.max(1));
+        loop {
+            let n = self.next_u64();
+            if n < zone {
+                return n % bound.max(1);
+            }
+        }
+    }
+}
+
+/// Fill `buf` from this thread's generator, seeding it first if needed
+///
+/// Fails only if the generator has never been seeded and the entropy
+/// source is unavailable; a failed reseed keeps the current key.
+pub fn fill(buf: &mut [u8]) -> PlatformResult<()> {
+    RNG.with(|rng| {
+        let mut rng = rng.borrow_mut();
+        let mut seed = [0u8; 32];
+        match rng.as_mut() {
+            None => {
+                platform::entropy(&mut seed)?;
+                *rng = Some(ChaChaRng::from_seed(seed));
+            }
+            Some(current) if current.since_reseed >= RESEED_BYTES => {
+                if platform::entropy(&mut seed).is_ok() {
+                    current.reseed(&seed);
+                }
+            }
+            Some(_) => {}
+        }
+        if let Some(rng) = rng.as_mut() {
+            rng.fill(buf);
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chacha20_block() {
+        // RFC 8439 section 2.3.2
+        let key: [u8; 32] = std::array::from_fn(|i| i as u8);
+        let nonce = [0, 0, 0, 0x09, 0, 0, 0, 0x4a, 0, 0, 0, 0];
+        let block = chacha20_block(&key, 1, &nonce);
+        assert_eq!(
+            block[..16],
+            [
+                0x10, 0xf1, 0xe7, 0xe4, 0xd1, 0x3b, 0x59, 0x15, 0x50, 0x0f, 0xdd, 0x1f, 0xa3, 0x20,
+                0x71, 0xc4
+            ]
+        );
+        assert_eq!(
+            block[48..],
+            [
+                0xb5, 0x12, 0x9c, 0xd1, 0xde, 0x16, 0x4e, 0xb9, 0xcb, 0xd0, 0x83, 0xe8, 0xa2, 0x50,
+                0x3c, 0x4e
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rng() {
+        let mut a = ChaChaRng::from_seed([7; 32]);
+        let mut b = ChaChaRng::from_seed([7; 32]);
+        let (mut x, mut y) = ([0u8; 100], [0u8; 100]);
+        a.fill(&mut x);
+        b.fill(&mut y[..40]);
+        b.fill(&mut y[40..]);
+        // The same seed gives the same stream however it is read
+        assert_eq!(x, y);
+        // The key itself is never output
+        let mut first = [0u8; 32];
+        ChaChaRng::from_seed([7; 32]).fill(&mut first);
+        assert_ne!(first, chacha20_block(&[7; 32], 0, &[0; 12])[..32]);
+        assert_eq!(first, chacha20_block(&[7; 32], 0, &[0; 12])[32..]);
+
+        // Long reads cross refills
+        let mut long = vec![0u8; 5000];
+        a.fill(&mut long);
+        assert!(long.iter().filter(|&&b| b == 0).count() < 100);
+
+        a.reseed(b"more");
+        let mut after = [0u8; 100];
+        a.fill(&mut after);
+        b.fill(&mut long);
+        let mut unseeded = [0u8; 100];
+        b.fill(&mut unseeded);
+        assert_ne!(after, unseeded);
+
+        for bound in [1, 2, 3, 10, 1000] {
+            assert!((0..200).all(|_| a.below(bound) < bound));
+        }
+        let mut seen = [false; 6];
+        for _ in 0..200 {
+            seen[a.below(6) as usize] = true;
+        }
+        assert!(seen.iter().all(|&s| s));
+    }
+
+    #[test]
+    fn test_fill() {
+        let (mut x, mut y) = ([0u8; 32], [0u8; 32]);
+        fill(&mut x).unwrap();
+        fill(&mut y).unwrap();
+        assert_ne!(x, y);
+        assert_ne!(x, [0; 32]);
+    }
+}
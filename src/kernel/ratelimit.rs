@@ -0,0 +1,78 @@
+//! Bandwidth limiting for network transfers
+//!
+//! A token bucket: the bucket fills at the configured rate up to one
+//! second's worth, and sending takes from it. A send larger than what is in
+//! the bucket goes into debt, and [`RateLimiter::reserve`] says how long to
+//! wait before it to pay the debt off, so transfers average out at the rate
+//! however they are split up.
+
+/// Paces transfers to a number of bytes per second
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimiter {
+    /// Bytes per millisecond, 0 for no limit
+    rate: f64,
+    /// Bytes available now (negative when in debt)
+    tokens: f64,
+    /// When `tokens` was last brought up to date
+    last: Option<f64>,
+}
+
+impl RateLimiter {
+    /// Limit to `bytes_per_sec`; 0 means no limit
+    pub fn new(bytes_per_sec: u64) -> Self {
+        let rate = bytes_per_sec as f64 / 1000.0;
+        Self {
+            rate,
+            tokens: rate * 1000.0,
+            last: None,
+        }
+    }
+
+    /// Check if there is a limit at all
+    pub fn is_limited(&self) -> bool {
+        self.rate > 0.0
+    }
+
+    /// Take `bytes` at time `now` (milliseconds); returns how long to wait
+    /// before sending them
+    pub fn reserve(&mut self, now: f64, bytes: usize) -> f64 {
+        if !self.is_limited() {
+            return 0.0;
+        }
+        let burst = self.rate * 1000.0;
+        if let Some(last) = self.last {
+            self.tokens = (self.tokens + (now - last).max(0.0) * self.rate).min(burst);
+        }
+        self.last = Some(now);
+        self.tokens -= bytes as f64;
+        if self.tokens >= 0.0 {
+            0.0
+        } else {
+            -self.tokens / self.rate
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reserve() {
+        let mut unlimited = RateLimiter::new(0);
+        assert!(!unlimited.is_limited());
+        assert_eq!(unlimited.reserve(0.0, 1 << 30), 0.0);
+
+        // 1000 bytes/s: the first second's worth goes at once
+        let mut limiter = RateLimiter::new(1000);
+        assert_eq!(limiter.reserve(0.0, 1000), 0.0);
+        // The next 500 bytes have to wait half a second
+        assert_eq!(limiter.reserve(0.0, 500), 500.0);
+        // Sent after waiting, then 1500 more bytes later on
+        assert_eq!(limiter.reserve(500.0, 1500), 1500.0);
+
+        // An idle limiter only saves up one second
+        assert_eq!(limiter.reserve(60_000.0, 1000), 0.0);
+        assert_eq!(limiter.reserve(60_000.0, 100), 100.0);
+    }
+}
@@ -5,6 +5,11 @@
 
 use std::collections::HashMap;
 
+/// semop flag: undo the operation when the process exits
+pub const SEM_UNDO: i32 = 0x1000;
+/// semop flag: fail instead of waiting
+pub const IPC_NOWAIT: i32 = 0o4000;
+
 /// Semaphore ID
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct SemId(pub u32);
@@ -41,6 +46,8 @@ impl Semaphore {
 pub struct SemaphoreSet {
     /// Set ID
     pub id: SemId,
+    /// Key the set was created with (None for private sets)
+    pub key: Option<i32>,
     /// Semaphores in this set
     semaphores: Vec<Semaphore>,
     /// Owner UID
@@ -59,6 +66,7 @@ impl SemaphoreSet {
     pub fn new(id: SemId, nsems: usize, uid: u32, gid: u32, now: f64) -> Self {
         Self {
             id,
+            key: None,
             semaphores: vec![Semaphore::new(0); nsems],
             uid,
             gid,
@@ -350,7 +358,8 @@ impl SemaphoreManager {
         // Create new
         let id = SemId(self.next_id);
         self.next_id += 1;
-        let set = SemaphoreSet::new(id, nsems, uid, gid, now);
+        let mut set = SemaphoreSet::new(id, nsems, uid, gid, now);
+        set.key = Some(key);
         self.sets.insert(id, set);
         self.key_map.insert(key, id);
         Ok(id)
@@ -416,6 +425,19 @@ impl SemaphoreManager {
         }
     }
 
+    /// Processes that took from a semaphore with SEM_UNDO and haven't put
+    /// it back, i.e. those a process waiting on it is waiting for
+    pub fn holders(&self, id: SemId, sem_num: usize) -> Vec<u32> {
+        let mut pids: Vec<u32> = self
+            .sem_adjs
+            .iter()
+            .filter(|(_, adj)| adj.adjustments.get(&(id, sem_num)).is_some_and(|&a| a > 0))
+            .map(|(&pid, _)| pid)
+            .collect();
+        pids.sort_unstable();
+        pids
+    }
+
     /// Get the semadj for a process (for debugging/introspection)
     pub fn get_sem_adj(&self, pid: u32) -> Option<&SemAdj> {
         self.sem_adjs.get(&pid)
@@ -479,9 +501,39 @@ impl SemaphoreManager {
         Ok(())
     }
 
-    /// List all semaphore set IDs
+    /// Set ownership and permissions (IPC_SET)
+    pub fn semctl_set(
+        &mut self,
+        id: SemId,
+        uid: Option<u32>,
+        gid: Option<u32>,
+        mode: Option<u16>,
+    ) -> Result<(), SemError> {
+        let set = self.sets.get_mut(&id).ok_or(SemError::NotFound)?;
+
+        if let Some(u) = uid {
+            set.uid = u;
+        }
+        if let Some(g) = gid {
+            set.gid = g;
+        }
+        if let Some(m) = mode {
+            set.mode = m & 0o777;
+        }
+
+        Ok(())
+    }
+
+    /// Set created with `key`, if any
+    pub fn find_key(&self, key: i32) -> Option<SemId> {
+        self.key_map.get(&key).copied()
+    }
+
+    /// List all semaphore set IDs, in ascending order
     pub fn list(&self) -> Vec<SemId> {
-        self.sets.keys().copied().collect()
+        let mut ids: Vec<_> = self.sets.keys().copied().collect();
+        ids.sort_by_key(|id| id.0);
+        ids
     }
 
     /// Get info about a set
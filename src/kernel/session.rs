@@ -0,0 +1,166 @@
+//! Session lock
+//!
+//! The session locks when the user runs `lock` or when no input has arrived
+//! for the idle timeout (tracked by [`IdleMonitor`]). While locked, the
+//! platform blanks the screen and routes keys to a password prompt instead
+//! of the shell. Only input routing and drawing stop: the executor, timers
+//! and services keep running.
+//!
+//! The session unlocks with the password of the user who was logged in when
+//! it locked, or with root's. A user without a password unlocks with any
+//! input, as with `login`.
+
+use super::users::{Uid, UserDb};
+use crate::platform::idle::IdleMonitor;
+
+/// Why the session locked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockReason {
+    /// The `lock` command
+    Manual,
+    /// The idle timeout passed
+    Idle,
+}
+
+/// A held session lock
+#[derive(Debug, Clone, PartialEq)]
+pub struct LockInfo {
+    /// User whose password unlocks the session
+    pub uid: Uid,
+    pub reason: LockReason,
+    /// Platform time the lock was taken
+    pub since: f64,
+    /// Wrong passwords entered since locking
+    pub failed_attempts: u32,
+}
+
+/// Errors from unlocking the session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnlockError {
+    NotLocked,
+    BadPassword,
+}
+
+impl std::fmt::Display for UnlockError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnlockError::NotLocked => write!(f, "session is not locked"),
+            UnlockError::BadPassword => write!(f, "incorrect password"),
+        }
+    }
+}
+
+/// Lock state and idle tracking for the console session
+#[derive(Debug, Clone, Default)]
+pub struct SessionLock {
+    idle: IdleMonitor,
+    locked: Option<LockInfo>,
+}
+
+impl SessionLock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The idle monitor
+    pub fn idle(&self) -> &IdleMonitor {
+        &self.idle
+    }
+
+    /// Change the idle timeout; `None` disables auto-lock
+    pub fn set_idle_timeout(&mut self, timeout: Option<f64>) {
+        self.idle.set_timeout(timeout);
+    }
+
+    /// The current lock, if the session is locked
+    pub fn locked(&self) -> Option<&LockInfo> {
+        self.locked.as_ref()
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked.is_some()
+    }
+
+    /// Record an input event (keys typed at the lock prompt count too)
+    pub fn input(&mut self, now: f64) {
+        self.idle.input(now);
+    }
+
+    /// Lock the session for `uid`; returns false if it was already locked
+    pub fn lock(&mut self, uid: Uid, reason: LockReason) -> bool {
+        if self.locked.is_some() {
+            return false;
+        }
+        self.locked = Some(LockInfo {
+            uid,
+            reason,
+            since: self.idle.now(),
+            failed_attempts: 0,
+        });
+        true
+    }
+
+    /// Report the time and lock for `uid` if the idle timeout has passed
+    ///
+    /// Returns true if this call locked the session.
+    pub fn poll(&mut self, uid: Uid, now: f64) -> bool {
+        self.idle.expired(now) && self.lock(uid, LockReason::Idle)
+    }
+
+    /// Unlock with the locking user's or root's password
+    pub fn unlock(&mut self, users: &UserDb, password: &str) -> Result<Uid, UnlockError> {
+        let lock = self.locked.as_mut().ok_or(UnlockError::NotLocked)?;
+        let accepted = [lock.uid, Uid(0)]
+            .iter()
+            .filter_map(|uid| users.get_user(*uid))
+            .any(|user| user.check_password(password));
+        if !accepted {
+            lock.failed_attempts += 1;
+            return Err(UnlockError::BadPassword);
+        }
+        let uid = lock.uid;
+        self.locked = None;
+        self.idle.reset();
+        Ok(uid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_and_unlock() {
+        let mut users = UserDb::new();
+        let uid = users.add_user("alice", None).unwrap();
+        users.get_user_mut(uid).unwrap().set_password("secret");
+        users.get_user_mut(Uid(0)).unwrap().set_password("rootpw");
+
+        let mut session = SessionLock::new();
+        session.set_idle_timeout(Some(1000.0));
+        session.input(0.0);
+        assert!(!session.poll(uid, 500.0));
+        assert!(session.poll(uid, 1000.0));
+        assert_eq!(session.locked().map(|l| l.reason), Some(LockReason::Idle));
+        assert!(!session.lock(uid, LockReason::Manual));
+        assert_eq!(session.locked().map(|l| l.since), Some(1000.0));
+
+        assert_eq!(
+            session.unlock(&users, "wrong"),
+            Err(UnlockError::BadPassword)
+        );
+        assert_eq!(session.locked().map(|l| l.failed_attempts), Some(1));
+        assert_eq!(session.unlock(&users, "secret"), Ok(uid));
+        assert_eq!(
+            session.unlock(&users, "secret"),
+            Err(UnlockError::NotLocked)
+        );
+
+        // Unlocking restarts the idle clock
+        assert!(!session.poll(uid, 1500.0));
+
+        // Root's password works too
+        assert!(session.lock(uid, LockReason::Manual));
+        assert_eq!(session.unlock(&users, "rootpw"), Ok(uid));
+    }
+}
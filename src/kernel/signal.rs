@@ -10,29 +10,38 @@
 //! Note: axeberg uses its own signal numbering scheme for simplicity and clarity.
 //! These numbers intentionally differ from POSIX conventions:
 //!
-//! | Signal   | axeberg | POSIX |
-//! |----------|---------|-------|
-//! | SIGTERM  | 1       | 15    |
-//! | SIGKILL  | 2       | 9     |
-//! | SIGSTOP  | 3       | 19    |
-//! | SIGCONT  | 4       | 18    |
-//! | SIGINT   | 5       | 2     |
-//! | SIGQUIT  | 6       | 3     |
-//! | SIGHUP   | 7       | 1     |
-//! | SIGUSR1  | 8       | 10    |
-//! | SIGUSR2  | 9       | 12    |
-//! | SIGCHLD  | 10      | 17    |
-//! | SIGALRM  | 11      | 14    |
-//! | SIGPIPE  | 12      | 13    |
+//! | Signal         | axeberg | POSIX |
+//! |----------------|---------|-------|
+//! | SIGTERM        | 1       | 15    |
+//! | SIGKILL        | 2       | 9     |
+//! | SIGSTOP        | 3       | 19    |
+//! | SIGCONT        | 4       | 18    |
+//! | SIGINT         | 5       | 2     |
+//! | SIGQUIT        | 6       | 3     |
+//! | SIGHUP         | 7       | 1     |
+//! | SIGUSR1        | 8       | 10    |
+//! | SIGUSR2        | 9       | 12    |
+//! | SIGCHLD        | 10      | 17    |
+//! | SIGALRM        | 11      | 14    |
+//! | SIGPIPE        | 12      | 13    |
+//! | SIGMEMPRESSURE | 13      | -     |
+//!
+//! SIGMEMPRESSURE has no POSIX counterpart: the kernel sends it when memory
+//! runs short (see [`pressure`](super::pressure)) to processes that handle
+//! or block it, so they can free caches. Others ignore it.
 //!
 //! The rationale for custom numbering:
-//! - Simpler mental model (signals numbered 1-12)
+//! - Simpler mental model (signals numbered 1-13)
 //! - Easier to remember (no gaps like POSIX)
 //! - axeberg is not POSIX-compatible, so no confusion expected
 
 use super::process::Pid;
 use std::collections::{HashMap, HashSet, VecDeque};
 
+/// Bytes per record read from a signalfd: the signal number as a
+/// little-endian u32
+pub const SIGNALFD_RECORD_SIZE: usize = 4;
+
 /// Signal types
 ///
 /// See module documentation for signal number mapping.
@@ -63,9 +72,28 @@ pub enum Signal {
     SIGALRM = 11,
     /// Broken pipe
     SIGPIPE = 12,
+    /// Memory is running short
+    SIGMEMPRESSURE = 13,
 }
 
 impl Signal {
+    /// All signals, in number order
+    pub const ALL: [Signal; 13] = [
+        Signal::SIGTERM,
+        Signal::SIGKILL,
+        Signal::SIGSTOP,
+        Signal::SIGCONT,
+        Signal::SIGINT,
+        Signal::SIGQUIT,
+        Signal::SIGHUP,
+        Signal::SIGUSR1,
+        Signal::SIGUSR2,
+        Signal::SIGCHLD,
+        Signal::SIGALRM,
+        Signal::SIGPIPE,
+        Signal::SIGMEMPRESSURE,
+    ];
+
     /// Get signal from number
     pub fn from_num(n: u8) -> Option<Signal> {
         match n {
@@ -81,10 +109,21 @@ impl Signal {
             10 => Some(Signal::SIGCHLD),
             11 => Some(Signal::SIGALRM),
             12 => Some(Signal::SIGPIPE),
+            13 => Some(Signal::SIGMEMPRESSURE),
             _ => None,
         }
     }
 
+    /// Look up a signal by name, with or without the `SIG` prefix and in
+    /// any case (`INT`, `sigint`, `SIGINT`)
+    pub fn from_name(name: &str) -> Option<Signal> {
+        let upper = name.to_ascii_uppercase();
+        let name = upper.strip_prefix("SIG").unwrap_or(&upper);
+        Signal::ALL
+            .into_iter()
+            .find(|s| s.to_string()[3..] == *name)
+    }
+
     /// Get signal number
     pub fn num(&self) -> u8 {
         *self as u8
@@ -106,9 +145,11 @@ impl Signal {
             Signal::SIGKILL => SignalAction::Kill,
             Signal::SIGSTOP => SignalAction::Stop,
             Signal::SIGCONT => SignalAction::Continue,
-            Signal::SIGUSR1 | Signal::SIGUSR2 | Signal::SIGCHLD | Signal::SIGALRM => {
-                SignalAction::Ignore
-            }
+            Signal::SIGUSR1
+            | Signal::SIGUSR2
+            | Signal::SIGCHLD
+            | Signal::SIGALRM
+            | Signal::SIGMEMPRESSURE => SignalAction::Ignore,
         }
     }
 }
@@ -128,6 +169,7 @@ impl std::fmt::Display for Signal {
             Signal::SIGCHLD => write!(f, "SIGCHLD"),
             Signal::SIGALRM => write!(f, "SIGALRM"),
             Signal::SIGPIPE => write!(f, "SIGPIPE"),
+            Signal::SIGMEMPRESSURE => write!(f, "SIGMEMPRESSURE"),
         }
     }
 }
@@ -261,6 +303,23 @@ impl ProcessSignals {
         None
     }
 
+    /// Take the first pending signal in `mask` (bit N = signal N), blocked
+    /// or not, for a signalfd read
+    pub fn take_pending(&mut self, mask: u16) -> Option<Signal> {
+        let pos = self
+            .pending
+            .iter()
+            .position(|s| s.can_catch() && mask & (1 << s.num()) != 0)?;
+        self.pending.remove(pos)
+    }
+
+    /// Whether a signal in `mask` is pending, for polling a signalfd
+    pub fn has_pending_in(&self, mask: u16) -> bool {
+        self.pending
+            .iter()
+            .any(|s| s.can_catch() && mask & (1 << s.num()) != 0)
+    }
+
     /// Check if there are pending signals
     pub fn has_pending(&self) -> bool {
         self.pending
@@ -268,6 +327,28 @@ impl ProcessSignals {
             .any(|s| !self.blocked.contains(s) || !s.can_catch())
     }
 
+    /// The first pending signal that interrupts a blocking call (EINTR)
+    ///
+    /// A signal interrupts when it can be delivered and either has a
+    /// handler or ends the process; ignored, stop and continue signals
+    /// let the call carry on.
+    pub fn interrupting(&self) -> Option<Signal> {
+        self.pending.iter().copied().find(|&s| {
+            (!self.blocked.contains(&s) || !s.can_catch())
+                && matches!(
+                    resolve_action(s, &self.disposition),
+                    SignalAction::Handle | SignalAction::Terminate | SignalAction::Kill
+                )
+        })
+    }
+
+    /// Check if the process has asked for `signal`: it has a handler for
+    /// it, or blocks it to wait on it (a trap or a signalfd)
+    pub fn wants(&self, signal: Signal) -> bool {
+        self.disposition.get_action(signal) == SignalAction::Handle
+            || self.blocked.contains(&signal)
+    }
+
     /// Block a signal
     pub fn block(&mut self, signal: Signal) -> Result<(), SignalError> {
         if !signal.can_catch() {
@@ -338,11 +419,8 @@ impl ProcessSignals {
     fn set_blocked_mask(&mut self, mask: u16) -> u16 {
         let old_mask = self.get_blocked_mask();
         self.blocked.clear();
-        for i in 1..=12 {
-            if let Some(signal) = Signal::from_num(i)
-                && mask & (1 << i) != 0
-                && signal.can_catch()
-            {
+        for signal in Signal::ALL {
+            if mask & (1 << signal.num()) != 0 && signal.can_catch() {
                 self.blocked.insert(signal);
             }
         }
@@ -357,21 +435,16 @@ impl ProcessSignals {
         match how {
             SigProcMaskHow::Block => {
                 // Add signals to blocked set
-                for i in 1..=12 {
-                    if let Some(signal) = Signal::from_num(i)
-                        && mask & (1 << i) != 0
-                        && signal.can_catch()
-                    {
+                for signal in Signal::ALL {
+                    if mask & (1 << signal.num()) != 0 && signal.can_catch() {
                         self.blocked.insert(signal);
                     }
                 }
             }
             SigProcMaskHow::Unblock => {
                 // Remove signals from blocked set
-                for i in 1..=12 {
-                    if let Some(signal) = Signal::from_num(i)
-                        && mask & (1 << i) != 0
-                    {
+                for signal in Signal::ALL {
+                    if mask & (1 << signal.num()) != 0 {
                         self.blocked.remove(&signal);
                     }
                 }
@@ -480,6 +553,13 @@ mod tests {
         assert_eq!(Signal::SIGKILL.num(), 2);
         assert_eq!(Signal::from_num(1), Some(Signal::SIGTERM));
         assert_eq!(Signal::from_num(99), None);
+        for (i, signal) in Signal::ALL.into_iter().enumerate() {
+            assert_eq!(Signal::from_num(i as u8 + 1), Some(signal));
+        }
+        assert_eq!(
+            Signal::from_name("mempressure"),
+            Some(Signal::SIGMEMPRESSURE)
+        );
     }
 
     #[test]
@@ -497,6 +577,24 @@ mod tests {
         assert_eq!(Signal::SIGSTOP.default_action(), SignalAction::Stop);
         assert_eq!(Signal::SIGCONT.default_action(), SignalAction::Continue);
         assert_eq!(Signal::SIGUSR1.default_action(), SignalAction::Ignore);
+        assert_eq!(
+            Signal::SIGMEMPRESSURE.default_action(),
+            SignalAction::Ignore
+        );
+    }
+
+    #[test]
+    fn test_wants() {
+        let mut ps = ProcessSignals::new();
+        assert!(!ps.wants(Signal::SIGMEMPRESSURE));
+        ps.disposition
+            .set_action(Signal::SIGMEMPRESSURE, SignalAction::Handle)
+            .unwrap();
+        assert!(ps.wants(Signal::SIGMEMPRESSURE));
+
+        let mut ps = ProcessSignals::new();
+        ps.block(Signal::SIGMEMPRESSURE).unwrap();
+        assert!(ps.wants(Signal::SIGMEMPRESSURE));
     }
 
     #[test]
@@ -699,4 +797,50 @@ mod tests {
         assert!(pending & (1 << Signal::SIGUSR1.num()) != 0);
         assert!(pending & (1 << Signal::SIGINT.num()) == 0);
     }
+
+    #[test]
+    fn test_interrupting() {
+        let mut ps = ProcessSignals::new();
+
+        // Ignored by default: doesn't interrupt
+        ps.send(Signal::SIGUSR1);
+        ps.send(Signal::SIGCHLD);
+        assert_eq!(ps.interrupting(), None);
+
+        // A handler makes it interrupt
+        ps.disposition
+            .set_action(Signal::SIGUSR1, SignalAction::Handle)
+            .unwrap();
+        assert_eq!(ps.interrupting(), Some(Signal::SIGUSR1));
+
+        // Blocked signals wait, fatal ones interrupt
+        let mut ps = ProcessSignals::new();
+        ps.block(Signal::SIGTERM).unwrap();
+        ps.send(Signal::SIGTERM);
+        assert_eq!(ps.interrupting(), None);
+        ps.send(Signal::SIGINT);
+        assert_eq!(ps.interrupting(), Some(Signal::SIGINT));
+    }
+
+    #[test]
+    fn test_take_pending() {
+        let mut ps = ProcessSignals::new();
+        ps.block(Signal::SIGINT).unwrap();
+        ps.send(Signal::SIGTERM);
+        ps.send(Signal::SIGINT);
+        ps.send(Signal::SIGKILL);
+
+        let mask = (1 << Signal::SIGINT.num()) | (1 << Signal::SIGKILL.num());
+        assert_eq!(ps.take_pending(mask), Some(Signal::SIGINT));
+        assert_eq!(ps.take_pending(mask), None);
+        assert_eq!(ps.pending_count(), 2);
+    }
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(Signal::from_name("INT"), Some(Signal::SIGINT));
+        assert_eq!(Signal::from_name("sigterm"), Some(Signal::SIGTERM));
+        assert_eq!(Signal::from_name("SIGUSR2"), Some(Signal::SIGUSR2));
+        assert_eq!(Signal::from_name("EXIT"), None);
+    }
 }
@@ -0,0 +1,183 @@
+//! Synthetic filesystem registry
+//!
+//! /proc, /dev and /sys don't store files: their entries are generated
+//! from kernel state when listed or opened. Each one implements
+//! [`SyntheticFs`] and is registered at its mount point in a
+//! [`SyntheticFsRegistry`], and the path syscalls (`open`, `readdir`,
+//! `exists`, `metadata`, and `write` for stored attributes) dispatch to
+//! whichever filesystem owns the path. Adding another (say /run or a view
+//! of the package database) means implementing the trait and calling
+//! [`Kernel::register_synthetic_fs`]; no syscall needs to change.
+//!
+//! Paths handed to a filesystem are absolute and already resolved, e.g.
+//! `/proc/self/status`.
+
+use super::devfs::DevFs;
+use super::process::{Handle, OpenFlags};
+use super::syscall::{Kernel, SyscallError, SyscallResult};
+use std::rc::Rc;
+
+/// A filesystem whose entries are generated from kernel state
+pub trait SyntheticFs {
+    /// Filesystem type shown in the mount table, e.g. `proc`
+    fn fs_type(&self) -> &'static str;
+
+    /// Entries of the directory at `path`, or `None` if it isn't one
+    fn list_dir(&self, kernel: &Kernel, path: &str) -> Option<Vec<String>>;
+
+    /// Check if `path` exists
+    fn exists(&self, kernel: &Kernel, path: &str) -> bool;
+
+    /// Check if `path` is a directory
+    fn is_dir(&self, kernel: &Kernel, path: &str) -> bool {
+        self.list_dir(kernel, path).is_some()
+    }
+
+    /// Permission bits reported by `metadata`
+    fn mode(&self, is_dir: bool) -> u16 {
+        if is_dir { 0o555 } else { 0o444 }
+    }
+
+    /// Open the file at `path`, which exists and is not a directory
+    fn open(&self, kernel: &mut Kernel, path: &str, flags: OpenFlags) -> SyscallResult<Handle>;
+
+    /// Check if writes to `path` are applied with [`SyntheticFs::store`]
+    fn is_writable(&self, _path: &str) -> bool {
+        false
+    }
+
+    /// Apply the contents written so far to the attribute at `path`
+    fn store(&self, _kernel: &mut Kernel, _path: &str, _value: &[u8]) -> SyscallResult<()> {
+        Err(SyscallError::PermissionDenied)
+    }
+}
+
+/// Synthetic filesystems by mount point
+#[derive(Clone, Default)]
+pub struct SyntheticFsRegistry {
+    mounts: Vec<(String, Rc<dyn SyntheticFs>)>,
+}
+
+impl SyntheticFsRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registry with /proc, `devfs` at /dev and /sys
+    pub fn with_defaults(devfs: Rc<DevFs>) -> Self {
+        let mut registry = Self::new();
+        registry.register("/proc", Rc::new(super::procfs::ProcFs::new()));
+        registry.register("/dev", devfs);
+        registry.register("/sys", Rc::new(super::sysfs::SysFs::new()));
+        registry
+    }
+
+    /// Mount `fs` at `mount_point`, replacing any filesystem already there
+    pub fn register(&mut self, mount_point: &str, fs: Rc<dyn SyntheticFs>) {
+        let mount_point = mount_point.trim_end_matches('/').to_string();
+        match self.mounts.iter_mut().find(|(m, _)| *m == mount_point) {
+            Some(entry) => entry.1 = fs,
+            None => self.mounts.push((mount_point, fs)),
+        }
+    }
+
+    /// Remove the filesystem at `mount_point`; returns false if none was there
+    pub fn unregister(&mut self, mount_point: &str) -> bool {
+        let mount_point = mount_point.trim_end_matches('/');
+        let before = self.mounts.len();
+        self.mounts.retain(|(m, _)| m != mount_point);
+        self.mounts.len() != before
+    }
+
+    /// The filesystem that owns `path`, by longest mount point
+    pub fn lookup(&self, path: &str) -> Option<Rc<dyn SyntheticFs>> {
+        self.mounts
+            .iter()
+            .filter(|(m, _)| {
+                path.strip_prefix(m.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+            .max_by_key(|(m, _)| m.len())
+            .map(|(_, fs)| Rc::clone(fs))
+    }
+
+    /// Mount points, in registration order
+    pub fn mount_points(&self) -> impl Iterator<Item = &str> {
+        self.mounts.iter().map(|(m, _)| m.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::syscall::{self, KERNEL};
+
+    /// /run with a single fixed file
+    struct RunFs;
+
+    impl SyntheticFs for RunFs {
+        fn fs_type(&self) -> &'static str {
+            "runfs"
+        }
+
+        fn list_dir(&self, _kernel: &Kernel, path: &str) -> Option<Vec<String>> {
+            (path == "/run").then(|| vec!["motd".to_string()])
+        }
+
+        fn exists(&self, _kernel: &Kernel, path: &str) -> bool {
+            matches!(path, "/run" | "/run/motd")
+        }
+
+        fn open(
+            &self,
+            kernel: &mut Kernel,
+            path: &str,
+            _flags: OpenFlags,
+        ) -> SyscallResult<Handle> {
+            let uptime = format!("up {}\n", kernel.now());
+            Ok(kernel.create_file_object(path.into(), uptime.into_bytes(), true, false))
+        }
+    }
+
+    #[test]
+    fn test_lookup() {
+        let registry = SyntheticFsRegistry::with_defaults(Rc::new(DevFs::new()));
+        assert_eq!(registry.lookup("/proc").unwrap().fs_type(), "proc");
+        assert_eq!(registry.lookup("/dev/null").unwrap().fs_type(), "devfs");
+        assert!(registry.lookup("/processes").is_none());
+        assert!(registry.lookup("/home").is_none());
+        assert_eq!(
+            registry.mount_points().collect::<Vec<_>>(),
+            ["/proc", "/dev", "/sys"]
+        );
+
+        let mut registry = registry;
+        assert!(registry.unregister("/sys/"));
+        assert!(!registry.unregister("/sys"));
+        assert!(registry.lookup("/sys/kernel").is_none());
+    }
+
+    #[test]
+    fn test_register_new_fs() {
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+            k.register_synthetic_fs("/run", Rc::new(RunFs));
+        });
+
+        assert_eq!(syscall::readdir("/run").unwrap(), ["motd"]);
+        assert!(syscall::exists("/run/motd").unwrap());
+        assert!(!syscall::exists("/run/nope").unwrap());
+        let meta = syscall::metadata("/run/motd").unwrap();
+        assert!(meta.is_file);
+        assert_eq!(meta.mode, 0o444);
+        assert_eq!(syscall::read_file("/run/motd").unwrap(), "up 0\n");
+        assert_eq!(
+            syscall::open("/run", OpenFlags::READ),
+            Err(SyscallError::IsADirectory)
+        );
+        assert!(KERNEL.with(|k| k.borrow().mounts().is_mount_point("/run")));
+    }
+}
@@ -14,39 +14,82 @@
 //! - Process groups for job control (fg/bg)
 //! - Environment variables per-process
 
-use super::devfs::DevFs;
+use super::crash::{CRASH_DIR, CrashReport, FAULT_STATUS, MAX_CRASH_REPORTS};
+use super::deadlock::{DeadlockReport, WaitForGraph, WaitResource};
+use super::debugger::{
+    ArgValue, PathOp, PathWatch, PathWatchHit, SyscallArg, SyscallResult as DebugResult,
+    WasmDebugger,
+};
+use super::devfs::{DevFs, DeviceOps};
+use super::epoll::{EpollCtl, EpollEvent, EventQueue, EventSource};
+use super::executor::{PollSpan, TaskFault};
 use super::fifo::FifoRegistry;
-use super::flock::{FileLockManager, LockError, LockType, RangeLock};
-use super::init::InitSystem;
+use super::flock::{
+    F_LOCK, F_TEST, F_TLOCK, F_ULOCK, FileLockManager, LockError, LockType, RangeLock,
+};
+use super::guest::{GUEST_SOURCE, GuestSession};
+use super::httpcache::{self, CachedResponse};
+use super::init::{InitSystem, WATCHDOG_LOG, WatchdogIncident};
+use super::ipc_persist;
+use super::klog::{KernelLog, LogEntry, LogLevel};
+use super::ksyms::SymbolMap;
 use super::memory::{
     MemoryError, MemoryManager, MemoryStats, Protection, RegionId, ShmId, ShmInfo,
     SystemMemoryStats,
 };
-use super::mount::MountTable;
+use super::mount::{FsType, MountEntry, MountError, MountOptions, MountTable};
 use super::msgqueue::{MsgQueueError, MsgQueueId, MsgQueueManager, MsgQueueStats};
+use super::netperm::{
+    ALLOWLIST_PATH, Allowlist, NetDecision, NetPermissions, NetPrompt, PROMPT_TIMEOUT,
+};
 use super::object::{
-    ConsoleObject, FileObject, KernelObject, ObjectTable, PipeObject, WindowId, WindowObject,
+    ConsoleObject, FileObject, KernelObject, ObjectTable, PipeObject, SignalFdObject,
+    WatchdogObject, WindowId, WindowObject,
+};
+use super::poll::{PollEvents, PollFd, PollSource};
+use super::power::{Governor, PowerGovernor};
+use super::pressure::{self, MemorySample, PressureLevel, PressureMonitor};
+pub use super::process::{
+    EnvInherit, EnvSpec, F_GETFD, F_GETFL, F_SETFD, F_SETFL, Fd, FdFlags, Handle, O_NONBLOCK,
+    OpenFlags, Pgid, Pid, Process, ProcessState, ResourceUsage, Sid,
 };
-pub use super::process::{Fd, Handle, OpenFlags, Pgid, Pid, Process, ProcessState, Sid};
-use super::procfs::{ProcContext, ProcFs, SystemContext, generate_proc_content};
-use super::semaphore::SemaphoreManager;
-use super::signal::{SigProcMaskHow, Signal, SignalAction, SignalError, resolve_action};
-use super::sysfs::SysFs;
+use super::ptrace::{PtraceError, PtraceEvent, PtraceOptions, PtraceTable};
+use super::random;
+use super::semaphore::{IPC_NOWAIT, SEM_UNDO, SemError, SemId, SemOpResult, SemaphoreManager};
+use super::session::{LockReason, SessionLock, UnlockError};
+use super::signal::{
+    SIGNALFD_RECORD_SIZE, SigProcMaskHow, Signal, SignalAction, SignalError, resolve_action,
+};
+use super::synthfs::{SyntheticFs, SyntheticFsRegistry};
+use super::system::{self, SYSTEM_ROOT, Slot, SlotState, SystemBoot, SystemImage};
 use super::task::TaskId;
 use super::timer::{TimerId, TimerQueue};
-use super::trace::{TraceCategory, TraceSummary, Tracer};
-use super::tty::TtyManager;
-use super::uds::{SockAddr, SocketId, SocketResult, SocketType, UnixSocketManager};
+use super::trace::{TraceCategory, TraceEvent, TraceSummary, Tracer};
+use super::tty::{MouseEvent, PtySlave, TtyManager};
+use super::uds::{SockAddr, SocketError, SocketId, SocketResult, SocketType, UnixSocketManager};
 use super::users::{
-    Capability, FileMode, Gid, Group, ProcessCapabilities, Uid, User, UserDb, check_permission,
+    Capability, FileMode, Gid, Group, ProcessCapabilities, Uid, User, UserChange, UserDb,
+    check_acl_permission, check_permission,
 };
+use super::visualizer::{FdTableView, MountView, ObjectSnapshot, ObjectView};
 use crate::vfs::{
-    FileHandle as VfsFileHandle, FileSystem, MemoryFs, OpenOptions as VfsOpenOptions,
+    Acl, AclTag, ChangeCursor, EncryptedFs, FileHandle as VfsFileHandle, FileSystem, FsChange,
+    FsChangeKind, FsSnapshot, FsckReport, MemoryFs, Metadata as VfsMetadata,
+    OpenOptions as VfsOpenOptions, OverlayFs, TarFs, WebDavFs,
+    acl::{ACL_WRITE, ACL_XATTR},
+    compress,
+    export::{self, ArchiveStats},
+    memory::Repair,
+    snapshots::{self, FileVersion, SnapshotInfo},
+    sparse,
 };
+use std::borrow::Cow;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::io::SeekFrom;
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::task::Waker;
 
 // ========== SYSCALL NUMBERS ==========
 // Inspired by Linux: each syscall has a unique number for ABI stability,
@@ -65,6 +108,12 @@ pub enum SyscallNr {
     Open = 2,
     Close = 3,
     Seek = 5,
+    Poll = 7,
+    EpollCreate = 8,
+    EpollCtl = 9,
+    EpollWait = 10,
+    Pread = 17,
+    Pwrite = 18,
     Pipe = 22,
     Dup = 41,
 
@@ -78,6 +127,14 @@ pub enum SyscallNr {
     Readlink = 56,
     Stat = 57,
     Copy = 58,
+    AtomicWrite = 59,
+    Setxattr = 60,
+    Getxattr = 61,
+    Listxattr = 62,
+    Removexattr = 63,
+    Utimes = 64,
+    Fsync = 74,
+    Fdatasync = 75,
 
     // Process (100-149)
     Exit = 100,
@@ -129,6 +186,7 @@ pub enum SyscallNr {
     TraceEnable = 275,
     TraceDisable = 276,
     TraceSummary = 277,
+    Ptrace = 278,
 
     // Users/Security (300-324)
     Getuid = 300,
@@ -156,6 +214,11 @@ pub enum SyscallNr {
     // File Locking (350-359)
     Flock = 350,
     Fcntl = 351,
+    Lockf = 352,
+
+    // Semaphores (360-369)
+    Semget = 360,
+    Semop = 361,
 }
 
 /// Macro to generate syscall name lookup
@@ -176,6 +239,17 @@ macro_rules! syscall_names {
             pub fn num(&self) -> u32 {
                 *self as u32
             }
+
+            /// Every syscall, in declaration order
+            pub const ALL: &'static [SyscallNr] = &[$(SyscallNr::$variant,)*];
+
+            /// Look up a syscall by name
+            pub fn from_name(name: &str) -> Option<SyscallNr> {
+                match name {
+                    $($name => Some(SyscallNr::$variant),)*
+                    _ => None,
+                }
+            }
         }
     };
 }
@@ -188,6 +262,12 @@ syscall_names! {
     Open => "open",
     Close => "close",
     Seek => "seek",
+    Poll => "poll",
+    EpollCreate => "epoll_create",
+    EpollCtl => "epoll_ctl",
+    EpollWait => "epoll_wait",
+    Pread => "pread",
+    Pwrite => "pwrite",
     Pipe => "pipe",
     Dup => "dup",
     // Filesystem
@@ -200,6 +280,14 @@ syscall_names! {
     Readlink => "readlink",
     Stat => "stat",
     Copy => "copy",
+    AtomicWrite => "atomic_write",
+    Setxattr => "setxattr",
+    Getxattr => "getxattr",
+    Listxattr => "listxattr",
+    Removexattr => "removexattr",
+    Utimes => "utimes",
+    Fsync => "fsync",
+    Fdatasync => "fdatasync",
     // Process
     Exit => "exit",
     Getpid => "getpid",
@@ -244,6 +332,7 @@ syscall_names! {
     TraceEnable => "trace_enable",
     TraceDisable => "trace_disable",
     TraceSummary => "trace_summary",
+    Ptrace => "ptrace",
     // Users/Security
     Getuid => "getuid",
     Geteuid => "geteuid",
@@ -268,6 +357,10 @@ syscall_names! {
     // File Locking
     Flock => "flock",
     Fcntl => "fcntl",
+    Lockf => "lockf",
+    // Semaphores
+    Semget => "semget",
+    Semop => "semop",
 }
 
 impl std::fmt::Display for SyscallNr {
@@ -391,6 +484,18 @@ pub enum SyscallError {
     TooManyOpenFiles,
     /// Value too big for data type (E2BIG/EFBIG)
     TooBig,
+    /// Write to a read-only mount (EROFS)
+    ReadOnlyFs,
+    /// Waiting would deadlock (EDEADLK)
+    Deadlock,
+    /// Rename between filesystems (EXDEV)
+    CrossDevice,
+    /// No space left, or over the owner's disk quota (ENOSPC)
+    NoSpace,
+    /// No such extended attribute (ENODATA)
+    NoAttribute,
+    /// Offline mode is on (ENETDOWN)
+    NetworkDown,
 }
 
 impl std::fmt::Display for SyscallError {
@@ -414,6 +519,12 @@ impl std::fmt::Display for SyscallError {
             SyscallError::AlreadyExists => write!(f, "already exists"),
             SyscallError::TooManyOpenFiles => write!(f, "too many open files"),
             SyscallError::TooBig => write!(f, "value too large for data type"),
+            SyscallError::ReadOnlyFs => write!(f, "read-only file system"),
+            SyscallError::Deadlock => write!(f, "resource deadlock avoided"),
+            SyscallError::CrossDevice => write!(f, "invalid cross-device link"),
+            SyscallError::NoSpace => write!(f, "no space left on device"),
+            SyscallError::NoAttribute => write!(f, "no such attribute"),
+            SyscallError::NetworkDown => write!(f, "network is down (offline mode)"),
         }
     }
 }
@@ -430,6 +541,42 @@ impl From<SignalError> for SyscallError {
     }
 }
 
+impl From<MountError> for SyscallError {
+    fn from(e: MountError) -> Self {
+        match e {
+            MountError::MountPointNotFound => SyscallError::NotFound,
+            MountError::AlreadyMounted | MountError::Busy => SyscallError::Busy,
+            MountError::PermissionDenied => SyscallError::PermissionDenied,
+            MountError::NotMounted
+            | MountError::UnsupportedFilesystem
+            | MountError::InvalidOptions => SyscallError::InvalidArgument,
+        }
+    }
+}
+
+impl From<LockError> for SyscallError {
+    fn from(e: LockError) -> Self {
+        match e {
+            LockError::WouldBlock => SyscallError::WouldBlock,
+            LockError::InvalidArgument => SyscallError::InvalidArgument,
+            LockError::Deadlock => SyscallError::Deadlock,
+        }
+    }
+}
+
+impl From<SemError> for SyscallError {
+    fn from(e: SemError) -> Self {
+        match e {
+            SemError::InvalidSemNum | SemError::InvalidArgument | SemError::TooMany => {
+                SyscallError::InvalidArgument
+            }
+            SemError::NotFound => SyscallError::NotFound,
+            SemError::PermissionDenied => SyscallError::PermissionDenied,
+            SemError::AlreadyExists => SyscallError::AlreadyExists,
+        }
+    }
+}
+
 impl From<std::io::Error> for SyscallError {
     fn from(e: std::io::Error) -> Self {
         use std::io::ErrorKind;
@@ -439,6 +586,9 @@ impl From<std::io::Error> for SyscallError {
             ErrorKind::WouldBlock => SyscallError::WouldBlock,
             ErrorKind::BrokenPipe => SyscallError::BrokenPipe,
             ErrorKind::InvalidInput => SyscallError::InvalidArgument,
+            ErrorKind::ReadOnlyFilesystem => SyscallError::ReadOnlyFs,
+            ErrorKind::QuotaExceeded | ErrorKind::StorageFull => SyscallError::NoSpace,
+            ErrorKind::FileTooLarge => SyscallError::TooBig,
             _ => SyscallError::Io(e.to_string()),
         }
     }
@@ -447,7 +597,10 @@ impl From<std::io::Error> for SyscallError {
 /// File metadata returned by the metadata syscall
 #[derive(Debug, Clone)]
 pub struct FileMetadata {
+    /// Length in bytes (the apparent size)
     pub size: u64,
+    /// Bytes actually stored; less than `size` for a file with holes
+    pub allocated: u64,
     pub is_dir: bool,
     pub is_file: bool,
     pub is_symlink: bool,
@@ -458,6 +611,20 @@ pub struct FileMetadata {
     pub gid: u32,
     /// Unix permission mode (including setuid/setgid bits)
     pub mode: u16,
+    /// Last read, in milliseconds since the epoch
+    pub atime: f64,
+    /// Last change to the contents, in milliseconds since the epoch
+    pub mtime: f64,
+}
+
+/// A user's disk usage on the root filesystem, and their limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskQuota {
+    pub uid: Uid,
+    /// Bytes of file contents and symlink targets they own
+    pub used: u64,
+    /// The most they may own, if limited
+    pub limit: Option<u64>,
 }
 
 pub type SyscallResult<T> = Result<T, SyscallError>;
@@ -480,6 +647,8 @@ pub struct IpcSubsystem {
     pub file_locks: FileLockManager,
     /// Unix domain socket manager
     pub sockets: UnixSocketManager,
+    /// Who waits for whom, for deadlock detection
+    pub waits: WaitForGraph,
 }
 
 impl IpcSubsystem {
@@ -490,6 +659,7 @@ impl IpcSubsystem {
             semaphores: SemaphoreManager::new(),
             file_locks: FileLockManager::new(),
             sockets: UnixSocketManager::new(),
+            waits: WaitForGraph::new(),
         }
     }
 }
@@ -528,20 +698,104 @@ impl Default for TimeSubsystem {
     }
 }
 
+/// Files the user database is saved to and loaded from
+const USER_DB_FILES: &[&str] = &["/etc/passwd", "/etc/group", "/etc/shadow"];
+
+/// Make `dir` a root-owned directory everyone can write to (mode 1777),
+/// where the sticky bit keeps users from removing each other's files
+fn share_dir(fs: &mut dyn FileSystem, dir: &str) {
+    if fs.metadata(dir).is_ok_and(|meta| meta.is_dir) {
+        let _ = fs.chown(dir, Some(Uid::ROOT.0), Some(Gid::ROOT.0));
+        let _ = fs.chmod(dir, 0o1777);
+    }
+}
+
+/// Rename `from` to `to` within `fs`, replacing a file or link at `to`
+///
+/// Filesystems refuse to rename over an existing entry, so that one is
+/// moved aside first and removed once `from` is in its place, or put back
+/// if it can't be. It all happens in one call, so a snapshot sees either
+/// the old `to` or the new one, never neither: writing a temporary file
+/// and renaming it over the real one is safe against a save or a reload.
+/// Directories are never replaced, nor put in place of anything else.
+fn replace_path(fs: &mut dyn FileSystem, from: &str, to: &str) -> SyscallResult<()> {
+    // A change of case in a case-insensitive directory finds `from`
+    // itself at `to`, and is left to the filesystem; anywhere else, a
+    // rename to the same path changes nothing
+    match fs.rename(from, to) {
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists && from == to => return Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+        result => return Ok(result?),
+    }
+    match (fs.metadata(from)?.is_dir, fs.metadata(to)?.is_dir) {
+        (true, true) => return Err(SyscallError::AlreadyExists),
+        (false, true) => return Err(SyscallError::IsADirectory),
+        (true, false) => return Err(SyscallError::NotADirectory),
+        (false, false) => {}
+    }
+
+    let (dir, name) = to.rsplit_once('/').unwrap_or(("", to));
+    let aside = format!("{}/.{}.replaced", dir, name);
+    fs.rename(to, &aside)?;
+    if let Err(e) = fs.rename(from, to) {
+        let _ = fs.rename(&aside, to);
+        return Err(e.into());
+    }
+    fs.remove_file(&aside)?;
+    Ok(())
+}
+
+/// Check if `process` may access a file with metadata `meta`, through the
+/// file's access control list if it has one
+fn may_access(
+    process: &Process,
+    meta: &VfsMetadata,
+    want_read: bool,
+    want_write: bool,
+    want_exec: bool,
+) -> bool {
+    let (uid, gid, mode) = (Uid(meta.uid), Gid(meta.gid), FileMode::new(meta.mode));
+    match &meta.acl {
+        Some(acl) => check_acl_permission(
+            uid,
+            gid,
+            mode,
+            acl,
+            process.euid,
+            process.egid,
+            &process.groups,
+            want_read,
+            want_write,
+            want_exec,
+        ),
+        None => check_permission(
+            uid,
+            gid,
+            mode,
+            process.euid,
+            process.egid,
+            &process.groups,
+            want_read,
+            want_write,
+            want_exec,
+        ),
+    }
+}
+
 /// Virtual Filesystem Subsystem - manages all filesystem-related state
 pub struct VfsSubsystem {
     /// The main virtual filesystem
     pub vfs: MemoryFs,
     /// Map from kernel Handle to VFS FileHandle for open files
     pub vfs_handles: HashMap<Handle, usize>,
-    /// Proc filesystem handler
-    pub procfs: ProcFs,
-    /// Device filesystem handler
-    pub devfs: DevFs,
-    /// Sysfs handler
-    pub sysfs: SysFs,
+    /// Synthetic filesystems (/proc, /dev, /sys, ...) by mount point
+    pub synthetic: SyntheticFsRegistry,
+    /// Device nodes, also registered at /dev in `synthetic`
+    pub devfs: Rc<DevFs>,
     /// Mount table
     pub mounts: MountTable,
+    /// An `fsync` asked for the filesystem to be saved
+    pub persist_requested: bool,
 }
 
 impl VfsSubsystem {
@@ -551,17 +805,110 @@ impl VfsSubsystem {
         let _ = vfs.create_dir("/dev");
         let _ = vfs.create_dir("/home");
         let _ = vfs.create_dir("/home/user");
+        let _ = vfs.create_dir("/home/guest");
+        let _ = vfs.chown("/home/guest", Some(999), Some(999));
         let _ = vfs.create_dir("/tmp");
+        share_dir(&mut vfs, "/tmp");
         let _ = vfs.create_dir("/etc");
+        let _ = vfs.create_dir("/etc/terminal");
         let _ = vfs.create_dir("/root");
+        let _ = vfs.create_dir("/var");
+        let _ = vfs.create_dir("/var/log");
 
+        let devfs = Rc::new(DevFs::new());
         Self {
             vfs,
             vfs_handles: HashMap::new(),
-            procfs: ProcFs::new(),
-            devfs: DevFs::new(),
-            sysfs: SysFs::new(),
+            synthetic: SyntheticFsRegistry::with_defaults(Rc::clone(&devfs)),
+            devfs,
             mounts: MountTable::with_defaults(now),
+            persist_requested: false,
+        }
+    }
+
+    /// The filesystem serving `path` (absolute and resolved), and the path
+    /// within it: one attached to the mount table, or else `vfs`
+    pub fn route<'p>(&self, path: &'p str) -> (&dyn FileSystem, Cow<'p, str>) {
+        match self.mounts.filesystem_for(path) {
+            Some((fs, inner)) => (fs, Cow::Owned(inner)),
+            None => (&self.vfs, Cow::Borrowed(path)),
+        }
+    }
+
+    /// Mutable version of [`VfsSubsystem::route`]
+    pub fn route_mut<'p>(&mut self, path: &'p str) -> (&mut dyn FileSystem, Cow<'p, str>) {
+        match self.mounts.filesystem_for_mut(path) {
+            Some((fs, inner)) => (fs, Cow::Owned(inner)),
+            None => (&mut self.vfs, Cow::Borrowed(path)),
+        }
+    }
+
+    /// Have the filesystem serving `target` fold case under it as its
+    /// mount says
+    fn sync_casefold(&mut self, target: &str) {
+        let on = self
+            .mounts
+            .get_mount(target)
+            .is_some_and(|entry| entry.options.casefold);
+        let (fs, inner) = self.route_mut(target);
+        fs.set_casefold(&inner, on);
+    }
+
+    /// Check if `a` and `b` are served by the same filesystem
+    fn same_filesystem(&self, a: &str, b: &str) -> bool {
+        self.mounts.attached_mount(a) == self.mounts.attached_mount(b)
+    }
+
+    /// Copy a file, from one filesystem to another if need be
+    fn copy_file(&mut self, from: &str, to: &str) -> std::io::Result<u64> {
+        let to_inner = self.route(to).1.into_owned();
+        if self.same_filesystem(from, to) {
+            let (fs, from_inner) = self.route_mut(from);
+            return fs.copy_file(&from_inner, &to_inner);
+        }
+
+        let (fs, from_inner) = self.route_mut(from);
+        let meta = fs.metadata(&from_inner)?;
+        if meta.is_dir {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Cannot copy directory with copy_file",
+            ));
+        }
+        if meta.is_symlink {
+            let target = fs.read_link(&from_inner)?;
+            let (fs, _) = self.route_mut(to);
+            fs.symlink(&target, &to_inner)?;
+            return Ok(target.len() as u64);
+        }
+        let handle = fs.open(&from_inner, VfsOpenOptions::new().read(true))?;
+        let mut data = vec![0u8; meta.size as usize];
+        let read = fs.read(handle, &mut data);
+        fs.close(handle)?;
+        read?;
+
+        let (fs, _) = self.route_mut(to);
+        let handle = fs.open(
+            &to_inner,
+            VfsOpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true),
+        )?;
+        let written = fs.write(handle, &data);
+        fs.close(handle)?;
+        written?;
+        fs.chmod(&to_inner, meta.mode)?;
+        Ok(meta.size)
+    }
+
+    /// Close the VFS handle behind a file object that has been released
+    fn close_released(&mut self, handle: Handle, object: &KernelObject) {
+        if let Some(vh) = self.vfs_handles.remove(&handle)
+            && let KernelObject::File(file) = object
+        {
+            let (fs, _) = self.route_mut(&file.path.to_string_lossy());
+            let _ = fs.close(vh);
         }
     }
 }
@@ -574,6 +921,11 @@ pub struct ProcessSubsystem {
     pub next_pid: u32,
     /// The currently running process
     pub current: Option<Pid>,
+    /// Recent task faults, oldest first
+    pub crashes: VecDeque<CrashReport>,
+    /// Effective ids processes had before running a setuid or setgid
+    /// program, to restore when it exits
+    pub elevated: HashMap<Pid, (Uid, Gid)>,
 }
 
 impl ProcessSubsystem {
@@ -582,6 +934,8 @@ impl ProcessSubsystem {
             processes: HashMap::new(),
             next_pid: 1, // PID 0 is reserved
             current: None,
+            crashes: VecDeque::new(),
+            elevated: HashMap::new(),
         }
     }
 
@@ -629,6 +983,30 @@ pub struct Kernel {
     memory: MemoryManager,
     /// Tracer for instrumentation and debugging
     tracer: Tracer,
+    /// Kernel message log (dmesg)
+    log: KernelLog,
+    /// Per-process syscall tracing (strace -p)
+    ptrace: PtraceTable,
+    /// Syscall-level debugger (path watchpoints)
+    debugger: WasmDebugger,
+    /// Names of kernel tasks that run no process, for the symbol map
+    task_names: BTreeMap<TaskId, String>,
+    /// Tasks waiting in `poll` for I/O on some source to change
+    pollers: Vec<Waker>,
+    /// Outbound connections waiting for the user's permission
+    netperm: NetPermissions,
+    /// Offline mode: every connection fails at once
+    offline: bool,
+    /// Console session lock and idle tracking
+    session: SessionLock,
+    /// Power governor (page visibility, /sys/power/governor)
+    power: PowerGovernor,
+    /// Memory pressure monitor
+    pressure: PressureMonitor,
+    /// Guest session overlay over the root filesystem
+    guest: Option<GuestSession>,
+    /// System partition booted on /system
+    system: Option<SystemBoot>,
 
     // ========== SINGLETONS ==========
     /// User and group database
@@ -639,13 +1017,25 @@ pub struct Kernel {
     ttys: TtyManager,
 }
 
-/// Cryptographically secure random bytes for /dev/random and /dev/urandom
+/// What a vault's file holds: its inner filesystem, deflated
+fn vault_image(vault: &dyn FileSystem) -> std::io::Result<Vec<u8>> {
+    let image = vault.image().unwrap_or_else(|| {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "filesystem can't be saved to a file",
+        ))
+    })?;
+    Ok(compress::pack(&image))
+}
+
+/// Random bytes for salts and nonces, from the kernel's CSPRNG
+/// ([`super::random`])
 ///
-/// Uses the `getrandom` crate which provides OS-level cryptographic randomness.
-/// In WASM environments, this uses the Web Crypto API (crypto.getRandomValues).
-fn generate_random_bytes(len: usize) -> Vec<u8> {
+/// Panics only if the generator has never been seeded and can't be: the
+/// host has no entropy source (in the browser, no crypto.getRandomValues).
+pub(crate) fn generate_random_bytes(len: usize) -> Vec<u8> {
     let mut buf = vec![0u8; len];
-    getrandom::fill(&mut buf).expect("getrandom failed - crypto API unavailable");
+    random::fill(&mut buf).expect("no entropy source to seed the kernel RNG");
     buf
 }
 
@@ -672,6 +1062,18 @@ impl Kernel {
             console_handle,
             memory: MemoryManager::new(),
             tracer: Tracer::new(),
+            log: KernelLog::new(),
+            ptrace: PtraceTable::new(),
+            debugger: WasmDebugger::new(),
+            task_names: BTreeMap::new(),
+            pollers: Vec::new(),
+            netperm: NetPermissions::new(),
+            offline: false,
+            session: SessionLock::new(),
+            power: PowerGovernor::new(),
+            pressure: PressureMonitor::new(),
+            guest: None,
+            system: None,
             // Singletons
             users: UserDb::new(),
             init: InitSystem::new(),
@@ -710,6 +1112,19 @@ impl Kernel {
     /// Replace the VFS (for restoring from persistence)
     pub fn set_vfs(&mut self, vfs: MemoryFs) {
         self.fs.vfs = vfs;
+        // Like the mode a fresh system gets, whatever was saved
+        share_dir(&mut self.fs.vfs, "/tmp");
+        let folded: Vec<String> = self
+            .fs
+            .mounts
+            .list()
+            .into_iter()
+            .filter(|entry| entry.options.casefold)
+            .map(|entry| entry.target.clone())
+            .collect();
+        for target in folded {
+            self.fs.sync_casefold(&target);
+        }
     }
 
     pub fn init(&self) -> &InitSystem {
@@ -725,6 +1140,8 @@ impl Kernel {
     }
 
     pub fn fifos_mut(&mut self) -> &mut FifoRegistry {
+        // Whatever the caller does happens before the woken tasks run
+        self.wake_pollers();
         &mut self.ipc.fifos
     }
 
@@ -752,6 +1169,312 @@ impl Kernel {
         &mut self.fs.mounts
     }
 
+    /// Mount a filesystem at `target`
+    ///
+    /// A tmpfs gets an empty `MemoryFs` of its own, which isn't saved and
+    /// is dropped when it is unmounted. Other types are entries for what
+    /// the main VFS or a synthetic filesystem already serves there.
+    pub fn sys_mount(
+        &mut self,
+        source: &str,
+        target: &str,
+        fstype: FsType,
+        options: MountOptions,
+    ) -> SyscallResult<()> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let target = self.resolve_path(current, target)?;
+        let target = target.to_str().ok_or(SyscallError::InvalidArgument)?;
+        let now = self.now();
+        if fstype == FsType::Tmpfs {
+            if self.fs.synthetic.lookup(target).is_some() {
+                return Err(SyscallError::Busy);
+            }
+            if !self.sys_metadata(target)?.is_dir {
+                return Err(SyscallError::NotADirectory);
+            }
+            let mut tmpfs = MemoryFs::new();
+            share_dir(&mut tmpfs, "/");
+            self.fs
+                .mounts
+                .attach(source, target, fstype, options, Box::new(tmpfs), now)?;
+        } else {
+            self.fs.mounts.mount(source, target, fstype, options, now)?;
+        }
+        self.fs.sync_casefold(target);
+        Ok(())
+    }
+
+    /// Mount an overlay at `target`: a fresh in-memory upper layer over
+    /// copies of the `lower` directories, topmost first
+    ///
+    /// The lower directories are copied when the overlay is mounted, so
+    /// later changes to them don't show through it. Whatever is written to
+    /// the overlay is dropped when it is unmounted.
+    pub fn sys_mount_overlay(
+        &mut self,
+        source: &str,
+        lower: &[&str],
+        target: &str,
+        options: MountOptions,
+    ) -> SyscallResult<()> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        if lower.is_empty() {
+            return Err(SyscallError::InvalidArgument);
+        }
+        let target = self.resolve_path(current, target)?;
+        let target = target.to_str().ok_or(SyscallError::InvalidArgument)?;
+        if self.fs.synthetic.lookup(target).is_some() {
+            return Err(SyscallError::Busy);
+        }
+        if !self.sys_metadata(target)?.is_dir {
+            return Err(SyscallError::NotADirectory);
+        }
+
+        let mut layers: Vec<Box<dyn FileSystem>> = Vec::new();
+        for dir in lower {
+            let dir = self.resolve_path(current, dir)?;
+            let dir = dir.to_str().ok_or(SyscallError::InvalidArgument)?;
+            if !self.sys_metadata(dir)?.is_dir {
+                return Err(SyscallError::NotADirectory);
+            }
+            self.check_file_permission(dir, true, false, true)?;
+            let (fs, inner) = self.fs.route_mut(dir);
+            layers.push(Box::new(crate::vfs::copy_tree(fs, &inner)?));
+        }
+
+        let overlay = OverlayFs::new(MemoryFs::new(), layers);
+        let now = self.now();
+        self.fs.mounts.attach(
+            source,
+            target,
+            FsType::Overlay,
+            options,
+            Box::new(overlay),
+            now,
+        )?;
+        Ok(())
+    }
+
+    /// Mount the tar archive at `archive` read-only on `target`
+    ///
+    /// The archive is read (with the caller's permissions) and parsed up
+    /// front; later changes to the file don't show through the mount.
+    pub fn sys_mount_tar(
+        &mut self,
+        archive: &str,
+        target: &str,
+        mut options: MountOptions,
+    ) -> SyscallResult<()> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let target = self.resolve_path(current, target)?;
+        let target = target.to_str().ok_or(SyscallError::InvalidArgument)?;
+        if self.fs.synthetic.lookup(target).is_some() {
+            return Err(SyscallError::Busy);
+        }
+        if !self.sys_metadata(target)?.is_dir {
+            return Err(SyscallError::NotADirectory);
+        }
+
+        let archive = self.resolve_path(current, archive)?;
+        let archive = archive.to_str().ok_or(SyscallError::InvalidArgument)?;
+        let fd = self.sys_open(archive, OpenFlags::READ)?;
+        let mut data = Vec::new();
+        let mut buf = vec![0u8; 64 * 1024];
+        let read = loop {
+            match self.sys_read(fd, &mut buf) {
+                Ok(0) => break Ok(()),
+                Ok(n) => data.extend_from_slice(&buf[..n]),
+                Err(e) => break Err(e),
+            }
+        };
+        self.sys_close(fd)?;
+        read?;
+
+        let tar = TarFs::new(data)?;
+        options.read_only = true;
+        let now = self.now();
+        self.fs
+            .mounts
+            .attach(archive, target, FsType::Tar, options, Box::new(tar), now)?;
+        Ok(())
+    }
+
+    /// Mount the vault kept in the file `source` on `target`, opening it
+    /// with `passphrase` (see [`crate::vfs::encrypted`])
+    ///
+    /// With `create`, a missing `source` becomes a new, empty vault owned
+    /// by the caller. The file is read (and a new one written) with the
+    /// caller's permissions; the vault goes back into it on every save and
+    /// when it is unmounted, see [`Kernel::save_vaults`].
+    pub fn sys_mount_encrypted(
+        &mut self,
+        source: &str,
+        target: &str,
+        passphrase: &str,
+        create: bool,
+        options: MountOptions,
+    ) -> SyscallResult<()> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let target = self.resolve_path(current, target)?;
+        let target = target.to_str().ok_or(SyscallError::InvalidArgument)?;
+        if self.fs.synthetic.lookup(target).is_some() {
+            return Err(SyscallError::Busy);
+        }
+        if !self.sys_metadata(target)?.is_dir {
+            return Err(SyscallError::NotADirectory);
+        }
+
+        let source = self.resolve_path(current, source)?;
+        let source = source.to_str().ok_or(SyscallError::InvalidArgument)?;
+        let vault = match self.sys_metadata(source) {
+            Ok(_) => {
+                if !options.read_only {
+                    self.check_file_permission(source, false, true, false)?;
+                }
+                let fd = self.sys_open(source, OpenFlags::READ)?;
+                let mut data = Vec::new();
+                let mut buf = vec![0u8; 64 * 1024];
+                let read = loop {
+                    match self.sys_read(fd, &mut buf) {
+                        Ok(0) => break Ok(()),
+                        Ok(n) => data.extend_from_slice(&buf[..n]),
+                        Err(e) => break Err(e),
+                    }
+                };
+                self.sys_close(fd)?;
+                read?;
+                let inner = MemoryFs::from_json(&compress::unpack(&data)?)?;
+                EncryptedFs::open(inner, passphrase)?
+            }
+            Err(SyscallError::NotFound) if create => {
+                let process = self.get_current_process()?;
+                let (uid, gid) = (process.euid.0, process.egid.0);
+                let mut vault = EncryptedFs::create(MemoryFs::new(), passphrase)?;
+                vault.chown("/", Some(uid), Some(gid))?;
+                vault.chmod("/", 0o700)?;
+                let image = vault_image(&vault)?;
+                let fd = self.sys_open(source, OpenFlags::WRITE)?;
+                let written = self.sys_write(fd, &image);
+                self.sys_close(fd)?;
+                written?;
+                vault
+            }
+            Err(e) => return Err(e),
+        };
+
+        let now = self.now();
+        self.fs.mounts.attach(
+            source,
+            target,
+            FsType::Encrypted,
+            options,
+            Box::new(vault),
+            now,
+        )?;
+        Ok(())
+    }
+
+    /// Mount the WebDAV share `fs`, read from `url`, on `target`
+    ///
+    /// The share is read over the network first (see
+    /// [`crate::vfs::webdav::connect`]); here its copy is given to the
+    /// caller, who can then change it as the server lets them.
+    pub fn sys_mount_webdav(
+        &mut self,
+        url: &str,
+        mut fs: WebDavFs,
+        target: &str,
+        options: MountOptions,
+    ) -> SyscallResult<()> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let target = self.resolve_path(current, target)?;
+        let target = target.to_str().ok_or(SyscallError::InvalidArgument)?;
+        if self.fs.synthetic.lookup(target).is_some() {
+            return Err(SyscallError::Busy);
+        }
+        if !self.sys_metadata(target)?.is_dir {
+            return Err(SyscallError::NotADirectory);
+        }
+
+        let process = self.get_current_process()?;
+        fs.set_owner(process.euid.0, process.egid.0)?;
+        let now = self.now();
+        self.fs
+            .mounts
+            .attach(url, target, FsType::WebDav, options, Box::new(fs), now)?;
+        Ok(())
+    }
+
+    /// Write every vault mounted read-write back to its file
+    ///
+    /// Skipped during a guest session, whose changes are never saved.
+    pub fn save_vaults(&mut self) -> std::io::Result<()> {
+        if self.guest.is_some() {
+            return Ok(());
+        }
+        let vaults: Vec<(String, String)> = self
+            .fs
+            .mounts
+            .list()
+            .into_iter()
+            .filter(|e| e.fstype == FsType::Encrypted && !e.options.read_only)
+            .map(|e| (e.source.clone(), e.target.clone()))
+            .collect();
+        for (source, target) in vaults {
+            self.save_vault(&source, &target)?;
+        }
+        Ok(())
+    }
+
+    /// Write the vault mounted at `target` back to `source`
+    fn save_vault(&mut self, source: &str, target: &str) -> std::io::Result<()> {
+        let Some((vault, _)) = self.fs.mounts.filesystem_for(target) else {
+            return Ok(());
+        };
+        let image = vault_image(vault)?;
+        let (fs, inner) = self.fs.route_mut(source);
+        crate::vfs::atomic_write(fs, &inner, &image)
+    }
+
+    /// Unmount the filesystem at `target`
+    ///
+    /// Fails with `Busy` while a file under an attached filesystem is open.
+    pub fn sys_umount(&mut self, target: &str) -> SyscallResult<MountEntry> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let target = self.resolve_path(current, target)?;
+        let target = target.to_str().ok_or(SyscallError::InvalidArgument)?;
+        if self.fs.mounts.is_attached(target) {
+            let busy = self.fs.vfs_handles.keys().any(|&handle| {
+                matches!(self.objects.get(handle), Some(KernelObject::File(file))
+                    if self.fs.mounts.attached_mount(&file.path.to_string_lossy()) == Some(target))
+            });
+            if busy {
+                return Err(SyscallError::Busy);
+            }
+        }
+        if let Some(entry) = self.fs.mounts.get_mount(target)
+            && entry.fstype == FsType::Encrypted
+            && !entry.options.read_only
+            && self.guest.is_none()
+        {
+            let source = entry.source.clone();
+            self.save_vault(&source, target)?;
+        }
+        let entry = self.fs.mounts.umount(target)?;
+        self.fs.sync_casefold(target);
+        Ok(entry)
+    }
+
+    /// Change the options of an existing mount, keeping those not named
+    pub fn sys_remount(&mut self, target: &str, options: &str) -> SyscallResult<()> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let target = self.resolve_path(current, target)?;
+        let target = target.to_str().ok_or(SyscallError::InvalidArgument)?;
+        self.fs.mounts.remount(target, options)?;
+        self.fs.sync_casefold(target);
+        Ok(())
+    }
+
     pub fn ttys(&self) -> &TtyManager {
         &self.ttys
     }
@@ -760,6 +1483,184 @@ impl Kernel {
         &mut self.ttys
     }
 
+    /// Allocate a pseudo-terminal; returns the path of its slave end,
+    /// `/dev/pts/N`
+    pub fn sys_openpty(&mut self) -> SyscallResult<String> {
+        let name = self.ttys.open_pty();
+        let slave = PtySlave { name: name.clone() };
+        if let Err(e) = self.fs.devfs.register(&name, Rc::new(slave)) {
+            self.ttys.close_pty(&name);
+            return Err(e);
+        }
+        Ok(format!("/dev/{}", name))
+    }
+
+    /// Release the pseudo-terminal at `path`; processes controlled by it
+    /// lose their controlling terminal
+    pub fn sys_closepty(&mut self, path: &str) -> SyscallResult<()> {
+        let name = path.strip_prefix("/dev/").unwrap_or(path);
+        if !self.ttys.close_pty(name) {
+            return Err(SyscallError::NotFound);
+        }
+        let _ = self.fs.devfs.unregister(name);
+        for process in self.proc.processes.values_mut() {
+            if process.ctty.as_deref() == Some(name) {
+                process.ctty = None;
+            }
+        }
+        Ok(())
+    }
+
+    /// Report a mouse event over terminal `name` to the program reading
+    /// it, if that program asked for such events; returns whether it did
+    pub fn sys_tty_mouse(&mut self, name: &str, event: &MouseEvent) -> SyscallResult<bool> {
+        let tty = self.ttys.get_tty(name).ok_or(SyscallError::NotFound)?;
+        let Some(report) = tty.mouse.encode(event) else {
+            return Ok(false);
+        };
+        self.sys_tty_input(name, &report)?;
+        Ok(true)
+    }
+
+    /// Queue `data` as input typed on terminal `name`
+    pub fn sys_tty_input(&mut self, name: &str, data: &[u8]) -> SyscallResult<()> {
+        if self.ttys.get_tty(name).is_none() {
+            return Err(SyscallError::NotFound);
+        }
+        self.console().ok_or(SyscallError::BadFd)?.push_input(data);
+        Ok(())
+    }
+
+    /// Put virtual console `n` on screen, starting a shell on it the first
+    /// time, and return that console's shell
+    ///
+    /// The process running now is remembered as the old console's shell. A
+    /// new console's shell is a login shell for the same user, with the
+    /// console as its controlling terminal and foreground process group.
+    /// The caller makes the returned shell current once it is done with
+    /// the old console.
+    pub fn sys_chvt(&mut self, n: usize) -> SyscallResult<Pid> {
+        if self.ttys.vt(n).is_none() {
+            return Err(SyscallError::InvalidArgument);
+        }
+        if self.session.is_locked() {
+            return Err(SyscallError::PermissionDenied);
+        }
+        let old = self.ttys.active_vt();
+        if let (Some(pid), Some(vt)) = (self.proc.current, self.ttys.vt_mut(old)) {
+            vt.shell = Some(pid.0);
+        }
+        let user = self
+            .users
+            .get_user(self.sys_getuid()?)
+            .cloned()
+            .ok_or(SyscallError::NotFound)?;
+
+        self.ttys.set_active_vt(n);
+        let shell = self.ttys.vt(n).and_then(|vt| vt.shell).map(Pid);
+        if let Some(pid) = shell
+            && self.proc.processes.get(&pid).is_some_and(Process::is_alive)
+        {
+            return Ok(pid);
+        }
+        let pid = self.spawn_login_shell(
+            &user.name,
+            user.uid,
+            user.gid,
+            &user.home,
+            &user.shell,
+            None,
+        );
+        if let Some(vt) = self.ttys.vt_mut(n) {
+            vt.shell = Some(pid.0);
+        }
+        Ok(pid)
+    }
+
+    /// Path of the terminal open on `fd`, or `None` if it isn't one
+    ///
+    /// The console reports as the process's controlling terminal, since
+    /// every terminal shares it.
+    pub fn sys_ttyname(&self, fd: Fd) -> SyscallResult<Option<String>> {
+        let handle = self.get_handle(fd)?;
+        if handle == self.console_handle {
+            let process = self.get_current_process()?;
+            let name = process.ctty.as_deref().unwrap_or("console");
+            return Ok(Some(format!("/dev/{}", name)));
+        }
+        Ok(match self.objects.get(handle) {
+            Some(KernelObject::Device(dev)) if dev.ops.is_tty() => {
+                Some(dev.path.to_string_lossy().into_owned())
+            }
+            _ => None,
+        })
+    }
+
+    /// Controlling terminal of a process, e.g. `tty1` or `pts/0`
+    pub fn process_tty(&self, pid: Pid) -> Option<String> {
+        self.proc.processes.get(&pid)?.ctty.clone()
+    }
+
+    /// Snapshot the object table, fd tables and mounts for the inspector
+    pub fn object_snapshot(&self) -> ObjectSnapshot {
+        let mut fd_tables: Vec<FdTableView> = self
+            .proc
+            .processes
+            .values()
+            .map(|p| {
+                let mut fds: Vec<_> = p.files.iter().collect();
+                fds.sort_by_key(|(fd, _)| fd.0);
+                FdTableView {
+                    pid: p.pid,
+                    name: p.name.clone(),
+                    fds,
+                }
+            })
+            .collect();
+        fd_tables.sort_by_key(|t| t.pid.0);
+
+        let mut owners: HashMap<Handle, Vec<(Pid, Fd)>> = HashMap::new();
+        for table in &fd_tables {
+            for &(fd, handle) in &table.fds {
+                owners.entry(handle).or_default().push((table.pid, fd));
+            }
+        }
+
+        let mut objects: Vec<ObjectView> = self
+            .objects
+            .iter()
+            .map(|(handle, obj, refcount)| ObjectView {
+                handle,
+                kind: obj.type_name(),
+                detail: obj.describe(),
+                refcount,
+                owners: owners.remove(&handle).unwrap_or_default(),
+            })
+            .collect();
+        objects.sort_by_key(|o| o.handle.0);
+
+        let mut mounts: Vec<MountView> = self
+            .fs
+            .mounts
+            .list()
+            .into_iter()
+            .map(|m| MountView {
+                source: m.source.clone(),
+                target: m.target.clone(),
+                fstype: m.fstype.as_str().to_string(),
+                options: m.options.to_string(),
+            })
+            .collect();
+        mounts.sort_by(|a, b| a.target.cmp(&b.target));
+
+        ObjectSnapshot {
+            timestamp: self.time.now,
+            objects,
+            fd_tables,
+            mounts,
+        }
+    }
+
     pub fn current_process(&self) -> Option<&Process> {
         self.proc
             .current
@@ -811,6 +1712,9 @@ impl Kernel {
         self.proc.next_pid += 1;
 
         let mut process = Process::new(pid, name.to_string(), parent);
+        if let Some(parent) = parent.and_then(|p| self.proc.processes.get(&p)) {
+            process.umask = parent.umask;
+        }
 
         // Give the process stdin/stdout/stderr pointing to console
         // Retain the console handle for each fd (3 references)
@@ -823,6 +1727,23 @@ impl Kernel {
         process.files.insert(Fd::STDERR, self.console_handle);
 
         self.proc.processes.insert(pid, process);
+        if let Some(parent) = parent {
+            self.ptrace.on_spawn(parent, pid);
+        }
+        pid
+    }
+
+    /// Create a new process whose environment is `env` applied to its
+    /// parent's (or, without a parent, to the default one)
+    pub fn spawn_process_with(&mut self, name: &str, parent: Option<Pid>, env: &EnvSpec) -> Pid {
+        let base = parent
+            .and_then(|p| self.proc.processes.get(&p))
+            .map(|p| p.environ.clone());
+        let pid = self.spawn_process(name, parent);
+        if let Some(process) = self.proc.processes.get_mut(&pid) {
+            let base = base.unwrap_or_else(|| process.environ.clone());
+            process.environ = env.resolve(&base);
+        }
         pid
     }
 
@@ -863,11 +1784,14 @@ impl Kernel {
         process.files.insert(Fd::STDOUT, self.console_handle);
         process.files.insert(Fd::STDERR, self.console_handle);
 
-        // Associate TTY with this session
-        if let Some(tty) = self.ttys.get_tty_mut("tty1") {
+        // It runs on the console on screen, and leads its session there
+        let vt = self.ttys.active_vt();
+        let tty_name = format!("tty{}", vt);
+        if let Some(tty) = self.ttys.get_tty_mut(&tty_name) {
             tty.session = Some(pid.0);
             tty.pgrp = Some(pid.0);
         }
+        process.ctty = Some(tty_name);
 
         // Track parent-child relationship
         if let Some(parent_pid) = parent
@@ -877,6 +1801,9 @@ impl Kernel {
         }
 
         self.proc.processes.insert(pid, process);
+        if let Some(parent) = parent {
+            self.ptrace.on_spawn(parent, pid);
+        }
         pid
     }
 
@@ -926,6 +1853,7 @@ impl Kernel {
 
         // Insert child process
         self.proc.processes.insert(child_pid, child);
+        self.ptrace.on_spawn(parent_pid, child_pid);
 
         Ok(child_pid)
     }
@@ -960,6 +1888,7 @@ impl Kernel {
         if meta.is_dir {
             return Err(SyscallError::IsADirectory);
         }
+        self.check_exec_mount(&resolved_str)?;
 
         // Get the process
         let process = self
@@ -978,12 +1907,10 @@ impl Kernel {
 
         for fd in cloexec_fds {
             if let Some(handle) = process.files.remove(fd) {
-                // Release the handle
-                if self.objects.release(handle).is_some() {
-                    // Clean up VFS handle if present
-                    if let Some(vh) = self.fs.vfs_handles.remove(&handle) {
-                        let _ = self.fs.vfs.close(vh);
-                    }
+                // Release the handle, cleaning up its VFS handle if present
+                if let Some(object) = self.objects.release(handle) {
+                    self.fs.close_released(handle, &object);
+                    self.objects.recycle(object);
                 }
             }
         }
@@ -1071,6 +1998,7 @@ impl Kernel {
         // Transition to Zombie state
         process.state = ProcessState::Zombie(exit_code);
         process.task = None; // Task has completed
+        self.release_ipc(pid);
 
         // Note: SIGCHLD is not sent here by design. The default action for SIGCHLD
         // is Ignore, and axebergos uses non-blocking waitpid() for child reaping.
@@ -1079,10 +2007,68 @@ impl Kernel {
         Ok(())
     }
 
-    /// Get exec arguments from current process environment
+    /// Handle a task that panicked (see [`crate::kernel::crash`])
     ///
-    /// Returns (path, args) if the process has been exec'd.
-    pub fn sys_get_exec_info(&self) -> SyscallResult<Option<(String, Vec<String>)>> {
+    /// The process it ran, if any, becomes a zombie with [`FAULT_STATUS`].
+    /// A crash report is kept, written to [`CRASH_DIR`] and traced.
+    pub fn task_fault(&mut self, fault: &TaskFault) -> CrashReport {
+        let now = self.time.now;
+        let process = self
+            .proc
+            .processes
+            .values_mut()
+            .find(|p| p.task == Some(fault.task));
+        let (pid, name) = match process {
+            Some(process) => {
+                process.state = ProcessState::Zombie(FAULT_STATUS);
+                process.task = None;
+                (Some(process.pid), process.name.clone())
+            }
+            None => (
+                None,
+                self.task_names
+                    .get(&fault.task)
+                    .cloned()
+                    .unwrap_or_else(|| "kernel".to_string()),
+            ),
+        };
+        if let Some(pid) = pid {
+            self.ptrace.on_exit(pid, FAULT_STATUS);
+            self.release_ipc(pid);
+        }
+
+        let report = CrashReport::new(fault, pid, &name, now);
+        if !self.fs.vfs.exists(CRASH_DIR) {
+            let _ = self.fs.vfs.create_dir(CRASH_DIR);
+        }
+        let path = format!("{}/{}", CRASH_DIR, report.file_name());
+        let _ = crate::vfs::write_string(&mut self.fs.vfs, &path, &report.to_text());
+
+        self.log.log(now, LogLevel::Err, "fault", &report.summary());
+        let mut event =
+            TraceEvent::with_detail(now, TraceCategory::Process, "fault", report.summary())
+                .with_task(fault.task.0);
+        if let Some(pid) = pid {
+            event = event.with_pid(pid.0);
+        }
+        self.tracer.trace(event);
+
+        if self.proc.crashes.len() >= MAX_CRASH_REPORTS {
+            self.proc.crashes.pop_front();
+        }
+        self.proc.crashes.push_back(report.clone());
+        report
+    }
+
+    /// Recent crash reports, oldest first
+    pub fn crash_reports(&self) -> &VecDeque<CrashReport> {
+        &self.proc.crashes
+    }
+
+    /// Get exec arguments from current process environment
+    ///
+    /// Returns (path, args) if the process has been exec'd.
+    pub fn sys_get_exec_info(&self) -> SyscallResult<Option<(String, Vec<String>)>> {
         let process = self.get_current_process()?;
 
         let path = match process.environ.get("_EXEC_PATH") {
@@ -1169,6 +2155,7 @@ impl Kernel {
     }
 
     pub fn console(&mut self) -> Option<&mut ConsoleObject> {
+        self.wake_pollers();
         match self.objects.get_mut(self.console_handle) {
             Some(KernelObject::Console(c)) => Some(c),
             _ => None,
@@ -1215,3331 +2202,7059 @@ impl Kernel {
         &mut self.tracer
     }
 
+    /// Name a kernel task that runs no process, e.g. an idle job
+    pub fn name_task(&mut self, task: TaskId, name: &str) {
+        self.task_names.insert(task, name.to_string());
+    }
+
+    /// The kernel symbol map, with each task named after the process
+    /// running on it, or the name given to it with [`Self::name_task`]
+    pub fn ksyms(&self) -> SymbolMap {
+        let mut map = SymbolMap::new();
+        for (task, name) in &self.task_names {
+            map.add_task(*task, name);
+        }
+        for process in self.proc.processes.values() {
+            if let Some(task) = process.task {
+                map.add_task(task, &process.name);
+            }
+        }
+        map
+    }
+
     pub fn trace_reset(&mut self) {
         self.tracer.reset();
     }
 
-    // ========== SYSCALLS ==========
-
-    /// Open a file or device
-    pub fn sys_open(&mut self, path: &str, flags: OpenFlags) -> SyscallResult<Fd> {
-        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+    /// Record one executor tick: a frame span plus a span per task poll
+    pub fn trace_frame(&mut self, start: f64, end: f64, polls: &[PollSpan]) {
+        self.tracer.scheduler.record_tick(polls.len(), end - start);
+
+        if self.tracer.wants(TraceCategory::Scheduler) {
+            for poll in polls {
+                let pid = self
+                    .proc
+                    .processes
+                    .values()
+                    .find(|p| p.task == Some(poll.task))
+                    .map(|p| p.pid);
+                let name = if poll.completed {
+                    "poll (done)"
+                } else {
+                    "poll"
+                };
+                let mut event = TraceEvent::instant(poll.start, TraceCategory::Scheduler, name)
+                    .with_task(poll.task.0)
+                    .with_duration(poll.end - poll.start);
+                if let Some(pid) = pid {
+                    event = event.with_pid(pid.0);
+                }
+                self.tracer.trace(event);
+            }
+        }
 
-        // Resolve path
-        let resolved = self.resolve_path(current, path)?;
+        self.tracer.trace(
+            TraceEvent::instant(start, TraceCategory::Compositor, "frame")
+                .with_duration(end - start),
+        );
+    }
 
-        // Handle special paths
-        let resolved_str = resolved.to_string_lossy();
-        let handle = if resolved_str.starts_with("/dev/") {
-            self.open_device(&resolved, flags)?
-        } else if ProcFs::is_proc_path(&resolved_str) {
-            self.open_proc(&resolved_str, current)?
-        } else if SysFs::is_sys_path(&resolved_str) {
-            self.open_sysfs(&resolved_str)?
-        } else {
-            // SEC-011: Check path traversal permissions for regular files
-            self.check_path_traversal(&resolved_str)?;
-            self.open_file(&resolved, flags)?
-        };
+    /// Attach the current process as tracer of `tracee` (like PTRACE_SEIZE)
+    ///
+    /// Allowed for your own processes, for root, and with CAP_SYS_PTRACE.
+    /// Attaching again updates the options.
+    pub fn sys_ptrace_attach(&mut self, tracee: Pid, options: PtraceOptions) -> SyscallResult<()> {
+        let tracer = self.get_current_process()?;
+        let tracer_pid = tracer.pid;
+        let allowed_any =
+            tracer.euid == Uid::ROOT || tracer.capabilities.has_effective(Capability::SysPtrace);
+        let tracer_uid = tracer.uid;
 
-        // Add to process file table
-        let process = self
+        let target = self
             .proc
             .processes
-            .get_mut(&current)
+            .get(&tracee)
             .ok_or(SyscallError::NoProcess)?;
-        let fd = process
-            .files
-            .alloc(handle)
-            .ok_or(SyscallError::TooManyOpenFiles)?;
-        Ok(fd)
-    }
-
-    /// Open a /proc file
-    fn open_proc(&mut self, path: &str, current_pid: Pid) -> SyscallResult<Handle> {
-        // Get list of PIDs for procfs
-        let pids: Vec<u32> = self.proc.processes.keys().map(|p| p.0).collect();
-
-        // Check if path exists in procfs
-        if !self.fs.procfs.exists(path, &pids) {
-            return Err(SyscallError::NotFound);
+        if matches!(target.state, ProcessState::Zombie(_)) {
+            return Err(SyscallError::NoProcess);
         }
-
-        // Check if it's a directory
-        if self.fs.procfs.is_dir(path, &pids) {
-            return Err(SyscallError::IsADirectory);
+        if !allowed_any && target.uid != tracer_uid {
+            return Err(SyscallError::PermissionDenied);
         }
 
-        // Generate system context
-        let sys_stats = self.memory.system_stats();
-        let sys_ctx = SystemContext {
-            uptime_secs: self.time.now,
-            total_memory: 64 * 1024 * 1024, // 64MB simulated
-            used_memory: sys_stats.total_allocated as u64,
-            free_memory: 64 * 1024 * 1024 - sys_stats.total_allocated as u64,
-            num_processes: self.proc.processes.len(),
-        };
+        self.ptrace
+            .attach(tracer_pid, tracee, options)
+            .map_err(|e| match e {
+                PtraceError::AlreadyTraced(_) => SyscallError::Busy,
+                PtraceError::NotTraced => SyscallError::InvalidArgument,
+            })
+    }
 
-        // Determine which PID the path refers to
-        let target_pid = if path.starts_with("/proc/self/") {
-            Some(current_pid)
-        } else if let Some(rest) = path.strip_prefix("/proc/") {
-            let parts: Vec<&str> = rest.split('/').collect();
-            if !parts.is_empty() {
-                parts[0].parse::<u32>().ok().map(Pid)
-            } else {
-                None
-            }
-        } else {
-            None
-        };
+    /// Stop tracing `tracee`
+    pub fn sys_ptrace_detach(&mut self, tracee: Pid) -> SyscallResult<()> {
+        let tracer = self.get_current_pid()?;
+        self.ptrace
+            .detach(tracer, tracee)
+            .map_err(|_| SyscallError::NoProcess)
+    }
 
-        // SEC-010: Permission check for /proc/[pid]/* access
-        // Only allow process to read its own /proc/[pid]/* files, or root can read any
-        if let Some(target) = target_pid
-            && target != current_pid
-        {
-            // Accessing another process's /proc entries
-            let current_process = self.get_current_process()?;
-            let is_root = current_process.euid == Uid::ROOT;
+    /// Collect the trace events queued for the current process
+    ///
+    /// Also returns how many events were dropped because the queue was full.
+    pub fn sys_ptrace_events(&mut self) -> SyscallResult<(Vec<PtraceEvent>, u64)> {
+        let tracer = self.get_current_pid()?;
+        Ok(self.ptrace.take_events(tracer))
+    }
 
-            if !is_root {
-                // Check if this is a sensitive file that requires ownership
-                let sensitive_files = ["environ", "cmdline", "maps", "fd", "cwd", "exe"];
-                let is_sensitive = sensitive_files.iter().any(|f| path.contains(f));
+    /// Processes the current process is tracing
+    pub fn sys_ptrace_tracees(&self) -> SyscallResult<Vec<Pid>> {
+        let tracer = self.get_current_pid()?;
+        Ok(self.ptrace.tracees(tracer))
+    }
 
-                if is_sensitive {
-                    return Err(SyscallError::PermissionDenied);
-                }
-            }
-        }
+    /// Which process traces `pid`, if any
+    pub fn ptrace_tracer_of(&self, pid: Pid) -> Option<Pid> {
+        self.ptrace.tracer_of(pid)
+    }
 
-        // Generate process context if needed
-        let proc_ctx = target_pid.and_then(|pid| {
-            self.proc.processes.get(&pid).map(|p| {
-                ProcContext {
-                    pid: p.pid.0,
-                    ppid: p.parent.map(|pp| pp.0),
-                    name: &p.name,
-                    state: match &p.state {
-                        ProcessState::Running => "R (running)",
-                        ProcessState::Sleeping => "S (sleeping)",
-                        ProcessState::Stopped => "T (stopped)",
-                        ProcessState::Zombie(_) => "Z (zombie)",
-                        ProcessState::Blocked(_) => "D (blocked)",
-                    },
-                    uid: p.uid.0,
-                    gid: p.gid.0,
-                    cwd: p.cwd.to_str().unwrap_or("/"),
-                    cmdline: &p.name,
-                    environ: &[], // Will be filled from snapshot
-                    memory_used: p.memory.stats().allocated as u64,
-                    memory_limit: p.memory.stats().limit as u64,
-                }
-            })
-        });
+    // ========== DEBUGGER ==========
 
-        // Generate file content
-        let content = generate_proc_content(path, current_pid.0, proc_ctx.as_ref(), &sys_ctx)
-            .ok_or(SyscallError::NotFound)?;
+    /// The syscall-level debugger
+    pub fn debugger(&self) -> &WasmDebugger {
+        &self.debugger
+    }
 
-        // Create a file object with the generated content
-        let handle = self.create_file_object(PathBuf::from(path), content, true, false);
-        Ok(handle)
+    /// Mutable access to the debugger
+    pub fn debugger_mut(&mut self) -> &mut WasmDebugger {
+        &mut self.debugger
     }
 
-    /// Open a /sys file
-    fn open_sysfs(&mut self, path: &str) -> SyscallResult<Handle> {
-        // Check if path exists
-        if !self.fs.sysfs.exists(path) {
-            return Err(SyscallError::NotFound);
+    /// Whose processes the current process may debug: `None` for everyone
+    /// (root or CAP_SYS_PTRACE), otherwise only its own user's
+    fn debug_scope(&self) -> SyscallResult<Option<Uid>> {
+        let process = self.get_current_process()?;
+        if process.euid == Uid::ROOT || process.capabilities.has_effective(Capability::SysPtrace) {
+            Ok(None)
+        } else {
+            Ok(Some(process.uid))
         }
+    }
 
-        // Check if it's a directory
-        if self.fs.sysfs.is_dir(path) {
-            return Err(SyscallError::IsADirectory);
+    /// Set a path watchpoint, enabling the debugger
+    ///
+    /// Unprivileged users only catch their own processes.
+    pub fn sys_debug_watch_path(&mut self, path: &str, ops: &[PathOp]) -> SyscallResult<u32> {
+        if ops.is_empty() {
+            return Err(SyscallError::InvalidArgument);
         }
+        let scope = self.debug_scope()?;
+        let current = self.get_current_pid()?;
+        let resolved = self.resolve_path(current, path)?;
+        let path = resolved.to_str().ok_or(SyscallError::InvalidArgument)?;
 
-        // Generate content
-        let content = self
-            .fs
-            .sysfs
-            .generate_content(path)
-            .ok_or(SyscallError::NotFound)?;
-
-        // Create a file object with the generated content
-        let handle = self.create_file_object(PathBuf::from(path), content, true, false);
-        Ok(handle)
+        self.debugger.enable();
+        Ok(self.debugger.add_path_watch(path, ops, scope))
     }
 
-    /// Read from a file descriptor
-    pub fn sys_read(&mut self, fd: Fd, buf: &mut [u8]) -> SyscallResult<usize> {
-        let handle = self.get_handle(fd)?;
-        let obj = self.objects.get_mut(handle).ok_or(SyscallError::BadFd)?;
-        Ok(obj.read(buf)?)
+    /// Remove a path watchpoint
+    pub fn sys_debug_unwatch_path(&mut self, id: u32) -> SyscallResult<()> {
+        let scope = self.debug_scope()?;
+        let watch = self.debugger.path_watch(id).ok_or(SyscallError::NotFound)?;
+        if scope.is_some() && watch.uid != scope {
+            return Err(SyscallError::PermissionDenied);
+        }
+        self.debugger.remove_path_watch(id);
+        Ok(())
     }
 
-    /// Write to a file descriptor
-    pub fn sys_write(&mut self, fd: Fd, buf: &[u8]) -> SyscallResult<usize> {
-        let handle = self.get_handle(fd)?;
-        let obj = self.objects.get_mut(handle).ok_or(SyscallError::BadFd)?;
-        Ok(obj.write(buf)?)
+    /// List path watchpoints
+    pub fn sys_debug_path_watches(&self) -> Vec<PathWatch> {
+        let mut watches: Vec<_> = self.debugger.path_watches().cloned().collect();
+        watches.sort_by_key(|w| w.id);
+        watches
     }
 
-    /// Close a file descriptor
-    pub fn sys_close(&mut self, fd: Fd) -> SyscallResult<()> {
-        let process = self.get_current_process_mut()?;
-
-        let handle = process.files.remove(fd).ok_or(SyscallError::BadFd)?;
-
-        // Sync file to VFS if it's a file (before potential release)
-        if let Some(KernelObject::File(_)) = self.objects.get(handle) {
-            self.sync_file(handle)?;
-        }
+    /// Collect the watchpoint hits the current process may see
+    pub fn sys_debug_path_hits(&mut self) -> SyscallResult<Vec<PathWatchHit>> {
+        let scope = self.debug_scope()?;
+        Ok(self.debugger.take_path_hits(scope))
+    }
 
-        // Release the handle (decrements refcount)
-        // If refcount drops to 0, the object is removed
-        if let Some(_removed_object) = self.objects.release(handle) {
-            // Object was deallocated - clean up VFS handle if present
-            if let Some(vh) = self.fs.vfs_handles.remove(&handle) {
-                let _ = self.fs.vfs.close(vh);
+    /// Resume the processes paused by watchpoints that the caller may control
+    pub fn sys_debug_continue(&mut self) -> SyscallResult<Vec<Pid>> {
+        let scope = self.debug_scope()?;
+        let mut resumed = Vec::new();
+        for pid in self.debugger.paused().to_vec() {
+            let Some(process) = self.proc.processes.get_mut(&pid) else {
+                self.debugger.release(pid);
+                continue;
+            };
+            if scope.is_some_and(|uid| uid != process.uid) {
+                continue;
+            }
+            if process.state == ProcessState::Stopped {
+                process.state = ProcessState::Running;
             }
+            self.debugger.release(pid);
+            resumed.push(pid);
         }
-
-        Ok(())
+        self.debugger.continue_execution();
+        Ok(resumed)
     }
 
-    /// Seek within a file
-    pub fn sys_seek(&mut self, fd: Fd, pos: SeekFrom) -> SyscallResult<u64> {
-        let handle = self.get_handle(fd)?;
-        let obj = self.objects.get_mut(handle).ok_or(SyscallError::BadFd)?;
-        Ok(obj.seek(pos)?)
+    /// Resolve a path for the debugger, ignoring errors
+    fn debug_resolve(&self, path: &str) -> Option<String> {
+        let current = self.proc.current?;
+        let resolved = self.resolve_path(current, path).ok()?;
+        resolved.to_str().map(String::from)
     }
 
-    /// Create a pipe (returns read_fd, write_fd)
-    pub fn sys_pipe(&mut self) -> SyscallResult<(Fd, Fd)> {
-        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-
-        // Create pipe object
-        let pipe = PipeObject::new(4096);
-        let handle = self.objects.insert(KernelObject::Pipe(pipe));
-
-        // Allocate two fds pointing to same pipe
-        // (In a real OS these would be separate read/write ends)
-        let process = self
-            .proc
-            .processes
-            .get_mut(&current)
-            .ok_or(SyscallError::NoProcess)?;
-        let read_fd = process
-            .files
-            .alloc(handle)
-            .ok_or(SyscallError::TooManyOpenFiles)?;
-        let write_fd = match process.files.alloc(handle) {
-            Some(fd) => fd,
-            None => {
-                // Clean up the read fd if we can't allocate write fd
-                process.files.remove(read_fd);
-                return Err(SyscallError::TooManyOpenFiles);
-            }
+    /// Report a path access to the debugger
+    ///
+    /// When a watchpoint fires, the current process is stopped, as if by
+    /// SIGSTOP, until the debugger continues it.
+    fn debug_path_access(
+        &mut self,
+        op: PathOp,
+        path: &str,
+        syscall: &str,
+        args: Vec<SyscallArg>,
+        result: DebugResult,
+    ) {
+        let Some(pid) = self.proc.current else {
+            return;
         };
+        let Some(process) = self.proc.processes.get_mut(&pid) else {
+            return;
+        };
+        let task = process.task.unwrap_or(TaskId(0));
+        let now = self.time.now;
 
-        Ok((read_fd, write_fd))
+        if self.debugger.on_path_access(
+            op,
+            path,
+            syscall,
+            pid,
+            process.uid,
+            task,
+            args,
+            result,
+            now,
+        ) && !matches!(process.state, ProcessState::Zombie(_))
+        {
+            process.state = ProcessState::Stopped;
+        }
     }
 
-    /// Create a window (returns fd for the window)
-    pub fn sys_window_create(&mut self, _title: &str) -> SyscallResult<Fd> {
-        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+    // ========== SESSION LOCK ==========
 
-        // Generate a unique window ID for this window object
-        static NEXT_WINDOW_ID: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(1);
-        let window_id = WindowId(NEXT_WINDOW_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed));
+    /// The session lock and idle monitor
+    pub fn session(&self) -> &SessionLock {
+        &self.session
+    }
 
-        let window = WindowObject::new(window_id);
-        let handle = self.objects.insert(KernelObject::Window(window));
+    /// Record an input event reported by the platform at `now`
+    pub fn session_input(&mut self, now: f64) {
+        self.session.input(now);
+    }
 
-        let process = self
-            .proc
-            .processes
-            .get_mut(&current)
-            .ok_or(SyscallError::NoProcess)?;
-        let fd = process
-            .files
-            .alloc(handle)
-            .ok_or(SyscallError::TooManyOpenFiles)?;
+    /// Lock the session if it has been idle past the timeout; the platform
+    /// calls this periodically. Returns true if the session just locked.
+    pub fn session_poll(&mut self, now: f64) -> bool {
+        // Without a current process only root can unlock
+        let uid = self.sys_getuid().unwrap_or(Uid(0));
+        self.session.poll(uid, now)
+    }
 
-        Ok(fd)
+    /// Lock the session for the current user
+    pub fn sys_session_lock(&mut self) -> SyscallResult<()> {
+        let uid = self.sys_getuid()?;
+        self.session.lock(uid, LockReason::Manual);
+        Ok(())
     }
 
-    /// Duplicate a file descriptor
-    pub fn sys_dup(&mut self, fd: Fd) -> SyscallResult<Fd> {
-        // Get the handle for the existing fd (using scoped borrow)
-        let handle = {
-            let process = self.get_current_process()?;
-            process.files.get(fd).ok_or(SyscallError::BadFd)?
-        };
+    /// Unlock the session with the locking user's or root's password
+    pub fn sys_session_unlock(&mut self, password: &str) -> SyscallResult<()> {
+        match self.session.unlock(&self.users, password) {
+            Ok(_) => Ok(()),
+            Err(UnlockError::BadPassword) => Err(SyscallError::PermissionDenied),
+            Err(UnlockError::NotLocked) => Err(SyscallError::InvalidArgument),
+        }
+    }
 
-        // Retain the object (increment refcount)
-        if !self.objects.retain(handle) {
-            return Err(SyscallError::BadFd);
+    /// Set the idle timeout in milliseconds; `None` disables auto-lock
+    pub fn sys_set_idle_timeout(&mut self, timeout: Option<f64>) -> SyscallResult<()> {
+        if timeout.is_some_and(|t| !t.is_finite() || t <= 0.0) {
+            return Err(SyscallError::InvalidArgument);
         }
+        self.session.set_idle_timeout(timeout);
+        Ok(())
+    }
 
-        // Allocate a new fd pointing to the same handle
-        let process = self.get_current_process_mut()?;
-        let new_fd = process
-            .files
-            .alloc(handle)
-            .ok_or(SyscallError::TooManyOpenFiles)?;
-        Ok(new_fd)
+    // ========== POWER ==========
+
+    /// The power governor
+    pub fn power(&self) -> &PowerGovernor {
+        &self.power
     }
 
-    /// Get current working directory
-    pub fn sys_getcwd(&self) -> SyscallResult<PathBuf> {
-        let process = self.get_current_process()?;
-        Ok(process.cwd.clone())
+    pub fn memory(&self) -> &MemoryManager {
+        &self.memory
     }
 
-    /// Change working directory
-    pub fn sys_chdir(&mut self, path: &str) -> SyscallResult<()> {
-        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        let resolved = self.resolve_path(current, path)?;
+    /// Handle of the console device
+    pub(crate) fn console_handle(&self) -> Handle {
+        self.console_handle
+    }
 
-        // Verify path exists and is a directory
-        let path_str = resolved.to_str().ok_or(SyscallError::InvalidArgument)?;
-        let meta = self.fs.vfs.metadata(path_str)?;
-        if !meta.is_dir {
-            return Err(SyscallError::NotADirectory);
+    pub fn synthetic_fs(&self) -> &SyntheticFsRegistry {
+        &self.fs.synthetic
+    }
+
+    /// Mount a synthetic filesystem, adding it to the mount table
+    pub fn register_synthetic_fs(&mut self, mount_point: &str, fs: Rc<dyn SyntheticFs>) {
+        if !self.fs.mounts.is_mount_point(mount_point) {
+            let fstype = FsType::parse(fs.fs_type());
+            let _ = self.fs.mounts.mount(
+                fs.fs_type(),
+                mount_point,
+                fstype,
+                MountOptions::new(),
+                self.time.now,
+            );
         }
+        self.fs.synthetic.register(mount_point, fs);
+    }
 
-        // Check execute permission on directory (required to cd into it)
-        self.check_file_permission(path_str, false, false, true)?;
+    /// Add the device node /dev/`name` (see [`DevFs::register`])
+    pub fn register_device(&mut self, name: &str, ops: Rc<dyn DeviceOps>) -> SyscallResult<()> {
+        self.fs.devfs.register(name, ops)
+    }
 
-        let process = self
-            .proc
-            .processes
-            .get_mut(&current)
-            .ok_or(SyscallError::NoProcess)?;
-        process.cwd = resolved;
-        Ok(())
+    /// Remove the device node /dev/`name`; open descriptors keep working
+    pub fn unregister_device(&mut self, name: &str) -> SyscallResult<()> {
+        self.fs.devfs.unregister(name)
     }
 
-    /// Exit the current process
-    pub fn sys_exit(&mut self, code: i32) -> SyscallResult<()> {
-        let process = self.get_current_process_mut()?;
-        process.state = ProcessState::Zombie(code);
-        Ok(())
+    /// Select the governor (also settable through /sys/power/governor)
+    pub fn sys_set_governor(&mut self, governor: Governor) {
+        self.power.set_governor(governor);
     }
 
-    /// Get current process ID
-    pub fn sys_getpid(&self) -> SyscallResult<Pid> {
-        self.proc.current.ok_or(SyscallError::NoProcess)
+    /// Report page visibility; returns true if the power mode changed
+    pub fn set_page_hidden(&mut self, hidden: bool) -> bool {
+        self.power.set_hidden(hidden)
     }
 
-    /// Get parent process ID
-    pub fn sys_getppid(&self) -> SyscallResult<Option<Pid>> {
-        let process = self.get_current_process()?;
-        Ok(process.parent)
+    /// Ask to persist state at `now`; returns true if it should be written
+    /// now rather than batched
+    pub fn power_request_persist(&mut self, now: f64) -> bool {
+        self.power.request_persist(now)
     }
 
-    // ========== ENVIRONMENT SYSCALLS ==========
-    // Like Linux: each process has its own environment (inherited on spawn)
+    /// Check if a batched persistence write is due at `now`
+    pub fn power_persist_due(&mut self, now: f64) -> bool {
+        self.power.persist_due(now)
+    }
 
-    /// Get an environment variable
-    pub fn sys_getenv(&self, name: &str) -> SyscallResult<Option<String>> {
-        let process = self.get_current_process()?;
-        Ok(process.getenv(name).map(|s| s.to_string()))
+    // ========== MEMORY PRESSURE ==========
+
+    /// The memory pressure monitor
+    pub fn pressure(&self) -> &PressureMonitor {
+        &self.pressure
     }
 
-    /// Set an environment variable
-    pub fn sys_setenv(&mut self, name: &str, value: &str) -> SyscallResult<()> {
-        if name.is_empty() || name.contains('=') {
-            return Err(SyscallError::InvalidArgument);
+    /// Memory the kernel accounts for (process regions and shared
+    /// segments) against the system limit
+    fn memory_sample(&self) -> MemorySample {
+        let regions: usize = self
+            .proc
+            .processes
+            .values()
+            .map(|p| p.memory.allocated())
+            .sum();
+        let stats = self.memory.system_stats();
+        MemorySample {
+            used: (regions + stats.total_allocated) as u64,
+            limit: stats.system_limit as u64,
         }
-        let process = self.get_current_process_mut()?;
-        process.setenv(name, value);
-        Ok(())
     }
 
-    /// Remove an environment variable
-    pub fn sys_unsetenv(&mut self, name: &str) -> SyscallResult<bool> {
-        let process = self.get_current_process_mut()?;
-        Ok(process.unsetenv(name))
+    /// Check memory pressure, with the platform's heap figures if it has
+    /// them; returns the new level if it changed
+    ///
+    /// Changes are logged. When the level rises, every process that
+    /// handles or blocks SIGMEMPRESSURE is sent it.
+    pub fn check_memory_pressure(
+        &mut self,
+        platform: Option<MemorySample>,
+    ) -> Option<PressureLevel> {
+        let previous = self.pressure.level();
+        let mut samples = vec![self.memory_sample()];
+        samples.extend(platform);
+        let level = self.pressure.update(&samples)?;
+        let percent = self.pressure.percent().unwrap_or(0);
+        if level > previous {
+            // Spare file buffers are the kernel's own cache, and so are
+            // shared blocks no file holds any more
+            self.objects.buffers.clear();
+            self.fs.vfs.sweep_blocks();
+            let notified = self.notify_memory_pressure();
+            self.klog(
+                LogLevel::Warn,
+                "memory",
+                &format!(
+                    "memory pressure {} ({}% in use), notified {} process(es)",
+                    level.name(),
+                    percent,
+                    notified
+                ),
+            );
+        } else {
+            self.klog(
+                LogLevel::Info,
+                "memory",
+                &format!("memory pressure {} ({}% in use)", level.name(), percent),
+            );
+        }
+        Some(level)
     }
 
-    /// Get all environment variables
-    pub fn sys_environ(&self) -> SyscallResult<Vec<(String, String)>> {
-        let process = self.get_current_process()?;
-        Ok(process
-            .environ()
-            .iter()
-            .map(|(k, v)| (k.clone(), v.clone()))
-            .collect())
+    /// Send SIGMEMPRESSURE to the processes that asked for it; returns
+    /// how many there were
+    fn notify_memory_pressure(&mut self) -> usize {
+        let mut notified = 0;
+        let mut woken = Vec::new();
+        for process in self.proc.processes.values_mut() {
+            if matches!(process.state, ProcessState::Zombie(_))
+                || !process.signals.wants(Signal::SIGMEMPRESSURE)
+            {
+                continue;
+            }
+            process.signals.send(Signal::SIGMEMPRESSURE);
+            notified += 1;
+            if process.signals.interrupting().is_some()
+                && let Some(task) = process.task
+            {
+                woken.push(task);
+            }
+        }
+        for task in woken {
+            self.time.timers.schedule(0.0, self.time.now, Some(task));
+        }
+        notified
     }
 
-    // ========== WAITPID SYSCALL ==========
-    // Like Linux waitpid: wait for child process state changes
+    // ========== GUEST SESSION ==========
 
-    /// Wait for a child process to change state
-    ///
-    /// - pid > 0: wait for specific child
-    /// - pid = -1: wait for any child
-    /// - pid = 0: wait for any child in same process group
-    /// - pid < -1: wait for any child in process group |pid|
-    pub fn sys_waitpid(&mut self, pid: i32, flags: WaitFlags) -> SyscallResult<(Pid, WaitStatus)> {
+    /// The active guest session
+    pub fn guest(&self) -> Option<&GuestSession> {
+        self.guest.as_ref()
+    }
+
+    /// Start a guest session: remount / as an overlay whose changes are
+    /// thrown away when the session ends
+    pub fn sys_guest_start(&mut self) -> SyscallResult<()> {
+        if self.guest.is_some() {
+            return Err(SyscallError::Busy);
+        }
+        let now = self.time.now;
+        let overlay = MountEntry::new(GUEST_SOURCE, "/", FsType::Overlay, MountOptions::new(), now);
+        let root = self.fs.mounts.replace(overlay);
+        self.guest = Some(GuestSession::new(&self.fs.vfs, now, root));
+        Ok(())
+    }
+
+    /// Keep a path (and everything under it) when the guest session ends
+    pub fn sys_guest_export(&mut self, path: &str) -> SyscallResult<String> {
         let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let resolved = self.resolve_path(current, path)?;
+        let path = resolved.to_string_lossy().into_owned();
+        let guest = self.guest.as_mut().ok_or(SyscallError::InvalidArgument)?;
+        if !self.fs.vfs.exists(&path) && !guest.lower().contains(&path) {
+            return Err(SyscallError::NotFound);
+        }
+        guest.export(&path);
+        Ok(path)
+    }
+
+    /// End the guest session, discarding every change that wasn't exported
+    pub fn sys_guest_end(&mut self) -> SyscallResult<()> {
+        let guest = self.guest.as_ref().ok_or(SyscallError::InvalidArgument)?;
+        let real = guest.finish(&self.fs.vfs)?;
+        let root = guest.root_mount().cloned();
+        self.guest = None;
+
+        // Handles into the overlay are gone; open files keep their data
+        // but no longer write back
+        self.fs.vfs_handles.clear();
+        self.fs.vfs = real;
+        if let Some(root) = root {
+            self.fs.mounts.replace(root);
+        }
+        // Users added as a guest go away with /etc/passwd
+        self.load_user_db();
+        Ok(())
+    }
 
-        // Find matching children
-        let children: Vec<Pid> = {
-            let parent = self
-                .proc
-                .processes
-                .get(&current)
-                .ok_or(SyscallError::NoProcess)?;
-            let pgid = parent.pgid;
+    /// The filesystem as persistence should see it: the real filesystem
+    /// under the overlay during a guest session
+    pub fn persistent_vfs_json(&self) -> std::io::Result<Vec<u8>> {
+        match &self.guest {
+            Some(guest) => serde_json::to_vec(guest.lower())
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+            None => self.fs.vfs.to_json(),
+        }
+    }
 
-            parent
-                .children
-                .iter()
-                .filter(|&child_pid| {
-                    if pid > 0 {
-                        // Wait for specific child
-                        // Safe: pid > 0 guarantees positive value fits in u32
-                        u32::try_from(pid)
-                            .map(|p| child_pid.0 == p)
-                            .unwrap_or(false)
-                    } else if pid == -1 {
-                        // Wait for any child
-                        true
-                    } else if pid == 0 {
-                        // Wait for any child in same process group
-                        self.proc
-                            .processes
-                            .get(child_pid)
-                            .map(|p| p.pgid == pgid)
-                            .unwrap_or(false)
-                    } else {
-                        // Wait for any child in specified process group
-                        // Safe: pid < -1 means -pid > 1, use checked negation for i32::MIN
-                        let target_pgid = pid
-                            .checked_neg()
-                            .and_then(|n| u32::try_from(n).ok())
-                            .map(Pgid);
-                        target_pgid
-                            .and_then(|tpg| {
-                                self.proc.processes.get(child_pid).map(|p| p.pgid == tpg)
-                            })
-                            .unwrap_or(false)
-                    }
-                })
-                .copied()
-                .collect()
+    // ========== SYSTEM PARTITION ==========
+
+    /// What the last boot of the system partition did
+    pub fn system(&self) -> Option<&SystemBoot> {
+        self.system.as_ref()
+    }
+
+    /// Slot state on disk
+    pub fn system_state(&mut self) -> SlotState {
+        system::load_state(&mut self.fs.vfs)
+    }
+
+    /// Boot the system partition: switch to a staged update or roll back a
+    /// failed one, then mount the active image read-only on /system
+    pub fn sys_system_boot(&mut self, factory: &SystemImage) -> SyscallResult<SystemBoot> {
+        let boot = system::boot(&mut self.fs.vfs, factory)?;
+        let options = MountOptions {
+            read_only: true,
+            ..Default::default()
         };
+        let entry = MountEntry::new(
+            &boot.slot.source(),
+            SYSTEM_ROOT,
+            FsType::MemoryFs,
+            options,
+            self.time.now,
+        );
+        self.fs.mounts.replace(entry);
+        self.system = Some(boot.clone());
+        Ok(boot)
+    }
 
-        if children.is_empty() {
-            return Err(SyscallError::NoProcess);
-        }
+    /// Confirm the running slot booted, so it isn't rolled back
+    pub fn sys_system_mark_good(&mut self) -> SyscallResult<bool> {
+        Ok(system::mark_good(&mut self.fs.vfs)?)
+    }
 
-        // Look for a child that has changed state
-        for child_pid in children {
-            // First check state without mutable borrow
-            let state_info = self
-                .proc
-                .processes
-                .get(&child_pid)
-                .map(|child| (child.state.clone(), child.was_continued));
+    /// Stage a system image for the next boot (root only)
+    pub fn sys_system_stage(&mut self, data: &[u8]) -> SyscallResult<(Slot, String)> {
+        self.require_system_admin()?;
+        let (slot, image) = system::stage(&mut self.fs.vfs, data).map_err(|e| match e.kind() {
+            std::io::ErrorKind::ResourceBusy => SyscallError::Busy,
+            std::io::ErrorKind::InvalidData => SyscallError::InvalidData,
+            _ => SyscallError::from(e),
+        })?;
+        Ok((slot, image.version))
+    }
 
-            if let Some((state, was_continued)) = state_info {
-                match state {
-                    ProcessState::Zombie(exit_code) => {
-                        let status = WaitStatus::Exited(exit_code);
-                        // Reap the zombie
-                        self.proc.processes.remove(&child_pid);
-                        // Remove from parent's children list
-                        if let Some(parent) = self.proc.processes.get_mut(&current) {
-                            parent.children.retain(|&p| p != child_pid);
-                        }
-                        return Ok((child_pid, status));
-                    }
-                    ProcessState::Stopped if flags.untraced => {
-                        return Ok((child_pid, WaitStatus::Stopped));
-                    }
-                    ProcessState::Running if flags.continued && was_continued => {
-                        // Child was recently continued - clear flag and report
-                        if let Some(child) = self.proc.processes.get_mut(&child_pid) {
-                            child.was_continued = false;
-                        }
-                        return Ok((child_pid, WaitStatus::Continued));
-                    }
-                    _ => {}
-                }
-            }
-        }
+    /// Boot the other slot next time (root only)
+    pub fn sys_system_rollback(&mut self) -> SyscallResult<Slot> {
+        self.require_system_admin()?;
+        Ok(system::rollback(&mut self.fs.vfs)?)
+    }
 
-        // No child ready
-        if flags.nohang {
-            Ok((Pid(0), WaitStatus::NoChild))
-        } else {
-            // In a real OS we'd block here
-            // For now, return no child (caller should retry)
-            Err(SyscallError::WouldBlock)
+    /// Slot changes are root's, and would be thrown away in a guest session
+    fn require_system_admin(&self) -> SyscallResult<()> {
+        if self.get_current_process()?.euid != Uid::ROOT {
+            return Err(SyscallError::PermissionDenied);
+        }
+        if self.guest.is_some() {
+            return Err(SyscallError::Busy);
         }
+        Ok(())
     }
 
-    // ========== PROCESS GROUP SYSCALLS ==========
-    // Like Linux: process groups for job control (fg/bg)
+    // ========== SYSCALLS ==========
 
-    /// Get process group ID
-    pub fn sys_getpgid(&self, pid: Pid) -> SyscallResult<Pgid> {
-        let process = self
-            .proc
-            .processes
-            .get(&pid)
-            .ok_or(SyscallError::NoProcess)?;
-        Ok(process.pgid)
+    /// Open a file or device
+    pub fn sys_open(&mut self, path: &str, flags: OpenFlags) -> SyscallResult<Fd> {
+        let result = self.open_path(path, flags);
+        if self.debugger.has_path_watches()
+            && let Some(resolved) = self.debug_resolve(path)
+        {
+            let args = vec![debug_path_arg("path", &resolved), debug_open_flags(flags)];
+            let ret = debug_result(&result, |fd| fd.0 as i32);
+            self.debug_path_access(PathOp::Open, &resolved, "open", args, ret);
+        }
+        result
     }
 
-    /// Set process group ID
-    pub fn sys_setpgid(&mut self, pid: Pid, pgid: Pgid) -> SyscallResult<()> {
+    fn open_path(&mut self, path: &str, flags: OpenFlags) -> SyscallResult<Fd> {
         let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
 
-        // Can only setpgid on self or children
-        if pid != current {
-            let parent = self
-                .proc
-                .processes
-                .get(&current)
-                .ok_or(SyscallError::NoProcess)?;
-            if !parent.children.contains(&pid) {
-                return Err(SyscallError::PermissionDenied);
+        // Resolve path
+        let resolved = self.resolve_path(current, path)?;
+
+        // Synthetic filesystems generate their files
+        let resolved_str = resolved.to_string_lossy();
+        let handle = if let Some(fs) = self.fs.synthetic.lookup(&resolved_str) {
+            if !fs.exists(self, &resolved_str) {
+                return Err(SyscallError::NotFound);
             }
-        }
+            if fs.is_dir(self, &resolved_str) {
+                return Err(SyscallError::IsADirectory);
+            }
+            fs.open(self, &resolved_str, flags)?
+        } else {
+            // SEC-011: Check path traversal permissions for regular files
+            self.check_path_traversal(&resolved_str)?;
+            // Truncating writes every byte of a mandatory-locked file
+            if flags.truncate
+                && self.has_mandatory_locking(&resolved_str)
+                && self
+                    .ipc
+                    .file_locks
+                    .io_conflict(&resolved_str, current, LockType::Exclusive, 0, u64::MAX)
+                    .is_some()
+            {
+                return Err(SyscallError::WouldBlock);
+            }
+            self.open_file(&resolved, flags)?
+        };
+        self.objects.set_nonblocking(handle, flags.nonblock);
 
+        // Add to process file table
         let process = self
             .proc
             .processes
-            .get_mut(&pid)
+            .get_mut(&current)
             .ok_or(SyscallError::NoProcess)?;
-        process.pgid = pgid;
-        Ok(())
+        let fd = process
+            .files
+            .alloc(handle)
+            .ok_or(SyscallError::TooManyOpenFiles)?;
+        Ok(fd)
     }
 
-    // ========== IOCTL SYSCALL ==========
-    // Like Linux ioctl: generic device control interface
-
-    /// Perform device-specific control operation
-    pub fn sys_ioctl(&mut self, fd: Fd, request: IoctlRequest) -> SyscallResult<IoctlResult> {
+    /// Read from a file descriptor
+    pub fn sys_read(&mut self, fd: Fd, buf: &mut [u8]) -> SyscallResult<usize> {
         let handle = self.get_handle(fd)?;
+        self.check_mandatory_lock(handle, LockType::Shared, buf.len())?;
         let obj = self.objects.get_mut(handle).ok_or(SyscallError::BadFd)?;
-
-        match (obj, request) {
-            (KernelObject::Console(_), IoctlRequest::GetWinSize) => {
-                // Return current terminal size (from terminal module)
-                // For now, return a reasonable default
-                Ok(IoctlResult::WinSize(WinSize {
-                    rows: 24,
-                    cols: 80,
-                    xpixel: 0,
-                    ypixel: 0,
-                }))
+        let result = match obj {
+            KernelObject::Device(dev) => {
+                let (ops, offset) = (Rc::clone(&dev.ops), dev.position);
+                let result = ops.read(self, offset, buf);
+                self.advance_device(handle, &result);
+                result
             }
-            (KernelObject::Console(_), IoctlRequest::SetWinSize) => {
-                // Setting window size from ioctl not supported in WASM
-                Err(SyscallError::InvalidArgument)
+            KernelObject::SignalFd(sfd) => {
+                let mask = sfd.mask;
+                self.read_signalfd(mask, buf)
             }
-            (KernelObject::Console(console), IoctlRequest::TcFlush) => {
-                console.clear_input();
-                Ok(IoctlResult::Ok)
+            obj => obj.read(buf).map_err(SyscallError::from),
+        };
+        if result.is_ok() {
+            self.wake_pollers();
+        }
+        match result {
+            // A read that would wait on an empty pipe is interrupted instead
+            Err(SyscallError::WouldBlock) if self.interrupting_signal().is_some() => {
+                Err(SyscallError::Interrupted)
             }
-            _ => Err(SyscallError::InvalidArgument),
+            result => result,
         }
     }
 
-    // ========== HELPERS ==========
+    /// Read up to `len` bytes of the file at `path` from `offset`, handing
+    /// them to `sink` straight from where the filesystem keeps them
+    ///
+    /// This is a read without a descriptor or a buffer in between: the
+    /// WASM runtime copies each slice into a module's linear memory as it
+    /// comes, so file data is copied once. Generated files are read
+    /// through an ordinary descriptor.
+    pub fn sys_read_at_with(
+        &mut self,
+        path: &str,
+        offset: u64,
+        len: usize,
+        sink: &mut dyn FnMut(&[u8]),
+    ) -> SyscallResult<usize> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let resolved = self.resolve_path(current, path)?;
+        let resolved = resolved.to_string_lossy();
+        if self.fs.synthetic.lookup(&resolved).is_some() {
+            return self.read_at_buffered(&resolved, offset, len, sink);
+        }
+        self.check_path_traversal(&resolved)?;
+        if !self.ipc.file_locks.is_empty()
+            && self.has_mandatory_locking(&resolved)
+            && self
+                .ipc
+                .file_locks
+                .io_conflict(&resolved, current, LockType::Shared, offset, len as u64)
+                .is_some()
+        {
+            return Err(SyscallError::WouldBlock);
+        }
 
-    /// Get a handle from the current process's file table
-    fn get_handle(&self, fd: Fd) -> SyscallResult<Handle> {
-        let process = self.get_current_process()?;
-        process.files.get(fd).ok_or(SyscallError::BadFd)
-    }
-
-    /// Resolve a path relative to a process's cwd
-    fn resolve_path(&self, pid: Pid, path: &str) -> SyscallResult<PathBuf> {
-        let process = self
-            .proc
-            .processes
-            .get(&pid)
-            .ok_or(SyscallError::NoProcess)?;
-
-        // Use jail-aware path resolution
-        // This handles both regular paths and chrooted processes
-        process
-            .resolve_jailed_path(Path::new(path))
-            .map_err(|_| SyscallError::PermissionDenied)
+        let (fs, inner) = self.fs.route_mut(&resolved);
+        let vh = fs.open(&inner, VfsOpenOptions::new().read(true))?;
+        let result = self
+            .check_handle_permission(&resolved, vh, true, false, false)
+            .and_then(|()| {
+                let (fs, _) = self.fs.route_mut(&resolved);
+                fs.seek(vh, SeekFrom::Start(offset))?;
+                Ok(fs.read_with(vh, len, sink)?)
+            });
+        let (fs, _) = self.fs.route_mut(&resolved);
+        fs.close(vh)?;
+        result
     }
 
-    /// Check if the current process has permission to access a file
-    /// Returns Ok(()) if allowed, Err(PermissionDenied) if not
-    fn check_file_permission(
-        &self,
+    /// [`Self::sys_read_at_with`] for a file only a descriptor can read
+    fn read_at_buffered(
+        &mut self,
         path: &str,
-        want_read: bool,
-        want_write: bool,
-        want_exec: bool,
+        offset: u64,
+        len: usize,
+        sink: &mut dyn FnMut(&[u8]),
+    ) -> SyscallResult<usize> {
+        let fd = self.sys_open(path, OpenFlags::READ)?;
+        let mut buf = vec![0u8; len];
+        let result = self
+            .sys_seek(fd, SeekFrom::Start(offset))
+            .and_then(|_| self.sys_read(fd, &mut buf));
+        self.sys_close(fd)?;
+        let n = result?;
+        sink(&buf[..n]);
+        Ok(n)
+    }
+
+    /// Refuse a read (`Shared`) or write (`Exclusive`) of `len` bytes at a
+    /// file's position that another process has locked, if the file has
+    /// mandatory locking (see [`super::flock`])
+    fn check_mandatory_lock(
+        &self,
+        handle: Handle,
+        access: LockType,
+        len: usize,
     ) -> SyscallResult<()> {
-        let process = self.get_current_process()?;
-
-        // Get file metadata
-        let meta = self.fs.vfs.metadata(path)?;
-
-        // Check permission
-        let allowed = check_permission(
-            Uid(meta.uid),
-            Gid(meta.gid),
-            FileMode::new(meta.mode),
-            process.euid,
-            process.egid,
-            &process.groups,
-            want_read,
-            want_write,
-            want_exec,
-        );
-
-        if allowed {
-            Ok(())
-        } else {
-            Err(SyscallError::PermissionDenied)
+        if self.ipc.file_locks.is_empty() {
+            return Ok(());
+        }
+        let Some(KernelObject::File(file)) = self.objects.get(handle) else {
+            return Ok(());
+        };
+        let path = file.path.to_string_lossy();
+        if !self.has_mandatory_locking(&path) {
+            return Ok(());
+        }
+        let pid = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        match self
+            .ipc
+            .file_locks
+            .io_conflict(&path, pid, access, file.position, len as u64)
+        {
+            Some(_) => Err(SyscallError::WouldBlock),
+            None => Ok(()),
         }
     }
 
-    /// Check permission on an already-opened file handle (atomic, TOCTOU-safe)
-    ///
-    /// This is preferred over check_file_permission when the file is already open,
-    /// as it checks permissions on the actual opened file, not a path that could
-    /// have changed between check and use.
-    fn check_handle_permission(
-        &self,
-        vfs_handle: VfsFileHandle,
-        want_read: bool,
-        want_write: bool,
-        want_exec: bool,
-    ) -> SyscallResult<()> {
-        let process = self.get_current_process()?;
-
-        // Get metadata from the opened file handle (not the path)
-        let meta = self.fs.vfs.fstat(vfs_handle)?;
-
-        // Check permission
-        let allowed = check_permission(
-            Uid(meta.uid),
-            Gid(meta.gid),
-            FileMode::new(meta.mode),
-            process.euid,
-            process.egid,
-            &process.groups,
-            want_read,
-            want_write,
-            want_exec,
-        );
+    /// Check if locks on `path` are mandatory: set-group-ID without group
+    /// execute
+    fn has_mandatory_locking(&self, path: &str) -> bool {
+        self.fs
+            .vfs
+            .metadata(path)
+            .is_ok_and(|meta| meta.mode & 0o2010 == 0o2000)
+    }
 
-        if allowed {
-            Ok(())
-        } else {
-            Err(SyscallError::PermissionDenied)
+    /// Fill `buf` with records of the current process's pending signals in
+    /// `mask`; `WouldBlock` if there are none
+    fn read_signalfd(&mut self, mask: u16, buf: &mut [u8]) -> SyscallResult<usize> {
+        if buf.len() < SIGNALFD_RECORD_SIZE {
+            return Err(SyscallError::InvalidArgument);
+        }
+        let process = self.get_current_process_mut()?;
+        let mut n = 0;
+        for record in buf.chunks_exact_mut(SIGNALFD_RECORD_SIZE) {
+            let Some(signal) = process.signals.take_pending(mask) else {
+                break;
+            };
+            record.copy_from_slice(&u32::from(signal.num()).to_le_bytes());
+            n += SIGNALFD_RECORD_SIZE;
+        }
+        if n == 0 {
+            return Err(SyscallError::WouldBlock);
         }
+        Ok(n)
     }
 
-    /// SEC-011: Check execute permission on ALL directories in the path
-    /// This is required to traverse to the target file/directory
-    fn check_path_traversal(&self, path: &str) -> SyscallResult<()> {
-        let process = self.get_current_process()?;
-
-        // Root can always traverse
-        if process.euid == Uid::ROOT {
-            return Ok(());
+    /// Move a device descriptor's offset past a successful transfer
+    fn advance_device(&mut self, handle: Handle, result: &SyscallResult<usize>) {
+        if let (Ok(n), Some(KernelObject::Device(dev))) = (result, self.objects.get_mut(handle)) {
+            dev.position += *n as u64;
         }
+    }
 
-        // Split path into components and check each directory
-        let path = Path::new(path);
-        let mut current_path = PathBuf::from("/");
-
-        for component in path.components() {
-            use std::path::Component;
-            match component {
-                Component::RootDir => continue,
-                Component::CurDir => continue,
-                Component::ParentDir => {
-                    current_path.pop();
-                }
-                Component::Normal(name) => {
-                    // Check execute permission on current directory before entering
-                    let current_str = current_path.to_string_lossy();
-                    if current_str != "/" {
-                        // Check if current_path is a directory we need execute permission for
-                        if let Ok(meta) = self.fs.vfs.metadata(&current_str)
-                            && meta.is_dir
-                        {
-                            let allowed = check_permission(
-                                Uid(meta.uid),
-                                Gid(meta.gid),
-                                FileMode::new(meta.mode),
-                                process.euid,
-                                process.egid,
-                                &process.groups,
-                                false,
-                                false,
-                                true, // Need execute to traverse
-                            );
-                            if !allowed {
-                                return Err(SyscallError::PermissionDenied);
-                            }
-                        }
-                    }
-                    current_path.push(name);
-                }
-                Component::Prefix(_) => {} // Windows-only
+    /// Write to a file descriptor
+    pub fn sys_write(&mut self, fd: Fd, buf: &[u8]) -> SyscallResult<usize> {
+        let handle = self.get_handle(fd)?;
+        self.check_writable_handle(handle)?;
+        self.check_mandatory_lock(handle, LockType::Exclusive, buf.len())?;
+        self.check_write_quota(handle, buf.len())?;
+        let obj = self.objects.get_mut(handle).ok_or(SyscallError::BadFd)?;
+        let mut result = match obj {
+            KernelObject::Device(dev) => {
+                let (ops, offset) = (Rc::clone(&dev.ops), dev.position);
+                let result = ops.write(self, offset, buf);
+                self.advance_device(handle, &result);
+                result
+            }
+            KernelObject::Watchdog(w) => {
+                let service = w.service.clone();
+                self.pet_watchdog(&service).map(|()| buf.len())
+            }
+            obj => obj.write(buf).map_err(SyscallError::from),
+        };
+        if handle == self.console_handle && result.is_ok() {
+            let name = self.get_current_process().ok().and_then(|p| p.ctty.clone());
+            if let Some(tty) = self.ttys.get_tty_mut(name.as_deref().unwrap_or("console")) {
+                tty.mouse.observe(buf);
             }
         }
-
-        // Check the final directory component if the path is a directory path
-        // (for the parent directory of a file)
-        if let Some(parent) = path.parent()
-            && parent != Path::new("")
-            && parent != Path::new("/")
-        {
-            let parent_str = parent.to_string_lossy();
-            if let Ok(meta) = self.fs.vfs.metadata(&parent_str)
-                && meta.is_dir
+        if let Some(KernelObject::File(file)) = self.objects.get(handle) {
+            let path = file.path.to_string_lossy().into_owned();
+            if let Some(fs) = self.fs.synthetic.lookup(&path)
+                && fs.is_writable(&path)
             {
-                let allowed = check_permission(
-                    Uid(meta.uid),
-                    Gid(meta.gid),
-                    FileMode::new(meta.mode),
-                    process.euid,
-                    process.egid,
-                    &process.groups,
-                    false,
-                    false,
-                    true,
-                );
-                if !allowed {
-                    return Err(SyscallError::PermissionDenied);
+                let value = file.data.clone();
+                if let Err(e) = fs.store(self, &path, &value) {
+                    result = Err(e);
                 }
             }
         }
-
-        Ok(())
+        if self.debugger.has_path_watches()
+            && let Some(KernelObject::File(file)) = self.objects.get(handle)
+        {
+            let path = file.path.to_string_lossy().into_owned();
+            let args = vec![
+                SyscallArg {
+                    name: "fd".to_string(),
+                    value: fd.0 as i32,
+                    interpreted: ArgValue::Fd(fd),
+                },
+                SyscallArg {
+                    name: "count".to_string(),
+                    value: buf.len() as i32,
+                    interpreted: ArgValue::Size(buf.len()),
+                },
+            ];
+            let ret = debug_result(&result, |n| *n as i32);
+            self.debug_path_access(PathOp::Write, &path, "write", args, ret);
+        }
+        if result.is_ok() {
+            self.wake_pollers();
+        }
+        result
     }
 
-    /// Check if the current process has write permission on the parent directory
-    /// (needed for creating/deleting files in the directory)
-    fn check_parent_write_permission(&self, path: &str) -> SyscallResult<()> {
-        // Get parent directory
-        let parent = Path::new(path)
-            .parent()
-            .map(|p| p.to_string_lossy().to_string())
-            .unwrap_or_else(|| "/".to_string());
-
-        let parent = if parent.is_empty() { "/" } else { &parent };
+    /// Close a file descriptor
+    pub fn sys_close(&mut self, fd: Fd) -> SyscallResult<()> {
+        let process = self.get_current_process_mut()?;
 
-        // Need write and execute on parent directory to create/delete files
-        self.check_file_permission(parent, false, true, true)
-    }
+        let handle = process.files.remove(fd).ok_or(SyscallError::BadFd)?;
 
-    /// SEC-015: Check sticky bit restriction for file deletion
-    /// When a directory has the sticky bit set, only the owner of the file,
-    /// the owner of the directory, or root can delete files in it.
-    fn check_sticky_bit(&self, path: &str) -> SyscallResult<()> {
-        let process = self.get_current_process()?;
+        // Sync file to VFS if it's a file (before potential release)
+        if let Some(KernelObject::File(file)) = self.objects.get(handle) {
+            let path =
+                (!self.ipc.file_locks.is_empty()).then(|| file.path.to_string_lossy().into_owned());
+            self.sync_file(handle)?;
+            if let Some(path) = path {
+                self.release_closed_locks(&path);
+            }
+        }
 
-        // Root can always delete
-        if process.euid == Uid::ROOT {
-            return Ok(());
+        // Release the handle (decrements refcount)
+        // If refcount drops to 0, the object is removed
+        if let Some(removed_object) = self.objects.release(handle) {
+            // Object was deallocated - clean up VFS handle if present
+            self.fs.close_released(handle, &removed_object);
+            self.objects.recycle(removed_object);
         }
+        self.wake_pollers();
 
-        // Get parent directory
-        let parent_path = Path::new(path)
-            .parent()
-            .map(|p| p.to_string_lossy().to_string())
-            .unwrap_or_else(|| "/".to_string());
-        let parent = if parent_path.is_empty() {
-            "/"
-        } else {
-            &parent_path
-        };
+        Ok(())
+    }
 
-        // Get parent directory metadata
-        let parent_meta = self.fs.vfs.metadata(parent)?;
-        let parent_mode = FileMode::new(parent_meta.mode);
+    /// Report which of `fds` are ready, filling in their `revents`;
+    /// returns how many have something to report
+    ///
+    /// With nothing ready this fails with `WouldBlock` unless `timeout`
+    /// (milliseconds) has run out, in which case it returns 0, or with
+    /// `Interrupted` when a signal is waiting. `None` waits for ever. Use
+    /// [`poll`] to wait.
+    pub fn sys_poll(&self, fds: &mut [PollFd], timeout: Option<f64>) -> SyscallResult<usize> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        self.poll_as(current, fds, timeout)
+    }
 
-        // If sticky bit is not set, no restriction
-        if !parent_mode.is_sticky() {
-            return Ok(());
+    /// [`Self::sys_poll`] for process `pid`
+    fn poll_as(&self, pid: Pid, fds: &mut [PollFd], timeout: Option<f64>) -> SyscallResult<usize> {
+        let process = self
+            .proc
+            .processes
+            .get(&pid)
+            .ok_or(SyscallError::NoProcess)?;
+        let mut ready = 0;
+        for pfd in fds.iter_mut() {
+            let state = self.source_poll_ready(process, &pfd.source);
+            pfd.revents = pfd.events.report(state);
+            if pfd.is_ready() {
+                ready += 1;
+            }
         }
 
-        // Sticky bit is set - check ownership
-        // Can delete if: owner of directory, or owner of file
-        let is_dir_owner = parent_meta.uid == process.euid.0;
-        if is_dir_owner {
-            return Ok(());
+        if ready > 0 || timeout.is_some_and(|t| t <= 0.0) {
+            Ok(ready)
+        } else if process.signals.interrupting().is_some() {
+            Err(SyscallError::Interrupted)
+        } else {
+            Err(SyscallError::WouldBlock)
         }
+    }
 
-        // Check if owner of the file being deleted
-        if let Ok(file_meta) = self.fs.vfs.metadata(path)
-            && file_meta.uid == process.euid.0
-        {
-            return Ok(());
+    /// What `source` could do now without blocking, for `process`
+    fn source_poll_ready(&self, process: &Process, source: &PollSource) -> PollEvents {
+        match source {
+            PollSource::Fd(fd) => self.fd_poll_ready(process, *fd),
+            PollSource::Socket(id) => self.ipc.sockets.poll_ready(*id),
+            PollSource::Fifo(path) => match self.ipc.fifos.get(path) {
+                Some(fifo) => fifo.borrow().poll_ready(),
+                None => PollEvents {
+                    invalid: true,
+                    ..PollEvents::NONE
+                },
+            },
         }
+    }
 
-        // Not allowed
-        Err(SyscallError::PermissionDenied)
+    /// What descriptor `fd` of `process` could do now without blocking
+    ///
+    /// Files, directories and devices never block. An event queue is
+    /// readable when a wait on it would report something.
+    fn fd_poll_ready(&self, process: &Process, fd: Fd) -> PollEvents {
+        let object = process.files.get(fd).and_then(|h| self.objects.get(h));
+        match object {
+            Some(KernelObject::Pipe(pipe)) => pipe.poll_ready(),
+            Some(KernelObject::EventQueue(queue)) => PollEvents {
+                readable: queue.is_ready(&self.queue_states(process, queue), self.time.now),
+                ..PollEvents::NONE
+            },
+            Some(KernelObject::Console(console)) => console.poll_ready(),
+            Some(KernelObject::SignalFd(sfd)) => PollEvents {
+                readable: process.signals.has_pending_in(sfd.mask),
+                ..PollEvents::NONE
+            },
+            Some(KernelObject::Watchdog(_)) => PollEvents::WRITE,
+            Some(_) => PollEvents::READ_WRITE,
+            None => PollEvents {
+                invalid: true,
+                ..PollEvents::NONE
+            },
+        }
     }
 
-    /// Get the current process's effective UID (for setting file ownership)
-    fn current_euid(&self) -> SyscallResult<Uid> {
-        let process = self.get_current_process()?;
-        Ok(process.euid)
+    /// Wake the tasks waiting in [`poll`] to check their sources again
+    fn wake_pollers(&mut self) {
+        for waker in self.pollers.drain(..) {
+            waker.wake();
+        }
     }
 
-    /// Get the current process's effective GID
-    fn current_egid(&self) -> SyscallResult<Gid> {
-        let process = self.get_current_process()?;
-        Ok(process.egid)
+    /// Check if descriptor `fd` of `process` is an event queue
+    fn is_event_queue(&self, process: &Process, fd: Fd) -> bool {
+        let object = process.files.get(fd).and_then(|h| self.objects.get(h));
+        matches!(object, Some(KernelObject::EventQueue(_)))
     }
 
-    /// Create a file object and insert it into the kernel object store
+    /// Readiness of the source of each of `queue`'s interests, by token,
+    /// for `process`
     ///
-    /// This is a helper to reduce duplication across open_device, open_proc, open_sysfs, etc.
-    fn create_file_object(
-        &mut self,
-        path: PathBuf,
-        data: Vec<u8>,
-        readable: bool,
-        writable: bool,
-    ) -> Handle {
-        let file = FileObject::new(path, data, readable, writable);
-        self.objects.insert(KernelObject::File(file))
+    /// Timers are the queue's own business and read as not ready here, and
+    /// so does an event queue watched by another.
+    fn queue_states(&self, process: &Process, queue: &EventQueue) -> BTreeMap<u64, PollEvents> {
+        queue
+            .interests()
+            .map(|(token, interest)| {
+                let state = match &interest.source {
+                    EventSource::Poll(PollSource::Fd(fd)) if self.is_event_queue(process, *fd) => {
+                        PollEvents::NONE
+                    }
+                    EventSource::Poll(source) => self.source_poll_ready(process, source),
+                    EventSource::Signals(mask) => PollEvents {
+                        readable: process.signals.has_pending_in(*mask),
+                        ..PollEvents::NONE
+                    },
+                    EventSource::Timer { .. } => PollEvents::NONE,
+                };
+                (token, state)
+            })
+            .collect()
     }
 
-    /// Open a device (paths starting with /dev/)
-    fn open_device(&mut self, path: &Path, _flags: OpenFlags) -> SyscallResult<Handle> {
-        let name = path
-            .file_name()
-            .and_then(|s| s.to_str())
-            .ok_or(SyscallError::NotFound)?;
+    /// epoll_create - make an event queue (see [`crate::kernel::epoll`])
+    pub fn sys_epoll_create(&mut self) -> SyscallResult<Fd> {
+        let handle = self
+            .objects
+            .insert(KernelObject::EventQueue(EventQueue::new()));
+        let process = self.get_current_process_mut()?;
+        process
+            .files
+            .alloc(handle)
+            .ok_or(SyscallError::TooManyOpenFiles)
+    }
 
-        match name {
-            "console" => Ok(self.console_handle),
-            "null" => {
-                // /dev/null - discard all writes, return EOF on read
-                Ok(self.create_file_object(path.to_path_buf(), Vec::new(), true, true))
-            }
-            "zero" => {
-                // /dev/zero - returns infinite zeros
-                Ok(self.create_file_object(path.to_path_buf(), vec![0; 4096], true, false))
-            }
-            "random" | "urandom" => {
-                // /dev/random and /dev/urandom - return cryptographically secure random bytes
-                // Uses Web Crypto API in WASM environments
-                let random_data = generate_random_bytes(4096);
-                Ok(self.create_file_object(path.to_path_buf(), random_data, true, false))
-            }
-            _ => Err(SyscallError::NotFound),
+    /// epoll_ctl - add, change or drop an interest of event queue `epfd`
+    ///
+    /// Adding a token already in use fails with `AlreadyExists`, and
+    /// changing or dropping one that isn't with `NotFound`. A watched
+    /// descriptor must be open and may not be an event queue itself;
+    /// timers need a delay of at least 0 and an interval above 0.
+    pub fn sys_epoll_ctl(&mut self, epfd: Fd, ctl: EpollCtl) -> SyscallResult<()> {
+        let handle = self.get_handle(epfd)?;
+        if let EpollCtl::Add(_, interest) | EpollCtl::Modify(_, interest) = &ctl {
+            match interest.source {
+                EventSource::Poll(PollSource::Fd(fd)) => {
+                    let process = self.get_current_process()?;
+                    if process.files.get(fd).is_none() {
+                        return Err(SyscallError::BadFd);
+                    }
+                    if self.is_event_queue(process, fd) {
+                        return Err(SyscallError::InvalidArgument);
+                    }
+                }
+                EventSource::Timer { delay, interval }
+                    if delay < 0.0
+                        || delay.is_nan()
+                        || interval.is_some_and(|i| i <= 0.0 || i.is_nan()) =>
+                {
+                    return Err(SyscallError::InvalidArgument);
+                }
+                _ => {}
+            }
+        }
+        let now = self.time.now;
+        let Some(KernelObject::EventQueue(queue)) = self.objects.get_mut(handle) else {
+            return Err(SyscallError::InvalidArgument);
+        };
+        match ctl {
+            EpollCtl::Add(token, interest) => queue
+                .add(token, interest, now)
+                .then_some(())
+                .ok_or(SyscallError::AlreadyExists)?,
+            EpollCtl::Modify(token, interest) => queue
+                .modify(token, interest, now)
+                .then_some(())
+                .ok_or(SyscallError::NotFound)?,
+            EpollCtl::Delete(token) => queue
+                .remove(token)
+                .then_some(())
+                .ok_or(SyscallError::NotFound)?,
         }
+        // Waiters may have a new source to watch or a new timer to wake for
+        self.wake_pollers();
+        Ok(())
     }
 
-    /// Open a regular file
+    /// epoll_wait - report up to `max` ready interests of event queue
+    /// `epfd`
     ///
-    /// Uses atomic permission checking (TOCTOU-safe): opens the file first,
-    /// then checks permissions on the actual opened file handle.
-    fn open_file(&mut self, path: &Path, flags: OpenFlags) -> SyscallResult<Handle> {
-        let path_str = path.to_str().ok_or(SyscallError::InvalidArgument)?;
-
-        // Check if file exists (needed to determine if we're creating a new file)
-        let file_exists = self.fs.vfs.exists(path_str);
+    /// Blocks like [`Self::sys_poll`]: with nothing ready this fails with
+    /// `WouldBlock` unless `timeout` (milliseconds) has run out, in which
+    /// case it returns no events, or with `Interrupted` when a signal is
+    /// waiting. Use [`epoll_wait`] to wait.
+    pub fn sys_epoll_wait(
+        &mut self,
+        epfd: Fd,
+        max: usize,
+        timeout: Option<f64>,
+    ) -> SyscallResult<Vec<EpollEvent>> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        self.epoll_wait_as(current, epfd, max, timeout)
+    }
 
-        // For new files, check parent directory permission first
-        // This must happen before open since the file doesn't exist yet
-        if !file_exists {
-            if flags.create {
-                self.check_parent_write_permission(path_str)?;
-            } else {
-                return Err(SyscallError::NotFound);
-            }
+    /// [`Self::sys_epoll_wait`] for process `pid`
+    fn epoll_wait_as(
+        &mut self,
+        pid: Pid,
+        epfd: Fd,
+        max: usize,
+        timeout: Option<f64>,
+    ) -> SyscallResult<Vec<EpollEvent>> {
+        if max == 0 {
+            return Err(SyscallError::InvalidArgument);
         }
-
-        // Convert our flags to VFS options
-        // Note: We always need read access in VFS to read existing content into FileObject,
-        // but the actual permissions are tracked separately in the FileObject
-        let vfs_opts = VfsOpenOptions {
-            read: true, // Always need to read existing content
-            write: flags.write,
-            create: flags.create,
-            truncate: flags.truncate,
+        let process = self
+            .proc
+            .processes
+            .get(&pid)
+            .ok_or(SyscallError::NoProcess)?;
+        let handle = process.files.get(epfd).ok_or(SyscallError::BadFd)?;
+        let Some(KernelObject::EventQueue(queue)) = self.objects.get(handle) else {
+            return Err(SyscallError::InvalidArgument);
         };
+        let states = self.queue_states(process, queue);
+        let interrupted = process.signals.interrupting().is_some();
+        let now = self.time.now;
+        let Some(KernelObject::EventQueue(queue)) = self.objects.get_mut(handle) else {
+            return Err(SyscallError::InvalidArgument);
+        };
+        let events = queue.collect(&states, now, max);
 
-        // Open via VFS first (before permission check for TOCTOU safety)
-        let vfs_handle = self.fs.vfs.open(path_str, vfs_opts)?;
-
-        // For existing files, check permissions AFTER opening (TOCTOU-safe)
-        // This uses fstat on the opened handle, not the path that could have changed
-        if file_exists
-            && self
-                .check_handle_permission(vfs_handle, flags.read, flags.write, false)
-                .is_err()
-        {
-            // Permission denied - close the handle and return error
-            let _ = self.fs.vfs.close(vfs_handle);
-            return Err(SyscallError::PermissionDenied);
+        if !events.is_empty() || timeout.is_some_and(|t| t <= 0.0) {
+            Ok(events)
+        } else if interrupted {
+            Err(SyscallError::Interrupted)
+        } else {
+            Err(SyscallError::WouldBlock)
         }
+    }
 
-        // If we just created a new file, set ownership and apply umask
-        if !file_exists && flags.create {
-            let euid = self.current_euid()?;
-            let egid = self.current_egid()?;
-            let _ = self.fs.vfs.chown(path_str, Some(euid.0), Some(egid.0));
-
-            // SEC-014: Apply umask to new file mode
-            // Default file mode is 0o666, apply umask to get final mode
-            let umask = self.get_current_process()?.umask;
-            let new_mode = 0o666 & !umask;
-            let _ = self.fs.vfs.chmod(path_str, new_mode);
+    /// When the first timer of event queue `epfd` of process `pid` expires
+    fn epoll_next_deadline(&self, pid: Pid, epfd: Fd) -> Option<f64> {
+        let handle = self.proc.processes.get(&pid)?.files.get(epfd)?;
+        match self.objects.get(handle) {
+            Some(KernelObject::EventQueue(queue)) => queue.next_deadline(),
+            _ => None,
         }
+    }
 
-        // Read the file contents (using fstat for consistency with atomic open)
-        let meta = self.fs.vfs.fstat(vfs_handle)?;
-        let file_size = usize::try_from(meta.size).map_err(|_| SyscallError::TooBig)?;
-        let mut data = vec![0u8; file_size];
-        if !data.is_empty() {
-            self.fs.vfs.read(vfs_handle, &mut data)?;
+    /// Drop the current process's locks on `path` once it has no
+    /// descriptor left for it
+    fn release_closed_locks(&mut self, path: &str) {
+        if self.ipc.file_locks.is_empty() {
+            return;
         }
-
-        // For append mode, seek to end
-        if flags.append {
-            self.fs.vfs.seek(vfs_handle, SeekFrom::End(0))?;
-        } else {
-            // Seek back to start
-            self.fs.vfs.seek(vfs_handle, SeekFrom::Start(0))?;
+        let Ok(process) = self.get_current_process() else {
+            return;
+        };
+        let pid = process.pid;
+        let still_open = process.files.iter().any(|(_, h)| {
+            matches!(self.objects.get(h), Some(KernelObject::File(f)) if f.path.to_string_lossy() == path)
+        });
+        if !still_open {
+            self.ipc.file_locks.release_file(path, pid);
         }
+    }
 
-        // Create a FileObject that mirrors the VFS file
-        // For append mode, position at end
-        let mut file = FileObject::new(path.to_path_buf(), data, flags.read, flags.write);
-        if flags.append {
-            file.position = file.data.len() as u64;
-        }
+    /// Release an exiting process's locks, undo its SEM_UNDO operations
+    /// and drop it from the wait-for graph
+    fn release_ipc(&mut self, pid: Pid) {
+        self.ipc.file_locks.release_all(pid);
+        self.ipc.semaphores.undo_all(pid.0, self.time.now);
+        self.ipc.waits.remove(pid);
+    }
 
-        let handle = self.objects.insert(KernelObject::File(file));
+    /// Seek within a file
+    pub fn sys_seek(&mut self, fd: Fd, pos: SeekFrom) -> SyscallResult<u64> {
+        let handle = self.get_handle(fd)?;
+        let obj = self.objects.get_mut(handle).ok_or(SyscallError::BadFd)?;
+        Ok(obj.seek(pos)?)
+    }
 
-        // Track the VFS handle for sync/close
-        self.fs.vfs_handles.insert(handle, vfs_handle);
+    /// Read from a file at `offset`, leaving the descriptor's position
+    /// where it was
+    ///
+    /// Otherwise like [`Kernel::sys_read`], locks and permissions included.
+    /// Descriptors that can't seek (pipes, devices, sockets) fail with
+    /// `InvalidArgument`.
+    pub fn sys_pread(&mut self, fd: Fd, buf: &mut [u8], offset: u64) -> SyscallResult<usize> {
+        self.at_offset(fd, offset, |k| k.sys_read(fd, buf))
+    }
 
-        Ok(handle)
+    /// Write to a file at `offset`, leaving the descriptor's position
+    /// where it was
+    ///
+    /// Otherwise like [`Kernel::sys_write`]: writing past the end fills
+    /// the gap with zeros. Descriptors that can't seek fail with
+    /// `InvalidArgument`, and writes that would end past
+    /// [`FileObject::MAX_SIZE`] with `TooBig`.
+    pub fn sys_pwrite(&mut self, fd: Fd, buf: &[u8], offset: u64) -> SyscallResult<usize> {
+        if offset
+            .checked_add(buf.len() as u64)
+            .is_none_or(|end| end > FileObject::MAX_SIZE)
+        {
+            return Err(SyscallError::TooBig);
+        }
+        self.at_offset(fd, offset, |k| k.sys_write(fd, buf))
     }
 
-    /// Sync a file back to the VFS (on close or explicit sync)
-    fn sync_file(&mut self, handle: Handle) -> SyscallResult<()> {
-        let vfs_handle = self.fs.vfs_handles.get(&handle).copied();
+    /// Run `op` with a file descriptor moved to `offset`, then put its
+    /// position back
+    fn at_offset<T>(
+        &mut self,
+        fd: Fd,
+        offset: u64,
+        op: impl FnOnce(&mut Self) -> SyscallResult<T>,
+    ) -> SyscallResult<T> {
+        let handle = self.get_handle(fd)?;
+        let Some(KernelObject::File(file)) = self.objects.get_mut(handle) else {
+            return Err(SyscallError::InvalidArgument);
+        };
+        let position = std::mem::replace(&mut file.position, offset);
+        let result = op(self);
+        if let Some(KernelObject::File(file)) = self.objects.get_mut(handle) {
+            file.position = position;
+        }
+        result
+    }
 
-        if let Some(vh) = vfs_handle {
-            // Get the file data
-            if let Some(KernelObject::File(file)) = self.objects.get(handle) {
-                let data = file.data.clone();
-                let path = file.path.clone();
-
-                // Write back to VFS
-                self.fs.vfs.seek(vh, SeekFrom::Start(0))?;
-
-                // Truncate and write
-                let path_str = path.to_str().ok_or(SyscallError::InvalidArgument)?;
-
-                // Close old handle and reopen with truncate
-                let _ = self.fs.vfs.close(vh);
-                let new_vh = self.fs.vfs.open(
-                    path_str,
-                    VfsOpenOptions {
-                        read: false,
-                        write: true,
-                        create: true,
-                        truncate: true,
-                    },
-                )?;
-                self.fs.vfs.write(new_vh, &data)?;
-                self.fs.vfs_handles.insert(handle, new_vh);
-            }
+    /// Flush a file's writes to the VFS and ask for the filesystem to be
+    /// saved
+    ///
+    /// The save itself is asynchronous: it is written at the next
+    /// scheduler tick, whatever the auto-save settings and the power
+    /// governor say (see [`Kernel::take_persist_request`]). Descriptors
+    /// that aren't files (pipes, devices) fail with `InvalidArgument`.
+    pub fn sys_fsync(&mut self, fd: Fd) -> SyscallResult<()> {
+        let handle = self.get_handle(fd)?;
+        if !matches!(self.objects.get(handle), Some(KernelObject::File(_))) {
+            return Err(SyscallError::InvalidArgument);
         }
+        self.sync_file(handle)?;
+        self.fs.persist_requested = true;
         Ok(())
     }
 
-    /// Create a directory
-    pub fn sys_mkdir(&mut self, path: &str) -> SyscallResult<()> {
-        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        let resolved = self.resolve_path(current, path)?;
-        let path_str = resolved.to_str().ok_or(SyscallError::InvalidArgument)?;
+    /// Like [`Kernel::sys_fsync`]: metadata is saved with the contents
+    /// anyway, so there is nothing to leave out
+    pub fn sys_fdatasync(&mut self, fd: Fd) -> SyscallResult<()> {
+        self.sys_fsync(fd)
+    }
 
-        // Check write/execute permission on parent directory
-        self.check_parent_write_permission(path_str)?;
+    /// Whether an `fsync` asked for a save since the last call
+    pub fn take_persist_request(&mut self) -> bool {
+        std::mem::take(&mut self.fs.persist_requested)
+    }
 
-        self.fs.vfs.create_dir(path_str)?;
+    /// Create a pipe (returns read_fd, write_fd)
+    pub fn sys_pipe(&mut self) -> SyscallResult<(Fd, Fd)> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
 
-        // Set ownership to current user
-        let euid = self.current_euid()?;
-        let egid = self.current_egid()?;
-        let _ = self.fs.vfs.chown(path_str, Some(euid.0), Some(egid.0));
+        // Create pipe object
+        let pipe = PipeObject::new(4096);
+        let handle = self.objects.insert(KernelObject::Pipe(pipe));
 
-        // SEC-014: Apply umask to new directory mode
-        // Default directory mode is 0o777, apply umask to get final mode
-        let umask = self.get_current_process()?.umask;
-        let new_mode = 0o777 & !umask;
-        let _ = self.fs.vfs.chmod(path_str, new_mode);
+        // Allocate two fds pointing to same pipe
+        // (In a real OS these would be separate read/write ends)
+        let process = self
+            .proc
+            .processes
+            .get_mut(&current)
+            .ok_or(SyscallError::NoProcess)?;
+        let read_fd = process
+            .files
+            .alloc(handle)
+            .ok_or(SyscallError::TooManyOpenFiles)?;
+        let write_fd = match process.files.alloc(handle) {
+            Some(fd) => fd,
+            None => {
+                // Clean up the read fd if we can't allocate write fd
+                process.files.remove(read_fd);
+                return Err(SyscallError::TooManyOpenFiles);
+            }
+        };
 
-        Ok(())
+        Ok((read_fd, write_fd))
     }
 
-    /// List directory contents
-    pub fn sys_readdir(&mut self, path: &str) -> SyscallResult<Vec<String>> {
+    /// Create a window (returns fd for the window)
+    pub fn sys_window_create(&mut self, _title: &str) -> SyscallResult<Fd> {
         let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        let resolved = self.resolve_path(current, path)?;
-        let path_str = resolved.to_str().ok_or(SyscallError::InvalidArgument)?;
-
-        // Handle /proc directory listings (always readable)
-        if ProcFs::is_proc_path(path_str) {
-            let pids: Vec<u32> = self.proc.processes.keys().map(|p| p.0).collect();
-            if let Some(entries) = self.fs.procfs.list_dir(path_str, &pids) {
-                return Ok(entries);
-            }
-            return Err(SyscallError::NotFound);
-        }
 
-        // Handle /dev directory listings (always readable)
-        if DevFs::is_dev_path(path_str) {
-            if let Some(entries) = self.fs.devfs.list_dir(path_str) {
-                return Ok(entries);
-            }
-            return Err(SyscallError::NotFound);
-        }
+        // Generate a unique window ID for this window object
+        static NEXT_WINDOW_ID: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(1);
+        let window_id = WindowId(NEXT_WINDOW_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed));
 
-        // Handle /sys directory listings (always readable)
-        if SysFs::is_sys_path(path_str) {
-            if let Some(entries) = self.fs.sysfs.list_dir(path_str) {
-                return Ok(entries);
-            }
-            return Err(SyscallError::NotFound);
-        }
+        let window = WindowObject::new(window_id);
+        let handle = self.objects.insert(KernelObject::Window(window));
 
-        // Check read and execute permission on directory
-        self.check_file_permission(path_str, true, false, true)?;
+        let process = self
+            .proc
+            .processes
+            .get_mut(&current)
+            .ok_or(SyscallError::NoProcess)?;
+        let fd = process
+            .files
+            .alloc(handle)
+            .ok_or(SyscallError::TooManyOpenFiles)?;
 
-        let entries = self.fs.vfs.read_dir(path_str)?;
-        Ok(entries.into_iter().map(|e| e.name).collect())
+        Ok(fd)
     }
 
-    /// Check if a path exists
-    pub fn sys_exists(&self, path: &str) -> SyscallResult<bool> {
-        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        let resolved = self.resolve_path(current, path)?;
-        let path_str = resolved.to_str().ok_or(SyscallError::InvalidArgument)?;
+    /// Duplicate a file descriptor
+    pub fn sys_dup(&mut self, fd: Fd) -> SyscallResult<Fd> {
+        // Get the handle for the existing fd (using scoped borrow)
+        let handle = {
+            let process = self.get_current_process()?;
+            process.files.get(fd).ok_or(SyscallError::BadFd)?
+        };
 
-        // Handle /proc paths
-        if ProcFs::is_proc_path(path_str) {
-            let pids: Vec<u32> = self.proc.processes.keys().map(|p| p.0).collect();
-            return Ok(self.fs.procfs.exists(path_str, &pids));
+        // Retain the object (increment refcount)
+        if !self.objects.retain(handle) {
+            return Err(SyscallError::BadFd);
         }
 
-        // Handle /dev paths
-        if DevFs::is_dev_path(path_str) {
-            return Ok(self.fs.devfs.exists(path_str));
-        }
+        // Allocate a new fd pointing to the same handle
+        let process = self.get_current_process_mut()?;
+        let new_fd = process
+            .files
+            .alloc(handle)
+            .ok_or(SyscallError::TooManyOpenFiles)?;
+        Ok(new_fd)
+    }
 
-        // Handle /sys paths
-        if SysFs::is_sys_path(path_str) {
-            return Ok(self.fs.sysfs.exists(path_str));
+    /// fcntl - get or set the flags of `fd`
+    ///
+    /// `F_GETFD`/`F_SETFD` read and write its [`FdFlags`] (`FD_CLOEXEC`);
+    /// `F_GETFL`/`F_SETFL` the status flags of the object it refers to,
+    /// of which only `O_NONBLOCK` is kept. Status flags are shared with
+    /// every descriptor for the object, as dup and fork leave them. Gets
+    /// return the flags; sets return 0.
+    pub fn sys_fcntl(&mut self, fd: Fd, cmd: i32, arg: u32) -> SyscallResult<u32> {
+        let handle = self.get_handle(fd)?;
+        match cmd {
+            F_GETFD => {
+                let process = self.get_current_process()?;
+                Ok(process.files.get_flags(fd).unwrap_or_default().to_bits())
+            }
+            F_SETFD => {
+                let process = self.get_current_process_mut()?;
+                process.files.set_flags(fd, FdFlags::from_bits(arg));
+                Ok(0)
+            }
+            F_GETFL => Ok(if self.objects.nonblocking(handle) {
+                O_NONBLOCK
+            } else {
+                0
+            }),
+            F_SETFL => {
+                self.objects.set_nonblocking(handle, arg & O_NONBLOCK != 0);
+                Ok(0)
+            }
+            _ => Err(SyscallError::InvalidArgument),
         }
+    }
 
-        Ok(self.fs.vfs.exists(path_str))
+    /// Get current working directory
+    pub fn sys_getcwd(&self) -> SyscallResult<PathBuf> {
+        let process = self.get_current_process()?;
+        Ok(process.cwd.clone())
     }
 
-    /// Get file/directory metadata
-    pub fn sys_metadata(&self, path: &str) -> SyscallResult<FileMetadata> {
+    /// Change working directory
+    pub fn sys_chdir(&mut self, path: &str) -> SyscallResult<()> {
         let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
         let resolved = self.resolve_path(current, path)?;
-        let path_str = resolved.to_str().ok_or(SyscallError::InvalidArgument)?;
 
-        // Handle /proc paths
-        if ProcFs::is_proc_path(path_str) {
-            let pids: Vec<u32> = self.proc.processes.keys().map(|p| p.0).collect();
-            if !self.fs.procfs.exists(path_str, &pids) {
-                return Err(SyscallError::NotFound);
-            }
-            let is_dir = self.fs.procfs.is_dir(path_str, &pids);
-            return Ok(FileMetadata {
-                size: 0, // /proc files have dynamic size
-                is_dir,
-                is_file: !is_dir,
-                is_symlink: false,
-                symlink_target: None,
-                uid: 0, // root owns /proc
-                gid: 0,
-                mode: if is_dir { 0o555 } else { 0o444 }, // read-only
-            });
+        // Verify path exists and is a directory
+        let path_str = resolved.to_str().ok_or(SyscallError::InvalidArgument)?;
+        let (fs, inner) = self.fs.route(path_str);
+        let meta = fs.metadata(&inner)?;
+        if !meta.is_dir {
+            return Err(SyscallError::NotADirectory);
         }
 
-        // Handle /dev paths
-        if DevFs::is_dev_path(path_str) {
-            if !self.fs.devfs.exists(path_str) {
-                return Err(SyscallError::NotFound);
-            }
-            let is_dir = self.fs.devfs.is_dir(path_str);
-            return Ok(FileMetadata {
-                size: 0,
-                is_dir,
-                is_file: !is_dir,
-                is_symlink: false,
-                symlink_target: None,
-                uid: 0, // root owns /dev
-                gid: 0,
-                mode: if is_dir { 0o755 } else { 0o666 }, // device files are rw for all
-            });
-        }
-
-        // Handle /sys paths
-        if SysFs::is_sys_path(path_str) {
-            if !self.fs.sysfs.exists(path_str) {
-                return Err(SyscallError::NotFound);
-            }
-            let is_dir = self.fs.sysfs.is_dir(path_str);
-            return Ok(FileMetadata {
-                size: 0,
-                is_dir,
-                is_file: !is_dir,
-                is_symlink: false,
-                symlink_target: None,
-                uid: 0, // root owns /sys
-                gid: 0,
-                mode: if is_dir { 0o555 } else { 0o444 }, // read-only
-            });
-        }
-
-        let meta = self.fs.vfs.metadata(path_str)?;
-        Ok(FileMetadata {
-            size: meta.size,
-            is_dir: meta.is_dir,
-            is_file: meta.is_file,
-            is_symlink: meta.is_symlink,
-            symlink_target: meta.symlink_target,
-            uid: meta.uid,
-            gid: meta.gid,
-            mode: meta.mode,
-        })
-    }
-
-    /// Remove a file
-    pub fn sys_remove_file(&mut self, path: &str) -> SyscallResult<()> {
-        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        let resolved = self.resolve_path(current, path)?;
-        let path_str = resolved.to_str().ok_or(SyscallError::InvalidArgument)?;
-
-        // Check write/execute permission on parent directory
-        self.check_parent_write_permission(path_str)?;
-
-        // SEC-015: Check sticky bit restriction
-        self.check_sticky_bit(path_str)?;
+        // Check execute permission on directory (required to cd into it)
+        self.check_file_permission(path_str, false, false, true)?;
 
-        self.fs.vfs.remove_file(path_str)?;
+        let process = self
+            .proc
+            .processes
+            .get_mut(&current)
+            .ok_or(SyscallError::NoProcess)?;
+        process.cwd = resolved;
         Ok(())
     }
 
-    /// Remove a directory (must be empty)
-    pub fn sys_remove_dir(&mut self, path: &str) -> SyscallResult<()> {
-        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        let resolved = self.resolve_path(current, path)?;
-        let path_str = resolved.to_str().ok_or(SyscallError::InvalidArgument)?;
-
-        // Check write/execute permission on parent directory
-        self.check_parent_write_permission(path_str)?;
-
-        // SEC-015: Check sticky bit restriction
-        self.check_sticky_bit(path_str)?;
-
-        self.fs.vfs.remove_dir(path_str)?;
+    /// Exit the current process
+    pub fn sys_exit(&mut self, code: i32) -> SyscallResult<()> {
+        let process = self.get_current_process_mut()?;
+        process.state = ProcessState::Zombie(code);
+        let pid = process.pid;
+        self.ptrace.on_exit(pid, code);
+        self.release_ipc(pid);
         Ok(())
     }
 
-    /// Rename/move a file or directory
-    pub fn sys_rename(&mut self, from: &str, to: &str) -> SyscallResult<()> {
-        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        let from_resolved = self.resolve_path(current, from)?;
-        let to_resolved = self.resolve_path(current, to)?;
-        let from_str = from_resolved
-            .to_str()
-            .ok_or(SyscallError::InvalidArgument)?;
-        let to_str = to_resolved.to_str().ok_or(SyscallError::InvalidArgument)?;
-
-        // Check write/execute permission on both source and destination parent directories
-        self.check_parent_write_permission(from_str)?;
-        self.check_parent_write_permission(to_str)?;
-
-        self.fs.vfs.rename(from_str, to_str)?;
-        Ok(())
+    /// Get current process ID
+    pub fn sys_getpid(&self) -> SyscallResult<Pid> {
+        self.proc.current.ok_or(SyscallError::NoProcess)
     }
 
-    /// Copy a file
-    pub fn sys_copy_file(&mut self, from: &str, to: &str) -> SyscallResult<u64> {
-        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        let from_resolved = self.resolve_path(current, from)?;
-        let to_resolved = self.resolve_path(current, to)?;
-        let from_str = from_resolved
-            .to_str()
-            .ok_or(SyscallError::InvalidArgument)?;
-        let to_str = to_resolved.to_str().ok_or(SyscallError::InvalidArgument)?;
-
-        // Check read permission on source file
-        self.check_file_permission(from_str, true, false, false)?;
-        // Check write permission on destination parent directory
-        self.check_parent_write_permission(to_str)?;
-
-        let size = self.fs.vfs.copy_file(from_str, to_str)?;
-
-        // Set ownership of new file to current user
-        let euid = self.current_euid()?;
-        let egid = self.current_egid()?;
-        let _ = self.fs.vfs.chown(to_str, Some(euid.0), Some(egid.0));
-
-        Ok(size)
+    /// Get parent process ID
+    pub fn sys_getppid(&self) -> SyscallResult<Option<Pid>> {
+        let process = self.get_current_process()?;
+        Ok(process.parent)
     }
 
-    /// Create a symbolic link
-    pub fn sys_symlink(&mut self, target: &str, link_path: &str) -> SyscallResult<()> {
-        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        let link_resolved = self.resolve_path(current, link_path)?;
-        let link_str = link_resolved
-            .to_str()
-            .ok_or(SyscallError::InvalidArgument)?;
-
-        // Check write/execute permission on parent directory
-        self.check_parent_write_permission(link_str)?;
-
-        // Target is stored as-is (can be relative or absolute)
-        self.fs.vfs.symlink(target, link_str)?;
+    // ========== ENVIRONMENT SYSCALLS ==========
+    // Like Linux: each process has its own environment (inherited on spawn)
 
-        // Set ownership of symlink to current user
-        let euid = self.current_euid()?;
-        let egid = self.current_egid()?;
-        let _ = self.fs.vfs.chown(link_str, Some(euid.0), Some(egid.0));
+    /// Get an environment variable
+    pub fn sys_getenv(&self, name: &str) -> SyscallResult<Option<String>> {
+        let process = self.get_current_process()?;
+        Ok(process.getenv(name).map(|s| s.to_string()))
+    }
 
+    /// Set an environment variable
+    pub fn sys_setenv(&mut self, name: &str, value: &str) -> SyscallResult<()> {
+        if name.is_empty() || name.contains('=') {
+            return Err(SyscallError::InvalidArgument);
+        }
+        let process = self.get_current_process_mut()?;
+        process.setenv(name, value);
         Ok(())
     }
 
-    /// Read the target of a symbolic link
-    pub fn sys_read_link(&self, path: &str) -> SyscallResult<String> {
-        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        let resolved = self.resolve_path(current, path)?;
-        let path_str = resolved.to_str().ok_or(SyscallError::InvalidArgument)?;
-        let target = self.fs.vfs.read_link(path_str)?;
-        Ok(target)
+    /// Remove an environment variable
+    pub fn sys_unsetenv(&mut self, name: &str) -> SyscallResult<bool> {
+        let process = self.get_current_process_mut()?;
+        Ok(process.unsetenv(name))
     }
 
-    // ========== MEMORY SYSCALLS ==========
+    /// Get all environment variables
+    pub fn sys_environ(&self) -> SyscallResult<Vec<(String, String)>> {
+        let process = self.get_current_process()?;
+        Ok(process
+            .environ()
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect())
+    }
 
-    /// Allocate a memory region for the current process
-    pub fn sys_alloc(&mut self, size: usize, prot: Protection) -> SyscallResult<RegionId> {
+    // ========== WAITPID SYSCALL ==========
+    // Like Linux waitpid: wait for child process state changes
+
+    /// Wait for a child process to change state
+    ///
+    /// - pid > 0: wait for specific child
+    /// - pid = -1: wait for any child
+    /// - pid = 0: wait for any child in same process group
+    /// - pid < -1: wait for any child in process group |pid|
+    pub fn sys_waitpid(&mut self, pid: i32, flags: WaitFlags) -> SyscallResult<(Pid, WaitStatus)> {
         let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        let process = self
-            .proc
-            .processes
-            .get_mut(&current)
-            .ok_or(SyscallError::NoProcess)?;
 
-        let region_id = self.memory.alloc_region_id();
-        process.memory.allocate(region_id, size, prot)?;
+        // Find matching children
+        let children: Vec<Pid> = {
+            let parent = self
+                .proc
+                .processes
+                .get(&current)
+                .ok_or(SyscallError::NoProcess)?;
+            let pgid = parent.pgid;
 
-        Ok(region_id)
-    }
+            parent
+                .children
+                .iter()
+                .filter(|&child_pid| {
+                    if pid > 0 {
+                        // Wait for specific child
+                        // Safe: pid > 0 guarantees positive value fits in u32
+                        u32::try_from(pid)
+                            .map(|p| child_pid.0 == p)
+                            .unwrap_or(false)
+                    } else if pid == -1 {
+                        // Wait for any child
+                        true
+                    } else if pid == 0 {
+                        // Wait for any child in same process group
+                        self.proc
+                            .processes
+                            .get(child_pid)
+                            .map(|p| p.pgid == pgid)
+                            .unwrap_or(false)
+                    } else {
+                        // Wait for any child in specified process group
+                        // Safe: pid < -1 means -pid > 1, use checked negation for i32::MIN
+                        let target_pgid = pid
+                            .checked_neg()
+                            .and_then(|n| u32::try_from(n).ok())
+                            .map(Pgid);
+                        target_pgid
+                            .and_then(|tpg| {
+                                self.proc.processes.get(child_pid).map(|p| p.pgid == tpg)
+                            })
+                            .unwrap_or(false)
+                    }
+                })
+                .copied()
+                .collect()
+        };
 
-    /// Free a memory region
-    pub fn sys_free(&mut self, region_id: RegionId) -> SyscallResult<()> {
-        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        let process = self
-            .proc
-            .processes
-            .get_mut(&current)
-            .ok_or(SyscallError::NoProcess)?;
+        if children.is_empty() {
+            return Err(SyscallError::NoProcess);
+        }
 
-        process.memory.free(region_id)?;
-        Ok(())
-    }
+        // Look for a child that has changed state
+        for child_pid in children {
+            // First check state without mutable borrow
+            let state_info = self
+                .proc
+                .processes
+                .get(&child_pid)
+                .map(|child| (child.state.clone(), child.was_continued));
 
-    /// Read from a memory region
-    pub fn sys_mem_read(
-        &mut self,
-        region_id: RegionId,
-        offset: usize,
-        buf: &mut [u8],
-    ) -> SyscallResult<usize> {
-        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        let process = self
-            .proc
-            .processes
-            .get_mut(&current)
-            .ok_or(SyscallError::NoProcess)?;
-
-        let region = process
-            .memory
-            .get(region_id)
-            .ok_or(SyscallError::Memory(MemoryError::InvalidRegion))?;
+            if let Some((state, was_continued)) = state_info {
+                match state {
+                    ProcessState::Zombie(exit_code) => {
+                        let status = WaitStatus::Exited(exit_code);
+                        // Reap the zombie
+                        self.proc.processes.remove(&child_pid);
+                        self.proc.elevated.remove(&child_pid);
+                        // Remove from parent's children list
+                        if let Some(parent) = self.proc.processes.get_mut(&current) {
+                            parent.children.retain(|&p| p != child_pid);
+                        }
+                        return Ok((child_pid, status));
+                    }
+                    ProcessState::Stopped if flags.untraced => {
+                        return Ok((child_pid, WaitStatus::Stopped));
+                    }
+                    ProcessState::Running if flags.continued && was_continued => {
+                        // Child was recently continued - clear flag and report
+                        if let Some(child) = self.proc.processes.get_mut(&child_pid) {
+                            child.was_continued = false;
+                        }
+                        return Ok((child_pid, WaitStatus::Continued));
+                    }
+                    _ => {}
+                }
+            }
+        }
 
-        Ok(region.read(offset, buf)?)
+        // No child ready
+        if flags.nohang {
+            Ok((Pid(0), WaitStatus::NoChild))
+        } else if self.interrupting_signal().is_some() {
+            Err(SyscallError::Interrupted)
+        } else {
+            // In a real OS we'd block here
+            // For now, return no child (caller should retry)
+            Err(SyscallError::WouldBlock)
+        }
     }
 
-    /// Write to a memory region
-    pub fn sys_mem_write(
-        &mut self,
-        region_id: RegionId,
-        offset: usize,
-        buf: &[u8],
-    ) -> SyscallResult<usize> {
-        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+    // ========== PROCESS GROUP SYSCALLS ==========
+    // Like Linux: process groups for job control (fg/bg)
+
+    /// Get process group ID
+    pub fn sys_getpgid(&self, pid: Pid) -> SyscallResult<Pgid> {
         let process = self
             .proc
             .processes
-            .get_mut(&current)
+            .get(&pid)
             .ok_or(SyscallError::NoProcess)?;
-
-        let region = process
-            .memory
-            .get_mut(region_id)
-            .ok_or(SyscallError::Memory(MemoryError::InvalidRegion))?;
-
-        Ok(region.write(offset, buf)?)
-    }
-
-    /// Create a shared memory segment
-    pub fn sys_shmget(&mut self, size: usize) -> SyscallResult<ShmId> {
-        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        Ok(self.memory.shmget(size, current)?)
+        Ok(process.pgid)
     }
 
-    /// Attach to a shared memory segment
-    pub fn sys_shmat(&mut self, shm_id: ShmId, prot: Protection) -> SyscallResult<RegionId> {
+    /// Set process group ID
+    pub fn sys_setpgid(&mut self, pid: Pid, pgid: Pgid) -> SyscallResult<()> {
         let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
 
-        // Get the shared memory region from the manager
-        let region = self.memory.shmat(shm_id, current, prot)?;
-        let region_id = region.id;
+        // Can only setpgid on self or children
+        if pid != current {
+            let parent = self
+                .proc
+                .processes
+                .get(&current)
+                .ok_or(SyscallError::NoProcess)?;
+            if !parent.children.contains(&pid) {
+                return Err(SyscallError::PermissionDenied);
+            }
+        }
 
-        // Attach to the process memory
         let process = self
             .proc
             .processes
-            .get_mut(&current)
+            .get_mut(&pid)
             .ok_or(SyscallError::NoProcess)?;
-        process.memory.attach_shm(shm_id, region)?;
-
-        Ok(region_id)
+        process.pgid = pgid;
+        Ok(())
     }
 
-    /// Detach from a shared memory segment
-    pub fn sys_shmdt(&mut self, shm_id: ShmId) -> SyscallResult<()> {
-        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+    // ========== IOCTL SYSCALL ==========
+    // Like Linux ioctl: generic device control interface
 
-        // Sync changes back to shared memory before detaching
-        let process = self
-            .proc
-            .processes
-            .get_mut(&current)
-            .ok_or(SyscallError::NoProcess)?;
+    /// Perform device-specific control operation
+    pub fn sys_ioctl(&mut self, fd: Fd, request: IoctlRequest) -> SyscallResult<IoctlResult> {
+        let handle = self.get_handle(fd)?;
+        let obj = self.objects.get_mut(handle).ok_or(SyscallError::BadFd)?;
 
-        // Get the region data before detaching
-        if let Some(region_id) = process.memory.shm_region(shm_id)
-            && let Some(region) = process.memory.get(region_id)
-        {
-            let data = region.as_slice().to_vec();
-            self.memory.shm_sync(shm_id, &data)?;
+        match (obj, request) {
+            (KernelObject::Device(dev), request) => {
+                let ops = Rc::clone(&dev.ops);
+                ops.ioctl(self, request)
+            }
+            (KernelObject::Console(_), IoctlRequest::GetWinSize) => {
+                // Return current terminal size (from terminal module)
+                // For now, return a reasonable default
+                Ok(IoctlResult::WinSize(WinSize {
+                    rows: 24,
+                    cols: 80,
+                    xpixel: 0,
+                    ypixel: 0,
+                }))
+            }
+            (KernelObject::Console(_), IoctlRequest::SetWinSize) => {
+                // Setting window size from ioctl not supported in WASM
+                Err(SyscallError::InvalidArgument)
+            }
+            (KernelObject::Console(console), IoctlRequest::TcFlush) => {
+                console.clear_input();
+                Ok(IoctlResult::Ok)
+            }
+            _ => Err(SyscallError::InvalidArgument),
         }
+    }
 
-        // Detach from process memory
-        process.memory.detach_shm(shm_id)?;
-
-        // Detach from global shared memory (may remove if refcount hits 0)
-        self.memory.shmdt(shm_id, current)?;
+    // ========== HELPERS ==========
 
-        Ok(())
+    /// Get a handle from the current process's file table
+    fn get_handle(&self, fd: Fd) -> SyscallResult<Handle> {
+        let process = self.get_current_process()?;
+        process.files.get(fd).ok_or(SyscallError::BadFd)
     }
 
-    /// Sync shared memory region (write local changes to shared segment)
-    pub fn sys_shm_sync(&mut self, shm_id: ShmId) -> SyscallResult<()> {
-        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+    /// Resolve a path relative to a process's cwd
+    fn resolve_path(&self, pid: Pid, path: &str) -> SyscallResult<PathBuf> {
         let process = self
             .proc
             .processes
-            .get(&current)
+            .get(&pid)
             .ok_or(SyscallError::NoProcess)?;
 
-        // Get the region data
-        let region_id = process
-            .memory
-            .shm_region(shm_id)
-            .ok_or(SyscallError::Memory(MemoryError::NotAttached))?;
+        // Use jail-aware path resolution
+        // This handles both regular paths and chrooted processes
+        process
+            .resolve_jailed_path(Path::new(path))
+            .map_err(|_| SyscallError::PermissionDenied)
+    }
 
-        let region = process
-            .memory
-            .get(region_id)
-            .ok_or(SyscallError::Memory(MemoryError::InvalidRegion))?;
+    /// Check if the current process has permission to access a file
+    /// Returns Ok(()) if allowed, Err(PermissionDenied) if not
+    fn check_file_permission(
+        &self,
+        path: &str,
+        want_read: bool,
+        want_write: bool,
+        want_exec: bool,
+    ) -> SyscallResult<()> {
+        let process = self.get_current_process()?;
 
-        let data = region.as_slice().to_vec();
-        self.memory.shm_sync(shm_id, &data)?;
+        if want_write {
+            self.check_writable_mount(path)?;
+        }
 
-        Ok(())
-    }
+        // Get file metadata
+        let (fs, inner) = self.fs.route(path);
+        let meta = fs.metadata(&inner)?;
 
-    /// Refresh local shared memory region from shared segment
-    pub fn sys_shm_refresh(&mut self, shm_id: ShmId) -> SyscallResult<()> {
-        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        // Check permission
+        let allowed = may_access(process, &meta, want_read, want_write, want_exec);
 
-        // Get the latest shared data
-        let data = self.memory.shm_read(shm_id)?.to_vec();
+        if allowed {
+            Ok(())
+        } else {
+            Err(SyscallError::PermissionDenied)
+        }
+    }
 
-        // Update local region
-        let process = self
-            .proc
-            .processes
-            .get_mut(&current)
-            .ok_or(SyscallError::NoProcess)?;
-        let region_id = process
-            .memory
-            .shm_region(shm_id)
-            .ok_or(SyscallError::Memory(MemoryError::NotAttached))?;
+    /// Check permission on an already-opened file handle (atomic, TOCTOU-safe)
+    ///
+    /// This is preferred over check_file_permission when the file is already open,
+    /// as it checks permissions on the actual opened file, not a path that could
+    /// have changed between check and use.
+    fn check_handle_permission(
+        &self,
+        path: &str,
+        vfs_handle: VfsFileHandle,
+        want_read: bool,
+        want_write: bool,
+        want_exec: bool,
+    ) -> SyscallResult<()> {
+        let process = self.get_current_process()?;
 
-        let region = process
-            .memory
-            .get_mut(region_id)
-            .ok_or(SyscallError::Memory(MemoryError::InvalidRegion))?;
+        // Get metadata from the opened file handle (not the path)
+        let (fs, _) = self.fs.route(path);
+        let meta = fs.fstat(vfs_handle)?;
 
-        region.write(0, &data)?;
-        Ok(())
-    }
+        // Check permission
+        let allowed = may_access(process, &meta, want_read, want_write, want_exec);
 
-    /// Get shared memory info
-    pub fn sys_shm_info(&self, shm_id: ShmId) -> SyscallResult<ShmInfo> {
-        Ok(self.memory.shm_info(shm_id)?)
+        if allowed {
+            Ok(())
+        } else {
+            Err(SyscallError::PermissionDenied)
+        }
     }
 
-    /// List all shared memory segments
-    pub fn sys_shm_list(&self) -> SyscallResult<Vec<ShmInfo>> {
-        Ok(self.memory.shm_list())
-    }
+    /// SEC-011: Check execute permission on ALL directories in the path
+    /// This is required to traverse to the target file/directory
+    fn check_path_traversal(&self, path: &str) -> SyscallResult<()> {
+        let process = self.get_current_process()?;
 
-    /// Get memory stats for current process
-    pub fn sys_memstats(&self) -> SyscallResult<MemoryStats> {
-        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        let process = self
-            .proc
-            .processes
-            .get(&current)
-            .ok_or(SyscallError::NoProcess)?;
-        Ok(process.memory.stats())
-    }
+        // Root can always traverse
+        if process.euid == Uid::ROOT {
+            return Ok(());
+        }
 
-    /// Set memory limit for current process
-    pub fn sys_set_memlimit(&mut self, limit: usize) -> SyscallResult<()> {
-        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        let process = self
-            .proc
-            .processes
-            .get_mut(&current)
-            .ok_or(SyscallError::NoProcess)?;
-        process.memory.set_limit(limit);
-        Ok(())
-    }
+        // Split path into components and check each directory
+        let path = Path::new(path);
+        let mut current_path = PathBuf::from("/");
 
-    /// Get system-wide memory stats
-    pub fn sys_system_memstats(&self) -> SyscallResult<SystemMemoryStats> {
-        Ok(self.memory.system_stats())
-    }
+        for component in path.components() {
+            use std::path::Component;
+            match component {
+                Component::RootDir => continue,
+                Component::CurDir => continue,
+                Component::ParentDir => {
+                    current_path.pop();
+                }
+                Component::Normal(name) => {
+                    // Check execute permission on current directory before entering
+                    let current_str = current_path.to_string_lossy();
+                    if current_str != "/" {
+                        // Check if current_path is a directory we need execute permission for
+                        let (fs, inner) = self.fs.route(&current_str);
+                        if let Ok(meta) = fs.metadata(&inner)
+                            && meta.is_dir
+                        {
+                            let allowed = may_access(process, &meta, false, false, true);
+                            if !allowed {
+                                return Err(SyscallError::PermissionDenied);
+                            }
+                        }
+                    }
+                    current_path.push(name);
+                }
+                Component::Prefix(_) => {} // Windows-only
+            }
+        }
 
-    // ========== TIMER SYSCALLS ==========
+        // Check the final directory component if the path is a directory path
+        // (for the parent directory of a file)
+        if let Some(parent) = path.parent()
+            && parent != Path::new("")
+            && parent != Path::new("/")
+        {
+            let parent_str = parent.to_string_lossy();
+            let (fs, inner) = self.fs.route(&parent_str);
+            if let Ok(meta) = fs.metadata(&inner)
+                && meta.is_dir
+            {
+                let allowed = may_access(process, &meta, false, false, true);
+                if !allowed {
+                    return Err(SyscallError::PermissionDenied);
+                }
+            }
+        }
 
-    /// Get current kernel time
-    pub fn sys_now(&self) -> f64 {
-        self.time.now
+        Ok(())
     }
 
-    /// Set current kernel time (called from runtime with rAF timestamp)
-    pub fn set_time(&mut self, now: f64) {
-        self.time.now = now;
+    /// Refuse to modify anything on a read-only mount
+    fn check_writable_mount(&self, path: &str) -> SyscallResult<()> {
+        let resolved = self
+            .fs
+            .vfs
+            .resolve_symlinks(path)
+            .unwrap_or_else(|_| path.to_string());
+        match self.fs.mounts.get_containing_mount(&resolved) {
+            Some(mount) if mount.options.read_only => Err(SyscallError::ReadOnlyFs),
+            _ => Ok(()),
+        }
     }
 
-    /// Schedule a one-shot timer
-    pub fn sys_timer_set(
-        &mut self,
-        delay_ms: f64,
-        wake_task: Option<TaskId>,
-    ) -> SyscallResult<TimerId> {
-        if delay_ms < 0.0 {
-            return Err(SyscallError::InvalidArgument);
+    /// Refuse writes through a file opened before its mount went read-only
+    ///
+    /// Writable synthetic files (`/sys/power/governor`) are kernel settings
+    /// rather than data, and stay writable on read-only mounts.
+    fn check_writable_handle(&self, handle: Handle) -> SyscallResult<()> {
+        let Some(KernelObject::File(file)) = self.objects.get(handle) else {
+            return Ok(());
+        };
+        let path = file.path.to_string_lossy();
+        if !file.writable || self.fs.synthetic.lookup(&path).is_some() {
+            return Ok(());
         }
-        Ok(self
-            .time
-            .timers
-            .schedule(delay_ms, self.time.now, wake_task))
+        self.check_writable_mount(&path)
     }
 
-    /// Schedule a repeating interval timer
-    pub fn sys_timer_interval(
-        &mut self,
-        interval_ms: f64,
-        wake_task: Option<TaskId>,
-    ) -> SyscallResult<TimerId> {
-        if interval_ms <= 0.0 {
-            return Err(SyscallError::InvalidArgument);
+    /// Refuse to run anything from a `noexec` mount; returns whether the
+    /// file's setuid and setgid bits count, which they don't on a `nosuid`
+    /// mount
+    fn check_exec_mount(&self, path: &str) -> SyscallResult<bool> {
+        let resolved = self
+            .fs
+            .vfs
+            .resolve_symlinks(path)
+            .unwrap_or_else(|_| path.to_string());
+        match self.fs.mounts.get_containing_mount(&resolved) {
+            Some(mount) if mount.options.noexec => Err(SyscallError::PermissionDenied),
+            Some(mount) => Ok(!mount.options.nosuid),
+            None => Ok(true),
         }
-        Ok(self
-            .time
-            .timers
-            .schedule_interval(interval_ms, self.time.now, wake_task))
     }
 
-    /// Cancel a timer
-    pub fn sys_timer_cancel(&mut self, timer_id: TimerId) -> SyscallResult<bool> {
-        Ok(self.time.timers.cancel(timer_id))
+    /// Check that `path` may be run, for a program loader; returns whether
+    /// its setuid and setgid bits should be honored
+    ///
+    /// Fails with `PermissionDenied` for a file on a `noexec` mount. The
+    /// bits are ignored on a `nosuid` mount.
+    pub fn sys_check_exec(&self, path: &str) -> SyscallResult<bool> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let resolved = self.resolve_path(current, path)?;
+        self.check_exec_mount(&resolved.to_string_lossy())
     }
 
-    /// Check if a timer is pending
-    pub fn sys_timer_pending(&self, timer_id: TimerId) -> SyscallResult<bool> {
-        Ok(self.time.timers.is_pending(timer_id))
-    }
+    /// Take on the effective ids the setuid and setgid bits of the program
+    /// at `path` give, for a program loader; returns whether they changed,
+    /// and [`Kernel::sys_exec_restore`] puts the caller's back
+    ///
+    /// Fails with `PermissionDenied` for a file on a `noexec` mount. The
+    /// bits are ignored on a `nosuid` mount, and on a program anyone but
+    /// its owner can write, who could make it run anything. Every change
+    /// is logged under `audit`.
+    pub fn sys_exec_setid(&mut self, path: &str) -> SyscallResult<bool> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let program = self.follow_links(current, path)?;
+        if !self.check_exec_mount(&program)? {
+            return Ok(false);
+        }
+        let (fs, inner) = self.fs.route(&program);
+        let meta = fs.metadata(&inner)?;
+        let mode = FileMode::new(meta.mode);
+        if !meta.is_file || !(mode.is_setuid() || mode.is_setgid()) {
+            return Ok(false);
+        }
+        // A named user or group with a `w` entry can rewrite it too
+        let acl_writable = meta.acl.as_ref().is_some_and(|acl| {
+            acl.entries()
+                .iter()
+                .any(|e| e.tag != AclTag::User(meta.uid) && acl.effective(e.perms) & ACL_WRITE != 0)
+        });
+        if meta.mode & 0o022 != 0 || acl_writable {
+            let message = format!(
+                "ignoring setuid/setgid bits of {}: writable by others",
+                program
+            );
+            self.klog(LogLevel::Warn, "audit", &message);
+            return Ok(false);
+        }
 
-    /// Get time until next timer fires (for sleep optimization)
-    pub fn time_until_next_timer(&self) -> Option<f64> {
-        self.time.timers.time_until_next(self.time.now)
+        let process = self.get_current_process_mut()?;
+        let before = (process.euid, process.egid);
+        if mode.is_setuid() {
+            process.euid = Uid(meta.uid);
+        }
+        if mode.is_setgid() {
+            process.egid = Gid(meta.gid);
+        }
+        let (uid, euid, egid) = (process.uid, process.euid, process.egid);
+        if (euid, egid) == before {
+            return Ok(false);
+        }
+        // A program run from an elevated one restores to the first ids
+        self.proc.elevated.entry(current).or_insert(before);
+        let message = format!(
+            "pid {} uid {} ran {} as uid {} gid {}",
+            current.0, uid.0, program, euid.0, egid.0
+        );
+        self.klog(LogLevel::Notice, "audit", &message);
+        Ok(true)
     }
 
-    /// Get pending timer count
-    pub fn pending_timer_count(&self) -> usize {
-        self.time.timers.pending_count()
+    /// Put back the effective ids the current process had before
+    /// [`Kernel::sys_exec_setid`] changed them
+    pub fn sys_exec_restore(&mut self) -> SyscallResult<()> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        if let Some((euid, egid)) = self.proc.elevated.remove(&current) {
+            let process = self.get_current_process_mut()?;
+            process.euid = euid;
+            process.egid = egid;
+        }
+        Ok(())
     }
 
-    /// Tick timers, returning tasks to wake
-    pub fn tick_timers(&mut self) -> Vec<TaskId> {
-        self.time.timers.tick(self.time.now)
-    }
+    /// Check if the current process has write permission on the parent directory
+    /// (needed for creating/deleting files in the directory)
+    fn check_parent_write_permission(&self, path: &str) -> SyscallResult<()> {
+        // The entry itself may be a mount point (e.g. rmdir /system)
+        self.check_writable_mount(path)?;
 
-    /// Set an alarm for the current process (SIGALRM after delay)
-    pub fn sys_alarm(&mut self, delay_ms: f64) -> SyscallResult<TimerId> {
-        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        let process = self
-            .proc
-            .processes
-            .get(&current)
-            .ok_or(SyscallError::NoProcess)?;
-        let task = process.task;
-        self.sys_timer_set(delay_ms, task)
+        // Get parent directory
+        let parent = Path::new(path)
+            .parent()
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_else(|| "/".to_string());
+
+        let parent = if parent.is_empty() { "/" } else { &parent };
+
+        // Need write and execute on parent directory to create/delete files
+        self.check_file_permission(parent, false, true, true)
     }
 
-    // ========== SIGNAL SYSCALLS ==========
+    /// SEC-015: Check sticky bit restriction for file deletion
+    /// When a directory has the sticky bit set, only the owner of the file,
+    /// the owner of the directory, or root can delete files in it.
+    fn check_sticky_bit(&self, path: &str) -> SyscallResult<()> {
+        let process = self.get_current_process()?;
 
-    /// Send a signal to a process
-    ///
-    /// Permission rules:
-    /// - A process can signal itself
-    /// - Root (UID 0) can signal any process
-    /// - CAP_KILL allows signaling any process
-    /// - Same real or effective UID can signal a process
-    pub fn sys_kill(&mut self, pid: Pid, signal: Signal) -> SyscallResult<()> {
-        // Get current process info for permission check
-        let current_pid = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        let current = self
-            .proc
-            .processes
-            .get(&current_pid)
-            .ok_or(SyscallError::NoProcess)?;
+        // Root can always delete
+        if process.euid == Uid::ROOT {
+            return Ok(());
+        }
 
-        let current_euid = current.euid;
-        let current_uid = current.uid;
-        let has_cap_kill = current.capabilities.has_effective(Capability::Kill);
+        // Get parent directory
+        let parent_path = Path::new(path)
+            .parent()
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_else(|| "/".to_string());
+        let parent = if parent_path.is_empty() {
+            "/"
+        } else {
+            &parent_path
+        };
 
-        // Get target process
-        let target = self
-            .proc
-            .processes
-            .get_mut(&pid)
-            .ok_or(SyscallError::NoProcess)?;
+        // Get parent directory metadata
+        let (fs, inner) = self.fs.route(parent);
+        let parent_meta = fs.metadata(&inner)?;
+        let parent_mode = FileMode::new(parent_meta.mode);
 
-        // Can't signal zombies
-        if matches!(target.state, ProcessState::Zombie(_)) {
-            return Err(SyscallError::NoProcess);
+        // If sticky bit is not set, no restriction
+        if !parent_mode.is_sticky() {
+            return Ok(());
         }
 
-        // Permission check
-        let can_signal = current_pid == pid  // Can signal self
-            || current_euid == Uid::ROOT      // Root can signal anyone
-            || has_cap_kill                    // CAP_KILL can signal anyone
-            || current_euid == target.euid     // Same effective UID
-            || current_uid == target.uid; // Same real UID
+        // Sticky bit is set - check ownership
+        // Can delete if: owner of directory, or owner of file
+        let is_dir_owner = parent_meta.uid == process.euid.0;
+        if is_dir_owner {
+            return Ok(());
+        }
 
-        if !can_signal {
-            return Err(SyscallError::PermissionDenied);
+        // Check if owner of the file being deleted
+        let (fs, inner) = self.fs.route(path);
+        if let Ok(file_meta) = fs.metadata(&inner)
+            && file_meta.uid == process.euid.0
+        {
+            return Ok(());
         }
 
-        // Queue the signal
-        target.signals.send(signal);
+        // Not allowed
+        Err(SyscallError::PermissionDenied)
+    }
 
-        Ok(())
+    /// Get the current process's effective UID (for setting file ownership)
+    fn current_euid(&self) -> SyscallResult<Uid> {
+        let process = self.get_current_process()?;
+        Ok(process.euid)
     }
 
-    /// Set signal handler for current process
-    pub fn sys_signal(
-        &mut self,
-        signal: Signal,
-        action: SignalAction,
-    ) -> SyscallResult<SignalAction> {
-        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        let process = self
-            .proc
-            .processes
-            .get_mut(&current)
-            .ok_or(SyscallError::NoProcess)?;
-
-        let old_action = process.signals.disposition.get_action(signal);
-        process.signals.disposition.set_action(signal, action)?;
-
-        Ok(old_action)
-    }
-
-    /// Block a signal for current process
-    pub fn sys_sigblock(&mut self, signal: Signal) -> SyscallResult<()> {
-        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        let process = self
-            .proc
-            .processes
-            .get_mut(&current)
-            .ok_or(SyscallError::NoProcess)?;
-        process.signals.block(signal)?;
-        Ok(())
+    /// Get the current process's effective GID
+    fn current_egid(&self) -> SyscallResult<Gid> {
+        let process = self.get_current_process()?;
+        Ok(process.egid)
     }
 
-    /// Unblock a signal for current process
-    pub fn sys_sigunblock(&mut self, signal: Signal) -> SyscallResult<()> {
-        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        let process = self
-            .proc
-            .processes
-            .get_mut(&current)
-            .ok_or(SyscallError::NoProcess)?;
-        process.signals.unblock(signal);
-        Ok(())
+    /// Look up an object in the kernel object store
+    pub(crate) fn object_mut(&mut self, handle: Handle) -> Option<&mut KernelObject> {
+        self.objects.get_mut(handle)
     }
 
-    /// Check if current process has pending signals
-    pub fn sys_sigpending(&self) -> SyscallResult<bool> {
-        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        let process = self
-            .proc
-            .processes
-            .get(&current)
-            .ok_or(SyscallError::NoProcess)?;
-        Ok(process.signals.has_pending())
+    /// Insert an object into the kernel object store
+    pub(crate) fn insert_object(&mut self, object: KernelObject) -> Handle {
+        self.objects.insert(object)
     }
 
-    /// Get pending signals as a bitmask
-    pub fn sys_sigpending_mask(&self) -> SyscallResult<u16> {
-        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        let process = self
-            .proc
-            .processes
-            .get(&current)
-            .ok_or(SyscallError::NoProcess)?;
-        Ok(process.signals.get_pending_mask())
+    /// Share the object behind the current process's `fd`, for opens
+    /// like /dev/stdout that reach an already open descriptor
+    pub(crate) fn share_fd_handle(&mut self, fd: Fd) -> SyscallResult<Handle> {
+        let handle = self.get_handle(fd)?;
+        if !self.objects.retain(handle) {
+            return Err(SyscallError::BadFd);
+        }
+        Ok(handle)
     }
 
-    /// sigprocmask - examine and change blocked signals
-    ///
-    /// # Arguments
-    /// * `how` - How to modify the mask (Block, Unblock, SetMask)
-    /// * `mask` - Bitmask of signals (bit N = signal N)
+    /// Create a file object and insert it into the kernel object store
     ///
-    /// # Returns
-    /// The old signal mask before modification
-    pub fn sys_sigprocmask(&mut self, how: SigProcMaskHow, mask: u16) -> SyscallResult<u16> {
-        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        let process = self
-            .proc
-            .processes
-            .get_mut(&current)
-            .ok_or(SyscallError::NoProcess)?;
-        Ok(process.signals.sigprocmask(how, mask))
+    /// Used by the synthetic filesystems to hand out generated files.
+    pub fn create_file_object(
+        &mut self,
+        path: PathBuf,
+        data: Vec<u8>,
+        readable: bool,
+        writable: bool,
+    ) -> Handle {
+        let file = FileObject::new(path, data, readable, writable);
+        self.objects.insert(KernelObject::File(file))
     }
 
-    /// Get current signal mask
-    pub fn sys_siggetmask(&self) -> SyscallResult<u16> {
-        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        let process = self
-            .proc
-            .processes
-            .get(&current)
-            .ok_or(SyscallError::NoProcess)?;
-        Ok(process.signals.get_blocked_mask())
-    }
+    /// Open a regular file
+    ///
+    /// Uses atomic permission checking (TOCTOU-safe): opens the file first,
+    /// then checks permissions on the actual opened file handle.
+    fn open_file(&mut self, path: &Path, flags: OpenFlags) -> SyscallResult<Handle> {
+        let path_str = path.to_str().ok_or(SyscallError::InvalidArgument)?;
 
-    // ========== Process Priority ==========
+        // Check if file exists (needed to determine if we're creating a new file)
+        let file_exists = {
+            let (fs, inner) = self.fs.route(path_str);
+            fs.exists(&inner)
+        };
 
-    /// nice - adjust process scheduling priority
-    ///
-    /// Adds the increment to the current nice value (clamped to -20..19).
-    /// Returns the new nice value.
-    ///
-    /// Note: In real POSIX, only root can decrease nice (increase priority).
-    /// We allow any user to adjust priority for simplicity.
-    pub fn sys_nice(&mut self, increment: i8) -> SyscallResult<i8> {
-        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        let process = self
-            .proc
-            .processes
-            .get_mut(&current)
-            .ok_or(SyscallError::NoProcess)?;
+        // Opening for write truncates before the handle check below
+        if flags.write || flags.truncate {
+            self.check_writable_mount(path_str)?;
+        }
 
-        let new_nice = (process.nice as i16 + increment as i16).clamp(-20, 19) as i8;
-        process.nice = new_nice;
-        Ok(new_nice)
-    }
+        // For new files, check parent directory permission first
+        // This must happen before open since the file doesn't exist yet
+        if !file_exists {
+            if flags.create {
+                self.check_parent_write_permission(path_str)?;
+            } else {
+                return Err(SyscallError::NotFound);
+            }
+        }
 
-    /// getpriority - get process scheduling priority
-    ///
-    /// Returns the nice value for the specified process.
-    /// Use pid=0 for current process.
-    pub fn sys_getpriority(&self, pid: Pid) -> SyscallResult<i8> {
-        let target_pid = if pid.0 == 0 {
-            self.proc.current.ok_or(SyscallError::NoProcess)?
-        } else {
-            pid
+        // Convert our flags to VFS options
+        // Note: We always need read access in VFS to read existing content into FileObject,
+        // but the actual permissions are tracked separately in the FileObject
+        let vfs_opts = VfsOpenOptions {
+            read: true, // Always need to read existing content
+            write: flags.write,
+            create: flags.create,
+            truncate: flags.truncate,
         };
 
-        let process = self
-            .proc
-            .processes
-            .get(&target_pid)
-            .ok_or(SyscallError::NoProcess)?;
+        // Open via VFS first (before permission check for TOCTOU safety)
+        let (fs, inner) = self.fs.route_mut(path_str);
+        let vfs_handle = fs.open(&inner, vfs_opts)?;
 
-        Ok(process.nice)
-    }
+        // For existing files, check permissions AFTER opening (TOCTOU-safe)
+        // This uses fstat on the opened handle, not the path that could have changed
+        if file_exists
+            && self
+                .check_handle_permission(path_str, vfs_handle, flags.read, flags.write, false)
+                .is_err()
+        {
+            // Permission denied - close the handle and return error
+            let (fs, _) = self.fs.route_mut(path_str);
+            let _ = fs.close(vfs_handle);
+            return Err(SyscallError::PermissionDenied);
+        }
 
-    /// setpriority - set process scheduling priority
-    ///
-    /// Sets the nice value for the specified process.
-    /// Use pid=0 for current process.
-    ///
-    /// Note: In real POSIX, only root can decrease nice (increase priority).
-    /// We allow any user to adjust priority for simplicity.
-    pub fn sys_setpriority(&mut self, pid: Pid, priority: i8) -> SyscallResult<()> {
-        let target_pid = if pid.0 == 0 {
-            self.proc.current.ok_or(SyscallError::NoProcess)?
+        // If we just created a new file, set ownership and apply umask
+        if !file_exists && flags.create {
+            let euid = self.current_euid()?;
+            let egid = self.current_egid()?;
+
+            // SEC-014: Apply umask to new file mode
+            // Default file mode is 0o666, apply umask to get final mode
+            let umask = self.get_current_process()?.umask;
+            let new_mode = 0o666 & !umask;
+
+            let (fs, inner) = self.fs.route_mut(path_str);
+            let _ = fs.chown(&inner, Some(euid.0), Some(egid.0));
+            let _ = fs.chmod(&inner, new_mode);
+        }
+
+        // Read the file contents (using fstat for consistency with atomic open)
+        let (fs, _) = self.fs.route_mut(path_str);
+        let meta = fs.fstat(vfs_handle)?;
+        let file_size = usize::try_from(meta.size).map_err(|_| SyscallError::TooBig)?;
+        let mut data = self.objects.buffers.take(file_size);
+        if !data.is_empty() {
+            fs.read(vfs_handle, &mut data)?;
+        }
+
+        // For append mode, seek to end
+        if flags.append {
+            fs.seek(vfs_handle, SeekFrom::End(0))?;
         } else {
-            pid
-        };
+            // Seek back to start
+            fs.seek(vfs_handle, SeekFrom::Start(0))?;
+        }
 
-        let process = self
-            .proc
-            .processes
-            .get_mut(&target_pid)
-            .ok_or(SyscallError::NoProcess)?;
+        // Create a FileObject that mirrors the VFS file
+        // For append mode, position at end
+        let mut file = FileObject::new(path.to_path_buf(), data, flags.read, flags.write);
+        if flags.append {
+            file.position = file.data.len() as u64;
+        }
 
-        process.nice = priority.clamp(-20, 19);
-        Ok(())
-    }
+        let handle = self.objects.insert(KernelObject::File(file));
 
-    /// Process pending signals for a process, returns action to take
-    pub fn process_signals(&mut self, pid: Pid) -> Option<(Signal, SignalAction)> {
-        let process = self.proc.processes.get_mut(&pid)?;
+        // Track the VFS handle for sync/close
+        self.fs.vfs_handles.insert(handle, vfs_handle);
 
-        // Get next pending signal
-        let signal = process.signals.next_pending()?;
+        Ok(handle)
+    }
 
-        // Resolve the action
-        let action = resolve_action(signal, &process.signals.disposition);
+    /// Sync a file back to the VFS (on close or explicit sync)
+    fn sync_file(&mut self, handle: Handle) -> SyscallResult<()> {
+        let vfs_handle = self.fs.vfs_handles.get(&handle).copied();
 
-        // Apply immediate actions
-        match action {
-            SignalAction::Kill | SignalAction::Terminate => {
-                process.state = ProcessState::Zombie(-(signal.num() as i32));
-            }
-            SignalAction::Stop => {
-                process.state = ProcessState::Stopped;
-                process.signals.stop();
-            }
-            SignalAction::Continue => {
-                if process.state == ProcessState::Stopped {
-                    process.state = ProcessState::Running;
-                    process.was_continued = true; // Set flag for WCONTINUED
+        if let Some(vh) = vfs_handle {
+            // Get the file data; a read-only handle has nothing to write
+            // back, and may be on a read-only filesystem
+            if let Some(KernelObject::File(file)) = self.objects.get_mut(handle)
+                && file.writable
+            {
+                // Borrowed out of the object rather than copied, and put
+                // back once written
+                let data = std::mem::take(&mut file.data);
+                let path = std::mem::take(&mut file.path);
+                let written = self.write_back(vh, &path, &data);
+                let path_str = path.to_string_lossy().into_owned();
+                if let Some(KernelObject::File(file)) = self.objects.get_mut(handle) {
+                    file.data = data;
+                    file.path = path;
                 }
-                process.signals.cont();
-            }
-            SignalAction::Ignore => {
-                // Nothing to do
-            }
-            SignalAction::Handle | SignalAction::Default => {
-                // Handler will be called by caller
+                self.fs.vfs_handles.insert(handle, written?);
+                self.reload_user_db(&path_str);
             }
         }
+        Ok(())
+    }
 
-        Some((signal, action))
+    /// Replace the contents of the file behind VFS handle `vh` with `data`,
+    /// returning the handle that replaces it
+    fn write_back(
+        &mut self,
+        vh: VfsFileHandle,
+        path: &Path,
+        data: &[u8],
+    ) -> SyscallResult<VfsFileHandle> {
+        let path_str = path.to_str().ok_or(SyscallError::InvalidArgument)?;
+        let (fs, inner) = self.fs.route_mut(path_str);
+        fs.seek(vh, SeekFrom::Start(0))?;
+
+        // Close old handle and reopen with truncate
+        let _ = fs.close(vh);
+        let new_vh = fs.open(
+            &inner,
+            VfsOpenOptions {
+                read: false,
+                write: true,
+                create: true,
+                truncate: true,
+            },
+        )?;
+        // Long runs of zeros go back as holes
+        sparse::write_sparse(fs, new_vh, data)?;
+        Ok(new_vh)
     }
 
-    // ========== MESSAGE QUEUE SYSCALLS ==========
-
-    /// msgget - get or create a message queue
-    ///
-    /// # Arguments
-    /// * `key` - Queue key. Negative = create private queue, non-negative = get/create by key
-    /// * `create` - If true, create queue if it doesn't exist
-    ///
-    /// # Returns
-    /// Queue ID on success
-    pub fn sys_msgget(&mut self, key: i32, create: bool) -> SyscallResult<u32> {
+    /// Create a directory
+    pub fn sys_mkdir(&mut self, path: &str) -> SyscallResult<()> {
         let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        let process = self
-            .proc
-            .processes
-            .get(&current)
-            .ok_or(SyscallError::NoProcess)?;
+        let resolved = self.resolve_path(current, path)?;
+        let path_str = resolved.to_str().ok_or(SyscallError::InvalidArgument)?;
 
-        let uid = process.euid.0;
-        let gid = process.egid.0;
+        // Check write/execute permission on parent directory
+        self.check_parent_write_permission(path_str)?;
 
-        let id = self
-            .ipc
-            .msgqueues
-            .msgget(key, uid, gid, create)
-            .map_err(|e| match e {
-                MsgQueueError::NotFound => SyscallError::NotFound,
-                MsgQueueError::AlreadyExists => SyscallError::AlreadyExists,
-                _ => SyscallError::Io("message queue error".into()),
-            })?;
+        let (fs, inner) = self.fs.route_mut(path_str);
+        fs.create_dir(&inner)?;
 
-        Ok(id.0)
-    }
+        // Set ownership to current user
+        let euid = self.current_euid()?;
+        let egid = self.current_egid()?;
 
-    /// msgsnd - send a message to a queue
-    ///
-    /// # Arguments
-    /// * `queue_id` - Queue identifier
-    /// * `mtype` - Message type (must be > 0)
-    /// * `data` - Message data
-    ///
-    /// # Returns
-    /// () on success
-    pub fn sys_msgsnd(&mut self, queue_id: u32, mtype: i64, data: Vec<u8>) -> SyscallResult<()> {
-        use super::msgqueue::Message;
+        // SEC-014: Apply umask to new directory mode
+        // Default directory mode is 0o777, apply umask to get final mode
+        let umask = self.get_current_process()?.umask;
+        let new_mode = 0o777 & !umask;
 
-        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        let process = self
-            .proc
-            .processes
-            .get(&current)
-            .ok_or(SyscallError::NoProcess)?;
+        let (fs, inner) = self.fs.route_mut(path_str);
+        let _ = fs.chown(&inner, Some(euid.0), Some(egid.0));
+        let _ = fs.chmod(&inner, new_mode);
 
-        // Check write permission
-        let queue = self
-            .ipc
-            .msgqueues
-            .get(MsgQueueId(queue_id))
-            .ok_or(SyscallError::NotFound)?;
+        Ok(())
+    }
 
-        if process.euid.0 != 0 && process.euid.0 != queue.uid {
-            // Check mode for write permission
-            let can_write = if process.euid.0 == queue.uid {
-                queue.mode & 0o200 != 0
-            } else if process.egid.0 == queue.gid {
-                queue.mode & 0o020 != 0
-            } else {
-                queue.mode & 0o002 != 0
-            };
-            if !can_write {
-                return Err(SyscallError::PermissionDenied);
-            }
+    /// List directory contents
+    pub fn sys_readdir(&mut self, path: &str) -> SyscallResult<Vec<String>> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let resolved = self.resolve_path(current, path)?;
+        let path_str = resolved.to_str().ok_or(SyscallError::InvalidArgument)?;
+
+        // Synthetic filesystem listings (always readable)
+        if let Some(fs) = self.fs.synthetic.lookup(path_str) {
+            return fs.list_dir(self, path_str).ok_or(SyscallError::NotFound);
         }
 
-        let now = self.time.now;
-        let msg = Message::new(mtype, data);
+        // Check read and execute permission on directory
+        self.check_file_permission(path_str, true, false, true)?;
 
-        self.ipc
-            .msgqueues
-            .msgsnd(MsgQueueId(queue_id), msg, now)
-            .map_err(|e| match e {
-                MsgQueueError::InvalidType => SyscallError::InvalidArgument,
-                MsgQueueError::QueueFull => SyscallError::WouldBlock,
-                MsgQueueError::NotFound => SyscallError::NotFound,
-                _ => SyscallError::Io("message queue error".into()),
-            })
+        let (fs, inner) = self.fs.route(path_str);
+        let entries = fs.read_dir(&inner)?;
+        Ok(entries.into_iter().map(|e| e.name).collect())
     }
 
-    /// msgrcv - receive a message from a queue
-    ///
-    /// # Arguments
-    /// * `queue_id` - Queue identifier
-    /// * `mtype` - Message type selector:
-    ///   - 0: receive first message
-    ///   - >0: receive first message with matching type
-    ///   - <0: receive first message with type <= |mtype|
-    ///
-    /// # Returns
-    /// (mtype, data) on success
-    pub fn sys_msgrcv(&mut self, queue_id: u32, mtype: i64) -> SyscallResult<(i64, Vec<u8>)> {
+    /// Check if a path exists
+    pub fn sys_exists(&self, path: &str) -> SyscallResult<bool> {
         let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        let process = self
-            .proc
-            .processes
-            .get(&current)
-            .ok_or(SyscallError::NoProcess)?;
+        let resolved = self.resolve_path(current, path)?;
+        let path_str = resolved.to_str().ok_or(SyscallError::InvalidArgument)?;
 
-        // Check read permission
-        let queue = self
-            .ipc
-            .msgqueues
-            .get(MsgQueueId(queue_id))
-            .ok_or(SyscallError::NotFound)?;
+        if let Some(fs) = self.fs.synthetic.lookup(path_str) {
+            return Ok(fs.exists(self, path_str));
+        }
 
-        if process.euid.0 != 0 && process.euid.0 != queue.uid {
-            // Check mode for read permission
-            let can_read = if process.euid.0 == queue.uid {
-                queue.mode & 0o400 != 0
-            } else if process.egid.0 == queue.gid {
-                queue.mode & 0o040 != 0
-            } else {
-                queue.mode & 0o004 != 0
-            };
-            if !can_read {
-                return Err(SyscallError::PermissionDenied);
+        let (fs, inner) = self.fs.route(path_str);
+        Ok(fs.exists(&inner))
+    }
+
+    /// Get file/directory metadata
+    pub fn sys_metadata(&self, path: &str) -> SyscallResult<FileMetadata> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let resolved = self.resolve_path(current, path)?;
+        let path_str = resolved.to_str().ok_or(SyscallError::InvalidArgument)?;
+
+        // Synthetic files are owned by root and have no fixed size or times
+        if let Some(fs) = self.fs.synthetic.lookup(path_str) {
+            if !fs.exists(self, path_str) {
+                return Err(SyscallError::NotFound);
             }
+            let is_dir = fs.is_dir(self, path_str);
+            return Ok(FileMetadata {
+                size: 0,
+                allocated: 0,
+                is_dir,
+                is_file: !is_dir,
+                is_symlink: false,
+                symlink_target: None,
+                uid: 0,
+                gid: 0,
+                mode: fs.mode(is_dir),
+                atime: 0.0,
+                mtime: 0.0,
+            });
         }
 
-        let now = self.time.now;
-
-        let msg = self
-            .ipc
-            .msgqueues
-            .msgrcv(MsgQueueId(queue_id), mtype, now)
-            .map_err(|e| match e {
-                MsgQueueError::NoMessage => SyscallError::WouldBlock,
-                MsgQueueError::NotFound => SyscallError::NotFound,
-                _ => SyscallError::Io("message queue error".into()),
-            })?;
+        let (fs, inner) = self.fs.route(path_str);
+        let meta = fs.metadata(&inner)?;
+        Ok(FileMetadata {
+            size: meta.size,
+            allocated: meta.allocated,
+            is_dir: meta.is_dir,
+            is_file: meta.is_file,
+            is_symlink: meta.is_symlink,
+            symlink_target: meta.symlink_target,
+            uid: meta.uid,
+            gid: meta.gid,
+            mode: meta.mode,
+            atime: meta.atime,
+            mtime: meta.mtime,
+        })
+    }
 
-        Ok((msg.mtype, msg.data))
+    /// The absolute path `path` names once symbolic links are followed
+    pub fn sys_realpath(&self, path: &str) -> SyscallResult<String> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        self.follow_links(current, path)
     }
 
-    /// msgctl - message queue control operations
-    ///
-    /// # Arguments
-    /// * `queue_id` - Queue identifier
-    /// * `cmd` - Command:
-    ///   - 0 (IPC_RMID): Remove queue
-    ///   - 1 (IPC_STAT): Get queue stats (returns via separate method)
-    ///   - 2 (IPC_SET): Set queue attributes
-    /// * `uid`, `gid`, `mode`, `max_bytes` - Values for IPC_SET
+    /// Set the access and modification times of `path`, following links
     ///
-    /// # Returns
-    /// () on success, stats for IPC_STAT
-    pub fn sys_msgctl_rmid(&mut self, queue_id: u32) -> SyscallResult<()> {
+    /// A time left as `None` becomes the current time. Only the owner and
+    /// root may set times.
+    pub fn sys_utimes(
+        &mut self,
+        path: &str,
+        atime: Option<f64>,
+        mtime: Option<f64>,
+    ) -> SyscallResult<()> {
         let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        let process = self
-            .proc
-            .processes
-            .get(&current)
-            .ok_or(SyscallError::NoProcess)?;
-
-        // Check owner or root
-        let queue = self
-            .ipc
-            .msgqueues
-            .get(MsgQueueId(queue_id))
-            .ok_or(SyscallError::NotFound)?;
+        let path = self.follow_links(current, path)?;
+        self.check_writable_mount(&path)?;
 
-        if process.euid.0 != 0 && process.euid.0 != queue.uid {
+        let euid = self.current_euid()?;
+        let (fs, inner) = self.fs.route_mut(&path);
+        let meta = fs.metadata(&inner)?;
+        if euid != Uid::ROOT && meta.uid != euid.0 {
             return Err(SyscallError::PermissionDenied);
         }
+        fs.utimes(&inner, atime, mtime)?;
+        Ok(())
+    }
 
-        self.ipc
-            .msgqueues
-            .msgctl_rmid(MsgQueueId(queue_id))
-            .map_err(|_| SyscallError::NotFound)
+    /// Remove a file
+    pub fn sys_remove_file(&mut self, path: &str) -> SyscallResult<()> {
+        let result = self.unlink_path(path);
+        if self.debugger.has_path_watches()
+            && let Some(resolved) = self.debug_resolve(path)
+        {
+            let args = vec![debug_path_arg("path", &resolved)];
+            let ret = debug_result(&result, |_| 0);
+            self.debug_path_access(PathOp::Unlink, &resolved, "unlink", args, ret);
+        }
+        result
     }
 
-    /// msgctl IPC_STAT - get queue statistics
-    pub fn sys_msgctl_stat(&self, queue_id: u32) -> SyscallResult<MsgQueueStats> {
+    fn unlink_path(&mut self, path: &str) -> SyscallResult<()> {
         let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        let process = self
-            .proc
-            .processes
-            .get(&current)
-            .ok_or(SyscallError::NoProcess)?;
+        let resolved = self.resolve_path(current, path)?;
+        let path_str = resolved.to_str().ok_or(SyscallError::InvalidArgument)?;
 
-        // Check read permission
-        let queue = self
-            .ipc
-            .msgqueues
-            .get(MsgQueueId(queue_id))
-            .ok_or(SyscallError::NotFound)?;
+        // Check write/execute permission on parent directory
+        self.check_parent_write_permission(path_str)?;
 
-        if process.euid.0 != 0 && process.euid.0 != queue.uid {
-            let can_read = if process.euid.0 == queue.uid {
-                queue.mode & 0o400 != 0
-            } else if process.egid.0 == queue.gid {
-                queue.mode & 0o040 != 0
-            } else {
-                queue.mode & 0o004 != 0
-            };
-            if !can_read {
-                return Err(SyscallError::PermissionDenied);
-            }
-        }
+        // SEC-015: Check sticky bit restriction
+        self.check_sticky_bit(path_str)?;
 
-        self.ipc
-            .msgqueues
-            .msgctl_stat(MsgQueueId(queue_id))
-            .map_err(|_| SyscallError::NotFound)
+        let (fs, inner) = self.fs.route_mut(path_str);
+        fs.remove_file(&inner)?;
+        Ok(())
     }
 
-    /// msgctl IPC_SET - set queue attributes
-    pub fn sys_msgctl_set(
-        &mut self,
-        queue_id: u32,
-        uid: Option<u32>,
-        gid: Option<u32>,
-        mode: Option<u16>,
-        max_bytes: Option<usize>,
-    ) -> SyscallResult<()> {
+    /// Remove a directory (must be empty)
+    pub fn sys_remove_dir(&mut self, path: &str) -> SyscallResult<()> {
         let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        let process = self
-            .proc
-            .processes
-            .get(&current)
-            .ok_or(SyscallError::NoProcess)?;
+        let resolved = self.resolve_path(current, path)?;
+        let path_str = resolved.to_str().ok_or(SyscallError::InvalidArgument)?;
 
-        // Check owner or root
-        let queue = self
-            .ipc
-            .msgqueues
-            .get(MsgQueueId(queue_id))
-            .ok_or(SyscallError::NotFound)?;
+        // Check write/execute permission on parent directory
+        self.check_parent_write_permission(path_str)?;
 
-        if process.euid.0 != 0 && process.euid.0 != queue.uid {
-            return Err(SyscallError::PermissionDenied);
-        }
+        // SEC-015: Check sticky bit restriction
+        self.check_sticky_bit(path_str)?;
 
-        // Non-root can only set uid/gid to themselves
-        if process.euid.0 != 0
-            && let Some(u) = uid
-            && u != process.euid.0
-            && u != queue.uid
+        let (fs, inner) = self.fs.route_mut(path_str);
+        fs.remove_dir(&inner)?;
+        Ok(())
+    }
+
+    /// Rename/move a file or directory
+    ///
+    /// For path watchpoints this unlinks `from` and writes `to`.
+    pub fn sys_rename(&mut self, from: &str, to: &str) -> SyscallResult<()> {
+        let result = self.rename_path(from, to);
+        if self.debugger.has_path_watches()
+            && let (Some(from), Some(to)) = (self.debug_resolve(from), self.debug_resolve(to))
         {
-            return Err(SyscallError::PermissionDenied);
+            for (op, path) in [(PathOp::Unlink, &from), (PathOp::Write, &to)] {
+                let args = vec![debug_path_arg("from", &from), debug_path_arg("to", &to)];
+                let ret = debug_result(&result, |_| 0);
+                self.debug_path_access(op, path, "rename", args, ret);
+            }
         }
+        result
+    }
 
-        self.ipc
-            .msgqueues
-            .msgctl_set(MsgQueueId(queue_id), uid, gid, mode, max_bytes)
-            .map_err(|_| SyscallError::NotFound)
+    /// Undo VFS changes, restoring modified and deleted paths from `image`
+    ///
+    /// The caller needs the access it would need to make each change by
+    /// hand: write on the parent directory of created and deleted paths,
+    /// and write on modified files.
+    pub fn sys_vfs_revert(
+        &mut self,
+        image: &FsSnapshot,
+        changes: &[FsChange],
+    ) -> SyscallResult<()> {
+        let changed: HashSet<&str> = changes.iter().map(|c| c.path.as_str()).collect();
+        for change in changes {
+            if change.kind == FsChangeKind::Modified {
+                self.check_file_permission(&change.path, false, true, false)?;
+            }
+            let parent_changed = Path::new(&change.path)
+                .parent()
+                .and_then(Path::to_str)
+                .is_some_and(|parent| changed.contains(parent));
+            if !parent_changed {
+                self.check_parent_write_permission(&change.path)?;
+            }
+        }
+        self.fs.vfs.revert(image, changes)?;
+        Ok(())
     }
 
-    // ========== FILE LOCKING SYSCALLS ==========
+    fn rename_path(&mut self, from: &str, to: &str) -> SyscallResult<()> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let from_resolved = self.resolve_path(current, from)?;
+        let to_resolved = self.resolve_path(current, to)?;
+        let from_str = from_resolved
+            .to_str()
+            .ok_or(SyscallError::InvalidArgument)?;
+        let to_str = to_resolved.to_str().ok_or(SyscallError::InvalidArgument)?;
 
-    /// flock - apply or remove an advisory lock on an open file
+        // Check write/execute permission on both source and destination parent directories
+        self.check_parent_write_permission(from_str)?;
+        self.check_parent_write_permission(to_str)?;
+
+        // SEC-015: Moving a file out of a sticky directory removes it from
+        // there, and replacing one removes what was there
+        self.check_sticky_bit(from_str)?;
+        let (fs, to_inner) = self.fs.route(to_str);
+        let replaced = from_str != to_str && fs.exists(&to_inner);
+        if replaced {
+            self.check_sticky_bit(to_str)?;
+        }
+
+        // Mount points can't be moved, and nothing moves between filesystems
+        if self.fs.mounts.is_attached(from_str) || self.fs.mounts.is_attached(to_str) {
+            return Err(SyscallError::Busy);
+        }
+        if !self.fs.same_filesystem(from_str, to_str) {
+            return Err(SyscallError::CrossDevice);
+        }
+        let to_inner = self.fs.route(to_str).1.into_owned();
+        let (fs, from_inner) = self.fs.route_mut(from_str);
+        replace_path(fs, &from_inner, &to_inner)?;
+        self.retarget_open_files(from_str, to_str, replaced);
+        self.reload_user_db(to_str);
+        Ok(())
+    }
+
+    /// Follow a rename of `from` to `to` with the files open under `from`,
+    /// so they write back to where they are now
     ///
-    /// # Arguments
-    /// * `fd` - File descriptor to lock
-    /// * `operation` - Lock operation:
-    ///   - 0 (LOCK_UN): Unlock
-    ///   - 1 (LOCK_SH): Shared lock
-    ///   - 2 (LOCK_EX): Exclusive lock
-    ///   - Add 4 (LOCK_NB) for non-blocking
+    /// Files open on a `to` that was replaced are gone, like unlinked
+    /// ones: what is written to them is dropped rather than written over
+    /// what replaced them.
+    fn retarget_open_files(&mut self, from: &str, to: &str, replaced: bool) {
+        let handles: Vec<_> = self
+            .fs
+            .vfs_handles
+            .iter()
+            .map(|(&h, &vh)| (h, vh))
+            .collect();
+        for (handle, vh) in handles {
+            let Some(KernelObject::File(file)) = self.objects.get_mut(handle) else {
+                continue;
+            };
+            let moved = match file.path.strip_prefix(from) {
+                Ok(rest) if rest.as_os_str().is_empty() => PathBuf::from(to),
+                Ok(rest) => Path::new(to).join(rest),
+                Err(_) if replaced && file.path == Path::new(to) => {
+                    self.fs.vfs_handles.remove(&handle);
+                    let (fs, _) = self.fs.route_mut(to);
+                    let _ = fs.close(vh);
+                    continue;
+                }
+                Err(_) => continue,
+            };
+            let path = moved.to_string_lossy().into_owned();
+            file.path = moved;
+            let (fs, inner) = self.fs.route_mut(&path);
+            let _ = fs.close(vh);
+            match fs.open(&inner, VfsOpenOptions::new().read(true)) {
+                Ok(new_vh) => self.fs.vfs_handles.insert(handle, new_vh),
+                Err(_) => self.fs.vfs_handles.remove(&handle),
+            };
+        }
+    }
+
+    /// Resolve `path` for process `pid` and follow symbolic links to what
+    /// they point at, across mounts
+    fn follow_links(&self, pid: Pid, path: &str) -> SyscallResult<String> {
+        let resolved = self.resolve_path(pid, path)?;
+        let mut path = resolved
+            .to_str()
+            .ok_or(SyscallError::InvalidArgument)?
+            .to_string();
+        for _ in 0..crate::vfs::MAX_LINK_DEPTH {
+            let (fs, inner) = self.fs.route(&path);
+            match fs.metadata(&inner) {
+                Ok(meta) if meta.is_symlink => {
+                    let target = fs.read_link(&inner)?;
+                    path = crate::vfs::link_target(&path, &target);
+                }
+                _ => break,
+            }
+        }
+        Ok(path)
+    }
+
+    /// Replace a file's contents in one step (see [`crate::vfs::atomic_write`])
     ///
-    /// # Returns
-    /// () on success
-    pub fn sys_flock(&mut self, fd: Fd, operation: i32) -> SyscallResult<()> {
+    /// Symbolic links are followed first, so permissions are checked on
+    /// the file that is replaced. That takes write permission on the file,
+    /// if it exists, and on its directory, where the temporary file goes.
+    /// A new file belongs to the caller; an existing one keeps its owner
+    /// and mode.
+    pub fn sys_atomic_write(&mut self, path: &str, data: &[u8]) -> SyscallResult<()> {
         let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        let process = self
-            .proc
-            .processes
-            .get(&current)
-            .ok_or(SyscallError::NoProcess)?;
-
-        // Get the file path from the fd
-        let path = {
-            let file = process
-                .files
-                .get(fd)
-                .and_then(|h| self.objects.get(h))
-                .ok_or(SyscallError::BadFd)?;
+        let path = self.follow_links(current, path)?;
 
-            match file {
-                KernelObject::File(f) => f.path.to_string_lossy().to_string(),
-                _ => return Err(SyscallError::BadFd),
-            }
+        // Generated and device files have no contents to swap
+        if self.fs.synthetic.lookup(&path).is_some() || path.starts_with("/dev/") {
+            return Err(SyscallError::InvalidArgument);
+        }
+        self.check_path_traversal(&path)?;
+        let (fs, inner) = self.fs.route(&path);
+        let exists = match fs.metadata(&inner) {
+            Ok(meta) if meta.is_dir => return Err(SyscallError::IsADirectory),
+            Ok(_) => true,
+            Err(_) => false,
         };
+        if exists {
+            self.check_file_permission(&path, false, true, false)?;
+            self.check_sticky_bit(&path)?;
+            if self.has_mandatory_locking(&path)
+                && self
+                    .ipc
+                    .file_locks
+                    .io_conflict(&path, current, LockType::Exclusive, 0, u64::MAX)
+                    .is_some()
+            {
+                return Err(SyscallError::WouldBlock);
+            }
+        }
+        self.check_parent_write_permission(&path)?;
 
-        // Parse operation
-        let blocking = operation & 4 == 0; // LOCK_NB = 4
-        let lock_type = match operation & 3 {
-            0 => LockType::Unlock,
-            1 => LockType::Shared,
-            2 => LockType::Exclusive,
-            _ => return Err(SyscallError::InvalidArgument),
-        };
+        let euid = self.current_euid()?;
+        let egid = self.current_egid()?;
+        let (fs, inner) = self.fs.route_mut(&path);
+        // A new file is created empty first, so its contents count
+        // against the caller's quota like an existing file's
+        if !exists {
+            let handle = fs.open(&inner, VfsOpenOptions::new().write(true).create(true))?;
+            fs.close(handle)?;
+            let _ = fs.chown(&inner, Some(euid.0), Some(egid.0));
+        }
+        let result = crate::vfs::atomic_write(fs, &inner, data);
+        if result.is_err() && !exists {
+            let _ = fs.remove_file(&inner);
+        }
+        result?;
+        self.reload_user_db(&path);
+        Ok(())
+    }
 
-        self.ipc
-            .file_locks
-            .flock(&path, current, lock_type, blocking)
-            .map_err(|e| match e {
-                LockError::WouldBlock => SyscallError::WouldBlock,
-                LockError::InvalidArgument => SyscallError::InvalidArgument,
-                LockError::Deadlock => SyscallError::WouldBlock, // Treat as WouldBlock
-            })
+    /// Copy a file
+    pub fn sys_copy_file(&mut self, from: &str, to: &str) -> SyscallResult<u64> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let from_resolved = self.resolve_path(current, from)?;
+        let to_resolved = self.resolve_path(current, to)?;
+        let from_str = from_resolved
+            .to_str()
+            .ok_or(SyscallError::InvalidArgument)?;
+        let to_str = to_resolved.to_str().ok_or(SyscallError::InvalidArgument)?;
+
+        // Check read permission on source file
+        self.check_file_permission(from_str, true, false, false)?;
+        // Check write permission on destination parent directory
+        self.check_parent_write_permission(to_str)?;
+
+        let size = self.fs.copy_file(from_str, to_str)?;
+
+        // Set ownership of new file to current user
+        let euid = self.current_euid()?;
+        let egid = self.current_egid()?;
+        let (fs, inner) = self.fs.route_mut(to_str);
+        let _ = fs.chown(&inner, Some(euid.0), Some(egid.0));
+        self.reload_user_db(to_str);
+
+        Ok(size)
     }
 
-    /// fcntl F_SETLK/F_SETLKW - set a byte-range lock
-    ///
-    /// # Arguments
-    /// * `fd` - File descriptor
-    /// * `lock_type` - 0=unlock, 1=read lock, 2=write lock
-    /// * `start` - Starting offset
-    /// * `len` - Length (0 = to end of file)
-    /// * `whence` - How to interpret start (0=SET, 1=CUR, 2=END)
-    /// * `blocking` - Wait for lock if blocked
-    ///
-    /// # Returns
-    /// () on success
-    pub fn sys_fcntl_lock(
-        &mut self,
-        fd: Fd,
-        lock_type: i32,
-        start: u64,
-        len: u64,
-        whence: i32,
-        blocking: bool,
-    ) -> SyscallResult<()> {
+    /// Create a symbolic link
+    pub fn sys_symlink(&mut self, target: &str, link_path: &str) -> SyscallResult<()> {
         let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
-        let process = self
-            .proc
-            .processes
-            .get(&current)
-            .ok_or(SyscallError::NoProcess)?;
+        let link_resolved = self.resolve_path(current, link_path)?;
+        let link_str = link_resolved
+            .to_str()
+            .ok_or(SyscallError::InvalidArgument)?;
 
-        // Get the file path from the fd
-        let path = {
-            let file = process
-                .files
-                .get(fd)
-                .and_then(|h| self.objects.get(h))
-                .ok_or(SyscallError::BadFd)?;
+        // Check write/execute permission on parent directory
+        self.check_parent_write_permission(link_str)?;
 
-            match file {
-                KernelObject::File(f) => f.path.to_string_lossy().to_string(),
-                _ => return Err(SyscallError::BadFd),
-            }
-        };
+        // Target is stored as-is (can be relative or absolute)
+        let (fs, inner) = self.fs.route_mut(link_str);
+        fs.symlink(target, &inner)?;
 
-        let lt = match lock_type {
-            0 => LockType::Unlock,
-            1 => LockType::Shared,
-            2 => LockType::Exclusive,
-            _ => return Err(SyscallError::InvalidArgument),
-        };
+        // Set ownership of symlink to current user
+        let euid = self.current_euid()?;
+        let egid = self.current_egid()?;
+        let (fs, inner) = self.fs.route_mut(link_str);
+        let _ = fs.chown(&inner, Some(euid.0), Some(egid.0));
 
-        let lock = RangeLock {
-            pid: current,
-            lock_type: lt,
-            start,
-            len,
-            whence,
-        };
+        Ok(())
+    }
 
-        self.ipc
-            .file_locks
-            .fcntl_lock(&path, current, lock, blocking)
-            .map_err(|e| match e {
-                LockError::WouldBlock => SyscallError::WouldBlock,
-                LockError::InvalidArgument => SyscallError::InvalidArgument,
-                LockError::Deadlock => SyscallError::WouldBlock,
-            })
+    /// Read the target of a symbolic link
+    pub fn sys_read_link(&self, path: &str) -> SyscallResult<String> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let resolved = self.resolve_path(current, path)?;
+        let path_str = resolved.to_str().ok_or(SyscallError::InvalidArgument)?;
+        let (fs, inner) = self.fs.route(path_str);
+        let target = fs.read_link(&inner)?;
+        Ok(target)
     }
 
-    /// fcntl F_GETLK - test if a lock can be placed
-    ///
-    /// Returns information about a conflicting lock, or the input lock
-    /// with type set to UNLOCK if no conflict.
-    pub fn sys_fcntl_getlk(
-        &self,
-        fd: Fd,
-        lock_type: i32,
-        start: u64,
-        len: u64,
-        whence: i32,
-    ) -> SyscallResult<(i32, Pid, u64, u64)> {
+    // ========== MEMORY SYSCALLS ==========
+
+    /// Allocate a memory region for the current process
+    pub fn sys_alloc(&mut self, size: usize, prot: Protection) -> SyscallResult<RegionId> {
         let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
         let process = self
             .proc
             .processes
-            .get(&current)
+            .get_mut(&current)
             .ok_or(SyscallError::NoProcess)?;
 
-        // Get the file path from the fd
-        let path = {
-            let file = process
+        let region_id = self.memory.alloc_region_id();
+        process.memory.allocate(region_id, size, prot)?;
+
+        Ok(region_id)
+    }
+
+    /// Free a memory region
+    pub fn sys_free(&mut self, region_id: RegionId) -> SyscallResult<()> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let process = self
+            .proc
+            .processes
+            .get_mut(&current)
+            .ok_or(SyscallError::NoProcess)?;
+
+        process.memory.free(region_id)?;
+        Ok(())
+    }
+
+    /// Read from a memory region
+    pub fn sys_mem_read(
+        &mut self,
+        region_id: RegionId,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> SyscallResult<usize> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let process = self
+            .proc
+            .processes
+            .get_mut(&current)
+            .ok_or(SyscallError::NoProcess)?;
+
+        let region = process
+            .memory
+            .get(region_id)
+            .ok_or(SyscallError::Memory(MemoryError::InvalidRegion))?;
+
+        Ok(region.read(offset, buf)?)
+    }
+
+    /// Write to a memory region
+    pub fn sys_mem_write(
+        &mut self,
+        region_id: RegionId,
+        offset: usize,
+        buf: &[u8],
+    ) -> SyscallResult<usize> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let process = self
+            .proc
+            .processes
+            .get_mut(&current)
+            .ok_or(SyscallError::NoProcess)?;
+
+        let region = process
+            .memory
+            .get_mut(region_id)
+            .ok_or(SyscallError::Memory(MemoryError::InvalidRegion))?;
+
+        Ok(region.write(offset, buf)?)
+    }
+
+    /// Create a shared memory segment
+    pub fn sys_shmget(&mut self, size: usize) -> SyscallResult<ShmId> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        Ok(self.memory.shmget(size, current)?)
+    }
+
+    /// Attach to a shared memory segment
+    pub fn sys_shmat(&mut self, shm_id: ShmId, prot: Protection) -> SyscallResult<RegionId> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+
+        // Get the shared memory region from the manager
+        let region = self.memory.shmat(shm_id, current, prot)?;
+        let region_id = region.id;
+
+        // Attach to the process memory
+        let process = self
+            .proc
+            .processes
+            .get_mut(&current)
+            .ok_or(SyscallError::NoProcess)?;
+        process.memory.attach_shm(shm_id, region)?;
+
+        Ok(region_id)
+    }
+
+    /// Detach from a shared memory segment
+    pub fn sys_shmdt(&mut self, shm_id: ShmId) -> SyscallResult<()> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+
+        // Sync changes back to shared memory before detaching
+        let process = self
+            .proc
+            .processes
+            .get_mut(&current)
+            .ok_or(SyscallError::NoProcess)?;
+
+        // Get the region data before detaching
+        if let Some(region_id) = process.memory.shm_region(shm_id)
+            && let Some(region) = process.memory.get(region_id)
+        {
+            let data = region.as_slice().to_vec();
+            self.memory.shm_sync(shm_id, &data)?;
+        }
+
+        // Detach from process memory
+        process.memory.detach_shm(shm_id)?;
+
+        // Detach from global shared memory (may remove if refcount hits 0)
+        self.memory.shmdt(shm_id, current)?;
+
+        Ok(())
+    }
+
+    /// Sync shared memory region (write local changes to shared segment)
+    pub fn sys_shm_sync(&mut self, shm_id: ShmId) -> SyscallResult<()> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let process = self
+            .proc
+            .processes
+            .get(&current)
+            .ok_or(SyscallError::NoProcess)?;
+
+        // Get the region data
+        let region_id = process
+            .memory
+            .shm_region(shm_id)
+            .ok_or(SyscallError::Memory(MemoryError::NotAttached))?;
+
+        let region = process
+            .memory
+            .get(region_id)
+            .ok_or(SyscallError::Memory(MemoryError::InvalidRegion))?;
+
+        let data = region.as_slice().to_vec();
+        self.memory.shm_sync(shm_id, &data)?;
+
+        Ok(())
+    }
+
+    /// Refresh local shared memory region from shared segment
+    pub fn sys_shm_refresh(&mut self, shm_id: ShmId) -> SyscallResult<()> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+
+        // Get the latest shared data
+        let data = self.memory.shm_read(shm_id)?.to_vec();
+
+        // Update local region
+        let process = self
+            .proc
+            .processes
+            .get_mut(&current)
+            .ok_or(SyscallError::NoProcess)?;
+        let region_id = process
+            .memory
+            .shm_region(shm_id)
+            .ok_or(SyscallError::Memory(MemoryError::NotAttached))?;
+
+        let region = process
+            .memory
+            .get_mut(region_id)
+            .ok_or(SyscallError::Memory(MemoryError::InvalidRegion))?;
+
+        region.write(0, &data)?;
+        Ok(())
+    }
+
+    /// Get shared memory info
+    pub fn sys_shm_info(&self, shm_id: ShmId) -> SyscallResult<ShmInfo> {
+        Ok(self.memory.shm_info(shm_id)?)
+    }
+
+    /// List all shared memory segments
+    pub fn sys_shm_list(&self) -> SyscallResult<Vec<ShmInfo>> {
+        Ok(self.memory.shm_list())
+    }
+
+    /// Get memory stats for current process
+    pub fn sys_memstats(&self) -> SyscallResult<MemoryStats> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let process = self
+            .proc
+            .processes
+            .get(&current)
+            .ok_or(SyscallError::NoProcess)?;
+        Ok(process.memory.stats())
+    }
+
+    /// Set memory limit for current process
+    pub fn sys_set_memlimit(&mut self, limit: usize) -> SyscallResult<()> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let process = self
+            .proc
+            .processes
+            .get_mut(&current)
+            .ok_or(SyscallError::NoProcess)?;
+        process.memory.set_limit(limit);
+        Ok(())
+    }
+
+    /// Get system-wide memory stats
+    pub fn sys_system_memstats(&self) -> SyscallResult<SystemMemoryStats> {
+        Ok(self.memory.system_stats())
+    }
+
+    // ========== TIMER SYSCALLS ==========
+
+    /// Get current kernel time
+    pub fn sys_now(&self) -> f64 {
+        self.time.now
+    }
+
+    /// Set current kernel time (called from runtime with rAF timestamp)
+    pub fn set_time(&mut self, now: f64) {
+        self.time.now = now;
+    }
+
+    /// Schedule a one-shot timer
+    pub fn sys_timer_set(
+        &mut self,
+        delay_ms: f64,
+        wake_task: Option<TaskId>,
+    ) -> SyscallResult<TimerId> {
+        if delay_ms < 0.0 {
+            return Err(SyscallError::InvalidArgument);
+        }
+        Ok(self
+            .time
+            .timers
+            .schedule(delay_ms, self.time.now, wake_task))
+    }
+
+    /// Schedule a repeating interval timer
+    pub fn sys_timer_interval(
+        &mut self,
+        interval_ms: f64,
+        wake_task: Option<TaskId>,
+    ) -> SyscallResult<TimerId> {
+        if interval_ms <= 0.0 {
+            return Err(SyscallError::InvalidArgument);
+        }
+        Ok(self
+            .time
+            .timers
+            .schedule_interval(interval_ms, self.time.now, wake_task))
+    }
+
+    /// Cancel a timer
+    pub fn sys_timer_cancel(&mut self, timer_id: TimerId) -> SyscallResult<bool> {
+        Ok(self.time.timers.cancel(timer_id))
+    }
+
+    /// Check if a timer is pending
+    pub fn sys_timer_pending(&self, timer_id: TimerId) -> SyscallResult<bool> {
+        Ok(self.time.timers.is_pending(timer_id))
+    }
+
+    /// Get time until next timer fires (for sleep optimization)
+    pub fn time_until_next_timer(&self) -> Option<f64> {
+        self.time.timers.time_until_next(self.time.now)
+    }
+
+    /// Get pending timer count
+    pub fn pending_timer_count(&self) -> usize {
+        self.time.timers.pending_count()
+    }
+
+    /// Apply the restart policy to services that missed their watchdog
+    /// deadline, appending each incident to [`WATCHDOG_LOG`] and the trace
+    pub fn check_watchdogs(&mut self) -> Vec<WatchdogIncident> {
+        let now = self.time.now;
+        let incidents = self.init.check_watchdogs(now);
+        if incidents.is_empty() {
+            return incidents;
+        }
+
+        let mut log =
+            crate::vfs::read_to_string(&mut self.fs.vfs, WATCHDOG_LOG).unwrap_or_default();
+        for incident in &incidents {
+            let line = incident.to_line();
+            log.push_str(&line);
+            log.push('\n');
+            self.log
+                .log(now, LogLevel::Warn, "watchdog", line.trim_start());
+            let mut event = TraceEvent::with_detail(now, TraceCategory::Process, "watchdog", line);
+            if let Some(pid) = incident.pid {
+                event = event.with_pid(pid);
+            }
+            self.tracer.trace(event);
+        }
+        if !self.fs.vfs.exists("/var/log") {
+            let _ = self.fs.vfs.create_dir("/var/log");
+        }
+        let _ = crate::vfs::write_string(&mut self.fs.vfs, WATCHDOG_LOG, &log);
+        incidents
+    }
+
+    /// Tick timers, returning tasks to wake
+    pub fn tick_timers(&mut self) -> Vec<TaskId> {
+        for origin in self.netperm.expire(self.time.now) {
+            let message = format!("no answer about {}; refused", origin);
+            self.klog(LogLevel::Warn, "netperm", &message);
+        }
+        self.time.timers.tick(self.time.now)
+    }
+
+    /// Set an alarm for the current process (SIGALRM after delay)
+    pub fn sys_alarm(&mut self, delay_ms: f64) -> SyscallResult<TimerId> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let process = self
+            .proc
+            .processes
+            .get(&current)
+            .ok_or(SyscallError::NoProcess)?;
+        let task = process.task;
+        self.sys_timer_set(delay_ms, task)
+    }
+
+    // ========== SIGNAL SYSCALLS ==========
+
+    /// Send a signal to a process
+    ///
+    /// Permission rules:
+    /// - A process can signal itself
+    /// - Root (UID 0) can signal any process
+    /// - CAP_KILL allows signaling any process
+    /// - Same real or effective UID can signal a process
+    pub fn sys_kill(&mut self, pid: Pid, signal: Signal) -> SyscallResult<()> {
+        // A signal interrupts a poll
+        self.wake_pollers();
+        // Get current process info for permission check
+        let current_pid = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let current = self
+            .proc
+            .processes
+            .get(&current_pid)
+            .ok_or(SyscallError::NoProcess)?;
+
+        let current_euid = current.euid;
+        let current_uid = current.uid;
+        let has_cap_kill = current.capabilities.has_effective(Capability::Kill);
+
+        // Get target process
+        let target = self
+            .proc
+            .processes
+            .get_mut(&pid)
+            .ok_or(SyscallError::NoProcess)?;
+
+        // Can't signal zombies
+        if matches!(target.state, ProcessState::Zombie(_)) {
+            return Err(SyscallError::NoProcess);
+        }
+
+        // Permission check
+        let can_signal = current_pid == pid  // Can signal self
+            || current_euid == Uid::ROOT      // Root can signal anyone
+            || has_cap_kill                    // CAP_KILL can signal anyone
+            || current_euid == target.euid     // Same effective UID
+            || current_uid == target.uid; // Same real UID
+
+        if !can_signal {
+            return Err(SyscallError::PermissionDenied);
+        }
+
+        // Queue the signal
+        target.signals.send(signal);
+
+        // Wake the target so a sleep it is blocked in sees the interruption
+        if target.signals.interrupting().is_some()
+            && let Some(task) = target.task
+        {
+            self.time.timers.schedule(0.0, self.time.now, Some(task));
+        }
+
+        Ok(())
+    }
+
+    /// Set signal handler for current process
+    pub fn sys_signal(
+        &mut self,
+        signal: Signal,
+        action: SignalAction,
+    ) -> SyscallResult<SignalAction> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let process = self
+            .proc
+            .processes
+            .get_mut(&current)
+            .ok_or(SyscallError::NoProcess)?;
+
+        let old_action = process.signals.disposition.get_action(signal);
+        process.signals.disposition.set_action(signal, action)?;
+
+        Ok(old_action)
+    }
+
+    /// Block a signal for current process
+    pub fn sys_sigblock(&mut self, signal: Signal) -> SyscallResult<()> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let process = self
+            .proc
+            .processes
+            .get_mut(&current)
+            .ok_or(SyscallError::NoProcess)?;
+        process.signals.block(signal)?;
+        Ok(())
+    }
+
+    /// Unblock a signal for current process
+    pub fn sys_sigunblock(&mut self, signal: Signal) -> SyscallResult<()> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let process = self
+            .proc
+            .processes
+            .get_mut(&current)
+            .ok_or(SyscallError::NoProcess)?;
+        process.signals.unblock(signal);
+        Ok(())
+    }
+
+    /// signalfd - receive signals as readable records
+    ///
+    /// With `fd` of `None`, creates a descriptor; otherwise changes the mask
+    /// of an existing signalfd. Reads return one [`SIGNALFD_RECORD_SIZE`]
+    /// record per pending signal in `mask` (bit N = signal N), removing it,
+    /// and `WouldBlock` when none is pending. Signals read this way are
+    /// usually blocked first so they aren't also delivered; SIGKILL and
+    /// SIGSTOP are dropped from the mask.
+    pub fn sys_signalfd(&mut self, fd: Option<Fd>, mask: u16) -> SyscallResult<Fd> {
+        let mask = mask & !(1 << Signal::SIGKILL.num()) & !(1 << Signal::SIGSTOP.num());
+        if let Some(fd) = fd {
+            let handle = self.get_handle(fd)?;
+            return match self.objects.get_mut(handle) {
+                Some(KernelObject::SignalFd(sfd)) => {
+                    sfd.mask = mask;
+                    Ok(fd)
+                }
+                _ => Err(SyscallError::InvalidArgument),
+            };
+        }
+        let handle = self
+            .objects
+            .insert(KernelObject::SignalFd(SignalFdObject { mask }));
+        let process = self.get_current_process_mut()?;
+        process
+            .files
+            .alloc(handle)
+            .ok_or(SyscallError::TooManyOpenFiles)
+    }
+
+    /// Open the watchdog of `service`; each write to it (or
+    /// `sys_watchdog_pet`) tells init the service is alive
+    ///
+    /// Only root and the service's main process may open it, and only if
+    /// the service has a watchdog timeout.
+    pub fn sys_watchdog_open(&mut self, service: &str) -> SyscallResult<Fd> {
+        let process = self.get_current_process()?;
+        let (pid, euid) = (process.pid, process.euid);
+        let svc = self
+            .init
+            .get_service(service)
+            .ok_or(SyscallError::NotFound)?;
+        if euid != Uid::ROOT && svc.pid != Some(pid.0) {
+            return Err(SyscallError::PermissionDenied);
+        }
+        if svc.config.watchdog_ms.is_none() {
+            return Err(SyscallError::InvalidArgument);
+        }
+
+        let handle = self.objects.insert(KernelObject::Watchdog(WatchdogObject {
+            service: service.to_string(),
+        }));
+        let process = self.get_current_process_mut()?;
+        process
+            .files
+            .alloc(handle)
+            .ok_or(SyscallError::TooManyOpenFiles)
+    }
+
+    /// Pet the watchdog open on `fd`
+    pub fn sys_watchdog_pet(&mut self, fd: Fd) -> SyscallResult<()> {
+        let handle = self.get_handle(fd)?;
+        let service = match self.objects.get(handle) {
+            Some(KernelObject::Watchdog(w)) => w.service.clone(),
+            _ => return Err(SyscallError::InvalidArgument),
+        };
+        self.pet_watchdog(&service)
+    }
+
+    /// Pet a service's watchdog; fails if it isn't running
+    fn pet_watchdog(&mut self, service: &str) -> SyscallResult<()> {
+        self.init
+            .pet_watchdog(service, self.time.now)
+            .map_err(|_| SyscallError::InvalidArgument)
+    }
+
+    /// The pending signal that interrupts the current process's blocking
+    /// calls, if any
+    ///
+    /// Blocking calls (sleeps, `waitpid`, reads from an empty pipe) return
+    /// [`SyscallError::Interrupted`] while one is pending, like EINTR.
+    pub fn interrupting_signal(&self) -> Option<Signal> {
+        let current = self.proc.current?;
+        self.proc.processes.get(&current)?.signals.interrupting()
+    }
+
+    /// Check if current process has pending signals
+    pub fn sys_sigpending(&self) -> SyscallResult<bool> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let process = self
+            .proc
+            .processes
+            .get(&current)
+            .ok_or(SyscallError::NoProcess)?;
+        Ok(process.signals.has_pending())
+    }
+
+    /// Get pending signals as a bitmask
+    pub fn sys_sigpending_mask(&self) -> SyscallResult<u16> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let process = self
+            .proc
+            .processes
+            .get(&current)
+            .ok_or(SyscallError::NoProcess)?;
+        Ok(process.signals.get_pending_mask())
+    }
+
+    /// sigprocmask - examine and change blocked signals
+    ///
+    /// # Arguments
+    /// * `how` - How to modify the mask (Block, Unblock, SetMask)
+    /// * `mask` - Bitmask of signals (bit N = signal N)
+    ///
+    /// # Returns
+    /// The old signal mask before modification
+    pub fn sys_sigprocmask(&mut self, how: SigProcMaskHow, mask: u16) -> SyscallResult<u16> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let process = self
+            .proc
+            .processes
+            .get_mut(&current)
+            .ok_or(SyscallError::NoProcess)?;
+        Ok(process.signals.sigprocmask(how, mask))
+    }
+
+    /// Get current signal mask
+    pub fn sys_siggetmask(&self) -> SyscallResult<u16> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let process = self
+            .proc
+            .processes
+            .get(&current)
+            .ok_or(SyscallError::NoProcess)?;
+        Ok(process.signals.get_blocked_mask())
+    }
+
+    // ========== Process Priority ==========
+
+    /// nice - adjust process scheduling priority
+    ///
+    /// Adds the increment to the current nice value (clamped to -20..19).
+    /// Returns the new nice value.
+    ///
+    /// Note: In real POSIX, only root can decrease nice (increase priority).
+    /// We allow any user to adjust priority for simplicity.
+    pub fn sys_nice(&mut self, increment: i8) -> SyscallResult<i8> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let process = self
+            .proc
+            .processes
+            .get_mut(&current)
+            .ok_or(SyscallError::NoProcess)?;
+
+        let new_nice = (process.nice as i16 + increment as i16).clamp(-20, 19) as i8;
+        process.nice = new_nice;
+        Ok(new_nice)
+    }
+
+    /// getpriority - get process scheduling priority
+    ///
+    /// Returns the nice value for the specified process.
+    /// Use pid=0 for current process.
+    pub fn sys_getpriority(&self, pid: Pid) -> SyscallResult<i8> {
+        let target_pid = if pid.0 == 0 {
+            self.proc.current.ok_or(SyscallError::NoProcess)?
+        } else {
+            pid
+        };
+
+        let process = self
+            .proc
+            .processes
+            .get(&target_pid)
+            .ok_or(SyscallError::NoProcess)?;
+
+        Ok(process.nice)
+    }
+
+    /// setpriority - set process scheduling priority
+    ///
+    /// Sets the nice value for the specified process.
+    /// Use pid=0 for current process.
+    ///
+    /// Note: In real POSIX, only root can decrease nice (increase priority).
+    /// We allow any user to adjust priority for simplicity.
+    pub fn sys_setpriority(&mut self, pid: Pid, priority: i8) -> SyscallResult<()> {
+        let target_pid = if pid.0 == 0 {
+            self.proc.current.ok_or(SyscallError::NoProcess)?
+        } else {
+            pid
+        };
+
+        let process = self
+            .proc
+            .processes
+            .get_mut(&target_pid)
+            .ok_or(SyscallError::NoProcess)?;
+
+        process.nice = priority.clamp(-20, 19);
+        Ok(())
+    }
+
+    /// Process pending signals for a process, returns action to take
+    pub fn process_signals(&mut self, pid: Pid) -> Option<(Signal, SignalAction)> {
+        let process = self.proc.processes.get_mut(&pid)?;
+
+        // Get next pending signal
+        let signal = process.signals.next_pending()?;
+
+        // Resolve the action
+        let action = resolve_action(signal, &process.signals.disposition);
+
+        // Apply immediate actions
+        match action {
+            SignalAction::Kill | SignalAction::Terminate => {
+                process.state = ProcessState::Zombie(-(signal.num() as i32));
+                self.release_ipc(pid);
+            }
+            SignalAction::Stop => {
+                process.state = ProcessState::Stopped;
+                process.signals.stop();
+            }
+            SignalAction::Continue => {
+                if process.state == ProcessState::Stopped {
+                    process.state = ProcessState::Running;
+                    process.was_continued = true; // Set flag for WCONTINUED
+                }
+                process.signals.cont();
+            }
+            SignalAction::Ignore => {
+                // Nothing to do
+            }
+            SignalAction::Handle | SignalAction::Default => {
+                // Handler will be called by caller
+            }
+        }
+
+        Some((signal, action))
+    }
+
+    // ========== MESSAGE QUEUE SYSCALLS ==========
+
+    /// msgget - get or create a message queue
+    ///
+    /// # Arguments
+    /// * `key` - Queue key. Negative = create private queue, non-negative = get/create by key
+    /// * `create` - If true, create queue if it doesn't exist
+    ///
+    /// # Returns
+    /// Queue ID on success
+    pub fn sys_msgget(&mut self, key: i32, create: bool) -> SyscallResult<u32> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let process = self
+            .proc
+            .processes
+            .get(&current)
+            .ok_or(SyscallError::NoProcess)?;
+
+        let uid = process.euid.0;
+        let gid = process.egid.0;
+
+        let id = self
+            .ipc
+            .msgqueues
+            .msgget(key, uid, gid, create)
+            .map_err(|e| match e {
+                MsgQueueError::NotFound => SyscallError::NotFound,
+                MsgQueueError::AlreadyExists => SyscallError::AlreadyExists,
+                _ => SyscallError::Io("message queue error".into()),
+            })?;
+
+        Ok(id.0)
+    }
+
+    /// msgsnd - send a message to a queue
+    ///
+    /// # Arguments
+    /// * `queue_id` - Queue identifier
+    /// * `mtype` - Message type (must be > 0)
+    /// * `data` - Message data
+    ///
+    /// # Returns
+    /// () on success
+    pub fn sys_msgsnd(&mut self, queue_id: u32, mtype: i64, data: Vec<u8>) -> SyscallResult<()> {
+        use super::msgqueue::Message;
+
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let process = self
+            .proc
+            .processes
+            .get(&current)
+            .ok_or(SyscallError::NoProcess)?;
+
+        // Check write permission
+        let queue = self
+            .ipc
+            .msgqueues
+            .get(MsgQueueId(queue_id))
+            .ok_or(SyscallError::NotFound)?;
+
+        if process.euid.0 != 0 && process.euid.0 != queue.uid {
+            // Check mode for write permission
+            let can_write = if process.euid.0 == queue.uid {
+                queue.mode & 0o200 != 0
+            } else if process.egid.0 == queue.gid {
+                queue.mode & 0o020 != 0
+            } else {
+                queue.mode & 0o002 != 0
+            };
+            if !can_write {
+                return Err(SyscallError::PermissionDenied);
+            }
+        }
+
+        let now = self.time.now;
+        let msg = Message::new(mtype, data);
+
+        self.ipc
+            .msgqueues
+            .msgsnd(MsgQueueId(queue_id), msg, now)
+            .map_err(|e| match e {
+                MsgQueueError::InvalidType => SyscallError::InvalidArgument,
+                MsgQueueError::QueueFull => SyscallError::WouldBlock,
+                MsgQueueError::NotFound => SyscallError::NotFound,
+                _ => SyscallError::Io("message queue error".into()),
+            })
+    }
+
+    /// msgrcv - receive a message from a queue
+    ///
+    /// # Arguments
+    /// * `queue_id` - Queue identifier
+    /// * `mtype` - Message type selector:
+    ///   - 0: receive first message
+    ///   - >0: receive first message with matching type
+    ///   - <0: receive first message with type <= |mtype|
+    ///
+    /// # Returns
+    /// (mtype, data) on success
+    pub fn sys_msgrcv(&mut self, queue_id: u32, mtype: i64) -> SyscallResult<(i64, Vec<u8>)> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let process = self
+            .proc
+            .processes
+            .get(&current)
+            .ok_or(SyscallError::NoProcess)?;
+
+        // Check read permission
+        let queue = self
+            .ipc
+            .msgqueues
+            .get(MsgQueueId(queue_id))
+            .ok_or(SyscallError::NotFound)?;
+
+        if process.euid.0 != 0 && process.euid.0 != queue.uid {
+            // Check mode for read permission
+            let can_read = if process.euid.0 == queue.uid {
+                queue.mode & 0o400 != 0
+            } else if process.egid.0 == queue.gid {
+                queue.mode & 0o040 != 0
+            } else {
+                queue.mode & 0o004 != 0
+            };
+            if !can_read {
+                return Err(SyscallError::PermissionDenied);
+            }
+        }
+
+        let now = self.time.now;
+
+        let msg = self
+            .ipc
+            .msgqueues
+            .msgrcv(MsgQueueId(queue_id), mtype, now)
+            .map_err(|e| match e {
+                MsgQueueError::NoMessage => SyscallError::WouldBlock,
+                MsgQueueError::NotFound => SyscallError::NotFound,
+                _ => SyscallError::Io("message queue error".into()),
+            })?;
+
+        Ok((msg.mtype, msg.data))
+    }
+
+    /// msgctl - message queue control operations
+    ///
+    /// # Arguments
+    /// * `queue_id` - Queue identifier
+    /// * `cmd` - Command:
+    ///   - 0 (IPC_RMID): Remove queue
+    ///   - 1 (IPC_STAT): Get queue stats (returns via separate method)
+    ///   - 2 (IPC_SET): Set queue attributes
+    /// * `uid`, `gid`, `mode`, `max_bytes` - Values for IPC_SET
+    ///
+    /// # Returns
+    /// () on success, stats for IPC_STAT
+    pub fn sys_msgctl_rmid(&mut self, queue_id: u32) -> SyscallResult<()> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let process = self
+            .proc
+            .processes
+            .get(&current)
+            .ok_or(SyscallError::NoProcess)?;
+
+        // Check owner or root
+        let queue = self
+            .ipc
+            .msgqueues
+            .get(MsgQueueId(queue_id))
+            .ok_or(SyscallError::NotFound)?;
+
+        if process.euid.0 != 0 && process.euid.0 != queue.uid {
+            return Err(SyscallError::PermissionDenied);
+        }
+
+        self.ipc
+            .msgqueues
+            .msgctl_rmid(MsgQueueId(queue_id))
+            .map_err(|_| SyscallError::NotFound)
+    }
+
+    /// msgctl IPC_STAT - get queue statistics
+    pub fn sys_msgctl_stat(&self, queue_id: u32) -> SyscallResult<MsgQueueStats> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let process = self
+            .proc
+            .processes
+            .get(&current)
+            .ok_or(SyscallError::NoProcess)?;
+
+        // Check read permission
+        let queue = self
+            .ipc
+            .msgqueues
+            .get(MsgQueueId(queue_id))
+            .ok_or(SyscallError::NotFound)?;
+
+        if process.euid.0 != 0 && process.euid.0 != queue.uid {
+            let can_read = if process.euid.0 == queue.uid {
+                queue.mode & 0o400 != 0
+            } else if process.egid.0 == queue.gid {
+                queue.mode & 0o040 != 0
+            } else {
+                queue.mode & 0o004 != 0
+            };
+            if !can_read {
+                return Err(SyscallError::PermissionDenied);
+            }
+        }
+
+        self.ipc
+            .msgqueues
+            .msgctl_stat(MsgQueueId(queue_id))
+            .map_err(|_| SyscallError::NotFound)
+    }
+
+    /// msgctl IPC_SET - set queue attributes
+    pub fn sys_msgctl_set(
+        &mut self,
+        queue_id: u32,
+        uid: Option<u32>,
+        gid: Option<u32>,
+        mode: Option<u16>,
+        max_bytes: Option<usize>,
+    ) -> SyscallResult<()> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let process = self
+            .proc
+            .processes
+            .get(&current)
+            .ok_or(SyscallError::NoProcess)?;
+
+        // Check owner or root
+        let queue = self
+            .ipc
+            .msgqueues
+            .get(MsgQueueId(queue_id))
+            .ok_or(SyscallError::NotFound)?;
+
+        if process.euid.0 != 0 && process.euid.0 != queue.uid {
+            return Err(SyscallError::PermissionDenied);
+        }
+
+        // Non-root can only set uid/gid to themselves
+        if process.euid.0 != 0
+            && let Some(u) = uid
+            && u != process.euid.0
+            && u != queue.uid
+        {
+            return Err(SyscallError::PermissionDenied);
+        }
+
+        self.ipc
+            .msgqueues
+            .msgctl_set(MsgQueueId(queue_id), uid, gid, mode, max_bytes)
+            .map_err(|_| SyscallError::NotFound)
+    }
+
+    /// semctl IPC_RMID - remove a semaphore set (owner or root)
+    pub fn sys_semctl_rmid(&mut self, sem_id: u32) -> SyscallResult<()> {
+        let process = self.get_current_process()?;
+        let set = self
+            .ipc
+            .semaphores
+            .get_set(SemId(sem_id))
+            .ok_or(SyscallError::NotFound)?;
+
+        if process.euid.0 != 0 && process.euid.0 != set.uid {
+            return Err(SyscallError::PermissionDenied);
+        }
+
+        self.ipc
+            .semaphores
+            .semctl_rmid(SemId(sem_id))
+            .map_err(|_| SyscallError::NotFound)
+    }
+
+    /// semget - get or create a set of `nsems` semaphores
+    ///
+    /// A negative key creates a private set. Returns the set id.
+    pub fn sys_semget(&mut self, key: i32, nsems: usize, create: bool) -> SyscallResult<u32> {
+        let process = self.get_current_process()?;
+        let (uid, gid) = (process.euid.0, process.egid.0);
+
+        let id = self
+            .ipc
+            .semaphores
+            .semget(key, nsems, uid, gid, create, self.time.now)
+            .map_err(SyscallError::from)?;
+        Ok(id.0)
+    }
+
+    /// semop - add `sem_op` to semaphore `sem_num` of a set
+    ///
+    /// A negative `sem_op` takes from the semaphore and zero waits for it to
+    /// reach zero. With `SEM_UNDO` in `flags` the operation is reversed when
+    /// the process exits. An operation that can't complete fails with
+    /// `WouldBlock`; without `IPC_NOWAIT` the process is then waiting for
+    /// the processes that took from the semaphore with `SEM_UNDO`, and
+    /// `Deadlock` is returned instead if one of them waits for it.
+    pub fn sys_semop(
+        &mut self,
+        sem_id: u32,
+        sem_num: usize,
+        sem_op: i32,
+        flags: i32,
+    ) -> SyscallResult<()> {
+        let process = self.get_current_process()?;
+        let (pid, euid, egid) = (process.pid, process.euid.0, process.egid.0);
+
+        let set = self
+            .ipc
+            .semaphores
+            .get_set(SemId(sem_id))
+            .ok_or(SyscallError::NotFound)?;
+        let can_alter = if euid == 0 {
+            true
+        } else if euid == set.uid {
+            set.mode & 0o200 != 0
+        } else if egid == set.gid {
+            set.mode & 0o020 != 0
+        } else {
+            set.mode & 0o002 != 0
+        };
+        if !can_alter {
+            return Err(SyscallError::PermissionDenied);
+        }
+
+        self.ipc.waits.clear(pid);
+        let result = self
+            .ipc
+            .semaphores
+            .semop_with_undo(
+                SemId(sem_id),
+                sem_num,
+                sem_op,
+                pid.0,
+                self.time.now,
+                flags & SEM_UNDO != 0,
+            )
+            .map_err(SyscallError::from)?;
+        if result == SemOpResult::Completed {
+            return Ok(());
+        }
+
+        if flags & IPC_NOWAIT == 0 && sem_op < 0 {
+            let holders: Vec<Pid> = self
+                .ipc
+                .semaphores
+                .holders(SemId(sem_id), sem_num)
+                .into_iter()
+                .map(Pid)
+                .collect();
+            self.wait_for(
+                pid,
+                WaitResource::Semaphore(SemId(sem_id), sem_num),
+                &holders,
+            )?;
+        }
+        Err(SyscallError::WouldBlock)
+    }
+
+    // ========== DEADLOCK DETECTION ==========
+
+    /// Record that `pid` waits on `resource` until `holders` act
+    ///
+    /// If that would close a cycle of waits, the cycle is traced and
+    /// `Deadlock` returned; `pid` is then no longer waiting.
+    fn wait_for(&mut self, pid: Pid, resource: WaitResource, holders: &[Pid]) -> SyscallResult<()> {
+        let now = self.time.now;
+        self.ipc
+            .waits
+            .wait(pid, resource, holders, now)
+            .map_err(|report| {
+                let detail = format!("deadlock avoided: {}", report);
+                self.log.log(now, LogLevel::Warn, "ipc", &detail);
+                self.tracer.trace(
+                    TraceEvent::with_detail(
+                        now,
+                        TraceCategory::Ipc,
+                        "deadlock",
+                        report.to_string(),
+                    )
+                    .with_pid(pid.0),
+                );
+                SyscallError::Deadlock
+            })
+    }
+
+    /// Record that `pid`, the process of a bound channel half, waits on
+    /// channel `channel` for `holders`
+    pub fn channel_wait(&mut self, pid: Pid, channel: u64, holders: &[Pid]) -> SyscallResult<()> {
+        self.wait_for(pid, WaitResource::Channel(channel), holders)
+    }
+
+    /// Clear `pid`'s wait if it is on channel `channel`
+    pub fn channel_done(&mut self, pid: Pid, channel: u64) {
+        if self.ipc.waits.waiting_on(pid) == Some(&WaitResource::Channel(channel)) {
+            self.ipc.waits.clear(pid);
+        }
+    }
+
+    /// The wait-for graph
+    pub fn waits(&self) -> &WaitForGraph {
+        &self.ipc.waits
+    }
+
+    /// Recently detected deadlocks, oldest first
+    pub fn deadlocks(&self) -> &VecDeque<DeadlockReport> {
+        self.ipc.waits.reports()
+    }
+
+    // ========== KERNEL LOG SYSCALLS ==========
+
+    /// Add a message to the kernel log
+    pub fn klog(&mut self, level: LogLevel, facility: &str, message: &str) {
+        self.log.log(self.time.now, level, facility, message);
+    }
+
+    /// The kernel log, oldest first
+    pub fn sys_klog_read(&self) -> Vec<LogEntry> {
+        self.log.entries().iter().cloned().collect()
+    }
+
+    /// Empty the kernel log (root only, like `dmesg -C`)
+    pub fn sys_klog_clear(&mut self) -> SyscallResult<()> {
+        if self.get_current_process()?.euid != Uid::ROOT {
+            return Err(SyscallError::PermissionDenied);
+        }
+        self.log.clear();
+        Ok(())
+    }
+
+    // ========== DISK QUOTA SYSCALLS ==========
+
+    /// Disk usage and limit for `uid` (root or CAP_SYS_ADMIN for others)
+    pub fn sys_quota(&self, uid: Uid) -> SyscallResult<DiskQuota> {
+        let process = self.get_current_process()?;
+        if uid != process.uid
+            && uid != process.euid
+            && process.euid != Uid::ROOT
+            && !process.capabilities.has_effective(Capability::SysAdmin)
+        {
+            return Err(SyscallError::PermissionDenied);
+        }
+        Ok(DiskQuota {
+            uid,
+            used: self.fs.vfs.usage(uid.0),
+            limit: self.fs.vfs.quota(uid.0),
+        })
+    }
+
+    /// Usage and limits for every user who owns files or has a limit,
+    /// by uid (root or CAP_SYS_ADMIN)
+    pub fn sys_quota_report(&self) -> SyscallResult<Vec<DiskQuota>> {
+        let process = self.get_current_process()?;
+        if process.euid != Uid::ROOT && !process.capabilities.has_effective(Capability::SysAdmin) {
+            return Err(SyscallError::PermissionDenied);
+        }
+        let vfs = &self.fs.vfs;
+        let mut uids: BTreeSet<u32> = vfs.usage_by_owner().into_keys().collect();
+        uids.extend(vfs.quotas().keys());
+        Ok(uids
+            .into_iter()
+            .map(|uid| DiskQuota {
+                uid: Uid(uid),
+                used: vfs.usage(uid),
+                limit: vfs.quota(uid),
+            })
+            .collect())
+    }
+
+    /// Limit the bytes `uid` may own on the root filesystem, or lift the
+    /// limit with `None` (root or CAP_SYS_ADMIN)
+    pub fn sys_set_quota(&mut self, uid: Uid, limit: Option<u64>) -> SyscallResult<()> {
+        let process = self.get_current_process()?;
+        if process.euid != Uid::ROOT && !process.capabilities.has_effective(Capability::SysAdmin) {
+            return Err(SyscallError::PermissionDenied);
+        }
+        self.fs.vfs.set_quota(uid.0, limit);
+        Ok(())
+    }
+
+    /// Check that writing `len` bytes to file `handle` keeps the file's
+    /// owner within their quota
+    ///
+    /// Writes are buffered until close, so this is what makes a write
+    /// fail when it happens rather than the close after it.
+    fn check_write_quota(&self, handle: Handle, len: usize) -> SyscallResult<()> {
+        let Some(KernelObject::File(file)) = self.objects.get(handle) else {
+            return Ok(());
+        };
+        let path = file.path.to_string_lossy();
+        if !file.writable || self.fs.mounts.attached_mount(&path).is_some() {
+            return Ok(());
+        }
+        let Ok(meta) = self.fs.vfs.metadata(&path) else {
+            return Ok(());
+        };
+        let end = file.position.saturating_add(len as u64);
+        let new_len = (file.data.len() as u64).max(end);
+        self.fs
+            .vfs
+            .check_quota(meta.uid, new_len.saturating_sub(meta.size))?;
+        Ok(())
+    }
+
+    // ========== FILESYSTEM CHECK ==========
+
+    /// Check the root filesystem, repairing what `repair` allows
+    ///
+    /// Anyone may check; repairing needs root or CAP_SYS_ADMIN. Each fix
+    /// is logged.
+    pub fn sys_fsck(&mut self, repair: Repair) -> SyscallResult<FsckReport> {
+        if repair != Repair::Nothing {
+            let process = self.get_current_process()?;
+            if process.euid != Uid::ROOT
+                && !process.capabilities.has_effective(Capability::SysAdmin)
+            {
+                return Err(SyscallError::PermissionDenied);
+            }
+        }
+        let report = self.fs.vfs.fsck(repair);
+        self.log_fsck(&report);
+        Ok(report)
+    }
+
+    /// Log the problems an fsck fixed
+    fn log_fsck(&mut self, report: &FsckReport) {
+        for finding in report.findings.iter().filter(|f| f.fixed) {
+            let message = format!("{}: fixed", finding.problem);
+            self.klog(LogLevel::Warn, "fsck", &message);
+        }
+    }
+
+    // ========== NAMED SNAPSHOTS ==========
+
+    /// Take a snapshot of the root filesystem called `name`, at wall-clock
+    /// `time` (see [`crate::vfs::snapshots`])
+    ///
+    /// Snapshots are root's, and can't be taken in a guest session.
+    pub fn sys_snapshot_create(&mut self, name: &str, time: f64) -> SyscallResult<SnapshotInfo> {
+        self.require_system_admin()?;
+        // Keyed IPC objects and open vaults go in with the files
+        self.save_vaults()?;
+        self.save_ipc_state()?;
+        let info = snapshots::create(&mut self.fs.vfs, name, time)?;
+        let message = format!("snapshot {} taken ({} paths)", info.name, info.paths);
+        self.klog(LogLevel::Notice, "snapshot", &message);
+        Ok(info)
+    }
+
+    /// The snapshots, oldest first
+    pub fn sys_snapshot_list(&mut self) -> SyscallResult<Vec<SnapshotInfo>> {
+        self.require_system_admin()?;
+        Ok(snapshots::list(&mut self.fs.vfs))
+    }
+
+    /// What changed from snapshot `from` to `to`, or to the live
+    /// filesystem if `to` is `None`
+    pub fn sys_snapshot_diff(
+        &mut self,
+        from: &str,
+        to: Option<&str>,
+    ) -> SyscallResult<Vec<FsChange>> {
+        self.require_system_admin()?;
+        Ok(snapshots::diff(&mut self.fs.vfs, from, to)?)
+    }
+
+    /// Roll the root filesystem back to snapshot `name`, returning the
+    /// paths that changed
+    ///
+    /// Users, the hostname and keyed IPC objects are reloaded from the
+    /// restored files, as after a restore from storage.
+    pub fn sys_snapshot_restore(&mut self, name: &str) -> SyscallResult<Vec<FsChange>> {
+        self.require_system_admin()?;
+        let changes = snapshots::restore(&mut self.fs.vfs, name)?;
+        self.load_user_db();
+        self.load_hostname();
+        let _ = self.load_ipc_state();
+        let message = format!("rolled back to snapshot {} ({} paths)", name, changes.len());
+        self.klog(LogLevel::Notice, "snapshot", &message);
+        Ok(changes)
+    }
+
+    /// The versions of file `path` the snapshots hold, oldest first
+    pub fn sys_snapshot_versions(&mut self, path: &str) -> SyscallResult<Vec<FileVersion>> {
+        self.require_system_admin()?;
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let path = self.resolve_path(current, path)?;
+        let path = path.to_str().ok_or(SyscallError::InvalidArgument)?;
+        Ok(snapshots::versions(&mut self.fs.vfs, path))
+    }
+
+    /// Delete snapshot `name`
+    pub fn sys_snapshot_remove(&mut self, name: &str) -> SyscallResult<()> {
+        self.require_system_admin()?;
+        Ok(snapshots::remove(&mut self.fs.vfs, name)?)
+    }
+
+    // ========== NETWORK PERMISSIONS ==========
+
+    /// The decisions in [`ALLOWLIST_PATH`]
+    fn read_allowlist(&mut self) -> Allowlist {
+        crate::vfs::read_to_string(&mut self.fs.vfs, ALLOWLIST_PATH)
+            .map(|content| Allowlist::parse(&content))
+            .unwrap_or_default()
+    }
+
+    /// Check that a connection to `url` may be made (see
+    /// [`crate::kernel::netperm`])
+    ///
+    /// Fails with `NetworkDown` in offline mode, with `PermissionDenied`
+    /// if its origin is denied, and with `WouldBlock` if there is no
+    /// decision yet, in which case the user is asked. Use [`net_access`]
+    /// to wait for the answer. Relative URLs stay on the page's origin and
+    /// are always allowed; other URLs without an origin are denied.
+    pub fn sys_net_access(&mut self, url: &str) -> SyscallResult<()> {
+        if self.offline {
+            return Err(SyscallError::NetworkDown);
+        }
+        let Some(origin) = super::netperm::origin(url) else {
+            if super::netperm::is_relative(url) {
+                return Ok(());
+            }
+            return Err(SyscallError::PermissionDenied);
+        };
+        match self.read_allowlist().get(&origin) {
+            Some(NetDecision::Allow) => Ok(()),
+            Some(NetDecision::Deny) => Err(SyscallError::PermissionDenied),
+            None => {
+                let asked = self.netperm.prompts().any(|p| p.origin == origin);
+                self.netperm.ask(&origin, self.time.now);
+                if !asked {
+                    let message = format!("connection to {} waits for permission", origin);
+                    self.klog(LogLevel::Notice, "netperm", &message);
+                }
+                Err(SyscallError::WouldBlock)
+            }
+        }
+    }
+
+    /// Allow or deny connections to `origin` (root), waking requests
+    /// waiting for the answer
+    ///
+    /// Denying an origin also drops what the HTTP cache holds from it.
+    pub fn sys_netperm_set(&mut self, origin: &str, decision: NetDecision) -> SyscallResult<()> {
+        self.require_system_admin()?;
+        let mut list = self.read_allowlist();
+        list.set(origin, decision);
+        self.write_allowlist(&list)?;
+        if decision == NetDecision::Deny {
+            httpcache::clear_origin(&mut self.fs.vfs, origin)?;
+        }
+        self.netperm.decided(origin);
+        let message = format!("{} {}", decision.name(), origin);
+        self.klog(LogLevel::Notice, "netperm", &message);
+        Ok(())
+    }
+
+    /// Forget the decision for `origin` (root), so the next connection
+    /// asks again; what the HTTP cache holds from it is dropped, so it
+    /// isn't served meanwhile
+    pub fn sys_netperm_revoke(&mut self, origin: &str) -> SyscallResult<()> {
+        self.require_system_admin()?;
+        let mut list = self.read_allowlist();
+        if !list.remove(origin) {
+            return Err(SyscallError::NotFound);
+        }
+        self.write_allowlist(&list)?;
+        httpcache::clear_origin(&mut self.fs.vfs, origin)?;
+        let message = format!("revoked {}", origin);
+        self.klog(LogLevel::Notice, "netperm", &message);
+        Ok(())
+    }
+
+    /// Replace [`ALLOWLIST_PATH`], root's and readable by all
+    fn write_allowlist(&mut self, list: &Allowlist) -> SyscallResult<()> {
+        let vfs = &mut self.fs.vfs;
+        if !vfs.exists("/etc/net") {
+            vfs.create_dir("/etc/net")?;
+            vfs.chown("/etc/net", Some(0), Some(0))?;
+        }
+        crate::vfs::atomic_write(vfs, ALLOWLIST_PATH, list.render().as_bytes())?;
+        vfs.chown(ALLOWLIST_PATH, Some(0), Some(0))?;
+        vfs.chmod(ALLOWLIST_PATH, 0o644)?;
+        Ok(())
+    }
+
+    /// The decisions, by origin
+    pub fn sys_netperm_list(&mut self) -> Vec<(String, NetDecision)> {
+        self.read_allowlist()
+            .entries()
+            .map(|(origin, decision)| (origin.to_string(), decision))
+            .collect()
+    }
+
+    /// Origins connections are waiting to reach, by origin
+    pub fn sys_netperm_prompts(&self) -> Vec<NetPrompt> {
+        self.netperm.prompts().cloned().collect()
+    }
+
+    /// The oldest notice asking for a network permission, to show at the
+    /// prompt
+    pub fn take_net_notice(&mut self) -> Option<String> {
+        self.netperm.take_notice()
+    }
+
+    // ========== OFFLINE MODE AND HTTP CACHE ==========
+
+    /// Turn offline mode on or off; while it is on, every connection fails
+    /// with `NetworkDown` without trying the network
+    pub fn sys_set_offline(&mut self, offline: bool) -> SyscallResult<()> {
+        self.get_current_process()?;
+        if self.offline != offline {
+            self.offline = offline;
+            let message = if offline { "offline" } else { "online" };
+            self.klog(LogLevel::Notice, "net", message);
+            // Requests waiting for a permission give up now
+            if offline {
+                self.netperm.clear();
+            }
+        }
+        Ok(())
+    }
+
+    /// Check if offline mode is on
+    pub fn sys_offline(&self) -> bool {
+        self.offline
+    }
+
+    /// The cached response for `url` (see [`crate::kernel::httpcache`]),
+    /// unless connections to its origin are denied
+    pub fn sys_http_cache_get(&mut self, url: &str) -> Option<CachedResponse> {
+        if let Some(origin) = super::netperm::origin(url)
+            && self.read_allowlist().get(&origin) == Some(NetDecision::Deny)
+        {
+            return None;
+        }
+        httpcache::load(&mut self.fs.vfs, url)
+    }
+
+    /// Keep `response` in the HTTP cache
+    pub fn sys_http_cache_put(&mut self, response: &CachedResponse) -> SyscallResult<()> {
+        Ok(httpcache::store(&mut self.fs.vfs, response)?)
+    }
+
+    /// Responses in the HTTP cache, and the bytes they take
+    pub fn sys_http_cache_usage(&self) -> (usize, u64) {
+        httpcache::usage(&self.fs.vfs)
+    }
+
+    /// Empty the HTTP cache (root); returns how many responses it held
+    pub fn sys_http_cache_clear(&mut self) -> SyscallResult<usize> {
+        self.require_system_admin()?;
+        Ok(httpcache::clear(&mut self.fs.vfs)?)
+    }
+
+    // ========== EXTENDED ATTRIBUTE SYSCALLS ==========
+
+    /// Resolve `path` for an extended attribute call, following symbolic
+    /// links, and check the caller may use attribute `name` (if given) and
+    /// read the file, or write it if `write`
+    ///
+    /// `user.` attributes follow the file's permissions; `trusted.` ones
+    /// need root or CAP_SYS_ADMIN. The access control list
+    /// ([`ACL_XATTR`]) can be read by anyone who can reach the file and,
+    /// like the mode, set only by its owner or root.
+    fn xattr_path(&self, path: &str, name: Option<&str>, write: bool) -> SyscallResult<String> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let path = self.follow_links(current, path)?;
+        if let Some(name) = name {
+            match name.split_once('.') {
+                _ if name == ACL_XATTR => {}
+                Some(("user", rest)) if !rest.is_empty() => {}
+                Some(("trusted", rest)) if !rest.is_empty() => {
+                    if !self.can_use_trusted_xattrs()? {
+                        return Err(SyscallError::PermissionDenied);
+                    }
+                }
+                _ => return Err(SyscallError::InvalidArgument),
+            }
+        }
+        // Generated files have no attributes
+        if self.fs.synthetic.lookup(&path).is_some() {
+            return Err(SyscallError::InvalidArgument);
+        }
+        self.check_path_traversal(&path)?;
+        if name == Some(ACL_XATTR) {
+            if write {
+                self.check_writable_mount(&path)?;
+                let euid = self.current_euid()?;
+                let (fs, inner) = self.fs.route(&path);
+                if euid != Uid::ROOT && fs.metadata(&inner)?.uid != euid.0 {
+                    return Err(SyscallError::PermissionDenied);
+                }
+            }
+            return Ok(path);
+        }
+        self.check_file_permission(&path, !write, write, false)?;
+        Ok(path)
+    }
+
+    fn can_use_trusted_xattrs(&self) -> SyscallResult<bool> {
+        let process = self.get_current_process()?;
+        Ok(process.euid == Uid::ROOT || process.capabilities.has_effective(Capability::SysAdmin))
+    }
+
+    /// Set extended attribute `name` of `path` to `value`
+    pub fn sys_setxattr(&mut self, path: &str, name: &str, value: &[u8]) -> SyscallResult<()> {
+        let path = self.xattr_path(path, Some(name), true)?;
+        if name == ACL_XATTR && Acl::decode(value).is_none() {
+            return Err(SyscallError::InvalidArgument);
+        }
+        let (fs, inner) = self.fs.route_mut(&path);
+        fs.setxattr(&inner, name, value)?;
+        Ok(())
+    }
+
+    /// Get extended attribute `name` of `path`
+    pub fn sys_getxattr(&self, path: &str, name: &str) -> SyscallResult<Vec<u8>> {
+        let path = self.xattr_path(path, Some(name), false)?;
+        let (fs, inner) = self.fs.route(&path);
+        fs.getxattr(&inner, name).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => SyscallError::NoAttribute,
+            _ => e.into(),
+        })
+    }
+
+    /// Names of the extended attributes of `path` the caller can read
+    pub fn sys_listxattr(&self, path: &str) -> SyscallResult<Vec<String>> {
+        let path = self.xattr_path(path, None, false)?;
+        let trusted = self.can_use_trusted_xattrs()?;
+        let (fs, inner) = self.fs.route(&path);
+        let mut names = fs.listxattr(&inner)?;
+        names.retain(|name| trusted || !name.starts_with("trusted."));
+        Ok(names)
+    }
+
+    /// Remove extended attribute `name` of `path`
+    pub fn sys_removexattr(&mut self, path: &str, name: &str) -> SyscallResult<()> {
+        let path = self.xattr_path(path, Some(name), true)?;
+        let (fs, inner) = self.fs.route_mut(&path);
+        fs.removexattr(&inner, name).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => SyscallError::NoAttribute,
+            _ => e.into(),
+        })
+    }
+
+    // ========== FILE LOCKING SYSCALLS ==========
+
+    /// flock - apply or remove an advisory lock on an open file
+    ///
+    /// # Arguments
+    /// * `fd` - File descriptor to lock
+    /// * `operation` - Lock operation:
+    ///   - 0 (LOCK_UN): Unlock
+    ///   - 1 (LOCK_SH): Shared lock
+    ///   - 2 (LOCK_EX): Exclusive lock
+    ///   - Add 4 (LOCK_NB) for non-blocking
+    ///
+    /// # Returns
+    /// () on success
+    pub fn sys_flock(&mut self, fd: Fd, operation: i32) -> SyscallResult<()> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let process = self
+            .proc
+            .processes
+            .get(&current)
+            .ok_or(SyscallError::NoProcess)?;
+
+        // Get the file path from the fd
+        let path = {
+            let file = process
+                .files
+                .get(fd)
+                .and_then(|h| self.objects.get(h))
+                .ok_or(SyscallError::BadFd)?;
+
+            match file {
+                KernelObject::File(f) => f.path.to_string_lossy().to_string(),
+                _ => return Err(SyscallError::BadFd),
+            }
+        };
+
+        // Parse operation
+        let blocking = operation & 4 == 0; // LOCK_NB = 4
+        let lock_type = match operation & 3 {
+            0 => LockType::Unlock,
+            1 => LockType::Shared,
+            2 => LockType::Exclusive,
+            _ => return Err(SyscallError::InvalidArgument),
+        };
+
+        // Any earlier wait is over: this call either succeeds or waits anew
+        self.ipc.waits.clear(current);
+        let result = self
+            .ipc
+            .file_locks
+            .flock(&path, current, lock_type, blocking);
+        if result == Err(LockError::WouldBlock) && blocking {
+            let holders = self
+                .ipc
+                .file_locks
+                .flock_blockers(&path, current, lock_type);
+            self.wait_for(current, WaitResource::FileLock(path), &holders)?;
+        }
+        result.map_err(SyscallError::from)
+    }
+
+    /// fcntl F_SETLK/F_SETLKW - set a byte-range lock
+    ///
+    /// # Arguments
+    /// * `fd` - File descriptor
+    /// * `lock_type` - 0=unlock, 1=read lock, 2=write lock
+    /// * `start` - Starting offset
+    /// * `len` - Length (0 = to end of file)
+    /// * `whence` - How to interpret start (0=SET, 1=CUR, 2=END)
+    /// * `blocking` - Wait for lock if blocked
+    ///
+    /// # Returns
+    /// () on success
+    pub fn sys_fcntl_lock(
+        &mut self,
+        fd: Fd,
+        lock_type: i32,
+        start: u64,
+        len: u64,
+        whence: i32,
+        blocking: bool,
+    ) -> SyscallResult<()> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let process = self
+            .proc
+            .processes
+            .get(&current)
+            .ok_or(SyscallError::NoProcess)?;
+
+        // Get the file path from the fd
+        let path = {
+            let file = process
+                .files
+                .get(fd)
+                .and_then(|h| self.objects.get(h))
+                .ok_or(SyscallError::BadFd)?;
+
+            match file {
+                KernelObject::File(f) => f.path.to_string_lossy().to_string(),
+                _ => return Err(SyscallError::BadFd),
+            }
+        };
+
+        let lt = match lock_type {
+            0 => LockType::Unlock,
+            1 => LockType::Shared,
+            2 => LockType::Exclusive,
+            _ => return Err(SyscallError::InvalidArgument),
+        };
+
+        let lock = RangeLock {
+            pid: current,
+            lock_type: lt,
+            start,
+            len,
+            whence,
+        };
+
+        self.ipc.waits.clear(current);
+        let holders = if blocking {
+            self.ipc.file_locks.range_blockers(&path, current, &lock)
+        } else {
+            Vec::new()
+        };
+        let result = self
+            .ipc
+            .file_locks
+            .fcntl_lock(&path, current, lock, blocking);
+        if result == Err(LockError::WouldBlock) && blocking {
+            self.wait_for(current, WaitResource::FileLock(path), &holders)?;
+        }
+        result.map_err(SyscallError::from)
+    }
+
+    /// lockf - lock, test or unlock `len` bytes from the file position
+    /// (0 = to end of file) with an exclusive byte-range lock
+    ///
+    /// `cmd` is one of `F_ULOCK`, `F_LOCK`, `F_TLOCK` or `F_TEST`. Like
+    /// `F_SETLKW`, `F_LOCK` can't wait here, so a held section fails with
+    /// `WouldBlock` as with `F_TLOCK`, or `Deadlock` if waiting for it would
+    /// deadlock.
+    pub fn sys_lockf(&mut self, fd: Fd, cmd: i32, len: u64) -> SyscallResult<()> {
+        let handle = self.get_handle(fd)?;
+        let start = match self.objects.get(handle) {
+            Some(KernelObject::File(f)) => f.position,
+            _ => return Err(SyscallError::BadFd),
+        };
+        match cmd {
+            F_ULOCK => self.sys_fcntl_lock(fd, 0, start, len, 0, false),
+            F_LOCK | F_TLOCK => self.sys_fcntl_lock(fd, 2, start, len, 0, cmd == F_LOCK),
+            F_TEST => match self.sys_fcntl_getlk(fd, 2, start, len, 0)? {
+                (0, ..) => Ok(()),
+                _ => Err(SyscallError::WouldBlock),
+            },
+            _ => Err(SyscallError::InvalidArgument),
+        }
+    }
+
+    /// fcntl F_GETLK - test if a lock can be placed
+    ///
+    /// Returns information about a conflicting lock, or the input lock
+    /// with type set to UNLOCK if no conflict.
+    pub fn sys_fcntl_getlk(
+        &self,
+        fd: Fd,
+        lock_type: i32,
+        start: u64,
+        len: u64,
+        whence: i32,
+    ) -> SyscallResult<(i32, Pid, u64, u64)> {
+        let current = self.proc.current.ok_or(SyscallError::NoProcess)?;
+        let process = self
+            .proc
+            .processes
+            .get(&current)
+            .ok_or(SyscallError::NoProcess)?;
+
+        // Get the file path from the fd
+        let path = {
+            let file = process
                 .files
                 .get(fd)
                 .and_then(|h| self.objects.get(h))
                 .ok_or(SyscallError::BadFd)?;
 
-            match file {
-                KernelObject::File(f) => f.path.to_string_lossy().to_string(),
-                _ => return Err(SyscallError::BadFd),
-            }
-        };
+            match file {
+                KernelObject::File(f) => f.path.to_string_lossy().to_string(),
+                _ => return Err(SyscallError::BadFd),
+            }
+        };
+
+        let lt = match lock_type {
+            0 => LockType::Unlock,
+            1 => LockType::Shared,
+            2 => LockType::Exclusive,
+            _ => return Err(SyscallError::InvalidArgument),
+        };
+
+        let lock = RangeLock {
+            pid: current,
+            lock_type: lt,
+            start,
+            len,
+            whence,
+        };
+
+        match self.ipc.file_locks.get_lock(&path, current, &lock) {
+            Some(conflict) => {
+                let ctype = match conflict.lock_type {
+                    LockType::Unlock => 0,
+                    LockType::Shared => 1,
+                    LockType::Exclusive => 2,
+                };
+                Ok((ctype, conflict.pid, conflict.start, conflict.len))
+            }
+            None => Ok((0, Pid(0), 0, 0)), // No conflict, return UNLOCK
+        }
+    }
+
+    /// Get process state
+    pub fn get_process_state(&self, pid: Pid) -> Option<ProcessState> {
+        self.proc.processes.get(&pid).map(|p| p.state.clone())
+    }
+
+    /// List all processes
+    pub fn list_processes(&self) -> Vec<(Pid, String, ProcessState)> {
+        self.proc
+            .processes
+            .values()
+            .map(|p| (p.pid, p.name.clone(), p.state.clone()))
+            .collect()
+    }
+
+    // ========== USER/GROUP SYSCALLS ==========
+
+    /// Get real user ID
+    pub fn sys_getuid(&self) -> SyscallResult<Uid> {
+        let process = self.get_current_process()?;
+        Ok(process.uid)
+    }
+
+    /// Get effective user ID
+    pub fn sys_geteuid(&self) -> SyscallResult<Uid> {
+        let process = self.get_current_process()?;
+        Ok(process.euid)
+    }
+
+    /// Get real group ID
+    pub fn sys_getgid(&self) -> SyscallResult<Gid> {
+        let process = self.get_current_process()?;
+        Ok(process.gid)
+    }
+
+    /// Get effective group ID
+    pub fn sys_getegid(&self) -> SyscallResult<Gid> {
+        let process = self.get_current_process()?;
+        Ok(process.egid)
+    }
+
+    /// Get supplementary group list
+    pub fn sys_getgroups(&self) -> SyscallResult<Vec<Gid>> {
+        let process = self.get_current_process()?;
+        Ok(process.groups.clone())
+    }
+
+    /// Set real user ID (requires root or CAP_SETUID, or setting to own uid)
+    pub fn sys_setuid(&mut self, uid: Uid) -> SyscallResult<()> {
+        let process = self.get_current_process_mut()?;
+
+        // Check for CAP_SETUID or root
+        let has_setuid_cap =
+            process.euid == Uid::ROOT || process.capabilities.has_effective(Capability::Setuid);
+
+        if has_setuid_cap {
+            // Privileged: set all three IDs (real, effective, saved)
+            process.uid = uid;
+            process.euid = uid;
+            process.suid = uid;
+        } else {
+            // Non-privileged: can only set effective UID to real or saved UID
+            if uid != process.uid && uid != process.suid {
+                return Err(SyscallError::PermissionDenied);
+            }
+            process.euid = uid;
+        }
+        Ok(())
+    }
+
+    /// Set effective user ID
+    pub fn sys_seteuid(&mut self, euid: Uid) -> SyscallResult<()> {
+        let process = self.get_current_process_mut()?;
+
+        // Check for CAP_SETUID or root
+        let has_setuid_cap =
+            process.euid == Uid::ROOT || process.capabilities.has_effective(Capability::Setuid);
+
+        // Can set euid to real uid, saved uid, or if privileged any uid
+        if !has_setuid_cap && euid != process.uid && euid != process.suid {
+            return Err(SyscallError::PermissionDenied);
+        }
+
+        process.euid = euid;
+        Ok(())
+    }
+
+    /// Set real group ID (requires root or CAP_SETGID, or setting to own gid)
+    pub fn sys_setgid(&mut self, gid: Gid) -> SyscallResult<()> {
+        let process = self.get_current_process_mut()?;
+
+        // Check for CAP_SETGID or root
+        let has_setgid_cap =
+            process.euid == Uid::ROOT || process.capabilities.has_effective(Capability::Setgid);
+
+        if has_setgid_cap {
+            // Privileged: set all three IDs (real, effective, saved)
+            process.gid = gid;
+            process.egid = gid;
+            process.sgid = gid;
+        } else {
+            // Non-privileged: can only set effective GID to real or saved GID
+            if gid != process.gid && gid != process.sgid {
+                return Err(SyscallError::PermissionDenied);
+            }
+            process.egid = gid;
+        }
+        Ok(())
+    }
+
+    /// Set effective group ID
+    pub fn sys_setegid(&mut self, egid: Gid) -> SyscallResult<()> {
+        let process = self.get_current_process_mut()?;
+
+        // Check for CAP_SETGID or root
+        let has_setgid_cap =
+            process.euid == Uid::ROOT || process.capabilities.has_effective(Capability::Setgid);
+
+        // Can set egid to real gid, saved gid, or if privileged any gid
+        if !has_setgid_cap && egid != process.gid && egid != process.sgid {
+            return Err(SyscallError::PermissionDenied);
+        }
+
+        process.egid = egid;
+        Ok(())
+    }
+
+    /// Set supplementary group list (requires root or CAP_SETGID)
+    pub fn sys_setgroups(&mut self, groups: Vec<Gid>) -> SyscallResult<()> {
+        let process = self.get_current_process_mut()?;
+
+        // Check for CAP_SETGID or root
+        let has_setgid_cap =
+            process.euid == Uid::ROOT || process.capabilities.has_effective(Capability::Setgid);
+
+        if !has_setgid_cap {
+            return Err(SyscallError::PermissionDenied);
+        }
+
+        process.groups = groups;
+        Ok(())
+    }
+
+    /// Set file mode creation mask (umask)
+    /// Returns the previous umask value
+    pub fn sys_umask(&mut self, mask: u16) -> SyscallResult<u16> {
+        let process = self.get_current_process_mut()?;
+        let old_mask = process.umask;
+        // Only keep the permission bits (rwxrwxrwx = 0o777)
+        process.umask = mask & 0o777;
+        Ok(old_mask)
+    }
+
+    /// Get resource limit for a resource
+    pub fn sys_getrlimit(
+        &self,
+        resource: super::process::RlimitResource,
+    ) -> SyscallResult<super::process::Rlimit> {
+        let process = self.get_current_process()?;
+        Ok(process.rlimits.get(resource))
+    }
+
+    /// Set resource limit for a resource
+    pub fn sys_setrlimit(
+        &mut self,
+        resource: super::process::RlimitResource,
+        limit: super::process::Rlimit,
+    ) -> SyscallResult<()> {
+        let process = self.get_current_process_mut()?;
+        let current = process.rlimits.get(resource);
+
+        // Validate the new limit
+        // Soft limit cannot exceed hard limit
+        if limit.soft > limit.hard {
+            return Err(SyscallError::InvalidArgument);
+        }
+
+        // Check for CAP_SYS_RESOURCE or root to raise hard limit
+        let has_resource_cap = process.euid == Uid::ROOT
+            || process.capabilities.has_effective(Capability::SysResource);
+
+        // Cannot raise hard limit without CAP_SYS_RESOURCE
+        if limit.hard > current.hard && !has_resource_cap {
+            return Err(SyscallError::PermissionDenied);
+        }
+
+        process.rlimits.set(resource, limit);
+        Ok(())
+    }
+
+    /// Resources the current process has used
+    pub fn sys_getrusage(&self) -> SyscallResult<ResourceUsage> {
+        let process = self.get_current_process()?;
+        Ok(ResourceUsage {
+            peak_memory: process.memory.peak(),
+            ..process.usage
+        })
+    }
+
+    /// Restart the current process's peak memory from what it has now,
+    /// like writing 5 to Linux's `/proc/self/clear_refs`; returns the old
+    /// peak
+    pub fn sys_reset_peak_memory(&mut self) -> SyscallResult<usize> {
+        Ok(self.get_current_process_mut()?.memory.reset_peak())
+    }
+
+    /// Charge a syscall taking `ms` to `pid`'s usage
+    fn charge_syscall(&mut self, pid: Option<Pid>, ms: f64) {
+        if let Some(process) = pid.and_then(|pid| self.proc.processes.get_mut(&pid)) {
+            process.usage.charge_syscall(ms);
+        }
+    }
+
+    // ========== CAPABILITY SYSCALLS ==========
+
+    /// Get capabilities for a process
+    ///
+    /// If pid is 0, returns capabilities of the current process.
+    /// Otherwise, returns capabilities of the specified process (requires CAP_SYS_PTRACE
+    /// or same UID).
+    ///
+    /// Returns (permitted, effective, inheritable) capability sets.
+    pub fn sys_capget(&self, pid: Pid) -> SyscallResult<ProcessCapabilities> {
+        let current = self.get_current_process()?;
+        let target_pid = if pid.0 == 0 { current.pid } else { pid };
+
+        // Check if requesting own capabilities
+        if target_pid == current.pid {
+            return Ok(current.capabilities);
+        }
+
+        // Requesting another process's capabilities
+        let target = self
+            .proc
+            .processes
+            .get(&target_pid)
+            .ok_or(SyscallError::NoProcess)?;
+
+        // Must have CAP_SYS_PTRACE or be same UID to read other process capabilities
+        if current.euid != Uid::ROOT
+            && !current.capabilities.has_effective(Capability::SysPtrace)
+            && current.euid != target.euid
+        {
+            return Err(SyscallError::PermissionDenied);
+        }
+
+        Ok(target.capabilities)
+    }
+
+    /// Set capabilities for the current process
+    ///
+    /// Rules:
+    /// - Can only modify own process capabilities
+    /// - Cannot add capabilities not in permitted set to effective set
+    /// - Cannot add capabilities not in permitted set to inheritable set (unless CAP_SETPCAP)
+    /// - Removing from permitted is permanent (cannot be undone)
+    pub fn sys_capset(&mut self, caps: ProcessCapabilities) -> SyscallResult<()> {
+        let process = self.get_current_process_mut()?;
+
+        // Validate: effective must be subset of permitted
+        if !caps.effective.is_subset_of(&caps.permitted) {
+            return Err(SyscallError::InvalidArgument);
+        }
+
+        // Validate: new permitted must be subset of current permitted (can only drop, not gain)
+        if !caps.permitted.is_subset_of(&process.capabilities.permitted) {
+            return Err(SyscallError::PermissionDenied);
+        }
+
+        // Validate: inheritable changes require CAP_SETPCAP to add new capabilities
+        let new_inheritable = caps
+            .inheritable
+            .difference(&process.capabilities.inheritable);
+        if !new_inheritable.is_empty() && !process.capabilities.has_effective(Capability::Setpcap) {
+            // Without CAP_SETPCAP, can only add capabilities that are in permitted
+            if !new_inheritable.is_subset_of(&process.capabilities.permitted) {
+                return Err(SyscallError::PermissionDenied);
+            }
+        }
+
+        process.capabilities = caps;
+        Ok(())
+    }
+
+    /// Raise a capability (add to effective set if in permitted)
+    /// Convenience wrapper around capset
+    pub fn sys_cap_raise(&mut self, cap: Capability) -> SyscallResult<()> {
+        let process = self.get_current_process_mut()?;
+
+        if !process.capabilities.raise(cap) {
+            return Err(SyscallError::PermissionDenied);
+        }
+
+        Ok(())
+    }
+
+    /// Lower a capability (remove from effective set)
+    /// Convenience wrapper around capset
+    pub fn sys_cap_lower(&mut self, cap: Capability) -> SyscallResult<()> {
+        let process = self.get_current_process_mut()?;
+        process.capabilities.lower(cap);
+        Ok(())
+    }
+
+    /// Drop a capability permanently (remove from all sets)
+    /// This cannot be undone!
+    pub fn sys_cap_drop(&mut self, cap: Capability) -> SyscallResult<()> {
+        let process = self.get_current_process_mut()?;
+        process.capabilities.drop_cap(cap);
+        Ok(())
+    }
+
+    /// Check if a capability is effective for the current process
+    pub fn sys_cap_check(&self, cap: Capability) -> SyscallResult<bool> {
+        let process = self.get_current_process()?;
+        Ok(process.capabilities.has_effective(cap))
+    }
+
+    /// Change the root directory for the current process (chroot)
+    ///
+    /// This creates a "jail" - the process will see `path` as "/" and cannot
+    /// access anything outside it via ".." traversal. Requires CAP_SYS_CHROOT.
+    ///
+    /// After chroot:
+    /// - Absolute paths start from the new root
+    /// - Relative paths resolve from cwd (which should be changed to "/" or inside jail)
+    /// - The jail is inherited by child processes
+    ///
+    /// Security notes:
+    /// - The caller should chdir to "/" or a path inside the jail after chroot
+    /// - CAP_SYS_CHROOT is required (root-only by default)
+    /// - Once jailed, a process cannot escape (no ".." tricks work)
+    pub fn sys_chroot(&mut self, path: &str) -> SyscallResult<()> {
+        // Check if the path exists and is a directory
+        {
+            let process = self.get_current_process()?;
+
+            // Check for CAP_SYS_CHROOT capability
+            if !process.capabilities.has_effective(Capability::SysChroot) {
+                return Err(SyscallError::PermissionDenied);
+            }
+
+            // Resolve the path (using current jail if any)
+            let resolved_path = process
+                .resolve_jailed_path(Path::new(path))
+                .map_err(|_| SyscallError::PermissionDenied)?;
+
+            // Check if the path exists and is a directory
+            let resolved_str = resolved_path
+                .to_str()
+                .ok_or(SyscallError::InvalidArgument)?;
+            let meta = self
+                .fs
+                .vfs
+                .metadata(resolved_str)
+                .map_err(|_| SyscallError::NotFound)?;
+
+            if !meta.is_dir {
+                return Err(SyscallError::NotADirectory);
+            }
+        }
+
+        // All checks passed - set the jail root
+        let process = self.get_current_process_mut()?;
+
+        // Resolve path again with mutable access
+        let resolved_path = process
+            .resolve_jailed_path(Path::new(path))
+            .map_err(|_| SyscallError::PermissionDenied)?;
+
+        process.set_jail_root(resolved_path);
+
+        // Note: Best practice is to also chdir("/") after chroot
+        // but we don't do it automatically - let the caller decide
+
+        Ok(())
+    }
+
+    pub fn users(&self) -> &UserDb {
+        &self.users
+    }
+
+    pub fn users_mut(&mut self) -> &mut UserDb {
+        &mut self.users
+    }
+
+    /// Save user database to /etc/passwd, /etc/shadow, /etc/group
+    pub fn save_user_db(&mut self) {
+        use crate::vfs::atomic_write;
+
+        // Generate file contents
+        let passwd_content = self.users.to_passwd();
+        let shadow_content = self.users.to_shadow();
+        let group_content = self.users.to_group();
+
+        // Each file is replaced whole, so a reload mid-save can't lock
+        // everyone out with a truncated /etc/shadow
+
+        // Write /etc/passwd (readable by all)
+        let _ = atomic_write(&mut self.fs.vfs, "/etc/passwd", passwd_content.as_bytes());
+
+        // Write /etc/shadow (readable only by root)
+        let _ = atomic_write(&mut self.fs.vfs, "/etc/shadow", shadow_content.as_bytes());
+
+        // Write /etc/group (readable by all)
+        let _ = atomic_write(&mut self.fs.vfs, "/etc/group", group_content.as_bytes());
+
+        // Note: File permissions would be set here if the VFS supported chmod.
+        // For now, the files are created with default permissions.
+    }
+
+    /// Set the hostname (root or CAP_SYS_ADMIN)
+    pub fn sys_sethostname(&mut self, name: &str) -> SyscallResult<()> {
+        let process = self.get_current_process()?;
+        if process.euid != Uid::ROOT && !process.capabilities.has_effective(Capability::SysAdmin) {
+            return Err(SyscallError::PermissionDenied);
+        }
+        if !InitSystem::is_valid_hostname(name) {
+            return Err(SyscallError::InvalidArgument);
+        }
+        self.init.set_hostname(name);
+        Ok(())
+    }
+
+    /// Write keyed message queues and semaphore sets to the VFS so they
+    /// are part of the next snapshot (see [`super::ipc_persist`])
+    ///
+    /// Skipped during a guest session, whose changes are never saved.
+    pub fn save_ipc_state(&mut self) -> std::io::Result<()> {
+        if self.guest.is_some() {
+            return Ok(());
+        }
+        ipc_persist::save(&mut self.fs.vfs, &self.ipc.msgqueues, &self.ipc.semaphores)
+    }
+
+    /// Recreate keyed message queues and semaphore sets saved in the VFS
+    /// Returns true if there was saved state
+    pub fn load_ipc_state(&mut self) -> std::io::Result<bool> {
+        let now = self.now();
+        ipc_persist::load(
+            &mut self.fs.vfs,
+            &mut self.ipc.msgqueues,
+            &mut self.ipc.semaphores,
+            now,
+        )
+    }
+
+    /// Load the hostname from /etc/hostname
+    /// Returns true if the file existed and held a valid name
+    pub fn load_hostname(&mut self) -> bool {
+        let Ok(content) = crate::vfs::read_to_string(&mut self.fs.vfs, "/etc/hostname") else {
+            return false;
+        };
+        let name = content.trim();
+        if !InitSystem::is_valid_hostname(name) {
+            return false;
+        }
+        self.init.set_hostname(name);
+        true
+    }
+
+    /// Load user database from /etc/passwd, /etc/shadow, /etc/group
+    /// Returns true if files existed and were loaded
+    pub fn load_user_db(&mut self) -> bool {
+        match self.read_user_db() {
+            Some(users) => {
+                self.users = users;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Parse /etc/passwd, and /etc/group and /etc/shadow with it, if
+    /// there is a /etc/passwd
+    fn read_user_db(&mut self) -> Option<UserDb> {
+        use crate::vfs::read_to_string;
+
+        let passwd = read_to_string(&mut self.fs.vfs, "/etc/passwd").ok()?;
+        let mut users = UserDb::empty();
+        users.parse_passwd(&passwd);
+        if let Ok(content) = read_to_string(&mut self.fs.vfs, "/etc/group") {
+            users.parse_group(&content);
+        }
+        // Must be after passwd
+        if let Ok(content) = read_to_string(&mut self.fs.vfs, "/etc/shadow") {
+            users.parse_shadow(&content);
+        }
+        Some(users)
+    }
+
+    /// Reload the user database after `path` (resolved) was written, if
+    /// it is one of its files
+    ///
+    /// That is what makes editing /etc/passwd or /etc/group by hand take
+    /// effect. Files without a root account are ignored, so a bad edit
+    /// can't lock everyone out.
+    fn reload_user_db(&mut self, path: &str) {
+        if !USER_DB_FILES.contains(&path) || self.fs.mounts.attached_mount(path).is_some() {
+            return;
+        }
+        match self.read_user_db() {
+            Some(users) if users.get_user(Uid::ROOT).is_some() => {
+                self.users = users;
+                let message = format!("reloaded users and groups from {}", path);
+                self.klog(LogLevel::Info, "users", &message);
+            }
+            _ => {
+                let message = format!("ignoring {}: no root account", path);
+                self.klog(LogLevel::Warn, "users", &message);
+            }
+        }
+    }
+
+    pub fn get_user_by_name(&self, name: &str) -> Option<&User> {
+        self.users.get_user_by_name(name)
+    }
+
+    pub fn get_user_by_uid(&self, uid: Uid) -> Option<&User> {
+        self.users.get_user(uid)
+    }
+
+    pub fn get_group_by_name(&self, name: &str) -> Option<&Group> {
+        self.users.get_group_by_name(name)
+    }
+
+    pub fn get_group_by_gid(&self, gid: Gid) -> Option<&Group> {
+        self.users.get_group(gid)
+    }
+
+    /// Change file permissions
+    pub fn sys_chmod(&mut self, path: &str, mode: u16) -> SyscallResult<()> {
+        // Check if caller owns the file or is root
+        let process = self.get_current_process()?;
+        let euid = process.euid;
+
+        self.check_writable_mount(path)?;
+
+        // Get file metadata to check ownership
+        let (fs, inner) = self.fs.route_mut(path);
+        let meta = fs.metadata(&inner)?;
+
+        // Only root or file owner can chmod
+        if euid.0 != 0 && meta.uid != euid.0 {
+            return Err(SyscallError::PermissionDenied);
+        }
+
+        fs.chmod(&inner, mode)?;
+        // The group bits are the access control list's mask, as in POSIX
+        if let Some(mut acl) = meta.acl {
+            acl.set_mask(((mode >> 3) & 0o7) as u8);
+            fs.setxattr(&inner, ACL_XATTR, &acl.encode())?;
+        }
+        Ok(())
+    }
+
+    /// Change file ownership
+    pub fn sys_chown(
+        &mut self,
+        path: &str,
+        uid: Option<u32>,
+        gid: Option<u32>,
+    ) -> SyscallResult<()> {
+        let process = self.get_current_process()?;
+        let euid = process.euid;
+
+        self.check_writable_mount(path)?;
+
+        // Get file metadata to check ownership
+        let (fs, inner) = self.fs.route(path);
+        let meta = fs.metadata(&inner)?;
+
+        // Only root can change ownership
+        if euid != Uid::ROOT {
+            // SEC-013: Non-root users can only change group if they own the file
+            // and only to a group they belong to
+            if uid.is_some() {
+                return Err(SyscallError::PermissionDenied);
+            }
+
+            // Must own the file to change its group
+            if meta.uid != euid.0 {
+                return Err(SyscallError::PermissionDenied);
+            }
+
+            // Can only change to a group the user belongs to
+            if let Some(new_gid) = gid {
+                let groups = &process.groups;
+                let is_member = groups.iter().any(|g| g.0 == new_gid) || process.gid.0 == new_gid;
+                if !is_member {
+                    return Err(SyscallError::PermissionDenied);
+                }
+            }
+        }
+
+        let (fs, inner) = self.fs.route_mut(path);
+        fs.chown(&inner, uid, gid)?;
+        Ok(())
+    }
+
+    // ========== SOCKET SYSCALLS ==========
+
+    /// Create a Unix domain socket
+    pub fn sys_socket(&mut self, socket_type: SocketType) -> SocketId {
+        self.ipc.sockets.socket(socket_type)
+    }
+
+    /// Close a socket
+    pub fn sys_socket_close(&mut self, id: SocketId) -> SocketResult<()> {
+        self.wake_pollers();
+        self.ipc.sockets.close(id)
+    }
+
+    /// Bind a socket to an address
+    pub fn sys_bind(&mut self, id: SocketId, addr: SockAddr) -> SocketResult<()> {
+        self.ipc.sockets.bind(id, addr)
+    }
+
+    /// Listen for connections on a socket
+    pub fn sys_listen(&mut self, id: SocketId, backlog: usize) -> SocketResult<()> {
+        self.ipc.sockets.listen(id, backlog)
+    }
+
+    /// Accept a connection on a socket
+    pub fn sys_accept(&mut self, id: SocketId) -> SocketResult<(SocketId, SockAddr)> {
+        self.wake_pollers();
+        self.ipc.sockets.accept(id)
+    }
+
+    /// Connect a socket to an address
+    pub fn sys_connect(&mut self, id: SocketId, addr: &SockAddr) -> SocketResult<()> {
+        self.wake_pollers();
+        self.ipc.sockets.connect(id, addr)
+    }
+
+    /// Send data on a connected socket
+    pub fn sys_send(&mut self, id: SocketId, data: &[u8]) -> SocketResult<usize> {
+        self.wake_pollers();
+        self.ipc.sockets.send(id, data)
+    }
+
+    /// Receive data from a connected socket
+    pub fn sys_recv(&mut self, id: SocketId) -> SocketResult<Vec<u8>> {
+        self.wake_pollers();
+        self.ipc.sockets.recv(id)
+    }
+
+    /// Send datagram to an address
+    pub fn sys_sendto(
+        &mut self,
+        id: SocketId,
+        data: &[u8],
+        addr: &SockAddr,
+    ) -> SocketResult<usize> {
+        self.wake_pollers();
+        self.ipc.sockets.sendto(id, data, addr)
+    }
+
+    /// Receive datagram
+    pub fn sys_recvfrom(&mut self, id: SocketId) -> SocketResult<(Vec<u8>, Option<SockAddr>)> {
+        self.wake_pollers();
+        self.ipc.sockets.recvfrom(id)
+    }
+
+    /// Set socket to non-blocking mode
+    pub fn sys_socket_set_nonblocking(
+        &mut self,
+        id: SocketId,
+        nonblocking: bool,
+    ) -> SocketResult<()> {
+        self.ipc.sockets.set_nonblocking(id, nonblocking)
+    }
+
+    /// Get socket local address
+    pub fn sys_getsockname(&self, id: SocketId) -> SocketResult<Option<SockAddr>> {
+        self.ipc.sockets.local_addr(id)
+    }
+
+    /// Get socket peer address
+    pub fn sys_getpeername(&self, id: SocketId) -> SocketResult<Option<SockAddr>> {
+        self.ipc.sockets.peer_addr(id)
+    }
+}
+
+impl Default for Kernel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Global kernel instance
+thread_local! {
+    pub static KERNEL: RefCell<Kernel> = RefCell::new(Kernel::new());
+}
+
+// ========== SYSCALL TRACING ==========
+// Wrappers report to ptrace tracers through `traced`. The argument string
+// is only built when someone is tracing the calling process.
+
+/// How a syscall's return value is shown to a tracer
+pub trait TraceValue {
+    fn trace_value(&self) -> String;
+}
+
+impl TraceValue for () {
+    fn trace_value(&self) -> String {
+        "0".to_string()
+    }
+}
+
+impl TraceValue for usize {
+    fn trace_value(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl TraceValue for u32 {
+    fn trace_value(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl TraceValue for u64 {
+    fn trace_value(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl TraceValue for bool {
+    fn trace_value(&self) -> String {
+        u8::from(*self).to_string()
+    }
+}
+
+impl TraceValue for Fd {
+    fn trace_value(&self) -> String {
+        self.0.to_string()
+    }
+}
+
+impl TraceValue for Pid {
+    fn trace_value(&self) -> String {
+        self.0.to_string()
+    }
+}
+
+impl TraceValue for String {
+    fn trace_value(&self) -> String {
+        trace_str(self)
+    }
+}
+
+impl TraceValue for PathBuf {
+    fn trace_value(&self) -> String {
+        trace_str(&self.display().to_string())
+    }
+}
+
+impl TraceValue for Option<String> {
+    fn trace_value(&self) -> String {
+        self.as_deref()
+            .map_or_else(|| "NULL".to_string(), trace_str)
+    }
+}
+
+impl<T> TraceValue for Vec<T> {
+    fn trace_value(&self) -> String {
+        self.len().to_string()
+    }
+}
+
+impl TraceValue for (Fd, Fd) {
+    fn trace_value(&self) -> String {
+        format!("0 [{}, {}]", self.0.0, self.1.0)
+    }
+}
+
+impl TraceValue for (Pid, WaitStatus) {
+    fn trace_value(&self) -> String {
+        format!("{} {:?}", self.0.0, self.1)
+    }
+}
+
+impl TraceValue for FileMetadata {
+    fn trace_value(&self) -> String {
+        format!("0 {{mode={:#o}, size={}}}", self.mode, self.size)
+    }
+}
+
+/// Quote a string argument the way strace does, truncated to 32 chars
+pub fn trace_str(s: &str) -> String {
+    const MAX: usize = 32;
+    let escaped: String = s.chars().take(MAX).flat_map(char::escape_debug).collect();
+    if s.chars().count() > MAX {
+        format!("\"{}\"...", escaped)
+    } else {
+        format!("\"{}\"", escaped)
+    }
+}
+
+/// strace-style rendering of open flags
+fn trace_open_flags(flags: OpenFlags) -> String {
+    let mut parts = vec![match (flags.read, flags.write) {
+        (true, true) => "O_RDWR",
+        (false, true) => "O_WRONLY",
+        _ => "O_RDONLY",
+    }];
+    if flags.create {
+        parts.push("O_CREAT");
+    }
+    if flags.truncate {
+        parts.push("O_TRUNC");
+    }
+    if flags.append {
+        parts.push("O_APPEND");
+    }
+    parts.join("|")
+}
+
+/// A path argument for a debugger syscall record
+fn debug_path_arg(name: &str, path: &str) -> SyscallArg {
+    SyscallArg {
+        name: name.to_string(),
+        value: 0,
+        interpreted: ArgValue::Path(path.to_string()),
+    }
+}
+
+/// Open flags for a debugger syscall record
+fn debug_open_flags(flags: OpenFlags) -> SyscallArg {
+    let names: Vec<String> = trace_open_flags(flags)
+        .split('|')
+        .map(String::from)
+        .collect();
+    SyscallArg {
+        name: "flags".to_string(),
+        value: 0,
+        interpreted: ArgValue::Flags(0, names),
+    }
+}
+
+/// A syscall result for a debugger syscall record
+fn debug_result<T>(result: &SyscallResult<T>, value: impl FnOnce(&T) -> i32) -> DebugResult {
+    match result {
+        Ok(v) => DebugResult::Success(value(v)),
+        Err(e) => DebugResult::Error(-1, e.to_string()),
+    }
+}
+
+/// Run a syscall, reporting entry and exit to the caller's tracer if any
+fn traced<T: TraceValue>(
+    nr: SyscallNr,
+    args: impl FnOnce() -> String,
+    call: impl FnOnce(&mut Kernel) -> SyscallResult<T>,
+) -> SyscallResult<T> {
+    KERNEL.with(|k| {
+        let mut kernel = k.borrow_mut();
+        let current = kernel.proc.current;
+        let ptraced = current.filter(|&pid| kernel.ptrace.wants(pid, nr));
+        let timed = kernel.tracer.wants(TraceCategory::Syscall);
+        if ptraced.is_none() && !timed {
+            let start = kernel.tracer.now_or(f64::NAN);
+            let result = call(&mut kernel);
+            let end = kernel.tracer.now_or(f64::NAN);
+            kernel.charge_syscall(current, end - start);
+            return result;
+        }
+
+        if let Some(pid) = ptraced {
+            kernel.ptrace.record(PtraceEvent::SyscallEnter {
+                pid,
+                nr,
+                args: args(),
+            });
+        }
+        let now = kernel.time.now;
+        let start = kernel.tracer.now_or(now);
+        let result = call(&mut kernel);
+        let elapsed = kernel.tracer.now_or(f64::NAN) - start;
+        kernel.charge_syscall(current, elapsed);
+
+        if timed {
+            let end = kernel.tracer.now_or(now);
+            let mut event = TraceEvent::instant(start, TraceCategory::Syscall, nr.name())
+                .with_duration(end - start);
+            if let Some(pid) = current {
+                event = event.with_pid(pid.0);
+            }
+            if let Err(e) = &result {
+                event.detail = Some(e.to_string());
+            }
+            kernel.tracer.trace(event);
+        }
+        if let Some(pid) = ptraced {
+            let ret = match &result {
+                Ok(value) => Ok(value.trace_value()),
+                Err(e) => Err(e.to_string()),
+            };
+            kernel
+                .ptrace
+                .record(PtraceEvent::SyscallExit { pid, nr, ret });
+        }
+        result
+    })
+}
+
+// ========== PUBLIC API ==========
+// These functions provide the syscall interface to user code
+
+/// Open a file or device
+pub fn open(path: &str, flags: OpenFlags) -> SyscallResult<Fd> {
+    traced(
+        SyscallNr::Open,
+        || format!("{}, {}", trace_str(path), trace_open_flags(flags)),
+        |k| k.sys_open(path, flags),
+    )
+}
+
+/// Read from a file descriptor
+pub fn read(fd: Fd, buf: &mut [u8]) -> SyscallResult<usize> {
+    let len = buf.len();
+    traced(
+        SyscallNr::Read,
+        || format!("{}, {}", fd.0, len),
+        |k| k.sys_read(fd, buf),
+    )
+}
+
+/// Read up to `len` bytes from `fd`, waiting for a pipe or the console
+/// to have some
+///
+/// Resolves as soon as [`read`] would succeed, at end of file, or with
+/// `Interrupted` when a signal arrives. A descriptor in non-blocking mode
+/// (`O_NONBLOCK`, see [`fcntl`]) fails with `WouldBlock` at once instead.
+/// Writes to the object wake the future to try again, so it never spins.
+pub fn read_wait(fd: Fd, len: usize) -> ReadWait {
+    KERNEL.with(|k| ReadWait {
+        pid: k.borrow().proc.current,
+        fd,
+        len,
+    })
+}
+
+/// Future returned by [`read_wait`]
+pub struct ReadWait {
+    /// Process whose descriptor is read
+    pid: Option<Pid>,
+    fd: Fd,
+    len: usize,
+}
+
+impl std::future::Future for ReadWait {
+    type Output = SyscallResult<Vec<u8>>;
+
+    fn poll(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Self::Output> {
+        use std::task::Poll;
+
+        KERNEL.with(|k| {
+            let mut kernel = k.borrow_mut();
+            let Some(pid) = self.pid else {
+                return Poll::Ready(Err(SyscallError::NoProcess));
+            };
+            let current = kernel.proc.current.replace(pid);
+            let mut buf = vec![0; self.len];
+            let result = kernel.sys_read(self.fd, &mut buf);
+            let nonblocking = kernel
+                .get_handle(self.fd)
+                .is_ok_and(|h| kernel.objects.nonblocking(h));
+            kernel.proc.current = current;
+            match result {
+                Err(SyscallError::WouldBlock) if !nonblocking => {
+                    kernel.pollers.push(cx.waker().clone());
+                    Poll::Pending
+                }
+                result => Poll::Ready(result.map(|n| {
+                    buf.truncate(n);
+                    buf
+                })),
+            }
+        })
+    }
+}
+
+/// Read up to `len` bytes of `path` from `offset` into `sink`, with no
+/// copy in between (see [`Kernel::sys_read_at_with`])
+pub fn read_at_with(
+    path: &str,
+    offset: u64,
+    len: usize,
+    sink: &mut dyn FnMut(&[u8]),
+) -> SyscallResult<usize> {
+    traced(
+        SyscallNr::Read,
+        || format!("{}@{}, {}", trace_str(path), offset, len),
+        |k| k.sys_read_at_with(path, offset, len, sink),
+    )
+}
+
+/// Write to a file descriptor
+pub fn write(fd: Fd, buf: &[u8]) -> SyscallResult<usize> {
+    traced(
+        SyscallNr::Write,
+        || {
+            let text = String::from_utf8_lossy(buf);
+            format!("{}, {}, {}", fd.0, trace_str(&text), buf.len())
+        },
+        |k| k.sys_write(fd, buf),
+    )
+}
+
+/// Read from a file descriptor at `offset`, without moving its position
+pub fn pread(fd: Fd, buf: &mut [u8], offset: u64) -> SyscallResult<usize> {
+    let len = buf.len();
+    traced(
+        SyscallNr::Pread,
+        || format!("{}, {}, {}", fd.0, len, offset),
+        |k| k.sys_pread(fd, buf, offset),
+    )
+}
+
+/// Write to a file descriptor at `offset`, without moving its position
+pub fn pwrite(fd: Fd, buf: &[u8], offset: u64) -> SyscallResult<usize> {
+    traced(
+        SyscallNr::Pwrite,
+        || {
+            let text = String::from_utf8_lossy(buf);
+            format!("{}, {}, {}, {}", fd.0, trace_str(&text), buf.len(), offset)
+        },
+        |k| k.sys_pwrite(fd, buf, offset),
+    )
+}
+
+/// Move a file descriptor's position
+pub fn seek(fd: Fd, pos: SeekFrom) -> SyscallResult<u64> {
+    traced(
+        SyscallNr::Seek,
+        || format!("{}, {:?}", fd.0, pos),
+        |k| k.sys_seek(fd, pos),
+    )
+}
+
+/// Close a file descriptor
+pub fn close(fd: Fd) -> SyscallResult<()> {
+    traced(SyscallNr::Close, || fd.0.to_string(), |k| k.sys_close(fd))
+}
+
+/// Flush a file descriptor's writes and save the filesystem
+pub fn fsync(fd: Fd) -> SyscallResult<()> {
+    traced(SyscallNr::Fsync, || fd.0.to_string(), |k| k.sys_fsync(fd))
+}
+
+/// Flush a file descriptor's data and save the filesystem
+pub fn fdatasync(fd: Fd) -> SyscallResult<()> {
+    traced(
+        SyscallNr::Fdatasync,
+        || fd.0.to_string(),
+        |k| k.sys_fdatasync(fd),
+    )
+}
+
+/// Whether an `fsync` asked for a save since the last call
+pub fn take_persist_request() -> bool {
+    KERNEL.with(|k| k.borrow_mut().take_persist_request())
+}
+
+/// Create a pipe
+pub fn pipe() -> SyscallResult<(Fd, Fd)> {
+    traced(SyscallNr::Pipe, String::new, |k| k.sys_pipe())
+}
+
+/// Create a window
+pub fn window_create(title: &str) -> SyscallResult<Fd> {
+    KERNEL.with(|k| k.borrow_mut().sys_window_create(title))
+}
+
+/// Get current working directory
+pub fn getcwd() -> SyscallResult<PathBuf> {
+    traced(SyscallNr::Getcwd, String::new, |k| k.sys_getcwd())
+}
+
+/// Change working directory
+pub fn chdir(path: &str) -> SyscallResult<()> {
+    traced(SyscallNr::Chdir, || trace_str(path), |k| k.sys_chdir(path))
+}
+
+/// Exit the current process
+pub fn exit(code: i32) -> SyscallResult<()> {
+    traced(SyscallNr::Exit, || code.to_string(), |k| k.sys_exit(code))
+}
+
+/// Get current process ID
+pub fn getpid() -> SyscallResult<Pid> {
+    KERNEL.with(|k| k.borrow().sys_getpid())
+}
+
+/// Get parent process ID
+pub fn getppid() -> SyscallResult<Option<Pid>> {
+    KERNEL.with(|k| k.borrow().sys_getppid())
+}
+
+// ========== ENVIRONMENT API ==========
+
+/// Get an environment variable
+pub fn getenv(name: &str) -> SyscallResult<Option<String>> {
+    traced(
+        SyscallNr::Getenv,
+        || trace_str(name),
+        |k| k.sys_getenv(name),
+    )
+}
+
+/// Set an environment variable
+pub fn setenv(name: &str, value: &str) -> SyscallResult<()> {
+    traced(
+        SyscallNr::Setenv,
+        || format!("{}, {}", trace_str(name), trace_str(value)),
+        |k| k.sys_setenv(name, value),
+    )
+}
+
+/// Remove an environment variable
+pub fn unsetenv(name: &str) -> SyscallResult<bool> {
+    traced(
+        SyscallNr::Unsetenv,
+        || trace_str(name),
+        |k| k.sys_unsetenv(name),
+    )
+}
+
+/// Get all environment variables
+pub fn environ() -> SyscallResult<Vec<(String, String)>> {
+    KERNEL.with(|k| k.borrow().sys_environ())
+}
+
+// ========== WAITPID API ==========
+
+/// Wait for a child process to change state
+pub fn waitpid(pid: i32, flags: WaitFlags) -> SyscallResult<(Pid, WaitStatus)> {
+    traced(
+        SyscallNr::Waitpid,
+        || format!("{}, {:?}", pid, flags),
+        |k| k.sys_waitpid(pid, flags),
+    )
+}
+
+// ========== PROCESS GROUP API ==========
+
+/// Get process group ID
+pub fn getpgid(pid: Pid) -> SyscallResult<Pgid> {
+    KERNEL.with(|k| k.borrow().sys_getpgid(pid))
+}
+
+/// Set process group ID
+pub fn setpgid(pid: Pid, pgid: Pgid) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_setpgid(pid, pgid))
+}
+
+// ========== IOCTL API ==========
+
+/// Perform device-specific control operation
+pub fn ioctl(fd: Fd, request: IoctlRequest) -> SyscallResult<IoctlResult> {
+    KERNEL.with(|k| k.borrow_mut().sys_ioctl(fd, request))
+}
+
+/// Add the device node /dev/`name` with its handlers
+pub fn register_device(name: &str, ops: Rc<dyn DeviceOps>) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().register_device(name, ops))
+}
 
-        let lt = match lock_type {
-            0 => LockType::Unlock,
-            1 => LockType::Shared,
-            2 => LockType::Exclusive,
-            _ => return Err(SyscallError::InvalidArgument),
-        };
+/// Remove the device node /dev/`name`
+pub fn unregister_device(name: &str) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().unregister_device(name))
+}
 
-        let lock = RangeLock {
-            pid: current,
-            lock_type: lt,
-            start,
-            len,
-            whence,
-        };
+/// Create a directory
+pub fn mkdir(path: &str) -> SyscallResult<()> {
+    traced(SyscallNr::Mkdir, || trace_str(path), |k| k.sys_mkdir(path))
+}
 
-        match self.ipc.file_locks.get_lock(&path, current, &lock) {
-            Some(conflict) => {
-                let ctype = match conflict.lock_type {
-                    LockType::Unlock => 0,
-                    LockType::Shared => 1,
-                    LockType::Exclusive => 2,
-                };
-                Ok((ctype, conflict.pid, conflict.start, conflict.len))
-            }
-            None => Ok((0, Pid(0), 0, 0)), // No conflict, return UNLOCK
-        }
-    }
+/// List directory contents
+pub fn readdir(path: &str) -> SyscallResult<Vec<String>> {
+    traced(
+        SyscallNr::Readdir,
+        || trace_str(path),
+        |k| k.sys_readdir(path),
+    )
+}
 
-    /// Get process state
-    pub fn get_process_state(&self, pid: Pid) -> Option<ProcessState> {
-        self.proc.processes.get(&pid).map(|p| p.state.clone())
-    }
+/// Check if path exists
+pub fn exists(path: &str) -> SyscallResult<bool> {
+    // Reported as a stat, which is how it's implemented
+    traced(SyscallNr::Stat, || trace_str(path), |k| k.sys_exists(path))
+}
 
-    /// List all processes
-    pub fn list_processes(&self) -> Vec<(Pid, String, ProcessState)> {
-        self.proc
-            .processes
-            .values()
-            .map(|p| (p.pid, p.name.clone(), p.state.clone()))
-            .collect()
-    }
+/// Get file/directory metadata
+pub fn metadata(path: &str) -> SyscallResult<FileMetadata> {
+    traced(
+        SyscallNr::Stat,
+        || trace_str(path),
+        |k| k.sys_metadata(path),
+    )
+}
+
+/// The absolute path `path` names once symbolic links are followed
+pub fn realpath(path: &str) -> SyscallResult<String> {
+    traced(
+        SyscallNr::Readlink,
+        || trace_str(path),
+        |k| k.sys_realpath(path),
+    )
+}
+
+/// Set the access and modification times of `path`; `None` means now
+pub fn utimes(path: &str, atime: Option<f64>, mtime: Option<f64>) -> SyscallResult<()> {
+    traced(
+        SyscallNr::Utimes,
+        || {
+            let time = |t: Option<f64>| t.map_or("now".to_string(), |t| t.to_string());
+            format!("{}, {}, {}", trace_str(path), time(atime), time(mtime))
+        },
+        |k| k.sys_utimes(path, atime, mtime),
+    )
+}
 
-    // ========== USER/GROUP SYSCALLS ==========
+/// Remove a file
+pub fn remove_file(path: &str) -> SyscallResult<()> {
+    traced(
+        SyscallNr::Unlink,
+        || trace_str(path),
+        |k| k.sys_remove_file(path),
+    )
+}
 
-    /// Get real user ID
-    pub fn sys_getuid(&self) -> SyscallResult<Uid> {
-        let process = self.get_current_process()?;
-        Ok(process.uid)
-    }
+/// Remove a directory (must be empty)
+pub fn remove_dir(path: &str) -> SyscallResult<()> {
+    traced(
+        SyscallNr::Rmdir,
+        || trace_str(path),
+        |k| k.sys_remove_dir(path),
+    )
+}
 
-    /// Get effective user ID
-    pub fn sys_geteuid(&self) -> SyscallResult<Uid> {
-        let process = self.get_current_process()?;
-        Ok(process.euid)
-    }
+/// Remove a directory (POSIX-style alias for remove_dir)
+pub fn rmdir(path: &str) -> SyscallResult<()> {
+    remove_dir(path)
+}
 
-    /// Get real group ID
-    pub fn sys_getgid(&self) -> SyscallResult<Gid> {
-        let process = self.get_current_process()?;
-        Ok(process.gid)
-    }
+/// Remove a file (POSIX-style alias for remove_file)
+pub fn unlink(path: &str) -> SyscallResult<()> {
+    remove_file(path)
+}
 
-    /// Get effective group ID
-    pub fn sys_getegid(&self) -> SyscallResult<Gid> {
-        let process = self.get_current_process()?;
-        Ok(process.egid)
-    }
+/// Rename/move a file or directory
+pub fn rename(from: &str, to: &str) -> SyscallResult<()> {
+    traced(
+        SyscallNr::Rename,
+        || format!("{}, {}", trace_str(from), trace_str(to)),
+        |k| k.sys_rename(from, to),
+    )
+}
 
-    /// Get supplementary group list
-    pub fn sys_getgroups(&self) -> SyscallResult<Vec<Gid>> {
-        let process = self.get_current_process()?;
-        Ok(process.groups.clone())
-    }
+/// Copy a file
+pub fn copy_file(from: &str, to: &str) -> SyscallResult<u64> {
+    traced(
+        SyscallNr::Copy,
+        || format!("{}, {}", trace_str(from), trace_str(to)),
+        |k| k.sys_copy_file(from, to),
+    )
+}
 
-    /// Set real user ID (requires root or CAP_SETUID, or setting to own uid)
-    pub fn sys_setuid(&mut self, uid: Uid) -> SyscallResult<()> {
-        let process = self.get_current_process_mut()?;
+/// Create a symbolic link
+pub fn symlink(target: &str, link_path: &str) -> SyscallResult<()> {
+    traced(
+        SyscallNr::Symlink,
+        || format!("{}, {}", trace_str(target), trace_str(link_path)),
+        |k| k.sys_symlink(target, link_path),
+    )
+}
 
-        // Check for CAP_SETUID or root
-        let has_setuid_cap =
-            process.euid == Uid::ROOT || process.capabilities.has_effective(Capability::Setuid);
+/// Read the target of a symbolic link
+pub fn read_link(path: &str) -> SyscallResult<String> {
+    traced(
+        SyscallNr::Readlink,
+        || trace_str(path),
+        |k| k.sys_read_link(path),
+    )
+}
 
-        if has_setuid_cap {
-            // Privileged: set all three IDs (real, effective, saved)
-            process.uid = uid;
-            process.euid = uid;
-            process.suid = uid;
-        } else {
-            // Non-privileged: can only set effective UID to real or saved UID
-            if uid != process.uid && uid != process.suid {
-                return Err(SyscallError::PermissionDenied);
-            }
-            process.euid = uid;
+/// Replace a file's contents in one step, so it is never seen half-written
+pub fn atomic_write(path: &str, data: &[u8]) -> SyscallResult<()> {
+    traced(
+        SyscallNr::AtomicWrite,
+        || format!("{}, {} bytes", trace_str(path), data.len()),
+        |k| k.sys_atomic_write(path, data),
+    )
+}
+
+/// Read entire file contents as string (convenience function)
+pub fn read_file(path: &str) -> SyscallResult<String> {
+    let fd = open(path, OpenFlags::READ)?;
+    let mut contents = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = read(fd, &mut buf)?;
+        if n == 0 {
+            break;
         }
-        Ok(())
+        contents.extend_from_slice(&buf[..n]);
     }
+    close(fd)?;
+    String::from_utf8(contents).map_err(|_| SyscallError::InvalidData)
+}
 
-    /// Set effective user ID
-    pub fn sys_seteuid(&mut self, euid: Uid) -> SyscallResult<()> {
-        let process = self.get_current_process_mut()?;
-
-        // Check for CAP_SETUID or root
-        let has_setuid_cap =
-            process.euid == Uid::ROOT || process.capabilities.has_effective(Capability::Setuid);
+/// Write string to file (convenience function)
+pub fn write_file(path: &str, content: &str) -> SyscallResult<()> {
+    let fd = open(path, OpenFlags::WRITE)?;
+    write(fd, content.as_bytes())?;
+    close(fd)?;
+    Ok(())
+}
 
-        // Can set euid to real uid, saved uid, or if privileged any uid
-        if !has_setuid_cap && euid != process.uid && euid != process.suid {
-            return Err(SyscallError::PermissionDenied);
-        }
+/// Get file stat (wrapper around metadata)
+pub fn stat(path: &str) -> SyscallResult<FileStat> {
+    let meta = metadata(path)?;
+    Ok(FileStat {
+        is_dir: meta.is_dir,
+        size: meta.size,
+    })
+}
 
-        process.euid = euid;
-        Ok(())
-    }
+/// Simple stat result
+pub struct FileStat {
+    pub is_dir: bool,
+    pub size: u64,
+}
 
-    /// Set real group ID (requires root or CAP_SETGID, or setting to own gid)
-    pub fn sys_setgid(&mut self, gid: Gid) -> SyscallResult<()> {
-        let process = self.get_current_process_mut()?;
+/// Duplicate a file descriptor
+pub fn dup(fd: Fd) -> SyscallResult<Fd> {
+    traced(SyscallNr::Dup, || fd.0.to_string(), |k| k.sys_dup(fd))
+}
 
-        // Check for CAP_SETGID or root
-        let has_setgid_cap =
-            process.euid == Uid::ROOT || process.capabilities.has_effective(Capability::Setgid);
+/// Get or set the flags of `fd` (see [`Kernel::sys_fcntl`])
+pub fn fcntl(fd: Fd, cmd: i32, arg: u32) -> SyscallResult<u32> {
+    traced(
+        SyscallNr::Fcntl,
+        || format!("{}, {}, {:#o}", fd.0, cmd, arg),
+        |k| k.sys_fcntl(fd, cmd, arg),
+    )
+}
 
-        if has_setgid_cap {
-            // Privileged: set all three IDs (real, effective, saved)
-            process.gid = gid;
-            process.egid = gid;
-            process.sgid = gid;
-        } else {
-            // Non-privileged: can only set effective GID to real or saved GID
-            if gid != process.gid && gid != process.sgid {
-                return Err(SyscallError::PermissionDenied);
-            }
-            process.egid = gid;
-        }
-        Ok(())
-    }
+/// Put `fd` in non-blocking mode (`O_NONBLOCK`) or take it out
+pub fn set_nonblocking(fd: Fd, nonblocking: bool) -> SyscallResult<()> {
+    let flags = fcntl(fd, F_GETFL, 0)?;
+    let flags = if nonblocking {
+        flags | O_NONBLOCK
+    } else {
+        flags & !O_NONBLOCK
+    };
+    fcntl(fd, F_SETFL, flags).map(|_| ())
+}
 
-    /// Set effective group ID
-    pub fn sys_setegid(&mut self, egid: Gid) -> SyscallResult<()> {
-        let process = self.get_current_process_mut()?;
+/// Spawn a new process (internal, will be expanded)
+pub fn spawn_process(name: &str) -> Pid {
+    KERNEL.with(|k| k.borrow_mut().spawn_process(name, None))
+}
 
-        // Check for CAP_SETGID or root
-        let has_setgid_cap =
-            process.euid == Uid::ROOT || process.capabilities.has_effective(Capability::Setgid);
+/// Spawn a child of the current process with the environment `env` makes
+/// of the current one
+pub fn spawn_with_env(name: &str, env: &EnvSpec) -> Pid {
+    KERNEL.with(|k| {
+        let current = k.borrow().proc.current;
+        k.borrow_mut().spawn_process_with(name, current, env)
+    })
+}
 
-        // Can set egid to real gid, saved gid, or if privileged any gid
-        if !has_setgid_cap && egid != process.gid && egid != process.sgid {
-            return Err(SyscallError::PermissionDenied);
-        }
+/// Fork the current process (like Linux fork(2))
+///
+/// Creates a child process with COW memory. Returns the child PID to the caller.
+/// The child's memory is shared with the parent until either writes to it,
+/// at which point the written pages are copied (copy-on-write).
+pub fn fork() -> SyscallResult<Pid> {
+    traced(SyscallNr::Spawn, String::new, |k| k.sys_fork())
+}
 
-        process.egid = egid;
-        Ok(())
-    }
+/// Allocate a pseudo-terminal, returning its slave path `/dev/pts/N`
+pub fn openpty() -> SyscallResult<String> {
+    KERNEL.with(|k| k.borrow_mut().sys_openpty())
+}
 
-    /// Set supplementary group list (requires root or CAP_SETGID)
-    pub fn sys_setgroups(&mut self, groups: Vec<Gid>) -> SyscallResult<()> {
-        let process = self.get_current_process_mut()?;
+/// Release a pseudo-terminal
+pub fn closepty(path: &str) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_closepty(path))
+}
 
-        // Check for CAP_SETGID or root
-        let has_setgid_cap =
-            process.euid == Uid::ROOT || process.capabilities.has_effective(Capability::Setgid);
+/// Report a mouse event over a terminal to its program, if it asked for
+/// mouse events
+pub fn tty_mouse(name: &str, event: &MouseEvent) -> SyscallResult<bool> {
+    KERNEL.with(|k| k.borrow_mut().sys_tty_mouse(name, event))
+}
 
-        if !has_setgid_cap {
-            return Err(SyscallError::PermissionDenied);
-        }
+/// Queue input on a terminal as if typed
+pub fn tty_input(name: &str, data: &[u8]) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_tty_input(name, data))
+}
 
-        process.groups = groups;
-        Ok(())
-    }
+/// Path of the terminal open on `fd`, or `None` if it isn't a terminal
+pub fn ttyname(fd: Fd) -> SyscallResult<Option<String>> {
+    KERNEL.with(|k| k.borrow().sys_ttyname(fd))
+}
 
-    /// Set file mode creation mask (umask)
-    /// Returns the previous umask value
-    pub fn sys_umask(&mut self, mask: u16) -> SyscallResult<u16> {
-        let process = self.get_current_process_mut()?;
-        let old_mask = process.umask;
-        // Only keep the permission bits (rwxrwxrwx = 0o777)
-        process.umask = mask & 0o777;
-        Ok(old_mask)
-    }
+/// Controlling terminal of a process
+pub fn process_tty(pid: Pid) -> Option<String> {
+    KERNEL.with(|k| k.borrow().process_tty(pid))
+}
 
-    /// Get resource limit for a resource
-    pub fn sys_getrlimit(
-        &self,
-        resource: super::process::RlimitResource,
-    ) -> SyscallResult<super::process::Rlimit> {
-        let process = self.get_current_process()?;
-        Ok(process.rlimits.get(resource))
-    }
+/// Switch to virtual console `n`; see [`Kernel::sys_chvt`]
+pub fn chvt(n: usize) -> SyscallResult<Pid> {
+    KERNEL.with(|k| k.borrow_mut().sys_chvt(n))
+}
 
-    /// Set resource limit for a resource
-    pub fn sys_setrlimit(
-        &mut self,
-        resource: super::process::RlimitResource,
-        limit: super::process::Rlimit,
-    ) -> SyscallResult<()> {
-        let process = self.get_current_process_mut()?;
-        let current = process.rlimits.get(resource);
+/// Number of the virtual console on screen
+pub fn active_vt() -> usize {
+    KERNEL.with(|k| k.borrow().ttys().active_vt())
+}
 
-        // Validate the new limit
-        // Soft limit cannot exceed hard limit
-        if limit.soft > limit.hard {
-            return Err(SyscallError::InvalidArgument);
+/// Shell of virtual console `n`, if it has one
+pub fn vt_shell(n: usize) -> Option<Pid> {
+    KERNEL.with(|k| k.borrow().ttys().vt(n).and_then(|vt| vt.shell).map(Pid))
+}
+
+/// Remember `text` as output on virtual console `n`
+pub fn vt_record(n: usize, text: &str) {
+    KERNEL.with(|k| {
+        if let Some(vt) = k.borrow_mut().ttys_mut().vt_mut(n) {
+            vt.record(text);
         }
+    })
+}
 
-        // Check for CAP_SYS_RESOURCE or root to raise hard limit
-        let has_resource_cap = process.euid == Uid::ROOT
-            || process.capabilities.has_effective(Capability::SysResource);
+/// Lines to redraw virtual console `n` with
+pub fn vt_scrollback(n: usize) -> Vec<String> {
+    KERNEL.with(|k| {
+        k.borrow()
+            .ttys()
+            .vt(n)
+            .map(|vt| vt.scrollback().iter().cloned().collect())
+            .unwrap_or_default()
+    })
+}
 
-        // Cannot raise hard limit without CAP_SYS_RESOURCE
-        if limit.hard > current.hard && !has_resource_cap {
-            return Err(SyscallError::PermissionDenied);
-        }
+/// Spawn a new login shell process for a user
+/// Creates a new session leader with proper credentials and environment
+pub fn spawn_login_shell(username: &str, uid: u32, gid: u32, home: &str, shell: &str) -> Pid {
+    KERNEL.with(|k| {
+        let current = k.borrow().proc.current;
+        k.borrow_mut()
+            .spawn_login_shell(username, Uid(uid), Gid(gid), home, shell, current)
+    })
+}
 
-        process.rlimits.set(resource, limit);
-        Ok(())
-    }
+/// Set the current running process
+pub fn set_current_process(pid: Pid) {
+    KERNEL.with(|k| k.borrow_mut().set_current(pid))
+}
 
-    // ========== CAPABILITY SYSCALLS ==========
+/// setsid - Create a new session
+pub fn setsid() -> SyscallResult<u32> {
+    KERNEL.with(|k| k.borrow_mut().sys_setsid())
+}
 
-    /// Get capabilities for a process
-    ///
-    /// If pid is 0, returns capabilities of the current process.
-    /// Otherwise, returns capabilities of the specified process (requires CAP_SYS_PTRACE
-    /// or same UID).
-    ///
-    /// Returns (permitted, effective, inheritable) capability sets.
-    pub fn sys_capget(&self, pid: Pid) -> SyscallResult<ProcessCapabilities> {
-        let current = self.get_current_process()?;
-        let target_pid = if pid.0 == 0 { current.pid } else { pid };
+/// getsid - Get session ID
+pub fn getsid(pid: Option<u32>) -> SyscallResult<u32> {
+    KERNEL.with(|k| k.borrow().sys_getsid(pid.map(Pid)))
+}
 
-        // Check if requesting own capabilities
-        if target_pid == current.pid {
-            return Ok(current.capabilities);
-        }
+/// Get information about the current session
+pub fn get_session_info() -> Option<(u32, u32, u32, String, String)> {
+    KERNEL.with(|k| {
+        let kernel = k.borrow();
+        kernel.current_process().map(|proc| {
+            (
+                proc.pid.0,
+                proc.sid.0,
+                proc.pgid.0,
+                proc.environ.get("USER").cloned().unwrap_or_default(),
+                proc.ctty.clone().unwrap_or_default(),
+            )
+        })
+    })
+}
 
-        // Requesting another process's capabilities
-        let target = self
-            .proc
-            .processes
-            .get(&target_pid)
-            .ok_or(SyscallError::NoProcess)?;
+/// Save user database to /etc/passwd, /etc/shadow, /etc/group
+pub fn save_user_db() {
+    KERNEL.with(|k| k.borrow_mut().save_user_db())
+}
 
-        // Must have CAP_SYS_PTRACE or be same UID to read other process capabilities
-        if current.euid != Uid::ROOT
-            && !current.capabilities.has_effective(Capability::SysPtrace)
-            && current.euid != target.euid
-        {
-            return Err(SyscallError::PermissionDenied);
-        }
+/// Load user database from /etc/passwd, /etc/shadow, /etc/group
+pub fn load_user_db() -> bool {
+    KERNEL.with(|k| k.borrow_mut().load_user_db())
+}
 
-        Ok(target.capabilities)
-    }
+/// Get the hostname
+pub fn gethostname() -> String {
+    KERNEL.with(|k| k.borrow().init().hostname().to_string())
+}
 
-    /// Set capabilities for the current process
-    ///
-    /// Rules:
-    /// - Can only modify own process capabilities
-    /// - Cannot add capabilities not in permitted set to effective set
-    /// - Cannot add capabilities not in permitted set to inheritable set (unless CAP_SETPCAP)
-    /// - Removing from permitted is permanent (cannot be undone)
-    pub fn sys_capset(&mut self, caps: ProcessCapabilities) -> SyscallResult<()> {
-        let process = self.get_current_process_mut()?;
+/// Set the hostname (root or CAP_SYS_ADMIN)
+pub fn sethostname(name: &str) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_sethostname(name))
+}
 
-        // Validate: effective must be subset of permitted
-        if !caps.effective.is_subset_of(&caps.permitted) {
-            return Err(SyscallError::InvalidArgument);
+/// Push input to the console
+pub fn console_push_input(data: &[u8]) {
+    KERNEL.with(|k| {
+        if let Some(console) = k.borrow_mut().console() {
+            console.push_input(data);
         }
+    })
+}
 
-        // Validate: new permitted must be subset of current permitted (can only drop, not gain)
-        if !caps.permitted.is_subset_of(&process.capabilities.permitted) {
-            return Err(SyscallError::PermissionDenied);
-        }
+/// Take console output
+pub fn console_take_output() -> Vec<u8> {
+    KERNEL.with(|k| {
+        k.borrow_mut()
+            .console()
+            .map(|c| c.take_output())
+            .unwrap_or_default()
+    })
+}
 
-        // Validate: inheritable changes require CAP_SETPCAP to add new capabilities
-        let new_inheritable = caps
-            .inheritable
-            .difference(&process.capabilities.inheritable);
-        if !new_inheritable.is_empty() && !process.capabilities.has_effective(Capability::Setpcap) {
-            // Without CAP_SETPCAP, can only add capabilities that are in permitted
-            if !new_inheritable.is_subset_of(&process.capabilities.permitted) {
-                return Err(SyscallError::PermissionDenied);
-            }
-        }
+// ========== MEMORY API ==========
 
-        process.capabilities = caps;
-        Ok(())
-    }
+/// Allocate a memory region
+pub fn mem_alloc(size: usize, prot: Protection) -> SyscallResult<RegionId> {
+    KERNEL.with(|k| k.borrow_mut().sys_alloc(size, prot))
+}
 
-    /// Raise a capability (add to effective set if in permitted)
-    /// Convenience wrapper around capset
-    pub fn sys_cap_raise(&mut self, cap: Capability) -> SyscallResult<()> {
-        let process = self.get_current_process_mut()?;
+/// Free a memory region
+pub fn mem_free(region_id: RegionId) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_free(region_id))
+}
 
-        if !process.capabilities.raise(cap) {
-            return Err(SyscallError::PermissionDenied);
-        }
+/// Read from a memory region
+pub fn mem_read(region_id: RegionId, offset: usize, buf: &mut [u8]) -> SyscallResult<usize> {
+    KERNEL.with(|k| k.borrow_mut().sys_mem_read(region_id, offset, buf))
+}
 
-        Ok(())
-    }
+/// Write to a memory region
+pub fn mem_write(region_id: RegionId, offset: usize, buf: &[u8]) -> SyscallResult<usize> {
+    KERNEL.with(|k| k.borrow_mut().sys_mem_write(region_id, offset, buf))
+}
 
-    /// Lower a capability (remove from effective set)
-    /// Convenience wrapper around capset
-    pub fn sys_cap_lower(&mut self, cap: Capability) -> SyscallResult<()> {
-        let process = self.get_current_process_mut()?;
-        process.capabilities.lower(cap);
-        Ok(())
-    }
+/// Create a shared memory segment
+pub fn shmget(size: usize) -> SyscallResult<ShmId> {
+    KERNEL.with(|k| k.borrow_mut().sys_shmget(size))
+}
 
-    /// Drop a capability permanently (remove from all sets)
-    /// This cannot be undone!
-    pub fn sys_cap_drop(&mut self, cap: Capability) -> SyscallResult<()> {
-        let process = self.get_current_process_mut()?;
-        process.capabilities.drop_cap(cap);
-        Ok(())
-    }
+/// Attach to a shared memory segment
+pub fn shmat(shm_id: ShmId, prot: Protection) -> SyscallResult<RegionId> {
+    KERNEL.with(|k| k.borrow_mut().sys_shmat(shm_id, prot))
+}
 
-    /// Check if a capability is effective for the current process
-    pub fn sys_cap_check(&self, cap: Capability) -> SyscallResult<bool> {
-        let process = self.get_current_process()?;
-        Ok(process.capabilities.has_effective(cap))
-    }
+/// Detach from a shared memory segment
+pub fn shmdt(shm_id: ShmId) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_shmdt(shm_id))
+}
 
-    /// Change the root directory for the current process (chroot)
-    ///
-    /// This creates a "jail" - the process will see `path` as "/" and cannot
-    /// access anything outside it via ".." traversal. Requires CAP_SYS_CHROOT.
-    ///
-    /// After chroot:
-    /// - Absolute paths start from the new root
-    /// - Relative paths resolve from cwd (which should be changed to "/" or inside jail)
-    /// - The jail is inherited by child processes
-    ///
-    /// Security notes:
-    /// - The caller should chdir to "/" or a path inside the jail after chroot
-    /// - CAP_SYS_CHROOT is required (root-only by default)
-    /// - Once jailed, a process cannot escape (no ".." tricks work)
-    pub fn sys_chroot(&mut self, path: &str) -> SyscallResult<()> {
-        // Check if the path exists and is a directory
-        {
-            let process = self.get_current_process()?;
+/// Sync local changes to shared memory
+pub fn shm_sync(shm_id: ShmId) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_shm_sync(shm_id))
+}
+
+/// Refresh local region from shared memory
+pub fn shm_refresh(shm_id: ShmId) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_shm_refresh(shm_id))
+}
+
+/// Get shared memory info
+pub fn shm_info(shm_id: ShmId) -> SyscallResult<ShmInfo> {
+    KERNEL.with(|k| k.borrow().sys_shm_info(shm_id))
+}
+
+/// List all shared memory segments
+pub fn shm_list() -> SyscallResult<Vec<ShmInfo>> {
+    KERNEL.with(|k| k.borrow().sys_shm_list())
+}
 
-            // Check for CAP_SYS_CHROOT capability
-            if !process.capabilities.has_effective(Capability::SysChroot) {
-                return Err(SyscallError::PermissionDenied);
-            }
+/// Get memory stats for current process
+pub fn memstats() -> SyscallResult<MemoryStats> {
+    KERNEL.with(|k| k.borrow().sys_memstats())
+}
 
-            // Resolve the path (using current jail if any)
-            let resolved_path = process
-                .resolve_jailed_path(Path::new(path))
-                .map_err(|_| SyscallError::PermissionDenied)?;
+/// Set memory limit for current process
+pub fn set_memlimit(limit: usize) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_set_memlimit(limit))
+}
 
-            // Check if the path exists and is a directory
-            let resolved_str = resolved_path
-                .to_str()
-                .ok_or(SyscallError::InvalidArgument)?;
-            let meta = self
-                .fs
-                .vfs
-                .metadata(resolved_str)
-                .map_err(|_| SyscallError::NotFound)?;
+/// Get system-wide memory stats
+pub fn system_memstats() -> SyscallResult<SystemMemoryStats> {
+    KERNEL.with(|k| k.borrow().sys_system_memstats())
+}
 
-            if !meta.is_dir {
-                return Err(SyscallError::NotADirectory);
-            }
-        }
+/// Fill `buf` from the kernel's CSPRNG, as reading /dev/urandom does
+pub fn getrandom(buf: &mut [u8]) -> SyscallResult<()> {
+    random::fill(buf).map_err(|e| SyscallError::Io(e.to_string()))
+}
 
-        // All checks passed - set the jail root
-        let process = self.get_current_process_mut()?;
+// ========== TIMER API ==========
 
-        // Resolve path again with mutable access
-        let resolved_path = process
-            .resolve_jailed_path(Path::new(path))
-            .map_err(|_| SyscallError::PermissionDenied)?;
+/// Get current kernel time (monotonic ms)
+pub fn now() -> f64 {
+    KERNEL.with(|k| k.borrow().sys_now())
+}
 
-        process.set_jail_root(resolved_path);
+/// Set current time (called from runtime)
+pub fn set_time(time: f64) {
+    KERNEL.with(|k| k.borrow_mut().set_time(time))
+}
 
-        // Note: Best practice is to also chdir("/") after chroot
-        // but we don't do it automatically - let the caller decide
+/// Schedule a one-shot timer
+pub fn timer_set(delay_ms: f64, wake_task: Option<TaskId>) -> SyscallResult<TimerId> {
+    KERNEL.with(|k| k.borrow_mut().sys_timer_set(delay_ms, wake_task))
+}
 
-        Ok(())
-    }
+/// Schedule a repeating interval timer
+pub fn timer_interval(interval_ms: f64, wake_task: Option<TaskId>) -> SyscallResult<TimerId> {
+    KERNEL.with(|k| k.borrow_mut().sys_timer_interval(interval_ms, wake_task))
+}
 
-    pub fn users(&self) -> &UserDb {
-        &self.users
-    }
+/// Cancel a timer
+pub fn timer_cancel(timer_id: TimerId) -> SyscallResult<bool> {
+    KERNEL.with(|k| k.borrow_mut().sys_timer_cancel(timer_id))
+}
 
-    pub fn users_mut(&mut self) -> &mut UserDb {
-        &mut self.users
-    }
+/// Check if a timer is pending
+pub fn timer_pending(timer_id: TimerId) -> SyscallResult<bool> {
+    KERNEL.with(|k| k.borrow().sys_timer_pending(timer_id))
+}
 
-    /// Save user database to /etc/passwd, /etc/shadow, /etc/group
-    pub fn save_user_db(&mut self) {
-        use crate::vfs::write_string;
+/// Set an alarm (sends SIGALRM to current process after delay)
+pub fn alarm(delay_ms: f64) -> SyscallResult<TimerId> {
+    KERNEL.with(|k| k.borrow_mut().sys_alarm(delay_ms))
+}
 
-        // Generate file contents
-        let passwd_content = self.users.to_passwd();
-        let shadow_content = self.users.to_shadow();
-        let group_content = self.users.to_group();
+/// Get time until next timer fires
+pub fn time_until_next_timer() -> Option<f64> {
+    KERNEL.with(|k| k.borrow().time_until_next_timer())
+}
 
-        // Write /etc/passwd (readable by all)
-        let _ = write_string(&mut self.fs.vfs, "/etc/passwd", &passwd_content);
+/// Get pending timer count
+pub fn pending_timer_count() -> usize {
+    KERNEL.with(|k| k.borrow().pending_timer_count())
+}
 
-        // Write /etc/shadow (readable only by root)
-        let _ = write_string(&mut self.fs.vfs, "/etc/shadow", &shadow_content);
+/// Tick timers and return tasks to wake (call from runtime)
+pub fn tick_timers() -> Vec<TaskId> {
+    KERNEL.with(|k| k.borrow_mut().tick_timers())
+}
 
-        // Write /etc/group (readable by all)
-        let _ = write_string(&mut self.fs.vfs, "/etc/group", &group_content);
+/// Sleep for `ms` milliseconds of kernel time
+///
+/// The sleep ends early with [`SyscallError::Interrupted`] when a signal
+/// with a handler or a fatal disposition arrives for the calling process.
+pub fn sleep(ms: f64) -> Sleep {
+    KERNEL.with(|k| {
+        let kernel = k.borrow();
+        Sleep {
+            pid: kernel.proc.current,
+            deadline: kernel.time.now + ms.max(0.0),
+            timer: None,
+        }
+    })
+}
 
-        // Note: File permissions would be set here if the VFS supported chmod.
-        // For now, the files are created with default permissions.
-    }
+/// Future returned by [`sleep`]
+pub struct Sleep {
+    /// Process whose signals interrupt the sleep
+    pid: Option<Pid>,
+    deadline: f64,
+    /// Timer that wakes the process's task at the deadline
+    timer: Option<TimerId>,
+}
 
-    /// Load user database from /etc/passwd, /etc/shadow, /etc/group
-    /// Returns true if files existed and were loaded
-    pub fn load_user_db(&mut self) -> bool {
-        use crate::vfs::read_to_string;
+impl std::future::Future for Sleep {
+    type Output = SyscallResult<()>;
 
-        let mut loaded = false;
+    fn poll(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Self::Output> {
+        use std::task::Poll;
 
-        // Try to read /etc/passwd
-        if let Ok(content) = read_to_string(&mut self.fs.vfs, "/etc/passwd") {
-            // Clear existing users and parse from files
-            self.users = UserDb::empty();
-            self.users.parse_passwd(&content);
-            loaded = true;
-        }
+        KERNEL.with(|k| {
+            let mut kernel = k.borrow_mut();
+            let process = self.pid.and_then(|pid| kernel.proc.processes.get(&pid));
+            let interrupted = process.is_some_and(|p| p.signals.interrupting().is_some());
+            let task = process.and_then(|p| p.task);
 
-        // Try to read /etc/group
-        if let Ok(content) = read_to_string(&mut self.fs.vfs, "/etc/group") {
-            self.users.parse_group(&content);
+            if interrupted || kernel.time.now >= self.deadline {
+                if let Some(timer) = self.timer.take() {
+                    kernel.time.timers.cancel(timer);
+                }
+                return Poll::Ready(if interrupted {
+                    Err(SyscallError::Interrupted)
+                } else {
+                    Ok(())
+                });
+            }
+
+            match task {
+                Some(task) if self.timer.is_none() => {
+                    let now = kernel.time.now;
+                    let timer = kernel
+                        .time
+                        .timers
+                        .schedule(self.deadline - now, now, Some(task));
+                    self.timer = Some(timer);
+                }
+                Some(_) => {}
+                // Without a task for the timer to wake, ask to be polled again
+                None => cx.waker().wake_by_ref(),
+            }
+            Poll::Pending
+        })
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some(timer) = self.timer {
+            let _ = KERNEL.try_with(|k| {
+                if let Ok(mut kernel) = k.try_borrow_mut() {
+                    kernel.time.timers.cancel(timer);
+                }
+            });
         }
+    }
+}
 
-        // Try to read /etc/shadow (must be after passwd)
-        if let Ok(content) = read_to_string(&mut self.fs.vfs, "/etc/shadow") {
-            self.users.parse_shadow(&content);
+/// Wait until one of `fds` is ready, `timeout` milliseconds of kernel time
+/// pass, or a signal arrives (see [`Kernel::sys_poll`])
+///
+/// The future resolves to the poll set with `revents` filled in; on a
+/// timeout none of them is ready. Reads, writes and closes, socket and
+/// FIFO traffic, console input and signals wake it to check again, so it
+/// never spins.
+pub fn poll(fds: Vec<PollFd>, timeout: Option<f64>) -> PollFuture {
+    KERNEL.with(|k| {
+        let kernel = k.borrow();
+        PollFuture {
+            pid: kernel.proc.current,
+            fds,
+            deadline: timeout.map(|ms| kernel.time.now + ms.max(0.0)),
+            timer: None,
         }
+    })
+}
 
-        loaded
-    }
+/// Future returned by [`poll`]
+pub struct PollFuture {
+    /// Process whose descriptors are polled
+    pid: Option<Pid>,
+    fds: Vec<PollFd>,
+    deadline: Option<f64>,
+    /// Timer that wakes the process's task at the deadline
+    timer: Option<TimerId>,
+}
 
-    pub fn get_user_by_name(&self, name: &str) -> Option<&User> {
-        self.users.get_user_by_name(name)
-    }
+impl std::future::Future for PollFuture {
+    type Output = SyscallResult<Vec<PollFd>>;
 
-    pub fn get_user_by_uid(&self, uid: Uid) -> Option<&User> {
-        self.users.get_user(uid)
-    }
+    fn poll(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Self::Output> {
+        use std::task::Poll;
 
-    pub fn get_group_by_name(&self, name: &str) -> Option<&Group> {
-        self.users.get_group_by_name(name)
+        KERNEL.with(|k| {
+            let mut kernel = k.borrow_mut();
+            let Some(pid) = self.pid else {
+                return Poll::Ready(Err(SyscallError::NoProcess));
+            };
+            let now = kernel.time.now;
+            let remaining = self.deadline.map(|deadline| deadline - now);
+            let this = &mut *self;
+            match kernel.poll_as(pid, &mut this.fds, remaining) {
+                Err(SyscallError::WouldBlock) => {}
+                result => {
+                    if let Some(timer) = this.timer.take() {
+                        kernel.time.timers.cancel(timer);
+                    }
+                    return Poll::Ready(result.map(|_| std::mem::take(&mut this.fds)));
+                }
+            }
+
+            let task = kernel.proc.processes.get(&pid).and_then(|p| p.task);
+            if let (Some(deadline), Some(task), None) = (this.deadline, task, this.timer) {
+                let timer = kernel.time.timers.schedule(deadline - now, now, Some(task));
+                this.timer = Some(timer);
+            }
+            kernel.pollers.push(cx.waker().clone());
+            Poll::Pending
+        })
     }
+}
 
-    pub fn get_group_by_gid(&self, gid: Gid) -> Option<&Group> {
-        self.users.get_group(gid)
+impl Drop for PollFuture {
+    fn drop(&mut self) {
+        if let Some(timer) = self.timer {
+            let _ = KERNEL.try_with(|k| {
+                if let Ok(mut kernel) = k.try_borrow_mut() {
+                    kernel.time.timers.cancel(timer);
+                }
+            });
+        }
     }
+}
 
-    /// Change file permissions
-    pub fn sys_chmod(&mut self, path: &str, mode: u16) -> SyscallResult<()> {
-        // Check if caller owns the file or is root
-        let process = self.get_current_process()?;
-        let euid = process.euid;
+/// Make an event queue
+pub fn epoll_create() -> SyscallResult<Fd> {
+    KERNEL.with(|k| k.borrow_mut().sys_epoll_create())
+}
 
-        // Get file metadata to check ownership
-        let meta = self.fs.vfs.metadata(path)?;
+/// Add, change or drop an interest of event queue `epfd`
+pub fn epoll_ctl(epfd: Fd, ctl: EpollCtl) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_epoll_ctl(epfd, ctl))
+}
 
-        // Only root or file owner can chmod
-        if euid.0 != 0 && meta.uid != euid.0 {
-            return Err(SyscallError::PermissionDenied);
+/// Wait for up to `max` interests of event queue `epfd` to be ready, for
+/// at most `timeout` milliseconds (`None` waits for ever)
+///
+/// The future resolves to the ready interests, none on a timeout. It is
+/// woken by the same I/O and signals as [`poll`], and at the queue's next
+/// timer expiry, so a daemon can sit in it without spinning.
+pub fn epoll_wait(epfd: Fd, max: usize, timeout: Option<f64>) -> EpollWait {
+    KERNEL.with(|k| {
+        let kernel = k.borrow();
+        EpollWait {
+            pid: kernel.proc.current,
+            epfd,
+            max,
+            deadline: timeout.map(|ms| kernel.time.now + ms.max(0.0)),
+            timer: None,
         }
+    })
+}
 
-        self.fs.vfs.chmod(path, mode)?;
-        Ok(())
-    }
+/// Future returned by [`epoll_wait`]
+pub struct EpollWait {
+    /// Process whose descriptor `epfd` is
+    pid: Option<Pid>,
+    epfd: Fd,
+    max: usize,
+    deadline: Option<f64>,
+    /// Timer that wakes the process's task, and when it fires
+    timer: Option<(TimerId, f64)>,
+}
 
-    /// Change file ownership
-    pub fn sys_chown(
-        &mut self,
-        path: &str,
-        uid: Option<u32>,
-        gid: Option<u32>,
-    ) -> SyscallResult<()> {
-        let process = self.get_current_process()?;
-        let euid = process.euid;
+impl std::future::Future for EpollWait {
+    type Output = SyscallResult<Vec<EpollEvent>>;
 
-        // Get file metadata to check ownership
-        let meta = self.fs.vfs.metadata(path)?;
+    fn poll(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Self::Output> {
+        use std::task::Poll;
 
-        // Only root can change ownership
-        if euid != Uid::ROOT {
-            // SEC-013: Non-root users can only change group if they own the file
-            // and only to a group they belong to
-            if uid.is_some() {
-                return Err(SyscallError::PermissionDenied);
+        KERNEL.with(|k| {
+            let mut kernel = k.borrow_mut();
+            let Some(pid) = self.pid else {
+                return Poll::Ready(Err(SyscallError::NoProcess));
+            };
+            let now = kernel.time.now;
+            let this = &mut *self;
+            let remaining = this.deadline.map(|deadline| deadline - now);
+            match kernel.epoll_wait_as(pid, this.epfd, this.max, remaining) {
+                Err(SyscallError::WouldBlock) => {}
+                result => {
+                    if let Some((timer, _)) = this.timer.take() {
+                        kernel.time.timers.cancel(timer);
+                    }
+                    return Poll::Ready(result);
+                }
             }
 
-            // Must own the file to change its group
-            if meta.uid != euid.0 {
-                return Err(SyscallError::PermissionDenied);
+            // Wake at the deadline or the queue's next timer, whichever
+            // comes first
+            let wake_at = [this.deadline, kernel.epoll_next_deadline(pid, this.epfd)]
+                .into_iter()
+                .flatten()
+                .min_by(f64::total_cmp);
+            let task = kernel.proc.processes.get(&pid).and_then(|p| p.task);
+            if let (Some(at), Some(task)) = (wake_at, task)
+                && this.timer.is_none_or(|(_, fires)| fires != at)
+            {
+                if let Some((timer, _)) = this.timer.take() {
+                    kernel.time.timers.cancel(timer);
+                }
+                let timer = kernel
+                    .time
+                    .timers
+                    .schedule((at - now).max(0.0), now, Some(task));
+                this.timer = Some((timer, at));
             }
+            kernel.pollers.push(cx.waker().clone());
+            Poll::Pending
+        })
+    }
+}
 
-            // Can only change to a group the user belongs to
-            if let Some(new_gid) = gid {
-                let groups = &process.groups;
-                let is_member = groups.iter().any(|g| g.0 == new_gid) || process.gid.0 == new_gid;
-                if !is_member {
-                    return Err(SyscallError::PermissionDenied);
+impl Drop for EpollWait {
+    fn drop(&mut self) {
+        if let Some((timer, _)) = self.timer {
+            let _ = KERNEL.try_with(|k| {
+                if let Ok(mut kernel) = k.try_borrow_mut() {
+                    kernel.time.timers.cancel(timer);
                 }
-            }
+            });
         }
-
-        self.fs.vfs.chown(path, uid, gid)?;
-        Ok(())
     }
+}
 
-    // ========== SOCKET SYSCALLS ==========
+/// The pending signal that interrupts the current process's blocking
+/// calls, if any
+pub fn interrupting_signal() -> Option<Signal> {
+    KERNEL.with(|k| k.borrow().interrupting_signal())
+}
 
-    /// Create a Unix domain socket
-    pub fn sys_socket(&mut self, socket_type: SocketType) -> SocketId {
-        self.ipc.sockets.socket(socket_type)
-    }
+// ========== SIGNAL API ==========
 
-    /// Close a socket
-    pub fn sys_socket_close(&mut self, id: SocketId) -> SocketResult<()> {
-        self.ipc.sockets.close(id)
-    }
+/// Send a signal to a process
+pub fn kill(pid: Pid, signal: Signal) -> SyscallResult<()> {
+    traced(
+        SyscallNr::Kill,
+        || format!("{}, {:?}", pid.0, signal),
+        |k| k.sys_kill(pid, signal),
+    )
+}
 
-    /// Bind a socket to an address
-    pub fn sys_bind(&mut self, id: SocketId, addr: SockAddr) -> SocketResult<()> {
-        self.ipc.sockets.bind(id, addr)
-    }
+/// Set signal handler for current process
+pub fn signal(sig: Signal, action: SignalAction) -> SyscallResult<SignalAction> {
+    KERNEL.with(|k| k.borrow_mut().sys_signal(sig, action))
+}
 
-    /// Listen for connections on a socket
-    pub fn sys_listen(&mut self, id: SocketId, backlog: usize) -> SocketResult<()> {
-        self.ipc.sockets.listen(id, backlog)
-    }
+/// Create a signalfd, or change the mask of `fd`
+pub fn signalfd(fd: Option<Fd>, mask: u16) -> SyscallResult<Fd> {
+    KERNEL.with(|k| k.borrow_mut().sys_signalfd(fd, mask))
+}
 
-    /// Accept a connection on a socket
-    pub fn sys_accept(&mut self, id: SocketId) -> SocketResult<(SocketId, SockAddr)> {
-        self.ipc.sockets.accept(id)
-    }
+/// Open the watchdog of a service
+pub fn watchdog_open(service: &str) -> SyscallResult<Fd> {
+    KERNEL.with(|k| k.borrow_mut().sys_watchdog_open(service))
+}
 
-    /// Connect a socket to an address
-    pub fn sys_connect(&mut self, id: SocketId, addr: &SockAddr) -> SocketResult<()> {
-        self.ipc.sockets.connect(id, addr)
-    }
+/// Pet the watchdog open on `fd`
+pub fn watchdog_pet(fd: Fd) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_watchdog_pet(fd))
+}
 
-    /// Send data on a connected socket
-    pub fn sys_send(&mut self, id: SocketId, data: &[u8]) -> SocketResult<usize> {
-        self.ipc.sockets.send(id, data)
-    }
+/// Deal with services that missed their watchdog deadline
+pub fn check_watchdogs() -> Vec<WatchdogIncident> {
+    KERNEL.with(|k| k.borrow_mut().check_watchdogs())
+}
 
-    /// Receive data from a connected socket
-    pub fn sys_recv(&mut self, id: SocketId) -> SocketResult<Vec<u8>> {
-        self.ipc.sockets.recv(id)
-    }
+/// Block a signal
+pub fn sigblock(sig: Signal) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_sigblock(sig))
+}
 
-    /// Send datagram to an address
-    pub fn sys_sendto(
-        &mut self,
-        id: SocketId,
-        data: &[u8],
-        addr: &SockAddr,
-    ) -> SocketResult<usize> {
-        self.ipc.sockets.sendto(id, data, addr)
-    }
+/// Unblock a signal
+pub fn sigunblock(sig: Signal) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_sigunblock(sig))
+}
 
-    /// Receive datagram
-    pub fn sys_recvfrom(&mut self, id: SocketId) -> SocketResult<(Vec<u8>, Option<SockAddr>)> {
-        self.ipc.sockets.recvfrom(id)
-    }
+/// Check for pending signals
+pub fn sigpending() -> SyscallResult<bool> {
+    KERNEL.with(|k| k.borrow().sys_sigpending())
+}
 
-    /// Set socket to non-blocking mode
-    pub fn sys_socket_set_nonblocking(
-        &mut self,
-        id: SocketId,
-        nonblocking: bool,
-    ) -> SocketResult<()> {
-        self.ipc.sockets.set_nonblocking(id, nonblocking)
-    }
+/// Get pending signals as a bitmask
+pub fn sigpending_mask() -> SyscallResult<u16> {
+    KERNEL.with(|k| k.borrow().sys_sigpending_mask())
+}
 
-    /// Get socket local address
-    pub fn sys_getsockname(&self, id: SocketId) -> SocketResult<Option<SockAddr>> {
-        self.ipc.sockets.local_addr(id)
-    }
+/// Examine and change blocked signals (sigprocmask)
+///
+/// # Arguments
+/// * `how` - How to modify the mask (Block, Unblock, SetMask)
+/// * `mask` - Bitmask of signals (bit N = signal N)
+///
+/// # Returns
+/// The old signal mask before modification
+pub fn sigprocmask(how: SigProcMaskHow, mask: u16) -> SyscallResult<u16> {
+    KERNEL.with(|k| k.borrow_mut().sys_sigprocmask(how, mask))
+}
 
-    /// Get socket peer address
-    pub fn sys_getpeername(&self, id: SocketId) -> SocketResult<Option<SockAddr>> {
-        self.ipc.sockets.peer_addr(id)
-    }
+/// Get current signal mask
+pub fn siggetmask() -> SyscallResult<u16> {
+    KERNEL.with(|k| k.borrow().sys_siggetmask())
 }
 
-impl Default for Kernel {
-    fn default() -> Self {
-        Self::new()
-    }
+/// Adjust process scheduling priority (nice)
+///
+/// Adds the increment to the current nice value and returns the new value.
+/// The result is clamped to the range -20 to +19.
+pub fn nice(increment: i8) -> SyscallResult<i8> {
+    KERNEL.with(|k| k.borrow_mut().sys_nice(increment))
 }
 
-// Global kernel instance
-thread_local! {
-    pub static KERNEL: RefCell<Kernel> = RefCell::new(Kernel::new());
+/// Resources the current process has used
+pub fn getrusage() -> SyscallResult<ResourceUsage> {
+    KERNEL.with(|k| k.borrow().sys_getrusage())
 }
 
-// ========== PUBLIC API ==========
-// These functions provide the syscall interface to user code
+/// Restart the current process's peak memory tracking, returning the old
+/// peak
+pub fn reset_peak_memory() -> SyscallResult<usize> {
+    KERNEL.with(|k| k.borrow_mut().sys_reset_peak_memory())
+}
 
-/// Open a file or device
-pub fn open(path: &str, flags: OpenFlags) -> SyscallResult<Fd> {
-    KERNEL.with(|k| k.borrow_mut().sys_open(path, flags))
+/// Get process scheduling priority
+///
+/// Returns the nice value for the specified process.
+/// Use pid=0 for current process.
+pub fn getpriority(pid: Pid) -> SyscallResult<i8> {
+    KERNEL.with(|k| k.borrow().sys_getpriority(pid))
 }
 
-/// Read from a file descriptor
-pub fn read(fd: Fd, buf: &mut [u8]) -> SyscallResult<usize> {
-    KERNEL.with(|k| k.borrow_mut().sys_read(fd, buf))
+/// Set process scheduling priority
+///
+/// Sets the nice value for the specified process.
+/// Use pid=0 for current process.
+pub fn setpriority(pid: Pid, priority: i8) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_setpriority(pid, priority))
 }
 
-/// Write to a file descriptor
-pub fn write(fd: Fd, buf: &[u8]) -> SyscallResult<usize> {
-    KERNEL.with(|k| k.borrow_mut().sys_write(fd, buf))
+/// Process signals for a process (call from runtime)
+pub fn process_signals(pid: Pid) -> Option<(Signal, SignalAction)> {
+    KERNEL.with(|k| k.borrow_mut().process_signals(pid))
 }
 
-/// Close a file descriptor
-pub fn close(fd: Fd) -> SyscallResult<()> {
-    KERNEL.with(|k| k.borrow_mut().sys_close(fd))
+/// Get process state
+pub fn get_process_state(pid: Pid) -> Option<ProcessState> {
+    KERNEL.with(|k| k.borrow().get_process_state(pid))
 }
 
-/// Create a pipe
-pub fn pipe() -> SyscallResult<(Fd, Fd)> {
-    KERNEL.with(|k| k.borrow_mut().sys_pipe())
+/// List all processes
+pub fn list_processes() -> Vec<(Pid, String, ProcessState)> {
+    KERNEL.with(|k| k.borrow().list_processes())
 }
 
-/// Create a window
-pub fn window_create(title: &str) -> SyscallResult<Fd> {
-    KERNEL.with(|k| k.borrow_mut().sys_window_create(title))
+// ========== DISK QUOTA API ==========
+
+/// Disk usage and limit for `uid` (root or CAP_SYS_ADMIN for others)
+pub fn quota(uid: Uid) -> SyscallResult<DiskQuota> {
+    KERNEL.with(|k| k.borrow().sys_quota(uid))
 }
 
-/// Get current working directory
-pub fn getcwd() -> SyscallResult<PathBuf> {
-    KERNEL.with(|k| k.borrow().sys_getcwd())
+/// Usage and limits for every user who owns files or has a limit
+pub fn quota_report() -> SyscallResult<Vec<DiskQuota>> {
+    KERNEL.with(|k| k.borrow().sys_quota_report())
 }
 
-/// Change working directory
-pub fn chdir(path: &str) -> SyscallResult<()> {
-    KERNEL.with(|k| k.borrow_mut().sys_chdir(path))
+/// Limit the bytes `uid` may own, or lift the limit with `None`
+pub fn set_quota(uid: Uid, limit: Option<u64>) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_set_quota(uid, limit))
 }
 
-/// Exit the current process
-pub fn exit(code: i32) -> SyscallResult<()> {
-    KERNEL.with(|k| k.borrow_mut().sys_exit(code))
+/// Check the root filesystem, repairing what `repair` allows (root)
+pub fn fsck(repair: Repair) -> SyscallResult<FsckReport> {
+    KERNEL.with(|k| k.borrow_mut().sys_fsck(repair))
 }
 
-/// Get current process ID
-pub fn getpid() -> SyscallResult<Pid> {
-    KERNEL.with(|k| k.borrow().sys_getpid())
+// ========== SNAPSHOT API ==========
+
+/// Take a snapshot of the filesystem called `name`, at wall-clock `time` (root)
+pub fn snapshot_create(name: &str, time: f64) -> SyscallResult<SnapshotInfo> {
+    KERNEL.with(|k| k.borrow_mut().sys_snapshot_create(name, time))
 }
 
-/// Get parent process ID
-pub fn getppid() -> SyscallResult<Option<Pid>> {
-    KERNEL.with(|k| k.borrow().sys_getppid())
+/// The snapshots, oldest first (root)
+pub fn snapshot_list() -> SyscallResult<Vec<SnapshotInfo>> {
+    KERNEL.with(|k| k.borrow_mut().sys_snapshot_list())
 }
 
-// ========== ENVIRONMENT API ==========
+/// What changed from snapshot `from` to `to`, or to now (root)
+pub fn snapshot_diff(from: &str, to: Option<&str>) -> SyscallResult<Vec<FsChange>> {
+    KERNEL.with(|k| k.borrow_mut().sys_snapshot_diff(from, to))
+}
 
-/// Get an environment variable
-pub fn getenv(name: &str) -> SyscallResult<Option<String>> {
-    KERNEL.with(|k| k.borrow().sys_getenv(name))
+/// Roll the filesystem back to snapshot `name` (root)
+pub fn snapshot_restore(name: &str) -> SyscallResult<Vec<FsChange>> {
+    KERNEL.with(|k| k.borrow_mut().sys_snapshot_restore(name))
 }
 
-/// Set an environment variable
-pub fn setenv(name: &str, value: &str) -> SyscallResult<()> {
-    KERNEL.with(|k| k.borrow_mut().sys_setenv(name, value))
+/// The versions of file `path` kept in snapshots, oldest first (root)
+pub fn snapshot_versions(path: &str) -> SyscallResult<Vec<FileVersion>> {
+    KERNEL.with(|k| k.borrow_mut().sys_snapshot_versions(path))
 }
 
-/// Remove an environment variable
-pub fn unsetenv(name: &str) -> SyscallResult<bool> {
-    KERNEL.with(|k| k.borrow_mut().sys_unsetenv(name))
+/// Delete snapshot `name` (root)
+pub fn snapshot_remove(name: &str) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_snapshot_remove(name))
 }
 
-/// Get all environment variables
-pub fn environ() -> SyscallResult<Vec<(String, String)>> {
-    KERNEL.with(|k| k.borrow().sys_environ())
+// ========== NETWORK PERMISSION API ==========
+
+/// Wait for permission to connect to `url`
+///
+/// Resolves at once for an allowed or denied origin. For one with no
+/// decision the user is asked, and the future waits for `netperm allow`
+/// or `netperm deny`, failing with `PermissionDenied` if neither comes
+/// within [`PROMPT_TIMEOUT`].
+pub fn net_access(url: &str) -> NetAccess {
+    KERNEL.with(|k| NetAccess {
+        url: url.to_string(),
+        deadline: k.borrow().time.now + PROMPT_TIMEOUT,
+    })
 }
 
-// ========== WAITPID API ==========
+/// Future returned by [`net_access`]
+pub struct NetAccess {
+    url: String,
+    deadline: f64,
+}
 
-/// Wait for a child process to change state
-pub fn waitpid(pid: i32, flags: WaitFlags) -> SyscallResult<(Pid, WaitStatus)> {
-    KERNEL.with(|k| k.borrow_mut().sys_waitpid(pid, flags))
+impl std::future::Future for NetAccess {
+    type Output = SyscallResult<()>;
+
+    fn poll(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Self::Output> {
+        use std::task::Poll;
+
+        KERNEL.with(|k| {
+            let mut kernel = k.borrow_mut();
+            if kernel.time.now >= self.deadline {
+                return Poll::Ready(Err(SyscallError::PermissionDenied));
+            }
+            match kernel.sys_net_access(&self.url) {
+                Err(SyscallError::WouldBlock) => {
+                    kernel.netperm.wait(cx.waker().clone());
+                    Poll::Pending
+                }
+                result => Poll::Ready(result),
+            }
+        })
+    }
 }
 
-// ========== PROCESS GROUP API ==========
+/// Check at once that a connection to `url` may be made; `WouldBlock`
+/// means the user has been asked and it can be tried again once they
+/// answer
+pub fn net_check(url: &str) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_net_access(url))
+}
 
-/// Get process group ID
-pub fn getpgid(pid: Pid) -> SyscallResult<Pgid> {
-    KERNEL.with(|k| k.borrow().sys_getpgid(pid))
+/// Allow or deny connections to `origin` (root)
+pub fn netperm_set(origin: &str, decision: NetDecision) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_netperm_set(origin, decision))
 }
 
-/// Set process group ID
-pub fn setpgid(pid: Pid, pgid: Pgid) -> SyscallResult<()> {
-    KERNEL.with(|k| k.borrow_mut().sys_setpgid(pid, pgid))
+/// Forget the decision for `origin` (root)
+pub fn netperm_revoke(origin: &str) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_netperm_revoke(origin))
 }
 
-// ========== IOCTL API ==========
+/// The network permission decisions, by origin
+pub fn netperm_list() -> Vec<(String, NetDecision)> {
+    KERNEL.with(|k| k.borrow_mut().sys_netperm_list())
+}
 
-/// Perform device-specific control operation
-pub fn ioctl(fd: Fd, request: IoctlRequest) -> SyscallResult<IoctlResult> {
-    KERNEL.with(|k| k.borrow_mut().sys_ioctl(fd, request))
+/// Origins connections are waiting to reach
+pub fn netperm_prompts() -> Vec<NetPrompt> {
+    KERNEL.with(|k| k.borrow().sys_netperm_prompts())
 }
 
-/// Create a directory
-pub fn mkdir(path: &str) -> SyscallResult<()> {
-    KERNEL.with(|k| k.borrow_mut().sys_mkdir(path))
+/// The oldest notice asking for a network permission
+pub fn take_net_notice() -> Option<String> {
+    KERNEL.with(|k| k.borrow_mut().take_net_notice())
 }
 
-/// List directory contents
-pub fn readdir(path: &str) -> SyscallResult<Vec<String>> {
-    KERNEL.with(|k| k.borrow_mut().sys_readdir(path))
+// ========== OFFLINE MODE AND HTTP CACHE API ==========
+
+/// Turn offline mode on or off
+pub fn set_offline(offline: bool) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_set_offline(offline))
 }
 
-/// Check if path exists
-pub fn exists(path: &str) -> SyscallResult<bool> {
-    KERNEL.with(|k| k.borrow().sys_exists(path))
+/// Check if offline mode is on
+pub fn offline() -> bool {
+    KERNEL.with(|k| k.borrow().sys_offline())
 }
 
-/// Get file/directory metadata
-pub fn metadata(path: &str) -> SyscallResult<FileMetadata> {
-    KERNEL.with(|k| k.borrow().sys_metadata(path))
+/// The cached response for `url`
+pub fn http_cache_get(url: &str) -> Option<CachedResponse> {
+    KERNEL.with(|k| k.borrow_mut().sys_http_cache_get(url))
 }
 
-/// Remove a file
-pub fn remove_file(path: &str) -> SyscallResult<()> {
-    KERNEL.with(|k| k.borrow_mut().sys_remove_file(path))
+/// Keep `response` in the HTTP cache
+pub fn http_cache_put(response: &CachedResponse) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_http_cache_put(response))
 }
 
-/// Remove a directory (must be empty)
-pub fn remove_dir(path: &str) -> SyscallResult<()> {
-    KERNEL.with(|k| k.borrow_mut().sys_remove_dir(path))
+/// Responses in the HTTP cache, and the bytes they take
+pub fn http_cache_usage() -> (usize, u64) {
+    KERNEL.with(|k| k.borrow().sys_http_cache_usage())
 }
 
-/// Remove a directory (POSIX-style alias for remove_dir)
-pub fn rmdir(path: &str) -> SyscallResult<()> {
-    remove_dir(path)
+/// Empty the HTTP cache (root)
+pub fn http_cache_clear() -> SyscallResult<usize> {
+    KERNEL.with(|k| k.borrow_mut().sys_http_cache_clear())
 }
 
-/// Remove a file (POSIX-style alias for remove_file)
-pub fn unlink(path: &str) -> SyscallResult<()> {
-    remove_file(path)
+// ========== EXTENDED ATTRIBUTE API ==========
+
+/// Set extended attribute `name` (`user.*` or `trusted.*`) of `path`
+pub fn setxattr(path: &str, name: &str, value: &[u8]) -> SyscallResult<()> {
+    traced(
+        SyscallNr::Setxattr,
+        || {
+            format!(
+                "{}, {}, {} bytes",
+                trace_str(path),
+                trace_str(name),
+                value.len()
+            )
+        },
+        |k| k.sys_setxattr(path, name, value),
+    )
 }
 
-/// Rename/move a file or directory
-pub fn rename(from: &str, to: &str) -> SyscallResult<()> {
-    KERNEL.with(|k| k.borrow_mut().sys_rename(from, to))
+/// Get extended attribute `name` of `path`
+pub fn getxattr(path: &str, name: &str) -> SyscallResult<Vec<u8>> {
+    traced(
+        SyscallNr::Getxattr,
+        || format!("{}, {}", trace_str(path), trace_str(name)),
+        |k| k.sys_getxattr(path, name),
+    )
 }
 
-/// Copy a file
-pub fn copy_file(from: &str, to: &str) -> SyscallResult<u64> {
-    KERNEL.with(|k| k.borrow_mut().sys_copy_file(from, to))
+/// Names of the extended attributes of `path`
+pub fn listxattr(path: &str) -> SyscallResult<Vec<String>> {
+    traced(
+        SyscallNr::Listxattr,
+        || trace_str(path),
+        |k| k.sys_listxattr(path),
+    )
 }
 
-/// Create a symbolic link
-pub fn symlink(target: &str, link_path: &str) -> SyscallResult<()> {
-    KERNEL.with(|k| k.borrow_mut().sys_symlink(target, link_path))
+/// Remove extended attribute `name` of `path`
+pub fn removexattr(path: &str, name: &str) -> SyscallResult<()> {
+    traced(
+        SyscallNr::Removexattr,
+        || format!("{}, {}", trace_str(path), trace_str(name)),
+        |k| k.sys_removexattr(path, name),
+    )
 }
 
-/// Read the target of a symbolic link
-pub fn read_link(path: &str) -> SyscallResult<String> {
-    KERNEL.with(|k| k.borrow().sys_read_link(path))
+// ========== FILE LOCKING API ==========
+
+/// Apply or remove a whole-file lock (`LOCK_SH`, `LOCK_EX` or `LOCK_UN`,
+/// plus `LOCK_NB`)
+pub fn flock(fd: Fd, operation: i32) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_flock(fd, operation))
 }
 
-/// Read entire file contents as string (convenience function)
-pub fn read_file(path: &str) -> SyscallResult<String> {
-    let fd = open(path, OpenFlags::READ)?;
-    let mut contents = Vec::new();
-    let mut buf = [0u8; 4096];
-    loop {
-        let n = read(fd, &mut buf)?;
-        if n == 0 {
-            break;
-        }
-        contents.extend_from_slice(&buf[..n]);
-    }
-    close(fd)?;
-    String::from_utf8(contents).map_err(|_| SyscallError::InvalidData)
+/// Set or clear a byte-range lock (0 = unlock, 1 = read, 2 = write)
+pub fn fcntl_lock(
+    fd: Fd,
+    lock_type: i32,
+    start: u64,
+    len: u64,
+    blocking: bool,
+) -> SyscallResult<()> {
+    KERNEL.with(|k| {
+        k.borrow_mut()
+            .sys_fcntl_lock(fd, lock_type, start, len, 0, blocking)
+    })
 }
 
-/// Write string to file (convenience function)
-pub fn write_file(path: &str, content: &str) -> SyscallResult<()> {
-    let fd = open(path, OpenFlags::WRITE)?;
-    write(fd, content.as_bytes())?;
-    close(fd)?;
-    Ok(())
+/// The lock that would stop a byte-range lock being placed, as
+/// (type, pid, start, len); type 0 if none would
+pub fn fcntl_getlk(
+    fd: Fd,
+    lock_type: i32,
+    start: u64,
+    len: u64,
+) -> SyscallResult<(i32, Pid, u64, u64)> {
+    KERNEL.with(|k| k.borrow().sys_fcntl_getlk(fd, lock_type, start, len, 0))
 }
 
-/// Get file stat (wrapper around metadata)
-pub fn stat(path: &str) -> SyscallResult<FileStat> {
-    let meta = metadata(path)?;
-    Ok(FileStat {
-        is_dir: meta.is_dir,
-        size: meta.size,
+/// Lock, test or unlock a section from the file position
+pub fn lockf(fd: Fd, cmd: i32, len: u64) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_lockf(fd, cmd, len))
+}
+
+/// The process holding a whole-file lock on `path` that conflicts with
+/// `lock_type` for the current process
+pub fn flock_holder(path: &str, lock_type: LockType) -> Option<Pid> {
+    KERNEL.with(|k| {
+        let k = k.borrow();
+        let pid = k.proc.current?;
+        k.ipc.file_locks.flock_holder(path, pid, lock_type)
     })
 }
 
-/// Simple stat result
-pub struct FileStat {
-    pub is_dir: bool,
-    pub size: u64,
+// ========== SEMAPHORE API ==========
+
+/// Get or create a set of `nsems` semaphores (negative key = private)
+pub fn semget(key: i32, nsems: usize, create: bool) -> SyscallResult<u32> {
+    KERNEL.with(|k| k.borrow_mut().sys_semget(key, nsems, create))
 }
 
-/// Duplicate a file descriptor
-pub fn dup(fd: Fd) -> SyscallResult<Fd> {
-    KERNEL.with(|k| k.borrow_mut().sys_dup(fd))
+/// Add `sem_op` to a semaphore (`SEM_UNDO`, `IPC_NOWAIT` in `flags`)
+pub fn semop(sem_id: u32, sem_num: usize, sem_op: i32, flags: i32) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_semop(sem_id, sem_num, sem_op, flags))
+}
+
+// ========== DEADLOCK DETECTION API ==========
+
+/// Record that process `pid` waits on bounded channel `channel` for
+/// `holders`; `Deadlock` if that closes a cycle
+///
+/// Used by the channel futures, which may be polled while the kernel is
+/// busy; the wait isn't tracked then.
+pub fn channel_wait(pid: Pid, channel: u64, holders: &[Pid]) -> SyscallResult<()> {
+    KERNEL
+        .try_with(|k| match k.try_borrow_mut() {
+            Ok(mut kernel) => kernel.channel_wait(pid, channel, holders),
+            Err(_) => Ok(()),
+        })
+        .unwrap_or(Ok(()))
+}
+
+/// Clear process `pid`'s wait on bounded channel `channel`
+pub fn channel_done(pid: Pid, channel: u64) {
+    let _ = KERNEL.try_with(|k| {
+        if let Ok(mut kernel) = k.try_borrow_mut() {
+            kernel.channel_done(pid, channel);
+        }
+    });
 }
 
-/// Spawn a new process (internal, will be expanded)
-pub fn spawn_process(name: &str) -> Pid {
-    KERNEL.with(|k| k.borrow_mut().spawn_process(name, None))
+/// Recently detected deadlocks, oldest first
+pub fn deadlocks() -> Vec<DeadlockReport> {
+    KERNEL.with(|k| k.borrow().deadlocks().iter().cloned().collect())
 }
 
-/// Fork the current process (like Linux fork(2))
-///
-/// Creates a child process with COW memory. Returns the child PID to the caller.
-/// The child's memory is shared with the parent until either writes to it,
-/// at which point the written pages are copied (copy-on-write).
-pub fn fork() -> SyscallResult<Pid> {
-    KERNEL.with(|k| k.borrow_mut().sys_fork())
+// ========== Tracing API ==========
+
+/// Enable tracing
+pub fn trace_enable() {
+    KERNEL.with(|k| k.borrow_mut().trace_enable())
 }
 
-/// Spawn a new login shell process for a user
-/// Creates a new session leader with proper credentials and environment
-pub fn spawn_login_shell(username: &str, uid: u32, gid: u32, home: &str, shell: &str) -> Pid {
-    KERNEL.with(|k| {
-        let current = k.borrow().proc.current;
-        k.borrow_mut()
-            .spawn_login_shell(username, Uid(uid), Gid(gid), home, shell, current)
-    })
+/// Disable tracing
+pub fn trace_disable() {
+    KERNEL.with(|k| k.borrow_mut().trace_disable())
 }
 
-/// Set the current running process
-pub fn set_current_process(pid: Pid) {
-    KERNEL.with(|k| k.borrow_mut().set_current(pid))
+/// Check if tracing is enabled
+pub fn trace_enabled() -> bool {
+    KERNEL.with(|k| k.borrow().trace_enabled())
 }
 
-/// setsid - Create a new session
-pub fn setsid() -> SyscallResult<u32> {
-    KERNEL.with(|k| k.borrow_mut().sys_setsid())
+/// Get trace summary
+pub fn trace_summary() -> TraceSummary {
+    KERNEL.with(|k| k.borrow().trace_summary())
 }
 
-/// getsid - Get session ID
-pub fn getsid(pid: Option<u32>) -> SyscallResult<u32> {
-    KERNEL.with(|k| k.borrow().sys_getsid(pid.map(Pid)))
+/// Reset trace data
+pub fn trace_reset() {
+    KERNEL.with(|k| k.borrow_mut().trace_reset())
 }
 
-/// Get information about the current session
-pub fn get_session_info() -> Option<(u32, u32, u32, String, String)> {
+/// Trace a custom event
+pub fn trace_event(category: TraceCategory, name: &str, detail: Option<&str>) {
     KERNEL.with(|k| {
-        let kernel = k.borrow();
-        kernel.current_process().map(|proc| {
-            (
-                proc.pid.0,
-                proc.sid.0,
-                proc.pgid.0,
-                proc.environ.get("USER").cloned().unwrap_or_default(),
-                proc.ctty.clone().unwrap_or_default(),
-            )
-        })
+        let mut kernel = k.borrow_mut();
+        let now = kernel.time.now;
+        if let Some(d) = detail {
+            kernel.tracer_mut().trace_detail(now, category, name, d);
+        } else {
+            kernel.tracer_mut().trace_instant(now, category, name);
+        }
     })
 }
 
-/// Save user database to /etc/passwd, /etc/shadow, /etc/group
-pub fn save_user_db() {
-    KERNEL.with(|k| k.borrow_mut().save_user_db())
+/// Record one executor tick for the timeline (see `kernel::tick`)
+pub fn trace_frame(start: f64, end: f64, polls: &[PollSpan]) {
+    KERNEL.with(|k| k.borrow_mut().trace_frame(start, end, polls))
 }
 
-/// Load user database from /etc/passwd, /etc/shadow, /etc/group
-pub fn load_user_db() -> bool {
-    KERNEL.with(|k| k.borrow_mut().load_user_db())
+/// Copy the recorded trace events, oldest first
+pub fn trace_events() -> Vec<TraceEvent> {
+    KERNEL.with(|k| k.borrow().tracer().events().iter().cloned().collect())
 }
 
-/// Push input to the console
-pub fn console_push_input(data: &[u8]) {
-    KERNEL.with(|k| {
-        if let Some(console) = k.borrow_mut().console() {
-            console.push_input(data);
-        }
-    })
+/// Name a kernel task that runs no process
+pub fn name_task(task: TaskId, name: &str) {
+    KERNEL.with(|k| k.borrow_mut().name_task(task, name))
 }
 
-/// Take console output
-pub fn console_take_output() -> Vec<u8> {
-    KERNEL.with(|k| {
-        k.borrow_mut()
-            .console()
-            .map(|c| c.take_output())
-            .unwrap_or_default()
-    })
+/// The kernel symbol map (see [`crate::kernel::ksyms`])
+pub fn ksyms() -> SymbolMap {
+    KERNEL.with(|k| k.borrow().ksyms())
 }
 
-// ========== MEMORY API ==========
+// ========== PTRACE API ==========
 
-/// Allocate a memory region
-pub fn mem_alloc(size: usize, prot: Protection) -> SyscallResult<RegionId> {
-    KERNEL.with(|k| k.borrow_mut().sys_alloc(size, prot))
+/// Start tracing another process's syscalls
+pub fn ptrace_attach(pid: Pid, options: PtraceOptions) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_ptrace_attach(pid, options))
 }
 
-/// Free a memory region
-pub fn mem_free(region_id: RegionId) -> SyscallResult<()> {
-    KERNEL.with(|k| k.borrow_mut().sys_free(region_id))
+/// Stop tracing a process
+pub fn ptrace_detach(pid: Pid) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_ptrace_detach(pid))
 }
 
-/// Read from a memory region
-pub fn mem_read(region_id: RegionId, offset: usize, buf: &mut [u8]) -> SyscallResult<usize> {
-    KERNEL.with(|k| k.borrow_mut().sys_mem_read(region_id, offset, buf))
+/// Collect queued trace events and the number dropped since the last call
+pub fn ptrace_events() -> SyscallResult<(Vec<PtraceEvent>, u64)> {
+    KERNEL.with(|k| k.borrow_mut().sys_ptrace_events())
 }
 
-/// Write to a memory region
-pub fn mem_write(region_id: RegionId, offset: usize, buf: &[u8]) -> SyscallResult<usize> {
-    KERNEL.with(|k| k.borrow_mut().sys_mem_write(region_id, offset, buf))
+/// Processes traced by the current process
+pub fn ptrace_tracees() -> SyscallResult<Vec<Pid>> {
+    KERNEL.with(|k| k.borrow().sys_ptrace_tracees())
 }
 
-/// Create a shared memory segment
-pub fn shmget(size: usize) -> SyscallResult<ShmId> {
-    KERNEL.with(|k| k.borrow_mut().sys_shmget(size))
+/// Watch a path for opens, writes or unlinks
+pub fn debug_watch_path(path: &str, ops: &[PathOp]) -> SyscallResult<u32> {
+    KERNEL.with(|k| k.borrow_mut().sys_debug_watch_path(path, ops))
 }
 
-/// Attach to a shared memory segment
-pub fn shmat(shm_id: ShmId, prot: Protection) -> SyscallResult<RegionId> {
-    KERNEL.with(|k| k.borrow_mut().sys_shmat(shm_id, prot))
+/// Remove a path watchpoint
+pub fn debug_unwatch_path(id: u32) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_debug_unwatch_path(id))
 }
 
-/// Detach from a shared memory segment
-pub fn shmdt(shm_id: ShmId) -> SyscallResult<()> {
-    KERNEL.with(|k| k.borrow_mut().sys_shmdt(shm_id))
+/// List path watchpoints
+pub fn debug_path_watches() -> Vec<PathWatch> {
+    KERNEL.with(|k| k.borrow().sys_debug_path_watches())
 }
 
-/// Sync local changes to shared memory
-pub fn shm_sync(shm_id: ShmId) -> SyscallResult<()> {
-    KERNEL.with(|k| k.borrow_mut().sys_shm_sync(shm_id))
+/// Collect path watchpoint hits since the last call
+pub fn debug_path_hits() -> SyscallResult<Vec<PathWatchHit>> {
+    KERNEL.with(|k| k.borrow_mut().sys_debug_path_hits())
 }
 
-/// Refresh local region from shared memory
-pub fn shm_refresh(shm_id: ShmId) -> SyscallResult<()> {
-    KERNEL.with(|k| k.borrow_mut().sys_shm_refresh(shm_id))
+/// Resume processes paused by path watchpoints
+pub fn debug_continue() -> SyscallResult<Vec<Pid>> {
+    KERNEL.with(|k| k.borrow_mut().sys_debug_continue())
 }
 
-/// Get shared memory info
-pub fn shm_info(shm_id: ShmId) -> SyscallResult<ShmInfo> {
-    KERNEL.with(|k| k.borrow().sys_shm_info(shm_id))
+/// Snapshot kernel objects, fd tables and mounts
+pub fn object_snapshot() -> ObjectSnapshot {
+    KERNEL.with(|k| k.borrow().object_snapshot())
 }
 
-/// List all shared memory segments
-pub fn shm_list() -> SyscallResult<Vec<ShmInfo>> {
-    KERNEL.with(|k| k.borrow().sys_shm_list())
-}
+// ========== SESSION LOCK API ==========
 
-/// Get memory stats for current process
-pub fn memstats() -> SyscallResult<MemoryStats> {
-    KERNEL.with(|k| k.borrow().sys_memstats())
+/// Record an input event reported by the platform at `now`
+pub fn session_input(now: f64) {
+    KERNEL.with(|k| k.borrow_mut().session_input(now))
 }
 
-/// Set memory limit for current process
-pub fn set_memlimit(limit: usize) -> SyscallResult<()> {
-    KERNEL.with(|k| k.borrow_mut().sys_set_memlimit(limit))
+/// Lock the session if it has been idle past the timeout; returns true if
+/// it just locked
+pub fn session_poll(now: f64) -> bool {
+    KERNEL.with(|k| k.borrow_mut().session_poll(now))
 }
 
-/// Get system-wide memory stats
-pub fn system_memstats() -> SyscallResult<SystemMemoryStats> {
-    KERNEL.with(|k| k.borrow().sys_system_memstats())
+/// Lock the session for the current user
+pub fn session_lock() -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_session_lock())
 }
 
-// ========== TIMER API ==========
+/// Unlock the session with a password
+pub fn session_unlock(password: &str) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_session_unlock(password))
+}
 
-/// Get current kernel time (monotonic ms)
-pub fn now() -> f64 {
-    KERNEL.with(|k| k.borrow().sys_now())
+/// Check if the session is locked
+pub fn session_locked() -> bool {
+    KERNEL.with(|k| k.borrow().session().is_locked())
 }
 
-/// Set current time (called from runtime)
-pub fn set_time(time: f64) {
-    KERNEL.with(|k| k.borrow_mut().set_time(time))
+/// Snapshot of the session lock and idle monitor
+pub fn session() -> SessionLock {
+    KERNEL.with(|k| k.borrow().session().clone())
 }
 
-/// Schedule a one-shot timer
-pub fn timer_set(delay_ms: f64, wake_task: Option<TaskId>) -> SyscallResult<TimerId> {
-    KERNEL.with(|k| k.borrow_mut().sys_timer_set(delay_ms, wake_task))
+/// Set the idle timeout in milliseconds; `None` disables auto-lock
+pub fn set_idle_timeout(timeout: Option<f64>) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_set_idle_timeout(timeout))
 }
 
-/// Schedule a repeating interval timer
-pub fn timer_interval(interval_ms: f64, wake_task: Option<TaskId>) -> SyscallResult<TimerId> {
-    KERNEL.with(|k| k.borrow_mut().sys_timer_interval(interval_ms, wake_task))
+// ========== POWER API ==========
+
+/// Snapshot of the power governor
+pub fn power() -> PowerGovernor {
+    KERNEL.with(|k| k.borrow().power().clone())
 }
 
-/// Cancel a timer
-pub fn timer_cancel(timer_id: TimerId) -> SyscallResult<bool> {
-    KERNEL.with(|k| k.borrow_mut().sys_timer_cancel(timer_id))
+/// Select the power governor
+pub fn set_governor(governor: Governor) {
+    KERNEL.with(|k| k.borrow_mut().sys_set_governor(governor))
 }
 
-/// Check if a timer is pending
-pub fn timer_pending(timer_id: TimerId) -> SyscallResult<bool> {
-    KERNEL.with(|k| k.borrow().sys_timer_pending(timer_id))
+/// Report page visibility; returns true if the power mode changed
+pub fn set_page_hidden(hidden: bool) -> bool {
+    KERNEL.with(|k| k.borrow_mut().set_page_hidden(hidden))
 }
 
-/// Set an alarm (sends SIGALRM to current process after delay)
-pub fn alarm(delay_ms: f64) -> SyscallResult<TimerId> {
-    KERNEL.with(|k| k.borrow_mut().sys_alarm(delay_ms))
+/// Check if the system is saving power
+pub fn power_saving() -> bool {
+    KERNEL.with(|k| k.borrow().power().is_saving())
 }
 
-/// Get time until next timer fires
-pub fn time_until_next_timer() -> Option<f64> {
-    KERNEL.with(|k| k.borrow().time_until_next_timer())
+/// Milliseconds the platform should wait between executor ticks
+pub fn power_tick_interval() -> f64 {
+    KERNEL.with(|k| k.borrow().power().tick_interval())
 }
 
-/// Get pending timer count
-pub fn pending_timer_count() -> usize {
-    KERNEL.with(|k| k.borrow().pending_timer_count())
+/// Ask to persist state at `now`; returns true if it should be written now
+pub fn power_request_persist(now: f64) -> bool {
+    KERNEL.with(|k| k.borrow_mut().power_request_persist(now))
 }
 
-/// Tick timers and return tasks to wake (call from runtime)
-pub fn tick_timers() -> Vec<TaskId> {
-    KERNEL.with(|k| k.borrow_mut().tick_timers())
+/// Check if a batched persistence write is due at `now`
+pub fn power_persist_due(now: f64) -> bool {
+    KERNEL.with(|k| k.borrow_mut().power_persist_due(now))
 }
 
-// ========== SIGNAL API ==========
+// ========== MEMORY PRESSURE API ==========
 
-/// Send a signal to a process
-pub fn kill(pid: Pid, signal: Signal) -> SyscallResult<()> {
-    KERNEL.with(|k| k.borrow_mut().sys_kill(pid, signal))
+/// Check memory pressure if a check is due, with the platform's heap
+/// figures if it has them; returns the new level if it changed
+///
+/// When the level rises, the hooks registered with
+/// [`pressure::on_pressure`] trim their caches, and what they freed is
+/// logged.
+pub fn memory_pressure_tick(platform: Option<MemorySample>) -> Option<PressureLevel> {
+    let (previous, level) = KERNEL.with(|k| {
+        let mut k = k.borrow_mut();
+        let now = k.time.now;
+        if !k.pressure.due(now) {
+            return None;
+        }
+        let previous = k.pressure.level();
+        Some((previous, k.check_memory_pressure(platform)?))
+    })?;
+    if level > previous {
+        for freed in pressure::run_trim_hooks(level) {
+            klog(LogLevel::Info, "memory", &format!("trimmed {}", freed));
+        }
+    }
+    Some(level)
 }
 
-/// Set signal handler for current process
-pub fn signal(sig: Signal, action: SignalAction) -> SyscallResult<SignalAction> {
-    KERNEL.with(|k| k.borrow_mut().sys_signal(sig, action))
+/// The current memory pressure level
+pub fn memory_pressure() -> PressureLevel {
+    KERNEL.with(|k| k.borrow().pressure().level())
 }
 
-/// Block a signal
-pub fn sigblock(sig: Signal) -> SyscallResult<()> {
-    KERNEL.with(|k| k.borrow_mut().sys_sigblock(sig))
+// ========== GUEST SESSION API ==========
+
+/// Start a guest session with a throwaway overlay over /
+pub fn guest_start() -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_guest_start())
 }
 
-/// Unblock a signal
-pub fn sigunblock(sig: Signal) -> SyscallResult<()> {
-    KERNEL.with(|k| k.borrow_mut().sys_sigunblock(sig))
+/// Keep a path when the guest session ends; returns the resolved path
+pub fn guest_export(path: &str) -> SyscallResult<String> {
+    KERNEL.with(|k| k.borrow_mut().sys_guest_export(path))
 }
 
-/// Check for pending signals
-pub fn sigpending() -> SyscallResult<bool> {
-    KERNEL.with(|k| k.borrow().sys_sigpending())
+/// End the guest session, discarding unexported changes
+pub fn guest_end() -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_guest_end())
 }
 
-/// Get pending signals as a bitmask
-pub fn sigpending_mask() -> SyscallResult<u16> {
-    KERNEL.with(|k| k.borrow().sys_sigpending_mask())
+/// Check if a guest session is active
+pub fn guest_active() -> bool {
+    KERNEL.with(|k| k.borrow().guest().is_some())
 }
 
-/// Examine and change blocked signals (sigprocmask)
-///
-/// # Arguments
-/// * `how` - How to modify the mask (Block, Unblock, SetMask)
-/// * `mask` - Bitmask of signals (bit N = signal N)
-///
-/// # Returns
-/// The old signal mask before modification
-pub fn sigprocmask(how: SigProcMaskHow, mask: u16) -> SyscallResult<u16> {
-    KERNEL.with(|k| k.borrow_mut().sys_sigprocmask(how, mask))
+/// Paths the guest changed, and those that will be kept
+pub fn guest_status() -> Option<(Vec<FsChange>, Vec<String>)> {
+    KERNEL.with(|k| {
+        let k = k.borrow();
+        let guest = k.guest()?;
+        let exports = guest.exports().map(String::from).collect();
+        Some((guest.upper(k.vfs()), exports))
+    })
 }
 
-/// Get current signal mask
-pub fn siggetmask() -> SyscallResult<u16> {
-    KERNEL.with(|k| k.borrow().sys_siggetmask())
+// ========== MOUNT API ==========
+
+/// Mount an overlay of `lower` (topmost first) at `target`
+pub fn mount_overlay(
+    source: &str,
+    lower: &[&str],
+    target: &str,
+    options: MountOptions,
+) -> SyscallResult<()> {
+    KERNEL.with(|k| {
+        k.borrow_mut()
+            .sys_mount_overlay(source, lower, target, options)
+    })
 }
 
-/// Adjust process scheduling priority (nice)
-///
-/// Adds the increment to the current nice value and returns the new value.
-/// The result is clamped to the range -20 to +19.
-pub fn nice(increment: i8) -> SyscallResult<i8> {
-    KERNEL.with(|k| k.borrow_mut().sys_nice(increment))
+/// Mount a filesystem on `target`: a fresh one for a tmpfs, otherwise
+/// one the main VFS or a synthetic filesystem serves
+pub fn mount(
+    source: &str,
+    target: &str,
+    fstype: FsType,
+    options: MountOptions,
+) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_mount(source, target, fstype, options))
 }
 
-/// Get process scheduling priority
-///
-/// Returns the nice value for the specified process.
-/// Use pid=0 for current process.
-pub fn getpriority(pid: Pid) -> SyscallResult<i8> {
-    KERNEL.with(|k| k.borrow().sys_getpriority(pid))
+/// Mount the tar archive at `archive` read-only on `target`
+pub fn mount_tar(archive: &str, target: &str, options: MountOptions) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_mount_tar(archive, target, options))
 }
 
-/// Set process scheduling priority
-///
-/// Sets the nice value for the specified process.
-/// Use pid=0 for current process.
-pub fn setpriority(pid: Pid, priority: i8) -> SyscallResult<()> {
-    KERNEL.with(|k| k.borrow_mut().sys_setpriority(pid, priority))
+/// Mount the vault in the file `source` on `target`, making a new one if
+/// `create` and there is none
+pub fn mount_encrypted(
+    source: &str,
+    target: &str,
+    passphrase: &str,
+    create: bool,
+    options: MountOptions,
+) -> SyscallResult<()> {
+    KERNEL.with(|k| {
+        k.borrow_mut()
+            .sys_mount_encrypted(source, target, passphrase, create, options)
+    })
 }
 
-/// Process signals for a process (call from runtime)
-pub fn process_signals(pid: Pid) -> Option<(Signal, SignalAction)> {
-    KERNEL.with(|k| k.borrow_mut().process_signals(pid))
+/// Mount the WebDAV share `fs`, read from `url`, on `target`
+pub fn mount_webdav(
+    url: &str,
+    fs: WebDavFs,
+    target: &str,
+    options: MountOptions,
+) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_mount_webdav(url, fs, target, options))
 }
 
-/// Get process state
-pub fn get_process_state(pid: Pid) -> Option<ProcessState> {
-    KERNEL.with(|k| k.borrow().get_process_state(pid))
+/// Unmount the filesystem at `target`
+pub fn umount(target: &str) -> SyscallResult<MountEntry> {
+    KERNEL.with(|k| k.borrow_mut().sys_umount(target))
 }
 
-/// List all processes
-pub fn list_processes() -> Vec<(Pid, String, ProcessState)> {
-    KERNEL.with(|k| k.borrow().list_processes())
+/// Change the options of the mount at `target`, as `mount -o remount`
+pub fn remount(target: &str, options: &str) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_remount(target, options))
 }
 
-// ========== Tracing API ==========
+/// Check if saves of the root filesystem go through the journal
+pub fn persist_journaled() -> bool {
+    KERNEL.with(|k| {
+        k.borrow()
+            .mounts()
+            .get_mount("/")
+            .is_some_and(|root| root.options.journal)
+    })
+}
 
-/// Enable tracing
-pub fn trace_enable() {
-    KERNEL.with(|k| k.borrow_mut().trace_enable())
+/// Check if saves of the root filesystem are compressed
+pub fn persist_compressed() -> bool {
+    KERNEL.with(|k| {
+        k.borrow()
+            .mounts()
+            .get_mount("/")
+            .is_some_and(|root| root.options.compress)
+    })
 }
 
-/// Disable tracing
-pub fn trace_disable() {
-    KERNEL.with(|k| k.borrow_mut().trace_disable())
+// ========== SYSTEM PARTITION API ==========
+
+/// Boot the system partition from its slots, falling back to `factory`
+pub fn system_boot(factory: &SystemImage) -> SyscallResult<SystemBoot> {
+    KERNEL.with(|k| k.borrow_mut().sys_system_boot(factory))
 }
 
-/// Check if tracing is enabled
-pub fn trace_enabled() -> bool {
-    KERNEL.with(|k| k.borrow().trace_enabled())
+/// Confirm the running system booted; returns false if nothing was on trial
+pub fn system_mark_good() -> SyscallResult<bool> {
+    KERNEL.with(|k| k.borrow_mut().sys_system_mark_good())
 }
 
-/// Get trace summary
-pub fn trace_summary() -> TraceSummary {
-    KERNEL.with(|k| k.borrow().trace_summary())
+/// Stage a serialized system image; returns the slot and image version
+pub fn system_stage(data: &[u8]) -> SyscallResult<(Slot, String)> {
+    KERNEL.with(|k| k.borrow_mut().sys_system_stage(data))
 }
 
-/// Reset trace data
-pub fn trace_reset() {
-    KERNEL.with(|k| k.borrow_mut().trace_reset())
+/// Switch back to the other slot at the next boot
+pub fn system_rollback() -> SyscallResult<Slot> {
+    KERNEL.with(|k| k.borrow_mut().sys_system_rollback())
 }
 
-/// Trace a custom event
-pub fn trace_event(category: TraceCategory, name: &str, detail: Option<&str>) {
+/// The last system boot and the slot state on disk
+pub fn system_status() -> (Option<SystemBoot>, SlotState) {
     KERNEL.with(|k| {
-        let mut kernel = k.borrow_mut();
-        let now = kernel.time.now;
-        if let Some(d) = detail {
-            kernel.tracer_mut().trace_detail(now, category, name, d);
-        } else {
-            kernel.tracer_mut().trace_instant(now, category, name);
-        }
+        let mut k = k.borrow_mut();
+        (k.system().cloned(), k.system_state())
     })
 }
 
@@ -4595,6 +9310,11 @@ pub fn setgroups(groups: Vec<Gid>) -> SyscallResult<()> {
     KERNEL.with(|k| k.borrow_mut().sys_setgroups(groups))
 }
 
+/// Set the file mode creation mask, returning the previous one
+pub fn umask(mask: u16) -> SyscallResult<u16> {
+    KERNEL.with(|k| k.borrow_mut().sys_umask(mask))
+}
+
 /// Get user by name
 pub fn get_user_by_name(name: &str) -> Option<User> {
     KERNEL.with(|k| k.borrow().get_user_by_name(name).cloned())
@@ -4661,14 +9381,90 @@ pub fn add_group(name: &str) -> SyscallResult<Gid> {
     })
 }
 
+/// Remove a user (requires root)
+///
+/// Fails with `Busy` while a process runs as the user, and root can't be
+/// removed.
+pub fn remove_user(name: &str) -> SyscallResult<User> {
+    KERNEL.with(|k| {
+        let mut kernel = k.borrow_mut();
+        if kernel.sys_geteuid()? != Uid::ROOT {
+            return Err(SyscallError::PermissionDenied);
+        }
+        let uid = kernel
+            .users
+            .get_user_by_name(name)
+            .ok_or(SyscallError::NotFound)?
+            .uid;
+        if uid == Uid::ROOT {
+            return Err(SyscallError::PermissionDenied);
+        }
+        let running = kernel.proc.processes.values().any(|p| {
+            !matches!(p.state, ProcessState::Zombie(_)) && (p.uid == uid || p.euid == uid)
+        });
+        if running {
+            return Err(SyscallError::Busy);
+        }
+        kernel
+            .users
+            .remove_user(name)
+            .map_err(|_| SyscallError::NotFound)
+    })
+}
+
+/// Remove a group (requires root); a user's primary group is `Busy`
+pub fn remove_group(name: &str) -> SyscallResult<Group> {
+    KERNEL.with(|k| {
+        let mut kernel = k.borrow_mut();
+        if kernel.sys_geteuid()? != Uid::ROOT {
+            return Err(SyscallError::PermissionDenied);
+        }
+        if kernel.users.get_group_by_name(name).is_none() {
+            return Err(SyscallError::NotFound);
+        }
+        kernel
+            .users
+            .remove_group(name)
+            .map_err(|_| SyscallError::Busy)
+    })
+}
+
+/// Change a user's shell, home directory or groups (requires root)
+///
+/// A user or group that doesn't exist is `NotFound`, and then nothing
+/// changes.
+pub fn modify_user(name: &str, change: &UserChange) -> SyscallResult<()> {
+    KERNEL.with(|k| {
+        let mut kernel = k.borrow_mut();
+        if kernel.sys_geteuid()? != Uid::ROOT {
+            return Err(SyscallError::PermissionDenied);
+        }
+        kernel
+            .users
+            .modify_user(name, change)
+            .map_err(|_| SyscallError::NotFound)
+    })
+}
+
 /// Change file permissions
 pub fn chmod(path: &str, mode: u16) -> SyscallResult<()> {
-    KERNEL.with(|k| k.borrow_mut().sys_chmod(path, mode))
+    traced(
+        SyscallNr::Chmod,
+        || format!("{}, {:#o}", trace_str(path), mode),
+        |k| k.sys_chmod(path, mode),
+    )
 }
 
 /// Change file ownership
 pub fn chown(path: &str, uid: Option<u32>, gid: Option<u32>) -> SyscallResult<()> {
-    KERNEL.with(|k| k.borrow_mut().sys_chown(path, uid, gid))
+    traced(
+        SyscallNr::Chown,
+        || {
+            let id = |v: Option<u32>| v.map_or("-1".to_string(), |v| v.to_string());
+            format!("{}, {}, {}", trace_str(path), id(uid), id(gid))
+        },
+        |k| k.sys_chown(path, uid, gid),
+    )
 }
 
 // ========== EXEC FAMILY ==========
@@ -4701,6 +9497,23 @@ pub fn execl(path: &str, arg0: &str, args: &[&str]) -> SyscallResult<()> {
     execve(path, &argv, None)
 }
 
+/// Check that `path` may be run (not on a `noexec` mount); returns whether
+/// its setuid and setgid bits should be honored (not on a `nosuid` mount)
+pub fn check_exec(path: &str) -> SyscallResult<bool> {
+    KERNEL.with(|k| k.borrow().sys_check_exec(path))
+}
+
+/// Run the program at `path` with the effective ids its setuid and setgid
+/// bits give; returns whether they changed (see [`Kernel::sys_exec_setid`])
+pub fn exec_setid(path: &str) -> SyscallResult<bool> {
+    KERNEL.with(|k| k.borrow_mut().sys_exec_setid(path))
+}
+
+/// Put back the effective ids from before [`exec_setid`] changed them
+pub fn exec_restore() -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_exec_restore())
+}
+
 /// execlp - Execute a program by name (searches PATH)
 pub fn execlp(name: &str, arg0: &str, args: &[&str]) -> SyscallResult<()> {
     // Search PATH for the command
@@ -4764,6 +9577,40 @@ pub fn process_exit_status(pid: Pid, exit_code: i32) -> SyscallResult<()> {
     KERNEL.with(|k| k.borrow_mut().sys_process_exit_status(pid, exit_code))
 }
 
+/// Handle tasks that panicked (see `kernel::tick`)
+pub fn task_faults(faults: &[TaskFault]) -> Vec<CrashReport> {
+    let reports: Vec<CrashReport> = KERNEL.with(|k| {
+        let mut kernel = k.borrow_mut();
+        faults.iter().map(|f| kernel.task_fault(f)).collect()
+    });
+    if !reports.is_empty() {
+        super::crash::run_fault_hooks();
+    }
+    reports
+}
+
+/// Recent crash reports, oldest first
+pub fn crash_reports() -> Vec<CrashReport> {
+    KERNEL.with(|k| k.borrow().crash_reports().iter().cloned().collect())
+}
+
+// ========== KERNEL LOG API ==========
+
+/// Add a message to the kernel log (see `dmesg`)
+pub fn klog(level: LogLevel, facility: &str, message: &str) {
+    KERNEL.with(|k| k.borrow_mut().klog(level, facility, message))
+}
+
+/// The kernel log, oldest first
+pub fn klog_read() -> Vec<LogEntry> {
+    KERNEL.with(|k| k.borrow().sys_klog_read())
+}
+
+/// Empty the kernel log (root only)
+pub fn klog_clear() -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_klog_clear())
+}
+
 /// Get exec information for current process
 ///
 /// Returns (path, args) if the process has been exec'd.
@@ -4818,6 +9665,53 @@ pub fn recv(id: SocketId) -> SocketResult<Vec<u8>> {
     KERNEL.with(|k| k.borrow_mut().sys_recv(id))
 }
 
+/// Receive data from a connected socket, waiting for some to arrive
+///
+/// Resolves as soon as [`recv`] would succeed or fail for another reason.
+/// A socket in non-blocking mode (see [`socket_set_nonblocking`]) fails
+/// with `WouldBlock` at once instead. Sends to it wake the future to try
+/// again.
+pub fn recv_wait(id: SocketId) -> RecvWait {
+    RecvWait { id }
+}
+
+/// Future returned by [`recv_wait`]
+pub struct RecvWait {
+    id: SocketId,
+}
+
+impl std::future::Future for RecvWait {
+    type Output = SocketResult<Vec<u8>>;
+
+    fn poll(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Self::Output> {
+        use std::task::Poll;
+
+        KERNEL.with(|k| {
+            let mut kernel = k.borrow_mut();
+            let nonblocking = kernel
+                .ipc
+                .sockets
+                .get(self.id)
+                .is_some_and(|s| s.non_blocking);
+            // Receiving wakes every poller, so only try once there is
+            // something to report, or two waits would wake each other
+            if kernel.ipc.sockets.poll_ready(self.id).readable || nonblocking {
+                return Poll::Ready(kernel.sys_recv(self.id));
+            }
+            match kernel.ipc.sockets.recv(self.id) {
+                Err(SocketError::WouldBlock) => {
+                    kernel.pollers.push(cx.waker().clone());
+                    Poll::Pending
+                }
+                result => Poll::Ready(result),
+            }
+        })
+    }
+}
+
 /// Send datagram to address
 pub fn sendto(id: SocketId, data: &[u8], path: &str) -> SocketResult<usize> {
     KERNEL.with(|k| k.borrow_mut().sys_sendto(id, data, &SockAddr::new(path)))
@@ -4846,15 +9740,112 @@ pub fn getpeername(id: SocketId) -> SocketResult<Option<SockAddr>> {
 // ========== PERSISTENCE API ==========
 
 /// Get a JSON snapshot of the VFS for persistence
+///
+/// Mounted vaults are written back to their files first, so they are in
+/// it. During a guest session this is the real filesystem under the
+/// overlay, so guest changes are never saved.
 pub fn vfs_snapshot() -> std::io::Result<Vec<u8>> {
-    KERNEL.with(|k| k.borrow().vfs().to_json())
+    KERNEL.with(|k| {
+        let mut k = k.borrow_mut();
+        k.save_vaults()?;
+        k.save_ipc_state()?;
+        k.persistent_vfs_json()
+    })
 }
 
 /// Restore VFS from a JSON snapshot
-pub fn vfs_restore(data: &[u8]) -> std::io::Result<()> {
-    let vfs = MemoryFs::from_json(data)?;
-    KERNEL.with(|k| k.borrow_mut().set_vfs(vfs));
-    Ok(())
+///
+/// The snapshot is checked first and its errors repaired, as
+/// `fsck -p` would; the report says what was wrong.
+pub fn vfs_restore(data: &[u8]) -> std::io::Result<FsckReport> {
+    Ok(install_vfs(MemoryFs::from_json(data)?))
+}
+
+/// Write the whole VFS as a tar archive (see [`crate::vfs::export`])
+///
+/// Like [`vfs_snapshot`], vaults are written back first, and during a
+/// guest session it is the real filesystem under the overlay.
+pub fn vfs_export_tar() -> std::io::Result<(Vec<u8>, ArchiveStats)> {
+    KERNEL.with(|k| {
+        let mut k = k.borrow_mut();
+        k.save_vaults()?;
+        k.save_ipc_state()?;
+        match &k.guest {
+            Some(guest) => export::export(&mut MemoryFs::restore(guest.lower().clone())?),
+            None => export::export(&mut k.fs.vfs),
+        }
+    })
+}
+
+/// Replace the VFS with the contents of a tar archive
+///
+/// Checked and repaired as [`vfs_restore`] does.
+pub fn vfs_import_tar(data: &[u8]) -> std::io::Result<(FsckReport, ArchiveStats)> {
+    let (vfs, stats) = export::import(data)?;
+    Ok((install_vfs(vfs), stats))
+}
+
+/// Check and repair `vfs`, then make it the VFS
+fn install_vfs(mut vfs: MemoryFs) -> FsckReport {
+    let report = vfs.fsck(Repair::Errors);
+    KERNEL.with(|k| {
+        let mut k = k.borrow_mut();
+        k.log_fsck(&report);
+        k.set_vfs(vfs);
+        // Users and the hostname live in the restored /etc
+        k.load_user_db();
+        k.load_hostname();
+        // Unreadable IPC state loses the queues, not the whole restore
+        let _ = k.load_ipc_state();
+    });
+    report
+}
+
+/// Snapshot the VFS, to diff against later with [`vfs_changes_since`]
+pub fn vfs_capture() -> FsSnapshot {
+    KERNEL.with(|k| k.borrow().vfs().snapshot())
+}
+
+/// Total bytes of file content in the VFS
+pub fn vfs_size() -> usize {
+    KERNEL.with(|k| k.borrow().vfs().content_size())
+}
+
+/// Bytes the VFS's file contents take in memory, counting blocks that
+/// files share once (see [`vfs_size`])
+pub fn vfs_stored_size() -> usize {
+    KERNEL.with(|k| k.borrow().vfs().stored_size())
+}
+
+/// Paths changed since `before` was captured
+pub fn vfs_changes_since(before: &FsSnapshot) -> Vec<FsChange> {
+    KERNEL.with(|k| k.borrow().vfs().changes_since(before))
+}
+
+/// Fingerprint of a path's current state (None if it doesn't exist)
+pub fn vfs_fingerprint(path: &str) -> Option<u64> {
+    KERNEL.with(|k| k.borrow().vfs().fingerprint(path))
+}
+
+/// Fingerprints of `dir` and everything under it, by path
+pub fn vfs_fingerprints_under(dir: &str) -> BTreeMap<String, u64> {
+    KERNEL.with(|k| k.borrow().vfs().fingerprints_under(dir))
+}
+
+/// Where the VFS change log is now, for [`vfs_changed_since`]
+pub fn vfs_change_cursor() -> ChangeCursor {
+    KERNEL.with(|k| k.borrow().vfs().change_cursor())
+}
+
+/// Paths changed since `cursor`, or `None` if the caller has to rescan
+/// (see [`crate::vfs::changelog`])
+pub fn vfs_changed_since(cursor: ChangeCursor) -> Option<BTreeSet<String>> {
+    KERNEL.with(|k| k.borrow().vfs().changed_since(cursor))
+}
+
+/// Undo VFS changes using a before-image
+pub fn vfs_revert(image: &FsSnapshot, changes: &[FsChange]) -> SyscallResult<()> {
+    KERNEL.with(|k| k.borrow_mut().sys_vfs_revert(image, changes))
 }
 
 #[cfg(test)]
@@ -4877,6 +9868,45 @@ mod tests {
         assert!(fd.0 >= 3); // After stdin/stdout/stderr
     }
 
+    #[test]
+    fn test_getrusage() {
+        setup_test_kernel();
+        let before = getrusage().unwrap();
+        let fd = open("/dev/null", OpenFlags::WRITE).unwrap();
+        write(fd, b"x").unwrap();
+        close(fd).unwrap();
+        let after = getrusage().unwrap();
+        assert_eq!(after.syscalls - before.syscalls, 3);
+
+        let region = mem_alloc(4096, Protection::READ_WRITE).unwrap();
+        mem_free(region).unwrap();
+        assert_eq!(getrusage().unwrap().peak_memory, 4096);
+        assert_eq!(reset_peak_memory(), Ok(4096));
+        assert_eq!(getrusage().unwrap().peak_memory, 0);
+    }
+
+    #[test]
+    fn test_tty_mouse() {
+        use super::super::tty::{MouseAction, MouseButton};
+        setup_test_kernel();
+        let press = MouseEvent::new(MouseAction::Press(MouseButton::Left), 3, 2);
+        assert_eq!(tty_mouse("console", &press), Ok(false));
+        assert_eq!(tty_mouse("tty9", &press), Err(SyscallError::NotFound));
+
+        // Asking through the console turns reporting on for its terminal
+        write(Fd::STDOUT, b"\x1b[?1000;1006h").unwrap();
+        assert_eq!(tty_mouse("console", &press), Ok(true));
+
+        // ...and through a pty slave for that pty
+        let fd = open(&openpty().unwrap(), OpenFlags::RDWR).unwrap();
+        write(fd, b"\x1b[?1000h").unwrap();
+        assert_eq!(tty_mouse("pts/0", &press), Ok(true));
+
+        let mut buf = [0u8; 32];
+        let n = read(Fd::STDIN, &mut buf).unwrap();
+        assert_eq!(&buf[..n], b"\x1b[<0;3;2M\x1b[M #\"");
+    }
+
     #[test]
     fn test_write_stdout() {
         setup_test_kernel();
@@ -4920,6 +9950,77 @@ mod tests {
         assert_eq!(&buf[..n], b"test");
     }
 
+    #[test]
+    fn test_nonblocking_io() {
+        use std::future::Future;
+        use std::sync::Arc;
+        use std::task::{Context, Poll, Wake, Waker};
+
+        struct Noop;
+        impl Wake for Noop {
+            fn wake(self: Arc<Self>) {}
+        }
+
+        setup_test_kernel();
+        let waker = Waker::from(Arc::new(Noop));
+        let mut cx = Context::from_waker(&waker);
+        let (r, w) = pipe().unwrap();
+
+        // A blocking read waits for the writer
+        let mut waiting = Box::pin(read_wait(r, 8));
+        assert!(waiting.as_mut().poll(&mut cx).is_pending());
+        write(w, b"hi").unwrap();
+        assert_eq!(
+            waiting.as_mut().poll(&mut cx),
+            Poll::Ready(Ok(b"hi".to_vec()))
+        );
+
+        // In non-blocking mode it fails at once, through any descriptor
+        assert_eq!(fcntl(r, F_GETFL, 0), Ok(0));
+        set_nonblocking(r, true).unwrap();
+        assert_eq!(fcntl(r, F_GETFL, 0), Ok(O_NONBLOCK));
+        let copy = dup(r).unwrap();
+        assert_eq!(fcntl(copy, F_GETFL, 0), Ok(O_NONBLOCK));
+        let mut waiting = Box::pin(read_wait(copy, 8));
+        assert_eq!(
+            waiting.as_mut().poll(&mut cx),
+            Poll::Ready(Err(SyscallError::WouldBlock))
+        );
+        set_nonblocking(copy, false).unwrap();
+        assert_eq!(fcntl(r, F_GETFL, 0), Ok(0));
+
+        // Descriptor flags stay with the descriptor
+        fcntl(r, F_SETFD, FdFlags::CLOEXEC).unwrap();
+        assert_eq!(fcntl(r, F_GETFD, 0), Ok(FdFlags::CLOEXEC));
+        assert_eq!(fcntl(copy, F_GETFD, 0), Ok(0));
+        assert_eq!(fcntl(r, 99, 0), Err(SyscallError::InvalidArgument));
+        assert_eq!(fcntl(Fd(99), F_GETFL, 0), Err(SyscallError::BadFd));
+
+        // Opening with O_NONBLOCK
+        let fd = open("/dev/console", OpenFlags::READ.nonblocking()).unwrap();
+        assert_eq!(fcntl(fd, F_GETFL, 0), Ok(O_NONBLOCK));
+
+        // Sockets keep their own mode
+        let server = socket(SocketType::Stream);
+        bind(server, "/tmp/nonblock.sock").unwrap();
+        listen(server, 4).unwrap();
+        let client = socket(SocketType::Stream);
+        connect(client, "/tmp/nonblock.sock").unwrap();
+        let (conn, _) = accept(server).unwrap();
+        let mut waiting = Box::pin(recv_wait(conn));
+        assert!(waiting.as_mut().poll(&mut cx).is_pending());
+        send(client, b"ping").unwrap();
+        assert_eq!(
+            waiting.as_mut().poll(&mut cx),
+            Poll::Ready(Ok(b"ping".to_vec()))
+        );
+        socket_set_nonblocking(conn, true).unwrap();
+        assert_eq!(
+            Box::pin(recv_wait(conn)).as_mut().poll(&mut cx),
+            Poll::Ready(Err(SocketError::WouldBlock))
+        );
+    }
+
     #[test]
     fn test_close() {
         setup_test_kernel();
@@ -4950,6 +10051,443 @@ mod tests {
         close(fd).unwrap();
     }
 
+    #[test]
+    fn test_pread_pwrite() {
+        setup_test_kernel();
+
+        let fd = open("/tmp/table.db", OpenFlags::WRITE).unwrap();
+        write(fd, b"0123456789").unwrap();
+        assert_eq!(pwrite(fd, b"ab", 2), Ok(2));
+        // The position is where the plain write left it
+        write(fd, b"!").unwrap();
+        // Past the end, the gap is zeros
+        assert_eq!(pwrite(fd, b"z", 13), Ok(1));
+        // Files can't grow without bound, however the offset is reached
+        for offset in [u64::MAX, i64::MAX as u64, FileObject::MAX_SIZE] {
+            assert_eq!(pwrite(fd, b"x", offset), Err(SyscallError::TooBig));
+        }
+        seek(fd, SeekFrom::Start(i64::MAX as u64)).unwrap();
+        assert_eq!(write(fd, b"x"), Err(SyscallError::TooBig));
+        seek(fd, SeekFrom::End(0)).unwrap();
+        close(fd).unwrap();
+
+        let fd = open("/tmp/table.db", OpenFlags::READ).unwrap();
+        let mut buf = [0u8; 4];
+        assert_eq!(pread(fd, &mut buf, 1), Ok(4));
+        assert_eq!(&buf, b"1ab4");
+        assert_eq!(pread(fd, &mut buf, 100), Ok(0));
+        assert_eq!(pread(fd, &mut buf, u64::MAX), Ok(0));
+        let mut all = [0u8; 20];
+        let n = read(fd, &mut all).unwrap();
+        assert_eq!(&all[..n], b"01ab456789!\0\0z");
+        // Writes need a writable descriptor, wherever they go
+        assert!(pwrite(fd, b"x", 0).is_err());
+        close(fd).unwrap();
+
+        // Only files can be read at an offset
+        let (r, _w) = pipe().unwrap();
+        assert_eq!(pread(r, &mut buf, 0), Err(SyscallError::InvalidArgument));
+        assert_eq!(pread(Fd(99), &mut buf, 0), Err(SyscallError::BadFd));
+    }
+
+    #[test]
+    fn test_atomic_write() {
+        setup_test_kernel();
+
+        atomic_write("/tmp/conf", b"first").unwrap();
+        symlink("/tmp/conf", "/tmp/link").unwrap();
+        atomic_write("/tmp/link", b"second").unwrap();
+
+        let fd = open("/tmp/conf", OpenFlags::READ).unwrap();
+        let mut buf = [0u8; 20];
+        let n = read(fd, &mut buf).unwrap();
+        assert_eq!(&buf[..n], b"second");
+        close(fd).unwrap();
+        assert_eq!(metadata("/tmp/conf").unwrap().uid, geteuid().unwrap().0);
+
+        // Permissions are checked on the file the link leads to
+        let set_euid =
+            |uid| KERNEL.with(|k| k.borrow_mut().current_process_mut().unwrap().euid = uid);
+        set_euid(Uid::ROOT);
+        atomic_write("/tmp/root.conf", b"root").unwrap();
+        set_euid(Uid(1000));
+        assert_eq!(metadata("/tmp/root.conf").unwrap().uid, 0);
+        symlink("/tmp/root.conf", "/tmp/root.link").unwrap();
+        assert!(atomic_write("/tmp/root.link", b"mine").is_err());
+        assert!(atomic_write("/tmp/root.conf", b"mine").is_err());
+        assert_eq!(read_file("/tmp/root.conf").unwrap(), "root");
+        assert_eq!(
+            atomic_write("/dev/null", b"x"),
+            Err(SyscallError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn test_fsync() {
+        setup_test_kernel();
+        assert!(!take_persist_request());
+
+        let fd = open("/tmp/notes", OpenFlags::WRITE).unwrap();
+        write(fd, b"draft").unwrap();
+        fsync(fd).unwrap();
+        assert_eq!(read_file("/tmp/notes").unwrap(), "draft");
+        assert!(take_persist_request());
+        assert!(!take_persist_request());
+        fdatasync(fd).unwrap();
+        assert!(take_persist_request());
+        close(fd).unwrap();
+
+        assert_eq!(fsync(fd), Err(SyscallError::BadFd));
+        let (read_fd, _) = pipe().unwrap();
+        assert_eq!(fsync(read_fd), Err(SyscallError::InvalidArgument));
+        assert!(!take_persist_request());
+    }
+
+    #[test]
+    fn test_rename_replaces() {
+        setup_test_kernel();
+        write_file("/tmp/config", "old").unwrap();
+
+        // Write a temporary file and rename it over the real one
+        let fd = open("/tmp/.config.new", OpenFlags::WRITE).unwrap();
+        write(fd, b"new").unwrap();
+        fsync(fd).unwrap();
+        close(fd).unwrap();
+        rename("/tmp/.config.new", "/tmp/config").unwrap();
+        assert_eq!(read_file("/tmp/config").unwrap(), "new");
+        assert!(metadata("/tmp/.config.new").is_err());
+        assert_eq!(readdir("/tmp").unwrap(), vec!["config".to_string()]);
+
+        // A descriptor still open on the file writes back where it went
+        let fd = open("/tmp/draft", OpenFlags::WRITE).unwrap();
+        rename("/tmp/draft", "/tmp/config").unwrap();
+        write(fd, b"newer").unwrap();
+        close(fd).unwrap();
+        assert_eq!(read_file("/tmp/config").unwrap(), "newer");
+        assert!(metadata("/tmp/draft").is_err());
+
+        // One open on the file that was replaced writes nowhere
+        let stale = open("/tmp/config", OpenFlags::WRITE).unwrap();
+        write_file("/tmp/.config.new", "newest").unwrap();
+        rename("/tmp/.config.new", "/tmp/config").unwrap();
+        write(stale, b"stale").unwrap();
+        close(stale).unwrap();
+        assert_eq!(read_file("/tmp/config").unwrap(), "newest");
+
+        // Directories aren't replaced, nor put in place of files
+        mkdir("/tmp/dir").unwrap();
+        mkdir("/tmp/other").unwrap();
+        assert_eq!(
+            rename("/tmp/config", "/tmp/dir"),
+            Err(SyscallError::IsADirectory)
+        );
+        assert_eq!(
+            rename("/tmp/dir", "/tmp/config"),
+            Err(SyscallError::NotADirectory)
+        );
+        assert_eq!(
+            rename("/tmp/dir", "/tmp/other"),
+            Err(SyscallError::AlreadyExists)
+        );
+        rename("/tmp/config", "/tmp/config").unwrap();
+        assert_eq!(read_file("/tmp/config").unwrap(), "newest");
+    }
+
+    #[test]
+    fn test_user_db_reload() {
+        setup_test_kernel();
+        KERNEL.with(|k| k.borrow_mut().current_process_mut().unwrap().euid = Uid::ROOT);
+        let passwd = read_file("/etc/passwd").unwrap();
+
+        let group = read_file("/etc/group").unwrap() + "staff:x:50:user\n";
+        atomic_write("/etc/group", group.as_bytes()).unwrap();
+        assert_eq!(get_group_by_name("staff").unwrap().gid, Gid(50));
+
+        // A file without root is ignored
+        let rootless: String = passwd
+            .lines()
+            .filter(|line| !line.starts_with("root:"))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        write_file("/etc/passwd", &rootless).unwrap();
+        assert!(get_user_by_name("root").is_some());
+        assert!(
+            klog_read()
+                .iter()
+                .any(|e| e.facility == "users" && e.message.contains("no root account"))
+        );
+        write_file("/tmp/passwd", &passwd.replace("/home/user:", "/srv/user:")).unwrap();
+        rename("/tmp/passwd", "/etc/passwd").unwrap();
+        assert_eq!(get_user_by_name("user").unwrap().home, "/srv/user");
+    }
+
+    #[test]
+    fn test_sticky_tmp() {
+        setup_test_kernel();
+        let meta = metadata("/tmp").unwrap();
+        assert_eq!((meta.uid, meta.mode), (0, 0o1777));
+
+        let set_euid =
+            |uid| KERNEL.with(|k| k.borrow_mut().current_process_mut().unwrap().euid = uid);
+        set_euid(Uid(1001));
+        write_file("/tmp/alice", "a").unwrap();
+        set_euid(Uid(1002));
+        write_file("/tmp/bob", "b").unwrap();
+
+        // Others' files can't be removed, moved away or replaced
+        assert_eq!(
+            remove_file("/tmp/alice"),
+            Err(SyscallError::PermissionDenied)
+        );
+        assert_eq!(
+            rename("/tmp/alice", "/tmp/mine"),
+            Err(SyscallError::PermissionDenied)
+        );
+        assert_eq!(
+            rename("/tmp/bob", "/tmp/alice"),
+            Err(SyscallError::PermissionDenied)
+        );
+        assert_eq!(read_file("/tmp/alice").unwrap(), "a");
+
+        // Their own can
+        rename("/tmp/bob", "/tmp/bob2").unwrap();
+        remove_file("/tmp/bob2").unwrap();
+
+        // Without the sticky bit, write on the directory is enough
+        set_euid(Uid::ROOT);
+        mkdir("/tmp/open").unwrap();
+        chmod("/tmp/open", 0o777).unwrap();
+        set_euid(Uid(1001));
+        write_file("/tmp/open/alice", "a").unwrap();
+        set_euid(Uid(1002));
+        remove_file("/tmp/open/alice").unwrap();
+
+        // And without write on it, nothing can be added or removed
+        set_euid(Uid::ROOT);
+        chmod("/tmp/open", 0o755).unwrap();
+        write_file("/tmp/open/root", "r").unwrap();
+        set_euid(Uid(1002));
+        assert!(write_file("/tmp/open/bob", "b").is_err());
+        assert!(mkdir("/tmp/open/dir").is_err());
+        assert!(symlink("/tmp/alice", "/tmp/open/link").is_err());
+        assert!(remove_file("/tmp/open/root").is_err());
+        assert!(rename("/tmp/open/root", "/tmp/root").is_err());
+    }
+
+    #[test]
+    fn test_quota() {
+        setup_test_kernel();
+        let uid = getuid().unwrap();
+        assert_eq!(set_quota(uid, Some(8)), Err(SyscallError::PermissionDenied));
+        assert_eq!(quota_report(), Err(SyscallError::PermissionDenied));
+        assert_eq!(quota(Uid::ROOT), Err(SyscallError::PermissionDenied));
+
+        let before = quota(uid).unwrap().used;
+        KERNEL.with(|k| k.borrow_mut().current_process_mut().unwrap().euid = Uid::ROOT);
+        set_quota(uid, Some(before + 8)).unwrap();
+        KERNEL.with(|k| k.borrow_mut().current_process_mut().unwrap().euid = uid);
+
+        // The write that goes over fails, not the close after it
+        let fd = open("/tmp/big", OpenFlags::WRITE).unwrap();
+        assert_eq!(write(fd, b"12345"), Ok(5));
+        assert_eq!(write(fd, b"6789"), Err(SyscallError::NoSpace));
+        close(fd).unwrap();
+        assert_eq!(
+            quota(uid).unwrap(),
+            DiskQuota {
+                uid,
+                used: before + 5,
+                limit: Some(before + 8)
+            }
+        );
+        assert_eq!(
+            atomic_write("/tmp/other", b"123456"),
+            Err(SyscallError::NoSpace)
+        );
+        assert!(!exists("/tmp/other").unwrap());
+    }
+
+    #[test]
+    fn test_restore_repairs_snapshot() {
+        setup_test_kernel();
+        let mut snapshot: serde_json::Value =
+            serde_json::from_slice(&vfs_snapshot().unwrap()).unwrap();
+        snapshot["nodes"]["/srv/www/index.html"] = serde_json::json!({ "File": [104, 105] });
+        let data = serde_json::to_vec(&snapshot).unwrap();
+
+        let report = vfs_restore(&data).unwrap();
+        assert_eq!(report.fixed(), 2, "{:?}", report);
+        assert!(metadata("/srv/www").unwrap().is_dir);
+        assert_eq!(read_file("/srv/www/index.html").unwrap(), "hi");
+        assert!(
+            klog_read()
+                .iter()
+                .any(|e| e.facility == "fsck" && e.message.contains("/srv/www/index.html"))
+        );
+        assert!(fsck(Repair::Nothing).unwrap().findings.is_empty());
+    }
+
+    #[test]
+    fn test_named_snapshots() {
+        setup_test_kernel();
+        assert_eq!(
+            snapshot_create("before", 1.0).unwrap_err(),
+            SyscallError::PermissionDenied
+        );
+        KERNEL.with(|k| k.borrow_mut().current_process_mut().unwrap().euid = Uid::ROOT);
+
+        write_file("/tmp/keep", "a").unwrap();
+        let info = snapshot_create("before", 1.0).unwrap();
+        assert_eq!(snapshot_list().unwrap(), [info]);
+        write_file("/tmp/keep", "b").unwrap();
+        write_file("/tmp/new", "n").unwrap();
+
+        let changes: Vec<String> = snapshot_diff("before", None)
+            .unwrap()
+            .iter()
+            .map(|c| format!("{}{}", c.kind.marker(), c.path))
+            .collect();
+        assert_eq!(changes, ["~/tmp/keep", "+/tmp/new"]);
+
+        let versions = snapshot_versions("/tmp/keep").unwrap();
+        assert_eq!(versions.len(), 1);
+        assert_eq!(
+            (
+                versions[0].snapshot.as_str(),
+                versions[0].content.as_slice()
+            ),
+            ("before", &b"a"[..])
+        );
+        assert!(snapshot_versions("/tmp/new").unwrap().is_empty());
+
+        assert_eq!(snapshot_restore("before").unwrap().len(), 2);
+        assert_eq!(read_file("/tmp/keep").unwrap(), "a");
+        assert!(!exists("/tmp/new").unwrap());
+        assert!(
+            klog_read()
+                .iter()
+                .any(|e| e.facility == "snapshot" && e.message.contains("rolled back"))
+        );
+
+        snapshot_remove("before").unwrap();
+        assert!(snapshot_list().unwrap().is_empty());
+        assert_eq!(
+            snapshot_restore("before").unwrap_err(),
+            SyscallError::NotFound
+        );
+    }
+
+    #[test]
+    fn test_xattr() {
+        setup_test_kernel();
+        let set_euid =
+            |uid| KERNEL.with(|k| k.borrow_mut().current_process_mut().unwrap().euid = uid);
+
+        write_file("/tmp/doc", "hello").unwrap();
+        symlink("/tmp/doc", "/tmp/doc.link").unwrap();
+        setxattr("/tmp/doc.link", "user.mime", b"text/plain").unwrap();
+        assert_eq!(getxattr("/tmp/doc", "user.mime").unwrap(), b"text/plain");
+        assert_eq!(
+            getxattr("/tmp/doc", "user.x"),
+            Err(SyscallError::NoAttribute)
+        );
+        assert_eq!(getxattr("/tmp/nope", "user.x"), Err(SyscallError::NotFound));
+        assert_eq!(
+            setxattr("/tmp/doc", "mime", b""),
+            Err(SyscallError::InvalidArgument)
+        );
+        assert_eq!(
+            setxattr("/tmp/doc", "user.big", &[0; crate::vfs::XATTR_SIZE_MAX + 1]),
+            Err(SyscallError::TooBig)
+        );
+
+        // trusted.* is for root, and hidden from everyone else
+        assert_eq!(
+            setxattr("/tmp/doc", "trusted.tag", b"x"),
+            Err(SyscallError::PermissionDenied)
+        );
+        set_euid(Uid::ROOT);
+        setxattr("/tmp/doc", "trusted.tag", b"x").unwrap();
+        assert_eq!(listxattr("/tmp/doc").unwrap(), ["trusted.tag", "user.mime"]);
+        write_file("/tmp/root.doc", "").unwrap();
+        chown("/tmp/root.doc", Some(0), Some(0)).unwrap();
+        set_euid(Uid(1000));
+        assert_eq!(listxattr("/tmp/doc").unwrap(), ["user.mime"]);
+
+        // Setting needs write permission, reading needs read permission
+        assert_eq!(
+            setxattr("/tmp/root.doc", "user.x", b""),
+            Err(SyscallError::PermissionDenied)
+        );
+        assert!(listxattr("/tmp/root.doc").is_ok());
+        chmod("/tmp/doc", 0o200).unwrap();
+        assert_eq!(listxattr("/tmp/doc"), Err(SyscallError::PermissionDenied));
+        chmod("/tmp/doc", 0o644).unwrap();
+
+        removexattr("/tmp/doc", "user.mime").unwrap();
+        assert_eq!(
+            removexattr("/tmp/doc", "user.mime"),
+            Err(SyscallError::NoAttribute)
+        );
+        assert_eq!(
+            getxattr("/proc/uptime", "user.x"),
+            Err(SyscallError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn test_acl() {
+        use crate::vfs::{AclTag, acl::ACL_XATTR};
+        setup_test_kernel();
+        let set_euid =
+            |uid| KERNEL.with(|k| k.borrow_mut().current_process_mut().unwrap().euid = uid);
+
+        // uid 1000 shares a private file with uid 1001 only
+        write_file("/tmp/plan", "secret").unwrap();
+        chmod("/tmp/plan", 0o600).unwrap();
+        let mut acl = Acl::new();
+        acl.set(AclTag::User(1001), 0o6);
+        setxattr("/tmp/plan", ACL_XATTR, &acl.encode()).unwrap();
+        assert_eq!(metadata("/tmp/plan").unwrap().mode, 0o600);
+
+        set_euid(Uid(1001));
+        assert_eq!(read_file("/tmp/plan").unwrap(), "secret");
+        write_file("/tmp/plan", "shared").unwrap();
+        // Anyone may read the ACL, but only the owner may change it
+        assert_eq!(getxattr("/tmp/plan", ACL_XATTR).unwrap(), acl.encode());
+        assert_eq!(
+            setxattr("/tmp/plan", ACL_XATTR, b""),
+            Err(SyscallError::PermissionDenied)
+        );
+        set_euid(Uid(1002));
+        assert_eq!(read_file("/tmp/plan"), Err(SyscallError::PermissionDenied));
+
+        // chmod sets the mask from the group bits, which caps the entry
+        set_euid(Uid(1000));
+        chmod("/tmp/plan", 0o640).unwrap();
+        let masked = Acl::decode(&getxattr("/tmp/plan", ACL_XATTR).unwrap()).unwrap();
+        assert_eq!(masked.mask(), Some(0o4));
+        set_euid(Uid(1001));
+        assert_eq!(read_file("/tmp/plan").unwrap(), "shared");
+        assert_eq!(
+            write_file("/tmp/plan", "again"),
+            Err(SyscallError::PermissionDenied)
+        );
+        set_euid(Uid(1000));
+        chmod("/tmp/plan", 0o600).unwrap();
+        set_euid(Uid(1001));
+        assert_eq!(read_file("/tmp/plan"), Err(SyscallError::PermissionDenied));
+
+        set_euid(Uid(1000));
+        assert_eq!(
+            setxattr("/tmp/plan", ACL_XATTR, b"user:bob:rw-\n"),
+            Err(SyscallError::InvalidArgument)
+        );
+        removexattr("/tmp/plan", ACL_XATTR).unwrap();
+        set_euid(Uid(1001));
+        assert_eq!(read_file("/tmp/plan"), Err(SyscallError::PermissionDenied));
+    }
+
     #[test]
     fn test_mkdir_readdir() {
         setup_test_kernel();
@@ -5485,6 +11023,21 @@ mod tests {
         assert!(content.contains("Uid:"), "should have Uid field");
     }
 
+    #[test]
+    fn test_proc_kallsyms() {
+        setup_test_kernel();
+        let pid = getpid().unwrap();
+        set_process_task(pid, TaskId(4)).unwrap();
+        name_task(TaskId(2), "journal");
+
+        assert!(readdir("/proc").unwrap().contains(&"kallsyms".to_string()));
+        let content = read_file("/proc/kallsyms").unwrap();
+        assert!(content.contains(&format!("{:08x} s open\n", SyscallNr::Open.num())));
+        assert!(content.contains("00000001 e main\n"));
+        assert!(content.contains(&format!("{:08x} i exit\n", SyscallNr::Exit.num())));
+        assert!(content.ends_with("00000002 t journal\n00000004 t test\n"));
+    }
+
     #[test]
     fn test_proc_readdir() {
         setup_test_kernel();
@@ -5671,6 +11224,34 @@ mod tests {
         assert!(content.contains("axeberg"));
     }
 
+    #[test]
+    fn test_read_at_with() {
+        setup_test_kernel();
+        write_file("/tmp/data", "hello world").unwrap();
+
+        let read_at = |path: &str, offset: u64, len: usize| {
+            let mut seen = Vec::new();
+            read_at_with(path, offset, len, &mut |chunk| {
+                seen.extend_from_slice(chunk)
+            })
+            .map(|n| (n, String::from_utf8(seen).unwrap()))
+        };
+        assert_eq!(read_at("/tmp/data", 6, 100), Ok((5, "world".into())));
+        assert_eq!(read_at("/tmp/data", 0, 5), Ok((5, "hello".into())));
+        assert_eq!(read_at("/tmp/data", 11, 5), Ok((0, String::new())));
+        assert_eq!(read_at("/tmp/missing", 0, 5), Err(SyscallError::NotFound));
+
+        // Generated files go through a descriptor, and unreadable ones
+        // are refused
+        let (_, hostname) = read_at("/sys/kernel/hostname", 0, 64).unwrap();
+        assert!(hostname.contains("axeberg"));
+        chmod("/tmp/data", 0o200).unwrap();
+        assert_eq!(
+            read_at("/tmp/data", 0, 5),
+            Err(SyscallError::PermissionDenied)
+        );
+    }
+
     #[test]
     fn test_sys_kernel_ostype() {
         setup_test_kernel();
@@ -5684,6 +11265,292 @@ mod tests {
         assert!(content.contains("AxebergOS"));
     }
 
+    #[test]
+    fn test_sys_power_governor() {
+        setup_test_kernel();
+
+        assert!(
+            readdir("/sys/power")
+                .unwrap()
+                .contains(&"governor".to_string())
+        );
+        assert_eq!(read_file("/sys/power/governor").unwrap(), "ondemand\n");
+        assert_eq!(
+            read_file("/sys/power/available_governors").unwrap(),
+            "ondemand performance powersave\n"
+        );
+
+        write_file("/sys/power/governor", "powersave\n").unwrap();
+        assert_eq!(power().governor(), Governor::Powersave);
+        assert_eq!(read_file("/sys/power/mode").unwrap(), "saving\n");
+        assert_eq!(
+            write_file("/sys/power/governor", "turbo"),
+            Err(SyscallError::InvalidArgument)
+        );
+        assert_eq!(
+            write_file("/sys/power/mode", "full"),
+            Err(SyscallError::PermissionDenied)
+        );
+
+        // ondemand follows page visibility
+        set_governor(Governor::Ondemand);
+        assert!(!power_saving());
+        assert!(set_page_hidden(true));
+        assert!(power_saving());
+        assert!(set_page_hidden(false));
+        assert_eq!(read_file("/sys/power/mode").unwrap(), "full\n");
+    }
+
+    #[test]
+    fn test_memory_pressure() {
+        use std::cell::Cell;
+        setup_test_kernel();
+        let other = KERNEL.with(|k| k.borrow_mut().spawn_process("other", None));
+        signal(Signal::SIGMEMPRESSURE, SignalAction::Handle).unwrap();
+        thread_local! {
+            static TRIMS: Cell<u32> = const { Cell::new(0) };
+        }
+        pressure::on_pressure(|_| {
+            TRIMS.with(|t| t.set(t.get() + 1));
+            Some("test cache".into())
+        });
+
+        // Without a limit, only the platform's figures count
+        assert_eq!(memory_pressure_tick(None), None);
+        set_time(pressure::CHECK_INTERVAL_MS);
+        let heap = MemorySample {
+            used: 95,
+            limit: 100,
+        };
+        assert_eq!(
+            memory_pressure_tick(Some(heap)),
+            Some(PressureLevel::Critical)
+        );
+        assert_eq!(memory_pressure(), PressureLevel::Critical);
+        assert_eq!(TRIMS.with(Cell::get), 1);
+        assert_eq!(
+            sigpending_mask().unwrap(),
+            1 << Signal::SIGMEMPRESSURE.num()
+        );
+        KERNEL.with(|k| {
+            let k = k.borrow();
+            assert!(!k.proc.processes[&other].signals.has_pending());
+        });
+        let log = klog_read();
+        assert!(log.iter().any(|e| e.facility == "memory"
+            && e.message.contains("critical")
+            && e.message.contains("notified 1 process")));
+        assert!(log.iter().any(|e| e.message == "trimmed test cache"));
+
+        // Not checked again until the interval has passed
+        assert_eq!(memory_pressure_tick(None), None);
+        assert_eq!(memory_pressure(), PressureLevel::Critical);
+
+        // The kernel's own accounting counts once there is a limit
+        set_time(pressure::CHECK_INTERVAL_MS * 2.0);
+        KERNEL.with(|k| k.borrow_mut().memory.set_system_limit(1 << 20));
+        assert_eq!(memory_pressure_tick(None), Some(PressureLevel::Normal));
+        assert_eq!(TRIMS.with(Cell::get), 1);
+        shmget(800 << 10).unwrap();
+        set_time(pressure::CHECK_INTERVAL_MS * 3.0);
+        assert_eq!(memory_pressure_tick(None), Some(PressureLevel::Moderate));
+        assert_eq!(TRIMS.with(Cell::get), 2);
+    }
+
+    #[test]
+    fn test_mount_options_enforced() {
+        setup_test_kernel();
+        write_file("/tmp/tool", "\0asm").unwrap();
+        let fd = open("/tmp/log", OpenFlags::WRITE).unwrap();
+        assert_eq!(check_exec("/tmp/tool"), Ok(true));
+
+        // A descriptor opened before the remount can't write either
+        remount("/tmp", "ro").unwrap();
+        assert_eq!(write(fd, b"late"), Err(SyscallError::ReadOnlyFs));
+        assert_eq!(
+            open("/tmp/log", OpenFlags::WRITE),
+            Err(SyscallError::ReadOnlyFs)
+        );
+        assert_eq!(read_file("/tmp/tool").unwrap(), "\0asm");
+        remount("/tmp", "rw").unwrap();
+        assert_eq!(write(fd, b"ok"), Ok(2));
+        close(fd).unwrap();
+
+        remount("/tmp", "nosuid").unwrap();
+        assert_eq!(check_exec("/tmp/tool"), Ok(false));
+        remount("/tmp", "noexec").unwrap();
+        assert_eq!(check_exec("/tmp/tool"), Err(SyscallError::PermissionDenied));
+        assert_eq!(
+            execv("/tmp/tool", &["tool".to_string()]),
+            Err(SyscallError::PermissionDenied)
+        );
+        // Nor through a link from elsewhere
+        symlink("/tmp/tool", "/home/tool").unwrap();
+        assert_eq!(
+            check_exec("/home/tool"),
+            Err(SyscallError::PermissionDenied)
+        );
+
+        remount("/tmp", "exec,suid").unwrap();
+        assert_eq!(check_exec("/tmp/tool"), Ok(true));
+        execv("/tmp/tool", &["tool".to_string()]).unwrap();
+    }
+
+    #[test]
+    fn test_exec_setid() {
+        setup_test_kernel();
+        let set_euid =
+            |uid| KERNEL.with(|k| k.borrow_mut().current_process_mut().unwrap().euid = uid);
+        let audit = || {
+            klog_read()
+                .into_iter()
+                .filter(|entry| entry.facility == "audit")
+                .map(|entry| entry.message)
+                .collect::<Vec<_>>()
+        };
+        set_euid(Uid::ROOT);
+        mkdir("/bin").unwrap();
+        for name in ["passwd", "plain", "loose", "shared"] {
+            write_file(&format!("/bin/{name}.wasm"), "\0asm").unwrap();
+        }
+        chmod("/bin/passwd.wasm", 0o4755).unwrap();
+        chmod("/bin/loose.wasm", 0o4777).unwrap();
+        chmod("/bin/shared.wasm", 0o4755).unwrap();
+        let mut acl = Acl::new();
+        acl.set(AclTag::User(1001), 0o6);
+        setxattr("/bin/shared.wasm", ACL_XATTR, &acl.encode()).unwrap();
+        set_euid(Uid(1000));
+
+        // The program runs as its owner, and the kernel logs it
+        assert_eq!(exec_setid("/bin/passwd.wasm"), Ok(true));
+        assert_eq!(geteuid(), Ok(Uid::ROOT));
+        assert_eq!(getuid(), Ok(Uid(1000)));
+        exec_restore().unwrap();
+        assert_eq!(geteuid(), Ok(Uid(1000)));
+        assert_eq!(audit().len(), 1);
+        assert!(audit()[0].contains("uid 1000 ran /bin/passwd.wasm as uid 0"));
+
+        // Restoring without an elevation changes nothing
+        exec_restore().unwrap();
+        assert_eq!(geteuid(), Ok(Uid(1000)));
+
+        assert_eq!(exec_setid("/bin/plain.wasm"), Ok(false));
+        // Anyone could have replaced a program others can write to
+        assert_eq!(exec_setid("/bin/loose.wasm"), Ok(false));
+        assert_eq!(geteuid(), Ok(Uid(1000)));
+        assert!(audit()[1].contains("ignoring setuid/setgid bits of /bin/loose.wasm"));
+        // ...or that a named user may write to
+        assert_eq!(exec_setid("/bin/shared.wasm"), Ok(false));
+        assert!(audit()[2].contains("ignoring setuid/setgid bits of /bin/shared.wasm"));
+        // ...until chmod masks the entry's write away
+        set_euid(Uid::ROOT);
+        chmod("/bin/shared.wasm", 0o4755).unwrap();
+        set_euid(Uid(1000));
+        assert_eq!(exec_setid("/bin/shared.wasm"), Ok(true));
+        exec_restore().unwrap();
+
+        // Nor on a nosuid mount
+        KERNEL.with(|k| {
+            k.borrow_mut()
+                .fs
+                .mounts
+                .mount(
+                    "bin",
+                    "/bin",
+                    FsType::MemoryFs,
+                    MountOptions::parse("nosuid"),
+                    0.0,
+                )
+                .unwrap()
+        });
+        assert_eq!(exec_setid("/bin/passwd.wasm"), Ok(false));
+        assert_eq!(geteuid(), Ok(Uid(1000)));
+    }
+
+    #[test]
+    fn test_tmpfs_mounts_coexist() {
+        setup_test_kernel();
+        for dir in ["/tmp/scratch", "/tmp/layer", "/tmp/view"] {
+            mkdir(dir).unwrap();
+        }
+        write_file("/tmp/scratch/old", "hidden").unwrap();
+        write_file("/tmp/layer/base", "lower").unwrap();
+
+        // A tmpfs starts empty, over whatever was there
+        mount("tmpfs", "/tmp/scratch", FsType::Tmpfs, MountOptions::new()).unwrap();
+        assert_eq!(metadata("/tmp/scratch").unwrap().mode, 0o1777);
+        assert!(readdir("/tmp/scratch").unwrap().is_empty());
+        write_file("/tmp/scratch/note", "scratch").unwrap();
+        let in_root = |path: &str| KERNEL.with(|k| k.borrow().fs.vfs.exists(path));
+        assert!(!in_root("/tmp/scratch/note"));
+
+        // Beside an overlay, each serving its own files
+        mount_overlay("overlay", &["/tmp/layer"], "/tmp/view", MountOptions::new()).unwrap();
+        copy_file("/tmp/scratch/note", "/tmp/view/note").unwrap();
+        assert_eq!(read_file("/tmp/view/note").unwrap(), "scratch");
+        assert_eq!(read_file("/tmp/view/base").unwrap(), "lower");
+        assert_eq!(
+            rename("/tmp/scratch/note", "/tmp/view/moved"),
+            Err(SyscallError::CrossDevice)
+        );
+
+        // Unmounting drops its contents
+        umount("/tmp/scratch").unwrap();
+        assert_eq!(read_file("/tmp/scratch/old").unwrap(), "hidden");
+        assert!(!exists("/tmp/scratch/note").unwrap());
+        assert_eq!(
+            mount(
+                "tmpfs",
+                "/tmp/scratch/old",
+                FsType::Tmpfs,
+                MountOptions::new()
+            ),
+            Err(SyscallError::NotADirectory)
+        );
+    }
+
+    #[test]
+    fn test_system_partition_read_only() {
+        setup_test_kernel();
+        let mut factory = SystemImage::new("1.0.0");
+        factory.add("etc/version", "axeberg 1.0.0\n");
+        let boot = system_boot(&factory).unwrap();
+        assert_eq!(boot.slot, Slot::A);
+        assert_eq!(read_file("/system/etc/version").unwrap(), "axeberg 1.0.0\n");
+
+        // Even root can't change the system partition
+        KERNEL.with(|k| k.borrow_mut().current_process_mut().unwrap().euid = Uid::ROOT);
+        assert_eq!(
+            write_file("/system/etc/version", "hacked"),
+            Err(SyscallError::ReadOnlyFs)
+        );
+        assert_eq!(
+            write_file("/system/new", "x"),
+            Err(SyscallError::ReadOnlyFs)
+        );
+        assert_eq!(mkdir("/system/dir"), Err(SyscallError::ReadOnlyFs));
+        assert_eq!(unlink("/system/etc/version"), Err(SyscallError::ReadOnlyFs));
+        assert_eq!(
+            chmod("/system/etc/version", 0o777),
+            Err(SyscallError::ReadOnlyFs)
+        );
+        assert_eq!(
+            rename("/system/etc/version", "/tmp/version"),
+            Err(SyscallError::ReadOnlyFs)
+        );
+
+        // Nor can a symlink elsewhere reach into it
+        symlink("/system/etc", "/tmp/etc").unwrap();
+        assert_eq!(
+            write_file("/tmp/etc/version", "hacked"),
+            Err(SyscallError::ReadOnlyFs)
+        );
+
+        // User data stays writable
+        write_file("/tmp/notes", "ok").unwrap();
+        assert_eq!(read_file("/system/etc/version").unwrap(), "axeberg 1.0.0\n");
+    }
+
     // ============ Random Number Generation Tests ============
 
     #[test]
@@ -5886,6 +11753,51 @@ mod tests {
         assert_eq!(my_var, Some("my_value".to_string()));
     }
 
+    #[test]
+    fn test_spawn_with_env() {
+        setup_test_kernel();
+        setenv("SECRET", "hunter2").unwrap();
+        setenv("LANG", "C").unwrap();
+
+        let environ = |pid: Pid| {
+            KERNEL.with(|k| {
+                k.borrow()
+                    .get_process(pid)
+                    .map(|p| p.environ.clone())
+                    .unwrap_or_default()
+            })
+        };
+        let inherited = environ(spawn_with_env("child", &EnvSpec::inherit()));
+        assert_eq!(inherited.get("SECRET").map(String::as_str), Some("hunter2"));
+
+        let clean = environ(spawn_with_env("child", &EnvSpec::clean().set("A", "1")));
+        assert_eq!(clean.len(), 1);
+        assert_eq!(clean.get("A").map(String::as_str), Some("1"));
+
+        let allowed = environ(spawn_with_env("child", &EnvSpec::allow(["LANG"])));
+        assert_eq!(allowed.keys().collect::<Vec<_>>(), ["LANG"]);
+
+        // The parent keeps its own
+        assert_eq!(getenv("SECRET"), Ok(Some("hunter2".to_string())));
+    }
+
+    #[test]
+    fn test_umask() {
+        setup_test_kernel();
+        assert_eq!(umask(0o007), Ok(0o022));
+
+        let fd = open("/tmp/shared", OpenFlags::WRITE).unwrap();
+        close(fd).unwrap();
+        mkdir("/tmp/team").unwrap();
+        assert_eq!(metadata("/tmp/shared").unwrap().mode & 0o777, 0o660);
+        assert_eq!(metadata("/tmp/team").unwrap().mode & 0o777, 0o770);
+
+        // Children start with their parent's mask
+        let child = spawn_with_env("child", &EnvSpec::inherit());
+        let mask = KERNEL.with(|k| k.borrow().get_process(child).map(|p| p.umask));
+        assert_eq!(mask, Some(0o007));
+    }
+
     #[test]
     fn test_execve_not_found() {
         setup_test_kernel();
@@ -6166,6 +12078,732 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_blocking_calls_interrupted() {
+        setup_test_kernel();
+        let me = getpid().unwrap();
+        fork().unwrap();
+
+        let (read_fd, _write_fd) = pipe().unwrap();
+        let mut buf = [0u8; 4];
+        assert_eq!(read(read_fd, &mut buf), Err(SyscallError::WouldBlock));
+        assert_eq!(
+            waitpid(-1, WaitFlags::NONE).unwrap_err(),
+            SyscallError::WouldBlock
+        );
+
+        // An ignored signal doesn't interrupt anything
+        kill(me, Signal::SIGCHLD).unwrap();
+        assert_eq!(read(read_fd, &mut buf), Err(SyscallError::WouldBlock));
+
+        kill(me, Signal::SIGINT).unwrap();
+        assert_eq!(interrupting_signal(), Some(Signal::SIGINT));
+        assert_eq!(read(read_fd, &mut buf), Err(SyscallError::Interrupted));
+        assert_eq!(
+            waitpid(-1, WaitFlags::NONE).unwrap_err(),
+            SyscallError::Interrupted
+        );
+        // WNOHANG never blocks, so it is never interrupted
+        assert!(waitpid(-1, WaitFlags::NOHANG).is_ok());
+    }
+
+    #[test]
+    fn test_sleep_future() {
+        use std::future::Future;
+        use std::task::{Context, Poll, Waker};
+
+        setup_test_kernel();
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut nap = Box::pin(sleep(100.0));
+        assert!(nap.as_mut().poll(&mut cx).is_pending());
+        set_time(100.0);
+        assert_eq!(nap.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+
+        let mut nap = Box::pin(sleep(100.0));
+        assert!(nap.as_mut().poll(&mut cx).is_pending());
+        kill(getpid().unwrap(), Signal::SIGTERM).unwrap();
+        assert_eq!(
+            nap.as_mut().poll(&mut cx),
+            Poll::Ready(Err(SyscallError::Interrupted))
+        );
+    }
+
+    #[test]
+    fn test_poll_readiness() {
+        setup_test_kernel();
+        let sys_poll = |fds: &mut [PollFd]| KERNEL.with(|k| k.borrow().sys_poll(fds, Some(0.0)));
+
+        let (r, w) = pipe().unwrap();
+        let mut fds = vec![
+            PollFd::fd(r, PollEvents::READ),
+            PollFd::fd(w, PollEvents::WRITE),
+            PollFd::fd(Fd(99), PollEvents::READ),
+        ];
+        assert_eq!(sys_poll(&mut fds), Ok(2));
+        assert_eq!(fds[0].revents, PollEvents::NONE);
+        assert_eq!(fds[1].revents, PollEvents::WRITE);
+        assert!(fds[2].revents.invalid);
+        write(w, b"hi").unwrap();
+        assert_eq!(sys_poll(&mut fds[..1]), Ok(1));
+        assert_eq!(fds[0].revents, PollEvents::READ);
+
+        // Nothing ready: a zero timeout returns, no timeout would block
+        let mut fds = vec![PollFd::fd(Fd::STDIN, PollEvents::READ)];
+        assert_eq!(sys_poll(&mut fds), Ok(0));
+        assert_eq!(
+            KERNEL.with(|k| k.borrow().sys_poll(&mut fds, None)),
+            Err(SyscallError::WouldBlock)
+        );
+        console_push_input(b"x");
+        assert_eq!(sys_poll(&mut fds), Ok(1));
+
+        let server = socket(SocketType::Stream);
+        bind(server, "/tmp/poll.sock").unwrap();
+        listen(server, 4).unwrap();
+        let client = socket(SocketType::Stream);
+        let mut fds = vec![
+            PollFd::socket(server, PollEvents::READ),
+            PollFd::socket(client, PollEvents::WRITE),
+        ];
+        assert_eq!(sys_poll(&mut fds), Ok(0));
+        connect(client, "/tmp/poll.sock").unwrap();
+        let (conn, _) = accept(server).unwrap();
+        assert_eq!(sys_poll(&mut fds), Ok(1));
+        assert_eq!(fds[1].revents, PollEvents::WRITE);
+        socket_close(conn).unwrap();
+        assert_eq!(sys_poll(&mut fds), Ok(1));
+        assert!(fds[1].revents.hangup);
+
+        KERNEL.with(|k| k.borrow_mut().fifos_mut().mkfifo("/tmp/fifo").unwrap());
+        let mut fds = vec![
+            PollFd::fifo("/tmp/fifo", PollEvents::READ_WRITE),
+            PollFd::fifo("/tmp/nope", PollEvents::READ),
+        ];
+        assert_eq!(sys_poll(&mut fds), Ok(2));
+        // No writers means end of file; no readers means writes would fail
+        assert!(fds[0].revents.readable && fds[0].revents.error);
+        assert!(fds[1].revents.invalid);
+    }
+
+    #[test]
+    fn test_poll_future() {
+        use std::future::Future;
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::task::{Context, Poll, Wake, Waker};
+
+        struct Counter(AtomicUsize);
+        impl Wake for Counter {
+            fn wake(self: Arc<Self>) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        setup_test_kernel();
+        let wakes = Arc::new(Counter(AtomicUsize::new(0)));
+        let waker = Waker::from(wakes.clone());
+        let mut cx = Context::from_waker(&waker);
+        let (r, w) = pipe().unwrap();
+
+        // A write wakes the waiting poll, which then finds the data
+        let mut waiting = Box::pin(poll(vec![PollFd::fd(r, PollEvents::READ)], None));
+        assert!(waiting.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 0);
+        write(w, b"x").unwrap();
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 1);
+        match waiting.as_mut().poll(&mut cx) {
+            Poll::Ready(Ok(fds)) => assert_eq!(fds[0].revents, PollEvents::READ),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // Timeout
+        let mut buf = [0u8; 4];
+        read(r, &mut buf).unwrap();
+        let mut waiting = Box::pin(poll(vec![PollFd::fd(r, PollEvents::READ)], Some(50.0)));
+        assert!(waiting.as_mut().poll(&mut cx).is_pending());
+        set_time(50.0);
+        match waiting.as_mut().poll(&mut cx) {
+            Poll::Ready(Ok(fds)) => assert!(!fds[0].is_ready()),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // Signals interrupt the wait
+        let mut waiting = Box::pin(poll(vec![PollFd::fd(r, PollEvents::READ)], None));
+        assert!(waiting.as_mut().poll(&mut cx).is_pending());
+        kill(getpid().unwrap(), Signal::SIGTERM).unwrap();
+        assert!(matches!(
+            waiting.as_mut().poll(&mut cx),
+            Poll::Ready(Err(SyscallError::Interrupted))
+        ));
+    }
+
+    #[test]
+    fn test_epoll() {
+        use crate::kernel::epoll::Interest;
+        use std::future::Future;
+        use std::sync::Arc;
+        use std::task::{Context, Poll, Wake, Waker};
+
+        struct Noop;
+        impl Wake for Noop {
+            fn wake(self: Arc<Self>) {}
+        }
+
+        setup_test_kernel();
+        let epfd = epoll_create().unwrap();
+        let (r, w) = pipe().unwrap();
+        let readable = |fd| Interest::new(EventSource::Poll(PollSource::Fd(fd)), PollEvents::READ);
+
+        epoll_ctl(epfd, EpollCtl::Add(1, readable(r))).unwrap();
+        assert_eq!(
+            epoll_ctl(epfd, EpollCtl::Add(1, readable(r))),
+            Err(SyscallError::AlreadyExists)
+        );
+        assert_eq!(
+            epoll_ctl(epfd, EpollCtl::Add(2, readable(Fd(99)))),
+            Err(SyscallError::BadFd)
+        );
+        assert_eq!(
+            epoll_ctl(epfd, EpollCtl::Add(2, readable(epfd))),
+            Err(SyscallError::InvalidArgument)
+        );
+        assert_eq!(
+            epoll_ctl(epfd, EpollCtl::Add(2, Interest::timer(10.0, Some(0.0)))),
+            Err(SyscallError::InvalidArgument)
+        );
+        assert_eq!(
+            epoll_ctl(r, EpollCtl::Delete(1)),
+            Err(SyscallError::InvalidArgument)
+        );
+        assert_eq!(
+            epoll_ctl(epfd, EpollCtl::Delete(5)),
+            Err(SyscallError::NotFound)
+        );
+
+        // Nothing ready
+        assert_eq!(
+            KERNEL.with(|k| k.borrow_mut().sys_epoll_wait(epfd, 8, None)),
+            Err(SyscallError::WouldBlock)
+        );
+        assert_eq!(
+            KERNEL.with(|k| k.borrow_mut().sys_epoll_wait(epfd, 8, Some(0.0))),
+            Ok(vec![])
+        );
+
+        // Level-triggered: the pipe stays ready until it is read, and the
+        // queue's own descriptor polls readable meanwhile
+        write(w, b"x").unwrap();
+        let mut pfds = [PollFd::fd(epfd, PollEvents::READ)];
+        assert_eq!(
+            KERNEL.with(|k| k.borrow().sys_poll(&mut pfds, Some(0.0))),
+            Ok(1)
+        );
+        for _ in 0..2 {
+            let events = KERNEL
+                .with(|k| k.borrow_mut().sys_epoll_wait(epfd, 8, None))
+                .unwrap();
+            assert_eq!(events.len(), 1);
+            assert_eq!((events[0].token, events[0].events), (1, PollEvents::READ));
+        }
+        let mut buf = [0u8; 4];
+        read(r, &mut buf).unwrap();
+
+        // Signals, watched without being taken
+        let sigusr1 = 1 << Signal::SIGUSR1.num();
+        sigprocmask(SigProcMaskHow::Block, sigusr1).unwrap();
+        epoll_ctl(
+            epfd,
+            EpollCtl::Add(
+                2,
+                Interest::new(EventSource::Signals(sigusr1), PollEvents::READ).oneshot(),
+            ),
+        )
+        .unwrap();
+        kill(getpid().unwrap(), Signal::SIGUSR1).unwrap();
+        let events = KERNEL
+            .with(|k| k.borrow_mut().sys_epoll_wait(epfd, 8, Some(0.0)))
+            .unwrap();
+        assert_eq!(events.iter().map(|e| e.token).collect::<Vec<_>>(), [2]);
+        assert!(
+            KERNEL
+                .with(|k| k.borrow_mut().sys_epoll_wait(epfd, 8, Some(0.0)))
+                .unwrap()
+                .is_empty()
+        );
+        epoll_ctl(epfd, EpollCtl::Delete(2)).unwrap();
+
+        // A timer the queue armed ends the wait
+        let waker = Waker::from(Arc::new(Noop));
+        let mut cx = Context::from_waker(&waker);
+        epoll_ctl(epfd, EpollCtl::Add(3, Interest::timer(20.0, None))).unwrap();
+        let mut waiting = Box::pin(epoll_wait(epfd, 8, None));
+        assert!(waiting.as_mut().poll(&mut cx).is_pending());
+        set_time(20.0);
+        match waiting.as_mut().poll(&mut cx) {
+            Poll::Ready(Ok(events)) => {
+                assert_eq!((events[0].token, events[0].expirations), (3, 1))
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // And a timeout with nothing ready
+        let mut waiting = Box::pin(epoll_wait(epfd, 8, Some(10.0)));
+        assert!(waiting.as_mut().poll(&mut cx).is_pending());
+        set_time(30.0);
+        assert!(matches!(
+            waiting.as_mut().poll(&mut cx),
+            Poll::Ready(Ok(events)) if events.is_empty()
+        ));
+    }
+
+    #[test]
+    fn test_net_permissions() {
+        use std::future::Future;
+        use std::sync::Arc;
+        use std::task::{Context, Poll, Wake, Waker};
+
+        struct Noop;
+        impl Wake for Noop {
+            fn wake(self: Arc<Self>) {}
+        }
+
+        setup_test_kernel();
+        let set_euid =
+            |uid| KERNEL.with(|k| k.borrow_mut().current_process_mut().unwrap().euid = uid);
+
+        // Relative URLs stay on the page's origin
+        assert_eq!(net_check("/packages/index.json"), Ok(()));
+        assert_eq!(net_check("https://a.test/x"), Err(SyscallError::WouldBlock));
+        assert_eq!(
+            net_check("wss://a.test/socket"),
+            Err(SyscallError::WouldBlock)
+        );
+        let prompts = netperm_prompts();
+        assert_eq!(prompts.len(), 1);
+        assert_eq!(
+            (prompts[0].origin.as_str(), prompts[0].requests),
+            ("https://a.test", 2)
+        );
+        assert!(
+            take_net_notice()
+                .unwrap()
+                .contains("netperm allow https://a.test")
+        );
+        assert_eq!(take_net_notice(), None);
+
+        // Relative URLs stay on the page's origin; protocol-relative ones
+        // don't, and aren't let through without one
+        assert_eq!(net_check("/index.json"), Ok(()));
+        assert_eq!(
+            net_check("//other.test/x"),
+            Err(SyscallError::PermissionDenied)
+        );
+        assert_eq!(
+            net_check("https:///nohost"),
+            Err(SyscallError::PermissionDenied)
+        );
+
+        // Only root decides
+        assert_eq!(
+            netperm_set("https://a.test", NetDecision::Allow),
+            Err(SyscallError::PermissionDenied)
+        );
+
+        // A waiting request goes through once allowed
+        let waker = Waker::from(Arc::new(Noop));
+        let mut cx = Context::from_waker(&waker);
+        let mut waiting = Box::pin(net_access("https://a.test/y"));
+        assert!(waiting.as_mut().poll(&mut cx).is_pending());
+        set_euid(Uid::ROOT);
+        netperm_set("https://a.test", NetDecision::Allow).unwrap();
+        assert_eq!(waiting.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert!(netperm_prompts().is_empty());
+
+        netperm_set("http://b.test", NetDecision::Deny).unwrap();
+        assert_eq!(
+            net_check("http://b.test/"),
+            Err(SyscallError::PermissionDenied)
+        );
+        assert_eq!(
+            read_file(ALLOWLIST_PATH).unwrap(),
+            "# Network permissions (see netperm)\ndeny http://b.test\nallow https://a.test\n"
+        );
+        assert_eq!(
+            netperm_list(),
+            [
+                ("http://b.test".to_string(), NetDecision::Deny),
+                ("https://a.test".to_string(), NetDecision::Allow)
+            ]
+        );
+
+        // Revoking asks again next time
+        netperm_revoke("https://a.test").unwrap();
+        assert_eq!(
+            netperm_revoke("https://a.test"),
+            Err(SyscallError::NotFound)
+        );
+        assert_eq!(net_check("https://a.test/"), Err(SyscallError::WouldBlock));
+
+        // With no answer the request is refused
+        let mut waiting = Box::pin(net_access("https://a.test/z"));
+        assert!(waiting.as_mut().poll(&mut cx).is_pending());
+        set_time(PROMPT_TIMEOUT);
+        tick_timers();
+        assert!(netperm_prompts().is_empty());
+        assert_eq!(
+            waiting.as_mut().poll(&mut cx),
+            Poll::Ready(Err(SyscallError::PermissionDenied))
+        );
+    }
+
+    #[test]
+    fn test_offline_mode() {
+        use crate::kernel::httpcache::CachedResponse;
+        use std::future::Future;
+        use std::sync::Arc;
+        use std::task::{Context, Poll, Wake, Waker};
+
+        struct Noop;
+        impl Wake for Noop {
+            fn wake(self: Arc<Self>) {}
+        }
+
+        setup_test_kernel();
+        let waker = Waker::from(Arc::new(Noop));
+        let mut cx = Context::from_waker(&waker);
+
+        // Going offline ends waits for a permission
+        let mut waiting = Box::pin(net_access("https://a.test/x"));
+        assert!(waiting.as_mut().poll(&mut cx).is_pending());
+        assert!(!offline());
+        set_offline(true).unwrap();
+        assert!(offline());
+        assert!(netperm_prompts().is_empty());
+        assert_eq!(
+            waiting.as_mut().poll(&mut cx),
+            Poll::Ready(Err(SyscallError::NetworkDown))
+        );
+        assert_eq!(net_check("/index.json"), Err(SyscallError::NetworkDown));
+        set_offline(false).unwrap();
+        assert_eq!(net_check("/index.json"), Ok(()));
+
+        // The cache is the kernel's, kept for anyone
+        let url = "https://pkg.test/index.json";
+        let mut headers = std::collections::HashMap::new();
+        headers.insert("etag".to_string(), "\"1\"".to_string());
+        let response =
+            CachedResponse::from_response(url, 200, &headers, b"{}".to_vec(), 0.0).unwrap();
+        http_cache_put(&response).unwrap();
+        assert_eq!(http_cache_get(url), Some(response.clone()));
+        assert_eq!(http_cache_usage().0, 1);
+        assert_eq!(http_cache_clear(), Err(SyscallError::PermissionDenied));
+        KERNEL.with(|k| k.borrow_mut().current_process_mut().unwrap().euid = Uid::ROOT);
+        assert_eq!(http_cache_clear(), Ok(1));
+        assert_eq!(http_cache_get(url), None);
+
+        // Nothing is served from an origin that is denied, and denying or
+        // revoking one drops what was cached from it
+        http_cache_put(&response).unwrap();
+        netperm_set("https://pkg.test", NetDecision::Allow).unwrap();
+        write_file(ALLOWLIST_PATH, "deny https://pkg.test\n").unwrap();
+        assert_eq!(http_cache_get(url), None);
+        assert_eq!(http_cache_usage().0, 1);
+        netperm_set("https://pkg.test", NetDecision::Deny).unwrap();
+        assert_eq!(http_cache_usage().0, 0);
+        netperm_set("https://pkg.test", NetDecision::Allow).unwrap();
+        http_cache_put(&response).unwrap();
+        assert_eq!(http_cache_get(url), Some(response));
+        netperm_revoke("https://pkg.test").unwrap();
+        assert_eq!(http_cache_get(url), None);
+    }
+
+    #[test]
+    fn test_signalfd() {
+        setup_test_kernel();
+        let me = getpid().unwrap();
+        let mask = (1 << Signal::SIGINT.num()) | (1 << Signal::SIGUSR1.num());
+        sigprocmask(SigProcMaskHow::Block, mask).unwrap();
+        let fd = signalfd(None, mask).unwrap();
+
+        let mut buf = [0u8; 16];
+        assert_eq!(read(fd, &mut buf), Err(SyscallError::WouldBlock));
+        assert_eq!(read(fd, &mut buf[..2]), Err(SyscallError::InvalidArgument));
+
+        kill(me, Signal::SIGUSR1).unwrap();
+        kill(me, Signal::SIGTERM).unwrap();
+        kill(me, Signal::SIGINT).unwrap();
+        assert_eq!(read(fd, &mut buf), Ok(8));
+        assert_eq!(buf[..8], [8, 0, 0, 0, 5, 0, 0, 0]);
+        // SIGTERM isn't in the mask and stays pending
+        assert_eq!(sigpending_mask().unwrap(), 1 << Signal::SIGTERM.num());
+
+        // Narrow the mask of the same descriptor
+        assert_eq!(signalfd(Some(fd), 1 << Signal::SIGINT.num()), Ok(fd));
+        kill(me, Signal::SIGUSR1).unwrap();
+        // Nothing to read, and the pending SIGTERM interrupts the wait
+        assert_eq!(read(fd, &mut buf), Err(SyscallError::Interrupted));
+        let (pipe_fd, _) = pipe().unwrap();
+        assert_eq!(
+            signalfd(Some(pipe_fd), 0),
+            Err(SyscallError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn test_sparse_write_back() {
+        setup_test_kernel();
+        let fd = open("/tmp/sparse", OpenFlags::WRITE).unwrap();
+        KERNEL
+            .with(|k| k.borrow_mut().sys_seek(fd, SeekFrom::Start(1 << 20)))
+            .unwrap();
+        assert_eq!(write(fd, b"end"), Ok(3));
+        close(fd).unwrap();
+
+        // The zeros before the data aren't stored
+        let meta = metadata("/tmp/sparse").unwrap();
+        assert_eq!(meta.size, (1 << 20) + 3);
+        assert_eq!(meta.allocated, 3);
+        let data = read_file("/tmp/sparse").unwrap();
+        assert_eq!(&data[(1 << 20) - 1..], "\0end");
+    }
+
+    #[test]
+    fn test_file_locking() {
+        use super::super::flock::{F_TEST, F_TLOCK, LOCK_EX, LOCK_NB};
+        setup_test_kernel();
+        let me = getpid().unwrap();
+        let other = KERNEL.with(|k| k.borrow_mut().spawn_process("other", None));
+        let switch = |pid: Pid| KERNEL.with(|k| k.borrow_mut().set_current(pid));
+        let seek =
+            |fd: Fd, pos: u64| KERNEL.with(|k| k.borrow_mut().sys_seek(fd, SeekFrom::Start(pos)));
+        write_file("/tmp/data", "0123456789").unwrap();
+
+        // Lock bytes 0..4
+        let fd = open("/tmp/data", OpenFlags::RDWR).unwrap();
+        lockf(fd, F_TLOCK, 4).unwrap();
+
+        switch(other);
+        let ofd = open("/tmp/data", OpenFlags::RDWR).unwrap();
+        assert_eq!(lockf(ofd, F_TEST, 4), Err(SyscallError::WouldBlock));
+        assert_eq!(lockf(ofd, F_TLOCK, 0), Err(SyscallError::WouldBlock));
+        // Advisory: I/O still goes through
+        assert_eq!(write(ofd, b"a"), Ok(1));
+
+        // Set-group-ID without group execute makes the lock mandatory
+        chmod("/tmp/data", 0o2644).unwrap();
+        let mut buf = [0u8; 2];
+        seek(ofd, 0).unwrap();
+        assert_eq!(write(ofd, b"b"), Err(SyscallError::WouldBlock));
+        assert_eq!(read(ofd, &mut buf), Err(SyscallError::WouldBlock));
+        assert_eq!(
+            open("/tmp/data", OpenFlags::WRITE),
+            Err(SyscallError::WouldBlock)
+        );
+        seek(ofd, 5).unwrap();
+        assert_eq!(read(ofd, &mut buf), Ok(2));
+
+        // Closing the holder's last descriptor releases its locks
+        switch(me);
+        assert_eq!(write(fd, b"c"), Ok(1));
+        close(fd).unwrap();
+        switch(other);
+        seek(ofd, 0).unwrap();
+        assert_eq!(write(ofd, b"d"), Ok(1));
+
+        // So does exiting
+        flock(ofd, LOCK_EX).unwrap();
+        switch(me);
+        let fd = open("/tmp/data", OpenFlags::READ).unwrap();
+        assert_eq!(flock(fd, LOCK_EX | LOCK_NB), Err(SyscallError::WouldBlock));
+        assert_eq!(flock_holder("/tmp/data", LockType::Shared), Some(other));
+        switch(other);
+        exit(0).unwrap();
+        switch(me);
+        assert_eq!(flock(fd, LOCK_EX | LOCK_NB), Ok(()));
+    }
+
+    #[test]
+    fn test_deadlock_detection() {
+        use super::super::flock::{LOCK_EX, LOCK_UN};
+        setup_test_kernel();
+        let me = getpid().unwrap();
+        let other = KERNEL.with(|k| k.borrow_mut().spawn_process("other", None));
+        let switch = |pid: Pid| KERNEL.with(|k| k.borrow_mut().set_current(pid));
+        trace_enable();
+
+        // I hold a lock on /tmp/a, the other process takes the semaphore
+        let fd = open("/tmp/a", OpenFlags::WRITE).unwrap();
+        flock(fd, LOCK_EX).unwrap();
+        let sem = semget(-1, 1, true).unwrap();
+        KERNEL
+            .with(|k| {
+                k.borrow_mut()
+                    .semaphores_mut()
+                    .semctl_setval(SemId(sem), 0, 1, 0, 0.0)
+            })
+            .unwrap();
+        switch(other);
+        semop(sem, 0, -1, SEM_UNDO).unwrap();
+        let ofd = open("/tmp/a", OpenFlags::READ).unwrap();
+
+        // It waits for my lock, which is fine...
+        assert_eq!(flock(ofd, LOCK_EX), Err(SyscallError::WouldBlock));
+        // ...until I wait for its semaphore
+        switch(me);
+        assert_eq!(semop(sem, 0, -1, 0), Err(SyscallError::Deadlock));
+        assert_eq!(semop(sem, 0, -1, IPC_NOWAIT), Err(SyscallError::WouldBlock));
+
+        let reports = deadlocks();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].victim, me);
+        let pids: Vec<Pid> = reports[0].cycle.iter().map(|e| e.pid).collect();
+        assert_eq!(pids, [me, other]);
+        let traced = KERNEL.with(|k| {
+            k.borrow()
+                .tracer()
+                .events()
+                .iter()
+                .find(|e| e.name == "deadlock")
+                .map(|e| (e.pid, e.detail.clone()))
+        });
+        assert_eq!(
+            traced,
+            Some((
+                Some(me.0),
+                Some(format!(
+                    "{} -> semaphore {}[0] -> {} -> lock on /tmp/a -> {}",
+                    me.0, sem, other.0, me.0
+                ))
+            ))
+        );
+
+        // The other process still waits and gets the lock once I back off
+        switch(other);
+        assert_eq!(flock(ofd, LOCK_EX), Err(SyscallError::WouldBlock));
+        switch(me);
+        flock(fd, LOCK_UN).unwrap();
+        switch(other);
+        assert_eq!(flock(ofd, LOCK_EX), Ok(()));
+        assert!(KERNEL.with(|k| k.borrow().waits().waiting_on(other).is_none()));
+
+        // Its exit gives the semaphore back
+        exit(0).unwrap();
+        switch(me);
+        assert_eq!(semop(sem, 0, -1, 0), Ok(()));
+    }
+
+    #[test]
+    fn test_watchdog_fd() {
+        use super::super::init::{RestartPolicy, ServiceConfig};
+        setup_test_kernel();
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            let mut config = ServiceConfig::new("worker");
+            config.restart = RestartPolicy::Always;
+            config.watchdog_ms = Some(1000.0);
+            k.init_mut().register_service(config);
+            k.init_mut().start_service("worker").unwrap();
+        });
+        trace_enable();
+
+        // Only root or the service itself may pet it
+        assert_eq!(watchdog_open("worker"), Err(SyscallError::PermissionDenied));
+        KERNEL.with(|k| k.borrow_mut().current_process_mut().unwrap().euid = Uid::ROOT);
+        assert_eq!(watchdog_open("shell"), Err(SyscallError::InvalidArgument));
+        assert_eq!(watchdog_open("nope"), Err(SyscallError::NotFound));
+        let fd = watchdog_open("worker").unwrap();
+        assert!(check_watchdogs().is_empty());
+
+        set_time(800.0);
+        assert_eq!(write(fd, b"1"), Ok(1));
+        set_time(1500.0);
+        watchdog_pet(fd).unwrap();
+        set_time(2400.0);
+        assert!(check_watchdogs().is_empty());
+
+        set_time(2600.0);
+        let incidents = check_watchdogs();
+        assert_eq!(incidents.len(), 1);
+        let log = read_file(WATCHDOG_LOG).unwrap();
+        assert_eq!(log, format!("{}\n", incidents[0].to_line()));
+        assert!(log.contains("worker: watchdog timeout, silent 1100 ms"));
+        assert!(KERNEL.with(|k| {
+            k.borrow()
+                .tracer()
+                .events()
+                .iter()
+                .any(|e| e.name == "watchdog")
+        }));
+
+        // Restarted, and the descriptor keeps working
+        let status = KERNEL.with(|k| k.borrow().init().service_status("worker").unwrap());
+        assert_eq!(status.restart_count, 1);
+        assert_eq!(watchdog_pet(fd), Ok(()));
+        let file = open("/tmp/f", OpenFlags::WRITE).unwrap();
+        assert_eq!(watchdog_pet(file), Err(SyscallError::InvalidArgument));
+    }
+
+    #[test]
+    fn test_task_fault() {
+        use super::super::flock::LOCK_EX;
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        setup_test_kernel();
+        trace_enable();
+        set_time(1500.0);
+        let parent = getpid().unwrap();
+        let child = fork().unwrap();
+
+        let switch = |pid: Pid| KERNEL.with(|k| k.borrow_mut().set_current(pid));
+
+        // The child takes a lock, then panics
+        let crashing = crate::kernel::spawn(async move {
+            switch(child);
+            let fd = open("/tmp/data", OpenFlags::WRITE).unwrap();
+            flock(fd, LOCK_EX).unwrap();
+            switch(parent);
+            panic!("index out of bounds");
+        });
+        set_process_task(child, crashing).unwrap();
+        let ran = Rc::new(Cell::new(false));
+        let r = ran.clone();
+        crate::kernel::spawn(async move { r.set(true) });
+
+        crate::kernel::tick();
+        assert!(ran.get());
+
+        // The child is a zombie with the fault status and its lock is gone
+        assert_eq!(get_process_task(child), Ok(None));
+        assert_eq!(flock_holder("/tmp/data", LockType::Exclusive), None);
+        assert_eq!(
+            waitpid(child.0 as i32, WaitFlags::NONE),
+            Ok((child, WaitStatus::Exited(FAULT_STATUS)))
+        );
+
+        let reports = crash_reports();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].pid, Some(child));
+        assert_eq!(reports[0].message, "index out of bounds");
+        let text = read_file(&format!("/var/log/crash/test.{}.1500", child.0)).unwrap();
+        assert_eq!(text, reports[0].to_text());
+        assert!(KERNEL.with(|k| {
+            k.borrow()
+                .tracer()
+                .events()
+                .iter()
+                .any(|e| e.name == "fault" && e.pid == Some(child.0))
+        }));
+
+        // A task without a process is reported as the kernel's
+        crate::kernel::spawn(async { panic!("compositor") });
+        crate::kernel::tick();
+        let reports = crash_reports();
+        assert_eq!(reports.len(), 2);
+        assert_eq!((reports[1].pid, reports[1].name.as_str()), (None, "kernel"));
+    }
+
     /// Helper to call sys_chroot
     fn chroot(path: &str) -> SyscallResult<()> {
         KERNEL.with(|k| k.borrow_mut().sys_chroot(path))
@@ -3,8 +3,16 @@
 //! Provides a view into kernel object attributes. In a WASM environment,
 //! this is largely simulated but provides useful system information.
 
+use super::power::Governor;
+use super::process::{Handle, OpenFlags};
+use super::synthfs::SyntheticFs;
+use super::syscall::{Kernel, SyscallError, SyscallResult};
 use std::collections::HashMap;
 
+/// Power governor attributes under /sys/power, generated by the kernel
+/// from its `PowerGovernor` (`governor` is writable)
+pub const POWER_ATTRS: &[&str] = &["governor", "available_governors", "mode"];
+
 /// Sysfs manager
 pub struct SysFs {
     /// Static content cache
@@ -60,7 +68,12 @@ impl SysFs {
             ]),
             "/sys/devices/system/cpu/cpu0" => Some(vec!["cpufreq".to_string()]),
             "/sys/fs" => Some(vec!["cgroup".to_string()]),
-            "/sys/power" => Some(vec!["state".to_string()]),
+            "/sys/power" => Some(
+                std::iter::once("state")
+                    .chain(POWER_ATTRS.iter().copied())
+                    .map(String::from)
+                    .collect(),
+            ),
             "/sys/block" | "/sys/bus" | "/sys/firmware" | "/sys/module" => {
                 Some(Vec::new()) // Empty directories
             }
@@ -78,7 +91,18 @@ impl SysFs {
             return true;
         }
         // Check if it's a known file
-        self.generate_content(path).is_some()
+        Self::power_attr(path).is_some() || self.generate_content(path).is_some()
+    }
+
+    /// Name of the /sys/power governor attribute at `path`, if it is one
+    pub fn power_attr(path: &str) -> Option<&'static str> {
+        let name = path.strip_prefix("/sys/power/")?;
+        POWER_ATTRS.iter().copied().find(|attr| *attr == name)
+    }
+
+    /// Check if a sysfs file accepts writes
+    pub fn is_writable(path: &str) -> bool {
+        matches!(path, "/sys/power/governor" | "/sys/kernel/hostname")
     }
 
     /// Check if a path is a directory
@@ -107,3 +131,58 @@ impl Default for SysFs {
         Self::new()
     }
 }
+
+impl SyntheticFs for SysFs {
+    fn fs_type(&self) -> &'static str {
+        "sysfs"
+    }
+
+    fn list_dir(&self, _kernel: &Kernel, path: &str) -> Option<Vec<String>> {
+        SysFs::list_dir(self, path)
+    }
+
+    fn exists(&self, _kernel: &Kernel, path: &str) -> bool {
+        SysFs::exists(self, path)
+    }
+
+    fn open(&self, kernel: &mut Kernel, path: &str, flags: OpenFlags) -> SyscallResult<Handle> {
+        let writable = SysFs::is_writable(path);
+        if flags.write && !writable {
+            return Err(SyscallError::PermissionDenied);
+        }
+
+        // Writes start empty and are applied as they arrive (see sys_write)
+        let content = if flags.write {
+            Vec::new()
+        } else if let Some(attr) = SysFs::power_attr(path) {
+            kernel.power().attr(attr).unwrap_or_default().into_bytes()
+        } else if path == "/sys/kernel/hostname" {
+            format!("{}\n", kernel.init().hostname()).into_bytes()
+        } else {
+            self.generate_content(path).ok_or(SyscallError::NotFound)?
+        };
+
+        // Create a file object with the generated content
+        Ok(kernel.create_file_object(path.into(), content, true, writable))
+    }
+
+    fn is_writable(&self, path: &str) -> bool {
+        SysFs::is_writable(path)
+    }
+
+    fn store(&self, kernel: &mut Kernel, path: &str, value: &[u8]) -> SyscallResult<()> {
+        match path {
+            "/sys/power/governor" => {
+                let name = std::str::from_utf8(value).map_err(|_| SyscallError::InvalidData)?;
+                let governor = Governor::parse(name).ok_or(SyscallError::InvalidArgument)?;
+                kernel.sys_set_governor(governor);
+                Ok(())
+            }
+            "/sys/kernel/hostname" => {
+                let name = std::str::from_utf8(value).map_err(|_| SyscallError::InvalidData)?;
+                kernel.sys_sethostname(name.trim())
+            }
+            _ => Err(SyscallError::PermissionDenied),
+        }
+    }
+}
@@ -0,0 +1,482 @@
+//! System partition
+//!
+//! Built-in programs and default configuration live in a read-only
+//! `/system` tree, kept apart from writable user data. The tree is filled
+//! at boot from a system image held in one of two slots, A and B, under
+//! [`SLOT_DIR`]; an empty slot holds the factory image built into the
+//! binary.
+//!
+//! Updates are A/B: [`stage`] writes a new image into the slot that isn't
+//! running and marks it pending, and the next [`boot`] switches to it. The
+//! new slot is on trial until [`mark_good`] confirms the boot. If the
+//! system boots again before that, or the staged image fails
+//! verification, it rolls back to the slot it came from.
+
+use super::pkg::Checksum;
+use crate::vfs::{FileSystem, MemoryFs, write_string};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+
+/// Where the active image is mounted
+pub const SYSTEM_ROOT: &str = "/system";
+
+/// Directory holding the slot images and their state
+pub const SLOT_DIR: &str = "/var/lib/system";
+
+/// Slot state file
+pub const STATE_PATH: &str = "/var/lib/system/state";
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// One of the two system image slots
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Slot {
+    #[default]
+    A,
+    B,
+}
+
+impl Slot {
+    pub fn name(self) -> &'static str {
+        match self {
+            Slot::A => "a",
+            Slot::B => "b",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim() {
+            "a" | "A" => Some(Slot::A),
+            "b" | "B" => Some(Slot::B),
+            _ => None,
+        }
+    }
+
+    /// The slot updates are staged into while this one runs
+    pub fn other(self) -> Self {
+        match self {
+            Slot::A => Slot::B,
+            Slot::B => Slot::A,
+        }
+    }
+
+    /// Path of the image file for this slot
+    pub fn path(self) -> String {
+        format!("{}/{}", SLOT_DIR, self.name())
+    }
+
+    /// Mount source shown for /system when booted from this slot
+    pub fn source(self) -> String {
+        format!("system-{}", self.name())
+    }
+}
+
+/// A system image: the contents of `/system`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SystemImage {
+    pub version: String,
+    /// File contents keyed by path relative to `/system`
+    pub files: BTreeMap<String, String>,
+}
+
+/// On-disk form of an image, with a SHA-256 over version and files
+#[derive(Serialize, Deserialize)]
+struct ImageFile {
+    version: String,
+    checksum: String,
+    files: BTreeMap<String, String>,
+}
+
+/// Check that an image path stays inside /system
+fn valid_image_path(path: &str) -> bool {
+    !path.is_empty()
+        && !path.starts_with('/')
+        && path
+            .split('/')
+            .all(|part| !part.is_empty() && part != "." && part != "..")
+}
+
+impl SystemImage {
+    pub fn new(version: &str) -> Self {
+        Self {
+            version: version.to_string(),
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// Add a file at `path` relative to `/system`
+    pub fn add(&mut self, path: &str, content: impl Into<String>) {
+        self.files.insert(path.to_string(), content.into());
+    }
+
+    fn checksum(&self) -> io::Result<Checksum> {
+        let data = serde_json::to_vec(&(&self.version, &self.files))
+            .map_err(|e| invalid(e.to_string()))?;
+        Ok(Checksum::compute(&data))
+    }
+
+    /// Serialize the image with its checksum
+    pub fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        let file = ImageFile {
+            version: self.version.clone(),
+            checksum: self.checksum()?.to_hex(),
+            files: self.files.clone(),
+        };
+        serde_json::to_vec(&file).map_err(|e| invalid(e.to_string()))
+    }
+
+    /// Parse and verify a serialized image
+    pub fn from_bytes(data: &[u8]) -> io::Result<Self> {
+        let file: ImageFile =
+            serde_json::from_slice(data).map_err(|e| invalid(format!("bad image: {}", e)))?;
+        let image = Self {
+            version: file.version,
+            files: file.files,
+        };
+        if image.version.trim().is_empty() {
+            return Err(invalid("image has no version"));
+        }
+        if let Some(path) = image.files.keys().find(|p| !valid_image_path(p)) {
+            return Err(invalid(format!("bad path in image: {}", path)));
+        }
+        let actual = image.checksum()?.to_hex();
+        if actual != file.checksum.trim() {
+            return Err(invalid(format!(
+                "checksum mismatch: expected {}, got {}",
+                file.checksum.trim(),
+                actual
+            )));
+        }
+        Ok(image)
+    }
+}
+
+/// Which slot runs, and what happens at the next boot
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SlotState {
+    /// Slot booted last
+    pub active: Slot,
+    /// Slot to roll back to
+    pub previous: Option<Slot>,
+    /// Slot to switch to at the next boot
+    pub pending: Option<Slot>,
+    /// The active slot is new and its boot hasn't been confirmed
+    pub trying: bool,
+    /// Why the last update or boot failed
+    pub last_error: Option<String>,
+}
+
+impl SlotState {
+    /// Parse `key=value` lines; unknown keys are ignored
+    pub fn parse(content: &str) -> Self {
+        let mut state = Self::default();
+        for line in content.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim() {
+                "active" => state.active = Slot::parse(value).unwrap_or_default(),
+                "previous" => state.previous = Slot::parse(value),
+                "pending" => state.pending = Slot::parse(value),
+                "trying" => state.trying = value == "1",
+                "last_error" if !value.is_empty() => state.last_error = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        state
+    }
+
+    pub fn to_text(&self) -> String {
+        let slot = |s: Option<Slot>| s.map(Slot::name).unwrap_or("");
+        format!(
+            "active={}\nprevious={}\npending={}\ntrying={}\nlast_error={}\n",
+            self.active.name(),
+            slot(self.previous),
+            slot(self.pending),
+            u8::from(self.trying),
+            self.last_error.as_deref().unwrap_or("").replace('\n', " ")
+        )
+    }
+}
+
+/// What a boot of the system partition did
+#[derive(Debug, Clone, PartialEq)]
+pub struct SystemBoot {
+    /// Slot mounted on /system
+    pub slot: Slot,
+    pub version: String,
+    /// The slot is running from the factory image
+    pub factory: bool,
+    /// Switched to a newly staged slot this boot
+    pub switched: bool,
+    /// Why an update was rolled back this boot
+    pub rolled_back: Option<String>,
+}
+
+/// Read the slot state; a missing or unreadable file is the default
+pub fn load_state(fs: &mut MemoryFs) -> SlotState {
+    crate::vfs::read_to_string(fs, STATE_PATH)
+        .map(|content| SlotState::parse(&content))
+        .unwrap_or_default()
+}
+
+fn save_state(fs: &mut MemoryFs, state: &SlotState) -> io::Result<()> {
+    write_root_file(fs, STATE_PATH, &state.to_text(), 0o644)
+}
+
+/// Read and verify the image in `slot`; `None` for an empty slot
+pub fn read_slot(fs: &mut MemoryFs, slot: Slot) -> io::Result<Option<SystemImage>> {
+    let path = slot.path();
+    if !fs.exists(&path) {
+        return Ok(None);
+    }
+    let content = crate::vfs::read_to_string(fs, &path)?;
+    SystemImage::from_bytes(content.as_bytes()).map(Some)
+}
+
+/// Create `path` and any missing parents
+fn ensure_dir(fs: &mut MemoryFs, path: &str) -> io::Result<()> {
+    let mut current = String::new();
+    for part in path.split('/').filter(|p| !p.is_empty()) {
+        current.push('/');
+        current.push_str(part);
+        if !fs.exists(&current) {
+            fs.create_dir(&current)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_root_file(fs: &mut MemoryFs, path: &str, content: &str, mode: u16) -> io::Result<()> {
+    ensure_dir(fs, SLOT_DIR)?;
+    fs.chown(SLOT_DIR, Some(0), Some(0))?;
+    write_string(fs, path, content)?;
+    fs.chown(path, Some(0), Some(0))?;
+    fs.chmod(path, mode)
+}
+
+fn remove_tree(fs: &mut MemoryFs, path: &str) -> io::Result<()> {
+    for entry in fs.read_dir(path)? {
+        let child = format!("{}/{}", path, entry.name);
+        if entry.is_dir && !entry.is_symlink {
+            remove_tree(fs, &child)?;
+        } else {
+            fs.remove_file(&child)?;
+        }
+    }
+    fs.remove_dir(path)
+}
+
+/// Replace the `/system` tree with `image`, owned by root
+fn populate(fs: &mut MemoryFs, image: &SystemImage) -> io::Result<()> {
+    if fs.exists(SYSTEM_ROOT) {
+        remove_tree(fs, SYSTEM_ROOT)?;
+    }
+    fs.create_dir(SYSTEM_ROOT)?;
+    fs.chown(SYSTEM_ROOT, Some(0), Some(0))?;
+    for (rel, content) in &image.files {
+        let path = format!("{}/{}", SYSTEM_ROOT, rel);
+        let mut dir = SYSTEM_ROOT.to_string();
+        for part in rel.split('/').take(rel.split('/').count() - 1) {
+            dir.push('/');
+            dir.push_str(part);
+            if !fs.exists(&dir) {
+                fs.create_dir(&dir)?;
+                fs.chown(&dir, Some(0), Some(0))?;
+            }
+        }
+        write_string(fs, &path, content)?;
+        fs.chown(&path, Some(0), Some(0))?;
+        let mode = if rel.starts_with("bin/") {
+            0o755
+        } else {
+            0o644
+        };
+        fs.chmod(&path, mode)?;
+    }
+    Ok(())
+}
+
+/// Boot the system partition: roll back an unconfirmed update, switch to a
+/// pending one, then fill `/system` from the active slot
+pub fn boot(fs: &mut MemoryFs, factory: &SystemImage) -> io::Result<SystemBoot> {
+    let mut state = load_state(fs);
+    let mut rolled_back = None;
+    let mut switched = false;
+
+    if state.trying {
+        let failed = state.active;
+        let reason = format!("slot {} never finished booting", failed.name());
+        state.active = state.previous.take().unwrap_or(failed.other());
+        state.trying = false;
+        state.last_error = Some(reason.clone());
+        rolled_back = Some(reason);
+    }
+
+    if let Some(pending) = state.pending.take() {
+        match read_slot(fs, pending) {
+            Ok(_) => {
+                state.previous = Some(state.active);
+                state.active = pending;
+                state.trying = true;
+                state.last_error = None;
+                switched = true;
+            }
+            Err(e) => {
+                let reason = format!("slot {}: {}", pending.name(), e);
+                state.last_error = Some(reason.clone());
+                rolled_back = Some(reason);
+            }
+        }
+    }
+
+    let image = match read_slot(fs, state.active) {
+        Ok(image) => image,
+        Err(e) => {
+            // The running slot went bad; fall back to the other one
+            let reason = format!("slot {}: {}", state.active.name(), e);
+            state.active = state.active.other();
+            state.previous = None;
+            state.trying = false;
+            state.last_error = Some(reason.clone());
+            rolled_back = Some(reason);
+            switched = false;
+            read_slot(fs, state.active).unwrap_or(None)
+        }
+    };
+    let factory_boot = image.is_none();
+    let image = image.unwrap_or_else(|| factory.clone());
+
+    populate(fs, &image)?;
+    save_state(fs, &state)?;
+    Ok(SystemBoot {
+        slot: state.active,
+        version: image.version,
+        factory: factory_boot,
+        switched,
+        rolled_back,
+    })
+}
+
+/// Confirm that a newly switched slot booted; returns false if there was
+/// nothing to confirm
+pub fn mark_good(fs: &mut MemoryFs) -> io::Result<bool> {
+    let mut state = load_state(fs);
+    if !state.trying {
+        return Ok(false);
+    }
+    state.trying = false;
+    save_state(fs, &state)?;
+    Ok(true)
+}
+
+/// Verify `data` and stage it in the inactive slot for the next boot
+pub fn stage(fs: &mut MemoryFs, data: &[u8]) -> io::Result<(Slot, SystemImage)> {
+    let image = SystemImage::from_bytes(data)?;
+    let mut state = load_state(fs);
+    if state.trying {
+        // The inactive slot is what we would roll back to
+        return Err(io::Error::new(
+            io::ErrorKind::ResourceBusy,
+            "current system has not finished booting",
+        ));
+    }
+    let slot = state.active.other();
+    write_root_file(fs, &slot.path(), &String::from_utf8_lossy(data), 0o644)?;
+    state.pending = Some(slot);
+    state.last_error = None;
+    save_state(fs, &state)?;
+    Ok((slot, image))
+}
+
+/// Switch back to the other slot at the next boot
+pub fn rollback(fs: &mut MemoryFs) -> io::Result<Slot> {
+    let mut state = load_state(fs);
+    let slot = state.active.other();
+    read_slot(fs, slot)?;
+    state.pending = Some(slot);
+    save_state(fs, &state)?;
+    Ok(slot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::read_to_string;
+
+    fn image(version: &str) -> SystemImage {
+        let mut image = SystemImage::new(version);
+        image.add("bin/ls", "#!builtin ls\n");
+        image.add("etc/motd", format!("axeberg {}\n", version));
+        image
+    }
+
+    #[test]
+    fn test_image_round_trip() {
+        let image = image("1.0.0");
+        let bytes = image.to_bytes().unwrap();
+        assert_eq!(SystemImage::from_bytes(&bytes).unwrap(), image);
+
+        let tampered = String::from_utf8(bytes).unwrap().replace("1.0.0", "6.6.6");
+        assert!(SystemImage::from_bytes(tampered.as_bytes()).is_err());
+
+        let mut escape = SystemImage::new("1.0.0");
+        escape.add("../etc/passwd", "x");
+        assert!(SystemImage::from_bytes(&escape.to_bytes().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_update_and_rollback() {
+        let mut fs = MemoryFs::new();
+        let factory = image("1.0.0");
+
+        let boot1 = boot(&mut fs, &factory).unwrap();
+        assert_eq!(boot1.slot, Slot::A);
+        assert!(boot1.factory);
+        assert_eq!(fs.metadata("/system/bin/ls").unwrap().mode, 0o755);
+        assert_eq!(fs.metadata("/system/etc").unwrap().uid, 0);
+
+        let (slot, _) = stage(&mut fs, &image("2.0.0").to_bytes().unwrap()).unwrap();
+        assert_eq!(slot, Slot::B);
+        let boot2 = boot(&mut fs, &factory).unwrap();
+        assert!(boot2.switched);
+        assert_eq!(boot2.version, "2.0.0");
+        assert_eq!(
+            read_to_string(&mut fs, "/system/etc/motd").unwrap(),
+            "axeberg 2.0.0\n"
+        );
+
+        // Booting again without confirming rolls back to the factory slot
+        let boot3 = boot(&mut fs, &factory).unwrap();
+        assert_eq!(boot3.slot, Slot::A);
+        assert!(boot3.rolled_back.is_some());
+        assert_eq!(boot3.version, "1.0.0");
+
+        // A confirmed update sticks
+        stage(&mut fs, &image("2.0.1").to_bytes().unwrap()).unwrap();
+        boot(&mut fs, &factory).unwrap();
+        assert!(mark_good(&mut fs).unwrap());
+        let boot4 = boot(&mut fs, &factory).unwrap();
+        assert_eq!((boot4.slot, boot4.version.as_str()), (Slot::B, "2.0.1"));
+        assert!(boot4.rolled_back.is_none());
+    }
+
+    #[test]
+    fn test_corrupt_pending_slot() {
+        let mut fs = MemoryFs::new();
+        let factory = image("1.0.0");
+        boot(&mut fs, &factory).unwrap();
+        stage(&mut fs, &image("2.0.0").to_bytes().unwrap()).unwrap();
+        write_string(&mut fs, &Slot::B.path(), "{\"garbage\":true}").unwrap();
+
+        let boot = boot(&mut fs, &factory).unwrap();
+        assert_eq!(boot.slot, Slot::A);
+        assert!(!boot.switched);
+        assert!(boot.rolled_back.is_some());
+        assert!(load_state(&mut fs).last_error.is_some());
+    }
+}
@@ -69,6 +69,8 @@ pub struct TraceEvent {
     pub detail: Option<String>,
     /// Associated process ID (if any)
     pub pid: Option<u32>,
+    /// Associated executor task ID (if any)
+    pub task: Option<u64>,
     /// Duration in milliseconds (for span events)
     pub duration: Option<f64>,
 }
@@ -82,6 +84,7 @@ impl TraceEvent {
             name: name.into(),
             detail: None,
             pid: None,
+            task: None,
             duration: None,
         }
     }
@@ -99,6 +102,7 @@ impl TraceEvent {
             name: name.into(),
             detail: Some(detail.into()),
             pid: None,
+            task: None,
             duration: None,
         }
     }
@@ -109,6 +113,12 @@ impl TraceEvent {
         self
     }
 
+    /// Add executor task ID
+    pub fn with_task(mut self, task: u64) -> Self {
+        self.task = Some(task);
+        self
+    }
+
     /// Add duration
     pub fn with_duration(mut self, duration: f64) -> Self {
         self.duration = Some(duration);
@@ -356,6 +366,8 @@ pub struct Tracer {
     pub kernel: KernelStats,
     /// Start time for uptime calculation
     start_time: f64,
+    /// High-resolution clock for span durations (None = kernel time only)
+    clock: Option<fn() -> f64>,
 }
 
 impl Tracer {
@@ -369,6 +381,7 @@ impl Tracer {
             scheduler: SchedulerStats::new(),
             kernel: KernelStats::new(),
             start_time: 0.0,
+            clock: None,
         }
     }
 
@@ -377,6 +390,21 @@ impl Tracer {
         self.start_time = time;
     }
 
+    /// Set the clock used to time spans (e.g. `performance.now`)
+    pub fn set_clock(&mut self, clock: Option<fn() -> f64>) {
+        self.clock = clock;
+    }
+
+    /// Current time from the span clock, or `fallback` if none is set
+    pub fn now_or(&self, fallback: f64) -> f64 {
+        self.clock.map_or(fallback, |clock| clock())
+    }
+
+    /// Check if events of a category would be recorded
+    pub fn wants(&self, category: TraceCategory) -> bool {
+        self.should_trace(category)
+    }
+
     /// Get uptime
     pub fn uptime(&self, now: f64) -> f64 {
         now - self.start_time
@@ -3,8 +3,33 @@
 //! Provides terminal settings (termios-like) and job control.
 //! In this WASM environment, we simulate terminal behavior
 //! for the virtual console.
+//!
+//! Pseudo-terminals are allocated with [`Kernel::sys_openpty`]: each gets
+//! a `pts/N` entry here for its settings and a `/dev/pts/N` node in devfs
+//! for its slave end. There is a single physical console, so every slave
+//! reads and writes it.
+//!
+//! The screen is shared by [`VT_COUNT`] virtual consoles, `tty1` to
+//! `tty4`, switched with Alt+F1..F4 or `chvt`. Each has its own shell
+//! session, foreground process group and [`VirtualConsole`] scrollback;
+//! only the active one is drawn and gets the keyboard.
+//!
+//! Programs that want the mouse turn on xterm mouse reporting by writing
+//! the mode sequences to their terminal; [`MouseTracking`] follows them and
+//! encodes events, which then arrive as input.
+
+use super::devfs::{DeviceInfo, DeviceOps};
+use super::syscall::{IoctlRequest, IoctlResult, Kernel, SyscallError, SyscallResult, WinSize};
+use std::collections::{HashMap, VecDeque};
+
+/// Number of virtual consoles
+pub const VT_COUNT: usize = 4;
+
+/// Lines of output each virtual console keeps to redraw it with
+pub const VT_SCROLLBACK: usize = 1000;
 
-use std::collections::HashMap;
+/// Escape sequence that clears the screen
+const CLEAR_SCREEN: &str = "\x1b[2J";
 
 /// Terminal input modes (c_iflag)
 #[derive(Debug, Clone, Copy, Default)]
@@ -264,6 +289,8 @@ pub struct Tty {
     pub rows: u16,
     /// Number of columns
     pub cols: u16,
+    /// Mouse events the program in front asked for
+    pub mouse: MouseTracking,
 }
 
 impl Tty {
@@ -275,6 +302,7 @@ impl Tty {
             session: None,
             rows: 24,
             cols: 80,
+            mouse: MouseTracking::default(),
         }
     }
 
@@ -310,12 +338,220 @@ impl Tty {
     }
 }
 
+/// Which mouse events a program asked its terminal to report
+///
+/// Set with DEC private modes in the program's output: `CSI ? 1000 h`
+/// reports presses, releases and the wheel, 1002 adds motion while a
+/// button is held, and 1003 adds all motion. 1006 picks the SGR encoding,
+/// `CSI < b ; x ; y M` (`m` on release), over the legacy `CSI M` one whose
+/// coordinates stop at 223. `l` turns a mode back off.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MouseTracking {
+    /// Events being reported
+    pub mode: MouseMode,
+    /// Whether reports use the SGR encoding
+    pub sgr: bool,
+}
+
+/// Mouse reporting level, from DEC private modes 1000, 1002 and 1003
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MouseMode {
+    /// No reports: the terminal keeps the mouse for selection
+    #[default]
+    Off,
+    /// Presses, releases and the wheel
+    Click,
+    /// Clicks plus motion with a button held
+    Drag,
+    /// Clicks plus all motion
+    Motion,
+}
+
+/// Mouse button, numbered as in the report encoding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseButton {
+    Left = 0,
+    Middle = 1,
+    Right = 2,
+}
+
+/// What happened to the mouse
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseAction {
+    Press(MouseButton),
+    Release(MouseButton),
+    /// Motion with a button held
+    Drag(MouseButton),
+    /// Motion with no button held
+    Move,
+    ScrollUp,
+    ScrollDown,
+}
+
+/// A mouse event over a terminal, at a 1-based cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MouseEvent {
+    pub action: MouseAction,
+    pub col: u16,
+    pub row: u16,
+    pub shift: bool,
+    pub alt: bool,
+    pub ctrl: bool,
+}
+
+impl MouseEvent {
+    pub fn new(action: MouseAction, col: u16, row: u16) -> Self {
+        Self {
+            action,
+            col,
+            row,
+            shift: false,
+            alt: false,
+            ctrl: false,
+        }
+    }
+}
+
+impl MouseTracking {
+    /// Whether any events are reported
+    pub fn is_enabled(&self) -> bool {
+        self.mode != MouseMode::Off
+    }
+
+    /// Follow the mode changes in output written to the terminal
+    pub fn observe(&mut self, output: &[u8]) {
+        let mut rest = output;
+        while let Some(start) = rest.windows(3).position(|w| w == b"\x1b[?") {
+            let body = &rest[start + 3..];
+            let len = body
+                .iter()
+                .position(|b| !b.is_ascii_digit() && *b != b';')
+                .unwrap_or(body.len());
+            let on = match body.get(len) {
+                Some(b'h') => true,
+                Some(b'l') => false,
+                _ => {
+                    rest = body;
+                    continue;
+                }
+            };
+            for param in body[..len].split(|b| *b == b';') {
+                self.set(param, on);
+            }
+            rest = &body[len + 1..];
+        }
+    }
+
+    fn set(&mut self, param: &[u8], on: bool) {
+        let mode = match param {
+            b"1000" => MouseMode::Click,
+            b"1002" => MouseMode::Drag,
+            b"1003" => MouseMode::Motion,
+            b"1006" => {
+                self.sgr = on;
+                return;
+            }
+            _ => return,
+        };
+        if on {
+            self.mode = mode;
+        } else if self.mode == mode {
+            self.mode = MouseMode::Off;
+        }
+    }
+
+    /// The report for `event`, or `None` if it isn't being reported or
+    /// can't be encoded
+    pub fn encode(&self, event: &MouseEvent) -> Option<Vec<u8>> {
+        let (code, release) = match event.action {
+            MouseAction::Press(button) => (button as u8, false),
+            MouseAction::Release(button) => (button as u8, true),
+            MouseAction::ScrollUp => (64, false),
+            MouseAction::ScrollDown => (65, false),
+            MouseAction::Drag(button) if self.mode != MouseMode::Click => {
+                (button as u8 + 32, false)
+            }
+            MouseAction::Move if self.mode == MouseMode::Motion => (35, false),
+            MouseAction::Drag(_) | MouseAction::Move => return None,
+        };
+        if !self.is_enabled() || event.col == 0 || event.row == 0 {
+            return None;
+        }
+        let code = code
+            + if event.shift { 4 } else { 0 }
+            + if event.alt { 8 } else { 0 }
+            + if event.ctrl { 16 } else { 0 };
+        if self.sgr {
+            let end = if release { 'm' } else { 'M' };
+            return Some(format!("\x1b[<{};{};{}{}", code, event.col, event.row, end).into_bytes());
+        }
+        // The legacy encoding can't say which button was released
+        let code = if release { (code & !3) | 3 } else { code };
+        let col = u8::try_from(event.col.saturating_add(32)).ok()?;
+        let row = u8::try_from(event.row.saturating_add(32)).ok()?;
+        Some(vec![0x1b, b'[', b'M', code + 32, col, row])
+    }
+}
+
+/// One of the virtual consoles sharing the screen
+#[derive(Debug, Clone)]
+pub struct VirtualConsole {
+    /// Console number, 1 to [`VT_COUNT`]
+    pub number: usize,
+    /// Shell running on it, once it has been switched to
+    pub shell: Option<u32>,
+    /// What was last written to it, oldest first
+    scrollback: VecDeque<String>,
+}
+
+impl VirtualConsole {
+    fn new(number: usize) -> Self {
+        Self {
+            number,
+            shell: None,
+            scrollback: VecDeque::new(),
+        }
+    }
+
+    /// TTY name, e.g. `tty2`
+    pub fn tty(&self) -> String {
+        format!("tty{}", self.number)
+    }
+
+    /// Lines to redraw the console with
+    pub fn scrollback(&self) -> &VecDeque<String> {
+        &self.scrollback
+    }
+
+    /// Remember output written to the console, one entry per line; a
+    /// clear screen (`ESC [2J`) forgets what came before it
+    pub fn record(&mut self, text: &str) {
+        let text = match text.rfind(CLEAR_SCREEN) {
+            Some(at) => {
+                self.scrollback.clear();
+                text[at + CLEAR_SCREEN.len()..].trim_start_matches("\x1b[H")
+            }
+            None => text,
+        };
+        for line in text.lines() {
+            if self.scrollback.len() == VT_SCROLLBACK {
+                self.scrollback.pop_front();
+            }
+            self.scrollback.push_back(line.to_string());
+        }
+    }
+}
+
 /// TTY device manager
 pub struct TtyManager {
     /// Active TTY devices
     ttys: HashMap<String, Tty>,
     /// Current (controlling) TTY name
     current: Option<String>,
+    /// Virtual consoles, `tty1` first
+    vts: Vec<VirtualConsole>,
+    /// Number of the console on screen
+    active_vt: usize,
 }
 
 impl TtyManager {
@@ -323,11 +559,15 @@ impl TtyManager {
         let mut mgr = Self {
             ttys: HashMap::new(),
             current: None,
+            vts: (1..=VT_COUNT).map(VirtualConsole::new).collect(),
+            active_vt: 1,
         };
 
         // Create default console TTY
         mgr.create_tty("console");
-        mgr.create_tty("tty1");
+        for n in 1..=VT_COUNT {
+            mgr.create_tty(&format!("tty{}", n));
+        }
         mgr.current = Some("console".to_string());
 
         mgr
@@ -378,6 +618,51 @@ impl TtyManager {
         self.ttys.keys().map(|s| s.as_str()).collect()
     }
 
+    /// Create the pseudo-terminal `pts/N` with the lowest free N
+    pub fn open_pty(&mut self) -> String {
+        let name = (0..)
+            .map(|n| format!("pts/{}", n))
+            .find(|name| !self.ttys.contains_key(name))
+            .unwrap_or_default();
+        self.ttys.insert(name.clone(), Tty::new(&name));
+        name
+    }
+
+    /// Remove a pseudo-terminal; returns false if `name` isn't one
+    pub fn close_pty(&mut self, name: &str) -> bool {
+        if !name.starts_with("pts/") || self.ttys.remove(name).is_none() {
+            return false;
+        }
+        if self.current.as_deref() == Some(name) {
+            self.current = Some("console".to_string());
+        }
+        true
+    }
+
+    /// Number of the virtual console on screen
+    pub fn active_vt(&self) -> usize {
+        self.active_vt
+    }
+
+    /// Virtual console `n`, counting from 1
+    pub fn vt(&self, n: usize) -> Option<&VirtualConsole> {
+        self.vts.get(n.checked_sub(1)?)
+    }
+
+    /// Virtual console `n`, mutably
+    pub fn vt_mut(&mut self, n: usize) -> Option<&mut VirtualConsole> {
+        self.vts.get_mut(n.checked_sub(1)?)
+    }
+
+    /// Put virtual console `n` on screen; false if there is none
+    pub fn set_active_vt(&mut self, n: usize) -> bool {
+        if self.vt(n).is_none() {
+            return false;
+        }
+        self.active_vt = n;
+        true
+    }
+
     /// Get termios for a TTY
     pub fn tcgetattr(&self, name: &str) -> Option<Termios> {
         self.ttys.get(name).map(|t| t.termios.clone())
@@ -400,6 +685,66 @@ impl Default for TtyManager {
     }
 }
 
+/// Slave end of a pseudo-terminal, at /dev/pts/N
+pub struct PtySlave {
+    /// TTY name, `pts/N`
+    pub name: String,
+}
+
+impl PtySlave {
+    fn index(&self) -> u32 {
+        self.name
+            .strip_prefix("pts/")
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(0)
+    }
+}
+
+impl DeviceOps for PtySlave {
+    fn info(&self) -> DeviceInfo {
+        DeviceInfo::char(136, self.index(), 0o620)
+    }
+
+    fn is_tty(&self) -> bool {
+        true
+    }
+
+    fn read(&self, kernel: &mut Kernel, _offset: u64, buf: &mut [u8]) -> SyscallResult<usize> {
+        let console = kernel.console_handle();
+        let obj = kernel.object_mut(console).ok_or(SyscallError::BadFd)?;
+        Ok(obj.read(buf)?)
+    }
+
+    fn write(&self, kernel: &mut Kernel, _offset: u64, buf: &[u8]) -> SyscallResult<usize> {
+        let console = kernel.console_handle();
+        let obj = kernel.object_mut(console).ok_or(SyscallError::BadFd)?;
+        let n = obj.write(buf)?;
+        if let Some(tty) = kernel.ttys_mut().get_tty_mut(&self.name) {
+            tty.mouse.observe(buf);
+        }
+        Ok(n)
+    }
+
+    fn ioctl(&self, kernel: &mut Kernel, request: IoctlRequest) -> SyscallResult<IoctlResult> {
+        let tty = kernel
+            .ttys_mut()
+            .get_tty_mut(&self.name)
+            .ok_or(SyscallError::BadFd)?;
+        match request {
+            IoctlRequest::GetWinSize => {
+                let (rows, cols) = tty.get_winsize();
+                Ok(IoctlResult::WinSize(WinSize {
+                    rows,
+                    cols,
+                    xpixel: 0,
+                    ypixel: 0,
+                }))
+            }
+            _ => Err(SyscallError::InvalidArgument),
+        }
+    }
+}
+
 /// Parse stty-style setting string
 pub fn parse_stty_setting(termios: &mut Termios, setting: &str) -> Result<(), String> {
     let (negate, setting) = if let Some(s) = setting.strip_prefix('-') {
@@ -595,6 +940,60 @@ mod tests {
         assert!(!termios.lflag.icanon);
     }
 
+    #[test]
+    fn test_pty_allocation() {
+        let mut mgr = TtyManager::new();
+        assert_eq!(mgr.open_pty(), "pts/0");
+        assert_eq!(mgr.open_pty(), "pts/1");
+        assert!(mgr.set_current("pts/1"));
+        assert!(mgr.close_pty("pts/0"));
+        assert!(mgr.close_pty("pts/1"));
+        assert_eq!(mgr.current_tty().unwrap().name, "console");
+        assert!(!mgr.close_pty("pts/1"));
+        assert!(!mgr.close_pty("console"));
+        // Freed numbers are reused
+        assert_eq!(mgr.open_pty(), "pts/0");
+    }
+
+    #[test]
+    fn test_mouse_tracking() {
+        let press = MouseEvent::new(MouseAction::Press(MouseButton::Left), 10, 5);
+        let mut mouse = MouseTracking::default();
+        assert_eq!(mouse.encode(&press), None);
+
+        // Legacy encoding: everything offset by 32, releases as button 3
+        mouse.observe(b"\x1b[?1000h");
+        assert_eq!(mouse.mode, MouseMode::Click);
+        assert_eq!(mouse.encode(&press), Some(b"\x1b[M *%".to_vec()));
+        let release = MouseEvent::new(MouseAction::Release(MouseButton::Right), 1, 1);
+        assert_eq!(mouse.encode(&release), Some(b"\x1b[M#!!".to_vec()));
+        let far = MouseEvent::new(MouseAction::Press(MouseButton::Left), 300, 1);
+        assert_eq!(mouse.encode(&far), None);
+        let drag = MouseEvent::new(MouseAction::Drag(MouseButton::Left), 11, 5);
+        assert_eq!(mouse.encode(&drag), None);
+
+        // SGR encoding, with drags and modifiers
+        mouse.observe(b"text\x1b[?1002;1006hmore");
+        assert!(mouse.sgr);
+        assert_eq!(mouse.encode(&far), Some(b"\x1b[<0;300;1M".to_vec()));
+        assert_eq!(mouse.encode(&release), Some(b"\x1b[<2;1;1m".to_vec()));
+        let mut wheel = MouseEvent::new(MouseAction::ScrollDown, 2, 3);
+        wheel.ctrl = true;
+        assert_eq!(mouse.encode(&wheel), Some(b"\x1b[<81;2;3M".to_vec()));
+        assert_eq!(mouse.encode(&drag), Some(b"\x1b[<32;11;5M".to_vec()));
+        let moved = MouseEvent::new(MouseAction::Move, 1, 1);
+        assert_eq!(mouse.encode(&moved), None);
+        mouse.observe(b"\x1b[?1003h");
+        assert_eq!(mouse.encode(&moved), Some(b"\x1b[<35;1;1M".to_vec()));
+
+        // Turning off another mode leaves the current one; other private
+        // modes are ignored
+        mouse.observe(b"\x1b[?1000l\x1b[?25l");
+        assert_eq!(mouse.mode, MouseMode::Motion);
+        mouse.observe(b"\x1b[?1003l\x1b[?1006l");
+        assert_eq!(mouse, MouseTracking::default());
+    }
+
     #[test]
     fn test_winsize() {
         let mut tty = Tty::new("tty1");
@@ -3,6 +3,7 @@
 //! Provides local IPC using the filesystem namespace (AF_UNIX/AF_LOCAL).
 //! Supports both stream (connection-oriented) and datagram (connectionless) sockets.
 
+use super::poll::PollEvents;
 use std::collections::{HashMap, VecDeque};
 
 /// Unix domain socket types
@@ -504,6 +505,35 @@ impl UnixSocketManager {
             .is_some_and(|s| s.has_pending_connections())
     }
 
+    /// What the socket could do now without blocking
+    ///
+    /// A listening socket is readable with a connection to accept. A
+    /// connected stream socket whose peer has closed reports a hangup.
+    pub fn poll_ready(&self, id: SocketId) -> PollEvents {
+        let Some(socket) = self.sockets.get(&id) else {
+            return PollEvents {
+                invalid: true,
+                ..PollEvents::NONE
+            };
+        };
+        let peer = socket.peer_socket.and_then(|peer| self.sockets.get(&peer));
+        let hangup = socket.state == SocketState::Closed
+            || (socket.state == SocketState::Connected && peer.is_none());
+        let writable = match socket.socket_type {
+            SocketType::Datagram => true,
+            SocketType::Stream => {
+                socket.state == SocketState::Connected
+                    && peer.is_some_and(|p| p.recv_buffer_len() < p.buffer_size)
+            }
+        };
+        PollEvents {
+            readable: socket.has_data() || socket.has_pending_connections() || hangup,
+            writable,
+            hangup,
+            ..PollEvents::NONE
+        }
+    }
+
     /// Get socket state
     pub fn state(&self, id: SocketId) -> Option<SocketState> {
         self.sockets.get(&id).map(|s| s.state)
@@ -15,6 +15,9 @@
 
 use std::collections::HashMap;
 
+use super::syscall::generate_random_bytes;
+use crate::vfs::{Acl, AclTag};
+
 /// User identifier
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub struct Uid(pub u32);
@@ -156,7 +159,13 @@ impl FileMode {
         });
         s.push(if self.other_read() { 'r' } else { '-' });
         s.push(if self.other_write() { 'w' } else { '-' });
-        s.push(if self.other_exec() { 'x' } else { '-' });
+        s.push(if self.is_sticky() {
+            if self.other_exec() { 't' } else { 'T' }
+        } else if self.other_exec() {
+            'x'
+        } else {
+            '-'
+        });
         s
     }
 
@@ -164,6 +173,72 @@ impl FileMode {
     pub fn from_octal_str(s: &str) -> Option<Self> {
         u16::from_str_radix(s, 8).ok().map(FileMode::new)
     }
+
+    /// Apply a symbolic mode such as `u+rwx,g-w,o=r`
+    ///
+    /// Each clause names the classes it changes (`u`, `g`, `o`, `a`),
+    /// then one or more operators (`+`, `-`, `=`), each followed by the
+    /// bits to add, remove or set: any of `r`, `w`, `x`, `X` (execute,
+    /// for directories and files someone can already execute), `s`
+    /// (setuid/setgid) and `t` (sticky), or one class to copy from. A
+    /// clause naming no class changes all of them, except the bits set in
+    /// `umask`. `None` if `spec` isn't a symbolic mode.
+    pub fn apply_symbolic(self, spec: &str, is_dir: bool, umask: u16) -> Option<Self> {
+        const OPS: [char; 3] = ['+', '-', '='];
+        let mut mode = self.0;
+        for clause in spec.split(',') {
+            let (who, mut ops) = clause.split_at(clause.find(OPS)?);
+            let mut classes = 0;
+            for c in who.chars() {
+                classes |= match c {
+                    'u' => Self::S_ISUID | 0o700,
+                    'g' => Self::S_ISGID | 0o070,
+                    'o' => Self::S_ISVTX | 0o007,
+                    'a' => 0o7777,
+                    _ => return None,
+                };
+            }
+            let (classes, allowed) = match classes {
+                0 => (0o7777, !umask),
+                classes => (classes, 0o7777),
+            };
+            while let Some(op) = ops.chars().next() {
+                let body = &ops[1..];
+                let (perms, rest) = body.split_at(body.find(OPS).unwrap_or(body.len()));
+                // A class's rwx bits, repeated for every class
+                let copy = |bits: u16| (bits & 0o7) * 0o111;
+                let bits = match perms {
+                    "u" => copy(mode >> 6),
+                    "g" => copy(mode >> 3),
+                    "o" => copy(mode),
+                    _ => {
+                        let mut bits = 0;
+                        for c in perms.chars() {
+                            bits |= match c {
+                                'r' => 0o444,
+                                'w' => 0o222,
+                                'x' => 0o111,
+                                'X' if is_dir || mode & 0o111 != 0 => 0o111,
+                                'X' => 0,
+                                's' => Self::S_ISUID | Self::S_ISGID,
+                                't' => Self::S_ISVTX,
+                                _ => return None,
+                            };
+                        }
+                        bits
+                    }
+                } & classes
+                    & allowed;
+                mode = match op {
+                    '+' => mode | bits,
+                    '-' => mode & !bits,
+                    _ => (mode & !classes) | bits,
+                };
+                ops = rest;
+            }
+        }
+        Some(Self(mode))
+    }
 }
 
 impl std::fmt::Display for FileMode {
@@ -774,6 +849,17 @@ impl Group {
     }
 }
 
+/// Changes to an account, as `usermod` makes them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UserChange {
+    /// New login shell
+    pub shell: Option<String>,
+    /// New home directory
+    pub home: Option<String>,
+    /// Supplementary groups to add the user to, by name
+    pub add_groups: Vec<String>,
+}
+
 /// User and group database
 #[derive(Debug, Clone)]
 pub struct UserDb {
@@ -835,6 +921,10 @@ impl UserDb {
         db.create_system_user("nobody", Uid(65534), Gid(65534), "/nonexistent");
         db.create_system_group("nogroup", Gid(65534));
 
+        // Create guest account, kept to its home by the restricted shell
+        db.create_system_group("guest", Gid(999));
+        db.create_system_user("guest", Uid(999), Gid(999), "/home/guest");
+
         db
     }
 
@@ -849,15 +939,24 @@ impl UserDb {
             // 2. Require password setup during installation
             // For this educational OS, we allow passwordless root initially
             // Use `passwd root` to set a password
+        } else if name == "guest" {
+            user.shell = "/bin/rsh".to_string();
         }
         self.users.insert(uid, user);
         self.users_by_name.insert(name.to_string(), uid);
+        // New users come after it, as after parsing /etc/passwd
+        if uid.0 >= 1000 && uid.0 < 65534 && uid.0 >= self.next_uid {
+            self.next_uid = uid.0 + 1;
+        }
     }
 
     fn create_system_group(&mut self, name: &str, gid: Gid) {
         let group = Group::new(name, gid);
         self.groups.insert(gid, group);
         self.groups_by_name.insert(name.to_string(), gid);
+        if gid.0 >= 1000 && gid.0 < 65534 && gid.0 >= self.next_gid {
+            self.next_gid = gid.0 + 1;
+        }
     }
 
     // ========== /etc/passwd FORMAT ==========
@@ -1050,6 +1149,71 @@ impl UserDb {
         Ok(gid)
     }
 
+    /// Remove a user, and take them out of every group they are a member of
+    ///
+    /// The group of the same name that [`UserDb::add_user`] made for them
+    /// goes too, unless it is someone else's primary group or has other
+    /// members.
+    pub fn remove_user(&mut self, name: &str) -> Result<User, &'static str> {
+        let uid = *self.users_by_name.get(name).ok_or("No such user")?;
+        if uid == Uid::ROOT {
+            return Err("Cannot remove root");
+        }
+        self.users_by_name.remove(name);
+        let user = self.users.remove(&uid).ok_or("No such user")?;
+        for group in self.groups.values_mut() {
+            group.remove_member(name);
+        }
+
+        let own_group = self
+            .get_group(user.gid)
+            .is_some_and(|g| g.name == user.name && g.members.is_empty());
+        if own_group && !self.users.values().any(|u| u.gid == user.gid) {
+            self.groups.remove(&user.gid);
+            self.groups_by_name.remove(name);
+        }
+        Ok(user)
+    }
+
+    /// Remove a group; a user's primary group can't be removed
+    pub fn remove_group(&mut self, name: &str) -> Result<Group, &'static str> {
+        let gid = *self.groups_by_name.get(name).ok_or("No such group")?;
+        if self.users.values().any(|u| u.gid == gid) {
+            return Err("Group is a user's primary group");
+        }
+        self.groups_by_name.remove(name);
+        self.groups.remove(&gid).ok_or("No such group")
+    }
+
+    /// Change a user's shell, home directory or groups
+    ///
+    /// Nothing changes unless every group named exists.
+    pub fn modify_user(&mut self, name: &str, change: &UserChange) -> Result<(), &'static str> {
+        if !self.users_by_name.contains_key(name) {
+            return Err("No such user");
+        }
+        let gids = change
+            .add_groups
+            .iter()
+            .map(|group| self.groups_by_name.get(group).copied())
+            .collect::<Option<Vec<Gid>>>()
+            .ok_or("No such group")?;
+
+        for gid in gids {
+            if let Some(group) = self.groups.get_mut(&gid) {
+                group.add_member(name);
+            }
+        }
+        let user = self.get_user_by_name_mut(name).ok_or("No such user")?;
+        if let Some(shell) = &change.shell {
+            user.shell = shell.clone();
+        }
+        if let Some(home) = &change.home {
+            user.home = home.clone();
+        }
+        Ok(())
+    }
+
     /// Look up user by UID
     pub fn get_user(&self, uid: Uid) -> Option<&User> {
         self.users.get(&uid)
@@ -1140,18 +1304,7 @@ const SALT_LENGTH: usize = 16;
 /// Generate cryptographically random bytes for salt
 fn generate_salt() -> [u8; SALT_LENGTH] {
     let mut salt = [0u8; SALT_LENGTH];
-    // Use getrandom which works in both native and WASM environments
-    if getrandom::fill(&mut salt).is_err() {
-        // Fallback: use a timestamp-based seed (less secure, but better than nothing)
-        // This should rarely happen as getrandom supports WASM with wasm_js feature
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_nanos();
-        for (i, byte) in salt.iter_mut().enumerate() {
-            *byte = ((now >> (i * 8)) & 0xff) as u8;
-        }
-    }
+    salt.copy_from_slice(&generate_random_bytes(SALT_LENGTH));
     salt
 }
 
@@ -1312,6 +1465,68 @@ pub fn check_permission(
     (!want_read || r) && (!want_write || w) && (!want_exec || x)
 }
 
+/// Check access to a file that has an access control list, the way
+/// POSIX.1e does
+///
+/// The owner gets the owner bits and a user with their own entry gets that
+/// entry. Otherwise a member of the owning group or of a group with an
+/// entry gets access if any one of those (the group bits for the owning
+/// group) grants everything asked for. Everyone else gets the other bits.
+/// The list's mask caps every entry and the owning group's bits.
+#[allow(clippy::too_many_arguments)]
+pub fn check_acl_permission(
+    file_uid: Uid,
+    file_gid: Gid,
+    file_mode: FileMode,
+    acl: &Acl,
+    user_uid: Uid,
+    user_gid: Gid,
+    user_groups: &[Gid],
+    want_read: bool,
+    want_write: bool,
+    want_exec: bool,
+) -> bool {
+    if user_uid == Uid::ROOT || user_uid == file_uid {
+        return check_permission(
+            file_uid,
+            file_gid,
+            file_mode,
+            user_uid,
+            user_gid,
+            user_groups,
+            want_read,
+            want_write,
+            want_exec,
+        );
+    }
+
+    let want = (want_read as u8) << 2 | (want_write as u8) << 1 | want_exec as u8;
+    let grants = |perms: u8| perms & want == want;
+    if let Some(perms) = acl.get(AclTag::User(user_uid.0)) {
+        return grants(acl.effective(perms));
+    }
+
+    let in_group = |gid: Gid| user_gid == gid || user_groups.contains(&gid);
+    let mut matched = false;
+    if in_group(file_gid) {
+        matched = true;
+        if grants(acl.effective(((file_mode.0 >> 3) & 0o7) as u8)) {
+            return true;
+        }
+    }
+    for entry in acl.entries() {
+        if let AclTag::Group(gid) = entry.tag
+            && in_group(Gid(gid))
+        {
+            matched = true;
+            if grants(acl.effective(entry.perms)) {
+                return true;
+            }
+        }
+    }
+    !matched && grants((file_mode.0 & 0o7) as u8)
+}
+
 /// Check if a user can access a file with given permissions, considering capabilities
 ///
 /// This is an enhanced version of `check_permission` that also checks relevant
@@ -1400,15 +1615,57 @@ mod tests {
         assert_eq!(FileMode(0o4655).to_symbolic(), "rwSr-xr-x"); // setuid without exec
     }
 
+    #[test]
+    fn test_file_mode_sticky() {
+        assert_eq!(FileMode(0o1777).to_symbolic(), "rwxrwxrwt");
+        assert_eq!(FileMode(0o1776).to_symbolic(), "rwxrwxrwT"); // sticky without exec
+        assert_eq!(FileMode(0o2755).to_symbolic(), "rwxr-sr-x");
+    }
+
+    #[test]
+    fn test_file_mode_apply_symbolic() {
+        let apply = |mode: u16, spec: &str| {
+            FileMode(mode)
+                .apply_symbolic(spec, false, 0o022)
+                .map(|m| m.0)
+        };
+        assert_eq!(apply(0o644, "u+x"), Some(0o744));
+        assert_eq!(apply(0o777, "u+rwx,g-w,o=r"), Some(0o754));
+        assert_eq!(apply(0o644, "a+x"), Some(0o755));
+        assert_eq!(apply(0o600, "go=u"), Some(0o666));
+        assert_eq!(apply(0o750, "o=g-x"), Some(0o754));
+        assert_eq!(apply(0o755, "u+s,+t"), Some(0o5755));
+        assert_eq!(apply(0o6755, "ug-s"), Some(0o755));
+        assert_eq!(apply(0o644, "u="), Some(0o044));
+        // Without a class, the umask (022) keeps group and other write off
+        assert_eq!(apply(0o444, "+w"), Some(0o644));
+        assert_eq!(apply(0o666, "=rw"), Some(0o644));
+        // X only adds execute to directories and executables
+        assert_eq!(apply(0o644, "a+X"), Some(0o644));
+        assert_eq!(apply(0o744, "a+X"), Some(0o755));
+        assert_eq!(
+            FileMode(0o644).apply_symbolic("a+X", true, 0).map(|m| m.0),
+            Some(0o755)
+        );
+
+        for bad in ["", "u", "z+x", "u+q", "u+x,", "755"] {
+            assert_eq!(apply(0o644, bad), None, "{:?}", bad);
+        }
+    }
+
     #[test]
     fn test_user_db_default_users() {
-        let db = UserDb::new();
+        let mut db = UserDb::new();
 
         assert!(db.get_user_by_name("root").is_some());
         assert!(db.get_user_by_name("user").is_some());
 
         let root = db.get_user_by_name("root").unwrap();
         assert_eq!(root.uid, Uid::ROOT);
+
+        let guest = db.get_user_by_name("guest").unwrap();
+        assert_eq!(guest.shell, "/bin/rsh");
+        assert_eq!(db.add_user("alice", None), Ok(Uid(1001)));
     }
 
     #[test]
@@ -1420,6 +1677,91 @@ mod tests {
         assert_eq!(user.name, "testuser");
     }
 
+    #[test]
+    fn test_remove_user_and_group() {
+        let mut db = UserDb::new();
+        let uid = db.add_user("alice", None).unwrap();
+        let gid = db.get_user(uid).unwrap().gid;
+        db.add_group("devs").unwrap();
+        let change = UserChange {
+            add_groups: vec!["devs".to_string(), "wheel".to_string()],
+            ..UserChange::default()
+        };
+        db.modify_user("alice", &change).unwrap();
+        assert!(db.can_sudo("alice"));
+
+        // A user's primary group stays while they do
+        assert_eq!(
+            db.remove_group("alice").unwrap_err(),
+            "Group is a user's primary group"
+        );
+        let alice = db.remove_user("alice").unwrap();
+        assert_eq!(alice.uid, uid);
+        assert!(db.get_user_by_name("alice").is_none());
+        assert!(db.get_group(gid).is_none());
+        assert!(!db.can_sudo("alice"));
+        assert!(db.get_group_by_name("devs").unwrap().members.is_empty());
+
+        assert!(db.remove_group("devs").is_ok());
+        assert!(db.get_group_by_name("devs").is_none());
+        assert!(db.remove_group("devs").is_err());
+        assert_eq!(db.remove_user("root").unwrap_err(), "Cannot remove root");
+
+        // The group made for a user stays if others use it
+        let uid = db.add_user("bob", None).unwrap();
+        let gid = db.get_user(uid).unwrap().gid;
+        db.add_user("carol", Some(gid)).unwrap();
+        db.remove_user("bob").unwrap();
+        assert!(db.get_group(gid).is_some());
+    }
+
+    #[test]
+    fn test_modify_user() {
+        let mut db = UserDb::new();
+        let change = UserChange {
+            shell: Some("/bin/script".to_string()),
+            home: Some("/srv/user".to_string()),
+            add_groups: vec!["wheel".to_string(), "nonexistent".to_string()],
+        };
+        assert_eq!(db.modify_user("user", &change), Err("No such group"));
+        assert_eq!(db.get_user_by_name("user").unwrap().shell, "/bin/sh");
+
+        let change = UserChange {
+            add_groups: vec!["nogroup".to_string()],
+            ..change
+        };
+        db.modify_user("user", &change).unwrap();
+        let user = db.get_user_by_name("user").unwrap();
+        assert_eq!(
+            (user.shell.as_str(), user.home.as_str()),
+            ("/bin/script", "/srv/user")
+        );
+        assert!(db.get_user_groups("user").contains(&Gid(65534)));
+        assert!(db.modify_user("nobody-here", &change).is_err());
+    }
+
+    #[test]
+    fn test_passwd_group_round_trip() {
+        let mut db = UserDb::new();
+        let uid = db.add_user("alice", None).unwrap();
+        let change = UserChange {
+            shell: Some("/bin/script".to_string()),
+            add_groups: vec!["wheel".to_string()],
+            ..UserChange::default()
+        };
+        db.modify_user("alice", &change).unwrap();
+
+        let mut parsed = UserDb::empty();
+        parsed.parse_passwd(&db.to_passwd());
+        parsed.parse_group(&db.to_group());
+        assert_eq!(parsed.to_passwd(), db.to_passwd());
+        assert_eq!(parsed.to_group(), db.to_group());
+        assert_eq!(parsed.get_user(uid).unwrap().shell, "/bin/script");
+        assert!(parsed.can_sudo("alice"));
+        // New ids carry on after the parsed ones
+        assert_eq!(parsed.add_user("bob", None).unwrap(), Uid(uid.0 + 1));
+    }
+
     #[test]
     fn test_password() {
         let mut user = User::new("test", Uid(1), Gid(1));
@@ -1741,6 +2083,53 @@ mod tests {
         assert!(after_exec.permitted.is_empty());
     }
 
+    #[test]
+    fn test_acl_permission_check() {
+        // alice (1000) owns the file; others may read it
+        let check = |acl: &Acl, uid: u32, groups: &[Gid], write: bool| {
+            check_acl_permission(
+                Uid(1000),
+                Gid(1000),
+                FileMode(0o604),
+                acl,
+                Uid(uid),
+                Gid(uid),
+                groups,
+                true,
+                write,
+                false,
+            )
+        };
+        let mut acl = Acl::new();
+        acl.set(AclTag::User(1001), 0o6);
+        acl.set(AclTag::User(1002), 0);
+        acl.set(AclTag::Group(50), 0o4);
+        acl.set(AclTag::Group(60), 0);
+
+        assert!(check(&acl, 1000, &[], true));
+        assert!(check(&acl, 0, &[], true));
+        assert!(check(&acl, 1001, &[], true));
+        // A named user's entry wins over their groups and the other bits
+        assert!(!check(&acl, 1002, &[Gid(50)], false));
+        assert!(check(&acl, 1003, &[Gid(50)], false));
+        assert!(!check(&acl, 1003, &[Gid(50)], true));
+        // Any matching group entry may grant access...
+        assert!(check(&acl, 1003, &[Gid(60), Gid(50)], false));
+        // ...but a match that grants nothing shuts out the other bits
+        assert!(!check(&acl, 1003, &[Gid(60)], false));
+        assert!(!check(&acl, 1003, &[Gid(1000)], false));
+        assert!(check(&acl, 1003, &[], false));
+
+        // The mask caps named users, as after `chmod g-w`
+        acl.set_mask(0o4);
+        assert!(!check(&acl, 1001, &[], true));
+        assert!(check(&acl, 1001, &[], false));
+        acl.set_mask(0);
+        assert!(!check(&acl, 1001, &[], false));
+        assert!(!check(&acl, 1003, &[Gid(50)], false));
+        assert!(check(&acl, 1000, &[], true));
+    }
+
     #[test]
     fn test_permission_with_caps_dac_override() {
         let mode = FileMode(0o000); // No permissions
@@ -6,13 +6,14 @@
 //! - Task scheduler queue
 //! - Syscall activity monitor
 //! - Resource utilization graphs
+//! - Kernel object table, fd tables and mounts, with leak detection
 //!
 //! This module generates visualization data that can be rendered by the compositor
 //! or exported for external visualization tools.
 
 use super::executor::Priority;
 use super::memory::{MemoryStats, ProcessCowStats};
-use super::process::{Pid, ProcessState, RlimitResource};
+use super::process::{Fd, Handle, Pid, ProcessState, RlimitResource};
 use super::task::TaskId;
 use std::collections::HashMap;
 
@@ -198,7 +199,7 @@ impl ProcessTree {
     /// Get processes sorted by memory usage
     pub fn by_memory_usage(&self) -> Vec<&ProcessTreeNode> {
         let mut procs: Vec<_> = self.processes.values().collect();
-        procs.sort_by(|a, b| b.memory.cmp(&a.memory));
+        procs.sort_by_key(|p| std::cmp::Reverse(p.memory));
         procs
     }
 }
@@ -723,6 +724,164 @@ impl SyscallMonitor {
     }
 }
 
+// ============================================================================
+// Kernel Object Inspection
+// ============================================================================
+
+/// An entry in the kernel object table
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectView {
+    /// Object handle
+    pub handle: Handle,
+    /// Object type ("file", "pipe", ...)
+    pub kind: &'static str,
+    /// What the object refers to (path, buffered bytes, ...)
+    pub detail: String,
+    /// Reference count
+    pub refcount: usize,
+    /// File descriptors pointing at this object
+    pub owners: Vec<(Pid, Fd)>,
+}
+
+/// A process's file descriptor table
+#[derive(Debug, Clone, PartialEq)]
+pub struct FdTableView {
+    /// Process ID
+    pub pid: Pid,
+    /// Process name
+    pub name: String,
+    /// Open descriptors, sorted by fd
+    pub fds: Vec<(Fd, Handle)>,
+}
+
+/// A mount table entry
+#[derive(Debug, Clone, PartialEq)]
+pub struct MountView {
+    /// Device or source
+    pub source: String,
+    /// Mount point
+    pub target: String,
+    /// Filesystem type
+    pub fstype: String,
+    /// Mount options
+    pub options: String,
+}
+
+/// Kernel objects, fd tables and mounts at one point in time
+#[derive(Debug, Clone, Default)]
+pub struct ObjectSnapshot {
+    /// Timestamp when snapshot was taken
+    pub timestamp: f64,
+    /// Object table, sorted by handle
+    pub objects: Vec<ObjectView>,
+    /// Per-process fd tables, sorted by PID
+    pub fd_tables: Vec<FdTableView>,
+    /// Mounts, sorted by mount point
+    pub mounts: Vec<MountView>,
+}
+
+impl ObjectSnapshot {
+    /// Look up an object by handle
+    pub fn object(&self, handle: Handle) -> Option<&ObjectView> {
+        self.objects
+            .binary_search_by_key(&handle.0, |o| o.handle.0)
+            .ok()
+            .map(|i| &self.objects[i])
+    }
+
+    /// Compare against a later snapshot
+    pub fn diff(&self, later: &ObjectSnapshot) -> LeakReport {
+        let mut report = LeakReport {
+            from: self.timestamp,
+            to: later.timestamp,
+            ..LeakReport::default()
+        };
+
+        for obj in &later.objects {
+            match self.object(obj.handle) {
+                None if obj.owners.is_empty() => report.leaked.push(obj.clone()),
+                None => report.added.push(obj.clone()),
+                Some(before) if obj.refcount > before.refcount => {
+                    report.grown.push((obj.clone(), before.refcount));
+                }
+                Some(_) => {}
+            }
+        }
+        report.removed = self
+            .objects
+            .iter()
+            .filter(|o| later.object(o.handle).is_none())
+            .cloned()
+            .collect();
+
+        report
+    }
+}
+
+/// Differences between two object snapshots
+///
+/// Objects created in between that no file descriptor points at are reported
+/// as leaked: something still holds a reference nobody can close.
+#[derive(Debug, Clone, Default)]
+pub struct LeakReport {
+    /// Timestamp of the earlier snapshot
+    pub from: f64,
+    /// Timestamp of the later snapshot
+    pub to: f64,
+    /// New objects with no owning fd
+    pub leaked: Vec<ObjectView>,
+    /// New objects still owned by some fd
+    pub added: Vec<ObjectView>,
+    /// Objects whose reference count went up, with the earlier count
+    pub grown: Vec<(ObjectView, usize)>,
+    /// Objects freed in between
+    pub removed: Vec<ObjectView>,
+}
+
+impl LeakReport {
+    /// Check if nothing leaked
+    pub fn is_clean(&self) -> bool {
+        self.leaked.is_empty()
+    }
+
+    /// Render as a list of changes
+    pub fn render_ascii(&self) -> String {
+        let mut output = String::new();
+        output.push_str(&format!(
+            "=== Object Diff {:.1}ms → {:.1}ms ===\n",
+            self.from, self.to
+        ));
+
+        let line = |sign: &str, o: &ObjectView, note: &str| {
+            format!(
+                "{} #{:<4} {:9} refs {:<3} {}{}\n",
+                sign, o.handle.0, o.kind, o.refcount, o.detail, note
+            )
+        };
+        for obj in &self.leaked {
+            output.push_str(&line("!", obj, "  LEAK: no fds"));
+        }
+        for obj in &self.added {
+            output.push_str(&line("+", obj, ""));
+        }
+        for (obj, before) in &self.grown {
+            output.push_str(&line("^", obj, &format!("  (was {})", before)));
+        }
+        for obj in &self.removed {
+            output.push_str(&line("-", obj, ""));
+        }
+        if self.leaked.is_empty()
+            && self.added.is_empty()
+            && self.grown.is_empty()
+            && self.removed.is_empty()
+        {
+            output.push_str("  (no changes)\n");
+        }
+
+        output
+    }
+}
+
 // ============================================================================
 // Visualization Update/Snapshot
 // ============================================================================
@@ -978,4 +1137,55 @@ mod tests {
         assert!(freq.contains("read"));
         assert!(freq.contains("50"));
     }
+
+    fn object(
+        handle: u64,
+        kind: &'static str,
+        refcount: usize,
+        owners: &[(u32, u32)],
+    ) -> ObjectView {
+        ObjectView {
+            handle: Handle(handle),
+            kind,
+            detail: String::new(),
+            refcount,
+            owners: owners.iter().map(|&(p, f)| (Pid(p), Fd(f))).collect(),
+        }
+    }
+
+    #[test]
+    fn test_object_snapshot_diff() {
+        let before = ObjectSnapshot {
+            timestamp: 1.0,
+            objects: vec![
+                object(1, "console", 2, &[(1, 0), (1, 1)]),
+                object(2, "file", 1, &[(1, 3)]),
+                object(3, "pipe", 1, &[(1, 4)]),
+            ],
+            ..ObjectSnapshot::default()
+        };
+        let after = ObjectSnapshot {
+            timestamp: 2.0,
+            objects: vec![
+                object(1, "console", 3, &[(1, 0), (1, 1), (1, 2)]),
+                object(3, "pipe", 1, &[(1, 4)]),
+                object(4, "file", 1, &[]),
+                object(5, "pipe", 2, &[(1, 5), (1, 6)]),
+            ],
+            ..ObjectSnapshot::default()
+        };
+
+        let report = before.diff(&after);
+        assert!(!report.is_clean());
+        assert_eq!(report.leaked.len(), 1);
+        assert_eq!(report.leaked[0].handle, Handle(4));
+        assert_eq!(report.added[0].handle, Handle(5));
+        assert_eq!(report.grown[0].1, 2);
+        assert_eq!(report.removed[0].handle, Handle(2));
+
+        let text = report.render_ascii();
+        assert!(text.contains("! #4"));
+        assert!(text.contains("LEAK"));
+        assert!(after.diff(&after).render_ascii().contains("(no changes)"));
+    }
 }
@@ -2,9 +2,16 @@
 //!
 //! This module defines the stable interface between the kernel and WASM commands.
 
+use super::error::{WasmError, WasmResult};
+use std::collections::HashMap;
+
 /// ABI version number
 pub const ABI_VERSION: u32 = 1;
 
+/// Maximum combined size of the argument and environment blocks passed to
+/// `main`, like `ARG_MAX` on Unix
+pub const MAX_ARG_ENV_SIZE: usize = 64 * 1024;
+
 /// Required export names
 pub mod exports {
     /// The linear memory export name
@@ -13,6 +20,9 @@ pub mod exports {
     pub const MAIN: &str = "main";
     /// Optional: heap base for allocation
     pub const HEAP_BASE: &str = "__heap_base";
+
+    /// Every export name the kernel looks for
+    pub const ALL: &[&str] = &[MEMORY, MAIN, HEAP_BASE];
 }
 
 /// Import module namespace
@@ -23,8 +33,29 @@ pub mod syscalls {
     // File operations
     pub const OPEN: &str = "open";
     pub const CLOSE: &str = "close";
+    /// `read(fd, buf_ptr, len) -> bytes_read`
+    ///
+    /// File data is copied once, from where the VFS keeps it straight into
+    /// `buf_ptr..buf_ptr + len` in linear memory, with no copy in the
+    /// kernel or the runtime first. Only the bytes read are written; the
+    /// rest of the buffer is left as it was. A buffer that does not lie
+    /// wholly within linear memory is refused with `InvalidArgument`
+    /// before anything is read.
     pub const READ: &str = "read";
     pub const WRITE: &str = "write";
+    /// `pread(fd, buf_ptr, len, offset) -> bytes_read`
+    ///
+    /// Like [`READ`], from `offset` (an i64) rather than the descriptor's
+    /// position, which is left where it was. The standard streams can't
+    /// seek and are refused with `InvalidArgument`, as is a negative
+    /// offset.
+    pub const PREAD: &str = "pread";
+    /// `pwrite(fd, buf_ptr, len, offset) -> bytes_written`
+    ///
+    /// Like [`WRITE`], at `offset`, leaving the descriptor's position where
+    /// it was; writing past the end fills the gap with zeros. The
+    /// descriptor must have been opened for writing.
+    pub const PWRITE: &str = "pwrite";
     pub const STAT: &str = "stat";
 
     // Directory operations
@@ -38,6 +69,12 @@ pub mod syscalls {
     pub const EXIT: &str = "exit";
     pub const GETENV: &str = "getenv";
     pub const GETCWD: &str = "getcwd";
+
+    /// Every syscall import the kernel provides
+    pub const ALL: &[&str] = &[
+        OPEN, CLOSE, READ, WRITE, PREAD, PWRITE, STAT, MKDIR, READDIR, RMDIR, UNLINK, RENAME, EXIT,
+        GETENV, GETCWD,
+    ];
 }
 
 /// Standard file descriptors
@@ -247,6 +284,65 @@ impl ArgLayout {
     }
 }
 
+/// Environment entries as `NAME=value` strings, sorted by name
+pub fn env_entries(env: &HashMap<String, String>) -> Vec<String> {
+    let mut entries: Vec<String> = env.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+    entries.sort();
+    entries
+}
+
+/// Layout of the argument and environment blocks passed to `main`
+///
+/// The argv block comes first and the envp block directly after it; both
+/// use the [`ArgLayout`] format, with `NAME=value` strings for the
+/// environment.
+#[derive(Debug)]
+pub struct ExecLayout {
+    pub args: ArgLayout,
+    pub env: ArgLayout,
+}
+
+impl ExecLayout {
+    /// Calculate the layout, failing if the blocks together exceed
+    /// [`MAX_ARG_ENV_SIZE`]
+    pub fn new(args: &[&str], env: &[&str]) -> WasmResult<Self> {
+        let layout = Self {
+            args: ArgLayout::new(args),
+            env: ArgLayout::new(env),
+        };
+        let size = layout.total_size();
+        if size > MAX_ARG_ENV_SIZE {
+            return Err(WasmError::ArgumentsTooLarge {
+                size,
+                max: MAX_ARG_ENV_SIZE,
+            });
+        }
+        Ok(layout)
+    }
+
+    /// Total memory needed for both blocks
+    pub fn total_size(&self) -> usize {
+        self.args.total_size() + self.env.total_size()
+    }
+
+    /// Write both blocks to a memory buffer
+    ///
+    /// Returns the argv and envp pointers
+    pub fn write_to(
+        &self,
+        args: &[&str],
+        env: &[&str],
+        base_addr: u32,
+        buf: &mut [u8],
+    ) -> (u32, u32) {
+        let split = self.args.total_size();
+        let (arg_buf, env_buf) = buf.split_at_mut(split);
+        let argv = self.args.write_to(args, base_addr, arg_buf);
+        let envp = self.env.write_to(env, base_addr + split as u32, env_buf);
+        (argv, envp)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -354,4 +450,45 @@ mod tests {
         // Check argv pointer
         assert_eq!(argv_ptr, base_addr + strings_end as u32);
     }
+
+    #[test]
+    fn test_exec_layout() {
+        let args = &["env"];
+        let mut vars = HashMap::new();
+        vars.insert("PATH".to_string(), "/bin".to_string());
+        vars.insert("HOME".to_string(), "/home/user".to_string());
+        let entries = env_entries(&vars);
+        assert_eq!(entries, ["HOME=/home/user", "PATH=/bin"]);
+        let env: Vec<&str> = entries.iter().map(String::as_str).collect();
+
+        let layout = ExecLayout::new(args, &env).unwrap();
+        let base_addr = 2048u32;
+        let mut buf = vec![0u8; layout.total_size()];
+        let (argv, envp) = layout.write_to(args, &env, base_addr, &mut buf);
+        assert_eq!(argv, base_addr + 4);
+
+        // envp starts after the argv block; its first pointer is "HOME=..."
+        let env_base = base_addr + layout.args.total_size() as u32;
+        assert_eq!(envp, env_base + layout.env.strings_size as u32);
+        let at = (envp - base_addr) as usize;
+        let ptr0 = u32::from_le_bytes([buf[at], buf[at + 1], buf[at + 2], buf[at + 3]]);
+        assert_eq!(ptr0, env_base);
+        let start = (ptr0 - base_addr) as usize;
+        assert_eq!(&buf[start..start + 16], b"HOME=/home/user\0");
+        let end = at + 8;
+        assert_eq!(&buf[end..end + 4], &[0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_exec_layout_too_large() {
+        let big = "x".repeat(MAX_ARG_ENV_SIZE);
+        let err = ExecLayout::new(&["cat"], &[big.as_str()]).unwrap_err();
+        assert!(matches!(
+            err,
+            WasmError::ArgumentsTooLarge {
+                max: MAX_ARG_ENV_SIZE,
+                ..
+            }
+        ));
+    }
 }
@@ -9,8 +9,6 @@ use super::executor::WasmExecutor;
 #[cfg(target_arch = "wasm32")]
 use super::loader::ModuleValidator;
 use crate::kernel::syscall;
-#[cfg(target_arch = "wasm32")]
-use crate::kernel::users::{FileMode, Gid, Uid};
 use std::collections::HashMap;
 
 /// Default paths to search for WASM commands
@@ -117,28 +115,46 @@ impl WasmCommandRunner {
     /// Run a WASM command with arguments and stdin
     ///
     /// This is the main entry point for executing WASM commands.
-    /// Handles setuid/setgid bits: if the executable has these bits set,
-    /// the command runs with the file owner's effective uid/gid.
+    /// Handles setuid/setgid bits through [`syscall::exec_setid`]: if the
+    /// executable has these bits set, the command runs with the file
+    /// owner's effective uid/gid, and the kernel logs it. Commands on a
+    /// `noexec` mount don't run, and the bits are ignored on a `nosuid`
+    /// one.
     #[cfg(target_arch = "wasm32")]
     pub async fn run(
         &mut self,
         name: &str,
         args: &[String],
-        stdin: &str,
+        stdin: &[u8],
     ) -> WasmResult<CommandResult> {
         // Find the command
         let path = self.find_command(name).ok_or(WasmError::CommandNotFound {
             name: name.to_string(),
         })?;
 
-        // Check setuid/setgid bits and apply privilege changes
-        let (saved_euid, saved_egid) = self.apply_setuid_setgid(&path);
-
-        // Load the module
-        let module_bytes = self.load_module(&path)?;
+        // Take on the program's owner and group if it is setuid or setgid
+        let elevated = syscall::exec_setid(&path).map_err(|e| match e {
+            syscall::SyscallError::PermissionDenied => {
+                WasmError::PermissionDenied { path: path.clone() }
+            }
+            e => WasmError::IoError {
+                message: format!("{}: {}", path, e),
+            },
+        })?;
 
-        // Validate the module
-        ModuleValidator::validate(&module_bytes)?;
+        // Load and validate the module, dropping the ids again if it fails
+        let module_bytes = self
+            .load_module(&path)
+            .and_then(|bytes| ModuleValidator::validate(&bytes).map(|_| bytes));
+        let module_bytes = match module_bytes {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                if elevated {
+                    let _ = syscall::exec_restore();
+                }
+                return Err(e);
+            }
+        };
 
         // Prepare arguments (program name + args)
         let mut full_args: Vec<&str> = vec![name];
@@ -152,62 +168,14 @@ impl WasmCommandRunner {
         executor.set_cwd(&self.cwd);
 
         // Execute
-        let result = executor
-            .execute(&module_bytes, &full_args, stdin.as_bytes())
-            .await;
+        let result = executor.execute(&module_bytes, &full_args, stdin).await;
 
         // Restore original euid/egid after execution
-        self.restore_privileges(saved_euid, saved_egid);
-
-        result
-    }
-
-    /// Apply setuid/setgid bits from executable file
-    ///
-    /// If the executable has setuid bit set, changes effective UID to file owner.
-    /// If the executable has setgid bit set, changes effective GID to file group.
-    /// Returns the original (euid, egid) for later restoration.
-    #[cfg(target_arch = "wasm32")]
-    fn apply_setuid_setgid(&self, path: &str) -> (Option<Uid>, Option<Gid>) {
-        let mut saved_euid = None;
-        let mut saved_egid = None;
-
-        if let Ok(meta) = syscall::metadata(path) {
-            let mode = FileMode::new(meta.mode);
-
-            // Check setuid bit
-            if mode.is_setuid() {
-                // Save current euid
-                if let Ok(euid) = syscall::geteuid() {
-                    saved_euid = Some(euid);
-                }
-                // Set euid to file owner
-                let _ = syscall::seteuid(Uid(meta.uid));
-            }
-
-            // Check setgid bit
-            if mode.is_setgid() {
-                // Save current egid
-                if let Ok(egid) = syscall::getegid() {
-                    saved_egid = Some(egid);
-                }
-                // Set egid to file group
-                let _ = syscall::setegid(Gid(meta.gid));
-            }
+        if elevated {
+            let _ = syscall::exec_restore();
         }
 
-        (saved_euid, saved_egid)
-    }
-
-    /// Restore original effective uid/gid after setuid/setgid execution
-    #[cfg(target_arch = "wasm32")]
-    fn restore_privileges(&self, saved_euid: Option<Uid>, saved_egid: Option<Gid>) {
-        if let Some(euid) = saved_euid {
-            let _ = syscall::seteuid(euid);
-        }
-        if let Some(egid) = saved_egid {
-            let _ = syscall::setegid(egid);
-        }
+        result
     }
 
     /// Run a WASM command (synchronous wrapper for non-WASM targets)
@@ -216,7 +184,7 @@ impl WasmCommandRunner {
         &mut self,
         name: &str,
         _args: &[String],
-        _stdin: &str,
+        _stdin: &[u8],
     ) -> WasmResult<CommandResult> {
         // For native builds, return command not found
         Err(WasmError::CommandNotFound {
@@ -336,7 +304,7 @@ impl Default for WasmCommandRunner {
 pub async fn run_wasm_command(
     name: &str,
     args: &[String],
-    stdin: &str,
+    stdin: &[u8],
     cwd: &str,
     env: HashMap<String, String>,
 ) -> WasmResult<CommandResult> {
@@ -351,7 +319,7 @@ pub async fn run_wasm_command(
 pub async fn run_wasm_command(
     name: &str,
     _args: &[String],
-    _stdin: &str,
+    _stdin: &[u8],
     _cwd: &str,
     _env: HashMap<String, String>,
 ) -> WasmResult<CommandResult> {
@@ -48,6 +48,9 @@ pub enum WasmError {
     /// Command not found in filesystem
     CommandNotFound { name: String },
 
+    /// Command is on a `noexec` mount
+    PermissionDenied { path: String },
+
     /// I/O error reading module
     IoError { message: String },
 
@@ -56,6 +59,9 @@ pub enum WasmError {
 
     /// Maximum open files exceeded
     TooManyOpenFiles { max: usize },
+
+    /// Arguments and environment exceed the size limit (E2BIG)
+    ArgumentsTooLarge { size: usize, max: usize },
 }
 
 impl fmt::Display for WasmError {
@@ -107,6 +113,9 @@ impl fmt::Display for WasmError {
             Self::CommandNotFound { name } => {
                 write!(f, "command not found: {}", name)
             }
+            Self::PermissionDenied { path } => {
+                write!(f, "permission denied: {}", path)
+            }
             Self::IoError { message } => {
                 write!(f, "I/O error: {}", message)
             }
@@ -123,6 +132,13 @@ impl fmt::Display for WasmError {
             Self::TooManyOpenFiles { max } => {
                 write!(f, "too many open files (max {})", max)
             }
+            Self::ArgumentsTooLarge { size, max } => {
+                write!(
+                    f,
+                    "argument list too long: {} bytes of arguments and environment (max {})",
+                    size, max
+                )
+            }
         }
     }
 }
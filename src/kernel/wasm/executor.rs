@@ -3,8 +3,9 @@
 //! Executes WASM command modules using the browser's WebAssembly API.
 //! This is the core execution engine that bridges WASM modules to the kernel.
 
+use super::abi::{ExecLayout, env_entries};
 #[cfg(target_arch = "wasm32")]
-use super::abi::{ArgLayout, OpenFlags, SyscallError};
+use super::abi::{OpenFlags, SyscallError};
 #[cfg(target_arch = "wasm32")]
 use super::error::WasmError;
 use super::error::{CommandResult, WasmResult};
@@ -71,13 +72,15 @@ impl WasmMemoryRef {
         result
     }
 
-    /// Write bytes to WASM memory
+    /// Write bytes to WASM memory, in one copy through a view of just the
+    /// bytes written; any that would land past the end are dropped
     pub fn write(&self, offset: u32, data: &[u8]) {
+        let size = self.size();
+        let start = offset.min(size);
+        let n = (data.len() as u32).min(size - start);
         let buffer = self.memory.buffer();
-        let array = Uint8Array::new(&buffer);
-        for (i, &byte) in data.iter().enumerate() {
-            array.set_index(offset + i as u32, byte);
-        }
+        let view = Uint8Array::new_with_byte_offset_and_length(&buffer, start, n);
+        view.copy_from(&data[..n as usize]);
     }
 
     /// Read a null-terminated string from WASM memory
@@ -128,12 +131,9 @@ impl WasmMemoryRef {
     }
 
     pub fn write(&mut self, offset: u32, data: &[u8]) {
-        let start = offset as usize;
-        for (i, &byte) in data.iter().enumerate() {
-            if start + i < self.data.len() {
-                self.data[start + i] = byte;
-            }
-        }
+        let start = (offset as usize).min(self.data.len());
+        let n = data.len().min(self.data.len() - start);
+        self.data[start..start + n].copy_from_slice(&data[..n]);
     }
 
     pub fn read_string(&self, ptr: u32, max_len: u32) -> String {
@@ -204,6 +204,11 @@ impl WasmExecutor {
         args: &[&str],
         stdin: &[u8],
     ) -> WasmResult<CommandResult> {
+        // Check the argument and environment blocks fit before loading
+        let env = env_entries(&self.env);
+        let env: Vec<&str> = env.iter().map(String::as_str).collect();
+        let layout = ExecLayout::new(args, &env)?;
+
         // Create runtime with stdin and environment
         let mut runtime = Runtime::new();
         runtime.stdin = stdin.to_vec();
@@ -237,8 +242,8 @@ impl WasmExecutor {
 
         state.borrow_mut().memory = Some(WasmMemoryRef::new(memory.clone()));
 
-        // Set up arguments in WASM memory
-        let (argc, argv) = self.setup_args(&state, args)?;
+        // Set up arguments and environment in WASM memory
+        let (argc, argv, envp) = self.setup_args(&state, &layout, args, &env)?;
 
         // Get and call main function
         let main_fn = Reflect::get(&exports, &JsValue::from_str("main"))
@@ -249,8 +254,13 @@ impl WasmExecutor {
             got: "unknown".to_string(),
         })?;
 
-        // Call main(argc, argv)
-        let result = main_fn.call2(&JsValue::NULL, &JsValue::from(argc), &JsValue::from(argv));
+        // Call main(argc, argv, envp); a two-parameter main ignores envp
+        let result = main_fn.call3(
+            &JsValue::NULL,
+            &JsValue::from(argc),
+            &JsValue::from(argv),
+            &JsValue::from(envp),
+        );
 
         let exit_code = match result {
             Ok(val) => val.as_f64().unwrap_or(0.0) as i32,
@@ -283,10 +293,13 @@ impl WasmExecutor {
     pub async fn execute(
         &self,
         _module_bytes: &[u8],
-        _args: &[&str],
+        args: &[&str],
         _stdin: &[u8],
     ) -> WasmResult<CommandResult> {
-        // For native builds (testing), return a stub result
+        // For native builds (testing), check the limits and return a stub result
+        let env = env_entries(&self.env);
+        let env: Vec<&str> = env.iter().map(String::as_str).collect();
+        ExecLayout::new(args, &env)?;
         Ok(CommandResult::success())
     }
 
@@ -321,6 +334,8 @@ impl WasmExecutor {
         // Create syscall closures
         self.add_syscall_write(&env, Rc::clone(&state))?;
         self.add_syscall_read(&env, Rc::clone(&state))?;
+        self.add_syscall_pread(&env, Rc::clone(&state))?;
+        self.add_syscall_pwrite(&env, Rc::clone(&state))?;
         self.add_syscall_open(&env, Rc::clone(&state))?;
         self.add_syscall_close(&env, Rc::clone(&state))?;
         self.add_syscall_exit(&env, Rc::clone(&state))?;
@@ -369,15 +384,23 @@ impl WasmExecutor {
     #[cfg(target_arch = "wasm32")]
     fn add_syscall_read(&self, env: &Object, state: SharedRuntime) -> WasmResult<()> {
         let closure = Closure::wrap(Box::new(move |fd: i32, buf_ptr: i32, len: i32| -> i32 {
-            let mut buf = vec![0u8; len as usize];
-            let result = state.borrow_mut().runtime.sys_read(fd, &mut buf);
-            if result > 0 {
-                let state_ref = state.borrow();
-                if let Some(ref memory) = state_ref.memory {
-                    memory.write(buf_ptr as u32, &buf[..result as usize]);
-                }
+            let mut state_ref = state.borrow_mut();
+            let RuntimeState {
+                runtime, memory, ..
+            } = &mut *state_ref;
+            let Some(memory) = memory else {
+                return SyscallError::Generic.code();
+            };
+            if buf_ptr < 0 || len < 0 || buf_ptr as u64 + len as u64 > memory.size() as u64 {
+                return SyscallError::InvalidArgument.code();
             }
-            result
+            // Straight from the VFS into the module's buffer, with no copy
+            // in between (see `abi::syscalls::READ`)
+            let mut at = buf_ptr as u32;
+            runtime.sys_read_with(fd, len as usize, &mut |chunk| {
+                memory.write(at, chunk);
+                at += chunk.len() as u32;
+            })
         }) as Box<dyn Fn(i32, i32, i32) -> i32>);
 
         Reflect::set(env, &JsValue::from_str("read"), closure.as_ref()).map_err(|_| {
@@ -389,6 +412,67 @@ impl WasmExecutor {
         Ok(())
     }
 
+    /// Add pread syscall: pread(fd, buf_ptr, len, offset) -> bytes_read
+    #[cfg(target_arch = "wasm32")]
+    fn add_syscall_pread(&self, env: &Object, state: SharedRuntime) -> WasmResult<()> {
+        let closure = Closure::wrap(Box::new(
+            move |fd: i32, buf_ptr: i32, len: i32, offset: i64| -> i32 {
+                let mut state_ref = state.borrow_mut();
+                let RuntimeState {
+                    runtime, memory, ..
+                } = &mut *state_ref;
+                let Some(memory) = memory else {
+                    return SyscallError::Generic.code();
+                };
+                if buf_ptr < 0 || len < 0 || buf_ptr as u64 + len as u64 > memory.size() as u64 {
+                    return SyscallError::InvalidArgument.code();
+                }
+                let mut at = buf_ptr as u32;
+                runtime.sys_pread_with(fd, len as usize, offset, &mut |chunk| {
+                    memory.write(at, chunk);
+                    at += chunk.len() as u32;
+                })
+            },
+        ) as Box<dyn Fn(i32, i32, i32, i64) -> i32>);
+
+        Reflect::set(env, &JsValue::from_str("pread"), closure.as_ref()).map_err(|_| {
+            WasmError::InstantiationFailed {
+                reason: "failed to set pread import".to_string(),
+            }
+        })?;
+        closure.forget();
+        Ok(())
+    }
+
+    /// Add pwrite syscall: pwrite(fd, buf_ptr, len, offset) -> bytes_written
+    #[cfg(target_arch = "wasm32")]
+    fn add_syscall_pwrite(&self, env: &Object, state: SharedRuntime) -> WasmResult<()> {
+        let closure = Closure::wrap(Box::new(
+            move |fd: i32, buf_ptr: i32, len: i32, offset: i64| -> i32 {
+                let state_ref = state.borrow();
+                if let Some(ref memory) = state_ref.memory {
+                    if buf_ptr < 0 || len < 0 || buf_ptr as u64 + len as u64 > memory.size() as u64
+                    {
+                        return SyscallError::InvalidArgument.code();
+                    }
+                    let data = memory.read(buf_ptr as u32, len as u32);
+                    drop(state_ref);
+                    state.borrow_mut().runtime.sys_pwrite(fd, &data, offset)
+                } else {
+                    SyscallError::Generic.code()
+                }
+            },
+        ) as Box<dyn Fn(i32, i32, i32, i64) -> i32>);
+
+        Reflect::set(env, &JsValue::from_str("pwrite"), closure.as_ref()).map_err(|_| {
+            WasmError::InstantiationFailed {
+                reason: "failed to set pwrite import".to_string(),
+            }
+        })?;
+        closure.forget();
+        Ok(())
+    }
+
     /// Add open syscall: open(path_ptr, path_len, flags) -> fd
     #[cfg(target_arch = "wasm32")]
     fn add_syscall_open(&self, env: &Object, state: SharedRuntime) -> WasmResult<()> {
@@ -712,9 +796,15 @@ impl WasmExecutor {
             })
     }
 
-    /// Set up command arguments in WASM memory
+    /// Set up command arguments and environment in WASM memory
     #[cfg(target_arch = "wasm32")]
-    fn setup_args(&self, state: &SharedRuntime, args: &[&str]) -> WasmResult<(i32, i32)> {
+    fn setup_args(
+        &self,
+        state: &SharedRuntime,
+        layout: &ExecLayout,
+        args: &[&str],
+        env: &[&str],
+    ) -> WasmResult<(i32, i32, i32)> {
         let state_ref = state.borrow();
         let memory = state_ref
             .memory
@@ -723,20 +813,26 @@ impl WasmExecutor {
                 reason: "memory not available".to_string(),
             })?;
 
-        let layout = ArgLayout::new(args);
         let total_size = layout.total_size();
+        let available = memory.size().saturating_sub(1024 + 256);
+        if total_size as u32 > available {
+            return Err(WasmError::OutOfMemory {
+                requested: total_size as u32,
+                available,
+            });
+        }
 
-        // Allocate at the end of the first page (after potential data)
+        // Allocate at the end of memory (after potential data)
         // In a real implementation, we'd use __heap_base
-        let base_addr = (memory.size() - total_size as u32 - 256).max(1024);
+        let base_addr = memory.size() - total_size as u32 - 256;
 
         let mut buf = vec![0u8; total_size];
-        let argv_ptr = layout.write_to(args, base_addr, &mut buf);
+        let (argv_ptr, envp_ptr) = layout.write_to(args, env, base_addr, &mut buf);
 
         // Write to WASM memory
         memory.write(base_addr, &buf);
 
-        Ok((args.len() as i32, argv_ptr as i32))
+        Ok((args.len() as i32, argv_ptr as i32, envp_ptr as i32))
     }
 }
 
@@ -780,4 +876,22 @@ mod tests {
         let s = mem.read_string(0, 20);
         assert_eq!(s, "hello");
     }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn test_execute_environment_too_large() {
+        use super::super::abi::MAX_ARG_ENV_SIZE;
+        use super::super::error::WasmError;
+
+        let mut exec = WasmExecutor::new();
+        exec.set_env(HashMap::from([(
+            "BIG".to_string(),
+            "x".repeat(MAX_ARG_ENV_SIZE),
+        )]));
+        let result = futures::executor::block_on(exec.execute(&[], &["true"], &[]));
+        assert!(matches!(result, Err(WasmError::ArgumentsTooLarge { .. })));
+
+        exec.set_env(HashMap::from([("HOME".to_string(), "/".to_string())]));
+        assert!(futures::executor::block_on(exec.execute(&[], &["true"], &[])).is_ok());
+    }
 }
@@ -32,7 +32,7 @@
 //! | Export       | Type                            | Description                    |
 //! |-------------|--------------------------------|--------------------------------|
 //! | `memory`    | Memory                         | Linear memory for data exchange|
-//! | `main`      | `(argc: i32, argv: i32) -> i32`| Entry point, returns exit code |
+//! | `main`      | `(argc: i32, argv: i32, envp: i32) -> i32`| Entry point, returns exit code |
 //!
 //! Optional exports:
 //!
@@ -104,7 +104,7 @@
 //!
 //! ## Memory Layout for Arguments
 //!
-//! When `main(argc, argv)` is called:
+//! When `main(argc, argv, envp)` is called:
 //!
 //! ```text
 //! argv points to an array of i32 pointers:
@@ -124,6 +124,11 @@
 //!     A+24: 0               (null terminator)
 //! ```
 //!
+//! envp follows the argv block in the same format, pointing at `NAME=value`
+//! strings sorted by name. A `main` taking only `(argc, argv)` still works.
+//! Both blocks together may use at most [`MAX_ARG_ENV_SIZE`] bytes; larger
+//! ones fail with [`WasmError::ArgumentsTooLarge`] (`E2BIG`).
+//!
 //! ## Standard File Descriptors
 //!
 //! | fd | Purpose        |
@@ -211,6 +216,7 @@ mod executor;
 mod loader;
 mod runtime;
 mod wasi_preview2;
+mod wat;
 
 pub use abi::*;
 pub use command::*;
@@ -219,6 +225,7 @@ pub use executor::*;
 pub use loader::*;
 pub use runtime::*;
 pub use wasi_preview2::*;
+pub use wat::{WatError, WatResult, assemble};
 
 #[cfg(test)]
 mod tests;
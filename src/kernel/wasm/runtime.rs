@@ -5,6 +5,7 @@
 
 use super::abi::{OpenFlags, StatBuf, SyscallError, fd};
 use super::loader::FdTable;
+use crate::kernel::object::FileObject;
 use crate::kernel::syscall as ksyscall;
 use std::collections::HashMap;
 
@@ -39,6 +40,11 @@ pub struct Runtime {
 
     /// Exit code (if exited)
     exit_code: Option<i32>,
+
+    /// Kernel descriptors behind fds the command has written at an offset,
+    /// kept open until it closes them so each pwrite doesn't load and write
+    /// back the whole file
+    kernel_fds: HashMap<i32, ksyscall::Fd>,
 }
 
 impl Runtime {
@@ -53,23 +59,22 @@ impl Runtime {
             env: HashMap::new(),
             cwd: "/".to_string(),
             exit_code: None,
+            kernel_fds: HashMap::new(),
         }
     }
 
     /// Create a runtime with stdin data
     pub fn with_stdin(stdin: Vec<u8>) -> Self {
-        Self {
-            stdin,
-            ..Self::new()
-        }
+        let mut runtime = Self::new();
+        runtime.stdin = stdin;
+        runtime
     }
 
     /// Create a runtime with a specific working directory
     pub fn with_cwd(cwd: &str) -> Self {
-        Self {
-            cwd: cwd.to_string(),
-            ..Self::new()
-        }
+        let mut runtime = Self::new();
+        runtime.cwd = cwd.to_string();
+        runtime
     }
 
     /// Get current working directory
@@ -171,7 +176,9 @@ impl Runtime {
                     return SyscallError::BadFd.code();
                 }
 
-                // Get file path and write via VFS
+                // Get file path and write via VFS, after any positional
+                // writes so they aren't written back over this one
+                self.release_kernel_fd(fd);
                 if let Some(path) = self.fd_table.get_path(fd) {
                     // For simplicity, we append to file (proper seek support would need more state)
                     let content = String::from_utf8_lossy(data);
@@ -188,41 +195,150 @@ impl Runtime {
 
     /// Read syscall: read(fd, buf, len) -> bytes_read
     pub fn sys_read(&mut self, fd_num: i32, buf: &mut [u8]) -> i32 {
+        let mut filled = 0;
+        self.sys_read_with(fd_num, buf.len(), &mut |chunk| {
+            buf[filled..filled + chunk.len()].copy_from_slice(chunk);
+            filled += chunk.len();
+        })
+    }
+
+    /// Read syscall, handing the bytes read to `sink` as slices of where
+    /// they are kept (stdin, or the file in the VFS) rather than through a
+    /// buffer; the slices add up to the count returned, and at most `len`
+    pub fn sys_read_with(&mut self, fd_num: i32, len: usize, sink: &mut dyn FnMut(&[u8])) -> i32 {
         match fd_num {
-            fd if fd == fd::STDIN => self.read_stdin(buf) as i32,
+            fd if fd == fd::STDIN => {
+                let start = self.stdin_pos;
+                let n = len.min(self.stdin.len() - start);
+                sink(&self.stdin[start..start + n]);
+                self.stdin_pos += n;
+                n as i32
+            }
             fd if fd == fd::STDOUT || fd == fd::STDERR => SyscallError::InvalidArgument.code(),
             fd => {
                 if !self.fd_table.is_valid(fd) {
                     return SyscallError::BadFd.code();
                 }
+                let Some(path) = self.fd_table.get_path(fd) else {
+                    return SyscallError::BadFd.code();
+                };
+                let pos = self.fd_table.get_position(fd).unwrap_or(0);
+                // A module's read returns an i32
+                let len = len.min(i32::MAX as usize);
+                match self.read_file_at(fd, &path, pos, len, sink) {
+                    Ok(n) => {
+                        self.fd_table.advance_position(fd, n as u64);
+                        n as i32
+                    }
+                    Err(_) => SyscallError::NotFound.code(),
+                }
+            }
+        }
+    }
 
-                // Get file path and read via VFS
-                if let Some(path) = self.fd_table.get_path(fd) {
-                    match ksyscall::read_file(&path) {
-                        Ok(content) => {
-                            let bytes = content.as_bytes();
-                            let pos = self.fd_table.get_position(fd).unwrap_or(0) as usize;
+    /// Pread syscall: pread(fd, buf, len, offset) -> bytes_read
+    pub fn sys_pread(&mut self, fd_num: i32, buf: &mut [u8], offset: i64) -> i32 {
+        let mut filled = 0;
+        self.sys_pread_with(fd_num, buf.len(), offset, &mut |chunk| {
+            buf[filled..filled + chunk.len()].copy_from_slice(chunk);
+            filled += chunk.len();
+        })
+    }
+
+    /// Pread syscall, handing the bytes to `sink` like
+    /// [`Self::sys_read_with`]; reads from `offset` and leaves the
+    /// descriptor's position where it was
+    pub fn sys_pread_with(
+        &mut self,
+        fd_num: i32,
+        len: usize,
+        offset: i64,
+        sink: &mut dyn FnMut(&[u8]),
+    ) -> i32 {
+        let path = match self.positional_path(fd_num, offset) {
+            Ok(path) => path,
+            Err(e) => return e.code(),
+        };
+        let len = len.min(i32::MAX as usize);
+        match self.read_file_at(fd_num, &path, offset as u64, len, sink) {
+            Ok(n) => n as i32,
+            Err(_) => SyscallError::NotFound.code(),
+        }
+    }
 
-                            if pos >= bytes.len() {
-                                return 0; // EOF
-                            }
+    /// Read a file at `offset` through the kernel descriptor the command's
+    /// pwrites went to, if there is one, so it sees what they wrote
+    fn read_file_at(
+        &self,
+        fd_num: i32,
+        path: &str,
+        offset: u64,
+        len: usize,
+        sink: &mut dyn FnMut(&[u8]),
+    ) -> ksyscall::SyscallResult<usize> {
+        let Some(&kfd) = self.kernel_fds.get(&fd_num) else {
+            return ksyscall::read_at_with(path, offset, len, sink);
+        };
+        let mut buf = vec![0u8; len];
+        let n = ksyscall::pread(kfd, &mut buf, offset)?;
+        sink(&buf[..n]);
+        Ok(n)
+    }
 
-                            let remaining = &bytes[pos..];
-                            let to_read = std::cmp::min(remaining.len(), buf.len());
-                            buf[..to_read].copy_from_slice(&remaining[..to_read]);
+    /// Pwrite syscall: pwrite(fd, buf, len, offset) -> bytes_written
+    ///
+    /// Writes at `offset`, leaving the descriptor's position where it was;
+    /// writing past the end fills the gap with zeros. Writes that would end
+    /// past [`FileObject::MAX_SIZE`] are an invalid argument.
+    pub fn sys_pwrite(&mut self, fd_num: i32, data: &[u8], offset: i64) -> i32 {
+        let path = match self.positional_path(fd_num, offset) {
+            Ok(path) => path,
+            Err(e) => return e.code(),
+        };
+        if (offset as u64)
+            .checked_add(data.len() as u64)
+            .is_none_or(|end| end > FileObject::MAX_SIZE)
+        {
+            return SyscallError::InvalidArgument.code();
+        }
+        if !self
+            .fd_table
+            .get_flags(fd_num)
+            .is_some_and(|f| f.is_write())
+        {
+            return SyscallError::BadFd.code();
+        }
+        let kfd = match self.kernel_fds.get(&fd_num) {
+            Some(&kfd) => kfd,
+            None => {
+                let Ok(kfd) = ksyscall::open(&path, ksyscall::OpenFlags::RDWR) else {
+                    return SyscallError::NotFound.code();
+                };
+                self.kernel_fds.insert(fd_num, kfd);
+                kfd
+            }
+        };
+        match ksyscall::pwrite(kfd, data, offset as u64) {
+            Ok(n) => n as i32,
+            Err(_) => SyscallError::Generic.code(),
+        }
+    }
 
-                            // Advance position
-                            self.fd_table.advance_position(fd, to_read as u64);
+    /// Close the kernel descriptor behind `fd_num`, if it has one, writing
+    /// what was written through it back to the file
+    fn release_kernel_fd(&mut self, fd_num: i32) {
+        if let Some(kfd) = self.kernel_fds.remove(&fd_num) {
+            let _ = ksyscall::close(kfd);
+        }
+    }
 
-                            to_read as i32
-                        }
-                        Err(_) => SyscallError::NotFound.code(),
-                    }
-                } else {
-                    SyscallError::BadFd.code()
-                }
-            }
+    /// Path of the file a positional read or write at `offset` goes to;
+    /// the standard streams can't seek
+    fn positional_path(&self, fd_num: i32, offset: i64) -> Result<String, SyscallError> {
+        if fd_num == fd::STDIN || fd_num == fd::STDOUT || fd_num == fd::STDERR || offset < 0 {
+            return Err(SyscallError::InvalidArgument);
         }
+        self.fd_table.get_path(fd_num).ok_or(SyscallError::BadFd)
     }
 
     /// Open syscall: open(path, flags) -> fd
@@ -235,6 +351,7 @@ impl Runtime {
 
     /// Close syscall: close(fd) -> 0 or error
     pub fn sys_close(&mut self, fd: i32) -> i32 {
+        self.release_kernel_fd(fd);
         match self.fd_table.close(fd) {
             Ok(()) => 0,
             Err(_) => SyscallError::BadFd.code(),
@@ -401,6 +518,14 @@ impl Runtime {
     }
 }
 
+impl Drop for Runtime {
+    fn drop(&mut self) {
+        for (_, kfd) in self.kernel_fds.drain() {
+            let _ = ksyscall::close(kfd);
+        }
+    }
+}
+
 impl Default for Runtime {
     fn default() -> Self {
         Self::new()
@@ -498,6 +623,87 @@ mod tests {
         assert_eq!(&buf, b"hello");
     }
 
+    #[test]
+    fn test_sys_read_with() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+        KERNEL.with(|k| {
+            *k.borrow_mut() = Kernel::new();
+            let pid = k.borrow_mut().spawn_process("test", None);
+            k.borrow_mut().set_current(pid);
+        });
+        ksyscall::write_file("/tmp/big", &"x".repeat(10_000)).unwrap();
+
+        let mut runtime = Runtime::with_stdin(b"abc".to_vec());
+        let fd = runtime.sys_open("/tmp/big", OpenFlags::READ);
+        let mut chunks = Vec::new();
+        let n = runtime.sys_read_with(fd, 8192, &mut |chunk| chunks.push(chunk.len()));
+        assert_eq!(n, 8192);
+        // Slices of the file a block at a time, not a buffer's worth of
+        // copies
+        assert_eq!(chunks, [4096, 4096]);
+
+        let mut buf = [0u8; 8192];
+        assert_eq!(runtime.sys_read(fd, &mut buf), 10_000 - 8192);
+        assert_eq!(runtime.sys_read(fd, &mut buf), 0);
+
+        let mut seen = Vec::new();
+        let n = runtime.sys_read_with(fd::STDIN, 2, &mut |chunk| seen.extend_from_slice(chunk));
+        assert_eq!((n, seen.as_slice()), (2, &b"ab"[..]));
+    }
+
+    #[test]
+    fn test_sys_pread_pwrite() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+        KERNEL.with(|k| {
+            *k.borrow_mut() = Kernel::new();
+            let pid = k.borrow_mut().spawn_process("test", None);
+            k.borrow_mut().set_current(pid);
+        });
+        ksyscall::write_file("/tmp/rows", "0123456789").unwrap();
+
+        let mut runtime = Runtime::new();
+        let fd = runtime.sys_open("/tmp/rows", OpenFlags::READ_WRITE);
+        assert_eq!(runtime.sys_pwrite(fd, b"ab", 4), 2);
+        let mut buf = [0u8; 4];
+        assert_eq!(runtime.sys_pread(fd, &mut buf, 3), 4);
+        assert_eq!(&buf, b"3ab6");
+        // The position is untouched by either
+        assert_eq!(runtime.sys_read(fd, &mut buf), 4);
+        assert_eq!(&buf, b"0123");
+
+        assert_eq!(
+            runtime.sys_pread(fd, &mut buf, -1),
+            SyscallError::InvalidArgument.code()
+        );
+        assert_eq!(
+            runtime.sys_pwrite(fd::STDOUT, b"x", 0),
+            SyscallError::InvalidArgument.code()
+        );
+        assert_eq!(
+            runtime.sys_pread(42, &mut buf, 0),
+            SyscallError::BadFd.code()
+        );
+        for offset in [i64::MAX, FileObject::MAX_SIZE as i64] {
+            assert_eq!(
+                runtime.sys_pwrite(fd, b"x", offset),
+                SyscallError::InvalidArgument.code()
+            );
+        }
+        let ro = runtime.sys_open("/tmp/rows", OpenFlags::READ);
+        assert_eq!(runtime.sys_pwrite(ro, b"x", 0), SyscallError::BadFd.code());
+
+        // Later pwrites reuse the descriptor the first one opened, and the
+        // file is written back once, when the command closes it
+        let kfds = || KERNEL.with(|k| k.borrow().current_process().unwrap().files.len());
+        let open = kfds();
+        assert_eq!(runtime.sys_pwrite(fd, b"yz", 8), 2);
+        assert_eq!(kfds(), open);
+        assert_eq!(ksyscall::read_file("/tmp/rows").unwrap(), "0123456789");
+        assert_eq!(runtime.sys_close(fd), 0);
+        assert_eq!(kfds(), open - 1);
+        assert_eq!(ksyscall::read_file("/tmp/rows").unwrap(), "0123ab67yz");
+    }
+
     #[test]
     fn test_sys_open_close() {
         let mut runtime = Runtime::new();
@@ -176,6 +176,8 @@ mod abi_tests {
         assert_eq!(syscalls::OPEN, "open");
         assert_eq!(syscalls::WRITE, "write");
         assert_eq!(syscalls::READ, "read");
+        assert_eq!(syscalls::PREAD, "pread");
+        assert_eq!(syscalls::PWRITE, "pwrite");
         assert_eq!(syscalls::CLOSE, "close");
         assert_eq!(syscalls::EXIT, "exit");
     }
@@ -186,6 +188,22 @@ mod abi_tests {
         assert_eq!(fd::STDOUT, 1);
         assert_eq!(fd::STDERR, 2);
     }
+
+    #[test]
+    fn test_sdk_matches_kernel_abi() {
+        // The command SDK duplicates these values; they must never drift
+        for code in -11..=-1 {
+            let kernel = SyscallError::from_code(code).unwrap();
+            assert_eq!(axeberg_abi::Error::from_code(code).code(), kernel.code());
+        }
+        assert_eq!(axeberg_abi::io::STDOUT, fd::STDOUT);
+        assert_eq!(axeberg_abi::fs::OpenFlags::CREATE.0, OpenFlags::CREATE.0);
+        assert_eq!(
+            axeberg_abi::fs::OpenFlags::TRUNCATE.0,
+            OpenFlags::TRUNCATE.0
+        );
+        assert_eq!(axeberg_abi::fs::Metadata::STAT_SIZE, StatBuf::SIZE);
+    }
 }
 
 // =============================================================================
@@ -215,6 +233,19 @@ mod validation_tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_wat_assembles_minimal_module() {
+        let module = assemble(
+            r#"(module
+                 (memory (export "memory") 1)
+                 (func (export "main") (param i32 i32) (result i32)
+                   i32.const 42))"#,
+        )
+        .unwrap();
+        assert_eq!(module, exit_code_module(42));
+        assert!(ModuleValidator::validate(&module).is_ok());
+    }
+
     #[test]
     fn test_validate_missing_memory_export() {
         // A module with main but no memory export should fail
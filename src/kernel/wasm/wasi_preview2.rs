@@ -22,6 +22,8 @@
 use std::collections::HashMap;
 use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
+use crate::kernel::syscall::generate_random_bytes;
+
 // ============================================================================
 // Type Definitions (based on WIT types)
 // ============================================================================
@@ -443,28 +445,9 @@ impl Default for MonotonicClock {
 // wasi:random Interface
 // ============================================================================
 
-/// Get random bytes
+/// Get random bytes, from the kernel's CSPRNG
 pub fn get_random_bytes(len: usize) -> Vec<u8> {
-    use std::collections::hash_map::DefaultHasher;
-    use std::hash::{Hash, Hasher};
-
-    // Simple PRNG for demonstration (not cryptographically secure)
-    // In production, use a proper RNG
-    let mut result = Vec::with_capacity(len);
-    let mut hasher = DefaultHasher::new();
-
-    let seed = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_nanos();
-    seed.hash(&mut hasher);
-
-    for i in 0..len {
-        (i as u64).hash(&mut hasher);
-        result.push(hasher.finish() as u8);
-    }
-
-    result
+    generate_random_bytes(len)
 }
 
 /// Get a random u64
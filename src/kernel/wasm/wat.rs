@@ -0,0 +1,1895 @@
+//! WAT Assembler
+//!
+//! Translates the WebAssembly text format into a binary module so small
+//! commands can be written and installed entirely inside the OS (see the
+//! `wat2wasm` program).
+//!
+//! # Supported Subset
+//!
+//! - Module fields: `type`, `import` (func, memory), `func`, `memory`,
+//!   `global`, `export`, `start`, `data`
+//! - Inline `(export "name")` and `(import "mod" "name")` abbreviations
+//! - All MVP numeric, parametric, variable, memory and control
+//!   instructions except `call_indirect` (tables are not supported)
+//! - Both flat (`local.get 0 i32.const 1 i32.add`) and folded
+//!   (`(i32.add (local.get 0) (i32.const 1))`) instruction syntax
+//! - Symbolic `$names` for functions, locals, globals, types and labels
+//!
+//! The assembler resolves names and encodes instructions but does not
+//! type-check function bodies; the runtime rejects ill-typed modules
+//! when they are instantiated.
+//!
+//! # Example
+//!
+//! ```text
+//! (module
+//!   (import "env" "write" (func $write (param i32 i32 i32) (result i32)))
+//!   (memory (export "memory") 1)
+//!   (data (i32.const 16) "hello\n")
+//!   (func (export "main") (param i32 i32) (result i32)
+//!     (drop (call $write (i32.const 1) (i32.const 16) (i32.const 6)))
+//!     (i32.const 0)))
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+
+/// Error produced while assembling a module
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatError {
+    /// 1-based source line
+    pub line: usize,
+    /// 1-based source column
+    pub column: usize,
+    /// What went wrong
+    pub message: String,
+}
+
+impl fmt::Display for WatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for WatError {}
+
+/// Result type for assembler operations
+pub type WatResult<T> = Result<T, WatError>;
+
+/// Assemble WAT source into a WASM binary
+pub fn assemble(source: &str) -> WatResult<Vec<u8>> {
+    let sexps = parse_sexps(source)?;
+    let fields = module_fields(&sexps)?;
+    let mut module = Module::default();
+    module.declare(fields)?;
+    module.encode()
+}
+
+// ============ S-expressions ============
+
+/// Source position (line, column)
+type Pos = (usize, usize);
+
+#[derive(Debug, Clone)]
+enum Sexp {
+    Atom(String, Pos),
+    Str(Vec<u8>, Pos),
+    List(Vec<Sexp>, Pos),
+}
+
+impl Sexp {
+    fn pos(&self) -> Pos {
+        match self {
+            Sexp::Atom(_, p) | Sexp::Str(_, p) | Sexp::List(_, p) => *p,
+        }
+    }
+
+    fn atom(&self) -> Option<&str> {
+        match self {
+            Sexp::Atom(a, _) => Some(a),
+            _ => None,
+        }
+    }
+
+    fn id(&self) -> Option<&str> {
+        self.atom().filter(|a| a.starts_with('$'))
+    }
+
+    /// Head keyword of a list, e.g. `func` for `(func ...)`
+    fn head(&self) -> Option<&str> {
+        match self {
+            Sexp::List(items, _) => items.first().and_then(Sexp::atom),
+            _ => None,
+        }
+    }
+
+    fn is_list(&self, head: &str) -> bool {
+        self.head() == Some(head)
+    }
+}
+
+fn err<T>(pos: Pos, message: impl Into<String>) -> WatResult<T> {
+    Err(WatError {
+        line: pos.0,
+        column: pos.1,
+        message: message.into(),
+    })
+}
+
+struct Lexer<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(source: &'a str) -> Self {
+        Self {
+            chars: source.chars().peekable(),
+            line: 1,
+            column: 1,
+        }
+    }
+
+    fn pos(&self) -> Pos {
+        (self.line, self.column)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    /// Skip whitespace, `;; line` and nestable `(; block ;)` comments
+    fn skip_trivia(&mut self) -> WatResult<()> {
+        loop {
+            match self.chars.peek() {
+                Some(c) if c.is_whitespace() => {
+                    self.bump();
+                }
+                Some(';') => {
+                    let pos = self.pos();
+                    self.bump();
+                    if self.bump() != Some(';') {
+                        return err(pos, "unexpected ';'");
+                    }
+                    while let Some(c) = self.bump() {
+                        if c == '\n' {
+                            break;
+                        }
+                    }
+                }
+                Some('(') => {
+                    let mut ahead = self.chars.clone();
+                    ahead.next();
+                    if ahead.next() != Some(';') {
+                        return Ok(());
+                    }
+                    let pos = self.pos();
+                    self.bump();
+                    self.bump();
+                    let mut depth = 1;
+                    let mut prev = '\0';
+                    while depth > 0 {
+                        let Some(c) = self.bump() else {
+                            return err(pos, "unterminated block comment");
+                        };
+                        match (prev, c) {
+                            ('(', ';') => {
+                                depth += 1;
+                                prev = '\0';
+                                continue;
+                            }
+                            (';', ')') => {
+                                depth -= 1;
+                                prev = '\0';
+                                continue;
+                            }
+                            _ => {}
+                        }
+                        prev = c;
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn string(&mut self) -> WatResult<Vec<u8>> {
+        let pos = self.pos();
+        self.bump();
+        let mut out = Vec::new();
+        loop {
+            let Some(c) = self.bump() else {
+                return err(pos, "unterminated string");
+            };
+            match c {
+                '"' => return Ok(out),
+                '\\' => {
+                    let esc_pos = self.pos();
+                    match self.bump() {
+                        Some('n') => out.push(b'\n'),
+                        Some('t') => out.push(b'\t'),
+                        Some('r') => out.push(b'\r'),
+                        Some('\\') => out.push(b'\\'),
+                        Some('\'') => out.push(b'\''),
+                        Some('"') => out.push(b'"'),
+                        Some('u') => {
+                            if self.bump() != Some('{') {
+                                return err(esc_pos, "expected '{' after \\u");
+                            }
+                            let mut hex = String::new();
+                            loop {
+                                match self.bump() {
+                                    Some('}') => break,
+                                    Some(c) if c.is_ascii_hexdigit() || c == '_' => {
+                                        if c != '_' {
+                                            hex.push(c)
+                                        }
+                                    }
+                                    _ => return err(esc_pos, "malformed unicode escape"),
+                                }
+                            }
+                            let ch = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32);
+                            let Some(ch) = ch else {
+                                return err(esc_pos, "invalid unicode scalar value");
+                            };
+                            let mut buf = [0u8; 4];
+                            out.extend_from_slice(ch.encode_utf8(&mut buf).as_bytes());
+                        }
+                        Some(h) if h.is_ascii_hexdigit() => {
+                            let l = self.bump().filter(char::is_ascii_hexdigit);
+                            let Some(l) = l else {
+                                return err(esc_pos, "expected two hex digits");
+                            };
+                            let byte = u8::from_str_radix(&format!("{}{}", h, l), 16);
+                            out.push(byte.unwrap_or_default());
+                        }
+                        _ => return err(esc_pos, "unknown escape sequence"),
+                    }
+                }
+                c => {
+                    let mut buf = [0u8; 4];
+                    out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+            }
+        }
+    }
+
+    fn atom(&mut self) -> String {
+        let mut s = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if c.is_whitespace() || c == '(' || c == ')' || c == '"' || c == ';' {
+                break;
+            }
+            s.push(c);
+            self.bump();
+        }
+        s
+    }
+}
+
+fn parse_sexps(source: &str) -> WatResult<Vec<Sexp>> {
+    let mut lexer = Lexer::new(source);
+    // Stack of open lists; the bottom entry collects top-level items
+    let mut stack: Vec<(Vec<Sexp>, Pos)> = vec![(Vec::new(), (1, 1))];
+
+    loop {
+        lexer.skip_trivia()?;
+        let pos = lexer.pos();
+        match lexer.chars.peek().copied() {
+            None => break,
+            Some('(') => {
+                lexer.bump();
+                stack.push((Vec::new(), pos));
+            }
+            Some(')') => {
+                lexer.bump();
+                if stack.len() < 2 {
+                    return err(pos, "unexpected ')'");
+                }
+                if let Some((items, open)) = stack.pop()
+                    && let Some((parent, _)) = stack.last_mut()
+                {
+                    parent.push(Sexp::List(items, open));
+                }
+            }
+            Some('"') => {
+                let s = lexer.string()?;
+                if let Some((top, _)) = stack.last_mut() {
+                    top.push(Sexp::Str(s, pos));
+                }
+            }
+            Some(_) => {
+                let a = lexer.atom();
+                if let Some((top, _)) = stack.last_mut() {
+                    top.push(Sexp::Atom(a, pos));
+                }
+            }
+        }
+    }
+
+    if stack.len() > 1 {
+        let open = stack.last().map(|(_, p)| *p).unwrap_or((1, 1));
+        return err(open, "unclosed '('");
+    }
+    Ok(stack.pop().map(|(items, _)| items).unwrap_or_default())
+}
+
+/// Accept either `(module ...)` or a bare sequence of module fields
+fn module_fields(sexps: &[Sexp]) -> WatResult<&[Sexp]> {
+    match sexps {
+        [Sexp::List(items, _)] if sexps[0].is_list("module") => {
+            let mut rest = &items[1..];
+            if rest.first().and_then(Sexp::id).is_some() {
+                rest = &rest[1..];
+            }
+            Ok(rest)
+        }
+        _ => {
+            if let Some(s) = sexps.iter().find(|s| !matches!(s, Sexp::List(..))) {
+                return err(s.pos(), "expected a module field");
+            }
+            Ok(sexps)
+        }
+    }
+}
+
+// ============ Module ============
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ValType {
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+impl ValType {
+    fn parse(s: &Sexp) -> WatResult<Self> {
+        match s.atom() {
+            Some("i32") => Ok(ValType::I32),
+            Some("i64") => Ok(ValType::I64),
+            Some("f32") => Ok(ValType::F32),
+            Some("f64") => Ok(ValType::F64),
+            _ => err(s.pos(), "expected a value type"),
+        }
+    }
+
+    fn code(self) -> u8 {
+        match self {
+            ValType::I32 => 0x7F,
+            ValType::I64 => 0x7E,
+            ValType::F32 => 0x7D,
+            ValType::F64 => 0x7C,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct FuncType {
+    params: Vec<ValType>,
+    results: Vec<ValType>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Limits {
+    min: u32,
+    max: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExportKind {
+    Func = 0,
+    Memory = 2,
+    Global = 3,
+}
+
+enum ImportDesc {
+    Func(u32),
+    Memory(Limits),
+}
+
+struct Import {
+    module: Vec<u8>,
+    name: Vec<u8>,
+    desc: ImportDesc,
+}
+
+/// A function defined in the module, body not yet encoded
+struct FuncDef<'a> {
+    type_idx: u32,
+    locals: Vec<ValType>,
+    local_names: HashMap<String, u32>,
+    body: &'a [Sexp],
+}
+
+struct GlobalDef<'a> {
+    ty: ValType,
+    mutable: bool,
+    init: &'a [Sexp],
+}
+
+struct DataDef<'a> {
+    offset: &'a [Sexp],
+    bytes: Vec<u8>,
+}
+
+/// Deferred export target, resolved once all names are known
+struct ExportDef<'a> {
+    name: Vec<u8>,
+    kind: ExportKind,
+    target: &'a Sexp,
+}
+
+#[derive(Default)]
+struct Module<'a> {
+    types: Vec<FuncType>,
+    type_names: HashMap<String, u32>,
+    imports: Vec<Import>,
+    /// Number of imported functions (they come first in the index space)
+    imported_funcs: u32,
+    imported_memories: u32,
+    funcs: Vec<FuncDef<'a>>,
+    func_names: HashMap<String, u32>,
+    memories: Vec<Limits>,
+    memory_names: HashMap<String, u32>,
+    globals: Vec<GlobalDef<'a>>,
+    global_names: HashMap<String, u32>,
+    exports: Vec<(Vec<u8>, ExportKind, u32, Pos)>,
+    pending_exports: Vec<ExportDef<'a>>,
+    start: Option<&'a Sexp>,
+    data: Vec<DataDef<'a>>,
+}
+
+/// Register `$name` in a namespace, rejecting duplicates
+fn bind(names: &mut HashMap<String, u32>, id: Option<&Sexp>, index: u32) -> WatResult<()> {
+    if let Some(s) = id
+        && let Some(name) = s.id()
+        && names.insert(name.to_string(), index).is_some()
+    {
+        return err(s.pos(), format!("duplicate identifier {}", name));
+    }
+    Ok(())
+}
+
+/// Resolve a `$name` or numeric index
+fn resolve(names: &HashMap<String, u32>, s: &Sexp, what: &str) -> WatResult<u32> {
+    match s.atom() {
+        Some(a) if a.starts_with('$') => match names.get(a) {
+            Some(&i) => Ok(i),
+            None => err(s.pos(), format!("unknown {} {}", what, a)),
+        },
+        Some(a) => match parse_u32(a) {
+            Some(i) => Ok(i),
+            None => err(s.pos(), format!("expected {} index", what)),
+        },
+        None => err(s.pos(), format!("expected {} index", what)),
+    }
+}
+
+fn string_of(s: &Sexp) -> WatResult<Vec<u8>> {
+    match s {
+        Sexp::Str(b, _) => Ok(b.clone()),
+        _ => err(s.pos(), "expected a string"),
+    }
+}
+
+fn list_items(s: &Sexp) -> &[Sexp] {
+    match s {
+        Sexp::List(items, _) => items,
+        _ => &[],
+    }
+}
+
+impl<'a> Module<'a> {
+    /// First pass: record every field and name so bodies can refer forward
+    fn declare(&mut self, fields: &'a [Sexp]) -> WatResult<()> {
+        // Types first so `(type $t)` uses resolve regardless of order
+        for field in fields.iter().filter(|f| f.is_list("type")) {
+            let items = list_items(field);
+            let mut i = 1;
+            let id = items.get(i).filter(|s| s.id().is_some());
+            if id.is_some() {
+                i += 1;
+            }
+            let Some(func) = items.get(i).filter(|s| s.is_list("func")) else {
+                return err(field.pos(), "expected (func ...) in type definition");
+            };
+            let (ty, _, _) = self.signature(&list_items(func)[1..])?;
+            let idx = self.types.len() as u32;
+            self.types.push(ty);
+            bind(&mut self.type_names, id, idx)?;
+        }
+
+        // Imports must precede definitions in the index space
+        for field in fields {
+            match field.head() {
+                Some("import") => self.declare_import(field)?,
+                Some("func") | Some("memory")
+                    if list_items(field).iter().any(|s| s.is_list("import")) =>
+                {
+                    self.declare_inline_import(field)?
+                }
+                _ => {}
+            }
+        }
+
+        for field in fields {
+            let items = list_items(field);
+            let is_import = items.iter().any(|s| s.is_list("import"));
+            match field.head() {
+                Some("type") | Some("import") => {}
+                Some("func") if !is_import => self.declare_func(field)?,
+                Some("memory") if !is_import => self.declare_memory(field)?,
+                Some("func") | Some("memory") => {}
+                Some("global") => self.declare_global(field)?,
+                Some("export") => {
+                    let (Some(name), Some(desc)) = (items.get(1), items.get(2)) else {
+                        return err(field.pos(), "expected (export \"name\" (kind index))");
+                    };
+                    let kind = match desc.head() {
+                        Some("func") => ExportKind::Func,
+                        Some("memory") => ExportKind::Memory,
+                        Some("global") => ExportKind::Global,
+                        _ => return err(desc.pos(), "expected func, memory or global export"),
+                    };
+                    let Some(target) = list_items(desc).get(1) else {
+                        return err(desc.pos(), "missing export index");
+                    };
+                    self.pending_exports.push(ExportDef {
+                        name: string_of(name)?,
+                        kind,
+                        target,
+                    });
+                }
+                Some("start") => {
+                    let Some(target) = items.get(1) else {
+                        return err(field.pos(), "missing start function");
+                    };
+                    self.start = Some(target);
+                }
+                Some("data") => self.declare_data(field)?,
+                Some("table") | Some("elem") => {
+                    return err(field.pos(), "tables are not supported");
+                }
+                Some(other) => {
+                    return err(field.pos(), format!("unknown module field '{}'", other));
+                }
+                None => return err(field.pos(), "expected a module field"),
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse `(type $t)? (param ...)* (result ...)*` and return the type
+    /// index plus named params and how many items were consumed
+    fn signature(&self, items: &[Sexp]) -> WatResult<(FuncType, HashMap<String, u32>, usize)> {
+        let mut ty = FuncType::default();
+        let mut names = HashMap::new();
+        let mut used = 0;
+        let mut explicit = None;
+
+        for item in items {
+            match item.head() {
+                Some("type") => {
+                    let Some(idx) = list_items(item).get(1) else {
+                        return err(item.pos(), "missing type index");
+                    };
+                    explicit = Some((resolve(&self.type_names, idx, "type")?, item.pos()));
+                }
+                Some("param") => {
+                    let rest = &list_items(item)[1..];
+                    if let Some(id) = rest.first().and_then(Sexp::id) {
+                        if rest.len() != 2 {
+                            return err(item.pos(), "a named param takes exactly one type");
+                        }
+                        names.insert(id.to_string(), ty.params.len() as u32);
+                        ty.params.push(ValType::parse(&rest[1])?);
+                    } else {
+                        for t in rest {
+                            ty.params.push(ValType::parse(t)?);
+                        }
+                    }
+                }
+                Some("result") => {
+                    for t in &list_items(item)[1..] {
+                        ty.results.push(ValType::parse(t)?);
+                    }
+                }
+                _ => break,
+            }
+            used += 1;
+        }
+
+        if let Some((idx, pos)) = explicit {
+            let Some(declared) = self.types.get(idx as usize) else {
+                return err(pos, format!("type index {} out of range", idx));
+            };
+            let inline_given = !ty.params.is_empty() || !ty.results.is_empty();
+            if inline_given && *declared != ty {
+                return err(pos, "inline signature does not match type");
+            }
+            ty = declared.clone();
+        }
+        Ok((ty, names, used))
+    }
+
+    fn type_index(&mut self, ty: FuncType) -> u32 {
+        if let Some(i) = self.types.iter().position(|t| *t == ty) {
+            return i as u32;
+        }
+        self.types.push(ty);
+        (self.types.len() - 1) as u32
+    }
+
+    fn declare_import(&mut self, field: &'a Sexp) -> WatResult<()> {
+        let items = list_items(field);
+        let (Some(module), Some(name), Some(desc)) = (items.get(1), items.get(2), items.get(3))
+        else {
+            return err(field.pos(), "expected (import \"module\" \"name\" (desc))");
+        };
+        let desc_items = list_items(desc);
+        let mut i = 1;
+        let id = desc_items.get(1).filter(|s| s.id().is_some());
+        if id.is_some() {
+            i += 1;
+        }
+        self.push_import(
+            string_of(module)?,
+            string_of(name)?,
+            desc,
+            id,
+            &desc_items[i..],
+        )
+    }
+
+    /// `(func $f (import "m" "n") (param ...))` and the memory equivalent
+    fn declare_inline_import(&mut self, field: &'a Sexp) -> WatResult<()> {
+        let items = list_items(field);
+        let mut i = 1;
+        let id = items.get(1).filter(|s| s.id().is_some());
+        if id.is_some() {
+            i += 1;
+        }
+        let mut export_names = Vec::new();
+        let mut import = None;
+        while let Some(item) = items.get(i) {
+            match item.head() {
+                Some("export") => export_names.push(item),
+                Some("import") => import = Some(item),
+                _ => break,
+            }
+            i += 1;
+        }
+        let Some(import) = import else {
+            return Ok(());
+        };
+        let parts = list_items(import);
+        let (Some(module), Some(name)) = (parts.get(1), parts.get(2)) else {
+            return err(import.pos(), "expected (import \"module\" \"name\")");
+        };
+        let index = match field.head() {
+            Some("func") => self.imported_funcs,
+            _ => self.imported_memories,
+        };
+        self.push_import(string_of(module)?, string_of(name)?, field, id, &items[i..])?;
+        self.inline_exports(field, &export_names, index)
+    }
+
+    fn push_import(
+        &mut self,
+        module: Vec<u8>,
+        name: Vec<u8>,
+        desc: &'a Sexp,
+        id: Option<&'a Sexp>,
+        rest: &'a [Sexp],
+    ) -> WatResult<()> {
+        let desc = match desc.head() {
+            Some("func") => {
+                let (ty, _, used) = self.signature(rest)?;
+                if let Some(extra) = rest.get(used) {
+                    return err(extra.pos(), "imported functions cannot have a body");
+                }
+                bind(&mut self.func_names, id, self.imported_funcs)?;
+                self.imported_funcs += 1;
+                ImportDesc::Func(self.type_index(ty))
+            }
+            Some("memory") => {
+                bind(&mut self.memory_names, id, self.imported_memories)?;
+                self.imported_memories += 1;
+                ImportDesc::Memory(parse_limits(desc, rest)?)
+            }
+            _ => return err(desc.pos(), "only func and memory imports are supported"),
+        };
+        self.imports.push(Import { module, name, desc });
+        Ok(())
+    }
+
+    /// Queue `(export "name")` abbreviations found inside a definition
+    fn inline_exports(
+        &mut self,
+        field: &'a Sexp,
+        exports: &[&'a Sexp],
+        index: u32,
+    ) -> WatResult<()> {
+        let kind = match field.head() {
+            Some("func") => ExportKind::Func,
+            Some("memory") => ExportKind::Memory,
+            _ => ExportKind::Global,
+        };
+        for e in exports {
+            let Some(name) = list_items(e).get(1) else {
+                return err(e.pos(), "missing export name");
+            };
+            self.exports.push((string_of(name)?, kind, index, e.pos()));
+        }
+        Ok(())
+    }
+
+    /// Split `$id? (export "n")*` off the front of a definition
+    fn definition_header(items: &'a [Sexp]) -> (Option<&'a Sexp>, Vec<&'a Sexp>, usize) {
+        let mut i = 1;
+        let id = items.get(1).filter(|s| s.id().is_some());
+        if id.is_some() {
+            i += 1;
+        }
+        let mut exports = Vec::new();
+        while let Some(item) = items.get(i).filter(|s| s.is_list("export")) {
+            exports.push(item);
+            i += 1;
+        }
+        (id, exports, i)
+    }
+
+    fn declare_func(&mut self, field: &'a Sexp) -> WatResult<()> {
+        let items = list_items(field);
+        let (id, exports, mut i) = Self::definition_header(items);
+        let index = self.imported_funcs + self.funcs.len() as u32;
+        bind(&mut self.func_names, id, index)?;
+        self.inline_exports(field, &exports, index)?;
+
+        let (ty, mut local_names, used) = self.signature(&items[i..])?;
+        i += used;
+
+        let mut locals = Vec::new();
+        while let Some(item) = items.get(i).filter(|s| s.is_list("local")) {
+            let rest = &list_items(item)[1..];
+            if let Some(id) = rest.first().and_then(Sexp::id) {
+                if rest.len() != 2 {
+                    return err(item.pos(), "a named local takes exactly one type");
+                }
+                let idx = (ty.params.len() + locals.len()) as u32;
+                if local_names.insert(id.to_string(), idx).is_some() {
+                    return err(item.pos(), format!("duplicate local {}", id));
+                }
+                locals.push(ValType::parse(&rest[1])?);
+            } else {
+                for t in rest {
+                    locals.push(ValType::parse(t)?);
+                }
+            }
+            i += 1;
+        }
+
+        let type_idx = self.type_index(ty);
+        self.funcs.push(FuncDef {
+            type_idx,
+            locals,
+            local_names,
+            body: &items[i..],
+        });
+        Ok(())
+    }
+
+    fn declare_memory(&mut self, field: &'a Sexp) -> WatResult<()> {
+        let items = list_items(field);
+        let (id, exports, i) = Self::definition_header(items);
+        let index = self.imported_memories + self.memories.len() as u32;
+        bind(&mut self.memory_names, id, index)?;
+        self.inline_exports(field, &exports, index)?;
+
+        // `(memory (data "..."))` sizes the memory to fit the data
+        if let Some(data) = items.get(i).filter(|s| s.is_list("data")) {
+            let mut bytes = Vec::new();
+            for s in &list_items(data)[1..] {
+                bytes.extend(string_of(s)?);
+            }
+            let pages = bytes.len().div_ceil(65536) as u32;
+            self.memories.push(Limits {
+                min: pages,
+                max: Some(pages),
+            });
+            self.data.push(DataDef { offset: &[], bytes });
+            return Ok(());
+        }
+
+        self.memories.push(parse_limits(field, &items[i..])?);
+        Ok(())
+    }
+
+    fn declare_global(&mut self, field: &'a Sexp) -> WatResult<()> {
+        let items = list_items(field);
+        let (id, exports, i) = Self::definition_header(items);
+        if items.get(i).is_some_and(|s| s.is_list("import")) {
+            return err(field.pos(), "global imports are not supported");
+        }
+        let index = self.globals.len() as u32;
+        bind(&mut self.global_names, id, index)?;
+        self.inline_exports(field, &exports, index)?;
+
+        let Some(ty) = items.get(i) else {
+            return err(field.pos(), "missing global type");
+        };
+        let (ty, mutable) = if ty.is_list("mut") {
+            match list_items(ty).get(1) {
+                Some(t) => (ValType::parse(t)?, true),
+                None => return err(ty.pos(), "missing type in (mut ...)"),
+            }
+        } else {
+            (ValType::parse(ty)?, false)
+        };
+        self.globals.push(GlobalDef {
+            ty,
+            mutable,
+            init: &items[i + 1..],
+        });
+        Ok(())
+    }
+
+    fn declare_data(&mut self, field: &'a Sexp) -> WatResult<()> {
+        let items = list_items(field);
+        let mut i = 1;
+        if items.get(i).and_then(Sexp::id).is_some() {
+            i += 1;
+        }
+        // Optional memory use: `(memory 0)` or a bare index
+        if let Some(m) = items.get(i) {
+            let mem = if m.is_list("memory") {
+                list_items(m).get(1)
+            } else if m.atom().is_some() {
+                Some(m)
+            } else {
+                None
+            };
+            if let Some(mem) = mem {
+                if resolve(&self.memory_names, mem, "memory")? != 0 {
+                    return err(mem.pos(), "only memory 0 is supported");
+                }
+                i += 1;
+            }
+        }
+        let Some(offset) = items.get(i) else {
+            return err(field.pos(), "passive data segments are not supported");
+        };
+        let offset = if offset.is_list("offset") {
+            &list_items(offset)[1..]
+        } else if matches!(offset, Sexp::List(..)) {
+            std::slice::from_ref(offset)
+        } else {
+            return err(offset.pos(), "expected data offset expression");
+        };
+        let mut bytes = Vec::new();
+        for s in &items[i + 1..] {
+            bytes.extend(string_of(s)?);
+        }
+        self.data.push(DataDef { offset, bytes });
+        Ok(())
+    }
+
+    fn func_count(&self) -> u32 {
+        self.imported_funcs + self.funcs.len() as u32
+    }
+
+    /// Second pass: resolve deferred references and emit the binary
+    fn encode(mut self) -> WatResult<Vec<u8>> {
+        for e in std::mem::take(&mut self.pending_exports) {
+            let (names, limit) = match e.kind {
+                ExportKind::Func => (&self.func_names, self.func_count()),
+                ExportKind::Memory => (
+                    &self.memory_names,
+                    self.imported_memories + self.memories.len() as u32,
+                ),
+                ExportKind::Global => (&self.global_names, self.globals.len() as u32),
+            };
+            let idx = resolve(names, e.target, "export")?;
+            if idx >= limit {
+                return err(e.target.pos(), format!("export index {} out of range", idx));
+            }
+            self.exports.push((e.name, e.kind, idx, e.target.pos()));
+        }
+
+        let mut out = Vec::new();
+        out.extend_from_slice(b"\0asm");
+        out.extend_from_slice(&[1, 0, 0, 0]);
+
+        // Type section
+        let mut sec = Vec::new();
+        write_u32(&mut sec, self.types.len() as u32);
+        for t in &self.types {
+            sec.push(0x60);
+            write_u32(&mut sec, t.params.len() as u32);
+            sec.extend(t.params.iter().map(|v| v.code()));
+            write_u32(&mut sec, t.results.len() as u32);
+            sec.extend(t.results.iter().map(|v| v.code()));
+        }
+        section(&mut out, 1, &sec, !self.types.is_empty());
+
+        // Import section
+        let mut sec = Vec::new();
+        write_u32(&mut sec, self.imports.len() as u32);
+        for imp in &self.imports {
+            write_bytes(&mut sec, &imp.module);
+            write_bytes(&mut sec, &imp.name);
+            match &imp.desc {
+                ImportDesc::Func(t) => {
+                    sec.push(0x00);
+                    write_u32(&mut sec, *t);
+                }
+                ImportDesc::Memory(l) => {
+                    sec.push(0x02);
+                    write_limits(&mut sec, l);
+                }
+            }
+        }
+        section(&mut out, 2, &sec, !self.imports.is_empty());
+
+        // Function section
+        let mut sec = Vec::new();
+        write_u32(&mut sec, self.funcs.len() as u32);
+        for f in &self.funcs {
+            write_u32(&mut sec, f.type_idx);
+        }
+        section(&mut out, 3, &sec, !self.funcs.is_empty());
+
+        // Memory section
+        let mut sec = Vec::new();
+        write_u32(&mut sec, self.memories.len() as u32);
+        for l in &self.memories {
+            write_limits(&mut sec, l);
+        }
+        section(&mut out, 5, &sec, !self.memories.is_empty());
+
+        // Global section
+        let mut sec = Vec::new();
+        write_u32(&mut sec, self.globals.len() as u32);
+        for g in &self.globals {
+            sec.push(g.ty.code());
+            sec.push(g.mutable as u8);
+            let mut ctx = BodyContext::constant(&self);
+            ctx.sequence(g.init)?;
+            sec.extend(ctx.code);
+            sec.push(0x0B);
+        }
+        section(&mut out, 6, &sec, !self.globals.is_empty());
+
+        // Export section
+        let mut sec = Vec::new();
+        write_u32(&mut sec, self.exports.len() as u32);
+        let mut seen = std::collections::HashSet::new();
+        for (name, kind, idx, pos) in &self.exports {
+            if !seen.insert(name.clone()) {
+                return err(
+                    *pos,
+                    format!("duplicate export \"{}\"", String::from_utf8_lossy(name)),
+                );
+            }
+            write_bytes(&mut sec, name);
+            sec.push(*kind as u8);
+            write_u32(&mut sec, *idx);
+        }
+        section(&mut out, 7, &sec, !self.exports.is_empty());
+
+        // Start section
+        if let Some(start) = self.start {
+            let idx = resolve(&self.func_names, start, "function")?;
+            let mut sec = Vec::new();
+            write_u32(&mut sec, idx);
+            section(&mut out, 8, &sec, true);
+        }
+
+        // Code section
+        let mut sec = Vec::new();
+        write_u32(&mut sec, self.funcs.len() as u32);
+        for f in &self.funcs {
+            let mut body = Vec::new();
+            // Run-length encode locals by type
+            let mut groups: Vec<(u32, ValType)> = Vec::new();
+            for &l in &f.locals {
+                match groups.last_mut() {
+                    Some((n, t)) if *t == l => *n += 1,
+                    _ => groups.push((1, l)),
+                }
+            }
+            write_u32(&mut body, groups.len() as u32);
+            for (n, t) in groups {
+                write_u32(&mut body, n);
+                body.push(t.code());
+            }
+
+            let params = self.types[f.type_idx as usize].params.len();
+            let mut ctx = BodyContext {
+                module: &self,
+                locals: Some(&f.local_names),
+                local_count: (params + f.locals.len()) as u32,
+                labels: vec![None],
+                code: Vec::new(),
+            };
+            ctx.sequence(f.body)?;
+            if ctx.labels.len() != 1 {
+                let pos = f.body.last().map(Sexp::pos).unwrap_or((1, 1));
+                return err(pos, "missing 'end' for block");
+            }
+            body.extend(ctx.code);
+            body.push(0x0B);
+
+            write_u32(&mut sec, body.len() as u32);
+            sec.extend(body);
+        }
+        section(&mut out, 10, &sec, !self.funcs.is_empty());
+
+        // Data section
+        let mut sec = Vec::new();
+        write_u32(&mut sec, self.data.len() as u32);
+        for d in &self.data {
+            sec.push(0x00);
+            if d.offset.is_empty() {
+                sec.extend_from_slice(&[0x41, 0x00]);
+            } else {
+                let mut ctx = BodyContext::constant(&self);
+                ctx.sequence(d.offset)?;
+                sec.extend(ctx.code);
+            }
+            sec.push(0x0B);
+            write_bytes(&mut sec, &d.bytes);
+        }
+        section(&mut out, 11, &sec, !self.data.is_empty());
+
+        Ok(out)
+    }
+}
+
+fn parse_limits(field: &Sexp, rest: &[Sexp]) -> WatResult<Limits> {
+    let num = |s: &Sexp| match s.atom().and_then(parse_u32) {
+        Some(n) => Ok(n),
+        None => err(s.pos(), "expected a page count"),
+    };
+    match rest {
+        [min] => Ok(Limits {
+            min: num(min)?,
+            max: None,
+        }),
+        [min, max] => Ok(Limits {
+            min: num(min)?,
+            max: Some(num(max)?),
+        }),
+        _ => err(field.pos(), "expected memory limits: min [max]"),
+    }
+}
+
+// ============ Instructions ============
+
+/// Operand encoding for an instruction
+#[derive(Debug, Clone, Copy)]
+enum Imm {
+    None,
+    Local,
+    Global,
+    Func,
+    Label,
+    LabelTable,
+    I32,
+    I64,
+    F32,
+    F64,
+    /// Memory access with natural alignment (log2 bytes)
+    Mem(u32),
+    /// `memory.size` / `memory.grow` reserved zero byte
+    MemIdx,
+    Block,
+}
+
+/// Instruction name, opcode and immediate kind
+const INSTRUCTIONS: &[(&str, u8, Imm)] = &[
+    ("unreachable", 0x00, Imm::None),
+    ("nop", 0x01, Imm::None),
+    ("block", 0x02, Imm::Block),
+    ("loop", 0x03, Imm::Block),
+    ("if", 0x04, Imm::Block),
+    ("br", 0x0C, Imm::Label),
+    ("br_if", 0x0D, Imm::Label),
+    ("br_table", 0x0E, Imm::LabelTable),
+    ("return", 0x0F, Imm::None),
+    ("call", 0x10, Imm::Func),
+    ("drop", 0x1A, Imm::None),
+    ("select", 0x1B, Imm::None),
+    ("local.get", 0x20, Imm::Local),
+    ("local.set", 0x21, Imm::Local),
+    ("local.tee", 0x22, Imm::Local),
+    ("global.get", 0x23, Imm::Global),
+    ("global.set", 0x24, Imm::Global),
+    ("i32.load", 0x28, Imm::Mem(2)),
+    ("i64.load", 0x29, Imm::Mem(3)),
+    ("f32.load", 0x2A, Imm::Mem(2)),
+    ("f64.load", 0x2B, Imm::Mem(3)),
+    ("i32.load8_s", 0x2C, Imm::Mem(0)),
+    ("i32.load8_u", 0x2D, Imm::Mem(0)),
+    ("i32.load16_s", 0x2E, Imm::Mem(1)),
+    ("i32.load16_u", 0x2F, Imm::Mem(1)),
+    ("i64.load8_s", 0x30, Imm::Mem(0)),
+    ("i64.load8_u", 0x31, Imm::Mem(0)),
+    ("i64.load16_s", 0x32, Imm::Mem(1)),
+    ("i64.load16_u", 0x33, Imm::Mem(1)),
+    ("i64.load32_s", 0x34, Imm::Mem(2)),
+    ("i64.load32_u", 0x35, Imm::Mem(2)),
+    ("i32.store", 0x36, Imm::Mem(2)),
+    ("i64.store", 0x37, Imm::Mem(3)),
+    ("f32.store", 0x38, Imm::Mem(2)),
+    ("f64.store", 0x39, Imm::Mem(3)),
+    ("i32.store8", 0x3A, Imm::Mem(0)),
+    ("i32.store16", 0x3B, Imm::Mem(1)),
+    ("i64.store8", 0x3C, Imm::Mem(0)),
+    ("i64.store16", 0x3D, Imm::Mem(1)),
+    ("i64.store32", 0x3E, Imm::Mem(2)),
+    ("memory.size", 0x3F, Imm::MemIdx),
+    ("memory.grow", 0x40, Imm::MemIdx),
+    ("i32.const", 0x41, Imm::I32),
+    ("i64.const", 0x42, Imm::I64),
+    ("f32.const", 0x43, Imm::F32),
+    ("f64.const", 0x44, Imm::F64),
+    ("i32.eqz", 0x45, Imm::None),
+    ("i32.eq", 0x46, Imm::None),
+    ("i32.ne", 0x47, Imm::None),
+    ("i32.lt_s", 0x48, Imm::None),
+    ("i32.lt_u", 0x49, Imm::None),
+    ("i32.gt_s", 0x4A, Imm::None),
+    ("i32.gt_u", 0x4B, Imm::None),
+    ("i32.le_s", 0x4C, Imm::None),
+    ("i32.le_u", 0x4D, Imm::None),
+    ("i32.ge_s", 0x4E, Imm::None),
+    ("i32.ge_u", 0x4F, Imm::None),
+    ("i64.eqz", 0x50, Imm::None),
+    ("i64.eq", 0x51, Imm::None),
+    ("i64.ne", 0x52, Imm::None),
+    ("i64.lt_s", 0x53, Imm::None),
+    ("i64.lt_u", 0x54, Imm::None),
+    ("i64.gt_s", 0x55, Imm::None),
+    ("i64.gt_u", 0x56, Imm::None),
+    ("i64.le_s", 0x57, Imm::None),
+    ("i64.le_u", 0x58, Imm::None),
+    ("i64.ge_s", 0x59, Imm::None),
+    ("i64.ge_u", 0x5A, Imm::None),
+    ("f32.eq", 0x5B, Imm::None),
+    ("f32.ne", 0x5C, Imm::None),
+    ("f32.lt", 0x5D, Imm::None),
+    ("f32.gt", 0x5E, Imm::None),
+    ("f32.le", 0x5F, Imm::None),
+    ("f32.ge", 0x60, Imm::None),
+    ("f64.eq", 0x61, Imm::None),
+    ("f64.ne", 0x62, Imm::None),
+    ("f64.lt", 0x63, Imm::None),
+    ("f64.gt", 0x64, Imm::None),
+    ("f64.le", 0x65, Imm::None),
+    ("f64.ge", 0x66, Imm::None),
+    ("i32.clz", 0x67, Imm::None),
+    ("i32.ctz", 0x68, Imm::None),
+    ("i32.popcnt", 0x69, Imm::None),
+    ("i32.add", 0x6A, Imm::None),
+    ("i32.sub", 0x6B, Imm::None),
+    ("i32.mul", 0x6C, Imm::None),
+    ("i32.div_s", 0x6D, Imm::None),
+    ("i32.div_u", 0x6E, Imm::None),
+    ("i32.rem_s", 0x6F, Imm::None),
+    ("i32.rem_u", 0x70, Imm::None),
+    ("i32.and", 0x71, Imm::None),
+    ("i32.or", 0x72, Imm::None),
+    ("i32.xor", 0x73, Imm::None),
+    ("i32.shl", 0x74, Imm::None),
+    ("i32.shr_s", 0x75, Imm::None),
+    ("i32.shr_u", 0x76, Imm::None),
+    ("i32.rotl", 0x77, Imm::None),
+    ("i32.rotr", 0x78, Imm::None),
+    ("i64.clz", 0x79, Imm::None),
+    ("i64.ctz", 0x7A, Imm::None),
+    ("i64.popcnt", 0x7B, Imm::None),
+    ("i64.add", 0x7C, Imm::None),
+    ("i64.sub", 0x7D, Imm::None),
+    ("i64.mul", 0x7E, Imm::None),
+    ("i64.div_s", 0x7F, Imm::None),
+    ("i64.div_u", 0x80, Imm::None),
+    ("i64.rem_s", 0x81, Imm::None),
+    ("i64.rem_u", 0x82, Imm::None),
+    ("i64.and", 0x83, Imm::None),
+    ("i64.or", 0x84, Imm::None),
+    ("i64.xor", 0x85, Imm::None),
+    ("i64.shl", 0x86, Imm::None),
+    ("i64.shr_s", 0x87, Imm::None),
+    ("i64.shr_u", 0x88, Imm::None),
+    ("i64.rotl", 0x89, Imm::None),
+    ("i64.rotr", 0x8A, Imm::None),
+    ("f32.abs", 0x8B, Imm::None),
+    ("f32.neg", 0x8C, Imm::None),
+    ("f32.ceil", 0x8D, Imm::None),
+    ("f32.floor", 0x8E, Imm::None),
+    ("f32.trunc", 0x8F, Imm::None),
+    ("f32.nearest", 0x90, Imm::None),
+    ("f32.sqrt", 0x91, Imm::None),
+    ("f32.add", 0x92, Imm::None),
+    ("f32.sub", 0x93, Imm::None),
+    ("f32.mul", 0x94, Imm::None),
+    ("f32.div", 0x95, Imm::None),
+    ("f32.min", 0x96, Imm::None),
+    ("f32.max", 0x97, Imm::None),
+    ("f32.copysign", 0x98, Imm::None),
+    ("f64.abs", 0x99, Imm::None),
+    ("f64.neg", 0x9A, Imm::None),
+    ("f64.ceil", 0x9B, Imm::None),
+    ("f64.floor", 0x9C, Imm::None),
+    ("f64.trunc", 0x9D, Imm::None),
+    ("f64.nearest", 0x9E, Imm::None),
+    ("f64.sqrt", 0x9F, Imm::None),
+    ("f64.add", 0xA0, Imm::None),
+    ("f64.sub", 0xA1, Imm::None),
+    ("f64.mul", 0xA2, Imm::None),
+    ("f64.div", 0xA3, Imm::None),
+    ("f64.min", 0xA4, Imm::None),
+    ("f64.max", 0xA5, Imm::None),
+    ("f64.copysign", 0xA6, Imm::None),
+    ("i32.wrap_i64", 0xA7, Imm::None),
+    ("i32.trunc_f32_s", 0xA8, Imm::None),
+    ("i32.trunc_f32_u", 0xA9, Imm::None),
+    ("i32.trunc_f64_s", 0xAA, Imm::None),
+    ("i32.trunc_f64_u", 0xAB, Imm::None),
+    ("i64.extend_i32_s", 0xAC, Imm::None),
+    ("i64.extend_i32_u", 0xAD, Imm::None),
+    ("i64.trunc_f32_s", 0xAE, Imm::None),
+    ("i64.trunc_f32_u", 0xAF, Imm::None),
+    ("i64.trunc_f64_s", 0xB0, Imm::None),
+    ("i64.trunc_f64_u", 0xB1, Imm::None),
+    ("f32.convert_i32_s", 0xB2, Imm::None),
+    ("f32.convert_i32_u", 0xB3, Imm::None),
+    ("f32.convert_i64_s", 0xB4, Imm::None),
+    ("f32.convert_i64_u", 0xB5, Imm::None),
+    ("f32.demote_f64", 0xB6, Imm::None),
+    ("f64.convert_i32_s", 0xB7, Imm::None),
+    ("f64.convert_i32_u", 0xB8, Imm::None),
+    ("f64.convert_i64_s", 0xB9, Imm::None),
+    ("f64.convert_i64_u", 0xBA, Imm::None),
+    ("f64.promote_f32", 0xBB, Imm::None),
+    ("i32.reinterpret_f32", 0xBC, Imm::None),
+    ("i64.reinterpret_f64", 0xBD, Imm::None),
+    ("f32.reinterpret_i32", 0xBE, Imm::None),
+    ("f64.reinterpret_i64", 0xBF, Imm::None),
+    ("i32.extend8_s", 0xC0, Imm::None),
+    ("i32.extend16_s", 0xC1, Imm::None),
+    ("i64.extend8_s", 0xC2, Imm::None),
+    ("i64.extend16_s", 0xC3, Imm::None),
+    ("i64.extend32_s", 0xC4, Imm::None),
+];
+
+fn lookup(name: &str) -> Option<(u8, Imm)> {
+    INSTRUCTIONS
+        .iter()
+        .find(|(n, _, _)| *n == name)
+        .map(|&(_, op, imm)| (op, imm))
+}
+
+/// Encoder state for one function body or constant expression
+struct BodyContext<'m, 'a> {
+    module: &'m Module<'a>,
+    /// Local names; `None` in constant expressions
+    locals: Option<&'m HashMap<String, u32>>,
+    local_count: u32,
+    /// Enclosing block labels, innermost last; index 0 is the function
+    labels: Vec<Option<String>>,
+    code: Vec<u8>,
+}
+
+impl<'m, 'a> BodyContext<'m, 'a> {
+    fn constant(module: &'m Module<'a>) -> Self {
+        Self {
+            module,
+            locals: None,
+            local_count: 0,
+            labels: vec![None],
+            code: Vec::new(),
+        }
+    }
+
+    /// Encode a sequence of flat and folded instructions
+    fn sequence(&mut self, items: &[Sexp]) -> WatResult<()> {
+        let mut i = 0;
+        while i < items.len() {
+            i = self.instruction(items, i)?;
+        }
+        Ok(())
+    }
+
+    /// Encode the instruction starting at `items[i]`, returning the index
+    /// of the next unconsumed item
+    fn instruction(&mut self, items: &[Sexp], i: usize) -> WatResult<usize> {
+        let item = &items[i];
+        match item {
+            Sexp::List(..) => {
+                self.folded(item)?;
+                Ok(i + 1)
+            }
+            Sexp::Str(..) => err(item.pos(), "unexpected string in code"),
+            Sexp::Atom(name, pos) => {
+                let mut next = i + 1;
+                match name.as_str() {
+                    "else" => {
+                        self.code.push(0x05);
+                        next = self.skip_label(items, next);
+                    }
+                    "end" => {
+                        if self.labels.len() < 2 {
+                            return err(*pos, "'end' without matching block");
+                        }
+                        self.labels.pop();
+                        self.code.push(0x0B);
+                        next = self.skip_label(items, next);
+                    }
+                    _ => {
+                        let Some((op, imm)) = lookup(name) else {
+                            return err(*pos, format!("unknown instruction '{}'", name));
+                        };
+                        self.code.push(op);
+                        next = self.immediates(imm, item, items, next)?;
+                    }
+                }
+                Ok(next)
+            }
+        }
+    }
+
+    /// Skip the optional `$label` after `else`/`end`
+    fn skip_label(&self, items: &[Sexp], i: usize) -> usize {
+        if items.get(i).and_then(Sexp::id).is_some() {
+            i + 1
+        } else {
+            i
+        }
+    }
+
+    /// Encode `(op imm* operand*)`, `(block ...)`, `(loop ...)`, `(if ...)`
+    fn folded(&mut self, list: &Sexp) -> WatResult<()> {
+        let items = list_items(list);
+        let Some(name) = items.first().and_then(Sexp::atom) else {
+            return err(list.pos(), "expected an instruction");
+        };
+        let Some((op, imm)) = lookup(name) else {
+            return err(list.pos(), format!("unknown instruction '{}'", name));
+        };
+
+        match (name, imm) {
+            ("if", _) => {
+                let mut i = 1;
+                let label = items.get(i).and_then(Sexp::id).map(str::to_string);
+                if label.is_some() {
+                    i += 1;
+                }
+                let (bt, used) = self.block_type(&items[i..])?;
+                i += used;
+                let mut then = None;
+                let mut els = None;
+                for item in &items[i..] {
+                    match item.head() {
+                        Some("then") => then = Some(item),
+                        Some("else") => els = Some(item),
+                        _ if then.is_none() => self.folded(item)?,
+                        _ => return err(item.pos(), "unexpected item after (then ...)"),
+                    }
+                }
+                let Some(then) = then else {
+                    return err(list.pos(), "folded 'if' requires (then ...)");
+                };
+                self.code.push(op);
+                self.code.extend(bt);
+                self.labels.push(label);
+                self.sequence(&list_items(then)[1..])?;
+                if let Some(els) = els {
+                    self.code.push(0x05);
+                    self.sequence(&list_items(els)[1..])?;
+                }
+                self.labels.pop();
+                self.code.push(0x0B);
+            }
+            (_, Imm::Block) => {
+                self.code.push(op);
+                let next = self.immediates(imm, list, items, 1)?;
+                self.sequence(&items[next..])?;
+                self.labels.pop();
+                self.code.push(0x0B);
+            }
+            _ => {
+                // Immediates are the atoms right after the opcode; the
+                // remaining lists are operands, which are evaluated first
+                let start = self.code.len();
+                self.code.push(op);
+                let next = self.immediates(imm, list, items, 1)?;
+                let instr = self.code.split_off(start);
+                for operand in &items[next..] {
+                    if !matches!(operand, Sexp::List(..)) {
+                        return err(operand.pos(), "expected a folded operand");
+                    }
+                    self.folded(operand)?;
+                }
+                self.code.extend(instr);
+            }
+        }
+        Ok(())
+    }
+
+    /// `(result t)*` or `(type n)` after block/loop/if
+    fn block_type(&self, items: &[Sexp]) -> WatResult<(Vec<u8>, usize)> {
+        let mut results = Vec::new();
+        let mut used = 0;
+        for item in items {
+            match item.head() {
+                Some("result") => {
+                    for t in &list_items(item)[1..] {
+                        results.push(ValType::parse(t)?);
+                    }
+                }
+                Some("type") | Some("param") => {
+                    return err(item.pos(), "multi-value block types are not supported");
+                }
+                _ => break,
+            }
+            used += 1;
+        }
+        match results.as_slice() {
+            [] => Ok((vec![0x40], used)),
+            [t] => Ok((vec![t.code()], used)),
+            _ => err(items[0].pos(), "blocks may have at most one result"),
+        }
+    }
+
+    /// Encode immediates for `imm` from `items[i..]`, returning the next index
+    fn immediates(
+        &mut self,
+        imm: Imm,
+        at: &Sexp,
+        items: &[Sexp],
+        mut i: usize,
+    ) -> WatResult<usize> {
+        let mut operand = |what: &str| match items.get(i) {
+            Some(s @ Sexp::Atom(..)) => {
+                i += 1;
+                Ok(s)
+            }
+            _ => err(at.pos(), format!("missing {}", what)),
+        };
+
+        match imm {
+            Imm::None => {}
+            Imm::MemIdx => self.code.push(0x00),
+            Imm::Local => {
+                let s = operand("local index")?;
+                let Some(locals) = self.locals else {
+                    return err(s.pos(), "locals are not allowed in constant expressions");
+                };
+                let idx = resolve(locals, s, "local")?;
+                if idx >= self.local_count {
+                    return err(s.pos(), format!("local index {} out of range", idx));
+                }
+                write_u32(&mut self.code, idx);
+            }
+            Imm::Global => {
+                let s = operand("global index")?;
+                let idx = resolve(&self.module.global_names, s, "global")?;
+                if idx as usize >= self.module.globals.len() {
+                    return err(s.pos(), format!("global index {} out of range", idx));
+                }
+                write_u32(&mut self.code, idx);
+            }
+            Imm::Func => {
+                let s = operand("function index")?;
+                let idx = resolve(&self.module.func_names, s, "function")?;
+                if idx >= self.module.func_count() {
+                    return err(s.pos(), format!("function index {} out of range", idx));
+                }
+                write_u32(&mut self.code, idx);
+            }
+            Imm::Label => {
+                let s = operand("label")?;
+                let depth = self.label_depth(s)?;
+                write_u32(&mut self.code, depth);
+            }
+            Imm::LabelTable => {
+                let mut depths = Vec::new();
+                while let Some(s) = items.get(i).filter(|s| s.atom().is_some()) {
+                    let is_label = s.id().is_some() || s.atom().and_then(parse_u32).is_some();
+                    if !is_label {
+                        break;
+                    }
+                    depths.push(self.label_depth(s)?);
+                    i += 1;
+                }
+                let Some(default) = depths.pop() else {
+                    return err(at.pos(), "br_table needs at least a default label");
+                };
+                write_u32(&mut self.code, depths.len() as u32);
+                for d in depths {
+                    write_u32(&mut self.code, d);
+                }
+                write_u32(&mut self.code, default);
+            }
+            Imm::I32 => {
+                let s = operand("i32 literal")?;
+                let Some(v) = s
+                    .atom()
+                    .and_then(parse_int)
+                    .filter(|v| *v >= i128::from(i32::MIN) && *v <= i128::from(u32::MAX))
+                else {
+                    return err(s.pos(), "invalid i32 literal");
+                };
+                write_i64(&mut self.code, i64::from(v as u32 as i32));
+            }
+            Imm::I64 => {
+                let s = operand("i64 literal")?;
+                let Some(v) = s
+                    .atom()
+                    .and_then(parse_int)
+                    .filter(|v| *v >= i128::from(i64::MIN) && *v <= i128::from(u64::MAX))
+                else {
+                    return err(s.pos(), "invalid i64 literal");
+                };
+                write_i64(&mut self.code, v as u64 as i64);
+            }
+            Imm::F32 => {
+                let s = operand("f32 literal")?;
+                let Some(v) = s.atom().and_then(parse_float) else {
+                    return err(s.pos(), "invalid f32 literal");
+                };
+                self.code.extend_from_slice(&(v as f32).to_le_bytes());
+            }
+            Imm::F64 => {
+                let s = operand("f64 literal")?;
+                let Some(v) = s.atom().and_then(parse_float) else {
+                    return err(s.pos(), "invalid f64 literal");
+                };
+                self.code.extend_from_slice(&v.to_le_bytes());
+            }
+            Imm::Mem(natural) => {
+                let mut offset = 0;
+                let mut align = natural;
+                while let Some(s) = items.get(i) {
+                    let Some(a) = s.atom() else { break };
+                    if let Some(v) = a.strip_prefix("offset=") {
+                        let Some(v) = parse_u32(v) else {
+                            return err(s.pos(), "invalid offset");
+                        };
+                        offset = v;
+                    } else if let Some(v) = a.strip_prefix("align=") {
+                        let Some(v) = parse_u32(v).filter(|v| v.is_power_of_two()) else {
+                            return err(s.pos(), "alignment must be a power of two");
+                        };
+                        align = v.trailing_zeros();
+                        if align > natural {
+                            return err(s.pos(), "alignment larger than natural");
+                        }
+                    } else {
+                        break;
+                    }
+                    i += 1;
+                }
+                write_u32(&mut self.code, align);
+                write_u32(&mut self.code, offset);
+            }
+            Imm::Block => {
+                let label = items.get(i).and_then(Sexp::id).map(str::to_string);
+                if label.is_some() {
+                    i += 1;
+                }
+                let (bt, used) = self.block_type(&items[i..])?;
+                i += used;
+                self.code.extend(bt);
+                self.labels.push(label);
+            }
+        }
+        Ok(i)
+    }
+
+    fn label_depth(&self, s: &Sexp) -> WatResult<u32> {
+        if let Some(id) = s.id() {
+            return match self
+                .labels
+                .iter()
+                .rev()
+                .position(|l| l.as_deref() == Some(id))
+            {
+                Some(d) => Ok(d as u32),
+                None => err(s.pos(), format!("unknown label {}", id)),
+            };
+        }
+        match s.atom().and_then(parse_u32) {
+            Some(d) if (d as usize) < self.labels.len() => Ok(d),
+            Some(d) => err(s.pos(), format!("label depth {} out of range", d)),
+            None => err(s.pos(), "expected a label"),
+        }
+    }
+}
+
+// ============ Literals and encoding ============
+
+fn parse_u32(s: &str) -> Option<u32> {
+    parse_int(s).and_then(|v| u32::try_from(v).ok())
+}
+
+/// Parse a decimal or `0x` integer with optional sign and `_` separators
+fn parse_int(s: &str) -> Option<i128> {
+    let (neg, digits) = match s.as_bytes().first()? {
+        b'-' => (true, &s[1..]),
+        b'+' => (false, &s[1..]),
+        _ => (false, s),
+    };
+    let cleaned: String = digits.chars().filter(|&c| c != '_').collect();
+    if cleaned.is_empty() || digits.starts_with('_') || digits.ends_with('_') {
+        return None;
+    }
+    let v = match cleaned.strip_prefix("0x") {
+        Some(hex) => i128::from_str_radix(hex, 16).ok()?,
+        None => cleaned.parse::<i128>().ok()?,
+    };
+    Some(if neg { -v } else { v })
+}
+
+fn parse_float(s: &str) -> Option<f64> {
+    let cleaned: String = s.chars().filter(|&c| c != '_').collect();
+    let (neg, body) = match cleaned.as_bytes().first()? {
+        b'-' => (true, &cleaned[1..]),
+        b'+' => (false, &cleaned[1..]),
+        _ => (false, cleaned.as_str()),
+    };
+    let v = match body {
+        "inf" => f64::INFINITY,
+        "nan" => f64::NAN,
+        _ if body.starts_with("0x") => parse_int(body)? as f64,
+        _ => body.parse::<f64>().ok()?,
+    };
+    Some(if neg { -v } else { v })
+}
+
+fn write_u32(out: &mut Vec<u8>, mut v: u32) {
+    loop {
+        let byte = (v & 0x7F) as u8;
+        v >>= 7;
+        if v == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn write_i64(out: &mut Vec<u8>, mut v: i64) {
+    loop {
+        let byte = (v & 0x7F) as u8;
+        v >>= 7;
+        let done = (v == 0 && byte & 0x40 == 0) || (v == -1 && byte & 0x40 != 0);
+        if done {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_u32(out, bytes.len() as u32);
+    out.extend_from_slice(bytes);
+}
+
+fn write_limits(out: &mut Vec<u8>, l: &Limits) {
+    match l.max {
+        Some(max) => {
+            out.push(0x01);
+            write_u32(out, l.min);
+            write_u32(out, max);
+        }
+        None => {
+            out.push(0x00);
+            write_u32(out, l.min);
+        }
+    }
+}
+
+fn section(out: &mut Vec<u8>, id: u8, contents: &[u8], present: bool) {
+    if present {
+        out.push(id);
+        write_bytes(out, contents);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Strip the header and return (section id, contents) pairs
+    fn sections(module: &[u8]) -> Vec<(u8, Vec<u8>)> {
+        assert_eq!(&module[..8], b"\0asm\x01\0\0\0");
+        let mut out = Vec::new();
+        let mut i = 8;
+        while i < module.len() {
+            let id = module[i];
+            let len = module[i + 1] as usize;
+            assert!(len < 0x80, "test modules keep sections short");
+            out.push((id, module[i + 2..i + 2 + len].to_vec()));
+            i += 2 + len;
+        }
+        out
+    }
+
+    fn code_of(module: &[u8]) -> Vec<u8> {
+        let (_, code) = sections(module)
+            .into_iter()
+            .find(|(id, _)| *id == 10)
+            .unwrap();
+        // count, body size, local groups (assumed none)
+        assert_eq!(code[2], 0);
+        code[3..].to_vec()
+    }
+
+    #[test]
+    fn test_empty_module() {
+        assert_eq!(assemble("(module)").unwrap(), b"\0asm\x01\0\0\0");
+        assert_eq!(assemble(";; nothing\n").unwrap(), b"\0asm\x01\0\0\0");
+    }
+
+    #[test]
+    fn test_flat_and_folded_encode_identically() {
+        let flat = assemble("(func (param i32) (result i32) local.get 0 i32.const 1 i32.add)");
+        let folded =
+            assemble("(func (param $x i32) (result i32) (i32.add (local.get $x) (i32.const 1)))");
+        assert_eq!(flat.unwrap(), folded.unwrap());
+    }
+
+    #[test]
+    fn test_signed_leb_constants() {
+        let m = assemble(
+            "(func i32.const -1 drop i32.const 64 drop i64.const 0xffffffffffffffff drop)",
+        )
+        .unwrap();
+        assert_eq!(
+            code_of(&m),
+            [
+                0x41, 0x7F, 0x1A, 0x41, 0xC0, 0x00, 0x1A, 0x42, 0x7F, 0x1A, 0x0B
+            ]
+        );
+        assert!(assemble("(func i32.const 0x1_0000_0000 drop)").is_err());
+    }
+
+    #[test]
+    fn test_labels_resolve_to_depths() {
+        let m = assemble(
+            "(func $f
+               (block $out
+                 (loop $top
+                   br $top
+                   br_if $out
+                   br_table $top $out 0)))",
+        )
+        .unwrap();
+        assert_eq!(
+            code_of(&m),
+            [
+                0x02, 0x40, 0x03, 0x40, 0x0C, 0x00, 0x0D, 0x01, 0x0E, 0x02, 0x00, 0x01, 0x00, 0x0B,
+                0x0B, 0x0B
+            ]
+        );
+        assert!(assemble("(func br $nope)").is_err());
+    }
+
+    #[test]
+    fn test_folded_if_then_else() {
+        let m = assemble(
+            "(func (param i32) (result i32)
+               (if (result i32) (local.get 0)
+                 (then (i32.const 1))
+                 (else (i32.const 2))))",
+        )
+        .unwrap();
+        assert_eq!(
+            code_of(&m),
+            [
+                0x20, 0x00, 0x04, 0x7F, 0x41, 0x01, 0x05, 0x41, 0x02, 0x0B, 0x0B
+            ]
+        );
+    }
+
+    #[test]
+    fn test_memarg_defaults_and_overrides() {
+        let m = assemble("(memory 1) (func i32.const 0 i32.load offset=8 align=1 drop i32.const 0 i64.load8_u drop)")
+            .unwrap();
+        assert_eq!(
+            code_of(&m),
+            [
+                0x41, 0x00, 0x28, 0x00, 0x08, 0x1A, 0x41, 0x00, 0x31, 0x00, 0x00, 0x1A, 0x0B
+            ]
+        );
+        assert!(assemble("(func i32.const 0 i32.load8_u align=2 drop)").is_err());
+    }
+
+    #[test]
+    fn test_imports_come_first_in_index_space() {
+        let m = assemble(
+            r#"(func $main (export "main") call $exit)
+               (import "env" "exit" (func $exit (param i32)))"#,
+        )
+        .unwrap();
+        let secs = sections(&m);
+        let exports = &secs.iter().find(|(id, _)| *id == 7).unwrap().1;
+        // "main" exports function 1 because the import takes index 0
+        assert_eq!(exports, &[0x01, 0x04, b'm', b'a', b'i', b'n', 0x00, 0x01]);
+        assert_eq!(code_of(&m), [0x10, 0x00, 0x0B]);
+    }
+
+    #[test]
+    fn test_data_and_strings() {
+        let m = assemble(r#"(memory 1) (data (i32.const 8) "a\n" "\00\u{e9}")"#).unwrap();
+        let data = &sections(&m)
+            .into_iter()
+            .find(|(id, _)| *id == 11)
+            .unwrap()
+            .1;
+        assert_eq!(
+            data,
+            &[
+                0x01, 0x00, 0x41, 0x08, 0x0B, 0x05, b'a', b'\n', 0x00, 0xC3, 0xA9
+            ]
+        );
+    }
+
+    #[test]
+    fn test_globals_and_locals() {
+        let m = assemble(
+            "(global $g (mut i32) (i32.const 5))
+             (func (local $a i32) (local i64 i64)
+               (local.set $a (global.get $g))
+               (global.set $g (local.get $a)))",
+        )
+        .unwrap();
+        let secs = sections(&m);
+        let globals = &secs.iter().find(|(id, _)| *id == 6).unwrap().1;
+        assert_eq!(globals, &[0x01, 0x7F, 0x01, 0x41, 0x05, 0x0B]);
+        let code = &secs.iter().find(|(id, _)| *id == 10).unwrap().1;
+        assert_eq!(
+            &code[2..],
+            &[
+                0x02, 0x01, 0x7F, 0x02, 0x7E, 0x23, 0x00, 0x21, 0x00, 0x20, 0x00, 0x24, 0x00, 0x0B
+            ]
+        );
+    }
+
+    #[test]
+    fn test_error_positions() {
+        let e = assemble("(module\n  (func\n    local.get $missing))").unwrap_err();
+        assert_eq!((e.line, e.column), (3, 15));
+        assert!(e.message.contains("unknown local $missing"));
+
+        let e = assemble("(module (func)").unwrap_err();
+        assert_eq!((e.line, e.column), (1, 1));
+
+        let e = assemble("(func $a) (func $a)").unwrap_err();
+        assert!(e.message.contains("duplicate identifier $a"));
+
+        assert!(assemble("(table 1 funcref)").is_err());
+        assert!(assemble("(func block)").is_err());
+        assert!(assemble("(func end)").is_err());
+    }
+
+    #[test]
+    fn test_block_comments_nest() {
+        let m = assemble("(; outer (; inner ;) still ;) (module)").unwrap();
+        assert_eq!(m.len(), 8);
+        assert!(assemble("(; open").is_err());
+    }
+}
@@ -0,0 +1,103 @@
+//! Input idle tracking
+//!
+//! The platform reports every key or pointer event with [`IdleMonitor::input`]
+//! and polls [`IdleMonitor::expired`] with its own clock. Times are in
+//! milliseconds from whatever monotonic clock the platform uses
+//! (`performance.now()` in the browser); the monitor only compares them.
+
+/// Default idle time before the session locks (15 minutes)
+pub const DEFAULT_IDLE_TIMEOUT_MS: f64 = 15.0 * 60.0 * 1000.0;
+
+/// Tracks how long it has been since the last input event
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdleMonitor {
+    /// Time of the last input event
+    last_input: f64,
+    /// Latest time the platform reported
+    last_seen: f64,
+    /// Idle time after which the monitor expires (None = never)
+    timeout: Option<f64>,
+}
+
+impl IdleMonitor {
+    /// Create a monitor that expires after `timeout` ms without input
+    pub fn new(timeout: Option<f64>) -> Self {
+        Self {
+            last_input: 0.0,
+            last_seen: 0.0,
+            timeout,
+        }
+    }
+
+    /// Record an input event
+    pub fn input(&mut self, now: f64) {
+        self.last_input = now;
+        self.last_seen = self.last_seen.max(now);
+    }
+
+    /// Milliseconds without input, as of the latest reported time
+    pub fn idle_ms(&self) -> f64 {
+        (self.last_seen - self.last_input).max(0.0)
+    }
+
+    /// Latest time the platform reported
+    pub fn now(&self) -> f64 {
+        self.last_seen
+    }
+
+    /// The idle timeout, if auto-lock is enabled
+    pub fn timeout(&self) -> Option<f64> {
+        self.timeout
+    }
+
+    /// Change the idle timeout; `None` disables it
+    ///
+    /// The idle clock restarts so a shorter timeout doesn't fire at once.
+    pub fn set_timeout(&mut self, timeout: Option<f64>) {
+        self.timeout = timeout;
+        self.reset();
+    }
+
+    /// Restart the idle clock at the latest reported time
+    pub fn reset(&mut self) {
+        self.last_input = self.last_seen;
+    }
+
+    /// Report the current time and check if the timeout has passed
+    pub fn expired(&mut self, now: f64) -> bool {
+        self.last_seen = self.last_seen.max(now);
+        self.timeout.is_some_and(|t| self.idle_ms() >= t)
+    }
+}
+
+impl Default for IdleMonitor {
+    fn default() -> Self {
+        Self::new(Some(DEFAULT_IDLE_TIMEOUT_MS))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_idle_timeout() {
+        let mut idle = IdleMonitor::new(Some(1000.0));
+        idle.input(100.0);
+        assert!(!idle.expired(900.0));
+        assert_eq!(idle.idle_ms(), 800.0);
+        assert!(idle.expired(1100.0));
+
+        // Input resets the clock
+        idle.input(1200.0);
+        assert!(!idle.expired(1500.0));
+
+        // Shortening the timeout restarts the idle clock
+        idle.set_timeout(Some(100.0));
+        assert!(!idle.expired(1550.0));
+        assert!(idle.expired(1600.0));
+
+        idle.set_timeout(None);
+        assert!(!idle.expired(1_000_000.0));
+    }
+}
@@ -10,6 +10,8 @@
 //! The kernel and shell are platform-agnostic. Only the Platform implementation
 //! knows about the host environment.
 
+pub mod idle;
+
 #[cfg(target_arch = "wasm32")]
 #[cfg(target_os = "unknown")] // Browser WASM (no WASI)
 pub mod web;
@@ -117,3 +119,12 @@ pub trait AsyncPlatform: Platform {
     /// Initialize the platform asynchronously
     fn init(&mut self) -> impl std::future::Future<Output = PlatformResult<()>>;
 }
+
+/// Fill `buf` from the host's entropy source: `crypto.getRandomValues` in
+/// the browser, `random_get` under WASI and the OS generator natively
+///
+/// Only the kernel's generator ([`crate::kernel::random`]) reads it, to
+/// seed itself; everything else draws from that.
+pub fn entropy(buf: &mut [u8]) -> PlatformResult<()> {
+    getrandom::fill(buf).map_err(|e| PlatformError::NotSupported(format!("entropy source: {}", e)))
+}
@@ -0,0 +1,706 @@
+//! Declarative command specs
+//!
+//! A [`CommandSpec`] describes a program's flags and operands once. The
+//! program parses its arguments with it, and its `--help`, its manual page
+//! (for commands without a hand-written one) and its tab completion all
+//! come from the same description, so they can't drift apart.
+//!
+//! Parsing follows the usual conventions: short flags can be grouped
+//! (`-rf`), a flag's value can follow it or be attached (`-n 5`, `-n5`,
+//! `--lines=5`), flags and operands can be mixed, and `--` ends the flags.
+//! `-` on its own is an operand, and so is `-5` unless the command has a
+//! digit flag. Words from a [`Arg::raw`] operand on are operands whatever
+//! they look like, for commands that take an expression or another
+//! command's arguments.
+
+use std::fmt;
+
+/// What a command looks like from the command line
+pub struct CommandSpec {
+    pub name: &'static str,
+    /// One line, for `help` and the NAME section of the manual
+    pub summary: &'static str,
+    /// Paragraphs (separated by blank lines) for `--help` and the manual
+    pub description: &'static str,
+    pub flags: &'static [Flag],
+    pub args: &'static [Arg],
+}
+
+/// A flag, with a short form, a long form or both
+pub struct Flag {
+    pub short: Option<char>,
+    pub long: Option<&'static str>,
+    /// Name of the flag's value, if it takes one
+    pub value: Option<&'static str>,
+    pub help: &'static str,
+}
+
+impl Flag {
+    /// `-c` and `--long`
+    pub const fn new(short: char, long: &'static str, help: &'static str) -> Self {
+        Self {
+            short: Some(short),
+            long: Some(long),
+            value: None,
+            help,
+        }
+    }
+
+    /// `-c` only
+    pub const fn short(short: char, help: &'static str) -> Self {
+        Self {
+            short: Some(short),
+            long: None,
+            value: None,
+            help,
+        }
+    }
+
+    /// `--long` only
+    pub const fn long(long: &'static str, help: &'static str) -> Self {
+        Self {
+            short: None,
+            long: Some(long),
+            value: None,
+            help,
+        }
+    }
+
+    /// The flag takes a value, shown as `name`
+    pub const fn value(mut self, name: &'static str) -> Self {
+        self.value = Some(name);
+        self
+    }
+
+    /// Whether `name` is this flag's short letter or long name
+    fn is(&self, name: &str) -> bool {
+        self.long == Some(name)
+            || self
+                .short
+                .is_some_and(|c| name.len() == 1 && name.starts_with(c))
+    }
+
+    /// `-c, --long VALUE`, as help and the manual list it
+    fn label(&self) -> String {
+        let mut label = match (self.short, self.long) {
+            (Some(c), Some(long)) => format!("-{}, --{}", c, long),
+            (Some(c), None) => format!("-{}", c),
+            (None, Some(long)) => format!("--{}", long),
+            (None, None) => String::new(),
+        };
+        if let Some(value) = self.value {
+            label.push(' ');
+            label.push_str(value);
+        }
+        label
+    }
+}
+
+/// An operand
+pub struct Arg {
+    pub name: &'static str,
+    pub kind: ArgKind,
+    pub required: bool,
+    /// Takes every operand the others leave; at most one can be
+    pub repeated: bool,
+    /// This operand and every word after it are operands, even ones that
+    /// look like flags
+    pub raw: bool,
+}
+
+impl Arg {
+    /// A single required operand
+    pub const fn new(name: &'static str, kind: ArgKind) -> Self {
+        Self {
+            name,
+            kind,
+            required: true,
+            repeated: false,
+            raw: false,
+        }
+    }
+
+    /// The operand can be left out
+    pub const fn optional(mut self) -> Self {
+        self.required = false;
+        self
+    }
+
+    /// The operand takes every one the others leave over, as in
+    /// `SOURCE... DEST`
+    pub const fn repeated(mut self) -> Self {
+        self.repeated = true;
+        self
+    }
+
+    /// Flags end at this operand, as in `sudo COMMAND [ARG]...`; it
+    /// should be the last
+    pub const fn raw(mut self) -> Self {
+        self.raw = true;
+        self
+    }
+
+    /// `NAME`, `[NAME]`, `NAME...` or `[NAME]...`
+    fn synopsis(&self) -> String {
+        match (self.required, self.repeated) {
+            (true, false) => self.name.to_string(),
+            (true, true) => format!("{}...", self.name),
+            (false, false) => format!("[{}]", self.name),
+            (false, true) => format!("[{}]...", self.name),
+        }
+    }
+}
+
+/// What an operand names, for completion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgKind {
+    /// A file or directory
+    Path,
+    /// A directory
+    Dir,
+    /// A command name
+    Command,
+    /// Anything else
+    Text,
+}
+
+/// Why parsing stopped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArgError {
+    /// `-h` or `--help` was given (`-h` only if the command has no `-h`)
+    Help,
+    UnknownFlag(String),
+    MissingValue(String),
+    UnexpectedValue(String),
+    MissingOperand(&'static str),
+    ExtraOperand(String),
+}
+
+impl fmt::Display for ArgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgError::Help => write!(f, "help requested"),
+            ArgError::UnknownFlag(flag) => write!(f, "unknown option: {}", flag),
+            ArgError::MissingValue(flag) => write!(f, "option {} needs a value", flag),
+            ArgError::UnexpectedValue(flag) => write!(f, "option {} takes no value", flag),
+            ArgError::MissingOperand(name) => write!(f, "missing operand: expected {}", name),
+            ArgError::ExtraOperand(arg) => write!(f, "extra operand '{}'", arg),
+        }
+    }
+}
+
+impl ArgError {
+    /// Write the help for [`ArgError::Help`], or the error and usage line,
+    /// and return the exit status
+    pub fn report(&self, spec: &CommandSpec, stdout: &mut String, stderr: &mut String) -> i32 {
+        if *self == ArgError::Help {
+            stdout.push_str(&spec.help());
+            return 0;
+        }
+        stderr.push_str(&format!("{}: {}\n{}\n", spec.name, self, spec.usage()));
+        1
+    }
+}
+
+/// Parsed arguments
+pub struct Matches<'a> {
+    spec: &'a CommandSpec,
+    /// Flags given, in order, by index into [`CommandSpec::flags`]
+    flags: Vec<(usize, Option<String>)>,
+    /// Operands, in order
+    pub operands: Vec<String>,
+}
+
+impl Matches<'_> {
+    /// Whether flag `name` (short letter or long name) was given
+    pub fn flag(&self, name: &str) -> bool {
+        self.count(name) > 0
+    }
+
+    /// How many times flag `name` was given
+    pub fn count(&self, name: &str) -> usize {
+        self.flags
+            .iter()
+            .filter(|(i, _)| self.spec.flags[*i].is(name))
+            .count()
+    }
+
+    /// The last value given for flag `name`
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.values(name).last().copied()
+    }
+
+    /// Every value given for flag `name`, in order
+    pub fn values(&self, name: &str) -> Vec<&str> {
+        self.flags
+            .iter()
+            .filter(|(i, _)| self.spec.flags[*i].is(name))
+            .filter_map(|(_, value)| value.as_deref())
+            .collect()
+    }
+
+    /// Which of flags `names` was given last, for options that override
+    /// each other like `-L` and `-P`
+    pub fn last_of<'n>(&self, names: &[&'n str]) -> Option<&'n str> {
+        self.flags.iter().rev().find_map(|(i, _)| {
+            let flag = &self.spec.flags[*i];
+            names.iter().copied().find(|name| flag.is(name))
+        })
+    }
+
+    /// Operand `i`, if given
+    pub fn operand(&self, i: usize) -> Option<&str> {
+        self.operands.get(i).map(String::as_str)
+    }
+}
+
+impl CommandSpec {
+    /// Parse `args` (without the command name)
+    pub fn parse(&self, args: &[String]) -> Result<Matches<'_>, ArgError> {
+        let mut matches = Matches {
+            spec: self,
+            flags: Vec::new(),
+            operands: Vec::new(),
+        };
+        // `--help` alone asks for help even where flags end straight away
+        if args.len() == 1 && args[0] == "--help" {
+            return Err(ArgError::Help);
+        }
+        let raw_from = self.args.iter().position(|a| a.raw);
+        let mut args = args.iter();
+        let mut flags_done = false;
+        while let Some(arg) = args.next() {
+            if raw_from == Some(matches.operands.len()) {
+                flags_done = true;
+            }
+            if flags_done || !self.is_flag(arg) {
+                matches.operands.push(arg.clone());
+            } else if arg == "--" {
+                flags_done = true;
+            } else if let Some(long) = arg.strip_prefix("--") {
+                let (name, attached) = match long.split_once('=') {
+                    Some((name, value)) => (name, Some(value.to_string())),
+                    None => (long, None),
+                };
+                let Some(i) = self.flags.iter().position(|f| f.long == Some(name)) else {
+                    return Err(if name == "help" {
+                        ArgError::Help
+                    } else {
+                        ArgError::UnknownFlag(format!("--{}", name))
+                    });
+                };
+                let value = match (self.flags[i].value, attached) {
+                    (Some(_), Some(value)) => Some(value),
+                    (Some(_), None) => Some(
+                        args.next()
+                            .cloned()
+                            .ok_or_else(|| ArgError::MissingValue(format!("--{}", name)))?,
+                    ),
+                    (None, Some(_)) => {
+                        return Err(ArgError::UnexpectedValue(format!("--{}", name)));
+                    }
+                    (None, None) => None,
+                };
+                matches.flags.push((i, value));
+            } else {
+                let cluster = &arg[1..];
+                for (at, c) in cluster.char_indices() {
+                    let Some(i) = self.flags.iter().position(|f| f.short == Some(c)) else {
+                        return Err(if c == 'h' {
+                            ArgError::Help
+                        } else {
+                            ArgError::UnknownFlag(format!("-{}", c))
+                        });
+                    };
+                    if self.flags[i].value.is_none() {
+                        matches.flags.push((i, None));
+                        continue;
+                    }
+                    let rest = &cluster[at + c.len_utf8()..];
+                    let value = if rest.is_empty() {
+                        args.next()
+                            .cloned()
+                            .ok_or_else(|| ArgError::MissingValue(format!("-{}", c)))?
+                    } else {
+                        rest.to_string()
+                    };
+                    matches.flags.push((i, Some(value)));
+                    break;
+                }
+            }
+        }
+
+        if let Some(missing) = self
+            .args
+            .iter()
+            .filter(|a| a.required)
+            .nth(matches.operands.len())
+        {
+            return Err(ArgError::MissingOperand(missing.name));
+        }
+        let repeated = self.args.iter().any(|a| a.repeated);
+        if !repeated && let Some(extra) = matches.operands.get(self.args.len()) {
+            return Err(ArgError::ExtraOperand(extra.clone()));
+        }
+        Ok(matches)
+    }
+
+    /// Whether `arg` is a flag (or `--`) rather than an operand
+    fn is_flag(&self, arg: &str) -> bool {
+        let Some(rest) = arg.strip_prefix('-') else {
+            return false;
+        };
+        match rest.chars().next() {
+            None => false,
+            // A negative number, unless the command has digit flags
+            Some(c) if c.is_ascii_digit() => self.flags.iter().any(|f| f.short == Some(c)),
+            Some(_) => true,
+        }
+    }
+
+    /// `Usage: name [-a] [-b VALUE] OPERAND...`
+    pub fn usage(&self) -> String {
+        format!("Usage: {}", self.synopsis())
+    }
+
+    /// The command line the usage and the manual's SYNOPSIS show
+    fn synopsis(&self) -> String {
+        let mut parts = vec![self.name.to_string()];
+        for flag in self.flags {
+            let name = match (flag.short, flag.long) {
+                (Some(c), _) => format!("-{}", c),
+                (None, Some(long)) => format!("--{}", long),
+                (None, None) => continue,
+            };
+            parts.push(match flag.value {
+                Some(value) => format!("[{} {}]", name, value),
+                None => format!("[{}]", name),
+            });
+        }
+        parts.extend(self.args.iter().map(Arg::synopsis));
+        parts.join(" ")
+    }
+
+    /// What `--help` prints: usage, description and flags
+    pub fn help(&self) -> String {
+        let mut out = self.usage();
+        out.push('\n');
+        out.push_str(self.description.trim());
+        out.push('\n');
+        if !self.flags.is_empty() {
+            out.push_str("\nOptions:\n");
+            let labels: Vec<String> = self.flags.iter().map(Flag::label).collect();
+            let width = labels.iter().map(String::len).max().unwrap_or(0);
+            for (label, flag) in labels.iter().zip(self.flags) {
+                out.push_str(&format!(
+                    "  {:<width$}  {}\n",
+                    label,
+                    flag.help,
+                    width = width
+                ));
+            }
+        }
+        // A subcommand's page is its command's
+        let page = self.name.split(' ').next().unwrap_or(self.name);
+        out.push_str(&format!("\nSee 'man {}' for details.", page));
+        out
+    }
+
+    /// A manual page in the layout of the pre-rendered ones
+    pub fn man_page(&self) -> String {
+        let title = format!("{}(1)", self.name);
+        let middle = "General Commands Manual";
+        let gap = MAN_WIDTH.saturating_sub(2 * title.len() + middle.len());
+        let left = gap / 2;
+        let mut out = format!(
+            "{}{}{}{}{}\n",
+            title,
+            " ".repeat(left),
+            middle,
+            " ".repeat(gap - left),
+            title
+        );
+
+        out.push_str("\nNAME\n");
+        out.push_str(&wrap(&format!("{} - {}", self.name, self.summary), 7));
+        out.push_str("\nSYNOPSIS\n");
+        out.push_str(&wrap(&self.synopsis(), 7));
+        out.push_str("\nDESCRIPTION\n");
+        for paragraph in self.description.trim().split("\n\n") {
+            // A paragraph with indented lines is a table, kept as it is
+            if paragraph.lines().any(|line| line.starts_with(' ')) {
+                for line in paragraph.lines() {
+                    out.push_str(&format!("       {}\n", line));
+                }
+            } else {
+                out.push_str(&wrap(paragraph, 7));
+            }
+            out.push('\n');
+        }
+        out.push_str("OPTIONS\n");
+        let help = Flag::long("help", "Display usage information and exit.");
+        for flag in self.flags.iter().chain([&help]) {
+            out.push_str(&format!("       {}\n", flag.label()));
+            out.push_str(&wrap(flag.help, 11));
+            out.push('\n');
+        }
+        out.trim_end().to_string()
+    }
+
+    /// What tab completion should offer for `word`, the word being typed,
+    /// given the words before it on the line (the command name first)
+    pub fn complete(&self, before: &[&str], word: &str) -> Completion {
+        // Count the operands before this one, skipping flags and values
+        let raw_from = self.args.iter().position(|a| a.raw);
+        let mut operands = 0;
+        let mut words = before.iter().skip(1);
+        let mut flags_done = false;
+        while let Some(w) = words.next() {
+            if raw_from == Some(operands) {
+                flags_done = true;
+            }
+            if flags_done || !self.is_flag(w) {
+                operands += 1;
+            } else if *w == "--" {
+                flags_done = true;
+            } else if self.takes_value(w) && words.next().is_none() {
+                // Completing the flag's value
+                return Completion::Path;
+            }
+        }
+        let flags_done = flags_done || raw_from == Some(operands);
+
+        if !flags_done && (word == "-" || (word.starts_with('-') && self.is_flag(word))) {
+            let mut flags: Vec<String> = self
+                .flags
+                .iter()
+                .flat_map(|f| {
+                    let short = f.short.map(|c| format!("-{}", c));
+                    let long = f.long.map(|l| format!("--{}", l));
+                    short.into_iter().chain(long)
+                })
+                .chain(["--help".to_string()])
+                .filter(|f| f.starts_with(word))
+                .collect();
+            flags.sort();
+            return Completion::Words(flags);
+        }
+
+        let arg = self
+            .args
+            .get(operands)
+            .or_else(|| self.args.iter().find(|a| a.repeated));
+        match arg.map(|a| a.kind) {
+            Some(ArgKind::Path) => Completion::Path,
+            Some(ArgKind::Dir) => Completion::Dir,
+            Some(ArgKind::Command) => Completion::Command,
+            Some(ArgKind::Text) | None => Completion::Words(Vec::new()),
+        }
+    }
+
+    /// Whether flag word `w` ends in a flag that needs the next word as
+    /// its value
+    fn takes_value(&self, w: &str) -> bool {
+        if let Some(long) = w.strip_prefix("--") {
+            return !long.contains('=')
+                && self
+                    .flags
+                    .iter()
+                    .any(|f| f.long == Some(long) && f.value.is_some());
+        }
+        // In a cluster, the first flag with a value takes the rest
+        for (at, c) in w[1..].char_indices() {
+            if self
+                .flags
+                .iter()
+                .any(|f| f.short == Some(c) && f.value.is_some())
+            {
+                return at + c.len_utf8() == w.len() - 1;
+            }
+        }
+        false
+    }
+}
+
+/// What to offer for the word being completed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Completion {
+    /// These words (possibly none)
+    Words(Vec<String>),
+    /// Files and directories
+    Path,
+    /// Directories only
+    Dir,
+    /// Command names
+    Command,
+}
+
+/// Width of a pre-rendered manual page
+const MAN_WIDTH: usize = 78;
+
+/// Fill `text` to [`MAN_WIDTH`], indenting every line by `indent`
+fn wrap(text: &str, indent: usize) -> String {
+    let pad = " ".repeat(indent);
+    let mut out = String::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && indent + line.len() + 1 + word.len() > MAN_WIDTH {
+            out.push_str(&format!("{}{}\n", pad, line));
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        out.push_str(&format!("{}{}\n", pad, line));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static SPEC: CommandSpec = CommandSpec {
+        name: "tool",
+        summary: "do things to files",
+        description: "Do things to each FILE.\n\nOr to the current directory.\n\nModes:\n  fast  Quickly",
+        flags: &[
+            Flag::new('r', "recursive", "Descend into directories"),
+            Flag::short('f', "Never ask"),
+            Flag::new('n', "lines", "Stop after N lines").value("N"),
+            Flag::long("color", "Use colors"),
+        ],
+        args: &[
+            Arg::new("MODE", ArgKind::Text),
+            Arg::new("FILE", ArgKind::Path).optional().repeated(),
+        ],
+    };
+
+    fn parse(args: &[&str]) -> Result<Matches<'static>, ArgError> {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        SPEC.parse(&args)
+    }
+
+    #[test]
+    fn test_parse() {
+        let m = parse(&["-rf", "x", "--lines=3", "a", "-n5", "--", "-b"]).unwrap();
+        assert!(m.flag("recursive") && m.flag("f"));
+        assert!(!m.flag("color"));
+        assert_eq!(m.values("n"), ["3", "5"]);
+        assert_eq!(m.operands, ["x", "a", "-b"]);
+
+        let m = parse(&["-rn", "7", "-", "-12"]).unwrap();
+        assert_eq!(m.value("lines"), Some("7"));
+        assert_eq!(m.operands, ["-", "-12"]);
+
+        assert_eq!(parse(&["x", "-h"]).err(), Some(ArgError::Help));
+        assert_eq!(parse(&["--help"]).err(), Some(ArgError::Help));
+        assert_eq!(
+            parse(&["-x"]).err(),
+            Some(ArgError::UnknownFlag("-x".into()))
+        );
+        assert_eq!(
+            parse(&["x", "-n"]).err(),
+            Some(ArgError::MissingValue("-n".into()))
+        );
+        assert_eq!(
+            parse(&["x", "--color=1"]).err(),
+            Some(ArgError::UnexpectedValue("--color".into()))
+        );
+        assert_eq!(parse(&["-r"]).err(), Some(ArgError::MissingOperand("MODE")));
+
+        let m = parse(&["-f", "--recursive", "x", "-rf", "--color"]).unwrap();
+        assert_eq!(m.last_of(&["r", "f"]), Some("f"));
+        assert_eq!(m.last_of(&["recursive", "n"]), Some("recursive"));
+        assert_eq!(m.last_of(&["n"]), None);
+
+        // A repeated operand can come before a required one
+        static COPY: CommandSpec = CommandSpec {
+            name: "copy",
+            summary: "copy files",
+            description: "Copy each SOURCE to DEST.",
+            flags: &[],
+            args: &[
+                Arg::new("SOURCE", ArgKind::Path).repeated(),
+                Arg::new("DEST", ArgKind::Dir),
+            ],
+        };
+        let args: Vec<String> = ["a", "b", "c"].iter().map(|a| a.to_string()).collect();
+        assert_eq!(COPY.parse(&args).unwrap().operands, ["a", "b", "c"]);
+        assert_eq!(
+            COPY.parse(&args[..1]).err(),
+            Some(ArgError::MissingOperand("DEST"))
+        );
+        assert_eq!(COPY.usage(), "Usage: copy SOURCE... DEST");
+
+        // Flags end at a raw operand
+        static RUN: CommandSpec = CommandSpec {
+            name: "run",
+            summary: "run a command",
+            description: "Run COMMAND.",
+            flags: &[Flag::short('q', "Quietly")],
+            args: &[
+                Arg::new("COMMAND", ArgKind::Command),
+                Arg::new("ARG", ArgKind::Text).optional().repeated().raw(),
+            ],
+        };
+        let args: Vec<String> = ["-q", "ls", "-q", "--help", "--"]
+            .iter()
+            .map(|a| a.to_string())
+            .collect();
+        let m = RUN.parse(&args).unwrap();
+        assert!(m.flag("q"));
+        assert_eq!(m.operands, ["ls", "-q", "--help", "--"]);
+        assert_eq!(RUN.parse(&args[3..4]).err(), Some(ArgError::Help));
+        assert_eq!(RUN.complete(&["run", "ls"], "-"), Completion::Words(vec![]));
+        assert_eq!(
+            RUN.complete(&["run"], "-"),
+            Completion::Words(vec!["--help".into(), "-q".into()])
+        );
+    }
+
+    #[test]
+    fn test_help_and_man() {
+        assert_eq!(
+            SPEC.usage(),
+            "Usage: tool [-r] [-f] [-n N] [--color] MODE [FILE]..."
+        );
+        let help = SPEC.help();
+        assert!(help.contains("  -n, --lines N    Stop after N lines\n"));
+        assert!(help.contains("  --color          Use colors\n"));
+
+        let man = SPEC.man_page();
+        assert!(man.starts_with("tool(1)"));
+        assert_eq!(man.lines().next().unwrap().len(), MAN_WIDTH);
+        assert!(man.contains("NAME\n       tool - do things to files\n"));
+        assert!(man.contains("\n       Modes:\n         fast  Quickly\n\n"));
+        assert!(man.contains("       -r, --recursive\n           Descend into directories\n"));
+        assert!(man.ends_with("       --help\n           Display usage information and exit."));
+    }
+
+    #[test]
+    fn test_complete() {
+        assert_eq!(
+            SPEC.complete(&["tool"], "--"),
+            Completion::Words(vec![
+                "--color".into(),
+                "--help".into(),
+                "--lines".into(),
+                "--recursive".into()
+            ])
+        );
+        assert_eq!(
+            SPEC.complete(&["tool", "-r"], ""),
+            Completion::Words(vec![])
+        );
+        assert_eq!(SPEC.complete(&["tool", "-r", "x"], "sr"), Completion::Path);
+        assert_eq!(SPEC.complete(&["tool", "-n"], ""), Completion::Path);
+        assert_eq!(
+            SPEC.complete(&["tool", "-n", "3"], ""),
+            Completion::Words(vec![])
+        );
+    }
+}
@@ -0,0 +1,909 @@
+//! Remote backups
+//!
+//! The backup service uploads the persistent filesystem to an HTTP endpoint
+//! set up in [`CONFIG_PATH`]: a WebDAV collection, or any store that takes
+//! a `PUT` and a `GET` per object (S3-compatible buckets through URLs that
+//! allow both). Everything is encrypted with the configured password
+//! ([`crypto::encrypt`]) before it leaves the browser.
+//!
+//! Backups are incremental. Each holds only the paths created or changed
+//! since the one before it, plus the paths deleted since, and names that
+//! one as its parent; after [`BackupConfig::full_every`] of them a full
+//! backup starts a new chain. To tell what changed, the service keeps the
+//! fingerprint of every path as of the last upload in [`MANIFEST_PATH`],
+//! and lists the uploads in [`CATALOG_PATH`]. Neither, nor the
+//! configuration, is backed up: they belong to this device.
+//!
+//! A backup taken at `TIME` (milliseconds since the epoch) is stored as
+//! pieces `axeberg-TIME.axb.0`, `axeberg-TIME.axb.1`, ... of at most
+//! [`CHUNK_SIZE`] bytes, uploaded no faster than the configured limit, and
+//! then `axeberg-TIME.axb`, an index of the pieces. `backup restore TIME`
+//! fetches the chain back to its full backup and applies it in order.
+//!
+//! The platform calls [`tick`] with the wall-clock time; a backup starts
+//! when the configured interval has passed since the last one.
+
+use super::setup::as_root;
+use crate::kernel::crypto;
+use crate::kernel::pkg::Checksum;
+use crate::kernel::syscall;
+use crate::vfs::{FsSnapshot, MemoryFs};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+
+/// Backup settings
+pub const CONFIG_PATH: &str = "/etc/backup.conf";
+
+/// Where the service keeps its own state
+pub const STATE_DIR: &str = "/var/lib/backup";
+
+/// Fingerprints of the paths as of the last upload
+pub const MANIFEST_PATH: &str = "/var/lib/backup/manifest";
+
+/// One line per upload
+pub const CATALOG_PATH: &str = "/var/lib/backup/catalog";
+
+/// Largest piece uploaded in one request
+pub const CHUNK_SIZE: usize = 256 * 1024;
+
+/// How often [`tick`] looks at the schedule, in milliseconds
+const CHECK_INTERVAL: f64 = 60_000.0;
+
+/// Settings in [`CONFIG_PATH`], one `key = value` per line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupConfig {
+    /// Object URL: `{name}` is replaced by the object name, which is
+    /// otherwise appended after a `/`
+    pub url: String,
+    /// Encryption password
+    pub password: String,
+    /// Sent as the `Authorization` header, e.g. `Basic dXNlcjpwYXNz`
+    pub authorization: Option<String>,
+    /// Minutes between scheduled backups, 0 to only back up on request
+    pub interval: u64,
+    /// Upload limit in KiB per second, 0 for none
+    pub limit: u64,
+    /// Incremental backups before the next full one
+    pub full_every: u32,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            password: String::new(),
+            authorization: None,
+            interval: 60,
+            limit: 0,
+            full_every: 24,
+        }
+    }
+}
+
+impl BackupConfig {
+    /// Setting names, in the order they are written
+    pub const KEYS: [&'static str; 6] = [
+        "url",
+        "password",
+        "authorization",
+        "interval",
+        "limit",
+        "full_every",
+    ];
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut config = Self::default();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(format!("line {}: expected key = value", n + 1));
+            };
+            config
+                .set(key.trim(), value.trim())
+                .map_err(|e| format!("line {}: {}", n + 1, e))?;
+        }
+        Ok(config)
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let number = |value: &str| {
+            value
+                .parse::<u64>()
+                .map_err(|_| format!("{}: not a number: {}", key, value))
+        };
+        match key {
+            "url" => self.url = value.to_string(),
+            "password" => self.password = value.to_string(),
+            "authorization" => {
+                self.authorization = Some(value.to_string()).filter(|v| !v.is_empty())
+            }
+            "interval" => self.interval = number(value)?,
+            "limit" => self.limit = number(value)?,
+            "full_every" => {
+                self.full_every = u32::try_from(number(value)?)
+                    .map_err(|_| format!("full_every: too large: {}", value))?
+            }
+            _ => return Err(format!("unknown setting: {}", key)),
+        }
+        Ok(())
+    }
+
+    /// A setting's value, with the password masked
+    pub fn get(&self, key: &str) -> Option<String> {
+        Some(match key {
+            "url" => self.url.clone(),
+            "password" if self.password.is_empty() => String::new(),
+            "password" => "********".to_string(),
+            "authorization" => self.authorization.clone().unwrap_or_default(),
+            "interval" => self.interval.to_string(),
+            "limit" => self.limit.to_string(),
+            "full_every" => self.full_every.to_string(),
+            _ => return None,
+        })
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::from("# Remote backups, see 'backup --help'\n");
+        text.push_str(&format!("url = {}\n", self.url));
+        text.push_str(&format!("password = {}\n", self.password));
+        if let Some(auth) = &self.authorization {
+            text.push_str(&format!("authorization = {}\n", auth));
+        }
+        text.push_str(&format!("interval = {}\n", self.interval));
+        text.push_str(&format!("limit = {}\n", self.limit));
+        text.push_str(&format!("full_every = {}\n", self.full_every));
+        text
+    }
+
+    /// Check if there is somewhere to back up to and a key to do it with
+    pub fn is_configured(&self) -> bool {
+        !self.url.is_empty() && !self.password.is_empty()
+    }
+
+    /// URL of the object called `name`
+    pub fn object_url(&self, name: &str) -> String {
+        if self.url.contains("{name}") {
+            self.url.replace("{name}", name)
+        } else {
+            format!("{}/{}", self.url.trim_end_matches('/'), name)
+        }
+    }
+
+    /// Headers sent with every request
+    pub fn headers(&self) -> HashMap<String, String> {
+        self.authorization
+            .iter()
+            .map(|auth| ("Authorization".to_string(), auth.clone()))
+            .collect()
+    }
+}
+
+/// Name of the index of the backup taken at `time`
+pub fn object_name(time: i64) -> String {
+    format!("axeberg-{}.axb", time)
+}
+
+/// Name of piece `n` of an object
+pub fn chunk_name(object: &str, n: usize) -> String {
+    format!("{}.{}", object, n)
+}
+
+/// What one backup holds, before encryption
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Delta {
+    pub time: i64,
+    /// The backup this one is relative to; `None` for a full backup
+    pub parent: Option<i64>,
+    /// Created and changed paths, as they are now
+    pub image: FsSnapshot,
+    /// Deleted paths
+    pub removed: Vec<String>,
+}
+
+/// The index object of a backup, uploaded in the clear after its pieces
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ObjectIndex {
+    pub chunks: usize,
+    pub bytes: usize,
+    /// SHA-256 of the whole encrypted object, as hex
+    pub sha256: String,
+}
+
+impl ObjectIndex {
+    pub fn new(data: &[u8]) -> Self {
+        Self {
+            chunks: data.len().div_ceil(CHUNK_SIZE),
+            bytes: data.len(),
+            sha256: Checksum::compute(data).to_hex(),
+        }
+    }
+
+    /// Join downloaded pieces, checking they add up to the object
+    pub fn assemble(&self, chunks: &[Vec<u8>]) -> Result<Vec<u8>, String> {
+        let data = chunks.concat();
+        if chunks.len() != self.chunks || data.len() != self.bytes {
+            return Err(format!(
+                "incomplete: {} of {} bytes",
+                data.len(),
+                self.bytes
+            ));
+        }
+        if Checksum::compute(&data).to_hex() != self.sha256 {
+            return Err("checksum mismatch".to_string());
+        }
+        Ok(data)
+    }
+}
+
+/// One upload in the catalog
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CatalogEntry {
+    pub time: i64,
+    pub parent: Option<i64>,
+    /// Paths created or changed
+    pub changed: usize,
+    pub removed: usize,
+    /// Size of the encrypted object
+    pub bytes: usize,
+}
+
+impl CatalogEntry {
+    pub fn is_full(&self) -> bool {
+        self.parent.is_none()
+    }
+
+    /// `time parent changed removed bytes`, tab-separated; `-` for no parent
+    pub fn to_line(&self) -> String {
+        let parent = self.parent.map_or("-".to_string(), |p| p.to_string());
+        format!(
+            "{}\t{}\t{}\t{}\t{}",
+            self.time, parent, self.changed, self.removed, self.bytes
+        )
+    }
+
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t');
+        let time = fields.next()?.parse().ok()?;
+        let parent = match fields.next()? {
+            "-" => None,
+            p => Some(p.parse().ok()?),
+        };
+        Some(Self {
+            time,
+            parent,
+            changed: fields.next()?.parse().ok()?,
+            removed: fields.next()?.parse().ok()?,
+            bytes: fields.next()?.parse().ok()?,
+        })
+    }
+}
+
+/// State as of the last upload, for working out the next one
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// When the last backup was taken
+    pub time: i64,
+    /// Incremental backups since the last full one
+    pub chain: u32,
+    pub fingerprints: BTreeMap<String, u64>,
+}
+
+/// A backup ready to upload
+pub struct Prepared {
+    pub entry: CatalogEntry,
+    pub index: ObjectIndex,
+    /// The encrypted [`Delta`]
+    pub sealed: Vec<u8>,
+    /// What the manifest becomes once the upload succeeds
+    pub manifest: Manifest,
+}
+
+/// Paths that describe this device's backups rather than the system
+pub fn is_excluded(path: &str) -> bool {
+    path == CONFIG_PATH
+        || path
+            .strip_prefix(STATE_DIR)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Fingerprints of the paths that get backed up
+fn fingerprints(snapshot: &FsSnapshot) -> BTreeMap<String, u64> {
+    snapshot
+        .fingerprints()
+        .into_iter()
+        .filter(|(path, _)| !is_excluded(path))
+        .collect()
+}
+
+/// Work out the next backup from `snapshot`: full if there is no manifest
+/// or the chain is long enough, otherwise what changed since the manifest.
+/// `None` if nothing changed.
+pub fn prepare(
+    snapshot: &FsSnapshot,
+    manifest: Option<&Manifest>,
+    config: &BackupConfig,
+    time: i64,
+) -> Result<Option<Prepared>, String> {
+    prepare_with(snapshot, manifest, config, time, crypto::KDF_ITERATIONS)
+}
+
+fn prepare_with(
+    snapshot: &FsSnapshot,
+    manifest: Option<&Manifest>,
+    config: &BackupConfig,
+    time: i64,
+    iterations: u32,
+) -> Result<Option<Prepared>, String> {
+    let current = fingerprints(snapshot);
+    let base = manifest.filter(|m| m.chain < config.full_every);
+    let (parent, changed, removed, chain) = match base {
+        Some(base) => {
+            let changed: Vec<&str> = current
+                .iter()
+                .filter(|(path, print)| base.fingerprints.get(*path) != Some(print))
+                .map(|(path, _)| path.as_str())
+                .collect();
+            let removed: Vec<String> = base
+                .fingerprints
+                .keys()
+                .filter(|path| !current.contains_key(*path))
+                .cloned()
+                .collect();
+            if changed.is_empty() && removed.is_empty() {
+                return Ok(None);
+            }
+            (Some(base.time), changed, removed, base.chain + 1)
+        }
+        None => (
+            None,
+            current.keys().map(String::as_str).collect(),
+            Vec::new(),
+            0,
+        ),
+    };
+
+    let entry = CatalogEntry {
+        time,
+        parent,
+        changed: changed.len(),
+        removed: removed.len(),
+        bytes: 0,
+    };
+    let delta = Delta {
+        time,
+        parent,
+        image: snapshot.subset(changed),
+        removed,
+    };
+    let json = serde_json::to_vec(&delta).map_err(|e| e.to_string())?;
+    let sealed = crypto::encrypt_with(&config.password, &json, iterations);
+    Ok(Some(Prepared {
+        entry: CatalogEntry {
+            bytes: sealed.len(),
+            ..entry
+        },
+        index: ObjectIndex::new(&sealed),
+        sealed,
+        manifest: Manifest {
+            time,
+            chain,
+            fingerprints: current,
+        },
+    }))
+}
+
+/// Decrypt a downloaded backup
+pub fn open(sealed: &[u8], password: &str) -> Result<Delta, String> {
+    let json = crypto::decrypt(password, sealed).map_err(|e| e.to_string())?;
+    serde_json::from_slice(&json).map_err(|e| format!("unreadable backup: {}", e))
+}
+
+/// Apply a chain of backups, full backup first
+pub fn rebuild(chain: &[Delta]) -> Result<MemoryFs, String> {
+    let Some((full, rest)) = chain.split_first() else {
+        return Err("no backups to restore".to_string());
+    };
+    if full.parent.is_some() {
+        return Err(format!("{} is not a full backup", full.time));
+    }
+    let mut fs = MemoryFs::restore(full.image.clone()).map_err(|e| e.to_string())?;
+    for (previous, delta) in chain.iter().zip(rest) {
+        if delta.parent != Some(previous.time) {
+            return Err(format!("{} does not follow {}", delta.time, previous.time));
+        }
+        fs.apply(&delta.image, &delta.removed);
+    }
+    Ok(fs)
+}
+
+/// The settings, or the defaults if there are none yet
+pub fn load_config() -> Result<BackupConfig, String> {
+    match as_root(|| syscall::read_file(CONFIG_PATH)) {
+        Ok(text) => BackupConfig::parse(&text).map_err(|e| format!("{}: {}", CONFIG_PATH, e)),
+        Err(_) => Ok(BackupConfig::default()),
+    }
+}
+
+/// Write the settings, readable by root only (they hold the password)
+pub fn save_config(config: &BackupConfig) -> Result<(), String> {
+    as_root(|| {
+        syscall::atomic_write(CONFIG_PATH, config.to_text().as_bytes())?;
+        syscall::chmod(CONFIG_PATH, 0o600)
+    })
+    .map_err(|e| format!("{}: {}", CONFIG_PATH, e))
+}
+
+/// The uploads made from this device, oldest first
+pub fn read_catalog() -> Vec<CatalogEntry> {
+    as_root(|| syscall::read_file(CATALOG_PATH))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(CatalogEntry::parse)
+        .collect()
+}
+
+fn read_manifest() -> Option<Manifest> {
+    let json = as_root(|| syscall::read_file(MANIFEST_PATH)).ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+fn write_state(manifest: &Manifest, catalog: &[CatalogEntry]) -> Result<(), String> {
+    let manifest = serde_json::to_string(manifest).map_err(|e| e.to_string())?;
+    let mut lines: String = catalog.iter().map(|e| e.to_line() + "\n").collect();
+    if lines.is_empty() {
+        lines.push('\n');
+    }
+    as_root(|| {
+        for dir in ["/var", "/var/lib", STATE_DIR] {
+            if !syscall::exists(dir)? {
+                syscall::mkdir(dir)?;
+            }
+        }
+        syscall::atomic_write(MANIFEST_PATH, manifest.as_bytes())?;
+        syscall::atomic_write(CATALOG_PATH, lines.as_bytes())
+    })
+    .map_err(|e| format!("{}: {}", STATE_DIR, e))
+}
+
+/// The persistent filesystem as it is now
+fn snapshot() -> Result<FsSnapshot, String> {
+    let json = syscall::vfs_snapshot().map_err(|e| e.to_string())?;
+    serde_json::from_slice(&json).map_err(|e| e.to_string())
+}
+
+/// Work out the next backup of the running system
+pub fn prepare_now(config: &BackupConfig, time: i64) -> Result<Option<Prepared>, String> {
+    prepare(&snapshot()?, read_manifest().as_ref(), config, time)
+}
+
+/// Record a finished upload
+pub fn commit(prepared: Prepared) -> Result<(), String> {
+    let mut catalog = read_catalog();
+    catalog.push(prepared.entry);
+    write_state(&prepared.manifest, &catalog)
+}
+
+/// Replace the filesystem with a rebuilt chain, keeping this device's
+/// backup settings and state; the next backup continues the chain
+pub fn restore(chain: &[Delta]) -> Result<(), String> {
+    let fs = rebuild(chain)?;
+    let time = chain.last().map_or(0, |d| d.time);
+    let config = as_root(|| syscall::read_file(CONFIG_PATH)).ok();
+    let catalog = read_catalog();
+
+    let json = fs.to_json().map_err(|e| e.to_string())?;
+    syscall::vfs_restore(&json).map_err(|e| e.to_string())?;
+
+    if let Some(config) = config {
+        let _ = as_root(|| syscall::write_file(CONFIG_PATH, &config));
+        let _ = as_root(|| syscall::chmod(CONFIG_PATH, 0o600));
+    }
+    let manifest = Manifest {
+        time,
+        chain: chain.len().saturating_sub(1) as u32,
+        fingerprints: fingerprints(&fs.snapshot()),
+    };
+    write_state(&manifest, &catalog)
+}
+
+/// Check if a scheduled backup is due at `now`
+fn is_due(config: &BackupConfig, last: Option<f64>, now: f64) -> bool {
+    config.is_configured()
+        && config.interval > 0
+        && last.is_none_or(|last| now - last >= config.interval as f64 * 60_000.0)
+}
+
+/// What the service is doing, for `backup status`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Status {
+    /// `backing up` or `restoring` while one is in flight
+    pub busy: Option<&'static str>,
+    /// Wall-clock time of the last attempt this session
+    pub last_attempt: Option<f64>,
+    pub last_error: Option<String>,
+    /// When the schedule was last looked at
+    checked: Option<f64>,
+}
+
+thread_local! {
+    static STATUS: RefCell<Status> = RefCell::new(Status::default());
+}
+
+pub fn status() -> Status {
+    STATUS.with(|s| s.borrow().clone())
+}
+
+/// Mark the service busy; false if it already is
+fn begin(what: &'static str, now: f64) -> bool {
+    STATUS.with(|s| {
+        let mut s = s.borrow_mut();
+        if s.busy.is_some() {
+            return false;
+        }
+        s.busy = Some(what);
+        s.last_attempt = Some(now);
+        true
+    })
+}
+
+/// Mark the service idle again, logging how it went
+fn end(result: Result<String, String>) {
+    use crate::kernel::LogLevel;
+    let (level, message) = match &result {
+        Ok(message) => (LogLevel::Notice, message.clone()),
+        Err(e) => (LogLevel::Err, e.clone()),
+    };
+    syscall::klog(level, "backup", &message);
+    STATUS.with(|s| {
+        let mut s = s.borrow_mut();
+        s.busy = None;
+        s.last_error = result.err();
+    });
+}
+
+/// Start a backup now; the result goes to the kernel log
+pub fn start_backup(config: BackupConfig, now: f64) -> Result<(), String> {
+    if !config.is_configured() {
+        return Err(format!("no url or password set in {}", CONFIG_PATH));
+    }
+    if !begin("backing up", now) {
+        return Err("a backup or restore is already running".to_string());
+    }
+    #[cfg(target_arch = "wasm32")]
+    wasm_bindgen_futures::spawn_local(async move {
+        end(remote::backup(&config, now as i64).await);
+    });
+    #[cfg(not(target_arch = "wasm32"))]
+    end(Err("remote backups need a browser".to_string()));
+    Ok(())
+}
+
+/// Start restoring the backup taken at `time`; the result goes to the
+/// kernel log
+pub fn start_restore(config: BackupConfig, time: i64, now: f64) -> Result<(), String> {
+    if !config.is_configured() {
+        return Err(format!("no url or password set in {}", CONFIG_PATH));
+    }
+    if !begin("restoring", now) {
+        return Err("a backup or restore is already running".to_string());
+    }
+    #[cfg(target_arch = "wasm32")]
+    wasm_bindgen_futures::spawn_local(async move {
+        end(remote::restore(&config, time).await);
+    });
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let _ = time;
+        end(Err("remote backups need a browser".to_string()));
+    }
+    Ok(())
+}
+
+/// Start a scheduled backup if one is due at `now_ms` (wall-clock
+/// milliseconds); returns whether one started
+///
+/// Cheap to call on every executor tick: the schedule is only looked at
+/// once a minute.
+pub fn tick(now_ms: f64) -> bool {
+    let look = STATUS.with(|s| {
+        let mut s = s.borrow_mut();
+        if s.busy.is_some() || s.checked.is_some_and(|t| now_ms - t < CHECK_INTERVAL) {
+            return false;
+        }
+        s.checked = Some(now_ms);
+        true
+    });
+    if !look {
+        return false;
+    }
+    let Ok(config) = load_config() else {
+        return false;
+    };
+    // After a failure, wait a full interval before trying again
+    let last_success = read_catalog().last().map(|e| e.time as f64);
+    let last = match (last_success, status().last_attempt) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        (a, b) => a.or(b),
+    };
+    is_due(&config, last, now_ms) && start_backup(config, now_ms).is_ok()
+}
+
+/// Talking to the endpoint
+#[cfg(target_arch = "wasm32")]
+mod remote {
+    use super::*;
+    use crate::kernel::RateLimiter;
+    use crate::kernel::network::{self, HttpMethod};
+    use crate::shell::osc::Meter;
+
+    /// Longest chain followed on restore, against a parent loop
+    const MAX_CHAIN: usize = 1000;
+
+    async fn put(config: &BackupConfig, name: &str, data: Vec<u8>) -> Result<(), String> {
+        let url = config.object_url(name);
+        let response =
+            network::fetch_with_method(HttpMethod::Put, &url, config.headers(), Some(data)).await?;
+        if !(200..300).contains(&response.status) {
+            return Err(format!(
+                "PUT {}: HTTP {} {}",
+                url, response.status, response.status_text
+            ));
+        }
+        Ok(())
+    }
+
+    async fn get(config: &BackupConfig, name: &str) -> Result<Vec<u8>, String> {
+        let url = config.object_url(name);
+        let response =
+            network::fetch_with_method(HttpMethod::Get, &url, config.headers(), None).await?;
+        if !(200..300).contains(&response.status) {
+            return Err(format!(
+                "GET {}: HTTP {} {}",
+                url, response.status, response.status_text
+            ));
+        }
+        Ok(response.body)
+    }
+
+    /// Take and upload a backup: the pieces, then the index
+    pub async fn backup(config: &BackupConfig, time: i64) -> Result<String, String> {
+        let Some(prepared) = prepare_now(config, time)? else {
+            return Ok("nothing changed since the last backup".to_string());
+        };
+        let name = object_name(time);
+        let mut limiter = RateLimiter::new(config.limit * 1024);
+        // Shown above the prompt while the pieces go up
+        let mut meter = Meter::new(0, syscall::now());
+        let uploaded = network::put_chunked(
+            |n| config.object_url(&chunk_name(&name, n)),
+            &prepared.sealed,
+            CHUNK_SIZE,
+            &config.headers(),
+            &mut limiter,
+            |sent, total| {
+                if sent == 1 {
+                    meter = Meter::new(total as u64, syscall::now());
+                }
+                if let Some(report) = meter.update(sent as u64, syscall::now()) {
+                    crate::terminal::report(&report);
+                }
+            },
+        )
+        .await;
+        crate::terminal::report(&meter.finish());
+        uploaded?;
+        let index = serde_json::to_vec(&prepared.index).map_err(|e| e.to_string())?;
+        put(config, &name, index).await?;
+
+        let message = format!(
+            "uploaded {} backup {} ({} changed, {} removed, {} bytes)",
+            if prepared.entry.is_full() {
+                "full"
+            } else {
+                "incremental"
+            },
+            time,
+            prepared.entry.changed,
+            prepared.entry.removed,
+            prepared.entry.bytes
+        );
+        commit(prepared)?;
+        Ok(message)
+    }
+
+    async fn download(config: &BackupConfig, time: i64) -> Result<Delta, String> {
+        let name = object_name(time);
+        let index: ObjectIndex = serde_json::from_slice(&get(config, &name).await?)
+            .map_err(|e| format!("{}: {}", name, e))?;
+        let mut chunks = Vec::with_capacity(index.chunks);
+        for n in 0..index.chunks {
+            chunks.push(get(config, &chunk_name(&name, n)).await?);
+        }
+        let sealed = index
+            .assemble(&chunks)
+            .map_err(|e| format!("{}: {}", name, e))?;
+        open(&sealed, &config.password).map_err(|e| format!("{}: {}", name, e))
+    }
+
+    /// Fetch the chain ending at `time` and restore it
+    pub async fn restore(config: &BackupConfig, time: i64) -> Result<String, String> {
+        let mut chain = Vec::new();
+        let mut next = Some(time);
+        while let Some(time) = next {
+            if chain.len() >= MAX_CHAIN {
+                return Err("backup chain too long".to_string());
+            }
+            let delta = download(config, time).await?;
+            next = delta.parent;
+            chain.push(delta);
+        }
+        chain.reverse();
+        super::restore(&chain)?;
+        Ok(format!(
+            "restored backup {} ({} in chain); run 'save' to keep it",
+            time,
+            chain.len()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::{FileSystem, write_string};
+
+    fn settings() -> BackupConfig {
+        BackupConfig {
+            url: "https://dav.example.com/axeberg/".into(),
+            password: "s3cret".into(),
+            full_every: 2,
+            ..Default::default()
+        }
+    }
+
+    /// Upload and download through a map, the way the remote side does
+    fn upload(store: &mut HashMap<String, Vec<u8>>, prepared: &Prepared) {
+        let name = object_name(prepared.entry.time);
+        for (n, chunk) in prepared.sealed.chunks(CHUNK_SIZE).enumerate() {
+            store.insert(chunk_name(&name, n), chunk.to_vec());
+        }
+        store.insert(name, serde_json::to_vec(&prepared.index).unwrap());
+    }
+
+    fn download(store: &HashMap<String, Vec<u8>>, time: i64) -> Delta {
+        let name = object_name(time);
+        let index: ObjectIndex = serde_json::from_slice(&store[&name]).unwrap();
+        let chunks: Vec<Vec<u8>> = (0..index.chunks)
+            .map(|n| store[&chunk_name(&name, n)].clone())
+            .collect();
+        open(&index.assemble(&chunks).unwrap(), "s3cret").unwrap()
+    }
+
+    #[test]
+    fn test_config() {
+        let config = BackupConfig::parse(
+            "# comment\nurl = https://s3.example.com/b/{name}?sig=x\npassword = pw\nlimit = 64\n",
+        )
+        .unwrap();
+        assert_eq!(config.limit, 64);
+        assert_eq!(config.interval, 60);
+        assert_eq!(
+            config.object_url("axeberg-1.axb"),
+            "https://s3.example.com/b/axeberg-1.axb?sig=x"
+        );
+        assert_eq!(
+            BackupConfig::parse(&config.to_text()).unwrap(),
+            config.clone()
+        );
+        assert_eq!(config.get("password").as_deref(), Some("********"));
+        assert!(config.headers().is_empty());
+
+        assert_eq!(
+            BackupConfig::parse("speed = 3").unwrap_err(),
+            "line 1: unknown setting: speed"
+        );
+        assert!(BackupConfig::parse("limit = fast").is_err());
+        assert_eq!(
+            settings().object_url("x.axb"),
+            "https://dav.example.com/axeberg/x.axb"
+        );
+        assert!(!BackupConfig::default().is_configured());
+
+        let entry = CatalogEntry {
+            time: 5,
+            parent: Some(3),
+            changed: 2,
+            removed: 1,
+            bytes: 900,
+        };
+        assert_eq!(CatalogEntry::parse(&entry.to_line()), Some(entry));
+    }
+
+    #[test]
+    fn test_incremental_chain() {
+        let config = settings();
+        let mut fs = MemoryFs::new();
+        fs.create_dir("/etc").unwrap();
+        write_string(&mut fs, "/a.txt", "one").unwrap();
+        write_string(&mut fs, "/b.txt", "two").unwrap();
+        write_string(&mut fs, CONFIG_PATH, "url = x").unwrap();
+        let mut store = HashMap::new();
+
+        // The first backup is full and leaves out the settings
+        let first = prepare_with(&fs.snapshot(), None, &config, 100, 10)
+            .unwrap()
+            .unwrap();
+        assert!(first.entry.is_full());
+        assert!(!first.manifest.fingerprints.contains_key(CONFIG_PATH));
+        upload(&mut store, &first);
+        let manifest = first.manifest;
+
+        // Nothing changed, nothing to do
+        let same = prepare_with(&fs.snapshot(), Some(&manifest), &config, 150, 10).unwrap();
+        assert!(same.is_none());
+
+        // Then only the changes
+        write_string(&mut fs, "/a.txt", "uno").unwrap();
+        fs.remove_file("/b.txt").unwrap();
+        write_string(&mut fs, CONFIG_PATH, "url = y").unwrap();
+        let second = prepare_with(&fs.snapshot(), Some(&manifest), &config, 200, 10)
+            .unwrap()
+            .unwrap();
+        assert_eq!(second.entry.parent, Some(100));
+        assert_eq!((second.entry.changed, second.entry.removed), (1, 1));
+        assert_eq!(second.manifest.chain, 1);
+        upload(&mut store, &second);
+
+        write_string(&mut fs, "/c.txt", "three").unwrap();
+        let third = prepare_with(&fs.snapshot(), Some(&second.manifest), &config, 300, 10)
+            .unwrap()
+            .unwrap();
+        upload(&mut store, &third);
+
+        // After `full_every` incrementals the next one is full again
+        write_string(&mut fs, "/c.txt", "tres").unwrap();
+        let fourth = prepare_with(&fs.snapshot(), Some(&third.manifest), &config, 400, 10)
+            .unwrap()
+            .unwrap();
+        assert!(fourth.entry.is_full());
+
+        // Restoring 300 follows the chain back to 100
+        let chain: Vec<Delta> = [100, 200, 300]
+            .iter()
+            .map(|&time| download(&store, time))
+            .collect();
+        let mut restored = rebuild(&chain).unwrap();
+        assert_eq!(
+            crate::vfs::read_to_string(&mut restored, "/a.txt").unwrap(),
+            "uno"
+        );
+        assert!(!restored.exists("/b.txt"));
+        assert!(restored.exists("/c.txt"));
+        assert_eq!(
+            fingerprints(&restored.snapshot()),
+            third.manifest.fingerprints
+        );
+
+        assert!(rebuild(&chain[1..]).is_err());
+        assert!(rebuild(&[chain[0].clone(), chain[2].clone()]).is_err());
+        let index: ObjectIndex = serde_json::from_slice(&store[&object_name(200)]).unwrap();
+        assert!(index.assemble(&[]).is_err());
+        assert!(open(&store[&chunk_name(&object_name(200), 0)], "wrong").is_err());
+    }
+
+    #[test]
+    fn test_schedule() {
+        let mut config = settings();
+        assert!(is_due(&config, None, 0.0));
+        assert!(!is_due(&config, Some(0.0), 59.0 * 60_000.0));
+        assert!(is_due(&config, Some(0.0), 60.0 * 60_000.0));
+        config.interval = 0;
+        assert!(!is_due(&config, None, 0.0));
+        assert!(is_excluded("/var/lib/backup/catalog"));
+        assert!(!is_excluded("/var/lib/backups"));
+    }
+}
@@ -3,7 +3,15 @@
 //! These commands are implemented directly in the shell, not as separate programs.
 //! They need access to shell state (current directory, environment, etc.).
 
-use std::collections::HashMap;
+use super::help;
+use super::history;
+use super::journal::{DEFAULT_SHOW, JournalRequest};
+use super::osimage::OsImageRequest;
+use crate::kernel::process::{EnvInherit, EnvSpec};
+use crate::kernel::signal::Signal;
+use crate::kernel::syscall;
+use crate::kernel::users::FileMode;
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
 
 /// Result of executing a built-in command
@@ -27,6 +35,117 @@ pub enum BuiltinResult {
     SetAlias(Vec<(String, String)>),
     /// Request to remove aliases
     UnsetAlias(Vec<String>),
+    /// Request to show, replay or undo from the command journal
+    Journal(JournalRequest),
+    /// Request to change the shell options
+    SetOptions(ShellOptions),
+    /// Request to set (`Some`) or reset (`None`) traps, by condition
+    SetTraps(Vec<(String, Option<String>)>),
+    /// Request to run a command while holding a file lock
+    WithLock(LockRequest),
+    /// Request to run a command in a changed environment, from `env`
+    WithEnv(EnvSpec, String),
+    /// Request to export, import or inspect an OS image
+    OsImage(OsImageRequest),
+    /// Variables to update after one `getopts` step
+    Getopts(GetoptsResult),
+    /// Request to run the commands in a file with the given positional
+    /// parameters, from `source`
+    Source(String, Vec<String>),
+    /// Request to run a command from the history again, from `fc -s`
+    Rerun(String),
+}
+
+/// Where `getopts` is inside a cluster of short options like `-abc`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GetoptsState {
+    /// `OPTIND` value the offset belongs to; when a script resets `OPTIND`
+    /// the offset no longer applies
+    pub optind: usize,
+    /// Byte offset of the next option within the argument
+    pub offset: usize,
+}
+
+/// One step of `getopts`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GetoptsResult {
+    /// Variables to set (the NAME variable, `OPTIND` and maybe `OPTARG`)
+    pub set: Vec<(String, String)>,
+    /// Variables to unset
+    pub unset: Vec<String>,
+    /// New position within a cluster of options
+    pub state: GetoptsState,
+    /// Diagnostic for an unknown option or missing argument
+    pub error: String,
+    /// 0 while options remain, 1 at the end of the options
+    pub status: i32,
+}
+
+/// A command to run with a lock held, from `flock`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockRequest {
+    /// File to lock, created if missing
+    pub path: String,
+    /// Take a shared lock instead of an exclusive one
+    pub shared: bool,
+    /// `-n`: fail quietly if the lock is held
+    pub nonblock: bool,
+    /// Exit status when the lock is held
+    pub conflict_code: i32,
+    /// Command line to run
+    pub command: String,
+}
+
+/// Shell options changed with `set`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShellOptions {
+    /// `set -e`: stop a command list at the first failing command
+    pub errexit: bool,
+    /// `set -H` (on by default): expand `!` history references in typed
+    /// lines
+    pub histexpand: bool,
+    /// `set -o pipefail`: a pipeline fails if any of its commands fails
+    pub pipefail: bool,
+    /// `set -x`: print each command to stderr, expanded, before running it
+    pub xtrace: bool,
+}
+
+impl Default for ShellOptions {
+    fn default() -> Self {
+        Self {
+            errexit: false,
+            histexpand: true,
+            pipefail: false,
+            xtrace: false,
+        }
+    }
+}
+
+impl ShellOptions {
+    /// Option names, in the order `set -o` lists them
+    pub const NAMES: [&'static str; 4] = ["errexit", "histexpand", "pipefail", "xtrace"];
+
+    /// Look up an option by its `-o` name
+    pub fn get(&self, name: &str) -> Option<bool> {
+        match name {
+            "errexit" => Some(self.errexit),
+            "histexpand" => Some(self.histexpand),
+            "pipefail" => Some(self.pipefail),
+            "xtrace" => Some(self.xtrace),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, name: &str, on: bool) -> bool {
+        match name {
+            "errexit" => self.errexit = on,
+            "histexpand" => self.histexpand = on,
+            "pipefail" => self.pipefail = on,
+            "xtrace" => self.xtrace = on,
+            _ => return false,
+        }
+        true
+    }
 }
 
 /// Shell state accessible to built-in commands
@@ -43,6 +162,17 @@ pub struct ShellState {
     pub arrays: HashMap<String, Vec<String>>,
     /// Last command exit code
     pub last_status: i32,
+    /// Options set with `set`
+    pub options: ShellOptions,
+    /// Traps: command to run by condition (`EXIT`, `SIGINT`, ...); an
+    /// empty command ignores the signal
+    pub traps: BTreeMap<String, String>,
+    /// Position of `getopts` within clustered options
+    pub getopts: GetoptsState,
+    /// Typed command lines, oldest first, after history expansion
+    pub history: Vec<String>,
+    /// Positional parameters (`$1`, `$2`, ...) of the file being sourced
+    pub positional: Vec<String>,
 }
 
 impl ShellState {
@@ -54,6 +184,11 @@ impl ShellState {
             functions: HashMap::new(),
             arrays: HashMap::new(),
             last_status: 0,
+            options: ShellOptions::default(),
+            traps: BTreeMap::new(),
+            getopts: GetoptsState::default(),
+            history: Vec::new(),
+            positional: Vec::new(),
         }
     }
 
@@ -177,6 +312,17 @@ pub fn is_builtin(name: &str) -> bool {
             | "help"
             | "alias"
             | "unalias"
+            | "journal"
+            | "undo-last"
+            | "set"
+            | "trap"
+            | "flock"
+            | "osimage"
+            | "getopts"
+            | "source"
+            | "."
+            | "fc"
+            | "umask"
     )
 }
 
@@ -189,16 +335,73 @@ pub fn execute(name: &str, args: &[String], state: &ShellState) -> BuiltinResult
         "echo" => builtin_echo(args),
         "export" => builtin_export(args, state),
         "unset" => builtin_unset(args),
-        "env" => builtin_env(state),
+        "env" => builtin_env(args, state),
         "true" => BuiltinResult::Ok,
         "false" => BuiltinResult::Error("".into()),
-        "help" => builtin_help(),
+        "help" => builtin_help(args),
         "alias" => builtin_alias(args, state),
         "unalias" => builtin_unalias(args),
+        "journal" => builtin_journal(args),
+        "undo-last" => builtin_undo_last(args),
+        "set" => builtin_set(args, state),
+        "trap" => builtin_trap(args, state),
+        "flock" => builtin_flock(args),
+        "osimage" => builtin_osimage(args),
+        "getopts" => builtin_getopts(args, state),
+        "source" | "." => builtin_source(name, args),
+        "fc" => builtin_fc(args, state),
+        "umask" => builtin_umask(args),
         _ => BuiltinResult::Error(format!("{}: not a builtin", name)),
     }
 }
 
+const JOURNAL_USAGE: &str =
+    "journal: usage: journal [show] [N] | journal diff SEQ | journal replay FROM [TO]";
+
+/// journal - show or replay the command journal
+fn builtin_journal(args: &[String]) -> BuiltinResult {
+    // Entries may be given as `12` or `#12`
+    let seq = |arg: &str| arg.trim_start_matches('#').parse::<u64>().ok();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let request = match args.as_slice() {
+        [] | ["show"] => JournalRequest::Show {
+            count: DEFAULT_SHOW,
+        },
+        [n] | ["show", n] => match n.parse() {
+            Ok(count) => JournalRequest::Show { count },
+            Err(_) => return BuiltinResult::Error(JOURNAL_USAGE.into()),
+        },
+        ["diff", n] => match seq(n) {
+            Some(seq) => JournalRequest::Diff { seq },
+            None => return BuiltinResult::Error(format!("journal: {}: invalid entry", n)),
+        },
+        ["replay", from] => match seq(from) {
+            Some(from) => JournalRequest::Replay { from, to: from },
+            None => return BuiltinResult::Error(format!("journal: {}: invalid entry", from)),
+        },
+        ["replay", from, to] => match (seq(from), seq(to)) {
+            (Some(from), Some(to)) if from <= to => JournalRequest::Replay { from, to },
+            _ => return BuiltinResult::Error("journal: replay: invalid range".into()),
+        },
+        _ => return BuiltinResult::Error(JOURNAL_USAGE.into()),
+    };
+    BuiltinResult::Journal(request)
+}
+
+/// undo-last - revert the filesystem effects of the last destructive command
+fn builtin_undo_last(args: &[String]) -> BuiltinResult {
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        [] => BuiltinResult::Journal(JournalRequest::Undo { force: false }),
+        ["-f"] => BuiltinResult::Journal(JournalRequest::Undo { force: true }),
+        _ => BuiltinResult::Error("undo-last: usage: undo-last [-f]".into()),
+    }
+}
+
 /// cd - change directory
 fn builtin_cd(args: &[String], state: &ShellState) -> BuiltinResult {
     let target = if args.is_empty() {
@@ -244,7 +447,7 @@ fn resolve_path(cwd: &Path, path: &str) -> PathBuf {
 }
 
 /// Normalize a path (resolve . and ..)
-fn normalize_path(path: &Path) -> PathBuf {
+pub(crate) fn normalize_path(path: &Path) -> PathBuf {
     let mut result = PathBuf::new();
     for component in path.components() {
         match component {
@@ -344,6 +547,8 @@ fn process_escapes(s: &str) -> String {
                 Some('n') => result.push('\n'),
                 Some('t') => result.push('\t'),
                 Some('r') => result.push('\r'),
+                Some('a') => result.push('\x07'),
+                Some('e') => result.push('\x1b'),
                 Some('\\') => result.push('\\'),
                 Some('0') => result.push('\0'),
                 Some(other) => {
@@ -398,7 +603,7 @@ fn builtin_export(args: &[String], state: &ShellState) -> BuiltinResult {
 }
 
 /// Check if a string is a valid variable name
-fn is_valid_var_name(name: &str) -> bool {
+pub(crate) fn is_valid_var_name(name: &str) -> bool {
     if name.is_empty() {
         return false;
     }
@@ -427,10 +632,66 @@ fn builtin_unset(args: &[String]) -> BuiltinResult {
     BuiltinResult::Unset(vars_to_unset)
 }
 
-/// env - list environment variables
-fn builtin_env(state: &ShellState) -> BuiltinResult {
+const ENV_USAGE: &str =
+    "env: usage: env [-i] [-a NAME[,NAME...]] [-u NAME] [NAME=VALUE...] [COMMAND [ARGS...]]";
+
+/// env - list environment variables, or run a command with changed ones
+///
+/// `-i` starts from an empty environment and `-a` from just the named
+/// variables; `-u` removes one and `NAME=VALUE` sets one. The command
+/// runs with the result and the shell's own environment is left alone.
+fn builtin_env(args: &[String], state: &ShellState) -> BuiltinResult {
+    let mut spec = EnvSpec::inherit();
+    let mut i = 0;
+    while let Some(arg) = args.get(i) {
+        match arg.as_str() {
+            "-i" | "-" | "--ignore-environment" => spec.inherit = EnvInherit::Nothing,
+            "-u" | "--unset" => {
+                i += 1;
+                match args.get(i) {
+                    Some(name) => spec.unset.push(name.clone()),
+                    None => return BuiltinResult::Error(ENV_USAGE.into()),
+                }
+            }
+            "-a" | "--allow" => {
+                i += 1;
+                let Some(names) = args.get(i) else {
+                    return BuiltinResult::Error(ENV_USAGE.into());
+                };
+                let names = names.split(',').filter(|n| !n.is_empty()).map(String::from);
+                match &mut spec.inherit {
+                    EnvInherit::Only(allowed) => allowed.extend(names),
+                    EnvInherit::All => spec.inherit = EnvInherit::Only(names.collect()),
+                    // -i already keeps nothing
+                    EnvInherit::Nothing => {}
+                }
+            }
+            "--" => {
+                i += 1;
+                break;
+            }
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                return BuiltinResult::Error(format!("env: unknown option: {}", arg));
+            }
+            _ => break,
+        }
+        i += 1;
+    }
+    while let Some((name, value)) = args.get(i).and_then(|a| a.split_once('=')) {
+        if name.is_empty() {
+            return BuiltinResult::Error(format!("env: invalid assignment: {}", args[i]));
+        }
+        spec.set.push((name.to_string(), value.to_string()));
+        i += 1;
+    }
+
+    let command = &args[i.min(args.len())..];
+    if !command.is_empty() {
+        return BuiltinResult::WithEnv(spec, quote_words(command));
+    }
+    let env = spec.resolve(&state.env);
     let mut output = String::new();
-    let mut vars: Vec<_> = state.env.iter().collect();
+    let mut vars: Vec<_> = env.iter().collect();
     vars.sort_by(|a, b| a.0.cmp(b.0));
     for (name, value) in vars {
         output.push_str(&format!("{}={}\n", name, value));
@@ -438,8 +699,221 @@ fn builtin_env(state: &ShellState) -> BuiltinResult {
     BuiltinResult::Success(output.trim_end().to_string())
 }
 
-/// help - show available commands
-fn builtin_help() -> BuiltinResult {
+/// set - change or list shell options
+///
+/// `-e`/`+e`, `-H`/`+H` and `-x`/`+x` turn errexit, histexpand and
+/// xtrace on and off, `-o NAME` and `+o NAME` any option by name. With no
+/// arguments, or just `-o`, the options are listed.
+fn builtin_set(args: &[String], state: &ShellState) -> BuiltinResult {
+    if args.is_empty() || args == ["-o"] {
+        let mut output = String::new();
+        for name in ShellOptions::NAMES {
+            let on = state.options.get(name).unwrap_or(false);
+            output.push_str(&format!("{:<15}{}\n", name, if on { "on" } else { "off" }));
+        }
+        return BuiltinResult::Success(output.trim_end().to_string());
+    }
+
+    let mut options = state.options;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let on = match arg.chars().next() {
+            Some('-') => true,
+            Some('+') => false,
+            _ => {
+                return BuiltinResult::Error(format!(
+                    "set: {}: positional parameters are not supported",
+                    arg
+                ));
+            }
+        };
+        let flags = &arg[1..];
+        if flags == "o" {
+            let Some(name) = args.next() else {
+                return BuiltinResult::Error("set: -o: option name required".into());
+            };
+            if !options.set(name, on) {
+                return BuiltinResult::Error(format!("set: {}: invalid option name", name));
+            }
+            continue;
+        }
+        for flag in flags.chars() {
+            let name = match flag {
+                'e' => "errexit",
+                'H' => "histexpand",
+                'x' => "xtrace",
+                _ => return BuiltinResult::Error(format!("set: -{}: invalid option", flag)),
+            };
+            options.set(name, on);
+        }
+    }
+    BuiltinResult::SetOptions(options)
+}
+
+/// Condition a trap is set on: `EXIT` (or `0`), or a signal name with or
+/// without `SIG`
+fn trap_condition(name: &str) -> Result<String, String> {
+    if name == "0" || name.eq_ignore_ascii_case("EXIT") {
+        return Ok("EXIT".to_string());
+    }
+    match Signal::from_name(name) {
+        Some(signal) if signal.can_catch() => Ok(signal.to_string()),
+        Some(signal) => Err(format!("trap: {}: cannot be trapped", signal)),
+        None => Err(format!("trap: {}: invalid signal specification", name)),
+    }
+}
+
+/// trap - run a command when the shell gets a signal or exits
+///
+/// `trap CMD COND...` sets, `trap '' COND...` ignores, `trap - COND...`
+/// resets, and `trap` or `trap -p [COND...]` lists.
+fn builtin_trap(args: &[String], state: &ShellState) -> BuiltinResult {
+    let list = |conditions: Vec<String>| {
+        let lines: Vec<String> = conditions
+            .into_iter()
+            .filter_map(|c| {
+                let action = state.traps.get(&c)?;
+                Some(format!("trap -- '{}' {}", action.replace('\'', "'\\''"), c))
+            })
+            .collect();
+        BuiltinResult::Success(lines.join("\n"))
+    };
+    let conditions = |names: &[String]| -> Result<Vec<String>, String> {
+        names.iter().map(|n| trap_condition(n)).collect()
+    };
+
+    match args.first().map(String::as_str) {
+        None => list(state.traps.keys().cloned().collect()),
+        Some("-p") if args.len() == 1 => list(state.traps.keys().cloned().collect()),
+        Some("-p") => match conditions(&args[1..]) {
+            Ok(c) => list(c),
+            Err(e) => BuiltinResult::Error(e),
+        },
+        Some("-l") => {
+            let names: Vec<String> = Signal::ALL
+                .into_iter()
+                .map(|s| format!("{:>2}) {}", s.num(), s))
+                .collect();
+            BuiltinResult::Success(names.join("\n"))
+        }
+        Some(_) if args.len() < 2 => {
+            BuiltinResult::Error("trap: usage: trap [-lp] [[ACTION] CONDITION...]".into())
+        }
+        Some(action) => {
+            let action = (action != "-").then(|| action.to_string());
+            match conditions(&args[1..]) {
+                Ok(c) => {
+                    BuiltinResult::SetTraps(c.into_iter().map(|c| (c, action.clone())).collect())
+                }
+                Err(e) => BuiltinResult::Error(e),
+            }
+        }
+    }
+}
+
+const FLOCK_USAGE: &str = "flock: usage: flock [-s|-x] [-n] [-E CODE] FILE COMMAND [ARGS...] | flock [-s|-x] [-n] FILE -c COMMAND";
+
+/// flock - run a command while holding a lock on a file
+///
+/// A lock held by another process can't be waited for, so it always fails
+/// (with status 1, or `-E CODE`); `-n` just makes that quiet.
+fn builtin_flock(args: &[String]) -> BuiltinResult {
+    let mut request = LockRequest {
+        path: String::new(),
+        shared: false,
+        nonblock: false,
+        conflict_code: 1,
+        command: String::new(),
+    };
+    let mut i = 0;
+    while let Some(arg) = args.get(i).filter(|a| a.starts_with('-') && a.len() > 1) {
+        match arg.as_str() {
+            "-s" | "--shared" => request.shared = true,
+            "-x" | "-e" | "--exclusive" => request.shared = false,
+            "-n" | "--nonblock" => request.nonblock = true,
+            "-E" | "--conflict-exit-code" => {
+                i += 1;
+                match args.get(i).and_then(|c| c.parse::<u8>().ok()) {
+                    Some(code) => request.conflict_code = code.into(),
+                    None => return BuiltinResult::Error(FLOCK_USAGE.into()),
+                }
+            }
+            "--" => {
+                i += 1;
+                break;
+            }
+            _ => return BuiltinResult::Error(format!("flock: unknown option: {}", arg)),
+        }
+        i += 1;
+    }
+
+    let rest = &args[i.min(args.len())..];
+    request.command = match rest {
+        [_, flag, command] if flag == "-c" => command.clone(),
+        [_, command @ ..] if !command.is_empty() => quote_words(command),
+        _ => return BuiltinResult::Error(FLOCK_USAGE.into()),
+    };
+    request.path = rest[0].clone();
+    BuiltinResult::WithLock(request)
+}
+
+/// Join words into a command line that splits back into them
+fn quote_words(words: &[String]) -> String {
+    words
+        .iter()
+        .map(|w| format!("'{}'", w.replace('\'', "'\\''")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+const OSIMAGE_USAGE: &str =
+    "osimage: usage: osimage export|import FILE [-p PASSWORD] | osimage info FILE | osimage upload";
+
+/// osimage - move the whole system between installations as one file
+fn builtin_osimage(args: &[String]) -> BuiltinResult {
+    let mut password = None;
+    let mut words = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-p" | "--password" => match iter.next() {
+                Some(p) if !p.is_empty() => password = Some(p.clone()),
+                _ => return BuiltinResult::Error("osimage: -p needs a password".into()),
+            },
+            _ => words.push(arg.as_str()),
+        }
+    }
+
+    let request = match (words.as_slice(), password) {
+        (["export", path], password) => OsImageRequest::Export {
+            path: path.to_string(),
+            password,
+        },
+        (["import", path], password) => OsImageRequest::Import {
+            path: path.to_string(),
+            password,
+        },
+        (["info", path], None) => OsImageRequest::Info {
+            path: path.to_string(),
+        },
+        (["upload"], None) => OsImageRequest::Upload,
+        _ => return BuiltinResult::Error(OSIMAGE_USAGE.into()),
+    };
+    BuiltinResult::OsImage(request)
+}
+
+/// help - show available commands, a help topic or a command's summary
+fn builtin_help(args: &[String]) -> BuiltinResult {
+    match args.first().map(String::as_str) {
+        None => {}
+        Some("topics") => return BuiltinResult::Success(help::list_topics()),
+        Some(name) => {
+            return match help::lookup(name) {
+                Ok(text) => BuiltinResult::Success(text),
+                Err(e) => BuiltinResult::Error(e),
+            };
+        }
+    }
     BuiltinResult::Success(
         "Built-in commands:
   cd [dir]       Change directory
@@ -448,10 +922,17 @@ fn builtin_help() -> BuiltinResult {
   echo [args]    Print arguments
   export [VAR=val] Set environment variable
   unset VAR      Remove environment variable
-  env            List environment variables
+  env [-i] [VAR=val] [CMD] List variables, or run CMD with changed ones
   true           Return success
   false          Return failure
   help           Show this help
+  journal [N]    Show the command journal
+  undo-last [-f] Revert the last destructive command
+  set [-ex] [-o OPTION] Set errexit, xtrace or pipefail
+  trap [CMD] SIG Run CMD on a signal or EXIT
+  flock FILE CMD Run CMD holding a lock on FILE
+  umask [-S] [MODE] Show or set the file creation mask
+  osimage export|import FILE Save or restore the whole system
 
 File commands:
   ls [path]      List directory contents
@@ -484,32 +965,45 @@ Editor:
 Other:
   clear          Clear screen
   history [N]    Show command history
+  history FILE   List versions of FILE kept in snapshots
   sleep <N>      Wait N seconds
   save           Persist filesystem to storage
-  man <cmd>      Display manual page for command"
+  man <cmd>      Display manual page for command
+  tutorial       Guided lessons for new users
+
+Type 'help topics' for help by topic, or 'help <topic>' for one."
             .to_string(),
     )
 }
 
 /// alias - define or list aliases
+///
+/// `alias` and `alias -p` list every alias as an `alias NAME='VALUE'`
+/// line that can be run again, so the listing can be saved and sourced.
 fn builtin_alias(args: &[String], state: &ShellState) -> BuiltinResult {
+    let args = match args.first() {
+        Some(flag) if flag == "-p" => &args[1..],
+        Some(flag) if flag.starts_with('-') && !flag.contains('=') => {
+            return BuiltinResult::Error(format!(
+                "alias: {}: invalid option\nalias: usage: alias [-p] [name[=value] ...]",
+                flag
+            ));
+        }
+        _ => args,
+    };
+
     if args.is_empty() {
         // List all aliases
         if state.aliases.is_empty() {
             return BuiltinResult::Ok;
         }
-        let mut output = String::new();
         let mut aliases: Vec<_> = state.aliases.iter().collect();
         aliases.sort_by(|a, b| a.0.cmp(b.0));
-        for (name, value) in aliases {
-            // Quote value if it contains spaces
-            if value.contains(' ') {
-                output.push_str(&format!("alias {}='{}'\n", name, value));
-            } else {
-                output.push_str(&format!("alias {}={}\n", name, value));
-            }
-        }
-        return BuiltinResult::Success(output.trim_end().to_string());
+        let lines: Vec<_> = aliases
+            .into_iter()
+            .map(|(name, value)| alias_line(name, value))
+            .collect();
+        return BuiltinResult::Success(lines.join("\n"));
     }
 
     // Set aliases
@@ -518,16 +1012,14 @@ fn builtin_alias(args: &[String], state: &ShellState) -> BuiltinResult {
         if let Some(eq_pos) = arg.find('=') {
             let name = &arg[..eq_pos];
             let value = &arg[eq_pos + 1..];
-            // Strip quotes if present
-            let value = value.trim_matches('\'').trim_matches('"');
-            if name.is_empty() {
-                return BuiltinResult::Error("alias: invalid alias name".into());
+            if !is_valid_alias_name(name) {
+                return BuiltinResult::Error(format!("alias: `{}': invalid alias name", name));
             }
             to_set.push((name.to_string(), value.to_string()));
         } else {
             // Show specific alias
             if let Some(value) = state.aliases.get(arg) {
-                return BuiltinResult::Success(format!("alias {}='{}'", arg, value));
+                return BuiltinResult::Success(alias_line(arg, value));
             } else {
                 return BuiltinResult::Error(format!("alias: {}: not found", arg));
             }
@@ -542,6 +1034,43 @@ fn builtin_alias(args: &[String], state: &ShellState) -> BuiltinResult {
     }
 }
 
+/// An `alias` command line that defines NAME as VALUE again
+///
+/// The shell has no escapes outside quotes, so a value holding `'` is
+/// double-quoted instead, escaping `\` and `"`.
+fn alias_line(name: &str, value: &str) -> String {
+    if value.contains('\'') {
+        let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+        format!("alias {}=\"{}\"", name, escaped)
+    } else {
+        format!("alias {}='{}'", name, value)
+    }
+}
+
+/// Alias names are plain words: nothing the shell treats specially
+fn is_valid_alias_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.chars().any(|c| {
+            c.is_whitespace()
+                || matches!(
+                    c,
+                    '=' | '\''
+                        | '"'
+                        | '\\'
+                        | '/'
+                        | '$'
+                        | '`'
+                        | '|'
+                        | '&'
+                        | ';'
+                        | '<'
+                        | '>'
+                        | '('
+                        | ')'
+                )
+        })
+}
+
 /// unalias - remove alias
 fn builtin_unalias(args: &[String]) -> BuiltinResult {
     if args.is_empty() {
@@ -551,6 +1080,290 @@ fn builtin_unalias(args: &[String]) -> BuiltinResult {
     BuiltinResult::UnsetAlias(args.to_vec())
 }
 
+/// source - run the commands in a file in this shell, with any further
+/// arguments as its positional parameters
+fn builtin_source(name: &str, args: &[String]) -> BuiltinResult {
+    match args {
+        [path, params @ ..] => BuiltinResult::Source(path.clone(), params.to_vec()),
+        _ => BuiltinResult::Error(format!("{}: usage: {} FILE [ARG...]", name, name)),
+    }
+}
+
+const FC_USAGE: &str = "fc: usage: fc -l [-nr] [FIRST [LAST]] | fc -s [OLD=NEW] [FIRST]";
+
+/// Commands `fc -l` lists when not told which
+const FC_LIST_DEFAULT: usize = 16;
+
+/// umask - show or set the file creation mask
+///
+/// The mask is inherited by every process the shell starts, so a change
+/// affects the files and directories they create as well.
+fn builtin_umask(args: &[String]) -> BuiltinResult {
+    let (symbolic, operands) = match args.first().map(String::as_str) {
+        Some("-S") => (true, &args[1..]),
+        _ => (false, args),
+    };
+    let current = match syscall::umask(0) {
+        Ok(mask) => mask,
+        Err(e) => return BuiltinResult::Error(format!("umask: {}", e)),
+    };
+    let _ = syscall::umask(current);
+    match operands {
+        [] if symbolic => BuiltinResult::Success(symbolic_umask(current)),
+        [] => BuiltinResult::Success(format!("{:04o}", current)),
+        [spec] => match parse_umask(spec, current) {
+            Some(mask) => match syscall::umask(mask) {
+                Ok(_) => BuiltinResult::Ok,
+                Err(e) => BuiltinResult::Error(format!("umask: {}", e)),
+            },
+            None => BuiltinResult::Error(format!("umask: {}: invalid mask", spec)),
+        },
+        _ => BuiltinResult::Error("umask: usage: umask [-S] [MODE]".into()),
+    }
+}
+
+/// A mask as the permissions it leaves, like `u=rwx,g=rx,o=rx`
+fn symbolic_umask(mask: u16) -> String {
+    let allowed = !mask;
+    let class = |shift: u16| {
+        let bits = (allowed >> shift) & 0o7;
+        [(0o4, 'r'), (0o2, 'w'), (0o1, 'x')]
+            .iter()
+            .filter(|(bit, _)| bits & bit != 0)
+            .map(|&(_, c)| c)
+            .collect::<String>()
+    };
+    format!("u={},g={},o={}", class(6), class(3), class(0))
+}
+
+/// The mask `spec` gives: an octal mask, or a symbolic mode applied to
+/// the permissions `current` leaves, so `g+w` stops masking group write
+fn parse_umask(spec: &str, current: u16) -> Option<u16> {
+    if spec.starts_with(|c: char| c.is_ascii_digit()) {
+        return u16::from_str_radix(spec, 8).ok().filter(|&m| m <= 0o777);
+    }
+    let allowed = FileMode::new(!current & 0o777).apply_symbolic(spec, true, 0)?;
+    Some(!allowed.0 & 0o777)
+}
+
+/// fc - list or re-run commands from the history
+///
+/// FIRST and LAST are command numbers, negative offsets from the newest
+/// command, or the start of a command. Opening commands in an editor, what
+/// `fc` does with neither `-l` nor `-s`, isn't supported.
+fn builtin_fc(args: &[String], state: &ShellState) -> BuiltinResult {
+    let history = &state.history;
+    let (mut list, mut rerun, mut numbers, mut reverse) = (false, false, true, false);
+    let mut operands = Vec::new();
+    for arg in args {
+        match arg.strip_prefix('-') {
+            Some(flags) if !flags.is_empty() && flags.parse::<u64>().is_err() => {
+                for flag in flags.chars() {
+                    match flag {
+                        'l' => list = true,
+                        's' => rerun = true,
+                        'n' => numbers = false,
+                        'r' => reverse = true,
+                        _ => {
+                            return BuiltinResult::Error(format!(
+                                "fc: -{}: invalid option\n{}",
+                                flag, FC_USAGE
+                            ));
+                        }
+                    }
+                }
+            }
+            _ => operands.push(arg.as_str()),
+        }
+    }
+    let find = |spec: &str| {
+        history::fc_index(history, spec).ok_or_else(|| format!("fc: {}: no command found", spec))
+    };
+
+    if list && !rerun {
+        if history.is_empty() {
+            return BuiltinResult::Ok;
+        }
+        let range = match operands.as_slice() {
+            [] => Ok((
+                history.len().saturating_sub(FC_LIST_DEFAULT),
+                history.len() - 1,
+            )),
+            [first] => find(first).map(|first| (first, history.len() - 1)),
+            [first, last] => find(first).and_then(|first| Ok((first, find(last)?))),
+            _ => return BuiltinResult::Error(FC_USAGE.into()),
+        };
+        let (first, last) = match range {
+            Ok(range) => range,
+            Err(e) => return BuiltinResult::Error(e),
+        };
+        let mut indexes: Vec<usize> = (first.min(last)..=first.max(last)).collect();
+        if reverse != (first > last) {
+            indexes.reverse();
+        }
+        let lines: Vec<String> = indexes
+            .into_iter()
+            .map(|i| match numbers {
+                true => format!("{:5}  {}", i + 1, history[i]),
+                false => format!("       {}", history[i]),
+            })
+            .collect();
+        return BuiltinResult::Success(lines.join("\n"));
+    }
+
+    if rerun && !list {
+        let (substitution, first) = match operands.as_slice() {
+            [] => (None, None),
+            [one] if one.contains('=') => (Some(*one), None),
+            [one] => (None, Some(*one)),
+            [sub, first] if sub.contains('=') => (Some(*sub), Some(*first)),
+            _ => return BuiltinResult::Error(FC_USAGE.into()),
+        };
+        let index = match first {
+            Some(spec) => find(spec),
+            None if history.is_empty() => Err("fc: no command found".to_string()),
+            None => Ok(history.len() - 1),
+        };
+        let mut command = match index {
+            Ok(i) => history[i].clone(),
+            Err(e) => return BuiltinResult::Error(e),
+        };
+        if let Some((old, new)) = substitution.and_then(|s| s.split_once('='))
+            && !old.is_empty()
+        {
+            command = command.replace(old, new);
+        }
+        return BuiltinResult::Rerun(command);
+    }
+
+    BuiltinResult::Error(format!(
+        "fc: editing commands is not supported, use -l or -s\n{}",
+        FC_USAGE
+    ))
+}
+
+const GETOPTS_USAGE: &str = "getopts: usage: getopts OPTSTRING NAME [ARG...]";
+
+/// getopts - parse one option from ARGS, POSIX style
+///
+/// Each call stores the next option letter in NAME, its argument in
+/// `OPTARG` and the index of the next argument in `OPTIND`. Without ARGS it
+/// parses the positional parameters. It fails at the first operand, at
+/// `--`, or when ARGS run out. A leading `:` in OPTSTRING
+/// selects silent error reporting: unknown options set NAME to `?` and
+/// missing arguments set it to `:`, with the option letter in `OPTARG`.
+fn builtin_getopts(args: &[String], state: &ShellState) -> BuiltinResult {
+    let [optstring, name, params @ ..] = args else {
+        return BuiltinResult::Error(GETOPTS_USAGE.into());
+    };
+    let params = if params.is_empty() {
+        state.positional.as_slice()
+    } else {
+        params
+    };
+    if !is_valid_var_name(name) {
+        return BuiltinResult::Error(format!("getopts: `{}': not a valid identifier", name));
+    }
+    let (silent, optstring) = match optstring.strip_prefix(':') {
+        Some(rest) => (true, rest),
+        None => (false, optstring.as_str()),
+    };
+
+    let mut optind = state
+        .get_env("OPTIND")
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|&n| n >= 1)
+        .unwrap_or(1);
+    let mut offset = if state.getopts.optind == optind {
+        state.getopts.offset
+    } else {
+        0
+    };
+
+    let mut result = GetoptsResult {
+        set: Vec::new(),
+        unset: Vec::new(),
+        state: GetoptsState::default(),
+        error: String::new(),
+        status: 0,
+    };
+
+    let arg = params.get(optind - 1).map(String::as_str);
+    if offset == 0 || arg.is_none_or(|a| offset >= a.len()) {
+        offset = 0;
+        match arg {
+            Some("--") => {
+                optind += 1;
+                result.status = 1;
+            }
+            Some(a) if a.len() > 1 && a.starts_with('-') => offset = 1,
+            _ => result.status = 1,
+        }
+    }
+    if result.status != 0 {
+        result.set.push((name.clone(), "?".into()));
+        result.set.push(("OPTIND".into(), optind.to_string()));
+        result.unset.push("OPTARG".into());
+        return BuiltinResult::Getopts(result);
+    }
+
+    let Some(arg) = arg else {
+        return BuiltinResult::Error(GETOPTS_USAGE.into());
+    };
+    let Some(letter) = arg.get(offset..).and_then(|s| s.chars().next()) else {
+        return BuiltinResult::Error(GETOPTS_USAGE.into());
+    };
+    offset += letter.len_utf8();
+    let rest = &arg[offset..];
+    let takes_arg = match optstring.find(letter) {
+        Some(i) if letter != ':' => Some(optstring[i + letter.len_utf8()..].starts_with(':')),
+        _ => None,
+    };
+
+    let (value, optarg) = match takes_arg {
+        None => {
+            if !silent {
+                result.error = format!("getopts: illegal option -- {}", letter);
+            }
+            ("?".to_string(), silent.then(|| letter.to_string()))
+        }
+        Some(false) => (letter.to_string(), None),
+        Some(true) if !rest.is_empty() => {
+            offset = arg.len();
+            (letter.to_string(), Some(rest.to_string()))
+        }
+        Some(true) => match params.get(optind) {
+            Some(next) => {
+                optind += 1;
+                offset = arg.len();
+                (letter.to_string(), Some(next.clone()))
+            }
+            None => {
+                offset = arg.len();
+                if silent {
+                    (":".to_string(), Some(letter.to_string()))
+                } else {
+                    result.error = format!("getopts: option requires an argument -- {}", letter);
+                    ("?".to_string(), None)
+                }
+            }
+        },
+    };
+
+    if offset >= arg.len() {
+        optind += 1;
+        offset = 0;
+    }
+    result.set.push((name.clone(), value));
+    result.set.push(("OPTIND".into(), optind.to_string()));
+    match optarg {
+        Some(optarg) => result.set.push(("OPTARG".into(), optarg)),
+        None => result.unset.push("OPTARG".into()),
+    }
+    result.state = GetoptsState { optind, offset };
+    BuiltinResult::Getopts(result)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -813,6 +1626,51 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_env_command() {
+        let mut state = make_state();
+        state.set_env("SECRET", "x");
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        // Without a command, the changed environment is listed
+        assert_eq!(
+            execute("env", &args(&["-i", "A=1", "B=2"]), &state),
+            BuiltinResult::Success("A=1\nB=2".into())
+        );
+        assert_eq!(
+            execute(
+                "env",
+                &args(&["-a", "HOME,NOPE", "-u", "HOME", "C=3"]),
+                &state
+            ),
+            BuiltinResult::Success("C=3".into())
+        );
+
+        assert_eq!(
+            execute(
+                "env",
+                &args(&["-u", "SECRET", "LANG=C", "echo", "it's"]),
+                &state
+            ),
+            BuiltinResult::WithEnv(
+                EnvSpec::inherit().unset("SECRET").set("LANG", "C"),
+                "'echo' 'it'\\''s'".into()
+            )
+        );
+        assert_eq!(
+            execute("env", &args(&["-a", "PATH", "-a", "HOME", "pwd"]), &state),
+            BuiltinResult::WithEnv(EnvSpec::allow(["PATH", "HOME"]), "'pwd'".into())
+        );
+        assert!(matches!(
+            execute("env", &args(&["-u"]), &state),
+            BuiltinResult::Error(_)
+        ));
+        assert!(matches!(
+            execute("env", &args(&["-x", "pwd"]), &state),
+            BuiltinResult::Error(_)
+        ));
+    }
+
     // ============ true/false ============
 
     #[test]
@@ -845,6 +1703,322 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_trap() {
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let mut state = ShellState::new();
+
+        assert_eq!(
+            execute("trap", &args(&["rm -f /tmp/x", "INT", "exit"]), &state),
+            BuiltinResult::SetTraps(vec![
+                ("SIGINT".into(), Some("rm -f /tmp/x".into())),
+                ("EXIT".into(), Some("rm -f /tmp/x".into())),
+            ])
+        );
+        assert_eq!(
+            execute("trap", &args(&["-", "SIGTERM", "0"]), &state),
+            BuiltinResult::SetTraps(vec![("SIGTERM".into(), None), ("EXIT".into(), None)])
+        );
+        assert_eq!(
+            execute("trap", &args(&["echo", "KILL"]), &state),
+            BuiltinResult::Error("trap: SIGKILL: cannot be trapped".into())
+        );
+        assert!(matches!(
+            execute("trap", &args(&["echo", "SIGNOPE"]), &state),
+            BuiltinResult::Error(_)
+        ));
+        assert!(matches!(
+            execute("trap", &args(&["echo"]), &state),
+            BuiltinResult::Error(_)
+        ));
+
+        state.traps.insert("EXIT".into(), "echo 'bye'".into());
+        state.traps.insert("SIGHUP".into(), String::new());
+        assert_eq!(
+            execute("trap", &[], &state),
+            BuiltinResult::Success("trap -- 'echo '\\''bye'\\''' EXIT\ntrap -- '' SIGHUP".into())
+        );
+        assert_eq!(
+            execute("trap", &args(&["-p", "HUP"]), &state),
+            BuiltinResult::Success("trap -- '' SIGHUP".into())
+        );
+    }
+
+    #[test]
+    fn test_flock() {
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let state = ShellState::new();
+
+        assert_eq!(
+            execute("flock", &args(&["-n", "/tmp/lock", "echo", "it's"]), &state),
+            BuiltinResult::WithLock(LockRequest {
+                path: "/tmp/lock".into(),
+                shared: false,
+                nonblock: true,
+                conflict_code: 1,
+                command: "'echo' 'it'\\''s'".into(),
+            })
+        );
+        assert_eq!(
+            execute(
+                "flock",
+                &args(&["-s", "-E", "75", "lock", "-c", "cat x"]),
+                &state
+            ),
+            BuiltinResult::WithLock(LockRequest {
+                path: "lock".into(),
+                shared: true,
+                nonblock: false,
+                conflict_code: 75,
+                command: "cat x".into(),
+            })
+        );
+        assert!(matches!(
+            execute("flock", &args(&["/tmp/lock"]), &state),
+            BuiltinResult::Error(_)
+        ));
+        assert!(matches!(
+            execute("flock", &args(&["-w", "5", "/tmp/lock", "true"]), &state),
+            BuiltinResult::Error(_)
+        ));
+    }
+
+    #[test]
+    fn test_osimage() {
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let state = ShellState::new();
+
+        assert_eq!(
+            execute("osimage", &args(&["export", "backup.axi"]), &state),
+            BuiltinResult::OsImage(OsImageRequest::Export {
+                path: "backup.axi".into(),
+                password: None,
+            })
+        );
+        assert_eq!(
+            execute("osimage", &args(&["import", "-p", "pw", "b.axi"]), &state),
+            BuiltinResult::OsImage(OsImageRequest::Import {
+                path: "b.axi".into(),
+                password: Some("pw".into()),
+            })
+        );
+        assert!(matches!(
+            execute("osimage", &args(&["export", "b.axi", "-p"]), &state),
+            BuiltinResult::Error(_)
+        ));
+        assert!(matches!(
+            execute("osimage", &args(&["info", "b.axi", "-p", "pw"]), &state),
+            BuiltinResult::Error(_)
+        ));
+        assert!(matches!(
+            execute("osimage", &args(&["import"]), &state),
+            BuiltinResult::Error(_)
+        ));
+    }
+
+    #[test]
+    fn test_set_options() {
+        let state = ShellState::new();
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        let expected = ShellOptions {
+            errexit: true,
+            histexpand: true,
+            pipefail: true,
+            xtrace: true,
+        };
+        assert_eq!(
+            execute("set", &args(&["-ex", "-o", "pipefail"]), &state),
+            BuiltinResult::SetOptions(expected)
+        );
+
+        let mut state = ShellState::new();
+        state.options = expected;
+        assert_eq!(
+            execute("set", &args(&["+x", "+o", "errexit"]), &state),
+            BuiltinResult::SetOptions(ShellOptions {
+                pipefail: true,
+                ..ShellOptions::default()
+            })
+        );
+        assert_eq!(
+            execute("set", &[], &state),
+            BuiltinResult::Success(
+                "errexit        on\nhistexpand     on\npipefail       on\nxtrace         on".into()
+            )
+        );
+        assert!(matches!(
+            execute("set", &args(&["-q"]), &state),
+            BuiltinResult::Error(_)
+        ));
+        assert!(matches!(
+            execute("set", &args(&["-o", "nonsense"]), &state),
+            BuiltinResult::Error(_)
+        ));
+    }
+
+    #[test]
+    fn test_help_topic() {
+        let state = make_state();
+        match execute("help", &["text".into()], &state) {
+            BuiltinResult::Success(s) => assert!(s.contains("man grep")),
+            _ => panic!("expected Success"),
+        }
+        match execute("help", &["topics".into()], &state) {
+            BuiltinResult::Success(s) => assert!(s.contains("files")),
+            _ => panic!("expected Success"),
+        }
+        assert!(matches!(
+            execute("help", &["nope".into()], &state),
+            BuiltinResult::Error(_)
+        ));
+    }
+
+    // ============ getopts ============
+
+    fn strings(a: &[&str]) -> Vec<String> {
+        a.iter().map(|s| s.to_string()).collect()
+    }
+
+    /// Run `getopts` until it fails, applying each step to the state, and
+    /// collect (NAME, OPTARG, error) per option
+    fn getopts_all(state: &mut ShellState, argv: &[&str]) -> Vec<(String, Option<String>, String)> {
+        let mut seen = Vec::new();
+        loop {
+            let BuiltinResult::Getopts(result) = execute("getopts", &strings(argv), state) else {
+                panic!("expected Getopts");
+            };
+            for (name, value) in &result.set {
+                state.set_env(name, value);
+            }
+            for name in &result.unset {
+                state.unset_env(name);
+            }
+            state.getopts = result.state;
+            if result.status != 0 {
+                return seen;
+            }
+            let opt = state.get_env("opt").unwrap_or_default().to_string();
+            let optarg = state.get_env("OPTARG").map(str::to_string);
+            seen.push((opt, optarg, result.error));
+        }
+    }
+
+    #[test]
+    fn test_getopts_clustered() {
+        let mut state = make_state();
+        let seen = getopts_all(
+            &mut state,
+            &["avf:", "opt", "-av", "-fout.txt", "-f", "x", "file", "-a"],
+        );
+        let names: Vec<_> = seen
+            .iter()
+            .map(|(o, a, _)| (o.as_str(), a.as_deref()))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("a", None),
+                ("v", None),
+                ("f", Some("out.txt")),
+                ("f", Some("x"))
+            ]
+        );
+        // Stops at the first operand, which OPTIND points at
+        assert_eq!(state.get_env("OPTIND"), Some("5"));
+        assert_eq!(state.get_env("opt"), Some("?"));
+
+        // `--` ends the options and is skipped
+        state.unset_env("OPTIND");
+        let seen = getopts_all(&mut state, &["ab", "opt", "-ba", "--", "-a"]);
+        assert_eq!(seen.len(), 2);
+        assert_eq!(state.get_env("OPTIND"), Some("3"));
+
+        // Resetting OPTIND starts over, even mid-cluster
+        state.set_env("OPTIND", "1");
+        state.getopts = GetoptsState {
+            optind: 1,
+            offset: 2,
+        };
+        let seen = getopts_all(&mut state, &["ab", "opt", "-ab"]);
+        assert_eq!(seen.len(), 1);
+        state.set_env("OPTIND", "1");
+        assert_eq!(getopts_all(&mut state, &["ab", "opt", "-ab"]).len(), 2);
+    }
+
+    #[test]
+    fn test_getopts_errors() {
+        let mut state = make_state();
+        let seen = getopts_all(&mut state, &["af:", "opt", "-x", "-af"]);
+        assert_eq!(seen.len(), 3);
+        assert_eq!(seen[0].0, "?");
+        assert_eq!(seen[0].2, "getopts: illegal option -- x");
+        assert_eq!(seen[1].0, "a");
+        assert_eq!(
+            seen[2],
+            (
+                "?".into(),
+                None,
+                "getopts: option requires an argument -- f".into()
+            )
+        );
+
+        // Silent mode reports through NAME and OPTARG instead
+        state.unset_env("OPTIND");
+        let seen = getopts_all(&mut state, &[":f:", "opt", "-x", "-f"]);
+        assert_eq!(
+            seen,
+            [
+                ("?".into(), Some("x".into()), String::new()),
+                (":".into(), Some("f".into()), String::new())
+            ]
+        );
+
+        assert!(matches!(
+            execute("getopts", &strings(&["ab"]), &state),
+            BuiltinResult::Error(_)
+        ));
+        assert!(matches!(
+            execute("getopts", &strings(&["ab", "1bad"]), &state),
+            BuiltinResult::Error(_)
+        ));
+    }
+
+    // ============ umask ============
+
+    #[test]
+    fn test_umask() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        let state = make_state();
+        let umask = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+            execute("umask", &args, &state)
+        };
+        assert_eq!(umask(&[]), BuiltinResult::Success("0022".into()));
+        assert_eq!(
+            umask(&["-S"]),
+            BuiltinResult::Success("u=rwx,g=rx,o=rx".into())
+        );
+
+        assert_eq!(umask(&["007"]), BuiltinResult::Ok);
+        assert_eq!(umask(&[]), BuiltinResult::Success("0007".into()));
+        assert_eq!(umask(&["g-w,o+r"]), BuiltinResult::Ok);
+        assert_eq!(
+            umask(&["-S"]),
+            BuiltinResult::Success("u=rwx,g=rx,o=r".into())
+        );
+
+        assert!(matches!(umask(&["999"]), BuiltinResult::Error(_)));
+        assert!(matches!(umask(&["g+q"]), BuiltinResult::Error(_)));
+        assert_eq!(umask(&[]), BuiltinResult::Success("0023".into()));
+    }
+
     // ============ is_builtin ============
 
     #[test]
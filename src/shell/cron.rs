@@ -0,0 +1,413 @@
+//! Cron runner
+//!
+//! Runs the crontabs in `/var/spool/cron/<user>` that `crontab` installs.
+//! The platform calls [`tick`] with the wall-clock time; each minute that
+//! has passed since the last tick is matched against every entry, and
+//! `@reboot` entries run on the first tick. Output goes to `/var/log/cron`.
+//!
+//! Entries prefixed with `@batch` are background work that can wait: while
+//! the power governor saves power they are queued, and they run once full
+//! speed returns.
+
+use super::Executor;
+use crate::kernel::syscall;
+use std::cell::RefCell;
+
+/// Directory of per-user crontabs
+pub const SPOOL_DIR: &str = "/var/spool/cron";
+
+/// Log of job runs
+pub const LOG_PATH: &str = "/var/log/cron";
+
+/// Most missed minutes caught up in one tick (e.g. after the page slept)
+const MAX_CATCH_UP: i64 = 60;
+
+/// When an entry runs
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schedule {
+    /// Once, on the first tick after boot
+    Reboot,
+    /// Minute, hour, day of month, month and day of week masks
+    Fields([u64; 5]),
+}
+
+/// Valid range of each time field
+const FIELD_RANGES: [(u32, u32); 5] = [(0, 59), (0, 23), (1, 31), (1, 12), (0, 6)];
+
+impl Schedule {
+    /// Parse the schedule part of an entry (`@daily` or five fields)
+    fn parse(fields: &[&str]) -> Option<Self> {
+        match fields {
+            ["@reboot"] => Some(Schedule::Reboot),
+            ["@hourly"] => Self::parse(&["0", "*", "*", "*", "*"]),
+            ["@daily"] | ["@midnight"] => Self::parse(&["0", "0", "*", "*", "*"]),
+            ["@weekly"] => Self::parse(&["0", "0", "*", "*", "0"]),
+            ["@monthly"] => Self::parse(&["0", "0", "1", "*", "*"]),
+            [_, _, _, _, _] => {
+                let mut masks = [0u64; 5];
+                for (i, field) in fields.iter().enumerate() {
+                    masks[i] = parse_field(field, FIELD_RANGES[i])?;
+                }
+                Some(Schedule::Fields(masks))
+            }
+            _ => None,
+        }
+    }
+
+    /// Check if the schedule fires at a minute
+    pub fn matches(&self, time: &CivilTime) -> bool {
+        let Schedule::Fields(masks) = self else {
+            return false;
+        };
+        let values = [time.minute, time.hour, time.day, time.month, time.weekday];
+        masks
+            .iter()
+            .zip(values)
+            .all(|(mask, value)| mask & (1 << value) != 0)
+    }
+}
+
+/// Parse one field: `*`, `N`, `A-B`, `*/S`, `A-B/S` or a comma list of those
+fn parse_field(field: &str, (min, max): (u32, u32)) -> Option<u64> {
+    let mut mask = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|s| *s > 0)?),
+            None => (part, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((a, b)) = range.split_once('-') {
+            (a.parse().ok()?, b.parse().ok()?)
+        } else {
+            let n = range.parse().ok()?;
+            (n, n)
+        };
+        if start < min || end > max || start > end {
+            return None;
+        }
+        for value in (start..=end).step_by(step as usize) {
+            mask |= 1 << value;
+        }
+    }
+    Some(mask)
+}
+
+/// One crontab entry
+#[derive(Debug, Clone, PartialEq)]
+pub struct CronJob {
+    pub schedule: Schedule,
+    /// Marked `@batch`: may be deferred while saving power
+    pub batch: bool,
+    pub command: String,
+}
+
+impl CronJob {
+    /// Parse a crontab line; `None` for blank lines, comments and bad entries
+    pub fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (batch, line) = match line.strip_prefix("@batch") {
+            Some(rest) if rest.starts_with(char::is_whitespace) => (true, rest.trim_start()),
+            _ => (false, line),
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let count = if words.first()?.starts_with('@') {
+            1
+        } else {
+            5
+        };
+        if words.len() <= count {
+            return None;
+        }
+        Some(Self {
+            schedule: Schedule::parse(&words[..count])?,
+            batch,
+            command: words[count..].join(" "),
+        })
+    }
+}
+
+/// Parse a whole crontab, skipping lines that aren't valid entries
+pub fn parse_crontab(text: &str) -> Vec<CronJob> {
+    text.lines().filter_map(CronJob::parse).collect()
+}
+
+/// Calendar fields of a minute (UTC)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CivilTime {
+    pub minute: u32,
+    pub hour: u32,
+    pub day: u32,
+    pub month: u32,
+    /// 0 = Sunday
+    pub weekday: u32,
+}
+
+impl CivilTime {
+    /// Fields of a minute counted from the Unix epoch
+    pub fn from_epoch_minute(minute: i64) -> Self {
+        let days = minute.div_euclid(24 * 60);
+        let of_day = minute.rem_euclid(24 * 60);
+        // Civil-from-days (Howard Hinnant), eras of 400 years from 0000-03-01
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z - era * 146_097;
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        Self {
+            minute: (of_day % 60) as u32,
+            hour: (of_day / 60) as u32,
+            day: day as u32,
+            month: month as u32,
+            // 1970-01-01 was a Thursday
+            weekday: (days + 4).rem_euclid(7) as u32,
+        }
+    }
+}
+
+/// A job that is due, with the crontab it came from
+#[derive(Debug, Clone, PartialEq)]
+pub struct DueJob {
+    pub user: String,
+    pub command: String,
+}
+
+/// Scheduler state between ticks
+#[derive(Debug, Default)]
+pub struct CronState {
+    /// Last minute that was checked
+    last_minute: Option<i64>,
+    /// `@batch` jobs held back while saving power
+    deferred: Vec<DueJob>,
+}
+
+impl CronState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Batch jobs waiting for full speed
+    pub fn deferred(&self) -> &[DueJob] {
+        &self.deferred
+    }
+
+    /// Check if [`Self::due`] could return anything: a new minute has
+    /// started, or deferred batch jobs can be released
+    pub fn pending(&self, now_ms: f64, defer_batch: bool) -> bool {
+        let now = (now_ms / 60_000.0).floor() as i64;
+        self.last_minute.is_none_or(|last| now > last)
+            || (!defer_batch && !self.deferred.is_empty())
+    }
+
+    /// Jobs due at `now_ms` given each user's crontab
+    ///
+    /// Batch jobs are queued instead while `defer_batch` is set and
+    /// released on the first tick without it.
+    pub fn due(
+        &mut self,
+        crontabs: &[(String, Vec<CronJob>)],
+        now_ms: f64,
+        defer_batch: bool,
+    ) -> Vec<DueJob> {
+        let now = (now_ms / 60_000.0).floor() as i64;
+        let minutes = match self.last_minute {
+            Some(last) if now <= last => Vec::new(),
+            Some(last) => ((last + 1).max(now - MAX_CATCH_UP + 1)..=now).collect(),
+            None => vec![now],
+        };
+        let booting = self.last_minute.is_none();
+        self.last_minute = Some(self.last_minute.map_or(now, |last| last.max(now)));
+
+        let mut due = Vec::new();
+        if !defer_batch {
+            due.append(&mut self.deferred);
+        }
+        for (user, jobs) in crontabs {
+            for job in jobs {
+                let fires = match job.schedule {
+                    Schedule::Reboot => booting,
+                    Schedule::Fields(_) => minutes
+                        .iter()
+                        .any(|m| job.schedule.matches(&CivilTime::from_epoch_minute(*m))),
+                };
+                if !fires {
+                    continue;
+                }
+                let run = DueJob {
+                    user: user.clone(),
+                    command: job.command.clone(),
+                };
+                if job.batch && defer_batch {
+                    if !self.deferred.contains(&run) {
+                        self.deferred.push(run);
+                    }
+                } else {
+                    due.push(run);
+                }
+            }
+        }
+        due
+    }
+}
+
+thread_local! {
+    static CRON: RefCell<CronState> = RefCell::new(CronState::new());
+    static EXECUTOR: RefCell<Executor> = RefCell::new(Executor::new());
+}
+
+/// Read every user's crontab from the spool
+fn load_crontabs() -> Vec<(String, Vec<CronJob>)> {
+    let Ok(mut users) = syscall::readdir(SPOOL_DIR) else {
+        return Vec::new();
+    };
+    users.sort();
+    users
+        .into_iter()
+        .filter_map(|user| {
+            let text = syscall::read_file(&format!("{}/{}", SPOOL_DIR, user)).ok()?;
+            Some((user, parse_crontab(&text)))
+        })
+        .collect()
+}
+
+/// Append a line to the cron log
+fn log(line: &str) {
+    let mut log = syscall::read_file(LOG_PATH).unwrap_or_default();
+    log.push_str(line);
+    log.push('\n');
+    let _ = syscall::write_file(LOG_PATH, &log);
+}
+
+/// Run the jobs due at `now_ms` (wall-clock milliseconds since the epoch);
+/// returns how many ran
+///
+/// Cheap to call on every executor tick: crontabs are only read when a new
+/// minute starts.
+pub fn tick(now_ms: f64) -> usize {
+    let defer_batch = syscall::power().defer_batch();
+    if !CRON.with(|c| c.borrow().pending(now_ms, defer_batch)) {
+        return 0;
+    }
+    let crontabs = load_crontabs();
+    let due = CRON.with(|c| c.borrow_mut().due(&crontabs, now_ms, defer_batch));
+    for job in &due {
+        let result = EXECUTOR.with(|e| e.borrow_mut().execute_line(&job.command));
+        log(&format!(
+            "({}) CMD ({}) exit {}",
+            job.user, job.command, result.code
+        ));
+        for line in result.output.lines().chain(result.error.lines()) {
+            log(&format!("({}) {}", job.user, line));
+        }
+    }
+    due.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::Governor;
+    use crate::kernel::syscall::{KERNEL, Kernel};
+
+    /// 2024-01-01 00:00 UTC, a Monday
+    const NEW_YEAR: i64 = 28_401_120;
+
+    fn minute_ms(minute: i64) -> f64 {
+        minute as f64 * 60_000.0
+    }
+
+    #[test]
+    fn test_parse_and_match() {
+        let time = CivilTime::from_epoch_minute(NEW_YEAR + 9 * 60 + 30);
+        assert_eq!(
+            time,
+            CivilTime {
+                minute: 30,
+                hour: 9,
+                day: 1,
+                month: 1,
+                weekday: 1
+            }
+        );
+
+        let job = CronJob::parse("*/15 9-17 * * 1-5 echo work").unwrap();
+        assert!(!job.batch);
+        assert_eq!(job.command, "echo work");
+        assert!(job.schedule.matches(&time));
+        let sunday = CivilTime::from_epoch_minute(NEW_YEAR - 24 * 60 + 9 * 60 + 30);
+        assert_eq!(sunday.weekday, 0);
+        assert!(!job.schedule.matches(&sunday));
+
+        let batch = CronJob::parse("@batch @daily updatedb").unwrap();
+        assert!(batch.batch);
+        assert!(
+            batch
+                .schedule
+                .matches(&CivilTime::from_epoch_minute(NEW_YEAR))
+        );
+
+        assert!(CronJob::parse("# comment").is_none());
+        assert!(CronJob::parse("61 * * * * echo").is_none());
+        assert!(CronJob::parse("* * * *").is_none());
+        assert_eq!(parse_crontab("@reboot echo up\nbad\n").len(), 1);
+    }
+
+    #[test]
+    fn test_batch_deferred_while_saving() {
+        let crontabs = vec![(
+            "user".to_string(),
+            parse_crontab("@reboot echo boot\n@batch 0 * * * * echo sync\n0 * * * * echo now\n"),
+        )];
+        let mut cron = CronState::new();
+        let due = cron.due(&crontabs, minute_ms(NEW_YEAR - 1), false);
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].command, "echo boot");
+
+        // Saving power: only the regular job runs at the top of the hour
+        let due = cron.due(&crontabs, minute_ms(NEW_YEAR), true);
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].command, "echo now");
+        assert_eq!(cron.deferred().len(), 1);
+        assert!(!cron.pending(minute_ms(NEW_YEAR), true));
+        assert!(cron.pending(minute_ms(NEW_YEAR), false));
+
+        // Full speed releases it
+        let due = cron.due(&crontabs, minute_ms(NEW_YEAR + 1), false);
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].command, "echo sync");
+        assert!(cron.deferred().is_empty());
+    }
+
+    #[test]
+    fn test_tick_runs_and_logs() {
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        CRON.with(|c| *c.borrow_mut() = CronState::new());
+        let _ = syscall::mkdir("/var/spool");
+        let _ = syscall::mkdir(SPOOL_DIR);
+        syscall::write_file(
+            &format!("{}/user", SPOOL_DIR),
+            "@batch * * * * * echo batched\n",
+        )
+        .unwrap();
+
+        syscall::set_governor(Governor::Powersave);
+        assert_eq!(tick(minute_ms(NEW_YEAR)), 0);
+        syscall::set_governor(Governor::Performance);
+        assert_eq!(tick(minute_ms(NEW_YEAR + 1)), 2);
+
+        let log = syscall::read_file(LOG_PATH).unwrap();
+        assert!(log.contains("(user) CMD (echo batched) exit 0"));
+        assert!(log.contains("(user) batched"));
+    }
+}
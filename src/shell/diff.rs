@@ -0,0 +1,389 @@
+//! Diff engine
+//!
+//! Line diffs by longest common subsequence, word diffs within changed
+//! lines, and three-way merges. `diff`, `merge3` and the editor's swap
+//! recovery are built on it.
+//!
+//! A three-way merge takes the changes each side made to a common base:
+//! where only one side changed a region, its version wins; where both
+//! changed it differently, the region is a conflict, written out between
+//! markers for someone to resolve:
+//!
+//! ```text
+//! <<<<<<< ours
+//! our lines
+//! ||||||| base        (only with the base shown)
+//! original lines
+//! =======
+//! their lines
+//! >>>>>>> theirs
+//! ```
+
+use std::ops::Range;
+
+/// Longest side of a change worth aligning item by item; past it, a
+/// change is all removals then all additions
+pub const MAX_ALIGN: usize = 2000;
+
+/// One step from the old sequence to the new one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edit<T> {
+    Same(T),
+    Removed(T),
+    Added(T),
+}
+
+/// A run of changes: the old items in `old` became the new ones in `new`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+}
+
+/// Edits turning `old` into `new`
+pub fn diff<T: PartialEq + Copy>(old: &[T], new: &[T]) -> Vec<Edit<T>> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (a, b) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+
+    let mut edits: Vec<Edit<T>> = old[..prefix].iter().map(|&x| Edit::Same(x)).collect();
+    if a.len() > MAX_ALIGN || b.len() > MAX_ALIGN {
+        edits.extend(a.iter().map(|&x| Edit::Removed(x)));
+        edits.extend(b.iter().map(|&x| Edit::Added(x)));
+    } else {
+        // Longest common subsequence, filled from the end
+        let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lcs[i][j] = if a[i] == b[j] {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    lcs[i + 1][j].max(lcs[i][j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() || j < b.len() {
+            if i < a.len() && j < b.len() && a[i] == b[j] {
+                edits.push(Edit::Same(a[i]));
+                i += 1;
+                j += 1;
+            } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+                edits.push(Edit::Removed(a[i]));
+                i += 1;
+            } else {
+                edits.push(Edit::Added(b[j]));
+                j += 1;
+            }
+        }
+    }
+    edits.extend(old[old.len() - suffix..].iter().map(|&x| Edit::Same(x)));
+    edits
+}
+
+/// Line diff of two texts
+pub fn lines<'a>(old: &'a str, new: &'a str) -> Vec<Edit<&'a str>> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    diff(&old, &new)
+}
+
+/// Group edits into runs of changes
+pub fn hunks<T>(edits: &[Edit<T>]) -> Vec<Hunk> {
+    let mut hunks: Vec<Hunk> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    let mut in_hunk = false;
+    for edit in edits {
+        match edit {
+            Edit::Same(_) => {
+                i += 1;
+                j += 1;
+                in_hunk = false;
+                continue;
+            }
+            Edit::Removed(_) => i += 1,
+            Edit::Added(_) => j += 1,
+        }
+        match hunks.last_mut() {
+            Some(hunk) if in_hunk => {
+                hunk.old.end = i;
+                hunk.new.end = j;
+            }
+            _ => {
+                let (start_i, start_j) = match edit {
+                    Edit::Removed(_) => (i - 1, j),
+                    _ => (i, j - 1),
+                };
+                hunks.push(Hunk {
+                    old: start_i..i,
+                    new: start_j..j,
+                });
+                in_hunk = true;
+            }
+        }
+    }
+    hunks
+}
+
+/// Split a line into words, runs of whitespace and single punctuation
+/// characters, which together give back the line
+pub fn tokens(text: &str) -> Vec<&str> {
+    let kind = |c: char| {
+        if c.is_whitespace() {
+            0
+        } else if c.is_alphanumeric() || c == '_' {
+            1
+        } else {
+            2
+        }
+    };
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut last = None;
+    for (i, c) in text.char_indices() {
+        let k = kind(c);
+        if i > start && (last != Some(k) || k == 2) {
+            tokens.push(&text[start..i]);
+            start = i;
+        }
+        last = Some(k);
+    }
+    if start < text.len() {
+        tokens.push(&text[start..]);
+    }
+    tokens
+}
+
+/// Word diff of two texts, in runs: neighbouring edits of the same kind
+/// are joined
+pub fn words<'a>(old: &'a str, new: &'a str) -> Vec<Edit<String>> {
+    let mut runs: Vec<Edit<String>> = Vec::new();
+    for edit in diff(&tokens(old), &tokens(new)) {
+        match (runs.last_mut(), edit) {
+            (Some(Edit::Same(run)), Edit::Same(t))
+            | (Some(Edit::Removed(run)), Edit::Removed(t))
+            | (Some(Edit::Added(run)), Edit::Added(t)) => run.push_str(t),
+            (_, Edit::Same(t)) => runs.push(Edit::Same(t.to_string())),
+            (_, Edit::Removed(t)) => runs.push(Edit::Removed(t.to_string())),
+            (_, Edit::Added(t)) => runs.push(Edit::Added(t.to_string())),
+        }
+    }
+    runs
+}
+
+/// Part of a three-way merge
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Chunk<'a> {
+    /// Lines both sides agree on, or only one side changed
+    Clean(Vec<&'a str>),
+    /// Lines both sides changed differently
+    Conflict {
+        base: Vec<&'a str>,
+        ours: Vec<&'a str>,
+        theirs: Vec<&'a str>,
+    },
+}
+
+/// For each line of `old`, the line of `new` it was kept as
+fn kept<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Option<usize>> {
+    let mut map = vec![None; old.len()];
+    let (mut i, mut j) = (0, 0);
+    for edit in diff(old, new) {
+        match edit {
+            Edit::Same(_) => {
+                map[i] = Some(j);
+                i += 1;
+                j += 1;
+            }
+            Edit::Removed(_) => i += 1,
+            Edit::Added(_) => j += 1,
+        }
+    }
+    map
+}
+
+/// Merge the changes `ours` and `theirs` each made to `base`
+pub fn merge3<'a>(base: &'a str, ours: &'a str, theirs: &'a str) -> Vec<Chunk<'a>> {
+    let base: Vec<&str> = base.lines().collect();
+    let ours: Vec<&str> = ours.lines().collect();
+    let theirs: Vec<&str> = theirs.lines().collect();
+    let in_ours = kept(&base, &ours);
+    let in_theirs = kept(&base, &theirs);
+
+    let mut chunks = Vec::new();
+    let mut clean: Vec<&str> = Vec::new();
+    let (mut i, mut j, mut k) = (0, 0, 0);
+    loop {
+        // The next base line both sides kept, or the ends
+        let sync = (i..base.len()).find_map(|n| Some((n, in_ours[n]?, in_theirs[n]?)));
+        let (n, o, t) = sync.unwrap_or((base.len(), ours.len(), theirs.len()));
+        let (b, ou, th) = (&base[i..n], &ours[j..o], &theirs[k..t]);
+        if ou == th || th == b {
+            clean.extend(ou);
+        } else if ou == b {
+            clean.extend(th);
+        } else {
+            if !clean.is_empty() {
+                chunks.push(Chunk::Clean(std::mem::take(&mut clean)));
+            }
+            chunks.push(Chunk::Conflict {
+                base: b.to_vec(),
+                ours: ou.to_vec(),
+                theirs: th.to_vec(),
+            });
+        }
+        if sync.is_none() {
+            break;
+        }
+        clean.push(base[n]);
+        (i, j, k) = (n + 1, o + 1, t + 1);
+    }
+    if !clean.is_empty() {
+        chunks.push(Chunk::Clean(clean));
+    }
+    chunks
+}
+
+/// Number of conflicts in a merge
+pub fn conflicts(chunks: &[Chunk]) -> usize {
+    chunks
+        .iter()
+        .filter(|c| matches!(c, Chunk::Conflict { .. }))
+        .count()
+}
+
+/// Names for the three sides in conflict markers
+#[derive(Debug, Clone, Copy)]
+pub struct Labels<'a> {
+    pub ours: &'a str,
+    pub base: &'a str,
+    pub theirs: &'a str,
+}
+
+/// Write out a merge, conflicts between markers; the base's side of a
+/// conflict is included if `show_base`
+pub fn render_merge(chunks: &[Chunk], labels: Labels, show_base: bool) -> String {
+    let mut out = String::new();
+    let mut push = |line: &str| {
+        out.push_str(line);
+        out.push('\n');
+    };
+    for chunk in chunks {
+        match chunk {
+            Chunk::Clean(lines) => lines.iter().for_each(|l| push(l)),
+            Chunk::Conflict { base, ours, theirs } => {
+                push(&format!("<<<<<<< {}", labels.ours));
+                ours.iter().for_each(|l| push(l));
+                if show_base {
+                    push(&format!("||||||| {}", labels.base));
+                    base.iter().for_each(|l| push(l));
+                }
+                push("=======");
+                theirs.iter().for_each(|l| push(l));
+                push(&format!(">>>>>>> {}", labels.theirs));
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_and_hunks() {
+        let edits = lines("a\nb\nc\nd", "a\nc\nx\nd\ne");
+        assert_eq!(
+            edits,
+            [
+                Edit::Same("a"),
+                Edit::Removed("b"),
+                Edit::Same("c"),
+                Edit::Added("x"),
+                Edit::Same("d"),
+                Edit::Added("e"),
+            ]
+        );
+        assert_eq!(
+            hunks(&edits),
+            [
+                Hunk {
+                    old: 1..2,
+                    new: 1..1
+                },
+                Hunk {
+                    old: 3..3,
+                    new: 2..3
+                },
+                Hunk {
+                    old: 4..4,
+                    new: 4..5
+                },
+            ]
+        );
+        assert_eq!(
+            hunks(&lines("x\ny", "z")),
+            [Hunk {
+                old: 0..2,
+                new: 0..1
+            }]
+        );
+        assert!(hunks(&lines("same", "same")).is_empty());
+    }
+
+    #[test]
+    fn test_words() {
+        assert_eq!(
+            tokens("let x=1;  y"),
+            ["let", " ", "x", "=", "1", ";", "  ", "y"]
+        );
+        assert_eq!(
+            words("the quick fox", "the slow brown fox"),
+            [
+                Edit::Same("the ".to_string()),
+                Edit::Removed("quick".to_string()),
+                Edit::Added("slow brown".to_string()),
+                Edit::Same(" fox".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge3() {
+        let base = "a\nb\nc\nd\ne";
+        // Changes to different lines merge cleanly
+        let chunks = merge3(base, "A\nb\nc\nd\ne", "a\nb\nc\nd\nE\nf");
+        assert_eq!(conflicts(&chunks), 0);
+        let labels = Labels {
+            ours: "ours",
+            base: "base",
+            theirs: "theirs",
+        };
+        assert_eq!(render_merge(&chunks, labels, false), "A\nb\nc\nd\nE\nf\n");
+
+        // The same change on both sides is no conflict
+        let chunks = merge3(base, "a\nB\nc\nd\ne", "a\nB\nc\nd\ne");
+        assert_eq!(render_merge(&chunks, labels, false), "a\nB\nc\nd\ne\n");
+
+        // Different changes to the same line conflict
+        let chunks = merge3(base, "a\nb\nours\nd\ne", "a\nb\ntheirs\nd\nE");
+        assert_eq!(conflicts(&chunks), 1);
+        assert_eq!(
+            render_merge(&chunks, labels, true),
+            "a\nb\n<<<<<<< ours\nours\n||||||| base\nc\n=======\ntheirs\n>>>>>>> theirs\nd\nE\n"
+        );
+
+        // One side deleting what the other left alone
+        let chunks = merge3(base, "a\nc\nd\ne", base);
+        assert_eq!(render_merge(&chunks, labels, false), "a\nc\nd\ne\n");
+    }
+}
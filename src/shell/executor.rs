@@ -7,11 +7,25 @@
 //! 4. Running external commands via the program registry
 //! 5. Running WASM command modules from /bin
 
-use super::builtins::{self, BuiltinResult, ShellState};
+use super::builtins::{self, BuiltinResult, GetoptsResult, LockRequest, ShellState};
+use super::history;
+use super::journal::{self, Journal, JournalEntry, JournalRequest};
+use super::login::{self, LoginShell};
+use super::osimage::{self, ImageContents, OsImageRequest, Session};
 use super::parser::{ArrayAssignment, CommandList, LogicalOp, ParsedLine, Pipeline, SimpleCommand};
 use super::programs;
-use crate::kernel::syscall;
+use super::script::{self, Repl, SCRIPT_COMMAND, ScriptHost};
+use super::stats::{self, Record};
+use super::tutorial;
+use crate::kernel::flock::{LOCK_EX, LOCK_NB, LOCK_SH, LockType};
+use crate::kernel::klog::LogLevel;
+use crate::kernel::process::{EnvSpec, Pid};
+use crate::kernel::signal::{SIGNALFD_RECORD_SIZE, SigProcMaskHow, Signal, SignalAction};
+use crate::kernel::syscall::{self, SyscallError};
+use crate::kernel::users::Uid;
 use crate::kernel::wasm::WasmCommandRunner;
+use crate::vfs::FsChangeKind;
+use crate::vfs::integrity;
 use std::collections::HashMap;
 use std::path::Path;
 
@@ -73,12 +87,50 @@ impl ExecResult {
 /// - stderr: Buffer for standard error
 ///
 /// Returns: Exit code (0 for success)
+///
+/// Pipes and redirections carry raw bytes, so a program of this type
+/// sees exactly what the previous command wrote, binary or not.
 pub type ProgramFn =
+    fn(args: &[String], stdin: &[u8], stdout: &mut Vec<u8>, stderr: &mut String) -> i32;
+
+/// A program that only deals in text
+///
+/// Its stdin is decoded as UTF-8, with invalid sequences replaced by
+/// U+FFFD, and its output is passed on as UTF-8 bytes.
+pub type TextProgramFn =
     fn(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut String) -> i32;
 
+/// A registered program
+#[derive(Clone, Copy)]
+pub enum Program {
+    Text(TextProgramFn),
+    Binary(ProgramFn),
+}
+
+impl Program {
+    /// Run the program, appending its output to `stdout`
+    pub fn run(
+        &self,
+        args: &[String],
+        stdin: &[u8],
+        stdout: &mut Vec<u8>,
+        stderr: &mut String,
+    ) -> i32 {
+        match self {
+            Program::Binary(func) => func(args, stdin, stdout, stderr),
+            Program::Text(func) => {
+                let mut text = String::new();
+                let code = func(args, &String::from_utf8_lossy(stdin), &mut text, stderr);
+                stdout.extend_from_slice(text.as_bytes());
+                code
+            }
+        }
+    }
+}
+
 /// Registry of available programs
 pub struct ProgramRegistry {
-    programs: HashMap<String, ProgramFn>,
+    programs: HashMap<String, Program>,
 }
 
 impl ProgramRegistry {
@@ -88,11 +140,12 @@ impl ProgramRegistry {
         };
 
         // File operations
-        reg.register("cat", programs::prog_cat);
+        reg.register_binary("cat", programs::prog_cat);
         reg.register("ls", programs::prog_ls);
         reg.register("mkdir", programs::prog_mkdir);
         reg.register("touch", programs::prog_touch);
         reg.register("rm", programs::prog_rm);
+        reg.register("trash", programs::prog_trash);
         reg.register("cp", programs::prog_cp);
         reg.register("mv", programs::prog_mv);
         reg.register("ln", programs::prog_ln);
@@ -102,11 +155,11 @@ impl ProgramRegistry {
         // Text processing
         reg.register("head", programs::prog_head);
         reg.register("tail", programs::prog_tail);
-        reg.register("wc", programs::prog_wc);
+        reg.register_binary("wc", programs::prog_wc);
         reg.register("grep", programs::prog_grep);
         reg.register("sort", programs::prog_sort);
         reg.register("uniq", programs::prog_uniq);
-        reg.register("tee", programs::prog_tee);
+        reg.register_binary("tee", programs::prog_tee);
         reg.register("rev", programs::prog_rev);
         reg.register("cut", programs::prog_cut);
         reg.register("tr", programs::prog_tr);
@@ -114,8 +167,11 @@ impl ProgramRegistry {
         reg.register("fold", programs::prog_fold);
         reg.register("paste", programs::prog_paste);
         reg.register("comm", programs::prog_comm);
-        reg.register("strings", programs::prog_strings);
+        reg.register_binary("strings", programs::prog_strings);
         reg.register("diff", programs::prog_diff);
+        reg.register("merge3", programs::prog_merge3);
+        reg.register("spell", programs::prog_spell);
+        reg.register("unicode", programs::prog_unicode);
 
         // Filesystem management
         reg.register("save", programs::prog_save);
@@ -123,18 +179,36 @@ impl ProgramRegistry {
         reg.register("fsreset", programs::prog_fsreset);
         reg.register("autosave", programs::prog_autosave);
         reg.register("find", programs::prog_find);
+        reg.register("updatedb", programs::prog_updatedb);
+        reg.register("locate", programs::prog_locate);
+        reg.register("search", programs::prog_search);
         reg.register("du", programs::prog_du);
         reg.register("df", programs::prog_df);
+        reg.register("quota", programs::prog_quota);
+        reg.register("fsck", programs::prog_fsck);
+        reg.register("snapshot", programs::prog_snapshot);
+        reg.register("vfs", programs::prog_vfs);
+        reg.register("getfattr", programs::prog_getfattr);
+        reg.register("setfattr", programs::prog_setfattr);
+
+        // Archives
+        reg.register("zip", programs::prog_zip);
+        reg.register("unzip", programs::prog_unzip);
 
         // Network
         reg.register("curl", programs::prog_curl);
         reg.register("wget", programs::prog_wget);
+        reg.register("exportfs", programs::prog_exportfs);
+        reg.register("netperm", programs::prog_netperm);
+        reg.register("offline", programs::prog_offline);
 
         // System info
         reg.register("whoami", programs::prog_whoami);
         reg.register("hostname", programs::prog_hostname);
         reg.register("uname", programs::prog_uname);
         reg.register("uptime", programs::prog_uptime);
+        reg.register("dmesg", programs::prog_dmesg);
+        reg.register("stats", programs::prog_stats);
         reg.register("free", programs::prog_free);
         reg.register("id", programs::prog_id);
         reg.register("groups", programs::prog_groups);
@@ -147,6 +221,7 @@ impl ProgramRegistry {
         reg.register("fg", programs::prog_fg);
         reg.register("bg", programs::prog_bg);
         reg.register("strace", programs::prog_strace);
+        reg.register("debug", programs::prog_debug);
         reg.register("kill", programs::prog_kill);
         reg.register("sleep", programs::prog_sleep);
 
@@ -162,37 +237,54 @@ impl ProgramRegistry {
         reg.register("xargs", programs::prog_xargs);
         reg.register("seq", programs::prog_seq);
         reg.register("yes", programs::prog_yes);
+        reg.register("shuf", programs::prog_shuf);
+        reg.register("uuidgen", programs::prog_uuidgen);
+        reg.register("mktemp", programs::prog_mktemp);
         reg.register("basename", programs::prog_basename);
         reg.register("dirname", programs::prog_dirname);
         reg.register("cal", programs::prog_cal);
         reg.register("edit", programs::prog_edit);
+        reg.register("chars", programs::prog_chars);
         reg.register("man", programs::prog_man);
+        reg.register("tutorial", programs::prog_tutorial);
         reg.register("printenv", programs::prog_printenv);
 
         // Encoding utilities
-        reg.register("base64", programs::prog_base64);
-        reg.register("xxd", programs::prog_xxd);
+        reg.register_binary("base64", programs::prog_base64);
+        reg.register_binary("xxd", programs::prog_xxd);
+        reg.register_binary("sha256sum", programs::prog_sha256sum);
+        reg.register_binary("crypt", programs::prog_crypt);
+        reg.register_binary("qr", programs::prog_qr);
 
         // User management
         reg.register("su", programs::prog_su);
         reg.register("sudo", programs::prog_sudo);
         reg.register("useradd", programs::prog_useradd);
         reg.register("groupadd", programs::prog_groupadd);
+        reg.register("userdel", programs::prog_userdel);
+        reg.register("groupdel", programs::prog_groupdel);
+        reg.register("usermod", programs::prog_usermod);
         reg.register("passwd", programs::prog_passwd);
         reg.register("login", programs::prog_login);
         reg.register("logout", programs::prog_logout);
+        reg.register("lock", programs::prog_lock);
+        reg.register("guest", programs::prog_guest);
         reg.register("who", programs::prog_who);
         reg.register("w", programs::prog_w);
 
         // Permissions
         reg.register("chmod", programs::prog_chmod);
         reg.register("chown", programs::prog_chown);
+        reg.register("getfacl", programs::prog_getfacl);
+        reg.register("setfacl", programs::prog_setfacl);
         reg.register("chgrp", programs::prog_chgrp);
 
         // System services
         reg.register("systemctl", programs::prog_systemctl);
         reg.register("reboot", programs::prog_reboot);
         reg.register("poweroff", programs::prog_poweroff);
+        reg.register("backup", programs::prog_backup);
+        reg.register("update", programs::prog_update);
 
         // IPC
         reg.register("mkfifo", programs::prog_mkfifo);
@@ -207,10 +299,16 @@ impl ProgramRegistry {
         // TTY
         reg.register("stty", programs::prog_stty);
         reg.register("tty", programs::prog_tty);
+        reg.register("chvt", programs::prog_chvt);
+        reg.register("profile", programs::prog_profile);
+        reg.register("widgets", programs::prog_widgets);
 
         // Package manager
         reg.register("pkg", programs::prog_pkg);
 
+        // Developer tools
+        reg.register("wat2wasm", programs::prog_wat2wasm);
+
         // Cron/scheduling
         reg.register("crontab", programs::prog_crontab);
         reg.register("at", programs::prog_at);
@@ -218,11 +316,17 @@ impl ProgramRegistry {
         reg
     }
 
-    pub fn register(&mut self, name: &str, func: ProgramFn) {
-        self.programs.insert(name.to_string(), func);
+    pub fn register(&mut self, name: &str, func: TextProgramFn) {
+        self.programs.insert(name.to_string(), Program::Text(func));
     }
 
-    pub fn get(&self, name: &str) -> Option<ProgramFn> {
+    /// Register a program that reads and writes raw bytes
+    pub fn register_binary(&mut self, name: &str, func: ProgramFn) {
+        self.programs
+            .insert(name.to_string(), Program::Binary(func));
+    }
+
+    pub fn get(&self, name: &str) -> Option<Program> {
         self.programs.get(name).copied()
     }
 
@@ -253,6 +357,29 @@ pub struct Executor {
     procsub_counter: u64,
     /// Pending output substitutions: (temp_file_path, command_to_run)
     pending_output_substitutions: Vec<(String, String)>,
+    /// Temp files made for process substitutions, removed once the line
+    /// that used them is done
+    procsub_files: Vec<String>,
+    /// Active `script` REPL; while set, input lines go to it instead
+    script_repl: Option<Repl>,
+    /// The REPL is the session's login shell, so leaving it logs out
+    login_repl: bool,
+    /// Process and user the shell last ran as, to notice `login` and `su`
+    session: (Option<Pid>, Option<Uid>),
+    /// Command journal (records each line's VFS changes for undo-last)
+    journal: Journal,
+    /// Program runs of the current line, for the usage statistics
+    stats: Vec<Record>,
+    /// signalfd the trapped signals are read from, once a trap is set
+    trap_fd: Option<syscall::Fd>,
+    /// Signals the traps handle or ignore (bit N = signal N)
+    trapped: u16,
+    /// Set while a trap runs, so traps don't nest
+    in_trap: bool,
+    /// Set while `fc -s` re-runs a command, so re-runs don't nest
+    rerunning: bool,
+    /// Command `fc -s` re-ran, to go in the history instead of the line
+    rerun: Option<String>,
 }
 
 impl Executor {
@@ -275,6 +402,67 @@ impl Executor {
             wasm_runner,
             procsub_counter: 0,
             pending_output_substitutions: Vec::new(),
+            procsub_files: Vec::new(),
+            script_repl: None,
+            login_repl: false,
+            session: session(),
+            journal: Journal::new(),
+            stats: Vec::new(),
+            trap_fd: None,
+            trapped: 0,
+            in_trap: false,
+            rerunning: false,
+            rerun: None,
+        }
+    }
+
+    /// Change the working directory as `cd` would, restricted shell
+    /// included
+    pub fn chdir(&mut self, path: &str) -> ExecResult {
+        let target = self.state.cwd.join(path);
+        if let BuiltinResult::Error(e) = self.restrict(BuiltinResult::Cd(target.clone())) {
+            self.state.last_status = 1;
+            return ExecResult::success().with_error(e);
+        }
+        self.change_directory(&target)
+    }
+
+    /// Prompt of the active `script` REPL, if any
+    pub fn script_prompt(&self) -> Option<&'static str> {
+        self.script_repl.as_ref().map(Repl::prompt)
+    }
+
+    /// Feed a line to the active `script` REPL
+    ///
+    /// The REPL is taken out while it runs so commands it execs go to the
+    /// shell rather than back into the REPL.
+    fn feed_script_repl(&mut self, line: &str) -> Option<ExecResult> {
+        let mut repl = self.script_repl.take()?;
+        match repl.feed(line, self) {
+            Some(result) => {
+                self.script_repl = Some(repl);
+                self.state.last_status = result.code;
+                Some(result)
+            }
+            None => Some(ExecResult::success()),
+        }
+    }
+
+    /// Run the `script` command, entering the REPL when it asks for one
+    fn execute_script(
+        &mut self,
+        args: &[String],
+        stdin: Option<&str>,
+        stdout: &mut String,
+        stderr: &mut String,
+    ) -> i32 {
+        match script::run_command(args, stdin, self, stdout, stderr) {
+            Some(code) => code,
+            None => {
+                self.script_repl = Some(Repl::new());
+                stdout.push_str(script::REPL_BANNER);
+                0
+            }
         }
     }
 
@@ -302,27 +490,51 @@ impl Executor {
         &mut self,
         name: &str,
         args: &[String],
-        stdin: &str,
+        stdin: &[u8],
     ) -> ExecResult {
+        let mut stdout = Vec::new();
+        let mut stderr = String::new();
+        let code = self
+            .run_wasm_command(name, args, stdin, &mut stdout, &mut stderr)
+            .await;
+        ExecResult {
+            code,
+            output: String::from_utf8_lossy(&stdout).into_owned(),
+            error: stderr,
+            should_exit: false,
+        }
+    }
+
+    /// Run a WASM command, appending its raw output to `stdout`
+    #[cfg(target_arch = "wasm32")]
+    async fn run_wasm_command(
+        &mut self,
+        name: &str,
+        args: &[String],
+        stdin: &[u8],
+        stdout: &mut Vec<u8>,
+        stderr: &mut String,
+    ) -> i32 {
         self.sync_wasm_runner();
 
-        match self.wasm_runner.run(name, args, stdin).await {
+        let code = match self.wasm_runner.run(name, args, stdin).await {
             Ok(result) => {
-                self.state.last_status = result.exit_code;
-                ExecResult {
-                    code: result.exit_code,
-                    output: result.stdout_str(),
-                    error: result.stderr_str(),
-                    should_exit: false,
-                }
+                stdout.extend_from_slice(&result.stdout);
+                stderr.push_str(&result.stderr_str());
+                result.exit_code
             }
             Err(e) => {
-                self.state.last_status = 1;
-                ExecResult::success()
-                    .with_error(format!("{}: {}", name, e))
-                    .with_code(1)
+                stderr.push_str(&format!("{}: {}", name, e));
+                // What a Unix shell returns for a command it can't run
+                if matches!(e, crate::kernel::wasm::WasmError::PermissionDenied { .. }) {
+                    126
+                } else {
+                    1
+                }
             }
-        }
+        };
+        self.state.last_status = code;
+        code
     }
 
     /// Execute a WASM command (non-WASM stub)
@@ -331,7 +543,7 @@ impl Executor {
         &mut self,
         name: &str,
         _args: &[String],
-        _stdin: &str,
+        _stdin: &[u8],
     ) -> ExecResult {
         self.state.last_status = 127;
         ExecResult::success()
@@ -352,8 +564,8 @@ impl Executor {
             return Some(self.execute_single(cmd));
         }
 
-        // Registry programs are sync
-        if self.registry.contains(&cmd.program) {
+        // Registry programs and scripts are sync
+        if self.registry.contains(&cmd.program) || cmd.program == SCRIPT_COMMAND {
             return Some(self.execute_single(cmd));
         }
 
@@ -376,18 +588,176 @@ impl Executor {
         self.wasm_runner.list_commands()
     }
 
+    /// Note a program run for the usage statistics, written when the line
+    /// finishes
+    fn record_stat(&mut self, program: &str, started: f64, code: i32) {
+        if stats::is_tracked(program, code) {
+            self.stats.push(Record {
+                program: program.to_string(),
+                ms: (stats::clock() - started).max(0.0),
+                code,
+            });
+        }
+    }
+
+    /// Execute a line typed at the prompt
+    ///
+    /// Unlike [`Executor::execute_line`], history references like `!!` are
+    /// expanded first (unless `set +H`), the expanded line is shown, and
+    /// the line goes in the history.
+    pub fn execute_interactive(&mut self, line: &str) -> ExecResult {
+        let mut result = self.run_interactive(line);
+        self.follow_session(&mut result);
+        result
+    }
+
+    /// [`Executor::execute_interactive`] before the session is looked at
+    fn run_interactive(&mut self, line: &str) -> ExecResult {
+        // Lines for the script REPL are its own
+        if self.script_repl.is_some() {
+            return self.execute_line(line);
+        }
+
+        let mut line = line.to_string();
+        let mut expanded = false;
+        if self.state.options.histexpand {
+            match history::expand(&line, &self.state.history) {
+                Ok(Some(text)) => {
+                    line = text;
+                    expanded = true;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    self.state.last_status = 1;
+                    return ExecResult::success().with_error(e);
+                }
+            }
+        }
+
+        let mut result = self.execute_line(&line);
+        if expanded {
+            show_command(&mut result, &line);
+        }
+        let entry = self.rerun.take().unwrap_or(line);
+        self.remember(entry);
+        result
+    }
+
+    /// Take on the login shell of a session the line began with `login` or
+    /// `su`, and log out of a session whose login shell was the REPL once
+    /// it is left
+    fn follow_session(&mut self, result: &mut ExecResult) {
+        if self.login_repl && self.script_repl.is_none() {
+            self.login_repl = false;
+            let logout = self.execute_line("logout");
+            result.output.push_str(&logout.output);
+            result.error.push_str(&logout.error);
+        }
+
+        let session = session();
+        if session == self.session {
+            return;
+        }
+        self.session = session;
+        if let Ok(cwd) = syscall::getcwd() {
+            self.state.cwd = cwd;
+        }
+        for name in ["HOME", "USER", "SHELL", "PATH"] {
+            if let Ok(Some(value)) = syscall::getenv(name) {
+                self.state.set_env(name, value);
+            }
+        }
+        if login::current() == LoginShell::Script && self.script_repl.is_none() {
+            self.script_repl = Some(Repl::new());
+            self.login_repl = true;
+            result.output.push_str(script::REPL_BANNER);
+        }
+    }
+
+    /// Refuse what a restricted shell doesn't allow: leaving the home
+    /// directory, or changing `PATH` or `SHELL`
+    fn restrict(&self, result: BuiltinResult) -> BuiltinResult {
+        let Some(home) = login::restricted_home() else {
+            return result;
+        };
+        let protected = |name: &String| login::RESTRICTED_VARS.contains(&name.as_str());
+        let refused = match &result {
+            BuiltinResult::Cd(path) if !builtins::normalize_path(path).starts_with(&home) => {
+                Some(format!("cd: {}: restricted", path.display()))
+            }
+            BuiltinResult::Export(pairs) => pairs
+                .iter()
+                .map(|(name, _)| name)
+                .find(|name| protected(name))
+                .map(|name| format!("export: {}: restricted", name)),
+            BuiltinResult::Unset(vars) => vars
+                .iter()
+                .find(|name| protected(name))
+                .map(|name| format!("unset: {}: restricted", name)),
+            BuiltinResult::WithEnv(spec, _) => {
+                let env = spec.resolve(&self.state.env);
+                login::RESTRICTED_VARS
+                    .iter()
+                    .find(|name| env.get(**name) != self.state.env.get(**name))
+                    .map(|name| format!("env: {}: restricted", name))
+            }
+            _ => None,
+        };
+        match refused {
+            Some(e) => BuiltinResult::Error(e),
+            None => result,
+        }
+    }
+
+    /// Add a typed line to the history, unless it repeats the last one
+    pub fn remember(&mut self, line: String) {
+        let history = &mut self.state.history;
+        if line.trim().is_empty() || history.last() == Some(&line) {
+            return;
+        }
+        if history.len() >= history::MAX_HISTORY {
+            history.remove(0);
+        }
+        history.push(line);
+    }
+
     /// Execute a command line string
+    ///
+    /// Each top-level line is recorded in the command journal with the
+    /// filesystem changes it made.
     pub fn execute_line(&mut self, line: &str) -> ExecResult {
+        if let Some(result) = self.feed_script_repl(line) {
+            return result;
+        }
+
         // Skip empty lines and comments
         let line = line.trim();
         if line.is_empty() || line.starts_with('#') {
             return ExecResult::success();
         }
 
+        let before = self.journal.begin();
+        let outermost = before.is_some();
+        let mut result = self.run_line(line);
+        if result.should_exit {
+            self.run_exit_trap(&mut result);
+        }
+        let cwd = self.state.cwd.display().to_string();
+        self.journal.finish(before, line, &cwd, result.code);
+        if outermost {
+            stats::record_all(&std::mem::take(&mut self.stats));
+        }
+        observe_tutorial(line, &cwd, &mut result);
+        result
+    }
+
+    /// Expand, parse and run a non-empty command line
+    fn run_line(&mut self, line: &str) -> ExecResult {
         // Expand aliases in the line
         let line = self.expand_aliases(line);
 
         // Expand command substitution $(cmd) and `cmd` in the line BEFORE parsing
+        let procsubs = self.procsub_mark();
         let line = self.expand_substitution_in_line(&line);
 
         #[cfg(all(target_arch = "wasm32", not(test)))]
@@ -396,7 +766,10 @@ impl Executor {
         // Parse the line (may be a command or function definition)
         let parsed = match super::parser::parse_line(&line) {
             Ok(p) => p,
-            Err(e) => return ExecResult::success().with_error(format!("parse error: {}", e)),
+            Err(e) => {
+                let result = ExecResult::success().with_error(format!("parse error: {}", e));
+                return self.finish_process_substitutions(procsubs, result);
+            }
         };
 
         let result = match parsed {
@@ -413,6 +786,7 @@ impl Executor {
             }
             ParsedLine::Command(cmd_list) => self.execute_command_list(&cmd_list),
         };
+        let result = self.finish_process_substitutions(procsubs, result);
 
         #[cfg(all(target_arch = "wasm32", not(test)))]
         if !result.error.is_empty() {
@@ -451,14 +825,15 @@ impl Executor {
     pub fn execute_command_list(&mut self, cmd_list: &CommandList) -> ExecResult {
         // Execute the first pipeline
         let mut result = self.execute_pipeline(&cmd_list.first);
+        self.run_traps(&mut result);
 
-        // Short-circuit on exit
-        if result.should_exit {
+        // Short-circuit on exit, or on failure with `set -e`
+        if result.should_exit || self.errexit(result.code, cmd_list.rest.first()) {
             return result;
         }
 
         // Execute remaining pipelines based on logical operators
-        for (op, pipeline) in &cmd_list.rest {
+        for (i, (op, pipeline)) in cmd_list.rest.iter().enumerate() {
             let should_execute = match op {
                 LogicalOp::Sequence => true,        // Always execute
                 LogicalOp::And => result.code == 0, // Execute if previous succeeded
@@ -466,18 +841,9 @@ impl Executor {
             };
 
             if should_execute {
-                let next_result = self.execute_pipeline(pipeline);
-
-                // Combine outputs
-                if !result.output.is_empty() && !next_result.output.is_empty() {
-                    result.output.push('\n');
-                }
-                result.output.push_str(&next_result.output);
-
-                if !result.error.is_empty() && !next_result.error.is_empty() {
-                    result.error.push('\n');
-                }
-                result.error.push_str(&next_result.error);
+                let mut next_result = self.execute_pipeline(pipeline);
+                self.run_traps(&mut next_result);
+                merge_output(&mut result, &next_result);
 
                 // Update exit code to the last executed command
                 result.code = next_result.code;
@@ -487,6 +853,9 @@ impl Executor {
                     result.should_exit = true;
                     return result;
                 }
+                if self.errexit(result.code, cmd_list.rest.get(i + 1)) {
+                    return result;
+                }
             }
         }
 
@@ -495,13 +864,31 @@ impl Executor {
 
     /// Execute a parsed pipeline
     pub fn execute_pipeline(&mut self, pipeline: &Pipeline) -> ExecResult {
+        if pipeline.timed {
+            let timing = Timing::start();
+            let untimed = Pipeline {
+                timed: false,
+                posix_time: false,
+                ..pipeline.clone()
+            };
+            let mut result = self.execute_pipeline(&untimed);
+            timing.report(&mut result, pipeline.posix_time);
+            return result;
+        }
         if pipeline.commands.is_empty() {
             return ExecResult::success();
         }
 
         // For single commands without pipes, execute directly
         if pipeline.commands.len() == 1 {
-            return self.execute_single(&pipeline.commands[0]);
+            let started = stats::clock();
+            let trace = self.trace(&pipeline.commands[0]);
+            let mut result = self.execute_single(&pipeline.commands[0]);
+            self.record_stat(&pipeline.commands[0].program, started, result.code);
+            if let Some(trace) = trace {
+                prepend_line(&mut result.error, &trace);
+            }
+            return result;
         }
 
         // For pipelines, chain the commands
@@ -521,26 +908,41 @@ impl Executor {
             return self.execute_line(&body);
         }
 
-        // Handle external programs from registry
-        if let Some(prog) = self.registry.get(&cmd.program) {
-            let mut stdout = String::new();
+        // Handle external programs from registry, and scripts
+        let prog = self.registry.get(&cmd.program);
+        if prog.is_some() || cmd.program == SCRIPT_COMMAND {
+            let mut stdout = Vec::new();
             let mut stderr = String::new();
 
             // Handle input redirection
             let stdin = if let Some(ref redir) = cmd.stdin {
                 match self.read_file(&redir.path) {
-                    Ok(content) => content,
+                    Ok(content) => Some(content),
                     Err(e) => return ExecResult::success().with_error(e),
                 }
             } else {
-                String::new()
+                None
             };
 
             // Expand glob patterns in arguments
-            let args = self.expand_args(&cmd.args);
+            let args = self.expand_args(cmd);
 
             // Execute program with stdin passed directly
-            let code = prog(&args, &stdin, &mut stdout, &mut stderr);
+            let code = match prog {
+                Some(prog) => prog.run(
+                    &args,
+                    stdin.as_deref().unwrap_or_default(),
+                    &mut stdout,
+                    &mut stderr,
+                ),
+                None => {
+                    let stdin = stdin.as_deref().map(String::from_utf8_lossy);
+                    let mut text = String::new();
+                    let code = self.execute_script(&args, stdin.as_deref(), &mut text, &mut stderr);
+                    stdout = text.into_bytes();
+                    code
+                }
+            };
 
             // Handle output redirection
             if let Some(ref redir) = cmd.stdout {
@@ -552,7 +954,7 @@ impl Executor {
 
             // Handle stderr redirection
             if let Some(ref redir) = cmd.stderr {
-                if let Err(e) = self.write_file(&redir.path, &stderr, redir.append) {
+                if let Err(e) = self.write_file(&redir.path, stderr.as_bytes(), redir.append) {
                     return ExecResult::success().with_error(e);
                 }
                 stderr.clear();
@@ -562,7 +964,7 @@ impl Executor {
 
             return ExecResult {
                 code,
-                output: stdout,
+                output: String::from_utf8_lossy(&stdout).into_owned(),
                 error: stderr,
                 should_exit: false,
             };
@@ -601,8 +1003,8 @@ impl Executor {
             return self.execute_single(cmd);
         }
 
-        // Handle registry programs (sync)
-        if self.registry.contains(&cmd.program) {
+        // Handle registry programs and scripts (sync)
+        if self.registry.contains(&cmd.program) || cmd.program == SCRIPT_COMMAND {
             return self.execute_single(cmd);
         }
 
@@ -615,32 +1017,41 @@ impl Executor {
                     Err(e) => return ExecResult::success().with_error(e),
                 }
             } else {
-                String::new()
+                Vec::new()
             };
 
             // Expand glob patterns in arguments
-            let args = self.expand_args(&cmd.args);
+            let args = self.expand_args(cmd);
 
             // Execute WASM command
-            let mut result = self.execute_wasm_command(&cmd.program, &args, &stdin).await;
+            let mut stdout = Vec::new();
+            let mut stderr = String::new();
+            let code = self
+                .run_wasm_command(&cmd.program, &args, &stdin, &mut stdout, &mut stderr)
+                .await;
 
             // Handle output redirection
             if let Some(ref redir) = cmd.stdout {
-                if let Err(e) = self.write_file(&redir.path, &result.output, redir.append) {
+                if let Err(e) = self.write_file(&redir.path, &stdout, redir.append) {
                     return ExecResult::success().with_error(e);
                 }
-                result.output.clear();
+                stdout.clear();
             }
 
             // Handle stderr redirection
             if let Some(ref redir) = cmd.stderr {
-                if let Err(e) = self.write_file(&redir.path, &result.error, redir.append) {
+                if let Err(e) = self.write_file(&redir.path, stderr.as_bytes(), redir.append) {
                     return ExecResult::success().with_error(e);
                 }
-                result.error.clear();
+                stderr.clear();
             }
 
-            return result;
+            return ExecResult {
+                code,
+                output: String::from_utf8_lossy(&stdout).into_owned(),
+                error: stderr,
+                should_exit: false,
+            };
         }
 
         // Command not found
@@ -659,14 +1070,17 @@ impl Executor {
     /// Execute a pipeline of commands asynchronously
     #[cfg(target_arch = "wasm32")]
     pub async fn execute_piped_async(&mut self, commands: &[SimpleCommand]) -> ExecResult {
-        let mut pipe_input = String::new();
-        let mut final_stdout = String::new();
+        let mut pipe_input = Vec::new();
+        let mut final_stdout = Vec::new();
         let mut final_stderr = String::new();
         let mut last_code = 0;
+        // Status of the rightmost failing command, for pipefail
+        let mut failed_code = 0;
 
         for (i, cmd) in commands.iter().enumerate() {
             let is_first = i == 0;
             let is_last = i == commands.len() - 1;
+            let started = stats::clock();
 
             // Handle input redirection on first command
             if is_first {
@@ -679,18 +1093,23 @@ impl Executor {
             }
 
             // Execute the command
-            let mut stdout = String::new();
+            let mut stdout = Vec::new();
             let mut stderr = String::new();
 
             // Expand glob patterns in arguments
-            let expanded_args = self.expand_args(&cmd.args);
+            let expanded_args = self.expand_args(cmd);
+            if let Some(trace) = self.trace(cmd) {
+                final_stderr.push_str(&trace);
+                final_stderr.push('\n');
+            }
 
             if builtins::is_builtin(&cmd.program) {
                 // Builtins in a pipeline get the pipe input as implicit stdin
-                let result = builtins::execute(&cmd.program, &expanded_args, &self.state);
+                let result =
+                    self.restrict(builtins::execute(&cmd.program, &expanded_args, &self.state));
                 match result {
                     BuiltinResult::Success(s) => {
-                        stdout = s;
+                        stdout = s.into_bytes();
                         last_code = 0;
                     }
                     BuiltinResult::Ok => {
@@ -731,31 +1150,101 @@ impl Executor {
                         }
                         last_code = 0;
                     }
+                    BuiltinResult::Journal(request) => {
+                        let result = self.run_journal(request);
+                        stdout = result.output.into_bytes();
+                        stderr = result.error;
+                        last_code = result.code;
+                    }
+                    BuiltinResult::SetOptions(options) => {
+                        self.state.options = options;
+                        last_code = 0;
+                    }
+                    BuiltinResult::SetTraps(changes) => {
+                        self.set_traps(changes);
+                        last_code = 0;
+                    }
+                    BuiltinResult::WithLock(request) => {
+                        let result = self.run_locked(request);
+                        stdout = result.output.into_bytes();
+                        stderr = result.error;
+                        last_code = result.code;
+                    }
+                    BuiltinResult::WithEnv(spec, command) => {
+                        let result = self.run_with_env(&spec, &command);
+                        stdout = result.output.into_bytes();
+                        stderr = result.error;
+                        last_code = result.code;
+                    }
+                    BuiltinResult::OsImage(request) => {
+                        let result = self.run_osimage(request);
+                        stdout = result.output.into_bytes();
+                        stderr = result.error;
+                        last_code = result.code;
+                    }
+                    BuiltinResult::Getopts(result) => {
+                        let result = self.apply_getopts(result);
+                        stderr = result.error;
+                        last_code = result.code;
+                    }
+                    BuiltinResult::Source(path, params) => {
+                        let result = self.run_source(&path, params);
+                        if result.should_exit {
+                            return result;
+                        }
+                        stdout = result.output.into_bytes();
+                        stderr = result.error;
+                        last_code = result.code;
+                    }
+                    BuiltinResult::Rerun(command) => {
+                        let result = self.run_again(&command);
+                        if result.should_exit {
+                            return result;
+                        }
+                        stdout = result.output.into_bytes();
+                        stderr = result.error;
+                        last_code = result.code;
+                    }
                 }
             } else if let Some(body) = self.state.get_function(&cmd.program).map(|s| s.to_string())
             {
                 // Execute shell function
                 let result = self.execute_line(&body);
-                stdout = result.output;
+                stdout = result.output.into_bytes();
                 stderr = result.error;
                 last_code = result.code;
             } else if let Some(prog) = self.registry.get(&cmd.program) {
                 // Registry program - pass pipe_input as stdin
-                last_code = prog(&expanded_args, &pipe_input, &mut stdout, &mut stderr);
+                last_code = prog.run(&expanded_args, &pipe_input, &mut stdout, &mut stderr);
+            } else if cmd.program == SCRIPT_COMMAND {
+                let input = std::mem::take(&mut pipe_input);
+                let input = String::from_utf8_lossy(&input);
+                let mut text = String::new();
+                last_code =
+                    self.execute_script(&expanded_args, Some(&input), &mut text, &mut stderr);
+                stdout = text.into_bytes();
             } else if self.is_wasm_command(&cmd.program) {
                 // WASM command - execute async with pipe_input
-                let result = self
-                    .execute_wasm_command(&cmd.program, &expanded_args, &pipe_input)
+                last_code = self
+                    .run_wasm_command(
+                        &cmd.program,
+                        &expanded_args,
+                        &pipe_input,
+                        &mut stdout,
+                        &mut stderr,
+                    )
                     .await;
-                stdout = result.output;
-                stderr = result.error;
-                last_code = result.code;
             } else {
                 // Command not found
                 stderr = format!("{}: command not found", cmd.program);
                 last_code = 127;
             }
 
+            self.record_stat(&cmd.program, started, last_code);
+            if last_code != 0 {
+                failed_code = last_code;
+            }
+
             // Collect stderr
             if !stderr.is_empty() {
                 final_stderr.push_str(&stderr);
@@ -779,11 +1268,14 @@ impl Executor {
             }
         }
 
+        if self.state.options.pipefail {
+            last_code = failed_code;
+        }
         self.state.last_status = last_code;
 
         ExecResult {
             code: last_code,
-            output: final_stdout,
+            output: String::from_utf8_lossy(&final_stdout).into_owned(),
             error: final_stderr,
             should_exit: false,
         }
@@ -798,13 +1290,31 @@ impl Executor {
     /// Execute a parsed pipeline asynchronously
     #[cfg(target_arch = "wasm32")]
     pub async fn execute_pipeline_async(&mut self, pipeline: &Pipeline) -> ExecResult {
+        if pipeline.timed {
+            let timing = Timing::start();
+            let untimed = Pipeline {
+                timed: false,
+                posix_time: false,
+                ..pipeline.clone()
+            };
+            let mut result = Box::pin(self.execute_pipeline_async(&untimed)).await;
+            timing.report(&mut result, pipeline.posix_time);
+            return result;
+        }
         if pipeline.commands.is_empty() {
             return ExecResult::success();
         }
 
         // For single commands, execute directly
         if pipeline.commands.len() == 1 {
-            return self.execute_single_async(&pipeline.commands[0]).await;
+            let started = stats::clock();
+            let trace = self.trace(&pipeline.commands[0]);
+            let mut result = self.execute_single_async(&pipeline.commands[0]).await;
+            self.record_stat(&pipeline.commands[0].program, started, result.code);
+            if let Some(trace) = trace {
+                prepend_line(&mut result.error, &trace);
+            }
+            return result;
         }
 
         // For pipelines, chain the commands
@@ -822,14 +1332,15 @@ impl Executor {
     pub async fn execute_command_list_async(&mut self, cmd_list: &CommandList) -> ExecResult {
         // Execute the first pipeline
         let mut result = self.execute_pipeline_async(&cmd_list.first).await;
+        self.run_traps(&mut result);
 
-        // Short-circuit on exit
-        if result.should_exit {
+        // Short-circuit on exit, or on failure with `set -e`
+        if result.should_exit || self.errexit(result.code, cmd_list.rest.first()) {
             return result;
         }
 
         // Execute remaining pipelines based on logical operators
-        for (op, pipeline) in &cmd_list.rest {
+        for (i, (op, pipeline)) in cmd_list.rest.iter().enumerate() {
             let should_execute = match op {
                 LogicalOp::Sequence => true,        // Always execute
                 LogicalOp::And => result.code == 0, // Execute if previous succeeded
@@ -837,18 +1348,9 @@ impl Executor {
             };
 
             if should_execute {
-                let next_result = self.execute_pipeline_async(pipeline).await;
-
-                // Combine outputs
-                if !result.output.is_empty() && !next_result.output.is_empty() {
-                    result.output.push('\n');
-                }
-                result.output.push_str(&next_result.output);
-
-                if !result.error.is_empty() && !next_result.error.is_empty() {
-                    result.error.push('\n');
-                }
-                result.error.push_str(&next_result.error);
+                let mut next_result = self.execute_pipeline_async(pipeline).await;
+                self.run_traps(&mut next_result);
+                merge_output(&mut result, &next_result);
 
                 // Update exit code to the last executed command
                 result.code = next_result.code;
@@ -858,6 +1360,9 @@ impl Executor {
                     result.should_exit = true;
                     return result;
                 }
+                if self.errexit(result.code, cmd_list.rest.get(i + 1)) {
+                    return result;
+                }
             }
         }
 
@@ -873,16 +1378,39 @@ impl Executor {
     /// Execute a command line asynchronously (supports WASM commands)
     #[cfg(target_arch = "wasm32")]
     pub async fn execute_line_async(&mut self, line: &str) -> ExecResult {
+        if let Some(result) = self.feed_script_repl(line) {
+            return result;
+        }
+
         // Skip empty lines and comments
         let line = line.trim();
         if line.is_empty() || line.starts_with('#') {
             return ExecResult::success();
         }
 
+        let before = self.journal.begin();
+        let outermost = before.is_some();
+        let mut result = self.run_line_async(line).await;
+        if result.should_exit {
+            self.run_exit_trap(&mut result);
+        }
+        let cwd = self.state.cwd.display().to_string();
+        self.journal.finish(before, line, &cwd, result.code);
+        if outermost {
+            stats::record_all(&std::mem::take(&mut self.stats));
+        }
+        observe_tutorial(line, &cwd, &mut result);
+        result
+    }
+
+    /// Expand, parse and run a non-empty command line asynchronously
+    #[cfg(target_arch = "wasm32")]
+    async fn run_line_async(&mut self, line: &str) -> ExecResult {
         // Expand aliases in the line
         let line = self.expand_aliases(line);
 
         // Expand command substitution
+        let procsubs = self.procsub_mark();
         let line = self.expand_substitution_in_line(&line);
 
         #[cfg(all(target_arch = "wasm32", not(test)))]
@@ -891,11 +1419,15 @@ impl Executor {
         // Parse the command
         let cmd_list = match super::parser::parse_command_list(&line) {
             Ok(c) => c,
-            Err(e) => return ExecResult::success().with_error(format!("parse error: {}", e)),
+            Err(e) => {
+                let result = ExecResult::success().with_error(format!("parse error: {}", e));
+                return self.finish_process_substitutions(procsubs, result);
+            }
         };
 
         // Execute the command list asynchronously (full pipeline support)
-        self.execute_command_list_async(&cmd_list).await
+        let result = self.execute_command_list_async(&cmd_list).await;
+        self.finish_process_substitutions(procsubs, result)
     }
 
     /// Execute a command line asynchronously (non-WASM stub)
@@ -906,14 +1438,17 @@ impl Executor {
 
     /// Execute a pipeline of commands
     fn execute_piped(&mut self, commands: &[SimpleCommand]) -> ExecResult {
-        let mut pipe_input = String::new();
-        let mut final_stdout = String::new();
+        let mut pipe_input = Vec::new();
+        let mut final_stdout = Vec::new();
         let mut final_stderr = String::new();
         let mut last_code = 0;
+        // Status of the rightmost failing command, for pipefail
+        let mut failed_code = 0;
 
         for (i, cmd) in commands.iter().enumerate() {
             let is_first = i == 0;
             let is_last = i == commands.len() - 1;
+            let started = stats::clock();
 
             // Handle input redirection on first command
             if is_first && let Some(ref redir) = cmd.stdin {
@@ -924,18 +1459,23 @@ impl Executor {
             }
 
             // Execute the command
-            let mut stdout = String::new();
+            let mut stdout = Vec::new();
             let mut stderr = String::new();
 
             // Expand glob patterns in arguments
-            let expanded_args = self.expand_args(&cmd.args);
+            let expanded_args = self.expand_args(cmd);
+            if let Some(trace) = self.trace(cmd) {
+                final_stderr.push_str(&trace);
+                final_stderr.push('\n');
+            }
 
             if builtins::is_builtin(&cmd.program) {
                 // Builtins in a pipeline get the pipe input as implicit stdin
-                let result = builtins::execute(&cmd.program, &expanded_args, &self.state);
+                let result =
+                    self.restrict(builtins::execute(&cmd.program, &expanded_args, &self.state));
                 match result {
                     BuiltinResult::Success(s) => {
-                        stdout = s;
+                        stdout = s.into_bytes();
                         last_code = 0;
                     }
                     BuiltinResult::Ok => {
@@ -976,23 +1516,90 @@ impl Executor {
                         }
                         last_code = 0;
                     }
+                    BuiltinResult::Journal(request) => {
+                        let result = self.run_journal(request);
+                        stdout = result.output.into_bytes();
+                        stderr = result.error;
+                        last_code = result.code;
+                    }
+                    BuiltinResult::SetOptions(options) => {
+                        self.state.options = options;
+                        last_code = 0;
+                    }
+                    BuiltinResult::SetTraps(changes) => {
+                        self.set_traps(changes);
+                        last_code = 0;
+                    }
+                    BuiltinResult::WithLock(request) => {
+                        let result = self.run_locked(request);
+                        stdout = result.output.into_bytes();
+                        stderr = result.error;
+                        last_code = result.code;
+                    }
+                    BuiltinResult::WithEnv(spec, command) => {
+                        let result = self.run_with_env(&spec, &command);
+                        stdout = result.output.into_bytes();
+                        stderr = result.error;
+                        last_code = result.code;
+                    }
+                    BuiltinResult::OsImage(request) => {
+                        let result = self.run_osimage(request);
+                        stdout = result.output.into_bytes();
+                        stderr = result.error;
+                        last_code = result.code;
+                    }
+                    BuiltinResult::Getopts(result) => {
+                        let result = self.apply_getopts(result);
+                        stderr = result.error;
+                        last_code = result.code;
+                    }
+                    BuiltinResult::Source(path, params) => {
+                        let result = self.run_source(&path, params);
+                        if result.should_exit {
+                            return result;
+                        }
+                        stdout = result.output.into_bytes();
+                        stderr = result.error;
+                        last_code = result.code;
+                    }
+                    BuiltinResult::Rerun(command) => {
+                        let result = self.run_again(&command);
+                        if result.should_exit {
+                            return result;
+                        }
+                        stdout = result.output.into_bytes();
+                        stderr = result.error;
+                        last_code = result.code;
+                    }
                 }
             } else if let Some(body) = self.state.get_function(&cmd.program).map(|s| s.to_string())
             {
                 // Execute shell function - function output becomes pipe output
                 let result = self.execute_line(&body);
-                stdout = result.output;
+                stdout = result.output.into_bytes();
                 stderr = result.error;
                 last_code = result.code;
             } else if let Some(prog) = self.registry.get(&cmd.program) {
                 // Pass pipe input directly via stdin parameter
-                last_code = prog(&expanded_args, &pipe_input, &mut stdout, &mut stderr);
+                last_code = prog.run(&expanded_args, &pipe_input, &mut stdout, &mut stderr);
+            } else if cmd.program == SCRIPT_COMMAND {
+                let input = std::mem::take(&mut pipe_input);
+                let input = String::from_utf8_lossy(&input);
+                let mut text = String::new();
+                last_code =
+                    self.execute_script(&expanded_args, Some(&input), &mut text, &mut stderr);
+                stdout = text.into_bytes();
             } else {
                 return ExecResult::success()
                     .with_error(format!("{}: command not found", cmd.program))
                     .with_code(127);
             }
 
+            self.record_stat(&cmd.program, started, last_code);
+            if last_code != 0 {
+                failed_code = last_code;
+            }
+
             // Collect stderr
             if !stderr.is_empty() {
                 final_stderr.push_str(&stderr);
@@ -1016,11 +1623,14 @@ impl Executor {
             }
         }
 
+        if self.state.options.pipefail {
+            last_code = failed_code;
+        }
         self.state.last_status = last_code;
 
         ExecResult {
             code: last_code,
-            output: final_stdout,
+            output: String::from_utf8_lossy(&final_stdout).into_owned(),
             error: final_stderr,
             should_exit: false,
         }
@@ -1029,14 +1639,14 @@ impl Executor {
     /// Execute a built-in command
     fn execute_builtin(&mut self, cmd: &SimpleCommand) -> ExecResult {
         // Expand glob patterns in arguments
-        let expanded_args = self.expand_args(&cmd.args);
-        let result = builtins::execute(&cmd.program, &expanded_args, &self.state);
+        let expanded_args = self.expand_args(cmd);
+        let result = self.restrict(builtins::execute(&cmd.program, &expanded_args, &self.state));
 
         match result {
             BuiltinResult::Success(output) => {
                 // Handle output redirection
                 let final_output = if let Some(ref redir) = cmd.stdout {
-                    if let Err(e) = self.write_file(&redir.path, &output, redir.append) {
+                    if let Err(e) = self.write_file(&redir.path, output.as_bytes(), redir.append) {
                         return ExecResult::success().with_error(e);
                     }
                     String::new()
@@ -1054,7 +1664,7 @@ impl Executor {
             BuiltinResult::Error(e) => {
                 // Handle stderr redirection
                 let error = if let Some(ref redir) = cmd.stderr {
-                    if let Err(err) = self.write_file(&redir.path, &e, redir.append) {
+                    if let Err(err) = self.write_file(&redir.path, e.as_bytes(), redir.append) {
                         return ExecResult::success().with_error(err);
                     }
                     String::new()
@@ -1098,6 +1708,572 @@ impl Executor {
                 self.state.last_status = 0;
                 ExecResult::success()
             }
+            BuiltinResult::Journal(request) => {
+                let mut result = self.run_journal(request);
+                if let Some(ref redir) = cmd.stdout {
+                    if let Err(e) =
+                        self.write_file(&redir.path, result.output.as_bytes(), redir.append)
+                    {
+                        return ExecResult::success().with_error(e);
+                    }
+                    result.output.clear();
+                }
+                self.state.last_status = result.code;
+                result
+            }
+            BuiltinResult::SetOptions(options) => {
+                self.state.options = options;
+                self.state.last_status = 0;
+                ExecResult::success()
+            }
+            BuiltinResult::SetTraps(changes) => {
+                self.set_traps(changes);
+                self.state.last_status = 0;
+                ExecResult::success()
+            }
+            BuiltinResult::WithEnv(spec, command) => {
+                let mut result = self.run_with_env(&spec, &command);
+                if let Some(ref redir) = cmd.stdout {
+                    if let Err(e) =
+                        self.write_file(&redir.path, result.output.as_bytes(), redir.append)
+                    {
+                        return ExecResult::success().with_error(e);
+                    }
+                    result.output.clear();
+                }
+                self.state.last_status = result.code;
+                result
+            }
+            BuiltinResult::WithLock(request) => {
+                let mut result = self.run_locked(request);
+                if let Some(ref redir) = cmd.stdout {
+                    if let Err(e) =
+                        self.write_file(&redir.path, result.output.as_bytes(), redir.append)
+                    {
+                        return ExecResult::success().with_error(e);
+                    }
+                    result.output.clear();
+                }
+                self.state.last_status = result.code;
+                result
+            }
+            BuiltinResult::OsImage(request) => {
+                let mut result = self.run_osimage(request);
+                if let Some(ref redir) = cmd.stdout {
+                    if let Err(e) =
+                        self.write_file(&redir.path, result.output.as_bytes(), redir.append)
+                    {
+                        return ExecResult::success().with_error(e);
+                    }
+                    result.output.clear();
+                }
+                self.state.last_status = result.code;
+                result
+            }
+            BuiltinResult::Getopts(result) => {
+                let result = self.apply_getopts(result);
+                self.state.last_status = result.code;
+                result
+            }
+            BuiltinResult::Source(path, params) => {
+                let mut result = self.run_source(&path, params);
+                if let Some(ref redir) = cmd.stdout {
+                    if let Err(e) =
+                        self.write_file(&redir.path, result.output.as_bytes(), redir.append)
+                    {
+                        return ExecResult::success().with_error(e);
+                    }
+                    result.output.clear();
+                }
+                self.state.last_status = result.code;
+                result
+            }
+            BuiltinResult::Rerun(command) => {
+                let mut result = self.run_again(&command);
+                if let Some(ref redir) = cmd.stdout {
+                    if let Err(e) =
+                        self.write_file(&redir.path, result.output.as_bytes(), redir.append)
+                    {
+                        return ExecResult::success().with_error(e);
+                    }
+                    result.output.clear();
+                }
+                self.state.last_status = result.code;
+                result
+            }
+        }
+    }
+
+    /// Run a command from the history again, from `fc -s`, showing it
+    /// first; it goes in the history in place of the `fc` line
+    fn run_again(&mut self, command: &str) -> ExecResult {
+        if self.rerunning {
+            return ExecResult::success().with_error("fc: a re-run command can't re-run another");
+        }
+        self.rerunning = true;
+        let mut result = self.run_line(command);
+        self.rerunning = false;
+        self.rerun = Some(command.to_string());
+        show_command(&mut result, command);
+        result
+    }
+
+    /// Run each line of a file as if it was typed, from `source`
+    ///
+    /// `params`, if any, are the positional parameters while it runs;
+    /// otherwise the file sees the current ones.
+    fn run_source(&mut self, path: &str, params: Vec<String>) -> ExecResult {
+        let content = match self.read_file(path) {
+            Ok(content) => content,
+            Err(e) => return ExecResult::success().with_error(format!("source: {}", e)),
+        };
+        let outer =
+            (!params.is_empty()).then(|| std::mem::replace(&mut self.state.positional, params));
+        let mut result = ExecResult::success();
+        for line in String::from_utf8_lossy(&content).lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let next = self.run_line(line);
+            merge_output(&mut result, &next);
+            result.code = next.code;
+            if next.should_exit {
+                result.should_exit = true;
+                break;
+            }
+        }
+        if let Some(outer) = outer {
+            self.state.positional = outer;
+        }
+        result
+    }
+
+    /// Apply one `getopts` step to the shell variables
+    fn apply_getopts(&mut self, result: GetoptsResult) -> ExecResult {
+        for (name, value) in result.set {
+            self.state.set_env(&name, &value);
+        }
+        for name in result.unset {
+            self.state.unset_env(&name);
+        }
+        self.state.getopts = result.state;
+        let code = result.status;
+        if result.error.is_empty() {
+            ExecResult::success().with_code(code)
+        } else {
+            ExecResult::success()
+                .with_error(result.error)
+                .with_code(code)
+        }
+    }
+
+    /// Set or reset traps, then route the trapped signals to the trap
+    /// signalfd: handled signals are blocked so they are only read from it,
+    /// ignored ones get the Ignore action, and reset ones are restored
+    fn set_traps(&mut self, changes: Vec<(String, Option<String>)>) {
+        for (condition, action) in changes {
+            match action {
+                Some(action) => self.state.traps.insert(condition, action),
+                None => self.state.traps.remove(&condition),
+            };
+        }
+
+        let (mut handled, mut ignored) = (0u16, 0u16);
+        for (condition, action) in &self.state.traps {
+            if let Some(signal) = Signal::from_name(condition) {
+                let bit = 1 << signal.num();
+                if action.is_empty() {
+                    ignored |= bit;
+                } else {
+                    handled |= bit;
+                }
+            }
+        }
+
+        // Kernel failures (no shell process) leave the traps recorded but
+        // inactive
+        for signal in Signal::ALL {
+            let bit = 1 << signal.num();
+            if (handled | ignored | self.trapped) & bit == 0 {
+                continue;
+            }
+            let action = if ignored & bit != 0 {
+                SignalAction::Ignore
+            } else {
+                SignalAction::Default
+            };
+            let _ = syscall::signal(signal, action);
+        }
+        let _ = syscall::sigprocmask(SigProcMaskHow::Unblock, self.trapped & !handled);
+        let _ = syscall::sigprocmask(SigProcMaskHow::Block, handled);
+        if handled != 0 || self.trap_fd.is_some() {
+            self.trap_fd = syscall::signalfd(self.trap_fd, handled).ok();
+        }
+        self.trapped = handled | ignored;
+    }
+
+    /// Run the traps of signals received since the last check, adding
+    /// their output to `result`
+    ///
+    /// `$?` is kept across a trap, like other shells.
+    fn run_traps(&mut self, result: &mut ExecResult) {
+        let Some(fd) = self.trap_fd else {
+            return;
+        };
+        if self.in_trap {
+            return;
+        }
+        let mut buf = [0u8; 8 * SIGNALFD_RECORD_SIZE];
+        while let Ok(n) = syscall::read(fd, &mut buf) {
+            let signals: Vec<Signal> = buf[..n]
+                .chunks_exact(SIGNALFD_RECORD_SIZE)
+                .filter_map(|r| Signal::from_num(r[0]))
+                .collect();
+            for signal in signals {
+                if let Some(action) = self.state.traps.get(&signal.to_string()).cloned() {
+                    self.run_trap(&action, result);
+                }
+            }
+        }
+    }
+
+    /// Run the EXIT trap, once, as the shell exits
+    fn run_exit_trap(&mut self, result: &mut ExecResult) {
+        if let Some(action) = self.state.traps.remove("EXIT") {
+            self.run_trap(&action, result);
+            result.should_exit = true;
+        }
+    }
+
+    fn run_trap(&mut self, action: &str, result: &mut ExecResult) {
+        if action.is_empty() || self.in_trap {
+            return;
+        }
+        let status = self.state.last_status;
+        self.in_trap = true;
+        let trap_result = self.run_line(action);
+        self.in_trap = false;
+        self.state.last_status = status;
+        merge_output(result, &trap_result);
+        if trap_result.should_exit {
+            result.should_exit = true;
+            result.code = trap_result.code;
+        }
+    }
+
+    /// Run a `flock` command with its lock held
+    ///
+    /// The lock belongs to the shell process and is released when the
+    /// descriptor is closed afterwards.
+    fn run_locked(&mut self, request: LockRequest) -> ExecResult {
+        let path = if request.path.starts_with('/') {
+            request.path.clone()
+        } else {
+            format!("{}/{}", self.state.cwd.display(), request.path)
+        };
+        let flags = syscall::OpenFlags {
+            read: true,
+            write: false,
+            create: true,
+            truncate: false,
+            append: false,
+            nonblock: false,
+        };
+        let fd = match syscall::open(&path, flags) {
+            Ok(fd) => fd,
+            Err(e) => {
+                return ExecResult::success()
+                    .with_error(format!("flock: {}: {}", request.path, e))
+                    .with_code(1);
+            }
+        };
+
+        let (operation, lock_type) = if request.shared {
+            (LOCK_SH, LockType::Shared)
+        } else {
+            (LOCK_EX, LockType::Exclusive)
+        };
+        if let Err(e) = syscall::flock(fd, operation | LOCK_NB) {
+            let holder = syscall::flock_holder(&path, lock_type);
+            let _ = syscall::close(fd);
+            if e != SyscallError::WouldBlock {
+                return ExecResult::success()
+                    .with_error(format!("flock: {}: {}", request.path, e))
+                    .with_code(1);
+            }
+            let error = match holder {
+                _ if request.nonblock => String::new(),
+                Some(pid) => format!("flock: {}: locked by process {}", request.path, pid.0),
+                None => format!("flock: {}: locked", request.path),
+            };
+            return ExecResult::success()
+                .with_error(error)
+                .with_code(request.conflict_code);
+        }
+
+        let result = self.run_line(&request.command);
+        let _ = syscall::close(fd);
+        result
+    }
+
+    /// Run `command` with the shell's and the shell process's environments
+    /// changed by `spec`, then put them back
+    fn run_with_env(&mut self, spec: &EnvSpec, command: &str) -> ExecResult {
+        let saved = std::mem::take(&mut self.state.env);
+        self.state.env = spec.resolve(&saved);
+        let process_env: HashMap<String, String> =
+            syscall::environ().unwrap_or_default().into_iter().collect();
+        set_process_environ(&process_env, &spec.resolve(&process_env));
+
+        let result = self.run_line(command);
+
+        let changed: HashMap<String, String> =
+            syscall::environ().unwrap_or_default().into_iter().collect();
+        set_process_environ(&changed, &process_env);
+        self.state.env = saved;
+        result
+    }
+
+    /// Carry out a `journal` or `undo-last` request
+    fn run_journal(&mut self, request: JournalRequest) -> ExecResult {
+        match request {
+            JournalRequest::Show { count } => {
+                let entries = journal::read_entries();
+                let undoable = self.journal.undoable();
+                let mut out = String::from("  SEQ EXIT CHANGES      COMMAND");
+                for entry in entries.iter().skip(entries.len().saturating_sub(count)) {
+                    let mark = if undoable.contains(&entry.seq) {
+                        '*'
+                    } else {
+                        ' '
+                    };
+                    out.push_str(&format!(
+                        "\n{:>5}{}{:>4} {:<12} {}",
+                        entry.seq,
+                        mark,
+                        entry.code,
+                        entry.summary(),
+                        entry.command
+                    ));
+                }
+                ExecResult::success().with_output(out)
+            }
+            JournalRequest::Diff { seq } => {
+                let Some(entry) = journal::read_entries().into_iter().find(|e| e.seq == seq) else {
+                    return ExecResult::success()
+                        .with_error(format!("journal: #{}: no such entry", seq));
+                };
+                let mut out = format!(
+                    "#{} {} (exit {}, uid {}, cwd {})",
+                    entry.seq, entry.command, entry.code, entry.uid, entry.cwd
+                );
+                for change in &entry.changes {
+                    out.push_str(&format!("\n{} {}", change.kind.marker(), change.path));
+                }
+                ExecResult::success().with_output(out)
+            }
+            JournalRequest::Replay { from, to } => {
+                let entries: Vec<JournalEntry> = journal::read_entries()
+                    .into_iter()
+                    .filter(|e| {
+                        (from..=to).contains(&e.seq) && !journal::is_journal_command(&e.command)
+                    })
+                    .collect();
+                if entries.is_empty() {
+                    return ExecResult::success()
+                        .with_error(format!("journal: no entries in #{}..#{}", from, to));
+                }
+
+                let cwd = self.state.cwd.clone();
+                let mut result = ExecResult::success();
+                for entry in entries {
+                    result.output.push_str(&format!("+ {}\n", entry.command));
+                    let step = match self.change_directory(Path::new(&entry.cwd)) {
+                        r if r.code != 0 => r,
+                        _ => self.execute_line(&entry.command),
+                    };
+                    result.output.push_str(&step.output);
+                    if !step.output.is_empty() && !step.output.ends_with('\n') {
+                        result.output.push('\n');
+                    }
+                    if step.code != 0 {
+                        result.error = format!(
+                            "journal: #{} exited with {}\n{}",
+                            entry.seq, step.code, step.error
+                        );
+                        result.code = step.code;
+                        break;
+                    }
+                }
+                self.change_directory(&cwd);
+                if result.output.ends_with('\n') {
+                    result.output.pop();
+                }
+                result
+            }
+            JournalRequest::Undo { force } => match self.journal.undo_last(force) {
+                Ok(entry) => {
+                    let mut out = format!("undo-last: reverted #{} {}", entry.seq, entry.command);
+                    for change in &entry.changes {
+                        let action = match change.kind {
+                            FsChangeKind::Created => "removed",
+                            FsChangeKind::Modified | FsChangeKind::Deleted => "restored",
+                        };
+                        out.push_str(&format!("\n{} {}", action, change.path));
+                    }
+                    ExecResult::success().with_output(out)
+                }
+                Err(e) => ExecResult::success().with_error(format!("undo-last: {}", e)),
+            },
+        }
+    }
+
+    /// Carry out an `osimage` request
+    fn run_osimage(&mut self, request: OsImageRequest) -> ExecResult {
+        let fail = |e: String| ExecResult::success().with_error(format!("osimage: {}", e));
+        // Images carry /etc/shadow, and importing one replaces every user
+        let privileged = matches!(
+            request,
+            OsImageRequest::Export { .. } | OsImageRequest::Import { .. }
+        );
+        if privileged && syscall::geteuid().ok() != Some(Uid::ROOT) {
+            return fail("permission denied (must be root)".into());
+        }
+
+        match request {
+            OsImageRequest::Export { path, password } => {
+                let vfs = match syscall::vfs_snapshot()
+                    .and_then(|json| serde_json::from_slice(&json).map_err(Into::into))
+                {
+                    Ok(vfs) => vfs,
+                    Err(e) => return fail(format!("snapshot failed: {}", e)),
+                };
+                let contents = ImageContents {
+                    session: Session::capture(&self.state),
+                    vfs,
+                };
+                let image =
+                    match osimage::write(&syscall::gethostname(), &contents, password.as_deref()) {
+                        Ok(image) => image,
+                        Err(e) => return fail(e),
+                    };
+                if let Err(e) = self.write_file(&path, &image, false) {
+                    return fail(e);
+                }
+
+                #[cfg(target_arch = "wasm32")]
+                {
+                    let name = path.rsplit('/').next().unwrap_or(&path);
+                    if let Err(e) = osimage::download(name, &image) {
+                        return fail(format!("download failed: {}", e));
+                    }
+                }
+
+                let kind = if password.is_some() {
+                    "encrypted image"
+                } else {
+                    "image"
+                };
+                ExecResult::success().with_output(format!(
+                    "osimage: wrote {} ({} bytes) to {}",
+                    kind,
+                    image.len(),
+                    path
+                ))
+            }
+            OsImageRequest::Import { path, password } => {
+                let image = match self.read_file(&path) {
+                    Ok(image) => image,
+                    Err(e) => return fail(e),
+                };
+                let (header, contents) = match osimage::read(&image, password.as_deref()) {
+                    Ok(read) => read,
+                    Err(e) => return fail(format!("{}: {}", path, e)),
+                };
+                let restored = serde_json::to_vec(&contents.vfs)
+                    .map_err(Into::into)
+                    .and_then(|json| syscall::vfs_restore(&json));
+                if let Err(e) = restored {
+                    return fail(format!("restore failed: {}", e));
+                }
+
+                let cwd = contents.session.cwd();
+                contents.session.apply(&mut self.state);
+                if self.change_directory(&cwd).code != 0 {
+                    self.change_directory(Path::new("/"));
+                }
+                let message = format!(
+                    "imported {} (axeberg {}, from {})",
+                    path, header.version, header.hostname
+                );
+                syscall::klog(LogLevel::Notice, "osimage", &message);
+                ExecResult::success().with_output(format!(
+                    "osimage: {}\nRun 'save' to keep it across reloads.",
+                    message
+                ))
+            }
+            OsImageRequest::Info { path } => {
+                let image = match self.read_file(&path) {
+                    Ok(image) => image,
+                    Err(e) => return fail(e),
+                };
+                let (header, payload) = match osimage::read_header(&image) {
+                    Ok(read) => read,
+                    Err(e) => return fail(format!("{}: {}", path, e)),
+                };
+                let mut out = format!(
+                    "Format:    {}\nVersion:   {}\nHostname:  {}\nSize:      {} bytes\nEncrypted: {}",
+                    header.format,
+                    header.version,
+                    header.hostname,
+                    image.len(),
+                    if header.encrypted { "yes" } else { "no" }
+                );
+                // Encrypted payloads are checked when the password is known
+                if !header.encrypted {
+                    let check = match integrity::unseal(payload) {
+                        Ok(_) => "ok".to_string(),
+                        Err(e) => e.to_string(),
+                    };
+                    out.push_str(&format!("\nChecksum:  {}", check));
+                }
+                ExecResult::success().with_output(out)
+            }
+            OsImageRequest::Upload => {
+                #[cfg(target_arch = "wasm32")]
+                {
+                    let dir = self.state.cwd.display().to_string();
+                    wasm_bindgen_futures::spawn_local(async move {
+                        let message = match osimage::pick_file(".axi").await {
+                            Ok(Some((name, data))) => {
+                                let path = format!("{}/{}", dir.trim_end_matches('/'), name);
+                                let written = syscall::open(&path, syscall::OpenFlags::WRITE)
+                                    .and_then(|fd| {
+                                        let result = syscall::write(fd, &data).map(|_| ());
+                                        let _ = syscall::close(fd);
+                                        result
+                                    });
+                                match written {
+                                    Ok(()) => {
+                                        format!("uploaded {} (run 'osimage import {}')", path, path)
+                                    }
+                                    Err(e) => format!("upload to {} failed: {}", path, e),
+                                }
+                            }
+                            Ok(None) => "upload cancelled".to_string(),
+                            Err(e) => format!("upload failed: {}", e),
+                        };
+                        crate::console_log!("[osimage] {}", message);
+                        syscall::klog(LogLevel::Notice, "osimage", &message);
+                    });
+                    ExecResult::success().with_output(
+                        "Choose an image in the file picker...\n(Check 'dmesg' for the result)",
+                    )
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                fail("upload needs a browser".into())
+            }
         }
     }
 
@@ -1136,7 +2312,7 @@ impl Executor {
     }
 
     /// Read a file for input redirection
-    fn read_file(&self, path: &str) -> Result<String, String> {
+    fn read_file(&self, path: &str) -> Result<Vec<u8>, String> {
         let full_path = if path.starts_with('/') {
             path.to_string()
         } else {
@@ -1160,11 +2336,11 @@ impl Executor {
         }
 
         let _ = syscall::close(fd);
-        String::from_utf8(content).map_err(|_| format!("{}: invalid UTF-8", path))
+        Ok(content)
     }
 
     /// Write to a file for output redirection
-    fn write_file(&self, path: &str, content: &str, append: bool) -> Result<(), String> {
+    fn write_file(&self, path: &str, content: &[u8], append: bool) -> Result<(), String> {
         let full_path = if path.starts_with('/') {
             path.to_string()
         } else {
@@ -1179,32 +2355,61 @@ impl Executor {
 
         let fd = syscall::open(&full_path, flags).map_err(|e| format!("{}: {}", path, e))?;
 
-        syscall::write(fd, content.as_bytes()).map_err(|e| format!("{}: {}", path, e))?;
+        syscall::write(fd, content).map_err(|e| format!("{}: {}", path, e))?;
 
         syscall::close(fd).map_err(|e| format!("{}: {}", path, e))?;
 
         Ok(())
     }
 
-    /// Expand glob patterns in arguments
-    fn expand_args(&self, args: &[String]) -> Vec<String> {
+    /// Expand `$?` and glob patterns in arguments
+    ///
+    /// `$?` is expanded here rather than with the rest of the line so that
+    /// it sees the status of the command just before it: `false; echo $?`.
+    /// One the parser found in single quotes stays as written.
+    fn expand_args(&self, cmd: &SimpleCommand) -> Vec<String> {
         let mut expanded = Vec::new();
-        for arg in args {
-            if is_glob_pattern(arg) {
-                let matches = expand_glob(arg, &self.state.cwd.display().to_string());
+        for (i, arg) in cmd.args.iter().enumerate() {
+            let quoted = cmd.quoted_dollars.get(i).map_or(&[][..], Vec::as_slice);
+            let arg = expand_vars(arg, quoted, &self.state);
+            if is_glob_pattern(&arg) {
+                let matches = expand_glob(&arg, &self.state.cwd.display().to_string());
                 if matches.is_empty() {
                     // No match - keep the original pattern (bash behavior)
-                    expanded.push(arg.clone());
+                    expanded.push(arg);
                 } else {
                     expanded.extend(matches);
                 }
             } else {
-                expanded.push(arg.clone());
+                expanded.push(arg);
             }
         }
         expanded
     }
 
+    /// The `set -x` trace of a command, with its arguments expanded
+    fn trace(&self, cmd: &SimpleCommand) -> Option<String> {
+        if !self.state.options.xtrace {
+            return None;
+        }
+        let mut line = format!("+ {}", cmd.program);
+        for arg in self.expand_args(cmd) {
+            line.push(' ');
+            line.push_str(&trace_quote(&arg));
+        }
+        Some(line)
+    }
+
+    /// Check if `set -e` stops a command list after a pipeline
+    ///
+    /// A pipeline on the left of `&&` or `||` is being tested, so its
+    /// failure doesn't count.
+    fn errexit(&self, code: i32, next: Option<&(LogicalOp, Pipeline)>) -> bool {
+        self.state.options.errexit
+            && code != 0
+            && !matches!(next, Some((LogicalOp::And | LogicalOp::Or, _)))
+    }
+
     /// Expand command substitution in a full line (before parsing)
     fn expand_substitution_in_line(&mut self, line: &str) -> String {
         self.expand_substitution_in_arg(line)
@@ -1279,19 +2484,24 @@ impl Executor {
     /// Execute process substitution for input: <(cmd)
     /// Returns a path to a file containing the command output
     fn execute_process_substitution_input(&mut self, cmd: &str) -> String {
-        // Execute the command and capture output
-        let output = self.execute_substitution(cmd);
+        // Execute the command and capture output as a file would hold it,
+        // ending in a newline
+        let mut output = self.substitution_output(cmd);
+        if !output.is_empty() && !output.ends_with('\n') {
+            output.push('\n');
+        }
 
         // Ensure /tmp exists
         let _ = syscall::mkdir("/tmp");
 
         // Create a temp file with the output
         let temp_path = format!("/tmp/procsub_{}", self.next_procsub_id());
-        if let Err(_e) = self.write_file(&temp_path, &output, false) {
+        if let Err(_e) = self.write_file(&temp_path, output.as_bytes(), false) {
             // On error, return a path that will cause an error when read
             return "/dev/null".to_string(); // fallback
         }
 
+        self.procsub_files.push(temp_path.clone());
         temp_path
     }
 
@@ -1314,10 +2524,49 @@ impl Executor {
         // Store the command to execute later with this output file
         self.pending_output_substitutions
             .push((temp_path.clone(), cmd.to_string()));
+        self.procsub_files.push(temp_path.clone());
 
         temp_path
     }
 
+    /// Where the process substitutions of the line about to be expanded
+    /// will start, for [`Self::finish_process_substitutions`]
+    fn procsub_mark(&self) -> (usize, usize) {
+        (
+            self.procsub_files.len(),
+            self.pending_output_substitutions.len(),
+        )
+    }
+
+    /// Once a line is done, run its `>(cmd)` commands on what was written
+    /// to their files, adding their output to the line's, and remove the
+    /// line's temp files
+    ///
+    /// Only substitutions made since `mark` are touched, so a function or
+    /// script run by the line doesn't clean up files the line still needs.
+    fn finish_process_substitutions(
+        &mut self,
+        mark: (usize, usize),
+        mut result: ExecResult,
+    ) -> ExecResult {
+        let (files, outputs) = mark;
+        let pending: Vec<_> = self.pending_output_substitutions.drain(outputs..).collect();
+        for (path, cmd) in pending {
+            if syscall::exists(&path).unwrap_or(false) {
+                let consumer = format!("cat {} | {}", path, cmd);
+                if let Ok(pipeline) = super::parser::parse(&consumer) {
+                    let consumed = self.execute_pipeline(&pipeline);
+                    result.output.push_str(&consumed.output);
+                    result.error.push_str(&consumed.error);
+                }
+            }
+        }
+        for path in self.procsub_files.drain(files..) {
+            let _ = syscall::remove_file(&path);
+        }
+        result
+    }
+
     /// Generate unique ID for process substitution temp files
     fn next_procsub_id(&mut self) -> u64 {
         self.procsub_counter += 1;
@@ -1354,31 +2603,39 @@ impl Executor {
 
     /// Execute a command for substitution and return its output
     fn execute_substitution(&mut self, cmd: &str) -> String {
+        // Trim trailing newline for substitution (bash behavior)
+        self.substitution_output(cmd)
+            .trim_end_matches('\n')
+            .to_string()
+    }
+
+    /// Execute a command for substitution and return all of its output
+    fn substitution_output(&mut self, cmd: &str) -> String {
         // Recursively expand any nested substitutions first
         let expanded_cmd = self.expand_substitution_in_line(cmd);
 
         // Parse and execute the command
         match super::parser::parse(&expanded_cmd) {
-            Ok(pipeline) => {
-                let result = self.execute_pipeline(&pipeline);
-                // Trim trailing newline for substitution (bash behavior)
-                result.output.trim_end_matches('\n').to_string()
-            }
+            Ok(pipeline) => self.execute_pipeline(&pipeline).output,
             Err(_) => String::new(),
         }
     }
 
     /// Expand aliases in a command line
     fn expand_aliases(&self, line: &str) -> String {
-        // Split line into potential command segments (separated by |, ;, &&, ||)
-        // For simplicity, we'll just expand the first word of each pipe segment
+        self.expand_aliases_guarded(line, &mut Vec::new())
+    }
+
+    /// Expand the command word of each command in `line`, skipping the
+    /// aliases in `active`, which are already being expanded
+    fn expand_aliases_guarded(&self, line: &str, active: &mut Vec<String>) -> String {
+        // Split line into command segments (separated by |, ;, &, && and ||)
         let mut result = String::new();
         let mut current_segment = String::new();
         let mut in_quote = false;
         let mut quote_char = ' ';
-        let mut chars = line.chars().peekable();
 
-        while let Some(c) = chars.next() {
+        for c in line.chars() {
             match c {
                 '"' | '\'' if !in_quote => {
                     in_quote = true;
@@ -1389,18 +2646,14 @@ impl Executor {
                     in_quote = false;
                     current_segment.push(c);
                 }
-                '|' | ';' if !in_quote => {
+                // `&` in a redirection like `2>&1` doesn't end the command
+                '&' if current_segment.ends_with(['<', '>']) => current_segment.push(c),
+                '|' | ';' | '&' if !in_quote => {
                     // End of segment, expand and add
-                    result.push_str(&self.expand_alias_in_segment(&current_segment));
+                    result.push_str(&self.expand_alias_in_segment(&current_segment, active));
                     result.push(c);
                     current_segment.clear();
                 }
-                '&' if !in_quote && chars.peek() == Some(&'&') => {
-                    chars.next();
-                    result.push_str(&self.expand_alias_in_segment(&current_segment));
-                    result.push_str("&&");
-                    current_segment.clear();
-                }
                 _ => {
                     current_segment.push(c);
                 }
@@ -1409,34 +2662,183 @@ impl Executor {
 
         // Handle last segment
         if !current_segment.is_empty() {
-            result.push_str(&self.expand_alias_in_segment(&current_segment));
+            result.push_str(&self.expand_alias_in_segment(&current_segment, active));
         }
 
         result
     }
 
-    /// Expand alias in a single command segment
-    fn expand_alias_in_segment(&self, segment: &str) -> String {
+    /// Expand the alias in command position of a single command segment
+    ///
+    /// Like other shells: a quoted command word is never an alias, an
+    /// alias is not expanded again inside its own expansion (so
+    /// `alias ls='ls -F'` terminates), and an alias whose value ends in a
+    /// blank makes the word after it a command word too.
+    fn expand_alias_in_segment(&self, segment: &str, active: &mut Vec<String>) -> String {
         let trimmed = segment.trim_start();
         if trimmed.is_empty() {
             return segment.to_string();
         }
+        let leading_ws = &segment[..segment.len() - trimmed.len()];
 
         // Find the first word (the command)
         let first_word_end = trimmed
-            .find(|c: char| c.is_whitespace())
+            .find(|c: char| c.is_whitespace() || matches!(c, '<' | '>' | '(' | ')'))
             .unwrap_or(trimmed.len());
         let first_word = &trimmed[..first_word_end];
         let rest = &trimmed[first_word_end..];
 
-        // Check if it's an alias
-        if let Some(alias_value) = self.state.aliases.get(first_word) {
-            // Preserve leading whitespace from original segment
-            let leading_ws = &segment[..segment.len() - trimmed.len()];
-            format!("{}{}{}", leading_ws, alias_value, rest)
+        let alias_value = match self.state.aliases.get(first_word) {
+            Some(value) if !active.iter().any(|a| a == first_word) => value,
+            _ => return segment.to_string(),
+        };
+
+        active.push(first_word.to_string());
+        let expanded = self.expand_aliases_guarded(alias_value, active);
+        active.pop();
+
+        let rest = if alias_value.ends_with(char::is_whitespace) {
+            self.expand_alias_in_segment(rest, active)
         } else {
-            segment.to_string()
+            rest.to_string()
+        };
+        format!("{}{}{}", leading_ws, expanded, rest)
+    }
+}
+
+/// Put the command line that ran in front of its output
+fn show_command(result: &mut ExecResult, command: &str) {
+    result.output = if result.output.is_empty() {
+        command.to_string()
+    } else {
+        format!("{}\n{}", command, result.output)
+    };
+}
+
+/// Append the output and errors of `next` to `result`, a line apart
+fn merge_output(result: &mut ExecResult, next: &ExecResult) {
+    if !result.output.is_empty() && !next.output.is_empty() {
+        result.output.push('\n');
+    }
+    result.output.push_str(&next.output);
+
+    if !result.error.is_empty() && !next.error.is_empty() {
+        result.error.push('\n');
+    }
+    result.error.push_str(&next.error);
+}
+
+/// What a pipeline prefixed with `time` has used so far
+struct Timing {
+    started: f64,
+    usage: syscall::ResourceUsage,
+}
+
+impl Timing {
+    /// Start timing; the shell's peak memory starts over so the report
+    /// shows the pipeline's own
+    fn start() -> Self {
+        let _ = syscall::reset_peak_memory();
+        Self {
+            started: stats::clock(),
+            usage: syscall::getrusage().unwrap_or_default(),
+        }
+    }
+
+    /// Add the bash-style report to `result`'s error output, or with
+    /// `posix` the `time -p` one: real, user and sys time in seconds
+    ///
+    /// Commands run in the shell's process and keep it busy until they
+    /// finish, so user time is the wall time not spent in syscalls.
+    fn report(self, result: &mut ExecResult, posix: bool) {
+        let real = (stats::clock() - self.started).max(0.0);
+        let usage = syscall::getrusage().unwrap_or_default();
+        let sys = (usage.sys_ms - self.usage.sys_ms).clamp(0.0, real);
+        if !result.error.is_empty() && !result.error.ends_with('\n') {
+            result.error.push('\n');
+        }
+        if posix {
+            result.error.push_str(&format!(
+                "real {:.2}\nuser {:.2}\nsys {:.2}\n",
+                real / 1000.0,
+                (real - sys) / 1000.0,
+                sys / 1000.0,
+            ));
+            return;
+        }
+        result.error.push_str(&format!(
+            "\nreal\t{}\nuser\t{}\nsys\t{}\nmaxmem\t{}K\nsyscalls\t{}\n",
+            format_duration(real),
+            format_duration(real - sys),
+            format_duration(sys),
+            usage.peak_memory.div_ceil(1024),
+            usage.syscalls - self.usage.syscalls,
+        ));
+    }
+}
+
+/// Milliseconds as bash's `time` shows them: `0m1.250s`
+fn format_duration(ms: f64) -> String {
+    let ms = ms.max(0.0).round() as u64;
+    format!("{}m{}.{:03}s", ms / 60_000, ms / 1000 % 60, ms % 1000)
+}
+
+/// Put a line in front of a command's error output
+fn prepend_line(error: &mut String, line: &str) {
+    if error.is_empty() {
+        error.push_str(line);
+    } else {
+        error.insert(0, '\n');
+        error.insert_str(0, line);
+    }
+}
+
+/// Quote an argument for a `set -x` trace so it reads back as one word
+fn trace_quote(arg: &str) -> String {
+    if !arg.is_empty()
+        && !arg
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '\'' | '"' | '\\' | '|' | ';' | '&'))
+    {
+        return arg.to_string();
+    }
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+/// Process and user the shell runs as
+fn session() -> (Option<Pid>, Option<Uid>) {
+    (syscall::getpid().ok(), syscall::getuid().ok())
+}
+
+/// Change the current process's environment from `from` to `to`
+fn set_process_environ(from: &HashMap<String, String>, to: &HashMap<String, String>) {
+    for name in from.keys().filter(|name| !to.contains_key(*name)) {
+        let _ = syscall::unsetenv(name);
+    }
+    for (name, value) in to
+        .iter()
+        .filter(|(name, value)| from.get(*name) != Some(value))
+    {
+        let _ = syscall::setenv(name, value);
+    }
+}
+
+/// Let a running tutorial lesson look at the line that just ran, adding
+/// its progress to the output
+fn observe_tutorial(line: &str, cwd: &str, result: &mut ExecResult) {
+    if tutorial::is_tutorial_command(line) {
+        return;
+    }
+    let observed = tutorial::Observed {
+        line: Some(line),
+        code: result.code,
+        cwd,
+    };
+    if let Some(note) = tutorial::observe(&observed) {
+        if !result.output.is_empty() && !result.output.ends_with('\n') {
+            result.output.push('\n');
         }
+        result.output.push_str(&note);
     }
 }
 
@@ -1446,7 +2848,7 @@ fn is_glob_pattern(s: &str) -> bool {
 }
 
 /// Match a pattern against a filename (not full path)
-fn glob_match(pattern: &str, name: &str) -> bool {
+pub(crate) fn glob_match(pattern: &str, name: &str) -> bool {
     glob_match_chars(
         &mut pattern.chars().peekable(),
         &mut name.chars().peekable(),
@@ -1548,8 +2950,53 @@ fn glob_match_chars(
             }
         }
     }
-    // Pattern consumed - name should also be consumed
-    name.next().is_none()
+    // Pattern consumed - name should also be consumed
+    name.next().is_none()
+}
+
+/// Expand the parameters in `arg`, but for those whose `$` is at one of
+/// the `quoted` offsets
+///
+/// `$?` is the last exit status, `$1` to `$9` and `$#` the positional
+/// parameters, and `$NAME` and `${NAME}` variables. Unset ones expand to
+/// nothing; a `$` that starts none of these stays as written.
+fn expand_vars(arg: &str, quoted: &[usize], state: &ShellState) -> String {
+    let mut out = String::with_capacity(arg.len());
+    let mut last = 0;
+    for (i, _) in arg.match_indices('$') {
+        if i < last || quoted.contains(&i) {
+            continue;
+        }
+        let rest = &arg[i + 1..];
+        let (value, len) = match rest.chars().next() {
+            Some('?') => (state.last_status.to_string(), 1),
+            Some('#') => (state.positional.len().to_string(), 1),
+            Some(c @ '1'..='9') => {
+                let n = c as usize - '1' as usize;
+                (state.positional.get(n).cloned().unwrap_or_default(), 1)
+            }
+            Some('{') => match rest[1..].split_once('}') {
+                Some((name, _)) if builtins::is_valid_var_name(name) => (
+                    state.get_env(name).unwrap_or_default().to_string(),
+                    name.len() + 2,
+                ),
+                _ => continue,
+            },
+            Some(c) if c == '_' || c.is_ascii_alphabetic() => {
+                let end = rest
+                    .find(|c: char| c != '_' && !c.is_ascii_alphanumeric())
+                    .unwrap_or(rest.len());
+                let name = &rest[..end];
+                (state.get_env(name).unwrap_or_default().to_string(), end)
+            }
+            _ => continue,
+        };
+        out.push_str(&arg[last..i]);
+        out.push_str(&value);
+        last = i + 1 + len;
+    }
+    out.push_str(&arg[last..]);
+    out
 }
 
 /// Expand a glob pattern against the filesystem
@@ -1698,6 +3145,25 @@ fn expand_glob_traverse(dir: &str, suffix: &str, results: &mut Vec<String>) {
     }
 }
 
+impl ScriptHost for Executor {
+    fn exec(&mut self, line: &str) -> ExecResult {
+        self.execute_line(line)
+    }
+
+    fn get_env(&self, name: &str) -> Option<String> {
+        self.state.get_env(name).map(str::to_string)
+    }
+
+    fn set_env(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let export = BuiltinResult::Export(vec![(name.to_string(), value.to_string())]);
+        if let BuiltinResult::Error(e) = self.restrict(export) {
+            return Err(e);
+        }
+        self.state.set_env(name, value);
+        Ok(())
+    }
+}
+
 impl Default for Executor {
     fn default() -> Self {
         Self::new()
@@ -1726,6 +3192,18 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_every_program_has_spec() {
+        // test takes no options, so `test --help` is a string test
+        for name in ProgramRegistry::new().list() {
+            assert!(
+                programs::spec(name).is_some() || matches!(name, "test" | "["),
+                "{} has no command spec",
+                name
+            );
+        }
+    }
+
     // ============ Executor basics ============
 
     #[test]
@@ -1891,15 +3369,57 @@ mod tests {
         assert_eq!(exec.state.get_env("FOO"), None);
     }
 
+    #[test]
+    fn test_getopts_sets_variables() {
+        let mut exec = Executor::new();
+        let line = "getopts ab: opt -ab val rest";
+        assert_eq!(exec.execute_line(line).code, 0);
+        assert_eq!(exec.state.get_env("opt"), Some("a"));
+        assert_eq!(exec.state.get_env("OPTIND"), Some("1"));
+        assert_eq!(exec.execute_line(line).code, 0);
+        assert_eq!(exec.state.get_env("opt"), Some("b"));
+        assert_eq!(exec.state.get_env("OPTARG"), Some("val"));
+        assert_eq!(exec.state.get_env("OPTIND"), Some("3"));
+        assert_eq!(exec.execute_line(line).code, 1);
+        assert_eq!(exec.state.get_env("opt"), Some("?"));
+
+        exec.execute_line("unset OPTIND");
+        let result = exec.execute_line("getopts a opt -z");
+        assert_eq!(result.code, 0);
+        assert!(result.error.contains("illegal option -- z"));
+    }
+
+    #[test]
+    fn test_getopts_through_the_shell() {
+        setup_kernel();
+        let mut exec = Executor::new();
+        let result = exec.execute_line("getopts ab: opt -a; echo $opt $OPTIND");
+        assert_eq!(result.output, "a 2");
+        exec.execute_line("unset OPTIND");
+        let result = exec.execute_line("getopts ab: opt -b val x; echo $opt $OPTARG $OPTIND");
+        assert_eq!(result.output, "b val 3");
+
+        // Without ARGs, a sourced file's positional parameters are parsed
+        let script = "getopts vf: opt && echo $opt\n\
+                      getopts vf: opt && echo $opt $OPTARG\n\
+                      getopts vf: opt || echo $# operands from $OPTIND: $3\n";
+        syscall::write_file("/tmp/opts.sh", script).unwrap();
+        exec.execute_line("unset OPTIND");
+        let result = exec.execute_line("source /tmp/opts.sh -v -f out.txt notes.md");
+        assert_eq!(result.output, "v\nf out.txt\n4 operands from 4: out.txt");
+        assert!(exec.state.positional.is_empty());
+    }
+
     // ============ Programs ============
 
     #[test]
     fn test_prog_wc() {
         let args: Vec<String> = vec![];
         let stdin = "hello world\nfoo bar baz";
-        let mut stdout = String::new();
+        let mut stdout = Vec::new();
         let mut stderr = String::new();
-        let code = programs::prog_wc(&args, stdin, &mut stdout, &mut stderr);
+        let code = programs::prog_wc(&args, stdin.as_bytes(), &mut stdout, &mut stderr);
+        let stdout = String::from_utf8(stdout).unwrap();
         assert_eq!(code, 0);
         assert!(stdout.contains("2")); // 2 lines
         assert!(stdout.contains("5")); // 5 words
@@ -2191,6 +3711,33 @@ mod tests {
         assert!(!result.output.is_empty());
     }
 
+    #[test]
+    fn test_binary_pipes_and_redirection() {
+        let mut exec = setup_redirect_test();
+        let data = [0x1f, 0x8b, 0x08, 0x00, 0xff, 0xfe, b'\n', 0x80];
+        let fd = syscall::open("/tmp/blob", syscall::OpenFlags::WRITE).unwrap();
+        syscall::write(fd, &data).unwrap();
+        syscall::close(fd).unwrap();
+
+        let result = exec.execute_line("cat /tmp/blob > /tmp/copy");
+        assert_eq!(result.code, 0, "{}", result.error);
+        assert_eq!(programs::read_file_bytes("/tmp/copy").unwrap(), data);
+
+        let result =
+            exec.execute_line("cat < /tmp/blob | tee /tmp/tee | base64 | base64 -d > /tmp/round");
+        assert_eq!(result.code, 0, "{}", result.error);
+        assert_eq!(programs::read_file_bytes("/tmp/tee").unwrap(), data);
+        assert_eq!(programs::read_file_bytes("/tmp/round").unwrap(), data);
+
+        let result = exec.execute_line("cat /tmp/blob | wc -c");
+        assert_eq!(result.output, "8");
+
+        // Text programs and the terminal see invalid UTF-8 replaced
+        let result = exec.execute_line("cat /tmp/blob | head -n 1");
+        assert!(result.output.starts_with('\u{1f}'));
+        assert!(result.output.contains('\u{fffd}'));
+    }
+
     // ============ Glob Pattern Matching ============
 
     #[test]
@@ -2287,6 +3834,134 @@ mod tests {
         assert_eq!(result.output, "hello world");
     }
 
+    #[test]
+    fn test_alias_command_position_only() {
+        let mut exec = Executor::new();
+        exec.execute_line("alias hi='echo hello'");
+
+        assert_eq!(exec.execute_line("echo hi").output, "hi");
+        assert_eq!(exec.execute_line("echo a; hi").output, "a\nhello");
+        assert_eq!(
+            exec.execute_line("echo a && hi there").output,
+            "a\nhello there"
+        );
+        assert_eq!(exec.execute_line("echo 'x | hi'").output, "x | hi");
+        // A quoted command word is never an alias
+        assert_ne!(exec.execute_line("'hi'").code, 0);
+    }
+
+    #[test]
+    fn test_alias_recursion_guard() {
+        let mut exec = Executor::new();
+        exec.execute_line("alias echo='echo say:'");
+        assert_eq!(exec.execute_line("echo hi").output, "say: hi");
+
+        exec.execute_line("alias ping=pong");
+        exec.execute_line("alias pong=ping");
+        let result = exec.execute_line("ping");
+        assert_ne!(result.code, 0);
+
+        // Nested aliases expand, including after separators in a value
+        exec.execute_line("alias greet='echo hello'");
+        exec.execute_line("alias twice='greet; greet'");
+        assert_eq!(exec.execute_line("twice").output, "say: hello\nsay: hello");
+    }
+
+    #[test]
+    fn test_alias_trailing_space() {
+        let mut exec = Executor::new();
+        exec.execute_line("alias greet='echo hello'");
+        exec.execute_line("alias loud='echo '");
+        exec.execute_line("alias quiet='echo'");
+
+        assert_eq!(exec.execute_line("loud greet").output, "echo hello");
+        assert_eq!(exec.execute_line("quiet greet").output, "greet");
+    }
+
+    #[test]
+    fn test_alias_print_roundtrip() {
+        setup_kernel();
+        let mut exec = Executor::new();
+        exec.execute_line("alias ll='ls -l'");
+        exec.execute_line(r#"alias say="echo \"it's here\"""#);
+        assert_eq!(exec.state.get_alias("say"), Some(r#"echo "it's here""#));
+
+        let listing = exec.execute_line("alias -p").output;
+        assert_eq!(exec.execute_line("alias").output, listing);
+        assert!(listing.contains("alias ll='ls -l'"));
+        syscall::write_file("/tmp/aliases", &listing).unwrap();
+
+        let mut fresh = Executor::new();
+        let result = fresh.execute_line("source /tmp/aliases");
+        assert_eq!(result.code, 0, "{}", result.error);
+        assert_eq!(fresh.state.aliases, exec.state.aliases);
+        assert_eq!(fresh.execute_line("say").output, "it's here");
+
+        assert_ne!(exec.execute_line("alias -x").code, 0);
+        assert_ne!(exec.execute_line("alias 'a b=c'").code, 0);
+        assert_ne!(exec.execute_line("source /tmp/missing").code, 0);
+    }
+
+    // ============ History ============
+
+    #[test]
+    fn test_history_expansion() {
+        let mut exec = Executor::new();
+        exec.execute_interactive("echo one two");
+        let result = exec.execute_interactive("echo !!");
+        assert_eq!(result.output, "echo echo one two\necho one two");
+        assert_eq!(exec.execute_interactive("echo !$").output, "echo two\ntwo");
+        assert_eq!(
+            exec.state.history,
+            ["echo one two", "echo echo one two", "echo two"]
+        );
+
+        let result = exec.execute_interactive("!nope");
+        assert_eq!(result.error, "!nope: event not found");
+        assert_eq!(exec.state.history.len(), 3);
+
+        // set +H turns it off
+        exec.execute_interactive("set +H");
+        assert_eq!(exec.execute_interactive("echo !!").output, "!!");
+        exec.execute_interactive("set -o histexpand");
+        assert_eq!(exec.execute_interactive("!-2").output, "echo !!\n!!");
+
+        // Lines run by scripts and traps aren't history
+        exec.execute_line("echo quiet");
+        assert_ne!(exec.state.history.last().unwrap(), "echo quiet");
+    }
+
+    #[test]
+    fn test_fc() {
+        let mut exec = Executor::new();
+        for line in ["echo alpha", "echo beta", "echo gamma"] {
+            exec.execute_interactive(line);
+        }
+
+        let result = exec.execute_interactive("fc -l");
+        assert_eq!(
+            result.output,
+            "    1  echo alpha\n    2  echo beta\n    3  echo gamma"
+        );
+        let result = exec.execute_interactive("fc -lnr 1 2");
+        assert_eq!(result.output, "       echo beta\n       echo alpha");
+        assert_eq!(
+            exec.execute_interactive("fc -l -1").output,
+            "    5  fc -lnr 1 2"
+        );
+
+        // fc -s re-runs, with a substitution, and takes the fc line's place
+        let result = exec.execute_interactive("fc -s beta=delta 2");
+        assert_eq!(result.output, "echo delta\ndelta");
+        assert_eq!(exec.state.history.last().unwrap(), "echo delta");
+        let result = exec.execute_interactive("fc -s 1");
+        assert_eq!(result.output, "echo alpha\nalpha");
+
+        assert_ne!(exec.execute_interactive("fc -s nope").code, 0);
+        assert_ne!(exec.execute_interactive("fc").code, 0);
+        assert_ne!(exec.execute_interactive("fc -q").code, 0);
+    }
+
     // ============ Command Substitution ============
 
     #[test]
@@ -2475,6 +4150,229 @@ mod tests {
         assert!(!result.output.contains("after"));
     }
 
+    // ============ Exit Status and Options ============
+
+    #[test]
+    fn test_status_expansion() {
+        let mut exec = Executor::new();
+        assert_eq!(exec.execute_line("false; echo $?").output, "1");
+        assert_eq!(exec.execute_line("echo $?").output, "0");
+        assert_eq!(
+            exec.execute_line("nonsense; echo status=$?").output,
+            "status=127"
+        );
+        // Single quotes keep it as written, double quotes don't
+        assert_eq!(exec.execute_line("false; echo '$?'").output, "$?");
+        assert_eq!(exec.execute_line("false; echo \"$?\"").output, "1");
+        assert_eq!(exec.execute_line("false; echo $?'$?'").output, "1$?");
+    }
+
+    #[test]
+    fn test_variable_expansion() {
+        let mut exec = Executor::new();
+        exec.execute_line("export NAME=world");
+        assert_eq!(exec.execute_line("echo $NAME").output, "world");
+        assert_eq!(
+            exec.execute_line("echo \"hi ${NAME}s\"").output,
+            "hi worlds"
+        );
+        assert_eq!(exec.execute_line("echo '$NAME'").output, "$NAME");
+        assert_eq!(exec.execute_line("echo x$UNSET.y").output, "x.y");
+        // A `$` that starts no parameter is kept
+        assert_eq!(
+            exec.execute_line("echo \"a$ $% ${a[1]}\"").output,
+            "a$ $% ${a[1]}"
+        );
+    }
+
+    #[test]
+    fn test_pipefail() {
+        let mut exec = Executor::new();
+        assert_eq!(exec.execute_line("false | true").code, 0);
+
+        exec.execute_line("set -o pipefail");
+        assert_eq!(exec.execute_line("false | true").code, 1);
+        assert_eq!(exec.execute_line("false | true; echo $?").output, "1");
+        assert_eq!(exec.execute_line("true | true").code, 0);
+
+        exec.execute_line("set +o pipefail");
+        assert_eq!(exec.execute_line("false | true").code, 0);
+    }
+
+    #[test]
+    fn test_errexit() {
+        let mut exec = Executor::new();
+        exec.execute_line("set -e");
+
+        let result = exec.execute_line("echo one; false; echo two");
+        assert_eq!(result.code, 1);
+        assert_eq!(result.output, "one");
+
+        // Failures that are being tested don't stop the list
+        let result = exec.execute_line("false || echo handled; false && echo no; echo three");
+        assert_eq!(result.output, "handled\nthree");
+
+        // A failing function stops its caller too
+        exec.execute_line("check() { false; echo inside }");
+        let result = exec.execute_line("check; echo after");
+        assert_eq!(result.code, 1);
+        assert!(result.output.is_empty());
+
+        exec.execute_line("set +e");
+        assert_eq!(exec.execute_line("false; echo two").output, "two");
+    }
+
+    #[test]
+    fn test_xtrace() {
+        let mut exec = Executor::new();
+        exec.execute_line("set -x");
+
+        let result = exec.execute_line("false; echo \"a b\" $? | cat");
+        assert_eq!(result.output, "a b 1");
+        assert_eq!(result.error, "+ false\n+ echo 'a b' 1\n+ cat\n");
+
+        let result = exec.execute_line("set +x");
+        assert_eq!(result.error, "+ set +x");
+        assert!(exec.execute_line("echo quiet").error.is_empty());
+    }
+
+    #[test]
+    fn test_trap() {
+        use crate::kernel::signal::Signal;
+        setup_kernel();
+        let mut exec = Executor::new();
+        let me = syscall::getpid().unwrap();
+
+        exec.execute_line("trap 'echo caught; false' INT; trap 'echo bye' EXIT; trap '' HUP");
+        assert_eq!(syscall::siggetmask().unwrap(), 1 << Signal::SIGINT.num());
+        assert!(
+            exec.execute_line("trap")
+                .output
+                .contains("trap -- 'echo bye' EXIT")
+        );
+
+        // The trap runs after the command the signal arrived during, and
+        // leaves $? alone
+        let result = exec.execute_line(&format!("kill -INT {}; echo next $?", me.0));
+        assert_eq!(result.output, "caught\nnext 0");
+        // Ignored signals don't end the shell
+        syscall::kill(me, Signal::SIGHUP).unwrap();
+        assert_eq!(exec.execute_line("echo still here").output, "still here");
+
+        let result = exec.execute_line("exit 3");
+        assert!(result.should_exit);
+        assert_eq!((result.output.as_str(), result.code), ("bye", 3));
+
+        exec.execute_line("trap - INT HUP");
+        assert_eq!(syscall::siggetmask().unwrap(), 0);
+        assert!(exec.state.traps.is_empty());
+    }
+
+    #[test]
+    fn test_flock() {
+        use crate::kernel::flock::LOCK_EX;
+        setup_kernel();
+        let mut exec = Executor::new();
+        let me = syscall::getpid().unwrap();
+
+        let result = exec.execute_line("flock /tmp/job.lock echo 'it ran'");
+        assert_eq!((result.output.as_str(), result.code), ("it ran", 0));
+        // The shell itself holds the lock, so a nested flock gets it too
+        let result =
+            exec.execute_line("flock /tmp/job.lock flock -n /tmp/job.lock -c 'echo nested'");
+        assert_eq!(result.output, "nested");
+
+        // Another process holding the lock makes flock fail
+        let other = syscall::KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            let other = k.spawn_process("cron", None);
+            k.set_current(other);
+            other
+        });
+        let fd = syscall::open("/tmp/job.lock", syscall::OpenFlags::READ).unwrap();
+        syscall::flock(fd, LOCK_EX).unwrap();
+        syscall::KERNEL.with(|k| k.borrow_mut().set_current(me));
+
+        let result = exec.execute_line("flock /tmp/job.lock echo no");
+        assert_eq!(result.code, 1);
+        assert_eq!(result.output, "");
+        assert_eq!(
+            result.error,
+            format!("flock: /tmp/job.lock: locked by process {}", other.0)
+        );
+        let result = exec.execute_line("flock -n -E 75 /tmp/job.lock echo no");
+        assert_eq!((result.code, result.error.as_str()), (75, ""));
+    }
+
+    #[test]
+    fn test_env_command() {
+        setup_kernel();
+        let mut exec = Executor::new();
+        exec.execute_line("export SECRET=x");
+        syscall::setenv("SECRET", "x").unwrap();
+
+        let result = exec.execute_line("env -i GREETING=hi env");
+        assert_eq!(result.output, "GREETING=hi");
+        let result = exec.execute_line("env -i GREETING=hi printenv");
+        assert_eq!(result.output.trim(), "GREETING=hi");
+        let result = exec.execute_line("env -u SECRET env");
+        assert!(!result.output.contains("SECRET"), "{}", result.output);
+        let result = exec.execute_line("env -a SECRET printenv SECRET");
+        assert_eq!(result.output.trim(), "x");
+
+        // The shell and its process get their own environment back
+        assert_eq!(exec.state.get_env("SECRET"), Some("x"));
+        assert!(exec.state.get_env("GREETING").is_none());
+        assert_eq!(syscall::getenv("SECRET"), Ok(Some("x".to_string())));
+        assert_eq!(syscall::getenv("GREETING"), Ok(None));
+    }
+
+    #[test]
+    fn test_osimage_roundtrip() {
+        setup_kernel();
+        let mut exec = Executor::new();
+        exec.execute_line("mkdir -p /tmp/work");
+        exec.execute_line("echo kept > /tmp/work/notes.txt");
+        exec.execute_line("alias ll='ls -l'");
+        exec.execute_line("export EDITOR=edit");
+        exec.execute_line("cd /tmp/work");
+
+        let result = exec.execute_line("osimage export /tmp/backup.axi");
+        assert_eq!(result.code, 0, "{}", result.error);
+        assert!(result.output.ends_with("to /tmp/backup.axi"));
+        let info = exec.execute_line("osimage info /tmp/backup.axi").output;
+        assert!(info.contains("Encrypted: no"));
+        assert!(info.ends_with("Checksum:  ok"));
+
+        exec.execute_line("rm /tmp/work/notes.txt");
+        exec.execute_line("unalias ll");
+        exec.execute_line("unset EDITOR");
+        exec.execute_line("cd /");
+
+        let result = exec.execute_line("osimage import /tmp/backup.axi");
+        assert_eq!(result.code, 0, "{}", result.error);
+        assert_eq!(exec.execute_line("cat notes.txt").output.trim(), "kept");
+        assert_eq!(exec.state.cwd, Path::new("/tmp/work"));
+        assert_eq!(exec.state.get_alias("ll"), Some("ls -l"));
+        assert_eq!(exec.state.get_env("EDITOR"), Some("edit"));
+        // The image was written after its own snapshot was taken
+        assert!(!syscall::exists("/tmp/backup.axi").unwrap());
+
+        let result = exec.execute_line("osimage import /tmp/work/notes.txt");
+        assert_eq!(
+            result.error,
+            "osimage: /tmp/work/notes.txt: not an OS image"
+        );
+
+        // Only root may export or import
+        syscall::KERNEL.with(|k| {
+            k.borrow_mut().current_process_mut().unwrap().euid = crate::kernel::users::Uid(1000)
+        });
+        let result = exec.execute_line("osimage export /tmp/other.axi");
+        assert_eq!(result.code, 1);
+        assert!(result.error.contains("permission denied"));
+    }
+
     // ============ Shell Functions ============
 
     #[test]
@@ -2707,6 +4605,71 @@ mod tests {
         assert_eq!(result.output.trim(), "from_procsub");
     }
 
+    #[test]
+    fn test_time_keyword() {
+        let mut exec = setup_redirect_test();
+        syscall::write_file("/tmp/data", "b\na\n").unwrap();
+
+        let result = exec.execute_line("time cat /tmp/data | sort");
+        assert_eq!(result.output.trim_end(), "a\nb");
+        let lines: Vec<&str> = result.error.lines().collect();
+        assert_eq!(lines[0], "");
+        assert!(lines[1].starts_with("real\t0m"));
+        assert!(lines[2].starts_with("user\t0m"));
+        assert!(lines[3].starts_with("sys\t0m"));
+        assert!(lines[4].starts_with("maxmem\t"));
+        let syscalls: u64 = lines[5]
+            .strip_prefix("syscalls\t")
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(syscalls >= 3, "cat opens, reads and closes");
+
+        // The status is the pipeline's; other pipelines on the line aren't timed
+        let result = exec.execute_line("time false || echo no");
+        assert_eq!(result.output.trim(), "no");
+        assert_eq!(result.error.matches("real").count(), 1);
+
+        // `-p` is the POSIX format, in seconds
+        let result = exec.execute_line("time -p cat /tmp/data");
+        assert_eq!(result.code, 0);
+        assert_eq!(result.output, "b\na\n");
+        let lines: Vec<&str> = result.error.lines().collect();
+        assert_eq!(lines.len(), 3);
+        for (line, name) in lines.iter().zip(["real ", "user ", "sys "]) {
+            let secs = line.strip_prefix(name).unwrap();
+            assert!(secs.parse::<f64>().is_ok(), "{}", line);
+        }
+
+        assert_eq!(format_duration(83_250.4), "1m23.250s");
+    }
+
+    #[test]
+    fn test_process_substitution_files() {
+        let mut exec = setup_redirect_test();
+        syscall::write_file("/tmp/a", "3\n1\n2\n").unwrap();
+        syscall::write_file("/tmp/b", "2\n3\n1\n").unwrap();
+
+        // Each side is a file the outer command can read
+        let result = exec.execute_line("diff <(sort /tmp/a) <(sort /tmp/b)");
+        assert_eq!(result.code, 0, "{}", result.error);
+        assert_eq!(result.output, "");
+        let result = exec.execute_line("cat <(echo x) <(echo y)");
+        assert_eq!(result.output, "x\ny\n");
+
+        // What is written to >(cmd) is fed to cmd afterwards
+        let result = exec.execute_line("echo written > >(tr w W)");
+        assert_eq!(result.output.trim_end(), "Written");
+
+        // The temp files are gone once the line is done
+        assert!(
+            syscall::readdir("/tmp")
+                .unwrap()
+                .iter()
+                .all(|name| !name.starts_with("procsub_"))
+        );
+    }
+
     #[test]
     fn test_process_substitution_mixed_with_regular_args() {
         let mut exec = setup_redirect_test();
@@ -2730,4 +4693,288 @@ mod tests {
         assert_eq!(id2, 2);
         assert_eq!(id3, 3);
     }
+
+    #[test]
+    fn test_script_run_file_and_inline() {
+        let mut exec = setup_redirect_test();
+        syscall::write_file(
+            "/tmp/greet.sc",
+            "let who = args[0]\nsetenv(\"GREETED\", who)\nprint(\"hello\", who)\n",
+        )
+        .unwrap();
+
+        let result = exec.execute_line("script run /tmp/greet.sc world");
+        assert_eq!(result.code, 0, "{}", result.error);
+        assert_eq!(result.output, "hello world\n");
+        assert_eq!(exec.state.get_env("GREETED"), Some("world"));
+
+        // exec() runs through the shell, and output can be redirected
+        let result =
+            exec.execute_line("script -e 'print(trim(exec(\"echo hi\").out))' > /tmp/out.txt");
+        assert_eq!(result.code, 0, "{}", result.error);
+        assert_eq!(syscall::read_file("/tmp/out.txt").unwrap(), "hi\n");
+
+        let result = exec.execute_line("echo 'print(1 +)' | script");
+        assert_eq!(result.code, 1);
+        assert!(result.error.contains("script: <stdin>: line 1:"));
+
+        let result = exec.execute_line("script --help");
+        assert_eq!(result.code, 0);
+        assert!(
+            result
+                .output
+                .starts_with("Usage: script [-e CODE] [FILE] [ARG]...")
+        );
+        let result = exec.execute_line("script -e");
+        assert_eq!(result.code, 2);
+        assert!(result.error.contains("option -e needs a value"));
+    }
+
+    #[test]
+    fn test_script_repl() {
+        let mut exec = setup_redirect_test();
+        assert_eq!(exec.script_prompt(), None);
+
+        let result = exec.execute_line("script");
+        assert!(result.output.contains("REPL"));
+        assert_eq!(exec.script_prompt(), Some("script> "));
+
+        assert_eq!(exec.execute_line("let xs = [1, 2]").output, "");
+        // An open block continues onto the next line
+        exec.execute_line("fn total() {");
+        assert_eq!(exec.script_prompt(), Some("... "));
+        exec.execute_line("return xs[0] + xs[1] }");
+        assert_eq!(exec.execute_line("total()").output, "3\n");
+        assert_eq!(exec.execute_line("\"s\"").output, "\"s\"\n");
+
+        let result = exec.execute_line("nope()");
+        assert_eq!(result.code, 1);
+        assert!(result.error.contains("undefined variable 'nope'"));
+
+        exec.execute_line(".exit");
+        assert_eq!(exec.script_prompt(), None);
+        assert_eq!(exec.execute_line("echo back").output.trim(), "back");
+    }
+
+    #[test]
+    fn test_login_shells() {
+        syscall::KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = syscall::Kernel::new();
+            let pid = k.spawn_login_shell(
+                "root",
+                Uid::ROOT,
+                crate::kernel::Gid::ROOT,
+                "/root",
+                "/bin/sh",
+                None,
+            );
+            k.set_current(pid);
+        });
+        let mut exec = Executor::new();
+        let result = exec.execute_line("useradd -s /bin/script alice");
+        assert_eq!(result.code, 0, "{}", result.error);
+        exec.execute_line("mkdir /home/guest/notes");
+        let result = exec.execute_line("useradd -s /bin/zsh bob");
+        assert!(
+            result
+                .error
+                .contains("/bin/zsh is not listed in /etc/shells")
+        );
+
+        // The restricted shell stays in the home directory, with PATH fixed
+        exec.execute_interactive("login guest");
+        assert_eq!(exec.state.cwd, Path::new("/home/guest"));
+        assert_eq!(exec.execute_interactive("cd notes").code, 0);
+        assert_eq!(exec.execute_interactive("cd ..").code, 0);
+        let result = exec.execute_interactive("cd ..");
+        assert!(result.error.contains("cd: /home: restricted"));
+        assert!(exec.execute_interactive("cd /etc").code != 0);
+        assert_eq!(exec.state.cwd, Path::new("/home/guest"));
+        let result = exec.execute_interactive("export PATH=/tmp");
+        assert!(result.error.contains("export: PATH: restricted"));
+        assert!(exec.execute_interactive("unset SHELL").code != 0);
+        assert!(exec.execute_interactive("env PATH=/tmp true").code != 0);
+        // ...whether changed from a script or the script REPL
+        exec.execute_interactive("script");
+        let result = exec.execute_interactive("setenv(\"PATH\", \"/tmp\")");
+        assert!(result.error.contains("export: PATH: restricted"));
+        exec.execute_interactive(".exit");
+        assert_ne!(exec.state.get_env("PATH"), Some("/tmp"));
+        assert!(exec.chdir("/etc").error.contains("restricted"));
+        assert_eq!(exec.state.cwd, Path::new("/home/guest"));
+        assert_eq!(exec.execute_interactive("export EDITOR=vi").code, 0);
+        exec.execute_interactive("logout");
+
+        // A script login shell is the REPL, and leaving it logs out
+        let result = exec.execute_interactive("login alice");
+        assert!(result.output.contains("REPL"));
+        assert_eq!(exec.script_prompt(), Some("script> "));
+        assert_eq!(exec.execute_interactive("1 + 2").output, "3\n");
+        let result = exec.execute_interactive(".exit");
+        assert!(result.output.contains("Session"));
+        assert_eq!(exec.script_prompt(), None);
+        assert_eq!(syscall::getuid().unwrap(), Uid::ROOT);
+
+        // A shell dropped from /etc/shells can't be logged in with
+        exec.execute_line("echo /bin/sh > /etc/shells");
+        let result = exec.execute_interactive("login guest");
+        assert!(result.error.contains("/bin/rsh is not listed"));
+        assert_eq!(syscall::getuid().unwrap(), Uid::ROOT);
+    }
+
+    // ============ Command Journal ============
+
+    #[test]
+    fn test_journal_undo_last() {
+        let mut exec = setup_redirect_test();
+        exec.execute_line("echo one > /tmp/a.txt");
+        exec.execute_line("echo keep > /tmp/b.txt");
+        exec.execute_line("rm /tmp/a.txt && echo two > /tmp/b.txt && mkdir /tmp/d");
+        exec.execute_line("ls /tmp");
+
+        let shown = exec.execute_line("journal 3").output;
+        let lines: Vec<&str> = shown.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[2].contains("* "));
+        assert!(
+            lines[2]
+                .ends_with("+1 ~1 -1     rm /tmp/a.txt && echo two > /tmp/b.txt && mkdir /tmp/d")
+        );
+        assert!(lines[3].ends_with("-            ls /tmp"));
+
+        let seq: u64 = lines[2].split('*').next().unwrap().trim().parse().unwrap();
+        let diff = exec.execute_line(&format!("journal diff {}", seq)).output;
+        assert!(diff.contains("\n- /tmp/a.txt"));
+        assert!(diff.contains("\n~ /tmp/b.txt"));
+        assert!(diff.contains("\n+ /tmp/d"));
+
+        let result = exec.execute_line("undo-last");
+        assert_eq!(result.code, 0, "{}", result.error);
+        assert!(result.output.contains("restored /tmp/a.txt"));
+        assert!(result.output.contains("removed /tmp/d"));
+        assert_eq!(exec.execute_line("cat /tmp/a.txt").output.trim(), "one");
+        assert_eq!(exec.execute_line("cat /tmp/b.txt").output.trim(), "keep");
+        assert!(!syscall::exists("/tmp/d").unwrap());
+
+        // The undo itself is journaled but can't be undone; the next
+        // undo-last reaches the command before, which only created a file
+        let result = exec.execute_line("undo-last");
+        assert_eq!(result.code, 1);
+        assert!(result.error.contains("nothing to undo"));
+        assert!(exec.execute_line("journal").output.contains("undo-last"));
+    }
+
+    #[test]
+    fn test_journal_undo_conflict_and_replay() {
+        let mut exec = setup_redirect_test();
+        exec.execute_line("echo v1 > /tmp/f.txt");
+        exec.execute_line("echo v2 > /tmp/f.txt");
+        let seq = journal::read_entries().last().unwrap().seq;
+
+        // Edit the file behind the journal's back
+        syscall::KERNEL.with(|k| {
+            crate::vfs::write_string(k.borrow_mut().vfs_mut(), "/tmp/f.txt", "v3").unwrap();
+        });
+        let result = exec.execute_line("undo-last");
+        assert!(result.error.contains("/tmp/f.txt: changed since"));
+        let result = exec.execute_line("undo-last -f");
+        assert_eq!(result.code, 0, "{}", result.error);
+        assert_eq!(exec.execute_line("cat /tmp/f.txt").output.trim(), "v1");
+
+        // Replay runs the command again from its recorded cwd
+        exec.execute_line("cd /");
+        let result = exec.execute_line(&format!("journal replay {}", seq));
+        assert_eq!(result.code, 0, "{}", result.error);
+        assert!(result.output.starts_with("+ echo v2 > /tmp/f.txt"));
+        assert_eq!(exec.execute_line("cat /tmp/f.txt").output.trim(), "v2");
+        assert_eq!(exec.state.cwd, std::path::PathBuf::from("/"));
+
+        // The whole replay is one undoable entry
+        assert_eq!(exec.execute_line("undo-last").code, 0);
+        assert_eq!(exec.execute_line("cat /tmp/f.txt").output.trim(), "v1");
+
+        assert!(exec.execute_line("journal replay 9999").code != 0);
+        assert!(exec.execute_line("journal diff x").code != 0);
+        assert!(exec.execute_line("journal bogus args here").code != 0);
+    }
+
+    // ============ Tutorial ============
+
+    #[test]
+    fn test_tutorial_follows_commands() {
+        setup_kernel();
+        let mut exec = Executor::new();
+        exec.execute_line("cd /home/user");
+
+        let result = exec.execute_line("tutorial shell");
+        assert_eq!(result.code, 0, "{}", result.error);
+        assert!(result.output.contains("Step 1/7"));
+
+        let result = exec.execute_line("pwd");
+        assert!(
+            result
+                .output
+                .starts_with("/home/user\n✓ Print the directory")
+        );
+        exec.execute_line("ls");
+        exec.execute_line("mkdir projects");
+        let result = exec.execute_line("cd projects");
+        assert!(result.output.contains("Step 5/7"));
+
+        assert!(
+            exec.execute_line("tutorial hint")
+                .output
+                .contains("echo hello")
+        );
+        let result = exec.execute_line("echo hello > hello.txt");
+        assert!(result.output.contains("Step 6/7"));
+        let result = exec.execute_line("tutorial stop");
+        assert!(result.output.contains("Left lesson 'shell'"));
+        assert!(
+            !exec
+                .execute_line("cat hello.txt | wc -w")
+                .output
+                .contains('✓')
+        );
+    }
+
+    // ============ Usage statistics ============
+
+    #[test]
+    fn test_stats_recorded_per_program() {
+        setup_kernel();
+        let mut exec = Executor::new();
+        exec.execute_line("ls /");
+        exec.execute_line("ls / | wc -l");
+        exec.execute_line("cat /nope");
+        exec.execute_line("nosuchcmd --secret-arg");
+
+        let db = stats::load();
+        assert_eq!(db.commands["ls"].runs, 2);
+        assert_eq!(db.commands["wc"].runs, 1);
+        assert_eq!(db.commands["cat"].failures, 1);
+        assert!(!db.commands.contains_key("nosuchcmd"));
+        assert!(
+            !syscall::read_file(stats::STATS_PATH)
+                .unwrap()
+                .contains("secret")
+        );
+
+        let top = exec.execute_line("stats top 1").output;
+        assert!(top.lines().nth(1).unwrap().starts_with("ls"));
+        assert!(exec.execute_line("stats errors").output.contains("cat"));
+        assert!(!stats::load().commands.contains_key("stats"));
+
+        let result = exec.execute_line("stats purge cat");
+        assert_eq!(result.code, 0, "{}", result.error);
+        assert!(!stats::load().commands.contains_key("cat"));
+        exec.execute_line("stats purge");
+        assert!(!syscall::exists(stats::STATS_PATH).unwrap());
+        assert!(
+            exec.execute_line("stats")
+                .output
+                .contains("No command statistics")
+        );
+    }
 }
@@ -0,0 +1,144 @@
+//! Help topics
+//!
+//! `help TOPIC` groups related commands under a short description, with
+//! each command's one-line summary taken from its manual page. `help
+//! COMMAND` points at the manual page for a single command.
+
+use super::programs::{man_summary, manual};
+
+/// A help topic: name, description and related commands
+pub struct Topic {
+    pub name: &'static str,
+    pub summary: &'static str,
+    pub commands: &'static [&'static str],
+}
+
+/// Help topics, in the order `help topics` lists them
+pub const TOPICS: &[Topic] = &[
+    Topic {
+        name: "shell",
+        summary: "Running commands, pipes, redirection, variables and aliases",
+        commands: &[
+            "echo", "cd", "pwd", "type", "which", "test", "expr", "printf", "xargs", "time",
+        ],
+    },
+    Topic {
+        name: "files",
+        summary: "Creating, copying, moving and finding files",
+        commands: &[
+            "ls", "cat", "cp", "mv", "rm", "mkdir", "touch", "ln", "find", "tree", "du", "df",
+            "quota", "getfattr", "setfattr", "mktemp", "zip", "unzip", "crypt", "qr",
+        ],
+    },
+    Topic {
+        name: "text",
+        summary: "Searching, sorting and transforming text",
+        commands: &[
+            "grep", "sort", "uniq", "wc", "head", "tail", "cut", "tr", "paste", "nl", "diff",
+            "merge3", "comm", "spell", "unicode", "shuf",
+        ],
+    },
+    Topic {
+        name: "editor",
+        summary: "Editing files with the built-in editor (Ctrl+S saves, Ctrl+Q quits)",
+        commands: &["edit", "chars"],
+    },
+    Topic {
+        name: "processes",
+        summary: "Jobs, processes and tracing",
+        commands: &["ps", "kill", "jobs", "fg", "bg", "strace", "uptime", "free"],
+    },
+    Topic {
+        name: "users",
+        summary: "Users, groups, passwords and sessions",
+        commands: &[
+            "id", "whoami", "groups", "passwd", "su", "sudo", "login", "lock",
+        ],
+    },
+    Topic {
+        name: "desktop",
+        summary: "Terminal profiles, themes and the session",
+        commands: &["profile", "widgets", "clear", "stty", "tty", "lock"],
+    },
+    Topic {
+        name: "system",
+        summary: "Host name, time, mounts and persistence",
+        commands: &[
+            "hostname", "uname", "date", "cal", "mount", "exportfs", "save", "fsck", "snapshot",
+            "vfs", "pkg", "update", "stats", "dmesg", "uuidgen",
+        ],
+    },
+    Topic {
+        name: "tutorial",
+        summary: "Guided lessons: run 'tutorial' to list them, 'tutorial shell' to start",
+        commands: &["tutorial", "man"],
+    },
+];
+
+/// Look up a topic by name
+pub fn topic(name: &str) -> Option<&'static Topic> {
+    TOPICS.iter().find(|t| t.name == name)
+}
+
+/// The list of topics for `help topics`
+pub fn list_topics() -> String {
+    let mut out = String::from("Help topics:\n");
+    for t in TOPICS {
+        out.push_str(&format!("  {:<10} {}\n", t.name, t.summary));
+    }
+    out.push_str("\nType 'help TOPIC' for a topic or 'man COMMAND' for a command.");
+    out
+}
+
+/// Help for a topic or a command
+pub fn lookup(name: &str) -> Result<String, String> {
+    if let Some(t) = topic(name) {
+        let mut out = format!("{} - {}\n\n", t.name, t.summary);
+        for command in t.commands {
+            let summary = man_summary(command).unwrap_or("");
+            out.push_str(&format!("  {:<10} {}\n", command, summary));
+        }
+        let manuals: Vec<&str> = t
+            .commands
+            .iter()
+            .copied()
+            .filter(|c| manual(c).is_some())
+            .collect();
+        if !manuals.is_empty() {
+            out.push_str(&format!("\nManual pages: man {}", manuals.join(", man ")));
+        } else {
+            out.push_str("\nType 'COMMAND --help' for usage.");
+        }
+        return Ok(out);
+    }
+    if let Some(summary) = man_summary(name) {
+        return Ok(format!(
+            "{} - {}\nSee 'man {}' for details.",
+            name, summary, name
+        ));
+    }
+    Err(format!(
+        "help: no help topics match '{}'. Try 'help topics' or '{} --help'.",
+        name, name
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        let files = lookup("files").unwrap();
+        assert!(files.starts_with("files - "));
+        assert!(files.contains("ls         list directory contents"));
+        assert!(files.contains("Manual pages: man ls, man cat"));
+
+        assert_eq!(
+            lookup("ls").unwrap(),
+            "ls - list directory contents\nSee 'man ls' for details."
+        );
+        assert!(lookup("nonsense").is_err());
+        assert!(list_topics().contains("tutorial"));
+    }
+}
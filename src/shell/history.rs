@@ -0,0 +1,354 @@
+//! Command history: bash-style history expansion and `fc` ranges
+//!
+//! History expansion rewrites a typed line before it is parsed:
+//!
+//! | Form | Expands to |
+//! |------|------------|
+//! | `!!` | The previous command |
+//! | `!N` / `!-N` | Command N / the Nth command back |
+//! | `!STRING` | The newest command starting with STRING |
+//! | `!?STRING?` | The newest command containing STRING |
+//! | `!$`, `!^`, `!*` | Last word, first argument, all arguments of the previous command |
+//!
+//! An event may be followed by a word designator: `:N`, `:^`, `:$` or
+//! `:*`, as in `!grep:$`. A `!` before a blank, `=`, `(`, `"` or the end
+//! of the line stays as it is, as does anything in single quotes (a `'`
+//! inside double quotes opens none); `\!` is a literal `!`. Commands are
+//! numbered from 1, oldest first.
+
+/// Maximum number of commands kept
+pub const MAX_HISTORY: usize = 1000;
+
+/// Expand the history references in `line`
+///
+/// Returns `None` when there are none, so the caller can tell whether to
+/// show the expanded line, and an error naming the reference when an
+/// event or word doesn't exist.
+pub fn expand(line: &str, history: &[String]) -> Result<Option<String>, String> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut out = String::with_capacity(line.len());
+    let mut changed = false;
+    let mut in_single = false;
+    let mut in_double = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            // A quote inside the other kind is an ordinary character
+            '\'' if !in_double => in_single = !in_single,
+            '"' if !in_single => in_double = !in_double,
+            '\\' if in_double && chars.get(i + 1) == Some(&'"') => {
+                out.push_str("\\\"");
+                i += 2;
+                continue;
+            }
+            '\\' if !in_single && chars.get(i + 1) == Some(&'!') => {
+                out.push('!');
+                changed = true;
+                i += 2;
+                continue;
+            }
+            '!' if !in_single => {
+                let next = chars.get(i + 1).copied();
+                if next.is_some_and(|n| !n.is_whitespace() && !matches!(n, '=' | '(' | '"')) {
+                    let (text, used) = reference(&chars[i..], history)?;
+                    out.push_str(&text);
+                    changed = true;
+                    i += used;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        out.push(c);
+        i += 1;
+    }
+
+    Ok(changed.then_some(out))
+}
+
+/// Expand the reference at the start of `chars` (which begins with `!`),
+/// returning the text and the number of characters it took up
+fn reference(chars: &[char], history: &[String]) -> Result<(String, usize), String> {
+    let spelled = |used: usize| chars[..used].iter().collect::<String>();
+    let not_found = |used: usize| format!("{}: event not found", spelled(used));
+
+    // Shorthands for words of the previous command
+    if let Some(&word @ ('$' | '^' | '*')) = chars.get(1) {
+        let command = history.last().ok_or_else(|| not_found(2))?;
+        let text = select_words(command, word_range(word))
+            .ok_or_else(|| format!("{}: bad word specifier", spelled(2)))?;
+        return Ok((text, 2));
+    }
+
+    let (command, mut used) = event(chars, history).map_err(not_found)?;
+
+    // Optional word designator
+    if chars.get(used) == Some(&':') {
+        let start = used;
+        let designator = match chars.get(used + 1) {
+            Some(&d @ ('$' | '^' | '*')) => {
+                used += 2;
+                Some(word_range(d))
+            }
+            Some(d) if d.is_ascii_digit() => {
+                let digits = chars[used + 1..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_digit())
+                    .count();
+                let n: String = chars[used + 1..used + 1 + digits].iter().collect();
+                used += 1 + digits;
+                n.parse::<usize>().ok().map(|n| (n, Some(n)))
+            }
+            // A lone `:` isn't a designator: `!!:` keeps the colon
+            _ => None,
+        };
+        if let Some(range) = designator {
+            let text = select_words(command, range)
+                .ok_or_else(|| format!("{}: bad word specifier", spelled(used)))?;
+            return Ok((text, used));
+        }
+        used = start;
+    }
+    Ok((command.clone(), used))
+}
+
+/// Find the event `chars` names, and the characters it took up
+///
+/// Fails with the number of characters of the reference not found.
+fn event<'h>(chars: &[char], history: &'h [String]) -> Result<(&'h String, usize), usize> {
+    match chars.get(1) {
+        Some('!') => history.last().map(|c| (c, 2)).ok_or(2),
+        Some('?') => {
+            let len = chars[2..].iter().take_while(|&&c| c != '?').count();
+            let needle: String = chars[2..2 + len].iter().collect();
+            // The closing `?` may be left out at the end of the line
+            let used = (2 + len + 1).min(chars.len());
+            history
+                .iter()
+                .rev()
+                .find(|c| !needle.is_empty() && c.contains(&needle))
+                .map(|c| (c, used))
+                .ok_or(used)
+        }
+        Some(c) if c.is_ascii_digit() || *c == '-' => {
+            let negative = *c == '-';
+            let start = if negative { 2 } else { 1 };
+            let digits = chars[start..]
+                .iter()
+                .take_while(|c| c.is_ascii_digit())
+                .count();
+            let used = start + digits;
+            let n: usize = chars[start..used]
+                .iter()
+                .collect::<String>()
+                .parse()
+                .map_err(|_| used)?;
+            lookup(history, if negative { -(n as i64) } else { n as i64 })
+                .map(|c| (c, used))
+                .ok_or(used)
+        }
+        _ => {
+            let len = chars[1..]
+                .iter()
+                .take_while(|&&c| !c.is_whitespace() && !matches!(c, ':' | ';' | '|' | '&'))
+                .count();
+            let prefix: String = chars[1..1 + len].iter().collect();
+            let used = 1 + len;
+            history
+                .iter()
+                .rev()
+                .find(|c| c.starts_with(&prefix))
+                .map(|c| (c, used))
+                .ok_or(used)
+        }
+    }
+}
+
+/// Command `n` (from 1), or the `-n`th back for negative `n`
+pub fn lookup(history: &[String], n: i64) -> Option<&String> {
+    let index = match n {
+        0 => return None,
+        n if n < 0 => history.len().checked_sub(n.unsigned_abs() as usize)?,
+        n => n as usize - 1,
+    };
+    history.get(index)
+}
+
+/// Word range for a `$`, `^` or `*` designator: first and last word,
+/// `None` for the last word of the command
+fn word_range(designator: char) -> (usize, Option<usize>) {
+    match designator {
+        '^' => (1, Some(1)),
+        '*' => (1, None),
+        _ => (usize::MAX, None),
+    }
+}
+
+/// Words `first..=last` of `command` joined by spaces; `usize::MAX` as
+/// `first` means the last word
+fn select_words(command: &str, (first, last): (usize, Option<usize>)) -> Option<String> {
+    let words = split_words(command);
+    if first == usize::MAX {
+        return words.last().cloned();
+    }
+    let last = last.unwrap_or(words.len().saturating_sub(1));
+    if first > last {
+        // `*` of a command without arguments is empty
+        return (last + 1 == first).then(String::new);
+    }
+    words.get(first..=last).map(|w| w.join(" "))
+}
+
+/// Split a command line into words, keeping quotes with the words they are
+/// part of so they expand the way they were typed
+fn split_words(command: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quote = None;
+    for c in command.chars() {
+        match (c, quote) {
+            ('\'' | '"', None) => {
+                quote = Some(c);
+                word.push(c);
+            }
+            (c, Some(q)) if c == q => {
+                quote = None;
+                word.push(c);
+            }
+            (c, None) if c.is_whitespace() => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            _ => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// Resolve an `fc` FIRST or LAST operand to an index into `history`: a
+/// command number, a negative offset from the end, or the newest command
+/// starting with a string
+pub fn fc_index(history: &[String], spec: &str) -> Option<usize> {
+    match spec.parse::<i64>() {
+        Ok(n) if n < 0 => history.len().checked_sub(n.unsigned_abs() as usize),
+        Ok(n) => {
+            // Like other shells, a number past either end means that end
+            let n = usize::try_from(n).ok()?;
+            (!history.is_empty()).then(|| n.clamp(1, history.len()) - 1)
+        }
+        Err(_) => history.iter().rposition(|c| c.starts_with(spec)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history() -> Vec<String> {
+        [
+            "ls -l /etc",
+            "grep -n root /etc/passwd",
+            "cat 'my notes.txt' todo",
+            "echo done",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+    }
+
+    fn expanded(line: &str) -> Result<Option<String>, String> {
+        expand(line, &history())
+    }
+
+    #[test]
+    fn test_expand_events() {
+        assert_eq!(expanded("!!"), Ok(Some("echo done".into())));
+        assert_eq!(expanded("sudo !!"), Ok(Some("sudo echo done".into())));
+        assert_eq!(expanded("!1"), Ok(Some("ls -l /etc".into())));
+        assert_eq!(expanded("!-2"), Ok(Some("cat 'my notes.txt' todo".into())));
+        assert_eq!(
+            expanded("!grep | wc -l"),
+            Ok(Some("grep -n root /etc/passwd | wc -l".into()))
+        );
+        assert_eq!(
+            expanded("!?passwd?"),
+            Ok(Some("grep -n root /etc/passwd".into()))
+        );
+        assert_eq!(
+            expanded("!?notes"),
+            Ok(Some("cat 'my notes.txt' todo".into()))
+        );
+    }
+
+    #[test]
+    fn test_expand_words() {
+        assert_eq!(expanded("vi !$"), Ok(Some("vi done".into())));
+        assert_eq!(expanded("echo !^"), Ok(Some("echo done".into())));
+        assert_eq!(
+            expanded("less !grep:$"),
+            Ok(Some("less /etc/passwd".into()))
+        );
+        assert_eq!(expanded("rm !-2:1"), Ok(Some("rm 'my notes.txt'".into())));
+        assert_eq!(
+            expanded("echo !-2:*"),
+            Ok(Some("echo 'my notes.txt' todo".into()))
+        );
+        assert_eq!(expanded("!!:0"), Ok(Some("echo".into())));
+        assert_eq!(expanded("!!:7"), Err("!!:7: bad word specifier".into()));
+    }
+
+    #[test]
+    fn test_expand_leaves_literals() {
+        assert_eq!(expanded("echo hi"), Ok(None));
+        assert_eq!(expanded("echo hi!"), Ok(None));
+        assert_eq!(expanded("test ! -f x"), Ok(None));
+        assert_eq!(expanded("[ a != b ]"), Ok(None));
+        assert_eq!(expanded("echo '!!'"), Ok(None));
+        assert_eq!(expanded("echo \"hi!\""), Ok(None));
+        assert_eq!(expanded("echo \\!!"), Ok(Some("echo !!".into())));
+        assert_eq!(
+            expanded("echo \"!!\""),
+            Ok(Some("echo \"echo done\"".into()))
+        );
+        assert_eq!(
+            expanded("echo \"it's !!\""),
+            Ok(Some("echo \"it's echo done\"".into()))
+        );
+        assert_eq!(
+            expanded("echo '\"' !!"),
+            Ok(Some("echo '\"' echo done".into()))
+        );
+        assert_eq!(expanded("echo '\"!!\"'"), Ok(None));
+        assert_eq!(
+            expanded("echo \"\\\"'\" !!"),
+            Ok(Some("echo \"\\\"'\" echo done".into()))
+        );
+    }
+
+    #[test]
+    fn test_expand_errors() {
+        assert_eq!(expanded("!nope"), Err("!nope: event not found".into()));
+        assert_eq!(expanded("!9"), Err("!9: event not found".into()));
+        assert_eq!(expanded("!-9"), Err("!-9: event not found".into()));
+        assert_eq!(expand("!!", &[]), Err("!!: event not found".into()));
+        assert_eq!(expand("!$", &[]), Err("!$: event not found".into()));
+    }
+
+    #[test]
+    fn test_fc_index() {
+        let h = history();
+        assert_eq!(fc_index(&h, "2"), Some(1));
+        assert_eq!(fc_index(&h, "-1"), Some(3));
+        assert_eq!(fc_index(&h, "-9"), None);
+        assert_eq!(fc_index(&h, "99"), Some(3));
+        assert_eq!(fc_index(&h, "cat"), Some(2));
+        assert_eq!(fc_index(&h, "nope"), None);
+        assert_eq!(fc_index(&[], "1"), None);
+    }
+}
@@ -0,0 +1,599 @@
+//! Content indexing
+//!
+//! The indexer keeps an inverted index of the filesystem in [`INDEX_PATH`]:
+//! every path for `locate`, and for each word in a text file the lines it
+//! is on, for `search`. Both answer from the index instead of walking the
+//! tree and reading every file.
+//!
+//! The index is kept current from the VFS change feed
+//! ([`crate::vfs::changelog`]): an update looks only at the paths changed
+//! since the last one, and only reads files whose fingerprint differs from
+//! the one indexed. When the feed can't say what changed - after boot, or
+//! once too much has - the update compares fingerprints over the whole
+//! tree, which still only rereads the files that changed. The platform
+//! calls [`tick`] from an idle task to update in the background; `locate`
+//! and `search` update first, so they never answer from a stale index.
+//! Under memory pressure [`trim`] drops the in-memory copy; the next
+//! update reads it back.
+//!
+//! The index is built as root but answers as the caller: `locate` leaves
+//! out paths in directories the caller can't list, and `search` reads each
+//! hit's lines with the caller's permissions.
+
+use super::setup::as_root;
+use crate::kernel::pressure::PressureLevel;
+use crate::kernel::syscall;
+use crate::vfs::ChangeCursor;
+use crate::vfs::changelog::covers;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Where the indexer keeps its state
+pub const STATE_DIR: &str = "/var/lib/index";
+
+/// The persisted index
+pub const INDEX_PATH: &str = "/var/lib/index/db";
+
+/// Larger files are listed by name only
+pub const MAX_TEXT_SIZE: u64 = 1024 * 1024;
+
+/// How often [`tick`] updates, in milliseconds
+const UPDATE_INTERVAL: f64 = 15_000.0;
+
+/// Words shorter or longer than this aren't indexed
+const MIN_WORD: usize = 2;
+const MAX_WORD: usize = 64;
+
+/// An indexed path
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct FileEntry {
+    fingerprint: u64,
+    /// Words it contains, to drop its postings when it changes
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    words: Vec<String>,
+}
+
+/// Paths and the words in them
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchIndex {
+    files: BTreeMap<String, FileEntry>,
+    /// Word -> path -> lines (from 1) it is on
+    terms: BTreeMap<String, BTreeMap<String, Vec<u32>>>,
+}
+
+/// What an update did
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UpdateStats {
+    /// Paths added or reindexed
+    pub indexed: usize,
+    pub removed: usize,
+}
+
+impl UpdateStats {
+    pub fn is_empty(&self) -> bool {
+        self.indexed == 0 && self.removed == 0
+    }
+}
+
+/// A file matching a search, best first
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    pub path: String,
+    pub score: f64,
+    /// Lines holding any of the words
+    pub lines: Vec<u32>,
+}
+
+/// What a query asks of the paths it matches, from its `path:DIR` and
+/// `ext:EXT` terms
+///
+/// A path has to be under one of the directories and have one of the
+/// extensions; with none of a kind, any will do.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PathFilter {
+    pub dirs: Vec<String>,
+    /// Lowercased, without the dot
+    pub exts: Vec<String>,
+}
+
+impl PathFilter {
+    /// Take the filters out of `terms`, returning the rest; `resolve`
+    /// makes each directory absolute. `ext:` takes several extensions
+    /// separated by commas, as in `ext:md,txt`.
+    pub fn split<'a>(terms: &[&'a str], resolve: impl Fn(&str) -> String) -> (Self, Vec<&'a str>) {
+        let mut filter = Self::default();
+        let mut rest = Vec::new();
+        for &term in terms {
+            if let Some(dir) = term.strip_prefix("path:").filter(|d| !d.is_empty()) {
+                filter.dirs.push(resolve(dir));
+            } else if let Some(exts) = term.strip_prefix("ext:").filter(|e| !e.is_empty()) {
+                filter.exts.extend(
+                    exts.split(',')
+                        .map(|e| e.trim_start_matches('.').to_lowercase())
+                        .filter(|e| !e.is_empty()),
+                );
+            } else {
+                rest.push(term);
+            }
+        }
+        (filter, rest)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dirs.is_empty() && self.exts.is_empty()
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        let name = &path[path.rfind('/').map_or(0, |i| i + 1)..];
+        let ext = name
+            .rfind('.')
+            .filter(|&i| i > 0)
+            .map(|i| name[i + 1..].to_lowercase());
+        (self.dirs.is_empty() || self.dirs.iter().any(|dir| covers(dir, path)))
+            && (self.exts.is_empty() || ext.is_some_and(|ext| self.exts.contains(&ext)))
+    }
+}
+
+/// The indexable words of `text`, lowercased
+pub fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|w| (MIN_WORD..=MAX_WORD).contains(&w.chars().count()))
+        .map(str::to_lowercase)
+}
+
+/// Paths that describe the index rather than the system
+pub fn is_excluded(path: &str) -> bool {
+    covers(STATE_DIR, path)
+}
+
+impl SearchIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Indexed paths
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Distinct words indexed
+    pub fn term_count(&self) -> usize {
+        self.terms.len()
+    }
+
+    fn remove(&mut self, path: &str) {
+        let Some(entry) = self.files.remove(path) else {
+            return;
+        };
+        for word in entry.words {
+            if let Some(postings) = self.terms.get_mut(&word) {
+                postings.remove(path);
+                if postings.is_empty() {
+                    self.terms.remove(&word);
+                }
+            }
+        }
+    }
+
+    fn insert(&mut self, path: &str, fingerprint: u64, text: Option<&str>) {
+        self.remove(path);
+        let mut lines_of: BTreeMap<String, Vec<u32>> = BTreeMap::new();
+        for (n, line) in text.unwrap_or_default().lines().enumerate() {
+            for word in words(line) {
+                let lines = lines_of.entry(word).or_default();
+                let n = n as u32 + 1;
+                if lines.last() != Some(&n) {
+                    lines.push(n);
+                }
+            }
+        }
+        let words = lines_of.keys().cloned().collect();
+        for (word, lines) in lines_of {
+            self.terms
+                .entry(word)
+                .or_default()
+                .insert(path.to_string(), lines);
+        }
+        self.files
+            .insert(path.to_string(), FileEntry { fingerprint, words });
+    }
+
+    /// Bring the paths under `roots` up to date: `current` holds the
+    /// fingerprint of every path there now, and `read` the text of a file
+    /// (`None` for anything that isn't text)
+    pub fn update(
+        &mut self,
+        roots: &BTreeSet<String>,
+        current: &BTreeMap<String, u64>,
+        mut read: impl FnMut(&str) -> Option<String>,
+    ) -> UpdateStats {
+        let mut stats = UpdateStats::default();
+        let gone: Vec<String> = self
+            .files
+            .keys()
+            .filter(|path| roots.iter().any(|root| covers(root, path)))
+            .filter(|path| !current.contains_key(*path))
+            .cloned()
+            .collect();
+        for path in gone {
+            self.remove(&path);
+            stats.removed += 1;
+        }
+        for (path, &fingerprint) in current {
+            if is_excluded(path) || self.files.get(path).map(|e| e.fingerprint) == Some(fingerprint)
+            {
+                continue;
+            }
+            let text = read(path);
+            self.insert(path, fingerprint, text.as_deref());
+            stats.indexed += 1;
+        }
+        stats
+    }
+
+    /// Paths `filter` lets through matching any of `patterns` (or, with
+    /// none, all of them): a pattern with `*`, `?` or `[` has to match the
+    /// whole path, one without is looked for anywhere in it
+    pub fn locate<'a>(
+        &'a self,
+        patterns: &[String],
+        ignore_case: bool,
+        filter: &'a PathFilter,
+    ) -> impl Iterator<Item = &'a str> + 'a {
+        let patterns: Vec<(String, bool)> = patterns
+            .iter()
+            .map(|p| {
+                let p = if ignore_case {
+                    p.to_lowercase()
+                } else {
+                    p.clone()
+                };
+                let glob = p.contains(['*', '?', '[']);
+                // In a path a * crosses directories
+                let p = if glob {
+                    p.replace("**", "*").replace('*', "**")
+                } else {
+                    p
+                };
+                (p, glob)
+            })
+            .collect();
+        self.files.keys().map(String::as_str).filter(move |path| {
+            if !filter.matches(path) {
+                return false;
+            }
+            if patterns.is_empty() {
+                return true;
+            }
+            let path = if ignore_case {
+                path.to_lowercase()
+            } else {
+                path.to_string()
+            };
+            patterns.iter().any(|(p, glob)| {
+                if *glob {
+                    super::executor::glob_match(p, &path)
+                } else {
+                    path.contains(p.as_str())
+                }
+            })
+        })
+    }
+
+    /// Files `filter` lets through holding every word of `query`, ranked
+    /// by how often they use the words, rarer words counting for more
+    pub fn search(&self, query: &str, filter: &PathFilter) -> Vec<SearchHit> {
+        let query: BTreeSet<String> = words(query).collect();
+        let mut postings = Vec::new();
+        for word in &query {
+            match self.terms.get(word) {
+                Some(p) => postings.push(p),
+                None => return Vec::new(),
+            }
+        }
+        let Some((first, rest)) = postings.split_first() else {
+            return Vec::new();
+        };
+
+        let total = self.files.len() as f64;
+        let mut hits: Vec<SearchHit> = first
+            .keys()
+            .filter(|path| rest.iter().all(|p| p.contains_key(*path)))
+            .filter(|path| filter.matches(path))
+            .map(|path| {
+                let mut score = 0.0;
+                let mut lines = BTreeSet::new();
+                for p in &postings {
+                    let on = &p[path];
+                    let idf = (1.0 + total / p.len() as f64).ln();
+                    score += (1.0 + (on.len() as f64).ln()) * idf;
+                    lines.extend(on.iter().copied());
+                }
+                SearchHit {
+                    path: path.clone(),
+                    score,
+                    lines: lines.into_iter().collect(),
+                }
+            })
+            .collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.path.cmp(&b.path)));
+        hits
+    }
+}
+
+/// The service's in-memory copy of the index
+#[derive(Default)]
+struct Indexer {
+    index: Option<SearchIndex>,
+    /// Change log position the index is current to
+    cursor: Option<ChangeCursor>,
+    /// When [`tick`] last updated
+    updated: Option<f64>,
+}
+
+thread_local! {
+    static INDEXER: RefCell<Indexer> = RefCell::new(Indexer::default());
+}
+
+/// Text of `path` if it is a text file small enough to index
+fn read_text(path: &str) -> Option<String> {
+    let meta = syscall::metadata(path).ok()?;
+    if !meta.is_file || meta.is_symlink || meta.size > MAX_TEXT_SIZE {
+        return None;
+    }
+    syscall::read_file(path).ok().filter(|t| !t.contains('\0'))
+}
+
+fn load() -> SearchIndex {
+    as_root(|| syscall::read_file(INDEX_PATH))
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+fn save(index: &SearchIndex) -> Result<(), String> {
+    let json = serde_json::to_string(index).map_err(|e| e.to_string())?;
+    as_root(|| {
+        for dir in ["/var", "/var/lib", STATE_DIR] {
+            if !syscall::exists(dir)? {
+                syscall::mkdir(dir)?;
+            }
+        }
+        syscall::write_file(INDEX_PATH, &json)
+    })
+    .map_err(|e| format!("{}: {}", INDEX_PATH, e))
+}
+
+/// Bring the index up to date with the filesystem; `full` compares the
+/// whole tree instead of following the change feed
+pub fn update(full: bool) -> Result<UpdateStats, String> {
+    let (mut index, cursor) = INDEXER.with(|i| {
+        let mut i = i.borrow_mut();
+        (i.index.take().unwrap_or_else(load), i.cursor)
+    });
+    let changed = cursor
+        .filter(|_| !full)
+        .and_then(syscall::vfs_changed_since);
+    let roots: BTreeSet<String> = match changed {
+        Some(paths) => paths.into_iter().filter(|p| !is_excluded(p)).collect(),
+        None => BTreeSet::from(["/".to_string()]),
+    };
+
+    let mut current = BTreeMap::new();
+    for root in &roots {
+        current.extend(syscall::vfs_fingerprints_under(root));
+    }
+    let stats = as_root(|| index.update(&roots, &current, read_text));
+    let saved = if stats.is_empty() {
+        Ok(())
+    } else {
+        save(&index)
+    };
+
+    INDEXER.with(|i| {
+        let mut i = i.borrow_mut();
+        i.index = Some(index);
+        // After the save, so the index's own write isn't seen as a change
+        i.cursor = Some(syscall::vfs_change_cursor());
+    });
+    saved.map(|()| stats)
+}
+
+/// Run `f` on the index, updated first
+pub fn with_index<T>(f: impl FnOnce(&SearchIndex) -> T) -> Result<T, String> {
+    update(false)?;
+    Ok(INDEXER.with(|i| f(i.borrow().index.as_ref().unwrap_or(&SearchIndex::default()))))
+}
+
+/// Update the index if it is due at `now_ms`; returns what changed
+///
+/// Cheap to call on every idle tick: it only runs every
+/// [`UPDATE_INTERVAL`], and then only looks at what changed.
+pub fn tick(now_ms: f64) -> Option<UpdateStats> {
+    let due = INDEXER.with(|i| {
+        let mut i = i.borrow_mut();
+        if i.updated.is_some_and(|t| now_ms - t < UPDATE_INTERVAL) {
+            return false;
+        }
+        i.updated = Some(now_ms);
+        true
+    });
+    if !due {
+        return None;
+    }
+    match update(false) {
+        Ok(stats) => Some(stats),
+        Err(e) => {
+            syscall::klog(crate::kernel::LogLevel::Err, "indexer", &e);
+            None
+        }
+    }
+}
+
+/// Drop the in-memory index, for a memory pressure hook; the next update
+/// reloads it from [`INDEX_PATH`], which every update that changed it wrote
+pub fn trim(_level: PressureLevel) -> Option<String> {
+    let index = INDEXER.with(|i| i.borrow_mut().index.take())?;
+    Some(format!("file search index ({} paths)", index.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::{FileSystem, MemoryFs, read_to_string, write_string};
+
+    /// Update `index` from `fs` the way the service does
+    fn refresh(
+        index: &mut SearchIndex,
+        fs: &mut MemoryFs,
+        cursor: Option<ChangeCursor>,
+    ) -> (UpdateStats, ChangeCursor) {
+        let roots = cursor
+            .and_then(|c| fs.changed_since(c))
+            .unwrap_or_else(|| BTreeSet::from(["/".to_string()]));
+        let mut current = BTreeMap::new();
+        for root in &roots {
+            current.extend(fs.fingerprints_under(root));
+        }
+        let stats = index.update(&roots, &current, |path| read_to_string(fs, path).ok());
+        (stats, fs.change_cursor())
+    }
+
+    fn search(index: &SearchIndex, query: &str) -> Vec<(String, Vec<u32>)> {
+        index
+            .search(query, &PathFilter::default())
+            .into_iter()
+            .map(|hit| (hit.path, hit.lines))
+            .collect()
+    }
+
+    #[test]
+    fn test_words() {
+        let found: Vec<String> = words("Fix the parse_args() bug, x = 42!").collect();
+        assert_eq!(found, ["fix", "the", "parse_args", "bug", "42"]);
+    }
+
+    #[test]
+    fn test_path_filter() {
+        let terms = ["ship", "path:notes", "ext:MD,.txt", "path:", "it"];
+        let (filter, rest) = PathFilter::split(&terms, |dir| format!("/home/user/{dir}"));
+        assert_eq!(filter.dirs, ["/home/user/notes"]);
+        assert_eq!(filter.exts, ["md", "txt"]);
+        assert_eq!(rest, ["ship", "path:", "it"]);
+
+        assert!(filter.matches("/home/user/notes/plan.md"));
+        assert!(filter.matches("/home/user/notes/old/TODO.TXT"));
+        assert!(!filter.matches("/home/user/notes/plan.rs"));
+        assert!(!filter.matches("/home/user/notes2/plan.md"));
+        assert!(!filter.matches("/home/user/notes/.md"));
+        assert!(PathFilter::default().matches("/anything"));
+
+        let mut index = SearchIndex::new();
+        index.insert("/notes/plan.md", 1, Some("ship it"));
+        index.insert("/notes/plan.txt", 2, Some("ship it"));
+        index.insert("/src/ship.rs", 3, Some("fn ship()"));
+        let (md, _) = PathFilter::split(&["ext:md"], str::to_string);
+        let hits: Vec<String> = index
+            .search("ship", &md)
+            .into_iter()
+            .map(|h| h.path)
+            .collect();
+        assert_eq!(hits, ["/notes/plan.md"]);
+        let (src, _) = PathFilter::split(&["path:/src"], str::to_string);
+        let found: Vec<&str> = index.locate(&[], false, &src).collect();
+        assert_eq!(found, ["/src/ship.rs"]);
+        assert_eq!(index.locate(&["plan".to_string()], false, &src).count(), 0);
+    }
+
+    #[test]
+    fn test_trim() {
+        INDEXER.with(|i| i.borrow_mut().index = Some(SearchIndex::new()));
+        assert_eq!(
+            trim(PressureLevel::Moderate).as_deref(),
+            Some("file search index (0 paths)")
+        );
+        assert_eq!(trim(PressureLevel::Critical), None);
+    }
+
+    #[test]
+    fn test_incremental_index() {
+        let mut fs = MemoryFs::new();
+        fs.create_dir("/notes").unwrap();
+        write_string(
+            &mut fs,
+            "/notes/rust.md",
+            "Rust notes\nborrow checker\nrust rust",
+        )
+        .unwrap();
+        write_string(&mut fs, "/notes/todo.txt", "buy milk\nlearn Rust").unwrap();
+        let mut index = SearchIndex::new();
+        let (stats, cursor) = refresh(&mut index, &mut fs, None);
+        assert_eq!(stats.indexed, 4);
+        assert_eq!(index.len(), 4);
+
+        // More uses of a word rank higher; every word has to be there
+        assert_eq!(
+            search(&index, "rust"),
+            [
+                ("/notes/rust.md".to_string(), vec![1, 3]),
+                ("/notes/todo.txt".to_string(), vec![2]),
+            ]
+        );
+        assert_eq!(
+            search(&index, "learn RUST"),
+            [("/notes/todo.txt".to_string(), vec![2])]
+        );
+        assert!(search(&index, "python").is_empty());
+
+        // Only what changed is looked at
+        write_string(&mut fs, "/notes/todo.txt", "buy bread").unwrap();
+        fs.rename("/notes/rust.md", "/notes/lang.md").unwrap();
+        let (stats, cursor) = refresh(&mut index, &mut fs, Some(cursor));
+        assert_eq!(
+            stats,
+            UpdateStats {
+                indexed: 2,
+                removed: 1
+            }
+        );
+        assert_eq!(
+            search(&index, "rust"),
+            [("/notes/lang.md".to_string(), vec![1, 3])]
+        );
+        assert!(search(&index, "milk").is_empty());
+
+        let (stats, _) = refresh(&mut index, &mut fs, Some(cursor));
+        assert!(stats.is_empty());
+        // A full rescan finds nothing new either
+        let (stats, _) = refresh(&mut index, &mut fs, None);
+        assert!(stats.is_empty());
+
+        let patterns = |p: &[&str]| p.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let all = PathFilter::default();
+        let found: Vec<&str> = index.locate(&patterns(&["todo"]), false, &all).collect();
+        assert_eq!(found, ["/notes/todo.txt"]);
+        let found: Vec<&str> = index.locate(&patterns(&["/*.MD"]), true, &all).collect();
+        assert_eq!(found, ["/notes/lang.md"]);
+        assert_eq!(index.locate(&patterns(&["*.md"]), false, &all).count(), 1);
+        assert_eq!(
+            index
+                .locate(&patterns(&["*.MD", "*.txt?"]), false, &all)
+                .count(),
+            0
+        );
+
+        // The index's own files aren't indexed
+        fs.create_dir("/var").unwrap();
+        fs.create_dir("/var/lib").unwrap();
+        fs.create_dir(STATE_DIR).unwrap();
+        write_string(&mut fs, INDEX_PATH, "{}").unwrap();
+        refresh(&mut index, &mut fs, None);
+        let found: Vec<&str> = index.locate(&patterns(&["/var"]), false, &all).collect();
+        assert_eq!(found, ["/var", "/var/lib"]);
+    }
+}
@@ -0,0 +1,466 @@
+//! Journal - command journal with replay and undo
+//!
+//! Every command line the shell runs is appended to [`JOURNAL_PATH`]
+//! together with the paths it created, modified or deleted. The diff comes
+//! from snapshotting the VFS before the line runs and comparing afterwards,
+//! so it covers programs, redirections and WASM commands alike.
+//!
+//! For commands that modified or deleted something, the shell also keeps
+//! the before-images of those paths in memory. `undo-last` puts them back
+//! and removes whatever the command created. Only the last [`UNDO_DEPTH`]
+//! commands of the current session can be undone.
+//!
+//! The file keeps the newest [`MAX_ENTRIES`] entries: [`tick`] compacts it
+//! now and then, as idle work.
+//!
+//! ```text
+//! $ rm notes.txt
+//! $ journal
+//!   SEQ EXIT CHANGES      COMMAND
+//!    41*   0 -1           rm notes.txt
+//! $ undo-last
+//! undo-last: reverted #41 rm notes.txt
+//! restored /home/user/notes.txt
+//! ```
+
+use super::programs::read_file_content;
+use super::setup::as_root;
+use crate::kernel::syscall::{self, OpenFlags};
+use crate::vfs::{FsChange, FsChangeKind, FsSnapshot};
+use std::cell::Cell;
+use std::collections::VecDeque;
+
+/// Where the journal is written
+pub const JOURNAL_PATH: &str = "/var/log/shell-journal";
+
+/// Number of commands whose before-images are kept for `undo-last`
+pub const UNDO_DEPTH: usize = 16;
+
+/// Largest before-image kept for one command, in bytes of file content
+pub const MAX_BEFORE_IMAGE: usize = 4 * 1024 * 1024;
+
+/// Entries `journal show` lists by default
+pub const DEFAULT_SHOW: usize = 20;
+
+/// Entries the file keeps when it is compacted
+pub const MAX_ENTRIES: usize = 5000;
+
+/// How often [`tick`] compacts, in milliseconds
+const COMPACT_INTERVAL: f64 = 10.0 * 60.0 * 1000.0;
+
+thread_local! {
+    /// When [`tick`] last compacted
+    static COMPACTED: Cell<Option<f64>> = const { Cell::new(None) };
+}
+
+/// What a `journal` or `undo-last` builtin asks the executor to do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JournalRequest {
+    /// List the last `count` entries
+    Show { count: usize },
+    /// Show the paths one entry changed
+    Diff { seq: u64 },
+    /// Run the commands of entries `from..=to` again
+    Replay { from: u64, to: u64 },
+    /// Revert the most recent destructive command
+    Undo { force: bool },
+}
+
+/// One command in the journal
+#[derive(Debug, Clone, PartialEq)]
+pub struct JournalEntry {
+    pub seq: u64,
+    /// Kernel time the command finished, in milliseconds
+    pub time: f64,
+    pub uid: u32,
+    pub code: i32,
+    pub cwd: String,
+    pub command: String,
+    pub changes: Vec<FsChange>,
+}
+
+impl JournalEntry {
+    /// Check if the command modified or deleted existing paths
+    pub fn is_destructive(&self) -> bool {
+        self.changes.iter().any(|c| c.kind != FsChangeKind::Created)
+    }
+
+    /// Change counts, e.g. `+1 ~0 -2`, or `-` when nothing changed
+    pub fn summary(&self) -> String {
+        if self.changes.is_empty() {
+            return "-".to_string();
+        }
+        let count = |kind| self.changes.iter().filter(|c| c.kind == kind).count();
+        format!(
+            "+{} ~{} -{}",
+            count(FsChangeKind::Created),
+            count(FsChangeKind::Modified),
+            count(FsChangeKind::Deleted)
+        )
+    }
+
+    /// Format as one journal line: tab-separated fields, then one field
+    /// per change
+    pub fn to_line(&self) -> String {
+        let mut fields = vec![
+            self.seq.to_string(),
+            format!("{:.0}", self.time),
+            self.uid.to_string(),
+            self.code.to_string(),
+            escape(&self.cwd),
+            escape(&self.command),
+        ];
+        fields.extend(
+            self.changes
+                .iter()
+                .map(|c| format!("{}{}", c.kind.marker(), escape(&c.path))),
+        );
+        fields.join("\t")
+    }
+
+    /// Parse a line written by [`JournalEntry::to_line`]
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t');
+        let seq = fields.next()?.parse().ok()?;
+        let time = fields.next()?.parse().ok()?;
+        let uid = fields.next()?.parse().ok()?;
+        let code = fields.next()?.parse().ok()?;
+        let cwd = unescape(fields.next()?);
+        let command = unescape(fields.next()?);
+        let changes = fields
+            .map(|field| {
+                let mut chars = field.chars();
+                let kind = FsChangeKind::from_marker(chars.next()?)?;
+                Some(FsChange {
+                    path: unescape(chars.as_str()),
+                    kind,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(Self {
+            seq,
+            time,
+            uid,
+            code,
+            cwd,
+            command,
+            changes,
+        })
+    }
+}
+
+/// Escape the characters the line format uses as separators
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Check if a command line runs the journal itself (never replayed)
+pub fn is_journal_command(line: &str) -> bool {
+    matches!(
+        line.split_whitespace().next(),
+        Some("journal" | "undo-last")
+    )
+}
+
+/// Read every entry in the journal file, oldest first
+///
+/// Lines that don't parse are skipped.
+pub fn read_entries() -> Vec<JournalEntry> {
+    read_file_content(JOURNAL_PATH)
+        .map(|content| content.lines().filter_map(JournalEntry::parse).collect())
+        .unwrap_or_default()
+}
+
+/// Append an entry to the journal file, creating `/var/log` if needed
+fn append(entry: &JournalEntry) {
+    let _ = syscall::mkdir("/var");
+    let _ = syscall::mkdir("/var/log");
+    if let Ok(fd) = syscall::open(JOURNAL_PATH, OpenFlags::APPEND) {
+        let _ = syscall::write(fd, format!("{}\n", entry.to_line()).as_bytes());
+        let _ = syscall::close(fd);
+    }
+}
+
+/// Drop all but the newest [`MAX_ENTRIES`] lines from the journal file;
+/// returns how many were dropped
+pub fn compact() -> Result<usize, String> {
+    let Ok(content) = read_file_content(JOURNAL_PATH) else {
+        return Ok(0);
+    };
+    let lines: Vec<&str> = content.lines().collect();
+    let dropped = lines.len().saturating_sub(MAX_ENTRIES);
+    if dropped == 0 {
+        return Ok(0);
+    }
+    let mut kept = lines[dropped..].join("\n");
+    kept.push('\n');
+    as_root(|| syscall::write_file(JOURNAL_PATH, &kept))
+        .map_err(|e| format!("{}: {}", JOURNAL_PATH, e))?;
+    Ok(dropped)
+}
+
+/// Compact the journal file if it is due at `now_ms`; returns how many
+/// entries were dropped
+///
+/// Cheap to call on every idle tick: it only runs every
+/// [`COMPACT_INTERVAL`].
+pub fn tick(now_ms: f64) -> usize {
+    if COMPACTED
+        .get()
+        .is_some_and(|t| now_ms - t < COMPACT_INTERVAL)
+    {
+        return 0;
+    }
+    COMPACTED.set(Some(now_ms));
+    compact().unwrap_or_else(|e| {
+        syscall::klog(crate::kernel::LogLevel::Err, "journal", &e);
+        0
+    })
+}
+
+/// What `undo-last` needs to revert one command
+#[derive(Clone)]
+struct UndoRecord {
+    entry: JournalEntry,
+    /// Before-state of modified and deleted paths (None = too large to keep)
+    image: Option<FsSnapshot>,
+    /// Fingerprint of each changed path right after the command
+    after: Vec<Option<u64>>,
+}
+
+/// Journal state for one shell
+#[derive(Clone, Default)]
+pub struct Journal {
+    /// Nesting of command lines (functions, substitutions, replay)
+    depth: usize,
+    /// Next sequence number (read from the file on first use)
+    next_seq: Option<u64>,
+    /// Undoable commands, oldest first
+    undo: VecDeque<UndoRecord>,
+    /// The line being recorded is an undo; don't make it undoable
+    undoing: bool,
+}
+
+impl Journal {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start running a command line
+    ///
+    /// Returns the VFS snapshot to pass to [`Journal::finish`]. Nested lines
+    /// get `None` and are recorded as part of the outermost one.
+    pub fn begin(&mut self) -> Option<FsSnapshot> {
+        self.depth += 1;
+        (self.depth == 1).then(syscall::vfs_capture)
+    }
+
+    /// Finish a command line started with [`Journal::begin`], recording it
+    /// if it was the outermost one
+    pub fn finish(
+        &mut self,
+        before: Option<FsSnapshot>,
+        command: &str,
+        cwd: &str,
+        code: i32,
+    ) -> Option<JournalEntry> {
+        self.depth = self.depth.saturating_sub(1);
+        let before = before?;
+        let undoing = std::mem::take(&mut self.undoing);
+
+        let changes: Vec<FsChange> = syscall::vfs_changes_since(&before)
+            .into_iter()
+            .filter(|c| c.path != JOURNAL_PATH)
+            .collect();
+        let entry = JournalEntry {
+            seq: self.take_seq(),
+            time: syscall::now(),
+            uid: syscall::getuid().map(|u| u.0).unwrap_or(0),
+            code,
+            cwd: cwd.to_string(),
+            command: command.to_string(),
+            changes,
+        };
+
+        if entry.is_destructive() && !undoing {
+            self.remember(&before, &entry);
+        }
+        append(&entry);
+        Some(entry)
+    }
+
+    fn take_seq(&mut self) -> u64 {
+        let seq = *self
+            .next_seq
+            .get_or_insert_with(|| read_entries().last().map_or(1, |e| e.seq + 1));
+        self.next_seq = Some(seq + 1);
+        seq
+    }
+
+    /// Keep the before-images `undo-last` needs for `entry`
+    fn remember(&mut self, before: &FsSnapshot, entry: &JournalEntry) {
+        let image = before.subset(
+            entry
+                .changes
+                .iter()
+                .filter(|c| c.kind != FsChangeKind::Created)
+                .map(|c| c.path.as_str()),
+        );
+        let image = (image.content_size() <= MAX_BEFORE_IMAGE).then_some(image);
+        let after = entry
+            .changes
+            .iter()
+            .map(|c| syscall::vfs_fingerprint(&c.path))
+            .collect();
+
+        self.undo.push_back(UndoRecord {
+            entry: entry.clone(),
+            image,
+            after,
+        });
+        if self.undo.len() > UNDO_DEPTH {
+            self.undo.pop_front();
+        }
+    }
+
+    /// Sequence numbers of the commands that can be undone, oldest first
+    pub fn undoable(&self) -> Vec<u64> {
+        self.undo
+            .iter()
+            .filter(|r| r.image.is_some())
+            .map(|r| r.entry.seq)
+            .collect()
+    }
+
+    /// Revert the most recent destructive command
+    ///
+    /// Fails if a path it changed has changed again since, unless `force`
+    /// is set. A command whose before-image was too large to keep is
+    /// dropped from the undo list so the next call reaches the one before.
+    pub fn undo_last(&mut self, force: bool) -> Result<JournalEntry, String> {
+        let record = self.undo.back().ok_or("nothing to undo")?;
+        let entry = &record.entry;
+        let Some(image) = &record.image else {
+            let msg = format!(
+                "#{} {}: before-image over {} bytes was not kept; skipped",
+                entry.seq, entry.command, MAX_BEFORE_IMAGE
+            );
+            self.undo.pop_back();
+            return Err(msg);
+        };
+
+        if !force {
+            for (change, after) in entry.changes.iter().zip(&record.after) {
+                if syscall::vfs_fingerprint(&change.path) != *after {
+                    return Err(format!(
+                        "{}: changed since #{}; use -f to undo anyway",
+                        change.path, entry.seq
+                    ));
+                }
+            }
+        }
+
+        syscall::vfs_revert(image, &entry.changes)
+            .map_err(|e| format!("#{} {}: {}", entry.seq, entry.command, e))?;
+        self.undoing = true;
+        self.undo
+            .pop_back()
+            .map(|r| r.entry)
+            .ok_or_else(|| "nothing to undo".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_round_trip() {
+        let entry = JournalEntry {
+            seq: 7,
+            time: 1234.0,
+            uid: 1000,
+            code: 1,
+            cwd: "/home/user".to_string(),
+            command: "echo 'a\tb' > x\\y".to_string(),
+            changes: vec![
+                FsChange {
+                    path: "/home/user/x\\y".to_string(),
+                    kind: FsChangeKind::Created,
+                },
+                FsChange {
+                    path: "/tmp/old".to_string(),
+                    kind: FsChangeKind::Deleted,
+                },
+            ],
+        };
+        let line = entry.to_line();
+        assert_eq!(line.matches('\t').count(), 7);
+        assert_eq!(JournalEntry::parse(&line), Some(entry.clone()));
+        assert_eq!(entry.summary(), "+1 ~0 -1");
+        assert!(entry.is_destructive());
+
+        assert!(JournalEntry::parse("garbage").is_none());
+        assert!(JournalEntry::parse("1\t0\t0\t0\t/\tls\t?bad").is_none());
+        assert!(is_journal_command("undo-last -f"));
+        assert!(!is_journal_command("journalctl"));
+    }
+
+    #[test]
+    fn test_compact() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+        KERNEL.with(|k| {
+            *k.borrow_mut() = Kernel::new();
+            let pid = k.borrow_mut().spawn_process("sh", None);
+            k.borrow_mut().set_current(pid);
+        });
+        assert_eq!(compact(), Ok(0));
+
+        let line = |seq: usize| {
+            JournalEntry {
+                seq: seq as u64,
+                time: 0.0,
+                uid: 0,
+                code: 0,
+                cwd: "/".to_string(),
+                command: format!("echo {}", seq),
+                changes: Vec::new(),
+            }
+            .to_line()
+                + "\n"
+        };
+        let content: String = (1..=MAX_ENTRIES + 3).map(line).collect();
+        as_root(|| syscall::write_file(JOURNAL_PATH, &content)).unwrap();
+
+        assert_eq!(tick(1000.0), 3);
+        let entries = read_entries();
+        assert_eq!(entries.len(), MAX_ENTRIES);
+        assert_eq!(entries[0].seq, 4);
+        assert_eq!(entries[0].command, "echo 4");
+
+        // Not due again yet
+        as_root(|| append(&JournalEntry::parse(line(MAX_ENTRIES + 4).trim_end()).unwrap()));
+        assert_eq!(tick(2000.0), 0);
+        assert_eq!(compact(), Ok(1));
+    }
+}
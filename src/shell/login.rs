@@ -0,0 +1,145 @@
+//! Login shells
+//!
+//! Each user has a login shell in `/etc/passwd`. The shell honors it when a
+//! session starts (`login`, `su`): `/bin/sh` is the ordinary shell,
+//! `/bin/script` drops straight into the script REPL, and `/bin/rsh` is a
+//! restricted shell that keeps the user inside their home directory and
+//! won't let them change `PATH` or `SHELL`. A shell may only be used if
+//! it is listed in `/etc/shells`.
+
+use crate::kernel::syscall;
+
+/// Shells users may log in with, one path per line
+pub const SHELLS_PATH: &str = "/etc/shells";
+
+/// What `/etc/shells` lists when it is missing
+pub const DEFAULT_SHELLS: &[&str] = &["/bin/sh", "/bin/rsh", "/bin/script"];
+
+/// Variables a restricted shell won't let the user change
+pub const RESTRICTED_VARS: &[&str] = &["PATH", "SHELL"];
+
+/// How the shell behaves for a login shell path
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginShell {
+    /// The ordinary shell
+    Sh,
+    /// The shell, kept inside the home directory with a fixed `PATH`
+    Restricted,
+    /// The script language REPL; leaving it logs out
+    Script,
+}
+
+impl LoginShell {
+    /// The kind of shell at `path`, by its file name
+    pub fn from_path(path: &str) -> Option<Self> {
+        match path.rsplit('/').next()? {
+            "sh" => Some(Self::Sh),
+            "rsh" => Some(Self::Restricted),
+            "script" => Some(Self::Script),
+            _ => None,
+        }
+    }
+}
+
+/// Shell paths listed in `content`, skipping blank lines and comments
+pub fn parse_shells(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Shells listed in `/etc/shells`, or the defaults without one
+pub fn allowed_shells() -> Vec<String> {
+    match syscall::read_file(SHELLS_PATH) {
+        Ok(content) => parse_shells(&content),
+        Err(_) => DEFAULT_SHELLS.iter().map(|s| s.to_string()).collect(),
+    }
+}
+
+/// Check that `path` may be used as a login shell
+pub fn check_shell(path: &str) -> Result<LoginShell, String> {
+    if !allowed_shells().iter().any(|s| s == path) {
+        return Err(format!("{} is not listed in {}", path, SHELLS_PATH));
+    }
+    LoginShell::from_path(path).ok_or_else(|| format!("{}: unknown shell", path))
+}
+
+/// The login shell of the user running the shell
+pub fn current() -> LoginShell {
+    syscall::getuid()
+        .ok()
+        .and_then(syscall::get_user_by_uid)
+        .and_then(|user| LoginShell::from_path(&user.shell))
+        .unwrap_or(LoginShell::Sh)
+}
+
+/// Home directory a restricted shell keeps the current user inside, if
+/// their login shell is restricted
+pub fn restricted_home() -> Option<String> {
+    let user = syscall::get_user_by_uid(syscall::getuid().ok()?)?;
+    (LoginShell::from_path(&user.shell) == Some(LoginShell::Restricted)).then_some(user.home)
+}
+
+/// Write the default `/etc/shells` if there isn't one
+pub fn install() -> Result<(), String> {
+    if syscall::exists(SHELLS_PATH).unwrap_or(false) {
+        return Ok(());
+    }
+    let content: String = DEFAULT_SHELLS.iter().map(|s| format!("{}\n", s)).collect();
+    syscall::write_file(SHELLS_PATH, &content).map_err(|e| format!("{}: {}", SHELLS_PATH, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::syscall::{KERNEL, Kernel};
+
+    #[test]
+    fn test_login_shell_kinds() {
+        assert_eq!(LoginShell::from_path("/bin/sh"), Some(LoginShell::Sh));
+        assert_eq!(
+            LoginShell::from_path("/bin/rsh"),
+            Some(LoginShell::Restricted)
+        );
+        assert_eq!(
+            LoginShell::from_path("/usr/bin/script"),
+            Some(LoginShell::Script)
+        );
+        assert_eq!(LoginShell::from_path("/bin/zsh"), None);
+        assert_eq!(
+            parse_shells("# shells\n/bin/sh\n\n  /bin/rsh  \n"),
+            ["/bin/sh", "/bin/rsh"]
+        );
+    }
+
+    #[test]
+    fn test_etc_shells() {
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("sh", None);
+            k.set_current(pid);
+        });
+
+        // Without /etc/shells the defaults apply
+        assert_eq!(check_shell("/bin/script"), Ok(LoginShell::Script));
+        install().unwrap();
+        assert_eq!(
+            syscall::read_file(SHELLS_PATH).unwrap(),
+            "/bin/sh\n/bin/rsh\n/bin/script\n"
+        );
+
+        syscall::write_file(SHELLS_PATH, "/bin/sh\n/bin/zsh\n").unwrap();
+        install().unwrap();
+        assert_eq!(check_shell("/bin/sh"), Ok(LoginShell::Sh));
+        assert!(check_shell("/bin/rsh").unwrap_err().contains("not listed"));
+        assert!(
+            check_shell("/bin/zsh")
+                .unwrap_err()
+                .contains("unknown shell")
+        );
+    }
+}
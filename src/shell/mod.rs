@@ -9,39 +9,139 @@
 //!
 //! Built incrementally with comprehensive tests at each step.
 
+pub mod argparse;
+pub mod backup;
 pub mod builtins;
+pub mod cron;
+pub mod diff;
 pub mod executor;
+pub mod help;
+pub mod history;
+pub mod indexer;
+pub mod journal;
+pub mod login;
+pub mod osc;
+pub mod osimage;
 pub mod parser;
+pub mod paste;
+pub mod profile;
 pub mod programs;
+pub mod qr;
+pub mod script;
+pub mod setup;
+pub mod spell;
+pub mod stats;
+pub mod swap;
 pub mod terminal;
+pub mod tutorial;
+pub mod unicode;
+pub mod update;
+pub mod widgets;
 
 pub use builtins::{BuiltinResult, ShellState, execute as execute_builtin, is_builtin};
 pub use executor::{ExecResult, Executor, ProgramRegistry};
 pub use parser::{ParseError, Pipeline, Redirect, SimpleCommand, parse};
 pub use terminal::{Selection, TermPos, Terminal};
 
-use std::cell::RefCell;
+use crate::kernel::SystemImage;
+use crate::kernel::syscall;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
 
 thread_local! {
-    static EXECUTOR: RefCell<Executor> = RefCell::new(Executor::new());
+    /// A shell per virtual console, started when it is first used
+    static EXECUTORS: RefCell<BTreeMap<usize, Executor>> = const { RefCell::new(BTreeMap::new()) };
+    /// Virtual console the last shell ran on
+    static CONSOLE: Cell<usize> = const { Cell::new(1) };
 }
 
-/// Execute a command and return the output
+/// Make the shell process of the virtual console on screen current, if
+/// it was switched to (see [`syscall::chvt`]) since a shell last ran
+pub fn follow_console() -> usize {
+    let vt = syscall::active_vt();
+    if CONSOLE.with(|c| c.replace(vt)) != vt
+        && let Some(pid) = syscall::vt_shell(vt)
+    {
+        syscall::set_current_process(pid);
+    }
+    vt
+}
+
+/// Run `f` with the shell of the virtual console on screen
+fn with_executor<R>(f: impl FnOnce(&mut Executor) -> R) -> R {
+    let vt = follow_console();
+    EXECUTORS.with(|execs| f(execs.borrow_mut().entry(vt).or_insert_with(Executor::new)))
+}
+
+/// Execute a typed command and return the output
 pub fn execute_command(line: &str) -> String {
-    EXECUTOR.with(|exec| {
-        let result = exec.borrow_mut().execute_line(line);
-        let mut output = String::new();
+    with_executor(|exec| combined_output(&exec.execute_interactive(line)))
+}
 
-        if !result.output.is_empty() {
-            output.push_str(&result.output);
+/// Add lines from an earlier session to the shell's history
+pub fn remember_history(lines: &[String]) {
+    with_executor(|exec| {
+        for line in lines {
+            exec.remember(line.clone());
         }
-        if !result.error.is_empty() {
-            if !output.is_empty() {
-                output.push('\n');
-            }
-            output.push_str(&result.error);
+    })
+}
+
+/// Output followed by errors, a line apart
+fn combined_output(result: &ExecResult) -> String {
+    let mut output = String::new();
+
+    if !result.output.is_empty() {
+        output.push_str(&result.output);
+    }
+    if !result.error.is_empty() {
+        if !output.is_empty() {
+            output.push('\n');
         }
+        output.push_str(&result.error);
+    }
 
-        output
-    })
+    output
+}
+
+/// Prompt of the `script` REPL when one is active
+pub fn script_prompt() -> Option<&'static str> {
+    with_executor(|exec| exec.script_prompt())
+}
+
+/// Start the shell in a profile's `cwd` and run its `startup` command,
+/// returning the output
+pub fn start_profile(profile: &profile::TerminalProfile) -> String {
+    let mut output = String::new();
+    if let Some(cwd) = &profile.cwd {
+        let result = with_executor(|exec| exec.chdir(cwd));
+        output.push_str(&result.error);
+    }
+    if let Some(startup) = &profile.startup {
+        if !output.is_empty() {
+            output.push('\n');
+        }
+        let result = with_executor(|exec| exec.execute_line(startup));
+        output.push_str(&combined_output(&result));
+    }
+    output
+}
+
+/// The factory system image: a marker for each built-in program, the
+/// default configuration and the word list, mounted read-only on /system
+pub fn system_image() -> SystemImage {
+    let mut image = SystemImage::new(env!("CARGO_PKG_VERSION"));
+    for name in ProgramRegistry::new().list() {
+        image.add(&format!("bin/{}", name), format!("#!builtin {}\n", name));
+    }
+    image.add(
+        "etc/terminal/profiles.toml",
+        profile::ProfileSet::default().to_toml(),
+    );
+    image.add("usr/share/dict/words", spell::BUNDLED);
+    image.add(
+        "etc/version",
+        format!("axeberg {}\n", env!("CARGO_PKG_VERSION")),
+    );
+    image
 }
@@ -0,0 +1,379 @@
+//! OSC escape sequences in command output
+//!
+//! Programs talk to the terminal itself with operating system commands,
+//! `ESC ] Ps ; Pt` ended by BEL or `ESC \`. The terminals take them out of
+//! the output before showing it and act on the ones they know:
+//!
+//! ```text
+//! ESC ] 0 ; TITLE BEL       set the window title (2 does the same)
+//! ESC ] 52 ; c ; BASE64 BEL copy the decoded text to the clipboard
+//! ESC ] 9 ; 4 ; ST ; PR BEL report progress (see below)
+//! ```
+//!
+//! Progress is the ConEmu and Windows Terminal sequence: `ST` is 0 to
+//! clear the report, 1 for running, 2 for failed, 3 for running with no
+//! idea how far along and 4 for paused, and `PR` is the percentage. An
+//! axeberg terminal also takes the seconds left as a third field
+//! (`9;4;1;42;75`); other terminals ignore it. A long-running program
+//! reports through a [`Meter`], which works out the percentage and time
+//! left from how much is done, and clears the report when it finishes.
+//! The main terminal draws it as a bar above the prompt, and a terminal
+//! window as a badge on its title bar.
+//!
+//! The copy goes through /dev/clipboard as the process running the
+//! command, so a program can set the clipboard this way exactly when it
+//! could write /dev/clipboard itself. Asking for the clipboard back
+//! (`52;c;?`) is not answered: output can't read it.
+
+use crate::kernel::syscall::{self, OpenFlags, SyscallResult};
+
+/// Where copies go
+pub const CLIPBOARD_DEVICE: &str = "/dev/clipboard";
+
+/// Longest OSC 52 payload accepted, in base64 characters
+pub const MAX_COPY: usize = 1 << 20;
+
+/// A request from a program to its terminal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OscCommand {
+    /// OSC 0 or 2: set the window title
+    Title(String),
+    /// OSC 52: put text on the clipboard
+    Copy(Vec<u8>),
+    /// OSC 9;4: show progress, or stop showing it
+    Progress(Option<Progress>),
+}
+
+/// Clears a progress report
+pub const CLEAR_PROGRESS: &str = "\x1b]9;4;0;0\x07";
+
+/// Width of an inline progress bar, brackets excluded
+const BAR_WIDTH: usize = 20;
+
+/// How a reported piece of work is going
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressState {
+    /// Running, `percent` done
+    Normal,
+    /// Stopped by an error
+    Error,
+    /// Running, but how far along is unknown
+    Indeterminate,
+    /// Waiting
+    Paused,
+}
+
+/// A progress report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    pub state: ProgressState,
+    /// 0 to 100
+    pub percent: u8,
+    /// Seconds left, if the program can tell
+    pub eta: Option<u32>,
+}
+
+impl Progress {
+    /// Running, `percent` done
+    pub fn new(percent: u8) -> Self {
+        Self {
+            state: ProgressState::Normal,
+            percent: percent.min(100),
+            eta: None,
+        }
+    }
+
+    /// The sequence that reports this
+    pub fn encode(&self) -> String {
+        let state = match self.state {
+            ProgressState::Normal => 1,
+            ProgressState::Error => 2,
+            ProgressState::Indeterminate => 3,
+            ProgressState::Paused => 4,
+        };
+        match self.eta {
+            Some(eta) => format!("\x1b]9;4;{};{};{}\x07", state, self.percent, eta),
+            None => format!("\x1b]9;4;{};{}\x07", state, self.percent),
+        }
+    }
+
+    /// One line of text: a bar, the percentage, and the time left
+    pub fn render(&self) -> String {
+        let filled = self.percent as usize * BAR_WIDTH / 100;
+        let bar = match self.state {
+            ProgressState::Indeterminate => "~".repeat(BAR_WIDTH),
+            _ => format!("{}{}", "#".repeat(filled), "-".repeat(BAR_WIDTH - filled)),
+        };
+        let status = match (self.state, self.eta) {
+            (ProgressState::Error, _) => " failed".to_string(),
+            (ProgressState::Paused, _) => " paused".to_string(),
+            (ProgressState::Indeterminate, _) => " working".to_string(),
+            (ProgressState::Normal, Some(eta)) => format!(" {} left", format_eta(eta)),
+            (ProgressState::Normal, None) => String::new(),
+        };
+        match self.state {
+            ProgressState::Indeterminate => format!("[{}]{}", bar, status),
+            _ => format!("[{}] {:>3}%{}", bar, self.percent, status),
+        }
+    }
+}
+
+/// Seconds as `M:SS`, or `H:MM:SS` past an hour
+fn format_eta(seconds: u32) -> String {
+    let (h, m, s) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if h > 0 {
+        format!("{}:{:02}:{:02}", h, m, s)
+    } else {
+        format!("{}:{:02}", m, s)
+    }
+}
+
+/// Turns how much work is done into progress reports
+///
+/// Times are in milliseconds, as [`syscall::now`] gives them. The time
+/// left is estimated from the rate so far, once a second has passed.
+#[derive(Debug, Clone)]
+pub struct Meter {
+    total: u64,
+    started: f64,
+    last: Option<Progress>,
+}
+
+impl Meter {
+    /// Meter `total` units of work starting at `now`; a total of 0 means
+    /// it is unknown
+    pub fn new(total: u64, now: f64) -> Self {
+        Self {
+            total,
+            started: now,
+            last: None,
+        }
+    }
+
+    /// The report for `done` units at `now`, if it differs from the last
+    pub fn update(&mut self, done: u64, now: f64) -> Option<String> {
+        let progress = if self.total == 0 {
+            Progress {
+                state: ProgressState::Indeterminate,
+                percent: 0,
+                eta: None,
+            }
+        } else {
+            let done = done.min(self.total);
+            let elapsed = now - self.started;
+            let eta = (done > 0 && elapsed >= 1000.0).then(|| {
+                let left = elapsed * (self.total - done) as f64 / done as f64;
+                (left / 1000.0).round() as u32
+            });
+            Progress {
+                eta,
+                ..Progress::new((done * 100 / self.total) as u8)
+            }
+        };
+        self.report(progress)
+    }
+
+    /// The report that the work failed where it stands
+    pub fn fail(&mut self) -> String {
+        let percent = self.last.map_or(0, |p| p.percent);
+        let failed = Progress {
+            state: ProgressState::Error,
+            eta: None,
+            ..Progress::new(percent)
+        };
+        self.last = Some(failed);
+        failed.encode()
+    }
+
+    /// The report that the work is over
+    pub fn finish(&mut self) -> String {
+        self.last = None;
+        CLEAR_PROGRESS.to_string()
+    }
+
+    fn report(&mut self, progress: Progress) -> Option<String> {
+        if self.last == Some(progress) {
+            return None;
+        }
+        self.last = Some(progress);
+        Some(progress.encode())
+    }
+}
+
+/// Take the OSC sequences out of `text`, returning what is left to show
+/// and the requests the terminal knows, in order
+///
+/// Unknown and malformed sequences are dropped too; an unterminated one is
+/// left in place.
+pub fn extract(text: &str) -> (String, Vec<OscCommand>) {
+    let mut shown = String::with_capacity(text.len());
+    let mut commands = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("\x1b]") {
+        let body = &rest[start + 2..];
+        let Some((end, terminator)) = body
+            .find('\x07')
+            .map(|i| (i, 1))
+            .into_iter()
+            .chain(body.find("\x1b\\").map(|i| (i, 2)))
+            .min()
+        else {
+            break;
+        };
+        shown.push_str(&rest[..start]);
+        commands.extend(parse(&body[..end]));
+        rest = &body[end + terminator..];
+    }
+    shown.push_str(rest);
+    (shown, commands)
+}
+
+/// Parse the part of a sequence between `ESC ]` and its terminator
+fn parse(body: &str) -> Option<OscCommand> {
+    let (code, arg) = body.split_once(';')?;
+    match code {
+        "0" | "2" => Some(OscCommand::Title(
+            arg.chars().filter(|c| !c.is_control()).collect(),
+        )),
+        "9" => {
+            let mut fields = arg.split(';');
+            if fields.next() != Some("4") {
+                return None;
+            }
+            let state = match fields.next()? {
+                "0" => return Some(OscCommand::Progress(None)),
+                "1" => ProgressState::Normal,
+                "2" => ProgressState::Error,
+                "3" => ProgressState::Indeterminate,
+                "4" => ProgressState::Paused,
+                _ => return None,
+            };
+            let percent = fields.next().map_or(Some(0), |p| p.parse::<u8>().ok())?;
+            let eta = fields.next().and_then(|e| e.parse().ok());
+            Some(OscCommand::Progress(Some(Progress {
+                state,
+                percent: percent.min(100),
+                eta,
+            })))
+        }
+        "52" => {
+            let (_selection, data) = arg.split_once(';')?;
+            if data == "?" || data.len() > MAX_COPY {
+                return None;
+            }
+            decode_base64(data).map(OscCommand::Copy)
+        }
+        _ => None,
+    }
+}
+
+/// Decode standard base64, padded or not
+fn decode_base64(data: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len() / 4 * 3);
+    let (mut bits, mut count) = (0u32, 0);
+    for c in data.trim_end_matches('=').bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        bits = (bits << 6) | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            out.push((bits >> count) as u8);
+        }
+    }
+    Some(out)
+}
+
+/// Copy `data` to the clipboard as the current process
+pub fn copy(data: &[u8]) -> SyscallResult<()> {
+    let fd = syscall::open(CLIPBOARD_DEVICE, OpenFlags::WRITE)?;
+    let result = syscall::write(fd, data).map(|_| ());
+    let _ = syscall::close(fd);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract() {
+        let (shown, commands) = extract("a\x1b]0;build: ok\x07b\x1b]52;c;aGVsbG8=\x1b\\c");
+        assert_eq!(shown, "abc");
+        assert_eq!(
+            commands,
+            [
+                OscCommand::Title("build: ok".into()),
+                OscCommand::Copy(b"hello".to_vec())
+            ]
+        );
+
+        // Queries, other codes and bad payloads are swallowed
+        let (shown, commands) = extract("\x1b]52;c;?\x07\x1b]8;;http://x\x07\x1b]52;c;*\x07x");
+        assert_eq!(shown, "x");
+        assert!(commands.is_empty());
+
+        // Unpadded base64, and an unterminated sequence left alone
+        let (shown, commands) = extract("\x1b]2;t\x1b\\\x1b]52;p;aGk\x07\x1b]0;open");
+        assert_eq!(shown, "\x1b]0;open");
+        assert_eq!(
+            commands,
+            [
+                OscCommand::Title("t".into()),
+                OscCommand::Copy(b"hi".to_vec())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_progress() {
+        let (shown, commands) = extract("a\x1b]9;4;1;42;75\x07b\x1b]9;4;3\x1b\\\x1b]9;4;0;0\x07");
+        assert_eq!(shown, "ab");
+        let running = Progress {
+            eta: Some(75),
+            ..Progress::new(42)
+        };
+        assert_eq!(
+            commands,
+            [
+                OscCommand::Progress(Some(running)),
+                OscCommand::Progress(Some(Progress {
+                    state: ProgressState::Indeterminate,
+                    percent: 0,
+                    eta: None
+                })),
+                OscCommand::Progress(None)
+            ]
+        );
+        assert_eq!(running.render(), "[########------------]  42% 1:15 left");
+        assert_eq!(
+            extract(&running.encode()).1,
+            [OscCommand::Progress(Some(running))]
+        );
+
+        // ConEmu's other OSC 9 requests, and bad states, are dropped
+        assert!(extract("\x1b]9;1;500\x07\x1b]9;4;7;10\x07").1.is_empty());
+    }
+
+    #[test]
+    fn test_meter() {
+        let mut meter = Meter::new(200, 0.0);
+        assert_eq!(meter.update(0, 0.0), Some(Progress::new(0).encode()));
+        // Nothing new to report
+        assert_eq!(meter.update(1, 10.0), None);
+        let report = meter.update(50, 2000.0).unwrap();
+        assert_eq!(report, "\x1b]9;4;1;25;6\x07");
+        assert_eq!(meter.fail(), "\x1b]9;4;2;25\x07");
+        assert_eq!(meter.finish(), CLEAR_PROGRESS);
+
+        let mut unknown = Meter::new(0, 0.0);
+        assert_eq!(unknown.update(5, 0.0), Some("\x1b]9;4;3;0\x07".to_string()));
+        assert_eq!(format_eta(3725), "1:02:05");
+    }
+}
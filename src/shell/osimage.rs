@@ -0,0 +1,343 @@
+//! OS images - the whole system in one portable file
+//!
+//! `osimage export` writes everything that makes this installation what it
+//! is into a single file: the persistent filesystem, which holds the users
+//! (/etc), the installed packages (/var/lib/pkg), IPC state and the command
+//! journal, and the shell session - working directory, environment,
+//! aliases, functions and arrays. `osimage import` replaces the state of
+//! any other installation, in any browser, with it.
+//!
+//! ```text
+//! AXI1 {"format":1,"version":"0.1.0","hostname":"axeberg","encrypted":false}
+//! <payload>
+//! ```
+//!
+//! The first line says what the file is. The payload after it is the JSON
+//! of [`ImageContents`], either sealed with a checksum footer
+//! ([`integrity::seal`]) or encrypted with a password
+//! ([`crypto::encrypt`]), which also authenticates it.
+
+use super::builtins::ShellState;
+use crate::kernel::crypto::{self, CryptoError};
+use crate::vfs::FsSnapshot;
+use crate::vfs::integrity::{self, IntegrityError};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+
+/// First bytes of every image
+pub const MAGIC: &str = "AXI1";
+
+/// Payload format written by this version
+pub const FORMAT: u32 = 1;
+
+/// What the first line says about an image
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageHeader {
+    pub format: u32,
+    /// axeberg version that wrote it
+    pub version: String,
+    /// Hostname of the system it came from
+    pub hostname: String,
+    pub encrypted: bool,
+}
+
+/// The shell session carried in an image
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Session {
+    pub cwd: String,
+    pub env: BTreeMap<String, String>,
+    pub aliases: BTreeMap<String, String>,
+    pub functions: BTreeMap<String, String>,
+    #[serde(default)]
+    pub arrays: BTreeMap<String, Vec<String>>,
+}
+
+impl Session {
+    pub fn capture(state: &ShellState) -> Self {
+        Self {
+            cwd: state.cwd.display().to_string(),
+            env: state.env.clone().into_iter().collect(),
+            aliases: state.aliases.clone().into_iter().collect(),
+            functions: state.functions.clone().into_iter().collect(),
+            arrays: state.arrays.clone().into_iter().collect(),
+        }
+    }
+
+    /// Replace the variables, aliases, functions and arrays of `state`;
+    /// the caller changes to [`Session::cwd`] once the filesystem is back
+    pub fn apply(self, state: &mut ShellState) {
+        state.env = self.env.into_iter().collect();
+        state.aliases = self.aliases.into_iter().collect();
+        state.functions = self.functions.into_iter().collect();
+        state.arrays = self.arrays.into_iter().collect();
+    }
+
+    pub fn cwd(&self) -> PathBuf {
+        PathBuf::from(&self.cwd)
+    }
+}
+
+/// Everything an image restores
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ImageContents {
+    pub session: Session,
+    pub vfs: FsSnapshot,
+}
+
+/// What an `osimage` builtin asks the executor to do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OsImageRequest {
+    /// Write an image to `path` (and offer it as a download in the browser)
+    Export {
+        path: String,
+        password: Option<String>,
+    },
+    /// Replace the system with the image at `path`
+    Import {
+        path: String,
+        password: Option<String>,
+    },
+    /// Show the header of the image at `path`
+    Info { path: String },
+    /// Pick an image on the host and copy it into the current directory
+    Upload,
+}
+
+/// Why an image couldn't be read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImageError {
+    /// The file doesn't start with [`MAGIC`]
+    NotAnImage,
+    /// The header line is unreadable
+    BadHeader(String),
+    /// Written by a newer version in a format this one doesn't know
+    UnsupportedFormat(u32),
+    /// The image is encrypted and no password was given
+    PasswordRequired,
+    Crypto(CryptoError),
+    Integrity(IntegrityError),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::NotAnImage => write!(f, "not an OS image"),
+            ImageError::BadHeader(e) => write!(f, "bad header: {}", e),
+            ImageError::UnsupportedFormat(n) => write!(f, "unsupported image format {}", n),
+            ImageError::PasswordRequired => write!(f, "image is encrypted (use -p PASSWORD)"),
+            ImageError::Crypto(e) => write!(f, "{}", e),
+            ImageError::Integrity(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+/// Serialize `contents` into an image, encrypted if `password` is given
+pub fn write(
+    hostname: &str,
+    contents: &ImageContents,
+    password: Option<&str>,
+) -> Result<Vec<u8>, String> {
+    write_with(hostname, contents, password, crypto::KDF_ITERATIONS)
+}
+
+fn write_with(
+    hostname: &str,
+    contents: &ImageContents,
+    password: Option<&str>,
+    iterations: u32,
+) -> Result<Vec<u8>, String> {
+    let header = ImageHeader {
+        format: FORMAT,
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        hostname: hostname.to_string(),
+        encrypted: password.is_some(),
+    };
+    let payload = serde_json::to_vec(contents).map_err(|e| e.to_string())?;
+    let mut image = format!(
+        "{} {}\n",
+        MAGIC,
+        serde_json::to_string(&header).map_err(|e| e.to_string())?
+    )
+    .into_bytes();
+    match password {
+        Some(password) => image.extend(crypto::encrypt_with(password, &payload, iterations)),
+        None => image.extend(integrity::seal(&payload)),
+    }
+    Ok(image)
+}
+
+/// Read the header line, returning it and the payload after it
+pub fn read_header(image: &[u8]) -> Result<(ImageHeader, &[u8]), ImageError> {
+    let rest = image
+        .strip_prefix(MAGIC.as_bytes())
+        .and_then(|rest| rest.strip_prefix(b" "))
+        .ok_or(ImageError::NotAnImage)?;
+    let end = rest
+        .iter()
+        .position(|&b| b == b'\n')
+        .ok_or_else(|| ImageError::BadHeader("no end of line".into()))?;
+    let header: ImageHeader =
+        serde_json::from_slice(&rest[..end]).map_err(|e| ImageError::BadHeader(e.to_string()))?;
+    if header.format > FORMAT {
+        return Err(ImageError::UnsupportedFormat(header.format));
+    }
+    Ok((header, &rest[end + 1..]))
+}
+
+/// Check, decrypt if needed, and parse an image
+pub fn read(
+    image: &[u8],
+    password: Option<&str>,
+) -> Result<(ImageHeader, ImageContents), ImageError> {
+    let (header, payload) = read_header(image)?;
+    let parse = |data: &[u8]| serde_json::from_slice::<ImageContents>(data);
+    let contents = if header.encrypted {
+        let password = password.ok_or(ImageError::PasswordRequired)?;
+        let plain = crypto::decrypt(password, payload).map_err(ImageError::Crypto)?;
+        parse(&plain).map_err(|e| ImageError::Integrity(IntegrityError::Invalid(e.to_string())))?
+    } else {
+        integrity::open(payload, parse).map_err(ImageError::Integrity)?
+    };
+    Ok((header, contents))
+}
+
+/// Offer `data` to the browser as a download called `name`
+pub fn download(name: &str, data: &[u8]) -> Result<(), String> {
+    use wasm_bindgen::JsCast;
+
+    let document = web_sys::window()
+        .and_then(|w| w.document())
+        .ok_or("No document")?;
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(data));
+    let options = web_sys::BlobPropertyBag::new();
+    options.set_type("application/octet-stream");
+    let blob = web_sys::Blob::new_with_u8_array_sequence_and_options(&parts, &options)
+        .map_err(|e| format!("Failed to create blob: {:?}", e))?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob)
+        .map_err(|e| format!("Failed to create URL: {:?}", e))?;
+
+    let anchor: web_sys::HtmlAnchorElement = document
+        .create_element("a")
+        .map_err(|e| format!("Failed to create link: {:?}", e))?
+        .dyn_into()
+        .map_err(|_| "Failed to cast to HtmlAnchorElement")?;
+    anchor.set_href(&url);
+    anchor.set_download(name);
+    anchor.click();
+    let _ = web_sys::Url::revoke_object_url(&url);
+    Ok(())
+}
+
+/// Ask the user to pick a file; its name and contents, or `None` if the
+/// picker was cancelled
+pub async fn pick_file(accept: &str) -> Result<Option<(String, Vec<u8>)>, String> {
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_futures::JsFuture;
+
+    let document = web_sys::window()
+        .and_then(|w| w.document())
+        .ok_or("No document")?;
+    let input: web_sys::HtmlInputElement = document
+        .create_element("input")
+        .map_err(|e| format!("Failed to create input: {:?}", e))?
+        .dyn_into()
+        .map_err(|_| "Failed to cast to HtmlInputElement")?;
+    input.set_type("file");
+    input.set_accept(accept);
+
+    // Settles on a choice or on cancel; `files` tells which
+    let chosen = js_sys::Promise::new(&mut |resolve, _reject| {
+        input.set_onchange(Some(&resolve));
+        let _ = input.add_event_listener_with_callback("cancel", &resolve);
+    });
+    input.click();
+    JsFuture::from(chosen)
+        .await
+        .map_err(|e| format!("File picker failed: {:?}", e))?;
+
+    let Some(file) = input.files().and_then(|files| files.get(0)) else {
+        return Ok(None);
+    };
+    let buffer = JsFuture::from(file.array_buffer())
+        .await
+        .map_err(|e| format!("Failed to read file: {:?}", e))?;
+    Ok(Some((
+        file.name(),
+        js_sys::Uint8Array::new(&buffer).to_vec(),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::MemoryFs;
+
+    fn contents() -> ImageContents {
+        let mut state = ShellState::new();
+        state.cwd = PathBuf::from("/home/user");
+        state.set_env("EDITOR", "edit");
+        state.set_alias("ll", "ls -l");
+        ImageContents {
+            session: Session::capture(&state),
+            vfs: MemoryFs::new().snapshot(),
+        }
+    }
+
+    #[test]
+    fn test_plain_image() {
+        let image = write("axeberg", &contents(), None).unwrap();
+        assert!(image.starts_with(b"AXI1 {\"format\":1,"));
+
+        let (header, read_back) = read(&image, None).unwrap();
+        assert!(!header.encrypted);
+        assert_eq!(header.hostname, "axeberg");
+        assert_eq!(read_back.session, contents().session);
+        assert_eq!(read_back.session.cwd(), PathBuf::from("/home/user"));
+
+        let mut state = ShellState::new();
+        read_back.session.apply(&mut state);
+        assert_eq!(state.get_alias("ll"), Some("ls -l"));
+
+        // A damaged payload is caught by its checksum
+        let mut damaged = image.clone();
+        let payload = damaged.iter().position(|&b| b == b'\n').unwrap() + 1;
+        damaged[payload + 10] ^= 1;
+        assert!(matches!(
+            read(&damaged, None),
+            Err(ImageError::Integrity(
+                IntegrityError::ChecksumMismatch { .. }
+            ))
+        ));
+        assert_eq!(
+            read_header(b"{\"nodes\":{}}").unwrap_err(),
+            ImageError::NotAnImage
+        );
+    }
+
+    #[test]
+    fn test_encrypted_image() {
+        let image = write_with("axeberg", &contents(), Some("s3cret"), 10).unwrap();
+        assert!(!image.windows(6).any(|w| w == b"EDITOR"));
+
+        let (header, read_back) = read(&image, Some("s3cret")).unwrap();
+        assert!(header.encrypted);
+        assert_eq!(read_back.session, contents().session);
+
+        assert_eq!(read(&image, None).err(), Some(ImageError::PasswordRequired));
+        assert_eq!(
+            read(&image, Some("guess")).err(),
+            Some(ImageError::Crypto(CryptoError::BadPassword))
+        );
+
+        // Newer formats are refused rather than misread
+        let newer = String::from_utf8_lossy(&image).replacen("\"format\":1", "\"format\":9", 1);
+        assert_eq!(
+            read_header(newer.as_bytes()).unwrap_err(),
+            ImageError::UnsupportedFormat(9)
+        );
+    }
+}
@@ -18,6 +18,9 @@ pub struct SimpleCommand {
     pub program: String,
     /// Arguments (not including program name)
     pub args: Vec<String>,
+    /// Byte offsets of the `$`s in each argument that were single-quoted,
+    /// and so stay as written (`echo '$?'`)
+    pub quoted_dollars: Vec<Vec<usize>>,
     /// Input redirection: < file
     pub stdin: Option<Redirect>,
     /// Output redirection: > file or >> file
@@ -33,6 +36,7 @@ impl SimpleCommand {
         Self {
             program: program.into(),
             args: Vec::new(),
+            quoted_dollars: Vec::new(),
             stdin: None,
             stdout: None,
             stderr: None,
@@ -143,6 +147,10 @@ pub struct Pipeline {
     pub commands: Vec<SimpleCommand>,
     /// Run in background (&)
     pub background: bool,
+    /// Prefixed with the `time` keyword: report what it used to stderr
+    pub timed: bool,
+    /// `time -p`: report only real, user and sys time, in seconds
+    pub posix_time: bool,
 }
 
 impl Pipeline {
@@ -150,6 +158,8 @@ impl Pipeline {
         Self {
             commands: vec![cmd],
             background: false,
+            timed: false,
+            posix_time: false,
         }
     }
 
@@ -162,6 +172,11 @@ impl Pipeline {
         self.background = true;
         self
     }
+
+    pub fn timed(mut self) -> Self {
+        self.timed = true;
+        self
+    }
 }
 
 /// Parse error
@@ -332,8 +347,9 @@ pub enum ParsedLine {
 /// Token types
 #[derive(Debug, Clone, PartialEq, Eq)]
 enum Token {
-    /// A word (program name, argument, filename)
-    Word(String),
+    /// A word (program name, argument, filename), with the byte offsets
+    /// of the `$`s in it that were single-quoted
+    Word(String, Vec<usize>),
     /// Pipe: |
     Pipe,
     /// Input redirect: <
@@ -505,6 +521,7 @@ impl<'a> Lexer<'a> {
 
     fn read_word(&mut self) -> Result<Option<Token>, ParseError> {
         let mut word = String::new();
+        let mut quoted = Vec::new();
 
         while let Some(&c) = self.chars.peek() {
             match c {
@@ -516,7 +533,11 @@ impl<'a> Lexer<'a> {
                 // Quotes can appear mid-word: foo"bar"baz
                 '"' | '\'' => {
                     self.chars.next();
-                    word.push_str(&self.read_quoted_content(c)?);
+                    let content = self.read_quoted_content(c)?;
+                    if c == '\'' {
+                        quoted.extend(content.match_indices('$').map(|(i, _)| word.len() + i));
+                    }
+                    word.push_str(&content);
                 }
                 _ => {
                     word.push(c);
@@ -528,14 +549,18 @@ impl<'a> Lexer<'a> {
         if word.is_empty() {
             Ok(None)
         } else {
-            Ok(Some(Token::Word(word)))
+            Ok(Some(Token::Word(word, quoted)))
         }
     }
 
     fn read_quoted_string(&mut self, quote: char) -> Result<Option<Token>, ParseError> {
         self.chars.next(); // consume opening quote
         let content = self.read_quoted_content(quote)?;
-        Ok(Some(Token::Word(content)))
+        let quoted = match quote {
+            '\'' => content.match_indices('$').map(|(i, _)| i).collect(),
+            _ => Vec::new(),
+        };
+        Ok(Some(Token::Word(content, quoted)))
     }
 
     fn read_quoted_content(&mut self, quote: char) -> Result<String, ParseError> {
@@ -591,7 +616,7 @@ fn try_parse_array(input: &str) -> Result<Option<ArrayAssignment>, ParseError> {
 
     // First token must be a word (array name, possibly with = or +=)
     let first_word = match lexer.next_token()? {
-        Some(Token::Word(w)) => w,
+        Some(Token::Word(w, _)) => w,
         _ => return Ok(None),
     };
 
@@ -648,7 +673,7 @@ fn try_parse_array(input: &str) -> Result<Option<ArrayAssignment>, ParseError> {
         Some(Token::LeftBracket) => {
             // Get index
             let index = match lexer.next_token()? {
-                Some(Token::Word(w)) => w.parse::<usize>().map_err(|_| {
+                Some(Token::Word(w, _)) => w.parse::<usize>().map_err(|_| {
                     ParseError::UnexpectedToken(format!("invalid array index: {}", w))
                 })?,
                 _ => return Ok(None),
@@ -662,7 +687,7 @@ fn try_parse_array(input: &str) -> Result<Option<ArrayAssignment>, ParseError> {
 
             // Get value (which includes the =)
             let value_with_eq = match lexer.next_token()? {
-                Some(Token::Word(w)) => w,
+                Some(Token::Word(w, _)) => w,
                 None => return Ok(None),
                 _ => return Ok(None),
             };
@@ -685,7 +710,7 @@ fn collect_array_elements(lexer: &mut Lexer) -> Result<Vec<String>, ParseError>
     loop {
         match lexer.next_token()? {
             Some(Token::RightParen) => break,
-            Some(Token::Word(w)) => elements.push(w),
+            Some(Token::Word(w, _)) => elements.push(w),
             None => return Err(ParseError::UnexpectedEnd),
             Some(t) => {
                 return Err(ParseError::UnexpectedToken(format!(
@@ -707,7 +732,7 @@ fn try_parse_function(input: &str) -> Result<Option<ShellFunction>, ParseError>
 
     // First token must be a word (function name)
     let name = match lexer.next_token()? {
-        Some(Token::Word(w)) => w,
+        Some(Token::Word(w, _)) => w,
         _ => return Ok(None),
     };
 
@@ -747,7 +772,7 @@ fn try_parse_function(input: &str) -> Result<Option<ShellFunction>, ParseError>
                 }
                 body_parts.push("}".to_string());
             }
-            Some(Token::Word(w)) => body_parts.push(w),
+            Some(Token::Word(w, _)) => body_parts.push(w),
             Some(Token::Pipe) => body_parts.push("|".to_string()),
             Some(Token::RedirectIn) => body_parts.push("<".to_string()),
             Some(Token::RedirectOut) => body_parts.push(">".to_string()),
@@ -800,11 +825,14 @@ fn parse_pipeline_internal(
 ) -> Result<(Pipeline, Option<LogicalOp>), ParseError> {
     let mut commands = Vec::new();
     let mut current_words = Vec::new();
+    let mut current_quoted = Vec::new();
     let mut stdin = None;
     let mut stdout = None;
     let mut stderr = None;
     let mut heredoc = None;
     let mut background = false;
+    let mut timed = false;
+    let mut posix_time = false;
     let mut trailing_op: Option<LogicalOp> = None;
     let mut expecting_command = true; // True at start and after pipe
 
@@ -830,8 +858,25 @@ fn parse_pipeline_internal(
         };
 
         match token {
-            Token::Word(w) => {
+            // `time` before the first command times the whole pipeline
+            Token::Word(w, _)
+                if w == "time" && !timed && commands.is_empty() && current_words.is_empty() =>
+            {
+                timed = true;
+            }
+            // `time -p` reports in the POSIX format
+            Token::Word(w, _)
+                if w == "-p"
+                    && timed
+                    && !posix_time
+                    && commands.is_empty()
+                    && current_words.is_empty() =>
+            {
+                posix_time = true;
+            }
+            Token::Word(w, quoted) => {
                 current_words.push(w);
+                current_quoted.push(quoted);
                 expecting_command = false;
             }
             Token::Pipe => {
@@ -840,6 +885,7 @@ fn parse_pipeline_internal(
                 }
                 let cmd = build_command(
                     &mut current_words,
+                    &mut current_quoted,
                     stdin.take(),
                     stdout.take(),
                     stderr.take(),
@@ -918,12 +964,19 @@ fn parse_pipeline_internal(
 
     // Build final command
     if current_words.is_empty() {
-        if expecting_command {
-            // Either empty input, or trailing pipe
+        // Either empty input, or trailing pipe; `time` alone times nothing
+        if expecting_command && !(timed && commands.is_empty()) {
             return Err(ParseError::EmptyCommand);
         }
     } else {
-        let cmd = build_command(&mut current_words, stdin, stdout, stderr, heredoc);
+        let cmd = build_command(
+            &mut current_words,
+            &mut current_quoted,
+            stdin,
+            stdout,
+            stderr,
+            heredoc,
+        );
         commands.push(cmd);
     }
 
@@ -931,6 +984,8 @@ fn parse_pipeline_internal(
         Pipeline {
             commands,
             background,
+            timed,
+            posix_time,
         },
         trailing_op,
     ))
@@ -944,7 +999,7 @@ pub fn parse(input: &str) -> Result<Pipeline, ParseError> {
 
 fn expect_word(lexer: &mut Lexer) -> Result<String, ParseError> {
     match lexer.next_token()? {
-        Some(Token::Word(w)) => Ok(w),
+        Some(Token::Word(w, _)) => Ok(w),
         Some(t) => Err(ParseError::UnexpectedToken(format!("{:?}", t))),
         None => Err(ParseError::MissingRedirectTarget),
     }
@@ -952,6 +1007,7 @@ fn expect_word(lexer: &mut Lexer) -> Result<String, ParseError> {
 
 fn build_command(
     words: &mut Vec<String>,
+    quoted: &mut Vec<Vec<usize>>,
     stdin: Option<Redirect>,
     stdout: Option<Redirect>,
     stderr: Option<Redirect>,
@@ -959,9 +1015,12 @@ fn build_command(
 ) -> SimpleCommand {
     let program = words.remove(0);
     let args = std::mem::take(words);
+    quoted.remove(0);
+    let quoted_dollars = std::mem::take(quoted);
     SimpleCommand {
         program,
         args,
+        quoted_dollars,
         stdin,
         stdout,
         stderr,
@@ -1212,6 +1271,32 @@ mod tests {
         assert!(result.background);
     }
 
+    // ============ Time ============
+
+    #[test]
+    fn test_time_keyword() {
+        let result = parse("time sort data | uniq").unwrap();
+        assert!(result.timed);
+        assert_eq!(result.commands.len(), 2);
+        assert_eq!(result.commands[0].program, "sort");
+
+        // Only in front of the first command
+        let result = parse("echo time | time").unwrap();
+        assert!(!result.timed);
+        assert_eq!(result.commands[0].args, vec!["time"]);
+        assert_eq!(result.commands[1].program, "time");
+
+        let result = parse("time").unwrap();
+        assert!(result.timed && result.commands.is_empty());
+        assert!(parse("time echo |").is_err());
+
+        let result = parse("time -p sort -p").unwrap();
+        assert!(result.timed && result.posix_time);
+        assert_eq!(result.commands[0].program, "sort");
+        assert_eq!(result.commands[0].args, vec!["-p"]);
+        assert!(!parse("echo -p").unwrap().posix_time);
+    }
+
     // ============ Complex Cases ============
 
     #[test]
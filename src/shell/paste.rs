@@ -0,0 +1,273 @@
+//! Bracketed paste
+//!
+//! With bracketed paste mode on (`ESC [ ? 2004 h`), the terminal wraps
+//! pasted text in markers so it can be told apart from typing:
+//!
+//! ```text
+//! ESC [ 200 ~  pasted text  ESC [ 201 ~
+//! ```
+//!
+//! A paste goes into the input line as it is instead of running line by
+//! line. One with line breaks in it would run commands as soon as it
+//! lands, so the terminal asks first ([`MultiLinePaste`]); the editor
+//! takes the block whole.
+
+/// Turns bracketed paste mode on
+pub const ENABLE: &str = "\x1b[?2004h";
+
+/// Turns bracketed paste mode off
+pub const DISABLE: &str = "\x1b[?2004l";
+
+/// Comes before pasted text
+pub const START: &str = "\x1b[200~";
+
+/// Comes after pasted text
+pub const END: &str = "\x1b[201~";
+
+/// Longest paste kept, in bytes; the rest is dropped
+pub const MAX_PASTE: usize = 1 << 20;
+
+/// Commands listed when asking about a multi-line paste
+pub const PREVIEW_LINES: usize = 8;
+
+/// Terminal input, with pastes told apart from typing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    /// Keys typed, as the terminal sent them
+    Typed(String),
+    /// A paste, markers taken off and [`normalize`]d
+    Pasted(String),
+}
+
+/// Splits terminal input into typing and pastes
+///
+/// A paste can arrive over several chunks; it comes out once its end
+/// marker has.
+#[derive(Debug, Default)]
+pub struct PasteDecoder {
+    /// The paste so far, while inside one
+    paste: Option<String>,
+}
+
+impl PasteDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether a paste has started and not ended
+    pub fn in_paste(&self) -> bool {
+        self.paste.is_some()
+    }
+
+    /// Take a chunk of terminal input
+    pub fn feed(&mut self, data: &str) -> Vec<Input> {
+        let mut inputs = Vec::new();
+        let mut rest = data;
+        loop {
+            match self.paste.as_mut() {
+                Some(paste) => {
+                    // Search from a little before the new data, in case
+                    // the end marker was split between chunks
+                    let from = paste.len().saturating_sub(END.len() - 1);
+                    let from = paste.floor_char_boundary(from);
+                    paste.push_str(rest);
+                    let Some(end) = paste[from..].find(END).map(|i| from + i) else {
+                        if paste.len() > MAX_PASTE + END.len() {
+                            // Drop the middle, keeping enough of the tail
+                            // to see the marker
+                            let cut = paste.floor_char_boundary(MAX_PASTE);
+                            let keep = paste.len() - (END.len() - 1);
+                            let keep = paste.floor_char_boundary(keep);
+                            paste.replace_range(cut..keep, "");
+                        }
+                        break;
+                    };
+                    let after = paste.len() - end - END.len();
+                    let kept = paste.floor_char_boundary(end.min(MAX_PASTE));
+                    let text = normalize(&paste[..kept]);
+                    inputs.push(Input::Pasted(text));
+                    rest = &data[data.len() - after..];
+                    self.paste = None;
+                }
+                None => {
+                    let Some(start) = rest.find(START) else {
+                        if !rest.is_empty() {
+                            inputs.push(Input::Typed(rest.to_string()));
+                        }
+                        break;
+                    };
+                    if start > 0 {
+                        inputs.push(Input::Typed(rest[..start].to_string()));
+                    }
+                    self.paste = Some(String::new());
+                    rest = &rest[start + START.len()..];
+                }
+            }
+        }
+        inputs
+    }
+}
+
+/// Pasted text as it should be inserted: line breaks made `\n`, and
+/// control characters other than tabs and line breaks (escape sequences
+/// included) taken out
+pub fn normalize(text: &str) -> String {
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => {
+                // Drop a CSI sequence whole, or just the escape
+                if chars.next_if_eq(&'[').is_some() {
+                    while chars.next_if(|c| !('\x40'..='\x7e').contains(c)).is_some() {}
+                    chars.next();
+                }
+            }
+            '\n' | '\t' => out.push(c),
+            c if c.is_control() => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// A paste with line breaks, held until it is confirmed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultiLinePaste {
+    /// Complete lines to run, blank ones left out
+    pub commands: Vec<String>,
+    /// The unfinished last line, left in the input line
+    pub rest: String,
+}
+
+impl MultiLinePaste {
+    /// Split an input line with a paste in it into commands and the
+    /// unfinished rest; `None` if there is no complete command
+    pub fn split(line: &str) -> Option<Self> {
+        let (complete, rest) = line.rsplit_once('\n')?;
+        let commands: Vec<String> = complete
+            .split('\n')
+            .filter(|l| !l.trim().is_empty())
+            .map(String::from)
+            .collect();
+        if commands.is_empty() {
+            return None;
+        }
+        Some(Self {
+            commands,
+            rest: rest.to_string(),
+        })
+    }
+
+    /// Lines asking whether to run the commands, listing the first
+    /// [`PREVIEW_LINES`]; the last is the question
+    pub fn question(&self) -> Vec<String> {
+        let n = self.commands.len();
+        let mut lines = vec![format!(
+            "Pasted text has {} command{}:",
+            n,
+            if n == 1 { "" } else { "s" }
+        )];
+        lines.extend(
+            self.commands
+                .iter()
+                .take(PREVIEW_LINES)
+                .map(|c| format!("  {}", c)),
+        );
+        if n > PREVIEW_LINES {
+            lines.push(format!("  ... and {} more", n - PREVIEW_LINES));
+        }
+        lines.push(format!(
+            "Run {}? [y/N] ",
+            if n == 1 { "it" } else { "them" }
+        ));
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decoder() {
+        let mut decoder = PasteDecoder::new();
+        assert_eq!(decoder.feed("ls"), vec![Input::Typed("ls".into())]);
+        assert_eq!(
+            decoder.feed("a\x1b[200~one\r\ntwo\x1b[201~b"),
+            vec![
+                Input::Typed("a".into()),
+                Input::Pasted("one\ntwo".into()),
+                Input::Typed("b".into()),
+            ]
+        );
+
+        // Split across chunks, the end marker too
+        assert!(decoder.feed("\x1b[200~echo é").is_empty());
+        assert!(decoder.in_paste());
+        assert!(decoder.feed("\x1b[20").is_empty());
+        assert_eq!(
+            decoder.feed("1~x"),
+            vec![Input::Pasted("echo é".into()), Input::Typed("x".into())]
+        );
+        assert!(!decoder.in_paste());
+
+        // An empty paste still counts
+        assert_eq!(
+            decoder.feed("\x1b[200~\x1b[201~"),
+            vec![Input::Pasted(String::new())]
+        );
+    }
+
+    #[test]
+    fn test_decoder_limit() {
+        let mut decoder = PasteDecoder::new();
+        decoder.feed(START);
+        let chunk = "x".repeat(MAX_PASTE / 2);
+        for _ in 0..3 {
+            assert!(decoder.feed(&chunk).is_empty());
+        }
+        let inputs = decoder.feed(&format!("yy{}", END));
+        let [Input::Pasted(text)] = inputs.as_slice() else {
+            panic!("expected a paste, got {:?}", inputs);
+        };
+        assert_eq!(text.len(), MAX_PASTE);
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("a\r\nb\rc\n"), "a\nb\nc\n");
+        assert_eq!(normalize("\tif x\x07"), "\tif x");
+        assert_eq!(normalize("red \x1b[31mtext\x1b[0m"), "red text");
+        assert_eq!(normalize("a\x1b[201~b"), "ab");
+    }
+
+    #[test]
+    fn test_multi_line_paste() {
+        assert_eq!(MultiLinePaste::split("echo hi"), None);
+        assert_eq!(MultiLinePaste::split("\n  \nls"), None);
+
+        let paste = MultiLinePaste::split("cd /tmp\n\nls -l\nech").unwrap();
+        assert_eq!(paste.commands, vec!["cd /tmp", "ls -l"]);
+        assert_eq!(paste.rest, "ech");
+        assert_eq!(
+            paste.question(),
+            vec![
+                "Pasted text has 2 commands:",
+                "  cd /tmp",
+                "  ls -l",
+                "Run them? [y/N] ",
+            ]
+        );
+
+        let paste = MultiLinePaste::split("rm -rf build\n").unwrap();
+        assert_eq!(paste.rest, "");
+        assert_eq!(paste.question().last().unwrap(), "Run it? [y/N] ");
+
+        let many = "true\n".repeat(PREVIEW_LINES + 2);
+        let question = MultiLinePaste::split(&many).unwrap().question();
+        assert_eq!(question.len(), PREVIEW_LINES + 3);
+        assert_eq!(question[PREVIEW_LINES + 1], "  ... and 2 more");
+    }
+}
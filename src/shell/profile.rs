@@ -0,0 +1,630 @@
+//! Terminal profiles
+//!
+//! A profile bundles how a terminal window looks and starts: font, color
+//! overrides on top of the built-in Tokyo Night theme, the directory it
+//! opens in and a command to run first. Profiles live in
+//! [`PROFILES_PATH`], a small TOML file:
+//!
+//! ```text
+//! default = "work"
+//!
+//! [work]
+//! font_size = 15
+//! cwd = "/home/user/src"
+//! startup = "git status"
+//!
+//! [work.colors]
+//! background = "#101010"
+//! ```
+//!
+//! When the file is missing, the defaults shipped in the read-only system
+//! partition ([`SYSTEM_PROFILES_PATH`]) are used, and failing that a single
+//! `default` profile with the built-in look.
+
+use super::programs::read_file_content;
+use crate::kernel::syscall;
+use std::collections::BTreeMap;
+
+/// Where profiles are stored
+pub const PROFILES_PATH: &str = "/etc/terminal/profiles.toml";
+
+/// Default profiles in the system partition
+pub const SYSTEM_PROFILES_PATH: &str = "/system/etc/terminal/profiles.toml";
+
+/// Name of the profile used when none is configured
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Default font size in pixels
+pub const DEFAULT_FONT_SIZE: u32 = 14;
+
+/// Default font stack
+pub const DEFAULT_FONT_FAMILY: &str = "'JetBrains Mono', 'Fira Code', 'Cascadia Code', monospace";
+
+/// Smallest and largest accepted font size
+pub const FONT_SIZE_RANGE: (u32, u32) = (6, 72);
+
+/// Theme colors (xterm.js theme keys) and their built-in values
+pub const THEME: &[(&str, &str)] = &[
+    ("foreground", "#c0caf5"),
+    ("background", "#1a1b26"),
+    ("cursor", "#7aa2f7"),
+    ("cursorAccent", "#1a1b26"),
+    ("selectionBackground", "#33467c"),
+    ("black", "#15161e"),
+    ("red", "#f7768e"),
+    ("green", "#9ece6a"),
+    ("yellow", "#e0af68"),
+    ("blue", "#7aa2f7"),
+    ("magenta", "#bb9af7"),
+    ("cyan", "#7dcfff"),
+    ("white", "#a9b1d6"),
+    ("brightBlack", "#414868"),
+    ("brightRed", "#f7768e"),
+    ("brightGreen", "#9ece6a"),
+    ("brightYellow", "#e0af68"),
+    ("brightBlue", "#7aa2f7"),
+    ("brightMagenta", "#bb9af7"),
+    ("brightCyan", "#7dcfff"),
+    ("brightWhite", "#c0caf5"),
+];
+
+/// Named themes as overrides of [`THEME`]; `tokyo-night` is the built-in look
+pub const THEMES: &[(&str, &[(&str, &str)])] = &[
+    ("tokyo-night", &[]),
+    (
+        "light",
+        &[
+            ("foreground", "#343b58"),
+            ("background", "#e6e7ed"),
+            ("cursor", "#34548a"),
+            ("cursorAccent", "#e6e7ed"),
+            ("selectionBackground", "#c0c4d6"),
+            ("white", "#343b58"),
+            ("brightWhite", "#1a1b26"),
+        ],
+    ),
+    (
+        "solarized",
+        &[
+            ("foreground", "#839496"),
+            ("background", "#002b36"),
+            ("cursor", "#93a1a1"),
+            ("cursorAccent", "#002b36"),
+            ("selectionBackground", "#073642"),
+        ],
+    ),
+    (
+        "high-contrast",
+        &[
+            ("foreground", "#ffffff"),
+            ("background", "#000000"),
+            ("cursor", "#ffff00"),
+            ("cursorAccent", "#000000"),
+            ("selectionBackground", "#5c5c5c"),
+        ],
+    ),
+];
+
+/// Settings of one terminal profile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TerminalProfile {
+    pub name: String,
+    pub font_size: u32,
+    pub font_family: String,
+    /// Overrides of [`THEME`] colors, by theme key
+    pub colors: BTreeMap<String, String>,
+    /// Command run when the terminal starts
+    pub startup: Option<String>,
+    /// Directory the terminal starts in
+    pub cwd: Option<String>,
+}
+
+impl TerminalProfile {
+    /// Create a profile with the built-in look
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            font_size: DEFAULT_FONT_SIZE,
+            font_family: DEFAULT_FONT_FAMILY.to_string(),
+            colors: BTreeMap::new(),
+            startup: None,
+            cwd: None,
+        }
+    }
+
+    /// Color for a theme key, with the override applied
+    pub fn color(&self, key: &str) -> Option<&str> {
+        self.colors.get(key).map(String::as_str).or_else(|| {
+            THEME
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, value)| *value)
+        })
+    }
+
+    /// The full theme, overrides applied, in [`THEME`] order
+    pub fn theme(&self) -> Vec<(&'static str, &str)> {
+        THEME
+            .iter()
+            .map(|(key, default)| {
+                let value = self.colors.get(*key).map_or(*default, String::as_str);
+                (*key, value)
+            })
+            .collect()
+    }
+
+    /// Get a setting as text (`colors.KEY` for colors)
+    pub fn get(&self, key: &str) -> Option<String> {
+        match key {
+            "font_size" => Some(self.font_size.to_string()),
+            "font_family" => Some(self.font_family.clone()),
+            "startup" => self.startup.clone(),
+            "cwd" => self.cwd.clone(),
+            _ => self.color(key.strip_prefix("colors.")?).map(str::to_string),
+        }
+    }
+
+    /// Change a setting (`colors.KEY` for colors); an empty value clears
+    /// `startup`, `cwd` and color overrides
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "font_size" => {
+                let size: u32 = value
+                    .parse()
+                    .map_err(|_| format!("font_size: invalid number '{}'", value))?;
+                let (min, max) = FONT_SIZE_RANGE;
+                if !(min..=max).contains(&size) {
+                    return Err(format!("font_size: must be between {} and {}", min, max));
+                }
+                self.font_size = size;
+            }
+            "font_family" => {
+                if value.is_empty() {
+                    return Err("font_family: must not be empty".to_string());
+                }
+                self.font_family = value.to_string();
+            }
+            "startup" => self.startup = (!value.is_empty()).then(|| value.to_string()),
+            "cwd" => {
+                if !value.is_empty() && !value.starts_with('/') {
+                    return Err(format!("cwd: '{}' is not an absolute path", value));
+                }
+                self.cwd = (!value.is_empty()).then(|| value.to_string());
+            }
+            _ => {
+                let color = key
+                    .strip_prefix("colors.")
+                    .ok_or_else(|| format!("unknown setting '{}'", key))?;
+                self.set_color(color, value)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn set_color(&mut self, key: &str, value: &str) -> Result<(), String> {
+        if !THEME.iter().any(|(k, _)| *k == key) {
+            return Err(format!("unknown color '{}'", key));
+        }
+        if value.is_empty() {
+            self.colors.remove(key);
+            return Ok(());
+        }
+        if !is_hex_color(value) {
+            return Err(format!(
+                "{}: '{}' is not a #rrggbb or #rrggbbaa color",
+                key, value
+            ));
+        }
+        self.colors.insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    /// Replace the color overrides with a named theme from [`THEMES`]
+    pub fn set_theme(&mut self, name: &str) -> Result<(), String> {
+        let (_, colors) = THEMES
+            .iter()
+            .find(|(theme, _)| *theme == name)
+            .ok_or_else(|| format!("unknown theme '{}'", name))?;
+        self.colors = colors
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        Ok(())
+    }
+
+    /// Every setting as `key = value` pairs, in file order
+    pub fn settings(&self) -> Vec<(String, String)> {
+        let mut out = vec![
+            ("font_size".to_string(), self.font_size.to_string()),
+            ("font_family".to_string(), self.font_family.clone()),
+        ];
+        if let Some(cwd) = &self.cwd {
+            out.push(("cwd".to_string(), cwd.clone()));
+        }
+        if let Some(startup) = &self.startup {
+            out.push(("startup".to_string(), startup.clone()));
+        }
+        out.extend(
+            self.colors
+                .iter()
+                .map(|(k, v)| (format!("colors.{}", k), v.clone())),
+        );
+        out
+    }
+}
+
+/// Check for `#rrggbb` or `#rrggbbaa`
+fn is_hex_color(value: &str) -> bool {
+    value
+        .strip_prefix('#')
+        .is_some_and(|hex| matches!(hex.len(), 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Check if a profile name is usable as a TOML table name
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// All profiles and which one is the default
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileSet {
+    /// Name of the default profile
+    pub default: String,
+    /// Profiles in file order
+    pub profiles: Vec<TerminalProfile>,
+}
+
+impl Default for ProfileSet {
+    fn default() -> Self {
+        Self {
+            default: DEFAULT_PROFILE.to_string(),
+            profiles: vec![TerminalProfile::new(DEFAULT_PROFILE)],
+        }
+    }
+}
+
+impl ProfileSet {
+    /// Look up a profile by name
+    pub fn get(&self, name: &str) -> Option<&TerminalProfile> {
+        self.profiles.iter().find(|p| p.name == name)
+    }
+
+    /// Look up a profile by name, mutably
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut TerminalProfile> {
+        self.profiles.iter_mut().find(|p| p.name == name)
+    }
+
+    /// The default profile, or the built-in look if it doesn't exist
+    pub fn default_profile(&self) -> TerminalProfile {
+        self.get(&self.default)
+            .cloned()
+            .unwrap_or_else(|| TerminalProfile::new(&self.default))
+    }
+
+    /// Resolve an optional profile name, falling back to the default
+    pub fn resolve(&self, name: Option<&str>) -> Result<TerminalProfile, String> {
+        match name {
+            Some(name) => self
+                .get(name)
+                .cloned()
+                .ok_or_else(|| format!("no such profile '{}'", name)),
+            None => Ok(self.default_profile()),
+        }
+    }
+
+    /// Add a profile copied from `from` (or the built-in look)
+    pub fn create(&mut self, name: &str, from: Option<&str>) -> Result<(), String> {
+        if !is_valid_name(name) {
+            return Err(format!("invalid profile name '{}'", name));
+        }
+        if self.get(name).is_some() {
+            return Err(format!("profile '{}' already exists", name));
+        }
+        let mut profile = match from {
+            Some(from) => self
+                .get(from)
+                .cloned()
+                .ok_or_else(|| format!("no such profile '{}'", from))?,
+            None => TerminalProfile::new(name),
+        };
+        profile.name = name.to_string();
+        self.profiles.push(profile);
+        Ok(())
+    }
+
+    /// Remove a profile; the default profile can't be removed
+    pub fn remove(&mut self, name: &str) -> Result<(), String> {
+        if name == self.default {
+            return Err(format!("'{}' is the default profile", name));
+        }
+        let before = self.profiles.len();
+        self.profiles.retain(|p| p.name != name);
+        if self.profiles.len() == before {
+            return Err(format!("no such profile '{}'", name));
+        }
+        Ok(())
+    }
+
+    /// Make an existing profile the default
+    pub fn set_default(&mut self, name: &str) -> Result<(), String> {
+        if self.get(name).is_none() {
+            return Err(format!("no such profile '{}'", name));
+        }
+        self.default = name.to_string();
+        Ok(())
+    }
+
+    /// Parse a profiles file
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let mut default = None;
+        let mut profiles: Vec<TerminalProfile> = Vec::new();
+        // (profile index, in the colors subtable)
+        let mut section: Option<(usize, bool)> = None;
+
+        for (i, raw) in content.lines().enumerate() {
+            let line = strip_comment(raw).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let err = |msg: String| format!("line {}: {}", i + 1, msg);
+
+            if let Some(header) = line.strip_prefix('[') {
+                let header = header
+                    .strip_suffix(']')
+                    .ok_or_else(|| err("unterminated table header".to_string()))?
+                    .trim();
+                let (name, colors) = match header.strip_suffix(".colors") {
+                    Some(name) => (name, true),
+                    None => (header, false),
+                };
+                if !is_valid_name(name) {
+                    return Err(err(format!("invalid profile name '{}'", name)));
+                }
+                let index = match profiles.iter().position(|p| p.name == name) {
+                    Some(_) if !colors => {
+                        return Err(err(format!("duplicate profile '{}'", name)));
+                    }
+                    Some(index) => index,
+                    None => {
+                        profiles.push(TerminalProfile::new(name));
+                        profiles.len() - 1
+                    }
+                };
+                section = Some((index, colors));
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| err(format!("expected key = value, got '{}'", line)))?;
+            let key = key.trim();
+            let value = parse_value(value.trim()).map_err(&err)?;
+
+            match section {
+                None if key == "default" => default = Some(value),
+                None => return Err(err(format!("unknown top-level key '{}'", key))),
+                Some((index, colors)) => {
+                    let key = if colors {
+                        format!("colors.{}", key)
+                    } else {
+                        key.to_string()
+                    };
+                    profiles[index].set(&key, &value).map_err(&err)?;
+                }
+            }
+        }
+
+        if profiles.is_empty() {
+            profiles.push(TerminalProfile::new(DEFAULT_PROFILE));
+        }
+        let default = match default {
+            Some(name) if profiles.iter().any(|p| p.name == name) => name,
+            Some(name) => return Err(format!("default profile '{}' is not defined", name)),
+            None => profiles[0].name.clone(),
+        };
+        Ok(Self { default, profiles })
+    }
+
+    /// Serialize to the profiles file format
+    pub fn to_toml(&self) -> String {
+        let mut out = String::from("# Terminal profiles (see `profile --help`)\n");
+        out.push_str(&format!("default = {}\n", quote(&self.default)));
+        for profile in &self.profiles {
+            out.push_str(&format!("\n[{}]\n", profile.name));
+            out.push_str(&format!("font_size = {}\n", profile.font_size));
+            out.push_str(&format!("font_family = {}\n", quote(&profile.font_family)));
+            if let Some(cwd) = &profile.cwd {
+                out.push_str(&format!("cwd = {}\n", quote(cwd)));
+            }
+            if let Some(startup) = &profile.startup {
+                out.push_str(&format!("startup = {}\n", quote(startup)));
+            }
+            if !profile.colors.is_empty() {
+                out.push_str(&format!("\n[{}.colors]\n", profile.name));
+                for (key, value) in &profile.colors {
+                    out.push_str(&format!("{} = {}\n", key, quote(value)));
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Drop a `#` comment, ignoring `#` inside strings
+pub(crate) fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Parse a basic string or a bare integer
+pub(crate) fn parse_value(value: &str) -> Result<String, String> {
+    if let Some(inner) = value.strip_prefix('"') {
+        let inner = inner
+            .strip_suffix('"')
+            .ok_or_else(|| format!("unterminated string {}", value))?;
+        let mut out = String::with_capacity(inner.len());
+        let mut chars = inner.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                out.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('n') => out.push('\n'),
+                Some('t') => out.push('\t'),
+                Some(c @ ('"' | '\\')) => out.push(c),
+                other => {
+                    return Err(format!(
+                        "invalid escape '\\{}'",
+                        other.map(String::from).unwrap_or_default()
+                    ));
+                }
+            }
+        }
+        return Ok(out);
+    }
+    if !value.is_empty() && value.chars().all(|c| c.is_ascii_digit()) {
+        return Ok(value.to_string());
+    }
+    Err(format!("expected a string or number, got '{}'", value))
+}
+
+/// Quote a value as a TOML basic string
+fn quote(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t");
+    format!("\"{}\"", escaped)
+}
+
+/// Load profiles from [`PROFILES_PATH`]
+///
+/// A missing file falls back to [`SYSTEM_PROFILES_PATH`], then to the
+/// built-in default profile.
+pub fn load() -> Result<ProfileSet, String> {
+    for path in [PROFILES_PATH, SYSTEM_PROFILES_PATH] {
+        if let Ok(content) = read_file_content(path) {
+            return ProfileSet::parse(&content).map_err(|e| format!("{}: {}", path, e));
+        }
+    }
+    Ok(ProfileSet::default())
+}
+
+/// Write profiles to [`PROFILES_PATH`], creating `/etc/terminal` if needed
+pub fn save(profiles: &ProfileSet) -> Result<(), String> {
+    if !syscall::exists("/etc/terminal").unwrap_or(false) {
+        syscall::mkdir("/etc/terminal").map_err(|e| format!("/etc/terminal: {}", e))?;
+    }
+    syscall::atomic_write(PROFILES_PATH, profiles.to_toml().as_bytes())
+        .map_err(|e| format!("{}: {}", PROFILES_PATH, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_round_trip() {
+        let content = r##"
+# my profiles
+default = "work"
+
+[plain]
+
+[work]
+font_size = 16   # bigger
+cwd = "/home/user"
+startup = "echo \"hi\" # not a comment"
+
+[work.colors]
+background = "#101010"
+brightRed = "#ff0000ff"
+"##;
+        let set = ProfileSet::parse(content).unwrap();
+        assert_eq!(set.default, "work");
+        assert_eq!(set.profiles.len(), 2);
+
+        let work = set.get("work").unwrap();
+        assert_eq!(work.font_size, 16);
+        assert_eq!(work.cwd.as_deref(), Some("/home/user"));
+        assert_eq!(work.startup.as_deref(), Some("echo \"hi\" # not a comment"));
+        assert_eq!(work.color("background"), Some("#101010"));
+        assert_eq!(work.color("foreground"), Some("#c0caf5"));
+        assert_eq!(work.theme().len(), THEME.len());
+        assert_eq!(work.get("colors.brightRed").as_deref(), Some("#ff0000ff"));
+        assert_eq!(set.get("plain"), Some(&TerminalProfile::new("plain")));
+
+        assert_eq!(ProfileSet::parse(&set.to_toml()).unwrap(), set);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = |s: &str| ProfileSet::parse(s).unwrap_err();
+        assert!(err("[a]\nfont_size = 200").starts_with("line 2: font_size"));
+        assert!(err("[a]\n[a]").contains("duplicate profile"));
+        assert!(err("[a.colors]\nmauve = \"#000000\"").contains("unknown color"));
+        assert!(err("[a.colors]\nred = \"red\"").contains("not a #rrggbb"));
+        assert!(err("default = \"b\"\n[a]").contains("'b' is not defined"));
+        assert!(err("[a b]").contains("invalid profile name"));
+        assert!(err("[a]\ncwd = relative").contains("expected a string"));
+
+        // Empty file gives the built-in default
+        assert_eq!(ProfileSet::parse("").unwrap(), ProfileSet::default());
+    }
+
+    #[test]
+    fn test_themes() {
+        let mut profile = TerminalProfile::new("p");
+        profile.set_theme("light").unwrap();
+        assert_eq!(profile.color("background"), Some("#e6e7ed"));
+        assert!(THEMES.iter().all(|(_, colors)| {
+            colors
+                .iter()
+                .all(|(k, v)| THEME.iter().any(|(t, _)| t == k) && is_hex_color(v))
+        }));
+
+        profile.set_theme("tokyo-night").unwrap();
+        assert!(profile.colors.is_empty());
+        assert!(profile.set_theme("neon").is_err());
+    }
+
+    #[test]
+    fn test_edit_profiles() {
+        let mut set = ProfileSet::default();
+        set.create("big", Some(DEFAULT_PROFILE)).unwrap();
+        assert!(set.create("big", None).is_err());
+        assert!(set.create("x", Some("missing")).is_err());
+
+        let big = set.get_mut("big").unwrap();
+        big.set("font_size", "20").unwrap();
+        big.set("colors.cursor", "#ffffff").unwrap();
+        big.set("cwd", "/tmp").unwrap();
+        assert!(big.set("cwd", "tmp").is_err());
+        assert!(big.set("nope", "1").is_err());
+        big.set("colors.cursor", "").unwrap();
+        assert!(big.colors.is_empty());
+
+        set.set_default("big").unwrap();
+        assert_eq!(set.default_profile().font_size, 20);
+        assert!(set.remove("big").is_err());
+        set.set_default(DEFAULT_PROFILE).unwrap();
+        set.remove("big").unwrap();
+        assert!(set.resolve(Some("big")).is_err());
+        assert_eq!(set.resolve(None).unwrap().name, DEFAULT_PROFILE);
+    }
+}
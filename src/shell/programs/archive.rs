@@ -0,0 +1,605 @@
+//! Archive programs
+//!
+//! `zip` bundles files and directory trees into a zip archive and `unzip`
+//! lists, checks or unpacks one (the format is in [`crate::vfs::zip`]). In
+//! the browser `zip -D` also downloads the archive, and `unzip -U` unpacks a
+//! zip picked from the host's files.
+
+use super::encoding::write_output;
+use super::{absolute, read_file_bytes};
+use crate::kernel::syscall;
+use crate::shell::argparse::{Arg, ArgError, ArgKind, CommandSpec, Flag};
+use crate::vfs::zip::{self, ZipEntry, ZipKind, ZipWriter};
+
+pub static ZIP: CommandSpec = CommandSpec {
+    name: "zip",
+    summary: "bundle files into a zip archive",
+    description: "Bundle files into a zip archive. '.zip' is added to ARCHIVE if it has no extension; an existing archive is replaced.",
+    flags: &[
+        Flag::short('r', "Include the contents of directories"),
+        Flag::short('0', "Store files without compressing them"),
+        Flag::short('q', "Don't list what is added"),
+        Flag::short('D', "Download the archive as well (browser only)"),
+    ],
+    args: &[
+        Arg::new("ARCHIVE", ArgKind::Path),
+        Arg::new("PATH", ArgKind::Path).repeated(),
+    ],
+};
+
+pub static UNZIP: CommandSpec = CommandSpec {
+    name: "unzip",
+    summary: "list, check or unpack a zip archive",
+    description: "Unpack a zip archive into the current directory. NAMEs pick members; a directory picks everything in it. With -U there is no ARCHIVE: the zip is picked in the browser.",
+    flags: &[
+        Flag::short('l', "List the members instead"),
+        Flag::short('t', "Check every member instead"),
+        Flag::short('o', "Overwrite existing files (they are skipped otherwise)"),
+        Flag::short('q', "Don't list what is unpacked"),
+        Flag::short('d', "Unpack into DIR, creating it if needed").value("DIR"),
+        Flag::short('U', "Unpack a zip chosen in the browser's file picker"),
+    ],
+    args: &[
+        Arg::new("ARCHIVE", ArgKind::Path).optional(),
+        Arg::new("NAME", ArgKind::Text).optional().repeated(),
+    ],
+};
+
+struct ZipOptions {
+    recurse: bool,
+    compress: bool,
+    quiet: bool,
+    /// Absolute path of the archive being written, left out of it
+    archive: String,
+    mtime: u64,
+}
+
+/// Create `path` and any missing parents
+fn make_dirs(path: &str) -> Result<(), String> {
+    let mut current = String::new();
+    if path.starts_with('/') {
+        current.push('/');
+    }
+    for part in path.split('/').filter(|part| !part.is_empty()) {
+        if !current.is_empty() && !current.ends_with('/') {
+            current.push('/');
+        }
+        current.push_str(part);
+        match syscall::metadata(&current) {
+            Ok(meta) if meta.is_dir || meta.is_symlink => {}
+            Ok(_) => return Err(format!("{}: Not a directory", current)),
+            Err(_) => syscall::mkdir(&current).map_err(|e| format!("{}: {}", current, e))?,
+        }
+    }
+    Ok(())
+}
+
+/// Percentage saved by compression, as zip tools report it
+fn saved(size: usize, stored: usize) -> usize {
+    (stored * 100)
+        .checked_div(size)
+        .map_or(0, |percent| 100 - percent.min(100))
+}
+
+/// Add `path` to the archive, and with `-r` everything under it
+fn add_path(
+    writer: &mut ZipWriter,
+    path: &str,
+    opts: &ZipOptions,
+    stdout: &mut String,
+    stderr: &mut String,
+) -> bool {
+    if absolute(path) == opts.archive {
+        return true;
+    }
+    let meta = match syscall::metadata(path) {
+        Ok(meta) => meta,
+        Err(e) => {
+            stderr.push_str(&format!("zip: {}: {}\n", path, e));
+            return false;
+        }
+    };
+    let name = path.trim_start_matches('/');
+    let report = |stdout: &mut String, text: String| {
+        if !opts.quiet {
+            stdout.push_str(&format!("  adding: {}\n", text));
+        }
+    };
+
+    if meta.is_symlink {
+        let target = meta.symlink_target.unwrap_or_default();
+        if let Err(e) = writer.add_symlink(path, &target, opts.mtime) {
+            stderr.push_str(&format!("zip: {}: {}\n", path, e));
+            return false;
+        }
+        report(stdout, format!("{} -> {} (stored 0%)", name, target));
+        return true;
+    }
+
+    if meta.is_dir {
+        // The root itself has no member name
+        if !name.trim_matches(['/', '.']).is_empty() {
+            if let Err(e) = writer.add_dir(path, meta.mode, opts.mtime) {
+                stderr.push_str(&format!("zip: {}: {}\n", path, e));
+                return false;
+            }
+            report(
+                stdout,
+                format!("{}/ (stored 0%)", name.trim_end_matches('/')),
+            );
+        }
+        if !opts.recurse {
+            return true;
+        }
+        let mut children = match syscall::readdir(path) {
+            Ok(children) => children,
+            Err(e) => {
+                stderr.push_str(&format!("zip: {}: {}\n", path, e));
+                return false;
+            }
+        };
+        children.sort();
+        let mut ok = true;
+        for child in children {
+            let child = format!("{}/{}", path.trim_end_matches('/'), child);
+            ok &= add_path(writer, &child, opts, stdout, stderr);
+        }
+        return ok;
+    }
+
+    let data = match read_file_bytes(path) {
+        Ok(data) => data,
+        Err(e) => {
+            stderr.push_str(&format!("zip: {}: {}\n", path, e));
+            return false;
+        }
+    };
+    match writer.add_file(path, &data, meta.mode, opts.mtime, opts.compress) {
+        Ok(stored) if stored < data.len() => {
+            let percent = saved(data.len(), stored);
+            report(stdout, format!("{} (deflated {}%)", name, percent));
+            true
+        }
+        Ok(_) => {
+            report(stdout, format!("{} (stored 0%)", name));
+            true
+        }
+        Err(e) => {
+            stderr.push_str(&format!("zip: {}: {}\n", path, e));
+            false
+        }
+    }
+}
+
+/// zip - bundle files into a zip archive
+pub fn prog_zip(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match ZIP.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&ZIP, stdout, stderr),
+    };
+    let recurse = m.flag("r");
+    let compress = !m.flag("0");
+    let quiet = m.flag("q");
+    let download = m.flag("D");
+    let (archive, paths) = m.operands.split_first().expect("ARCHIVE is required");
+
+    let mut archive = archive.to_string();
+    if !archive.rsplit('/').next().unwrap_or_default().contains('.') {
+        archive.push_str(".zip");
+    }
+    let opts = ZipOptions {
+        recurse,
+        compress,
+        quiet,
+        archive: absolute(&archive),
+        mtime: crate::shell::stats::clock() as u64,
+    };
+
+    let mut writer = ZipWriter::new();
+    let mut code = 0;
+    for path in paths {
+        if !add_path(&mut writer, path, &opts, stdout, stderr) {
+            code = 1;
+        }
+    }
+    if writer.is_empty() {
+        stderr.push_str("zip: nothing to add\n");
+        return 1;
+    }
+    let data = writer.finish();
+    if let Err(e) = write_output(&archive, &data, false) {
+        stderr.push_str(&format!("zip: {}: {}\n", archive, e));
+        return 1;
+    }
+
+    if download {
+        let name = archive.rsplit('/').next().unwrap_or(&archive);
+        #[cfg(target_arch = "wasm32")]
+        if let Err(e) = crate::shell::osimage::download(name, &data) {
+            stderr.push_str(&format!("zip: download failed: {}\n", e));
+            code = 1;
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            stderr.push_str(&format!("zip: downloading {} needs a browser\n", name));
+            code = 1;
+        }
+    }
+    code
+}
+
+/// Whether `entry` is picked by the NAME operands
+fn picked(entry: &ZipEntry, names: &[impl AsRef<str>]) -> bool {
+    names.is_empty()
+        || names.iter().any(|name| {
+            let name = format!("/{}", name.as_ref().trim_matches('/'));
+            entry.path == name || entry.path.starts_with(&format!("{}/", name))
+        })
+}
+
+/// Unpack `entries` under `dest`, reporting to `stdout` unless `quiet`.
+/// Returns the exit code.
+fn extract(
+    data: &[u8],
+    entries: &[&ZipEntry],
+    dest: &str,
+    overwrite: bool,
+    quiet: bool,
+    stdout: &mut String,
+    stderr: &mut String,
+) -> i32 {
+    if let Err(e) = make_dirs(dest) {
+        stderr.push_str(&format!("unzip: {}\n", e));
+        return 1;
+    }
+    let dest = dest.trim_end_matches('/');
+    let mut code = 0;
+    // Links go last, so nothing is written through one the archive made
+    let (links, members): (Vec<&ZipEntry>, Vec<&ZipEntry>) = entries
+        .iter()
+        .copied()
+        .partition(|entry| entry.kind == ZipKind::Symlink);
+    for entry in members.into_iter().chain(links) {
+        if entry.path == "/" {
+            continue;
+        }
+        let target = format!("{}{}", dest, entry.path);
+        let name = &entry.path[1..];
+
+        if entry.kind == ZipKind::Dir {
+            let created = syscall::metadata(&target).is_err();
+            let made = make_dirs(&target).and_then(|()| {
+                // Directories that were already there keep their mode
+                if created {
+                    syscall::chmod(&target, entry.mode & 0o777).map_err(|e| e.to_string())?;
+                }
+                Ok(())
+            });
+            match made {
+                Ok(()) if !quiet => stdout.push_str(&format!("   creating: {}/\n", name)),
+                Ok(()) => {}
+                Err(e) => {
+                    stderr.push_str(&format!("unzip: {}\n", e));
+                    code = 1;
+                }
+            }
+            continue;
+        }
+
+        if syscall::metadata(&target).is_ok() {
+            if !overwrite {
+                stderr.push_str(&format!(
+                    "unzip: skipping {}: already exists (use -o to overwrite)\n",
+                    name
+                ));
+                continue;
+            }
+            if let Err(e) = syscall::remove_file(&target) {
+                stderr.push_str(&format!("unzip: {}: {}\n", target, e));
+                code = 1;
+                continue;
+            }
+        }
+        let contents = match zip::read_file(data, entry) {
+            Ok(contents) => contents,
+            Err(e) => {
+                stderr.push_str(&format!("unzip: {}: {}\n", name, e));
+                code = 1;
+                continue;
+            }
+        };
+        let parent = target.rsplit_once('/').map_or("", |(parent, _)| parent);
+        let written = make_dirs(parent).and_then(|()| {
+            if entry.kind == ZipKind::Symlink {
+                let link = String::from_utf8_lossy(&contents);
+                syscall::symlink(&link, &target).map_err(|e| format!("{}: {}", target, e))
+            } else {
+                write_output(&target, &contents, false)
+                    .and_then(|()| {
+                        syscall::chmod(&target, entry.mode & 0o777).map_err(|e| e.to_string())
+                    })
+                    .map_err(|e| format!("{}: {}", target, e))
+            }
+        });
+        if let Err(e) = written {
+            stderr.push_str(&format!("unzip: {}\n", e));
+            code = 1;
+            continue;
+        }
+        if quiet {
+            continue;
+        }
+        let verb = match entry.kind {
+            ZipKind::Symlink => {
+                let link = String::from_utf8_lossy(&contents);
+                stdout.push_str(&format!("    linking: {} -> {}\n", name, link));
+                continue;
+            }
+            _ if entry.method == zip::METHOD_DEFLATED => "  inflating",
+            _ => " extracting",
+        };
+        stdout.push_str(&format!("{}: {}\n", verb, name));
+    }
+    code
+}
+
+/// Unpack a zip chosen in the browser's file picker into `dest`
+#[cfg(target_arch = "wasm32")]
+fn unzip_upload(dest: &str, overwrite: bool, stdout: &mut String) -> i32 {
+    use crate::kernel::klog::LogLevel;
+
+    let dest = absolute(dest);
+    wasm_bindgen_futures::spawn_local(async move {
+        let message = match crate::shell::osimage::pick_file(".zip").await {
+            Ok(Some((name, data))) => match zip::read_entries(&data) {
+                Ok(entries) => {
+                    let entries: Vec<&ZipEntry> = entries.iter().collect();
+                    let (mut out, mut err) = (String::new(), String::new());
+                    extract(&data, &entries, &dest, overwrite, true, &mut out, &mut err);
+                    let mut message = format!(
+                        "unpacked {} ({} members) into {}",
+                        name,
+                        entries.len(),
+                        dest
+                    );
+                    for line in err.lines() {
+                        message.push_str("; ");
+                        message.push_str(line.trim_start_matches("unzip: "));
+                    }
+                    message
+                }
+                Err(e) => format!("{}: {}", name, e),
+            },
+            Ok(None) => "upload cancelled".to_string(),
+            Err(e) => format!("upload failed: {}", e),
+        };
+        crate::console_log!("[unzip] {}", message);
+        syscall::klog(LogLevel::Notice, "unzip", &message);
+    });
+    stdout.push_str("Choose a zip in the file picker...\n(Check 'dmesg' for the result)\n");
+    0
+}
+
+/// unzip - list, check or unpack a zip archive
+pub fn prog_unzip(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match UNZIP.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&UNZIP, stdout, stderr),
+    };
+    let list = m.flag("l");
+    let test = m.flag("t");
+    let overwrite = m.flag("o");
+    let quiet = m.flag("q");
+    let upload = m.flag("U");
+    let dest = m.value("d").unwrap_or(".");
+    let operands = &m.operands;
+
+    if upload {
+        if !operands.is_empty() || list || test {
+            stderr.push_str("unzip: -U takes no archive\n");
+            return 2;
+        }
+        #[cfg(target_arch = "wasm32")]
+        return unzip_upload(dest, overwrite, stdout);
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            stderr.push_str("unzip: upload needs a browser\n");
+            return 1;
+        }
+    }
+
+    let Some((archive, names)) = operands.split_first() else {
+        return ArgError::MissingOperand("ARCHIVE").report(&UNZIP, stdout, stderr);
+    };
+    let data = match read_file_bytes(archive) {
+        Ok(data) => data,
+        Err(e) => {
+            stderr.push_str(&format!("unzip: {}: {}\n", archive, e));
+            return 1;
+        }
+    };
+    let all = match zip::read_entries(&data) {
+        Ok(entries) => entries,
+        Err(e) => {
+            stderr.push_str(&format!("unzip: {}: {}\n", archive, e));
+            return 1;
+        }
+    };
+    let entries: Vec<&ZipEntry> = all.iter().filter(|e| picked(e, names)).collect();
+    let mut code = 0;
+    for name in names {
+        if !all.iter().any(|entry| picked(entry, &[name])) {
+            stderr.push_str(&format!("unzip: {}: not in archive\n", name));
+            code = 1;
+        }
+    }
+
+    if list {
+        stdout.push_str("  Length      Date    Time    Name\n");
+        stdout.push_str("---------  ---------- -----   ----\n");
+        let mut total = 0;
+        for entry in &entries {
+            let mut name = entry.path[1..].to_string();
+            if entry.kind == ZipKind::Dir {
+                name.push('/');
+            }
+            stdout.push_str(&format!(
+                "{:>9}  {}   {}\n",
+                entry.size,
+                entry.modified(),
+                name
+            ));
+            total += entry.size;
+        }
+        stdout.push_str("---------                     -------\n");
+        let plural = if entries.len() == 1 { "" } else { "s" };
+        stdout.push_str(&format!(
+            "{:>9}                     {} file{}\n",
+            total,
+            entries.len(),
+            plural
+        ));
+        return code;
+    }
+
+    if test {
+        let mut bad = 0;
+        for entry in &entries {
+            match zip::read_file(&data, entry) {
+                Ok(_) if !quiet => {
+                    stdout.push_str(&format!("    testing: {}   OK\n", &entry.path[1..]))
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    stderr.push_str(&format!("unzip: {}: {}\n", &entry.path[1..], e));
+                    bad += 1;
+                }
+            }
+        }
+        if bad == 0 {
+            stdout.push_str(&format!("No errors detected in {}.\n", archive));
+            return code;
+        }
+        return 1;
+    }
+
+    if !quiet {
+        stdout.push_str(&format!("Archive:  {}\n", archive));
+    }
+    code.max(extract(
+        &data, &entries, dest, overwrite, quiet, stdout, stderr,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::syscall::{KERNEL, Kernel};
+
+    fn setup() {
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+    }
+
+    fn run(
+        prog: fn(&[String], &str, &mut String, &mut String) -> i32,
+        args: &[&str],
+    ) -> (i32, String, String) {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        let (mut stdout, mut stderr) = (String::new(), String::new());
+        let code = prog(&args, "", &mut stdout, &mut stderr);
+        (code, stdout, stderr)
+    }
+
+    #[test]
+    fn test_zip_unzip() {
+        setup();
+        syscall::mkdir("/tmp/src").unwrap();
+        syscall::mkdir("/tmp/src/sub").unwrap();
+        let text = "all work and no play\n".repeat(50);
+        write_output("/tmp/src/notes.txt", text.as_bytes(), false).unwrap();
+        write_output("/tmp/src/sub/run.sh", b"echo hi\n", false).unwrap();
+        syscall::chmod("/tmp/src/sub/run.sh", 0o755).unwrap();
+        syscall::symlink("notes.txt", "/tmp/src/link").unwrap();
+        syscall::chdir("/tmp").unwrap();
+
+        let (code, out, err) = run(prog_zip, &["-r", "bundle", "src"]);
+        assert_eq!(code, 0, "{}", err);
+        assert!(out.contains("  adding: src/ (stored 0%)"));
+        assert!(out.contains("  adding: src/notes.txt (deflated 9"));
+        assert!(out.contains("  adding: src/link -> notes.txt"));
+
+        let (code, out, _) = run(prog_unzip, &["-l", "bundle.zip"]);
+        assert_eq!(code, 0);
+        assert!(out.contains("src/sub/run.sh"));
+        assert!(out.contains("5 files"));
+        let (code, out, _) = run(prog_unzip, &["-t", "bundle.zip"]);
+        assert_eq!(code, 0);
+        assert!(out.contains("No errors detected"));
+
+        let (code, out, err) = run(prog_unzip, &["-d", "/tmp/out", "bundle.zip"]);
+        assert_eq!(code, 0, "{}", err);
+        assert!(out.contains("  inflating: src/notes.txt"));
+        assert!(out.contains("    linking: src/link -> notes.txt"));
+        assert_eq!(
+            read_file_bytes("/tmp/out/src/notes.txt").unwrap(),
+            text.as_bytes()
+        );
+        assert_eq!(
+            syscall::metadata("/tmp/out/src/sub/run.sh").unwrap().mode,
+            0o755
+        );
+        assert_eq!(
+            syscall::read_link("/tmp/out/src/link").unwrap(),
+            "notes.txt"
+        );
+
+        // Existing files are kept without -o
+        write_output("/tmp/out/src/notes.txt", b"mine", false).unwrap();
+        let (code, _, err) = run(
+            prog_unzip,
+            &["-q", "-d", "/tmp/out", "bundle.zip", "src/notes.txt"],
+        );
+        assert_eq!(code, 0);
+        assert!(err.contains("skipping src/notes.txt"));
+        assert_eq!(read_file_bytes("/tmp/out/src/notes.txt").unwrap(), b"mine");
+        let (code, _, _) = run(
+            prog_unzip,
+            &["-qo", "-d", "/tmp/out", "bundle.zip", "src/notes.txt"],
+        );
+        assert_eq!(code, 0);
+        assert_eq!(
+            read_file_bytes("/tmp/out/src/notes.txt").unwrap(),
+            text.as_bytes()
+        );
+
+        let (code, _, err) = run(prog_unzip, &["bundle.zip", "missing"]);
+        assert_eq!(code, 1);
+        assert!(err.contains("missing: not in archive"));
+        let (code, _, err) = run(prog_unzip, &["src/notes.txt"]);
+        assert_eq!(code, 1);
+        assert!(err.contains("not a zip archive"));
+        let (code, _, err) = run(prog_zip, &["empty.zip", "/nowhere"]);
+        assert_eq!(code, 1);
+        assert!(err.contains("nothing to add"));
+    }
+
+    #[test]
+    fn test_unzip_stays_in_dest() {
+        setup();
+        let mut writer = ZipWriter::new();
+        writer
+            .add_file("../../escape.txt", b"nope", 0o644, 0, true)
+            .unwrap();
+        write_output("/tmp/evil.zip", &writer.finish(), false).unwrap();
+
+        let (code, _, err) = run(prog_unzip, &["-d", "/tmp/x", "/tmp/evil.zip"]);
+        assert_eq!(code, 0, "{}", err);
+        assert!(syscall::exists("/tmp/x/escape.txt").unwrap());
+        assert!(!syscall::exists("/escape.txt").unwrap());
+    }
+}
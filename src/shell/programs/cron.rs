@@ -1,7 +1,59 @@
 //! Cron and scheduling programs
 
-use super::{args_to_strs, check_help};
 use crate::kernel::syscall;
+use crate::shell::argparse::{Arg, ArgKind, CommandSpec, Flag};
+
+pub static CRONTAB: CommandSpec = CommandSpec {
+    name: "crontab",
+    summary: "maintain cron tables for scheduled jobs",
+    description: "Maintain cron tables for scheduled jobs. With no options, list the current crontab; given FILE, install it.
+
+Crontab format:
+  minute hour day month weekday command
+  @reboot  Run at startup
+  @hourly  Run every hour (0 * * * *)
+  @daily   Run daily (0 0 * * *)
+  @batch   Prefix: background job, deferred while saving power
+
+Examples:
+  */5 * * * * echo 'every 5 min'    Run every 5 minutes
+  0 * * * * date                    Run at the top of every hour
+  @reboot /var/packages/startup     Run at boot
+  @batch @daily updatedb            Daily, waits for full speed",
+    flags: &[
+        Flag::short('l', "List current crontab"),
+        Flag::short('e', "Edit crontab (prints current, use crontab FILE to set)"),
+        Flag::short('r', "Remove crontab"),
+    ],
+    args: &[Arg::new("FILE", ArgKind::Path).optional().repeated()],
+};
+
+pub static AT: CommandSpec = CommandSpec {
+    name: "at",
+    summary: "schedule a one-time job",
+    description: "Schedule a command to run at a specific time.
+
+Time formats:
+  +5m    5 minutes from now
+  +1h    1 hour from now
+  +30s   30 seconds from now
+
+Examples:
+  at +5m echo 'Hello'     Run in 5 minutes
+  at +1h date             Run in 1 hour",
+    flags: &[
+        Flag::short('l', "List pending jobs"),
+        Flag::short('r', "Remove job ID").value("ID"),
+        Flag::short('d', "Same as -r").value("ID"),
+    ],
+    args: &[
+        Arg::new("TIME", ArgKind::Text).optional(),
+        Arg::new("COMMAND", ArgKind::Command)
+            .optional()
+            .repeated()
+            .raw(),
+    ],
+};
 
 /// crontab - maintain cron tables for scheduled jobs
 pub fn prog_crontab(
@@ -10,15 +62,10 @@ pub fn prog_crontab(
     stdout: &mut String,
     stderr: &mut String,
 ) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: crontab [-l | -e | -r] [file]\n\nMaintain cron tables for scheduled jobs.\n\nOptions:\n  -l        List current crontab\n  -e        Edit crontab (prints current, use crontab file to set)\n  -r        Remove crontab\n  file      Install crontab from file\n\nCrontab format:\n  minute hour day month weekday command\n  @reboot  Run at startup\n  @hourly  Run every hour (0 * * * *)\n  @daily   Run daily (0 0 * * *)\n\nExamples:\n  */5 * * * * echo 'every 5 min'    Run every 5 minutes\n  0 * * * * date                    Run at the top of every hour\n  @reboot /var/packages/startup     Run at boot",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
+    let m = match CRONTAB.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&CRONTAB, stdout, stderr),
+    };
 
     // Ensure cron directories exist
     let _ = syscall::mkdir("/var");
@@ -38,7 +85,8 @@ pub fn prog_crontab(
 
     let crontab_path = format!("/var/spool/cron/{}", username);
 
-    if args.is_empty() || args[0] == "-l" {
+    let args = &m.operands;
+    if m.flag("l") || !(m.flag("e") || m.flag("r") || !args.is_empty()) {
         // List crontab
         match syscall::open(&crontab_path, syscall::OpenFlags::READ) {
             Ok(fd) => {
@@ -72,7 +120,14 @@ pub fn prog_crontab(
         return 0;
     }
 
-    match args[0] {
+    let action = if m.flag("e") {
+        "-e"
+    } else if m.flag("r") {
+        "-r"
+    } else {
+        args[0].as_str()
+    };
+    match action {
         "-e" => {
             // Print current crontab for manual editing
             stdout.push_str("# Edit your crontab below, then save with:\n");
@@ -124,11 +179,8 @@ pub fn prog_crontab(
                             let content = &buf[..n];
 
                             // Write to crontab
-                            match syscall::open(&crontab_path, syscall::OpenFlags::WRITE) {
-                                Ok(out_fd) => {
-                                    let _ = syscall::write(out_fd, content);
-                                    let _ = syscall::close(out_fd);
-
+                            match syscall::atomic_write(&crontab_path, content) {
+                                Ok(()) => {
                                     // Parse and validate entries
                                     let text = String::from_utf8_lossy(content);
                                     let mut entry_count = 0;
@@ -163,10 +215,8 @@ pub fn prog_crontab(
                     // Maybe it's inline content
                     let content = args.join(" ");
 
-                    match syscall::open(&crontab_path, syscall::OpenFlags::WRITE) {
-                        Ok(out_fd) => {
-                            let _ = syscall::write(out_fd, content.as_bytes());
-                            let _ = syscall::close(out_fd);
+                    match syscall::atomic_write(&crontab_path, content.as_bytes()) {
+                        Ok(()) => {
                             stdout.push_str(&format!("crontab: installed for {}\n", username));
                             0
                         }
@@ -183,28 +233,31 @@ pub fn prog_crontab(
 
 /// at - schedule a one-time job
 pub fn prog_at(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: at <time> <command>\n       at -l         List pending jobs\n       at -r <id>    Remove a job\n\nSchedule a command to run at a specific time.\n\nTime formats:\n  +5m    5 minutes from now\n  +1h    1 hour from now\n  +30s   30 seconds from now\n\nExamples:\n  at +5m echo 'Hello'     Run in 5 minutes\n  at +1h date             Run in 1 hour",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
+    let m = match AT.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&AT, stdout, stderr),
+    };
 
     // Ensure at spool directory exists
     let _ = syscall::mkdir("/var");
     let _ = syscall::mkdir("/var/spool");
     let _ = syscall::mkdir("/var/spool/at");
 
-    if args.is_empty() {
+    let args = &m.operands;
+    let removing = m.value("r").or(m.value("d"));
+    let action = if m.flag("l") {
+        "list"
+    } else if removing.is_some() {
+        "remove"
+    } else if let Some(first) = args.first() {
+        first.as_str()
+    } else {
         stderr.push_str("at: missing time specification\nTry 'at --help' for usage.\n");
         return 1;
-    }
+    };
 
-    match args[0] {
-        "-l" | "list" => {
+    match action {
+        "list" => {
             // List pending jobs
             match syscall::readdir("/var/spool/at") {
                 Ok(entries) => {
@@ -242,12 +295,11 @@ pub fn prog_at(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut
                 }
             }
         }
-        "-r" | "-d" | "remove" => {
-            if args.len() < 2 {
+        "remove" => {
+            let Some(job_id) = removing.or_else(|| args.get(1).map(String::as_str)) else {
                 stderr.push_str("at: missing job ID\n");
                 return 1;
-            }
-            let job_id = args[1];
+            };
             let job_path = format!("/var/spool/at/{}", job_id);
 
             match syscall::remove_file(&job_path) {
@@ -409,11 +461,16 @@ mod tests {
 
     #[test]
     fn test_at_remove_missing_id() {
-        let args = vec!["-r".to_string()];
+        let args = vec!["remove".to_string()];
         let mut stdout = String::new();
         let mut stderr = String::new();
         let result = prog_at(&args, "", &mut stdout, &mut stderr);
         assert_eq!(result, 1);
         assert!(stderr.contains("missing job ID"));
+
+        let args = vec!["-r".to_string()];
+        let mut stderr = String::new();
+        assert_eq!(prog_at(&args, "", &mut String::new(), &mut stderr), 1);
+        assert!(stderr.contains("at: option -r needs a value"));
     }
 }
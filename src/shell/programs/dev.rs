@@ -0,0 +1,178 @@
+//! Developer tools
+
+use crate::kernel::syscall;
+use crate::kernel::wasm::{ModuleValidator, assemble};
+
+use super::read_file_content;
+use crate::shell::argparse::{Arg, ArgKind, CommandSpec, Flag};
+
+pub static WAT2WASM: CommandSpec = CommandSpec {
+    name: "wat2wasm",
+    summary: "assemble a WebAssembly text module",
+    description: "Assemble a WebAssembly text module into a binary module.
+
+Reads FILE (or stdin when FILE is '-' or omitted) and writes the module to
+OUTPUT, which defaults to FILE with its extension replaced by .wasm.
+A module that exports `memory` and `main` can be run as a command once it
+is in /bin, e.g. `wat2wasm hello.wat -o /bin/hello.wasm`.",
+    flags: &[
+        Flag::short('o', "Write the module to OUTPUT").value("OUTPUT"),
+        Flag::short('n', "Check the source only, do not write anything"),
+    ],
+    args: &[Arg::new("FILE", ArgKind::Path).optional()],
+};
+
+/// wat2wasm - assemble WAT source into a WASM module
+pub fn prog_wat2wasm(
+    args: &[String],
+    stdin: &str,
+    stdout: &mut String,
+    stderr: &mut String,
+) -> i32 {
+    let m = match WAT2WASM.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&WAT2WASM, stdout, stderr),
+    };
+    let output = m.value("o");
+    let input = m.operand(0);
+    let check_only = m.flag("n");
+
+    let (source, name) = match input {
+        Some(path) if path != "-" => match read_file_content(path) {
+            Ok(s) => (s, path),
+            Err(e) => {
+                stderr.push_str(&format!("wat2wasm: {}: {}\n", path, e));
+                return 1;
+            }
+        },
+        _ => (stdin.to_string(), "<stdin>"),
+    };
+
+    let module = match assemble(&source) {
+        Ok(m) => m,
+        Err(e) => {
+            stderr.push_str(&format!("wat2wasm: {}:{}\n", name, e));
+            return 1;
+        }
+    };
+
+    // Still a valid module, just not one the shell can run
+    if let Err(e) = ModuleValidator::validate(&module) {
+        stderr.push_str(&format!("wat2wasm: warning: not a command: {}\n", e));
+    }
+
+    if check_only {
+        return 0;
+    }
+
+    let output = match (output, input) {
+        (Some(out), _) => out.to_string(),
+        (None, Some(path)) if path != "-" => wasm_output_path(path),
+        _ => {
+            stderr.push_str("wat2wasm: -o is required when reading stdin\n");
+            return 2;
+        }
+    };
+
+    let written = syscall::open(&output, syscall::OpenFlags::WRITE).and_then(|fd| {
+        let result = syscall::write(fd, &module);
+        let _ = syscall::close(fd);
+        result
+    });
+    match written {
+        Ok(_) => {
+            stdout.push_str(&format!("{}: {} bytes\n", output, module.len()));
+            0
+        }
+        Err(e) => {
+            stderr.push_str(&format!("wat2wasm: {}: {}\n", output, e));
+            1
+        }
+    }
+}
+
+/// `hello.wat` -> `hello.wasm`; other names get `.wasm` appended
+fn wasm_output_path(input: &str) -> String {
+    match input.strip_suffix(".wat") {
+        Some(stem) => format!("{}.wasm", stem),
+        None => format!("{}.wasm", input),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::syscall::{KERNEL, Kernel};
+
+    fn setup_kernel() {
+        KERNEL.with(|k| {
+            *k.borrow_mut() = Kernel::new();
+            let pid = k.borrow_mut().spawn_process("test", None);
+            k.borrow_mut().set_current(pid);
+        });
+    }
+
+    fn run(args: &[&str], stdin: &str) -> (i32, String, String) {
+        let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        let mut stdout = String::new();
+        let mut stderr = String::new();
+        let code = prog_wat2wasm(&args, stdin, &mut stdout, &mut stderr);
+        (code, stdout, stderr)
+    }
+
+    const HELLO: &str = r#"
+        (module
+          (import "env" "write" (func $write (param i32 i32 i32) (result i32)))
+          (memory (export "memory") 1)
+          (data (i32.const 16) "hello\n")
+          (func (export "main") (param i32 i32) (result i32)
+            (drop (call $write (i32.const 1) (i32.const 16) (i32.const 6)))
+            (i32.const 0)))
+    "#;
+
+    #[test]
+    fn test_wat2wasm_writes_module() {
+        setup_kernel();
+        syscall::write_file("/tmp/hello.wat", HELLO).unwrap();
+
+        let (code, stdout, stderr) = run(&["/tmp/hello.wat"], "");
+        assert_eq!(code, 0, "{}", stderr);
+        assert!(stderr.is_empty());
+        assert!(stdout.starts_with("/tmp/hello.wasm: "));
+
+        let fd = syscall::open("/tmp/hello.wasm", syscall::OpenFlags::READ).unwrap();
+        let mut buf = [0u8; 512];
+        let n = syscall::read(fd, &mut buf).unwrap();
+        assert_eq!(&buf[..4], b"\0asm");
+        assert!(ModuleValidator::validate(&buf[..n]).is_ok());
+    }
+
+    #[test]
+    fn test_wat2wasm_stdin_requires_output() {
+        setup_kernel();
+        let (code, _, stderr) = run(&[], HELLO);
+        assert_eq!(code, 2);
+        assert!(stderr.contains("-o is required"));
+
+        let (code, _, _) = run(&["-o", "/tmp/out.wasm"], HELLO);
+        assert_eq!(code, 0);
+    }
+
+    #[test]
+    fn test_wat2wasm_reports_errors_and_warnings() {
+        setup_kernel();
+        let (code, _, stderr) = run(&["-n"], "(module\n  (func (i32.bogus)))");
+        assert_eq!(code, 1);
+        assert!(stderr.contains("<stdin>:2:9: unknown instruction 'i32.bogus'"));
+
+        let (code, _, stderr) = run(&["-n"], "(module (func (export \"f\")))");
+        assert_eq!(code, 0);
+        assert!(stderr.contains("warning: not a command"));
+    }
+
+    #[test]
+    fn test_wasm_output_path() {
+        assert_eq!(wasm_output_path("a/hello.wat"), "a/hello.wasm");
+        assert_eq!(wasm_output_path("tool"), "tool.wasm");
+    }
+}
@@ -1,28 +1,42 @@
 //! Encoding utility programs
-
-use super::{args_to_strs, check_help, read_file_content};
+//!
+//! These read and write raw bytes, so binary data survives a pipe:
+//! `cat image.png | base64 | base64 -d > copy.png`.
+//!
+//! `crypt` is the crypto toolbox: AES-256-GCM file encryption under a
+//! password or key file, SHA-256, HMAC-SHA256 and key generation.
+
+use super::{parse_bytes, read_file_bytes};
+use crate::kernel::aes::{self, AesGcm};
+use crate::kernel::crypto::{self, CryptoError};
+use crate::kernel::pkg::Checksum;
+use crate::kernel::syscall;
+use crate::shell::argparse::{Arg, ArgKind, CommandSpec, Flag};
+use crate::shell::qr;
+
+pub static BASE64: CommandSpec = CommandSpec {
+    name: "base64",
+    summary: "base64 encode or decode",
+    description: "Base64 encode FILE, or standard input, or decode it with -d.",
+    flags: &[Flag::new('d', "decode", "Decode")],
+    args: &[Arg::new("FILE", ArgKind::Path).optional()],
+};
 
 /// Base64 encode or decode
-pub fn prog_base64(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: base64 [-d] [FILE]\nBase64 encode or decode.\n  -d  Decode",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
-
-    let decode = args.iter().any(|a| *a == "-d" || *a == "--decode");
-    let file_args: Vec<&str> = args
-        .iter()
-        .filter(|a| !a.starts_with('-'))
-        .map(|s| s.as_ref())
-        .collect();
+pub fn prog_base64(
+    args: &[String],
+    stdin: &[u8],
+    stdout: &mut Vec<u8>,
+    stderr: &mut String,
+) -> i32 {
+    let m = match parse_bytes(&BASE64, args, stdout, stderr) {
+        Ok(m) => m,
+        Err(code) => return code,
+    };
+    let decode = m.flag("d");
 
-    let input = if let Some(file) = file_args.first() {
-        match read_file_content(file) {
+    let input = if let Some(file) = m.operand(0) {
+        match read_file_bytes(file) {
             Ok(c) => c,
             Err(e) => {
                 stderr.push_str(&format!("base64: {}: {}\n", file, e));
@@ -30,12 +44,16 @@ pub fn prog_base64(args: &[String], stdin: &str, stdout: &mut String, stderr: &m
             }
         }
     } else {
-        stdin.to_string()
+        stdin.to_vec()
     };
 
     if decode {
         // Simple base64 decode
-        let chars: Vec<char> = input.chars().filter(|c| !c.is_whitespace()).collect();
+        let chars: Vec<char> = input
+            .iter()
+            .map(|b| *b as char)
+            .filter(|c| !c.is_whitespace())
+            .collect();
         let mut result = Vec::new();
         let mut i = 0;
 
@@ -63,18 +81,12 @@ pub fn prog_base64(args: &[String], stdin: &str, stdout: &mut String, stderr: &m
             i += 4;
         }
 
-        if let Ok(s) = String::from_utf8(result) {
-            stdout.push_str(&s);
-        } else {
-            stderr.push_str("base64: invalid encoding\n");
-            return 1;
-        }
+        stdout.extend_from_slice(&result);
     } else {
         // Base64 encode
-        let bytes = input.as_bytes();
         let mut result = String::new();
 
-        for chunk in bytes.chunks(3) {
+        for chunk in input.chunks(3) {
             let val = match chunk.len() {
                 3 => ((chunk[0] as u32) << 16) | ((chunk[1] as u32) << 8) | (chunk[2] as u32),
                 2 => ((chunk[0] as u32) << 16) | ((chunk[1] as u32) << 8),
@@ -96,8 +108,8 @@ pub fn prog_base64(args: &[String], stdin: &str, stdout: &mut String, stderr: &m
             });
         }
 
-        stdout.push_str(&result);
-        stdout.push('\n');
+        stdout.extend_from_slice(result.as_bytes());
+        stdout.push(b'\n');
     }
 
     0
@@ -120,17 +132,23 @@ fn base64_decode_char(c: char) -> u8 {
     }
 }
 
-/// xxd - hex dump
-pub fn prog_xxd(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
+pub static XXD: CommandSpec = CommandSpec {
+    name: "xxd",
+    summary: "make a hexdump",
+    description: "Make a hexdump of FILE, or of standard input: sixteen bytes a line, in hex and as text.",
+    flags: &[],
+    args: &[Arg::new("FILE", ArgKind::Path).optional()],
+};
 
-    if let Some(help) = check_help(&args, "Usage: xxd [FILE]\nMake a hexdump.") {
-        stdout.push_str(&help);
-        return 0;
-    }
+/// xxd - hex dump
+pub fn prog_xxd(args: &[String], stdin: &[u8], stdout: &mut Vec<u8>, stderr: &mut String) -> i32 {
+    let m = match parse_bytes(&XXD, args, stdout, stderr) {
+        Ok(m) => m,
+        Err(code) => return code,
+    };
 
-    let input = if let Some(file) = args.first() {
-        match read_file_content(file) {
+    let bytes = if let Some(file) = m.operand(0) {
+        match read_file_bytes(file) {
             Ok(c) => c,
             Err(e) => {
                 stderr.push_str(&format!("xxd: {}: {}\n", file, e));
@@ -138,43 +156,412 @@ pub fn prog_xxd(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut
             }
         }
     } else {
-        stdin.to_string()
+        stdin.to_vec()
     };
 
-    let bytes = input.as_bytes();
-
+    let mut out = String::new();
     for (offset, chunk) in bytes.chunks(16).enumerate() {
         // Offset
-        stdout.push_str(&format!("{:08x}: ", offset * 16));
+        out.push_str(&format!("{:08x}: ", offset * 16));
 
         // Hex bytes
         for (i, byte) in chunk.iter().enumerate() {
-            stdout.push_str(&format!("{:02x}", byte));
+            out.push_str(&format!("{:02x}", byte));
             if i % 2 == 1 {
-                stdout.push(' ');
+                out.push(' ');
             }
         }
 
         // Padding for incomplete lines
         for i in chunk.len()..16 {
-            stdout.push_str("  ");
+            out.push_str("  ");
             if i % 2 == 1 {
-                stdout.push(' ');
+                out.push(' ');
             }
         }
 
         // ASCII representation
-        stdout.push(' ');
+        out.push(' ');
         for byte in chunk {
             if *byte >= 0x20 && *byte < 0x7f {
-                stdout.push(*byte as char);
+                out.push(*byte as char);
             } else {
-                stdout.push('.');
+                out.push('.');
             }
         }
-        stdout.push('\n');
+        out.push('\n');
+    }
+    stdout.extend_from_slice(out.as_bytes());
+
+    0
+}
+
+pub static SHA256SUM: CommandSpec = CommandSpec {
+    name: "sha256sum",
+    summary: "print SHA-256 checksums",
+    description: "Print SHA-256 checksums of files, or of stdin.",
+    flags: &[],
+    args: &[Arg::new("FILE", ArgKind::Path).optional().repeated()],
+};
+
+/// sha256sum - print SHA-256 checksums
+pub fn prog_sha256sum(
+    args: &[String],
+    stdin: &[u8],
+    stdout: &mut Vec<u8>,
+    stderr: &mut String,
+) -> i32 {
+    let m = match parse_bytes(&SHA256SUM, args, stdout, stderr) {
+        Ok(m) => m,
+        Err(code) => return code,
+    };
+    let args = &m.operands;
+
+    if args.is_empty() {
+        let line = format!("{}  -\n", Checksum::compute(stdin));
+        stdout.extend_from_slice(line.as_bytes());
+        return 0;
+    }
+
+    let mut code = 0;
+    for file in args {
+        match read_file_bytes(file) {
+            Ok(data) => {
+                let line = format!("{}  {}\n", Checksum::compute(&data), file);
+                stdout.extend_from_slice(line.as_bytes());
+            }
+            Err(e) => {
+                stderr.push_str(&format!("sha256sum: {}: {}\n", file, e));
+                code = 1;
+            }
+        }
+    }
+    code
+}
+
+/// Start of a file encrypted by `crypt enc`
+const CRYPT_MAGIC: &[u8; 8] = b"AXCRYPT1";
+
+const CRYPT_SALT_LEN: usize = 16;
+
+/// magic | PBKDF2 rounds (u32 LE, 0 for a key file) | salt | nonce
+const CRYPT_HEADER_LEN: usize = CRYPT_MAGIC.len() + 4 + CRYPT_SALT_LEN + aes::NONCE_LEN;
+
+/// Bytes in a key from `crypt keygen`
+const CRYPT_KEY_LEN: usize = 32;
+
+/// What `crypt enc` and `crypt dec` derive the AES key from
+enum CryptKey {
+    Password(String),
+    /// A raw key, from a key file
+    Key([u8; CRYPT_KEY_LEN]),
+}
+
+pub(super) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub(super) fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Read a key file from `crypt keygen`: 64 hex digits
+fn read_key_file(path: &str) -> Result<[u8; CRYPT_KEY_LEN], String> {
+    let text = read_file_bytes(path)?;
+    from_hex(String::from_utf8_lossy(&text).trim())
+        .and_then(|key| key.try_into().ok())
+        .ok_or_else(|| format!("{}: not a key from 'crypt keygen'", path))
+}
+
+fn random_bytes<const N: usize>() -> Result<[u8; N], String> {
+    let mut bytes = [0; N];
+    syscall::getrandom(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(bytes)
+}
+
+fn crypt_cipher(key: &CryptKey, salt: &[u8], iterations: u32) -> Result<AesGcm, String> {
+    let key = match key {
+        CryptKey::Password(password) => {
+            crypto::pbkdf2_sha256(password.as_bytes(), salt, iterations)
+        }
+        CryptKey::Key(key) => *key,
+    };
+    AesGcm::new(&key).ok_or_else(|| "bad key".to_string())
+}
+
+/// Encrypt `plaintext` into the `crypt` file format; the header is
+/// authenticated along with the data
+fn crypt_seal(key: &CryptKey, plaintext: &[u8]) -> Result<Vec<u8>, String> {
+    crypt_seal_with(key, plaintext, crypto::KDF_ITERATIONS)
+}
+
+/// Encrypt with a given number of PBKDF2 rounds for a password
+fn crypt_seal_with(key: &CryptKey, plaintext: &[u8], rounds: u32) -> Result<Vec<u8>, String> {
+    let iterations = match key {
+        CryptKey::Password(_) => rounds,
+        CryptKey::Key(_) => 0,
+    };
+    let salt: [u8; CRYPT_SALT_LEN] = random_bytes()?;
+    let nonce: [u8; aes::NONCE_LEN] = random_bytes()?;
+    let cipher = crypt_cipher(key, &salt, iterations)?;
+
+    let mut sealed = Vec::with_capacity(CRYPT_HEADER_LEN + plaintext.len() + aes::TAG_LEN);
+    sealed.extend_from_slice(CRYPT_MAGIC);
+    sealed.extend_from_slice(&iterations.to_le_bytes());
+    sealed.extend_from_slice(&salt);
+    sealed.extend_from_slice(&nonce);
+    let body = cipher.seal(&nonce, &sealed, plaintext);
+    sealed.extend(body);
+    Ok(sealed)
+}
+
+/// Check and decrypt a file from [`crypt_seal`]
+fn crypt_open(key: &CryptKey, sealed: &[u8]) -> Result<Vec<u8>, String> {
+    if !sealed.starts_with(CRYPT_MAGIC) {
+        return Err("not encrypted by 'crypt enc'".to_string());
+    }
+    if sealed.len() < CRYPT_HEADER_LEN + aes::TAG_LEN {
+        return Err(CryptoError::Truncated.to_string());
+    }
+    let (header, body) = sealed.split_at(CRYPT_HEADER_LEN);
+    let mut rounds = [0; 4];
+    rounds.copy_from_slice(&header[8..12]);
+    let iterations = u32::from_le_bytes(rounds);
+    let salt = &header[12..12 + CRYPT_SALT_LEN];
+    let mut nonce = [0; aes::NONCE_LEN];
+    nonce.copy_from_slice(&header[12 + CRYPT_SALT_LEN..]);
+
+    match (key, iterations) {
+        (CryptKey::Password(_), 0) => return Err("encrypted with a key file (use -k)".into()),
+        (CryptKey::Key(_), 1..) => return Err("encrypted with a password (use -p)".into()),
+        // Checked before the tag, so bound it before deriving a key
+        (CryptKey::Password(_), _) => {
+            crypto::check_iterations(iterations).map_err(|e| e.to_string())?;
+        }
+        _ => {}
+    }
+    let cipher = crypt_cipher(key, salt, iterations)?;
+    cipher.open(&nonce, header, body).map_err(|e| match e {
+        CryptoError::BadPassword if iterations == 0 => "wrong key or damaged data".to_string(),
+        e => e.to_string(),
+    })
+}
+
+/// Write `data` to `path`, readable only by its owner if `private`
+pub(super) fn write_output(path: &str, data: &[u8], private: bool) -> Result<(), String> {
+    let fd = syscall::open(path, syscall::OpenFlags::WRITE).map_err(|e| e.to_string())?;
+    // Made private while still empty, so the data is never readable by
+    // anyone else, even if the chmod fails
+    if private && let Err(e) = syscall::chmod(path, 0o600) {
+        let _ = syscall::close(fd);
+        return Err(e.to_string());
+    }
+    let written = syscall::write(fd, data);
+    let _ = syscall::close(fd);
+    written.map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+pub static CRYPT: CommandSpec = CommandSpec {
+    name: "crypt",
+    summary: "encrypt, decrypt, hash, sign and make keys",
+    description: "Encrypt and decrypt files (AES-256-GCM), hash them (SHA-256), sign them (HMAC-SHA256) and make keys. Input is FILE, or stdin. COMMAND is one of:
+
+  enc (-p PASSWORD | -k KEYFILE) [-o OUT] [FILE]
+  dec (-p PASSWORD | -k KEYFILE) [-o OUT] [FILE]
+  hash [FILE]...
+  hmac (-k KEYFILE | -K HEXKEY) [FILE]...
+  keygen [-o OUT]",
+    flags: &[
+        Flag::short('p', "Derive the key from a password (PBKDF2)").value("PASSWORD"),
+        Flag::short('k', "Use a key from 'crypt keygen'").value("KEYFILE"),
+        Flag::short('K', "HMAC key given in hex").value("HEXKEY"),
+        Flag::short('o', "Write to OUT instead of stdout").value("OUT"),
+    ],
+    args: &[
+        Arg::new("COMMAND", ArgKind::Text),
+        Arg::new("FILE", ArgKind::Path).optional().repeated(),
+    ],
+};
+
+/// crypt - encrypt, decrypt, hash, sign and make keys
+pub fn prog_crypt(args: &[String], stdin: &[u8], stdout: &mut Vec<u8>, stderr: &mut String) -> i32 {
+    let m = match parse_bytes(&CRYPT, args, stdout, stderr) {
+        Ok(m) => m,
+        Err(code) => return code,
+    };
+    let command = m.operands[0].as_str();
+    let files: Vec<&str> = m.operands[1..].iter().map(String::as_str).collect();
+    let password = m.value("p");
+    let key_file = m.value("k");
+    let hex_key = m.value("K");
+    let output = m.value("o");
+
+    let allowed = match command {
+        "enc" | "dec" => "-p -k -o",
+        "hmac" => "-k -K -o",
+        "hash" | "keygen" => "-o",
+        other => {
+            stderr.push_str(&format!("crypt: unknown command {}\n", other));
+            return 2;
+        }
+    };
+    for (flag, given) in [
+        ("-p", password),
+        ("-k", key_file),
+        ("-K", hex_key),
+        ("-o", output),
+    ] {
+        if given.is_some() && !allowed.split(' ').any(|a| a == flag) {
+            stderr.push_str(&format!("crypt: {} doesn't take {}\n", command, flag));
+            return 2;
+        }
     }
 
+    let read_input = |file: &str| match file {
+        "-" => Ok(stdin.to_vec()),
+        file => read_file_bytes(file).map_err(|e| format!("{}: {}", file, e)),
+    };
+    let emit = |data: &[u8], stdout: &mut Vec<u8>, private: bool| match output {
+        Some(path) => write_output(path, data, private).map_err(|e| format!("{}: {}", path, e)),
+        None => {
+            stdout.extend_from_slice(data);
+            Ok(())
+        }
+    };
+
+    let result = match command {
+        "enc" | "dec" => {
+            let key = match (password, key_file) {
+                (Some(password), None) => Ok(CryptKey::Password(password.to_string())),
+                (None, Some(path)) => read_key_file(path).map(CryptKey::Key),
+                _ => Err("give one of -p PASSWORD or -k KEYFILE".to_string()),
+            };
+            let input = match files.as_slice() {
+                [] => Ok(stdin.to_vec()),
+                [file] => read_input(file),
+                _ => Err("one input file at a time".to_string()),
+            };
+            key.and_then(|key| {
+                let input = input?;
+                match command {
+                    "enc" => emit(&crypt_seal(&key, &input)?, stdout, false),
+                    _ => emit(&crypt_open(&key, &input)?, stdout, true),
+                }
+            })
+        }
+        "hash" | "hmac" => {
+            let key = match (command, key_file, hex_key) {
+                ("hash", _, _) => Ok(None),
+                (_, Some(path), None) => read_key_file(path).map(|key| Some(key.to_vec())),
+                (_, None, Some(hex)) => from_hex(hex)
+                    .map(Some)
+                    .ok_or_else(|| format!("{}: not a hex key", hex)),
+                _ => Err("give one of -k KEYFILE or -K HEXKEY".to_string()),
+            };
+            let names = if files.is_empty() { vec!["-"] } else { files };
+            key.and_then(|key| {
+                let mut lines = String::new();
+                for name in names {
+                    let data = read_input(name)?;
+                    let digest = match &key {
+                        Some(key) => to_hex(&crypto::hmac_sha256(key, &data)),
+                        None => Checksum::compute(&data).to_string(),
+                    };
+                    lines.push_str(&format!("{}  {}\n", digest, name));
+                }
+                emit(lines.as_bytes(), stdout, false)
+            })
+        }
+        _ if !files.is_empty() => Err("keygen takes no input".to_string()),
+        _ => random_bytes::<CRYPT_KEY_LEN>()
+            .and_then(|key| emit(format!("{}\n", to_hex(&key)).as_bytes(), stdout, true)),
+    };
+
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            stderr.push_str(&format!("crypt: {}\n", e));
+            1
+        }
+    }
+}
+
+pub static QR: CommandSpec = CommandSpec {
+    name: "qr",
+    summary: "encode text as a QR code",
+    description: "Show TEXT, or stdin, as a QR code drawn with block characters.",
+    flags: &[
+        Flag::short('l', "Error correction: L 7%, M 15% (default), Q 25%, H 30%").value("LEVEL"),
+        Flag::new(
+            'i',
+            "invert",
+            "Draw dark modules as blocks, for light backgrounds",
+        ),
+        Flag::short('o', "Write a PNG image to FILE instead").value("FILE"),
+        Flag::short('s', "Pixels per module in the PNG (default 8)").value("SCALE"),
+    ],
+    args: &[Arg::new("TEXT", ArgKind::Text).optional().repeated()],
+};
+
+/// qr - encode text as a QR code
+pub fn prog_qr(args: &[String], stdin: &[u8], stdout: &mut Vec<u8>, stderr: &mut String) -> i32 {
+    let m = match parse_bytes(&QR, args, stdout, stderr) {
+        Ok(m) => m,
+        Err(code) => return code,
+    };
+    let level = m.value("l");
+    let output = m.value("o");
+    let scale = m.value("s");
+    let invert = m.flag("i");
+    let words = &m.operands;
+
+    let ecc = match level.map(qr::Ecc::parse) {
+        None => qr::Ecc::Medium,
+        Some(Some(ecc)) => ecc,
+        Some(None) => {
+            stderr.push_str("qr: level must be L, M, Q or H\n");
+            return 2;
+        }
+    };
+    let scale = match scale.map(str::parse::<usize>) {
+        None => 8,
+        Some(Ok(n)) if (1..=64).contains(&n) => n,
+        Some(_) => {
+            stderr.push_str("qr: scale must be 1 to 64\n");
+            return 2;
+        }
+    };
+
+    // `echo URL | qr` shouldn't put the newline in the code
+    let data = if words.is_empty() {
+        stdin.strip_suffix(b"\n").unwrap_or(stdin).to_vec()
+    } else {
+        words.join(" ").into_bytes()
+    };
+
+    let code = match qr::QrCode::encode(&data, ecc) {
+        Ok(code) => code,
+        Err(e) => {
+            stderr.push_str(&format!("qr: {}\n", e));
+            return 1;
+        }
+    };
+    match output {
+        Some(path) => {
+            if let Err(e) = write_output(path, &code.to_png(scale), false) {
+                stderr.push_str(&format!("qr: {}: {}\n", path, e));
+                return 1;
+            }
+        }
+        None => stdout.extend_from_slice(code.to_text(invert).as_bytes()),
+    }
     0
 }
 
@@ -186,10 +573,11 @@ mod tests {
     fn test_base64_encode() {
         let args = vec![];
         let stdin = "Hello, World!";
-        let mut stdout = String::new();
+        let mut stdout = Vec::new();
         let mut stderr = String::new();
 
-        let result = prog_base64(&args, stdin, &mut stdout, &mut stderr);
+        let result = prog_base64(&args, stdin.as_bytes(), &mut stdout, &mut stderr);
+        let stdout = String::from_utf8(stdout).unwrap();
 
         assert_eq!(result, 0);
         assert_eq!(stdout.trim(), "SGVsbG8sIFdvcmxkIQ==");
@@ -200,10 +588,11 @@ mod tests {
     fn test_base64_decode() {
         let args = vec!["-d".to_string()];
         let stdin = "SGVsbG8sIFdvcmxkIQ==";
-        let mut stdout = String::new();
+        let mut stdout = Vec::new();
         let mut stderr = String::new();
 
-        let result = prog_base64(&args, stdin, &mut stdout, &mut stderr);
+        let result = prog_base64(&args, stdin.as_bytes(), &mut stdout, &mut stderr);
+        let stdout = String::from_utf8(stdout).unwrap();
 
         assert_eq!(result, 0);
         assert_eq!(stdout, "Hello, World!");
@@ -214,10 +603,11 @@ mod tests {
     fn test_base64_encode_empty() {
         let args = vec![];
         let stdin = "";
-        let mut stdout = String::new();
+        let mut stdout = Vec::new();
         let mut stderr = String::new();
 
-        let result = prog_base64(&args, stdin, &mut stdout, &mut stderr);
+        let result = prog_base64(&args, stdin.as_bytes(), &mut stdout, &mut stderr);
+        let stdout = String::from_utf8(stdout).unwrap();
 
         assert_eq!(result, 0);
         assert_eq!(stdout.trim(), "");
@@ -252,10 +642,11 @@ mod tests {
     fn test_xxd_simple() {
         let args: Vec<String> = vec![];
         let stdin = "Hello";
-        let mut stdout = String::new();
+        let mut stdout = Vec::new();
         let mut stderr = String::new();
 
-        let result = prog_xxd(&args, stdin, &mut stdout, &mut stderr);
+        let result = prog_xxd(&args, stdin.as_bytes(), &mut stdout, &mut stderr);
+        let stdout = String::from_utf8(stdout).unwrap();
 
         assert_eq!(result, 0);
         // xxd outputs hex pairs with spaces: "4865 6c6c 6f"
@@ -269,10 +660,11 @@ mod tests {
     fn test_xxd_empty() {
         let args = vec![];
         let stdin = "";
-        let mut stdout = String::new();
+        let mut stdout = Vec::new();
         let mut stderr = String::new();
 
-        let result = prog_xxd(&args, stdin, &mut stdout, &mut stderr);
+        let result = prog_xxd(&args, stdin.as_bytes(), &mut stdout, &mut stderr);
+        let stdout = String::from_utf8(stdout).unwrap();
 
         assert_eq!(result, 0);
         assert_eq!(stdout, "");
@@ -282,13 +674,193 @@ mod tests {
     fn test_xxd_multiline() {
         let args = vec![];
         let stdin = "0123456789abcdef0123456789abcdef0";
-        let mut stdout = String::new();
+        let mut stdout = Vec::new();
         let mut stderr = String::new();
 
-        let result = prog_xxd(&args, stdin, &mut stdout, &mut stderr);
+        let result = prog_xxd(&args, stdin.as_bytes(), &mut stdout, &mut stderr);
+        let stdout = String::from_utf8(stdout).unwrap();
 
         assert_eq!(result, 0);
         // Should have 2 lines (16 bytes each)
         assert_eq!(stdout.lines().count(), 3); // 16 + 16 + 1 byte
     }
+
+    #[test]
+    fn test_base64_binary_round_trip() {
+        let data = [0x00, 0xff, 0x1f, 0x8b, 0x08, 0x80];
+        let mut encoded = Vec::new();
+        let mut stderr = String::new();
+        assert_eq!(prog_base64(&[], &data, &mut encoded, &mut stderr), 0);
+        assert_eq!(encoded, b"AP8fiwiA\n");
+
+        let mut decoded = Vec::new();
+        let args = vec!["-d".to_string()];
+        assert_eq!(prog_base64(&args, &encoded, &mut decoded, &mut stderr), 0);
+        assert_eq!(decoded, data);
+        assert_eq!(stderr, "");
+    }
+
+    #[test]
+    fn test_sha256sum_stdin() {
+        let mut stdout = Vec::new();
+        let mut stderr = String::new();
+
+        let result = prog_sha256sum(&[], b"hello", &mut stdout, &mut stderr);
+
+        assert_eq!(result, 0);
+        assert_eq!(
+            String::from_utf8(stdout).unwrap(),
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824  -\n"
+        );
+    }
+
+    fn crypt(args: &[&str], stdin: &[u8]) -> (i32, Vec<u8>, String) {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        let mut stdout = Vec::new();
+        let mut stderr = String::new();
+        let code = prog_crypt(&args, stdin, &mut stdout, &mut stderr);
+        (code, stdout, stderr)
+    }
+
+    #[test]
+    fn test_crypt_format() {
+        let password = CryptKey::Password("hunter2".to_string());
+        let sealed = crypt_seal_with(&password, b"attack at dawn", 10).unwrap();
+        assert!(sealed.starts_with(CRYPT_MAGIC));
+        assert_eq!(sealed.len(), CRYPT_HEADER_LEN + 14 + aes::TAG_LEN);
+        assert_eq!(crypt_open(&password, &sealed).unwrap(), b"attack at dawn");
+        assert_ne!(
+            crypt_seal_with(&password, b"attack at dawn", 10).unwrap(),
+            sealed
+        );
+
+        let wrong = CryptKey::Password("hunter3".to_string());
+        assert_eq!(
+            crypt_open(&wrong, &sealed).unwrap_err(),
+            "wrong password or damaged data"
+        );
+        // The header is authenticated too
+        let mut damaged = sealed.clone();
+        damaged[9] ^= 1;
+        assert!(crypt_open(&password, &damaged).is_err());
+        assert_eq!(
+            crypt_open(&CryptKey::Key([0; 32]), &sealed).unwrap_err(),
+            "encrypted with a password (use -p)"
+        );
+        assert_eq!(
+            crypt_open(&password, b"plain text").unwrap_err(),
+            "not encrypted by 'crypt enc'"
+        );
+        // A planted round count is refused before any key is derived
+        let mut planted = sealed.clone();
+        planted[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(
+            crypt_open(&password, &planted).unwrap_err(),
+            CryptoError::BadIterations.to_string()
+        );
+    }
+
+    #[test]
+    fn test_crypt() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+
+        let (code, _, _) = crypt(&["keygen", "-o", "/tmp/key"], b"");
+        assert_eq!(code, 0);
+        assert_eq!(syscall::metadata("/tmp/key").unwrap().mode & 0o777, 0o600);
+        let key = read_file_bytes("/tmp/key").unwrap();
+        assert_eq!(key.len(), 65);
+
+        let (code, sealed, _) = crypt(&["enc", "-k", "/tmp/key"], b"secret\x00bytes");
+        assert_eq!(code, 0);
+        assert!(!sealed.windows(6).any(|w| w == b"secret"));
+        let (code, out, _) = crypt(&["dec", "-k", "/tmp/key", "-"], &sealed);
+        assert_eq!((code, out.as_slice()), (0, &b"secret\x00bytes"[..]));
+
+        let (code, _, err) = crypt(&["dec", "-p", "pw"], &sealed);
+        assert_eq!(
+            (code, err.as_str()),
+            (1, "crypt: encrypted with a key file (use -k)\n")
+        );
+        crypt(&["keygen", "-o", "/tmp/other"], b"");
+        let (code, _, err) = crypt(&["dec", "-k", "/tmp/other"], &sealed);
+        assert_eq!(
+            (code, err.as_str()),
+            (1, "crypt: wrong key or damaged data\n")
+        );
+
+        let (code, out, _) = crypt(&["hash"], b"abc");
+        assert_eq!(code, 0);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad  -\n"
+        );
+        // RFC 4231 test case 2
+        let (code, out, _) = crypt(&["hmac", "-K", "4a656665"], b"what do ya want for nothing?");
+        assert_eq!(code, 0);
+        assert!(
+            out.starts_with(b"5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843")
+        );
+
+        assert_eq!(
+            crypt(&["enc"], b"x").2,
+            "crypt: give one of -p PASSWORD or -k KEYFILE\n"
+        );
+        assert_eq!(crypt(&["hash", "-p", "pw"], b"x").0, 2);
+        assert_eq!(crypt(&["frob"], b"x").0, 2);
+        let (code, _, err) = crypt(&[], b"x");
+        assert_eq!(code, 1);
+        assert!(err.starts_with("crypt: missing operand: expected COMMAND\n"));
+    }
+
+    #[test]
+    fn test_qr() {
+        let run = |args: &[&str], stdin: &[u8]| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            let mut stdout = Vec::new();
+            let mut stderr = String::new();
+            let code = prog_qr(&args, stdin, &mut stdout, &mut stderr);
+            (code, String::from_utf8(stdout).unwrap(), stderr)
+        };
+
+        let (code, out, _) = run(&["https://example.com"], b"");
+        assert_eq!(code, 0);
+        let expected = qr::QrCode::encode(b"https://example.com", qr::Ecc::Medium).unwrap();
+        assert_eq!(out, expected.to_text(false));
+        // The trailing newline from echo is dropped
+        assert_eq!(run(&[], b"https://example.com\n").1, out);
+        assert_eq!(
+            run(&["-i", "-l", "h", "hi", "there"], b"").1,
+            qr::QrCode::encode(b"hi there", qr::Ecc::High)
+                .unwrap()
+                .to_text(true)
+        );
+
+        assert_eq!(run(&["-l", "X", "a"], b"").0, 2);
+        assert_eq!(run(&["-s", "0", "a"], b"").0, 2);
+        let (code, _, err) = run(&["-o"], b"");
+        assert_eq!(code, 1);
+        assert!(err.starts_with("qr: option -o needs a value\n"));
+        let (code, _, err) = run(&[], &[b'a'; 3000]);
+        assert_eq!(code, 1);
+        assert!(err.contains("too much"));
+
+        use crate::kernel::syscall::{KERNEL, Kernel};
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        let (code, out, _) = run(&["-o", "/tmp/code.png", "-s", "2", "hello"], b"");
+        assert_eq!((code, out.as_str()), (0, ""));
+        let png = read_file_bytes("/tmp/code.png").unwrap();
+        let expected = qr::QrCode::encode(b"hello", qr::Ecc::Medium).unwrap();
+        assert_eq!(png, expected.to_png(2));
+    }
 }
@@ -1,43 +1,43 @@
 //! File operations programs
 //!
-//! Programs for basic file manipulation: cat, ls, mkdir, touch, rm, cp, mv, ln, readlink, tree
+//! Programs for basic file manipulation: cat, ls, mkdir, touch, rm, cp, mv, ln, readlink, tree,
+//! mktemp
 
-use super::{args_to_strs, check_help};
+use super::{absolute, parse_bytes, random_below, trash};
 use crate::kernel::syscall;
+use crate::kernel::users::{FileMode, Gid, Uid};
+use crate::shell::argparse::{Arg, ArgKind, CommandSpec, Flag};
+
+pub static CAT: CommandSpec = CommandSpec {
+    name: "cat",
+    summary: "concatenate files and print on the standard output",
+    description: "Concatenate FILEs and print them to stdout. With no FILE, copy stdin.",
+    flags: &[],
+    args: &[Arg::new("FILE", ArgKind::Path).optional().repeated()],
+};
 
 /// cat - concatenate files or stdin
-pub fn prog_cat(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let files = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &files,
-        "Usage: cat [FILE]...\nConcatenate files and print to stdout. See 'man cat' for details.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
+pub fn prog_cat(args: &[String], stdin: &[u8], stdout: &mut Vec<u8>, stderr: &mut String) -> i32 {
+    let files = match parse_bytes(&CAT, args, stdout, stderr) {
+        Ok(m) => m.operands,
+        Err(code) => return code,
+    };
 
     if files.is_empty() {
         // Read from stdin
-        if !stdin.is_empty() {
-            stdout.push_str(stdin);
-        }
+        stdout.extend_from_slice(stdin);
         return 0;
     }
 
     let mut code = 0;
-    for file in files {
+    for file in &files {
         match syscall::open(file, syscall::OpenFlags::READ) {
             Ok(fd) => {
                 let mut buf = [0u8; 1024];
                 loop {
                     match syscall::read(fd, &mut buf) {
                         Ok(0) => break,
-                        Ok(n) => {
-                            if let Ok(s) = std::str::from_utf8(&buf[..n]) {
-                                stdout.push_str(s);
-                            }
-                        }
+                        Ok(n) => stdout.extend_from_slice(&buf[..n]),
                         Err(e) => {
                             stderr.push_str(&format!("cat: {}: {}\n", file, e));
                             code = 1;
@@ -56,35 +56,41 @@ pub fn prog_cat(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut
     code
 }
 
+pub static LS: CommandSpec = CommandSpec {
+    name: "ls",
+    summary: "list directory contents",
+    description: "List the entries of each PATH, or of the current directory. A PATH that is not a directory is listed by itself.",
+    flags: &[
+        Flag::short(
+            'l',
+            "Long listing: mode, owner, group, size and modification time",
+        ),
+        Flag::short(
+            'a',
+            "Accepted for compatibility; hidden entries are always listed",
+        ),
+    ],
+    args: &[Arg::new("PATH", ArgKind::Path).optional().repeated()],
+};
+
 /// ls - list directory contents
 pub fn prog_ls(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let paths = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &paths,
-        "Usage: ls [-la] [PATH]...\nList directory contents. See 'man ls' for details.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
+    let m = match LS.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&LS, stdout, stderr),
+    };
+    let long = m.flag("l");
+    let mut paths = m.operands;
+    if paths.is_empty() {
+        paths.push(String::from("."));
     }
 
-    let paths: Vec<&str> = paths
-        .into_iter()
-        .filter(|p| !p.starts_with('-') || *p == "-")
-        .collect();
-    let paths = if paths.is_empty() { vec!["."] } else { paths };
-
-    // ANSI color codes
-    const BLUE: &str = "\x1b[34m"; // directories
-    const CYAN: &str = "\x1b[36m"; // symlinks
-    const RESET: &str = "\x1b[0m";
-
     let mut code = 0;
-    for path in paths {
-        match syscall::readdir(path) {
-            Ok(entries) => {
-                for entry in entries {
-                    // Check if it's a directory
+    for path in &paths {
+        let rows = match syscall::readdir(path) {
+            Ok(entries) => entries
+                .iter()
+                .map(|entry| {
                     let full_path = if path == "." {
                         entry.clone()
                     } else if path == "/" {
@@ -92,33 +98,25 @@ pub fn prog_ls(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut
                     } else {
                         format!("{}/{}", path, entry)
                     };
-
-                    let meta = syscall::metadata(&full_path);
-                    let is_dir = meta.as_ref().map(|m| m.is_dir).unwrap_or(false);
-                    let is_symlink = meta.as_ref().map(|m| m.is_symlink).unwrap_or(false);
-                    let symlink_target = meta.as_ref().ok().and_then(|m| m.symlink_target.clone());
-
-                    if is_symlink {
-                        stdout.push_str(CYAN);
-                        stdout.push_str(&entry);
-                        stdout.push_str(RESET);
-                        if let Some(target) = symlink_target {
-                            stdout.push_str(" -> ");
-                            stdout.push_str(&target);
-                        }
-                    } else if is_dir {
-                        stdout.push_str(BLUE);
-                        stdout.push_str(&entry);
-                        stdout.push_str(RESET);
-                    } else {
-                        stdout.push_str(&entry);
-                    }
-                    stdout.push('\n');
+                    ls_row(syscall::metadata(&full_path).ok(), entry)
+                })
+                .collect(),
+            Err(e) => match syscall::metadata(path) {
+                // A file operand is listed by itself
+                Ok(meta) if !meta.is_dir => vec![ls_row(Some(meta), path)],
+                _ => {
+                    stderr.push_str(&format!("ls: {}: {}\n", path, e));
+                    code = 1;
+                    continue;
                 }
-            }
-            Err(e) => {
-                stderr.push_str(&format!("ls: {}: {}\n", path, e));
-                code = 1;
+            },
+        };
+        if long {
+            list_long(&rows, stdout);
+        } else {
+            for (_, name) in rows {
+                stdout.push_str(&name);
+                stdout.push('\n');
             }
         }
     }
@@ -131,26 +129,110 @@ pub fn prog_ls(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut
     code
 }
 
-/// mkdir - create directories
-pub fn prog_mkdir(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let paths = args_to_strs(args);
+/// One `ls` entry: its metadata, if it could be read, and its name,
+/// colored by type
+fn ls_row(
+    meta: Option<syscall::FileMetadata>,
+    name: &str,
+) -> (Option<syscall::FileMetadata>, String) {
+    // ANSI color codes
+    const BLUE: &str = "\x1b[34m"; // directories
+    const CYAN: &str = "\x1b[36m"; // symlinks
+    const RESET: &str = "\x1b[0m";
 
-    if let Some(help) = check_help(
-        &paths,
-        "Usage: mkdir DIRECTORY...\nCreate directories. See 'man mkdir' for details.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
+    let shown = match &meta {
+        Some(m) if m.is_symlink => match &m.symlink_target {
+            Some(target) => format!("{}{}{} -> {}", CYAN, name, RESET, target),
+            None => format!("{}{}{}", CYAN, name, RESET),
+        },
+        Some(m) if m.is_dir => format!("{}{}{}", BLUE, name, RESET),
+        _ => name.to_string(),
+    };
+    (meta, shown)
+}
 
-    if paths.is_empty() {
-        stderr.push_str("mkdir: missing operand\n");
-        return 1;
+/// `ls -l` lines: type and mode, owner, group, size, modification time
+/// and name, with the columns lined up
+fn list_long(rows: &[(Option<syscall::FileMetadata>, String)], stdout: &mut String) {
+    let columns: Vec<[String; 5]> = rows
+        .iter()
+        .map(|(meta, _)| match meta {
+            Some(meta) => {
+                let kind = if meta.is_symlink {
+                    'l'
+                } else if meta.is_dir {
+                    'd'
+                } else {
+                    '-'
+                };
+                let user = syscall::get_user_by_uid(Uid(meta.uid))
+                    .map_or_else(|| meta.uid.to_string(), |u| u.name);
+                let group = syscall::get_group_by_gid(Gid(meta.gid))
+                    .map_or_else(|| meta.gid.to_string(), |g| g.name);
+                [
+                    format!("{}{}", kind, FileMode::new(meta.mode).to_symbolic()),
+                    user,
+                    group,
+                    meta.size.to_string(),
+                    format_mtime(meta.mtime),
+                ]
+            }
+            None => ["??????????", "?", "?", "?", "?"].map(String::from),
+        })
+        .collect();
+    let width = |i: usize| columns.iter().map(|c| c[i].len()).max().unwrap_or(0);
+    let (user, group, size, time) = (width(1), width(2), width(3), width(4));
+    for (c, (_, name)) in columns.iter().zip(rows) {
+        stdout.push_str(&format!(
+            "{} {:<user$} {:<group$} {:>size$} {:<time$} {}\n",
+            c[0], c[1], c[2], c[3], c[4], name
+        ));
     }
+}
+
+/// Milliseconds since the epoch as `YYYY-MM-DD HH:MM` (UTC)
+fn format_mtime(mtime: f64) -> String {
+    let secs = (mtime / 1000.0) as i64;
+    let (year, month, day) = crate::kernel::calendar::civil_from_days(secs.div_euclid(86_400));
+    let of_day = secs.rem_euclid(86_400);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        of_day / 3600,
+        of_day / 60 % 60
+    )
+}
+
+pub static MKDIR: CommandSpec = CommandSpec {
+    name: "mkdir",
+    summary: "make directories",
+    description: "Create each DIRECTORY. Parent directories must already exist, unless -p is given.",
+    flags: &[Flag::new(
+        'p',
+        "parents",
+        "Create parent directories as needed; existing directories are not an error",
+    )],
+    args: &[Arg::new("DIRECTORY", ArgKind::Dir).repeated()],
+};
+
+/// mkdir - create directories
+pub fn prog_mkdir(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match MKDIR.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&MKDIR, stdout, stderr),
+    };
+    let parents = m.flag("p");
 
     let mut code = 0;
-    for path in paths {
-        if let Err(e) = syscall::mkdir(path) {
+    for path in &m.operands {
+        let made = if parents {
+            mkdir_parents(path)
+        } else {
+            syscall::mkdir(path)
+        };
+        if let Err(e) = made {
             stderr.push_str(&format!("mkdir: {}: {}\n", path, e));
             code = 1;
         }
@@ -158,25 +240,44 @@ pub fn prog_mkdir(args: &[String], _stdin: &str, stdout: &mut String, stderr: &m
     code
 }
 
-/// touch - create empty files
-pub fn prog_touch(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let paths = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &paths,
-        "Usage: touch FILE...\nCreate empty files or update timestamps. See 'man touch' for details.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
+/// Create `path` and any missing directories above it
+pub(super) fn mkdir_parents(path: &str) -> syscall::SyscallResult<()> {
+    let mut dir = if path.starts_with('/') {
+        String::from("/")
+    } else {
+        String::new()
+    };
+    for part in path.split('/').filter(|p| !p.is_empty()) {
+        if !dir.is_empty() && !dir.ends_with('/') {
+            dir.push('/');
+        }
+        dir.push_str(part);
+        match syscall::metadata(&dir) {
+            Ok(meta) if meta.is_dir => {}
+            Ok(_) => return Err(syscall::SyscallError::NotADirectory),
+            Err(_) => syscall::mkdir(&dir)?,
+        }
     }
+    Ok(())
+}
 
-    if paths.is_empty() {
-        stderr.push_str("touch: missing operand\n");
-        return 1;
-    }
+pub static TOUCH: CommandSpec = CommandSpec {
+    name: "touch",
+    summary: "create empty files",
+    description: "Create each FILE empty, or update its timestamps if it exists.",
+    flags: &[],
+    args: &[Arg::new("FILE", ArgKind::Path).repeated()],
+};
+
+/// touch - create empty files
+pub fn prog_touch(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let paths = match TOUCH.parse(args) {
+        Ok(m) => m.operands,
+        Err(e) => return e.report(&TOUCH, stdout, stderr),
+    };
 
     let mut code = 0;
-    for path in paths {
+    for path in &paths {
         // OpenFlags::WRITE includes create and truncate
         match syscall::open(path, syscall::OpenFlags::WRITE) {
             Ok(fd) => {
@@ -191,179 +292,659 @@ pub fn prog_touch(args: &[String], _stdin: &str, stdout: &mut String, stderr: &m
     code
 }
 
+pub static RM: CommandSpec = CommandSpec {
+    name: "rm",
+    summary: "remove files or directories",
+    description: "Remove each FILE. Directories are only removed with -r, which removes everything below them; symbolic links are removed, never followed. With -i, each answer is read from a line of stdin, and no answer means no. With --trash, or rm = true in ~/.config/trash.toml, each FILE is moved to the trash instead, where 'trash restore' gets it back; what is already in the trash is removed for good.",
+    flags: &[
+        Flag::new('r', "recursive", "Remove directories and their contents"),
+        Flag::short('R', "Same as -r"),
+        Flag::new('f', "force", "Ignore nonexistent files and never ask"),
+        Flag::new('i', "interactive", "Ask before every removal"),
+        Flag::new('v', "verbose", "Print each file removed"),
+        Flag::long("trash", "Move each FILE to the trash instead (see trash)"),
+        Flag::long("no-trash", "Remove for good, even with the trash on"),
+    ],
+    args: &[Arg::new("FILE", ArgKind::Path).repeated()],
+};
+
+/// How `rm` removes, from its flags
+pub(super) struct RemoveOptions {
+    pub(super) recursive: bool,
+    pub(super) interactive: bool,
+    pub(super) verbose: bool,
+}
+
 /// rm - remove files
-pub fn prog_rm(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: rm [-r] FILE...\nRemove files or directories. See 'man rm' for details.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
+pub fn prog_rm(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match RM.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&RM, stdout, stderr),
+    };
+    let force = m.flag("f");
+    let opts = RemoveOptions {
+        recursive: m.flag("r") || m.flag("R"),
+        // Whichever of -i and -f comes last wins
+        interactive: m.last_of(&["i", "f"]) == Some("i"),
+        verbose: m.flag("v"),
+    };
+    let mut io = Io::new(stdin, stdout, stderr);
+    // The trash is used if the settings turn it on, unless the flags say
+    let trash = match m.last_of(&["trash", "no-trash"]) {
+        Some("no-trash") => None,
+        chosen => match trash::load_config() {
+            Ok(config) => (chosen.is_some() || config.rm).then_some(config),
+            Err(e) => {
+                io.stderr.push_str(&format!("rm: {}\n", e));
+                return 1;
+            }
+        },
+    };
+
+    let mut failed = false;
+    let mut trashed = false;
+    for path in &m.operands {
+        if matches!(base_name(path), "." | "..") {
+            io.stderr.push_str(&format!(
+                "rm: refusing to remove '.' or '..' directory: skipping '{}'\n",
+                path
+            ));
+            failed = true;
+            continue;
+        }
+        if opts.recursive && absolute(path) == "/" {
+            io.stderr
+                .push_str("rm: it is dangerous to operate recursively on '/'\n");
+            failed = true;
+            continue;
+        }
+        match syscall::metadata(path) {
+            // What is already in the trash goes for good
+            Ok(meta) if trash.is_some() && !trash::in_trash(&absolute(path)) => {
+                match trash_path(path, &meta, &opts, &mut io) {
+                    Removal::Removed => trashed = true,
+                    Removal::Failed => failed = true,
+                    Removal::Kept => {}
+                }
+            }
+            Ok(meta) => failed |= remove_tree(path, &meta, &opts, &mut io) == Removal::Failed,
+            Err(syscall::SyscallError::NotFound) if force => {}
+            Err(e) => {
+                io.stderr
+                    .push_str(&format!("rm: cannot remove '{}': {}\n", path, e));
+                failed = true;
+            }
+        }
     }
 
-    if args.is_empty() {
-        stderr.push_str("rm: missing operand\n");
-        return 1;
+    if let Some(config) = trash
+        && trashed
+    {
+        for entry in trash::evict(config.max_size, &mut io) {
+            if opts.verbose {
+                io.stdout
+                    .push_str(&format!("emptied '{}' from the trash\n", entry.name));
+            }
+        }
     }
 
-    let recursive = args.iter().any(|&a| a == "-r" || a == "-rf" || a == "-fr");
-    let paths: Vec<&str> = args
-        .iter()
-        .copied()
-        .filter(|a| !a.starts_with('-'))
-        .collect();
+    if failed { 1 } else { 0 }
+}
 
-    if paths.is_empty() {
-        stderr.push_str("rm: missing operand\n");
-        return 1;
+/// Move `path` to the trash for `rm`; directories only with -r
+fn trash_path(
+    path: &str,
+    meta: &syscall::FileMetadata,
+    opts: &RemoveOptions,
+    io: &mut Io,
+) -> Removal {
+    if meta.is_dir && !opts.recursive {
+        io.stderr
+            .push_str(&format!("rm: cannot remove '{}': Is a directory\n", path));
+        return Removal::Failed;
     }
-
-    let mut failed = false;
-    for path in paths {
-        // Check if it's a directory
-        match syscall::metadata(path) {
-            Ok(meta) if meta.is_dir => {
-                if recursive {
-                    if let Err(e) = syscall::remove_dir(path) {
-                        stderr.push_str(&format!("rm: cannot remove '{}': {}\n", path, e));
-                        failed = true;
-                    }
-                } else {
-                    stderr.push_str(&format!("rm: cannot remove '{}': Is a directory\n", path));
-                    failed = true;
-                }
+    if opts.interactive && !io.confirm(&format!("rm: move '{}' to the trash? ", path)) {
+        return Removal::Kept;
+    }
+    match trash::put(path, io) {
+        Ok(_) => {
+            if opts.verbose {
+                io.stdout.push_str(&format!("trashed '{}'\n", path));
             }
-            Ok(_) => {
-                if let Err(e) = syscall::remove_file(path) {
-                    stderr.push_str(&format!("rm: cannot remove '{}': {}\n", path, e));
-                    failed = true;
-                }
+            Removal::Removed
+        }
+        Err(e) => {
+            io.stderr.push_str(&format!("rm: {}\n", e));
+            Removal::Failed
+        }
+    }
+}
+
+/// What became of a path `rm` was asked to remove
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum Removal {
+    Removed,
+    /// A question was answered no, here or below
+    Kept,
+    Failed,
+}
+
+/// Remove `path`, and with -r everything below it, depth first
+///
+/// A directory is only asked about, and removed, once everything in it
+/// is gone.
+pub(super) fn remove_tree(
+    path: &str,
+    meta: &syscall::FileMetadata,
+    opts: &RemoveOptions,
+    io: &mut Io,
+) -> Removal {
+    let removed = |io: &mut Io, result: syscall::SyscallResult<()>, what: &str| match result {
+        Ok(()) => {
+            if opts.verbose {
+                io.stdout.push_str(&format!("removed {}'{}'\n", what, path));
             }
+            Removal::Removed
+        }
+        Err(e) => {
+            io.stderr
+                .push_str(&format!("rm: cannot remove '{}': {}\n", path, e));
+            Removal::Failed
+        }
+    };
+
+    if !meta.is_dir {
+        if opts.interactive && !io.confirm(&format!("rm: remove '{}'? ", path)) {
+            return Removal::Kept;
+        }
+        return removed(io, syscall::remove_file(path), "");
+    }
+
+    if !opts.recursive {
+        io.stderr
+            .push_str(&format!("rm: cannot remove '{}': Is a directory\n", path));
+        return Removal::Failed;
+    }
+    if opts.interactive && !io.confirm(&format!("rm: descend into directory '{}'? ", path)) {
+        return Removal::Kept;
+    }
+    let mut entries = match syscall::readdir(path) {
+        Ok(entries) => entries,
+        Err(e) => return removed(io, Err(e), ""),
+    };
+    entries.sort();
+    let mut outcome = Removal::Removed;
+    for name in entries {
+        let child = join(path, &name);
+        let removal = match syscall::metadata(&child) {
+            Ok(meta) => remove_tree(&child, &meta, opts, io),
             Err(e) => {
-                stderr.push_str(&format!("rm: cannot remove '{}': {}\n", path, e));
-                failed = true;
+                io.stderr
+                    .push_str(&format!("rm: cannot remove '{}': {}\n", child, e));
+                Removal::Failed
             }
+        };
+        if removal == Removal::Failed || outcome == Removal::Removed {
+            outcome = removal;
         }
     }
+    if outcome != Removal::Removed {
+        return outcome;
+    }
+    if opts.interactive && !io.confirm(&format!("rm: remove directory '{}'? ", path)) {
+        return Removal::Kept;
+    }
+    removed(io, syscall::remove_dir(path), "directory ")
+}
 
-    if failed { 1 } else { 0 }
+pub static CP: CommandSpec = CommandSpec {
+    name: "cp",
+    summary: "copy files and directories",
+    description: "Copy SOURCE to DEST, or each SOURCE into the directory DEST. Directories are only copied with -r. Symbolic links named on the command line are followed unless -r or -P is given; links below a directory are copied as links unless -L is given. With -i, each answer is read from a line of stdin, so printf 'y\\n' | cp -i a b overwrites b.",
+    flags: &[
+        Flag::new('r', "recursive", "Copy directories and their contents"),
+        Flag::short('R', "Same as -r"),
+        Flag::new(
+            'p',
+            "preserve",
+            "Keep the mode, ownership and timestamps; ownership only as root",
+        ),
+        Flag::new('i', "interactive", "Ask before overwriting"),
+        Flag::new('v', "verbose", "Print each file copied"),
+        Flag::new('L', "dereference", "Always follow symbolic links"),
+        Flag::new('P', "no-dereference", "Never follow symbolic links"),
+    ],
+    args: &[
+        Arg::new("SOURCE", ArgKind::Path).repeated(),
+        Arg::new("DEST", ArgKind::Path),
+    ],
+};
+
+/// How `cp` copies, from its flags
+struct CopyOptions {
+    recursive: bool,
+    preserve: bool,
+    interactive: bool,
+    verbose: bool,
+    /// Follow symbolic links below a directory being copied
+    dereference: bool,
 }
 
 /// cp - copy files
-pub fn prog_cp(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: cp SOURCE DEST\nCopy files. See 'man cp' for details.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
+pub fn prog_cp(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match CP.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&CP, stdout, stderr),
+    };
+    let recursive = m.flag("r") || m.flag("R");
+    let link_policy = m.last_of(&["L", "P"]);
+    let opts = CopyOptions {
+        recursive,
+        preserve: m.flag("p"),
+        interactive: m.flag("i"),
+        verbose: m.flag("v"),
+        dereference: link_policy == Some("L"),
+    };
+    // Operands are followed, as GNU cp does, unless copying a tree
+    let follow = link_policy.map_or(!recursive, |policy| policy == "L");
+    let mut io = Io::new(stdin, stdout, stderr);
+
+    let Some((dest, sources)) = m.operands.split_last() else {
+        return 1;
+    };
+    let into = is_dir(dest);
+    if sources.len() > 1 && !into {
+        io.stderr
+            .push_str(&format!("cp: target '{}' is not a directory\n", dest));
+        return 1;
     }
 
-    if args.len() < 2 {
-        stderr.push_str("cp: missing operand\n");
-        return 1;
+    let mut failed = false;
+    for src in sources {
+        let dst = if into {
+            join(dest, base_name(src))
+        } else {
+            dest.clone()
+        };
+        if recursive && is_dir(src) && is_within(&absolute(&dst), &absolute(src)) {
+            io.stderr.push_str(&format!(
+                "cp: cannot copy a directory, '{}', into itself, '{}'\n",
+                src, dst
+            ));
+            failed = true;
+            continue;
+        }
+        failed |= !copy_tree(src, &dst, follow, &opts, &mut io);
     }
 
-    let src = &args[0];
-    let dst = &args[1];
+    if failed { 1 } else { 0 }
+}
 
-    match syscall::copy_file(src, dst) {
-        Ok(_) => 0,
-        Err(e) => {
-            stderr.push_str(&format!("cp: cannot copy '{}' to '{}': {}\n", src, dst, e));
-            1
+/// Copy `src` to `dst`, and with -r everything below it
+///
+/// A symbolic link at `src` is copied as what it points at if `follow`
+/// is set, and as a link otherwise. Returns false if anything failed.
+fn copy_tree(src: &str, dst: &str, follow: bool, opts: &CopyOptions, io: &mut Io) -> bool {
+    let fail = |io: &mut Io, e: &dyn std::fmt::Display| {
+        io.stderr
+            .push_str(&format!("cp: cannot copy '{}' to '{}': {}\n", src, dst, e));
+        false
+    };
+    let resolved = if follow {
+        match syscall::realpath(src) {
+            Ok(path) => path,
+            Err(e) => return fail(io, &e),
+        }
+    } else {
+        src.to_string()
+    };
+    let meta = match syscall::metadata(&resolved) {
+        Ok(meta) => meta,
+        Err(e) => return fail(io, &e),
+    };
+    let existing = syscall::metadata(dst).ok();
+
+    if meta.is_dir {
+        if !opts.recursive {
+            io.stderr.push_str(&format!(
+                "cp: -r not specified; omitting directory '{}'\n",
+                src
+            ));
+            return false;
         }
+        match existing {
+            Some(_) if is_dir(dst) => {}
+            Some(_) => {
+                io.stderr.push_str(&format!(
+                    "cp: cannot overwrite non-directory '{}' with directory '{}'\n",
+                    dst, src
+                ));
+                return false;
+            }
+            None => {
+                if let Err(e) = syscall::mkdir(dst) {
+                    return fail(io, &e);
+                }
+                if opts.verbose {
+                    io.stdout.push_str(&format!("'{}' -> '{}'\n", src, dst));
+                }
+            }
+        }
+        let mut entries = match syscall::readdir(&resolved) {
+            Ok(entries) => entries,
+            Err(e) => return fail(io, &e),
+        };
+        entries.sort();
+        let mut ok = true;
+        for name in entries {
+            let (from, to) = (join(src, &name), join(dst, &name));
+            ok &= copy_tree(&from, &to, opts.dereference, opts, io);
+        }
+        // Last, so copying the contents doesn't touch the times again
+        if opts.preserve
+            && let Err(e) = preserve(dst, &meta)
+        {
+            return fail(io, &e);
+        }
+        return ok;
+    }
+
+    if let Some(existing) = &existing {
+        if existing.is_dir {
+            io.stderr.push_str(&format!(
+                "cp: cannot overwrite directory '{}' with non-directory '{}'\n",
+                dst, src
+            ));
+            return false;
+        }
+        if absolute(src) == absolute(dst) {
+            io.stderr
+                .push_str(&format!("cp: '{}' and '{}' are the same file\n", src, dst));
+            return false;
+        }
+        if opts.interactive && !io.confirm(&format!("cp: overwrite '{}'? ", dst)) {
+            return true;
+        }
+    }
+
+    let copied = if meta.is_symlink {
+        // A link is recreated rather than copied over what is there
+        syscall::read_link(&resolved).and_then(|target| {
+            if existing.is_some() {
+                syscall::remove_file(dst)?;
+            }
+            syscall::symlink(&target, dst)
+        })
+    } else {
+        syscall::copy_file(&resolved, dst).and_then(|_| {
+            if opts.preserve {
+                preserve(dst, &meta)
+            } else {
+                Ok(())
+            }
+        })
+    };
+    match copied {
+        Ok(()) => {
+            if opts.verbose {
+                io.stdout.push_str(&format!("'{}' -> '{}'\n", src, dst));
+            }
+            true
+        }
+        Err(e) => fail(io, &e),
     }
 }
 
+/// Give `path` the mode, ownership and times in `meta`
+///
+/// Only root can give files away, so as with GNU `cp -p`, a copy that
+/// can't be chowned quietly stays the caller's.
+fn preserve(path: &str, meta: &syscall::FileMetadata) -> syscall::SyscallResult<()> {
+    let path = absolute(path);
+    let _ = syscall::chown(&path, Some(meta.uid), Some(meta.gid));
+    syscall::chmod(&path, meta.mode)?;
+    syscall::utimes(&path, Some(meta.atime), Some(meta.mtime))
+}
+
+pub static MV: CommandSpec = CommandSpec {
+    name: "mv",
+    summary: "move or rename files",
+    description: "Rename SOURCE to DEST, or move each SOURCE into the directory DEST. Between filesystems, files and directories are copied with their mode, ownership and timestamps, then removed. With -i, each answer is read from a line of stdin, and no answer means no.",
+    flags: &[
+        Flag::new('f', "force", "Never ask before overwriting"),
+        Flag::new('i', "interactive", "Ask before overwriting"),
+        Flag::new('v', "verbose", "Print each file moved"),
+    ],
+    args: &[
+        Arg::new("SOURCE", ArgKind::Path).repeated(),
+        Arg::new("DEST", ArgKind::Path),
+    ],
+};
+
 /// mv - move/rename files
-pub fn prog_mv(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: mv SOURCE DEST\nMove or rename files. See 'man mv' for details.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
+pub fn prog_mv(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match MV.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&MV, stdout, stderr),
+    };
+    let interactive = m.last_of(&["i", "f"]) == Some("i");
+    let verbose = m.flag("v");
+    let mut io = Io::new(stdin, stdout, stderr);
+
+    let Some((dest, sources)) = m.operands.split_last() else {
+        return 1;
+    };
+    let into = is_dir(dest) && !matches!(sources, [src] if respells(src, dest));
+    if sources.len() > 1 && !into {
+        io.stderr
+            .push_str(&format!("mv: target '{}' is not a directory\n", dest));
+        return 1;
     }
 
-    if args.len() < 2 {
-        stderr.push_str("mv: missing operand\n");
-        return 1;
+    let mut failed = false;
+    for src in sources {
+        let dst = if into {
+            join(dest, base_name(src))
+        } else {
+            dest.clone()
+        };
+        match move_path(src, &dst, interactive, &mut io) {
+            Ok(false) => {}
+            Ok(true) => {
+                if verbose {
+                    io.stdout
+                        .push_str(&format!("renamed '{}' -> '{}'\n", src, dst));
+                }
+            }
+            Err(e) => {
+                io.stderr.push_str(&format!("mv: {}\n", e));
+                failed = true;
+            }
+        }
+    }
+
+    if failed { 1 } else { 0 }
+}
+
+/// Move `src` to `dst`, replacing a file or empty directory there
+///
+/// Returns whether it moved; it doesn't if `-i` was answered no.
+pub(super) fn move_path(
+    src: &str,
+    dst: &str,
+    interactive: bool,
+    io: &mut Io,
+) -> Result<bool, String> {
+    let cannot = |e: syscall::SyscallError| format!("cannot move '{}' to '{}': {}", src, dst, e);
+    let meta = syscall::metadata(src).map_err(cannot)?;
+    let (from, to) = (absolute(src), absolute(dst));
+    if from == to {
+        return Err(format!("'{}' and '{}' are the same file", src, dst));
+    }
+    if meta.is_dir && is_within(&to, &from) {
+        return Err(format!(
+            "cannot move '{}' to a subdirectory of itself, '{}'",
+            src, dst
+        ));
     }
 
-    let src = &args[0];
-    let dst = &args[1];
+    if !respells(src, dst)
+        && let Ok(existing) = syscall::metadata(dst)
+    {
+        match (meta.is_dir, existing.is_dir) {
+            (false, true) => {
+                return Err(format!(
+                    "cannot overwrite directory '{}' with non-directory",
+                    dst
+                ));
+            }
+            (true, false) => {
+                return Err(format!(
+                    "cannot overwrite non-directory '{}' with directory '{}'",
+                    dst, src
+                ));
+            }
+            _ => {}
+        }
+        if interactive && !io.confirm(&format!("mv: overwrite '{}'? ", dst)) {
+            return Ok(false);
+        }
+        // A file is replaced by the rename itself, so it is never gone
+        // before its replacement is there
+        if existing.is_dir {
+            syscall::remove_dir(dst).map_err(cannot)?;
+        }
+    }
 
     match syscall::rename(src, dst) {
-        Ok(()) => 0,
-        Err(e) => {
-            stderr.push_str(&format!("mv: cannot move '{}' to '{}': {}\n", src, dst, e));
-            1
+        Ok(()) => Ok(true),
+        // Between filesystems the tree is copied, then the original removed
+        Err(syscall::SyscallError::CrossDevice) => {
+            let copy = CopyOptions {
+                recursive: true,
+                preserve: true,
+                interactive: false,
+                verbose: false,
+                dereference: false,
+            };
+            let remove = RemoveOptions {
+                recursive: true,
+                interactive: false,
+                verbose: false,
+            };
+            // Both report what went wrong, and with which file
+            if !copy_tree(src, dst, false, &copy, io)
+                || remove_tree(src, &meta, &remove, io) != Removal::Removed
+            {
+                return Err(format!("cannot move '{}' to '{}'", src, dst));
+            }
+            Ok(true)
         }
+        Err(e) => Err(cannot(e)),
     }
 }
 
-/// ln - create symbolic links
-pub fn prog_ln(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
+/// Where `rm`, `cp` and `mv` write, and read answers to `-i` questions
+pub(super) struct Io<'a> {
+    /// One line of stdin per question
+    answers: std::str::Lines<'a>,
+    pub(super) stdout: &'a mut String,
+    pub(super) stderr: &'a mut String,
+}
 
-    if let Some(help) = check_help(
-        &args,
-        "Usage: ln -s TARGET LINK_NAME\nCreate symbolic links. See 'man ln' for details.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
+impl<'a> Io<'a> {
+    pub(super) fn new(stdin: &'a str, stdout: &'a mut String, stderr: &'a mut String) -> Self {
+        Self {
+            answers: stdin.lines(),
+            stdout,
+            stderr,
+        }
     }
 
-    // Parse flags
-    let mut symbolic = false;
-    let mut force = false;
-    let mut targets: Vec<&str> = Vec::new();
-
-    for arg in &args {
-        if *arg == "-s" || *arg == "--symbolic" {
-            symbolic = true;
-        } else if *arg == "-f" || *arg == "--force" {
-            force = true;
-        } else if let Some(flags) = arg.strip_prefix('-') {
-            // Handle combined flags like -sf
-            for c in flags.chars() {
-                match c {
-                    's' => symbolic = true,
-                    'f' => force = true,
-                    _ => {
-                        stderr.push_str(&format!("ln: unknown option: -{}\n", c));
-                        return 1;
-                    }
-                }
-            }
-        } else {
-            targets.push(arg);
-        }
+    /// Ask `question` on stderr; only an answer starting with `y` is yes
+    fn confirm(&mut self, question: &str) -> bool {
+        self.stderr.push_str(question);
+        self.stderr.push('\n');
+        self.answers
+            .next()
+            .is_some_and(|answer| answer.trim_start().starts_with(['y', 'Y']))
     }
+}
 
-    if targets.len() < 2 {
-        stderr.push_str("ln: missing file operand\n");
-        stderr.push_str("Usage: ln [-sf] TARGET LINK_NAME\n");
-        return 1;
+/// The last component of `path`
+fn base_name(path: &str) -> &str {
+    let path = path.trim_end_matches('/');
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+/// `name` inside directory `dir`
+fn join(dir: &str, name: &str) -> String {
+    if dir.ends_with('/') {
+        format!("{}{}", dir, name)
+    } else {
+        format!("{}/{}", dir, name)
     }
+}
 
-    if !symbolic {
+/// Whether `dst` is `src` spelled in another case, which on a `casefold`
+/// mount names the same file; moving it there changes the spelling
+fn respells(src: &str, dst: &str) -> bool {
+    let (from, to) = (absolute(src), absolute(dst));
+    let (parent, name) = match to.rsplit_once('/') {
+        Some(("", name)) => ("/", name),
+        Some((parent, name)) => (parent, name),
+        None => return false,
+    };
+    from != to
+        && from.to_lowercase() == to.to_lowercase()
+        && syscall::readdir(parent).is_ok_and(|names| !names.iter().any(|n| n == name))
+}
+
+/// Whether `path` is a directory, or a link to one
+fn is_dir(path: &str) -> bool {
+    syscall::realpath(path)
+        .and_then(|path| syscall::metadata(&path))
+        .is_ok_and(|meta| meta.is_dir)
+}
+
+/// Whether absolute path `path` is `dir` or somewhere below it
+fn is_within(path: &str, dir: &str) -> bool {
+    dir == "/"
+        || path
+            .strip_prefix(dir)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+pub static LN: CommandSpec = CommandSpec {
+    name: "ln",
+    summary: "make links between files",
+    description: "Create a symbolic link to TARGET named LINK_NAME. Only symbolic links are supported, so -s is required.",
+    flags: &[
+        Flag::new('s', "symbolic", "Make a symbolic link (required)"),
+        Flag::new('f', "force", "Remove an existing LINK_NAME first"),
+    ],
+    args: &[
+        Arg::new("TARGET", ArgKind::Path),
+        Arg::new("LINK_NAME", ArgKind::Path),
+    ],
+};
+
+/// ln - create symbolic links
+pub fn prog_ln(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match LN.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&LN, stdout, stderr),
+    };
+
+    if !m.flag("s") {
         stderr.push_str("ln: hard links not supported, use -s for symbolic links\n");
         return 1;
     }
 
-    let target = targets[0];
-    let link_name = targets[1];
+    let (target, link_name) = (&m.operands[0], &m.operands[1]);
 
     // If force, try to remove existing link
-    if force {
+    if m.flag("f") {
         let _ = syscall::remove_file(link_name);
     }
 
@@ -376,6 +957,14 @@ pub fn prog_ln(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut
     }
 }
 
+pub static READLINK: CommandSpec = CommandSpec {
+    name: "readlink",
+    summary: "print the target of a symbolic link",
+    description: "Print the path the symbolic link FILE points to.",
+    flags: &[],
+    args: &[Arg::new("FILE", ArgKind::Path)],
+};
+
 /// readlink - print value of a symbolic link
 pub fn prog_readlink(
     args: &[String],
@@ -383,14 +972,15 @@ pub fn prog_readlink(
     stdout: &mut String,
     stderr: &mut String,
 ) -> i32 {
-    let args = args_to_strs(args);
-
     if args.is_empty() {
         stderr.push_str("readlink: missing file operand\n");
         return 1;
     }
-
-    let path = &args[0];
+    let m = match READLINK.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&READLINK, stdout, stderr),
+    };
+    let path = &m.operands[0];
 
     match syscall::read_link(path) {
         Ok(target) => {
@@ -404,19 +994,21 @@ pub fn prog_readlink(
     }
 }
 
+pub static TREE: CommandSpec = CommandSpec {
+    name: "tree",
+    summary: "list contents of directories in a tree-like format",
+    description: "Show the directory structure under DIRECTORY, or the current directory, as a tree.",
+    flags: &[],
+    args: &[Arg::new("DIRECTORY", ArgKind::Dir).optional()],
+};
+
 /// tree - display directory tree
 pub fn prog_tree(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let paths = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &paths,
-        "Usage: tree [DIRECTORY]\nDisplay directory tree. See 'man tree' for details.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
-
-    let path = if paths.is_empty() { "." } else { paths[0] };
+    let m = match TREE.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&TREE, stdout, stderr),
+    };
+    let path = m.operand(0).unwrap_or(".");
 
     // ANSI colors
     const BLUE: &str = "\x1b[34m";
@@ -512,6 +1104,99 @@ pub fn prog_tree(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mu
     0
 }
 
+/// Characters the X's of a `mktemp` template become
+const TEMP_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+/// Names `mktemp` tries before giving up
+const TEMP_TRIES: usize = 100;
+
+pub static MKTEMP: CommandSpec = CommandSpec {
+    name: "mktemp",
+    summary: "create a temporary file or directory",
+    description: "Create a file (mode 600) or directory (mode 700) named from TEMPLATE, its trailing X's (at least 3) replaced with random characters, and print its name. The default template is tmp.XXXXXXXXXX in $TMPDIR or /tmp.",
+    flags: &[
+        Flag::short('d', "Create a directory"),
+        Flag::short('u', "Only print a name, creating nothing"),
+        Flag::short('p', "Put TEMPLATE in DIR").value("DIR"),
+        Flag::short('t', "Put TEMPLATE in $TMPDIR or /tmp"),
+    ],
+    args: &[Arg::new("TEMPLATE", ArgKind::Path).optional()],
+};
+
+/// mktemp - create a file or directory with a unique random name
+pub fn prog_mktemp(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match MKTEMP.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&MKTEMP, stdout, stderr),
+    };
+    let dir_mode = m.flag("d");
+    let dry_run = m.flag("u");
+    let parent = m.value("p");
+    let template = m.operand(0);
+
+    let tmpdir = || {
+        syscall::getenv("TMPDIR")
+            .ok()
+            .flatten()
+            .filter(|dir| !dir.is_empty())
+            .unwrap_or_else(|| String::from("/tmp"))
+    };
+    // Without a template the default goes in the temporary directory
+    let in_tmp = m.flag("t") || template.is_none();
+    let template = template.unwrap_or("tmp.XXXXXXXXXX");
+    if (parent.is_some() || in_tmp) && template.contains('/') {
+        stderr.push_str(&format!(
+            "mktemp: {}: template must not contain '/'\n",
+            template
+        ));
+        return 1;
+    }
+    let template = match parent {
+        Some(dir) => format!("{}/{}", dir.trim_end_matches('/'), template),
+        None if in_tmp => {
+            format!("{}/{}", tmpdir().trim_end_matches('/'), template)
+        }
+        None => template.to_string(),
+    };
+    let xs = template.len() - template.trim_end_matches('X').len();
+    if xs < 3 {
+        stderr.push_str(&format!("mktemp: {}: too few X's at the end\n", template));
+        return 1;
+    }
+    let stem = &template[..template.len() - xs];
+
+    for _ in 0..TEMP_TRIES {
+        let mut path = stem.to_string();
+        for _ in 0..xs {
+            match random_below(TEMP_CHARS.len() as u64) {
+                Ok(n) => path.push(TEMP_CHARS[n as usize] as char),
+                Err(e) => {
+                    stderr.push_str(&format!("mktemp: {}\n", e));
+                    return 1;
+                }
+            }
+        }
+        if syscall::exists(&path).unwrap_or(false) {
+            continue;
+        }
+        if !dry_run {
+            let (created, mode) = match dir_mode {
+                true => (syscall::mkdir(&path), 0o700),
+                false => (syscall::write_file(&path, ""), 0o600),
+            };
+            if let Err(e) = created.and_then(|()| syscall::chmod(&path, mode)) {
+                stderr.push_str(&format!("mktemp: {}: {}\n", path, e));
+                return 1;
+            }
+        }
+        stdout.push_str(&path);
+        stdout.push('\n');
+        return 0;
+    }
+    stderr.push_str(&format!("mktemp: {}: no unused name found\n", template));
+    1
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -519,12 +1204,12 @@ mod tests {
     #[test]
     fn test_cat_stdin() {
         let args: Vec<String> = vec![];
-        let stdin = "hello world";
-        let mut stdout = String::new();
+        let stdin = b"hello world\xff";
+        let mut stdout = Vec::new();
         let mut stderr = String::new();
         let code = prog_cat(&args, stdin, &mut stdout, &mut stderr);
         assert_eq!(code, 0);
-        assert_eq!(stdout, "hello world");
+        assert_eq!(stdout, b"hello world\xff");
     }
 
     #[test]
@@ -537,6 +1222,51 @@ mod tests {
         assert!(stdout.contains("Usage:"));
     }
 
+    #[test]
+    fn test_ls_long() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        syscall::mkdir("/tmp/l").unwrap();
+        syscall::mkdir("/tmp/l/shared").unwrap();
+        syscall::chmod("/tmp/l/shared", 0o1777).unwrap();
+        syscall::write_file("/tmp/l/su", "#!").unwrap();
+        syscall::chmod("/tmp/l/su", 0o4755).unwrap();
+        syscall::write_file("/tmp/l/sg", "").unwrap();
+        syscall::chmod("/tmp/l/sg", 0o2644).unwrap();
+
+        let args = vec!["-l".to_string(), "/tmp/l".to_string()];
+        let (mut stdout, mut stderr) = (String::new(), String::new());
+        assert_eq!(prog_ls(&args, "", &mut stdout, &mut stderr), 0);
+        let line = |name: &str| {
+            stdout
+                .lines()
+                .find(|l| l.contains(name))
+                .unwrap()
+                .to_string()
+        };
+        assert!(line("shared").starts_with("drwxrwxrwt "));
+        assert!(line("su").starts_with("-rwsr-xr-x "));
+        assert!(line("sg").starts_with("-rw-r-Sr-- "));
+        assert!(line("su").ends_with(" 2 1970-01-01 00:00 su"));
+
+        // A file operand is a single row
+        let args = vec!["-l".to_string(), "/tmp/l/su".to_string()];
+        let (mut stdout, mut stderr) = (String::new(), String::new());
+        assert_eq!(prog_ls(&args, "", &mut stdout, &mut stderr), 0);
+        assert!(stdout.starts_with("-rwsr-xr-x "));
+        assert!(stdout.ends_with(" 2 1970-01-01 00:00 /tmp/l/su"));
+        let args = vec!["/tmp/l/sg".to_string(), "/tmp/l/none".to_string()];
+        let (mut stdout, mut stderr) = (String::new(), String::new());
+        assert_eq!(prog_ls(&args, "", &mut stdout, &mut stderr), 1);
+        assert_eq!(stdout, "/tmp/l/sg");
+        assert!(stderr.starts_with("ls: /tmp/l/none: "));
+    }
+
     #[test]
     fn test_mkdir_missing_operand() {
         let args: Vec<String> = vec![];
@@ -547,6 +1277,29 @@ mod tests {
         assert!(stderr.contains("missing operand"));
     }
 
+    #[test]
+    fn test_mkdir_parents() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        let run = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            prog_mkdir(&args, "", &mut stdout, &mut stderr)
+        };
+
+        assert_eq!(run(&["/tmp/a/b/c"]), 1);
+        assert_eq!(run(&["-p", "/tmp/a/b/c"]), 0);
+        assert!(syscall::metadata("/tmp/a/b/c").unwrap().is_dir);
+        assert_eq!(run(&["-p", "/tmp/a/b"]), 0);
+        syscall::write_file("/tmp/a/file", "").unwrap();
+        assert_eq!(run(&["-p", "/tmp/a/file/d"]), 1);
+    }
+
     #[test]
     fn test_touch_missing_operand() {
         let args: Vec<String> = vec![];
@@ -567,6 +1320,73 @@ mod tests {
         assert!(stderr.contains("missing operand"));
     }
 
+    #[test]
+    fn test_rm_force() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        syscall::mkdir("/tmp/junk").unwrap();
+        let run = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = prog_rm(&args, "", &mut stdout, &mut stderr);
+            (code, stderr)
+        };
+
+        assert_eq!(run(&["/tmp/missing"]).0, 1);
+        assert_eq!(run(&["-f", "/tmp/missing"]), (0, String::new()));
+        assert!(run(&["-f", "/tmp/junk"]).1.contains("Is a directory"));
+        assert_eq!(run(&["-rf", "/tmp/junk", "/tmp/missing"]).0, 0);
+        assert!(!syscall::exists("/tmp/junk").unwrap());
+        assert!(run(&["-x", "/tmp/a"]).1.contains("unknown option: -x"));
+    }
+
+    #[test]
+    fn test_rm_recursive() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        let run = |args: &[&str], stdin: &str| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = prog_rm(&args, stdin, &mut stdout, &mut stderr);
+            (code, stdout, stderr)
+        };
+        syscall::mkdir("/tmp/t").unwrap();
+        syscall::mkdir("/tmp/t/sub").unwrap();
+        syscall::write_file("/tmp/t/sub/a", "a").unwrap();
+        syscall::write_file("/tmp/t/b", "b").unwrap();
+        syscall::write_file("/tmp/kept", "").unwrap();
+        syscall::symlink("/tmp/kept", "/tmp/t/link").unwrap();
+
+        // Declining keeps the file, and so the directories above it
+        let (code, _, stderr) = run(&["-ri", "/tmp/t"], "y\nn\ny\ny\ny\ny\ny\n");
+        assert_eq!(code, 0);
+        assert!(stderr.contains("rm: descend into directory '/tmp/t'? "));
+        assert!(syscall::exists("/tmp/t/b").unwrap());
+        assert!(!syscall::exists("/tmp/t/link").unwrap());
+        assert!(!syscall::exists("/tmp/t/sub").unwrap());
+
+        let (code, stdout, _) = run(&["-rv", "/tmp/t"], "");
+        assert_eq!(code, 0);
+        assert_eq!(stdout, "removed '/tmp/t/b'\nremoved directory '/tmp/t'\n");
+        assert!(syscall::exists("/tmp/kept").unwrap());
+
+        // -f after -i never asks
+        assert_eq!(run(&["-if", "/tmp/kept"], "").0, 0);
+        assert!(!syscall::exists("/tmp/kept").unwrap());
+        assert!(run(&["-r", "/tmp/.."], "").2.contains("refusing to remove"));
+        assert!(run(&["-rf", "/"], "").2.contains("recursively on '/'"));
+    }
+
     #[test]
     fn test_cp_missing_operand() {
         let args: Vec<String> = vec![];
@@ -577,6 +1397,70 @@ mod tests {
         assert!(stderr.contains("missing operand"));
     }
 
+    #[test]
+    fn test_cp_recursive() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        let run = |args: &[&str], stdin: &str| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = prog_cp(&args, stdin, &mut stdout, &mut stderr);
+            (code, stdout, stderr)
+        };
+        syscall::mkdir("/tmp/src").unwrap();
+        syscall::mkdir("/tmp/src/sub").unwrap();
+        syscall::write_file("/tmp/src/sub/a", "a").unwrap();
+        syscall::symlink("sub/a", "/tmp/src/link").unwrap();
+        syscall::chmod("/tmp/src/sub/a", 0o600).unwrap();
+        syscall::utimes("/tmp/src/sub/a", Some(5.0), Some(7.0)).unwrap();
+
+        let (code, _, stderr) = run(&["/tmp/src", "/tmp/dst"], "");
+        assert_eq!(code, 1);
+        assert!(stderr.contains("-r not specified; omitting directory '/tmp/src'"));
+        assert!(
+            run(&["-r", "/tmp/src", "/tmp/src/sub"], "")
+                .2
+                .contains("into itself")
+        );
+
+        let (code, stdout, _) = run(&["-rpv", "/tmp/src", "/tmp/dst"], "");
+        assert_eq!(code, 0);
+        assert!(stdout.contains("'/tmp/src/sub/a' -> '/tmp/dst/sub/a'\n"));
+        let meta = syscall::metadata("/tmp/dst/sub/a").unwrap();
+        assert_eq!((meta.mode, meta.atime, meta.mtime), (0o600, 5.0, 7.0));
+        assert_eq!(syscall::read_file("/tmp/dst/sub/a").unwrap(), "a");
+        // Links below the tree stay links, unless -L
+        assert_eq!(syscall::read_link("/tmp/dst/link").unwrap(), "sub/a");
+        assert_eq!(run(&["-rL", "/tmp/src", "/tmp/deref"], "").0, 0);
+        assert!(!syscall::metadata("/tmp/deref/link").unwrap().is_symlink);
+
+        // A link operand is followed unless -P
+        assert_eq!(run(&["/tmp/src/link", "/tmp/file"], "").0, 0);
+        assert!(!syscall::metadata("/tmp/file").unwrap().is_symlink);
+        assert_eq!(run(&["-LP", "/tmp/src/link", "/tmp/copy"], "").0, 0);
+        assert!(syscall::metadata("/tmp/copy").unwrap().is_symlink);
+
+        // Several sources go into a directory; -i reads its answers from stdin
+        syscall::write_file("/tmp/dst/file", "old").unwrap();
+        assert!(
+            run(&["/tmp/file", "/tmp/copy", "/tmp/file"], "")
+                .2
+                .contains("target '/tmp/file' is not a directory")
+        );
+        let (code, _, stderr) = run(&["-iP", "/tmp/file", "/tmp/copy", "/tmp/dst"], "n\n");
+        assert_eq!(code, 0);
+        assert!(stderr.contains("cp: overwrite '/tmp/dst/file'? "));
+        assert_eq!(syscall::read_file("/tmp/dst/file").unwrap(), "old");
+        assert!(syscall::metadata("/tmp/dst/copy").unwrap().is_symlink);
+        assert_eq!(run(&["-i", "/tmp/file", "/tmp/dst"], "y\n").0, 0);
+        assert_eq!(syscall::read_file("/tmp/dst/file").unwrap(), "a");
+    }
+
     #[test]
     fn test_mv_missing_operand() {
         let args: Vec<String> = vec![];
@@ -587,6 +1471,53 @@ mod tests {
         assert!(stderr.contains("missing operand"));
     }
 
+    #[test]
+    fn test_mv_into_directory() {
+        use crate::kernel::mount::MountOptions;
+        use crate::kernel::syscall::{KERNEL, Kernel};
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        let run = |args: &[&str], stdin: &str| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = prog_mv(&args, stdin, &mut stdout, &mut stderr);
+            (code, stdout, stderr)
+        };
+        syscall::mkdir("/tmp/d").unwrap();
+        syscall::mkdir("/tmp/d/sub").unwrap();
+        syscall::write_file("/tmp/d/sub/a", "a").unwrap();
+        syscall::write_file("/tmp/x", "x").unwrap();
+        syscall::write_file("/tmp/y", "y").unwrap();
+
+        let (code, _, stderr) = run(&["/tmp/d", "/tmp/d/sub"], "");
+        assert_eq!(code, 1);
+        assert!(stderr.contains("to a subdirectory of itself"));
+
+        let (code, stdout, _) = run(&["-v", "/tmp/x", "/tmp/y", "/tmp/d"], "");
+        assert_eq!(code, 0);
+        assert_eq!(
+            stdout,
+            "renamed '/tmp/x' -> '/tmp/d/x'\nrenamed '/tmp/y' -> '/tmp/d/y'\n"
+        );
+        assert_eq!(run(&["-i", "/tmp/d/x", "/tmp/d/y"], "").0, 0);
+        assert_eq!(syscall::read_file("/tmp/d/y").unwrap(), "y");
+        assert_eq!(run(&["/tmp/d/x", "/tmp/d/y"], "").0, 0);
+        assert_eq!(syscall::read_file("/tmp/d/y").unwrap(), "x");
+
+        // Between filesystems a directory is copied, then removed
+        syscall::mkdir("/tmp/lower").unwrap();
+        syscall::mkdir("/tmp/o").unwrap();
+        let options = MountOptions::default();
+        syscall::mount_overlay("overlay", &["/tmp/lower"], "/tmp/o", options).unwrap();
+        assert_eq!(run(&["/tmp/d", "/tmp/o/moved"], "").0, 0);
+        assert_eq!(syscall::read_file("/tmp/o/moved/sub/a").unwrap(), "a");
+        assert!(!syscall::exists("/tmp/d").unwrap());
+    }
+
     #[test]
     fn test_ln_requires_symbolic() {
         let args = vec!["target".to_string(), "link".to_string()];
@@ -606,4 +1537,46 @@ mod tests {
         assert_eq!(code, 1);
         assert!(stderr.contains("missing file operand"));
     }
+
+    #[test]
+    fn test_mktemp() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        syscall::mkdir("/tmp/work").unwrap();
+        let run = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = prog_mktemp(&args, "", &mut stdout, &mut stderr);
+            (code, stdout.trim_end().to_string(), stderr)
+        };
+
+        let (code, file, _) = run(&[]);
+        assert_eq!(code, 0);
+        assert!(file.starts_with("/tmp/tmp.") && file.len() == "/tmp/tmp.".len() + 10);
+        let meta = syscall::metadata(&file).unwrap();
+        assert!(meta.is_file);
+        assert_eq!(meta.mode & 0o777, 0o600);
+        assert_ne!(run(&[]).1, file);
+
+        let (_, dir, _) = run(&["-d", "-p", "/tmp/work", "build.XXXX"]);
+        assert!(dir.starts_with("/tmp/work/build."));
+        let meta = syscall::metadata(&dir).unwrap();
+        assert!(meta.is_dir);
+        assert_eq!(meta.mode & 0o777, 0o700);
+
+        let (_, name, _) = run(&["-u", "/tmp/work/x.XXX"]);
+        assert!(name.starts_with("/tmp/work/x."));
+        assert!(!syscall::exists(&name).unwrap());
+
+        syscall::setenv("TMPDIR", "/tmp/work").unwrap();
+        assert!(run(&["-t", "t.XXX"]).1.starts_with("/tmp/work/t."));
+        assert_eq!(run(&["a.XX"]).0, 1);
+        assert_eq!(run(&["-p", "/tmp", "a/b.XXX"]).0, 1);
+        assert_eq!(run(&["/nonexistent/a.XXX"]).0, 1);
+    }
 }
@@ -6,19 +6,33 @@
 //! - `fsreset`: Reset OPFS storage
 //! - `autosave`: Configure automatic filesystem saving
 //! - `find`: Search for files and directories
+//! - `updatedb`, `locate`, `search`: Look paths and words up in the file index
 //! - `du`: Disk usage analyzer
 //! - `df`: Filesystem space usage
+//! - `quota`: Per-user disk usage and limits
+//! - `fsck`: Check and repair the filesystem
+//! - `snapshot`: Named filesystem snapshots with rollback
+//! - `vfs`: Export and import the whole filesystem as a tar archive
+//! - `getfattr`, `setfattr`: Read and write extended attributes
 
-use super::{args_to_strs, check_help};
 use crate::kernel::syscall;
+use crate::shell::argparse::{Arg, ArgKind, CommandSpec, Flag};
+use std::collections::HashMap;
+
+pub static SAVE: CommandSpec = CommandSpec {
+    name: "save",
+    summary: "save the filesystem to OPFS",
+    description: "Save the filesystem to browser storage (OPFS) now, rather than waiting for autosave. The result is logged to the browser console.",
+    flags: &[],
+    args: &[],
+};
 
 /// save - save filesystem to OPFS
-pub fn prog_save(
-    _args: &[String],
-    __stdin: &str,
-    stdout: &mut String,
-    _stderr: &mut String,
-) -> i32 {
+pub fn prog_save(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    if let Err(e) = SAVE.parse(args) {
+        return e.report(&SAVE, stdout, stderr);
+    }
+
     // Queue the async save operation
     #[cfg(target_arch = "wasm32")]
     {
@@ -40,10 +54,15 @@ pub fn prog_save(
                 }
             };
 
-            if let Err(e) = Persistence::save(&fs).await {
-                crate::console_log!("[save] Save failed: {}", e);
-            } else {
-                crate::console_log!("[save] Filesystem saved to OPFS");
+            match Persistence::save(
+                &fs,
+                syscall::persist_journaled(),
+                syscall::persist_compressed(),
+            )
+            .await
+            {
+                Ok(backend) => crate::console_log!("[save] Filesystem saved to {}", backend),
+                Err(e) => crate::console_log!("[save] Save failed: {}", e),
             }
         });
     }
@@ -52,20 +71,23 @@ pub fn prog_save(
     0
 }
 
+pub static FSLOAD: CommandSpec = CommandSpec {
+    name: "fsload",
+    summary: "reload the filesystem from OPFS",
+    description: "Reload filesystem from OPFS storage, replacing the one in memory. The result is logged to the browser console.",
+    flags: &[],
+    args: &[],
+};
+
 /// fsload - reload filesystem from OPFS
 pub fn prog_fsload(
     args: &[String],
     __stdin: &str,
     stdout: &mut String,
-    _stderr: &mut String,
+    stderr: &mut String,
 ) -> i32 {
-    let args = args_to_strs(args);
-    if let Some(help) = check_help(
-        &args,
-        "Usage: fsload\nReload filesystem from OPFS storage.\nSee 'man fsload' for details.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
+    if let Err(e) = FSLOAD.parse(args) {
+        return e.report(&FSLOAD, stdout, stderr);
     }
 
     #[cfg(target_arch = "wasm32")]
@@ -73,9 +95,13 @@ pub fn prog_fsload(
         use crate::vfs::Persistence;
         wasm_bindgen_futures::spawn_local(async {
             match Persistence::load().await {
-                Ok(Some(fs)) => {
+                Ok(Some(restored)) => {
+                    for line in Persistence::describe(&restored) {
+                        crate::console_log!("[fsload] {}", line);
+                        syscall::klog(crate::kernel::LogLevel::Warn, "persist", &line);
+                    }
                     // Serialize and restore
-                    match fs.to_json() {
+                    match restored.value.to_json() {
                         Ok(data) => {
                             if let Err(e) = syscall::vfs_restore(&data) {
                                 crate::console_log!("[fsload] Restore failed: {}", e);
@@ -102,6 +128,14 @@ pub fn prog_fsload(
     0
 }
 
+pub static FSRESET: CommandSpec = CommandSpec {
+    name: "fsreset",
+    summary: "clear OPFS storage",
+    description: "Clear OPFS storage, so the next page load starts with a fresh filesystem. Without -f, only warn.",
+    flags: &[Flag::new('f', "force", "Force reset without confirmation")],
+    args: &[],
+};
+
 /// fsreset - clear OPFS and reset to fresh filesystem
 pub fn prog_fsreset(
     args: &[String],
@@ -109,16 +143,12 @@ pub fn prog_fsreset(
     stdout: &mut String,
     stderr: &mut String,
 ) -> i32 {
-    let args = args_to_strs(args);
-    if let Some(help) = check_help(
-        &args,
-        "Usage: fsreset [-f]\nClear OPFS storage and reset filesystem.\n  -f  Force reset without confirmation\nSee 'man fsreset' for details.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
+    let m = match FSRESET.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&FSRESET, stdout, stderr),
+    };
 
-    let force = args.iter().any(|a| *a == "-f" || *a == "--force");
+    let force = m.flag("f");
 
     if !force {
         stderr.push_str("fsreset: This will clear all saved data!\n");
@@ -142,6 +172,22 @@ pub fn prog_fsreset(
     0
 }
 
+pub static AUTOSAVE: CommandSpec = CommandSpec {
+    name: "autosave",
+    summary: "configure automatic filesystem saving",
+    description: "Configure automatic filesystem saving. COMMAND is one of:
+
+  on            Enable auto-save
+  off           Disable auto-save
+  status        Show current settings (the default)
+  interval N    Set commands between saves (default: 10)",
+    flags: &[],
+    args: &[
+        Arg::new("COMMAND", ArgKind::Text).optional(),
+        Arg::new("N", ArgKind::Text).optional(),
+    ],
+};
+
 /// autosave - configure automatic filesystem saving
 pub fn prog_autosave(
     args: &[String],
@@ -149,19 +195,16 @@ pub fn prog_autosave(
     stdout: &mut String,
     stderr: &mut String,
 ) -> i32 {
-    let args = args_to_strs(args);
-    if let Some(help) = check_help(
-        &args,
-        "Usage: autosave [on|off|status|interval N]\nConfigure automatic filesystem saving.\n  on       Enable auto-save\n  off      Disable auto-save\n  status   Show current settings\n  interval Set commands between saves (default: 10)\nSee 'man autosave' for details.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
+    let m = match AUTOSAVE.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&AUTOSAVE, stdout, stderr),
+    };
 
     #[cfg(target_arch = "wasm32")]
     {
         use crate::terminal;
 
+        let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
         if args.is_empty() || (args.len() == 1 && args[0] == "status") {
             let (enabled, interval) = terminal::get_autosave_settings();
             stdout.push_str(&format!(
@@ -206,24 +249,36 @@ pub fn prog_autosave(
 
     #[cfg(not(target_arch = "wasm32"))]
     {
-        let _ = stderr;
+        let _ = (stderr, m);
         stdout.push_str("autosave: not available in this build\n");
     }
 
     0
 }
 
+pub static FIND: CommandSpec = CommandSpec {
+    name: "find",
+    summary: "search for files",
+    description:
+        "List the files under PATH, or the current directory, that pass every test in EXPRESSION:
+
+  -name PATTERN   the name matches PATTERN, which can hold a *
+  -type TYPE      the file is of TYPE: f (file), d (directory) or l (symlink)",
+    flags: &[],
+    // Raw, since the tests in the expression look like flags
+    args: &[
+        Arg::new("PATH", ArgKind::Path).optional().raw(),
+        Arg::new("EXPRESSION", ArgKind::Text).optional().repeated(),
+    ],
+};
+
 /// find - search for files and directories
 pub fn prog_find(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: find [PATH] [-name PATTERN] [-type TYPE]\nSearch for files.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
+    let m = match FIND.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&FIND, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
 
     // Parse arguments
     let mut start_path = ".";
@@ -333,27 +388,248 @@ pub fn prog_find(args: &[String], __stdin: &str, stdout: &mut String, stderr: &m
     0
 }
 
-/// du - disk usage
-pub fn prog_du(args: &[String], __stdin: &str, stdout: &mut String, _stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
+pub static UPDATEDB: CommandSpec = CommandSpec {
+    name: "updatedb",
+    summary: "bring the file index up to date",
+    description: "Bring the index used by locate and search up to date. The indexer service does this in the background; only what changed is reindexed.",
+    flags: &[Flag::short(
+        'f',
+        "Compare the whole filesystem instead of following the change feed",
+    )],
+    args: &[],
+};
+
+/// updatedb - bring the file index up to date
+pub fn prog_updatedb(
+    args: &[String],
+    __stdin: &str,
+    stdout: &mut String,
+    stderr: &mut String,
+) -> i32 {
+    use crate::shell::indexer;
+
+    let m = match UPDATEDB.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&UPDATEDB, stdout, stderr),
+    };
 
-    if let Some(help) = check_help(
-        &args,
-        "Usage: du [-s] [-h] [PATH...]\nEstimate file space usage.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
+    let stats = match indexer::update(m.flag("f")) {
+        Ok(stats) => stats,
+        Err(e) => {
+            stderr.push_str(&format!("updatedb: {}\n", e));
+            return 1;
+        }
+    };
+    let (paths, words) =
+        indexer::with_index(|index| (index.len(), index.term_count())).unwrap_or_default();
+    stdout.push_str(&format!(
+        "updatedb: {} indexed, {} removed; {} paths, {} words\n",
+        stats.indexed, stats.removed, paths, words
+    ));
+    0
+}
+
+/// Check if the caller can list `dir` and every directory above it
+fn can_list(dir: &str, cache: &mut HashMap<String, bool>) -> bool {
+    if let Some(&known) = cache.get(dir) {
+        return known;
     }
+    let parent = match dir.rfind('/') {
+        Some(0) if dir.len() > 1 => Some("/"),
+        Some(0) | None => None,
+        Some(i) => Some(&dir[..i]),
+    };
+    let listable = parent.is_none_or(|p| can_list(p, cache)) && syscall::readdir(dir).is_ok();
+    cache.insert(dir.to_string(), listable);
+    listable
+}
 
-    let summary_only = args.contains(&"-s");
-    let human_readable = args.contains(&"-h");
-    let paths: Vec<&str> = args
-        .iter()
-        .filter(|a| !a.starts_with('-'))
-        .copied()
-        .collect();
+pub static LOCATE: CommandSpec = CommandSpec {
+    name: "locate",
+    summary: "find paths by name in the file index",
+    description: "List indexed paths matching any PATTERN.
+
+A PATTERN with *, ? or [ must match the whole path (* spans directories); any other is matched anywhere in the path. path:DIR keeps to paths under DIR and ext:EXT[,EXT...] to files with one of the extensions.",
+    flags: &[
+        Flag::short('i', "Ignore case"),
+        Flag::short('c', "Print the number of matches instead"),
+        Flag::short('l', "Stop after N matches").value("N"),
+    ],
+    args: &[Arg::new("PATTERN", ArgKind::Text).repeated()],
+};
+
+/// locate - find paths by name in the file index
+pub fn prog_locate(
+    args: &[String],
+    __stdin: &str,
+    stdout: &mut String,
+    stderr: &mut String,
+) -> i32 {
+    use crate::shell::indexer;
 
-    let paths = if paths.is_empty() { vec!["."] } else { paths };
+    let m = match LOCATE.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&LOCATE, stdout, stderr),
+    };
+
+    let ignore_case = m.flag("i");
+    let count = m.flag("c");
+    let limit = match m.value("l").map(str::parse) {
+        None => usize::MAX,
+        Some(Ok(n)) => n,
+        Some(Err(_)) => {
+            stderr.push_str("locate: -l needs a number\n");
+            return 2;
+        }
+    };
+    let patterns: Vec<&str> = m.operands.iter().map(String::as_str).collect();
+    let (filter, patterns) = indexer::PathFilter::split(&patterns, super::absolute);
+    let patterns: Vec<String> = patterns.into_iter().map(str::to_string).collect();
+    if patterns.is_empty() && filter.is_empty() {
+        stderr.push_str("locate: no pattern to search for specified\n");
+        return 2;
+    }
+
+    let found = indexer::with_index(|index| {
+        let mut cache = HashMap::new();
+        index
+            .locate(&patterns, ignore_case, &filter)
+            .filter(|path| {
+                let parent = match path.rfind('/') {
+                    Some(0) | None => "/",
+                    Some(i) => &path[..i],
+                };
+                can_list(parent, &mut cache)
+            })
+            .take(limit)
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    });
+    let found = match found {
+        Ok(found) => found,
+        Err(e) => {
+            stderr.push_str(&format!("locate: {}\n", e));
+            return 1;
+        }
+    };
+
+    if count {
+        stdout.push_str(&format!("{}\n", found.len()));
+    } else {
+        for path in &found {
+            stdout.push_str(path);
+            stdout.push('\n');
+        }
+    }
+    if found.is_empty() { 1 } else { 0 }
+}
+
+pub static SEARCH: CommandSpec = CommandSpec {
+    name: "search",
+    summary: "ranked full-text search of the file index",
+    description: "Find the files holding every WORD, best matches first, and print the lines with any of them as FILE:LINE:TEXT. Words are matched whole and without regard to case. path:DIR keeps to files under DIR and ext:EXT[,EXT...] to files with one of the extensions.",
+    flags: &[
+        Flag::short('l', "Print only the file names"),
+        Flag::short('n', "Print at most N lines (default 50)").value("N"),
+    ],
+    args: &[Arg::new("WORD", ArgKind::Text).repeated()],
+};
+
+/// search - ranked full-text search of the file index
+pub fn prog_search(
+    args: &[String],
+    __stdin: &str,
+    stdout: &mut String,
+    stderr: &mut String,
+) -> i32 {
+    use crate::shell::indexer;
+
+    let m = match SEARCH.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&SEARCH, stdout, stderr),
+    };
+
+    let names_only = m.flag("l");
+    let limit = match m.value("n").map(str::parse) {
+        None => 50,
+        Some(Ok(n)) => n,
+        Some(Err(_)) => {
+            stderr.push_str("search: -n needs a number\n");
+            return 2;
+        }
+    };
+    let query: Vec<&str> = m.operands.iter().map(String::as_str).collect();
+    let (filter, query) = indexer::PathFilter::split(&query, super::absolute);
+    let query = query.join(" ");
+    if indexer::words(&query).next().is_none() {
+        stderr.push_str("search: no words to search for\n");
+        return 2;
+    }
+
+    let hits = match indexer::with_index(|index| index.search(&query, &filter)) {
+        Ok(hits) => hits,
+        Err(e) => {
+            stderr.push_str(&format!("search: {}\n", e));
+            return 1;
+        }
+    };
+
+    let mut printed = 0;
+    for hit in hits {
+        if printed >= limit {
+            break;
+        }
+        // Read as the caller: files they can't read don't show up
+        let Ok(text) = syscall::read_file(&hit.path) else {
+            continue;
+        };
+        if names_only {
+            stdout.push_str(&format!("{}\n", hit.path));
+            printed += 1;
+            continue;
+        }
+        let lines: Vec<&str> = text.lines().collect();
+        for n in hit.lines {
+            if printed >= limit {
+                break;
+            }
+            if let Some(line) = lines.get(n as usize - 1) {
+                stdout.push_str(&format!("{}:{}:{}\n", hit.path, n, line));
+                printed += 1;
+            }
+        }
+    }
+    if printed == 0 { 1 } else { 0 }
+}
+
+pub static DU: CommandSpec = CommandSpec {
+    name: "du",
+    summary: "estimate file space usage",
+    description: "Print the space used under each PATH, or the current directory, in 1K blocks. Holes in sparse files take no space.",
+    flags: &[
+        Flag::short('s', "Print only a total for each PATH"),
+        Flag::short('h', "Print sizes in K, M and G"),
+        Flag::long(
+            "apparent-size",
+            "Count file lengths rather than space used, holes included",
+        ),
+    ],
+    args: &[Arg::new("PATH", ArgKind::Path).optional().repeated()],
+};
+
+/// du - disk usage
+pub fn prog_du(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match DU.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&DU, stdout, stderr),
+    };
+    let summary_only = m.flag("s");
+    let human_readable = m.flag("h");
+    let apparent = m.flag("apparent-size");
+    let mut paths = m.operands;
+    if paths.is_empty() {
+        paths.push(String::from("."));
+    }
 
     fn format_size(size: u64, human: bool) -> String {
         if human {
@@ -371,12 +647,18 @@ pub fn prog_du(args: &[String], __stdin: &str, stdout: &mut String, _stderr: &mu
         }
     }
 
-    fn du_recursive(path: &str, human: bool, summary: bool, stdout: &mut String) -> u64 {
+    fn du_recursive(
+        path: &str,
+        human: bool,
+        summary: bool,
+        apparent: bool,
+        stdout: &mut String,
+    ) -> u64 {
         let mut total: u64 = 0;
 
         if let Ok(meta) = syscall::metadata(path) {
             if meta.is_file {
-                total = meta.size;
+                total = if apparent { meta.size } else { meta.allocated };
             } else if meta.is_dir
                 && let Ok(entries) = syscall::readdir(path)
             {
@@ -386,7 +668,7 @@ pub fn prog_du(args: &[String], __stdin: &str, stdout: &mut String, _stderr: &mu
                     } else {
                         format!("{}/{}", path, entry)
                     };
-                    let sub_size = du_recursive(&full, human, true, stdout);
+                    let sub_size = du_recursive(&full, human, true, apparent, stdout);
                     total += sub_size;
                 }
             }
@@ -399,7 +681,7 @@ pub fn prog_du(args: &[String], __stdin: &str, stdout: &mut String, _stderr: &mu
         total
     }
 
-    for path in paths {
+    for path in &paths {
         let resolved = if path == "." {
             syscall::getcwd()
                 .map(|p| p.to_string_lossy().to_string())
@@ -411,7 +693,7 @@ pub fn prog_du(args: &[String], __stdin: &str, stdout: &mut String, _stderr: &mu
             format!("{}/{}", cwd.display(), path)
         };
 
-        let total = du_recursive(&resolved, human_readable, summary_only, stdout);
+        let total = du_recursive(&resolved, human_readable, summary_only, apparent, stdout);
         if summary_only {
             stdout.push_str(&format!(
                 "{}\t{}\n",
@@ -424,23 +706,27 @@ pub fn prog_du(args: &[String], __stdin: &str, stdout: &mut String, _stderr: &mu
     0
 }
 
-/// df - filesystem space
-pub fn prog_df(args: &[String], __stdin: &str, stdout: &mut String, _stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(&args, "Usage: df [-h]\nShow filesystem disk space usage.") {
-        stdout.push_str(&help);
-        return 0;
-    }
+pub static DF: CommandSpec = CommandSpec {
+    name: "df",
+    summary: "report file system disk space usage",
+    description: "Show the size, used and available space of the virtual filesystem.",
+    flags: &[Flag::short('h', "Print sizes in K, M and G")],
+    args: &[],
+};
 
-    let human_readable = args.contains(&"-h");
+/// df - filesystem space
+pub fn prog_df(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let human_readable = match DF.parse(args) {
+        Ok(m) => m.flag("h"),
+        Err(e) => return e.report(&DF, stdout, stderr),
+    };
 
     // Calculate total VFS size by walking the filesystem
     fn count_size(path: &str) -> u64 {
         let mut total: u64 = 0;
         if let Ok(meta) = syscall::metadata(path) {
             if meta.is_file {
-                total = meta.size;
+                total = meta.allocated;
             } else if meta.is_dir
                 && let Ok(entries) = syscall::readdir(path)
             {
@@ -460,11 +746,7 @@ pub fn prog_df(args: &[String], __stdin: &str, stdout: &mut String, _stderr: &mu
     let used = count_size("/");
     let total: u64 = 1024 * 1024 * 100; // 100MB virtual filesystem
     let available = total.saturating_sub(used);
-    let use_pct = if total > 0 {
-        (used * 100 / total) as u32
-    } else {
-        0
-    };
+    let use_pct = (used * 100).checked_div(total).unwrap_or(0) as u32;
 
     fn format_size(size: u64, human: bool) -> String {
         if human {
@@ -491,9 +773,588 @@ pub fn prog_df(args: &[String], __stdin: &str, stdout: &mut String, _stderr: &mu
         use_pct
     ));
 
+    // Files holding the same blocks share one copy of them
+    let data = syscall::vfs_size() as u64;
+    let stored = syscall::vfs_stored_size() as u64;
+    if stored < data {
+        stdout.push_str(&format!(
+            "Deduplication saves {}: {} of file data stored in {}\n",
+            format_size(data - stored, true),
+            format_size(data, true),
+            format_size(stored, true)
+        ));
+    }
+
+    0
+}
+
+pub static QUOTA: CommandSpec = CommandSpec {
+    name: "quota",
+    summary: "show or set per-user disk quotas",
+    description: "Show how much of their disk quota USER, or the current user, is using.",
+    flags: &[
+        Flag::short('a', "Report every user (root)"),
+        Flag::short(
+            's',
+            "Limit USER to LIMIT bytes (K, M or G suffix); 'none' lifts it (root)",
+        )
+        .value("LIMIT"),
+    ],
+    args: &[Arg::new("USER", ArgKind::Text).optional()],
+};
+
+/// quota - show or set per-user disk quotas
+pub fn prog_quota(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    use crate::kernel::users::Uid;
+
+    let m = match QUOTA.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&QUOTA, stdout, stderr),
+    };
+
+    fn format_size(size: u64) -> String {
+        if size >= 1024 * 1024 * 1024 {
+            format!("{:.1}G", size as f64 / (1024.0 * 1024.0 * 1024.0))
+        } else if size >= 1024 * 1024 {
+            format!("{:.1}M", size as f64 / (1024.0 * 1024.0))
+        } else if size >= 1024 {
+            format!("{:.1}K", size as f64 / 1024.0)
+        } else {
+            format!("{}B", size)
+        }
+    }
+
+    fn lookup(user: &str) -> Option<Uid> {
+        user.parse::<u32>()
+            .ok()
+            .map(Uid)
+            .or_else(|| syscall::get_user_by_name(user).map(|u| u.uid))
+    }
+
+    let report = m.flag("a");
+    let limit = match m.value("s") {
+        None => None,
+        Some("none") => Some(None),
+        Some(value) => match crate::kernel::mount::parse_size(value) {
+            Ok(bytes) => Some(Some(bytes as u64)),
+            Err(()) => {
+                stderr.push_str(&format!("quota: invalid limit: '{}'\n", value));
+                return 1;
+            }
+        },
+    };
+
+    let user = m.operand(0);
+    let uid = match user {
+        None => syscall::getuid().ok(),
+        Some(user) => match lookup(user) {
+            Some(uid) => Some(uid),
+            None => {
+                stderr.push_str(&format!("quota: no such user: '{}'\n", user));
+                return 1;
+            }
+        },
+    };
+
+    if let Some(limit) = limit {
+        let Some(uid) = uid.filter(|_| user.is_some()) else {
+            stderr.push_str("quota: -s needs a user\n");
+            return 1;
+        };
+        return match syscall::set_quota(uid, limit) {
+            Ok(()) => 0,
+            Err(e) => {
+                stderr.push_str(&format!("quota: {}\n", e));
+                1
+            }
+        };
+    }
+
+    let quotas = if report {
+        syscall::quota_report()
+    } else {
+        uid.ok_or(syscall::SyscallError::NoProcess)
+            .and_then(syscall::quota)
+            .map(|q| vec![q])
+    };
+    let quotas = match quotas {
+        Ok(quotas) => quotas,
+        Err(e) => {
+            stderr.push_str(&format!("quota: {}\n", e));
+            return 1;
+        }
+    };
+
+    stdout.push_str("User             Used   Limit  Use%\n");
+    for quota in quotas {
+        let name = syscall::get_user_by_uid(quota.uid)
+            .map(|u| u.name)
+            .unwrap_or_else(|| quota.uid.0.to_string());
+        let (limit, pct) = match quota.limit {
+            Some(limit) => (
+                format_size(limit),
+                format!("{}%", (quota.used * 100).checked_div(limit).unwrap_or(100)),
+            ),
+            None => ("none".to_string(), "-".to_string()),
+        };
+        stdout.push_str(&format!(
+            "{:<12} {:>8} {:>7} {:>5}\n",
+            name,
+            format_size(quota.used),
+            limit,
+            pct
+        ));
+    }
+
     0
 }
 
+pub static FSCK: CommandSpec = CommandSpec {
+    name: "fsck",
+    summary: "check and repair the filesystem",
+    description: "Check the root filesystem for orphaned paths, missing metadata, wrong usage counts and dangling or looping symlinks. Without -p or -y nothing is changed. Exits 0 if consistent, 1 if errors were repaired, 4 if errors are left and 8 if the check couldn't run.",
+    flags: &[
+        Flag::short('n', "Check only, changing nothing (the default)"),
+        Flag::short('p', "Repair errors, but keep dangling symlinks (root)"),
+        Flag::short('y', "Repair everything, removing bad symlinks too (root)"),
+    ],
+    args: &[],
+};
+
+/// fsck - check and repair the filesystem
+pub fn prog_fsck(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    use crate::vfs::memory::Repair;
+
+    let m = match FSCK.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&FSCK, stdout, stderr),
+    };
+    let repair = match (m.flag("n"), m.flag("p"), m.flag("y")) {
+        (false, false, false) | (true, false, false) => Repair::Nothing,
+        (false, true, false) => Repair::Errors,
+        (false, false, true) => Repair::All,
+        _ => {
+            stderr.push_str(&format!(
+                "fsck: only one of -n, -p and -y may be given\n{}\n",
+                FSCK.usage()
+            ));
+            return 16;
+        }
+    };
+
+    let report = match syscall::fsck(repair) {
+        Ok(report) => report,
+        Err(e) => {
+            stderr.push_str(&format!("fsck: {}\n", e));
+            return 8;
+        }
+    };
+
+    for finding in &report.findings {
+        let kind = if finding.problem.is_error() {
+            "error"
+        } else {
+            "warning"
+        };
+        let fixed = if finding.fixed { " (fixed)" } else { "" };
+        stdout.push_str(&format!("{}: {}{}\n", kind, finding.problem, fixed));
+    }
+    stdout.push_str(&format!(
+        "/: {} paths, {} errors, {} warnings, {} fixed\n",
+        report.paths,
+        report.errors(),
+        report.warnings(),
+        report.fixed()
+    ));
+
+    if !report.is_consistent() {
+        4
+    } else if report.fixed() > 0 && report.errors() > 0 {
+        1
+    } else {
+        0
+    }
+}
+
+pub static SNAPSHOT: CommandSpec = CommandSpec {
+    name: "snapshot",
+    summary: "take, compare and roll back to named filesystem snapshots",
+    description: "Keep named copies of the whole filesystem and go back to them (root). COMMAND is one of:
+
+  list                list the snapshots, oldest first (the default)
+  create NAME         take a snapshot called NAME
+  diff NAME [NAME2]   show what changed since NAME, or from NAME to NAME2
+  restore NAME        roll the filesystem back to NAME
+  delete NAME         delete a snapshot
+
+Snapshots are kept in /var/lib/snapshots and saved with the filesystem. Rolling back leaves them alone, so the ones taken since are still there to go forward again.",
+    flags: &[],
+    args: &[
+        Arg::new("COMMAND", ArgKind::Text).optional(),
+        Arg::new("NAME", ArgKind::Text).optional().repeated(),
+    ],
+};
+
+/// snapshot - named filesystem snapshots
+pub fn prog_snapshot(
+    args: &[String],
+    __stdin: &str,
+    stdout: &mut String,
+    stderr: &mut String,
+) -> i32 {
+    use super::services::ago;
+    use crate::shell::stats::clock;
+
+    let m = match SNAPSHOT.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&SNAPSHOT, stdout, stderr),
+    };
+    let operands: Vec<&str> = m.operands.iter().map(String::as_str).collect();
+
+    let result = match operands.as_slice() {
+        [] | ["list"] => syscall::snapshot_list().map(|snapshots| {
+            if snapshots.is_empty() {
+                stdout.push_str("No snapshots\n");
+                return;
+            }
+            let now = clock();
+            stdout.push_str(&format!(
+                "{:<24} {:>8} {:>10}  TAKEN\n",
+                "NAME", "PATHS", "BYTES"
+            ));
+            for snapshot in snapshots {
+                stdout.push_str(&format!(
+                    "{:<24} {:>8} {:>10}  {}\n",
+                    snapshot.name,
+                    snapshot.paths,
+                    snapshot.bytes,
+                    ago(now, snapshot.time)
+                ));
+            }
+        }),
+        ["create", name] => syscall::snapshot_create(name, clock()).map(|info| {
+            stdout.push_str(&format!(
+                "snapshot: created {} ({} paths, {} bytes)\n",
+                info.name, info.paths, info.bytes
+            ));
+        }),
+        ["diff", from] | ["diff", from, _] => {
+            syscall::snapshot_diff(from, operands.get(2).copied()).map(|changes| {
+                for change in changes {
+                    stdout.push_str(&format!("{} {}\n", change.kind.marker(), change.path));
+                }
+            })
+        }
+        ["restore", name] => syscall::snapshot_restore(name).map(|changes| {
+            stdout.push_str(&format!(
+                "snapshot: rolled back to {} ({} paths changed)\n",
+                name,
+                changes.len()
+            ));
+        }),
+        ["delete", name] => syscall::snapshot_remove(name),
+        _ => {
+            stderr.push_str(&format!(
+                "snapshot: invalid command: {}\n{}\n",
+                operands.join(" "),
+                SNAPSHOT.usage()
+            ));
+            return 1;
+        }
+    };
+
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            stderr.push_str(&format!("snapshot: {}\n", e));
+            1
+        }
+    }
+}
+
+pub static VFS: CommandSpec = CommandSpec {
+    name: "vfs",
+    summary: "export or import the whole filesystem as a tar archive",
+    description: "Move the whole filesystem in and out as one tar archive, with modes, owners, times, symbolic links and extended attributes (root). COMMAND is one of:
+
+  export FILE   write the filesystem to FILE; in the browser it is downloaded too
+  import FILE   replace the filesystem with the archive FILE
+  import -U     replace it with an archive chosen in the browser's file picker
+
+The archive is an ordinary tar, so it can be unpacked with any tar, and an archive made by tar elsewhere can be imported. An import is checked and repaired as 'fsck -p' would; run 'save' to keep it across reloads.",
+    flags: &[Flag::short(
+        'U',
+        "Import an archive chosen in the browser's file picker",
+    )],
+    args: &[
+        Arg::new("COMMAND", ArgKind::Text),
+        Arg::new("FILE", ArgKind::Path).optional(),
+    ],
+};
+
+/// vfs - export and import the whole filesystem
+pub fn prog_vfs(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    use super::encoding::write_output;
+    use super::read_file_bytes;
+    use crate::kernel::Uid;
+    use crate::kernel::klog::LogLevel;
+
+    let m = match VFS.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&VFS, stdout, stderr),
+    };
+    // The archive holds /etc/shadow, and importing one replaces every user
+    if syscall::geteuid().ok() != Some(Uid::ROOT) {
+        stderr.push_str("vfs: permission denied (must be root)\n");
+        return 1;
+    }
+    let operands: Vec<&str> = m.operands.iter().map(String::as_str).collect();
+
+    let result = match (operands.as_slice(), m.flag("U")) {
+        (["export", file], false) => syscall::vfs_export_tar()
+            .map_err(|e| format!("export failed: {}", e))
+            .and_then(|(archive, stats)| {
+                write_output(file, &archive, true).map_err(|e| format!("{}: {}", file, e))?;
+                stdout.push_str(&format!(
+                    "vfs: exported {} entries ({} bytes of files) to {} ({} bytes)\n",
+                    stats.entries,
+                    stats.bytes,
+                    file,
+                    archive.len()
+                ));
+                download(file, &archive)
+            }),
+        (["import", file], false) => read_file_bytes(file)
+            .map_err(|e| format!("{}: {}", file, e))
+            .and_then(|archive| import(file, &archive))
+            .map(|message| {
+                syscall::klog(LogLevel::Notice, "vfs", &message);
+                stdout.push_str(&format!(
+                    "vfs: {}\nRun 'save' to keep it across reloads.\n",
+                    message
+                ));
+            }),
+        (["import"], true) => upload(stdout),
+        _ => {
+            stderr.push_str(&format!(
+                "vfs: invalid command: {}\n{}\n",
+                operands.join(" "),
+                VFS.usage()
+            ));
+            return 1;
+        }
+    };
+
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            stderr.push_str(&format!("vfs: {}\n", e));
+            1
+        }
+    }
+}
+
+/// Replace the filesystem with `archive`, saying what was imported
+fn import(name: &str, archive: &[u8]) -> Result<String, String> {
+    let (report, stats) =
+        syscall::vfs_import_tar(archive).map_err(|e| format!("{}: {}", name, e))?;
+    let mut message = format!(
+        "imported {} entries ({} bytes of files) from {}",
+        stats.entries, stats.bytes, name
+    );
+    if report.fixed() > 0 {
+        message.push_str(&format!(", {} problems repaired", report.fixed()));
+    }
+    Ok(message)
+}
+
+/// Offer the exported archive as a download, in the browser
+fn download(file: &str, archive: &[u8]) -> Result<(), String> {
+    #[cfg(target_arch = "wasm32")]
+    {
+        let name = file.rsplit('/').next().unwrap_or(file);
+        crate::shell::osimage::download(name, archive)
+            .map_err(|e| format!("download failed: {}", e))
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let _ = (file, archive);
+        Ok(())
+    }
+}
+
+/// Import an archive chosen in the browser's file picker
+fn upload(stdout: &mut String) -> Result<(), String> {
+    #[cfg(target_arch = "wasm32")]
+    {
+        wasm_bindgen_futures::spawn_local(async {
+            let message = match crate::shell::osimage::pick_file(".tar").await {
+                Ok(Some((name, data))) => import(&name, &data).unwrap_or_else(|e| e),
+                Ok(None) => "upload cancelled".to_string(),
+                Err(e) => format!("upload failed: {}", e),
+            };
+            crate::console_log!("[vfs] {}", message);
+            syscall::klog(crate::kernel::klog::LogLevel::Notice, "vfs", &message);
+        });
+        stdout.push_str(
+            "Choose a tar archive in the file picker...\n(Check 'dmesg' for the result)\n",
+        );
+        Ok(())
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let _ = stdout;
+        Err("import -U needs a browser".into())
+    }
+}
+
+pub static GETFATTR: CommandSpec = CommandSpec {
+    name: "getfattr",
+    summary: "get extended attributes of files",
+    description: "List the names of the extended attributes of each FILE, or with -d their values too. Values print as quoted text, or as hex when they aren't text.",
+    flags: &[
+        Flag::short('d', "Print values as well as names"),
+        Flag::short('n', "Print only attribute NAME and its value").value("NAME"),
+        Flag::short('e', "Print values as 'text' or 'hex'").value("ENCODING"),
+        Flag::long(
+            "only-values",
+            "Print the bare values, without names or quotes",
+        ),
+    ],
+    args: &[Arg::new("FILE", ArgKind::Path).repeated()],
+};
+
+/// getfattr - print extended attributes
+pub fn prog_getfattr(
+    args: &[String],
+    _stdin: &str,
+    stdout: &mut String,
+    stderr: &mut String,
+) -> i32 {
+    let m = match GETFATTR.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&GETFATTR, stdout, stderr),
+    };
+    let hex = match m.value("e") {
+        None | Some("text") => false,
+        Some("hex") => true,
+        Some(other) => {
+            stderr.push_str(&format!("getfattr: unknown encoding: '{}'\n", other));
+            return 1;
+        }
+    };
+    let only_values = m.flag("only-values");
+    let with_values = m.flag("d") || m.flag("n") || only_values;
+
+    let encode = |value: &[u8]| match std::str::from_utf8(value) {
+        Ok(text) if !hex && !text.chars().any(char::is_control) => {
+            format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+        }
+        _ => format!("0x{}", super::encoding::to_hex(value)),
+    };
+
+    let mut code = 0;
+    for path in &m.operands {
+        let names = match m.value("n") {
+            Some(name) => vec![name.to_string()],
+            None => match syscall::listxattr(path) {
+                Ok(names) => names,
+                Err(e) => {
+                    stderr.push_str(&format!("getfattr: {}: {}\n", path, e));
+                    code = 1;
+                    continue;
+                }
+            },
+        };
+        let mut lines = String::new();
+        for name in names {
+            if !with_values {
+                lines.push_str(&format!("{}\n", name));
+                continue;
+            }
+            match syscall::getxattr(path, &name) {
+                Ok(value) if only_values => {
+                    stdout.push_str(&String::from_utf8_lossy(&value));
+                }
+                Ok(value) => lines.push_str(&format!("{}={}\n", name, encode(&value))),
+                Err(e) => {
+                    stderr.push_str(&format!("getfattr: {}: {}: {}\n", path, name, e));
+                    code = 1;
+                }
+            }
+        }
+        if !lines.is_empty() {
+            stdout.push_str(&format!("# file: {}\n{}\n", path, lines));
+        }
+    }
+    code
+}
+
+pub static SETFATTR: CommandSpec = CommandSpec {
+    name: "setfattr",
+    summary: "set extended attributes of files",
+    description: "Set extended attribute NAME of each FILE to VALUE, or remove attribute NAME with -x. Names are 'user.' followed by anything, or 'trusted.' for attributes only root can see. A VALUE starting with 0x is hex; one in double quotes has them stripped.",
+    flags: &[
+        Flag::short('n', "Set attribute NAME").value("NAME"),
+        Flag::short('v', "The value to set (empty if left out)").value("VALUE"),
+        Flag::short('x', "Remove attribute NAME").value("NAME"),
+    ],
+    args: &[Arg::new("FILE", ArgKind::Path).repeated()],
+};
+
+/// setfattr - set or remove extended attributes
+pub fn prog_setfattr(
+    args: &[String],
+    _stdin: &str,
+    stdout: &mut String,
+    stderr: &mut String,
+) -> i32 {
+    let m = match SETFATTR.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&SETFATTR, stdout, stderr),
+    };
+
+    let value = m.value("v").unwrap_or("");
+    let value = if let Some(digits) = value.strip_prefix("0x") {
+        match super::encoding::from_hex(digits) {
+            Some(bytes) => bytes,
+            None => {
+                stderr.push_str(&format!("setfattr: invalid hex value: '{}'\n", value));
+                return 1;
+            }
+        }
+    } else if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        value.as_bytes()[1..value.len() - 1].to_vec()
+    } else {
+        value.as_bytes().to_vec()
+    };
+
+    let (name, remove) = match (m.value("n"), m.value("x")) {
+        (Some(name), None) => (name, false),
+        (None, Some(name)) => (name, true),
+        _ => {
+            stderr.push_str("setfattr: give one of -n NAME or -x NAME\n");
+            stderr.push_str(&SETFATTR.usage());
+            stderr.push('\n');
+            return 1;
+        }
+    };
+
+    let mut code = 0;
+    for path in &m.operands {
+        let result = if remove {
+            syscall::removexattr(path, name)
+        } else {
+            syscall::setxattr(path, name, &value)
+        };
+        if let Err(e) = result {
+            stderr.push_str(&format!("setfattr: {}: {}\n", path, e));
+            code = 1;
+        }
+    }
+    code
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -506,6 +1367,7 @@ mod tests {
 
         let result = prog_save(&args, "", &mut stdout, &mut stderr);
         assert_eq!(result, 0);
+        assert!(stdout.contains("Usage: save"));
     }
 
     #[test]
@@ -574,6 +1436,38 @@ mod tests {
         assert!(stdout.contains("Usage: du"));
     }
 
+    #[test]
+    fn test_prog_du_apparent_size() {
+        use crate::kernel::syscall::{KERNEL, Kernel, OpenFlags};
+
+        KERNEL.with(|k| {
+            *k.borrow_mut() = Kernel::new();
+            let pid = k.borrow_mut().spawn_process("sh", None);
+            k.borrow_mut().set_current(pid);
+        });
+        let fd = syscall::open("/tmp/sparse", OpenFlags::WRITE).unwrap();
+        KERNEL
+            .with(|k| {
+                k.borrow_mut()
+                    .sys_seek(fd, std::io::SeekFrom::Start(1024 * 1024))
+            })
+            .unwrap();
+        syscall::write(fd, b"x").unwrap();
+        syscall::close(fd).unwrap();
+
+        let du = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            assert_eq!(prog_du(&args, "", &mut stdout, &mut stderr), 0);
+            stdout
+        };
+        assert_eq!(du(&["-s", "/tmp/sparse"]), "1\t/tmp/sparse\n");
+        assert_eq!(
+            du(&["-s", "--apparent-size", "/tmp/sparse"]),
+            "1025\t/tmp/sparse\n"
+        );
+    }
+
     #[test]
     fn test_prog_df_help() {
         let args = vec!["--help".to_string()];
@@ -585,6 +1479,207 @@ mod tests {
         assert!(stdout.contains("Usage: df"));
     }
 
+    #[test]
+    fn test_prog_quota() {
+        let args = vec!["--help".to_string()];
+        let (mut stdout, mut stderr) = (String::new(), String::new());
+        assert_eq!(prog_quota(&args, "", &mut stdout, &mut stderr), 0);
+        assert!(stdout.contains("Usage: quota"));
+
+        let args = vec!["-s".to_string(), "lots".to_string(), "0".to_string()];
+        assert_eq!(prog_quota(&args, "", &mut stdout, &mut stderr), 1);
+        assert!(stderr.contains("invalid limit"));
+    }
+
+    #[test]
+    fn test_prog_fsck() {
+        use crate::kernel::Uid;
+        use crate::kernel::syscall::{KERNEL, Kernel};
+
+        let run = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = prog_fsck(&args, "", &mut stdout, &mut stderr);
+            (code, stdout + &stderr)
+        };
+        KERNEL.with(|k| {
+            *k.borrow_mut() = Kernel::new();
+            let pid = k.borrow_mut().spawn_process("sh", None);
+            k.borrow_mut().set_current(pid);
+        });
+
+        let (code, out) = run(&[]);
+        assert_eq!(code, 0, "{}", out);
+        assert!(out.contains(", 0 errors, 0 warnings, 0 fixed"));
+
+        syscall::symlink("/nowhere", "/tmp/stale").unwrap();
+        let (code, out) = run(&["-n"]);
+        assert_eq!(code, 0);
+        assert!(out.contains("warning: /tmp/stale: dangling symlink to /nowhere\n"));
+        assert!(out.contains(", 0 errors, 1 warnings, 0 fixed"));
+
+        // Repairs are for root
+        assert_eq!(run(&["-y"]).0, 8);
+        KERNEL.with(|k| k.borrow_mut().current_process_mut().unwrap().euid = Uid::ROOT);
+        assert!(!run(&["-p"]).1.contains("(fixed)"));
+        let (code, out) = run(&["-y"]);
+        assert_eq!(code, 0);
+        assert!(out.contains("/tmp/stale: dangling symlink to /nowhere (fixed)"));
+        assert!(syscall::read_link("/tmp/stale").is_err());
+
+        assert_eq!(run(&["-p", "-y"]).0, 16);
+    }
+
+    #[test]
+    fn test_prog_vfs() {
+        use crate::kernel::Uid;
+        use crate::kernel::syscall::{KERNEL, Kernel};
+
+        let run = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = prog_vfs(&args, "", &mut stdout, &mut stderr);
+            (code, stdout + &stderr)
+        };
+        KERNEL.with(|k| {
+            *k.borrow_mut() = Kernel::new();
+            let pid = k.borrow_mut().spawn_process("sh", None);
+            k.borrow_mut().set_current(pid);
+        });
+
+        assert_eq!(run(&["export", "/tmp/all.tar"]).0, 1);
+
+        KERNEL.with(|k| k.borrow_mut().current_process_mut().unwrap().euid = Uid::ROOT);
+        syscall::write_file("/tmp/kept", "kept").unwrap();
+        let (code, out) = run(&["export", "/tmp/all.tar"]);
+        assert_eq!(code, 0, "{}", out);
+        assert!(out.starts_with("vfs: exported "));
+        assert_eq!(syscall::metadata("/tmp/all.tar").unwrap().mode, 0o600);
+        let archive = super::super::read_file_bytes("/tmp/all.tar").unwrap();
+
+        syscall::write_file("/tmp/kept", "changed").unwrap();
+        syscall::write_file("/tmp/later", "x").unwrap();
+        super::super::encoding::write_output("/tmp/copy.tar", &archive, false).unwrap();
+        let (code, out) = run(&["import", "/tmp/copy.tar"]);
+        assert_eq!(code, 0, "{}", out);
+        assert!(out.contains("Run 'save'"));
+        assert_eq!(syscall::read_file("/tmp/kept").unwrap(), "kept");
+        assert!(!syscall::exists("/tmp/later").unwrap());
+
+        syscall::write_file("/tmp/junk.tar", &"junk".repeat(200)).unwrap();
+        assert_eq!(run(&["import", "/tmp/junk.tar"]).0, 1);
+        assert_eq!(run(&["import", "-U"]).0, 1);
+        assert_eq!(run(&["frobnicate"]).0, 1);
+    }
+
+    #[test]
+    fn test_prog_snapshot() {
+        use crate::kernel::Uid;
+        use crate::kernel::syscall::{KERNEL, Kernel};
+
+        let run = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = prog_snapshot(&args, "", &mut stdout, &mut stderr);
+            (code, stdout + &stderr)
+        };
+        KERNEL.with(|k| {
+            *k.borrow_mut() = Kernel::new();
+            let pid = k.borrow_mut().spawn_process("sh", None);
+            k.borrow_mut().set_current(pid);
+        });
+
+        // Snapshots are root's
+        assert_eq!(run(&["create", "a"]).0, 1);
+
+        KERNEL.with(|k| k.borrow_mut().current_process_mut().unwrap().euid = Uid::ROOT);
+        assert_eq!(run(&[]), (0, "No snapshots\n".to_string()));
+        let (code, out) = run(&["create", "a"]);
+        assert_eq!(code, 0, "{}", out);
+        assert!(out.starts_with("snapshot: created a ("));
+        assert!(run(&["list"]).1.contains("\na "));
+
+        syscall::write_file("/tmp/after", "x").unwrap();
+        assert_eq!(run(&["diff", "a"]), (0, "+ /tmp/after\n".to_string()));
+        let (code, out) = run(&["restore", "a"]);
+        assert_eq!(code, 0, "{}", out);
+        assert!(out.contains("(1 paths changed)"));
+        assert!(!syscall::exists("/tmp/after").unwrap());
+
+        assert_eq!(run(&["delete", "a"]).0, 0);
+        assert_eq!(run(&["delete", "a"]).0, 1);
+        let (code, out) = run(&["frobnicate"]);
+        assert_eq!(code, 1);
+        assert!(out.contains("invalid command: frobnicate"));
+    }
+
+    #[test]
+    fn test_prog_fattr() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+
+        let run = |prog: fn(&[String], &str, &mut String, &mut String) -> i32, args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = prog(&args, "", &mut stdout, &mut stderr);
+            (code, stdout + &stderr)
+        };
+        KERNEL.with(|k| {
+            *k.borrow_mut() = Kernel::new();
+            let pid = k.borrow_mut().spawn_process("sh", None);
+            k.borrow_mut().set_current(pid);
+        });
+        syscall::write_file("/tmp/page.html", "<p>").unwrap();
+
+        let file = "/tmp/page.html";
+        assert_eq!(
+            run(prog_setfattr, &["-n", "user.mime", "-v", "text/html", file]).0,
+            0
+        );
+        assert_eq!(
+            run(prog_setfattr, &["-n", "user.raw", "-v", "0x00ff", file]).0,
+            0
+        );
+        assert_eq!(
+            run(
+                prog_setfattr,
+                &["-n", "user.note", "-v", "\"say \"hi\"\"", file]
+            )
+            .0,
+            0
+        );
+        assert_eq!(
+            run(prog_getfattr, &[file]),
+            (
+                0,
+                "# file: /tmp/page.html\nuser.mime\nuser.note\nuser.raw\n\n".to_string()
+            )
+        );
+        assert_eq!(
+            run(prog_getfattr, &["-d", file]).1,
+            "# file: /tmp/page.html\nuser.mime=\"text/html\"\n\
+             user.note=\"say \\\"hi\\\"\"\nuser.raw=0x00ff\n\n"
+        );
+        assert_eq!(
+            run(prog_getfattr, &["-n", "user.mime", "-e", "hex", file]).1,
+            "# file: /tmp/page.html\nuser.mime=0x746578742f68746d6c\n\n"
+        );
+        assert_eq!(
+            run(prog_getfattr, &["--only-values", "-n", "user.mime", file]).1,
+            "text/html"
+        );
+
+        assert_eq!(run(prog_setfattr, &["-x", "user.mime", file]).0, 0);
+        let (code, out) = run(prog_getfattr, &["-n", "user.mime", file]);
+        assert_eq!(code, 1);
+        assert!(out.contains("no such attribute"));
+        assert_eq!(run(prog_setfattr, &[file]).0, 1);
+        assert!(
+            run(prog_setfattr, &["-n", "bad", file])
+                .1
+                .contains("invalid argument")
+        );
+    }
+
     #[test]
     fn test_prog_df_output_format() {
         let args = vec![];
@@ -597,6 +1692,24 @@ mod tests {
         assert!(stdout.contains("axeberg-vfs"));
     }
 
+    #[test]
+    fn test_prog_df_dedup() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        let data = "x".repeat(64 * 1024);
+        syscall::write_file("/tmp/one", &data).unwrap();
+        syscall::write_file("/tmp/two", &data).unwrap();
+
+        let (mut stdout, mut stderr) = (String::new(), String::new());
+        assert_eq!(prog_df(&[], "", &mut stdout, &mut stderr), 0);
+        assert!(stdout.contains("Deduplication saves"), "{}", stdout);
+    }
+
     #[test]
     fn test_prog_df_no_args() {
         let args: Vec<String> = vec![];
@@ -608,4 +1721,63 @@ mod tests {
         assert!(stdout.contains("Filesystem"));
         assert!(stdout.contains("axeberg-vfs"));
     }
+
+    #[test]
+    fn test_locate_and_search() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+
+        let run = |prog: fn(&[String], &str, &mut String, &mut String) -> i32, args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = prog(&args, "", &mut stdout, &mut stderr);
+            (code, stdout + &stderr)
+        };
+        KERNEL.with(|k| {
+            *k.borrow_mut() = Kernel::new();
+            let pid = k.borrow_mut().spawn_process("sh", None);
+            k.borrow_mut().set_current(pid);
+        });
+        syscall::mkdir("/tmp/notes").unwrap();
+        syscall::write_file(
+            "/tmp/notes/plan.md",
+            "# Plan\nship the indexer\nthen ship search",
+        )
+        .unwrap();
+        syscall::write_file("/tmp/notes/todo.txt", "review indexer docs").unwrap();
+
+        let (code, out) = run(prog_updatedb, &[]);
+        assert_eq!(code, 0, "{out}");
+        assert!(out.starts_with("updatedb: "), "{out}");
+
+        let (code, out) = run(prog_locate, &["plan"]);
+        assert_eq!((code, out.as_str()), (0, "/tmp/notes/plan.md\n"));
+        assert_eq!(run(prog_locate, &["-c", "-i", "/TMP/NOTES/*"]).1, "2\n");
+        assert_eq!(run(prog_locate, &["nothing-here"]).0, 1);
+
+        let (code, out) = run(prog_search, &["ship"]);
+        assert_eq!(code, 0);
+        assert_eq!(
+            out,
+            "/tmp/notes/plan.md:2:ship the indexer\n/tmp/notes/plan.md:3:then ship search\n"
+        );
+        // New files are picked up from the change feed without updatedb
+        syscall::write_file("/tmp/notes/more.txt", "indexer indexer indexer").unwrap();
+        let (_, out) = run(prog_search, &["-l", "INDEXER"]);
+        assert_eq!(
+            out,
+            "/tmp/notes/more.txt\n/tmp/notes/plan.md\n/tmp/notes/todo.txt\n"
+        );
+        assert_eq!(run(prog_search, &["ship", "docs"]).0, 1);
+        assert_eq!(run(prog_search, &["-"]).0, 2);
+        assert_eq!(run(prog_search, &["ext:md"]).0, 2);
+
+        // Path and extension filters
+        let (_, out) = run(prog_search, &["-l", "indexer", "ext:txt"]);
+        assert_eq!(out, "/tmp/notes/more.txt\n/tmp/notes/todo.txt\n");
+        syscall::chdir("/tmp").unwrap();
+        assert_eq!(run(prog_search, &["indexer", "path:elsewhere"]).0, 1);
+        let (_, out) = run(prog_locate, &["path:notes", "ext:md"]);
+        assert_eq!(out, "/tmp/notes/plan.md\n");
+        assert_eq!(run(prog_locate, &["-c", "ext:txt", "o"]).1, "2\n");
+    }
 }
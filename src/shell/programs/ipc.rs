@@ -1,7 +1,16 @@
 //! IPC (Inter-Process Communication) programs
 
-use super::{args_to_strs, check_help};
-use crate::kernel::syscall;
+use crate::kernel::syscall::{self, Kernel, SyscallError};
+use crate::kernel::users::Uid;
+use crate::shell::argparse::{Arg, ArgKind, CommandSpec, Flag};
+
+pub static MKFIFO: CommandSpec = CommandSpec {
+    name: "mkfifo",
+    summary: "make named pipes",
+    description: "Create named pipes (FIFOs).",
+    flags: &[Flag::short('m', "Set permission mode (octal)").value("MODE")],
+    args: &[Arg::new("NAME", ArgKind::Path).repeated()],
+};
 
 pub fn prog_mkfifo(
     args: &[String],
@@ -9,27 +18,13 @@ pub fn prog_mkfifo(
     stdout: &mut String,
     stderr: &mut String,
 ) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: mkfifo NAME...\nCreate named pipes (FIFOs).\n\nOptions:\n  -m MODE  Set permission mode (octal)",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
-
-    if args.is_empty() {
-        stderr.push_str("mkfifo: missing operand\n");
-        return 1;
-    }
+    let m = match MKFIFO.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&MKFIFO, stdout, stderr),
+    };
 
     let mut exit_code = 0;
-    for path in &args {
-        if path.starts_with('-') {
-            continue; // Skip options for now
-        }
-
+    for path in &m.operands {
         syscall::KERNEL.with(|k| {
             let mut kernel = k.borrow_mut();
             match kernel.fifos_mut().mkfifo(path) {
@@ -47,24 +42,51 @@ pub fn prog_mkfifo(
     exit_code
 }
 
-pub fn prog_ipcs(args: &[String], __stdin: &str, stdout: &mut String, _stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
+/// Key column for ipcs; private objects show as IPC_PRIVATE (0)
+fn format_key(key: Option<i32>) -> String {
+    format!("0x{:08x}", key.unwrap_or(0) as u32)
+}
 
-    if let Some(help) = check_help(
-        &args,
-        "Usage: ipcs [options]\nShow IPC facilities.\n\nOptions:\n  -a  Show all (default)\n  -q  Show message queues\n  -s  Show semaphores\n  -m  Show shared memory",
-    ) {
-        stdout.push_str(&help);
-        return 0;
+/// Parse a key given as decimal or 0x-prefixed hex
+fn parse_key(s: &str) -> Option<i32> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok().map(|k| k as i32),
+        None => s.parse().ok(),
     }
+}
 
-    let show_all = args.is_empty() || args.contains(&"-a");
-    let show_queues = show_all || args.contains(&"-q");
-    let show_sems = show_all || args.contains(&"-s");
-    let show_shm = show_all || args.contains(&"-m");
+pub static IPCS: CommandSpec = CommandSpec {
+    name: "ipcs",
+    summary: "show IPC facilities",
+    description: "Show message queues, semaphore sets and shared memory segments.",
+    flags: &[
+        Flag::short('a', "Show all (default)"),
+        Flag::short('q', "Show message queues"),
+        Flag::short('s', "Show semaphores"),
+        Flag::short('m', "Show shared memory"),
+    ],
+    args: &[],
+};
+
+pub fn prog_ipcs(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match IPCS.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&IPCS, stdout, stderr),
+    };
+
+    let show_all = m.flag("a") || !(m.flag("q") || m.flag("s") || m.flag("m"));
+    let show_queues = show_all || m.flag("q");
+    let show_sems = show_all || m.flag("s");
+    let show_shm = show_all || m.flag("m");
 
     syscall::KERNEL.with(|k| {
         let kernel = k.borrow();
+        let owner = |uid: u32| {
+            kernel
+                .get_user_by_uid(Uid(uid))
+                .map(|u| u.name.clone())
+                .unwrap_or_else(|| uid.to_string())
+        };
 
         // Message Queues
         if show_queues {
@@ -75,10 +97,16 @@ pub fn prog_ipcs(args: &[String], __stdin: &str, stdout: &mut String, _stderr: &
                 stdout.push_str("(none)\n");
             } else {
                 for id in queues {
-                    if let Ok(stats) = kernel.msgqueues().msgctl_stat(id) {
+                    if let Some(queue) = kernel.msgqueues().get(id) {
+                        let stats = queue.stats();
                         stdout.push_str(&format!(
-                            "{:<10} {:<10} {:<10} {:<10} {:<12} {}\n",
-                            "-", id.0, "-", "0644", stats.msg_cbytes, stats.msg_qnum
+                            "{} {:<10} {:<10} {:<10o} {:<12} {}\n",
+                            format_key(queue.key),
+                            id.0,
+                            owner(queue.uid),
+                            queue.mode,
+                            stats.msg_cbytes,
+                            stats.msg_qnum
                         ));
                     }
                 }
@@ -96,10 +124,10 @@ pub fn prog_ipcs(args: &[String], __stdin: &str, stdout: &mut String, _stderr: &
                 for id in sems {
                     if let Some(set) = kernel.semaphores().get_set(id) {
                         stdout.push_str(&format!(
-                            "{:<10} {:<10} {:<10} {:04o}       {}\n",
-                            "-",
+                            "{} {:<10} {:<10} {:<10o} {}\n",
+                            format_key(set.key),
                             id.0,
-                            set.uid,
+                            owner(set.uid),
                             set.mode,
                             set.len()
                         ));
@@ -131,114 +159,96 @@ pub fn prog_ipcs(args: &[String], __stdin: &str, stdout: &mut String, _stderr: &
     0
 }
 
-pub fn prog_ipcrm(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: ipcrm [options]\nRemove IPC resources.\n\nOptions:\n  -q ID   Remove message queue with ID\n  -s ID   Remove semaphore set with ID\n  -m ID   Remove shared memory with ID\n  -a      Remove all IPC resources",
-    ) {
-        stdout.push_str(&help);
-        return 0;
+/// Message queue or semaphore set id to remove, by id or by key
+fn resolve_ipc_id(kernel: &Kernel, opt: &str, arg: &str) -> Result<u32, String> {
+    let by_key = opt == "-Q" || opt == "-S";
+    if !by_key {
+        return arg
+            .parse::<u32>()
+            .map_err(|_| format!("ipcrm: invalid id: {}\n", arg));
     }
+    let key = parse_key(arg)
+        .filter(|k| *k >= 0)
+        .ok_or_else(|| format!("ipcrm: invalid key: {}\n", arg))?;
+    let id = if opt == "-Q" {
+        kernel.msgqueues().find_key(key).map(|id| id.0)
+    } else {
+        kernel.semaphores().find_key(key).map(|id| id.0)
+    };
+    id.ok_or_else(|| format!("ipcrm: invalid key: {}\n", arg))
+}
+
+pub static IPCRM: CommandSpec = CommandSpec {
+    name: "ipcrm",
+    summary: "remove IPC resources",
+    description: "Remove message queues and semaphore sets, by id or by key.",
+    flags: &[
+        Flag::short('q', "Remove message queue with ID").value("ID"),
+        Flag::short('Q', "Remove message queue with KEY").value("KEY"),
+        Flag::short('s', "Remove semaphore set with ID").value("ID"),
+        Flag::short('S', "Remove semaphore set with KEY").value("KEY"),
+        Flag::short('m', "Remove shared memory with ID").value("ID"),
+        Flag::short('a', "Remove all IPC resources you own"),
+    ],
+    args: &[],
+};
+
+pub fn prog_ipcrm(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match IPCRM.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&IPCRM, stdout, stderr),
+    };
 
     let mut exit_code = 0;
 
     // Check for -a (remove all)
-    if args.contains(&"-a") {
+    if m.flag("a") {
         syscall::KERNEL.with(|k| {
             let mut kernel = k.borrow_mut();
 
-            // Remove all message queues
-            let queues: Vec<_> = kernel.msgqueues().list();
-            for id in queues {
-                let _ = kernel.msgqueues_mut().msgctl_rmid(id);
+            // Objects owned by someone else stay (unless we're root)
+            for id in kernel.msgqueues().list() {
+                let _ = kernel.sys_msgctl_rmid(id.0);
             }
-
-            // Remove all semaphores
-            let sems: Vec<_> = kernel.semaphores().list();
-            for id in sems {
-                let _ = kernel.semaphores_mut().semctl_rmid(id);
+            for id in kernel.semaphores().list() {
+                let _ = kernel.sys_semctl_rmid(id.0);
             }
         });
         stdout.push_str("All IPC resources removed.\n");
         return 0;
     }
 
-    let mut i = 0;
-    while i < args.len() {
-        let opt = args[i];
-        match opt {
-            "-q" => {
-                if i + 1 >= args.len() {
-                    stderr.push_str("ipcrm: option requires an argument -- 'q'\n");
-                    exit_code = 1;
+    for opt in ["-q", "-Q", "-s", "-S"] {
+        for arg in m.values(&opt[1..]) {
+            let result = syscall::KERNEL.with(|k| {
+                let mut kernel = k.borrow_mut();
+                let id = resolve_ipc_id(&kernel, opt, arg)?;
+                let removed = if opt.eq_ignore_ascii_case("-q") {
+                    kernel.sys_msgctl_rmid(id)
                 } else {
-                    i += 1;
-                    if let Ok(id) = args[i].parse::<u32>() {
-                        use crate::kernel::msgqueue::MsgQueueId;
-                        let success = syscall::KERNEL.with(|k| {
-                            k.borrow_mut()
-                                .msgqueues_mut()
-                                .msgctl_rmid(MsgQueueId(id))
-                                .is_ok()
-                        });
-                        if !success {
-                            stderr.push_str(&format!("ipcrm: invalid id: {}\n", id));
-                            exit_code = 1;
-                        }
-                    } else {
-                        stderr.push_str(&format!("ipcrm: invalid id: {}\n", args[i]));
-                        exit_code = 1;
+                    kernel.sys_semctl_rmid(id)
+                };
+                removed.map_err(|e| match e {
+                    SyscallError::PermissionDenied => {
+                        format!("ipcrm: permission denied for id: {}\n", id)
                     }
-                }
-            }
-            "-s" => {
-                if i + 1 >= args.len() {
-                    stderr.push_str("ipcrm: option requires an argument -- 's'\n");
-                    exit_code = 1;
-                } else {
-                    i += 1;
-                    if let Ok(id) = args[i].parse::<u32>() {
-                        use crate::kernel::semaphore::SemId;
-                        let success = syscall::KERNEL.with(|k| {
-                            k.borrow_mut()
-                                .semaphores_mut()
-                                .semctl_rmid(SemId(id))
-                                .is_ok()
-                        });
-                        if !success {
-                            stderr.push_str(&format!("ipcrm: invalid id: {}\n", id));
-                            exit_code = 1;
-                        }
-                    } else {
-                        stderr.push_str(&format!("ipcrm: invalid id: {}\n", args[i]));
-                        exit_code = 1;
-                    }
-                }
-            }
-            "-m" => {
-                if i + 1 >= args.len() {
-                    stderr.push_str("ipcrm: option requires an argument -- 'm'\n");
-                    exit_code = 1;
-                } else {
-                    i += 1;
-                    // Note: Shared memory segments cannot be removed directly in this implementation
-                    // They are automatically cleaned up when all processes detach
-                    stderr.push_str(&format!(
-                        "ipcrm: shared memory removal not supported (id: {})\n",
-                        args[i]
-                    ));
-                    stderr.push_str(
-                        "       Shared memory is cleaned up when all processes detach.\n",
-                    );
-                }
-            }
-            _ => {
-                // Skip unknown options
+                    _ => format!("ipcrm: invalid id: {}\n", id),
+                })
+            });
+            if let Err(msg) = result {
+                stderr.push_str(&msg);
+                exit_code = 1;
             }
         }
-        i += 1;
+    }
+    for id in m.values("m") {
+        // Note: Shared memory segments cannot be removed directly in this implementation
+        // They are automatically cleaned up when all processes detach
+        stderr.push_str(&format!(
+            "ipcrm: shared memory removal not supported (id: {})\n",
+            id
+        ));
+        stderr.push_str("       Shared memory is cleaned up when all processes detach.\n");
     }
 
     exit_code
@@ -265,7 +275,7 @@ mod tests {
         let mut stderr = String::new();
         let result = prog_mkfifo(&args, "", &mut stdout, &mut stderr);
         assert_eq!(result, 1);
-        assert!(stderr.contains("missing operand"));
+        assert!(stderr.contains("missing operand: expected NAME"));
     }
 
     #[test]
@@ -317,6 +327,122 @@ mod tests {
         let mut stderr = String::new();
         let result = prog_ipcrm(&args, "", &mut stdout, &mut stderr);
         assert_eq!(result, 1);
-        assert!(stderr.contains("option requires an argument"));
+        assert!(stderr.contains("option -q needs a value"));
+    }
+
+    fn setup_kernel() {
+        syscall::KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+    }
+
+    fn run(
+        prog: fn(&[String], &str, &mut String, &mut String) -> i32,
+        args: &[&str],
+    ) -> (i32, String, String) {
+        let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        let mut stdout = String::new();
+        let mut stderr = String::new();
+        let code = prog(&args, "", &mut stdout, &mut stderr);
+        (code, stdout, stderr)
+    }
+
+    #[test]
+    fn test_ipcs_lists_objects() {
+        setup_kernel();
+        syscall::KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            let q = k.sys_msgget(42, true).unwrap();
+            k.sys_msgsnd(q, 1, b"hello".to_vec()).unwrap();
+            k.sys_msgget(-1, true).unwrap();
+            k.semaphores_mut()
+                .semget(0x1234, 3, 0, 0, true, 0.0)
+                .unwrap();
+        });
+
+        let (code, out, _) = run(prog_ipcs, &["-q", "-s"]);
+        assert_eq!(code, 0);
+        assert!(out.contains("0x0000002a 1          user       644        5            1"));
+        assert!(out.contains("0x00000000 2 "));
+        assert!(out.contains("0x00001234 1          root       644        3"));
+        assert!(!out.contains("Shared Memory"));
+    }
+
+    #[test]
+    fn test_ipcrm_by_id_and_key() {
+        setup_kernel();
+        let (q, s) = syscall::KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            let q = k.sys_msgget(42, true).unwrap();
+            k.sys_msgget(43, true).unwrap();
+            let s = k
+                .semaphores_mut()
+                .semget(7, 1, 1000, 1000, true, 0.0)
+                .unwrap();
+            k.semaphores_mut()
+                .semget(8, 1, 1000, 1000, true, 0.0)
+                .unwrap();
+            (q, s.0)
+        });
+
+        assert_eq!(run(prog_ipcrm, &["-q", &q.to_string(), "-S", "0x8"]).0, 0);
+        assert_eq!(run(prog_ipcrm, &["-Q", "43", "-s", &s.to_string()]).0, 0);
+        syscall::KERNEL.with(|k| {
+            let k = k.borrow();
+            assert!(k.msgqueues().list().is_empty());
+            assert!(k.semaphores().list().is_empty());
+        });
+
+        let (code, _, err) = run(prog_ipcrm, &["-Q", "42"]);
+        assert_eq!(code, 1);
+        assert!(err.contains("invalid key: 42"));
+    }
+
+    #[test]
+    fn test_ipcrm_checks_owner() {
+        setup_kernel();
+        syscall::KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            k.sys_msgget(42, true).unwrap();
+            k.current_process_mut().unwrap().euid = Uid(2000);
+        });
+
+        let (code, _, err) = run(prog_ipcrm, &["-Q", "42"]);
+        assert_eq!(code, 1);
+        assert!(err.contains("permission denied"));
+
+        run(prog_ipcrm, &["-a"]);
+        syscall::KERNEL.with(|k| assert_eq!(k.borrow().msgqueues().list().len(), 1));
+    }
+
+    #[test]
+    fn test_keyed_objects_survive_restore() {
+        setup_kernel();
+        syscall::KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            let q = k.sys_msgget(42, true).unwrap();
+            k.sys_msgsnd(q, 3, b"queued".to_vec()).unwrap();
+            k.sys_msgget(-1, true).unwrap();
+            let s = k.semaphores_mut().semget(7, 2, 0, 0, true, 0.0).unwrap();
+            k.semaphores_mut()
+                .semctl_setall(s, &[1, 2], 0, 0.0)
+                .unwrap();
+        });
+
+        let data = syscall::vfs_snapshot().unwrap();
+        setup_kernel();
+        syscall::vfs_restore(&data).unwrap();
+
+        syscall::KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            assert_eq!(k.msgqueues().list().len(), 1);
+            let q = k.msgqueues().find_key(42).unwrap();
+            assert_eq!(k.sys_msgrcv(q.0, 0).unwrap(), (3, b"queued".to_vec()));
+            let s = k.semaphores().find_key(7).unwrap();
+            assert_eq!(k.semaphores().semctl_getall(s).unwrap(), [1, 2]);
+        });
     }
 }
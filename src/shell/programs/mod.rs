@@ -6,7 +6,9 @@
 use crate::kernel::syscall;
 
 // Program modules by category
+pub mod archive;
 pub mod cron;
+pub mod dev;
 pub mod encoding;
 pub mod file;
 pub mod fs;
@@ -20,11 +22,14 @@ pub mod services;
 pub mod shell;
 pub mod system;
 pub mod text;
+pub mod trash;
 pub mod tty;
 pub mod user;
 
 // Re-export all program functions for the registry
+pub use archive::*;
 pub use cron::*;
+pub use dev::*;
 pub use encoding::*;
 pub use file::*;
 pub use fs::*;
@@ -38,34 +43,72 @@ pub use services::*;
 pub use shell::*;
 pub use system::*;
 pub use text::*;
+pub use trash::*;
 pub use tty::*;
 pub use user::*;
 
-// ============ Shared Utilities ============
+// ============ Command Specs ============
 
-/// Check if args contain -h or --help and return usage message if so
-pub fn check_help(args: &[&str], usage: &str) -> Option<String> {
-    if args.iter().any(|a| *a == "-h" || *a == "--help") {
-        Some(usage.to_string())
-    } else {
-        None
-    }
+use crate::shell::argparse::{CommandSpec, Matches};
+use crate::shell::script::SCRIPT;
+
+/// Programs that parse their arguments with a [`CommandSpec`]
+pub static SPECS: &[&CommandSpec] = &[
+    &AT, &AUTOSAVE, &BACKUP, &BASE64, &BASENAME, &BG, &CAL, &CAT, &CHARS, &CHGRP, &CHMOD, &CHOWN,
+    &CHVT, &CLEAR, &COMM, &CP, &CRONTAB, &CRYPT, &CURL, &CUT, &DATE, &DEBUG, &DF, &DIFF, &DIRNAME,
+    &DMESG, &DU, &EDIT, &EXPORTFS, &EXPR, &FG, &FIND, &FINDMNT, &FOLD, &FREE, &FSCK, &FSLOAD,
+    &FSRESET, &GETFACL, &GETFATTR, &GREP, &GROUPADD, &GROUPDEL, &GROUPS, &GUEST, &HEAD, &HISTORY,
+    &HOSTNAME, &ID, &IPCRM, &IPCS, &JOBS, &KILL, &LN, &LOCATE, &LOCK, &LOGIN, &LOGOUT, &LS, &MAN,
+    &MERGE3, &MKDIR, &MKFIFO, &MKTEMP, &MOUNT, &MV, &NETPERM, &NL, &OFFLINE, &PASSWD, &PASTE, &PKG,
+    &POWEROFF, &PRINTENV, &PRINTF, &PROFILE, &PS, &QR, &QUOTA, &READLINK, &REBOOT, &REV, &RM,
+    &SAVE, &SCRIPT, &SEARCH, &SEQ, &SETFACL, &SETFATTR, &SHA256SUM, &SHUF, &SLEEP, &SNAPSHOT,
+    &SORT, &SPELL, &STATS, &STRACE, &STRINGS, &STTY, &SU, &SUDO, &SYSTEMCTL, &TAIL, &TEE, &TIME,
+    &TOUCH, &TR, &TRASH, &TREE, &TTY, &TUTORIAL, &TYPE, &UMOUNT, &UNAME, &UNICODE, &UNIQ, &UNZIP,
+    &UPDATE, &UPDATEDB, &UPTIME, &USERADD, &USERDEL, &USERMOD, &UUIDGEN, &VFS, &W, &WAT2WASM, &WC,
+    &WGET, &WHICH, &WHO, &WHOAMI, &WIDGETS, &XARGS, &XXD, &YES, &ZIP,
+];
+
+/// The spec of program `name`, if it has one
+pub fn spec(name: &str) -> Option<&'static CommandSpec> {
+    SPECS.iter().copied().find(|s| s.name == name)
 }
 
+/// [`CommandSpec::parse`] for a program whose standard output is bytes:
+/// help and usage errors are printed, and the exit status returned
+pub fn parse_bytes<'s>(
+    spec: &'s CommandSpec,
+    args: &[String],
+    stdout: &mut Vec<u8>,
+    stderr: &mut String,
+) -> Result<Matches<'s>, i32> {
+    spec.parse(args).map_err(|e| {
+        let mut out = String::new();
+        let code = e.report(spec, &mut out, stderr);
+        stdout.extend_from_slice(out.as_bytes());
+        code
+    })
+}
+
+// ============ Shared Utilities ============
+
 /// Helper to read file content as string
+///
+/// Invalid UTF-8 is replaced with U+FFFD; use [`read_file_bytes`] for
+/// binary files.
 pub fn read_file_content(path: &str) -> Result<String, String> {
+    read_file_bytes(path).map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Helper to read file content as raw bytes
+pub fn read_file_bytes(path: &str) -> Result<Vec<u8>, String> {
     match syscall::open(path, syscall::OpenFlags::READ) {
         Ok(fd) => {
-            let mut content = String::new();
+            let mut content = Vec::new();
             let mut buf = [0u8; 4096];
             loop {
                 match syscall::read(fd, &mut buf) {
                     Ok(0) => break,
-                    Ok(n) => {
-                        if let Ok(s) = std::str::from_utf8(&buf[..n]) {
-                            content.push_str(s);
-                        }
-                    }
+                    Ok(n) => content.extend_from_slice(&buf[..n]),
                     Err(e) => {
                         let _ = syscall::close(fd);
                         return Err(e.to_string());
@@ -79,7 +122,38 @@ pub fn read_file_content(path: &str) -> Result<String, String> {
     }
 }
 
-/// Convert String slice to &str slice for easier handling
-pub fn args_to_strs(args: &[String]) -> Vec<&str> {
-    args.iter().map(|s| s.as_str()).collect()
+/// `path` made absolute against the working directory, without `.` or `..`
+pub fn absolute(path: &str) -> String {
+    let full = if path.starts_with('/') {
+        path.to_string()
+    } else {
+        let cwd = syscall::getcwd().unwrap_or_else(|_| std::path::PathBuf::from("/"));
+        format!("{}/{}", cwd.display(), path)
+    };
+    let mut parts: Vec<&str> = Vec::new();
+    for part in full.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    format!("/{}", parts.join("/"))
+}
+
+/// A uniform random number below `bound` (which must not be 0) from the
+/// kernel's generator, without modulo bias
+pub fn random_below(bound: u64) -> Result<u64, String> {
+    // Reject the top partial copy of 0..bound in the u64 range
+    let zone = u64::MAX - u64::MAX % bound;
+    loop {
+        let mut bytes = [0; 8];
+        syscall::getrandom(&mut bytes).map_err(|e| e.to_string())?;
+        let n = u64::from_le_bytes(bytes);
+        if n < zone {
+            return Ok(n % bound);
+        }
+    }
 }
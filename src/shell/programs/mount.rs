@@ -1,21 +1,42 @@
 //! Mount and filesystem programs
 
-use super::{args_to_strs, check_help};
 use crate::kernel::syscall;
+use crate::shell::argparse::{Arg, ArgError, ArgKind, CommandSpec, Flag};
 
-pub fn prog_mount(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
+pub static MOUNT: CommandSpec = CommandSpec {
+    name: "mount",
+    summary: "mount a filesystem",
+    description: "Mount a filesystem of type TYPE (tmpfs by default) from SOURCE on TARGET. With no operands, show all mounts.
 
-    if let Some(help) = check_help(
-        &args,
-        "Usage: mount [-t TYPE] [-o OPTIONS] SOURCE TARGET\n       mount (show all mounts)\n\nMount a filesystem.\n\nOptions:\n  -t TYPE   Filesystem type (proc, sysfs, devfs, tmpfs)\n  -o OPTS   Mount options (ro, noexec, noatime, etc.)",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
+mount -o remount,OPTS TARGET changes only the options named. The journal option (on for / by default) makes saves go through a write-ahead journal that is replayed if a save is interrupted.
+
+An overlay takes its lower directories, topmost first, from -o lowerdir=DIR[:DIR...]; changes go to an in-memory upper layer. A tar mount serves the archive SOURCE read-only.
+
+An encrypted mount opens the vault in the file SOURCE with the passphrase on the first line of standard input; -o create makes a new vault if SOURCE doesn't exist.
+
+A webdav mount reads the share at the URL SOURCE in the background and sends changes back as they are made; -o user=NAME logs in as NAME with the password on the first line of standard input.",
+    flags: &[
+        Flag::short(
+            't',
+            "Filesystem type (proc, sysfs, devfs, tmpfs, overlay, tar, encrypted, webdav)",
+        )
+        .value("TYPE"),
+        Flag::short('o', "Mount options (ro, noexec, noatime, journal, etc.)").value("OPTS"),
+    ],
+    args: &[
+        Arg::new("SOURCE", ArgKind::Text).optional(),
+        Arg::new("TARGET", ArgKind::Dir).optional(),
+    ],
+};
+
+pub fn prog_mount(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match MOUNT.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&MOUNT, stdout, stderr),
+    };
 
     // No arguments: list all mounts
-    if args.is_empty() {
+    if m.operands.is_empty() {
         syscall::KERNEL.with(|k| {
             let kernel = k.borrow();
             for entry in kernel.mounts().list() {
@@ -31,46 +52,31 @@ pub fn prog_mount(args: &[String], __stdin: &str, stdout: &mut String, stderr: &
         return 0;
     }
 
-    // Parse arguments
-    let mut fstype = "tmpfs".to_string();
-    let mut options = "rw".to_string();
-    let mut positional: Vec<String> = Vec::new();
-    let mut i = 0;
-
-    while i < args.len() {
-        let arg = args[i];
-        match arg {
-            "-t" => {
-                if i + 1 < args.len() {
-                    i += 1;
-                    fstype = args[i].to_string();
-                } else {
-                    stderr.push_str("mount: option requires an argument -- 't'\n");
-                    return 1;
-                }
-            }
-            "-o" => {
-                if i + 1 < args.len() {
-                    i += 1;
-                    options = args[i].to_string();
-                } else {
-                    stderr.push_str("mount: option requires an argument -- 'o'\n");
-                    return 1;
-                }
-            }
-            _ if !arg.starts_with('-') => {
-                positional.push(args[i].to_string());
-            }
-            _ => {
-                // Unknown option
+    let fstype = m.value("t").unwrap_or("tmpfs");
+    let options = m.value("o").unwrap_or("rw");
+    let positional = &m.operands;
+
+    // `-o remount,OPTS TARGET` changes the options of an existing mount
+    if options.split(',').any(|opt| opt.trim() == "remount") {
+        let Some(target) = positional.last() else {
+            stderr.push_str("mount: remount: missing target\n");
+            return 1;
+        };
+        let changes: Vec<&str> = options
+            .split(',')
+            .filter(|opt| opt.trim() != "remount")
+            .collect();
+        return match syscall::remount(target, &changes.join(",")) {
+            Ok(()) => 0,
+            Err(e) => {
+                stderr.push_str(&format!("mount: {}: {}\n", target, e));
+                1
             }
-        }
-        i += 1;
+        };
     }
 
     if positional.len() < 2 {
-        stderr.push_str("mount: usage: mount [-t type] [-o options] source target\n");
-        return 1;
+        return ArgError::MissingOperand("TARGET").report(&MOUNT, stdout, stderr);
     }
 
     let source = &positional[0];
@@ -78,75 +84,225 @@ pub fn prog_mount(args: &[String], __stdin: &str, stdout: &mut String, stderr: &
 
     use crate::kernel::mount::{FsType, MountOptions};
 
-    let fs = FsType::parse(&fstype);
-    let opts = MountOptions::parse(&options);
-    let now = syscall::KERNEL.with(|k| k.borrow().now());
+    let fs = FsType::parse(fstype);
+    if fs == FsType::Overlay {
+        return mount_overlay(source, target, options, stderr);
+    }
+    if fs == FsType::Tar {
+        return match syscall::mount_tar(source, target, MountOptions::parse(options)) {
+            Ok(()) => 0,
+            Err(e) => {
+                stderr.push_str(&format!("mount: {}: {}\n", source, e));
+                1
+            }
+        };
+    }
+    if fs == FsType::Encrypted {
+        return mount_encrypted(source, target, options, stdin, stderr);
+    }
+    if fs == FsType::WebDav {
+        return mount_webdav(source, target, options, stdin, stdout, stderr);
+    }
+    match syscall::mount(source, target, fs, MountOptions::parse(options)) {
+        Ok(()) => 0,
+        Err(e) => {
+            stderr.push_str(&format!("mount: {}: {}\n", target, e));
+            1
+        }
+    }
+}
 
-    let result = syscall::KERNEL.with(|k| {
-        let mut kernel = k.borrow_mut();
-        kernel.mounts_mut().mount(source, target, fs, opts, now)
-    });
+/// `mount -t encrypted [-o create,...] SOURCE TARGET`, the passphrase on
+/// standard input
+fn mount_encrypted(
+    source: &str,
+    target: &str,
+    options: &str,
+    stdin: &str,
+    stderr: &mut String,
+) -> i32 {
+    use crate::kernel::mount::MountOptions;
 
-    match result {
+    let passphrase = stdin.lines().next().unwrap_or_default();
+    if passphrase.is_empty() {
+        stderr.push_str(&format!(
+            "mount: {}: passphrase expected on standard input\n",
+            source
+        ));
+        return 1;
+    }
+    let create = options.split(',').any(|opt| opt.trim() == "create");
+    match syscall::mount_encrypted(
+        source,
+        target,
+        passphrase,
+        create,
+        MountOptions::parse(options),
+    ) {
         Ok(()) => 0,
         Err(e) => {
-            stderr.push_str(&format!("mount: {:?}\n", e));
+            stderr.push_str(&format!("mount: {}: {}\n", source, e));
             1
         }
     }
 }
 
-pub fn prog_umount(
-    args: &[String],
-    __stdin: &str,
+/// `mount -t webdav [-o user=NAME,...] URL TARGET`, the password for
+/// NAME on standard input
+///
+/// The share is read in the background; how that went is reported when it
+/// is done.
+fn mount_webdav(
+    url: &str,
+    target: &str,
+    options: &str,
+    stdin: &str,
     stdout: &mut String,
     stderr: &mut String,
 ) -> i32 {
-    let args = args_to_strs(args);
+    use crate::kernel::mount::MountOptions;
+    use crate::vfs::webdav::Remote;
 
-    if let Some(help) = check_help(&args, "Usage: umount TARGET\nUnmount a filesystem.") {
-        stdout.push_str(&help);
-        return 0;
+    let mut remote = match Remote::new(url, None) {
+        Ok(remote) => remote,
+        Err(e) => {
+            stderr.push_str(&format!("mount: {}\n", e));
+            return 1;
+        }
+    };
+    if let Some(user) = options
+        .split(',')
+        .find_map(|opt| opt.trim().strip_prefix("user="))
+    {
+        let password = stdin.lines().next().unwrap_or_default();
+        remote = remote.with_login(user, password);
+    }
+    // Checked again when the share is in, but don't make them wait to hear
+    match syscall::metadata(target) {
+        Ok(meta) if meta.is_dir => {}
+        Ok(_) => {
+            stderr.push_str(&format!("mount: {}: Not a directory\n", target));
+            return 1;
+        }
+        Err(e) => {
+            stderr.push_str(&format!("mount: {}: {}\n", target, e));
+            return 1;
+        }
+    }
+    let opts = MountOptions::parse(options);
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        stdout.push_str(&format!("mount: reading {}...\n", url));
+        let (url, target) = (url.to_string(), target.to_string());
+        wasm_bindgen_futures::spawn_local(async move {
+            let mounted = match crate::vfs::webdav::connect(remote).await {
+                Ok(fs) => syscall::mount_webdav(&url, fs, &target, opts).map_err(|e| e.to_string()),
+                Err(e) => Err(e),
+            };
+            crate::terminal::report(&match mounted {
+                Ok(()) => format!("mount: {} on {}\n", url, target),
+                Err(e) => format!("mount: {}: {}\n", url, e),
+            });
+        });
+        0
     }
 
-    if args.is_empty() {
-        stderr.push_str("umount: usage: umount target\n");
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let _ = (remote, opts, stdout);
+        stderr.push_str("mount: webdav: not available in this build (requires WASM)\n");
+        1
+    }
+}
+
+/// `mount -t overlay -o lowerdir=A:B,... SOURCE TARGET`
+fn mount_overlay(source: &str, target: &str, options: &str, stderr: &mut String) -> i32 {
+    use crate::kernel::mount::MountOptions;
+
+    let mut lower: Vec<&str> = Vec::new();
+    let mut rest: Vec<&str> = Vec::new();
+    for opt in options.split(',') {
+        if let Some(dirs) = opt.strip_prefix("lowerdir=") {
+            lower.extend(dirs.split(':').filter(|d| !d.is_empty()));
+        } else if opt.starts_with("upperdir=") || opt.starts_with("workdir=") {
+            stderr.push_str(
+                "mount: overlay: the upper layer is always in memory; drop upperdir/workdir\n",
+            );
+            return 1;
+        } else {
+            rest.push(opt);
+        }
+    }
+    if lower.is_empty() {
+        stderr.push_str("mount: overlay: missing lowerdir=DIR[:DIR...]\n");
         return 1;
     }
 
-    let target = &args[0];
+    let opts = MountOptions::parse(&rest.join(","));
+    match syscall::mount_overlay(source, &lower, target, opts) {
+        Ok(()) => 0,
+        Err(e) => {
+            stderr.push_str(&format!("mount: {}: {}\n", target, e));
+            1
+        }
+    }
+}
+
+pub static UMOUNT: CommandSpec = CommandSpec {
+    name: "umount",
+    summary: "unmount a filesystem",
+    description: "Unmount the filesystem mounted on TARGET.",
+    flags: &[],
+    args: &[Arg::new("TARGET", ArgKind::Dir)],
+};
 
-    let result = syscall::KERNEL.with(|k| k.borrow_mut().mounts_mut().umount(target));
+pub fn prog_umount(
+    args: &[String],
+    __stdin: &str,
+    stdout: &mut String,
+    stderr: &mut String,
+) -> i32 {
+    let m = match UMOUNT.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&UMOUNT, stdout, stderr),
+    };
+    let target = &m.operands[0];
 
-    match result {
+    match syscall::umount(target) {
         Ok(_) => 0,
         Err(e) => {
-            stderr.push_str(&format!("umount: {}: {:?}\n", target, e));
+            stderr.push_str(&format!("umount: {}: {}\n", target, e));
             1
         }
     }
 }
 
+pub static FINDMNT: CommandSpec = CommandSpec {
+    name: "findmnt",
+    summary: "find a filesystem mount point",
+    description: "Show the mount on TARGET, or the mount TARGET is under.
+
+With no arguments, lists all mounts in a tree-like format.",
+    flags: &[],
+    args: &[Arg::new("TARGET", ArgKind::Path).optional()],
+};
+
 pub fn prog_findmnt(
     args: &[String],
     __stdin: &str,
     stdout: &mut String,
-    _stderr: &mut String,
+    stderr: &mut String,
 ) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: findmnt [TARGET]\nFind a filesystem mount point.\n\nWith no arguments, lists all mounts in a tree-like format.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
+    let m = match FINDMNT.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&FINDMNT, stdout, stderr),
+    };
 
     syscall::KERNEL.with(|k| {
         let kernel = k.borrow();
 
-        if args.is_empty() {
+        if m.operands.is_empty() {
             // List all mounts
             stdout.push_str("TARGET                  SOURCE     FSTYPE   OPTIONS\n");
             let mut mounts: Vec<_> = kernel.mounts().list();
@@ -167,7 +323,7 @@ pub fn prog_findmnt(
             }
         } else {
             // Find specific mount point
-            let target = &args[0];
+            let target = &m.operands[0];
             if let Some(entry) = kernel.mounts().get_mount(target) {
                 stdout.push_str(&format!(
                     "TARGET: {}\nSOURCE: {}\nFSTYPE: {}\nOPTIONS: {}\n",
@@ -215,7 +371,7 @@ mod tests {
         let mut stderr = String::new();
         let result = prog_mount(&args, "", &mut stdout, &mut stderr);
         assert_eq!(result, 1);
-        assert!(stderr.contains("usage"));
+        assert!(stderr.contains("missing operand: expected TARGET"));
     }
 
     #[test]
@@ -225,7 +381,424 @@ mod tests {
         let mut stderr = String::new();
         let result = prog_mount(&args, "", &mut stdout, &mut stderr);
         assert_eq!(result, 1);
-        assert!(stderr.contains("option requires an argument"));
+        assert!(stderr.contains("option -t needs a value"));
+    }
+
+    #[test]
+    fn test_mount_remount_journal() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        let run = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = prog_mount(&args, "", &mut stdout, &mut stderr);
+            (code, stdout, stderr)
+        };
+
+        assert!(syscall::persist_journaled());
+        assert_eq!(run(&["-o", "remount,nojournal", "/"]).0, 0);
+        assert!(!syscall::persist_journaled());
+        assert!(
+            run(&[])
+                .1
+                .contains("rootfs on / type memoryfs (rw,compress)")
+        );
+        assert_eq!(run(&["-o", "remount,journal", "/"]).0, 0);
+        assert!(syscall::persist_journaled());
+
+        assert!(syscall::persist_compressed());
+        assert_eq!(run(&["-o", "remount,nocompress", "/"]).0, 0);
+        assert!(!syscall::persist_compressed());
+
+        let (code, _, stderr) = run(&["-o", "remount,ro", "/nowhere"]);
+        assert_eq!(code, 1);
+        assert!(stderr.contains("/nowhere"));
+    }
+
+    #[test]
+    fn test_mount_casefold() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        let run = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = prog_mount(&args, "", &mut stdout, &mut stderr);
+            (code, stdout, stderr)
+        };
+
+        syscall::mkdir("/tmp/import").unwrap();
+        let (code, _, stderr) = run(&["-t", "tmpfs", "-o", "casefold", "tmpfs", "/tmp/import"]);
+        assert_eq!(code, 0, "{}", stderr);
+        syscall::mkdir("/tmp/import/Photos").unwrap();
+        syscall::write_file("/tmp/import/Photos/IMG_01.JPG", "jpeg").unwrap();
+
+        // Any spelling finds the file, which keeps its own
+        assert_eq!(
+            syscall::read_file("/tmp/import/photos/img_01.jpg").unwrap(),
+            "jpeg"
+        );
+        syscall::write_file("/tmp/import/PHOTOS/img_01.jpg", "new").unwrap();
+        assert_eq!(
+            syscall::readdir("/tmp/import/photos").unwrap(),
+            vec!["IMG_01.JPG"]
+        );
+        syscall::rename(
+            "/tmp/import/photos/img_01.jpg",
+            "/tmp/import/photos/img_01.jpg",
+        )
+        .unwrap();
+        assert_eq!(
+            syscall::readdir("/tmp/import/Photos").unwrap(),
+            vec!["img_01.jpg"]
+        );
+
+        let mv = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = crate::shell::programs::file::prog_mv(&args, "", &mut stdout, &mut stderr);
+            (code, stderr)
+        };
+        assert_eq!(
+            mv(&["/tmp/import/Photos", "/tmp/import/PHOTOS"]),
+            (0, String::new())
+        );
+        assert_eq!(syscall::readdir("/tmp/import").unwrap(), vec!["PHOTOS"]);
+        assert_eq!(
+            mv(&[
+                "/tmp/import/photos/img_01.jpg",
+                "/tmp/import/photos/Img_01.jpg"
+            ]),
+            (0, String::new())
+        );
+        assert_eq!(
+            syscall::read_file("/tmp/import/photos/IMG_01.jpg").unwrap(),
+            "new"
+        );
+
+        // Nowhere else, and not once the option is off
+        assert!(syscall::read_file("/tmp/IMPORT/photos/img_01.jpg").is_err());
+        assert_eq!(run(&["-o", "remount,nocasefold", "/tmp/import"]).0, 0);
+        assert!(syscall::read_file("/tmp/import/photos/img_01.jpg").is_err());
+        assert_eq!(run(&["-o", "remount,casefold", "/tmp/import"]).0, 0);
+        assert!(syscall::read_file("/tmp/import/photos/img_01.jpg").is_ok());
+        syscall::umount("/tmp/import").unwrap();
+        assert!(syscall::read_file("/tmp/import/photos/img_01.jpg").is_err());
+
+        let (code, _, stderr) = run(&["-o", "remount,casefold", "/proc"]);
+        assert_eq!(code, 1);
+        assert!(stderr.contains("/proc"));
+    }
+
+    #[test]
+    fn test_mount_overlay() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        for dir in ["/tmp/base", "/tmp/base/etc", "/tmp/extra", "/tmp/merged"] {
+            syscall::mkdir(dir).unwrap();
+        }
+        syscall::write_file("/tmp/base/etc/motd", "base").unwrap();
+        syscall::write_file("/tmp/base/etc/hosts", "hosts").unwrap();
+        syscall::write_file("/tmp/extra/notes", "extra").unwrap();
+
+        let run = |args: &[&str], prog: fn(&[String], &str, &mut String, &mut String) -> i32| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = prog(&args, "", &mut stdout, &mut stderr);
+            (code, stdout, stderr)
+        };
+
+        let (code, _, stderr) = run(&["-t", "overlay", "overlay", "/tmp/merged"], prog_mount);
+        assert_eq!(code, 1);
+        assert!(stderr.contains("lowerdir"));
+
+        let args = [
+            "-t",
+            "overlay",
+            "-o",
+            "lowerdir=/tmp/extra:/tmp/base",
+            "overlay",
+            "/tmp/merged",
+        ];
+        assert_eq!(run(&args, prog_mount).0, 0);
+        let (_, stdout, _) = run(&[], prog_mount);
+        assert!(stdout.contains("overlay on /tmp/merged type overlay (rw)"));
+
+        // Both lower directories show through
+        assert_eq!(syscall::read_file("/tmp/merged/etc/motd").unwrap(), "base");
+        assert_eq!(syscall::read_file("/tmp/merged/notes").unwrap(), "extra");
+        let mut names = syscall::readdir("/tmp/merged").unwrap();
+        names.sort();
+        assert_eq!(names, ["etc", "notes"]);
+
+        // Changes stay in the overlay
+        syscall::write_file("/tmp/merged/etc/motd", "changed").unwrap();
+        syscall::remove_file("/tmp/merged/etc/hosts").unwrap();
+        syscall::mkdir("/tmp/merged/new").unwrap();
+        assert_eq!(
+            syscall::read_file("/tmp/merged/etc/motd").unwrap(),
+            "changed"
+        );
+        assert!(!syscall::exists("/tmp/merged/etc/hosts").unwrap());
+        assert_eq!(syscall::read_file("/tmp/base/etc/motd").unwrap(), "base");
+        assert!(syscall::exists("/tmp/base/etc/hosts").unwrap());
+        assert!(!syscall::exists("/tmp/new").unwrap());
+
+        // Copies cross into and out of it; renames don't
+        syscall::copy_file("/tmp/extra/notes", "/tmp/merged/new/notes").unwrap();
+        assert_eq!(
+            syscall::read_file("/tmp/merged/new/notes").unwrap(),
+            "extra"
+        );
+        assert!(syscall::rename("/tmp/merged/notes", "/tmp/notes").is_err());
+
+        // Busy while a file in it is open
+        let fd = syscall::open("/tmp/merged/notes", syscall::OpenFlags::READ).unwrap();
+        let (code, _, stderr) = run(&["/tmp/merged"], prog_umount);
+        assert_eq!(code, 1);
+        assert!(stderr.contains("busy"));
+        syscall::close(fd).unwrap();
+
+        assert_eq!(run(&["/tmp/merged"], prog_umount).0, 0);
+        assert!(syscall::readdir("/tmp/merged").unwrap().is_empty());
+    }
+
+    /// A ustar archive of `(name, contents)`; names ending in `/` are
+    /// directories
+    fn tar(entries: &[(&str, &str)]) -> Vec<u8> {
+        let mut tar = Vec::new();
+        for (name, body) in entries {
+            let mut header = vec![0u8; 512];
+            header[..name.len()].copy_from_slice(name.as_bytes());
+            header[100..107].copy_from_slice(b"0000755");
+            header[124..135].copy_from_slice(format!("{:011o}", body.len()).as_bytes());
+            header[148..156].copy_from_slice(b"        ");
+            header[156] = if name.ends_with('/') { b'5' } else { b'0' };
+            header[257..263].copy_from_slice(b"ustar\0");
+            let sum: u32 = header.iter().map(|&b| u32::from(b)).sum();
+            header[148..155].copy_from_slice(format!("{:06o}\0", sum).as_bytes());
+            tar.extend(header);
+            tar.extend_from_slice(body.as_bytes());
+            tar.resize(tar.len().div_ceil(512) * 512, 0);
+        }
+        tar.extend([0u8; 1024]);
+        tar
+    }
+
+    #[test]
+    fn test_mount_tar() {
+        use crate::kernel::syscall::{KERNEL, Kernel, SyscallError};
+
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        syscall::mkdir("/tmp/mnt").unwrap();
+        let archive = tar(&[
+            ("data/", ""),
+            ("data/a.csv", "1,2,3\n"),
+            ("bin/run", "echo hi"),
+        ]);
+        let fd = syscall::open("/tmp/set.tar", syscall::OpenFlags::WRITE).unwrap();
+        syscall::write(fd, &archive).unwrap();
+        syscall::close(fd).unwrap();
+
+        let run = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = prog_mount(&args, "", &mut stdout, &mut stderr);
+            (code, stdout, stderr)
+        };
+        assert_eq!(run(&["-t", "tar", "/tmp/set.tar", "/tmp/mnt"]).0, 0);
+        assert!(
+            run(&[])
+                .1
+                .contains("/tmp/set.tar on /tmp/mnt type tar (ro)")
+        );
+
+        assert_eq!(
+            syscall::read_file("/tmp/mnt/data/a.csv").unwrap(),
+            "1,2,3\n"
+        );
+        let mut names = syscall::readdir("/tmp/mnt").unwrap();
+        names.sort();
+        assert_eq!(names, ["bin", "data"]);
+        assert_eq!(
+            syscall::write_file("/tmp/mnt/data/a.csv", "x"),
+            Err(SyscallError::ReadOnlyFs)
+        );
+        assert_eq!(
+            syscall::mkdir("/tmp/mnt/new"),
+            Err(SyscallError::ReadOnlyFs)
+        );
+
+        // Not an archive
+        syscall::mkdir("/tmp/other").unwrap();
+        syscall::write_file("/tmp/junk.tar", &"x".repeat(1024)).unwrap();
+        let (code, _, stderr) = run(&["-t", "tar", "/tmp/junk.tar", "/tmp/other"]);
+        assert_eq!(code, 1);
+        assert!(stderr.contains("checksum"));
+
+        assert_eq!(
+            syscall::umount("/tmp/mnt").map(|e| e.source).unwrap(),
+            "/tmp/set.tar"
+        );
+        assert!(syscall::readdir("/tmp/mnt").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_mount_encrypted() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+        use crate::vfs::{EncryptedFs, FileSystem, MemoryFs, compress};
+
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        syscall::mkdir("/tmp/private").unwrap();
+        let run = |args: &[&str], stdin: &str| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = prog_mount(&args, stdin, &mut stdout, &mut stderr);
+            (code, stdout, stderr)
+        };
+        let mount = ["-t", "encrypted", "/tmp/notes.vault", "/tmp/private"];
+
+        let (code, _, stderr) = run(&mount, "");
+        assert_eq!(code, 1);
+        assert!(stderr.contains("passphrase expected"));
+        let (code, _, stderr) = run(&mount, "hunter2\n");
+        assert_eq!(code, 1);
+        assert!(stderr.contains("not found"));
+
+        // A vault made with few PBKDF2 rounds, to keep the test quick
+        // (`-o create` uses the full count)
+        let mut vault = EncryptedFs::create_with(MemoryFs::new(), "hunter2", 10).unwrap();
+        let (uid, gid) = (syscall::getuid().unwrap(), syscall::getgid().unwrap());
+        vault.chown("/", Some(uid.0), Some(gid.0)).unwrap();
+        let image = compress::pack(&vault.image().unwrap().unwrap());
+        let fd = syscall::open("/tmp/notes.vault", syscall::OpenFlags::WRITE).unwrap();
+        syscall::write(fd, &image).unwrap();
+        syscall::close(fd).unwrap();
+
+        assert_eq!(run(&mount, "hunter2\n").0, 0);
+        assert!(
+            run(&[], "")
+                .1
+                .contains("/tmp/notes.vault on /tmp/private type encrypted (rw)")
+        );
+        syscall::mkdir("/tmp/private/diary").unwrap();
+        syscall::write_file("/tmp/private/diary/today", "Dear diary").unwrap();
+
+        // Written back on unmount, with nothing readable in the file
+        syscall::umount("/tmp/private").unwrap();
+        assert!(syscall::readdir("/tmp/private").unwrap().is_empty());
+        let fd = syscall::open("/tmp/notes.vault", syscall::OpenFlags::READ).unwrap();
+        let mut packed = vec![0u8; 1 << 20];
+        let n = syscall::read(fd, &mut packed).unwrap();
+        syscall::close(fd).unwrap();
+        let image = compress::unpack(&packed[..n]).unwrap();
+        let image = String::from_utf8_lossy(&image);
+        assert!(!image.contains("diary"));
+
+        let (code, _, stderr) = run(&mount, "hunter3\n");
+        assert_eq!(code, 1);
+        assert!(stderr.contains("wrong passphrase"));
+        assert_eq!(run(&mount, "hunter2\n").0, 0);
+        assert_eq!(
+            syscall::read_file("/tmp/private/diary/today").unwrap(),
+            "Dear diary"
+        );
+
+        // Saves write it back too
+        syscall::write_file("/tmp/private/todo", "buy milk").unwrap();
+        syscall::vfs_snapshot().unwrap();
+        syscall::umount("/tmp/private").unwrap();
+        assert_eq!(run(&mount, "hunter2\n").0, 0);
+        assert_eq!(syscall::read_file("/tmp/private/todo").unwrap(), "buy milk");
+    }
+
+    #[test]
+    fn test_mount_webdav() {
+        use crate::kernel::mount::MountOptions;
+        use crate::kernel::syscall::{KERNEL, Kernel, SyscallError};
+        use crate::vfs::webdav::{DavEntry, Remote, WebDavFs};
+
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        syscall::mkdir("/tmp/remote").unwrap();
+        let run = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = prog_mount(&args, "", &mut stdout, &mut stderr);
+            (code, stderr)
+        };
+        let (code, stderr) = run(&["-t", "webdav", "ftp://dav.test/", "/tmp/remote"]);
+        assert_eq!(code, 1);
+        assert!(stderr.contains("not an http(s) URL"));
+        let (code, stderr) = run(&["-t", "webdav", "https://dav.test/", "/tmp/none"]);
+        assert_eq!(code, 1);
+        assert!(stderr.contains("/tmp/none"));
+
+        // What the share would be once read
+        let url = "https://dav.test/share";
+        let mut fs = WebDavFs::new(Remote::new(url, None).unwrap());
+        let entry = |path: &str, is_dir| DavEntry {
+            path: path.into(),
+            is_dir,
+            size: 0,
+            mtime: None,
+        };
+        fs.load(&entry("/docs", true), b"").unwrap();
+        fs.load(&entry("/docs/readme", false), b"hello").unwrap();
+        syscall::mount_webdav(url, fs, "/tmp/remote", MountOptions::default()).unwrap();
+
+        let mut stdout = String::new();
+        prog_mount(&[], "", &mut stdout, &mut String::new());
+        assert!(stdout.contains("https://dav.test/share on /tmp/remote type webdav (rw)"));
+        assert_eq!(
+            syscall::read_file("/tmp/remote/docs/readme").unwrap(),
+            "hello"
+        );
+        let meta = syscall::metadata("/tmp/remote/docs").unwrap();
+        assert_eq!(meta.uid, syscall::getuid().unwrap().0);
+        syscall::write_file("/tmp/remote/docs/new", "data").unwrap();
+        assert_eq!(syscall::readdir("/tmp/remote/docs").unwrap().len(), 2);
+        assert!(syscall::symlink("readme", "/tmp/remote/docs/link").is_err());
+        assert_eq!(
+            syscall::mount_webdav(
+                url,
+                WebDavFs::new(Remote::new(url, None).unwrap()),
+                "/tmp/remote/docs/new",
+                MountOptions::default()
+            ),
+            Err(SyscallError::NotADirectory)
+        );
     }
 
     #[test]
@@ -246,7 +819,7 @@ mod tests {
         let mut stderr = String::new();
         let result = prog_umount(&args, "", &mut stdout, &mut stderr);
         assert_eq!(result, 1);
-        assert!(stderr.contains("usage"));
+        assert!(stderr.contains("missing operand: expected TARGET"));
     }
 
     #[test]
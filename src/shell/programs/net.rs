@@ -6,68 +6,83 @@
 //! Programs:
 //! - `curl`: Transfer data from URLs with support for custom methods and headers
 //! - `wget`: Download files from URLs to the filesystem
+//! - `exportfs`: Serve the filesystem over 9P through a WebSocket relay
+//! - `netperm`: Manage which origins network connections may reach
+//! - `offline`: Turn offline mode on or off
 
-use super::{args_to_strs, check_help};
-#[cfg(target_arch = "wasm32")]
+use super::absolute;
+use crate::kernel::httpcache;
+use crate::kernel::netperm::{self, NetDecision};
+use crate::kernel::ninep;
 use crate::kernel::syscall;
+use crate::shell::argparse::{Arg, ArgKind, CommandSpec, Flag};
+
+pub static CURL: CommandSpec = CommandSpec {
+    name: "curl",
+    summary: "transfer data from a URL",
+    description: "Transfer data from URL and print it.
+
+In offline mode only --cached works, and only for a URL in the cache.",
+    flags: &[
+        Flag::short('i', "Include headers in output"),
+        Flag::short('s', "Silent mode"),
+        Flag::short('X', "Specify request method").value("METHOD"),
+        Flag::short('H', "Add custom header").value("HEADER"),
+        Flag::long(
+            "cached",
+            "Use the cached copy, if there is one, without asking the server",
+        ),
+    ],
+    args: &[Arg::new("URL", ArgKind::Text)],
+};
 
 /// curl - transfer data from URL
 pub fn prog_curl(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-    if let Some(help) = check_help(
-        &args,
-        "Usage: curl [OPTIONS] URL\nTransfer data from URL.\n  -i  Include headers in output\n  -s  Silent mode\n  -X METHOD  Specify request method\n  -H HEADER  Add custom header\nSee 'man curl' for details.",
-    ) {
-        stdout.push_str(&help);
+    let m = match CURL.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&CURL, stdout, stderr),
+    };
+    let url = m.operand(0).unwrap_or_default().to_string();
+
+    // A cached copy needs no network, so it is served in any build
+    let use_cached = m.flag("cached");
+    if use_cached && let Some(cached) = syscall::http_cache_get(&url) {
+        if m.flag("i") {
+            stdout.push_str("HTTP/1.1 200 OK\nx-cache: HIT\n");
+            if let Some(content_type) = &cached.content_type {
+                stdout.push_str(&format!("content-type: {}\n", content_type));
+            }
+            stdout.push('\n');
+        }
+        stdout.push_str(&String::from_utf8_lossy(&cached.body));
         return 0;
     }
-
-    // Parse URL from arguments (needed for both wasm and non-wasm paths)
-    let url: String = args
-        .iter()
-        .find(|s| !s.starts_with('-') && !s.is_empty())
-        .map(|s| s.to_string())
-        .unwrap_or_default();
-
-    if url.is_empty() {
-        stderr.push_str("curl: no URL specified\n");
-        return 1;
+    if syscall::offline() {
+        stderr.push_str(&format!(
+            "curl: {}: offline mode is on; run 'offline off' to connect{}\n",
+            url,
+            if use_cached {
+                " (nothing cached)"
+            } else {
+                ", or use --cached"
+            }
+        ));
+        return 7;
     }
 
     #[cfg(target_arch = "wasm32")]
     {
+        use crate::kernel::httpcache::CachePolicy;
         use crate::kernel::network::{HttpMethod, HttpRequest};
 
-        // Parse WASM-specific options
-        let mut include_headers = false;
-        let mut method = "GET";
-        let mut headers: Vec<(String, String)> = Vec::new();
-        let mut i = 0;
-
-        while i < args.len() {
-            match args[i] {
-                "-i" => include_headers = true,
-                "-s" => {} // silent mode
-                "-X" => {
-                    i += 1;
-                    if i < args.len() {
-                        method = args[i];
-                    }
-                }
-                "-H" => {
-                    i += 1;
-                    if i < args.len() {
-                        if let Some(pos) = args[i].find(':') {
-                            let name = args[i][..pos].trim().to_string();
-                            let value = args[i][pos + 1..].trim().to_string();
-                            headers.push((name, value));
-                        }
-                    }
-                }
-                _ => {}
-            }
-            i += 1;
-        }
+        let include_headers = m.flag("i");
+        let method = m.value("X").unwrap_or("GET");
+        let headers: Vec<(String, String)> = m
+            .values("H")
+            .into_iter()
+            .filter_map(|header| header.split_once(':'))
+            .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+            .collect();
 
         let http_method = match method.to_uppercase().as_str() {
             "GET" => HttpMethod::Get,
@@ -87,7 +102,12 @@ pub fn prog_curl(args: &[String], __stdin: &str, stdout: &mut String, stderr: &m
         let headers_clone = headers.clone();
 
         wasm_bindgen_futures::spawn_local(async move {
-            let mut req = HttpRequest::new(http_method, &url_clone);
+            let policy = if use_cached {
+                CachePolicy::Prefer
+            } else {
+                CachePolicy::Revalidate
+            };
+            let mut req = HttpRequest::new(http_method, &url_clone).cache(policy);
             for (name, value) in headers_clone {
                 req = req.header(&name, &value);
             }
@@ -123,44 +143,28 @@ pub fn prog_curl(args: &[String], __stdin: &str, stdout: &mut String, stderr: &m
     0
 }
 
+pub static WGET: CommandSpec = CommandSpec {
+    name: "wget",
+    summary: "download a file from a URL",
+    description: "Download URL and save it to a file named after the last part of its path.
+
+In offline mode the cached copy is saved instead.",
+    flags: &[
+        Flag::short('O', "Save to FILE instead of default").value("FILE"),
+        Flag::short('q', "Quiet mode"),
+    ],
+    args: &[Arg::new("URL", ArgKind::Text)],
+};
+
 /// wget - download file from URL
 #[allow(unused_variables)]
 pub fn prog_wget(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-    if let Some(help) = check_help(
-        &args,
-        "Usage: wget [OPTIONS] URL\nDownload file from URL.\n  -O FILE  Save to FILE instead of default\n  -q       Quiet mode\nSee 'man wget' for details.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
-
-    // Parse arguments
-    let mut url = String::new();
-    let mut output_file = String::new();
-    let mut i = 0;
-
-    while i < args.len() {
-        match args[i] {
-            "-q" => {} // quiet mode
-            "-O" => {
-                i += 1;
-                if i < args.len() {
-                    output_file = args[i].to_string();
-                }
-            }
-            s if !s.starts_with('-') => {
-                url = s.to_string();
-            }
-            _ => {}
-        }
-        i += 1;
-    }
-
-    if url.is_empty() {
-        stderr.push_str("wget: no URL specified\n");
-        return 1;
-    }
+    let m = match WGET.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&WGET, stdout, stderr),
+    };
+    let url = m.operand(0).unwrap_or_default().to_string();
+    let output_file = m.value("O").unwrap_or_default().to_string();
 
     // Determine output filename
     let filename = if output_file.is_empty() {
@@ -170,15 +174,38 @@ pub fn prog_wget(args: &[String], __stdin: &str, stdout: &mut String, stderr: &m
         output_file
     };
 
+    if syscall::offline() {
+        let Some(cached) = syscall::http_cache_get(&url) else {
+            stderr.push_str(&format!(
+                "wget: {}: offline mode is on, and it isn't cached\n",
+                url
+            ));
+            return 1;
+        };
+        if let Err(e) = save(&filename, &cached.body) {
+            stderr.push_str(&format!("wget: failed to write {}: {}\n", filename, e));
+            return 1;
+        }
+        stdout.push_str(&format!(
+            "Saved {} -> {} from the cache ({} bytes)\n",
+            url,
+            filename,
+            cached.body.len()
+        ));
+        return 0;
+    }
+
     #[cfg(target_arch = "wasm32")]
     {
+        use crate::kernel::httpcache::CachePolicy;
         use crate::kernel::network::HttpRequest;
 
         let url_clone = url.clone();
         let filename_clone = filename.clone();
 
         wasm_bindgen_futures::spawn_local(async move {
-            match HttpRequest::get(&url_clone).send().await {
+            let request = HttpRequest::get(&url_clone).cache(CachePolicy::Revalidate);
+            match request.send().await {
                 Ok(resp) => {
                     if resp.status >= 200 && resp.status < 300 {
                         // Write to file
@@ -223,6 +250,272 @@ pub fn prog_wget(args: &[String], __stdin: &str, stdout: &mut String, stderr: &m
     0
 }
 
+/// Write `data` to `path`, replacing it
+fn save(path: &str, data: &[u8]) -> syscall::SyscallResult<()> {
+    let fd = syscall::open(path, syscall::OpenFlags::WRITE)?;
+    let result = syscall::write(fd, data);
+    syscall::close(fd)?;
+    result.map(|_| ())
+}
+
+pub static EXPORTFS: CommandSpec = CommandSpec {
+    name: "exportfs",
+    summary: "serve the filesystem over 9P",
+    description: "Serve the filesystem with the 9P2000 protocol, so another machine can mount it. A page can't accept connections, so exportfs dials out to a WebSocket relay at URL, which passes 9P messages between the browser and a client such as 'mount -t 9p'. Files are read and written as the user running exportfs.
+
+With no URL, list the exports running. With -d, stop the export to URL, or all of them.",
+    flags: &[
+        Flag::short('r', "Serve DIR instead of /").value("DIR"),
+        Flag::short('R', "Serve read-only"),
+        Flag::short('d', "Stop exporting"),
+    ],
+    args: &[Arg::new("URL", ArgKind::Text).optional()],
+};
+
+/// exportfs - serve the filesystem over 9P
+pub fn prog_exportfs(
+    args: &[String],
+    __stdin: &str,
+    stdout: &mut String,
+    stderr: &mut String,
+) -> i32 {
+    let m = match EXPORTFS.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&EXPORTFS, stdout, stderr),
+    };
+    let url = m.operand(0);
+
+    if m.flag("d") {
+        let ids: Vec<u32> = ninep::exports()
+            .into_iter()
+            .filter(|e| url.is_none_or(|url| e.url == url))
+            .map(|e| e.id)
+            .collect();
+        if ids.is_empty() {
+            stderr.push_str(&format!(
+                "exportfs: {}\n",
+                match url {
+                    Some(url) => format!("not exporting to {}", url),
+                    None => "nothing is exported".to_string(),
+                }
+            ));
+            return 1;
+        }
+        for id in ids {
+            ninep::stop(Some(id));
+        }
+        return 0;
+    }
+
+    let Some(url) = url else {
+        for export in ninep::exports() {
+            stdout.push_str(&format!(
+                "{:<4} {} {} ({})\n",
+                export.id,
+                export.root,
+                export.url,
+                if export.read_only { "ro" } else { "rw" }
+            ));
+        }
+        return 0;
+    };
+
+    let root = absolute(m.value("r").unwrap_or("/"));
+    match syscall::metadata(&root) {
+        Ok(meta) if meta.is_dir => {}
+        Ok(_) => {
+            stderr.push_str(&format!("exportfs: {}: not a directory\n", root));
+            return 1;
+        }
+        Err(e) => {
+            stderr.push_str(&format!("exportfs: {}: {}\n", root, e));
+            return 1;
+        }
+    }
+    let mut server = ninep::Server::new(&root);
+    server.set_read_only(m.flag("R"));
+    if let Ok(pid) = syscall::getpid() {
+        server.set_process(pid);
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        match ninep::serve_websocket(url, server) {
+            Ok(id) => {
+                stdout.push_str(&format!(
+                    "exportfs: exporting {} to {} [{}]\n",
+                    root, url, id
+                ));
+                0
+            }
+            Err(e) => {
+                stderr.push_str(&format!("exportfs: {}: {}\n", url, e));
+                1
+            }
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let _ = (url, server);
+        stderr.push_str("exportfs: not available in this build (requires WASM)\n");
+        1
+    }
+}
+
+pub static NETPERM: CommandSpec = CommandSpec {
+    name: "netperm",
+    summary: "allow or deny network connections by origin",
+    description: "Manage which origins fetches, WebSockets and 9P exports may connect to. The first connection to an origin with no decision waits, and a notice asks for one; with no answer in a minute it is refused. COMMAND is one of:
+
+  list            list the decisions and the origins waiting for one (the default)
+  allow ORIGIN    let connections to ORIGIN through (root)
+  deny ORIGIN     refuse them (root)
+  revoke ORIGIN   forget the decision, so the next connection asks again (root)
+
+ORIGIN is a URL such as https://example.com:8443, or just a host, taken as https. Decisions are kept in /etc/net/allowlist.",
+    flags: &[],
+    args: &[
+        Arg::new("COMMAND", ArgKind::Text).optional(),
+        Arg::new("ORIGIN", ArgKind::Text).optional(),
+    ],
+};
+
+/// The origin `text` names: a URL, or a bare host taken as https
+fn parse_origin(text: &str) -> Option<String> {
+    if text.contains("://") {
+        netperm::origin(text)
+    } else {
+        netperm::origin(&format!("https://{}", text))
+    }
+}
+
+/// netperm - network permissions by origin
+pub fn prog_netperm(
+    args: &[String],
+    __stdin: &str,
+    stdout: &mut String,
+    stderr: &mut String,
+) -> i32 {
+    use super::services::ago;
+
+    let m = match NETPERM.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&NETPERM, stdout, stderr),
+    };
+    let operands: Vec<&str> = m.operands.iter().map(String::as_str).collect();
+
+    let (command, origin) = match operands.as_slice() {
+        [] | ["list"] => {
+            let decisions = syscall::netperm_list();
+            let prompts = syscall::netperm_prompts();
+            if decisions.is_empty() && prompts.is_empty() {
+                stdout.push_str("No network permissions\n");
+                return 0;
+            }
+            for (origin, decision) in decisions {
+                stdout.push_str(&format!("{:<6} {}\n", decision.name(), origin));
+            }
+            let now = syscall::now();
+            for prompt in prompts {
+                stdout.push_str(&format!(
+                    "{:<6} {} ({} request{}, asked {})\n",
+                    "ask",
+                    prompt.origin,
+                    prompt.requests,
+                    if prompt.requests == 1 { "" } else { "s" },
+                    ago(now, prompt.since)
+                ));
+            }
+            return 0;
+        }
+        [command @ ("allow" | "deny" | "revoke"), origin] => (*command, *origin),
+        _ => {
+            stderr.push_str(&format!(
+                "netperm: invalid command: {}\n{}\n",
+                operands.join(" "),
+                NETPERM.usage()
+            ));
+            return 1;
+        }
+    };
+
+    let Some(origin) = parse_origin(origin) else {
+        stderr.push_str(&format!("netperm: {}: not an origin\n", origin));
+        return 1;
+    };
+    let result = match command {
+        "allow" => syscall::netperm_set(&origin, NetDecision::Allow),
+        "deny" => syscall::netperm_set(&origin, NetDecision::Deny),
+        _ => syscall::netperm_revoke(&origin),
+    };
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            stderr.push_str(&format!("netperm: {}: {}\n", origin, e));
+            1
+        }
+    }
+}
+
+pub static OFFLINE: CommandSpec = CommandSpec {
+    name: "offline",
+    summary: "work without the network",
+    description: "Turn offline mode on or off, or show it. While it is on, every fetch, WebSocket and 9P export fails at once instead of trying the network, and curl --cached, wget and pkg use what the HTTP cache holds. COMMAND is one of:
+
+  status   show the mode and the size of the cache (the default)
+  on       turn offline mode on
+  off      turn it off
+
+Responses are cached in /var/cache/http, and revalidated with the server while online.",
+    flags: &[],
+    args: &[Arg::new("COMMAND", ArgKind::Text).optional()],
+};
+
+/// offline - turn offline mode on or off
+pub fn prog_offline(
+    args: &[String],
+    __stdin: &str,
+    stdout: &mut String,
+    stderr: &mut String,
+) -> i32 {
+    let m = match OFFLINE.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&OFFLINE, stdout, stderr),
+    };
+    let offline = match m.operand(0) {
+        None | Some("status") => {
+            let (responses, bytes) = syscall::http_cache_usage();
+            stdout.push_str(&format!(
+                "Offline mode: {}\nHTTP cache: {} response{}, {} bytes in {}\n",
+                if syscall::offline() { "on" } else { "off" },
+                responses,
+                if responses == 1 { "" } else { "s" },
+                bytes,
+                httpcache::CACHE_DIR
+            ));
+            return 0;
+        }
+        Some("on") => true,
+        Some("off") => false,
+        Some(other) => {
+            stderr.push_str(&format!(
+                "offline: invalid command: {}\n{}\n",
+                other,
+                OFFLINE.usage()
+            ));
+            return 1;
+        }
+    };
+    match syscall::set_offline(offline) {
+        Ok(()) => 0,
+        Err(e) => {
+            stderr.push_str(&format!("offline: {}\n", e));
+            1
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -257,7 +550,7 @@ mod tests {
         let mut stderr = String::new();
         let result = prog_curl(&args, "", &mut stdout, &mut stderr);
         assert_eq!(result, 1);
-        assert!(stderr.contains("no URL specified"));
+        assert!(stderr.contains("missing operand: expected URL"));
     }
 
     #[test]
@@ -289,7 +582,7 @@ mod tests {
         let mut stderr = String::new();
         let result = prog_wget(&args, "", &mut stdout, &mut stderr);
         assert_eq!(result, 1);
-        assert!(stderr.contains("no URL specified"));
+        assert!(stderr.contains("missing operand: expected URL"));
     }
 
     #[test]
@@ -303,4 +596,142 @@ mod tests {
         // Non-WASM build returns a message about WASM requirement
         assert!(stdout.contains("not available") || stdout.contains("Downloading"));
     }
+
+    #[test]
+    fn test_exportfs() {
+        let run = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = prog_exportfs(&args, "", &mut stdout, &mut stderr);
+            (code, stdout, stderr)
+        };
+        assert_eq!(run(&[]), (0, String::new(), String::new()));
+        let (code, _, stderr) = run(&["-d", "ws://relay"]);
+        assert_eq!(code, 1);
+        assert!(stderr.contains("not exporting to ws://relay"));
+
+        assert_eq!(absolute("/home/./user/../guest/"), "/home/guest");
+        assert_eq!(absolute("/../.."), "/");
+    }
+
+    #[test]
+    fn test_netperm() {
+        use crate::kernel::Uid;
+        use crate::kernel::syscall::{KERNEL, Kernel};
+
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("sh", None);
+            k.set_current(pid);
+        });
+        let run = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = prog_netperm(&args, "", &mut stdout, &mut stderr);
+            (code, stdout, stderr)
+        };
+        let set_euid =
+            |uid| KERNEL.with(|k| k.borrow_mut().current_process_mut().unwrap().euid = uid);
+
+        assert_eq!(run(&[]).1, "No network permissions\n");
+        assert_eq!(
+            syscall::net_check("https://exfil.test/upload"),
+            Err(syscall::SyscallError::WouldBlock)
+        );
+        assert!(
+            run(&["list"])
+                .1
+                .contains("ask    https://exfil.test (1 request")
+        );
+
+        // Only root decides
+        set_euid(Uid(1000));
+        assert!(run(&["deny", "exfil.test"]).2.contains("ermission denied"));
+        set_euid(Uid::ROOT);
+        assert_eq!(run(&["deny", "exfil.test"]).0, 0);
+        assert_eq!(run(&["allow", "HTTP://Mirror.test:80/x"]).0, 0);
+        assert_eq!(
+            run(&[]).1,
+            "allow  http://mirror.test\ndeny   https://exfil.test\n"
+        );
+        assert_eq!(
+            syscall::read_file(netperm::ALLOWLIST_PATH).unwrap(),
+            "# Network permissions (see netperm)\nallow http://mirror.test\ndeny https://exfil.test\n"
+        );
+
+        assert_eq!(run(&["revoke", "exfil.test"]).0, 0);
+        assert!(run(&["revoke", "exfil.test"]).2.contains("exfil.test"));
+        assert_eq!(run(&["allow"]).0, 1);
+        assert!(run(&["allow", "https://"]).2.contains("not an origin"));
+    }
+
+    #[test]
+    fn test_offline() {
+        use crate::kernel::httpcache::CachedResponse;
+        use crate::kernel::syscall::{KERNEL, Kernel};
+        use std::collections::HashMap;
+
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("sh", None);
+            k.set_current(pid);
+        });
+        let run = |prog: fn(&[String], &str, &mut String, &mut String) -> i32, args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = prog(&args, "", &mut stdout, &mut stderr);
+            (code, stdout, stderr)
+        };
+
+        assert_eq!(
+            run(prog_offline, &[]).1,
+            "Offline mode: off\nHTTP cache: 0 responses, 0 bytes in /var/cache/http\n"
+        );
+        let url = "https://pkg.test/notes.txt";
+        let mut headers = HashMap::new();
+        headers.insert("content-type".to_string(), "text/plain".to_string());
+        let cached =
+            CachedResponse::from_response(url, 200, &headers, b"cached notes".to_vec(), 0.0);
+        syscall::http_cache_put(&cached.unwrap()).unwrap();
+
+        assert_eq!(run(prog_offline, &["on"]).0, 0);
+        assert!(
+            run(prog_offline, &["status"])
+                .1
+                .starts_with("Offline mode: on\nHTTP cache: 1 response,")
+        );
+        let (code, _, stderr) = run(prog_curl, &[url]);
+        assert_eq!(code, 7);
+        assert!(stderr.contains("offline mode is on"));
+        assert!(stderr.contains("--cached"));
+        assert_eq!(run(prog_curl, &["--cached", url]).1, "cached notes");
+        assert_eq!(
+            run(prog_curl, &["-i", "--cached", url]).1,
+            "HTTP/1.1 200 OK\nx-cache: HIT\ncontent-type: text/plain\n\ncached notes"
+        );
+        assert!(
+            run(prog_curl, &["--cached", "https://pkg.test/other"])
+                .2
+                .contains("nothing cached")
+        );
+
+        let (code, stdout, _) = run(prog_wget, &["-O", "/tmp/notes.txt", url]);
+        assert_eq!(code, 0);
+        assert!(stdout.contains("from the cache (12 bytes)"));
+        assert_eq!(
+            syscall::read_file("/tmp/notes.txt").unwrap(),
+            "cached notes"
+        );
+        assert!(
+            run(prog_wget, &["https://pkg.test/other"])
+                .2
+                .contains("isn't cached")
+        );
+
+        assert_eq!(run(prog_offline, &["off"]).0, 0);
+        assert!(!syscall::offline());
+        assert_eq!(run(prog_offline, &["sideways"]).0, 1);
+    }
 }
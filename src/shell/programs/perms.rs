@@ -1,57 +1,317 @@
 //! Permission management programs
 
-use super::args_to_strs;
-use crate::kernel::syscall;
+use super::absolute;
+use crate::kernel::syscall::{self, FileMetadata, SyscallError, SyscallResult};
+use crate::kernel::users::{FileMode, Gid, Uid};
+use crate::shell::argparse::{Arg, ArgKind, CommandSpec, Flag};
+use crate::vfs::acl::{self, ACL_XATTR, Acl, AclTag};
 
-/// chmod - change file permissions
-pub fn prog_chmod(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
+/// Flags `chmod`, `chown` and `chgrp` share
+struct Options {
+    recursive: bool,
+    verbose: bool,
+    /// Refuse to recurse from `/`, unless `--no-preserve-root`
+    preserve_root: bool,
+}
 
-    if args.len() < 2 || args.first().map(|s| s.as_ref()) == Some("--help") {
-        stdout.push_str("Usage: chmod MODE FILE...\nChange file permissions.\n\n");
-        stdout.push_str("MODE can be:\n");
-        stdout.push_str("  Octal: 755, 644, etc.\n");
-        stdout.push_str("  Symbolic: u+x, go-w, a=r, etc.\n");
-        return if args.is_empty() { 0 } else { 1 };
+/// The [`Options`] flags, for help; [`parse_options`] reads them
+const OPTION_FLAGS: &[Flag] = &[
+    Flag::new(
+        'R',
+        "recursive",
+        "Change directories and everything below them",
+    ),
+    Flag::new('v', "verbose", "Report each file processed"),
+    Flag::long("preserve-root", "Refuse -R on / (the default)"),
+    Flag::long("no-preserve-root", "Allow -R on /"),
+];
+
+/// Split the flags off `args`, leaving the operands
+///
+/// With `modes`, an argument such as `-w` that isn't a flag is taken as
+/// a symbolic mode.
+fn parse_options<'a>(
+    name: &str,
+    args: &[&'a str],
+    modes: bool,
+    stderr: &mut String,
+) -> Option<(Options, Vec<&'a str>)> {
+    let mut opts = Options {
+        recursive: false,
+        verbose: false,
+        preserve_root: true,
+    };
+    let mut operands = Vec::new();
+    let mut args = args.iter().copied();
+    while let Some(arg) = args.next() {
+        match arg {
+            "--" => operands.extend(args.by_ref()),
+            "--recursive" => opts.recursive = true,
+            "--verbose" => opts.verbose = true,
+            "--preserve-root" => opts.preserve_root = true,
+            "--no-preserve-root" => opts.preserve_root = false,
+            _ if arg.starts_with("--") => {
+                stderr.push_str(&format!("{}: unrecognized option '{}'\n", name, arg));
+                return None;
+            }
+            _ if arg.len() > 1 && arg.starts_with('-') => {
+                if modes && !arg[1..].contains(['R', 'v']) {
+                    operands.push(arg);
+                    continue;
+                }
+                for c in arg[1..].chars() {
+                    match c {
+                        'R' => opts.recursive = true,
+                        'v' => opts.verbose = true,
+                        _ => {
+                            stderr.push_str(&format!("{}: invalid option -- '{}'\n", name, c));
+                            return None;
+                        }
+                    }
+                }
+            }
+            _ => operands.push(arg),
+        }
     }
+    Some((opts, operands))
+}
 
-    let mode_str = &args[0];
-    let mode = if let Ok(octal) = u16::from_str_radix(mode_str, 8) {
-        octal
-    } else {
-        // Parse symbolic mode (simplified)
+/// Call `change` on `path` and, with -R, on everything below it, without
+/// following symbolic links; false if anything failed
+///
+/// `change` is told whether the path was named on the command line.
+fn change_tree(
+    name: &str,
+    path: &str,
+    opts: &Options,
+    stderr: &mut String,
+    change: &mut dyn FnMut(&str, &FileMetadata, bool) -> SyscallResult<()>,
+) -> bool {
+    if opts.recursive && opts.preserve_root && absolute(path) == "/" {
         stderr.push_str(&format!(
-            "chmod: invalid mode: '{}' (use octal for now)\n",
-            mode_str
+            "{0}: it is dangerous to operate recursively on '/'\n\
+             {0}: use --no-preserve-root to override this failsafe\n",
+            name
         ));
+        return false;
+    }
+    change_below(name, path, true, opts, stderr, change)
+}
+
+fn change_below(
+    name: &str,
+    path: &str,
+    named: bool,
+    opts: &Options,
+    stderr: &mut String,
+    change: &mut dyn FnMut(&str, &FileMetadata, bool) -> SyscallResult<()>,
+) -> bool {
+    let mut report = |e: syscall::SyscallError| {
+        stderr.push_str(&format!("{}: {}: {}\n", name, path, e));
+        false
+    };
+    let meta = match syscall::metadata(path) {
+        Ok(meta) => meta,
+        Err(e) => return report(e),
+    };
+    let mut ok = match change(path, &meta, named) {
+        Ok(()) => true,
+        Err(e) => report(e),
+    };
+    if opts.recursive && meta.is_dir && !meta.is_symlink {
+        let mut entries = match syscall::readdir(path) {
+            Ok(entries) => entries,
+            Err(e) => return report(e),
+        };
+        entries.sort();
+        for entry in entries {
+            let child = if path.ends_with('/') {
+                format!("{}{}", path, entry)
+            } else {
+                format!("{}/{}", path, entry)
+            };
+            ok &= change_below(name, &child, false, opts, stderr, change);
+        }
+    }
+    ok
+}
+
+/// `0755 (rwxr-xr-x)`, as `chmod -v` shows a mode
+fn describe_mode(mode: u16) -> String {
+    let mode = FileMode::new(mode);
+    format!("{} ({})", mode, mode.to_symbolic())
+}
+
+/// `user:group`, by name where they have one
+fn describe_owner(uid: u32, gid: u32) -> String {
+    let user = syscall::get_user_by_uid(Uid(uid)).map_or_else(|| uid.to_string(), |u| u.name);
+    let group = syscall::get_group_by_gid(Gid(gid)).map_or_else(|| gid.to_string(), |g| g.name);
+    format!("{}:{}", user, group)
+}
+
+pub static CHMOD: CommandSpec = CommandSpec {
+    name: "chmod",
+    summary: "change file permissions",
+    description: "Change the mode of each FILE to MODE. MODE is octal (755, 644, etc.) or symbolic (u+x, go-w, a=r, u+rwx,g-w,o=r, etc.). With no arguments, show this help.",
+    flags: OPTION_FLAGS,
+    // Raw, as a mode such as -w looks like a flag; parse_options sorts
+    // the flags from the operands
+    args: &[
+        Arg::new("MODE", ArgKind::Text).raw(),
+        Arg::new("FILE", ArgKind::Path).repeated(),
+    ],
+};
+
+/// chmod - change file permissions
+pub fn prog_chmod(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    if args.is_empty() {
+        stdout.push_str(&CHMOD.help());
+        stdout.push('\n');
+        return 0;
+    }
+    let m = match CHMOD.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&CHMOD, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
+
+    let Some((opts, operands)) = parse_options("chmod", &args, true, stderr) else {
         return 1;
     };
+    let Some((&mode_str, paths)) = operands.split_first() else {
+        stderr.push_str("chmod: missing operand\n");
+        return 1;
+    };
+    let octal = u16::from_str_radix(mode_str, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777);
+    if octal.is_none()
+        && FileMode::new(0)
+            .apply_symbolic(mode_str, false, 0)
+            .is_none()
+    {
+        stderr.push_str(&format!("chmod: invalid mode: '{}'\n", mode_str));
+        return 1;
+    }
+    if paths.is_empty() {
+        stderr.push_str(&format!("chmod: missing operand after '{}'\n", mode_str));
+        return 1;
+    }
+    let umask = syscall::umask(0).unwrap_or(0o022);
+    let _ = syscall::umask(umask);
+
+    let mut change = |path: &str, meta: &FileMetadata, named: bool| {
+        // Links met on the way down have no mode of their own to change
+        if meta.is_symlink && !named {
+            return Ok(());
+        }
+        let mode = match octal {
+            Some(mode) => mode,
+            None => FileMode::new(meta.mode)
+                .apply_symbolic(mode_str, meta.is_dir, umask)
+                .map_or(meta.mode, |mode| mode.0),
+        };
+        syscall::chmod(path, mode)?;
+        if opts.verbose {
+            let old = meta.mode & 0o7777;
+            if old == mode {
+                stdout.push_str(&format!(
+                    "mode of '{}' retained as {}\n",
+                    path,
+                    describe_mode(old)
+                ));
+            } else {
+                stdout.push_str(&format!(
+                    "mode of '{}' changed from {} to {}\n",
+                    path,
+                    describe_mode(old),
+                    describe_mode(mode)
+                ));
+            }
+        }
+        Ok(())
+    };
 
     let mut errors = 0;
-    for path in &args[1..] {
-        match syscall::chmod(path, mode) {
-            Ok(()) => {}
-            Err(e) => {
-                stderr.push_str(&format!("chmod: {}: {}\n", path, e));
-                errors += 1;
+    for path in paths {
+        if !change_tree("chmod", path, &opts, stderr, &mut change) {
+            errors += 1;
+        }
+    }
+
+    if errors > 0 { 1 } else { 0 }
+}
+
+/// Change the owner and group of each of `paths`, either left as is
+/// when `None`
+fn change_owners(
+    name: &str,
+    paths: &[&str],
+    uid: Option<u32>,
+    gid: Option<u32>,
+    opts: &Options,
+    stdout: &mut String,
+    stderr: &mut String,
+) -> i32 {
+    let mut change = |path: &str, meta: &FileMetadata, _named: bool| {
+        syscall::chown(path, uid, gid)?;
+        if opts.verbose {
+            let old = describe_owner(meta.uid, meta.gid);
+            let new = describe_owner(uid.unwrap_or(meta.uid), gid.unwrap_or(meta.gid));
+            if old == new {
+                stdout.push_str(&format!("ownership of '{}' retained as {}\n", path, old));
+            } else {
+                stdout.push_str(&format!(
+                    "changed ownership of '{}' from {} to {}\n",
+                    path, old, new
+                ));
             }
         }
+        Ok(())
+    };
+
+    let mut errors = 0;
+    for path in paths {
+        if !change_tree(name, path, opts, stderr, &mut change) {
+            errors += 1;
+        }
     }
 
     if errors > 0 { 1 } else { 0 }
 }
 
+pub static CHOWN: CommandSpec = CommandSpec {
+    name: "chown",
+    summary: "change file owner and group",
+    description: "Change the owner of each FILE to OWNER, and its group to GROUP when given. With no arguments, show this help.",
+    flags: OPTION_FLAGS,
+    // Raw, so parse_options reads the flags as it does for chmod
+    args: &[
+        Arg::new("[OWNER][:GROUP]", ArgKind::Text).raw(),
+        Arg::new("FILE", ArgKind::Path).repeated(),
+    ],
+};
+
 /// chown - change file owner
 pub fn prog_chown(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if args.len() < 2 || args.first().map(|s| s.as_ref()) == Some("--help") {
-        stdout.push_str("Usage: chown [OWNER][:GROUP] FILE...\nChange file owner and group.\n");
-        return if args.is_empty() { 0 } else { 1 };
+    if args.is_empty() {
+        stdout.push_str(&CHOWN.help());
+        stdout.push('\n');
+        return 0;
     }
+    let m = match CHOWN.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&CHOWN, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
 
-    let owner_str = &args[0];
-
+    let Some((opts, operands)) = parse_options("chown", &args, false, stderr) else {
+        return 1;
+    };
+    let Some((owner_str, paths)) = operands.split_first() else {
+        stderr.push_str("chown: missing operand\n");
+        return 1;
+    };
     // Parse owner:group or owner.group or just owner
     let (uid, gid) = if owner_str.contains(':') || owner_str.contains('.') {
         let sep = if owner_str.contains(':') { ':' } else { '.' };
@@ -94,30 +354,46 @@ pub fn prog_chown(args: &[String], __stdin: &str, stdout: &mut String, stderr: &
         (uid, None)
     };
 
-    let mut errors = 0;
-    for path in &args[1..] {
-        match syscall::chown(path, uid, gid) {
-            Ok(()) => {}
-            Err(e) => {
-                stderr.push_str(&format!("chown: {}: {}\n", path, e));
-                errors += 1;
-            }
-        }
+    if paths.is_empty() {
+        stderr.push_str(&format!("chown: missing operand after '{}'\n", owner_str));
+        return 1;
     }
 
-    if errors > 0 { 1 } else { 0 }
+    change_owners("chown", paths, uid, gid, &opts, stdout, stderr)
 }
 
+pub static CHGRP: CommandSpec = CommandSpec {
+    name: "chgrp",
+    summary: "change file group",
+    description: "Change the group of each FILE to GROUP. With no arguments, show this help.",
+    flags: OPTION_FLAGS,
+    // Raw, so parse_options reads the flags as it does for chmod
+    args: &[
+        Arg::new("GROUP", ArgKind::Text).raw(),
+        Arg::new("FILE", ArgKind::Path).repeated(),
+    ],
+};
+
 /// chgrp - change file group
 pub fn prog_chgrp(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if args.len() < 2 || args.first().map(|s| s.as_ref()) == Some("--help") {
-        stdout.push_str("Usage: chgrp GROUP FILE...\nChange file group.\n");
-        return if args.is_empty() { 0 } else { 1 };
+    if args.is_empty() {
+        stdout.push_str(&CHGRP.help());
+        stdout.push('\n');
+        return 0;
     }
+    let m = match CHGRP.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&CHGRP, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
 
-    let group_str = &args[0];
+    let Some((opts, operands)) = parse_options("chgrp", &args, false, stderr) else {
+        return 1;
+    };
+    let Some((group_str, paths)) = operands.split_first() else {
+        stderr.push_str("chgrp: missing operand\n");
+        return 1;
+    };
     let gid = if let Ok(n) = group_str.parse::<u32>() {
         n
     } else if let Some(group) = syscall::get_group_by_name(group_str) {
@@ -126,19 +402,248 @@ pub fn prog_chgrp(args: &[String], __stdin: &str, stdout: &mut String, stderr: &
         stderr.push_str(&format!("chgrp: invalid group: '{}'\n", group_str));
         return 1;
     };
+    if paths.is_empty() {
+        stderr.push_str(&format!("chgrp: missing operand after '{}'\n", group_str));
+        return 1;
+    }
 
-    let mut errors = 0;
-    for path in &args[1..] {
-        match syscall::chown(path, None, Some(gid)) {
-            Ok(()) => {}
+    change_owners("chgrp", paths, None, Some(gid), &opts, stdout, stderr)
+}
+
+pub static GETFACL: CommandSpec = CommandSpec {
+    name: "getfacl",
+    summary: "get file access control lists",
+    description: "Print each FILE's owner and group and its access control list: the owner, group and other permissions of its mode, with an entry for each user and group the list names.",
+    flags: &[],
+    args: &[Arg::new("FILE", ArgKind::Path).repeated()],
+};
+
+/// The ACL of `path`; an empty one if it has none
+fn read_acl(path: &str) -> SyscallResult<Acl> {
+    match syscall::getxattr(path, ACL_XATTR) {
+        Ok(value) => Ok(Acl::decode(&value).unwrap_or_default()),
+        Err(SyscallError::NoAttribute) => Ok(Acl::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// getfacl - print access control lists
+pub fn prog_getfacl(
+    args: &[String],
+    _stdin: &str,
+    stdout: &mut String,
+    stderr: &mut String,
+) -> i32 {
+    let m = match GETFACL.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&GETFACL, stdout, stderr),
+    };
+    let user_name =
+        |uid: u32| syscall::get_user_by_uid(Uid(uid)).map_or(uid.to_string(), |u| u.name);
+    let group_name =
+        |gid: u32| syscall::get_group_by_gid(Gid(gid)).map_or(gid.to_string(), |g| g.name);
+
+    let mut code = 0;
+    for path in &m.operands {
+        let (meta, list) = match syscall::metadata(path).and_then(|m| Ok((m, read_acl(path)?))) {
+            Ok(found) => found,
             Err(e) => {
-                stderr.push_str(&format!("chgrp: {}: {}\n", path, e));
-                errors += 1;
+                stderr.push_str(&format!("getfacl: {}: {}\n", path, e));
+                code = 1;
+                continue;
             }
+        };
+        let bits = |shift: u16| acl::perms_string(((meta.mode >> shift) & 0o7) as u8);
+        let mut out = format!(
+            "# file: {}\n# owner: {}\n# group: {}\nuser::{}\n",
+            path,
+            user_name(meta.uid),
+            group_name(meta.gid),
+            bits(6)
+        );
+        // Entries the mask cuts back show what they grant, as on Linux
+        let perms = |perms: u8| match list.effective(perms) {
+            granted if granted != perms => format!(
+                "{}\t#effective:{}",
+                acl::perms_string(perms),
+                acl::perms_string(granted)
+            ),
+            _ => acl::perms_string(perms),
+        };
+        let entries = list.entries();
+        for entry in entries {
+            if let AclTag::User(uid) = entry.tag {
+                out.push_str(&format!("user:{}:{}\n", user_name(uid), perms(entry.perms)));
+            }
+        }
+        out.push_str(&format!("group::{}\n", bits(3)));
+        for entry in entries {
+            if let AclTag::Group(gid) = entry.tag {
+                out.push_str(&format!(
+                    "group:{}:{}\n",
+                    group_name(gid),
+                    perms(entry.perms)
+                ));
+            }
+        }
+        if let Some(mask) = list.mask() {
+            out.push_str(&format!("mask::{}\n", acl::perms_string(mask)));
         }
+        out.push_str(&format!("other::{}\n\n", bits(0)));
+        stdout.push_str(&out);
     }
+    code
+}
 
-    if errors > 0 { 1 } else { 0 }
+pub static SETFACL: CommandSpec = CommandSpec {
+    name: "setfacl",
+    summary: "set file access control lists",
+    description: "Change each FILE's access control list. ENTRIES is a comma-separated list of u:USER:PERMS and g:GROUP:PERMS entries, with PERMS like rw- or 6; u::PERMS, g::PERMS and o::PERMS change the mode's owner, group and other bits instead. -b runs first, then -x, then -m. Only the owner and root may change a file's list.",
+    flags: &[
+        Flag::short('m', "Add or change ENTRIES").value("ENTRIES"),
+        Flag::short(
+            'x',
+            "Remove the entries for the users and groups in ENTRIES",
+        )
+        .value("ENTRIES"),
+        Flag::short('b', "Remove every entry"),
+    ],
+    args: &[Arg::new("FILE", ArgKind::Path).repeated()],
+};
+
+/// One entry of a `setfacl` list: a named user or group, or a class of
+/// the mode (`u::`, `g::`, `o::`) by its bit shift
+#[derive(Clone, Copy)]
+enum FaclTarget {
+    Named(AclTag),
+    Mode(u16),
+}
+
+/// Parse `u:NAME[:PERMS]` / `g:NAME[:PERMS]` / `o::[PERMS]`, with `user`,
+/// `group` and `other` spelled out too; PERMS must be given if `perms`
+fn parse_facl_entry(entry: &str, perms: bool) -> Result<(FaclTarget, u8), String> {
+    let invalid = || format!("invalid entry '{}'", entry);
+    let mut fields = entry.split(':');
+    let (kind, name) = (
+        fields.next().ok_or_else(invalid)?,
+        fields.next().ok_or_else(invalid)?,
+    );
+    let bits = match (fields.next(), perms) {
+        (Some(text), true) => acl::parse_perms(text).ok_or_else(invalid)?,
+        (None, false) => 0,
+        _ => return Err(invalid()),
+    };
+    if fields.next().is_some() {
+        return Err(invalid());
+    }
+    let target = match (kind, name) {
+        ("u" | "user", "") => FaclTarget::Mode(6),
+        ("g" | "group", "") => FaclTarget::Mode(3),
+        ("o" | "other", "") => FaclTarget::Mode(0),
+        ("u" | "user", name) => {
+            let uid = match name.parse() {
+                Ok(uid) => uid,
+                Err(_) => {
+                    syscall::get_user_by_name(name)
+                        .ok_or_else(|| format!("no such user '{}'", name))?
+                        .uid
+                        .0
+                }
+            };
+            FaclTarget::Named(AclTag::User(uid))
+        }
+        ("g" | "group", name) => {
+            let gid = match name.parse() {
+                Ok(gid) => gid,
+                Err(_) => {
+                    syscall::get_group_by_name(name)
+                        .ok_or_else(|| format!("no such group '{}'", name))?
+                        .gid
+                        .0
+                }
+            };
+            FaclTarget::Named(AclTag::Group(gid))
+        }
+        _ => return Err(invalid()),
+    };
+    Ok((target, bits))
+}
+
+/// setfacl - change access control lists
+pub fn prog_setfacl(
+    args: &[String],
+    _stdin: &str,
+    stdout: &mut String,
+    stderr: &mut String,
+) -> i32 {
+    let m = match SETFACL.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&SETFACL, stdout, stderr),
+    };
+    let parse = |list: Option<&str>, perms: bool| -> Result<Vec<(FaclTarget, u8)>, String> {
+        list.into_iter()
+            .flat_map(|list| list.split(','))
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| parse_facl_entry(entry, perms))
+            .collect()
+    };
+    let (remove, modify) = match (parse(m.value("x"), false), parse(m.value("m"), true)) {
+        (Ok(remove), Ok(modify)) => (remove, modify),
+        (Err(e), _) | (_, Err(e)) => {
+            stderr.push_str(&format!("setfacl: {}\n", e));
+            return 1;
+        }
+    };
+    if !m.flag("b") && remove.is_empty() && modify.is_empty() {
+        stderr.push_str(&format!("{}\n", SETFACL.usage()));
+        return 1;
+    }
+
+    let mut code = 0;
+    for path in &m.operands {
+        if let Err(e) = set_facl(path, m.flag("b"), &remove, &modify) {
+            stderr.push_str(&format!("setfacl: {}: {}\n", path, e));
+            code = 1;
+        }
+    }
+    code
+}
+
+/// Apply `setfacl`'s changes to `path`
+fn set_facl(
+    path: &str,
+    clear: bool,
+    remove: &[(FaclTarget, u8)],
+    modify: &[(FaclTarget, u8)],
+) -> SyscallResult<()> {
+    let mut list = if clear { Acl::new() } else { read_acl(path)? };
+    let mode = syscall::metadata(path)?.mode;
+    let mut new_mode = mode;
+    for &(target, _) in remove {
+        if let FaclTarget::Named(tag) = target {
+            list.remove(tag);
+        }
+    }
+    for &(target, perms) in modify {
+        match target {
+            FaclTarget::Named(tag) => list.set(tag, perms),
+            FaclTarget::Mode(shift) => {
+                new_mode = (new_mode & !(0o7 << shift)) | (u16::from(perms) << shift)
+            }
+        }
+    }
+
+    if new_mode != mode {
+        syscall::chmod(path, new_mode)?;
+    }
+    list.recalculate_mask(((new_mode >> 3) & 0o7) as u8);
+    if !list.is_empty() {
+        return syscall::setxattr(path, ACL_XATTR, &list.encode());
+    }
+    match syscall::removexattr(path, ACL_XATTR) {
+        Err(SyscallError::NoAttribute) => Ok(()),
+        result => result,
+    }
 }
 
 #[cfg(test)]
@@ -151,7 +656,7 @@ mod tests {
         let mut stdout = String::new();
         let mut stderr = String::new();
         let result = prog_chmod(&args, "", &mut stdout, &mut stderr);
-        assert_eq!(result, 1);
+        assert_eq!(result, 0);
         assert!(stdout.contains("Usage: chmod"));
         assert!(stdout.contains("MODE"));
     }
@@ -182,7 +687,7 @@ mod tests {
         let mut stdout = String::new();
         let mut stderr = String::new();
         let result = prog_chown(&args, "", &mut stdout, &mut stderr);
-        assert_eq!(result, 1);
+        assert_eq!(result, 0);
         assert!(stdout.contains("Usage: chown"));
         assert!(stdout.contains("OWNER"));
     }
@@ -203,7 +708,7 @@ mod tests {
         let mut stdout = String::new();
         let mut stderr = String::new();
         let result = prog_chgrp(&args, "", &mut stdout, &mut stderr);
-        assert_eq!(result, 1);
+        assert_eq!(result, 0);
         assert!(stdout.contains("Usage: chgrp"));
         assert!(stdout.contains("GROUP"));
     }
@@ -217,4 +722,164 @@ mod tests {
         assert_eq!(result, 0);
         assert!(stdout.contains("Usage: chgrp"));
     }
+
+    fn setup() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+    }
+
+    fn run(
+        prog: fn(&[String], &str, &mut String, &mut String) -> i32,
+        args: &[&str],
+    ) -> (i32, String, String) {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        let (mut stdout, mut stderr) = (String::new(), String::new());
+        let code = prog(&args, "", &mut stdout, &mut stderr);
+        (code, stdout, stderr)
+    }
+
+    fn mode(path: &str) -> u16 {
+        syscall::metadata(path).unwrap().mode & 0o7777
+    }
+
+    #[test]
+    fn test_chmod_symbolic() {
+        setup();
+        syscall::write_file("/tmp/f", "").unwrap();
+        syscall::chmod("/tmp/f", 0o777).unwrap();
+
+        assert_eq!(run(prog_chmod, &["u+rwx,g-w,o=r", "/tmp/f"]).0, 0);
+        assert_eq!(mode("/tmp/f"), 0o754);
+        // A mode starting with '-' isn't a flag
+        assert_eq!(run(prog_chmod, &["-x", "/tmp/f"]).0, 0);
+        assert_eq!(mode("/tmp/f"), 0o644);
+        assert_eq!(run(prog_chmod, &["u+s,+t", "/tmp/f"]).0, 0);
+        assert_eq!(mode("/tmp/f"), 0o5644);
+
+        let (code, stdout, _) = run(prog_chmod, &["-v", "640", "/tmp/f"]);
+        assert_eq!(code, 0);
+        assert_eq!(
+            stdout,
+            "mode of '/tmp/f' changed from 5644 (rwSr--r-T) to 0640 (rw-r-----)\n"
+        );
+        let (_, stdout, _) = run(prog_chmod, &["-v", "u=rw", "/tmp/f"]);
+        assert!(stdout.contains("retained as 0640"));
+
+        let (code, _, stderr) = run(prog_chmod, &["u+q", "/tmp/f"]);
+        assert_eq!(code, 1);
+        assert!(stderr.contains("invalid mode: 'u+q'"));
+        assert_eq!(mode("/tmp/f"), 0o640);
+    }
+
+    #[test]
+    fn test_chmod_recursive() {
+        setup();
+        syscall::mkdir("/tmp/proj").unwrap();
+        syscall::mkdir("/tmp/proj/src").unwrap();
+        syscall::write_file("/tmp/proj/src/main.rs", "").unwrap();
+        syscall::write_file("/tmp/proj/run.sh", "").unwrap();
+        syscall::chmod("/tmp/proj/run.sh", 0o744).unwrap();
+        syscall::write_file("/tmp/outside", "").unwrap();
+        syscall::symlink("/tmp/outside", "/tmp/proj/link").unwrap();
+
+        assert_eq!(run(prog_chmod, &["-R", "go-rwx,a+X", "/tmp/proj"]).0, 0);
+        assert_eq!(mode("/tmp/proj"), 0o711);
+        assert_eq!(mode("/tmp/proj/src"), 0o711);
+        assert_eq!(mode("/tmp/proj/src/main.rs"), 0o600);
+        assert_eq!(mode("/tmp/proj/run.sh"), 0o711);
+        // Links below aren't followed
+        assert_eq!(mode("/tmp/outside"), 0o644);
+
+        // Without -R only the directory changes
+        assert_eq!(run(prog_chmod, &["700", "/tmp/proj"]).0, 0);
+        assert_eq!(mode("/tmp/proj/src"), 0o711);
+
+        let (code, _, stderr) = run(prog_chmod, &["-R", "755", "/"]);
+        assert_eq!(code, 1);
+        assert!(stderr.contains("dangerous to operate recursively on '/'"));
+        assert_eq!(run(prog_chmod, &["-R", "755", "/tmp/proj/../.."]).0, 1);
+    }
+
+    #[test]
+    fn test_chown_recursive() {
+        use crate::kernel::syscall::KERNEL;
+        setup();
+        KERNEL.with(|k| k.borrow_mut().current_process_mut().unwrap().euid = Uid::ROOT);
+        syscall::mkdir("/tmp/share").unwrap();
+        syscall::mkdir("/tmp/share/docs").unwrap();
+        syscall::write_file("/tmp/share/docs/a.txt", "").unwrap();
+        let user = syscall::get_user_by_name("user").unwrap();
+        let owner = |path: &str| {
+            let meta = syscall::metadata(path).unwrap();
+            (meta.uid, meta.gid)
+        };
+
+        let (code, stdout, stderr) = run(prog_chown, &["-Rv", "user:user", "/tmp/share"]);
+        assert_eq!(code, 0, "{}", stderr);
+        assert_eq!(stdout.matches(" to user:user\n").count(), 3);
+        for path in ["/tmp/share", "/tmp/share/docs", "/tmp/share/docs/a.txt"] {
+            assert_eq!(owner(path), (user.uid.0, user.gid.0));
+            assert!(stdout.contains(&format!("changed ownership of '{}' from ", path)));
+        }
+
+        assert_eq!(run(prog_chgrp, &["-R", "0", "/tmp/share"]).0, 0);
+        assert_eq!(owner("/tmp/share/docs/a.txt"), (user.uid.0, 0));
+
+        let (code, _, stderr) = run(prog_chown, &["-R", "root", "/"]);
+        assert_eq!(code, 1);
+        assert!(stderr.contains("--no-preserve-root"));
+        let (code, _, stderr) = run(prog_chown, &["-x", "root", "/tmp/share"]);
+        assert_eq!(code, 1);
+        assert!(stderr.contains("invalid option -- 'x'"));
+    }
+
+    #[test]
+    fn test_getfacl_setfacl() {
+        use crate::kernel::syscall::KERNEL;
+        setup();
+        KERNEL.with(|k| k.borrow_mut().current_process_mut().unwrap().euid = Uid::ROOT);
+        syscall::write_file("/tmp/plan.txt", "").unwrap();
+        syscall::chmod("/tmp/plan.txt", 0o640).unwrap();
+
+        let (code, _, stderr) = run(
+            prog_setfacl,
+            &["-m", "u:user:rw,g:root:r-x,o::r", "/tmp/plan.txt"],
+        );
+        assert_eq!(code, 0, "{}", stderr);
+        assert_eq!(mode("/tmp/plan.txt"), 0o644);
+        let (code, stdout, _) = run(prog_getfacl, &["/tmp/plan.txt"]);
+        assert_eq!(code, 0);
+        assert!(stdout.ends_with(
+            "user::rw-\nuser:user:rw-\ngroup::r--\ngroup:root:r-x\nmask::rwx\nother::r--\n\n"
+        ));
+        // chmod narrows the mask, which caps the named entries
+        syscall::chmod("/tmp/plan.txt", 0o644).unwrap();
+        let (_, stdout, _) = run(prog_getfacl, &["/tmp/plan.txt"]);
+        assert!(stdout.contains("user:user:rw-\t#effective:r--\n"));
+        assert!(stdout.contains("mask::r--\n"));
+        assert!(stdout.starts_with("# file: /tmp/plan.txt\n# owner: root\n"));
+
+        assert_eq!(run(prog_setfacl, &["-x", "g:root", "/tmp/plan.txt"]).0, 0);
+        let acl = read_acl("/tmp/plan.txt").unwrap();
+        assert_eq!(acl.entries().len(), 1);
+        assert_eq!(acl.get(AclTag::User(0)), None);
+
+        assert_eq!(run(prog_setfacl, &["-b", "/tmp/plan.txt"]).0, 0);
+        assert_eq!(
+            syscall::getxattr("/tmp/plan.txt", ACL_XATTR),
+            Err(SyscallError::NoAttribute)
+        );
+
+        let (code, _, stderr) = run(prog_setfacl, &["-m", "u:nobody-here:r", "/tmp/plan.txt"]);
+        assert_eq!(code, 1);
+        assert!(stderr.contains("no such user 'nobody-here'"));
+        let (code, _, stderr) = run(prog_setfacl, &["-m", "u:user", "/tmp/plan.txt"]);
+        assert_eq!(code, 1);
+        assert!(stderr.contains("invalid entry 'u:user'"));
+    }
 }
@@ -4,25 +4,30 @@
 //!
 //! # Commands
 //!
-//! - `pkg install <name>[@version]` - Install a package from registry
+//! - `pkg install [-j N] <name>[@version]` - Install a package from registry
 //! - `pkg install-local <path>` - Install from local .axepkg file
 //! - `pkg remove <name>` - Remove an installed package
 //! - `pkg list` - List installed packages
 //! - `pkg info <name>` - Show package information
 //! - `pkg search <query>` - Search for packages
 //! - `pkg update` - Update registry index
-//! - `pkg upgrade` - Upgrade all packages
+//! - `pkg upgrade [-j N]` - Upgrade all packages
 //! - `pkg verify` - Verify installed packages
 //! - `pkg clean` - Clean package cache
 //! - `pkg init` - Initialize package directories
+//! - `pkg new <name> [dir]` - Scaffold a new WASM command project
+//! - `pkg system-upgrade [file]` - Stage a new system image for the next boot
+//! - `pkg system-status` - Show the running system image and update state
+//! - `pkg system-rollback` - Boot the other system slot next time
 
-use super::{args_to_strs, check_help};
-use crate::kernel::pkg::{PackageDatabase, PackageManager};
+use crate::kernel::pkg::{PackageDatabase, PackageManager, ProjectTemplate};
 use crate::kernel::syscall;
+use crate::shell::argparse::{Arg, ArgKind, CommandSpec, Flag, Matches};
 
-const HELP_TEXT: &str = r#"Usage: pkg <command> [args]
-
-WASM Package Manager for axeberg.
+pub static PKG: CommandSpec = CommandSpec {
+    name: "pkg",
+    summary: "WASM package manager",
+    description: r#"WASM Package Manager for axeberg.
 
 Commands:
   install <name>[@version]   Install a package from registry
@@ -36,52 +41,138 @@ Commands:
   verify                     Verify installed package integrity
   clean                      Clean package cache
   init                       Initialize package directories
+  new <name> [dir]           Create a new WASM command project
+  system-upgrade [file]      Stage a new system image for the next boot
+  system-status              Show the system image and update state
+  system-rollback            Boot the other system slot next time
 
-Options:
-  -h, --help                 Show this help message
-  -v, --version              Show version information
+install and upgrade take -j N (or --jobs N) to download N packages at
+once (default 4).
 
 Examples:
   pkg install hello          Install latest version of 'hello'
   pkg install hello@1.0.0    Install specific version
+  pkg upgrade -j 8           Upgrade, downloading eight packages at once
   pkg install-local ./my.axepkg  Install from local file
   pkg remove hello           Remove 'hello' package
   pkg list                   Show all installed packages
-
-Note: Some commands (search, update, upgrade) require network access
-and are only available in WASM builds."#;
+  pkg new greet              Scaffold ./greet as a command crate
+  pkg system-upgrade         Download and stage the latest system image
+
+Some commands (search, update, upgrade, and system-upgrade without a file)
+require network access and are only available in WASM builds. Packages
+download in parallel and install in dependency order, with a progress bar
+for each shown above the prompt. Staging and rolling back the system image
+require root."#,
+    flags: &[Flag::new('v', "version", "Show version information")],
+    args: &[
+        Arg::new("COMMAND", ArgKind::Text).optional(),
+        Arg::new("ARG", ArgKind::Text).optional().repeated().raw(),
+    ],
+};
+
+/// `-j N` for the commands that download
+const JOBS: Flag = Flag::new('j', "jobs", "Download N packages at once").value("N");
+
+static PKG_INSTALL: CommandSpec = CommandSpec {
+    name: "pkg install",
+    summary: "install a package from the registry",
+    description: "Install package NAME, at VERSION if given as NAME@VERSION.",
+    flags: &[JOBS],
+    args: &[Arg::new("NAME", ArgKind::Text)],
+};
+
+static PKG_INSTALL_LOCAL: CommandSpec = CommandSpec {
+    name: "pkg install-local",
+    summary: "install a package file",
+    description: "Install the package in .axepkg file PATH.",
+    flags: &[],
+    args: &[Arg::new("PATH", ArgKind::Path)],
+};
+
+static PKG_REMOVE: CommandSpec = CommandSpec {
+    name: "pkg remove",
+    summary: "remove a package",
+    description: "Remove installed package NAME.",
+    flags: &[],
+    args: &[Arg::new("NAME", ArgKind::Text)],
+};
+
+static PKG_INFO: CommandSpec = CommandSpec {
+    name: "pkg info",
+    summary: "show package information",
+    description: "Show what is known about installed package NAME.",
+    flags: &[],
+    args: &[Arg::new("NAME", ArgKind::Text)],
+};
+
+static PKG_SEARCH: CommandSpec = CommandSpec {
+    name: "pkg search",
+    summary: "search the registry",
+    description: "Search the registry for packages matching QUERY.",
+    flags: &[],
+    args: &[Arg::new("QUERY", ArgKind::Text).repeated()],
+};
+
+static PKG_UPGRADE: CommandSpec = CommandSpec {
+    name: "pkg upgrade",
+    summary: "upgrade all packages",
+    description: "Upgrade every installed package to its latest version.",
+    flags: &[JOBS],
+    args: &[],
+};
+
+static PKG_NEW: CommandSpec = CommandSpec {
+    name: "pkg new",
+    summary: "create a command project",
+    description: "Create a WASM command project called NAME in DIR, or ./NAME.",
+    flags: &[],
+    args: &[
+        Arg::new("NAME", ArgKind::Text),
+        Arg::new("DIR", ArgKind::Dir).optional(),
+    ],
+};
+
+static PKG_SYSTEM_UPGRADE: CommandSpec = CommandSpec {
+    name: "pkg system-upgrade",
+    summary: "stage a system image",
+    description: "Stage system image FILE, or the registry's latest, for the next boot.",
+    flags: &[],
+    args: &[Arg::new("FILE", ArgKind::Path).optional()],
+};
 
 pub fn prog_pkg(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
+    let m = match PKG.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&PKG, stdout, stderr),
+    };
 
-    if let Some(help) = check_help(&args, HELP_TEXT) {
-        stdout.push_str(&help);
+    if m.flag("version") {
+        stdout.push_str("pkg 1.0.0 (axeberg package manager)\n");
         return 0;
     }
 
-    if args.is_empty() {
+    let Some((command, args)) = m.operands.split_first() else {
         stderr.push_str("pkg: missing command\nTry 'pkg --help' for more information.\n");
         return 1;
-    }
-
-    // Handle version flag
-    if args[0] == "-v" || args[0] == "--version" {
-        stdout.push_str("pkg 1.0.0 (axeberg package manager)\n");
-        return 0;
-    }
+    };
 
-    match args[0] {
+    match command.as_str() {
         "init" => cmd_init(stdout, stderr),
-        "install" => cmd_install(&args[1..], stdout, stderr),
-        "install-local" => cmd_install_local(&args[1..], stdout, stderr),
-        "remove" | "uninstall" | "rm" => cmd_remove(&args[1..], stdout, stderr),
+        "install" => cmd_install(args, stdout, stderr),
+        "install-local" => cmd_install_local(args, stdout, stderr),
+        "remove" | "uninstall" | "rm" => cmd_remove(args, stdout, stderr),
         "list" | "ls" => cmd_list(stdout, stderr),
-        "info" | "show" => cmd_info(&args[1..], stdout, stderr),
-        "search" => cmd_search(&args[1..], stdout, stderr),
+        "info" | "show" => cmd_info(args, stdout, stderr),
+        "search" => cmd_search(args, stdout, stderr),
         "update" => cmd_update(stdout, stderr),
-        "upgrade" => cmd_upgrade(stdout, stderr),
+        "upgrade" => cmd_upgrade(args, stdout, stderr),
         "verify" => cmd_verify(stdout, stderr),
         "clean" => cmd_clean(stdout, stderr),
+        "new" => cmd_new(args, stdout, stderr),
+        "system-upgrade" => cmd_system_upgrade(args, stdout, stderr),
+        "system-status" => cmd_system_status(stdout),
+        "system-rollback" => cmd_system_rollback(stdout, stderr),
         cmd => {
             stderr.push_str(&format!("pkg: unknown command '{}'\n", cmd));
             stderr.push_str("Try 'pkg --help' for available commands.\n");
@@ -112,15 +203,21 @@ fn cmd_init(stdout: &mut String, stderr: &mut String) -> i32 {
 /// Install a package from registry
 #[allow(unused_variables)]
 #[allow(clippy::ptr_arg)]
-fn cmd_install(args: &[&str], stdout: &mut String, stderr: &mut String) -> i32 {
-    if args.is_empty() {
-        stderr.push_str("pkg install: missing package name\n");
-        stderr.push_str("Usage: pkg install <name>[@version]\n");
-        return 1;
-    }
+fn cmd_install(args: &[String], stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match PKG_INSTALL.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&PKG_INSTALL, stdout, stderr),
+    };
+    let jobs = match jobs(&m) {
+        Ok(jobs) => jobs,
+        Err(e) => {
+            stderr.push_str(&format!("pkg install: {}\n", e));
+            return 1;
+        }
+    };
 
     // Parse name[@version]
-    let spec = args[0];
+    let spec = m.operands[0].as_str();
     let (name, version) = if let Some(at_pos) = spec.find('@') {
         (&spec[..at_pos], Some(&spec[at_pos + 1..]))
     } else {
@@ -148,6 +245,7 @@ fn cmd_install(args: &[&str], stdout: &mut String, stderr: &mut String) -> i32 {
                 crate::console_log!("pkg install: init failed: {}", e);
                 return;
             }
+            prepare(&mut pm, jobs);
 
             match pm.install(&name, version.as_deref()).await {
                 Ok(id) => {
@@ -170,14 +268,12 @@ fn cmd_install(args: &[&str], stdout: &mut String, stderr: &mut String) -> i32 {
 }
 
 /// Install a package from local file
-fn cmd_install_local(args: &[&str], stdout: &mut String, stderr: &mut String) -> i32 {
-    if args.is_empty() {
-        stderr.push_str("pkg install-local: missing file path\n");
-        stderr.push_str("Usage: pkg install-local <path>\n");
-        return 1;
-    }
-
-    let path = args[0];
+fn cmd_install_local(args: &[String], stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match PKG_INSTALL_LOCAL.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&PKG_INSTALL_LOCAL, stdout, stderr),
+    };
+    let path = m.operands[0].as_str();
 
     // Check file exists
     if !syscall::exists(path).unwrap_or(false) {
@@ -204,14 +300,12 @@ fn cmd_install_local(args: &[&str], stdout: &mut String, stderr: &mut String) ->
 }
 
 /// Remove an installed package
-fn cmd_remove(args: &[&str], stdout: &mut String, stderr: &mut String) -> i32 {
-    if args.is_empty() {
-        stderr.push_str("pkg remove: missing package name\n");
-        stderr.push_str("Usage: pkg remove <name>\n");
-        return 1;
-    }
-
-    let name = args[0];
+fn cmd_remove(args: &[String], stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match PKG_REMOVE.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&PKG_REMOVE, stdout, stderr),
+    };
+    let name = m.operands[0].as_str();
 
     let mut pm = PackageManager::new();
     match pm.remove(name) {
@@ -267,14 +361,12 @@ fn cmd_list(stdout: &mut String, stderr: &mut String) -> i32 {
 }
 
 /// Show package information
-fn cmd_info(args: &[&str], stdout: &mut String, stderr: &mut String) -> i32 {
-    if args.is_empty() {
-        stderr.push_str("pkg info: missing package name\n");
-        stderr.push_str("Usage: pkg info <name>\n");
-        return 1;
-    }
-
-    let name = args[0];
+fn cmd_info(args: &[String], stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match PKG_INFO.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&PKG_INFO, stdout, stderr),
+    };
+    let name = m.operands[0].as_str();
 
     let mut db = PackageDatabase::new();
     match db.get_installed(name) {
@@ -327,14 +419,12 @@ fn cmd_info(args: &[&str], stdout: &mut String, stderr: &mut String) -> i32 {
 }
 
 /// Search for packages (async)
-fn cmd_search(args: &[&str], _stdout: &mut String, stderr: &mut String) -> i32 {
-    if args.is_empty() {
-        stderr.push_str("pkg search: missing search query\n");
-        stderr.push_str("Usage: pkg search <query>\n");
-        return 1;
-    }
-
-    let _query = args.join(" ");
+fn cmd_search(args: &[String], _stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match PKG_SEARCH.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&PKG_SEARCH, _stdout, stderr),
+    };
+    let _query = m.operands.join(" ");
 
     #[cfg(target_arch = "wasm32")]
     {
@@ -399,7 +489,19 @@ fn cmd_update(_stdout: &mut String, _stderr: &mut String) -> i32 {
 }
 
 /// Upgrade all packages (async)
-fn cmd_upgrade(_stdout: &mut String, _stderr: &mut String) -> i32 {
+fn cmd_upgrade(args: &[String], _stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match PKG_UPGRADE.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&PKG_UPGRADE, _stdout, stderr),
+    };
+    let jobs = match jobs(&m) {
+        Ok(jobs) => jobs,
+        Err(e) => {
+            stderr.push_str(&format!("pkg upgrade: {}\n", e));
+            return 1;
+        }
+    };
+
     #[cfg(target_arch = "wasm32")]
     {
         _stdout.push_str("Checking for upgrades...\n");
@@ -407,6 +509,7 @@ fn cmd_upgrade(_stdout: &mut String, _stderr: &mut String) -> i32 {
 
         wasm_bindgen_futures::spawn_local(async move {
             let mut pm = PackageManager::new();
+            prepare(&mut pm, jobs);
             match pm.upgrade_all().await {
                 Ok(upgraded) => {
                     if upgraded.is_empty() {
@@ -428,11 +531,166 @@ fn cmd_upgrade(_stdout: &mut String, _stderr: &mut String) -> i32 {
 
     #[cfg(not(target_arch = "wasm32"))]
     {
-        _stderr.push_str("pkg upgrade: requires WASM build for network access\n");
+        let _ = jobs;
+        stderr.push_str("pkg upgrade: requires WASM build for network access\n");
         1
     }
 }
 
+/// The `-j` count, if one was given
+fn jobs(m: &Matches) -> Result<Option<usize>, String> {
+    let Some(value) = m.value("jobs") else {
+        return Ok(None);
+    };
+    match value.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(Some(n)),
+        _ => Err(format!("invalid number of jobs: '{}'", value)),
+    }
+}
+
+/// Set the download parallelism and draw progress bars above the prompt,
+/// one per package, and report the whole run's progress
+#[cfg(target_arch = "wasm32")]
+fn prepare(pm: &mut PackageManager, jobs: Option<usize>) {
+    use crate::shell::osc::Meter;
+    use std::cell::{Cell, RefCell};
+
+    if let Some(jobs) = jobs {
+        pm.installer.set_parallel_downloads(jobs);
+    }
+    // Lines drawn last time, redrawn in place
+    let drawn = Cell::new(0);
+    let meter = RefCell::new(Meter::new(100, syscall::now()));
+    pm.on_progress(move |progress| {
+        if crate::terminal::can_notify() {
+            let lines = progress.render();
+            crate::terminal::renotify(drawn.get(), &lines);
+            drawn.set(lines.len());
+        }
+        // The package lines say what failed
+        let mut meter = meter.borrow_mut();
+        let report = if progress.is_done() || progress.is_failed() {
+            Some(meter.finish())
+        } else {
+            meter.update(progress.percent() as u64, syscall::now())
+        };
+        if let Some(report) = report {
+            crate::terminal::report(&report);
+        }
+    });
+}
+
+/// Stage a system image, downloaded from the registry or read from a file
+fn cmd_system_upgrade(args: &[String], stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match PKG_SYSTEM_UPGRADE.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&PKG_SYSTEM_UPGRADE, stdout, stderr),
+    };
+    if let Some(path) = m.operand(0) {
+        return match syscall::read_file(path) {
+            Ok(data) => stage_system_image(data.as_bytes(), stdout, stderr),
+            Err(e) => {
+                stderr.push_str(&format!("pkg system-upgrade: {}: {}\n", path, e));
+                1
+            }
+        };
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        stdout.push_str("Downloading system image...\n");
+        stdout.push_str("(Running in background - check console for results)\n");
+
+        wasm_bindgen_futures::spawn_local(async move {
+            let pm = PackageManager::new();
+            let staged = match pm.registry.download_system_image().await {
+                Ok(data) => syscall::system_stage(&data).map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
+            };
+            match staged {
+                Ok((slot, version)) => {
+                    crate::console_log!(
+                        "pkg: system {} staged in slot {}; reboot to switch",
+                        version,
+                        slot.name()
+                    );
+                }
+                Err(e) => {
+                    crate::console_log!("pkg system-upgrade: {}", e);
+                }
+            }
+        });
+        return 0;
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        stderr.push_str("pkg system-upgrade: downloading requires WASM build\n");
+        stderr.push_str("Use 'pkg system-upgrade <file>' to stage a local image.\n");
+        1
+    }
+}
+
+fn stage_system_image(data: &[u8], stdout: &mut String, stderr: &mut String) -> i32 {
+    match syscall::system_stage(data) {
+        Ok((slot, version)) => {
+            stdout.push_str(&format!(
+                "Staged system {} in slot {}; it takes over at the next boot.\n",
+                version,
+                slot.name()
+            ));
+            0
+        }
+        Err(e) => {
+            stderr.push_str(&format!("pkg system-upgrade: {}\n", e));
+            1
+        }
+    }
+}
+
+/// Show the running system image and what the next boot will do
+fn cmd_system_status(stdout: &mut String) -> i32 {
+    let (boot, state) = syscall::system_status();
+    match &boot {
+        Some(boot) => stdout.push_str(&format!(
+            "System:   {} (slot {}{})\n",
+            boot.version,
+            boot.slot.name(),
+            if boot.factory { ", factory image" } else { "" }
+        )),
+        None => stdout.push_str("System:   not booted\n"),
+    }
+    if state.trying {
+        stdout.push_str("Status:   on trial until this boot is confirmed\n");
+    }
+    if let Some(slot) = state.pending {
+        stdout.push_str(&format!("Pending:  slot {} at next boot\n", slot.name()));
+    }
+    if let Some(slot) = state.previous {
+        stdout.push_str(&format!("Previous: slot {}\n", slot.name()));
+    }
+    if let Some(reason) = boot.and_then(|b| b.rolled_back) {
+        stdout.push_str(&format!("Rolled back: {}\n", reason));
+    } else if let Some(error) = state.last_error {
+        stdout.push_str(&format!("Last error: {}\n", error));
+    }
+    0
+}
+
+/// Boot the other system slot next time
+fn cmd_system_rollback(stdout: &mut String, stderr: &mut String) -> i32 {
+    match syscall::system_rollback() {
+        Ok(slot) => {
+            stdout.push_str(&format!("Slot {} will boot next time.\n", slot.name()));
+            0
+        }
+        Err(e) => {
+            stderr.push_str(&format!("pkg system-rollback: {}\n", e));
+            1
+        }
+    }
+}
+
 /// Verify installed packages
 fn cmd_verify(stdout: &mut String, stderr: &mut String) -> i32 {
     let pm = PackageManager::new();
@@ -476,6 +734,54 @@ fn cmd_clean(stdout: &mut String, stderr: &mut String) -> i32 {
     }
 }
 
+/// Scaffold a new command project in the VFS
+fn cmd_new(args: &[String], stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match PKG_NEW.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&PKG_NEW, stdout, stderr),
+    };
+    let name = m.operands[0].as_str();
+    let mut template = match ProjectTemplate::new(name) {
+        Ok(t) => t,
+        Err(e) => {
+            stderr.push_str(&format!("pkg new: {}\n", e));
+            return 1;
+        }
+    };
+
+    // Record the current user as author
+    if let Ok(uid) = syscall::getuid()
+        && let Some(user) = syscall::get_user_by_uid(uid)
+    {
+        template = template.with_author(&user.name);
+    }
+
+    let dir = m.operand(1).unwrap_or(name);
+    let dir = if dir.starts_with('/') {
+        dir.to_string()
+    } else {
+        let cwd = syscall::getcwd()
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|_| "/".to_string());
+        format!("{}/{}", cwd.trim_end_matches('/'), dir)
+    };
+
+    match template.write_to(&dir) {
+        Ok(files) => {
+            stdout.push_str(&format!("Created command project '{}' in {}\n", name, dir));
+            for file in files {
+                stdout.push_str(&format!("  {}\n", file));
+            }
+            stdout.push_str("\nSee README.md for build and install steps.\n");
+            0
+        }
+        Err(e) => {
+            stderr.push_str(&format!("pkg new: {}\n", e));
+            1
+        }
+    }
+}
+
 /// Format a Unix timestamp for display
 fn format_timestamp(ts: u64) -> String {
     if ts == 0 {
@@ -554,9 +860,16 @@ mod tests {
         let result = prog_pkg(&args, "", &mut stdout, &mut stderr);
 
         assert_eq!(result, 0);
-        assert!(stdout.contains("Usage: pkg <command> [args]"));
+        assert!(stdout.contains("Usage: pkg [-v] [COMMAND] [ARG]..."));
         assert!(stdout.contains("WASM Package Manager"));
         assert!(stderr.is_empty());
+
+        // Subcommands have their own help
+        let args = vec!["install".to_string(), "--help".to_string()];
+        let mut stdout = String::new();
+        assert_eq!(prog_pkg(&args, "", &mut stdout, &mut String::new()), 0);
+        assert!(stdout.starts_with("Usage: pkg install [-j N] NAME\n"));
+        assert!(stdout.ends_with("See 'man pkg' for details."));
     }
 
     #[test]
@@ -600,7 +913,8 @@ mod tests {
         let result = prog_pkg(&args, "", &mut stdout, &mut stderr);
 
         assert_eq!(result, 1);
-        assert!(stderr.contains("pkg install: missing package name"));
+        assert!(stderr.contains("pkg install: missing operand: expected NAME"));
+        assert!(stderr.contains("Usage: pkg install [-j N] NAME"));
     }
 
     #[test]
@@ -611,7 +925,7 @@ mod tests {
         let result = prog_pkg(&args, "", &mut stdout, &mut stderr);
 
         assert_eq!(result, 1);
-        assert!(stderr.contains("pkg remove: missing package name"));
+        assert!(stderr.contains("pkg remove: missing operand: expected NAME"));
     }
 
     #[test]
@@ -622,7 +936,7 @@ mod tests {
         let result = prog_pkg(&args, "", &mut stdout, &mut stderr);
 
         assert_eq!(result, 1);
-        assert!(stderr.contains("pkg info: missing package name"));
+        assert!(stderr.contains("pkg info: missing operand: expected NAME"));
     }
 
     #[test]
@@ -633,7 +947,114 @@ mod tests {
         let result = prog_pkg(&args, "", &mut stdout, &mut stderr);
 
         assert_eq!(result, 1);
-        assert!(stderr.contains("pkg search: missing search query"));
+        assert!(stderr.contains("pkg search: missing operand: expected QUERY"));
+    }
+
+    #[test]
+    fn test_jobs() {
+        let parse = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            PKG_INSTALL
+                .parse(&args)
+                .map(|m| (jobs(&m), m.operands.clone()))
+        };
+        let (jobs, rest) = parse(&["-j", "8", "hello", "--jobs=2"]).unwrap();
+        assert_eq!((jobs, rest), (Ok(Some(2)), vec!["hello".to_string()]));
+        assert_eq!(parse(&["hello"]).unwrap().0, Ok(None));
+        assert!(parse(&["hello", "-j"]).is_err());
+        assert!(parse(&["hello", "--jobs", "0"]).unwrap().0.is_err());
+
+        let args = vec!["upgrade".to_string(), "-j".to_string(), "x".to_string()];
+        let mut stderr = String::new();
+        assert_eq!(prog_pkg(&args, "", &mut String::new(), &mut stderr), 1);
+        assert!(stderr.contains("invalid number of jobs"));
+    }
+
+    fn setup_kernel() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+        KERNEL.with(|k| {
+            *k.borrow_mut() = Kernel::new();
+            let pid = k.borrow_mut().spawn_process("test", None);
+            k.borrow_mut().set_current(pid);
+        });
+    }
+
+    #[test]
+    fn test_pkg_new_scaffolds_project() {
+        setup_kernel();
+        let args = vec![
+            "new".to_string(),
+            "greet".to_string(),
+            "/tmp/greet".to_string(),
+        ];
+        let mut stdout = String::new();
+        let mut stderr = String::new();
+        let result = prog_pkg(&args, "", &mut stdout, &mut stderr);
+
+        assert_eq!(result, 0, "stderr: {}", stderr);
+        assert!(syscall::exists("/tmp/greet/bin").unwrap());
+        let manifest = syscall::read_file("/tmp/greet/package.toml").unwrap();
+        assert!(manifest.contains("name = \"greet\""));
+        let lib = syscall::read_file("/tmp/greet/src/lib.rs").unwrap();
+        assert!(lib.contains("axeberg_abi::entry!"));
+
+        // Refuses to overwrite an existing project
+        let mut stderr = String::new();
+        let result = prog_pkg(&args, "", &mut String::new(), &mut stderr);
+        assert_eq!(result, 1);
+        assert!(stderr.contains("already exists"));
+    }
+
+    #[test]
+    fn test_pkg_system_upgrade() {
+        use crate::kernel::syscall::KERNEL;
+        use crate::kernel::{SystemImage, Uid};
+
+        setup_kernel();
+        syscall::system_boot(&crate::shell::system_image()).unwrap();
+        assert!(syscall::exists("/system/bin/pkg").unwrap());
+
+        let mut image = SystemImage::new("9.9.9");
+        image.add("etc/version", "axeberg 9.9.9\n");
+        let fd = syscall::open("/tmp/new.sysimg", syscall::OpenFlags::WRITE).unwrap();
+        syscall::write(fd, &image.to_bytes().unwrap()).unwrap();
+        syscall::close(fd).unwrap();
+        let args = vec!["system-upgrade".to_string(), "/tmp/new.sysimg".to_string()];
+
+        // Staging needs root
+        let mut stderr = String::new();
+        assert_eq!(prog_pkg(&args, "", &mut String::new(), &mut stderr), 1);
+        assert!(stderr.contains("permission denied"));
+
+        KERNEL.with(|k| {
+            k.borrow_mut().current_process_mut().unwrap().euid = Uid::ROOT;
+        });
+        let mut stdout = String::new();
+        let result = prog_pkg(&args, "", &mut stdout, &mut String::new());
+        assert_eq!(result, 0);
+        assert!(stdout.contains("9.9.9 in slot b"));
+
+        let mut stdout = String::new();
+        let args = vec!["system-status".to_string()];
+        prog_pkg(&args, "", &mut stdout, &mut String::new());
+        assert!(stdout.contains("Pending:  slot b"));
+
+        syscall::system_boot(&crate::shell::system_image()).unwrap();
+        assert_eq!(
+            syscall::read_file("/system/etc/version").unwrap(),
+            "axeberg 9.9.9\n"
+        );
+        assert!(!syscall::exists("/system/bin/pkg").unwrap());
+    }
+
+    #[test]
+    fn test_pkg_new_invalid_name() {
+        let args = vec!["new".to_string(), "Bad/Name".to_string()];
+        let mut stderr = String::new();
+        let result = prog_pkg(&args, "", &mut String::new(), &mut stderr);
+
+        assert_eq!(result, 1);
+        assert!(stderr.contains("pkg new:"));
     }
 
     #[test]
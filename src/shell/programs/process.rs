@@ -1,28 +1,33 @@
 //! Process control programs
 
-use super::{args_to_strs, check_help};
-use crate::kernel::syscall;
+use crate::kernel::syscall::{self, SyscallNr};
+use crate::kernel::{PathOp, PtraceEvent, PtraceOptions, SyscallFilter};
+use crate::shell::argparse::{Arg, ArgError, ArgKind, CommandSpec, Flag, Matches};
+use std::collections::HashMap;
+
+pub static SLEEP: CommandSpec = CommandSpec {
+    name: "sleep",
+    summary: "pause for a number of seconds",
+    description: "Pause for SECONDS, which can be a fraction. A signal cuts the pause short.",
+    flags: &[],
+    args: &[Arg::new("SECONDS", ArgKind::Text)],
+};
 
 /// sleep - pause for specified seconds
-pub fn prog_sleep(
-    args: &[String],
-    __stdin: &str,
-    _stdout: &mut String,
-    stderr: &mut String,
-) -> i32 {
-    let args = args_to_strs(args);
-
-    if args.is_empty() {
-        stderr.push_str("sleep: missing operand\n");
+pub fn prog_sleep(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match SLEEP.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&SLEEP, stdout, stderr),
+    };
+    let args = &m.operands;
+
+    let duration = args[0]
+        .parse()
+        .ok()
+        .and_then(|seconds| std::time::Duration::try_from_secs_f64(seconds).ok());
+    let Some(duration) = duration else {
+        stderr.push_str(&format!("sleep: invalid time interval '{}'\n", args[0]));
         return 1;
-    }
-
-    let seconds: f64 = match args[0].parse() {
-        Ok(n) => n,
-        Err(_) => {
-            stderr.push_str(&format!("sleep: invalid time interval '{}'\n", args[0]));
-            return 1;
-        }
     };
 
     // In WASM we can't actually block, but we can note the intent
@@ -33,28 +38,46 @@ pub fn prog_sleep(
         // Can't block in WASM - would need async support
         crate::console_log!(
             "[sleep] Would sleep for {} seconds (non-blocking in WASM)",
-            seconds
+            duration.as_secs_f64()
         );
     }
 
     #[cfg(not(target_arch = "wasm32"))]
     {
-        std::thread::sleep(std::time::Duration::from_secs_f64(seconds));
+        // Sleep in short slices so that a signal cuts it short, like EINTR
+        const SLICE: std::time::Duration = std::time::Duration::from_millis(20);
+        let deadline = std::time::Instant::now() + duration;
+        loop {
+            if let Some(signal) = syscall::interrupting_signal() {
+                return 128 + i32::from(signal.num());
+            }
+            let now = std::time::Instant::now();
+            if now >= deadline {
+                break;
+            }
+            std::thread::sleep((deadline - now).min(SLICE));
+        }
     }
 
     0
 }
 
-/// jobs - list background jobs
-pub fn prog_jobs(args: &[String], __stdin: &str, stdout: &mut String, _stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
+pub static JOBS: CommandSpec = CommandSpec {
+    name: "jobs",
+    summary: "list background jobs",
+    description: "List background jobs, with their state.",
+    flags: &[Flag::short('l', "Show process IDs too")],
+    args: &[],
+};
 
-    if let Some(help) = check_help(&args, "Usage: jobs [-l]\nList background jobs.") {
-        stdout.push_str(&help);
-        return 0;
-    }
+/// jobs - list background jobs
+pub fn prog_jobs(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match JOBS.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&JOBS, stdout, stderr),
+    };
 
-    let long_format = args.contains(&"-l");
+    let long_format = m.flag("l");
 
     // Get list of processes from kernel
     let processes = syscall::list_processes();
@@ -97,25 +120,30 @@ pub fn prog_jobs(args: &[String], __stdin: &str, stdout: &mut String, _stderr: &
     0
 }
 
+pub static FG: CommandSpec = CommandSpec {
+    name: "fg",
+    summary: "bring a job to the foreground",
+    description: "Bring job JOB (%N or N), or the most recent one, to the foreground, continuing it if it is stopped.",
+    flags: &[],
+    args: &[Arg::new("JOB", ArgKind::Text).optional()],
+};
+
 /// fg - bring job to foreground
 pub fn prog_fg(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(&args, "Usage: fg [%JOB]\nBring job to foreground.") {
-        stdout.push_str(&help);
-        return 0;
-    }
+    let m = match FG.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&FG, stdout, stderr),
+    };
 
     // Parse job specification
-    let job_spec = if args.is_empty() {
-        None // Use current job
-    } else {
-        let spec = args[0];
+    let job_spec = if let Some(spec) = m.operand(0) {
         if spec.starts_with('%') {
             spec.trim_start_matches('%').parse::<u32>().ok()
         } else {
             spec.parse::<u32>().ok()
         }
+    } else {
+        None // Use current job
     };
 
     // Get processes and find the matching job
@@ -155,25 +183,30 @@ pub fn prog_fg(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut
     }
 }
 
+pub static BG: CommandSpec = CommandSpec {
+    name: "bg",
+    summary: "continue a job in the background",
+    description: "Continue stopped job JOB (%N or N), or the most recent one, in the background.",
+    flags: &[],
+    args: &[Arg::new("JOB", ArgKind::Text).optional()],
+};
+
 /// bg - continue job in background
 pub fn prog_bg(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(&args, "Usage: bg [%JOB]\nContinue job in background.") {
-        stdout.push_str(&help);
-        return 0;
-    }
+    let m = match BG.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&BG, stdout, stderr),
+    };
 
     // Parse job specification (same as fg)
-    let job_spec = if args.is_empty() {
-        None
-    } else {
-        let spec = args[0];
+    let job_spec = if let Some(spec) = m.operand(0) {
         if spec.starts_with('%') {
             spec.trim_start_matches('%').parse::<u32>().ok()
         } else {
             spec.parse::<u32>().ok()
         }
+    } else {
+        None
     };
 
     let processes = syscall::list_processes();
@@ -211,6 +244,29 @@ pub fn prog_bg(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut
     }
 }
 
+pub static STRACE: CommandSpec = CommandSpec {
+    name: "strace",
+    summary: "trace system calls",
+    description: "Trace system calls.
+
+With -p, attach to a running process. Its syscalls are queued while it runs; run `strace -p PID` again to print the ones made since the last call.",
+    flags: &[
+        Flag::short('p', "Attach to process PID (may be repeated)").value("PID"),
+        Flag::short('f', "Also trace children the process spawns or forks"),
+        Flag::short(
+            'e',
+            "Only trace syscalls in SET (trace=SET), a comma-separated list of names or classes: file, desc, process, signal, ipc, memory, env",
+        )
+        .value("EXPR"),
+        Flag::short('c', "Count calls per syscall instead of printing each one"),
+        Flag::long("detach", "Print pending events, then stop tracing"),
+    ],
+    args: &[
+        Arg::new("COMMAND", ArgKind::Command).optional(),
+        Arg::new("ARG", ArgKind::Text).optional().repeated().raw(),
+    ],
+};
+
 /// strace - trace system calls
 pub fn prog_strace(
     args: &[String],
@@ -218,32 +274,25 @@ pub fn prog_strace(
     stdout: &mut String,
     stderr: &mut String,
 ) -> i32 {
-    let args = args_to_strs(args);
+    let m = match STRACE.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&STRACE, stdout, stderr),
+    };
 
-    if let Some(help) = check_help(
-        &args,
-        "Usage: strace [-c] COMMAND [ARGS...]\nTrace system calls.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
+    if m.flag("p") {
+        if let Some(extra) = m.operands.first() {
+            return ArgError::ExtraOperand(extra.clone()).report(&STRACE, stdout, stderr);
+        }
+        return strace_attach(&m, stdout, stderr);
     }
 
-    if args.is_empty() {
+    if m.operands.is_empty() {
         stderr.push_str("strace: must have COMMAND to run\n");
         return 1;
     }
 
-    let count_mode = args.contains(&"-c");
-    let cmd_args: Vec<_> = args
-        .iter()
-        .filter(|a| !a.starts_with('-'))
-        .copied()
-        .collect();
-
-    if cmd_args.is_empty() {
-        stderr.push_str("strace: must have COMMAND to run\n");
-        return 1;
-    }
+    let count_mode = m.flag("c");
+    let cmd_args = &m.operands;
 
     // Enable tracing
     syscall::trace_enable();
@@ -279,17 +328,315 @@ pub fn prog_strace(
     0
 }
 
-/// kill - send signal to process
-pub fn prog_kill(args: &[String], __stdin: &str, _stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
+/// strace -p: attach to running processes and print what they did
+fn strace_attach(m: &Matches, stdout: &mut String, stderr: &mut String) -> i32 {
+    let mut pids = Vec::new();
+    for pid in m.values("p") {
+        match pid.parse::<u32>() {
+            Ok(pid) => pids.push(syscall::Pid(pid)),
+            Err(_) => {
+                stderr.push_str(&format!("strace: invalid process id: '{}'\n", pid));
+                return 1;
+            }
+        }
+    }
+    let filter = match m.value("e").map(SyscallFilter::parse) {
+        None => None,
+        Some(Ok(f)) => Some(f),
+        Some(Err(e)) => {
+            stderr.push_str(&format!("strace: {}\n", e));
+            return 1;
+        }
+    };
+    let follow = m.flag("f");
+    let count = m.flag("c");
+    let detach = m.flag("detach");
+
+    // Attach to new processes; re-attach only to change options, so a plain
+    // `strace -p PID` keeps whatever -f/-e were given first
+    let tracees = syscall::ptrace_tracees().unwrap_or_default();
+    if !detach {
+        for &pid in &pids {
+            let attached = tracees.contains(&pid);
+            if attached && !follow && filter.is_none() {
+                continue;
+            }
+            let options = PtraceOptions {
+                follow_children: follow,
+                filter: filter.clone().unwrap_or_default(),
+            };
+            if let Err(e) = syscall::ptrace_attach(pid, options) {
+                stderr.push_str(&format!(
+                    "strace: attach: ptrace(PTRACE_SEIZE, {}): {}\n",
+                    pid.0, e
+                ));
+                return 1;
+            }
+            if !attached {
+                stderr.push_str(&format!("strace: Process {} attached\n", pid.0));
+            }
+        }
+    }
+
+    let (events, dropped) = syscall::ptrace_events().unwrap_or_default();
+    if dropped > 0 {
+        stderr.push_str(&format!(
+            "strace: {} events lost, queue was full\n",
+            dropped
+        ));
+    }
+    if count {
+        format_strace_counts(&events, stdout);
+    } else {
+        format_strace_events(&events, &pids, stdout);
+    }
+
+    if detach {
+        for &pid in &pids {
+            match syscall::ptrace_detach(pid) {
+                Ok(()) => stderr.push_str(&format!("strace: Process {} detached\n", pid.0)),
+                Err(_) => {
+                    stderr.push_str(&format!("strace: Process {} is not being traced\n", pid.0));
+                    return 1;
+                }
+            }
+        }
+    }
+
+    0
+}
+
+/// Print events one syscall per line, strace style
+///
+/// Processes other than those named with -p get a `[pid N]` prefix.
+fn format_strace_events(events: &[PtraceEvent], main: &[syscall::Pid], out: &mut String) {
+    let prefix = |pid: syscall::Pid| {
+        if main.contains(&pid) {
+            String::new()
+        } else {
+            format!("[pid {}] ", pid.0)
+        }
+    };
+    let ret = |ret: &Result<String, String>| match ret {
+        Ok(value) => format!("= {}", value),
+        Err(e) => format!("= -1 ({})", e),
+    };
 
-    if let Some(help) = check_help(
-        &args,
-        "Usage: kill [-s SIGNAL] PID...\nSend signal to processes.",
-    ) {
-        stderr.push_str(&help);
-        return 0;
+    let mut iter = events.iter().peekable();
+    while let Some(event) = iter.next() {
+        match event {
+            PtraceEvent::SyscallEnter { pid, nr, args } => {
+                let exit = iter.next_if(|next| {
+                    matches!(next, PtraceEvent::SyscallExit { pid: p, nr: n, .. } if p == pid && n == nr)
+                });
+                let line = format!("{}{}({})", prefix(*pid), nr.name(), args);
+                match exit {
+                    Some(PtraceEvent::SyscallExit { ret: r, .. }) => {
+                        out.push_str(&format!("{} {}\n", line, ret(r)))
+                    }
+                    _ => out.push_str(&format!("{} <unfinished ...>\n", line)),
+                }
+            }
+            PtraceEvent::SyscallExit { pid, nr, ret: r } => {
+                out.push_str(&format!(
+                    "{}<... {} resumed>) {}\n",
+                    prefix(*pid),
+                    nr.name(),
+                    ret(r)
+                ));
+            }
+            PtraceEvent::Attached { pid, .. } => {
+                out.push_str(&format!("strace: Process {} attached\n", pid.0));
+            }
+            PtraceEvent::Exited { pid, code } => {
+                out.push_str(&format!("{}+++ exited with {} +++\n", prefix(*pid), code));
+            }
+        }
     }
+}
+
+/// Print a per-syscall call/error summary (-c)
+fn format_strace_counts(events: &[PtraceEvent], out: &mut String) {
+    let mut by_nr: HashMap<SyscallNr, (u64, u64)> = HashMap::new();
+    for event in events {
+        if let PtraceEvent::SyscallExit { nr, ret, .. } = event {
+            let entry = by_nr.entry(*nr).or_default();
+            entry.0 += 1;
+            if ret.is_err() {
+                entry.1 += 1;
+            }
+        }
+    }
+    let mut counts: Vec<(SyscallNr, u64, u64)> =
+        by_nr.into_iter().map(|(nr, (c, e))| (nr, c, e)).collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.name().cmp(b.0.name())));
+
+    let rule = "--------- --------- ----------------\n";
+    out.push_str("    calls    errors syscall\n");
+    out.push_str(rule);
+    let (mut calls, mut errors) = (0, 0);
+    for (nr, c, e) in &counts {
+        out.push_str(&format!("{:>9} {:>9} {}\n", c, e, nr.name()));
+        calls += c;
+        errors += e;
+    }
+    out.push_str(rule);
+    out.push_str(&format!("{:>9} {:>9} total\n", calls, errors));
+}
+
+pub static DEBUG: CommandSpec = CommandSpec {
+    name: "debug",
+    summary: "path watchpoints in the kernel debugger",
+    description: "Break when a process opens, writes or unlinks a path. COMMAND is one of:
+
+  watch [-o OPS] PATH   watch PATH
+  unwatch ID            remove a watch
+  watches               list the watches
+  hits                  print the hits since the last call (the default)
+  continue              resume the paused processes
+
+A process that touches a watched path is paused (shown as T by ps) and the syscall that did it is recorded. Watching a directory covers everything below it. Without root or CAP_SYS_PTRACE only your own processes are caught.",
+    flags: &[],
+    args: &[
+        Arg::new("COMMAND", ArgKind::Text).optional(),
+        Arg::new("ARG", ArgKind::Text).optional().repeated().raw(),
+    ],
+};
+
+static DEBUG_WATCH: CommandSpec = CommandSpec {
+    name: "debug watch",
+    summary: "set a path watchpoint",
+    description: "Pause any process that touches PATH, or anything below it, and record the syscall that did it.",
+    flags: &[Flag::short(
+        'o',
+        "Comma-separated operations to watch: open, write, unlink (default: all)",
+    )
+    .value("OPS")],
+    args: &[Arg::new("PATH", ArgKind::Path)],
+};
+
+/// debug - path watchpoints in the kernel debugger
+pub fn prog_debug(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match DEBUG.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&DEBUG, stdout, stderr),
+    };
+    let args = &m.operands;
+
+    match args.first().map_or("hits", String::as_str) {
+        "watch" => debug_watch(&args[1..], stdout, stderr),
+        "unwatch" => {
+            let Some(id) = args.get(1).and_then(|a| a.parse::<u32>().ok()) else {
+                stderr.push_str("debug: unwatch: expected a watch ID\n");
+                return 1;
+            };
+            match syscall::debug_unwatch_path(id) {
+                Ok(()) => 0,
+                Err(e) => {
+                    stderr.push_str(&format!("debug: unwatch {}: {}\n", id, e));
+                    1
+                }
+            }
+        }
+        "watches" => {
+            let watches = syscall::debug_path_watches();
+            if watches.is_empty() {
+                stdout.push_str("no path watches set\n");
+            }
+            for watch in watches {
+                let ops: Vec<_> = watch.ops.iter().map(|op| op.name()).collect();
+                stdout.push_str(&format!(
+                    "{:<4} {:<18} {:>5} hits  {}\n",
+                    watch.id,
+                    ops.join(","),
+                    watch.hit_count,
+                    watch.path
+                ));
+            }
+            0
+        }
+        "hits" => match syscall::debug_path_hits() {
+            Ok(hits) => {
+                for hit in hits {
+                    stdout.push_str(&format!("{}\n", hit));
+                }
+                0
+            }
+            Err(e) => {
+                stderr.push_str(&format!("debug: {}\n", e));
+                1
+            }
+        },
+        "continue" => match syscall::debug_continue() {
+            Ok(pids) => {
+                for pid in pids {
+                    stdout.push_str(&format!("resumed pid {}\n", pid.0));
+                }
+                0
+            }
+            Err(e) => {
+                stderr.push_str(&format!("debug: {}\n", e));
+                1
+            }
+        },
+        other => {
+            stderr.push_str(&format!("debug: unknown command: {}\n", other));
+            1
+        }
+    }
+}
+
+/// debug watch: set a path watchpoint
+fn debug_watch(args: &[String], stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match DEBUG_WATCH.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&DEBUG_WATCH, stdout, stderr),
+    };
+    let path = m.operands[0].as_str();
+
+    let mut ops = PathOp::ALL.to_vec();
+    if let Some(spec) = m.value("o") {
+        ops.clear();
+        for name in spec.split(',') {
+            match PathOp::from_name(name) {
+                Some(op) if !ops.contains(&op) => ops.push(op),
+                Some(_) => {}
+                None => {
+                    stderr.push_str(&format!("debug: invalid operation '{}'\n", name));
+                    return 1;
+                }
+            }
+        }
+    }
+
+    match syscall::debug_watch_path(path, &ops) {
+        Ok(id) => {
+            stdout.push_str(&format!("watch {}: {}\n", id, path));
+            0
+        }
+        Err(e) => {
+            stderr.push_str(&format!("debug: watch {}: {}\n", path, e));
+            1
+        }
+    }
+}
+
+pub static KILL: CommandSpec = CommandSpec {
+    name: "kill",
+    summary: "send a signal to processes",
+    description: "Send SIGNAL (TERM by default) to each process PID. The signal can also be given as -SIGNAL, as in kill -9 PID or kill -KILL PID.",
+    flags: &[Flag::short('s', "Send SIGNAL, by name or number").value("SIGNAL")],
+    // Raw, since -9 and -KILL aren't flags the parser knows
+    args: &[Arg::new("PID", ArgKind::Text).repeated().raw()],
+};
+
+/// kill - send signal to process
+pub fn prog_kill(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match KILL.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&KILL, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
 
     // Parse signal
     let mut signal = crate::kernel::signal::Signal::SIGTERM;
@@ -308,6 +655,7 @@ pub fn prog_kill(args: &[String], __stdin: &str, _stdout: &mut String, stderr: &
                 "HUP" | "SIGHUP" | "1" => crate::kernel::signal::Signal::SIGHUP,
                 "USR1" | "SIGUSR1" | "10" => crate::kernel::signal::Signal::SIGUSR1,
                 "USR2" | "SIGUSR2" | "12" => crate::kernel::signal::Signal::SIGUSR2,
+                "MEMPRESSURE" | "SIGMEMPRESSURE" => crate::kernel::signal::Signal::SIGMEMPRESSURE,
                 s => {
                     stderr.push_str(&format!("kill: invalid signal: {}\n", s));
                     return 1;
@@ -376,6 +724,32 @@ mod tests {
         let result = prog_sleep(&args, "", &mut stdout, &mut stderr);
         assert_eq!(result, 1);
         assert!(stderr.contains("invalid time interval"));
+
+        let args = vec!["-1".to_string()];
+        assert_eq!(prog_sleep(&args, "", &mut stdout, &mut stderr), 1);
+    }
+
+    #[test]
+    fn test_sleep_interrupted() {
+        syscall::KERNEL.with(|k| {
+            *k.borrow_mut() = syscall::Kernel::new();
+            let pid = k.borrow_mut().spawn_process("test", None);
+            k.borrow_mut().set_current(pid);
+        });
+        let args = vec!["60".to_string()];
+        let mut stdout = String::new();
+        let mut stderr = String::new();
+
+        // An ignored signal doesn't wake it, a fatal one does
+        let pid = syscall::getpid().unwrap();
+        syscall::kill(pid, crate::kernel::Signal::SIGCHLD).unwrap();
+        assert_eq!(
+            prog_sleep(&["0.01".to_string()], "", &mut stdout, &mut stderr),
+            0
+        );
+        syscall::kill(pid, crate::kernel::Signal::SIGINT).unwrap();
+        let result = prog_sleep(&args, "", &mut stdout, &mut stderr);
+        assert_eq!(result, 128 + i32::from(crate::kernel::Signal::SIGINT.num()));
     }
 
     #[test]
@@ -433,6 +807,11 @@ mod tests {
         let mut stderr = String::new();
         let result = prog_kill(&[], "", &mut stdout, &mut stderr);
         assert_eq!(result, 1);
+        assert!(stderr.contains("missing operand: expected PID"));
+
+        let args = vec!["-9".to_string()];
+        let mut stderr = String::new();
+        assert_eq!(prog_kill(&args, "", &mut stdout, &mut stderr), 1);
         assert!(stderr.contains("missing pid"));
     }
 
@@ -463,6 +842,169 @@ mod tests {
         let mut stderr = String::new();
         let result = prog_kill(&args, "", &mut stdout, &mut stderr);
         assert_eq!(result, 0);
-        assert!(stderr.contains("Usage: kill"));
+        assert!(stdout.contains("Usage: kill"));
+    }
+
+    fn strace(args: &[&str]) -> (i32, String, String) {
+        let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        let mut stdout = String::new();
+        let mut stderr = String::new();
+        let code = prog_strace(&args, "", &mut stdout, &mut stderr);
+        (code, stdout, stderr)
+    }
+
+    /// Kernel with a tracer (current) and a target process
+    fn setup_trace() -> (syscall::Pid, syscall::Pid) {
+        syscall::KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = syscall::Kernel::new();
+            let tracer = k.spawn_process("tracer", None);
+            let target = k.spawn_process("target", None);
+            k.set_current(tracer);
+            (tracer, target)
+        })
+    }
+
+    #[test]
+    fn test_strace_attach_prints_live_syscalls() {
+        let (tracer, target) = setup_trace();
+        let pid = target.0.to_string();
+
+        let (code, stdout, stderr) = strace(&["-p", &pid, "-e", "trace=file"]);
+        assert_eq!(code, 0, "{}", stderr);
+        assert!(stdout.is_empty());
+        assert!(stderr.contains(&format!("Process {} attached", pid)));
+
+        // The target runs: a traced file syscall, an untraced write, a failure
+        syscall::set_current_process(target);
+        let fd = syscall::open("/tmp/t.txt", syscall::OpenFlags::WRITE).unwrap();
+        syscall::write(fd, b"x").unwrap();
+        let _ = syscall::metadata("/nope");
+        syscall::set_current_process(tracer);
+
+        let (code, stdout, _) = strace(&["-p", &pid]);
+        assert_eq!(code, 0);
+        assert_eq!(
+            stdout,
+            "open(\"/tmp/t.txt\", O_WRONLY|O_CREAT|O_TRUNC) = 3\n\
+             stat(\"/nope\") = -1 (not found)\n"
+        );
+
+        // Events are only printed once; --detach stops tracing
+        let (_, stdout, stderr) = strace(&["-p", &pid, "--detach"]);
+        assert!(stdout.is_empty());
+        assert!(stderr.contains("detached"));
+        assert!(syscall::ptrace_tracees().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_strace_follows_children_and_counts() {
+        let (tracer, target) = setup_trace();
+        let pid = target.0.to_string();
+        strace(&["-p", &pid, "-f"]);
+
+        syscall::set_current_process(target);
+        let child = syscall::fork().unwrap();
+        syscall::set_current_process(child);
+        syscall::getenv("HOME").unwrap();
+        syscall::exit(0).unwrap();
+        syscall::set_current_process(tracer);
+
+        let (_, stdout, _) = strace(&["-p", &pid, "-c"]);
+        assert!(stdout.contains("        1         0 spawn\n"), "{}", stdout);
+        assert!(stdout.contains("        1         0 getenv\n"));
+        assert!(stdout.contains("total"));
+
+        syscall::set_current_process(target);
+        syscall::getcwd().unwrap();
+        syscall::set_current_process(tracer);
+        let (_, stdout, _) = strace(&["-p", &pid]);
+        assert_eq!(stdout, "getcwd() = \"/\"\n");
+    }
+
+    #[test]
+    fn test_strace_attach_errors() {
+        setup_trace();
+        let (code, _, stderr) = strace(&["-p", "999"]);
+        assert_eq!(code, 1);
+        assert!(stderr.contains("ptrace(PTRACE_SEIZE, 999)"));
+
+        let (code, _, stderr) = strace(&["-p", "2", "-e", "trace=bogus"]);
+        assert_eq!(code, 1);
+        assert!(stderr.contains("invalid system call 'bogus'"));
+    }
+
+    fn debug(args: &[&str]) -> (i32, String, String) {
+        let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        let mut stdout = String::new();
+        let mut stderr = String::new();
+        let code = prog_debug(&args, "", &mut stdout, &mut stderr);
+        (code, stdout, stderr)
+    }
+
+    #[test]
+    fn test_debug_path_watch_pauses_writer() {
+        let (debugger, target) = setup_trace();
+        syscall::write_file("/tmp/app.conf", "a=1\n").unwrap();
+
+        let (code, stdout, stderr) = debug(&["watch", "-o", "write,unlink", "/tmp/app.conf"]);
+        assert_eq!(code, 0, "{}", stderr);
+        assert_eq!(stdout, "watch 1: /tmp/app.conf\n");
+
+        // Reading does not fire; writing does and pauses the writer
+        syscall::set_current_process(target);
+        syscall::read_file("/tmp/app.conf").unwrap();
+        let fd = syscall::open("/tmp/app.conf", syscall::OpenFlags::WRITE).unwrap();
+        syscall::write(fd, b"a=2\n").unwrap();
+        syscall::close(fd).unwrap();
+        syscall::set_current_process(debugger);
+
+        let state = |pid| {
+            syscall::KERNEL.with(|k| {
+                k.borrow()
+                    .proc()
+                    .processes
+                    .get(&pid)
+                    .map(|p| p.state.clone())
+            })
+        };
+        assert_eq!(state(target), Some(syscall::ProcessState::Stopped));
+
+        let (_, stdout, _) = debug(&["hits"]);
+        assert_eq!(
+            stdout,
+            format!(
+                "watch 1: pid {} write /tmp/app.conf: write(fd:{}, 4 bytes) = 4\n",
+                target.0, fd.0
+            )
+        );
+        let (_, stdout, _) = debug(&["watches"]);
+        assert!(stdout.contains("write,unlink"));
+        assert!(stdout.contains("    1 hits  /tmp/app.conf"));
+
+        let (_, stdout, _) = debug(&["continue"]);
+        assert_eq!(stdout, format!("resumed pid {}\n", target.0));
+        assert_eq!(state(target), Some(syscall::ProcessState::Running));
+
+        // Unlinking fires too; once unwatched nothing is recorded
+        syscall::remove_file("/tmp/app.conf").unwrap();
+        let (_, stdout, _) = debug(&[]);
+        assert!(stdout.contains("unlink /tmp/app.conf: unlink(\"/tmp/app.conf\") = 0"));
+        debug(&["continue"]);
+        assert_eq!(debug(&["unwatch", "1"]).0, 0);
+        syscall::write_file("/tmp/app.conf", "a=3\n").unwrap();
+        assert!(debug(&["hits"]).1.is_empty());
+    }
+
+    #[test]
+    fn test_debug_watch_errors() {
+        setup_trace();
+        let (code, _, stderr) = debug(&["watch", "-o", "exec", "/tmp"]);
+        assert_eq!(code, 1);
+        assert!(stderr.contains("invalid operation 'exec'"));
+
+        let (code, _, stderr) = debug(&["unwatch", "9"]);
+        assert_eq!(code, 1);
+        assert!(stderr.contains("not found"));
     }
 }
@@ -1,7 +1,34 @@
 //! System services programs
 
-use super::{args_to_strs, check_help};
 use crate::kernel::syscall;
+use crate::shell::argparse::{Arg, ArgError, ArgKind, CommandSpec};
+
+pub static SYSTEMCTL: CommandSpec = CommandSpec {
+    name: "systemctl",
+    summary: "manage services",
+    description:
+        "Manage the services init runs. With no COMMAND, show this help. COMMAND is one of:
+
+  list-units      List all units
+  status NAME     Show unit status
+  start NAME      Start a unit
+  stop NAME       Stop a unit
+  restart NAME    Restart a unit
+  enable NAME     Enable a unit
+  disable NAME    Disable a unit
+  get-default     Get default target
+  set-default T   Set default target
+  set-property NAME WatchdogSec=N
+                  Set a unit's watchdog timeout (0 = none)
+  set-property NAME Environment=VAR=VALUE | UnsetEnvironment=VAR
+                  Change a unit's environment (from its next start)",
+    flags: &[],
+    args: &[
+        Arg::new("COMMAND", ArgKind::Text).optional(),
+        Arg::new("NAME", ArgKind::Text).optional(),
+        Arg::new("PROPERTY", ArgKind::Text).optional(),
+    ],
+};
 
 /// systemctl - service management
 pub fn prog_systemctl(
@@ -10,20 +37,15 @@ pub fn prog_systemctl(
     stdout: &mut String,
     stderr: &mut String,
 ) -> i32 {
-    let args = args_to_strs(args);
+    let m = match SYSTEMCTL.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&SYSTEMCTL, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
 
     if args.is_empty() {
-        stdout.push_str("Usage: systemctl COMMAND [NAME...]\n\n");
-        stdout.push_str("Commands:\n");
-        stdout.push_str("  list-units      List all units\n");
-        stdout.push_str("  status NAME     Show unit status\n");
-        stdout.push_str("  start NAME      Start a unit\n");
-        stdout.push_str("  stop NAME       Stop a unit\n");
-        stdout.push_str("  restart NAME    Restart a unit\n");
-        stdout.push_str("  enable NAME     Enable a unit\n");
-        stdout.push_str("  disable NAME    Disable a unit\n");
-        stdout.push_str("  get-default     Get default target\n");
-        stdout.push_str("  set-default T   Set default target\n");
+        stdout.push_str(&SYSTEMCTL.help());
+        stdout.push('\n');
         return 0;
     }
 
@@ -75,6 +97,30 @@ pub fn prog_systemctl(
                     stdout.push_str(&format!("     Description: {}\n", status.description));
                     if let Some(pid) = status.pid {
                         stdout.push_str(&format!("     Main PID: {}\n", pid));
+                        let mut vars: Vec<_> = status.environ.iter().collect();
+                        vars.sort();
+                        let vars: Vec<_> =
+                            vars.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+                        stdout.push_str(&format!("     Environment: {}\n", vars.join(" ")));
+                    }
+                    if let Some(timeout) = status.watchdog_ms {
+                        let pet = match status.watchdog.last_pet {
+                            Some(t) => format!("last pet {:.1}s ago", (kernel.now() - t) / 1000.0),
+                            None => "not armed".to_string(),
+                        };
+                        stdout.push_str(&format!(
+                            "     Watchdog: {:.1}s timeout, {}\n",
+                            timeout / 1000.0,
+                            pet
+                        ));
+                    }
+                    if let Some(last) = status.watchdog.last_incident {
+                        stdout.push_str(&format!(
+                            "     Watchdog timeouts: {} (last {:.1}s ago), {} restarts\n",
+                            status.watchdog.incidents,
+                            (kernel.now() - last) / 1000.0,
+                            status.restart_count
+                        ));
                     }
                 } else {
                     stderr.push_str(&format!("Unit {} not found\n", name));
@@ -207,6 +253,54 @@ pub fn prog_systemctl(
                 1
             }
         }
+        "set-property" => {
+            let (Some(name), Some(value)) = (args.get(1), args.get(2)) else {
+                return ArgError::MissingOperand("PROPERTY").report(&SYSTEMCTL, stdout, stderr);
+            };
+            let env_change = match value.split_once('=') {
+                Some(("Environment", assignment)) => match assignment.split_once('=') {
+                    Some((var, val)) => Some((var, Some(val))),
+                    None => {
+                        stderr.push_str(&format!("systemctl: invalid value '{}'\n", value));
+                        return 1;
+                    }
+                },
+                Some(("UnsetEnvironment", var)) => Some((var, None)),
+                _ => None,
+            };
+            if let Some((var, val)) = env_change {
+                return syscall::KERNEL.with(|k| {
+                    match k.borrow_mut().init_mut().set_environment(name, var, val) {
+                        Ok(()) => 0,
+                        Err(e) => {
+                            stderr.push_str(&format!("systemctl: {}\n", e));
+                            1
+                        }
+                    }
+                });
+            }
+            let secs = match value.split_once('=') {
+                Some(("WatchdogSec", secs)) => secs.parse::<f64>().ok().filter(|s| *s >= 0.0),
+                _ => {
+                    stderr.push_str(&format!("systemctl: unknown property '{}'\n", value));
+                    return 1;
+                }
+            };
+            let Some(secs) = secs else {
+                stderr.push_str(&format!("systemctl: invalid value '{}'\n", value));
+                return 1;
+            };
+            let timeout = (secs > 0.0).then_some(secs * 1000.0);
+            syscall::KERNEL.with(
+                |k| match k.borrow_mut().init_mut().set_watchdog(name, timeout) {
+                    Ok(()) => 0,
+                    Err(e) => {
+                        stderr.push_str(&format!("systemctl: {}\n", e));
+                        1
+                    }
+                },
+            )
+        }
         _ => {
             stderr.push_str(&format!("systemctl: unknown command '{}'\n", cmd));
             1
@@ -214,18 +308,23 @@ pub fn prog_systemctl(
     }
 }
 
+pub static REBOOT: CommandSpec = CommandSpec {
+    name: "reboot",
+    summary: "reboot the system",
+    description: "Reboot the system.",
+    flags: &[],
+    args: &[],
+};
+
 /// reboot - reboot the system
 pub fn prog_reboot(
     args: &[String],
     __stdin: &str,
     stdout: &mut String,
-    _stderr: &mut String,
+    stderr: &mut String,
 ) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(&args, "Usage: reboot\nReboot the system.") {
-        stdout.push_str(&help);
-        return 0;
+    if let Err(e) = REBOOT.parse(args) {
+        return e.report(&REBOOT, stdout, stderr);
     }
 
     use crate::kernel::init::Target;
@@ -238,18 +337,23 @@ pub fn prog_reboot(
     0
 }
 
+pub static POWEROFF: CommandSpec = CommandSpec {
+    name: "poweroff",
+    summary: "power off the system",
+    description: "Power off the system.",
+    flags: &[],
+    args: &[],
+};
+
 /// poweroff - power off the system
 pub fn prog_poweroff(
     args: &[String],
     __stdin: &str,
     stdout: &mut String,
-    _stderr: &mut String,
+    stderr: &mut String,
 ) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(&args, "Usage: poweroff\nPower off the system.") {
-        stdout.push_str(&help);
-        return 0;
+    if let Err(e) = POWEROFF.parse(args) {
+        return e.report(&POWEROFF, stdout, stderr);
     }
 
     use crate::kernel::init::Target;
@@ -262,6 +366,361 @@ pub fn prog_poweroff(
     0
 }
 
+pub static BACKUP: CommandSpec = CommandSpec {
+    name: "backup",
+    summary: "back up the filesystem to a remote endpoint",
+    description: "Back up the filesystem, encrypted, to a remote HTTP endpoint.
+
+Commands:
+  status            Show settings and the backups taken (default)
+  now               Take a backup now
+  restore TIME      Replace the filesystem with the backup taken at TIME
+  restore latest    Restore the newest backup
+  config            Show the settings
+  config KEY VALUE  Change a setting
+
+Settings (in /etc/backup.conf):
+  url           Endpoint; {name} is replaced by the object name, which is
+                otherwise appended after a /
+  password      Encryption password
+  authorization Authorization header, e.g. 'Basic dXNlcjpwYXNz'
+  interval      Minutes between automatic backups, 0 for none (60)
+  limit         Upload limit in KiB/s, 0 for none (0)
+  full_every    Incremental backups between full ones (24)
+
+Backups run in the background; their results go to the kernel log (dmesg).
+
+Examples:
+  backup config url https://dav.example.com/axeberg/
+  backup config password hunter2
+  backup now
+  backup restore latest",
+    flags: &[],
+    args: &[
+        Arg::new("COMMAND", ArgKind::Text).optional(),
+        Arg::new("ARG", ArgKind::Text).optional().repeated().raw(),
+    ],
+};
+
+/// How long ago `time` (ms) was, roughly
+pub(crate) fn ago(now: f64, time: f64) -> String {
+    let secs = ((now - time) / 1000.0).max(0.0) as u64;
+    match secs {
+        0..60 => format!("{}s ago", secs),
+        60..3600 => format!("{}m ago", secs / 60),
+        3600..86400 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+/// backup - remote encrypted backups
+pub fn prog_backup(
+    args: &[String],
+    __stdin: &str,
+    stdout: &mut String,
+    stderr: &mut String,
+) -> i32 {
+    use crate::shell::backup::{self, BackupConfig};
+    use crate::shell::stats::clock;
+
+    let m = match BACKUP.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&BACKUP, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
+
+    // The settings hold the password and a restore replaces everything
+    if syscall::geteuid().unwrap_or_default().0 != 0 {
+        stderr.push_str("backup: permission denied (must be root)\n");
+        return 1;
+    }
+
+    let mut config = match backup::load_config() {
+        Ok(config) => config,
+        Err(e) => {
+            stderr.push_str(&format!("backup: {}\n", e));
+            return 1;
+        }
+    };
+    let now = clock();
+
+    match args.as_slice() {
+        [] | ["status"] => {
+            if config.is_configured() {
+                stdout.push_str(&format!("Endpoint: {}\n", config.url));
+            } else {
+                stdout.push_str(&format!(
+                    "Endpoint: not configured (set url and password in {})\n",
+                    backup::CONFIG_PATH
+                ));
+            }
+            let schedule = if config.interval == 0 {
+                "on request only".to_string()
+            } else {
+                format!("every {} min", config.interval)
+            };
+            stdout.push_str(&format!("Schedule: {}\n", schedule));
+            let status = backup::status();
+            if let Some(busy) = status.busy {
+                stdout.push_str(&format!("State:    {}\n", busy));
+            }
+            if let Some(e) = &status.last_error {
+                stdout.push_str(&format!("Last error: {}\n", e));
+            }
+
+            let catalog = backup::read_catalog();
+            if catalog.is_empty() {
+                stdout.push_str("No backups yet\n");
+                return 0;
+            }
+            stdout.push_str(&format!(
+                "\n{:<15} {:<12} {:>8} {:>8} {:>10}  AGE\n",
+                "TIME", "TYPE", "CHANGED", "REMOVED", "BYTES"
+            ));
+            for entry in catalog.iter().rev() {
+                stdout.push_str(&format!(
+                    "{:<15} {:<12} {:>8} {:>8} {:>10}  {}\n",
+                    entry.time,
+                    if entry.is_full() {
+                        "full"
+                    } else {
+                        "incremental"
+                    },
+                    entry.changed,
+                    entry.removed,
+                    entry.bytes,
+                    ago(now, entry.time as f64)
+                ));
+            }
+            0
+        }
+        ["now"] => match backup::start_backup(config, now) {
+            Ok(()) => {
+                stdout.push_str("backup: started, see dmesg for the result\n");
+                0
+            }
+            Err(e) => {
+                stderr.push_str(&format!("backup: {}\n", e));
+                1
+            }
+        },
+        ["restore", time] => {
+            let time = if *time == "latest" {
+                match backup::read_catalog().last() {
+                    Some(entry) => entry.time,
+                    None => {
+                        stderr.push_str("backup: no backups taken from this system\n");
+                        return 1;
+                    }
+                }
+            } else {
+                match time.parse() {
+                    Ok(time) => time,
+                    Err(_) => {
+                        stderr.push_str(&format!("backup: invalid time: {}\n", time));
+                        return 1;
+                    }
+                }
+            };
+            match backup::start_restore(config, time, now) {
+                Ok(()) => {
+                    stdout.push_str(&format!(
+                        "backup: restoring {}, see dmesg for the result\n",
+                        time
+                    ));
+                    0
+                }
+                Err(e) => {
+                    stderr.push_str(&format!("backup: {}\n", e));
+                    1
+                }
+            }
+        }
+        ["config"] => {
+            for key in BackupConfig::KEYS {
+                let value = config.get(key).unwrap_or_default();
+                stdout.push_str(&format!("{:<14} {}\n", key, value));
+            }
+            0
+        }
+        ["config", key, value] => {
+            if let Err(e) = config
+                .set(key, value)
+                .and_then(|()| backup::save_config(&config))
+            {
+                stderr.push_str(&format!("backup: {}\n", e));
+                return 1;
+            }
+            0
+        }
+        _ => {
+            stderr.push_str("backup: invalid arguments (see backup --help)\n");
+            2
+        }
+    }
+}
+
+pub static UPDATE: CommandSpec = CommandSpec {
+    name: "update",
+    summary: "check for and install system updates",
+    description: "Check for a newer axeberg release and install it.
+
+Commands:
+  status            Show the running version and what the last check found
+                    (default)
+  check             Check for a newer release now
+  notes             Show the release notes of the newest release found
+  apply             Save the filesystem and reload into the newer release
+  config            Show the settings
+  config KEY VALUE  Change a setting
+
+Settings (in /etc/update.conf):
+  url           Release manifest URL
+  interval      Minutes between automatic checks, 0 for none (360)
+
+Checks run in the background; their results go to the kernel log (dmesg), and a newer release is announced at the prompt once.",
+    flags: &[],
+    args: &[
+        Arg::new("COMMAND", ArgKind::Text).optional(),
+        Arg::new("ARG", ArgKind::Text).optional().repeated().raw(),
+    ],
+};
+
+/// update - check for and install system updates
+pub fn prog_update(
+    args: &[String],
+    __stdin: &str,
+    stdout: &mut String,
+    stderr: &mut String,
+) -> i32 {
+    use crate::shell::stats::clock;
+    use crate::shell::update::{self, UpdateConfig};
+
+    let m = match UPDATE.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&UPDATE, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
+
+    let mut config = match update::load_config() {
+        Ok(config) => config,
+        Err(e) => {
+            stderr.push_str(&format!("update: {}\n", e));
+            return 1;
+        }
+    };
+    let now = clock();
+    let state = update::read_state();
+
+    match args.as_slice() {
+        [] | ["status"] => {
+            stdout.push_str(&format!("Running:  axeberg {}\n", update::VERSION));
+            if config.url.is_empty() {
+                stdout.push_str(&format!(
+                    "Manifest: not configured (set url in {})\n",
+                    update::CONFIG_PATH
+                ));
+            } else {
+                stdout.push_str(&format!("Manifest: {}\n", config.url));
+            }
+            let schedule = if config.interval == 0 {
+                "on request only".to_string()
+            } else {
+                format!("every {} min", config.interval)
+            };
+            stdout.push_str(&format!("Schedule: {}\n", schedule));
+            let status = update::status();
+            if status.busy {
+                stdout.push_str("State:    checking\n");
+            }
+            if let Some(checked) = state.checked {
+                stdout.push_str(&format!("Checked:  {}\n", ago(now, checked)));
+            }
+            match &state.latest {
+                Some(release) if release.is_newer() => stdout.push_str(&format!(
+                    "Latest:   {} (run 'update apply' to install it)\n",
+                    release.version
+                )),
+                Some(_) => stdout.push_str("Latest:   up to date\n"),
+                None => {}
+            }
+            if let Some(e) = &status.last_error {
+                stdout.push_str(&format!("Last error: {}\n", e));
+            }
+            0
+        }
+        ["check"] => match update::start_check(config, now) {
+            Ok(()) => {
+                stdout.push_str("update: checking, see dmesg for the result\n");
+                0
+            }
+            Err(e) => {
+                stderr.push_str(&format!("update: {}\n", e));
+                1
+            }
+        },
+        ["notes"] => {
+            let Some(release) = state.latest else {
+                stderr.push_str("update: no release found yet, run 'update check'\n");
+                return 1;
+            };
+            stdout.push_str(&format!("axeberg {}\n\n", release.version));
+            match (&release.notes, &release.notes_url) {
+                (Some(notes), _) => {
+                    stdout.push_str(notes);
+                    if !notes.ends_with('\n') {
+                        stdout.push('\n');
+                    }
+                }
+                (None, Some(url)) => stdout.push_str(&format!(
+                    "Release notes could not be fetched from {}\n",
+                    url
+                )),
+                (None, None) => stdout.push_str("No release notes.\n"),
+            }
+            0
+        }
+        ["apply"] => match update::apply() {
+            Ok(version) => {
+                stdout.push_str(&format!(
+                    "update: saving the filesystem and reloading into {}\n",
+                    version
+                ));
+                0
+            }
+            Err(e) => {
+                stderr.push_str(&format!("update: {}\n", e));
+                1
+            }
+        },
+        ["config"] => {
+            for key in UpdateConfig::KEYS {
+                let value = config.get(key).unwrap_or_default();
+                stdout.push_str(&format!("{:<14} {}\n", key, value));
+            }
+            0
+        }
+        ["config", key, value] => {
+            if syscall::geteuid().unwrap_or_default().0 != 0 {
+                stderr.push_str("update: permission denied (must be root)\n");
+                return 1;
+            }
+            if let Err(e) = config
+                .set(key, value)
+                .and_then(|()| update::save_config(&config))
+            {
+                stderr.push_str(&format!("update: {}\n", e));
+                return 1;
+            }
+            0
+        }
+        _ => {
+            stderr.push_str("update: invalid arguments (see update --help)\n");
+            2
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -297,6 +756,82 @@ mod tests {
         assert!(stderr.contains("unit name required"));
     }
 
+    #[test]
+    fn test_systemctl_watchdog() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+
+        KERNEL.with(|k| {
+            *k.borrow_mut() = Kernel::new();
+            let pid = k.borrow_mut().spawn_process("test", None);
+            k.borrow_mut().set_current(pid);
+        });
+        let run = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = prog_systemctl(&args, "", &mut stdout, &mut stderr);
+            (code, stdout + &stderr)
+        };
+
+        assert_eq!(run(&["set-property", "shell", "WatchdogSec=2"]).0, 0);
+        assert_eq!(run(&["set-property", "shell", "Nice=5"]).0, 1);
+        assert_eq!(run(&["set-property", "nope", "WatchdogSec=2"]).0, 1);
+        run(&["start", "shell"]);
+        syscall::check_watchdogs();
+        let (_, out) = run(&["status", "shell"]);
+        assert!(
+            out.contains("Watchdog: 2.0s timeout, last pet 0.0s ago"),
+            "{out}"
+        );
+
+        syscall::set_time(3000.0);
+        assert_eq!(syscall::check_watchdogs().len(), 1);
+        let (_, out) = run(&["status", "shell"]);
+        assert!(
+            out.contains("Watchdog timeouts: 1 (last 0.0s ago)"),
+            "{out}"
+        );
+        assert!(out.contains("not armed"), "{out}");
+    }
+
+    #[test]
+    fn test_systemctl_environment() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+
+        KERNEL.with(|k| {
+            *k.borrow_mut() = Kernel::new();
+            let pid = k.borrow_mut().spawn_process("test", None);
+            k.borrow_mut().set_current(pid);
+        });
+        let run = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = prog_systemctl(&args, "", &mut stdout, &mut stderr);
+            (code, stdout + &stderr)
+        };
+
+        // The shell's own variables don't leak into the service
+        syscall::setenv("SECRET", "x").unwrap();
+        assert_eq!(run(&["set-property", "shell", "Environment=LANG=C"]).0, 0);
+        assert_eq!(run(&["set-property", "shell", "Environment=LANG"]).0, 1);
+        run(&["start", "shell"]);
+        let (_, out) = run(&["status", "shell"]);
+        assert!(
+            out.contains("Environment: LANG=C PATH=/bin:/usr/bin:/sbin\n"),
+            "{out}"
+        );
+
+        assert_eq!(
+            run(&["set-property", "shell", "UnsetEnvironment=LANG"]).0,
+            0
+        );
+        run(&["restart", "shell"]);
+        let (_, out) = run(&["status", "shell"]);
+        assert!(
+            out.contains("Environment: PATH=/bin:/usr/bin:/sbin\n"),
+            "{out}"
+        );
+    }
+
     #[test]
     fn test_reboot_help() {
         let args = vec!["--help".to_string()];
@@ -316,4 +851,109 @@ mod tests {
         assert_eq!(result, 0);
         assert!(stdout.contains("Usage: poweroff"));
     }
+
+    #[test]
+    fn test_backup() {
+        let run = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = prog_backup(&args, "", &mut stdout, &mut stderr);
+            (code, stdout + &stderr)
+        };
+        use crate::kernel::syscall::{KERNEL, Kernel};
+        use crate::kernel::users::Uid;
+
+        KERNEL.with(|k| {
+            *k.borrow_mut() = Kernel::new();
+            let pid = k.borrow_mut().spawn_process("sh", None);
+            k.borrow_mut().set_current(pid);
+        });
+        assert!(run(&["--help"]).1.contains("Usage: backup"));
+        let (code, out) = run(&["status"]);
+        assert_eq!(code, 1);
+        assert!(out.contains("must be root"), "{out}");
+
+        KERNEL.with(|k| k.borrow_mut().current_process_mut().unwrap().euid = Uid::ROOT);
+        let (code, out) = run(&[]);
+        assert_eq!(code, 0, "{out}");
+        assert!(out.contains("not configured"), "{out}");
+        assert!(out.contains("No backups yet"), "{out}");
+
+        assert_eq!(run(&["config", "url", "https://dav.example.com/b"]).0, 0);
+        assert_eq!(run(&["config", "password", "pw"]).0, 0);
+        let (code, out) = run(&["config", "limit", "fast"]);
+        assert_eq!(code, 1);
+        assert!(out.contains("not a number"), "{out}");
+        let (_, out) = run(&["config"]);
+        assert!(
+            out.contains("url            https://dav.example.com/b"),
+            "{out}"
+        );
+        assert!(out.contains("password       ********"), "{out}");
+
+        // Uploads need the browser's fetch
+        let (code, out) = run(&["now"]);
+        assert_eq!(code, 0, "{out}");
+        assert_eq!(
+            crate::shell::backup::status().last_error.as_deref(),
+            Some("remote backups need a browser")
+        );
+        let (code, out) = run(&["restore", "latest"]);
+        assert_eq!(code, 1);
+        assert!(out.contains("no backups"), "{out}");
+        assert_eq!(run(&["restore", "soon"]).0, 1);
+    }
+
+    #[test]
+    fn test_update() {
+        let run = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = prog_update(&args, "", &mut stdout, &mut stderr);
+            (code, stdout + &stderr)
+        };
+        use crate::kernel::syscall::{KERNEL, Kernel};
+        use crate::kernel::users::Uid;
+
+        KERNEL.with(|k| {
+            *k.borrow_mut() = Kernel::new();
+            let pid = k.borrow_mut().spawn_process("sh", None);
+            k.borrow_mut().set_current(pid);
+        });
+        assert!(run(&["--help"]).1.contains("Usage: update"));
+        let (code, out) = run(&[]);
+        assert_eq!(code, 0, "{out}");
+        assert!(out.contains("not configured"), "{out}");
+        assert_eq!(run(&["check"]).0, 1);
+        let (code, out) = run(&["notes"]);
+        assert_eq!(code, 1);
+        assert!(out.contains("no release found"), "{out}");
+
+        let (code, out) = run(&["config", "url", "https://example.com/release.json"]);
+        assert_eq!(code, 1);
+        assert!(out.contains("must be root"), "{out}");
+        KERNEL.with(|k| k.borrow_mut().current_process_mut().unwrap().euid = Uid::ROOT);
+        assert_eq!(
+            run(&["config", "url", "https://example.com/release.json"]).0,
+            0
+        );
+        assert_eq!(run(&["config", "interval", "often"]).0, 1);
+        let (_, out) = run(&["config"]);
+        assert!(
+            out.contains("url            https://example.com/release.json"),
+            "{out}"
+        );
+
+        // Fetching needs the browser
+        let (code, out) = run(&["check"]);
+        assert_eq!(code, 0, "{out}");
+        assert_eq!(
+            crate::shell::update::status().last_error.as_deref(),
+            Some("checking for updates needs a browser")
+        );
+        let (code, out) = run(&["apply"]);
+        assert_eq!(code, 1);
+        assert!(out.contains("no newer release"), "{out}");
+        assert_eq!(run(&["later"]).0, 2);
+    }
 }
@@ -1,29 +1,69 @@
 //! Shell utility programs
 
-use super::{args_to_strs, check_help};
+use super::services::ago;
+use super::{random_below, read_file_content};
 use crate::kernel::syscall;
+use crate::shell::argparse::{Arg, ArgKind, CommandSpec, Flag};
 use crate::shell::builtins;
 use crate::shell::executor::ProgramRegistry;
+use crate::shell::{journal, stats, swap};
+use std::borrow::Cow;
+
+pub static CLEAR: CommandSpec = CommandSpec {
+    name: "clear",
+    summary: "clear the terminal screen",
+    description: "Clear the terminal screen and move the cursor to the top left.",
+    flags: &[],
+    args: &[],
+};
 
 /// clear - clear the terminal screen
-pub fn prog_clear(
-    _args: &[String],
-    _stdin: &str,
-    stdout: &mut String,
-    _stderr: &mut String,
-) -> i32 {
+pub fn prog_clear(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    if let Err(e) = CLEAR.parse(args) {
+        return e.report(&CLEAR, stdout, stderr);
+    }
     stdout.push_str("\x1b[2J\x1b[H");
     0
 }
 
-/// history - display command history
+pub static HISTORY: CommandSpec = CommandSpec {
+    name: "history",
+    summary: "display command history",
+    description: "Print the command history. COMMAND is one of:
+
+  N                the last N commands
+  PATH             the versions of PATH kept in snapshots, oldest first, and the journaled commands that changed it
+  show PATH@N      print version N of PATH
+  restore PATH@N   write version N back over PATH",
+    flags: &[Flag::short('c', "Clear the history (not supported)")],
+    args: &[
+        Arg::new("COMMAND", ArgKind::Text).optional(),
+        Arg::new("PATH@N", ArgKind::Text).optional(),
+    ],
+};
+
+/// history - display command history, or the versions of a file kept in
+/// snapshots
 pub fn prog_history(
     args: &[String],
     _stdin: &str,
     stdout: &mut String,
-    _stderr: &mut String,
+    stderr: &mut String,
 ) -> i32 {
-    let args = args_to_strs(args);
+    let m = match HISTORY.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&HISTORY, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
+
+    match args.as_slice() {
+        ["show", spec] => return file_version(spec, false, stdout, stderr),
+        ["restore", spec] => return file_version(spec, true, stdout, stderr),
+        [path] if path.parse::<usize>().is_err() => {
+            return file_history(path, stdout, stderr);
+        }
+        _ => {}
+    }
 
     // Get history from terminal module
     #[cfg(target_arch = "wasm32")]
@@ -33,7 +73,7 @@ pub fn prog_history(
     let history: Vec<String> = Vec::new();
 
     // Check for -c (clear) flag
-    if args.contains(&"-c") {
+    if m.flag("c") {
         // Can't clear history from here - would need terminal module support
         stdout.push_str("history: clearing not supported\n");
         return 0;
@@ -58,20 +98,151 @@ pub fn prog_history(
     0
 }
 
+/// Commands `history PATH` lists from the journal
+const HISTORY_COMMANDS: usize = 10;
+
+/// `history PATH`: the versions of a file the snapshots hold, numbered
+/// oldest first, and the journaled commands that changed it
+fn file_history(path: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let path = super::absolute(path);
+    let versions = match syscall::snapshot_versions(&path) {
+        Ok(versions) => versions,
+        Err(e) => {
+            stderr.push_str(&format!("history: {}: {}\n", path, e));
+            return 1;
+        }
+    };
+    let current = super::read_file_bytes(&path).ok();
+
+    if versions.is_empty() {
+        stdout.push_str(&format!("No versions of {} in snapshots\n", path));
+    } else {
+        let now = stats::clock();
+        stdout.push_str(&format!(
+            "{:>7}  {:<10} {:>8}  SNAPSHOT\n",
+            "VERSION", "TAKEN", "BYTES"
+        ));
+        for (i, version) in versions.iter().enumerate() {
+            let mark = if current.as_ref() == Some(&version.content) {
+                " (current)"
+            } else {
+                ""
+            };
+            stdout.push_str(&format!(
+                "{:>7}  {:<10} {:>8}  {}{}\n",
+                i + 1,
+                ago(now, version.time),
+                version.content.len(),
+                version.snapshot,
+                mark
+            ));
+        }
+    }
+
+    let entries = journal::read_entries();
+    let changed: Vec<_> = entries
+        .iter()
+        .filter_map(|entry| {
+            let change = entry.changes.iter().find(|c| c.path == path)?;
+            Some((entry, change.kind.marker()))
+        })
+        .collect();
+    if !changed.is_empty() {
+        stdout.push_str("\nChanged by (journal):\n");
+        for (entry, marker) in changed
+            .iter()
+            .skip(changed.len().saturating_sub(HISTORY_COMMANDS))
+        {
+            stdout.push_str(&format!("{:>7}  {} {}\n", entry.seq, marker, entry.command));
+        }
+    }
+    0
+}
+
+/// `history show PATH@N` and `history restore PATH@N`: print version N
+/// of a file, or write it back over the file
+fn file_version(spec: &str, restore: bool, stdout: &mut String, stderr: &mut String) -> i32 {
+    let Some((path, n)) = spec
+        .rsplit_once('@')
+        .and_then(|(path, n)| Some((path, n.parse::<usize>().ok()?)))
+        .filter(|(path, n)| !path.is_empty() && *n > 0)
+    else {
+        stderr.push_str(&format!("history: {}: expected PATH@N\n", spec));
+        return 1;
+    };
+    let path = super::absolute(path);
+    let versions = match syscall::snapshot_versions(&path) {
+        Ok(versions) => versions,
+        Err(e) => {
+            stderr.push_str(&format!("history: {}: {}\n", path, e));
+            return 1;
+        }
+    };
+    let Some(version) = versions.get(n - 1) else {
+        stderr.push_str(&format!(
+            "history: {}: no version {} ({} kept)\n",
+            path,
+            n,
+            versions.len()
+        ));
+        return 1;
+    };
+
+    if !restore {
+        stdout.push_str(&String::from_utf8_lossy(&version.content));
+        return 0;
+    }
+    let written = syscall::open(&path, syscall::OpenFlags::WRITE).and_then(|fd| {
+        let written = syscall::write(fd, &version.content);
+        let _ = syscall::close(fd);
+        written
+    });
+    match written {
+        Ok(_) => {
+            stdout.push_str(&format!(
+                "history: restored {} to version {} (snapshot {})\n",
+                path, n, version.snapshot
+            ));
+            0
+        }
+        Err(e) => {
+            stderr.push_str(&format!("history: {}: {}\n", path, e));
+            1
+        }
+    }
+}
+
+pub static EDIT: CommandSpec = CommandSpec {
+    name: "edit",
+    summary: "open the text editor",
+    description: "Open FILE in the text editor. Ctrl+Q to quit, Ctrl+S to save. Set keymap = \"vi\" in ~/.config/editor.toml for vi keys. Markdown and text files are spell checked: Ctrl+E finds the next misspelling. See 'man edit' for details.",
+    flags: &[Flag::short('r', "List unsaved buffers kept in swap files")],
+    args: &[Arg::new("FILE", ArgKind::Path).optional()],
+};
+
 /// Text editor - opens a file for editing
 #[allow(unused_variables)]
 pub fn prog_edit(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
+    let m = match EDIT.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&EDIT, stdout, stderr),
+    };
 
-    if let Some(help) = check_help(
-        &args,
-        "Usage: edit [FILE]\nOpen text editor. Ctrl+Q to quit, Ctrl+S to save. See 'man edit' for details.",
-    ) {
-        stdout.push_str(&help);
+    if m.flag("r") {
+        let swaps = swap::list();
+        if swaps.is_empty() {
+            stdout.push_str(&format!("No swap files in {}", swap::swap_dir()));
+            return 0;
+        }
+        let now = stats::clock();
+        for swap in swaps {
+            stdout.push_str(&format!("{}\t{}\n", swap.path, ago(now, swap.time)));
+        }
+        stdout.pop();
         return 0;
     }
 
-    let filename = args.first().copied();
+    let filename = m.operand(0);
 
     #[cfg(target_arch = "wasm32")]
     {
@@ -95,26 +266,67 @@ pub fn prog_edit(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mu
     }
 }
 
-/// man - display manual pages
-pub fn prog_man(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
+pub static CHARS: CommandSpec = CommandSpec {
+    name: "chars",
+    summary: "pick a character to insert",
+    description: "Pick a character from a grid and insert it at the prompt. Starts with a search for NAME if given. Alt+U opens the picker while typing a command or in the editor. 'unicode' describes characters. See 'man chars' for details.",
+    flags: &[],
+    args: &[Arg::new("NAME", ArgKind::Text).optional().repeated()],
+};
+
+/// chars - pick a character to insert
+pub fn prog_chars(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match CHARS.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&CHARS, stdout, stderr),
+    };
 
-    if let Some(help) = check_help(
-        &args,
-        "Usage: man COMMAND\nDisplay manual page for a command. See 'man man' for details.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
+    #[cfg(target_arch = "wasm32")]
+    {
+        let _ = stderr;
+        crate::terminal::open_chars(&m.operands.join(" "));
+        0
     }
 
-    if args.is_empty() {
-        stderr.push_str("What manual page do you want?\n");
-        return 1;
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let _ = m;
+        stderr.push_str("chars: not available in this environment\n");
+        1
     }
+}
+
+pub static MAN: CommandSpec = CommandSpec {
+    name: "man",
+    summary: "display manual pages",
+    description: "Display the manual page for COMMAND. See 'man man' for details.",
+    flags: &[],
+    args: &[Arg::new("COMMAND", ArgKind::Command)],
+};
+
+/// man - display manual pages
+pub fn prog_man(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match MAN.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&MAN, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
 
     let page = args[0];
+    match manual(page) {
+        Some(content) => {
+            stdout.push_str(content.trim());
+            0
+        }
+        None => {
+            stderr.push_str(&format!("No manual entry for {}\n", page));
+            1
+        }
+    }
+}
 
-    // Embedded man pages (pre-rendered from scdoc)
+/// Embedded manual page for a command (pre-rendered from scdoc)
+pub fn man_page(page: &str) -> Option<&'static str> {
     let content = match page {
         "basename" => include_str!("../../../man/formatted/basename.txt"),
         "base64" => include_str!("../../../man/formatted/base64.txt"),
@@ -122,8 +334,10 @@ pub fn prog_man(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut
         "cal" => include_str!("../../../man/formatted/cal.txt"),
         "cat" => include_str!("../../../man/formatted/cat.txt"),
         "cd" => include_str!("../../../man/formatted/cd.txt"),
+        "chars" => include_str!("../../../man/formatted/chars.txt"),
         "comm" => include_str!("../../../man/formatted/comm.txt"),
         "cp" => include_str!("../../../man/formatted/cp.txt"),
+        "crypt" => include_str!("../../../man/formatted/crypt.txt"),
         "cut" => include_str!("../../../man/formatted/cut.txt"),
         "date" => include_str!("../../../man/formatted/date.txt"),
         "df" => include_str!("../../../man/formatted/df.txt"),
@@ -132,11 +346,13 @@ pub fn prog_man(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut
         "du" => include_str!("../../../man/formatted/du.txt"),
         "echo" => include_str!("../../../man/formatted/echo.txt"),
         "edit" => include_str!("../../../man/formatted/edit.txt"),
+        "exportfs" => include_str!("../../../man/formatted/exportfs.txt"),
         "expr" => include_str!("../../../man/formatted/expr.txt"),
         "fg" => include_str!("../../../man/formatted/fg.txt"),
         "find" => include_str!("../../../man/formatted/find.txt"),
         "fold" => include_str!("../../../man/formatted/fold.txt"),
         "free" => include_str!("../../../man/formatted/free.txt"),
+        "fsck" => include_str!("../../../man/formatted/fsck.txt"),
         "grep" => include_str!("../../../man/formatted/grep.txt"),
         "head" => include_str!("../../../man/formatted/head.txt"),
         "hostname" => include_str!("../../../man/formatted/hostname.txt"),
@@ -146,7 +362,9 @@ pub fn prog_man(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut
         "ln" => include_str!("../../../man/formatted/ln.txt"),
         "ls" => include_str!("../../../man/formatted/ls.txt"),
         "man" => include_str!("../../../man/formatted/man.txt"),
+        "merge3" => include_str!("../../../man/formatted/merge3.txt"),
         "mkdir" => include_str!("../../../man/formatted/mkdir.txt"),
+        "mktemp" => include_str!("../../../man/formatted/mktemp.txt"),
         "mv" => include_str!("../../../man/formatted/mv.txt"),
         "nl" => include_str!("../../../man/formatted/nl.txt"),
         "paste" => include_str!("../../../man/formatted/paste.txt"),
@@ -154,10 +372,15 @@ pub fn prog_man(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut
         "printf" => include_str!("../../../man/formatted/printf.txt"),
         "ps" => include_str!("../../../man/formatted/ps.txt"),
         "pwd" => include_str!("../../../man/formatted/pwd.txt"),
+        "qr" => include_str!("../../../man/formatted/qr.txt"),
+        "quota" => include_str!("../../../man/formatted/quota.txt"),
         "rev" => include_str!("../../../man/formatted/rev.txt"),
         "rm" => include_str!("../../../man/formatted/rm.txt"),
         "seq" => include_str!("../../../man/formatted/seq.txt"),
+        "shuf" => include_str!("../../../man/formatted/shuf.txt"),
+        "snapshot" => include_str!("../../../man/formatted/snapshot.txt"),
         "sort" => include_str!("../../../man/formatted/sort.txt"),
+        "spell" => include_str!("../../../man/formatted/spell.txt"),
         "strace" => include_str!("../../../man/formatted/strace.txt"),
         "strings" => include_str!("../../../man/formatted/strings.txt"),
         "tail" => include_str!("../../../man/formatted/tail.txt"),
@@ -168,26 +391,193 @@ pub fn prog_man(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut
         "touch" => include_str!("../../../man/formatted/touch.txt"),
         "tr" => include_str!("../../../man/formatted/tr.txt"),
         "tree" => include_str!("../../../man/formatted/tree.txt"),
+        "tutorial" => include_str!("../../../man/formatted/tutorial.txt"),
         "type" => include_str!("../../../man/formatted/type.txt"),
+        "umask" => include_str!("../../../man/formatted/umask.txt"),
         "uname" => include_str!("../../../man/formatted/uname.txt"),
+        "unicode" => include_str!("../../../man/formatted/unicode.txt"),
         "uniq" => include_str!("../../../man/formatted/uniq.txt"),
+        "unzip" => include_str!("../../../man/formatted/unzip.txt"),
+        "update" => include_str!("../../../man/formatted/update.txt"),
         "uptime" => include_str!("../../../man/formatted/uptime.txt"),
+        "uuidgen" => include_str!("../../../man/formatted/uuidgen.txt"),
+        "vfs" => include_str!("../../../man/formatted/vfs.txt"),
         "wc" => include_str!("../../../man/formatted/wc.txt"),
         "which" => include_str!("../../../man/formatted/which.txt"),
         "whoami" => include_str!("../../../man/formatted/whoami.txt"),
+        "widgets" => include_str!("../../../man/formatted/widgets.txt"),
         "xargs" => include_str!("../../../man/formatted/xargs.txt"),
         "xxd" => include_str!("../../../man/formatted/xxd.txt"),
         "yes" => include_str!("../../../man/formatted/yes.txt"),
-        _ => {
-            stderr.push_str(&format!("No manual entry for {}\n", page));
-            return 1;
+        "zip" => include_str!("../../../man/formatted/zip.txt"),
+        _ => return None,
+    };
+    Some(content)
+}
+
+/// The manual page for a command: the hand-written one, or else one
+/// generated from the command's spec
+pub fn manual(page: &str) -> Option<Cow<'static, str>> {
+    man_page(page)
+        .map(Cow::Borrowed)
+        .or_else(|| super::spec(page).map(|spec| Cow::Owned(spec.man_page())))
+}
+
+/// One-line summary of a command from the NAME section of its manual page
+pub fn man_summary(page: &str) -> Option<&'static str> {
+    let Some(content) = man_page(page) else {
+        return super::spec(page).map(|spec| spec.summary);
+    };
+    let mut lines = content.lines().skip_while(|l| l.trim() != "NAME").skip(1);
+    let (_, summary) = lines.next()?.split_once(" - ")?;
+    Some(summary.trim())
+}
+
+pub static TUTORIAL: CommandSpec = CommandSpec {
+    name: "tutorial",
+    summary: "guided lessons for new users",
+    description: "Guided lessons for the shell, editor and desktop. With no arguments, show the current step or list the lessons. Steps complete on their own as you run commands. COMMAND is one of:
+
+  LESSON   start a lesson from the beginning
+  list     list lessons and what you have finished
+  hint     show how to do the current step
+  skip     move on to the next step
+  stop     leave the current lesson",
+    flags: &[],
+    args: &[
+        Arg::new("COMMAND", ArgKind::Text).optional(),
+        Arg::new("LESSON", ArgKind::Text).optional(),
+    ],
+};
+
+/// tutorial - guided lessons for new users
+pub fn prog_tutorial(
+    args: &[String],
+    _stdin: &str,
+    stdout: &mut String,
+    stderr: &mut String,
+) -> i32 {
+    use crate::shell::tutorial::{self, LESSONS, Observed};
+
+    let m = match TUTORIAL.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&TUTORIAL, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
+
+    let list = |stdout: &mut String, progress: &tutorial::Progress| {
+        stdout.push_str("Lessons:\n");
+        for lesson in LESSONS {
+            let mark = if progress.done.contains(lesson.name) {
+                '✓'
+            } else {
+                ' '
+            };
+            stdout.push_str(&format!(
+                "  {} {:<8} {} ({} steps)\n",
+                mark,
+                lesson.name,
+                lesson.title,
+                lesson.steps.len()
+            ));
         }
+        stdout.push_str("Start one with 'tutorial LESSON'.\n");
     };
 
-    stdout.push_str(content.trim());
-    0
+    let mut progress = tutorial::load();
+    match args.as_slice() {
+        [] => {
+            // Steps done outside a command (in the editor) are noticed here
+            let cwd = syscall::getcwd()
+                .map(|p| p.display().to_string())
+                .unwrap_or_default();
+            if let Some(out) = tutorial::observe(&Observed {
+                line: None,
+                code: 0,
+                cwd: &cwd,
+            }) {
+                stdout.push_str(&out);
+                stdout.push('\n');
+                return 0;
+            }
+            match progress.current_step() {
+                Some((lesson, step)) => {
+                    stdout.push_str(&format!("Lesson: {}\n", lesson.title));
+                    stdout.push_str(&tutorial::describe(lesson, step));
+                    stdout.push_str("\n(tutorial hint shows how)\n");
+                }
+                None => list(stdout, &progress),
+            }
+            0
+        }
+        ["list"] => {
+            list(stdout, &progress);
+            0
+        }
+        ["hint"] | ["skip"] | ["stop"] => {
+            let Some((lesson, step)) = progress.current_step() else {
+                stderr.push_str("tutorial: no lesson in progress\n");
+                return 1;
+            };
+            match args[0] {
+                "hint" => {
+                    stdout.push_str(&format!("Try: {}\n", lesson.steps[step].hint));
+                    return 0;
+                }
+                "skip" if step + 1 < lesson.steps.len() => {
+                    progress.current = Some((lesson.name.to_string(), step + 1));
+                    stdout.push_str(&tutorial::describe(lesson, step + 1));
+                    stdout.push('\n');
+                }
+                _ => {
+                    progress.current = None;
+                    stdout.push_str(&format!("Left lesson '{}'.\n", lesson.name));
+                }
+            }
+            match tutorial::save(&progress) {
+                Ok(()) => 0,
+                Err(e) => {
+                    stderr.push_str(&format!("tutorial: {}\n", e));
+                    1
+                }
+            }
+        }
+        [name] | ["start", name] => {
+            let Some(lesson) = tutorial::lesson(name) else {
+                stderr.push_str(&format!(
+                    "tutorial: no lesson named '{}' (see tutorial list)\n",
+                    name
+                ));
+                return 1;
+            };
+            progress.current = Some((lesson.name.to_string(), 0));
+            if let Err(e) = tutorial::save(&progress) {
+                stderr.push_str(&format!("tutorial: {}\n", e));
+                return 1;
+            }
+            stdout.push_str(&format!(
+                "Lesson: {} ({} steps)\n{}\n",
+                lesson.title,
+                lesson.steps.len(),
+                tutorial::describe(lesson, 0)
+            ));
+            0
+        }
+        _ => {
+            stderr.push_str("tutorial: invalid arguments (see tutorial --help)\n");
+            2
+        }
+    }
 }
 
+pub static PRINTENV: CommandSpec = CommandSpec {
+    name: "printenv",
+    summary: "print environment variables",
+    description: "Print the environment of the kernel process, or the values of the variables NAME.",
+    flags: &[],
+    args: &[Arg::new("NAME", ArgKind::Text).optional().repeated()],
+};
+
 /// printenv - print environment variables (uses kernel syscalls)
 pub fn prog_printenv(
     args: &[String],
@@ -195,15 +585,11 @@ pub fn prog_printenv(
     stdout: &mut String,
     stderr: &mut String,
 ) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: printenv [NAME...]\nPrint environment variables from the kernel process.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
+    let m = match PRINTENV.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&PRINTENV, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
 
     // Get environment from kernel
     match syscall::environ() {
@@ -233,22 +619,25 @@ pub fn prog_printenv(
     }
 }
 
+pub static SEQ: CommandSpec = CommandSpec {
+    name: "seq",
+    summary: "print sequence of numbers",
+    description: "Print the numbers from FIRST (default 1) to LAST, INCREMENT (default 1) apart.",
+    flags: &[],
+    args: &[
+        Arg::new("FIRST", ArgKind::Text).optional(),
+        Arg::new("INCREMENT", ArgKind::Text).optional(),
+        Arg::new("LAST", ArgKind::Text),
+    ],
+};
+
 /// seq - print sequence of numbers
 pub fn prog_seq(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: seq [FIRST] [INCREMENT] LAST\nPrint sequence of numbers.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
-
-    if args.is_empty() {
-        stderr.push_str("seq: missing operand\n");
-        return 1;
-    }
+    let m = match SEQ.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&SEQ, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
 
     // Parse arguments
     let (first, increment, last) = match args.len() {
@@ -286,17 +675,21 @@ pub fn prog_seq(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut
     0
 }
 
+pub static YES: CommandSpec = CommandSpec {
+    name: "yes",
+    summary: "output a string repeatedly",
+    description: "Repeatedly output STRING, y by default (limited to 100 lines).",
+    flags: &[],
+    args: &[Arg::new("STRING", ArgKind::Text).optional().raw()],
+};
+
 /// yes - output string repeatedly (limited iterations for safety)
-pub fn prog_yes(args: &[String], _stdin: &str, stdout: &mut String, _stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: yes [STRING]\nRepeatedly output STRING (limited to 100 lines).",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
+pub fn prog_yes(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match YES.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&YES, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
 
     let text = if args.is_empty() { "y" } else { args[0] };
 
@@ -309,27 +702,200 @@ pub fn prog_yes(args: &[String], _stdin: &str, stdout: &mut String, _stderr: &mu
     0
 }
 
-/// basename - strip directory and suffix from filename
-pub fn prog_basename(
+pub static UUIDGEN: CommandSpec = CommandSpec {
+    name: "uuidgen",
+    summary: "print random UUIDs",
+    description: "Print random (version 4) UUIDs from the kernel's CSPRNG. See 'man uuidgen' for details.",
+    flags: &[
+        Flag::short('r', "Random UUID (the default)"),
+        Flag::short('C', "Print COUNT UUIDs, one per line").value("COUNT"),
+    ],
+    args: &[],
+};
+
+/// uuidgen - print random (version 4) UUIDs
+pub fn prog_uuidgen(
     args: &[String],
     _stdin: &str,
     stdout: &mut String,
     stderr: &mut String,
 ) -> i32 {
-    let args = args_to_strs(args);
+    let m = match UUIDGEN.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&UUIDGEN, stdout, stderr),
+    };
 
-    if let Some(help) = check_help(
-        &args,
-        "Usage: basename PATH [SUFFIX]\nStrip directory and suffix from PATH.",
-    ) {
-        stdout.push_str(&help);
+    let count = match m.value("C").map(str::parse::<usize>) {
+        None => 1,
+        Some(Ok(n)) => n,
+        Some(Err(_)) => {
+            stderr.push_str("uuidgen: -C needs a count\n");
+            return 1;
+        }
+    };
+
+    for _ in 0..count {
+        let mut bytes = [0u8; 16];
+        if let Err(e) = syscall::getrandom(&mut bytes) {
+            stderr.push_str(&format!("uuidgen: {}\n", e));
+            return 1;
+        }
+        // Version 4, RFC 4122 variant
+        bytes[6] = (bytes[6] & 0x0f) | 0x40;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+        let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        stdout.push_str(&format!(
+            "{}-{}-{}-{}-{}\n",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        ));
+    }
+    0
+}
+
+pub static SHUF: CommandSpec = CommandSpec {
+    name: "shuf",
+    summary: "print lines in random order",
+    description: "Print the lines of FILE (or stdin) in random order. With -e, shuffle the ARGs instead; with -i, the numbers LO to HI. See 'man shuf' for details.",
+    flags: &[
+        Flag::short('n', "Print at most COUNT lines").value("COUNT"),
+        Flag::short('r', "Pick with repetition (needs -n)"),
+        Flag::short('e', "Shuffle the arguments instead"),
+        Flag::short('i', "Shuffle the numbers LO to HI").value("LO-HI"),
+    ],
+    args: &[Arg::new("FILE", ArgKind::Path).optional().repeated()],
+};
+
+/// shuf - print lines in random order
+pub fn prog_shuf(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match SHUF.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&SHUF, stdout, stderr),
+    };
+
+    let count = match m.value("n").map(str::parse::<usize>) {
+        None => None,
+        Some(Ok(n)) => Some(n),
+        Some(Err(_)) => {
+            stderr.push_str("shuf: -n needs a count\n");
+            return 1;
+        }
+    };
+    let repeat = m.flag("r");
+    let echo = m.flag("e");
+    let range = match m.value("i").map(|r| {
+        let (lo, hi) = r.split_once('-')?;
+        Some((lo.parse::<u64>().ok()?, hi.parse::<u64>().ok()?))
+    }) {
+        None => None,
+        Some(Some((lo, hi))) if lo <= hi => Some(lo..=hi),
+        Some(_) => {
+            stderr.push_str("shuf: -i needs a range LO-HI\n");
+            return 1;
+        }
+    };
+    let operands = m.operands;
+
+    let mut lines: Vec<String> = if let Some(range) = range {
+        if range.end() - range.start() >= MAX_SHUF_LINES {
+            stderr.push_str(&format!(
+                "shuf: more than {} numbers in range\n",
+                MAX_SHUF_LINES
+            ));
+            return 1;
+        }
+        range.map(|n| n.to_string()).collect()
+    } else if echo {
+        operands
+    } else {
+        let text = match operands.as_slice() {
+            [] => stdin.to_string(),
+            [file] if file == "-" => stdin.to_string(),
+            [file] => match read_file_content(file) {
+                Ok(text) => text,
+                Err(e) => {
+                    stderr.push_str(&format!("shuf: {}: {}\n", file, e));
+                    return 1;
+                }
+            },
+            _ => {
+                stderr.push_str("shuf: extra operand\n");
+                return 1;
+            }
+        };
+        text.lines().map(str::to_string).collect()
+    };
+
+    if repeat {
+        let Some(count) = count else {
+            stderr.push_str("shuf: -r needs -n COUNT\n");
+            return 1;
+        };
+        if lines.is_empty() {
+            return 0;
+        }
+        for _ in 0..count {
+            match random_below(lines.len() as u64) {
+                Ok(j) => {
+                    stdout.push_str(&lines[j as usize]);
+                    stdout.push('\n');
+                }
+                Err(e) => {
+                    stderr.push_str(&format!("shuf: {}\n", e));
+                    return 1;
+                }
+            }
+        }
         return 0;
     }
 
-    if args.is_empty() {
-        stderr.push_str("basename: missing operand\n");
-        return 1;
+    // Fisher-Yates, stopping once COUNT lines are placed
+    let take = count.unwrap_or(lines.len()).min(lines.len());
+    for k in 0..take {
+        match random_below((lines.len() - k) as u64) {
+            Ok(j) => lines.swap(k, k + j as usize),
+            Err(e) => {
+                stderr.push_str(&format!("shuf: {}\n", e));
+                return 1;
+            }
+        }
     }
+    for line in &lines[..take] {
+        stdout.push_str(line);
+        stdout.push('\n');
+    }
+    0
+}
+
+/// Most numbers `shuf -i` will shuffle
+const MAX_SHUF_LINES: u64 = 1_000_000;
+
+pub static BASENAME: CommandSpec = CommandSpec {
+    name: "basename",
+    summary: "strip directory and suffix from filename",
+    description: "Print PATH without its directory, and without SUFFIX if it ends with it.",
+    flags: &[],
+    args: &[
+        Arg::new("PATH", ArgKind::Path),
+        Arg::new("SUFFIX", ArgKind::Text).optional(),
+    ],
+};
+
+/// basename - strip directory and suffix from filename
+pub fn prog_basename(
+    args: &[String],
+    _stdin: &str,
+    stdout: &mut String,
+    stderr: &mut String,
+) -> i32 {
+    let m = match BASENAME.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&BASENAME, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
 
     let path = args[0];
     let suffix = args.get(1).copied();
@@ -349,6 +915,14 @@ pub fn prog_basename(
     0
 }
 
+pub static DIRNAME: CommandSpec = CommandSpec {
+    name: "dirname",
+    summary: "strip last component from filename",
+    description: "Print PATH without its last component.",
+    flags: &[],
+    args: &[Arg::new("PATH", ArgKind::Path)],
+};
+
 /// dirname - strip last component from filename
 pub fn prog_dirname(
     args: &[String],
@@ -356,20 +930,11 @@ pub fn prog_dirname(
     stdout: &mut String,
     stderr: &mut String,
 ) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: dirname PATH\nStrip last component from PATH.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
-
-    if args.is_empty() {
-        stderr.push_str("dirname: missing operand\n");
-        return 1;
-    }
+    let m = match DIRNAME.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&DIRNAME, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
 
     let path = args[0];
 
@@ -389,17 +954,24 @@ pub fn prog_dirname(
     0
 }
 
+pub static XARGS: CommandSpec = CommandSpec {
+    name: "xargs",
+    summary: "build command lines from stdin",
+    description: "Build a command line from COMMAND (echo by default), ARGs and the words read from stdin.",
+    flags: &[],
+    args: &[
+        Arg::new("COMMAND", ArgKind::Command).optional(),
+        Arg::new("ARG", ArgKind::Text).optional().repeated().raw(),
+    ],
+};
+
 /// xargs - build command lines from stdin
-pub fn prog_xargs(args: &[String], stdin: &str, stdout: &mut String, _stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: xargs [COMMAND] [ARGS]\nBuild command lines from stdin.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
+pub fn prog_xargs(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match XARGS.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&XARGS, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
 
     // Get the command to run (default: echo)
     let cmd = if args.is_empty() { "echo" } else { args[0] };
@@ -424,15 +996,24 @@ pub fn prog_xargs(args: &[String], stdin: &str, stdout: &mut String, _stderr: &m
     0
 }
 
-/// cal - display a calendar
-pub fn prog_cal(args: &[String], _stdin: &str, stdout: &mut String, _stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
+pub static CAL: CommandSpec = CommandSpec {
+    name: "cal",
+    summary: "display a calendar",
+    description: "Display a calendar of this month, or of MONTH in YEAR. A single number is taken as a month of this year.",
+    flags: &[],
+    args: &[
+        Arg::new("MONTH", ArgKind::Text).optional(),
+        Arg::new("YEAR", ArgKind::Text).optional(),
+    ],
+};
 
-    if let Some(help) = check_help(&args, "Usage: cal [MONTH] [YEAR]\nDisplay a calendar.") {
-        stdout.push_str(&help);
-        return 0;
-    }
-    let args: Vec<String> = args.into_iter().map(|s| s.to_string()).collect();
+/// cal - display a calendar
+pub fn prog_cal(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match CAL.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&CAL, stdout, stderr),
+    };
+    let args = m.operands;
 
     // Get current date from system (or use defaults)
     let now = std::time::SystemTime::now()
@@ -576,22 +1157,25 @@ fn day_of_week(day: u32, month: u32, year: i32) -> u32 {
     ((h + 6) % 7) as u32
 }
 
+pub static PRINTF: CommandSpec = CommandSpec {
+    name: "printf",
+    summary: "format and print data",
+    description: "Print the ARGs as FORMAT describes, with the escapes \\n, \\t, \\r, \\a, \\e and \\0 and the conversions %s, %d, %i, %x, %X, %o and %c.",
+    flags: &[],
+    // Raw, so a format can start with -
+    args: &[
+        Arg::new("FORMAT", ArgKind::Text).raw(),
+        Arg::new("ARG", ArgKind::Text).optional().repeated(),
+    ],
+};
+
 /// printf - format and print data
 pub fn prog_printf(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if args.is_empty() {
-        stderr.push_str("printf: usage: printf FORMAT [ARG]...\n");
-        return 1;
-    }
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: printf FORMAT [ARG]...\nFormat and print data.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
+    let m = match PRINTF.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&PRINTF, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
 
     let format = args[0];
     let args = &args[1..];
@@ -605,6 +1189,8 @@ pub fn prog_printf(args: &[String], _stdin: &str, stdout: &mut String, stderr: &
                 Some('n') => stdout.push('\n'),
                 Some('t') => stdout.push('\t'),
                 Some('r') => stdout.push('\r'),
+                Some('a') => stdout.push('\x07'),
+                Some('e') => stdout.push('\x1b'),
                 Some('\\') => stdout.push('\\'),
                 Some('"') => stdout.push('"'),
                 Some('0') => stdout.push('\0'),
@@ -715,50 +1301,35 @@ pub fn prog_test(args: &[String], _stdin: &str, _stdout: &mut String, stderr: &m
                     0
                 }
             }
-            "-z" => {
-                if arg.is_empty() {
+            "-z"
+                if arg.is_empty() => {
                     0
-                } else {
-                    1
                 }
-            }
-            "-e" | "-a" => {
-                if syscall::exists(arg).unwrap_or(false) {
+            "-e" | "-a"
+                if syscall::exists(arg).unwrap_or(false) => {
                     0
-                } else {
-                    1
                 }
-            }
-            "-f" => {
-                if syscall::exists(arg).unwrap_or(false) {
+            "-f"
+                if syscall::exists(arg).unwrap_or(false) => {
                     if let Ok(meta) = syscall::stat(arg) {
                         if !meta.is_dir { 0 } else { 1 }
                     } else {
                         1
                     }
-                } else {
-                    1
                 }
-            }
-            "-d" => {
-                if syscall::exists(arg).unwrap_or(false) {
+            "-d"
+                if syscall::exists(arg).unwrap_or(false) => {
                     if let Ok(meta) = syscall::stat(arg) {
                         if meta.is_dir { 0 } else { 1 }
                     } else {
                         1
                     }
-                } else {
-                    1
                 }
-            }
-            "-r" | "-w" | "-x" => {
+            "-r" | "-w" | "-x"
                 // Assume readable/writable/executable if exists
-                if syscall::exists(arg).unwrap_or(false) {
+                if syscall::exists(arg).unwrap_or(false) => {
                     0
-                } else {
-                    1
                 }
-            }
             "-s" => {
                 // True if file exists and has size > 0
                 if let Ok(meta) = syscall::stat(arg) {
@@ -767,14 +1338,11 @@ pub fn prog_test(args: &[String], _stdin: &str, _stdout: &mut String, stderr: &m
                     1
                 }
             }
-            "-L" | "-h" => {
+            "-L" | "-h"
                 // True if symbolic link (check via read_link)
-                if syscall::read_link(arg).is_ok() {
+                if syscall::read_link(arg).is_ok() => {
                     0
-                } else {
-                    1
                 }
-            }
             _ => 1, // Unknown unary operator
         };
     }
@@ -810,21 +1378,21 @@ pub fn prog_test(args: &[String], _stdin: &str, _stdout: &mut String, stderr: &m
     1
 }
 
+pub static EXPR: CommandSpec = CommandSpec {
+    name: "expr",
+    summary: "evaluate expressions",
+    description: "Print the value of EXPRESSION: arithmetic and comparisons on integers, string comparisons, STRING : PREFIX, length STRING and substr STRING POS LEN.",
+    flags: &[],
+    // Raw, so operators such as - and negative numbers are operands
+    args: &[Arg::new("EXPRESSION", ArgKind::Text).repeated().raw()],
+};
+
 /// expr - evaluate expressions
 pub fn prog_expr(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args_ref = args_to_strs(args);
-
-    if args_ref.is_empty() {
-        stderr.push_str("expr: missing operand\n");
-        return 2;
-    }
-
-    if let Some(help) = check_help(&args_ref, "Usage: expr EXPRESSION\nEvaluate expressions.") {
-        stdout.push_str(&help);
-        return 0;
-    }
-
-    let args: Vec<String> = args_ref.into_iter().map(|s| s.to_string()).collect();
+    let args = match EXPR.parse(args) {
+        Ok(m) => m.operands,
+        Err(e) => return e.report(&EXPR, stdout, stderr),
+    };
 
     // Simple expression evaluation
     if args.len() == 1 {
@@ -941,19 +1509,21 @@ pub fn prog_expr(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mu
     2
 }
 
+pub static WHICH: CommandSpec = CommandSpec {
+    name: "which",
+    summary: "locate a command",
+    description: "Print where each COMMAND comes from: a shell builtin, or a program in /bin.",
+    flags: &[],
+    args: &[Arg::new("COMMAND", ArgKind::Command).repeated()],
+};
+
 /// which - locate a command
 pub fn prog_which(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if args.is_empty() {
-        stderr.push_str("which: missing argument\n");
-        return 1;
-    }
-
-    if let Some(help) = check_help(&args, "Usage: which COMMAND\nLocate a command.") {
-        stdout.push_str(&help);
-        return 0;
-    }
+    let m = match WHICH.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&WHICH, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
 
     let reg = ProgramRegistry::new();
     let mut exit_code = 0;
@@ -972,22 +1542,21 @@ pub fn prog_which(args: &[String], _stdin: &str, stdout: &mut String, stderr: &m
     exit_code
 }
 
+pub static TYPE: CommandSpec = CommandSpec {
+    name: "type",
+    summary: "describe a command",
+    description: "Describe how each COMMAND would be interpreted: as a shell builtin, or as a program in /bin.",
+    flags: &[],
+    args: &[Arg::new("COMMAND", ArgKind::Command).repeated()],
+};
+
 /// type - describe a command
 pub fn prog_type(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if args.is_empty() {
-        stderr.push_str("type: missing argument\n");
-        return 1;
-    }
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: type COMMAND\nDescribe how a command would be interpreted.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
+    let m = match TYPE.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&TYPE, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
 
     let reg = ProgramRegistry::new();
     let mut exit_code = 0;
@@ -1009,6 +1578,114 @@ pub fn prog_type(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mu
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::kernel::syscall::{KERNEL, Kernel};
+
+    #[test]
+    fn test_manual_from_spec() {
+        // readlink has no hand-written page, so its spec provides one
+        assert!(man_page("readlink").is_none());
+        let page = manual("readlink").unwrap();
+        assert!(page.starts_with("readlink(1)"));
+        assert!(page.contains("SYNOPSIS\n       readlink FILE\n"));
+        assert_eq!(
+            man_summary("readlink"),
+            Some("print the target of a symbolic link")
+        );
+
+        // A hand-written page wins
+        assert_eq!(manual("rm").as_deref(), man_page("rm"));
+        assert!(manual("nosuchcommand").is_none());
+
+        let args = vec!["readlink".to_string()];
+        let (mut stdout, mut stderr) = (String::new(), String::new());
+        assert_eq!(prog_man(&args, "", &mut stdout, &mut stderr), 0);
+        assert!(stdout.contains("OPTIONS\n       --help\n"));
+    }
+
+    #[test]
+    fn test_uuidgen() {
+        let run = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = prog_uuidgen(&args, "", &mut stdout, &mut stderr);
+            (code, stdout)
+        };
+        let (code, out) = run(&[]);
+        assert_eq!(code, 0);
+        let uuid = out.trim_end();
+        let groups: Vec<usize> = uuid.split('-').map(str::len).collect();
+        assert_eq!(groups, [8, 4, 4, 4, 12]);
+        assert_eq!(&uuid[14..15], "4");
+        assert!("89ab".contains(&uuid[19..20]));
+
+        let (_, out) = run(&["-r", "-C", "5"]);
+        let mut uuids: Vec<&str> = out.lines().collect();
+        uuids.dedup();
+        assert_eq!(uuids.len(), 5);
+        assert_eq!(run(&["-C"]).0, 1);
+        assert_eq!(run(&["-x"]).0, 1);
+    }
+
+    #[test]
+    fn test_shuf() {
+        let run = |args: &[&str], stdin: &str| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = prog_shuf(&args, stdin, &mut stdout, &mut stderr);
+            (code, stdout)
+        };
+        let sorted = |out: &str| {
+            let mut lines: Vec<String> = out.lines().map(str::to_string).collect();
+            lines.sort();
+            lines
+        };
+
+        let (code, out) = run(&[], "a\nb\nc\nd\n");
+        assert_eq!(code, 0);
+        assert_eq!(sorted(&out), ["a", "b", "c", "d"]);
+        let (_, out) = run(&["-n", "2"], "a\nb\nc\nd\n");
+        assert_eq!(out.lines().count(), 2);
+        let (_, out) = run(&["-e", "x", "y"], "");
+        assert_eq!(sorted(&out), ["x", "y"]);
+        let (_, out) = run(&["-i", "1-10"], "");
+        let mut numbers: Vec<u32> = out.lines().map(|n| n.parse().unwrap()).collect();
+        numbers.sort();
+        assert_eq!(numbers, (1..=10).collect::<Vec<_>>());
+        let (_, out) = run(&["-r", "-n", "50", "-e", "only"], "");
+        assert_eq!(out, "only\n".repeat(50));
+
+        // Every order of three lines turns up
+        let orders: std::collections::HashSet<String> =
+            (0..200).map(|_| run(&["-i", "1-3"], "").1).collect();
+        assert_eq!(orders.len(), 6);
+
+        assert_eq!(run(&["-r"], "a\n").0, 1);
+        assert_eq!(run(&["-i", "5-1"], "").0, 1);
+        assert_eq!(run(&["/missing"], "").0, 1);
+    }
+
+    #[test]
+    fn test_edit_lists_swaps() {
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        syscall::mkdir("/tmp/me").unwrap();
+        syscall::setenv("HOME", "/tmp/me").unwrap();
+        let args = vec!["-r".to_string()];
+
+        let mut stdout = String::new();
+        let mut stderr = String::new();
+        assert_eq!(prog_edit(&args, "", &mut stdout, &mut stderr), 0);
+        assert_eq!(stdout, "No swap files in /tmp/me/.cache/editor/swap");
+
+        swap::write("/tmp/notes.txt", "unsaved").unwrap();
+        let mut stdout = String::new();
+        assert_eq!(prog_edit(&args, "", &mut stdout, &mut stderr), 0);
+        assert_eq!(stdout, "/tmp/notes.txt\t0s ago");
+    }
 
     #[test]
     fn test_basename() {
@@ -1179,4 +1856,52 @@ mod tests {
         assert_eq!(days_in_month(2, 2019), 28); // Not leap year
         assert_eq!(days_in_month(4, 2020), 30);
     }
+
+    #[test]
+    fn test_file_history() {
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("sh", None);
+            k.set_current(pid);
+            k.current_process_mut().unwrap().euid = crate::kernel::Uid::ROOT;
+        });
+        let run = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = prog_history(&args, "", &mut stdout, &mut stderr);
+            (code, stdout, stderr)
+        };
+
+        syscall::write_file("/tmp/profile", "v1").unwrap();
+        syscall::snapshot_create("monday", 1.0).unwrap();
+        syscall::snapshot_create("tuesday", 2.0).unwrap();
+        syscall::write_file("/tmp/profile", "v2").unwrap();
+        syscall::snapshot_create("wednesday", 3.0).unwrap();
+        let _ = syscall::mkdir("/var/log");
+        syscall::write_file(
+            journal::JOURNAL_PATH,
+            "7\t10\t0\t0\t/\techo v2 > /tmp/profile\t~/tmp/profile\n",
+        )
+        .unwrap();
+
+        // "tuesday" holds the same as "monday"
+        let (code, out, _) = run(&["/tmp/profile"]);
+        assert_eq!(code, 0);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 6, "{}", out);
+        assert!(lines[1].ends_with(" monday"));
+        assert!(lines[2].ends_with(" wednesday (current)"));
+        assert!(lines[5].ends_with("7  ~ echo v2 > /tmp/profile"));
+
+        assert_eq!(run(&["show", "/tmp/profile@1"]).1, "v1");
+        let (code, out, _) = run(&["restore", "/tmp/profile@1"]);
+        assert_eq!(code, 0);
+        assert!(out.contains("version 1 (snapshot monday)"));
+        assert_eq!(syscall::read_file("/tmp/profile").unwrap(), "v1");
+
+        assert!(run(&["show", "/tmp/profile@3"]).2.contains("no version 3"));
+        assert!(run(&["show", "/tmp/profile"]).2.contains("expected PATH@N"));
+        assert!(run(&["/tmp/none"]).1.starts_with("No versions"));
+    }
 }
@@ -1,16 +1,23 @@
 //! System information programs
 
-use super::{args_to_strs, check_help};
 use crate::kernel::syscall;
+use crate::shell::argparse::{Arg, ArgKind, CommandSpec, Flag};
+
+pub static ID: CommandSpec = CommandSpec {
+    name: "id",
+    summary: "print user and group IDs",
+    description: "Print the user and group IDs of USER, or of the current process.",
+    flags: &[],
+    args: &[Arg::new("USER", ArgKind::Text).optional()],
+};
 
 /// id - print process and user IDs (uses kernel syscalls)
 pub fn prog_id(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(&args, "Usage: id [USER]\nPrint user and group IDs.") {
-        stdout.push_str(&help);
-        return 0;
-    }
+    let m = match ID.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&ID, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
 
     // Get user info - either for specified user or current process
     if let Some(username) = args.first() {
@@ -94,6 +101,14 @@ pub fn prog_id(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut
     0
 }
 
+pub static WHOAMI: CommandSpec = CommandSpec {
+    name: "whoami",
+    summary: "print effective username",
+    description: "Print the effective username.",
+    flags: &[],
+    args: &[],
+};
+
 /// whoami - print effective username
 pub fn prog_whoami(
     args: &[String],
@@ -101,11 +116,8 @@ pub fn prog_whoami(
     stdout: &mut String,
     stderr: &mut String,
 ) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(&args, "Usage: whoami\nPrint effective username.") {
-        stdout.push_str(&help);
-        return 0;
+    if let Err(e) = WHOAMI.parse(args) {
+        return e.report(&WHOAMI, stdout, stderr);
     }
 
     // Get effective user ID and look up the username
@@ -134,6 +146,14 @@ pub fn prog_whoami(
     }
 }
 
+pub static GROUPS: CommandSpec = CommandSpec {
+    name: "groups",
+    summary: "print group memberships",
+    description: "Print the groups USER, or the current process, belongs to.",
+    flags: &[],
+    args: &[Arg::new("USER", ArgKind::Text).optional()],
+};
+
 /// groups - print group memberships
 pub fn prog_groups(
     args: &[String],
@@ -141,12 +161,11 @@ pub fn prog_groups(
     stdout: &mut String,
     stderr: &mut String,
 ) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(&args, "Usage: groups [USER]\nPrint group memberships.") {
-        stdout.push_str(&help);
-        return 0;
-    }
+    let m = match GROUPS.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&GROUPS, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
 
     // Get groups for specified user or current process
     if let Some(username) = args.first() {
@@ -199,6 +218,14 @@ pub fn prog_groups(
     0
 }
 
+pub static HOSTNAME: CommandSpec = CommandSpec {
+    name: "hostname",
+    summary: "show or set system hostname",
+    description: "Print the hostname, or set it to NAME.",
+    flags: &[],
+    args: &[Arg::new("NAME", ArgKind::Text).optional()],
+};
+
 /// hostname - show or set system hostname
 pub fn prog_hostname(
     args: &[String],
@@ -206,77 +233,64 @@ pub fn prog_hostname(
     stdout: &mut String,
     stderr: &mut String,
 ) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: hostname [NAME]\nShow or set system hostname.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
+    let m = match HOSTNAME.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&HOSTNAME, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
 
     if args.is_empty() {
-        // Show hostname
-        match syscall::getenv("HOSTNAME") {
-            Ok(Some(hostname)) => {
-                stdout.push_str(&hostname);
-                stdout.push('\n');
-                0
-            }
-            Ok(None) => {
-                // Default hostname
-                stdout.push_str("axeberg\n");
-                0
-            }
-            Err(e) => {
-                stderr.push_str(&format!("hostname: {}\n", e));
-                1
-            }
-        }
+        stdout.push_str(&syscall::gethostname());
+        stdout.push('\n');
+        0
     } else {
-        // Set hostname
+        // Set hostname (same as writing /sys/kernel/hostname)
         let new_hostname = args[0];
-        match syscall::setenv("HOSTNAME", new_hostname) {
+        match syscall::sethostname(new_hostname) {
             Ok(()) => 0,
             Err(e) => {
-                stderr.push_str(&format!("hostname: {}\n", e));
+                stderr.push_str(&format!("hostname: {}: {}\n", new_hostname, e));
                 1
             }
         }
     }
 }
 
-/// uname - print system information
-pub fn prog_uname(
-    args: &[String],
-    __stdin: &str,
-    stdout: &mut String,
-    _stderr: &mut String,
-) -> i32 {
-    let args = args_to_strs(args);
+pub static UNAME: CommandSpec = CommandSpec {
+    name: "uname",
+    summary: "print system information",
+    description: "Print system information. With no options, print the kernel name.",
+    flags: &[
+        Flag::short('a', "Print everything"),
+        Flag::short('s', "Print the kernel name"),
+        Flag::short('n', "Print the hostname"),
+        Flag::short('r', "Print the kernel release"),
+        Flag::short('v', "Print the kernel version"),
+        Flag::short('m', "Print the machine type"),
+    ],
+    args: &[],
+};
 
-    if let Some(help) = check_help(&args, "Usage: uname [-amnrsv]\nPrint system information.") {
-        stdout.push_str(&help);
-        return 0;
-    }
+/// uname - print system information
+pub fn prog_uname(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match UNAME.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&UNAME, stdout, stderr),
+    };
 
     // System info
     let kernel_name = "axeberg";
-    let hostname = syscall::getenv("HOSTNAME")
-        .ok()
-        .flatten()
-        .unwrap_or_else(|| "axeberg".to_string());
+    let hostname = syscall::gethostname();
     let kernel_release = "0.1.0";
     let kernel_version = "axebergOS";
     let machine = "wasm32";
 
-    let show_all = args.contains(&"-a");
-    let show_kernel = args.is_empty() || args.contains(&"-s") || show_all;
-    let show_hostname = args.contains(&"-n") || show_all;
-    let show_release = args.contains(&"-r") || show_all;
-    let show_version = args.contains(&"-v") || show_all;
-    let show_machine = args.contains(&"-m") || show_all;
+    let show_all = m.flag("a");
+    let show_kernel = m.flag("s") || show_all;
+    let show_hostname = m.flag("n") || show_all;
+    let show_release = m.flag("r") || show_all;
+    let show_version = m.flag("v") || show_all;
+    let show_machine = m.flag("m") || show_all;
 
     let mut parts = Vec::new();
     if show_kernel {
@@ -304,23 +318,32 @@ pub fn prog_uname(
     0
 }
 
-/// ps - process status
-pub fn prog_ps(args: &[String], __stdin: &str, stdout: &mut String, _stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
+pub static PS: CommandSpec = CommandSpec {
+    name: "ps",
+    summary: "report process status",
+    description: "List the processes with their terminal and state.",
+    flags: &[
+        Flag::short('a', "List every process (the default)"),
+        Flag::short('l', "Long format, with parent and process group IDs"),
+    ],
+    args: &[],
+};
 
-    if let Some(help) = check_help(&args, "Usage: ps [-a] [-l]\nReport process status.") {
-        stdout.push_str(&help);
-        return 0;
-    }
+/// ps - process status
+pub fn prog_ps(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match PS.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&PS, stdout, stderr),
+    };
 
-    let long_format = args.contains(&"-l");
+    let long_format = m.flag("l");
 
     let processes = syscall::list_processes();
 
     if long_format {
-        stdout.push_str("  PID  PPID  PGID STATE    COMMAND\n");
+        stdout.push_str("  PID  PPID  PGID TTY      STATE    COMMAND\n");
     } else {
-        stdout.push_str("  PID STATE    COMMAND\n");
+        stdout.push_str("  PID TTY      STATE    COMMAND\n");
     }
 
     for (pid, name, state) in processes {
@@ -331,38 +354,45 @@ pub fn prog_ps(args: &[String], __stdin: &str, stdout: &mut String, _stderr: &mu
             syscall::ProcessState::Blocked(_) => "D",
             syscall::ProcessState::Zombie(_) => "Z",
         };
+        // Processes without a controlling terminal show '?'
+        let tty = syscall::process_tty(pid).unwrap_or_else(|| "?".to_string());
 
         if long_format {
             let ppid = syscall::getppid().ok().flatten().map(|p| p.0).unwrap_or(0);
             let pgid = syscall::getpgid(pid).ok().map(|p| p.0).unwrap_or(pid.0);
             stdout.push_str(&format!(
-                "{:>5} {:>5} {:>5} {:8} {}\n",
-                pid.0, ppid, pgid, state_str, name
+                "{:>5} {:>5} {:>5} {:8} {:8} {}\n",
+                pid.0, ppid, pgid, tty, state_str, name
             ));
         } else {
-            stdout.push_str(&format!("{:>5} {:8} {}\n", pid.0, state_str, name));
+            stdout.push_str(&format!(
+                "{:>5} {:8} {:8} {}\n",
+                pid.0, tty, state_str, name
+            ));
         }
     }
 
     0
 }
 
+pub static TIME: CommandSpec = CommandSpec {
+    name: "time",
+    summary: "time command execution",
+    description: "Run COMMAND and report how long it took.",
+    flags: &[],
+    args: &[
+        Arg::new("COMMAND", ArgKind::Command),
+        Arg::new("ARG", ArgKind::Text).optional().repeated().raw(),
+    ],
+};
+
 /// time - time command execution
 pub fn prog_time(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: time COMMAND [ARGS...]\nTime command execution.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
-
-    if args.is_empty() {
-        stderr.push_str("time: missing command\n");
-        return 1;
-    }
+    let m = match TIME.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&TIME, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
 
     let start = syscall::now();
 
@@ -383,13 +413,18 @@ pub fn prog_time(args: &[String], __stdin: &str, stdout: &mut String, stderr: &m
     0
 }
 
-/// date - print current date and time
-pub fn prog_date(args: &[String], __stdin: &str, stdout: &mut String, _stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
+pub static DATE: CommandSpec = CommandSpec {
+    name: "date",
+    summary: "print current date and time",
+    description: "Print the current date and time.",
+    flags: &[],
+    args: &[Arg::new("+FORMAT", ArgKind::Text).optional()],
+};
 
-    if let Some(help) = check_help(&args, "Usage: date [+FORMAT]\nPrint current date and time.") {
-        stdout.push_str(&help);
-        return 0;
+/// date - print current date and time
+pub fn prog_date(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    if let Err(e) = DATE.parse(args) {
+        return e.report(&DATE, stdout, stderr);
     }
 
     // Get current time from syscall
@@ -407,21 +442,23 @@ pub fn prog_date(args: &[String], __stdin: &str, stdout: &mut String, _stderr: &
     0
 }
 
+pub static UPTIME: CommandSpec = CommandSpec {
+    name: "uptime",
+    summary: "show how long the system has been running",
+    description: "Show how long the system has been running, with syscall and process counts.",
+    flags: &[],
+    args: &[],
+};
+
 /// uptime - show how long system has been running
 pub fn prog_uptime(
     args: &[String],
     __stdin: &str,
     stdout: &mut String,
-    _stderr: &mut String,
+    stderr: &mut String,
 ) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: uptime\nShow how long the system has been running.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
+    if let Err(e) = UPTIME.parse(args) {
+        return e.report(&UPTIME, stdout, stderr);
     }
 
     // Get trace summary for uptime info
@@ -462,18 +499,124 @@ pub fn prog_uptime(
     0
 }
 
-/// free - display amount of free and used memory
-pub fn prog_free(args: &[String], __stdin: &str, stdout: &mut String, _stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-    let human = args.iter().any(|a| *a == "-h" || *a == "--human");
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: free [-h]\nDisplay memory usage.\n  -h  Human readable output",
-    ) {
-        stdout.push_str(&help);
+pub static STATS: CommandSpec = CommandSpec {
+    name: "stats",
+    summary: "report command usage statistics",
+    description: "Report which commands are used most, which are slowest and which fail most often. Statistics stay on this machine in /var/lib/stats.db and hold command names only, never arguments. COMMAND is one of:
+
+  top [N]          most used commands (default 10)
+  slow [N]         longest average run time
+  errors [N]       most failures
+  purge [COMMAND]  delete all statistics, or one command's",
+    flags: &[],
+    args: &[
+        Arg::new("COMMAND", ArgKind::Text).optional(),
+        Arg::new("ARG", ArgKind::Text).optional(),
+    ],
+};
+
+/// stats - report local command usage statistics
+pub fn prog_stats(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    use crate::shell::stats::{self, CommandStats};
+
+    let m = match STATS.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&STATS, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
+
+    let table = |stdout: &mut String, title: &str, rows: Vec<(&str, &CommandStats)>| {
+        if !title.is_empty() {
+            stdout.push_str(&format!("{}:\n", title));
+        }
+        stdout.push_str("COMMAND        RUNS   FAIL  FAIL%    AVG ms    MAX ms\n");
+        for (name, s) in rows {
+            stdout.push_str(&format!(
+                "{:<12} {:>6} {:>6} {:>5.0}% {:>9.1} {:>9.1}\n",
+                name,
+                s.runs,
+                s.failures,
+                s.failure_rate() * 100.0,
+                s.mean_ms(),
+                s.max_ms
+            ));
+        }
+    };
+
+    let count = |arg: Option<&&str>, default: usize| match arg {
+        None => Ok(default),
+        Some(n) => n
+            .parse::<usize>()
+            .map_err(|_| format!("invalid count '{}'", n)),
+    };
+
+    if args.first() == Some(&"purge") {
+        return match (args.get(1), stats::purge(args.get(1).copied())) {
+            (_, Err(e)) => {
+                stderr.push_str(&format!("stats: {}\n", e));
+                1
+            }
+            (Some(name), Ok(0)) => {
+                stderr.push_str(&format!("stats: no statistics for '{}'\n", name));
+                1
+            }
+            (_, Ok(n)) => {
+                stdout.push_str(&format!(
+                    "Purged statistics for {} command{}\n",
+                    n,
+                    if n == 1 { "" } else { "s" }
+                ));
+                0
+            }
+        };
+    }
+
+    let db = stats::load();
+    if db.commands.is_empty() {
+        stdout.push_str("No command statistics yet\n");
         return 0;
     }
+    let result = match args.first() {
+        None => {
+            table(stdout, "Most used", db.top(10));
+            stdout.push('\n');
+            table(stdout, "Slowest", db.slowest(5));
+            let errors = db.errors(5);
+            if !errors.is_empty() {
+                stdout.push('\n');
+                table(stdout, "Most failures", errors);
+            }
+            Ok(())
+        }
+        Some(&"top") => count(args.get(1), 10).map(|n| table(stdout, "", db.top(n))),
+        Some(&"slow") => count(args.get(1), 10).map(|n| table(stdout, "", db.slowest(n))),
+        Some(&"errors") => count(args.get(1), 10).map(|n| table(stdout, "", db.errors(n))),
+        Some(other) => Err(format!("unknown report '{}' (see stats --help)", other)),
+    };
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            stderr.push_str(&format!("stats: {}\n", e));
+            2
+        }
+    }
+}
+
+pub static FREE: CommandSpec = CommandSpec {
+    name: "free",
+    summary: "display memory usage",
+    description: "Display the amount of free and used memory.",
+    flags: &[Flag::new('h', "human", "Human readable output")],
+    args: &[],
+};
+
+/// free - display amount of free and used memory
+pub fn prog_free(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match FREE.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&FREE, stdout, stderr),
+    };
+    let human = m.flag("h");
 
     let stats = syscall::system_memstats().unwrap_or_default();
 
@@ -510,6 +653,66 @@ pub fn prog_free(args: &[String], __stdin: &str, stdout: &mut String, _stderr: &
     0
 }
 
+pub static DMESG: CommandSpec = CommandSpec {
+    name: "dmesg",
+    summary: "print the kernel message log",
+    description: "Print the kernel message log.",
+    flags: &[
+        Flag::short('x', "Show the level of each message"),
+        Flag::short('l', "Only show these levels (err,warn,notice,info)").value("LEVELS"),
+        Flag::short('c', "Clear the log after printing it (root only)"),
+        Flag::short('C', "Clear the log without printing it (root only)"),
+    ],
+    args: &[],
+};
+
+/// dmesg - print the kernel message log
+pub fn prog_dmesg(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    use crate::kernel::LogLevel;
+
+    let m = match DMESG.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&DMESG, stdout, stderr),
+    };
+
+    let decode = m.flag("x");
+    let clear = m.flag("c") || m.flag("C");
+    let print = !m.flag("C");
+    let mut levels: Option<Vec<LogLevel>> = None;
+    if let Some(list) = m.value("l") {
+        let mut parsed = Vec::new();
+        for name in list.split(',') {
+            match LogLevel::from_name(name) {
+                Some(level) => parsed.push(level),
+                None => {
+                    stderr.push_str(&format!("dmesg: unknown level '{}'\n", name));
+                    return 1;
+                }
+            }
+        }
+        levels = Some(parsed);
+    }
+
+    if print {
+        for entry in syscall::klog_read() {
+            if levels.as_ref().is_some_and(|l| !l.contains(&entry.level)) {
+                continue;
+            }
+            if decode {
+                stdout.push_str(&format!("{:<7}", format!("{}:", entry.level)));
+            }
+            stdout.push_str(&entry.to_line());
+            stdout.push('\n');
+        }
+    }
+
+    if clear && let Err(e) = syscall::klog_clear() {
+        stderr.push_str(&format!("dmesg: clear: {}\n", e));
+        return 1;
+    }
+    0
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -577,6 +780,33 @@ mod tests {
         assert!(stdout.contains("process"));
     }
 
+    #[test]
+    fn test_ps_tty_column() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+        use crate::kernel::users::{Gid, Uid};
+
+        let shell = KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let daemon = k.spawn_process("daemon", None);
+            k.set_current(daemon);
+            k.spawn_login_shell("user", Uid(1000), Gid(1000), "/home/user", "/bin/sh", None)
+        });
+
+        let mut stdout = String::new();
+        assert_eq!(prog_ps(&[], "", &mut stdout, &mut String::new()), 0);
+        let lines: Vec<&str> = stdout.lines().collect();
+        assert_eq!(lines[0], "  PID TTY      STATE    COMMAND");
+        let row = |pid: u32| {
+            lines
+                .iter()
+                .find(|l| l.split_whitespace().next() == Some(&pid.to_string()))
+                .map(|l| l.split_whitespace().nth(1).unwrap_or_default().to_string())
+        };
+        assert_eq!(row(1).as_deref(), Some("?"));
+        assert_eq!(row(shell.0).as_deref(), Some("tty1"));
+    }
+
     #[test]
     fn test_date_help() {
         let args = vec!["--help".to_string()];
@@ -598,7 +828,7 @@ mod tests {
         let exit_code = prog_time(&args, "", &mut stdout, &mut stderr);
 
         assert_eq!(exit_code, 1);
-        assert!(stderr.contains("missing command"));
+        assert!(stderr.contains("missing operand: expected COMMAND"));
     }
 
     #[test]
@@ -649,4 +879,49 @@ mod tests {
         assert_eq!(exit_code, 0);
         assert!(stdout.contains("groups"));
     }
+
+    #[test]
+    fn test_dmesg() {
+        use crate::kernel::LogLevel;
+        use crate::kernel::syscall::{KERNEL, Kernel};
+        use crate::kernel::users::Uid;
+
+        KERNEL.with(|k| {
+            *k.borrow_mut() = Kernel::new();
+            let pid = k.borrow_mut().spawn_process("test", None);
+            k.borrow_mut().set_current(pid);
+        });
+        let run = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = prog_dmesg(&args, "", &mut stdout, &mut stderr);
+            (code, stdout + &stderr)
+        };
+
+        syscall::set_time(1500.0);
+        syscall::klog(LogLevel::Warn, "persist", "corrupt axeberg_fs.json: empty");
+        syscall::klog(LogLevel::Info, "persist", "restored from axeberg_fs.json.1");
+
+        let (code, out) = run(&[]);
+        assert_eq!(code, 0);
+        assert_eq!(
+            out,
+            "[    1.500000] persist: corrupt axeberg_fs.json: empty\n\
+             [    1.500000] persist: restored from axeberg_fs.json.1\n"
+        );
+        let (_, out) = run(&["-x", "-l", "warn,err"]);
+        assert_eq!(
+            out,
+            "warn:  [    1.500000] persist: corrupt axeberg_fs.json: empty\n"
+        );
+        assert_eq!(run(&["-l", "loud"]).0, 1);
+
+        // Only root may clear it
+        assert_eq!(run(&["-C"]).0, 1);
+        KERNEL.with(|k| k.borrow_mut().current_process_mut().unwrap().euid = Uid::ROOT);
+        let (code, out) = run(&["-c"]);
+        assert_eq!(code, 0);
+        assert_eq!(out.lines().count(), 2);
+        assert_eq!(run(&[]).1, "");
+    }
 }
@@ -1,45 +1,39 @@
 //! Text processing programs
 //!
 //! Programs for text manipulation: head, tail, wc, grep, sort, uniq, tee,
-//! rev, cut, tr, nl, fold, paste, comm, strings, diff
+//! rev, cut, tr, nl, fold, paste, comm, strings, diff, merge3, spell,
+//! unicode
 
-use super::{args_to_strs, check_help, read_file_content};
+use super::{parse_bytes, read_file_bytes, read_file_content};
 use crate::kernel::syscall;
+use crate::shell::argparse::{Arg, ArgKind, CommandSpec, Flag};
+use crate::shell::diff::{self, Edit, Labels};
+use crate::shell::spell;
+use crate::shell::unicode;
+
+pub static HEAD: CommandSpec = CommandSpec {
+    name: "head",
+    summary: "output first lines",
+    description: "Output the first N lines of FILE, or of stdin. See 'man head' for details.",
+    flags: &[Flag::short('n', "Output N lines (default 10)").value("N")],
+    args: &[Arg::new("FILE", ArgKind::Path).optional()],
+};
 
 /// head - output first lines
-pub fn prog_head(args: &[String], stdin: &str, stdout: &mut String, _stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: head [-n N] [FILE]\nOutput first N lines (default 10). See 'man head' for details.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
-
-    let mut n = 10;
-    let mut files = Vec::new();
+pub fn prog_head(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match HEAD.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&HEAD, stdout, stderr),
+    };
 
-    let mut i = 0;
-    while i < args.len() {
-        if args[i] == "-n" && i + 1 < args.len() {
-            n = args[i + 1].parse().unwrap_or(10);
-            i += 2;
-        } else if args[i].starts_with("-n") {
-            n = args[i][2..].parse().unwrap_or(10);
-            i += 1;
-        } else {
-            files.push(args[i]);
-            i += 1;
-        }
-    }
+    let n = m.value("n").map_or(10, |n| n.parse().unwrap_or(10));
+    let files = m.operands;
 
     let input = if files.is_empty() {
         stdin.to_string()
     } else {
         // Read first file
-        match syscall::read_file(files[0]) {
+        match syscall::read_file(&files[0]) {
             Ok(content) => content,
             Err(_) => return 1,
         }
@@ -60,27 +54,22 @@ pub fn prog_head(args: &[String], stdin: &str, stdout: &mut String, _stderr: &mu
     0
 }
 
-/// tail - output last lines
-pub fn prog_tail(args: &[String], stdin: &str, stdout: &mut String, _stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: tail [-n N] [FILE]\nOutput last N lines (default 10). See 'man tail' for details.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
+pub static TAIL: CommandSpec = CommandSpec {
+    name: "tail",
+    summary: "output last lines",
+    description: "Output the last N lines of stdin. See 'man tail' for details.",
+    flags: &[Flag::short('n', "Output N lines (default 10)").value("N")],
+    args: &[Arg::new("FILE", ArgKind::Path).optional()],
+};
 
-    let mut n = 10;
+/// tail - output last lines
+pub fn prog_tail(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match TAIL.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&TAIL, stdout, stderr),
+    };
 
-    for i in 0..args.len() {
-        if args[i] == "-n" && i + 1 < args.len() {
-            n = args[i + 1].parse().unwrap_or(10);
-        } else if args[i].starts_with("-n") {
-            n = args[i][2..].parse().unwrap_or(10);
-        }
-    }
+    let n = m.value("n").map_or(10, |n| n.parse().unwrap_or(10));
 
     let input = stdin.to_string();
     let lines: Vec<&str> = input.lines().collect();
@@ -98,30 +87,39 @@ pub fn prog_tail(args: &[String], stdin: &str, stdout: &mut String, _stderr: &mu
     0
 }
 
-/// wc - word, line, character count
-pub fn prog_wc(args: &[String], stdin: &str, stdout: &mut String, _stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
+pub static WC: CommandSpec = CommandSpec {
+    name: "wc",
+    summary: "word, line, character count",
+    description: "Count the lines, words and bytes of stdin. With no options, print all three. See 'man wc' for details.",
+    flags: &[
+        Flag::short('l', "Print the line count"),
+        Flag::short('w', "Print the word count"),
+        Flag::short('c', "Print the byte count"),
+        Flag::short('m', "Print the character count (the same as -c)"),
+    ],
+    args: &[Arg::new("FILE", ArgKind::Path).optional()],
+};
 
-    if let Some(help) = check_help(
-        &args,
-        "Usage: wc [-lwc] [FILE]\nCount lines, words, and characters. See 'man wc' for details.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
+/// wc - word, line, character count
+pub fn prog_wc(args: &[String], stdin: &[u8], stdout: &mut Vec<u8>, stderr: &mut String) -> i32 {
+    let m = match parse_bytes(&WC, args, stdout, stderr) {
+        Ok(m) => m,
+        Err(code) => return code,
+    };
 
-    let show_lines = args.contains(&"-l");
-    let show_words = args.contains(&"-w");
-    let show_chars = args.contains(&"-c") || args.contains(&"-m");
+    let show_lines = m.flag("l");
+    let show_words = m.flag("w");
+    let show_chars = m.flag("c") || m.flag("m");
     let show_all = !show_lines && !show_words && !show_chars;
 
-    let input = stdin.to_string();
+    // Count bytes of the raw input, so binary data is measured exactly
+    let input = String::from_utf8_lossy(stdin);
     let lines = input.lines().count();
     let words = input.split_whitespace().count();
-    let chars = input.len();
+    let chars = stdin.len();
 
     if show_all {
-        stdout.push_str(&format!("{} {} {}", lines, words, chars));
+        stdout.extend_from_slice(format!("{} {} {}", lines, words, chars).as_bytes());
     } else {
         let mut parts = Vec::new();
         if show_lines {
@@ -133,45 +131,66 @@ pub fn prog_wc(args: &[String], stdin: &str, stdout: &mut String, _stderr: &mut
         if show_chars {
             parts.push(chars.to_string());
         }
-        stdout.push_str(&parts.join(" "));
+        stdout.extend_from_slice(parts.join(" ").as_bytes());
     }
 
     0
 }
 
+pub static GREP: CommandSpec = CommandSpec {
+    name: "grep",
+    summary: "search for patterns",
+    description: "Print the lines of stdin that contain PATTERN, with the matches highlighted. Exit status is 0 if a line matched, 1 if not. See 'man grep' for details.",
+    flags: &[
+        Flag::short('i', "Case-insensitive matching"),
+        Flag::short('n', "Prefix each line with its line number"),
+        Flag::short('v', "Print the lines that do not match"),
+    ],
+    args: &[
+        Arg::new("PATTERN", ArgKind::Text),
+        Arg::new("FILE", ArgKind::Path).optional().repeated(),
+    ],
+};
+
 /// grep - search for patterns
 pub fn prog_grep(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: grep [-inv] PATTERN [FILE]...\nSearch for patterns in files. See 'man grep' for details.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
-
-    if args.is_empty() {
-        stderr.push_str("grep: missing pattern\n");
-        return 1;
-    }
+    let m = match GREP.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&GREP, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
 
     // ANSI color codes
     const RED: &str = "\x1b[31m";
     const RESET: &str = "\x1b[0m";
 
     let pattern = args[0];
+    let ignore_case = m.flag("i");
+    let invert = m.flag("v");
     let input = stdin.to_string();
     let mut found = false;
 
-    for line in input.lines() {
-        if line.contains(pattern) {
+    for (n, line) in input.lines().enumerate() {
+        let matched = if ignore_case {
+            line.to_lowercase().contains(&pattern.to_lowercase())
+        } else {
+            line.contains(pattern)
+        };
+        if matched == invert {
+            continue;
+        }
+        if m.flag("n") {
+            stdout.push_str(&format!("{}:", n + 1));
+        }
+        if invert || ignore_case {
+            stdout.push_str(line);
+        } else {
             // Highlight all matches in red
             let highlighted = line.replace(pattern, &format!("{}{}{}", RED, pattern, RESET));
             stdout.push_str(&highlighted);
-            stdout.push('\n');
-            found = true;
         }
+        stdout.push('\n');
+        found = true;
     }
 
     if stdout.ends_with('\n') {
@@ -181,20 +200,26 @@ pub fn prog_grep(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut
     if found { 0 } else { 1 }
 }
 
-/// sort - sort lines
-pub fn prog_sort(args: &[String], stdin: &str, stdout: &mut String, _stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
+pub static SORT: CommandSpec = CommandSpec {
+    name: "sort",
+    summary: "sort lines",
+    description: "Sort the lines of stdin. See 'man sort' for details.",
+    flags: &[
+        Flag::short('r', "Reverse the order"),
+        Flag::short('u', "Print equal lines only once"),
+    ],
+    args: &[Arg::new("FILE", ArgKind::Path).optional()],
+};
 
-    if let Some(help) = check_help(
-        &args,
-        "Usage: sort [-ru] [FILE]\nSort lines of text. See 'man sort' for details.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
+/// sort - sort lines
+pub fn prog_sort(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match SORT.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&SORT, stdout, stderr),
+    };
 
-    let reverse = args.contains(&"-r");
-    let unique = args.contains(&"-u");
+    let reverse = m.flag("r");
+    let unique = m.flag("u");
 
     let input = stdin.to_string();
     let mut lines: Vec<&str> = input.lines().collect();
@@ -211,19 +236,25 @@ pub fn prog_sort(args: &[String], stdin: &str, stdout: &mut String, _stderr: &mu
     0
 }
 
-/// uniq - filter adjacent duplicate lines
-pub fn prog_uniq(args: &[String], stdin: &str, stdout: &mut String, _stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
+pub static UNIQ: CommandSpec = CommandSpec {
+    name: "uniq",
+    summary: "filter adjacent duplicate lines",
+    description: "Print the lines of stdin, each run of equal lines once. See 'man uniq' for details.",
+    flags: &[Flag::short(
+        'c',
+        "Prefix each line with how often it occurred",
+    )],
+    args: &[Arg::new("FILE", ArgKind::Path).optional()],
+};
 
-    if let Some(help) = check_help(
-        &args,
-        "Usage: uniq [-c] [FILE]\nFilter adjacent duplicate lines. See 'man uniq' for details.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
+/// uniq - filter adjacent duplicate lines
+pub fn prog_uniq(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match UNIQ.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&UNIQ, stdout, stderr),
+    };
 
-    let count = args.contains(&"-c");
+    let count = m.flag("c");
 
     let input = stdin.to_string();
     let mut prev: Option<&str> = None;
@@ -258,28 +289,31 @@ pub fn prog_uniq(args: &[String], stdin: &str, stdout: &mut String, _stderr: &mu
     0
 }
 
-/// tee - read stdin and write to files
-pub fn prog_tee(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let files = args_to_strs(args);
+pub static TEE: CommandSpec = CommandSpec {
+    name: "tee",
+    summary: "read stdin and write to files",
+    description: "Copy stdin to each FILE and to stdout. See 'man tee' for details.",
+    flags: &[Flag::short(
+        'a',
+        "Append to the files instead of overwriting them",
+    )],
+    args: &[Arg::new("FILE", ArgKind::Path).optional().repeated()],
+};
 
-    if let Some(help) = check_help(
-        &files,
-        "Usage: tee [-a] FILE\nCopy stdin to file and stdout. See 'man tee' for details.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
-
-    let input = stdin.to_string();
+/// tee - read stdin and write to files
+pub fn prog_tee(args: &[String], stdin: &[u8], stdout: &mut Vec<u8>, stderr: &mut String) -> i32 {
+    let m = match parse_bytes(&TEE, args, stdout, stderr) {
+        Ok(m) => m,
+        Err(code) => return code,
+    };
 
     // Write to stdout
-    stdout.push_str(&input);
+    stdout.extend_from_slice(stdin);
 
     // Write to files
-    let append = files.contains(&"-a");
-    let files: Vec<&str> = files.into_iter().filter(|f| *f != "-a").collect();
+    let append = m.flag("a");
 
-    for file in files {
+    for file in &m.operands {
         let flags = if append {
             syscall::OpenFlags::APPEND
         } else {
@@ -288,7 +322,7 @@ pub fn prog_tee(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut
 
         match syscall::open(file, flags) {
             Ok(fd) => {
-                let _ = syscall::write(fd, input.as_bytes());
+                let _ = syscall::write(fd, stdin);
                 let _ = syscall::close(fd);
             }
             Err(e) => {
@@ -300,14 +334,21 @@ pub fn prog_tee(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut
     0
 }
 
+pub static REV: CommandSpec = CommandSpec {
+    name: "rev",
+    summary: "reverse lines",
+    description: "Reverse the characters of each line of stdin, or of FILE.",
+    flags: &[],
+    args: &[Arg::new("FILE", ArgKind::Path).optional()],
+};
+
 /// rev - reverse lines
 pub fn prog_rev(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(&args, "Usage: rev [FILE]\nReverse characters in each line.") {
-        stdout.push_str(&help);
-        return 0;
-    }
+    let m = match REV.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&REV, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
 
     let content = if !stdin.is_empty() {
         stdin.to_string()
@@ -332,55 +373,44 @@ pub fn prog_rev(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut
     0
 }
 
+pub static CUT: CommandSpec = CommandSpec {
+    name: "cut",
+    summary: "remove sections from each line",
+    description: "Print the FIELDS of each line of stdin, or of FILE. FIELDS is a list such as 1,3 or 2-4.",
+    flags: &[
+        Flag::short('d', "Split fields at DELIM (default tab)").value("DELIM"),
+        Flag::short('f', "Print these fields").value("FIELDS"),
+    ],
+    args: &[Arg::new("FILE", ArgKind::Path).optional()],
+};
+
 /// cut - remove sections from each line
 pub fn prog_cut(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: cut -d DELIM -f FIELDS [FILE]\nRemove sections from each line.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
-
-    // Parse options
-    let mut delimiter = '\t';
-    let mut fields: Option<Vec<usize>> = None;
-    let mut file: Option<&str> = None;
+    let m = match CUT.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&CUT, stdout, stderr),
+    };
 
-    let mut i = 0;
-    while i < args.len() {
-        match args[i] {
-            "-d" if i + 1 < args.len() => {
-                delimiter = args[i + 1].chars().next().unwrap_or('\t');
-                i += 2;
-            }
-            "-f" if i + 1 < args.len() => {
-                // Parse field list (e.g., "1,2,3" or "1-3")
-                let field_spec = args[i + 1];
-                let mut field_list = Vec::new();
-                for part in field_spec.split(',') {
-                    if let Some(dash_pos) = part.find('-') {
-                        let start: usize = part[..dash_pos].parse().unwrap_or(1);
-                        let end: usize = part[dash_pos + 1..].parse().unwrap_or(start);
-                        for f in start..=end {
-                            field_list.push(f);
-                        }
-                    } else if let Ok(f) = part.parse::<usize>() {
-                        field_list.push(f);
-                    }
+    let delimiter = m
+        .value("d")
+        .map_or('\t', |d| d.chars().next().unwrap_or('\t'));
+    // Parse field list (e.g., "1,2,3" or "1-3")
+    let fields = m.value("f").map(|field_spec| {
+        let mut field_list = Vec::new();
+        for part in field_spec.split(',') {
+            if let Some(dash_pos) = part.find('-') {
+                let start: usize = part[..dash_pos].parse().unwrap_or(1);
+                let end: usize = part[dash_pos + 1..].parse().unwrap_or(start);
+                for f in start..=end {
+                    field_list.push(f);
                 }
-                fields = Some(field_list);
-                i += 2;
+            } else if let Ok(f) = part.parse::<usize>() {
+                field_list.push(f);
             }
-            s if !s.starts_with('-') => {
-                file = Some(s);
-                i += 1;
-            }
-            _ => i += 1,
         }
-    }
+        field_list
+    });
+    let file = m.operand(0);
 
     let fields = match fields {
         Some(f) => f,
@@ -418,22 +448,24 @@ pub fn prog_cut(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut
     0
 }
 
+pub static TR: CommandSpec = CommandSpec {
+    name: "tr",
+    summary: "translate characters",
+    description: "Copy stdin, translating each character of SET1 to the one at the same place in SET2, or to SET2's last.",
+    flags: &[],
+    args: &[
+        Arg::new("SET1", ArgKind::Text),
+        Arg::new("SET2", ArgKind::Text),
+    ],
+};
+
 /// tr - translate characters
 pub fn prog_tr(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: tr SET1 SET2\nTranslate characters from SET1 to SET2.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
-
-    if args.len() < 2 {
-        stderr.push_str("tr: missing operand\n");
-        return 1;
-    }
+    let m = match TR.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&TR, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
 
     let set1: Vec<char> = args[0].chars().collect();
     let set2: Vec<char> = args[1].chars().collect();
@@ -454,16 +486,23 @@ pub fn prog_tr(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut S
     0
 }
 
+pub static NL: CommandSpec = CommandSpec {
+    name: "nl",
+    summary: "number lines",
+    description: "Print the lines of FILE, or of stdin, numbered.",
+    flags: &[],
+    args: &[Arg::new("FILE", ArgKind::Path).optional()],
+};
+
 /// nl - number lines
 pub fn prog_nl(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(&args, "Usage: nl [FILE]\nNumber lines of a file.") {
-        stdout.push_str(&help);
-        return 0;
-    }
+    let m = match NL.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&NL, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
 
-    let input = if let Some(file) = args.first().filter(|f| !f.starts_with('-')) {
+    let input = if let Some(file) = args.first() {
         match read_file_content(file) {
             Ok(c) => c,
             Err(e) => {
@@ -482,35 +521,25 @@ pub fn prog_nl(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut S
     0
 }
 
+pub static FOLD: CommandSpec = CommandSpec {
+    name: "fold",
+    summary: "wrap lines",
+    description: "Wrap the lines of FILE, or of stdin, at WIDTH characters.",
+    flags: &[Flag::short('w', "Width (default 80)").value("WIDTH")],
+    args: &[Arg::new("FILE", ArgKind::Path).optional()],
+};
+
 /// fold - wrap lines
 pub fn prog_fold(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: fold [-w WIDTH] [FILE]\nWrap lines at specified width.\n  -w WIDTH  Width (default 80)",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
+    let m = match FOLD.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&FOLD, stdout, stderr),
+    };
 
-    let mut width: usize = 80;
-    let mut i = 0;
-    let mut file = None;
-
-    while i < args.len() {
-        if args[i] == "-w" && i + 1 < args.len() {
-            width = args[i + 1].parse().unwrap_or(80);
-            i += 2;
-        } else if !args[i].starts_with('-') {
-            file = Some(args[i].to_string());
-            i += 1;
-        } else {
-            i += 1;
-        }
-    }
+    let width: usize = m.value("w").map_or(80, |w| w.parse().unwrap_or(80));
+    let file = m.operand(0);
 
-    let input = if let Some(ref f) = file {
+    let input = if let Some(f) = file {
         match read_file_content(f) {
             Ok(c) => c,
             Err(e) => {
@@ -534,19 +563,21 @@ pub fn prog_fold(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut
     0
 }
 
+pub static PASTE: CommandSpec = CommandSpec {
+    name: "paste",
+    summary: "merge lines of files",
+    description: "Print the lines of the FILEs side by side, separated by tabs.",
+    flags: &[],
+    args: &[Arg::new("FILE", ArgKind::Path).repeated()],
+};
+
 /// paste - merge lines of files
 pub fn prog_paste(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(&args, "Usage: paste FILE1 FILE2...\nMerge lines of files.") {
-        stdout.push_str(&help);
-        return 0;
-    }
-
-    if args.is_empty() {
-        stderr.push_str("paste: requires at least one file\n");
-        return 1;
-    }
+    let m = match PASTE.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&PASTE, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
 
     let mut file_lines: Vec<Vec<String>> = Vec::new();
     let mut max_lines = 0;
@@ -580,33 +611,34 @@ pub fn prog_paste(args: &[String], _stdin: &str, stdout: &mut String, stderr: &m
     0
 }
 
+pub static COMM: CommandSpec = CommandSpec {
+    name: "comm",
+    summary: "compare sorted files",
+    description: "Compare the sorted files FILE1 and FILE2 line by line, in three columns: lines only in FILE1, lines only in FILE2, and lines in both.",
+    flags: &[
+        Flag::short('1', "Suppress column 1 (lines unique to FILE1)"),
+        Flag::short('2', "Suppress column 2 (lines unique to FILE2)"),
+        Flag::short('3', "Suppress column 3 (common lines)"),
+    ],
+    args: &[
+        Arg::new("FILE1", ArgKind::Path),
+        Arg::new("FILE2", ArgKind::Path),
+    ],
+};
+
 /// comm - compare sorted files
 pub fn prog_comm(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: comm [-123] FILE1 FILE2\nCompare sorted files line by line.\n  -1  Suppress column 1 (lines unique to FILE1)\n  -2  Suppress column 2 (lines unique to FILE2)\n  -3  Suppress column 3 (common lines)",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
-
-    let suppress1 = args.iter().any(|a| a.contains('1'));
-    let suppress2 = args.iter().any(|a| a.contains('2'));
-    let suppress3 = args.iter().any(|a| a.contains('3'));
+    let m = match COMM.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&COMM, stdout, stderr),
+    };
 
-    let files: Vec<&str> = args
-        .iter()
-        .filter(|a| !a.starts_with('-'))
-        .map(|s| s.as_ref())
-        .collect();
-    if files.len() < 2 {
-        stderr.push_str("comm: requires two files\n");
-        return 1;
-    }
+    let suppress1 = m.flag("1");
+    let suppress2 = m.flag("2");
+    let suppress3 = m.flag("3");
+    let files = &m.operands;
 
-    let content1 = match read_file_content(files[0]) {
+    let content1 = match read_file_content(&files[0]) {
         Ok(c) => c,
         Err(e) => {
             stderr.push_str(&format!("comm: {}: {}\n", files[0], e));
@@ -614,7 +646,7 @@ pub fn prog_comm(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mu
         }
     };
 
-    let content2 = match read_file_content(files[1]) {
+    let content2 = match read_file_content(&files[1]) {
         Ok(c) => c,
         Err(e) => {
             stderr.push_str(&format!("comm: {}: {}\n", files[1], e));
@@ -671,36 +703,31 @@ pub fn prog_comm(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mu
     0
 }
 
-/// strings - print strings from binary
-pub fn prog_strings(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
+pub static STRINGS: CommandSpec = CommandSpec {
+    name: "strings",
+    summary: "print strings from binary",
+    description: "Print the runs of printable characters in FILE, or in stdin.",
+    flags: &[Flag::short('n', "Minimum string length (default 4)").value("MIN")],
+    args: &[Arg::new("FILE", ArgKind::Path).optional()],
+};
 
-    if let Some(help) = check_help(
-        &args,
-        "Usage: strings [-n MIN] [FILE]\nPrint printable strings from file.\n  -n MIN  Minimum string length (default 4)",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
+/// strings - print strings from binary
+pub fn prog_strings(
+    args: &[String],
+    stdin: &[u8],
+    stdout: &mut Vec<u8>,
+    stderr: &mut String,
+) -> i32 {
+    let m = match parse_bytes(&STRINGS, args, stdout, stderr) {
+        Ok(m) => m,
+        Err(code) => return code,
+    };
 
-    let mut min_len: usize = 4;
-    let mut i = 0;
-    let mut file = None;
-
-    while i < args.len() {
-        if args[i] == "-n" && i + 1 < args.len() {
-            min_len = args[i + 1].parse().unwrap_or(4);
-            i += 2;
-        } else if !args[i].starts_with('-') {
-            file = Some(args[i].to_string());
-            i += 1;
-        } else {
-            i += 1;
-        }
-    }
+    let min_len: usize = m.value("n").map_or(4, |n| n.parse().unwrap_or(4));
+    let file = m.operand(0);
 
-    let input = if let Some(ref f) = file {
-        match read_file_content(f) {
+    let bytes = if let Some(f) = file {
+        match read_file_bytes(f) {
             Ok(c) => c,
             Err(e) => {
                 stderr.push_str(&format!("strings: {}: {}\n", f, e));
@@ -708,57 +735,75 @@ pub fn prog_strings(args: &[String], stdin: &str, stdout: &mut String, stderr: &
             }
         }
     } else {
-        stdin.to_string()
+        stdin.to_vec()
     };
 
-    let bytes = input.as_bytes();
-    let mut current = String::new();
+    let mut current = Vec::new();
 
     for byte in bytes {
-        if *byte >= 0x20 && *byte < 0x7f {
-            current.push(*byte as char);
+        if (0x20..0x7f).contains(&byte) {
+            current.push(byte);
         } else {
             if current.len() >= min_len {
-                stdout.push_str(&current);
-                stdout.push('\n');
+                stdout.extend_from_slice(&current);
+                stdout.push(b'\n');
             }
             current.clear();
         }
     }
 
     if current.len() >= min_len {
-        stdout.push_str(&current);
-        stdout.push('\n');
+        stdout.extend_from_slice(&current);
+        stdout.push(b'\n');
     }
 
     0
 }
 
+pub static DIFF: CommandSpec = CommandSpec {
+    name: "diff",
+    summary: "compare files line by line",
+    description: "Compare FILE1 and FILE2 line by line. Exit status is 0 if they are the same, 1 if not, 2 on errors.",
+    flags: &[
+        Flag::long(
+            "word-diff",
+            "Show changes word by word, as [-removed-]{+added+}",
+        ),
+        Flag::long(
+            "color",
+            "Color changes, highlighting the words that changed in a line",
+        ),
+    ],
+    args: &[
+        Arg::new("FILE1", ArgKind::Path),
+        Arg::new("FILE2", ArgKind::Path),
+    ],
+};
+
 /// diff - compare files line by line
 pub fn prog_diff(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: diff FILE1 FILE2\nCompare files line by line.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
+    let m = match DIFF.parse(args) {
+        Ok(m) => m,
+        Err(e) => {
+            return match e.report(&DIFF, stdout, stderr) {
+                0 => 0,
+                _ => 2,
+            };
+        }
+    };
 
-    if args.len() < 2 {
-        stderr.push_str("diff: requires two files\n");
-        return 1;
-    }
+    let word_diff = m.flag("word-diff");
+    let color = m.flag("color");
+    let files = &m.operands;
 
-    let file1 = args[0];
-    let file2 = args[1];
+    let file1 = &files[0];
+    let file2 = &files[1];
 
     let content1 = match read_file_content(file1) {
         Ok(c) => c,
         Err(e) => {
             stderr.push_str(&format!("diff: {}: {}\n", file1, e));
-            return 1;
+            return 2;
         }
     };
 
@@ -766,43 +811,355 @@ pub fn prog_diff(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mu
         Ok(c) => c,
         Err(e) => {
             stderr.push_str(&format!("diff: {}: {}\n", file2, e));
-            return 1;
+            return 2;
         }
     };
 
     let lines1: Vec<&str> = content1.lines().collect();
     let lines2: Vec<&str> = content2.lines().collect();
+    let hunks = diff::hunks(&diff::diff(&lines1, &lines2));
+
+    for hunk in &hunks {
+        let (old, new) = (&lines1[hunk.old.clone()], &lines2[hunk.new.clone()]);
+        let op = match (old.is_empty(), new.is_empty()) {
+            (true, _) => 'a',
+            (_, true) => 'd',
+            _ => 'c',
+        };
+        stdout.push_str(&format!(
+            "{}{}{}\n",
+            line_range(&hunk.old),
+            op,
+            line_range(&hunk.new)
+        ));
+
+        if word_diff {
+            for run in diff::words(&old.join("\n"), &new.join("\n")) {
+                match (run, color) {
+                    (Edit::Same(text), _) => stdout.push_str(&text),
+                    (Edit::Removed(text), true) => {
+                        stdout.push_str(&format!("{}{}{}", RED, text, RESET))
+                    }
+                    (Edit::Added(text), true) => {
+                        stdout.push_str(&format!("{}{}{}", GREEN, text, RESET))
+                    }
+                    (Edit::Removed(text), false) => stdout.push_str(&format!("[-{}-]", text)),
+                    (Edit::Added(text), false) => stdout.push_str(&format!("{{+{}+}}", text)),
+                }
+            }
+            stdout.push('\n');
+            continue;
+        }
 
-    let mut has_diff = false;
-    let max_len = lines1.len().max(lines2.len());
+        // With color, paired lines of a change show which words changed
+        for (i, line) in old.iter().enumerate() {
+            stdout.push_str(&diff_line('<', line, new.get(i).filter(|_| color), color));
+        }
+        if op == 'c' {
+            stdout.push_str("---\n");
+        }
+        for (i, line) in new.iter().enumerate() {
+            stdout.push_str(&diff_line('>', line, old.get(i).filter(|_| color), color));
+        }
+    }
 
-    for i in 0..max_len {
-        let l1 = lines1.get(i).copied();
-        let l2 = lines2.get(i).copied();
+    if hunks.is_empty() { 0 } else { 1 }
+}
 
-        match (l1, l2) {
-            (Some(a), Some(b)) if a != b => {
-                stdout.push_str(&format!("{}c{}\n", i + 1, i + 1));
-                stdout.push_str(&format!("< {}\n", a));
-                stdout.push_str("---\n");
-                stdout.push_str(&format!("> {}\n", b));
-                has_diff = true;
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const RESET: &str = "\x1b[0m";
+
+/// A 0-based line range as diff shows it: 1-based, `N` or `N,M`, or the
+/// line before it if empty
+fn line_range(range: &std::ops::Range<usize>) -> String {
+    match range.len() {
+        0 => range.start.to_string(),
+        1 => range.end.to_string(),
+        _ => format!("{},{}", range.start + 1, range.end),
+    }
+}
+
+/// One `<` or `>` line of diff output; with `other`, the line it was
+/// changed from or to, the words that differ are highlighted
+fn diff_line(side: char, line: &str, other: Option<&&str>, color: bool) -> String {
+    if !color {
+        return format!("{} {}\n", side, line);
+    }
+    let (start, removed) = if side == '<' {
+        (RED, true)
+    } else {
+        (GREEN, false)
+    };
+    let mut out = format!("{}{} ", start, side);
+    match other {
+        Some(other) => {
+            let runs = if removed {
+                diff::words(line, other)
+            } else {
+                diff::words(other, line)
+            };
+            for run in runs {
+                match run {
+                    Edit::Same(text) => out.push_str(&text),
+                    Edit::Removed(text) if removed => {
+                        out.push_str(&format!("\x1b[7m{}\x1b[27m", text))
+                    }
+                    Edit::Added(text) if !removed => {
+                        out.push_str(&format!("\x1b[7m{}\x1b[27m", text))
+                    }
+                    _ => {}
+                }
             }
-            (Some(a), None) => {
-                stdout.push_str(&format!("{}d{}\n", i + 1, lines2.len()));
-                stdout.push_str(&format!("< {}\n", a));
-                has_diff = true;
+        }
+        None => out.push_str(line),
+    }
+    out.push_str(RESET);
+    out.push('\n');
+    out
+}
+
+pub static MERGE3: CommandSpec = CommandSpec {
+    name: "merge3",
+    summary: "three-way merge",
+    description: "Merge the changes OURS and THEIRS each made to BASE. Conflicting changes are written between <<<<<<< and >>>>>>> markers. Exit status is 0 for a clean merge, 1 with conflicts, 2 on errors. See 'man merge3' for details.",
+    flags: &[
+        Flag::short('o', "Write the result to OUT instead of standard output").value("OUT"),
+        Flag::long("diff3", "Show the base's lines in conflicts too"),
+        Flag::short(
+            'L',
+            "Name ours, base and theirs in conflict markers, in turn (default: the file names)",
+        )
+        .value("LABEL"),
+    ],
+    args: &[
+        Arg::new("BASE", ArgKind::Path),
+        Arg::new("OURS", ArgKind::Path),
+        Arg::new("THEIRS", ArgKind::Path),
+    ],
+};
+
+/// merge3 - three-way merge
+pub fn prog_merge3(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match MERGE3.parse(args) {
+        Ok(m) => m,
+        Err(e) => {
+            return match e.report(&MERGE3, stdout, stderr) {
+                0 => 0,
+                _ => 2,
+            };
+        }
+    };
+
+    let output = m.value("o");
+    let show_base = m.flag("diff3");
+    let labels = m.values("L");
+    let [base, ours, theirs] = [0, 1, 2].map(|i| m.operands[i].as_str());
+
+    let mut contents = Vec::new();
+    for file in [base, ours, theirs] {
+        match read_file_content(file) {
+            Ok(content) => contents.push(content),
+            Err(e) => {
+                stderr.push_str(&format!("merge3: {}: {}\n", file, e));
+                return 2;
             }
-            (None, Some(b)) => {
-                stdout.push_str(&format!("{}a{}\n", lines1.len(), i + 1));
-                stdout.push_str(&format!("> {}\n", b));
-                has_diff = true;
+        }
+    }
+    let chunks = diff::merge3(&contents[0], &contents[1], &contents[2]);
+    let label = |n: usize, default| labels.get(n).copied().unwrap_or(default);
+    let labels = Labels {
+        ours: label(0, ours),
+        base: label(1, base),
+        theirs: label(2, theirs),
+    };
+    let merged = diff::render_merge(&chunks, labels, show_base);
+
+    match output {
+        Some(path) => {
+            if let Err(e) = syscall::write_file(path, &merged) {
+                stderr.push_str(&format!("merge3: {}: {}\n", path, e));
+                return 2;
             }
-            _ => {}
         }
+        None => stdout.push_str(&merged),
     }
 
-    if has_diff { 1 } else { 0 }
+    match diff::conflicts(&chunks) {
+        0 => 0,
+        n => {
+            stderr.push_str(&format!("merge3: {} conflict(s)\n", n));
+            1
+        }
+    }
+}
+
+pub static SPELL: CommandSpec = CommandSpec {
+    name: "spell",
+    summary: "find misspelled words",
+    description: "Print the misspelled words in FILEs (or stdin), each once. With -a, add the WORDs given as FILEs to your personal dictionary instead. Words are checked against /usr/share/dict/words and ~/.config/spell/words. Exit status is 0 if everything is spelled right, 1 if not, 2 on errors. See 'man spell' for details.",
+    flags: &[
+        Flag::short('s', "Suggest corrections after each word"),
+        Flag::short('n', "Print every misspelling with FILE:LINE:"),
+        Flag::long("markdown", "Skip code and links, as for .md files"),
+        Flag::short('a', "Add words to your personal dictionary"),
+    ],
+    args: &[Arg::new("FILE", ArgKind::Path).optional().repeated()],
+};
+
+/// spell - find misspelled words
+pub fn prog_spell(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match SPELL.parse(args) {
+        Ok(m) => m,
+        Err(e) => {
+            return match e.report(&SPELL, stdout, stderr) {
+                0 => 0,
+                _ => 2,
+            };
+        }
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
+
+    if m.flag("a") {
+        if args.is_empty() {
+            stderr.push_str("spell: -a requires words to add\n");
+            return 2;
+        }
+        return match spell::add_personal(&args) {
+            Ok(()) => 0,
+            Err(e) => {
+                stderr.push_str(&format!("spell: {}\n", e));
+                2
+            }
+        };
+    }
+
+    let suggest = m.flag("s");
+    let lines = m.flag("n");
+    let markdown = m.flag("markdown");
+    let files = args;
+
+    let mut inputs = Vec::new();
+    if files.is_empty() {
+        inputs.push(("-", stdin.to_string()));
+    }
+    for file in files {
+        match read_file_content(file) {
+            Ok(content) => inputs.push((file, content)),
+            Err(e) => {
+                stderr.push_str(&format!("spell: {}: {}\n", file, e));
+                return 2;
+            }
+        }
+    }
+
+    let dict = spell::load();
+    let mut seen = std::collections::HashSet::new();
+    for (file, content) in &inputs {
+        let markdown = markdown || spell::is_markdown(file);
+        let mut fences = spell::Fences::default();
+        for (n, line) in content.lines().enumerate() {
+            if markdown && !fences.prose(line) {
+                continue;
+            }
+            for range in dict.misspelled(line, markdown) {
+                let word: String = line.chars().skip(range.start).take(range.len()).collect();
+                let first = seen.insert(word.clone());
+                if lines {
+                    stdout.push_str(&format!("{}:{}: ", file, n + 1));
+                } else if !first {
+                    continue;
+                }
+                stdout.push_str(&word);
+                if suggest {
+                    stdout.push(':');
+                    for (i, suggestion) in dict.suggest(&word, 5).iter().enumerate() {
+                        stdout.push_str(if i == 0 { " " } else { ", " });
+                        stdout.push_str(suggestion);
+                    }
+                }
+                stdout.push('\n');
+            }
+        }
+    }
+    if seen.is_empty() { 0 } else { 1 }
+}
+
+pub static UNICODE: CommandSpec = CommandSpec {
+    name: "unicode",
+    summary: "look up characters by name or code point",
+    description: "Describe characters: code point, UTF-8 bytes, display width and name. Each CHAR is a character, a code point U+XXXX or a range U+XXXX..U+YYYY; words that aren't characters are a name, looked up or searched for. Without arguments, describe each character of stdin. Exit status is 0 if anything matched, 1 if not, 2 on errors. See 'man unicode' for details.",
+    flags: &[
+        Flag::short('n', "Show at most N search results (default 20)").value("N"),
+        Flag::short('c', "Describe each character of TEXT").value("TEXT"),
+    ],
+    args: &[Arg::new("CHAR", ArgKind::Text).optional().repeated()],
+};
+
+/// unicode - look up characters by name or code point
+pub fn prog_unicode(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match UNICODE.parse(args) {
+        Ok(m) => m,
+        Err(e) => {
+            return match e.report(&UNICODE, stdout, stderr) {
+                0 => 0,
+                _ => 2,
+            };
+        }
+    };
+
+    let limit = match m.value("n").map(str::parse) {
+        None => 20,
+        Some(Ok(n)) => n,
+        Some(Err(_)) => {
+            stderr.push_str(&format!(
+                "unicode: invalid count '{}'\n",
+                m.value("n").unwrap_or_default()
+            ));
+            return 2;
+        }
+    };
+    let mut chars: Vec<char> = m.values("c").iter().flat_map(|text| text.chars()).collect();
+    let mut words = Vec::new();
+    for arg in &m.operands {
+        let arg = arg.as_str();
+        if let Some(range) = unicode::parse_range(arg) {
+            if range.end() - range.start() >= unicode::MAX_RANGE {
+                stderr.push_str(&format!(
+                    "unicode: {}: more than {} code points\n",
+                    arg,
+                    unicode::MAX_RANGE
+                ));
+                return 2;
+            }
+            chars.extend(range.filter_map(char::from_u32));
+        } else if let Some(c) = unicode::parse_codepoint(arg) {
+            chars.push(c);
+        } else if arg.chars().count() == 1 {
+            chars.extend(arg.chars());
+        } else if arg.starts_with("U+") || arg.starts_with("0x") {
+            stderr.push_str(&format!("unicode: {}: not a code point\n", arg));
+            return 2;
+        } else {
+            words.push(arg);
+        }
+    }
+    if args.is_empty() {
+        chars.extend(stdin.strip_suffix('\n').unwrap_or(stdin).chars());
+    }
+
+    if !words.is_empty() {
+        let name = words.join(" ");
+        match unicode::lookup(&name) {
+            Some(c) => chars.push(c),
+            None => chars.extend(unicode::search(&name, limit)),
+        }
+    }
+    for c in &chars {
+        stdout.push_str(&unicode::describe(*c));
+        stdout.push('\n');
+    }
+    if chars.is_empty() { 1 } else { 0 }
 }
 
 #[cfg(test)]
@@ -813,9 +1170,10 @@ mod tests {
     fn test_prog_wc() {
         let args: Vec<String> = vec![];
         let stdin = "hello world\nfoo bar baz";
-        let mut stdout = String::new();
+        let mut stdout = Vec::new();
         let mut stderr = String::new();
-        let code = prog_wc(&args, stdin, &mut stdout, &mut stderr);
+        let code = prog_wc(&args, stdin.as_bytes(), &mut stdout, &mut stderr);
+        let stdout = String::from_utf8(stdout).unwrap();
         assert_eq!(code, 0);
         assert!(stdout.contains("2")); // 2 lines
         assert!(stdout.contains("5")); // 5 words
@@ -838,6 +1196,12 @@ mod tests {
             .replace("[0m", "");
         assert!(plain.contains("apple"));
         assert!(plain.contains("apricot"));
+
+        let args: Vec<String> = ["-inv", "AP"].iter().map(|a| a.to_string()).collect();
+        let mut stdout = String::new();
+        let code = prog_grep(&args, stdin, &mut stdout, &mut stderr);
+        assert_eq!(code, 0);
+        assert_eq!(stdout, "2:banana\n4:cherry");
     }
 
     #[test]
@@ -906,4 +1270,159 @@ mod tests {
         assert!(stdout.contains("olleh"));
         assert!(stdout.contains("dlrow"));
     }
+
+    #[test]
+    fn test_prog_diff() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        syscall::write_file("/tmp/a", "one\ntwo\nthree\nfour\n").unwrap();
+        syscall::write_file("/tmp/b", "one\nthree\nfour score\nfive\n").unwrap();
+        let run = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = prog_diff(&args, "", &mut stdout, &mut stderr);
+            (code, stdout)
+        };
+
+        assert_eq!(
+            run(&["/tmp/a", "/tmp/b"]),
+            (
+                1,
+                "2d1\n< two\n4c3,4\n< four\n---\n> four score\n> five\n".to_string()
+            )
+        );
+        assert_eq!(
+            run(&["--word-diff", "/tmp/a", "/tmp/b"]),
+            (1, "2d1\n[-two-]\n4c3,4\nfour{+ score\nfive+}\n".to_string())
+        );
+        let (_, colored) = run(&["--color", "/tmp/a", "/tmp/b"]);
+        assert!(colored.contains("\x1b[32m> four\x1b[7m score\x1b[27m\x1b[0m"));
+        assert_eq!(run(&["/tmp/a", "/tmp/a"]), (0, String::new()));
+    }
+
+    #[test]
+    fn test_prog_merge3() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        syscall::write_file("/tmp/base", "port = 80\nhost = a\nlog = off\n").unwrap();
+        syscall::write_file("/tmp/ours", "port = 8080\nhost = a\nlog = off\n").unwrap();
+        syscall::write_file("/tmp/theirs", "port = 80\nhost = a\nlog = on\n").unwrap();
+        let run = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = prog_merge3(&args, "", &mut stdout, &mut stderr);
+            (code, stdout, stderr)
+        };
+
+        let (code, out, _) = run(&["/tmp/base", "/tmp/ours", "/tmp/theirs"]);
+        assert_eq!(
+            (code, out.as_str()),
+            (0, "port = 8080\nhost = a\nlog = on\n")
+        );
+
+        syscall::write_file("/tmp/theirs", "port = 81\nhost = a\nlog = off\n").unwrap();
+        let (code, out, err) = run(&[
+            "-L",
+            "mine",
+            "/tmp/base",
+            "/tmp/ours",
+            "/tmp/theirs",
+            "-o",
+            "/tmp/out",
+        ]);
+        assert_eq!(
+            (code, out.as_str(), err.as_str()),
+            (1, "", "merge3: 1 conflict(s)\n")
+        );
+        assert_eq!(
+            syscall::read_file("/tmp/out").unwrap(),
+            "<<<<<<< mine\nport = 8080\n=======\nport = 81\n>>>>>>> /tmp/theirs\nhost = a\nlog = off\n"
+        );
+        assert_eq!(run(&["/tmp/base", "/tmp/ours"]).0, 2);
+    }
+
+    #[test]
+    fn test_prog_spell() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        syscall::mkdir("/tmp/me").unwrap();
+        syscall::setenv("HOME", "/tmp/me").unwrap();
+        syscall::write_file(
+            "/tmp/notes.md",
+            "The quick brwon fox\n```\nlet mut qux = 1;\n```\nIt jumpd over `teh` brwon dog\n",
+        )
+        .unwrap();
+        let run = |args: &[&str], stdin: &str| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = prog_spell(&args, stdin, &mut stdout, &mut stderr);
+            (code, stdout, stderr)
+        };
+
+        let (code, out, _) = run(&["/tmp/notes.md"], "");
+        assert_eq!((code, out.as_str()), (1, "brwon\njumpd\n"));
+        let (_, out, _) = run(&["-n", "/tmp/notes.md"], "");
+        assert_eq!(
+            out,
+            "/tmp/notes.md:1: brwon\n/tmp/notes.md:5: jumpd\n/tmp/notes.md:5: brwon\n"
+        );
+        let (_, out, _) = run(&["-s"], "brwon");
+        assert!(out.starts_with("brwon: brown"));
+        assert_eq!(run(&["--markdown"], "all `teh` here").0, 0);
+        assert_eq!(run(&[], "all `teh` here").0, 1);
+
+        assert_eq!(run(&["-a", "jumpd", "Brwon"], "").0, 0);
+        assert_eq!(run(&["/tmp/notes.md"], "").0, 0);
+        assert_eq!(run(&["/tmp/missing"], "").0, 2);
+        assert_eq!(run(&["-a"], "").0, 2);
+    }
+    #[test]
+    fn test_prog_unicode() {
+        let run = |args: &[&str], stdin: &str| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = prog_unicode(&args, stdin, &mut stdout, &mut stderr);
+            (code, stdout, stderr)
+        };
+
+        let (code, out, _) = run(&["U+20AC", "é"], "");
+        assert_eq!(code, 0);
+        assert_eq!(
+            out,
+            "U+20AC  €   E2 82 AC     1  EURO SIGN\nU+00E9  é   C3 A9        1  LATIN SMALL LETTER E WITH ACUTE\n"
+        );
+        let (_, out, _) = run(&["euro", "sign"], "");
+        assert_eq!(out.lines().count(), 1);
+        let (_, out, _) = run(&["-n", "2", "arrow"], "");
+        assert_eq!(out.lines().count(), 2);
+        let (_, out, _) = run(&["U+41..U+43"], "");
+        assert_eq!(out.lines().count(), 3);
+        let (_, out, _) = run(&["-c", "中\u{301}"], "");
+        assert!(out.starts_with("U+4E2D  中  E4 B8 AD     2  CJK UNIFIED IDEOGRAPH-4E2D\n"));
+        assert!(out.contains("◌\u{301}"));
+        let (_, out, _) = run(&[], "a\tb\n");
+        assert_eq!(out.lines().count(), 3);
+        assert!(out.contains("U+0009"));
+        assert!(out.contains("-  <control>"));
+
+        assert_eq!(run(&["no such thing at all"], "").0, 1);
+        assert_eq!(run(&["U+110000"], "").0, 2);
+        assert_eq!(run(&["U+0..U+FFFF"], "").0, 2);
+        assert_eq!(run(&["-n"], "").0, 2);
+    }
 }
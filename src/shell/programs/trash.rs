@@ -0,0 +1,558 @@
+//! Trash
+//!
+//! With `rm = true` in `~/.config/trash.toml`, or `rm --trash`, `rm` moves
+//! files into `~/.trash` instead of removing them, so a mistaken `rm` can
+//! be undone with `trash restore`:
+//!
+//! ```toml
+//! # Make rm move files to the trash
+//! rm = true
+//! # Empty the oldest entries once the trash holds more than this
+//! max_size = "16M"
+//! ```
+//!
+//! Each trashed file or directory goes under `files/`, named after it
+//! with `.2`, `.3`... if that name is taken, and an entry of the same name
+//! under `info/` records where it came from:
+//!
+//! ```text
+//! axeberg-trash 1
+//! path: /home/user/notes.txt
+//! time: 1760000000000
+//! size: 1234
+//! ```
+//!
+//! Whenever something is trashed the oldest entries are emptied until the
+//! trash fits in `max_size` again; the newest entry always stays.
+
+use super::file::{Io, Removal, RemoveOptions, mkdir_parents, move_path, remove_tree};
+use super::services::ago;
+use crate::kernel::mount::parse_size;
+use crate::kernel::syscall;
+use crate::shell::argparse::{Arg, ArgKind, CommandSpec};
+use crate::shell::profile::{parse_value, strip_comment};
+use crate::shell::stats;
+use crate::shell::swap::absolute;
+
+/// Where trashed files go, relative to the home directory
+const TRASH_DIR: &str = ".trash";
+
+/// Settings file, relative to the home directory
+const CONFIG_FILE: &str = ".config/trash.toml";
+
+/// How big the trash may grow without a `max_size`
+const DEFAULT_MAX_SIZE: u64 = 16 * 1024 * 1024;
+
+/// First line of every info file
+const MAGIC: &str = "axeberg-trash 1";
+
+/// Trash settings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrashConfig {
+    /// `rm` trashes instead of removing
+    pub rm: bool,
+    /// Bytes the trash may hold before the oldest entries are emptied
+    pub max_size: u64,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        Self {
+            rm: false,
+            max_size: DEFAULT_MAX_SIZE,
+        }
+    }
+}
+
+impl TrashConfig {
+    /// Parse a settings file
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let mut config = Self::default();
+        for (i, raw) in content.lines().enumerate() {
+            let line = strip_comment(raw).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let err = |msg: String| format!("line {}: {}", i + 1, msg);
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| err(format!("expected key = value, got '{}'", line)))?;
+            let value = value.trim();
+            match key.trim() {
+                "rm" => {
+                    config.rm = match value {
+                        "true" => true,
+                        "false" => false,
+                        _ => return Err(err(format!("rm must be true or false, got {}", value))),
+                    };
+                }
+                "max_size" => {
+                    let size = if value.starts_with('"') {
+                        parse_value(value).map_err(&err)?
+                    } else {
+                        value.to_string()
+                    };
+                    config.max_size = parse_size(&size)
+                        .map_err(|()| err(format!("invalid size '{}'", size)))?
+                        as u64;
+                }
+                key => return Err(err(format!("unknown key '{}'", key))),
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// The current user's home directory
+fn home() -> String {
+    syscall::getenv("HOME")
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| "/tmp".to_string())
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// Load the current user's settings
+pub(super) fn load_config() -> Result<TrashConfig, String> {
+    let path = format!("{}/{}", home(), CONFIG_FILE);
+    match syscall::read_file(&path) {
+        Ok(content) => TrashConfig::parse(&content).map_err(|e| format!("{}: {}", path, e)),
+        Err(_) => Ok(TrashConfig::default()),
+    }
+}
+
+/// The user's trash directory
+pub(super) fn trash_dir() -> String {
+    format!("{}/{}", home(), TRASH_DIR)
+}
+
+/// Something in the trash
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrashEntry {
+    /// Name under `files/` and `info/`
+    pub name: String,
+    /// Absolute path it was trashed from
+    pub path: String,
+    /// When it was trashed, in milliseconds since the epoch
+    pub time: f64,
+    /// Bytes in it, counting everything below a directory
+    pub size: u64,
+}
+
+impl TrashEntry {
+    pub fn to_text(&self) -> String {
+        format!(
+            "{}\npath: {}\ntime: {:.0}\nsize: {}\n",
+            MAGIC, self.path, self.time, self.size
+        )
+    }
+
+    pub fn parse(name: &str, text: &str) -> Option<Self> {
+        let header = text.strip_prefix(MAGIC)?.strip_prefix('\n')?;
+        let (mut path, mut time, mut size) = (None, None, None);
+        for line in header.lines() {
+            match line.split_once(": ") {
+                Some(("path", value)) => path = Some(value.to_string()),
+                Some(("time", value)) => time = value.parse().ok(),
+                Some(("size", value)) => size = value.parse().ok(),
+                _ => {}
+            }
+        }
+        Some(Self {
+            name: name.to_string(),
+            path: path?,
+            time: time?,
+            size: size?,
+        })
+    }
+
+    /// Where the entry's file or directory is kept
+    pub fn file(&self) -> String {
+        format!("{}/files/{}", trash_dir(), self.name)
+    }
+
+    fn info(&self) -> String {
+        format!("{}/info/{}", trash_dir(), self.name)
+    }
+}
+
+/// Everything in the trash, oldest first
+pub(super) fn list() -> Vec<TrashEntry> {
+    let info = format!("{}/info", trash_dir());
+    let mut entries: Vec<TrashEntry> = syscall::readdir(&info)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|name| {
+            let text = syscall::read_file(&format!("{}/{}", info, name)).ok()?;
+            TrashEntry::parse(name, &text)
+        })
+        .collect();
+    entries.sort_by(|a, b| a.time.total_cmp(&b.time).then(a.name.cmp(&b.name)));
+    entries
+}
+
+/// Bytes in `path`, and everything below it if it is a directory
+fn tree_size(path: &str) -> u64 {
+    match syscall::metadata(path) {
+        Ok(meta) if meta.is_dir => syscall::readdir(path)
+            .unwrap_or_default()
+            .iter()
+            .map(|name| tree_size(&format!("{}/{}", path.trim_end_matches('/'), name)))
+            .sum(),
+        Ok(meta) if meta.is_file => meta.size,
+        _ => 0,
+    }
+}
+
+/// Whether absolute path `path` is in the trash, or is the trash
+pub(super) fn in_trash(path: &str) -> bool {
+    let dir = trash_dir();
+    path.strip_prefix(&dir)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Move `path` into the trash, recording where it came from
+pub(super) fn put(path: &str, io: &mut Io) -> Result<TrashEntry, String> {
+    let from = absolute(path);
+    if in_trash(&from) {
+        return Err(format!("cannot trash '{}': it is in the trash", path));
+    }
+    let dir = trash_dir();
+    for sub in ["files", "info"] {
+        mkdir_parents(&format!("{}/{}", dir, sub)).map_err(|e| format!("{}: {}", dir, e))?;
+    }
+
+    let base = from.rsplit('/').next().unwrap_or_default();
+    let taken = |name: &str| {
+        ["files", "info"]
+            .iter()
+            .any(|sub| syscall::exists(&format!("{}/{}/{}", dir, sub, name)).unwrap_or(false))
+    };
+    let name = std::iter::once(base.to_string())
+        .chain((2..).map(|n| format!("{}.{}", base, n)))
+        .find(|name| !taken(name))
+        .unwrap_or_default();
+    // Entries keep the order they were trashed in, even within a tick
+    let now = stats::clock();
+    let entry = TrashEntry {
+        name,
+        path: from,
+        time: list().last().map_or(now, |last| now.max(last.time + 1.0)),
+        size: tree_size(path),
+    };
+
+    // The info goes first, so a file in the trash always has one
+    syscall::write_file(&entry.info(), &entry.to_text())
+        .map_err(|e| format!("{}: {}", entry.info(), e))?;
+    if let Err(e) = move_path(path, &entry.file(), false, io) {
+        let _ = syscall::remove_file(&entry.info());
+        return Err(e);
+    }
+    Ok(entry)
+}
+
+/// Move an entry back where it came from
+pub(super) fn restore(entry: &TrashEntry, io: &mut Io) -> Result<(), String> {
+    if syscall::exists(&entry.path).unwrap_or(false) {
+        return Err(format!("cannot restore '{}': it exists", entry.path));
+    }
+    if let Some((parent, _)) = entry.path.rsplit_once('/')
+        && !parent.is_empty()
+    {
+        mkdir_parents(parent).map_err(|e| format!("{}: {}", parent, e))?;
+    }
+    move_path(&entry.file(), &entry.path, false, io)?;
+    let _ = syscall::remove_file(&entry.info());
+    Ok(())
+}
+
+/// Remove an entry for good
+pub(super) fn empty(entry: &TrashEntry, io: &mut Io) -> Result<(), String> {
+    let file = entry.file();
+    if let Ok(meta) = syscall::metadata(&file) {
+        let opts = RemoveOptions {
+            recursive: true,
+            interactive: false,
+            verbose: false,
+        };
+        if remove_tree(&file, &meta, &opts, io) != Removal::Removed {
+            return Err(format!("cannot empty '{}'", entry.name));
+        }
+    }
+    syscall::remove_file(&entry.info()).map_err(|e| format!("cannot empty '{}': {}", entry.name, e))
+}
+
+/// Empty the oldest entries until the trash holds at most `max_size`
+/// bytes, keeping the newest; returns the entries emptied
+pub(super) fn evict(max_size: u64, io: &mut Io) -> Vec<TrashEntry> {
+    let mut entries = list();
+    let mut total: u64 = entries.iter().map(|e| e.size).sum();
+    let mut evicted = Vec::new();
+    while total > max_size && entries.len() > 1 {
+        let oldest = entries.remove(0);
+        if empty(&oldest, io).is_err() {
+            break;
+        }
+        total -= oldest.size;
+        evicted.push(oldest);
+    }
+    evicted
+}
+
+/// The entry `which` names: the path it was trashed from, the most
+/// recent if that was trashed more than once, or else an entry name
+fn find(entries: &[TrashEntry], which: &str) -> Option<TrashEntry> {
+    let path = absolute(which);
+    entries
+        .iter()
+        .rev()
+        .find(|e| e.path == path)
+        .or_else(|| entries.iter().find(|e| e.name == which))
+        .cloned()
+}
+
+/// A byte count in K, M or G
+fn human_size(bytes: u64) -> String {
+    let units = [("G", 1 << 30), ("M", 1 << 20), ("K", 1 << 10)];
+    match units.iter().find(|&&(_, unit)| bytes >= unit) {
+        Some((suffix, unit)) => format!("{:.1}{}", bytes as f64 / *unit as f64, suffix),
+        None => format!("{}B", bytes),
+    }
+}
+
+pub static TRASH: CommandSpec = CommandSpec {
+    name: "trash",
+    summary: "move files to the trash and get them back",
+    description: "Keep removed files in ~/.trash until they are restored or emptied. COMMAND is one of:
+
+  list                 list what is in the trash, oldest first (the default)
+  put FILE...          move each FILE, or directory, to the trash
+  restore ENTRY...     move each ENTRY back where it was trashed from
+  empty [ENTRY...]     remove each ENTRY for good, or everything
+
+An ENTRY is a name from the list, or the path a file was trashed from. With rm = true in ~/.config/trash.toml, rm trashes files too. Once the trash holds more than max_size (16M unless set there), the oldest entries are emptied.",
+    flags: &[],
+    args: &[
+        Arg::new("COMMAND", ArgKind::Text).optional(),
+        Arg::new("ENTRY", ArgKind::Path).optional().repeated(),
+    ],
+};
+
+/// trash - the trash can
+pub fn prog_trash(args: &[String], stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match TRASH.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&TRASH, stdout, stderr),
+    };
+    let (command, names) = match m.operands.split_first() {
+        Some((command, names)) => (command.as_str(), names),
+        None => ("list", &[][..]),
+    };
+    let mut io = Io::new(stdin, stdout, stderr);
+    let mut failed = false;
+    let mut fail = |io: &mut Io, e: String| {
+        io.stderr.push_str(&format!("trash: {}\n", e));
+        failed = true;
+    };
+
+    match (command, names) {
+        ("list", []) => {
+            let entries = list();
+            if entries.is_empty() {
+                io.stdout.push_str("Trash is empty\n");
+                return 0;
+            }
+            let now = stats::clock();
+            io.stdout.push_str(&format!(
+                "{:<20} {:>7}  {:<9} PATH\n",
+                "NAME", "SIZE", "TRASHED"
+            ));
+            for entry in entries {
+                io.stdout.push_str(&format!(
+                    "{:<20} {:>7}  {:<9} {}\n",
+                    entry.name,
+                    human_size(entry.size),
+                    ago(now, entry.time),
+                    entry.path
+                ));
+            }
+        }
+        ("put", [_, ..]) => {
+            for path in names {
+                if let Err(e) = put(path, &mut io) {
+                    fail(&mut io, e);
+                }
+            }
+            match load_config() {
+                Ok(config) => {
+                    evict(config.max_size, &mut io);
+                }
+                Err(e) => fail(&mut io, e),
+            }
+        }
+        ("restore", [_, ..]) => {
+            let entries = list();
+            for which in names {
+                let result = match find(&entries, which) {
+                    Some(entry) => restore(&entry, &mut io),
+                    None => Err(format!("'{}' is not in the trash", which)),
+                };
+                if let Err(e) = result {
+                    fail(&mut io, e);
+                }
+            }
+        }
+        ("empty", []) => {
+            for entry in list() {
+                if let Err(e) = empty(&entry, &mut io) {
+                    fail(&mut io, e);
+                }
+            }
+        }
+        ("empty", _) => {
+            let entries = list();
+            for which in names {
+                let result = match find(&entries, which) {
+                    Some(entry) => empty(&entry, &mut io),
+                    None => Err(format!("'{}' is not in the trash", which)),
+                };
+                if let Err(e) = result {
+                    fail(&mut io, e);
+                }
+            }
+        }
+        _ => {
+            io.stderr.push_str(&format!(
+                "trash: invalid command: {}\n{}\n",
+                m.operands.join(" "),
+                TRASH.usage()
+            ));
+            return 1;
+        }
+    }
+    if failed { 1 } else { 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::syscall::{KERNEL, Kernel};
+
+    fn run(args: &[&str]) -> (i32, String, String) {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        let (mut stdout, mut stderr) = (String::new(), String::new());
+        let code = prog_trash(&args, "", &mut stdout, &mut stderr);
+        (code, stdout, stderr)
+    }
+
+    fn rm(args: &[&str]) -> (i32, String, String) {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        let (mut stdout, mut stderr) = (String::new(), String::new());
+        let code = super::super::prog_rm(&args, "", &mut stdout, &mut stderr);
+        (code, stdout, stderr)
+    }
+
+    fn setup() {
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        syscall::mkdir("/tmp/me").unwrap();
+        syscall::setenv("HOME", "/tmp/me").unwrap();
+        syscall::chdir("/tmp/me").unwrap();
+    }
+
+    #[test]
+    fn test_trash_config() {
+        assert_eq!(TrashConfig::parse("").unwrap(), TrashConfig::default());
+        let config = TrashConfig::parse("rm = true # please\nmax_size = \"2K\"\n").unwrap();
+        assert!(config.rm);
+        assert_eq!(config.max_size, 2048);
+        assert_eq!(TrashConfig::parse("max_size = 100").unwrap().max_size, 100);
+        assert_eq!(
+            TrashConfig::parse("rm = yes"),
+            Err("line 1: rm must be true or false, got yes".to_string())
+        );
+        assert!(TrashConfig::parse("max_size = \"lots\"").is_err());
+    }
+
+    #[test]
+    fn test_rm_to_trash_and_restore() {
+        setup();
+        syscall::write_file("notes.txt", "draft").unwrap();
+        syscall::mkdir("/tmp/me/src").unwrap();
+        syscall::write_file("/tmp/me/src/main.rs", "fn main() {}").unwrap();
+
+        // Plain rm removes for good until the trash is turned on
+        syscall::write_file("scratch", "x").unwrap();
+        assert_eq!(rm(&["scratch"]).0, 0);
+        assert_eq!(run(&[]).1, "Trash is empty\n");
+
+        syscall::mkdir("/tmp/me/.config").unwrap();
+        syscall::write_file("/tmp/me/.config/trash.toml", "rm = true\n").unwrap();
+        let (code, stdout, stderr) = rm(&["-rv", "notes.txt", "src"]);
+        assert_eq!(code, 0, "{}", stderr);
+        assert_eq!(stdout, "trashed 'notes.txt'\ntrashed 'src'\n");
+        assert!(!syscall::exists("/tmp/me/notes.txt").unwrap());
+        assert_eq!(
+            syscall::read_file("/tmp/me/.trash/files/src/main.rs").unwrap(),
+            "fn main() {}"
+        );
+
+        let entries = list();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].path, "/tmp/me/notes.txt");
+        assert_eq!(entries[1].size, 12);
+        let (_, stdout, _) = run(&["list"]);
+        assert!(stdout.contains("/tmp/me/src"), "{}", stdout);
+
+        // A second file of the same name gets a name of its own
+        syscall::write_file("notes.txt", "final").unwrap();
+        assert_eq!(rm(&["notes.txt"]).0, 0);
+        assert_eq!(list()[2].name, "notes.txt.2");
+
+        // By path the most recent comes back, by name any of them
+        assert_eq!(run(&["restore", "notes.txt"]).0, 0);
+        assert_eq!(syscall::read_file("notes.txt").unwrap(), "final");
+        let (code, _, stderr) = run(&["restore", "notes.txt"]);
+        assert_eq!(code, 1);
+        assert!(stderr.contains("it exists"), "{}", stderr);
+        assert_eq!(run(&["restore", "src"]).0, 0);
+        assert_eq!(
+            syscall::read_file("/tmp/me/src/main.rs").unwrap(),
+            "fn main() {}"
+        );
+
+        // --no-trash, and anything already in the trash, go for good
+        assert_eq!(rm(&["--no-trash", "notes.txt"]).0, 0);
+        assert_eq!(list().len(), 1);
+        assert_eq!(run(&["empty"]).0, 0);
+        assert_eq!(list().len(), 0);
+        assert!(syscall::readdir("/tmp/me/.trash/files").unwrap().is_empty());
+        let (code, _, stderr) = run(&["restore", "gone"]);
+        assert_eq!(code, 1);
+        assert!(stderr.contains("'gone' is not in the trash"));
+    }
+
+    #[test]
+    fn test_trash_eviction() {
+        setup();
+        syscall::mkdir("/tmp/me/.config").unwrap();
+        syscall::write_file("/tmp/me/.config/trash.toml", "max_size = 10\n").unwrap();
+        for name in ["a", "b", "c"] {
+            syscall::write_file(name, "12345").unwrap();
+            assert_eq!(run(&["put", name]).0, 0);
+        }
+        let names: Vec<String> = list().into_iter().map(|e| e.name).collect();
+        assert_eq!(names, ["b", "c"]);
+
+        // The newest stays, however big
+        syscall::write_file("big", &"x".repeat(100)).unwrap();
+        assert_eq!(run(&["put", "big"]).0, 0);
+        let names: Vec<String> = list().into_iter().map(|e| e.name).collect();
+        assert_eq!(names, ["big"]);
+    }
+}
@@ -1,18 +1,28 @@
 //! TTY (terminal) programs
 
-use super::{args_to_strs, check_help};
 use crate::kernel::syscall;
+use crate::kernel::tty::VT_COUNT;
+use crate::shell::argparse::{Arg, ArgKind, CommandSpec, Flag};
 
-pub fn prog_stty(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
+pub static STTY: CommandSpec = CommandSpec {
+    name: "stty",
+    summary: "change and print terminal line settings",
+    description: "Change and print terminal line settings. With no SETTING, or with -a, print all settings. A SETTING is one of:
 
-    if let Some(help) = check_help(
-        &args,
-        "Usage: stty [SETTING]...\n       stty -a\n       stty sane\n       stty raw\n\nChange and print terminal line settings.\n\nSettings:\n  -echo/-icanon/-isig  Toggle flags\n  sane                 Reset to sane defaults\n  raw                  Set raw mode\n  -a                   Print all settings",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
+  FLAG                  turn a flag such as echo, icanon or isig on (-FLAG turns it off)
+  sane                  reset to sane defaults
+  raw                   set raw mode",
+    flags: &[Flag::short('a', "Print all settings")],
+    args: &[Arg::new("SETTING", ArgKind::Text).optional().repeated().raw()],
+};
+
+pub fn prog_stty(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match STTY.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&STTY, stdout, stderr),
+    };
+    // Settings like -echo are operands, so -a is one too
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
 
     use crate::kernel::tty::{Termios, format_stty_settings, parse_stty_setting};
 
@@ -54,33 +64,246 @@ pub fn prog_stty(args: &[String], __stdin: &str, stdout: &mut String, stderr: &m
     })
 }
 
-pub fn prog_tty(args: &[String], __stdin: &str, stdout: &mut String, _stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
+pub static TTY: CommandSpec = CommandSpec {
+    name: "tty",
+    summary: "print the terminal name",
+    description: "Print the file name of the terminal connected to standard input. Exit with status 1 if it isn't a terminal.",
+    flags: &[Flag::short('s', "Print nothing, only set the exit status")],
+    args: &[],
+};
 
-    if let Some(help) = check_help(
-        &args,
-        "Usage: tty\nPrint the file name of the terminal connected to standard input.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
+pub fn prog_tty(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match TTY.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&TTY, stdout, stderr),
+    };
 
-    let silent = args.contains(&"-s");
+    let silent = m.flag("s");
 
-    syscall::KERNEL.with(|k| {
-        let kernel = k.borrow();
-        if let Some(tty) = kernel.ttys().current_tty() {
+    match syscall::ttyname(syscall::Fd::STDIN) {
+        Ok(Some(path)) => {
             if !silent {
-                stdout.push_str(&format!("/dev/{}\n", tty.name));
+                stdout.push_str(&format!("{}\n", path));
             }
             0
-        } else {
+        }
+        _ => {
             if !silent {
                 stdout.push_str("not a tty\n");
             }
             1
         }
-    })
+    }
+}
+
+pub static PROFILE: CommandSpec = CommandSpec {
+    name: "profile",
+    summary: "manage terminal profiles",
+    description: "Manage terminal profiles in /etc/terminal/profiles.toml. COMMAND is one of:
+
+  list                  list the profiles, the default marked * (the default)
+  show [NAME]           show a profile's settings
+  set NAME KEY VALUE    change a setting
+  unset NAME KEY        clear a setting
+  default NAME          make NAME the default profile
+  new NAME [FROM]       create a profile, copying FROM if given
+  rm NAME               remove a profile
+
+Keys:
+
+  font_size      Font size in pixels (6-72)
+  font_family    CSS font stack
+  cwd            Directory new terminals start in
+  startup        Command run when a terminal starts
+  colors.NAME    Theme color override (#rrggbb), e.g. colors.background
+
+Changes apply to terminals opened afterwards; the default profile is applied at boot.",
+    flags: &[],
+    args: &[
+        Arg::new("COMMAND", ArgKind::Text).optional(),
+        Arg::new("ARG", ArgKind::Text).optional().repeated().raw(),
+    ],
+};
+
+pub fn prog_profile(
+    args: &[String],
+    __stdin: &str,
+    stdout: &mut String,
+    stderr: &mut String,
+) -> i32 {
+    let m = match PROFILE.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&PROFILE, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
+
+    use crate::shell::profile;
+
+    let mut profiles = match profile::load() {
+        Ok(profiles) => profiles,
+        Err(e) => {
+            stderr.push_str(&format!("profile: {}\n", e));
+            return 1;
+        }
+    };
+
+    let result = match args.as_slice() {
+        [] | ["list"] => {
+            for p in &profiles.profiles {
+                let mark = if p.name == profiles.default { '*' } else { ' ' };
+                stdout.push_str(&format!("{} {:<12} {}px", mark, p.name, p.font_size));
+                if let Some(cwd) = &p.cwd {
+                    stdout.push_str(&format!("  cwd={}", cwd));
+                }
+                stdout.push('\n');
+            }
+            return 0;
+        }
+        ["show"] | ["show", _] => {
+            let name = args.get(1).copied();
+            match profiles.resolve(name) {
+                Ok(p) => {
+                    stdout.push_str(&format!("[{}]\n", p.name));
+                    for (key, value) in p.settings() {
+                        stdout.push_str(&format!("{} = {}\n", key, value));
+                    }
+                    return 0;
+                }
+                Err(e) => Err(e),
+            }
+        }
+        ["set", name, key, value] => profiles
+            .get_mut(name)
+            .ok_or_else(|| format!("no such profile '{}'", name))
+            .and_then(|p| p.set(key, value)),
+        ["unset", name, key] => profiles
+            .get_mut(name)
+            .ok_or_else(|| format!("no such profile '{}'", name))
+            .and_then(|p| p.set(key, "")),
+        ["default", name] => profiles.set_default(name),
+        ["new", name] => profiles.create(name, None),
+        ["new", name, from] => profiles.create(name, Some(from)),
+        ["rm", name] => profiles.remove(name),
+        _ => {
+            stderr.push_str("profile: invalid arguments (see profile --help)\n");
+            return 2;
+        }
+    };
+
+    match result.and_then(|()| profile::save(&profiles)) {
+        Ok(()) => 0,
+        Err(e) => {
+            stderr.push_str(&format!("profile: {}\n", e));
+            1
+        }
+    }
+}
+
+pub static WIDGETS: CommandSpec = CommandSpec {
+    name: "widgets",
+    summary: "show the status bar widgets",
+    description: "Show the status bar widgets set up in /etc/widgets.toml: each one's kind, refresh interval, what it shows and why its last refresh failed.
+
+'widgets reload' reads the file again now rather than within a few seconds of a change. See 'man widgets' for the settings.",
+    flags: &[],
+    args: &[Arg::new("COMMAND", ArgKind::Text).optional()],
+};
+
+/// widgets - status bar widgets
+pub fn prog_widgets(
+    args: &[String],
+    __stdin: &str,
+    stdout: &mut String,
+    stderr: &mut String,
+) -> i32 {
+    let m = match WIDGETS.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&WIDGETS, stdout, stderr),
+    };
+
+    use crate::shell::widgets;
+
+    match m.operand(0) {
+        None | Some("list") => {
+            let list = widgets::status();
+            if list.is_empty() {
+                stdout.push_str(&format!("No widgets set up in {}\n", widgets::CONFIG_PATH));
+            } else {
+                stdout.push_str(&format!(
+                    "{:<12} {:<8} {:>6}  SHOWS\n",
+                    "NAME", "KIND", "EVERY"
+                ));
+            }
+            for w in &list {
+                stdout.push_str(&format!(
+                    "{:<12} {:<8} {:>6}  {}",
+                    w.widget.name,
+                    w.widget.provider.kind(),
+                    format!("{}s", w.widget.interval),
+                    w.text()
+                ));
+                if let Some(e) = &w.last_error {
+                    stdout.push_str(&format!("  ({})", e));
+                }
+                stdout.push('\n');
+            }
+            match widgets::config_error() {
+                Some(e) => {
+                    stderr.push_str(&format!("widgets: {}\n", e));
+                    1
+                }
+                None => 0,
+            }
+        }
+        Some("reload") => match widgets::reload() {
+            Ok(()) => 0,
+            Err(e) => {
+                stderr.push_str(&format!("widgets: {}\n", e));
+                1
+            }
+        },
+        _ => {
+            stderr.push_str("widgets: invalid arguments (see widgets --help)\n");
+            2
+        }
+    }
+}
+
+pub static CHVT: CommandSpec = CommandSpec {
+    name: "chvt",
+    summary: "switch virtual console",
+    description: "Switch to virtual console N, 1 to 4, as Alt+F1..F4 do. Each console has its own shell, started as the current user the first time it is switched to, and its own scrollback. Without N, print the number of the console on screen.",
+    flags: &[],
+    args: &[Arg::new("N", ArgKind::Text).optional()],
+};
+
+/// chvt - switch virtual console
+pub fn prog_chvt(args: &[String], _stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match CHVT.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&CHVT, stdout, stderr),
+    };
+    let Some(n) = m.operand(0) else {
+        stdout.push_str(&format!("{}\n", syscall::active_vt()));
+        return 0;
+    };
+    let n = match n.parse() {
+        Ok(n) if (1..=VT_COUNT).contains(&n) => n,
+        _ => {
+            stderr.push_str(&format!(
+                "chvt: {}: not a console number (1-{})\n",
+                n, VT_COUNT
+            ));
+            return 1;
+        }
+    };
+    match syscall::chvt(n) {
+        Ok(_) => 0,
+        Err(e) => {
+            stderr.push_str(&format!("chvt: {}\n", e));
+            1
+        }
+    }
 }
 
 #[cfg(test)]
@@ -129,6 +352,54 @@ mod tests {
         assert!(stderr.is_empty());
     }
 
+    #[test]
+    fn test_tty_names_terminal() {
+        use crate::kernel::syscall::{Fd, KERNEL, Kernel, OpenFlags};
+
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_login_shell(
+                "user",
+                crate::kernel::users::Uid(1000),
+                crate::kernel::users::Gid(1000),
+                "/home/user",
+                "/bin/sh",
+                None,
+            );
+            k.set_current(pid);
+        });
+        let mut stdout = String::new();
+        assert_eq!(prog_tty(&[], "", &mut stdout, &mut String::new()), 0);
+        assert_eq!(stdout, "/dev/tty1\n");
+
+        // Standard input on a pseudo-terminal slave
+        let pts = syscall::openpty().unwrap();
+        assert_eq!(pts, "/dev/pts/0");
+        let fd = syscall::open(&pts, OpenFlags::RDWR).unwrap();
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            let process = k.current_process_mut().unwrap();
+            let slave = process.files.get(fd).unwrap();
+            process.files.insert(Fd::STDIN, slave);
+        });
+        let mut stdout = String::new();
+        assert_eq!(prog_tty(&[], "", &mut stdout, &mut String::new()), 0);
+        assert_eq!(stdout, "/dev/pts/0\n");
+
+        // ...and on a pipe
+        let (read_fd, _) = syscall::pipe().unwrap();
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            let process = k.current_process_mut().unwrap();
+            let pipe = process.files.get(read_fd).unwrap();
+            process.files.insert(Fd::STDIN, pipe);
+        });
+        let mut stdout = String::new();
+        assert_eq!(prog_tty(&[], "", &mut stdout, &mut String::new()), 1);
+        assert_eq!(stdout, "not a tty\n");
+    }
+
     #[test]
     fn test_stty_no_args() {
         let args: Vec<String> = vec![];
@@ -155,4 +426,151 @@ mod tests {
         assert!(!stdout.is_empty());
         assert!(stdout.contains("/dev/") || stdout.contains("not a tty"));
     }
+
+    #[test]
+    fn test_profile_edit() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+        use crate::shell::profile;
+
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        let run = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+            let (mut out, mut err) = (String::new(), String::new());
+            let code = prog_profile(&args, "", &mut out, &mut err);
+            (code, out, err)
+        };
+
+        assert_eq!(run(&["new", "big"]).0, 0);
+        assert_eq!(run(&["set", "big", "font_size", "20"]).0, 0);
+        assert_eq!(run(&["set", "big", "colors.background", "#000000"]).0, 0);
+        assert_eq!(run(&["default", "big"]).0, 0);
+        let (code, _, err) = run(&["set", "big", "font_size", "huge"]);
+        assert_eq!(code, 1);
+        assert!(err.starts_with("profile: font_size"));
+
+        let (_, out, _) = run(&["list"]);
+        assert!(out.contains("* big"));
+        let (_, out, _) = run(&["show"]);
+        assert!(out.contains("font_size = 20\n"));
+        assert!(out.contains("colors.background = #000000\n"));
+
+        let saved = profile::load().unwrap();
+        assert_eq!(saved.default, "big");
+        assert_eq!(saved.default_profile().color("background"), Some("#000000"));
+
+        assert_eq!(run(&["rm", "big"]).0, 1);
+        assert_eq!(run(&["bogus"]).0, 2);
+    }
+
+    #[test]
+    fn test_widgets() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+        use crate::shell::setup::as_root;
+        use crate::shell::widgets::CONFIG_PATH;
+
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        let run = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = prog_widgets(&args, "", &mut stdout, &mut stderr);
+            (code, stdout, stderr)
+        };
+
+        assert_eq!(run(&["reload"]).0, 0);
+        assert!(run(&[]).1.starts_with("No widgets"));
+
+        as_root(|| {
+            syscall::write_file(
+                CONFIG_PATH,
+                "[clock]\n[disk]\nkind = \"storage\"\ninterval = 60\n",
+            )
+        })
+        .unwrap();
+        assert_eq!(run(&["reload"]).0, 0);
+        let (code, stdout, _) = run(&["list"]);
+        assert_eq!(code, 0);
+        assert!(
+            stdout.contains("clock        clock        1s  --"),
+            "{}",
+            stdout
+        );
+        assert!(
+            stdout.contains("disk         storage     60s  --"),
+            "{}",
+            stdout
+        );
+
+        as_root(|| syscall::write_file(CONFIG_PATH, "[clock]\nformat = \"%Q\"\n")).unwrap();
+        let (code, _, stderr) = run(&["reload"]);
+        assert_eq!(code, 1);
+        assert!(stderr.contains("line 2"), "{}", stderr);
+        // Still listing the widgets from before
+        let (code, stdout, _) = run(&[]);
+        assert_eq!(code, 1);
+        assert!(stdout.contains("disk"));
+
+        assert_eq!(run(&["bogus"]).0, 2);
+    }
+
+    #[test]
+    fn test_chvt_consoles() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+        use crate::kernel::users::{Gid, Uid};
+
+        let shell = KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid =
+                k.spawn_login_shell("user", Uid(1000), Gid(1000), "/home/user", "/bin/sh", None);
+            k.set_current(pid);
+            pid
+        });
+        let run = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+            let (mut stdout, mut stderr) = (String::new(), String::new());
+            let code = prog_chvt(&args, "", &mut stdout, &mut stderr);
+            (code, stdout, stderr)
+        };
+        assert_eq!(run(&[]).1, "1\n");
+        syscall::vt_record(1, "$ ls\nnotes.txt");
+
+        // The first switch starts a shell for the same user on tty2
+        assert_eq!(run(&["2"]).0, 0);
+        assert_eq!(syscall::active_vt(), 2);
+        let second = syscall::vt_shell(2).unwrap();
+        assert_ne!(second, shell);
+        assert_eq!(syscall::process_tty(second).as_deref(), Some("tty2"));
+        KERNEL.with(|k| {
+            let k = k.borrow();
+            let pgrp = |name: &str| k.ttys().get_tty(name).unwrap().pgrp;
+            assert_eq!(pgrp("tty1"), Some(shell.0));
+            assert_eq!(pgrp("tty2"), Some(second.0));
+            assert_eq!(k.get_process(second).unwrap().uid, Uid(1000));
+        });
+        assert!(syscall::vt_scrollback(2).is_empty());
+        syscall::set_current_process(second);
+        syscall::vt_record(2, "$ clear\n\x1b[2J\x1b[H$ top");
+
+        // Back on tty1, its shell and scrollback are where they were
+        assert_eq!(run(&["1"]).0, 0);
+        assert_eq!(syscall::vt_shell(1), Some(shell));
+        assert_eq!(syscall::vt_shell(2), Some(second));
+        assert_eq!(syscall::vt_scrollback(1), ["$ ls", "notes.txt"]);
+        assert_eq!(syscall::vt_scrollback(2), ["$ top"]);
+
+        let (code, _, stderr) = run(&["5"]);
+        assert_eq!(code, 1);
+        assert!(stderr.contains("not a console number (1-4)"));
+        assert_eq!(syscall::active_vt(), 1);
+    }
 }
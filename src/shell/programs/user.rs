@@ -1,30 +1,33 @@
 //! User management programs
 
-use super::{args_to_strs, check_help};
-use crate::kernel::syscall;
+use crate::kernel::UserChange;
+use crate::kernel::syscall::{self, SyscallError};
+use crate::shell::argparse::{Arg, ArgError, ArgKind, CommandSpec, Flag};
+use crate::shell::login;
+
+pub static SU: CommandSpec = CommandSpec {
+    name: "su",
+    summary: "switch user",
+    description: "Switch to USER, root by default. A - before USER is the same as -l.",
+    flags: &[Flag::new(
+        'l',
+        "login",
+        "Start in USER's home directory, as a login shell does",
+    )],
+    args: &[Arg::new("USER", ArgKind::Text).optional().repeated()],
+};
 
 /// su - switch user (simulated)
 pub fn prog_su(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: su [-] [USER]\nSwitch user. Defaults to root.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
-
-    // Parse arguments
-    let mut login_shell = false;
-    let mut target_user = "root";
-
-    for arg in args {
-        if arg == "-" || arg == "-l" || arg == "--login" {
-            login_shell = true;
-        } else if !arg.starts_with('-') {
-            target_user = arg;
-        }
+    let m = match SU.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&SU, stdout, stderr),
+    };
+    let login_shell = m.flag("l") || m.operands.iter().any(|a| a == "-");
+    let mut names = m.operands.iter().filter(|a| *a != "-");
+    let target_user = names.next().map_or("root", String::as_str);
+    if let Some(extra) = names.next() {
+        return ArgError::ExtraOperand(extra.clone()).report(&SU, stdout, stderr);
     }
 
     // Look up target user
@@ -39,6 +42,12 @@ pub fn prog_su(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut
     // Check if we have permission (root can su to anyone, others need wheel group or password)
     let euid = syscall::geteuid().unwrap_or_default();
     if euid.0 != 0 {
+        // Only root may switch to an account whose shell isn't allowed
+        if let Err(e) = login::check_shell(&user.shell) {
+            stderr.push_str(&format!("su: {}\n", e));
+            return 1;
+        }
+
         // Non-root user - would need password in real system
         // For demo, check if user is in wheel group
         let groups = syscall::getgroups().unwrap_or_default();
@@ -61,23 +70,39 @@ pub fn prog_su(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut
         return 1;
     }
 
-    // Update environment
+    // Update environment; the shell carries on as the user's login shell
     let _ = syscall::setenv("USER", &user.name);
     let _ = syscall::setenv("HOME", &user.home);
+    let _ = syscall::setenv("SHELL", &user.shell);
     if login_shell {
-        let _ = syscall::setenv("SHELL", &user.shell);
+        let _ = syscall::chdir(&user.home);
     }
 
     stdout.push_str(&format!("Switched to user '{}'\n", user.name));
     0
 }
 
+pub static SUDO: CommandSpec = CommandSpec {
+    name: "sudo",
+    summary: "run a command as root",
+    description: "Run COMMAND as root. Members of the wheel group may use it. With no COMMAND, show this help.",
+    flags: &[],
+    args: &[
+        Arg::new("COMMAND", ArgKind::Command).optional(),
+        Arg::new("ARG", ArgKind::Text).optional().repeated().raw(),
+    ],
+};
+
 /// sudo - run command as root (simulated)
 pub fn prog_sudo(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if args.is_empty() || args.first().map(|s| s.as_ref()) == Some("--help") {
-        stdout.push_str("Usage: sudo COMMAND [ARG]...\nRun command as root.\n");
+    let m = match SUDO.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&SUDO, stdout, stderr),
+    };
+    let args = &m.operands;
+    if args.is_empty() {
+        stdout.push_str(&SUDO.help());
+        stdout.push('\n');
         return 0;
     }
 
@@ -118,6 +143,17 @@ pub fn prog_sudo(args: &[String], __stdin: &str, stdout: &mut String, stderr: &m
     0
 }
 
+pub static USERADD: CommandSpec = CommandSpec {
+    name: "useradd",
+    summary: "create a new user",
+    description: "Create user USERNAME, with a home directory in /home, and a group of the same name unless -g gives one. With no arguments, show this help. Needs root.",
+    flags: &[
+        Flag::short('g', "Make GID (a number or a group name) the user's group").value("GID"),
+        Flag::short('s', "Make SHELL the user's login shell").value("SHELL"),
+    ],
+    args: &[Arg::new("USERNAME", ArgKind::Text)],
+};
+
 /// useradd - create a new user
 pub fn prog_useradd(
     args: &[String],
@@ -125,12 +161,15 @@ pub fn prog_useradd(
     stdout: &mut String,
     stderr: &mut String,
 ) -> i32 {
-    let args = args_to_strs(args);
-
-    if args.is_empty() || args.first().map(|s| s.as_ref()) == Some("--help") {
-        stdout.push_str("Usage: useradd [-g GID] USERNAME\nCreate a new user.\n");
+    if args.is_empty() {
+        stdout.push_str(&USERADD.help());
+        stdout.push('\n');
         return 0;
     }
+    let m = match USERADD.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&USERADD, stdout, stderr),
+    };
 
     // Check if caller is root
     let euid = syscall::geteuid().unwrap_or_default();
@@ -139,35 +178,28 @@ pub fn prog_useradd(
         return 1;
     }
 
-    // Parse arguments
-    let mut gid: Option<crate::kernel::Gid> = None;
-    let mut username = None;
-    let mut iter = args.iter();
-
-    while let Some(arg) = iter.next() {
-        if *arg == "-g" {
-            if let Some(gid_str) = iter.next() {
-                if let Ok(n) = gid_str.parse::<u32>() {
-                    gid = Some(crate::kernel::Gid(n));
-                } else if let Some(group) = syscall::get_group_by_name(gid_str) {
-                    gid = Some(group.gid);
-                } else {
-                    stderr.push_str(&format!("useradd: group '{}' does not exist\n", gid_str));
-                    return 1;
-                }
+    let gid = match m.value("g") {
+        None => None,
+        Some(gid_str) => {
+            if let Ok(n) = gid_str.parse::<u32>() {
+                Some(crate::kernel::Gid(n))
+            } else if let Some(group) = syscall::get_group_by_name(gid_str) {
+                Some(group.gid)
+            } else {
+                stderr.push_str(&format!("useradd: group '{}' does not exist\n", gid_str));
+                return 1;
             }
-        } else if !arg.starts_with('-') {
-            username = Some(*arg);
         }
-    }
-
-    let username = match username {
-        Some(u) => u,
-        None => {
-            stderr.push_str("useradd: missing username\n");
+    };
+    let shell = match m.value("s").map(|path| (path, login::check_shell(path))) {
+        None => None,
+        Some((path, Ok(_))) => Some(path.to_string()),
+        Some((_, Err(e))) => {
+            stderr.push_str(&format!("useradd: {}\n", e));
             return 1;
         }
     };
+    let username = m.operands[0].as_str();
 
     // Check if user already exists
     if syscall::get_user_by_name(username).is_some() {
@@ -181,6 +213,13 @@ pub fn prog_useradd(
             // Create home directory
             let home = format!("/home/{}", username);
             let _ = syscall::mkdir(&home);
+            if let Some(shell) = shell {
+                let change = UserChange {
+                    shell: Some(shell),
+                    ..UserChange::default()
+                };
+                let _ = syscall::modify_user(username, &change);
+            }
 
             // Save updated user database to /etc/passwd, /etc/shadow, /etc/group
             syscall::save_user_db();
@@ -195,6 +234,14 @@ pub fn prog_useradd(
     }
 }
 
+pub static GROUPADD: CommandSpec = CommandSpec {
+    name: "groupadd",
+    summary: "create a new group",
+    description: "Create group GROUPNAME. With no arguments, show this help. Needs root.",
+    flags: &[],
+    args: &[Arg::new("GROUPNAME", ArgKind::Text)],
+};
+
 /// groupadd - create a new group
 pub fn prog_groupadd(
     args: &[String],
@@ -202,12 +249,15 @@ pub fn prog_groupadd(
     stdout: &mut String,
     stderr: &mut String,
 ) -> i32 {
-    let args = args_to_strs(args);
-
-    if args.is_empty() || args.first().map(|s| s.as_ref()) == Some("--help") {
-        stdout.push_str("Usage: groupadd GROUPNAME\nCreate a new group.\n");
+    if args.is_empty() {
+        stdout.push_str(&GROUPADD.help());
+        stdout.push('\n');
         return 0;
     }
+    let m = match GROUPADD.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&GROUPADD, stdout, stderr),
+    };
 
     // Check if caller is root
     let euid = syscall::geteuid().unwrap_or_default();
@@ -216,7 +266,7 @@ pub fn prog_groupadd(
         return 1;
     }
 
-    let groupname = &args[0];
+    let groupname = &m.operands[0];
 
     // Check if group already exists
     if syscall::get_group_by_name(groupname).is_some() {
@@ -242,6 +292,239 @@ pub fn prog_groupadd(
     }
 }
 
+pub static USERDEL: CommandSpec = CommandSpec {
+    name: "userdel",
+    summary: "delete a user account",
+    description: "Remove USER from /etc/passwd and from every group in /etc/group, along with the group of the same name made for them unless someone else uses it. Root and users with running processes can't be removed. Needs root.",
+    flags: &[Flag::new(
+        'r',
+        "remove",
+        "Remove the home directory and everything in it too",
+    )],
+    args: &[Arg::new("USER", ArgKind::Text)],
+};
+
+/// userdel - delete a user
+pub fn prog_userdel(
+    args: &[String],
+    _stdin: &str,
+    stdout: &mut String,
+    stderr: &mut String,
+) -> i32 {
+    let m = match USERDEL.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&USERDEL, stdout, stderr),
+    };
+    let name = m.operands[0].as_str();
+
+    if syscall::geteuid().unwrap_or_default().0 != 0 {
+        stderr.push_str("userdel: permission denied (must be root)\n");
+        return 1;
+    }
+    let user = match syscall::remove_user(name) {
+        Ok(user) => user,
+        Err(e) => {
+            let reason = match e {
+                SyscallError::NotFound => "does not exist".to_string(),
+                SyscallError::Busy => "is running processes".to_string(),
+                SyscallError::PermissionDenied => "can't be removed".to_string(),
+                e => e.to_string(),
+            };
+            stderr.push_str(&format!("userdel: user '{}' {}\n", name, reason));
+            return 1;
+        }
+    };
+    syscall::save_user_db();
+    stdout.push_str(&format!("Removed user '{}'\n", name));
+
+    if m.flag("r") {
+        let is_dir = syscall::metadata(&user.home).is_ok_and(|meta| meta.is_dir);
+        if user.home == "/" || !is_dir {
+            stderr.push_str(&format!(
+                "userdel: {}: home directory not removed\n",
+                user.home
+            ));
+            return 1;
+        }
+        if let Err(e) = remove_tree(&user.home) {
+            stderr.push_str(&format!("userdel: {}: {}\n", user.home, e));
+            return 1;
+        }
+        stdout.push_str(&format!("Removed home directory {}\n", user.home));
+    }
+    0
+}
+
+/// Remove `path` and everything below it, without following links
+fn remove_tree(path: &str) -> syscall::SyscallResult<()> {
+    for name in syscall::readdir(path)? {
+        let child = format!("{}/{}", path.trim_end_matches('/'), name);
+        if syscall::metadata(&child)?.is_dir {
+            remove_tree(&child)?;
+        } else {
+            syscall::remove_file(&child)?;
+        }
+    }
+    syscall::remove_dir(path)
+}
+
+pub static GROUPDEL: CommandSpec = CommandSpec {
+    name: "groupdel",
+    summary: "delete a group",
+    description: "Remove GROUP from /etc/group. A group that is some user's primary group can't be removed. Needs root.",
+    flags: &[],
+    args: &[Arg::new("GROUP", ArgKind::Text)],
+};
+
+/// groupdel - delete a group
+pub fn prog_groupdel(
+    args: &[String],
+    _stdin: &str,
+    stdout: &mut String,
+    stderr: &mut String,
+) -> i32 {
+    let m = match GROUPDEL.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&GROUPDEL, stdout, stderr),
+    };
+    let name = m.operands[0].as_str();
+
+    if syscall::geteuid().unwrap_or_default().0 != 0 {
+        stderr.push_str("groupdel: permission denied (must be root)\n");
+        return 1;
+    }
+    match syscall::remove_group(name) {
+        Ok(_) => {
+            syscall::save_user_db();
+            stdout.push_str(&format!("Removed group '{}'\n", name));
+            0
+        }
+        Err(e) => {
+            let reason = match e {
+                SyscallError::NotFound => "does not exist".to_string(),
+                SyscallError::Busy => "is the primary group of a user".to_string(),
+                e => e.to_string(),
+            };
+            stderr.push_str(&format!("groupdel: group '{}' {}\n", name, reason));
+            1
+        }
+    }
+}
+
+pub static USERMOD: CommandSpec = CommandSpec {
+    name: "usermod",
+    summary: "change a user account",
+    description: "Change USER's login shell, home directory or supplementary groups in /etc/passwd and /etc/group. The shell must be listed in /etc/shells. Groups are only added to, so -G needs -a; nothing changes if one of them doesn't exist. Needs root.",
+    flags: &[
+        Flag::new('s', "shell", "Set the login shell").value("SHELL"),
+        Flag::new('d', "home", "Set the home directory").value("HOME"),
+        Flag::new(
+            'm',
+            "move-home",
+            "With -d, move the old home directory's contents there",
+        ),
+        Flag::new('G', "groups", "Comma-separated supplementary groups").value("GROUPS"),
+        Flag::new('a', "append", "Add USER to the -G groups"),
+    ],
+    args: &[Arg::new("USER", ArgKind::Text)],
+};
+
+/// usermod - change a user
+pub fn prog_usermod(
+    args: &[String],
+    _stdin: &str,
+    stdout: &mut String,
+    stderr: &mut String,
+) -> i32 {
+    let m = match USERMOD.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&USERMOD, stdout, stderr),
+    };
+    let name = m.operands[0].as_str();
+
+    if syscall::geteuid().unwrap_or_default().0 != 0 {
+        stderr.push_str("usermod: permission denied (must be root)\n");
+        return 1;
+    }
+    let Some(user) = syscall::get_user_by_name(name) else {
+        stderr.push_str(&format!("usermod: user '{}' does not exist\n", name));
+        return 1;
+    };
+    let add_groups: Vec<String> = m
+        .values("G")
+        .iter()
+        .flat_map(|groups| groups.split(','))
+        .filter(|group| !group.is_empty())
+        .map(str::to_string)
+        .collect();
+    if m.flag("G") && !m.flag("a") {
+        stderr.push_str("usermod: -G only adds groups here; use -a -G\n");
+        return 1;
+    }
+    if let Some(group) = add_groups
+        .iter()
+        .find(|group| syscall::get_group_by_name(group).is_none())
+    {
+        stderr.push_str(&format!("usermod: group '{}' does not exist\n", group));
+        return 1;
+    }
+    if m.flag("m") && !m.flag("d") {
+        stderr.push_str("usermod: -m needs -d\n");
+        return 1;
+    }
+    if let Some(Err(e)) = m.value("s").map(login::check_shell) {
+        stderr.push_str(&format!("usermod: {}\n", e));
+        return 1;
+    }
+
+    let change = UserChange {
+        shell: m.value("s").map(str::to_string),
+        home: m.value("d").map(str::to_string),
+        add_groups,
+    };
+    if change == UserChange::default() {
+        stderr.push_str(&format!("{}\n", USERMOD.usage()));
+        return 1;
+    }
+
+    // The home directory moves first, so a failure leaves the account alone
+    if let (true, Some(home)) = (m.flag("m"), &change.home)
+        && *home != user.home
+        && syscall::exists(&user.home).unwrap_or(false)
+        && let Err(e) = syscall::rename(&user.home, home)
+    {
+        stderr.push_str(&format!(
+            "usermod: cannot move {} to {}: {}\n",
+            user.home, home, e
+        ));
+        return 1;
+    }
+    if let Err(e) = syscall::modify_user(name, &change) {
+        stderr.push_str(&format!("usermod: {}\n", e));
+        return 1;
+    }
+    syscall::save_user_db();
+    stdout.push_str(&format!("Modified user '{}'\n", name));
+    0
+}
+
+pub static PASSWD: CommandSpec = CommandSpec {
+    name: "passwd",
+    summary: "change user password",
+    description: "Set your own password to PASSWORD, or USER's (requires root). Root can clear USER's password by leaving PASSWORD out.
+
+Examples:
+  passwd mypassword          Set your own password
+  passwd root newpass        Set root's password (requires root)
+  passwd user                Clear user's password (requires root)",
+    flags: &[],
+    // Raw, so a password can start with -
+    args: &[
+        Arg::new("USER", ArgKind::Text).optional().raw(),
+        Arg::new("PASSWORD", ArgKind::Text).repeated(),
+    ],
+};
+
 /// passwd - change password
 pub fn prog_passwd(
     args: &[String],
@@ -249,23 +532,16 @@ pub fn prog_passwd(
     stdout: &mut String,
     stderr: &mut String,
 ) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: passwd [USER] [PASSWORD]\n\nChange user password.\n\nExamples:\n  passwd mypassword          Set your own password\n  passwd root newpass        Set root's password (requires root)\n  passwd user                Clear user's password (requires root)",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
+    let m = match PASSWD.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&PASSWD, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
 
     // Determine target user and new password
     let euid = syscall::geteuid().unwrap_or_default();
 
-    let (target, new_password) = if args.is_empty() {
-        stderr.push_str("passwd: usage: passwd [USER] <PASSWORD>\n");
-        return 1;
-    } else if args.len() == 1 {
+    let (target, new_password) = if args.len() == 1 {
         // Single arg: could be password for self, or username to clear password (if root)
         let current_user = syscall::get_user_by_uid(euid)
             .map(|u| u.name.clone())
@@ -343,24 +619,35 @@ pub fn prog_passwd(
     }
 }
 
+pub static LOGIN: CommandSpec = CommandSpec {
+    name: "login",
+    summary: "log in as a user",
+    description: "Log in as a user with password authentication.
+
+This command spawns a new login shell as the specified user, creating a proper session like Linux login(1).
+
+If no password is provided, allows login for users without passwords. Use 'logout' to end the current session. Use 'passwd' to change your password.
+
+Default users:
+  root     - password: root (uid 0)
+  user     - no password (uid 1000)
+  nobody   - no password (uid 65534)",
+    flags: &[],
+    args: &[
+        Arg::new("USERNAME", ArgKind::Text),
+        Arg::new("PASSWORD", ArgKind::Text).optional().repeated().raw(),
+    ],
+};
+
 /// login - log in as a user with password authentication
 /// This behaves like real Linux login(1): it spawns a NEW shell process
 /// as the target user with proper session management.
 pub fn prog_login(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: login <username> [password]\n\nLog in as a user with password authentication.\n\nThis command spawns a new login shell as the specified user,\ncreating a proper session like Linux login(1).\n\nIf no password is provided, allows login for users without passwords.\nUse 'logout' to end the current session.\nUse 'passwd' to change your password.\n\nDefault users:\n  root     - password: root (uid 0)\n  user     - no password (uid 1000)\n  nobody   - no password (uid 65534)",
-    ) {
-        stdout.push_str(&help);
-        return 0;
-    }
-
-    if args.is_empty() {
-        stderr.push_str("login: usage: login <username> [password]\n");
-        return 1;
-    }
+    let m = match LOGIN.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&LOGIN, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
 
     // Ensure session directory exists
     let _ = syscall::mkdir("/var");
@@ -418,6 +705,10 @@ pub fn prog_login(args: &[String], __stdin: &str, stdout: &mut String, stderr: &
             return 1;
         }
     };
+    if let Err(e) = login::check_shell(&shell) {
+        stderr.push_str(&format!("login: {}\n", e));
+        return 1;
+    }
 
     // Spawn a NEW login shell process with proper credentials
     // This is how real Linux login(1) works - it forks and execs a shell
@@ -434,7 +725,11 @@ pub fn prog_login(args: &[String], __stdin: &str, stdout: &mut String, stderr: &
     let now = syscall::now();
     let session_data = format!(
         "{}:{}:{}:{}:{}\n",
-        username, uid, new_pid.0, now as u64, "tty1"
+        username,
+        uid,
+        new_pid.0,
+        now as u64,
+        syscall::process_tty(new_pid).unwrap_or_else(|| "tty1".to_string())
     );
 
     // Write session file as root (temporarily)
@@ -477,6 +772,14 @@ pub fn prog_login(args: &[String], __stdin: &str, stdout: &mut String, stderr: &
     0
 }
 
+pub static LOGOUT: CommandSpec = CommandSpec {
+    name: "logout",
+    summary: "end the login session",
+    description: "End the current login session and return to the parent process. This terminates the login shell that was spawned by 'login'.",
+    flags: &[],
+    args: &[],
+};
+
 /// logout - log out current user
 /// In a real Linux system, this would exit the login shell and return to getty.
 /// Here we terminate the current session and switch back to the init/parent process.
@@ -484,16 +787,10 @@ pub fn prog_logout(
     args: &[String],
     __stdin: &str,
     stdout: &mut String,
-    _stderr: &mut String,
+    stderr: &mut String,
 ) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: logout\n\nEnd the current login session and return to the parent process.\nThis terminates the login shell that was spawned by 'login'.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
+    if let Err(e) = LOGOUT.parse(args) {
+        return e.report(&LOGOUT, stdout, stderr);
     }
 
     // Get current session info before logging out
@@ -551,13 +848,201 @@ pub fn prog_logout(
     0
 }
 
-/// who - show who is logged in
-pub fn prog_who(args: &[String], __stdin: &str, stdout: &mut String, _stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
+pub static LOCK: CommandSpec = CommandSpec {
+    name: "lock",
+    summary: "lock the session",
+    description: "Lock the session. The screen blanks and the next key press asks for the password of the user who locked it (or root's). Background services keep running while locked.",
+    flags: &[
+        Flag::short(
+            't',
+            "Lock automatically after MINUTES without input (off disables)",
+        )
+        .value("MINUTES"),
+        Flag::short('s', "Show lock status and idle time"),
+    ],
+    args: &[],
+};
+
+/// lock - lock the session or configure idle auto-lock
+pub fn prog_lock(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match LOCK.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&LOCK, stdout, stderr),
+    };
 
-    if let Some(help) = check_help(&args, "Usage: who\n\nShow who is logged in.") {
-        stdout.push_str(&help);
-        return 0;
+    match (m.value("t"), m.flag("s")) {
+        (None, false) => match syscall::session_lock() {
+            Ok(()) => 0,
+            Err(e) => {
+                stderr.push_str(&format!("lock: {}\n", e));
+                1
+            }
+        },
+        (Some(value), false) => {
+            let timeout = if value == "off" {
+                None
+            } else {
+                match value.parse::<f64>() {
+                    Ok(minutes) if minutes > 0.0 && minutes.is_finite() => {
+                        Some(minutes * 60.0 * 1000.0)
+                    }
+                    _ => {
+                        stderr.push_str(&format!("lock: invalid timeout '{}'\n", value));
+                        return 1;
+                    }
+                }
+            };
+            if let Err(e) = syscall::set_idle_timeout(timeout) {
+                stderr.push_str(&format!("lock: {}\n", e));
+                return 1;
+            }
+            0
+        }
+        (None, true) => {
+            let session = syscall::session();
+            let idle = session.idle();
+            match session.locked() {
+                Some(lock) => stdout.push_str(&format!(
+                    "locked: yes ({:?}, {} failed attempts)\n",
+                    lock.reason, lock.failed_attempts
+                )),
+                None => stdout.push_str("locked: no\n"),
+            }
+            stdout.push_str(&format!("idle: {}\n", format_minutes(idle.idle_ms())));
+            match idle.timeout() {
+                Some(timeout) => {
+                    stdout.push_str(&format!("auto-lock: after {}\n", format_minutes(timeout)))
+                }
+                None => stdout.push_str("auto-lock: off\n"),
+            }
+            0
+        }
+        _ => {
+            stderr.push_str("lock: invalid arguments (see lock --help)\n");
+            2
+        }
+    }
+}
+
+pub static GUEST: CommandSpec = CommandSpec {
+    name: "guest",
+    summary: "run a guest session",
+    description: "Run a guest session. / is remounted as an overlay: the real filesystem stays untouched underneath and every change lands in a throwaway layer that is discarded on exit and never saved. Export paths to keep them. COMMAND is one of:
+
+  start         Start a guest session (default)
+  status        Show changed paths and exports
+  export PATH   Keep PATH (and everything under it) on exit
+  exit          End the session, discarding everything not exported",
+    flags: &[],
+    args: &[
+        Arg::new("COMMAND", ArgKind::Text).optional(),
+        Arg::new("PATH", ArgKind::Path).optional().repeated(),
+    ],
+};
+
+/// guest - run a session whose filesystem changes are thrown away
+pub fn prog_guest(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    let m = match GUEST.parse(args) {
+        Ok(m) => m,
+        Err(e) => return e.report(&GUEST, stdout, stderr),
+    };
+    let args: Vec<&str> = m.operands.iter().map(String::as_str).collect();
+
+    match args.as_slice() {
+        [] | ["start"] => match syscall::guest_start() {
+            Ok(()) => {
+                stdout.push_str("Guest session started: changes are discarded on 'guest exit'\n");
+                0
+            }
+            Err(SyscallError::Busy) => {
+                stderr.push_str("guest: a guest session is already active\n");
+                1
+            }
+            Err(e) => {
+                stderr.push_str(&format!("guest: {}\n", e));
+                1
+            }
+        },
+        ["status"] => {
+            let Some((changes, exports)) = syscall::guest_status() else {
+                stdout.push_str("guest: no guest session\n");
+                return 0;
+            };
+            stdout.push_str(&format!("guest session: {} changed paths\n", changes.len()));
+            for change in &changes {
+                stdout.push_str(&format!("{} {}\n", change.kind.marker(), change.path));
+            }
+            for path in &exports {
+                stdout.push_str(&format!("export {}\n", path));
+            }
+            0
+        }
+        ["export", paths @ ..] if !paths.is_empty() => {
+            if !syscall::guest_active() {
+                stderr.push_str("guest: no guest session\n");
+                return 1;
+            }
+            let mut code = 0;
+            for path in paths {
+                match syscall::guest_export(path) {
+                    Ok(resolved) => stdout.push_str(&format!("keeping {}\n", resolved)),
+                    Err(e) => {
+                        stderr.push_str(&format!("guest: {}: {}\n", path, e));
+                        code = 1;
+                    }
+                }
+            }
+            code
+        }
+        ["exit"] => {
+            let Some((changes, exports)) = syscall::guest_status() else {
+                stderr.push_str("guest: no guest session\n");
+                return 1;
+            };
+            match syscall::guest_end() {
+                Ok(()) => {
+                    stdout.push_str(&format!(
+                        "Guest session ended: {} changed paths discarded, {} exports kept\n",
+                        changes.len(),
+                        exports.len()
+                    ));
+                    0
+                }
+                Err(e) => {
+                    stderr.push_str(&format!("guest: {}\n", e));
+                    1
+                }
+            }
+        }
+        _ => {
+            stderr.push_str("guest: invalid arguments (see guest --help)\n");
+            2
+        }
+    }
+}
+
+/// Format milliseconds as minutes and seconds, e.g. `15m`, `2m 30s`
+fn format_minutes(ms: f64) -> String {
+    let secs = (ms / 1000.0) as u64;
+    match (secs / 60, secs % 60) {
+        (0, s) => format!("{}s", s),
+        (m, 0) => format!("{}m", m),
+        (m, s) => format!("{}m {}s", m, s),
+    }
+}
+
+pub static WHO: CommandSpec = CommandSpec {
+    name: "who",
+    summary: "show who is logged in",
+    description: "Show who is logged in.",
+    flags: &[],
+    args: &[],
+};
+
+/// who - show who is logged in
+pub fn prog_who(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    if let Err(e) = WHO.parse(args) {
+        return e.report(&WHO, stdout, stderr);
     }
 
     // Read session file
@@ -611,16 +1096,18 @@ pub fn prog_who(args: &[String], __stdin: &str, stdout: &mut String, _stderr: &m
     0
 }
 
+pub static W: CommandSpec = CommandSpec {
+    name: "w",
+    summary: "show who is logged in and what they are doing",
+    description: "Show who is logged in and what they are doing.",
+    flags: &[],
+    args: &[],
+};
+
 /// w - show who is logged in and what they are doing
-pub fn prog_w(args: &[String], __stdin: &str, stdout: &mut String, _stderr: &mut String) -> i32 {
-    let args = args_to_strs(args);
-
-    if let Some(help) = check_help(
-        &args,
-        "Usage: w\n\nShow who is logged in and what they are doing.",
-    ) {
-        stdout.push_str(&help);
-        return 0;
+pub fn prog_w(args: &[String], __stdin: &str, stdout: &mut String, stderr: &mut String) -> i32 {
+    if let Err(e) = W.parse(args) {
+        return e.report(&W, stdout, stderr);
     }
 
     // Show current time
@@ -740,6 +1227,87 @@ mod tests {
         assert!(stdout.contains("Usage:"));
     }
 
+    #[test]
+    fn test_userdel_groupdel_usermod() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+            k.current_process_mut().unwrap().euid = crate::kernel::Uid::ROOT;
+        });
+        type Prog = fn(&[String], &str, &mut String, &mut String) -> i32;
+        let run = |prog: Prog, args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+            let (mut out, mut err) = (String::new(), String::new());
+            let code = prog(&args, "", &mut out, &mut err);
+            (code, out, err)
+        };
+
+        assert_eq!(run(prog_useradd, &["alice"]).0, 0);
+        assert_eq!(run(prog_groupadd, &["devs"]).0, 0);
+        let (code, _, err) = run(
+            prog_usermod,
+            &["-a", "-G", "devs,wheel", "-s", "/bin/script", "alice"],
+        );
+        assert_eq!(code, 0, "{}", err);
+        let passwd = syscall::read_file("/etc/passwd").unwrap();
+        assert!(
+            passwd.contains("alice:x:1001:1001::/home/alice:/bin/script\n"),
+            "{}",
+            passwd
+        );
+        let group = syscall::read_file("/etc/group").unwrap();
+        assert!(group.contains("devs:x:1002:alice\n"));
+        assert!(group.contains("wheel:x:10:user,alice\n"));
+
+        assert_eq!(run(prog_usermod, &["-G", "devs", "alice"]).0, 1);
+        let (code, _, err) = run(prog_usermod, &["-a", "-G", "nope", "alice"]);
+        assert_eq!(
+            (code, err.as_str()),
+            (1, "usermod: group 'nope' does not exist\n")
+        );
+        assert_eq!(run(prog_usermod, &["alice"]).0, 1);
+
+        syscall::write_file("/home/alice/notes", "hi").unwrap();
+        let (code, _, err) = run(prog_usermod, &["-m", "-d", "/home/al", "alice"]);
+        assert_eq!(code, 0, "{}", err);
+        assert_eq!(syscall::read_file("/home/al/notes").unwrap(), "hi");
+        assert_eq!(syscall::get_user_by_name("alice").unwrap().home, "/home/al");
+
+        // Edits by hand take effect
+        let edited = syscall::read_file("/etc/passwd")
+            .unwrap()
+            .replace("/home/al:/bin/script", "/home/al:/bin/sh");
+        syscall::write_file("/etc/passwd", &edited).unwrap();
+        assert_eq!(syscall::get_user_by_name("alice").unwrap().shell, "/bin/sh");
+
+        let (code, _, err) = run(prog_groupdel, &["alice"]);
+        assert_eq!(
+            (code, err.as_str()),
+            (
+                1,
+                "groupdel: group 'alice' is the primary group of a user\n"
+            )
+        );
+        let (code, out, _) = run(prog_userdel, &["-r", "alice"]);
+        assert_eq!(code, 0);
+        assert_eq!(
+            out,
+            "Removed user 'alice'\nRemoved home directory /home/al\n"
+        );
+        assert!(!syscall::exists("/home/al").unwrap());
+        assert!(syscall::get_user_by_name("alice").is_none());
+        assert!(!syscall::read_file("/etc/group").unwrap().contains("alice"));
+        assert_eq!(run(prog_userdel, &["alice"]).0, 1);
+        assert_eq!(run(prog_userdel, &["root"]).0, 1);
+
+        assert_eq!(run(prog_groupdel, &["devs"]).0, 0);
+        assert!(syscall::get_group_by_name("devs").is_none());
+        assert_eq!(run(prog_groupdel, &["devs"]).0, 1);
+    }
+
     #[test]
     fn test_passwd_help() {
         let args = vec!["--help".to_string()];
@@ -757,7 +1325,7 @@ mod tests {
         let mut stderr = String::new();
         let code = prog_passwd(&args, "", &mut stdout, &mut stderr);
         assert_eq!(code, 1);
-        assert!(stderr.contains("usage:"));
+        assert!(stderr.contains("missing operand: expected PASSWORD"));
     }
 
     #[test]
@@ -777,7 +1345,7 @@ mod tests {
         let mut stderr = String::new();
         let code = prog_login(&args, "", &mut stdout, &mut stderr);
         assert_eq!(code, 1);
-        assert!(stderr.contains("usage:"));
+        assert!(stderr.contains("missing operand: expected USERNAME"));
     }
 
     #[test]
@@ -809,4 +1377,100 @@ mod tests {
         assert_eq!(code, 0);
         assert!(stdout.contains("Usage:"));
     }
+
+    #[test]
+    fn test_lock() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        let run = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+            let (mut out, mut err) = (String::new(), String::new());
+            let code = prog_lock(&args, "", &mut out, &mut err);
+            (code, out, err)
+        };
+
+        assert_eq!(run(&["-t", "2.5"]).0, 0);
+        syscall::session_input(0.0);
+        syscall::session_poll(30_000.0);
+        let (_, out, _) = run(&["-s"]);
+        assert_eq!(out, "locked: no\nidle: 30s\nauto-lock: after 2m 30s\n");
+        assert!(syscall::session_poll(150_000.0));
+
+        let (_, out, _) = run(&["-s"]);
+        assert!(out.starts_with("locked: yes (Idle, 0 failed attempts)"));
+        assert_eq!(syscall::session_unlock("anything"), Ok(()));
+
+        assert_eq!(run(&[]).0, 0);
+        assert!(syscall::session_locked());
+
+        // Tasks keep running while locked
+        let ran = std::rc::Rc::new(std::cell::Cell::new(false));
+        let flag = ran.clone();
+        crate::kernel::spawn(async move { flag.set(true) });
+        crate::kernel::tick();
+        assert!(ran.get());
+        assert_eq!(run(&["-t", "-1"]).0, 1);
+        assert_eq!(run(&["-t", "off"]).0, 0);
+        assert_eq!(run(&["-x"]).0, 1);
+        assert_eq!(run(&["-t", "5", "-s"]).0, 2);
+    }
+
+    #[test]
+    fn test_guest() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        let run = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+            let (mut out, mut err) = (String::new(), String::new());
+            let code = prog_guest(&args, "", &mut out, &mut err);
+            (code, out, err)
+        };
+
+        syscall::write_file("/home/user/notes", "real").unwrap();
+        assert_eq!(run(&["exit"]).0, 1);
+        assert_eq!(run(&[]).0, 0);
+        assert_eq!(run(&["start"]).0, 1);
+        let root = || {
+            KERNEL.with(|k| {
+                let k = k.borrow();
+                let root = k.mounts().get_mount("/").unwrap();
+                format!("{} {}", root.source, root.fstype.as_str())
+            })
+        };
+        assert_eq!(root(), "guest overlay");
+
+        syscall::write_file("/home/user/notes", "scribbled").unwrap();
+        syscall::write_file("/tmp/keep", "keep").unwrap();
+        syscall::write_file("/tmp/junk", "junk").unwrap();
+        let (_, out, _) = run(&["export", "/tmp/keep"]);
+        assert_eq!(out, "keeping /tmp/keep\n");
+        assert_eq!(run(&["export", "/nowhere"]).0, 1);
+
+        // Persistence only ever sees the real filesystem
+        let saved = crate::vfs::MemoryFs::from_json(&syscall::vfs_snapshot().unwrap()).unwrap();
+        assert!(!crate::vfs::FileSystem::exists(&saved, "/tmp/keep"));
+
+        let (_, out, _) = run(&["status"]);
+        assert!(out.contains("~ /home/user/notes"));
+        assert!(out.contains("export /tmp/keep"));
+        let (code, out, _) = run(&["exit"]);
+        assert_eq!(code, 0);
+        assert!(out.contains("3 changed paths discarded, 1 exports kept"));
+
+        assert_eq!(syscall::read_file("/home/user/notes").unwrap(), "real");
+        assert_eq!(syscall::read_file("/tmp/keep").unwrap(), "keep");
+        assert!(!syscall::exists("/tmp/junk").unwrap());
+        assert!(!syscall::guest_active());
+        assert_eq!(root(), "rootfs memoryfs");
+    }
 }
@@ -0,0 +1,754 @@
+//! QR code encoding
+//!
+//! Encodes bytes as a QR code (ISO/IEC 18004, byte mode, versions 1 to 40)
+//! and draws it either with half-block characters for the terminal or as a
+//! PNG. The smallest version that fits the data at the requested error
+//! correction level is used, and the mask is picked by the standard's
+//! penalty rules. `qr` is the program on top of this.
+
+use crate::vfs::deflate::crc32;
+
+/// How much of the code can be damaged and still read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ecc {
+    /// About 7% of codewords
+    Low,
+    /// About 15% of codewords
+    Medium,
+    /// About 25% of codewords
+    Quartile,
+    /// About 30% of codewords
+    High,
+}
+
+impl Ecc {
+    /// `L`, `M`, `Q` or `H`, in either case
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_uppercase().as_str() {
+            "L" => Some(Ecc::Low),
+            "M" => Some(Ecc::Medium),
+            "Q" => Some(Ecc::Quartile),
+            "H" => Some(Ecc::High),
+            _ => None,
+        }
+    }
+
+    /// Row in the block tables
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    /// The two bits the format information stores
+    fn format_bits(self) -> u32 {
+        match self {
+            Ecc::Low => 1,
+            Ecc::Medium => 0,
+            Ecc::Quartile => 3,
+            Ecc::High => 2,
+        }
+    }
+}
+
+/// Error correction codewords per block, by level and version
+const ECC_CODEWORDS_PER_BLOCK: [[u8; 41]; 4] = [
+    [
+        0, 7, 10, 15, 20, 26, 18, 20, 24, 30, 18, 20, 24, 26, 30, 22, 24, 28, 30, 28, 28, 28, 28,
+        30, 30, 26, 28, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30,
+    ],
+    [
+        0, 10, 16, 26, 18, 24, 16, 18, 22, 22, 26, 30, 22, 22, 24, 24, 28, 28, 26, 26, 26, 26, 28,
+        28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28,
+    ],
+    [
+        0, 13, 22, 18, 26, 18, 24, 18, 22, 20, 24, 28, 26, 24, 20, 30, 24, 28, 28, 26, 30, 28, 30,
+        30, 30, 30, 28, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30,
+    ],
+    [
+        0, 17, 28, 22, 16, 22, 28, 26, 26, 24, 28, 24, 28, 22, 24, 24, 30, 28, 28, 26, 28, 30, 24,
+        30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30,
+    ],
+];
+
+/// Error correction blocks, by level and version
+const ERROR_CORRECTION_BLOCKS: [[u8; 41]; 4] = [
+    [
+        0, 1, 1, 1, 1, 1, 2, 2, 2, 2, 4, 4, 4, 4, 4, 6, 6, 6, 6, 7, 8, 8, 9, 9, 10, 12, 12, 12, 13,
+        14, 15, 16, 17, 18, 19, 19, 20, 21, 22, 24, 25,
+    ],
+    [
+        0, 1, 1, 1, 2, 2, 4, 4, 4, 5, 5, 5, 8, 9, 9, 10, 10, 11, 13, 14, 16, 17, 17, 18, 20, 21,
+        23, 25, 26, 28, 29, 31, 33, 35, 37, 38, 40, 43, 45, 47, 49,
+    ],
+    [
+        0, 1, 1, 2, 2, 4, 4, 6, 6, 8, 8, 8, 10, 12, 16, 12, 17, 16, 18, 21, 20, 23, 23, 25, 27, 29,
+        34, 34, 35, 38, 40, 43, 45, 48, 51, 53, 56, 59, 62, 65, 68,
+    ],
+    [
+        0, 1, 1, 2, 4, 4, 4, 5, 6, 8, 8, 11, 11, 16, 16, 18, 16, 19, 21, 25, 25, 25, 34, 30, 32,
+        35, 37, 40, 42, 45, 48, 51, 54, 57, 60, 63, 66, 70, 74, 77, 81,
+    ],
+];
+
+/// Light modules around the code, as the standard asks for
+pub const QUIET_ZONE: usize = 4;
+
+/// A QR code: a square of dark and light modules
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QrCode {
+    version: usize,
+    size: usize,
+    modules: Vec<bool>,
+    function: Vec<bool>,
+}
+
+impl QrCode {
+    /// Encode `data` in the smallest version that holds it at `ecc`
+    pub fn encode(data: &[u8], ecc: Ecc) -> Result<Self, String> {
+        let version = (1..=40)
+            .find(|&v| data_bits(data.len(), v) <= data_codewords(v, ecc) * 8)
+            .ok_or_else(|| {
+                format!(
+                    "{} bytes is too much for a QR code (at most {} at this level)",
+                    data.len(),
+                    max_bytes(ecc)
+                )
+            })?;
+
+        let capacity = data_codewords(version, ecc);
+        let mut bits = Bits::default();
+        bits.push(0b0100, 4);
+        bits.push(data.len() as u32, if version <= 9 { 8 } else { 16 });
+        for &b in data {
+            bits.push(u32::from(b), 8);
+        }
+        let terminator = (capacity * 8 - bits.len).min(4);
+        bits.push(0, terminator);
+        bits.push(0, (8 - bits.len % 8) % 8);
+        for pad in [0xEC, 0x11].into_iter().cycle() {
+            if bits.bytes.len() >= capacity {
+                break;
+            }
+            bits.push(pad, 8);
+        }
+
+        let size = version * 4 + 17;
+        let mut qr = QrCode {
+            version,
+            size,
+            modules: vec![false; size * size],
+            function: vec![false; size * size],
+        };
+        qr.draw_function_patterns();
+        let codewords = add_ecc_and_interleave(&bits.bytes, version, ecc);
+        qr.draw_codewords(&codewords);
+
+        let mut best = (0, i32::MAX);
+        for mask in 0..8 {
+            qr.apply_mask(mask);
+            qr.draw_format_bits(ecc, mask);
+            let penalty = qr.penalty();
+            if penalty < best.1 {
+                best = (mask, penalty);
+            }
+            // Masking twice undoes it
+            qr.apply_mask(mask);
+        }
+        qr.apply_mask(best.0);
+        qr.draw_format_bits(ecc, best.0);
+        Ok(qr)
+    }
+
+    /// Version, 1 to 40
+    pub fn version(&self) -> usize {
+        self.version
+    }
+
+    /// Modules along each side, without the quiet zone
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Whether the module at column `x`, row `y` is dark
+    ///
+    /// Anything outside the code, such as the quiet zone, is light.
+    pub fn is_dark(&self, x: usize, y: usize) -> bool {
+        x < self.size && y < self.size && self.modules[y * self.size + x]
+    }
+
+    /// Whether a module in quiet-zone coordinates is dark
+    fn is_dark_padded(&self, x: usize, y: usize) -> bool {
+        x >= QUIET_ZONE && y >= QUIET_ZONE && self.is_dark(x - QUIET_ZONE, y - QUIET_ZONE)
+    }
+
+    /// Draw with half blocks, two rows of modules per line
+    ///
+    /// Blocks are drawn for light modules, which reads right on a dark
+    /// terminal; `invert` draws the dark modules instead, for light ones.
+    pub fn to_text(&self, invert: bool) -> String {
+        let full = self.size + 2 * QUIET_ZONE;
+        let block = |x, y| y < full && self.is_dark_padded(x, y) == invert;
+        let mut out = String::new();
+        for y in (0..full).step_by(2) {
+            for x in 0..full {
+                out.push(match (block(x, y), block(x, y + 1)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                });
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// A 1-bit grayscale PNG, `scale` pixels per module, quiet zone included
+    pub fn to_png(&self, scale: usize) -> Vec<u8> {
+        let scale = scale.max(1);
+        let side = (self.size + 2 * QUIET_ZONE) * scale;
+        let row_len = side.div_ceil(8);
+        let mut raw = Vec::with_capacity((row_len + 1) * side);
+        for py in 0..side {
+            // Filter type: none
+            raw.push(0);
+            let mut row = vec![0u8; row_len];
+            for px in 0..side {
+                if !self.is_dark_padded(px / scale, py / scale) {
+                    row[px / 8] |= 0x80 >> (px % 8);
+                }
+            }
+            raw.extend_from_slice(&row);
+        }
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(side as u32).to_be_bytes());
+        header.extend_from_slice(&(side as u32).to_be_bytes());
+        // Bit depth 1, grayscale, deflate, adaptive filtering, no interlace
+        header.extend_from_slice(&[1, 0, 0, 0, 0]);
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png_chunk(&mut png, b"IHDR", &header);
+        png_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+        png_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    fn set_function(&mut self, x: usize, y: usize, dark: bool) {
+        let i = y * self.size + x;
+        self.modules[i] = dark;
+        self.function[i] = true;
+    }
+
+    fn draw_function_patterns(&mut self) {
+        let size = self.size;
+        for i in 0..size {
+            self.set_function(6, i, i % 2 == 0);
+            self.set_function(i, 6, i % 2 == 0);
+        }
+
+        for (cx, cy) in [(3, 3), (size - 4, 3), (3, size - 4)] {
+            for dy in -4i32..=4 {
+                for dx in -4i32..=4 {
+                    let (x, y) = (cx as i32 + dx, cy as i32 + dy);
+                    if (0..size as i32).contains(&x) && (0..size as i32).contains(&y) {
+                        let dist = dx.abs().max(dy.abs());
+                        self.set_function(x as usize, y as usize, dist != 2 && dist != 4);
+                    }
+                }
+            }
+        }
+
+        let positions = alignment_positions(self.version);
+        let last = positions.len().saturating_sub(1);
+        for (i, &cx) in positions.iter().enumerate() {
+            for (j, &cy) in positions.iter().enumerate() {
+                // The corners already have finder patterns
+                if [(0, 0), (0, last), (last, 0)].contains(&(i, j)) {
+                    continue;
+                }
+                for dy in -2i32..=2 {
+                    for dx in -2i32..=2 {
+                        let x = (cx as i32 + dx) as usize;
+                        let y = (cy as i32 + dy) as usize;
+                        self.set_function(x, y, dx.abs().max(dy.abs()) != 1);
+                    }
+                }
+            }
+        }
+
+        // Reserve the format area; the real bits go in once a mask is chosen
+        self.draw_format_bits(Ecc::Medium, 0);
+        self.draw_version();
+    }
+
+    fn draw_format_bits(&mut self, ecc: Ecc, mask: usize) {
+        let bits = format_information(ecc, mask);
+        let bit = |i: usize| (bits >> i) & 1 != 0;
+        let size = self.size;
+
+        for i in 0..6 {
+            self.set_function(8, i, bit(i));
+        }
+        self.set_function(8, 7, bit(6));
+        self.set_function(8, 8, bit(7));
+        self.set_function(7, 8, bit(8));
+        for i in 9..15 {
+            self.set_function(14 - i, 8, bit(i));
+        }
+
+        for i in 0..8 {
+            self.set_function(size - 1 - i, 8, bit(i));
+        }
+        for i in 8..15 {
+            self.set_function(8, size - 15 + i, bit(i));
+        }
+        // Always dark
+        self.set_function(8, size - 8, true);
+    }
+
+    fn draw_version(&mut self) {
+        if self.version < 7 {
+            return;
+        }
+        let bits = version_information(self.version);
+        for i in 0..18 {
+            let dark = (bits >> i) & 1 != 0;
+            let a = self.size - 11 + i % 3;
+            let b = i / 3;
+            self.set_function(a, b, dark);
+            self.set_function(b, a, dark);
+        }
+    }
+
+    /// Place codewords in the zigzag order, two columns at a time
+    fn draw_codewords(&mut self, data: &[u8]) {
+        let size = self.size;
+        let mut i = 0;
+        let mut right = size - 1;
+        while right >= 1 {
+            // The vertical timing pattern takes a whole column
+            if right == 6 {
+                right = 5;
+            }
+            let upward = (right + 1) & 2 == 0;
+            for vert in 0..size {
+                let y = if upward { size - 1 - vert } else { vert };
+                for j in 0..2 {
+                    let idx = y * size + right - j;
+                    if !self.function[idx] && i < data.len() * 8 {
+                        self.modules[idx] = (data[i / 8] >> (7 - i % 8)) & 1 != 0;
+                        i += 1;
+                    }
+                }
+            }
+            if right < 2 {
+                break;
+            }
+            right -= 2;
+        }
+    }
+
+    fn apply_mask(&mut self, mask: usize) {
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let invert = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                let i = y * self.size + x;
+                self.modules[i] ^= invert && !self.function[i];
+            }
+        }
+    }
+
+    /// The standard's score for how hard the code is to scan; lower wins
+    fn penalty(&self) -> i32 {
+        let size = self.size;
+        let mut result = 0;
+
+        for pass in 0..2 {
+            for a in 0..size {
+                let mut runs = Runs::new(size as i32);
+                let mut color = false;
+                let mut run = 0;
+                for b in 0..size {
+                    let dark = if pass == 0 {
+                        self.modules[a * size + b]
+                    } else {
+                        self.modules[b * size + a]
+                    };
+                    if dark == color {
+                        run += 1;
+                        if run == 5 {
+                            result += 3;
+                        } else if run > 5 {
+                            result += 1;
+                        }
+                    } else {
+                        runs.add(run);
+                        if !color {
+                            result += runs.finder_like() * 40;
+                        }
+                        color = dark;
+                        run = 1;
+                    }
+                }
+                result += runs.finish(color, run) * 40;
+            }
+        }
+
+        for y in 0..size - 1 {
+            for x in 0..size - 1 {
+                let c = self.modules[y * size + x];
+                if c == self.modules[y * size + x + 1]
+                    && c == self.modules[(y + 1) * size + x]
+                    && c == self.modules[(y + 1) * size + x + 1]
+                {
+                    result += 3;
+                }
+            }
+        }
+
+        let dark = self.modules.iter().filter(|&&m| m).count() as i32;
+        let total = (size * size) as i32;
+        let k = ((dark * 20 - total * 10).abs() + total - 1) / total - 1;
+        result + k * 10
+    }
+}
+
+/// The last seven run lengths of a row or column, newest first
+struct Runs {
+    size: i32,
+    history: [i32; 7],
+}
+
+impl Runs {
+    fn new(size: i32) -> Self {
+        Runs {
+            size,
+            history: [0; 7],
+        }
+    }
+
+    fn add(&mut self, mut run: i32) {
+        // The light border before the first run counts towards it
+        if self.history[0] == 0 {
+            run += self.size;
+        }
+        self.history.copy_within(0..6, 1);
+        self.history[0] = run;
+    }
+
+    /// Dark-light-dark-light-dark runs of 1:1:3:1:1 with light on a side
+    fn finder_like(&self) -> i32 {
+        let h = &self.history;
+        let n = h[1];
+        let core = n > 0 && h[2] == n && h[3] == n * 3 && h[4] == n && h[5] == n;
+        i32::from(core && h[0] >= n * 4 && h[6] >= n)
+            + i32::from(core && h[6] >= n * 4 && h[0] >= n)
+    }
+
+    /// End the line, counting the light border after it
+    fn finish(&mut self, color: bool, mut run: i32) -> i32 {
+        if color {
+            self.add(run);
+            run = 0;
+        }
+        self.add(run + self.size);
+        self.finder_like()
+    }
+}
+
+/// Bits packed into bytes, most significant first
+#[derive(Default)]
+struct Bits {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl Bits {
+    fn push(&mut self, value: u32, count: usize) {
+        for i in (0..count).rev() {
+            if self.len.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            if (value >> i) & 1 != 0
+                && let Some(last) = self.bytes.last_mut()
+            {
+                *last |= 0x80 >> (self.len % 8);
+            }
+            self.len += 1;
+        }
+    }
+}
+
+/// Bits byte mode needs for `len` bytes in `version`
+fn data_bits(len: usize, version: usize) -> usize {
+    4 + if version <= 9 { 8 } else { 16 } + len * 8
+}
+
+/// Most bytes a code can hold at `ecc`
+pub fn max_bytes(ecc: Ecc) -> usize {
+    (data_codewords(40, ecc) * 8 - data_bits(0, 40)) / 8
+}
+
+/// Modules left for data and error correction once the patterns are drawn
+fn raw_data_modules(version: usize) -> usize {
+    let mut result = (16 * version + 128) * version + 64;
+    if version >= 2 {
+        let align = version / 7 + 2;
+        result -= (25 * align - 10) * align - 55;
+        if version >= 7 {
+            result -= 36;
+        }
+    }
+    result
+}
+
+/// Data codewords a version holds at `ecc`
+fn data_codewords(version: usize, ecc: Ecc) -> usize {
+    raw_data_modules(version) / 8
+        - usize::from(ECC_CODEWORDS_PER_BLOCK[ecc.index()][version])
+            * usize::from(ERROR_CORRECTION_BLOCKS[ecc.index()][version])
+}
+
+/// Centres of the alignment patterns along each axis
+fn alignment_positions(version: usize) -> Vec<usize> {
+    if version == 1 {
+        return Vec::new();
+    }
+    let count = version / 7 + 2;
+    let step = (version * 8 + count * 3 + 5) / (count * 4 - 4) * 2;
+    let size = version * 4 + 17;
+    let mut result: Vec<usize> = (0..count - 1).map(|i| size - 7 - i * step).collect();
+    result.push(6);
+    result.reverse();
+    result
+}
+
+/// The 15 format bits: level and mask, BCH-protected and masked
+fn format_information(ecc: Ecc, mask: usize) -> u32 {
+    let data = ecc.format_bits() << 3 | mask as u32;
+    let mut rem = data;
+    for _ in 0..10 {
+        rem = (rem << 1) ^ ((rem >> 9) * 0x537);
+    }
+    (data << 10 | rem) ^ 0x5412
+}
+
+/// The 18 version bits for versions 7 and up
+fn version_information(version: usize) -> u32 {
+    let mut rem = version as u32;
+    for _ in 0..12 {
+        rem = (rem << 1) ^ ((rem >> 11) * 0x1F25);
+    }
+    (version as u32) << 12 | rem
+}
+
+/// Split data into blocks, add error correction to each and interleave
+fn add_ecc_and_interleave(data: &[u8], version: usize, ecc: Ecc) -> Vec<u8> {
+    let blocks = usize::from(ERROR_CORRECTION_BLOCKS[ecc.index()][version]);
+    let ecc_len = usize::from(ECC_CODEWORDS_PER_BLOCK[ecc.index()][version]);
+    let raw = raw_data_modules(version) / 8;
+    let short_blocks = blocks - raw % blocks;
+    let short_len = raw / blocks;
+
+    let divisor = reed_solomon_divisor(ecc_len);
+    let mut all = Vec::with_capacity(blocks);
+    let mut k = 0;
+    for i in 0..blocks {
+        let len = short_len - ecc_len + usize::from(i >= short_blocks);
+        let mut block = data[k..k + len].to_vec();
+        k += len;
+        let ecc = reed_solomon_remainder(&block, &divisor);
+        // A gap so short and long blocks line up when interleaved
+        if i < short_blocks {
+            block.push(0);
+        }
+        block.extend_from_slice(&ecc);
+        all.push(block);
+    }
+
+    let mut result = Vec::with_capacity(raw);
+    for i in 0..=short_len {
+        for (j, block) in all.iter().enumerate() {
+            if i != short_len - ecc_len || j >= short_blocks {
+                result.push(block[i]);
+            }
+        }
+    }
+    result
+}
+
+/// Multiply in GF(2^8) modulo x^8 + x^4 + x^3 + x^2 + 1
+fn gf_multiply(x: u8, y: u8) -> u8 {
+    let mut z: u32 = 0;
+    for i in (0..8).rev() {
+        z = (z << 1) ^ ((z >> 7) * 0x11D);
+        z ^= u32::from((y >> i) & 1) * u32::from(x);
+    }
+    z as u8
+}
+
+/// Generator polynomial of the given degree, leading 1 left out
+fn reed_solomon_divisor(degree: usize) -> Vec<u8> {
+    let mut result = vec![0u8; degree];
+    if let Some(last) = result.last_mut() {
+        *last = 1;
+    }
+    let mut root = 1u8;
+    for _ in 0..degree {
+        for j in 0..degree {
+            result[j] = gf_multiply(result[j], root);
+            if j + 1 < degree {
+                result[j] ^= result[j + 1];
+            }
+        }
+        root = gf_multiply(root, 0x02);
+    }
+    result
+}
+
+/// Error correction codewords for `data`
+fn reed_solomon_remainder(data: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut result = vec![0u8; divisor.len()];
+    for &b in data {
+        let factor = b ^ result[0];
+        result.rotate_left(1);
+        if let Some(last) = result.last_mut() {
+            *last = 0;
+        }
+        for (r, &d) in result.iter_mut().zip(divisor) {
+            *r ^= gf_multiply(d, factor);
+        }
+    }
+    result
+}
+
+fn png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// A zlib stream of uncompressed deflate blocks
+///
+/// QR images are small and PNG readers don't mind, so the pixels aren't
+/// worth compressing.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut chunks = data.chunks(0xFFFF).peekable();
+    if chunks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(chunk) = chunks.next() {
+        out.push(u8::from(chunks.peek().is_none()));
+        let len = chunk.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(chunk);
+    }
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + u32::from(byte)) % 65521;
+        b = (b + a) % 65521;
+    }
+    out.extend_from_slice(&(b << 16 | a).to_be_bytes());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capacity() {
+        // Data codewords from the standard's tables
+        assert_eq!(data_codewords(1, Ecc::Low), 19);
+        assert_eq!(data_codewords(1, Ecc::High), 9);
+        assert_eq!(data_codewords(5, Ecc::Quartile), 62);
+        assert_eq!(data_codewords(10, Ecc::Medium), 216);
+        assert_eq!(data_codewords(40, Ecc::Low), 2956);
+        assert_eq!(data_codewords(40, Ecc::High), 1276);
+        assert_eq!(max_bytes(Ecc::Low), 2953);
+        assert_eq!(alignment_positions(7), vec![6, 22, 38]);
+        assert_eq!(alignment_positions(32), vec![6, 34, 60, 86, 112, 138]);
+
+        assert_eq!(QrCode::encode(&[b'a'; 17], Ecc::Low).unwrap().version(), 1);
+        assert_eq!(QrCode::encode(&[b'a'; 18], Ecc::Low).unwrap().version(), 2);
+        assert!(QrCode::encode(&[0; 2953], Ecc::Low).is_ok());
+        assert!(QrCode::encode(&[0; 2954], Ecc::Low).is_err());
+    }
+
+    #[test]
+    fn test_reed_solomon() {
+        // "HELLO WORLD" at 1-M
+        let data = [
+            32, 91, 11, 120, 209, 114, 220, 77, 67, 64, 236, 17, 236, 17, 236, 17,
+        ];
+        assert_eq!(
+            reed_solomon_remainder(&data, &reed_solomon_divisor(10)),
+            vec![196, 35, 39, 119, 235, 215, 231, 226, 93, 23]
+        );
+    }
+
+    #[test]
+    fn test_format_and_version_bits() {
+        assert_eq!(format_information(Ecc::Medium, 0), 0b101010000010010);
+        assert_eq!(format_information(Ecc::Low, 4), 0b110011000101111);
+        assert_eq!(version_information(7), 0b000111110010010100);
+    }
+
+    #[test]
+    fn test_layout() {
+        let qr = QrCode::encode(b"https://example.com", Ecc::Medium).unwrap();
+        assert_eq!(qr.version(), 2);
+        assert_eq!(qr.size(), 25);
+        // Finder patterns in three corners, with their separators
+        for (cx, cy) in [(3, 3), (21, 3), (3, 21)] {
+            assert!(qr.is_dark(cx, cy));
+            assert!(qr.is_dark(cx - 3, cy - 3));
+            assert!(!qr.is_dark(cx - 2, cy));
+        }
+        assert!(!qr.is_dark(7, 0));
+        assert!(qr.is_dark(8, qr.size() - 8));
+        // Timing pattern
+        assert!((8..17).all(|i| qr.is_dark(i, 6) == (i % 2 == 0)));
+        // The two copies of the format bits agree
+        let first: Vec<bool> = (0..6).map(|i| qr.is_dark(8, i)).collect();
+        let second: Vec<bool> = (0..6).map(|i| qr.is_dark(24 - i, 8)).collect();
+        assert_eq!(first, second);
+
+        let text = qr.to_text(false);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 17);
+        assert!(lines.iter().all(|l| l.chars().count() == 33));
+        // The quiet zone is light, so drawn solid on a dark terminal
+        assert!(lines[0].chars().all(|c| c == '█'));
+        assert!(qr.to_text(true).lines().next().unwrap().trim().is_empty());
+    }
+
+    #[test]
+    fn test_png() {
+        let qr = QrCode::encode(b"hi", Ecc::Low).unwrap();
+        let png = qr.to_png(2);
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[12..16], b"IHDR");
+        let side = u32::from_be_bytes([png[16], png[17], png[18], png[19]]);
+        assert_eq!(side, (21 + 8) * 2);
+        // The header's CRC covers its type and data
+        let crc = u32::from_be_bytes([png[29], png[30], png[31], png[32]]);
+        assert_eq!(crc, crc32(&png[12..29]));
+        assert!(png.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]));
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+}
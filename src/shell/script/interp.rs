@@ -0,0 +1,1072 @@
+//! Tree-walking evaluator and builtin functions
+
+use super::parser::{BinOp, Expr, Stmt, StmtKind, UnOp, parse};
+use super::value::{Closure, Value, format_num};
+use super::{ScriptError, ScriptHost};
+use crate::kernel::syscall;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+
+/// Statements and loop iterations a single `run` may execute
+///
+/// Scripts run on the shell's thread, so a runaway loop would freeze the
+/// whole system without this budget.
+pub const DEFAULT_STEP_LIMIT: u64 = 1_000_000;
+
+/// Maximum nesting of function calls
+pub const MAX_CALL_DEPTH: usize = 64;
+
+/// Builtin functions available in every script
+const NATIVES: &[&str] = &[
+    // Core
+    "print",
+    "eprint",
+    "len",
+    "str",
+    "num",
+    "int",
+    "type",
+    "error",
+    // Strings and lists
+    "push",
+    "pop",
+    "keys",
+    "range",
+    "split",
+    "join",
+    "trim",
+    "upper",
+    "lower",
+    "replace",
+    "contains",
+    "starts_with",
+    "ends_with",
+    "slice",
+    "sort",
+    // Filesystem
+    "read",
+    "write",
+    "append",
+    "exists",
+    "isdir",
+    "ls",
+    "mkdir",
+    "rm",
+    // Environment and commands
+    "env",
+    "setenv",
+    "exec",
+];
+
+/// A variable scope; function scopes chain to the scope they were defined in
+#[derive(Default)]
+pub struct Scope {
+    vars: HashMap<String, Value>,
+    parent: Option<Rc<RefCell<Scope>>>,
+}
+
+impl Scope {
+    fn child(parent: &Rc<RefCell<Scope>>) -> Rc<RefCell<Scope>> {
+        Rc::new(RefCell::new(Scope {
+            vars: HashMap::new(),
+            parent: Some(parent.clone()),
+        }))
+    }
+
+    fn get(&self, name: &str) -> Option<Value> {
+        match self.vars.get(name) {
+            Some(v) => Some(v.clone()),
+            None => self.parent.as_ref()?.borrow().get(name),
+        }
+    }
+
+    /// Assign to the nearest scope that defines `name`
+    fn set(&mut self, name: &str, value: Value) -> bool {
+        if let Some(slot) = self.vars.get_mut(name) {
+            *slot = value;
+            return true;
+        }
+        match &self.parent {
+            Some(parent) => parent.borrow_mut().set(name, value),
+            None => false,
+        }
+    }
+}
+
+/// Non-local control flow out of a statement
+enum Flow {
+    Normal,
+    Break,
+    Continue,
+    Return(Value),
+}
+
+/// Script interpreter
+///
+/// Globals persist across calls to [`Interpreter::run`], which is what the
+/// REPL relies on.
+pub struct Interpreter {
+    globals: Rc<RefCell<Scope>>,
+    stdout: String,
+    stderr: String,
+    steps: u64,
+    step_limit: u64,
+    depth: usize,
+}
+
+type EvalResult = Result<Value, ScriptError>;
+
+impl Interpreter {
+    pub fn new() -> Self {
+        let mut globals = Scope::default();
+        for name in NATIVES {
+            globals.vars.insert(name.to_string(), Value::Native(name));
+        }
+        globals
+            .vars
+            .insert("args".to_string(), Value::list(Vec::new()));
+        Self {
+            globals: Rc::new(RefCell::new(globals)),
+            stdout: String::new(),
+            stderr: String::new(),
+            steps: 0,
+            step_limit: DEFAULT_STEP_LIMIT,
+            depth: 0,
+        }
+    }
+
+    /// Set the `args` list seen by the script
+    pub fn set_args(&mut self, args: &[String]) {
+        let args = args.iter().map(|a| Value::str(a.as_str())).collect();
+        self.set_global("args", Value::list(args));
+    }
+
+    /// Define or replace a global variable
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        self.globals
+            .borrow_mut()
+            .vars
+            .insert(name.to_string(), value);
+    }
+
+    /// Read a global variable
+    pub fn global(&self, name: &str) -> Option<Value> {
+        self.globals.borrow().get(name)
+    }
+
+    /// Change the per-run step budget
+    pub fn set_step_limit(&mut self, limit: u64) {
+        self.step_limit = limit;
+    }
+
+    /// Take everything printed with `print` since the last call
+    pub fn take_stdout(&mut self) -> String {
+        std::mem::take(&mut self.stdout)
+    }
+
+    /// Take everything printed with `eprint` since the last call
+    pub fn take_stderr(&mut self) -> String {
+        std::mem::take(&mut self.stderr)
+    }
+
+    /// Parse and run `src` in the global scope
+    ///
+    /// Returns the value of the final statement if it is an expression,
+    /// otherwise `nil`.
+    pub fn run(&mut self, src: &str, host: &mut dyn ScriptHost) -> EvalResult {
+        let stmts = parse(src)?;
+        self.steps = 0;
+        self.depth = 0;
+
+        let globals = self.globals.clone();
+        let mut last = Value::Nil;
+        for stmt in &stmts {
+            last = Value::Nil;
+            if let StmtKind::Expr(expr) = &stmt.kind {
+                self.tick(stmt.line)?;
+                last = self.eval(expr, &globals, host, stmt.line)?;
+                continue;
+            }
+            match self.exec(stmt, &globals, host)? {
+                Flow::Normal => {}
+                Flow::Return(_) => break,
+                Flow::Break | Flow::Continue => {
+                    return Err(ScriptError::new(
+                        stmt.line,
+                        "break or continue outside a loop",
+                    ));
+                }
+            }
+        }
+        Ok(last)
+    }
+
+    fn tick(&mut self, line: usize) -> Result<(), ScriptError> {
+        self.steps += 1;
+        if self.steps > self.step_limit {
+            return Err(ScriptError::new(
+                line,
+                format!("step limit of {} exceeded", self.step_limit),
+            ));
+        }
+        Ok(())
+    }
+
+    fn block(
+        &mut self,
+        stmts: &[Stmt],
+        scope: &Rc<RefCell<Scope>>,
+        host: &mut dyn ScriptHost,
+    ) -> Result<Flow, ScriptError> {
+        for stmt in stmts {
+            match self.exec(stmt, scope, host)? {
+                Flow::Normal => {}
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn exec(
+        &mut self,
+        stmt: &Stmt,
+        scope: &Rc<RefCell<Scope>>,
+        host: &mut dyn ScriptHost,
+    ) -> Result<Flow, ScriptError> {
+        let line = stmt.line;
+        self.tick(line)?;
+
+        match &stmt.kind {
+            StmtKind::Let(name, expr) => {
+                let value = self.eval(expr, scope, host, line)?;
+                scope.borrow_mut().vars.insert(name.clone(), value);
+            }
+            StmtKind::Assign(target, op, expr) => {
+                let mut value = self.eval(expr, scope, host, line)?;
+                if let Some(op) = op {
+                    let current = self.eval(target, scope, host, line)?;
+                    value = binary(*op, &current, &value, line)?;
+                }
+                self.assign(target, value, scope, host, line)?;
+            }
+            StmtKind::Expr(expr) => {
+                self.eval(expr, scope, host, line)?;
+            }
+            StmtKind::If(branches, otherwise) => {
+                for (cond, body) in branches {
+                    if self.eval(cond, scope, host, line)?.truthy() {
+                        return self.block(body, &Scope::child(scope), host);
+                    }
+                }
+                if let Some(body) = otherwise {
+                    return self.block(body, &Scope::child(scope), host);
+                }
+            }
+            StmtKind::While(cond, body) => {
+                while self.eval(cond, scope, host, line)?.truthy() {
+                    self.tick(line)?;
+                    match self.block(body, &Scope::child(scope), host)? {
+                        Flow::Break => break,
+                        Flow::Return(v) => return Ok(Flow::Return(v)),
+                        Flow::Normal | Flow::Continue => {}
+                    }
+                }
+            }
+            StmtKind::For(name, iter, body) => {
+                let items = match self.eval(iter, scope, host, line)? {
+                    Value::List(items) => items.borrow().clone(),
+                    Value::Map(entries) => entries
+                        .borrow()
+                        .keys()
+                        .map(|k| Value::str(k.as_str()))
+                        .collect(),
+                    Value::Str(s) => s.chars().map(|c| Value::str(c.to_string())).collect(),
+                    other => {
+                        return Err(ScriptError::new(
+                            line,
+                            format!("cannot iterate over {}", other.type_name()),
+                        ));
+                    }
+                };
+                for item in items {
+                    self.tick(line)?;
+                    let inner = Scope::child(scope);
+                    inner.borrow_mut().vars.insert(name.clone(), item);
+                    match self.block(body, &inner, host)? {
+                        Flow::Break => break,
+                        Flow::Return(v) => return Ok(Flow::Return(v)),
+                        Flow::Normal | Flow::Continue => {}
+                    }
+                }
+            }
+            StmtKind::Return(expr) => {
+                let value = match expr {
+                    Some(e) => self.eval(e, scope, host, line)?,
+                    None => Value::Nil,
+                };
+                return Ok(Flow::Return(value));
+            }
+            StmtKind::Break => return Ok(Flow::Break),
+            StmtKind::Continue => return Ok(Flow::Continue),
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn assign(
+        &mut self,
+        target: &Expr,
+        value: Value,
+        scope: &Rc<RefCell<Scope>>,
+        host: &mut dyn ScriptHost,
+        line: usize,
+    ) -> Result<(), ScriptError> {
+        match target {
+            Expr::Var(name) => {
+                if !scope.borrow_mut().set(name, value) {
+                    return Err(ScriptError::new(
+                        line,
+                        format!("assignment to undefined variable '{}' (use let)", name),
+                    ));
+                }
+                Ok(())
+            }
+            Expr::Index(container, index) => {
+                let container = self.eval(container, scope, host, line)?;
+                let index = self.eval(index, scope, host, line)?;
+                match (&container, &index) {
+                    (Value::List(items), Value::Num(_)) => {
+                        let mut items = items.borrow_mut();
+                        let i = list_index(&index, items.len(), line)?;
+                        items[i] = value;
+                        Ok(())
+                    }
+                    (Value::Map(entries), Value::Str(key)) => {
+                        entries.borrow_mut().insert(key.to_string(), value);
+                        Ok(())
+                    }
+                    _ => Err(ScriptError::new(
+                        line,
+                        format!(
+                            "cannot assign to {} index of {}",
+                            index.type_name(),
+                            container.type_name()
+                        ),
+                    )),
+                }
+            }
+            _ => Err(ScriptError::new(line, "cannot assign to this expression")),
+        }
+    }
+
+    fn eval(
+        &mut self,
+        expr: &Expr,
+        scope: &Rc<RefCell<Scope>>,
+        host: &mut dyn ScriptHost,
+        line: usize,
+    ) -> EvalResult {
+        Ok(match expr {
+            Expr::Nil => Value::Nil,
+            Expr::Bool(b) => Value::Bool(*b),
+            Expr::Num(n) => Value::Num(*n),
+            Expr::Str(s) => Value::str(s.as_str()),
+            Expr::Var(name) => match scope.borrow().get(name) {
+                Some(v) => v,
+                None => {
+                    return Err(ScriptError::new(
+                        line,
+                        format!("undefined variable '{}'", name),
+                    ));
+                }
+            },
+            Expr::List(items) => {
+                let mut values = Vec::with_capacity(items.len());
+                for item in items {
+                    values.push(self.eval(item, scope, host, line)?);
+                }
+                Value::list(values)
+            }
+            Expr::Map(entries) => {
+                let mut map = BTreeMap::new();
+                for (k, v) in entries {
+                    let key = match self.eval(k, scope, host, line)? {
+                        Value::Str(s) => s.to_string(),
+                        Value::Num(n) => format_num(n),
+                        other => {
+                            return Err(ScriptError::new(
+                                line,
+                                format!("map keys must be strings, not {}", other.type_name()),
+                            ));
+                        }
+                    };
+                    map.insert(key, self.eval(v, scope, host, line)?);
+                }
+                Value::map(map)
+            }
+            Expr::Unary(op, operand) => {
+                let v = self.eval(operand, scope, host, line)?;
+                match (op, v) {
+                    (UnOp::Not, v) => Value::Bool(!v.truthy()),
+                    (UnOp::Neg, Value::Num(n)) => Value::Num(-n),
+                    (UnOp::Neg, v) => {
+                        return Err(ScriptError::new(
+                            line,
+                            format!("cannot negate {}", v.type_name()),
+                        ));
+                    }
+                }
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let a = self.eval(lhs, scope, host, line)?;
+                let b = self.eval(rhs, scope, host, line)?;
+                binary(*op, &a, &b, line)?
+            }
+            Expr::And(lhs, rhs) => {
+                let a = self.eval(lhs, scope, host, line)?;
+                if !a.truthy() {
+                    return Ok(a);
+                }
+                self.eval(rhs, scope, host, line)?
+            }
+            Expr::Or(lhs, rhs) => {
+                let a = self.eval(lhs, scope, host, line)?;
+                if a.truthy() {
+                    return Ok(a);
+                }
+                self.eval(rhs, scope, host, line)?
+            }
+            Expr::Index(container, index) => {
+                let container = self.eval(container, scope, host, line)?;
+                let index = self.eval(index, scope, host, line)?;
+                index_value(&container, &index, line)?
+            }
+            Expr::Func(def) => Value::Func(Rc::new(Closure {
+                def: def.clone(),
+                scope: scope.clone(),
+            })),
+            Expr::Call(callee, args) => {
+                let callee = self.eval(callee, scope, host, line)?;
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args {
+                    values.push(self.eval(arg, scope, host, line)?);
+                }
+                self.call(&callee, values, host, line)?
+            }
+        })
+    }
+
+    /// Call a script or builtin function
+    pub fn call(
+        &mut self,
+        callee: &Value,
+        args: Vec<Value>,
+        host: &mut dyn ScriptHost,
+        line: usize,
+    ) -> EvalResult {
+        match callee {
+            Value::Native(name) => self.native(name, args, host, line),
+            Value::Func(closure) => {
+                let def = &closure.def;
+                if args.len() > def.params.len() {
+                    return Err(ScriptError::new(
+                        line,
+                        format!(
+                            "{}() takes {} arguments but {} were given",
+                            def.name,
+                            def.params.len(),
+                            args.len()
+                        ),
+                    ));
+                }
+                if self.depth >= MAX_CALL_DEPTH {
+                    return Err(ScriptError::new(line, "maximum call depth exceeded"));
+                }
+
+                let frame = Scope::child(&closure.scope);
+                {
+                    let mut frame = frame.borrow_mut();
+                    let mut args = args.into_iter();
+                    for param in &def.params {
+                        frame
+                            .vars
+                            .insert(param.clone(), args.next().unwrap_or(Value::Nil));
+                    }
+                }
+
+                self.depth += 1;
+                let flow = self.block(&def.body, &frame, host);
+                self.depth -= 1;
+                match flow? {
+                    Flow::Return(v) => Ok(v),
+                    Flow::Normal => Ok(Value::Nil),
+                    Flow::Break | Flow::Continue => {
+                        Err(ScriptError::new(line, "break or continue outside a loop"))
+                    }
+                }
+            }
+            other => Err(ScriptError::new(
+                line,
+                format!("{} is not callable", other.type_name()),
+            )),
+        }
+    }
+
+    fn native(
+        &mut self,
+        name: &str,
+        args: Vec<Value>,
+        host: &mut dyn ScriptHost,
+        line: usize,
+    ) -> EvalResult {
+        let fail = |msg: String| Err(ScriptError::new(line, format!("{}: {}", name, msg)));
+        let arg = |i: usize| args.get(i).cloned().unwrap_or(Value::Nil);
+        let text = |i: usize| -> Result<Rc<str>, ScriptError> {
+            match args.get(i) {
+                Some(Value::Str(s)) => Ok(s.clone()),
+                Some(other) => Err(ScriptError::new(
+                    line,
+                    format!("{}: expected string, got {}", name, other.type_name()),
+                )),
+                None => Err(ScriptError::new(
+                    line,
+                    format!("{}: missing argument {}", name, i + 1),
+                )),
+            }
+        };
+        let number = |i: usize| -> Result<f64, ScriptError> {
+            match args.get(i) {
+                Some(Value::Num(n)) => Ok(*n),
+                Some(other) => Err(ScriptError::new(
+                    line,
+                    format!("{}: expected number, got {}", name, other.type_name()),
+                )),
+                None => Err(ScriptError::new(
+                    line,
+                    format!("{}: missing argument {}", name, i + 1),
+                )),
+            }
+        };
+        let list = |i: usize| -> Result<Rc<RefCell<Vec<Value>>>, ScriptError> {
+            match args.get(i) {
+                Some(Value::List(items)) => Ok(items.clone()),
+                other => Err(ScriptError::new(
+                    line,
+                    format!(
+                        "{}: expected list, got {}",
+                        name,
+                        other.map_or("nothing", Value::type_name)
+                    ),
+                )),
+            }
+        };
+
+        Ok(match name {
+            "print" | "eprint" => {
+                let parts: Vec<String> = args.iter().map(Value::to_string).collect();
+                let out = if name == "print" {
+                    &mut self.stdout
+                } else {
+                    &mut self.stderr
+                };
+                out.push_str(&parts.join(" "));
+                out.push('\n');
+                Value::Nil
+            }
+            "len" => match arg(0) {
+                Value::Str(s) => Value::Num(s.chars().count() as f64),
+                Value::List(items) => Value::Num(items.borrow().len() as f64),
+                Value::Map(entries) => Value::Num(entries.borrow().len() as f64),
+                other => return fail(format!("{} has no length", other.type_name())),
+            },
+            "str" => Value::str(arg(0).to_string()),
+            "num" => match arg(0) {
+                Value::Num(n) => Value::Num(n),
+                Value::Str(s) => s.trim().parse().map(Value::Num).unwrap_or(Value::Nil),
+                _ => Value::Nil,
+            },
+            "int" => Value::Num(number(0)?.trunc()),
+            "type" => Value::str(arg(0).type_name()),
+            "error" => return Err(ScriptError::new(line, arg(0).to_string())),
+            "push" => {
+                let items = list(0)?;
+                items.borrow_mut().push(arg(1));
+                Value::List(items)
+            }
+            "pop" => list(0)?.borrow_mut().pop().unwrap_or(Value::Nil),
+            "keys" => match arg(0) {
+                Value::Map(entries) => Value::list(
+                    entries
+                        .borrow()
+                        .keys()
+                        .map(|k| Value::str(k.as_str()))
+                        .collect(),
+                ),
+                other => return fail(format!("expected map, got {}", other.type_name())),
+            },
+            "range" => {
+                let (start, end) = match args.len() {
+                    1 => (0.0, number(0)?),
+                    _ => (number(0)?, number(1)?),
+                };
+                let step = if args.len() > 2 { number(2)? } else { 1.0 };
+                if step == 0.0 {
+                    return fail("step must not be zero".to_string());
+                }
+                let mut items = Vec::new();
+                let mut n = start;
+                while (step > 0.0 && n < end) || (step < 0.0 && n > end) {
+                    self.tick(line)?;
+                    items.push(Value::Num(n));
+                    n += step;
+                }
+                Value::list(items)
+            }
+            "split" => {
+                let s = text(0)?;
+                let parts: Vec<Value> = match args.get(1) {
+                    Some(_) => {
+                        let sep = text(1)?;
+                        if sep.is_empty() {
+                            return fail("empty separator".to_string());
+                        }
+                        s.split(&*sep).map(Value::str).collect()
+                    }
+                    None => s.split_whitespace().map(Value::str).collect(),
+                };
+                Value::list(parts)
+            }
+            "join" => {
+                let sep = match args.get(1) {
+                    Some(_) => text(1)?.to_string(),
+                    None => String::new(),
+                };
+                let parts: Vec<String> = list(0)?.borrow().iter().map(Value::to_string).collect();
+                Value::str(parts.join(&sep))
+            }
+            "trim" => Value::str(text(0)?.trim()),
+            "upper" => Value::str(text(0)?.to_uppercase()),
+            "lower" => Value::str(text(0)?.to_lowercase()),
+            "replace" => Value::str(text(0)?.replace(&*text(1)?, &text(2)?)),
+            "starts_with" => Value::Bool(text(0)?.starts_with(&*text(1)?)),
+            "ends_with" => Value::Bool(text(0)?.ends_with(&*text(1)?)),
+            "contains" => match arg(0) {
+                Value::Str(s) => Value::Bool(s.contains(&*text(1)?)),
+                Value::List(items) => Value::Bool(items.borrow().contains(&arg(1))),
+                Value::Map(entries) => Value::Bool(entries.borrow().contains_key(&*text(1)?)),
+                other => return fail(format!("cannot search {}", other.type_name())),
+            },
+            "slice" => {
+                let clamp = |v: f64, len: usize| -> usize {
+                    let i = if v < 0.0 { len as f64 + v } else { v };
+                    (i.max(0.0) as usize).min(len)
+                };
+                match arg(0) {
+                    Value::Str(s) => {
+                        let chars: Vec<char> = s.chars().collect();
+                        let start = clamp(number(1)?, chars.len());
+                        let end = match args.get(2) {
+                            Some(_) => clamp(number(2)?, chars.len()),
+                            None => chars.len(),
+                        };
+                        Value::str(chars[start..end.max(start)].iter().collect::<String>())
+                    }
+                    Value::List(items) => {
+                        let items = items.borrow();
+                        let start = clamp(number(1)?, items.len());
+                        let end = match args.get(2) {
+                            Some(_) => clamp(number(2)?, items.len()),
+                            None => items.len(),
+                        };
+                        Value::list(items[start..end.max(start)].to_vec())
+                    }
+                    other => return fail(format!("cannot slice {}", other.type_name())),
+                }
+            }
+            "sort" => {
+                let items = list(0)?;
+                let mut sorted = items.borrow().clone();
+                let mut error = None;
+                sorted.sort_by(|a, b| match (a, b) {
+                    (Value::Num(x), Value::Num(y)) => x.total_cmp(y),
+                    (Value::Str(x), Value::Str(y)) => x.cmp(y),
+                    _ => {
+                        error = Some(format!(
+                            "cannot compare {} and {}",
+                            a.type_name(),
+                            b.type_name()
+                        ));
+                        std::cmp::Ordering::Equal
+                    }
+                });
+                if let Some(e) = error {
+                    return fail(e);
+                }
+                *items.borrow_mut() = sorted;
+                Value::List(items)
+            }
+            "read" => {
+                let path = text(0)?;
+                match syscall::read_file(&path) {
+                    Ok(content) => Value::str(content),
+                    Err(e) => return fail(format!("{}: {}", path, e)),
+                }
+            }
+            "write" | "append" => {
+                let path = text(0)?;
+                let data = arg(1).to_string();
+                let flags = if name == "write" {
+                    syscall::OpenFlags::WRITE
+                } else {
+                    syscall::OpenFlags::APPEND
+                };
+                let result = syscall::open(&path, flags).and_then(|fd| {
+                    let written = syscall::write(fd, data.as_bytes());
+                    let _ = syscall::close(fd);
+                    written
+                });
+                if let Err(e) = result {
+                    return fail(format!("{}: {}", path, e));
+                }
+                Value::Nil
+            }
+            "exists" => Value::Bool(syscall::exists(&text(0)?).unwrap_or(false)),
+            "isdir" => Value::Bool(syscall::metadata(&text(0)?).is_ok_and(|m| m.is_dir)),
+            "ls" => {
+                let path = match args.first() {
+                    Some(_) => text(0)?.to_string(),
+                    None => ".".to_string(),
+                };
+                match syscall::readdir(&path) {
+                    Ok(mut names) => {
+                        names.sort();
+                        Value::list(names.into_iter().map(Value::str).collect())
+                    }
+                    Err(e) => return fail(format!("{}: {}", path, e)),
+                }
+            }
+            "mkdir" => {
+                let path = text(0)?;
+                if let Err(e) = syscall::mkdir(&path) {
+                    return fail(format!("{}: {}", path, e));
+                }
+                Value::Nil
+            }
+            "rm" => {
+                let path = text(0)?;
+                let result = if syscall::metadata(&path).is_ok_and(|m| m.is_dir) {
+                    syscall::remove_dir(&path)
+                } else {
+                    syscall::remove_file(&path)
+                };
+                if let Err(e) = result {
+                    return fail(format!("{}: {}", path, e));
+                }
+                Value::Nil
+            }
+            "env" => host
+                .get_env(&text(0)?)
+                .map(Value::str)
+                .unwrap_or(Value::Nil),
+            "setenv" => {
+                if let Err(e) = host.set_env(&text(0)?, &arg(1).to_string()) {
+                    return fail(e);
+                }
+                Value::Nil
+            }
+            "exec" => {
+                let result = host.exec(&text(0)?);
+                let mut map = BTreeMap::new();
+                map.insert("code".to_string(), Value::Num(result.code as f64));
+                map.insert("out".to_string(), Value::str(result.output));
+                map.insert("err".to_string(), Value::str(result.error));
+                Value::map(map)
+            }
+            _ => return fail("unknown builtin".to_string()),
+        })
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn binary(op: BinOp, a: &Value, b: &Value, line: usize) -> EvalResult {
+    use Value::{Num, Str};
+    let type_error = || {
+        Err(ScriptError::new(
+            line,
+            format!(
+                "cannot apply {:?} to {} and {}",
+                op,
+                a.type_name(),
+                b.type_name()
+            )
+            .to_lowercase(),
+        ))
+    };
+
+    Ok(match (op, a, b) {
+        (BinOp::Eq, _, _) => Value::Bool(a == b),
+        (BinOp::Ne, _, _) => Value::Bool(a != b),
+        (BinOp::Add, Num(x), Num(y)) => Num(x + y),
+        // `+` with a string on either side concatenates
+        (BinOp::Add, Str(_), _) | (BinOp::Add, _, Str(_)) => Value::str(format!("{}{}", a, b)),
+        (BinOp::Add, Value::List(x), Value::List(y)) => {
+            let mut items = x.borrow().clone();
+            items.extend(y.borrow().iter().cloned());
+            Value::list(items)
+        }
+        (BinOp::Sub, Num(x), Num(y)) => Num(x - y),
+        (BinOp::Mul, Num(x), Num(y)) => Num(x * y),
+        (BinOp::Mul, Str(s), Num(n)) => Value::str(s.repeat(n.max(0.0) as usize)),
+        (BinOp::Div, Num(_), Num(y)) if *y == 0.0 => {
+            return Err(ScriptError::new(line, "division by zero"));
+        }
+        (BinOp::Div, Num(x), Num(y)) => Num(x / y),
+        (BinOp::Rem, Num(_), Num(y)) if *y == 0.0 => {
+            return Err(ScriptError::new(line, "division by zero"));
+        }
+        (BinOp::Rem, Num(x), Num(y)) => Num(x % y),
+        (BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge, _, _) => {
+            let ord = match (a, b) {
+                (Num(x), Num(y)) => x.partial_cmp(y),
+                (Str(x), Str(y)) => Some(x.cmp(y)),
+                _ => return type_error(),
+            };
+            let Some(ord) = ord else {
+                return Ok(Value::Bool(false));
+            };
+            Value::Bool(match op {
+                BinOp::Lt => ord.is_lt(),
+                BinOp::Le => ord.is_le(),
+                BinOp::Gt => ord.is_gt(),
+                _ => ord.is_ge(),
+            })
+        }
+        _ => return type_error(),
+    })
+}
+
+/// Resolve a (possibly negative) list index
+fn list_index(index: &Value, len: usize, line: usize) -> Result<usize, ScriptError> {
+    let Value::Num(n) = index else {
+        return Err(ScriptError::new(line, "list index must be a number"));
+    };
+    let i = if *n < 0.0 { len as f64 + n } else { *n };
+    if i < 0.0 || i >= len as f64 || i.fract() != 0.0 {
+        return Err(ScriptError::new(
+            line,
+            format!("index {} out of range for length {}", format_num(*n), len),
+        ));
+    }
+    Ok(i as usize)
+}
+
+fn index_value(container: &Value, index: &Value, line: usize) -> EvalResult {
+    match (container, index) {
+        (Value::List(items), _) => {
+            let items = items.borrow();
+            let i = list_index(index, items.len(), line)?;
+            Ok(items[i].clone())
+        }
+        (Value::Str(s), _) => {
+            let chars: Vec<char> = s.chars().collect();
+            let i = list_index(index, chars.len(), line)?;
+            Ok(Value::str(chars[i].to_string()))
+        }
+        // Missing keys read as nil, like Lua
+        (Value::Map(entries), Value::Str(key)) => {
+            Ok(entries.borrow().get(&**key).cloned().unwrap_or(Value::Nil))
+        }
+        _ => Err(ScriptError::new(
+            line,
+            format!(
+                "cannot index {} with {}",
+                container.type_name(),
+                index.type_name()
+            ),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::syscall::{KERNEL, Kernel};
+    use crate::shell::ExecResult;
+
+    /// Host that records commands and keeps its own environment
+    #[derive(Default)]
+    struct TestHost {
+        env: HashMap<String, String>,
+        commands: Vec<String>,
+    }
+
+    impl ScriptHost for TestHost {
+        fn exec(&mut self, line: &str) -> ExecResult {
+            self.commands.push(line.to_string());
+            ExecResult::success().with_output(format!("ran {}\n", line))
+        }
+
+        fn get_env(&self, name: &str) -> Option<String> {
+            self.env.get(name).cloned()
+        }
+
+        fn set_env(&mut self, name: &str, value: &str) -> Result<(), String> {
+            self.env.insert(name.to_string(), value.to_string());
+            Ok(())
+        }
+    }
+
+    fn run(src: &str) -> (Result<Value, ScriptError>, String) {
+        let mut interp = Interpreter::new();
+        let result = interp.run(src, &mut TestHost::default());
+        (result, interp.take_stdout())
+    }
+
+    fn output(src: &str) -> String {
+        let (result, out) = run(src);
+        if let Err(e) = result {
+            panic!("script failed: {}", e);
+        }
+        out
+    }
+
+    #[test]
+    fn test_arithmetic_and_strings() {
+        assert_eq!(output("print(1 + 2 * 3, 7 % 4, 10 / 4)"), "7 3 2.5\n");
+        assert_eq!(output("print(\"n=\" + 5, \"ab\" * 3)"), "n=5 ababab\n");
+        assert_eq!(
+            output("print(-2 < 1, \"a\" < \"b\", 1 == 1.0)"),
+            "true true true\n"
+        );
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let src = r#"
+            let total = 0
+            for i in range(10) {
+                if i == 3 { continue }
+                if i > 6 { break }
+                total += i
+            }
+            let n = 0
+            while n < 5 { n = n + 1 }
+            print(total, n)
+        "#;
+        assert_eq!(output(src), "18 5\n");
+    }
+
+    #[test]
+    fn test_functions_and_closures() {
+        let src = r#"
+            fn fib(n) {
+                if n < 2 { return n }
+                return fib(n - 1) + fib(n - 2)
+            }
+            fn counter() {
+                let count = 0
+                return fn() { count += 1; return count }
+            }
+            let next = counter()
+            next(); next()
+            print(fib(15), next())
+        "#;
+        assert_eq!(output(src), "610 3\n");
+    }
+
+    #[test]
+    fn test_lists_and_maps() {
+        let src = r#"
+            let xs = [3, 1, 2]
+            push(xs, 0)
+            sort(xs)
+            let m = {name: "axe", "n": len(xs)}
+            m.extra = xs[-1]
+            m["name"] = upper(m.name)
+            print(xs, m, m.missing)
+            print(join(split("a b  c"), ","), slice("hello", 1, -1))
+        "#;
+        assert_eq!(
+            output(src),
+            "[0, 1, 2, 3] {\"extra\": 3, \"n\": 4, \"name\": \"AXE\"} nil\na,b,c ell\n"
+        );
+    }
+
+    #[test]
+    fn test_run_returns_last_expression() {
+        let mut interp = Interpreter::new();
+        let mut host = TestHost::default();
+        assert_eq!(interp.run("let x = 2", &mut host).unwrap(), Value::Nil);
+        // Globals persist between runs
+        assert_eq!(interp.run("x * 21", &mut host).unwrap(), Value::Num(42.0));
+    }
+
+    #[test]
+    fn test_runtime_errors_have_lines() {
+        let (result, _) = run("let a = 1\nprint(b)");
+        let err = result.unwrap_err();
+        assert_eq!(err.line, 2);
+        assert!(err.message.contains("undefined variable 'b'"));
+
+        let (result, _) = run("x = 1");
+        assert!(result.unwrap_err().message.contains("use let"));
+
+        let (result, _) = run("print([1][5])");
+        assert!(result.unwrap_err().message.contains("out of range"));
+    }
+
+    #[test]
+    fn test_step_and_depth_limits() {
+        let mut interp = Interpreter::new();
+        interp.set_step_limit(1000);
+        let err = interp
+            .run("while true { }", &mut TestHost::default())
+            .unwrap_err();
+        assert!(err.message.contains("step limit"));
+
+        let (result, _) = run("fn f() { return f() }\nf()");
+        assert!(result.unwrap_err().message.contains("call depth"));
+    }
+
+    #[test]
+    fn test_host_bindings() {
+        let mut interp = Interpreter::new();
+        let mut host = TestHost::default();
+        interp.set_args(&["a".to_string(), "b".to_string()]);
+        let src = r#"
+            setenv("GREETING", "hi " + args[1])
+            let r = exec("ls /")
+            print(env("GREETING"), env("MISSING"), r.code, trim(r.out))
+        "#;
+        interp.run(src, &mut host).unwrap();
+        assert_eq!(interp.take_stdout(), "hi b nil 0 ran ls /\n");
+        assert_eq!(host.commands, ["ls /"]);
+    }
+
+    #[test]
+    fn test_filesystem_bindings() {
+        KERNEL.with(|k| {
+            *k.borrow_mut() = Kernel::new();
+            let pid = k.borrow_mut().spawn_process("test", None);
+            k.borrow_mut().set_current(pid);
+        });
+
+        let src = r#"
+            mkdir("/tmp/s")
+            write("/tmp/s/a.txt", "one\n")
+            append("/tmp/s/a.txt", "two\n")
+            print(read("/tmp/s/a.txt") == "one\ntwo\n", ls("/tmp/s"), isdir("/tmp/s"))
+            rm("/tmp/s/a.txt")
+            print(exists("/tmp/s/a.txt"))
+        "#;
+        assert_eq!(output(src), "true [\"a.txt\"] true\nfalse\n");
+
+        let (result, _) = run("read(\"/nope\")");
+        assert!(result.unwrap_err().message.starts_with("read: /nope"));
+    }
+}
@@ -0,0 +1,275 @@
+//! Tokenizer for the script language
+
+use super::ScriptError;
+
+/// A token and the line it starts on
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: Tok,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tok {
+    Num(f64),
+    Str(String),
+    Ident(String),
+    // Keywords
+    Let,
+    Fn,
+    If,
+    Else,
+    While,
+    For,
+    In,
+    Return,
+    Break,
+    Continue,
+    True,
+    False,
+    Nil,
+    // Punctuation
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    LBracket,
+    RBracket,
+    Comma,
+    Dot,
+    Colon,
+    Semi,
+    Assign,
+    PlusAssign,
+    MinusAssign,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    Not,
+    And,
+    Or,
+    Eof,
+}
+
+fn keyword(word: &str) -> Option<Tok> {
+    Some(match word {
+        "let" => Tok::Let,
+        "fn" => Tok::Fn,
+        "if" => Tok::If,
+        "else" => Tok::Else,
+        "while" => Tok::While,
+        "for" => Tok::For,
+        "in" => Tok::In,
+        "return" => Tok::Return,
+        "break" => Tok::Break,
+        "continue" => Tok::Continue,
+        "true" => Tok::True,
+        "false" => Tok::False,
+        "nil" => Tok::Nil,
+        _ => return None,
+    })
+}
+
+/// Split source into tokens, ending with `Tok::Eof`
+///
+/// Comments run from `#` or `//` to the end of the line, so scripts can
+/// start with a `#!` line.
+pub fn tokenize(src: &str) -> Result<Vec<Token>, ScriptError> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+
+        if c == '\n' {
+            line += 1;
+            i += 1;
+            continue;
+        }
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c == '#' || (c == '/' && next == Some('/')) {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            continue;
+        }
+
+        let start_line = line;
+        let kind = if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '_') {
+                i += 1;
+            }
+            if i + 1 < chars.len() && chars[i] == '.' && chars[i + 1].is_ascii_digit() {
+                i += 1;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+            }
+            let text: String = chars[start..i].iter().filter(|&&c| c != '_').collect();
+            match text.parse::<f64>() {
+                Ok(n) => Tok::Num(n),
+                Err(_) => return Err(ScriptError::new(line, format!("bad number '{}'", text))),
+            }
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            keyword(&word).unwrap_or(Tok::Ident(word))
+        } else if c == '"' || c == '\'' {
+            i += 1;
+            let mut s = String::new();
+            loop {
+                let Some(&ch) = chars.get(i) else {
+                    return Err(ScriptError::incomplete(start_line, "unterminated string"));
+                };
+                i += 1;
+                match ch {
+                    _ if ch == c => break,
+                    '\\' => {
+                        let esc = chars.get(i).copied();
+                        i += 1;
+                        match esc {
+                            Some('n') => s.push('\n'),
+                            Some('t') => s.push('\t'),
+                            Some('r') => s.push('\r'),
+                            Some('0') => s.push('\0'),
+                            Some(e @ ('\\' | '"' | '\'')) => s.push(e),
+                            Some(e) => {
+                                return Err(ScriptError::new(
+                                    line,
+                                    format!("unknown escape '\\{}'", e),
+                                ));
+                            }
+                            None => {
+                                return Err(ScriptError::incomplete(line, "unterminated string"));
+                            }
+                        }
+                    }
+                    '\n' => {
+                        line += 1;
+                        s.push('\n');
+                    }
+                    _ => s.push(ch),
+                }
+            }
+            Tok::Str(s)
+        } else {
+            let two = |t: Tok| (t, 2);
+            let (tok, len) = match (c, next) {
+                ('=', Some('=')) => two(Tok::Eq),
+                ('!', Some('=')) => two(Tok::Ne),
+                ('<', Some('=')) => two(Tok::Le),
+                ('>', Some('=')) => two(Tok::Ge),
+                ('+', Some('=')) => two(Tok::PlusAssign),
+                ('-', Some('=')) => two(Tok::MinusAssign),
+                ('&', Some('&')) => two(Tok::And),
+                ('|', Some('|')) => two(Tok::Or),
+                ('(', _) => (Tok::LParen, 1),
+                (')', _) => (Tok::RParen, 1),
+                ('{', _) => (Tok::LBrace, 1),
+                ('}', _) => (Tok::RBrace, 1),
+                ('[', _) => (Tok::LBracket, 1),
+                (']', _) => (Tok::RBracket, 1),
+                (',', _) => (Tok::Comma, 1),
+                ('.', _) => (Tok::Dot, 1),
+                (':', _) => (Tok::Colon, 1),
+                (';', _) => (Tok::Semi, 1),
+                ('=', _) => (Tok::Assign, 1),
+                ('<', _) => (Tok::Lt, 1),
+                ('>', _) => (Tok::Gt, 1),
+                ('+', _) => (Tok::Plus, 1),
+                ('-', _) => (Tok::Minus, 1),
+                ('*', _) => (Tok::Star, 1),
+                ('/', _) => (Tok::Slash, 1),
+                ('%', _) => (Tok::Percent, 1),
+                ('!', _) => (Tok::Not, 1),
+                _ => {
+                    return Err(ScriptError::new(
+                        line,
+                        format!("unexpected character '{}'", c),
+                    ));
+                }
+            };
+            i += len;
+            tok
+        };
+
+        tokens.push(Token {
+            kind,
+            line: start_line,
+        });
+    }
+
+    tokens.push(Token {
+        kind: Tok::Eof,
+        line,
+    });
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(src: &str) -> Vec<Tok> {
+        tokenize(src).unwrap().into_iter().map(|t| t.kind).collect()
+    }
+
+    #[test]
+    fn test_tokenize_basics() {
+        assert_eq!(
+            kinds("let x = 1_000.5 // note"),
+            [
+                Tok::Let,
+                Tok::Ident("x".into()),
+                Tok::Assign,
+                Tok::Num(1000.5),
+                Tok::Eof
+            ]
+        );
+        assert_eq!(
+            kinds("a<=b && !c"),
+            [
+                Tok::Ident("a".into()),
+                Tok::Le,
+                Tok::Ident("b".into()),
+                Tok::And,
+                Tok::Not,
+                Tok::Ident("c".into()),
+                Tok::Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_strings_and_lines() {
+        let toks = tokenize("#!/bin/script\n'it\\'s'\n\"a\\tb\"").unwrap();
+        assert_eq!(toks[0].kind, Tok::Str("it's".into()));
+        assert_eq!(toks[0].line, 2);
+        assert_eq!(toks[1].kind, Tok::Str("a\tb".into()));
+        assert_eq!(toks[1].line, 3);
+    }
+
+    #[test]
+    fn test_unterminated_string_is_incomplete() {
+        let err = tokenize("print(\"abc").unwrap_err();
+        assert!(err.incomplete);
+    }
+}
@@ -0,0 +1,164 @@
+//! Script - embedded scripting language
+//!
+//! A small dynamically typed language for users who want more structure
+//! than shell scripts but don't want to compile a WASM command. Scripts run
+//! with `script run FILE`, inline with `script -e CODE`, or interactively
+//! in the REPL started by a bare `script`.
+//!
+//! ```text
+//! # count lines in every .txt file
+//! fn count(path) { return len(split(read(path), "\n")) - 1 }
+//!
+//! for name in ls(".") {
+//!     if ends_with(name, ".txt") { print(name, count(name)) }
+//! }
+//! let r = exec("whoami")
+//! print("user:", trim(r.out), "home:", env("HOME"))
+//! ```
+//!
+//! Values are nil, booleans, numbers (f64), strings, lists and maps. Lists
+//! and maps are shared by reference. Builtins cover strings and lists, the
+//! filesystem (`read`, `write`, `append`, `ls`, `mkdir`, `rm`, ...), the
+//! environment (`env`, `setenv`) and running shell commands (`exec`).
+
+mod interp;
+mod lexer;
+mod parser;
+mod repl;
+mod value;
+
+pub use interp::{DEFAULT_STEP_LIMIT, Interpreter, MAX_CALL_DEPTH};
+pub use repl::{REPL_BANNER, Repl};
+pub use value::Value;
+
+use super::ExecResult;
+use super::argparse::{Arg, ArgKind, CommandSpec, Flag};
+use super::programs::read_file_content;
+use std::fmt;
+
+/// Name the shell runs the interpreter under
+pub const SCRIPT_COMMAND: &str = "script";
+
+pub static SCRIPT: CommandSpec = CommandSpec {
+    name: SCRIPT_COMMAND,
+    summary: "run a program in the embedded script language",
+    description: "Run a program written in the embedded script language, from FILE (script run FILE is the same) or from CODE. With no arguments, starts an interactive REPL (type .exit to leave). When input is piped in, or FILE is '-', the program is read from stdin. ARGs are available to the program as the list `args`.",
+    flags: &[Flag::short('e', "Run CODE instead of reading a file").value("CODE")],
+    args: &[
+        Arg::new("FILE", ArgKind::Path).optional(),
+        Arg::new("ARG", ArgKind::Text).optional().repeated().raw(),
+    ],
+};
+
+/// Everything a script can reach outside the kernel syscalls
+///
+/// The shell implements this so `exec`, `env` and `setenv` act on the
+/// running shell's state.
+pub trait ScriptHost {
+    /// Run a shell command line
+    fn exec(&mut self, line: &str) -> ExecResult;
+    /// Read an environment variable
+    fn get_env(&self, name: &str) -> Option<String>;
+    /// Set an environment variable, unless the shell refuses to (a
+    /// restricted shell's `PATH`)
+    fn set_env(&mut self, name: &str, value: &str) -> Result<(), String>;
+}
+
+/// Run the `script` command
+///
+/// `stdin` is `None` when nothing was piped or redirected in. Returns `None`
+/// when the shell should enter the REPL.
+pub fn run_command(
+    args: &[String],
+    stdin: Option<&str>,
+    host: &mut dyn ScriptHost,
+    stdout: &mut String,
+    stderr: &mut String,
+) -> Option<i32> {
+    let mut rest = args;
+    if rest.first().is_some_and(|a| a == "run") {
+        rest = &rest[1..];
+    }
+
+    let m = match SCRIPT.parse(rest) {
+        Ok(m) => m,
+        Err(e) => {
+            return Some(match e.report(&SCRIPT, stdout, stderr) {
+                0 => 0,
+                _ => 2,
+            });
+        }
+    };
+
+    let (source, name, script_args) = match (m.value("e"), m.operand(0)) {
+        (Some(code), _) => (code.to_string(), "-e".to_string(), &m.operands[..]),
+        (None, Some("-")) => (
+            stdin.unwrap_or("").to_string(),
+            "<stdin>".to_string(),
+            &m.operands[1..],
+        ),
+        (None, Some(path)) => match read_file_content(path) {
+            Ok(content) => (content, path.to_string(), &m.operands[1..]),
+            Err(e) => {
+                stderr.push_str(&format!("script: {}: {}\n", path, e));
+                return Some(1);
+            }
+        },
+        (None, None) if args.is_empty() => match stdin {
+            Some(input) => (input.to_string(), "<stdin>".to_string(), &m.operands[..]),
+            None => return None,
+        },
+        (None, None) => {
+            stderr.push_str("script: run requires a file\n");
+            return Some(2);
+        }
+    };
+
+    let mut interp = Interpreter::new();
+    interp.set_args(script_args);
+    let result = interp.run(&source, host);
+    stdout.push_str(&interp.take_stdout());
+    stderr.push_str(&interp.take_stderr());
+
+    match result {
+        Ok(_) => Some(0),
+        Err(e) => {
+            stderr.push_str(&format!("script: {}: {}\n", name, e));
+            Some(1)
+        }
+    }
+}
+
+/// A parse or runtime error
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptError {
+    pub line: usize,
+    pub message: String,
+    /// The source ended early; the REPL reads another line instead of failing
+    pub incomplete: bool,
+}
+
+impl ScriptError {
+    pub fn new(line: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            message: message.into(),
+            incomplete: false,
+        }
+    }
+
+    pub fn incomplete(line: usize, message: impl Into<String>) -> Self {
+        Self {
+            incomplete: true,
+            ..Self::new(line, message)
+        }
+    }
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ScriptError {}
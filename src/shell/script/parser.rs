@@ -0,0 +1,483 @@
+//! Parser producing the script AST
+//!
+//! Statements may be separated by newlines or `;`. A `(` or `[` that starts
+//! a new line begins a new expression rather than calling or indexing the
+//! previous one.
+
+use super::ScriptError;
+use super::lexer::{Tok, Token, tokenize};
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnOp {
+    Neg,
+    Not,
+}
+
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Nil,
+    Bool(bool),
+    Num(f64),
+    Str(String),
+    Var(String),
+    List(Vec<Expr>),
+    Map(Vec<(Expr, Expr)>),
+    Unary(UnOp, Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Call(Box<Expr>, Vec<Expr>),
+    Index(Box<Expr>, Box<Expr>),
+    Func(Rc<FuncDef>),
+}
+
+/// A function literal or declaration
+#[derive(Debug)]
+pub struct FuncDef {
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: Vec<Stmt>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone)]
+pub enum StmtKind {
+    Let(String, Expr),
+    /// `target op= value`; `op` is `None` for plain `=`
+    Assign(Expr, Option<BinOp>, Expr),
+    Expr(Expr),
+    If(Vec<(Expr, Vec<Stmt>)>, Option<Vec<Stmt>>),
+    While(Expr, Vec<Stmt>),
+    For(String, Expr, Vec<Stmt>),
+    Return(Option<Expr>),
+    Break,
+    Continue,
+}
+
+/// Parse a whole program
+pub fn parse(src: &str) -> Result<Vec<Stmt>, ScriptError> {
+    let tokens = tokenize(src)?;
+    let mut p = Parser { tokens, pos: 0 };
+    let mut stmts = Vec::new();
+    while !p.at(&Tok::Eof) {
+        stmts.push(p.statement()?);
+    }
+    Ok(stmts)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Tok {
+        &self.tokens[self.pos.min(self.tokens.len() - 1)].kind
+    }
+
+    fn line(&self) -> usize {
+        self.tokens[self.pos.min(self.tokens.len() - 1)].line
+    }
+
+    fn prev_line(&self) -> usize {
+        self.tokens[self.pos.saturating_sub(1)].line
+    }
+
+    fn at(&self, tok: &Tok) -> bool {
+        self.peek() == tok
+    }
+
+    fn advance(&mut self) -> Tok {
+        let tok = self.peek().clone();
+        if self.pos < self.tokens.len() - 1 {
+            self.pos += 1;
+        }
+        tok
+    }
+
+    fn eat(&mut self, tok: &Tok) -> bool {
+        if self.at(tok) {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Error for an unexpected token; hitting end of input is "incomplete"
+    /// so the REPL can ask for another line
+    fn unexpected(&self, expected: &str) -> ScriptError {
+        if self.at(&Tok::Eof) {
+            ScriptError::incomplete(self.line(), format!("expected {}", expected))
+        } else {
+            ScriptError::new(
+                self.line(),
+                format!("expected {}, found {}", expected, describe(self.peek())),
+            )
+        }
+    }
+
+    fn expect(&mut self, tok: Tok, what: &str) -> Result<(), ScriptError> {
+        if self.eat(&tok) {
+            Ok(())
+        } else {
+            Err(self.unexpected(what))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, ScriptError> {
+        match self.peek().clone() {
+            Tok::Ident(name) => {
+                self.advance();
+                Ok(name)
+            }
+            _ => Err(self.unexpected("a name")),
+        }
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, ScriptError> {
+        self.expect(Tok::LBrace, "'{'")?;
+        let mut stmts = Vec::new();
+        while !self.eat(&Tok::RBrace) {
+            if self.at(&Tok::Eof) {
+                return Err(self.unexpected("'}'"));
+            }
+            stmts.push(self.statement()?);
+        }
+        Ok(stmts)
+    }
+
+    fn statement(&mut self) -> Result<Stmt, ScriptError> {
+        let line = self.line();
+        let kind = match self.peek() {
+            Tok::Let => {
+                self.advance();
+                let name = self.ident()?;
+                self.expect(Tok::Assign, "'='")?;
+                StmtKind::Let(name, self.expression()?)
+            }
+            Tok::Fn
+                if matches!(
+                    self.tokens.get(self.pos + 1).map(|t| &t.kind),
+                    Some(Tok::Ident(_))
+                ) =>
+            {
+                self.advance();
+                let name = self.ident()?;
+                let def = self.function_rest(name.clone())?;
+                StmtKind::Let(name, Expr::Func(def))
+            }
+            Tok::If => self.if_statement()?,
+            Tok::While => {
+                self.advance();
+                let cond = self.expression()?;
+                StmtKind::While(cond, self.block()?)
+            }
+            Tok::For => {
+                self.advance();
+                let name = self.ident()?;
+                self.expect(Tok::In, "'in'")?;
+                let iter = self.expression()?;
+                StmtKind::For(name, iter, self.block()?)
+            }
+            Tok::Return => {
+                self.advance();
+                let ends = matches!(self.peek(), Tok::Semi | Tok::RBrace | Tok::Eof)
+                    || self.line() != line;
+                StmtKind::Return(if ends { None } else { Some(self.expression()?) })
+            }
+            Tok::Break => {
+                self.advance();
+                StmtKind::Break
+            }
+            Tok::Continue => {
+                self.advance();
+                StmtKind::Continue
+            }
+            _ => {
+                let expr = self.expression()?;
+                let op = match self.peek() {
+                    Tok::Assign => Some(None),
+                    Tok::PlusAssign => Some(Some(BinOp::Add)),
+                    Tok::MinusAssign => Some(Some(BinOp::Sub)),
+                    _ => None,
+                };
+                match op {
+                    Some(op) => {
+                        if !matches!(expr, Expr::Var(_) | Expr::Index(..)) {
+                            return Err(ScriptError::new(line, "cannot assign to this expression"));
+                        }
+                        self.advance();
+                        StmtKind::Assign(expr, op, self.expression()?)
+                    }
+                    None => StmtKind::Expr(expr),
+                }
+            }
+        };
+        self.eat(&Tok::Semi);
+        Ok(Stmt { kind, line })
+    }
+
+    fn if_statement(&mut self) -> Result<StmtKind, ScriptError> {
+        let mut branches = Vec::new();
+        let mut otherwise = None;
+        self.expect(Tok::If, "'if'")?;
+        loop {
+            let cond = self.expression()?;
+            branches.push((cond, self.block()?));
+            if !self.eat(&Tok::Else) {
+                break;
+            }
+            if !self.eat(&Tok::If) {
+                otherwise = Some(self.block()?);
+                break;
+            }
+        }
+        Ok(StmtKind::If(branches, otherwise))
+    }
+
+    /// Parameters and body after `fn name`
+    fn function_rest(&mut self, name: String) -> Result<Rc<FuncDef>, ScriptError> {
+        self.expect(Tok::LParen, "'('")?;
+        let mut params = Vec::new();
+        while !self.eat(&Tok::RParen) {
+            params.push(self.ident()?);
+            if !self.eat(&Tok::Comma) {
+                self.expect(Tok::RParen, "')'")?;
+                break;
+            }
+        }
+        let body = self.block()?;
+        Ok(Rc::new(FuncDef { name, params, body }))
+    }
+
+    fn expression(&mut self) -> Result<Expr, ScriptError> {
+        self.or()
+    }
+
+    fn or(&mut self) -> Result<Expr, ScriptError> {
+        let mut lhs = self.and()?;
+        while self.eat(&Tok::Or) {
+            lhs = Expr::Or(Box::new(lhs), Box::new(self.and()?));
+        }
+        Ok(lhs)
+    }
+
+    fn and(&mut self) -> Result<Expr, ScriptError> {
+        let mut lhs = self.comparison()?;
+        while self.eat(&Tok::And) {
+            lhs = Expr::And(Box::new(lhs), Box::new(self.comparison()?));
+        }
+        Ok(lhs)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, ScriptError> {
+        let mut lhs = self.additive()?;
+        loop {
+            let op = match self.peek() {
+                Tok::Eq => BinOp::Eq,
+                Tok::Ne => BinOp::Ne,
+                Tok::Lt => BinOp::Lt,
+                Tok::Le => BinOp::Le,
+                Tok::Gt => BinOp::Gt,
+                Tok::Ge => BinOp::Ge,
+                _ => return Ok(lhs),
+            };
+            self.advance();
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.additive()?));
+        }
+    }
+
+    fn additive(&mut self) -> Result<Expr, ScriptError> {
+        let mut lhs = self.multiplicative()?;
+        loop {
+            let op = match self.peek() {
+                Tok::Plus => BinOp::Add,
+                Tok::Minus => BinOp::Sub,
+                _ => return Ok(lhs),
+            };
+            self.advance();
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.multiplicative()?));
+        }
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr, ScriptError> {
+        let mut lhs = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Tok::Star => BinOp::Mul,
+                Tok::Slash => BinOp::Div,
+                Tok::Percent => BinOp::Rem,
+                _ => return Ok(lhs),
+            };
+            self.advance();
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, ScriptError> {
+        if self.eat(&Tok::Minus) {
+            return Ok(Expr::Unary(UnOp::Neg, Box::new(self.unary()?)));
+        }
+        if self.eat(&Tok::Not) {
+            return Ok(Expr::Unary(UnOp::Not, Box::new(self.unary()?)));
+        }
+        self.postfix()
+    }
+
+    fn postfix(&mut self) -> Result<Expr, ScriptError> {
+        let mut expr = self.primary()?;
+        loop {
+            // Calls and indexing must stay on the line of the expression
+            let same_line = self.line() == self.prev_line();
+            if same_line && self.eat(&Tok::LParen) {
+                let args = self.list_items(Tok::RParen, "')'")?;
+                expr = Expr::Call(Box::new(expr), args);
+            } else if same_line && self.eat(&Tok::LBracket) {
+                let index = self.expression()?;
+                self.expect(Tok::RBracket, "']'")?;
+                expr = Expr::Index(Box::new(expr), Box::new(index));
+            } else if self.eat(&Tok::Dot) {
+                let field = self.ident()?;
+                expr = Expr::Index(Box::new(expr), Box::new(Expr::Str(field)));
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    /// Comma-separated expressions up to `close` (trailing comma allowed)
+    fn list_items(&mut self, close: Tok, what: &str) -> Result<Vec<Expr>, ScriptError> {
+        let mut items = Vec::new();
+        while !self.eat(&close) {
+            items.push(self.expression()?);
+            if !self.eat(&Tok::Comma) {
+                self.expect(close, what)?;
+                break;
+            }
+        }
+        Ok(items)
+    }
+
+    fn primary(&mut self) -> Result<Expr, ScriptError> {
+        let expr = match self.peek().clone() {
+            Tok::Num(n) => Expr::Num(n),
+            Tok::Str(s) => Expr::Str(s),
+            Tok::Ident(name) => Expr::Var(name),
+            Tok::True => Expr::Bool(true),
+            Tok::False => Expr::Bool(false),
+            Tok::Nil => Expr::Nil,
+            Tok::LParen => {
+                self.advance();
+                let inner = self.expression()?;
+                self.expect(Tok::RParen, "')'")?;
+                return Ok(inner);
+            }
+            Tok::LBracket => {
+                self.advance();
+                return Ok(Expr::List(self.list_items(Tok::RBracket, "']'")?));
+            }
+            Tok::LBrace => {
+                self.advance();
+                return self.map_literal();
+            }
+            Tok::Fn => {
+                self.advance();
+                return Ok(Expr::Func(self.function_rest("<fn>".to_string())?));
+            }
+            _ => return Err(self.unexpected("an expression")),
+        };
+        self.advance();
+        Ok(expr)
+    }
+
+    /// `{key: value, "other key": value}` (opening brace consumed)
+    fn map_literal(&mut self) -> Result<Expr, ScriptError> {
+        let mut entries = Vec::new();
+        while !self.eat(&Tok::RBrace) {
+            let key = match self.peek().clone() {
+                Tok::Ident(name) => {
+                    self.advance();
+                    Expr::Str(name)
+                }
+                _ => self.expression()?,
+            };
+            self.expect(Tok::Colon, "':'")?;
+            entries.push((key, self.expression()?));
+            if !self.eat(&Tok::Comma) {
+                self.expect(Tok::RBrace, "'}'")?;
+                break;
+            }
+        }
+        Ok(Expr::Map(entries))
+    }
+}
+
+fn describe(tok: &Tok) -> String {
+    match tok {
+        Tok::Num(n) => format!("number {}", n),
+        Tok::Str(_) => "string".to_string(),
+        Tok::Ident(name) => format!("'{}'", name),
+        Tok::Eof => "end of input".to_string(),
+        other => format!("{:?}", other).to_lowercase(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_precedence() {
+        let stmts = parse("1 + 2 * 3 == 7 && !false").unwrap();
+        let StmtKind::Expr(Expr::And(lhs, _)) = &stmts[0].kind else {
+            panic!("expected &&");
+        };
+        let Expr::Binary(BinOp::Eq, sum, _) = lhs.as_ref() else {
+            panic!("expected ==");
+        };
+        assert!(matches!(sum.as_ref(), Expr::Binary(BinOp::Add, _, _)));
+    }
+
+    #[test]
+    fn test_newline_separates_call() {
+        // `(b)` on its own line is a new statement, not a call of `a`
+        let stmts = parse("let a = 1\n(2)").unwrap();
+        assert_eq!(stmts.len(), 2);
+        assert_eq!(stmts[1].line, 2);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = parse("if x {").unwrap_err();
+        assert!(err.incomplete);
+
+        let err = parse("let = 3").unwrap_err();
+        assert!(!err.incomplete);
+        assert!(err.message.contains("expected a name"));
+
+        let err = parse("1 + 2 = 3").unwrap_err();
+        assert!(err.message.contains("cannot assign"));
+    }
+}
@@ -0,0 +1,86 @@
+//! Interactive read-eval-print loop
+
+use super::{Interpreter, ScriptHost, Value};
+use crate::shell::ExecResult;
+
+const PROMPT: &str = "script> ";
+const CONTINUATION_PROMPT: &str = "... ";
+
+pub const REPL_BANNER: &str = "script REPL - type .exit to return to the shell\n";
+
+/// REPL state kept by the shell between input lines
+///
+/// Input that ends mid-statement (an open brace, an unterminated string) is
+/// buffered until the statement is complete.
+pub struct Repl {
+    interp: Interpreter,
+    pending: String,
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        Self {
+            interp: Interpreter::new(),
+            pending: String::new(),
+        }
+    }
+
+    /// Prompt for the next line
+    pub fn prompt(&self) -> &'static str {
+        if self.pending.is_empty() {
+            PROMPT
+        } else {
+            CONTINUATION_PROMPT
+        }
+    }
+
+    /// Handle one line of input
+    ///
+    /// Returns `None` once the user leaves the REPL.
+    pub fn feed(&mut self, line: &str, host: &mut dyn ScriptHost) -> Option<ExecResult> {
+        if self.pending.is_empty() {
+            match line.trim() {
+                ".exit" | "exit" | "quit" => return None,
+                "" => return Some(ExecResult::success()),
+                _ => {}
+            }
+        }
+
+        self.pending.push_str(line);
+        self.pending.push('\n');
+
+        let result = self.interp.run(&self.pending, host);
+        if matches!(&result, Err(e) if e.incomplete) {
+            return Some(ExecResult::success());
+        }
+        self.pending.clear();
+
+        let mut output = self.interp.take_stdout();
+        let mut error = self.interp.take_stderr();
+        let code = match result {
+            Ok(Value::Nil) => 0,
+            Ok(value) => {
+                output.push_str(&value.repr());
+                output.push('\n');
+                0
+            }
+            Err(e) => {
+                error.push_str(&format!("error: {}\n", e));
+                1
+            }
+        };
+
+        Some(ExecResult {
+            code,
+            output,
+            error,
+            should_exit: false,
+        })
+    }
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,153 @@
+//! Runtime values
+
+use super::interp::Scope;
+use super::parser::FuncDef;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// A script value
+///
+/// Lists and maps are shared references, as in Lua and JavaScript:
+/// assigning one to another variable does not copy it.
+#[derive(Clone)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Num(f64),
+    Str(Rc<str>),
+    List(Rc<RefCell<Vec<Value>>>),
+    Map(Rc<RefCell<BTreeMap<String, Value>>>),
+    Func(Rc<Closure>),
+    Native(&'static str),
+}
+
+/// A user function together with the scope it was defined in
+pub struct Closure {
+    pub def: Rc<FuncDef>,
+    pub scope: Rc<RefCell<Scope>>,
+}
+
+impl Value {
+    pub fn str(s: impl Into<Rc<str>>) -> Value {
+        Value::Str(s.into())
+    }
+
+    pub fn list(items: Vec<Value>) -> Value {
+        Value::List(Rc::new(RefCell::new(items)))
+    }
+
+    pub fn map(entries: BTreeMap<String, Value>) -> Value {
+        Value::Map(Rc::new(RefCell::new(entries)))
+    }
+
+    /// Only `nil` and `false` are false
+    pub fn truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Bool(false))
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Nil => "nil",
+            Value::Bool(_) => "bool",
+            Value::Num(_) => "number",
+            Value::Str(_) => "string",
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
+            Value::Func(_) | Value::Native(_) => "function",
+        }
+    }
+
+    /// Source-like representation, used by the REPL and inside containers
+    pub fn repr(&self) -> String {
+        match self {
+            Value::Str(s) => format!("{:?}", s),
+            other => other.to_string(),
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Num(a), Value::Num(b)) => a == b,
+            (Value::Str(a), Value::Str(b)) => a == b,
+            (Value::List(a), Value::List(b)) => Rc::ptr_eq(a, b) || *a.borrow() == *b.borrow(),
+            (Value::Map(a), Value::Map(b)) => Rc::ptr_eq(a, b) || *a.borrow() == *b.borrow(),
+            (Value::Func(a), Value::Func(b)) => Rc::ptr_eq(a, b),
+            (Value::Native(a), Value::Native(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Num(n) => write!(f, "{}", format_num(*n)),
+            Value::Str(s) => write!(f, "{}", s),
+            Value::List(items) => {
+                let items: Vec<String> = items.borrow().iter().map(Value::repr).collect();
+                write!(f, "[{}]", items.join(", "))
+            }
+            Value::Map(entries) => {
+                let entries: Vec<String> = entries
+                    .borrow()
+                    .iter()
+                    .map(|(k, v)| format!("{:?}: {}", k, v.repr()))
+                    .collect();
+                write!(f, "{{{}}}", entries.join(", "))
+            }
+            Value::Func(c) => write!(f, "<fn {}>", c.def.name),
+            Value::Native(name) => write!(f, "<builtin {}>", name),
+        }
+    }
+}
+
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.repr())
+    }
+}
+
+/// Whole numbers print without a fractional part
+pub fn format_num(n: f64) -> String {
+    if n.fract() == 0.0 && n.abs() < 1e15 {
+        format!("{}", n as i64)
+    } else {
+        format!("{}", n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Value::Num(3.0).to_string(), "3");
+        assert_eq!(Value::Num(-0.5).to_string(), "-0.5");
+        let list = Value::list(vec![Value::str("a"), Value::Nil]);
+        assert_eq!(list.to_string(), "[\"a\", nil]");
+        let mut m = BTreeMap::new();
+        m.insert("k".to_string(), Value::Bool(true));
+        assert_eq!(Value::map(m).to_string(), "{\"k\": true}");
+    }
+
+    #[test]
+    fn test_truthiness_and_equality() {
+        assert!(Value::Num(0.0).truthy());
+        assert!(Value::str("").truthy());
+        assert!(!Value::Nil.truthy());
+        assert_eq!(
+            Value::list(vec![Value::Num(1.0)]),
+            Value::list(vec![Value::Num(1.0)])
+        );
+        assert_ne!(Value::Num(1.0), Value::str("1"));
+    }
+}
@@ -0,0 +1,412 @@
+//! First-boot setup
+//!
+//! When the system starts with no saved state, the terminal walks through
+//! a short setup before the first prompt: the primary user and password, a
+//! color theme, the keyboard layout and the hostname. [`SetupWizard`]
+//! collects the answers one step at a time, and [`SetupWizard::apply`]
+//! creates the user, writes the configuration, seeds the home directory
+//! with example files and logs the user in.
+
+use super::profile::{self, THEMES};
+use crate::kernel::syscall::{self, KERNEL};
+use crate::kernel::{Gid, Uid};
+
+/// Keyboard layout configuration
+pub const KEYBOARD_PATH: &str = "/etc/default/keyboard";
+
+/// Hostname loaded at boot
+pub const HOSTNAME_PATH: &str = "/etc/hostname";
+
+/// Keyboard layouts offered, by XKB name
+pub const KEYBOARD_LAYOUTS: &[(&str, &str)] = &[
+    ("us", "English (US)"),
+    ("gb", "English (UK)"),
+    ("de", "German"),
+    ("fr", "French"),
+    ("es", "Spanish"),
+    ("se", "Swedish"),
+    ("no", "Norwegian"),
+    ("dk", "Danish"),
+    ("fi", "Finnish"),
+    ("dvorak", "English (Dvorak)"),
+];
+
+/// Group whose members may use sudo
+const WHEEL_GID: Gid = Gid(10);
+
+/// Example files seeded in the new user's home directory
+const SEED_FILES: &[(&str, &str)] = &[
+    (
+        "welcome.txt",
+        "Welcome to axeberg!\n\n\
+         This is your personal computing environment.\n\
+         Tractable. Immediate. Yours.\n\n\
+         Type 'help' for available commands.\n",
+    ),
+    (
+        "notes.md",
+        "# Notes\n\nA place for your notes. Open it with 'edit notes.md'.\n",
+    ),
+    (
+        "examples/hello.sh",
+        "# Run with: sh examples/hello.sh\necho \"Hello, $USER!\"\nuname -a\n",
+    ),
+    (
+        "examples/README",
+        "Small examples to try:\n\n  \
+         sh examples/hello.sh     run a shell script\n  \
+         ls /system/bin           list the built-in programs\n  \
+         profile list             show terminal profiles\n",
+    ),
+];
+
+/// A step of the setup
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Username,
+    Password,
+    ConfirmPassword,
+    Theme,
+    Keyboard,
+    Hostname,
+    Done,
+}
+
+/// Answers collected by the first-boot setup
+#[derive(Debug, Clone)]
+pub struct SetupWizard {
+    step: Step,
+    username: String,
+    password: Option<String>,
+    theme: String,
+    keyboard: String,
+    hostname: String,
+}
+
+impl Default for SetupWizard {
+    fn default() -> Self {
+        Self {
+            step: Step::Username,
+            username: "user".to_string(),
+            password: None,
+            theme: THEMES[0].0.to_string(),
+            keyboard: KEYBOARD_LAYOUTS[0].0.to_string(),
+            hostname: syscall::gethostname(),
+        }
+    }
+}
+
+/// Check a username: a lowercase letter, then up to 31 lowercase
+/// letters, digits, `_` or `-`
+fn is_valid_username(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_lowercase())
+        && name.len() <= 32
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
+}
+
+impl SetupWizard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn step(&self) -> Step {
+        self.step
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.step == Step::Done
+    }
+
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    /// Prompt for the current step; Enter alone keeps the value in brackets
+    pub fn prompt(&self) -> String {
+        match self.step {
+            Step::Username => format!("Username [{}]: ", self.username),
+            Step::Password => "Password (Enter for none): ".to_string(),
+            Step::ConfirmPassword => "Confirm password: ".to_string(),
+            Step::Theme => {
+                let names: Vec<_> = THEMES.iter().map(|(name, _)| *name).collect();
+                format!("Theme ({}) [{}]: ", names.join(", "), self.theme)
+            }
+            Step::Keyboard => {
+                let names: Vec<_> = KEYBOARD_LAYOUTS.iter().map(|(name, _)| *name).collect();
+                format!(
+                    "Keyboard layout ({}) [{}]: ",
+                    names.join(", "),
+                    self.keyboard
+                )
+            }
+            Step::Hostname => format!("Hostname [{}]: ", self.hostname),
+            Step::Done => String::new(),
+        }
+    }
+
+    /// Whether the answer to the current step must not be echoed
+    pub fn is_secret(&self) -> bool {
+        matches!(self.step, Step::Password | Step::ConfirmPassword)
+    }
+
+    /// Take the answer to the current step and move on; on error the step
+    /// is asked again
+    pub fn answer(&mut self, input: &str) -> Result<(), String> {
+        let value = input.trim();
+        match self.step {
+            Step::Username => {
+                if !value.is_empty() {
+                    if !is_valid_username(value) {
+                        return Err(format!(
+                            "'{}' is not a valid username (lowercase letters, digits, _ and -)",
+                            value
+                        ));
+                    }
+                    if matches!(value, "root" | "nobody") {
+                        return Err(format!("'{}' is reserved", value));
+                    }
+                    self.username = value.to_string();
+                }
+                self.step = Step::Password;
+            }
+            Step::Password => {
+                if input.is_empty() {
+                    self.password = None;
+                    self.step = Step::Theme;
+                } else {
+                    self.password = Some(input.to_string());
+                    self.step = Step::ConfirmPassword;
+                }
+            }
+            Step::ConfirmPassword => {
+                if self.password.as_deref() != Some(input) {
+                    self.password = None;
+                    self.step = Step::Password;
+                    return Err("passwords do not match".to_string());
+                }
+                self.step = Step::Theme;
+            }
+            Step::Theme => {
+                if !value.is_empty() {
+                    if !THEMES.iter().any(|(name, _)| *name == value) {
+                        return Err(format!("unknown theme '{}'", value));
+                    }
+                    self.theme = value.to_string();
+                }
+                self.step = Step::Keyboard;
+            }
+            Step::Keyboard => {
+                if !value.is_empty() {
+                    if !KEYBOARD_LAYOUTS.iter().any(|(name, _)| *name == value) {
+                        return Err(format!("unknown keyboard layout '{}'", value));
+                    }
+                    self.keyboard = value.to_string();
+                }
+                self.step = Step::Hostname;
+            }
+            Step::Hostname => {
+                if !value.is_empty() {
+                    if !crate::kernel::init::InitSystem::is_valid_hostname(value) {
+                        return Err(format!("'{}' is not a valid hostname", value));
+                    }
+                    self.hostname = value.to_string();
+                }
+                self.step = Step::Done;
+            }
+            Step::Done => {}
+        }
+        Ok(())
+    }
+
+    /// Skip the remaining steps, keeping their defaults
+    pub fn skip(&mut self) {
+        if self.step == Step::ConfirmPassword {
+            self.password = None;
+        }
+        self.step = Step::Done;
+    }
+
+    /// Set up the system with the answers and log the user in; returns the
+    /// user's home directory
+    pub fn apply(&self) -> Result<String, String> {
+        let (uid, gid, home, shell) = as_root(|| self.configure())?;
+        let pid = syscall::spawn_login_shell(&self.username, uid.0, gid.0, &home, &shell);
+        syscall::set_current_process(pid);
+        let _ = syscall::chdir(&home);
+        Ok(home)
+    }
+
+    /// The privileged part of [`Self::apply`]
+    fn configure(&self) -> Result<(Uid, Gid, String, String), String> {
+        let name = &self.username;
+        if syscall::get_user_by_name(name).is_none() {
+            syscall::add_user(name, None).map_err(|e| format!("useradd {}: {}", name, e))?;
+        }
+        let user = KERNEL.with(|k| {
+            let mut kernel = k.borrow_mut();
+            let users = kernel.users_mut();
+            let user = users.get_user_by_name_mut(name)?;
+            match &self.password {
+                Some(password) => user.set_password(password),
+                None => user.password_hash = None,
+            }
+            let user = user.clone();
+            if let Some(wheel) = users.get_group_mut(WHEEL_GID) {
+                wheel.add_member(name);
+            }
+            Some(user)
+        });
+        let user = user.ok_or_else(|| format!("user '{}' was not created", name))?;
+        syscall::save_user_db();
+
+        let home = user.home.clone();
+        let owner = |path: &str| syscall::chown(path, Some(user.uid.0), Some(user.gid.0));
+        let mkdir = |path: &str| -> Result<(), String> {
+            if !syscall::exists(path).unwrap_or(false) {
+                syscall::mkdir(path).map_err(|e| format!("{}: {}", path, e))?;
+                owner(path).map_err(|e| format!("{}: {}", path, e))?;
+            }
+            Ok(())
+        };
+        mkdir(&home)?;
+        for (rel, content) in SEED_FILES {
+            let path = format!("{}/{}", home, rel);
+            if let Some((dir, _)) = rel.rsplit_once('/') {
+                mkdir(&format!("{}/{}", home, dir))?;
+            }
+            if !syscall::exists(&path).unwrap_or(false) {
+                syscall::write_file(&path, content).map_err(|e| format!("{}: {}", path, e))?;
+                owner(&path).map_err(|e| format!("{}: {}", path, e))?;
+            }
+        }
+
+        // Terminal theme, starting in the new home directory
+        let mut profiles = profile::load()?;
+        let default = profiles.default.clone();
+        if let Some(p) = profiles.get_mut(&default) {
+            p.set_theme(&self.theme)?;
+            p.cwd = Some(home.clone());
+        }
+        profile::save(&profiles)?;
+
+        mkdir("/etc/default")?;
+        let layout = format!("XKBLAYOUT=\"{}\"\n", self.keyboard);
+        syscall::atomic_write(KEYBOARD_PATH, layout.as_bytes())
+            .map_err(|e| format!("{}: {}", KEYBOARD_PATH, e))?;
+
+        syscall::write_file("/sys/kernel/hostname", &self.hostname)
+            .map_err(|e| format!("hostname: {}", e))?;
+        syscall::atomic_write(HOSTNAME_PATH, format!("{}\n", self.hostname).as_bytes())
+            .map_err(|e| format!("{}: {}", HOSTNAME_PATH, e))?;
+
+        Ok((user.uid, user.gid, home, user.shell))
+    }
+}
+
+/// Run `f` with root as the effective user, like a setuid helper
+pub(crate) fn as_root<T>(f: impl FnOnce() -> T) -> T {
+    let saved = KERNEL.with(|k| {
+        let mut kernel = k.borrow_mut();
+        let process = kernel.current_process_mut()?;
+        let saved = (process.euid, process.egid);
+        process.euid = Uid::ROOT;
+        process.egid = Gid::ROOT;
+        Some(saved)
+    });
+    let result = f();
+    if let Some((euid, egid)) = saved {
+        KERNEL.with(|k| {
+            if let Some(process) = k.borrow_mut().current_process_mut() {
+                process.euid = euid;
+                process.egid = egid;
+            }
+        });
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::syscall::Kernel;
+
+    fn setup_kernel() {
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+    }
+
+    #[test]
+    fn test_wizard_steps() {
+        setup_kernel();
+        let mut wizard = SetupWizard::new();
+        assert_eq!(wizard.prompt(), "Username [user]: ");
+        assert!(wizard.answer("Bad Name").is_err());
+        assert!(wizard.answer("root").is_err());
+        wizard.answer("alice").unwrap();
+
+        assert!(wizard.is_secret());
+        wizard.answer("secret").unwrap();
+        assert!(wizard.answer("typo").is_err());
+        assert_eq!(wizard.step(), Step::Password);
+        wizard.answer("secret").unwrap();
+        wizard.answer("secret").unwrap();
+
+        assert!(wizard.answer("neon").is_err());
+        wizard.answer("").unwrap();
+        assert!(wizard.prompt().contains("dvorak"));
+        wizard.answer("se").unwrap();
+        assert!(wizard.answer("-nope").is_err());
+        wizard.answer("").unwrap();
+        assert!(wizard.is_done());
+        assert_eq!(wizard.hostname, "axeberg");
+        assert_eq!(wizard.theme, "tokyo-night");
+    }
+
+    #[test]
+    fn test_apply() {
+        setup_kernel();
+        let mut wizard = SetupWizard::new();
+        for answer in ["alice", "pw", "pw", "light", "se", "box"] {
+            wizard.answer(answer).unwrap();
+        }
+        assert_eq!(wizard.apply().unwrap(), "/home/alice");
+
+        let alice = syscall::get_user_by_name("alice").unwrap();
+        assert!(alice.check_password("pw"));
+        assert_eq!(syscall::getuid().unwrap(), alice.uid);
+        assert!(
+            syscall::get_group_by_name("wheel")
+                .unwrap()
+                .members
+                .contains(&"alice".to_string())
+        );
+
+        let welcome = syscall::metadata("/home/alice/welcome.txt").unwrap();
+        assert_eq!(welcome.uid, alice.uid.0);
+        assert!(syscall::exists("/home/alice/examples/hello.sh").unwrap());
+
+        assert_eq!(syscall::gethostname(), "box");
+        assert_eq!(syscall::read_file(HOSTNAME_PATH).unwrap(), "box\n");
+        assert_eq!(syscall::read_file("/sys/kernel/hostname").unwrap(), "box\n");
+        assert!(
+            syscall::read_file(KEYBOARD_PATH)
+                .unwrap()
+                .contains("XKBLAYOUT=\"se\"")
+        );
+        let profile = profile::load().unwrap().default_profile();
+        assert_eq!(profile.color("background"), Some("#e6e7ed"));
+        assert_eq!(profile.cwd.as_deref(), Some("/home/alice"));
+
+        // The user database survives a restore from disk
+        let data = syscall::vfs_snapshot().unwrap();
+        setup_kernel();
+        syscall::vfs_restore(&data).unwrap();
+        assert!(syscall::get_user_by_name("alice").is_some());
+        assert_eq!(syscall::gethostname(), "box");
+    }
+}
@@ -0,0 +1,571 @@
+//! Spell checking
+//!
+//! Words are checked against the system word list, [`DICT_PATH`], and each
+//! user's personal list, [`PERSONAL_FILE`] in their home: one word per
+//! line, matched regardless of case. A small English list ships in the
+//! system partition and [`install`] links it into place at boot unless a
+//! list is already there, so installing a bigger one is just replacing the
+//! file.
+//!
+//! As with Unix `spell`, common suffixes and prefixes are stripped before
+//! giving up on a word ("stopped" is "stop", "unsaved" is "saved"), so the
+//! list only needs base forms. Suggestions are the words within a couple
+//! of edits (insertions, deletions, replacements or transpositions).
+
+use std::collections::HashSet;
+use std::ops::Range;
+
+use crate::kernel::syscall;
+
+/// System word list
+pub const DICT_PATH: &str = "/usr/share/dict/words";
+
+/// The bundled list, in the system partition
+pub const SYSTEM_DICT_PATH: &str = "/system/usr/share/dict/words";
+
+/// Personal word list, relative to the home directory
+pub const PERSONAL_FILE: &str = ".config/spell/words";
+
+/// The bundled list, also used when no list can be read
+pub const BUNDLED: &str = include_str!("../../share/dict/words");
+
+/// Most edits a suggestion may be from the word
+const MAX_DISTANCE: usize = 2;
+
+/// Suffixes stripped when looking a word up, with what replaces them
+const SUFFIXES: &[(&str, &str)] = &[
+    ("'s", ""),
+    ("s", ""),
+    ("es", ""),
+    ("ies", "y"),
+    ("ed", ""),
+    ("ed", "e"),
+    ("ied", "y"),
+    ("ing", ""),
+    ("ing", "e"),
+    ("er", ""),
+    ("er", "e"),
+    ("ier", "y"),
+    ("est", ""),
+    ("est", "e"),
+    ("iest", "y"),
+    ("ly", ""),
+    ("ily", "y"),
+    ("ally", ""),
+    ("ness", ""),
+    ("iness", "y"),
+    ("ment", ""),
+    ("less", ""),
+    ("ful", ""),
+    ("able", ""),
+    ("able", "e"),
+    ("ation", "e"),
+    ("ation", ""),
+    ("ity", ""),
+    ("ion", ""),
+    ("ion", "e"),
+    ("ive", ""),
+    ("ive", "e"),
+    ("al", ""),
+];
+
+/// Prefixes stripped when looking a word up
+const PREFIXES: &[&str] = &[
+    "un", "re", "non", "pre", "dis", "mis", "sub", "over", "under", "multi",
+];
+
+/// A set of known words
+#[derive(Debug, Clone, Default)]
+pub struct Dictionary {
+    words: HashSet<String>,
+}
+
+impl Dictionary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the words in a list, one per line; `#` starts a comment
+    pub fn add_list(&mut self, list: &str) {
+        for line in list.lines() {
+            let word = line.split('#').next().unwrap_or("").trim();
+            if !word.is_empty() {
+                self.words.insert(word.to_lowercase());
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// Whether `word` is spelled right
+    pub fn check(&self, word: &str) -> bool {
+        let word = word.to_lowercase().replace('’', "'");
+        let word = word.trim_end_matches('\'');
+        self.derives(word, 2)
+            || PREFIXES.iter().any(|prefix| {
+                word.strip_prefix(prefix)
+                    .is_some_and(|rest| rest.chars().count() > 2 && self.derives(rest, 2))
+            })
+    }
+
+    /// Whether `word` is in the list, or is one with up to `depth` suffixes
+    fn derives(&self, word: &str, depth: usize) -> bool {
+        if self.words.contains(word) {
+            return true;
+        }
+        depth > 0
+            && SUFFIXES.iter().any(|(suffix, replacement)| {
+                let Some(stem) = word.strip_suffix(suffix) else {
+                    return false;
+                };
+                if stem.chars().count() < 2 {
+                    return false;
+                }
+                let stem = format!("{}{}", stem, replacement);
+                if self.derives(&stem, depth - 1) {
+                    return true;
+                }
+                // A doubled final consonant: stopped, running, bigger
+                let last = stem.chars().last().unwrap_or(' ');
+                replacement.is_empty()
+                    && stem.len() > 2
+                    && stem[..stem.len() - last.len_utf8()].ends_with(last)
+                    && !"aeiouls".contains(last)
+                    && self.derives(&stem[..stem.len() - last.len_utf8()], depth - 1)
+            })
+    }
+
+    /// Up to `limit` corrections for `word`, closest first, in its case
+    pub fn suggest(&self, word: &str, limit: usize) -> Vec<String> {
+        let lower = word.to_lowercase();
+        let chars: Vec<char> = lower.chars().collect();
+        let mut found: Vec<(usize, String)> = edits(&chars)
+            .into_iter()
+            .filter(|candidate| self.check(candidate))
+            .map(|candidate| (1, candidate))
+            .collect();
+        for known in &self.words {
+            let len = known.chars().count();
+            if len.abs_diff(chars.len()) > MAX_DISTANCE {
+                continue;
+            }
+            let distance = distance(&chars, &known.chars().collect::<Vec<_>>());
+            if distance > 0 && distance <= MAX_DISTANCE {
+                found.push((distance, known.clone()));
+            }
+        }
+        // Closest first, then those keeping the first letter
+        let first = chars.first().copied();
+        found.sort_by(|a, b| {
+            (a.0, a.1.chars().next() != first, &a.1).cmp(&(b.0, b.1.chars().next() != first, &b.1))
+        });
+        let mut seen = HashSet::new();
+        found
+            .into_iter()
+            .filter(|(_, candidate)| seen.insert(candidate.clone()))
+            .take(limit)
+            .map(|(_, candidate)| match_case(word, &candidate))
+            .collect()
+    }
+
+    /// Misspelled words in a line of text, as character ranges
+    pub fn misspelled(&self, line: &str, markdown: bool) -> Vec<Range<usize>> {
+        words(line, markdown)
+            .into_iter()
+            .filter(|range| {
+                let word: String = line.chars().skip(range.start).take(range.len()).collect();
+                !self.check(&word)
+            })
+            .collect()
+    }
+}
+
+/// Every string one edit from `word`
+fn edits(word: &[char]) -> Vec<String> {
+    let mut out = Vec::new();
+    let text = |chars: &[char]| chars.iter().collect::<String>();
+    for i in 0..=word.len() {
+        for c in 'a'..='z' {
+            let mut inserted = word.to_vec();
+            inserted.insert(i, c);
+            out.push(text(&inserted));
+            if i < word.len() && word[i] != c {
+                let mut replaced = word.to_vec();
+                replaced[i] = c;
+                out.push(text(&replaced));
+            }
+        }
+        if i < word.len() {
+            let mut deleted = word.to_vec();
+            deleted.remove(i);
+            out.push(text(&deleted));
+        }
+        if i + 1 < word.len() {
+            let mut swapped = word.to_vec();
+            swapped.swap(i, i + 1);
+            out.push(text(&swapped));
+        }
+    }
+    out
+}
+
+/// Edit distance, counting a transposition of neighbours as one edit
+pub fn distance(a: &[char], b: &[char]) -> usize {
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    rows[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = best;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+/// `suggestion` capitalized like `word`
+fn match_case(word: &str, suggestion: &str) -> String {
+    let letters: Vec<char> = word.chars().filter(|c| c.is_alphabetic()).collect();
+    if letters.len() > 1 && letters.iter().all(|c| c.is_uppercase()) {
+        return suggestion.to_uppercase();
+    }
+    let mut chars = suggestion.chars();
+    match (word.chars().next(), chars.next()) {
+        (Some(w), Some(first)) if w.is_uppercase() => first.to_uppercase().chain(chars).collect(),
+        _ => suggestion.to_string(),
+    }
+}
+
+/// Words to check in a line, as character ranges
+///
+/// Only runs of letters (with apostrophes inside) count: anything touching
+/// digits or underscores, acronyms, mixed-case identifiers and paths, URLs
+/// and email addresses are skipped. With `markdown`, inline code, link
+/// targets and HTML tags are too.
+pub fn words(line: &str, markdown: bool) -> Vec<Range<usize>> {
+    let mut chars: Vec<char> = line.chars().collect();
+    if markdown {
+        mask_markdown(&mut chars);
+    }
+    let mut out = Vec::new();
+    let mut start = 0;
+    while start < chars.len() {
+        if chars[start].is_whitespace() {
+            start += 1;
+            continue;
+        }
+        // A whitespace-separated chunk
+        let end = (start..chars.len())
+            .find(|&i| chars[i].is_whitespace())
+            .unwrap_or(chars.len());
+        let chunk: String = chars[start..end].iter().collect();
+        let skip = chunk.contains("://")
+            || chunk.contains('@')
+            || chunk.starts_with('/')
+            || chunk.starts_with("~/")
+            || chunk.starts_with("./")
+            || chunk.starts_with('-')
+            || chunk.contains('\\')
+            // src/main.rs, a/b/c
+            || (chunk.contains('/') && (chunk.contains('.') || chunk.matches('/').count() > 1));
+        if !skip {
+            chunk_words(&chars, start, end, &mut out);
+        }
+        start = end;
+    }
+    out
+}
+
+/// Words in `chars[start..end]`, skipping those joined to code-like text
+fn chunk_words(chars: &[char], start: usize, end: usize, out: &mut Vec<Range<usize>>) {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == '\'' || c == '’';
+    let mut i = start;
+    while i < end {
+        if !is_word(chars[i]) {
+            i += 1;
+            continue;
+        }
+        let from = i;
+        while i < end && is_word(chars[i]) {
+            i += 1;
+        }
+        // Quotes around a word aren't part of it
+        let mut word = from..i;
+        while word.start < word.end && matches!(chars[word.start], '\'' | '’') {
+            word.start += 1;
+        }
+        while word.end > word.start && matches!(chars[word.end - 1], '\'' | '’') {
+            word.end -= 1;
+        }
+        let text = &chars[word.clone()];
+        // file.txt, foo.bar(), a::b
+        let joined = (from > start && matches!(chars[from - 1], '.' | ':' | '$' | '%'))
+            || (i + 1 < end && matches!(chars[i], '.' | ':') && is_word(chars[i + 1]));
+        let uppers = text.iter().filter(|c| c.is_uppercase()).count();
+        let code_like = text
+            .iter()
+            .any(|c| !c.is_alphabetic() && !matches!(c, '\'' | '’'))
+            || uppers > 1
+            || (uppers == 1 && !text[0].is_uppercase());
+        if text.len() > 1 && !joined && !code_like {
+            out.push(word);
+        }
+    }
+}
+
+/// Blank out Markdown inline code, link targets and HTML tags
+fn mask_markdown(chars: &mut [char]) {
+    let mut i = 0;
+    while i < chars.len() {
+        let close = match chars[i] {
+            '`' => Some('`'),
+            '<' => Some('>'),
+            '(' if i > 0 && chars[i - 1] == ']' => Some(')'),
+            _ => None,
+        };
+        let end = close.and_then(|close| (i + 1..chars.len()).find(|&j| chars[j] == close));
+        match end {
+            Some(end) => {
+                chars[i..=end].fill(' ');
+                i = end + 1;
+            }
+            None => i += 1,
+        }
+    }
+}
+
+/// Tracks fenced code blocks through a Markdown document, line by line
+#[derive(Debug, Default)]
+pub struct Fences {
+    fence: Option<String>,
+}
+
+impl Fences {
+    /// Whether `line`, the next one, is prose rather than code
+    pub fn prose(&mut self, line: &str) -> bool {
+        let trimmed = line.trim_start();
+        let marker: String = trimmed
+            .chars()
+            .take_while(|&c| c == '`' || c == '~')
+            .collect();
+        let is_fence = marker.len() >= 3
+            && (marker.chars().all(|c| c == '`') || marker.chars().all(|c| c == '~'));
+        match &self.fence {
+            Some(open) => {
+                if is_fence && marker.starts_with(open.as_str()) {
+                    self.fence = None;
+                }
+                false
+            }
+            None if is_fence => {
+                self.fence = Some(marker);
+                false
+            }
+            // Indented code
+            None => !line.starts_with("    ") && !line.starts_with('\t'),
+        }
+    }
+}
+
+/// Whether a file is checked as Markdown
+pub fn is_markdown(path: &str) -> bool {
+    path.ends_with(".md") || path.ends_with(".markdown")
+}
+
+/// Whether the editor checks a file's spelling: Markdown or plain text
+pub fn is_prose(path: &str) -> bool {
+    is_markdown(path) || path.ends_with(".txt")
+}
+
+/// The current user's personal word list
+pub fn personal_path() -> String {
+    let home = syscall::getenv("HOME")
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| "/tmp".to_string());
+    format!("{}/{}", home.trim_end_matches('/'), PERSONAL_FILE)
+}
+
+/// The system and personal word lists
+pub fn load() -> Dictionary {
+    let mut dict = Dictionary::new();
+    match [DICT_PATH, SYSTEM_DICT_PATH]
+        .iter()
+        .find_map(|path| syscall::read_file(path).ok())
+    {
+        Some(list) => dict.add_list(&list),
+        None => dict.add_list(BUNDLED),
+    }
+    if let Ok(list) = syscall::read_file(&personal_path()) {
+        dict.add_list(&list);
+    }
+    dict
+}
+
+/// Add words to the current user's personal list
+pub fn add_personal(words: &[&str]) -> Result<(), String> {
+    let path = personal_path();
+    let mut dir = String::new();
+    let parent = path.rsplit_once('/').map_or("", |(parent, _)| parent);
+    for part in parent.split('/').filter(|p| !p.is_empty()) {
+        dir.push('/');
+        dir.push_str(part);
+        if !syscall::exists(&dir).unwrap_or(false) {
+            syscall::mkdir(&dir).map_err(|e| format!("{}: {}", dir, e))?;
+        }
+    }
+    let mut list = syscall::read_file(&path).unwrap_or_default();
+    let mut known = Dictionary::new();
+    known.add_list(&list);
+    for word in words {
+        if !known.words.contains(&word.to_lowercase()) {
+            if !list.is_empty() && !list.ends_with('\n') {
+                list.push('\n');
+            }
+            list.push_str(word);
+            list.push('\n');
+            known.add_list(word);
+        }
+    }
+    syscall::write_file(&path, &list).map_err(|e| format!("{}: {}", path, e))
+}
+
+/// Link the bundled list to [`DICT_PATH`] if nothing is there yet
+pub fn install() -> Result<(), String> {
+    if syscall::read_link(DICT_PATH).is_ok() || syscall::exists(DICT_PATH).unwrap_or(false) {
+        return Ok(());
+    }
+    let mut dir = String::new();
+    for part in ["usr", "share", "dict"] {
+        dir.push('/');
+        dir.push_str(part);
+        if !syscall::exists(&dir).unwrap_or(false) {
+            syscall::mkdir(&dir).map_err(|e| format!("{}: {}", dir, e))?;
+        }
+    }
+    syscall::symlink(SYSTEM_DICT_PATH, DICT_PATH).map_err(|e| format!("{}: {}", DICT_PATH, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::syscall::{KERNEL, Kernel};
+
+    fn dict(list: &str) -> Dictionary {
+        let mut dict = Dictionary::new();
+        dict.add_list(list);
+        dict
+    }
+
+    #[test]
+    fn test_check() {
+        let dict = dict("stop\nmake\nparty\nsave\nquick\nhappy\nbig\nfile # comment\n");
+        for word in [
+            "stop",
+            "Stop",
+            "STOP",
+            "stops",
+            "stopped",
+            "stopping",
+            "making",
+            "parties",
+            "partied",
+            "unsaved",
+            "quickly",
+            "happily",
+            "happiness",
+            "bigger",
+            "file's",
+            "files'",
+        ] {
+            assert!(dict.check(word), "{}", word);
+        }
+        for word in ["stpo", "mkae", "prty", "sv", "comment"] {
+            assert!(!dict.check(word), "{}", word);
+        }
+        assert!(Dictionary::new().is_empty());
+        assert_eq!(dict.len(), 8);
+    }
+
+    #[test]
+    fn test_suggest() {
+        let dict = dict("hello\nhelp\nworld\nword\nrun\nthe\n");
+        assert_eq!(dict.suggest("helo", 2), ["hello", "help"]);
+        assert_eq!(dict.suggest("Wrold", 1), ["World"]);
+        assert_eq!(dict.suggest("THR", 1), ["THE"]);
+        assert!(dict.suggest("runing", 3).contains(&"running".to_string()));
+        assert!(dict.suggest("xyzzyq", 3).is_empty());
+        assert_eq!(distance(&['a', 'b'], &['b', 'a']), 1);
+    }
+
+    #[test]
+    fn test_words() {
+        let text = |line: &str, markdown: bool| -> Vec<String> {
+            words(line, markdown)
+                .into_iter()
+                .map(|r| line.chars().skip(r.start).take(r.len()).collect())
+                .collect()
+        };
+        assert_eq!(
+            text(
+                "It's a 'quoted' wrd, don't x86 foo_bar VFS camelCase",
+                false
+            ),
+            ["It's", "quoted", "wrd", "don't"]
+        );
+        assert_eq!(
+            text("see /usr/bin and https://x.org or main.rs now", false),
+            ["see", "and", "or", "now"]
+        );
+        assert_eq!(
+            text("Run `cargo tset` via [the docs](docs/setup.md) <br>", true),
+            ["Run", "via", "the", "docs"]
+        );
+
+        let mut fences = Fences::default();
+        let prose: Vec<bool> = ["text", "```rust", "let x;", "```", "    code", "more"]
+            .iter()
+            .map(|line| fences.prose(line))
+            .collect();
+        assert_eq!(prose, [true, false, false, false, false, true]);
+
+        let dict = dict("a\nword\nhere\n");
+        assert_eq!(dict.misspelled("a wrod here bda", false), [2..6, 12..15]);
+    }
+
+    #[test]
+    fn test_personal_list() {
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        syscall::mkdir("/tmp/me").unwrap();
+        syscall::setenv("HOME", "/tmp/me").unwrap();
+
+        assert!(!load().check("axebergish"));
+        add_personal(&["axebergish", "Zork"]).unwrap();
+        add_personal(&["zork"]).unwrap();
+        assert_eq!(
+            syscall::read_file("/tmp/me/.config/spell/words").unwrap(),
+            "axebergish\nZork\n"
+        );
+        let dict = load();
+        assert!(dict.check("axebergish") && dict.check("zork") && dict.check("hello"));
+    }
+}
@@ -0,0 +1,294 @@
+//! Stats - local command usage statistics
+//!
+//! The shell counts every program it runs, with how long it took and
+//! whether it failed, in [`STATS_PATH`]. The numbers never leave the
+//! machine; they are there to show which commands are used most, which
+//! are slow and which keep failing, so effort goes where it matters.
+//! `stats purge` deletes them.
+//!
+//! Only command names are kept, never arguments. Unknown commands
+//! (typos) and `stats` itself are not counted.
+//!
+//! ```text
+//! $ stats top 3
+//! COMMAND        RUNS   FAIL  FAIL%    AVG ms    MAX ms
+//! ls               42      0     0%       0.4       2.1
+//! cat              17      2    12%       0.2       0.9
+//! grep             11      4    36%       0.3       1.2
+//! ```
+
+use crate::kernel::syscall::{self, OpenFlags};
+use std::collections::BTreeMap;
+
+/// Where the statistics are kept
+pub const STATS_PATH: &str = "/var/lib/stats.db";
+
+/// First line of the stats file
+const HEADER: &str = "# command\truns\tfailures\ttotal_ms\tmax_ms\tlast_run";
+
+/// Usage of one command
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CommandStats {
+    pub runs: u64,
+    /// Runs that exited non-zero
+    pub failures: u64,
+    /// Time spent in all runs, in milliseconds
+    pub total_ms: f64,
+    /// Longest run, in milliseconds
+    pub max_ms: f64,
+    /// Kernel time of the last run
+    pub last_run: f64,
+}
+
+impl CommandStats {
+    /// Mean duration of a run, in milliseconds
+    pub fn mean_ms(&self) -> f64 {
+        if self.runs == 0 {
+            0.0
+        } else {
+            self.total_ms / self.runs as f64
+        }
+    }
+
+    /// Fraction of runs that failed
+    pub fn failure_rate(&self) -> f64 {
+        if self.runs == 0 {
+            0.0
+        } else {
+            self.failures as f64 / self.runs as f64
+        }
+    }
+}
+
+/// One program run, waiting to be written
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub program: String,
+    pub ms: f64,
+    pub code: i32,
+}
+
+/// Check if a run should be counted
+pub fn is_tracked(program: &str, code: i32) -> bool {
+    program != "stats" && code != 127
+}
+
+/// Usage of every command
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StatsDb {
+    pub commands: BTreeMap<String, CommandStats>,
+}
+
+impl StatsDb {
+    /// Parse the stats file; lines that don't parse are skipped
+    pub fn parse(text: &str) -> Self {
+        let mut db = Self::default();
+        for line in text.lines().filter(|l| !l.starts_with('#')) {
+            let fields: Vec<&str> = line.split('\t').collect();
+            let [name, runs, failures, total, max, last] = fields.as_slice() else {
+                continue;
+            };
+            let (Ok(runs), Ok(failures), Ok(total_ms), Ok(max_ms), Ok(last_run)) = (
+                runs.parse(),
+                failures.parse(),
+                total.parse(),
+                max.parse(),
+                last.parse(),
+            ) else {
+                continue;
+            };
+            db.commands.insert(
+                name.to_string(),
+                CommandStats {
+                    runs,
+                    failures,
+                    total_ms,
+                    max_ms,
+                    last_run,
+                },
+            );
+        }
+        db
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = format!("{}\n", HEADER);
+        for (name, s) in &self.commands {
+            out.push_str(&format!(
+                "{}\t{}\t{}\t{:.3}\t{:.3}\t{:.0}\n",
+                name, s.runs, s.failures, s.total_ms, s.max_ms, s.last_run
+            ));
+        }
+        out
+    }
+
+    /// Count one run of `program`
+    pub fn record(&mut self, record: &Record, now: f64) {
+        let stats = self.commands.entry(record.program.clone()).or_default();
+        stats.runs += 1;
+        if record.code != 0 {
+            stats.failures += 1;
+        }
+        stats.total_ms += record.ms;
+        stats.max_ms = stats.max_ms.max(record.ms);
+        stats.last_run = now;
+    }
+
+    /// The `n` most used commands
+    pub fn top(&self, n: usize) -> Vec<(&str, &CommandStats)> {
+        self.sorted(n, |a, b| b.runs.cmp(&a.runs))
+    }
+
+    /// The `n` commands with the longest mean duration
+    pub fn slowest(&self, n: usize) -> Vec<(&str, &CommandStats)> {
+        self.sorted(n, |a, b| b.mean_ms().total_cmp(&a.mean_ms()))
+    }
+
+    /// The `n` commands that failed most often
+    pub fn errors(&self, n: usize) -> Vec<(&str, &CommandStats)> {
+        let mut errors = self.sorted(usize::MAX, |a, b| {
+            b.failures
+                .cmp(&a.failures)
+                .then(b.failure_rate().total_cmp(&a.failure_rate()))
+        });
+        errors.retain(|(_, s)| s.failures > 0);
+        errors.truncate(n);
+        errors
+    }
+
+    fn sorted(
+        &self,
+        n: usize,
+        by: impl Fn(&CommandStats, &CommandStats) -> std::cmp::Ordering,
+    ) -> Vec<(&str, &CommandStats)> {
+        let mut all: Vec<(&str, &CommandStats)> = self
+            .commands
+            .iter()
+            .map(|(name, s)| (name.as_str(), s))
+            .collect();
+        // Stable sort keeps ties in name order
+        all.sort_by(|a, b| by(a.1, b.1));
+        all.truncate(n);
+        all
+    }
+}
+
+/// Load the statistics (empty if there are none yet)
+pub fn load() -> StatsDb {
+    syscall::read_file(STATS_PATH)
+        .map(|text| StatsDb::parse(&text))
+        .unwrap_or_default()
+}
+
+/// Write the statistics, creating `/var/lib` if needed
+pub fn save(db: &StatsDb) -> Result<(), String> {
+    let _ = syscall::mkdir("/var");
+    let _ = syscall::mkdir("/var/lib");
+    let fd = syscall::open(STATS_PATH, OpenFlags::WRITE)
+        .map_err(|e| format!("{}: {}", STATS_PATH, e))?;
+    let result = syscall::write(fd, db.to_text().as_bytes());
+    let _ = syscall::close(fd);
+    result
+        .map(|_| ())
+        .map_err(|e| format!("{}: {}", STATS_PATH, e))
+}
+
+/// Delete every statistic, or those of one command; returns how many
+/// commands were removed
+pub fn purge(command: Option<&str>) -> Result<usize, String> {
+    let mut db = load();
+    let removed = match command {
+        Some(name) => usize::from(db.commands.remove(name).is_some()),
+        None => std::mem::take(&mut db.commands).len(),
+    };
+    if db.commands.is_empty() {
+        match syscall::unlink(STATS_PATH) {
+            Ok(()) | Err(syscall::SyscallError::NotFound) => Ok(removed),
+            Err(e) => Err(format!("{}: {}", STATS_PATH, e)),
+        }
+    } else {
+        save(&db).map(|()| removed)
+    }
+}
+
+/// Add the runs of one command line to the statistics
+pub fn record_all(records: &[Record]) {
+    if records.is_empty() {
+        return;
+    }
+    let mut db = load();
+    let now = syscall::now();
+    for record in records {
+        db.record(record, now);
+    }
+    // Statistics are best effort: a read-only or full disk loses them
+    let _ = save(&db);
+}
+
+/// Wall clock for timing commands, in milliseconds
+///
+/// Kernel time only moves between executor ticks, so it can't time a
+/// command that runs within one.
+pub fn clock() -> f64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        js_sys::Date::now()
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs_f64() * 1000.0)
+            .unwrap_or(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(program: &str, ms: f64, code: i32) -> Record {
+        Record {
+            program: program.to_string(),
+            ms,
+            code,
+        }
+    }
+
+    #[test]
+    fn test_record_and_report() {
+        let mut db = StatsDb::default();
+        for record in [
+            run("ls", 1.0, 0),
+            run("ls", 3.0, 0),
+            run("ls", 2.0, 1),
+            run("grep", 10.0, 1),
+            run("grep", 10.0, 1),
+            run("cat", 0.5, 0),
+        ] {
+            db.record(&record, 5.0);
+        }
+
+        let ls = &db.commands["ls"];
+        assert_eq!((ls.runs, ls.failures), (3, 1));
+        assert_eq!(ls.mean_ms(), 2.0);
+        assert_eq!(ls.max_ms, 3.0);
+
+        let names = |v: Vec<(&str, &CommandStats)>| -> Vec<String> {
+            v.into_iter().map(|(n, _)| n.to_string()).collect()
+        };
+        assert_eq!(names(db.top(2)), ["ls", "grep"]);
+        assert_eq!(names(db.slowest(3)), ["grep", "ls", "cat"]);
+        assert_eq!(names(db.errors(5)), ["grep", "ls"]);
+
+        assert_eq!(StatsDb::parse(&db.to_text()), db);
+        assert!(StatsDb::parse("junk\nls\t1\n").commands.is_empty());
+    }
+
+    #[test]
+    fn test_tracked() {
+        assert!(is_tracked("ls", 1));
+        assert!(!is_tracked("stats", 0));
+        assert!(!is_tracked("sl", 127));
+    }
+}
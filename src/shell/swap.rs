@@ -0,0 +1,202 @@
+//! Editor swap files
+//!
+//! While a buffer has unsaved changes, `edit` keeps a copy of it in a swap
+//! file under [`SWAP_DIR`] in the user's home, rewritten shortly after
+//! each change ([`SWAP_DELAY_MS`]) and whenever a task crashes. Saving
+//! the buffer or quitting removes it, so one that is still there when the
+//! file is opened again means the editor never got to: the tab crashed or
+//! was reloaded. The editor then offers to recover it, show how it differs
+//! from the file, or discard it. `edit -r` lists the swap files left.
+//!
+//! A swap file is a short header, a blank line and the buffer:
+//!
+//! ```text
+//! axeberg-swap 1
+//! path: /home/user/notes.txt
+//! time: 1760000000000
+//!
+//! buffer contents...
+//! ```
+
+use super::diff::{self, Edit};
+use super::stats;
+use crate::kernel::syscall;
+
+/// Where swap files go, relative to the home directory
+pub const SWAP_DIR: &str = ".cache/editor/swap";
+
+/// How long a buffer goes unchanged before its swap file is written
+pub const SWAP_DELAY_MS: f64 = 2000.0;
+
+/// First line of every swap file
+const MAGIC: &str = "axeberg-swap 1";
+
+/// An unsaved buffer
+#[derive(Debug, Clone, PartialEq)]
+pub struct Swap {
+    /// Absolute path of the file the buffer belongs to
+    pub path: String,
+    /// When the swap was written, in milliseconds since the epoch
+    pub time: f64,
+    pub content: String,
+}
+
+impl Swap {
+    pub fn to_text(&self) -> String {
+        format!(
+            "{}\npath: {}\ntime: {:.0}\n\n{}",
+            MAGIC, self.path, self.time, self.content
+        )
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        let rest = text.strip_prefix(MAGIC)?.strip_prefix('\n')?;
+        let (header, content) = rest.split_once("\n\n")?;
+        let mut path = None;
+        let mut time = None;
+        for line in header.lines() {
+            match line.split_once(": ") {
+                Some(("path", value)) => path = Some(value.to_string()),
+                Some(("time", value)) => time = value.parse().ok(),
+                _ => {}
+            }
+        }
+        Some(Self {
+            path: path?,
+            time: time?,
+            content: content.to_string(),
+        })
+    }
+}
+
+/// The user's swap directory
+pub fn swap_dir() -> String {
+    let home = syscall::getenv("HOME")
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| "/tmp".to_string());
+    format!("{}/{}", home.trim_end_matches('/'), SWAP_DIR)
+}
+
+/// `file` as an absolute path
+pub fn absolute(file: &str) -> String {
+    if file.starts_with('/') {
+        return file.to_string();
+    }
+    let cwd = syscall::getcwd()
+        .map(|cwd| cwd.display().to_string())
+        .unwrap_or_else(|_| "/".to_string());
+    format!("{}/{}", cwd.trim_end_matches('/'), file)
+}
+
+/// Swap file for `file`: its absolute path with `%` for `/`, as vim does
+pub fn swap_path(file: &str) -> String {
+    format!("{}/{}.swp", swap_dir(), absolute(file).replace('/', "%"))
+}
+
+/// Write the swap file for `file`
+pub fn write(file: &str, content: &str) -> Result<(), String> {
+    let dir = swap_dir();
+    let mut path = String::new();
+    for part in dir.split('/').filter(|p| !p.is_empty()) {
+        path.push('/');
+        path.push_str(part);
+        if !syscall::exists(&path).unwrap_or(false) {
+            syscall::mkdir(&path).map_err(|e| format!("{}: {}", path, e))?;
+        }
+    }
+    let swap = Swap {
+        path: absolute(file),
+        time: stats::clock(),
+        content: content.to_string(),
+    };
+    let path = swap_path(file);
+    syscall::write_file(&path, &swap.to_text()).map_err(|e| format!("{}: {}", path, e))
+}
+
+/// The swap file left for `file`, if any
+pub fn read(file: &str) -> Option<Swap> {
+    Swap::parse(&syscall::read_file(&swap_path(file)).ok()?)
+}
+
+/// Remove the swap file for `file`, if there is one
+pub fn remove(file: &str) {
+    let _ = syscall::remove_file(&swap_path(file));
+}
+
+/// Every swap file the user has, by path
+pub fn list() -> Vec<Swap> {
+    let dir = swap_dir();
+    let mut swaps: Vec<Swap> = syscall::readdir(&dir)
+        .unwrap_or_default()
+        .iter()
+        .filter(|name| name.ends_with(".swp"))
+        .filter_map(|name| syscall::read_file(&format!("{}/{}", dir, name)).ok())
+        .filter_map(|text| Swap::parse(&text))
+        .collect();
+    swaps.sort_by(|a, b| a.path.cmp(&b.path));
+    swaps
+}
+
+/// Line diff from `old` to `new`: each line prefixed with `  ` if kept,
+/// `- ` if removed or `+ ` if added
+pub fn diff(old: &str, new: &str) -> Vec<String> {
+    diff::lines(old, new)
+        .into_iter()
+        .map(|edit| match edit {
+            Edit::Same(line) => format!("  {}", line),
+            Edit::Removed(line) => format!("- {}", line),
+            Edit::Added(line) => format!("+ {}", line),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::syscall::{KERNEL, Kernel};
+
+    #[test]
+    fn test_swap_files() {
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        syscall::mkdir("/tmp/me").unwrap();
+        syscall::setenv("HOME", "/tmp/me").unwrap();
+        syscall::chdir("/tmp/me").unwrap();
+
+        assert_eq!(
+            swap_path("notes.txt"),
+            "/tmp/me/.cache/editor/swap/%tmp%me%notes.txt.swp"
+        );
+        assert_eq!(read("notes.txt"), None);
+
+        write("notes.txt", "draft\n\nmore").unwrap();
+        write("/etc/motd", "hi").unwrap();
+        let swap = read("/tmp/me/notes.txt").unwrap();
+        assert_eq!(swap.path, "/tmp/me/notes.txt");
+        assert_eq!(swap.content, "draft\n\nmore");
+        assert!(swap.time > 0.0);
+        assert_eq!(Swap::parse(&swap.to_text()), Some(swap));
+
+        let paths: Vec<String> = list().into_iter().map(|s| s.path).collect();
+        assert_eq!(paths, ["/etc/motd", "/tmp/me/notes.txt"]);
+        remove("notes.txt");
+        remove("notes.txt");
+        assert_eq!(list().len(), 1);
+        assert_eq!(Swap::parse("not a swap"), None);
+    }
+
+    #[test]
+    fn test_diff() {
+        assert_eq!(
+            diff("a\nb\nc\nd", "a\nc\nx\nd"),
+            ["  a", "- b", "  c", "+ x", "  d"]
+        );
+        assert_eq!(diff("same", "same"), ["  same"]);
+        assert_eq!(diff("", "new"), ["+ new"]);
+    }
+}
@@ -6,8 +6,14 @@
 //! - Connection to shell executor
 //! - Keyboard event handling
 //! - Text selection with clipboard support
+//! - Title and clipboard requests from programs (OSC 0/2 and 52)
+//! - Mouse reporting to programs that ask for it (modes 1000/1002/1003/1006)
 
+use crate::kernel::syscall;
+use crate::kernel::tty::{MouseAction, MouseButton, MouseEvent, MouseTracking};
 use crate::shell::Executor;
+use crate::shell::osc::{self, OscCommand};
+use crate::shell::profile::TerminalProfile;
 use std::collections::VecDeque;
 
 /// A position in the terminal grid (row, column)
@@ -179,6 +185,15 @@ pub struct Terminal {
 
     /// Current text selection (if any)
     selection: Option<Selection>,
+
+    /// Profile the terminal was started with
+    profile: Option<TerminalProfile>,
+
+    /// OSC requests from command output, for the window to carry out
+    osc: Vec<OscCommand>,
+
+    /// Mouse events programs asked to have reported
+    mouse: MouseTracking,
 }
 
 impl Terminal {
@@ -199,6 +214,9 @@ impl Terminal {
             scroll_offset: 0,
             active: true,
             selection: None,
+            profile: None,
+            osc: Vec::new(),
+            mouse: MouseTracking::default(),
         };
 
         #[cfg(all(target_arch = "wasm32", not(test)))]
@@ -215,6 +233,34 @@ impl Terminal {
         term
     }
 
+    /// Create a terminal set up by `profile`: it starts in the profile's
+    /// `cwd` and runs its `startup` command
+    pub fn with_profile(profile: TerminalProfile) -> Self {
+        let mut term = Self::new();
+        if let Some(cwd) = &profile.cwd {
+            let result = term.executor.chdir(cwd);
+            if !result.error.is_empty() {
+                term.print_error(&result.error);
+            }
+            term.update_prompt();
+        }
+        if let Some(startup) = &profile.startup {
+            term.run(startup);
+        }
+        term.profile = Some(profile);
+        term
+    }
+
+    /// Profile the terminal was started with, if any
+    pub fn profile(&self) -> Option<&TerminalProfile> {
+        self.profile.as_ref()
+    }
+
+    /// Take the OSC requests made since the last call
+    pub fn take_osc(&mut self) -> Vec<OscCommand> {
+        std::mem::take(&mut self.osc)
+    }
+
     /// Print a line to the terminal
     pub fn print(&mut self, text: &str) {
         // Handle multiple lines
@@ -371,10 +417,6 @@ impl Terminal {
         self.cursor = 0;
         self.history_pos = None;
 
-        // Echo the input
-        self.lines
-            .push_back(TerminalLine::input(format!("{}{}", self.prompt, input)));
-
         // Add to history if non-empty
         if !input.trim().is_empty() {
             // Remove duplicate if at front
@@ -387,12 +429,21 @@ impl Terminal {
             }
         }
 
-        // Execute the command
-        let result = self.executor.execute_line(&input);
+        self.run(&input);
+    }
 
-        // Handle output
-        if !result.output.is_empty() {
-            self.print(&result.output);
+    /// Echo a command line and execute it
+    fn run(&mut self, input: &str) {
+        self.lines
+            .push_back(TerminalLine::input(format!("{}{}", self.prompt, input)));
+        let result = self.executor.execute_interactive(input);
+
+        // Handle output, minus the requests it makes of the terminal
+        let (output, requests) = osc::extract(&result.output);
+        self.osc.extend(requests);
+        self.mouse.observe(output.as_bytes());
+        if !output.is_empty() {
+            self.print(&output);
         }
         if !result.error.is_empty() {
             self.print_error(&result.error);
@@ -408,8 +459,12 @@ impl Terminal {
         }
     }
 
-    /// Update the prompt based on current directory
+    /// Update the prompt based on current directory, or the script REPL
     fn update_prompt(&mut self) {
+        if let Some(prompt) = self.executor.script_prompt() {
+            self.prompt = prompt.to_string();
+            return;
+        }
         let cwd = self.executor.state.cwd.display().to_string();
         // Shorten home directory
         let home = self.executor.state.get_env("HOME").unwrap_or("/home");
@@ -480,6 +535,22 @@ impl Terminal {
         self.cursor = start;
     }
 
+    /// Drop scrollback, keeping the last `keep` lines and never fewer than
+    /// fit on screen; returns how many lines were dropped
+    pub fn trim_scrollback(&mut self, keep: usize) -> usize {
+        let keep = keep.max(self.visible_rows);
+        let dropped = self.lines.len().saturating_sub(keep);
+        if dropped > 0 {
+            self.lines.drain(..dropped);
+            self.scroll_offset = self
+                .scroll_offset
+                .min(keep.saturating_sub(self.visible_rows));
+            // Selections are by line number, which just changed
+            self.selection = None;
+        }
+        dropped
+    }
+
     /// Scroll up by n lines
     pub fn scroll_up(&mut self, n: usize) {
         let max_scroll = self.lines.len().saturating_sub(self.visible_rows);
@@ -524,6 +595,39 @@ impl Terminal {
         self.lines.len()
     }
 
+    /// Mouse events being reported to programs
+    pub fn mouse_tracking(&self) -> MouseTracking {
+        self.mouse
+    }
+
+    /// Handle a mouse event at a 1-based cell of the visible area
+    ///
+    /// While a program has mouse reporting on, the event is reported to it
+    /// as terminal input; otherwise the left button selects text and the
+    /// wheel scrolls. Returns true if the event was used.
+    pub fn handle_mouse(&mut self, event: &MouseEvent) -> bool {
+        if let Some(report) = self.mouse.encode(event) {
+            let tty = syscall::getpid()
+                .ok()
+                .and_then(syscall::process_tty)
+                .unwrap_or_else(|| "console".to_string());
+            return syscall::tty_input(&tty, &report).is_ok();
+        }
+        let (line, col) = (
+            usize::from(event.row.saturating_sub(1)),
+            usize::from(event.col.saturating_sub(1)),
+        );
+        match event.action {
+            MouseAction::Press(MouseButton::Left) => self.start_selection(line, col),
+            MouseAction::Drag(MouseButton::Left) => self.update_selection(line, col),
+            MouseAction::Release(MouseButton::Left) => self.finish_selection(),
+            MouseAction::ScrollUp => self.scroll_up(3),
+            MouseAction::ScrollDown => self.scroll_down(3),
+            _ => return false,
+        }
+        true
+    }
+
     // ==================== Selection Methods ====================
 
     /// Start a selection at the given position (mouse down)
@@ -671,8 +775,9 @@ impl Terminal {
             // Complete command
             self.complete_command(&prefix)
         } else {
-            // Complete file path
-            self.complete_file(&prefix)
+            // Complete an argument, as the command's spec describes it
+            let before = &words[..words.len() - usize::from(!prefix.is_empty())];
+            self.complete_argument(before, &prefix)
         };
 
         if completions.is_empty() {
@@ -721,7 +826,7 @@ impl Terminal {
         // Built-in commands
         let builtins = [
             "cd", "pwd", "exit", "echo", "export", "unset", "env", "true", "false", "help",
-            "alias", "unalias",
+            "alias", "unalias", "set",
         ];
         for cmd in builtins {
             if cmd.starts_with(prefix) {
@@ -740,6 +845,28 @@ impl Terminal {
         completions
     }
 
+    /// Get completions for an argument of the command `before` starts
+    /// with; without a spec, every argument is a path
+    fn complete_argument(&self, before: &[&str], prefix: &str) -> Vec<String> {
+        use crate::shell::argparse::Completion;
+
+        let completion = before
+            .first()
+            .and_then(|name| crate::shell::programs::spec(name))
+            .map(|spec| spec.complete(before, prefix))
+            .unwrap_or(Completion::Path);
+        match completion {
+            Completion::Words(words) => words,
+            Completion::Path => self.complete_file(prefix),
+            Completion::Dir => {
+                let mut dirs = self.complete_file(prefix);
+                dirs.retain(|path| path.ends_with('/'));
+                dirs
+            }
+            Completion::Command => self.complete_command(prefix),
+        }
+    }
+
     /// Get file completions
     fn complete_file(&self, prefix: &str) -> Vec<String> {
         use crate::kernel::syscall;
@@ -988,6 +1115,64 @@ mod tests {
         assert!(has_path);
     }
 
+    #[test]
+    fn test_terminal_with_profile() {
+        use crate::kernel::syscall::{KERNEL, Kernel};
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+
+        let mut profile = TerminalProfile::new("work");
+        profile.cwd = Some("/tmp".to_string());
+        profile.startup = Some("echo started".to_string());
+
+        let term = Terminal::with_profile(profile);
+        assert_eq!(term.prompt, "/tmp $ ");
+        assert!(
+            term.lines
+                .iter()
+                .any(|l| l.is_input && l.text == "/tmp $ echo started")
+        );
+        assert!(
+            term.lines
+                .iter()
+                .any(|l| !l.is_input && l.text == "started")
+        );
+        assert_eq!(term.profile().map(|p| p.name.as_str()), Some("work"));
+
+        let mut profile = TerminalProfile::new("broken");
+        profile.cwd = Some("/no/such/dir".to_string());
+        let term = Terminal::with_profile(profile);
+        assert!(term.lines.iter().any(|l| l.text.contains("No such file")));
+    }
+
+    #[test]
+    fn test_trim_scrollback() {
+        let mut term = Terminal::new();
+        term.set_visible_rows(10);
+        for i in 0..100 {
+            term.print(&format!("line {}", i));
+        }
+        term.scroll_up(80);
+        term.start_selection(5, 0);
+
+        // 100 lines after the three of the welcome message
+        assert_eq!(term.trim_scrollback(30), 73);
+        assert_eq!(term.line_count(), 30);
+        assert!(term.selection().is_none());
+        assert_eq!(term.visible_lines().count(), 10);
+        assert_eq!(term.lines.back().unwrap().text, "line 99");
+        assert_eq!(term.lines.front().unwrap().text, "line 70");
+
+        // What is on screen stays
+        assert_eq!(term.trim_scrollback(0), 20);
+        assert_eq!(term.trim_scrollback(0), 0);
+        assert_eq!(term.line_count(), 10);
+    }
+
     #[test]
     fn test_terminal_max_lines_trimming() {
         let mut term = Terminal::new();
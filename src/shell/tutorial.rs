@@ -0,0 +1,435 @@
+//! Tutorial - guided lessons for new users
+//!
+//! A lesson is a list of small tasks ("make a directory named projects").
+//! Each task has a [`Check`] that looks at the system to decide whether the
+//! user has done it: the command line that just ran, the shell's working
+//! directory, files in the VFS or the terminal profiles. The executor calls
+//! [`observe`] after every command line, so the tutorial notices completed
+//! tasks however the user got there and moves on to the next one.
+//!
+//! Progress is kept in [`STATE_FILE`] in the user's home directory, so a
+//! lesson survives a reload and can be resumed with `tutorial`.
+//!
+//! ```text
+//! $ tutorial shell
+//! Lesson: The shell (7 steps)
+//! Step 1/7: Print the directory you are in
+//! $ pwd
+//! /home/user
+//! ✓ Print the directory you are in
+//! Step 2/7: List the files in your home directory
+//! ```
+
+use super::parser;
+use super::profile;
+use crate::kernel::syscall::{self, OpenFlags};
+use std::collections::BTreeSet;
+
+/// Tutorial progress, relative to the home directory
+pub const STATE_FILE: &str = ".tutorial";
+
+/// How a step decides it is done
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Check {
+    /// A command line running this program succeeded
+    Ran(&'static str),
+    /// A command line with a pipe succeeded
+    Pipeline,
+    /// A directory exists (relative to home)
+    Dir(&'static str),
+    /// A file (relative to home) contains the text; an empty needle only
+    /// asks for a non-empty file
+    Contains(&'static str, &'static str),
+    /// The shell's working directory (relative to home)
+    Cwd(&'static str),
+    /// A terminal profile exists
+    Profile(&'static str),
+    /// A terminal profile setting has this value
+    ProfileSetting(&'static str, &'static str, &'static str),
+}
+
+/// One task of a lesson
+#[derive(Debug, Clone, Copy)]
+pub struct Step {
+    pub task: &'static str,
+    pub hint: &'static str,
+    pub check: Check,
+}
+
+/// A lesson: an ordered list of steps
+#[derive(Debug, Clone, Copy)]
+pub struct Lesson {
+    pub name: &'static str,
+    pub title: &'static str,
+    pub steps: &'static [Step],
+}
+
+const fn step(task: &'static str, hint: &'static str, check: Check) -> Step {
+    Step { task, hint, check }
+}
+
+/// Lessons, in the order they are suggested
+pub const LESSONS: &[Lesson] = &[
+    Lesson {
+        name: "shell",
+        title: "The shell",
+        steps: &[
+            step("Print the directory you are in", "pwd", Check::Ran("pwd")),
+            step(
+                "List the files in your home directory",
+                "ls",
+                Check::Ran("ls"),
+            ),
+            step(
+                "Make a directory named projects in your home directory",
+                "mkdir projects",
+                Check::Dir("projects"),
+            ),
+            step(
+                "Change into projects",
+                "cd projects",
+                Check::Cwd("projects"),
+            ),
+            step(
+                "Create projects/hello.txt containing the word hello",
+                "echo hello > hello.txt",
+                Check::Contains("projects/hello.txt", "hello"),
+            ),
+            step(
+                "Count the words in hello.txt by piping it into wc",
+                "cat hello.txt | wc -w",
+                Check::Pipeline,
+            ),
+            step("Read the manual page for ls", "man ls", Check::Ran("man")),
+        ],
+    },
+    Lesson {
+        name: "editor",
+        title: "The editor",
+        steps: &[
+            step(
+                "Write a line to todo.txt in your home directory with the editor",
+                "cd; edit todo.txt - type a line, save with Ctrl+S, quit with Ctrl+Q, then run tutorial",
+                Check::Contains("todo.txt", ""),
+            ),
+            step(
+                "Search todo.txt for a word you wrote",
+                "grep WORD todo.txt",
+                Check::Ran("grep"),
+            ),
+        ],
+    },
+    Lesson {
+        name: "desktop",
+        title: "Terminal and desktop",
+        steps: &[
+            step(
+                "List your terminal profiles",
+                "profile list",
+                Check::Ran("profile"),
+            ),
+            step(
+                "Create a terminal profile named big",
+                "profile new big",
+                Check::Profile("big"),
+            ),
+            step(
+                "Give the big profile an 18px font",
+                "profile set big font_size 18",
+                Check::ProfileSetting("big", "font_size", "18"),
+            ),
+            step(
+                "Lock the session, then unlock it with your password",
+                "lock",
+                Check::Ran("lock"),
+            ),
+        ],
+    },
+];
+
+/// Look up a lesson by name
+pub fn lesson(name: &str) -> Option<&'static Lesson> {
+    LESSONS.iter().find(|l| l.name == name)
+}
+
+/// What the tutorial is looking at after a command
+#[derive(Debug, Clone, Copy)]
+pub struct Observed<'a> {
+    /// The command line that ran, if any
+    pub line: Option<&'a str>,
+    /// Its exit code
+    pub code: i32,
+    /// The shell's working directory
+    pub cwd: &'a str,
+}
+
+/// Tutorial progress of one user
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// Lesson in progress and its current step (0-based)
+    pub current: Option<(String, usize)>,
+    /// Lessons finished
+    pub done: BTreeSet<String>,
+}
+
+impl Progress {
+    /// Parse the state file (`lesson=`, `step=`, `done=` lines)
+    pub fn parse(text: &str) -> Self {
+        let mut progress = Self::default();
+        let mut lesson = None;
+        let mut step = 0;
+        for line in text.lines() {
+            match line.split_once('=') {
+                Some(("lesson", name)) if !name.is_empty() => lesson = Some(name.to_string()),
+                Some(("step", n)) => step = n.trim().parse().unwrap_or(0),
+                Some(("done", names)) => {
+                    progress.done = names
+                        .split(',')
+                        .filter(|n| !n.is_empty())
+                        .map(String::from)
+                        .collect();
+                }
+                _ => {}
+            }
+        }
+        progress.current = lesson.map(|l| (l, step));
+        progress
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        if let Some((lesson, step)) = &self.current {
+            out.push_str(&format!("lesson={}\nstep={}\n", lesson, step));
+        }
+        let done: Vec<&str> = self.done.iter().map(String::as_str).collect();
+        out.push_str(&format!("done={}\n", done.join(",")));
+        out
+    }
+
+    /// The lesson and step in progress
+    pub fn current_step(&self) -> Option<(&'static Lesson, usize)> {
+        let (name, step) = self.current.as_ref()?;
+        let lesson = lesson(name)?;
+        (*step < lesson.steps.len()).then_some((lesson, *step))
+    }
+}
+
+/// The user's home directory
+fn home() -> String {
+    syscall::getenv("HOME")
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| "/".to_string())
+}
+
+fn state_path(home: &str) -> String {
+    format!("{}/{}", home.trim_end_matches('/'), STATE_FILE)
+}
+
+/// Load the current user's progress
+pub fn load() -> Progress {
+    syscall::read_file(&state_path(&home()))
+        .map(|text| Progress::parse(&text))
+        .unwrap_or_default()
+}
+
+/// Save the current user's progress
+pub fn save(progress: &Progress) -> Result<(), String> {
+    let path = state_path(&home());
+    let fd = syscall::open(&path, OpenFlags::WRITE).map_err(|e| format!("{}: {}", path, e))?;
+    let result = syscall::write(fd, progress.to_text().as_bytes());
+    let _ = syscall::close(fd);
+    result.map(|_| ()).map_err(|e| format!("{}: {}", path, e))
+}
+
+/// "Step 2/7: task"
+pub fn describe(lesson: &Lesson, step: usize) -> String {
+    format!(
+        "Step {}/{}: {}",
+        step + 1,
+        lesson.steps.len(),
+        lesson.steps[step].task
+    )
+}
+
+/// Programs a command line runs
+fn programs(line: &str) -> Vec<String> {
+    let Ok(list) = parser::parse_command_list(line) else {
+        return Vec::new();
+    };
+    std::iter::once(&list.first)
+        .chain(list.rest.iter().map(|(_, p)| p))
+        .flat_map(|p| p.commands.iter().map(|c| c.program.clone()))
+        .collect()
+}
+
+fn is_pipeline(line: &str) -> bool {
+    parser::parse_command_list(line).is_ok_and(|list| {
+        std::iter::once(&list.first)
+            .chain(list.rest.iter().map(|(_, p)| p))
+            .any(|p| p.commands.len() > 1)
+    })
+}
+
+/// Check whether a step is done
+pub fn is_done(check: Check, home: &str, observed: &Observed) -> bool {
+    let path = |rel: &str| format!("{}/{}", home.trim_end_matches('/'), rel);
+    let ran = observed.code == 0;
+    match check {
+        Check::Ran(program) => {
+            ran && observed
+                .line
+                .is_some_and(|line| programs(line).iter().any(|p| p == program))
+        }
+        Check::Pipeline => ran && observed.line.is_some_and(is_pipeline),
+        Check::Dir(rel) => syscall::metadata(&path(rel)).is_ok_and(|m| m.is_dir),
+        Check::Contains(rel, needle) => syscall::read_file(&path(rel))
+            .is_ok_and(|text| !text.trim().is_empty() && text.contains(needle)),
+        Check::Cwd(rel) => observed.cwd.trim_end_matches('/') == path(rel),
+        Check::Profile(name) => profile::load().is_ok_and(|p| p.get(name).is_some()),
+        Check::ProfileSetting(name, key, value) => profile::load().is_ok_and(|p| {
+            p.get(name)
+                .and_then(|p| p.get(key))
+                .is_some_and(|v| v == value)
+        }),
+    }
+}
+
+/// Advance the lesson in progress past every step that is now done,
+/// returning what to tell the user, if anything
+pub fn observe(observed: &Observed) -> Option<String> {
+    let home = home();
+    let mut progress = load();
+    let (lesson, mut step) = progress.current_step()?;
+
+    let mut out = String::new();
+    while step < lesson.steps.len() && is_done(lesson.steps[step].check, &home, observed) {
+        out.push_str(&format!("✓ {}\n", lesson.steps[step].task));
+        step += 1;
+    }
+    if out.is_empty() {
+        return None;
+    }
+
+    if step < lesson.steps.len() {
+        out.push_str(&describe(lesson, step));
+        progress.current = Some((lesson.name.to_string(), step));
+    } else {
+        progress.current = None;
+        progress.done.insert(lesson.name.to_string());
+        out.push_str(&format!("Lesson '{}' complete!", lesson.name));
+        match LESSONS.iter().find(|l| !progress.done.contains(l.name)) {
+            Some(next) => out.push_str(&format!(" Next: tutorial {}", next.name)),
+            None => out.push_str(" You have finished every lesson."),
+        }
+    }
+    if let Err(e) = save(&progress) {
+        out.push_str(&format!("\ntutorial: {}", e));
+    }
+    Some(out)
+}
+
+/// Check whether a command line runs the `tutorial` program itself
+pub fn is_tutorial_command(line: &str) -> bool {
+    programs(line).iter().any(|p| p == "tutorial")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::syscall::{KERNEL, Kernel};
+
+    fn setup() {
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+    }
+
+    #[test]
+    fn test_progress_roundtrip() {
+        let mut progress = Progress {
+            current: Some(("shell".to_string(), 3)),
+            ..Progress::default()
+        };
+        progress.done.insert("editor".to_string());
+        let text = progress.to_text();
+        assert_eq!(Progress::parse(&text), progress);
+        assert_eq!(Progress::parse(""), Progress::default());
+    }
+
+    #[test]
+    fn test_checks() {
+        setup();
+        let observed = |line, cwd| Observed {
+            line: Some(line),
+            code: 0,
+            cwd,
+        };
+        let home = "/home/user";
+        let o = observed("ls -l | grep txt && pwd", home);
+        assert!(is_done(Check::Ran("grep"), home, &o));
+        assert!(is_done(Check::Ran("pwd"), home, &o));
+        assert!(!is_done(Check::Ran("cat"), home, &o));
+        assert!(is_done(Check::Pipeline, home, &o));
+        assert!(!is_done(
+            Check::Pipeline,
+            home,
+            &observed("ls || pwd", home)
+        ));
+        let failed = Observed { code: 1, ..o };
+        assert!(!is_done(Check::Ran("pwd"), home, &failed));
+
+        assert!(!is_done(Check::Dir("projects"), home, &o));
+        syscall::mkdir("/home/user/projects").unwrap();
+        assert!(is_done(Check::Dir("projects"), home, &o));
+        let o = observed("cd projects", "/home/user/projects");
+        assert!(is_done(Check::Cwd("projects"), home, &o));
+
+        let check = Check::Contains("projects/hello.txt", "hello");
+        assert!(!is_done(check, home, &o));
+        syscall::write_file("/home/user/projects/hello.txt", "hello\n").unwrap();
+        assert!(is_done(check, home, &o));
+    }
+
+    #[test]
+    fn test_observe_advances() {
+        setup();
+        let progress = Progress {
+            current: Some(("shell".to_string(), 0)),
+            ..Progress::default()
+        };
+        save(&progress).unwrap();
+
+        let run = |line: &str| {
+            observe(&Observed {
+                line: Some(line),
+                code: 0,
+                cwd: "/home/user",
+            })
+        };
+        assert!(run("echo hi").is_none());
+        let out = run("pwd").unwrap();
+        assert!(out.starts_with("✓ Print the directory"));
+        assert!(out.ends_with("Step 2/7: List the files in your home directory"));
+
+        // Steps already done are passed over together
+        syscall::mkdir("/home/user/projects").unwrap();
+        let out = run("ls").unwrap();
+        assert_eq!(out.matches('✓').count(), 2);
+        assert_eq!(load().current, Some(("shell".to_string(), 3)));
+
+        // Finishing the lesson records it and suggests the next one
+        let mut progress = load();
+        progress.current = Some(("shell".to_string(), 6));
+        save(&progress).unwrap();
+        let out = run("man ls").unwrap();
+        assert!(out.contains("Lesson 'shell' complete! Next: tutorial editor"));
+        let progress = load();
+        assert!(progress.current.is_none());
+        assert!(progress.done.contains("shell"));
+        assert!(run("pwd").is_none());
+    }
+}
@@ -0,0 +1,413 @@
+//! Unicode character lookup and the character picker
+//!
+//! Names come from the Unicode character database and widths are the
+//! columns a terminal gives a character: 2 for East Asian wide characters
+//! and most emoji, 0 for combining marks. `unicode` prints them; `chars`
+//! (and Alt+U at the prompt or in the editor) opens a [`Picker`] to insert
+//! a character at the cursor.
+
+use std::ops::RangeInclusive;
+
+use unicode_width::UnicodeWidthChar;
+
+/// Code points searched by name: the planes that have named characters
+const NAMED: [RangeInclusive<u32>; 2] = [0x0..=0x3FFFF, 0xE0000..=0xE01EF];
+
+/// Longest range `unicode` prints in one go
+pub const MAX_RANGE: u32 = 0x1000;
+
+/// Characters offered before anything is searched for, by group
+pub const GROUPS: &[(&str, &str)] = &[
+    ("Arrows", "←↑→↓↔↕↖↗↘↙⇐⇑⇒⇓⇔⇕↩↪↺↻⟵⟶⟷⟸⟹⇄⇆⇤⇥↞↠➜➔"),
+    (
+        "Math",
+        "±×÷≠≈≡≤≥∞√∛∑∏∫∂∆∇∈∉∋∩∪⊂⊃⊆⊇∀∃∄¬∧∨⊕⊗⊥∝∠°′″µπ∅ℕℤℚℝℂ½⅓¼¾",
+    ),
+    ("Greek", "αβγδεζηθικλμνξοπρστυφχψωΓΔΘΛΞΠΣΦΨΩ"),
+    ("Currency", "$¢£¥€₹₽₩₪₫₺₴₱₿¤"),
+    ("Punctuation", "–—…•·‘’‚“”„«»‹›¡¿§¶†‡©®™№‰‽⁂※"),
+    ("Box drawing", "─│┌┐└┘├┤┬┴┼═║╔╗╚╝╠╣╦╩╬╭╮╯╰░▒▓█▀▄▌▐"),
+    ("Shapes", "■□▪▫▲△▶▷▼▽◀◁●○◆◇◉◎★☆✓✗✔✘♠♣♥♦♪♫☐☑☒"),
+    (
+        "Emoji",
+        "😀😂🙂😉😍😎🤔😢😡😴👍👎👏🙏💪👀🎉🔥✨⭐💡📁📄🔒🔑🐛🚀✅❌⚡☕🍕🌍🌙🌈",
+    ),
+];
+
+/// `U+XXXX` or `0xXXXX` as a character
+pub fn parse_codepoint(s: &str) -> Option<char> {
+    let hex = s
+        .strip_prefix("U+")
+        .or_else(|| s.strip_prefix("u+"))
+        .or_else(|| s.strip_prefix("0x"))
+        .or_else(|| s.strip_prefix("0X"))?;
+    if hex.is_empty() || hex.len() > 6 {
+        return None;
+    }
+    char::from_u32(u32::from_str_radix(hex, 16).ok()?)
+}
+
+/// `U+XXXX..U+YYYY` as a range of code points
+pub fn parse_range(s: &str) -> Option<RangeInclusive<u32>> {
+    let (from, to) = s.split_once("..")?;
+    let from = parse_hex(from)?;
+    let to = parse_hex(to)?;
+    (from <= to).then_some(from..=to)
+}
+
+fn parse_hex(s: &str) -> Option<u32> {
+    let hex = s
+        .strip_prefix("U+")
+        .or_else(|| s.strip_prefix("u+"))
+        .or_else(|| s.strip_prefix("0x"))?;
+    u32::from_str_radix(hex, 16).ok().filter(|&n| n <= 0x10FFFF)
+}
+
+/// The character's name, or a description in angle brackets for the ones
+/// without (`<control>`, `<private use>`, `<unassigned>`)
+pub fn name(c: char) -> String {
+    if let Some(name) = unicode_names2::name(c) {
+        return name.to_string();
+    }
+    let code = c as u32;
+    if c.is_control() {
+        String::from("<control>")
+    } else if (0xE000..=0xF8FF).contains(&code) || code >= 0xF0000 {
+        String::from("<private use>")
+    } else {
+        String::from("<unassigned>")
+    }
+}
+
+/// The character named `name`, case insensitively
+pub fn lookup(name: &str) -> Option<char> {
+    unicode_names2::character(name)
+}
+
+/// Columns the character takes in a terminal; `None` for controls
+pub fn width(c: char) -> Option<usize> {
+    c.width()
+}
+
+/// Columns `text` takes in a terminal, controls counting for nothing
+pub fn str_width(text: &str) -> usize {
+    text.chars().filter_map(width).sum()
+}
+
+/// The character's UTF-8 encoding as hex bytes
+pub fn utf8(c: char) -> String {
+    let mut buf = [0; 4];
+    let bytes: Vec<String> = c
+        .encode_utf8(&mut buf)
+        .bytes()
+        .map(|b| format!("{:02X}", b))
+        .collect();
+    bytes.join(" ")
+}
+
+/// The character as it can be shown on its own: combining marks go on a
+/// dotted circle, controls are left out
+pub fn shown(c: char) -> String {
+    match width(c) {
+        None => String::new(),
+        Some(0) => format!("\u{25CC}{}", c),
+        Some(_) => c.to_string(),
+    }
+}
+
+/// A line describing the character: code point, the character, its UTF-8
+/// bytes, width and name
+pub fn describe(c: char) -> String {
+    let width = width(c).map_or(String::from("-"), |w| w.to_string());
+    let shown = shown(c);
+    let pad = 2usize.saturating_sub(str_width(&shown));
+    format!(
+        "U+{:04X}  {}{}  {:<11}  {}  {}",
+        c as u32,
+        shown,
+        " ".repeat(pad),
+        utf8(c),
+        width,
+        name(c)
+    )
+}
+
+/// Characters whose names have every word of `query` as the start of one
+/// of their words, in code point order, at most `limit` of them
+pub fn search(query: &str, limit: usize) -> Vec<char> {
+    let words = query_words(query);
+    if words.is_empty() {
+        return Vec::new();
+    }
+    NAMED
+        .iter()
+        .flat_map(|range| range.clone())
+        .filter_map(char::from_u32)
+        .filter(|&c| matches(c, &words))
+        .take(limit)
+        .collect()
+}
+
+fn query_words(query: &str) -> Vec<String> {
+    query.split_whitespace().map(str::to_uppercase).collect()
+}
+
+/// Whether every one of `words` starts a word of the character's name
+fn matches(c: char, words: &[String]) -> bool {
+    unicode_names2::name(c).is_some_and(|name| {
+        let name = name.to_string();
+        words.iter().all(|word| {
+            name.split([' ', '-'])
+                .any(|part| part.starts_with(word.as_str()))
+        })
+    })
+}
+
+/// State of the character picker: a grid of one group's characters, or of
+/// the search results while there is a query
+#[derive(Debug, Clone)]
+pub struct Picker {
+    group: usize,
+    query: String,
+    results: Vec<char>,
+    selected: usize,
+    /// Cells in a row of the grid
+    per_row: usize,
+}
+
+/// Columns each cell of the grid takes
+const CELL: usize = 4;
+
+/// Most search results shown
+const MAX_RESULTS: usize = 400;
+
+impl Picker {
+    /// A picker `cols` columns wide, searching for `query` if not empty
+    pub fn new(query: &str, cols: usize) -> Self {
+        let mut picker = Self {
+            group: 0,
+            query: String::new(),
+            results: Vec::new(),
+            selected: 0,
+            per_row: 1,
+        };
+        picker.resize(cols);
+        picker.set_query(query);
+        picker
+    }
+
+    pub fn resize(&mut self, cols: usize) {
+        self.per_row = (cols.saturating_sub(2) / CELL).max(1);
+    }
+
+    /// Characters in the grid
+    pub fn chars(&self) -> Vec<char> {
+        match self.query.is_empty() {
+            true => GROUPS[self.group].1.chars().collect(),
+            false => self.results.clone(),
+        }
+    }
+
+    /// The character under the cursor
+    pub fn selected(&self) -> Option<char> {
+        self.chars().get(self.selected).copied()
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    fn set_query(&mut self, query: &str) {
+        // Typing more only narrows the results, unless they were cut short
+        let narrower = !self.query.trim().is_empty()
+            && query.starts_with(&self.query)
+            && self.results.len() < MAX_RESULTS;
+        self.results = match narrower {
+            true => {
+                let words = query_words(query);
+                self.results
+                    .iter()
+                    .copied()
+                    .filter(|&c| matches(c, &words))
+                    .collect()
+            }
+            false => search(query, MAX_RESULTS),
+        };
+        self.query = query.to_string();
+        self.selected = 0;
+    }
+
+    /// Add to the search
+    pub fn type_char(&mut self, c: char) {
+        let query = format!("{}{}", self.query, c);
+        self.set_query(&query);
+    }
+
+    /// Take the last character off the search
+    pub fn backspace(&mut self) {
+        let mut query = self.query.clone();
+        query.pop();
+        self.set_query(&query);
+    }
+
+    /// Drop the search; false if there was none
+    pub fn clear(&mut self) -> bool {
+        if self.query.is_empty() {
+            return false;
+        }
+        self.set_query("");
+        true
+    }
+
+    /// Show the next group, or the previous one
+    pub fn next_group(&mut self, forward: bool) {
+        let n = GROUPS.len();
+        self.group = match forward {
+            true => (self.group + 1) % n,
+            false => (self.group + n - 1) % n,
+        };
+        self.set_query("");
+    }
+
+    /// Move the cursor `dx` cells across and `dy` rows down, staying in
+    /// the grid
+    pub fn move_by(&mut self, dx: isize, dy: isize) {
+        let len = self.chars().len();
+        if len == 0 {
+            return;
+        }
+        let target = self.selected as isize + dx + dy * self.per_row as isize;
+        self.selected = target.clamp(0, len as isize - 1) as usize;
+    }
+
+    /// The picker as a screen of `rows` lines
+    pub fn render(&self, rows: usize) -> String {
+        let mut out = String::from("\x1b[H\x1b[2J");
+        let tabs: Vec<String> = GROUPS
+            .iter()
+            .enumerate()
+            .map(
+                |(i, (name, _))| match i == self.group && self.query.is_empty() {
+                    true => format!("\x1b[7m {} \x1b[0m", name),
+                    false => format!(" {} ", name),
+                },
+            )
+            .collect();
+        out.push_str(&tabs.join(""));
+        out.push_str(&format!("\r\n\x1b[1mSearch:\x1b[0m {}\r\n", self.query));
+
+        let chars = self.chars();
+        // Two header lines, and three for the description and keys
+        let grid_rows = rows.saturating_sub(5).max(1);
+        let cursor_row = self.selected / self.per_row;
+        let first = cursor_row.saturating_sub(grid_rows - 1);
+        for row in chars
+            .chunks(self.per_row)
+            .enumerate()
+            .skip(first)
+            .take(grid_rows)
+        {
+            out.push_str("\r\n ");
+            for (i, &c) in row.1.iter().enumerate() {
+                let shown = shown(c);
+                let pad = CELL.saturating_sub(str_width(&shown) + 2);
+                let cell = format!(" {}{} ", shown, " ".repeat(pad));
+                match row.0 * self.per_row + i == self.selected {
+                    true => out.push_str(&format!("\x1b[7m{}\x1b[0m", cell)),
+                    false => out.push_str(&cell),
+                }
+            }
+        }
+        if chars.is_empty() {
+            out.push_str("\r\n \x1b[2mNo characters match\x1b[0m");
+        }
+        if let Some(c) = self.selected() {
+            out.push_str(&format!(
+                "\x1b[{};1H{}",
+                rows.saturating_sub(1),
+                describe(c)
+            ));
+        }
+        out.push_str(&format!(
+            "\x1b[{};1H\x1b[2mArrows move, Tab changes group, type to search, Enter inserts, Esc closes\x1b[0m",
+            rows
+        ));
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        assert_eq!(parse_codepoint("U+2192"), Some('→'));
+        assert_eq!(parse_codepoint("0x41"), Some('A'));
+        assert_eq!(parse_codepoint("U+D800"), None);
+        assert_eq!(parse_codepoint("2192"), None);
+        assert_eq!(parse_range("U+41..U+43"), Some(0x41..=0x43));
+        assert_eq!(parse_range("U+43..U+41"), None);
+
+        assert_eq!(name('→'), "RIGHTWARDS ARROW");
+        assert_eq!(name('\n'), "<control>");
+        assert_eq!(name('\u{E000}'), "<private use>");
+        assert_eq!(lookup("greek small letter lamda"), Some('λ'));
+        assert_eq!(lookup("no such character"), None);
+
+        assert_eq!(utf8('A'), "41");
+        assert_eq!(utf8('€'), "E2 82 AC");
+        assert_eq!(utf8('😀'), "F0 9F 98 80");
+        assert_eq!(width('A'), Some(1));
+        assert_eq!(width('中'), Some(2));
+        assert_eq!(width('\u{301}'), Some(0));
+        assert_eq!(width('\t'), None);
+        assert_eq!(str_width("a中😀"), 5);
+        assert_eq!(shown('\u{301}'), "◌\u{301}");
+        assert_eq!(shown('\x07'), "");
+        assert_eq!(describe('€'), "U+20AC  €   E2 82 AC     1  EURO SIGN");
+    }
+
+    #[test]
+    fn test_search() {
+        let arrows = search("right arrow", 1000);
+        assert!(arrows.contains(&'→'));
+        assert!(arrows.contains(&'⇒'));
+        assert!(!arrows.contains(&'←'));
+        assert_eq!(search("snowman", 1)[0], '☃');
+        assert_eq!(search("grinning", 100).len(), search("GRIN", 100).len());
+        assert!(search("", 10).is_empty());
+        assert_eq!(search("right arrow", 3).len(), 3);
+    }
+
+    #[test]
+    fn test_picker() {
+        let mut picker = Picker::new("", 22);
+        assert_eq!(picker.selected(), Some('←'));
+        picker.move_by(1, 1);
+        // 5 cells a row
+        assert_eq!(picker.selected(), Some('↖'));
+        picker.move_by(-100, 0);
+        assert_eq!(picker.selected(), Some('←'));
+        picker.next_group(false);
+        assert_eq!(picker.selected(), Some('😀'));
+        picker.next_group(true);
+        picker.next_group(true);
+        assert_eq!(picker.selected(), Some('±'));
+
+        for c in "euro".chars() {
+            picker.type_char(c);
+        }
+        assert_eq!(picker.query(), "euro");
+        assert!(picker.chars().contains(&'€'));
+        picker.type_char('x');
+        assert_eq!(picker.selected(), None);
+        assert!(picker.render(10).contains("No characters match"));
+        picker.backspace();
+        assert!(picker.selected().is_some());
+        assert!(picker.clear());
+        assert!(!picker.clear());
+        assert_eq!(picker.selected(), Some('±'));
+        assert!(picker.render(10).contains("PLUS-MINUS SIGN"));
+    }
+}
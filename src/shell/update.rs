@@ -0,0 +1,586 @@
+//! System updates
+//!
+//! The update service checks a release manifest, a small JSON document at
+//! the URL set in [`CONFIG_PATH`], for a build newer than the one running:
+//!
+//! ```json
+//! {"version": "0.2.0", "notes_url": "https://example.com/CHANGELOG.md"}
+//! ```
+//!
+//! The release notes come inline as `notes` or from `notes_url`. When a
+//! newer version turns up the user is told once, at the terminal, and
+//! `update notes` shows what changed. `update apply` saves the filesystem
+//! and reloads the page with the version in its query string (`?v=0.2.0`),
+//! which the page adds to the URLs of the WASM bundle so the browser
+//! fetches the new one rather than using its cache. A deployment that
+//! can't be updated that way sets `"reload": false` in the manifest.
+//!
+//! The platform calls [`tick`] with the wall-clock time; a check starts
+//! when the configured interval has passed since the last one.
+
+use super::setup::as_root;
+use crate::kernel::syscall;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+
+/// Update settings
+pub const CONFIG_PATH: &str = "/etc/update.conf";
+
+/// What the last check found, kept across reloads
+pub const STATE_PATH: &str = "/var/lib/update/state";
+
+/// The running build
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// How often [`tick`] looks at the schedule, in milliseconds
+const CHECK_INTERVAL: f64 = 60_000.0;
+
+/// Settings in [`CONFIG_PATH`], one `key = value` per line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpdateConfig {
+    /// Release manifest URL
+    pub url: String,
+    /// Minutes between checks, 0 to only check on request
+    pub interval: u64,
+}
+
+impl Default for UpdateConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            interval: 360,
+        }
+    }
+}
+
+impl UpdateConfig {
+    /// Setting names, in the order they are written
+    pub const KEYS: [&'static str; 2] = ["url", "interval"];
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut config = Self::default();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(format!("line {}: expected key = value", n + 1));
+            };
+            config
+                .set(key.trim(), value.trim())
+                .map_err(|e| format!("line {}: {}", n + 1, e))?;
+        }
+        Ok(config)
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "url" => self.url = value.to_string(),
+            "interval" => {
+                self.interval = value
+                    .parse()
+                    .map_err(|_| format!("{}: not a number: {}", key, value))?
+            }
+            _ => return Err(format!("unknown setting: {}", key)),
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        Some(match key {
+            "url" => self.url.clone(),
+            "interval" => self.interval.to_string(),
+            _ => return None,
+        })
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::from("# System updates, see 'update --help'\n");
+        text.push_str(&format!("url = {}\n", self.url));
+        text.push_str(&format!("interval = {}\n", self.interval));
+        text
+    }
+}
+
+/// A release manifest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Release {
+    pub version: String,
+    /// Release notes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    /// Where to fetch the release notes from, if not inline
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes_url: Option<String>,
+    /// Whether reloading the page picks the release up
+    #[serde(default = "reloadable")]
+    pub reload: bool,
+}
+
+fn reloadable() -> bool {
+    true
+}
+
+impl Release {
+    pub fn parse(json: &[u8]) -> Result<Self, String> {
+        let release: Release =
+            serde_json::from_slice(json).map_err(|e| format!("bad manifest: {}", e))?;
+        if compare_versions(&release.version, VERSION).is_none() {
+            return Err(format!(
+                "bad manifest: invalid version: {}",
+                release.version
+            ));
+        }
+        Ok(release)
+    }
+
+    /// Check if this release is newer than the running build
+    pub fn is_newer(&self) -> bool {
+        compare_versions(&self.version, VERSION) == Some(Ordering::Greater)
+    }
+}
+
+/// Compare two `MAJOR.MINOR.PATCH[-PRERELEASE]` versions, with missing
+/// numbers taken as 0 and a pre-release before its release; None if
+/// either isn't a version
+pub fn compare_versions(a: &str, b: &str) -> Option<Ordering> {
+    fn parse(version: &str) -> Option<(Vec<u64>, Option<&str>)> {
+        let version = version.trim().trim_start_matches('v');
+        let (core, pre) = match version.split_once('-') {
+            Some((core, pre)) => (core, Some(pre)),
+            None => (version, None),
+        };
+        let numbers = core
+            .split('.')
+            .map(|n| n.parse().ok())
+            .collect::<Option<Vec<u64>>>()?;
+        let valid_pre = |pre: &str| {
+            !pre.is_empty()
+                && pre
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+        };
+        if pre.is_some_and(|pre| !valid_pre(pre)) {
+            return None;
+        }
+        Some((numbers, pre))
+    }
+    let (mut a_core, a_pre) = parse(a)?;
+    let (mut b_core, b_pre) = parse(b)?;
+    let len = a_core.len().max(b_core.len());
+    a_core.resize(len, 0);
+    b_core.resize(len, 0);
+    Some(a_core.cmp(&b_core).then_with(|| match (a_pre, b_pre) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+        (Some(a), Some(b)) => {
+            let mut a = a.split('.');
+            let mut b = b.split('.');
+            loop {
+                match (a.next(), b.next()) {
+                    (None, None) => return Ordering::Equal,
+                    (None, Some(_)) => return Ordering::Less,
+                    (Some(_), None) => return Ordering::Greater,
+                    (Some(x), Some(y)) => {
+                        let order = match (x.parse::<u64>(), y.parse::<u64>()) {
+                            (Ok(x), Ok(y)) => x.cmp(&y),
+                            (Ok(_), Err(_)) => Ordering::Less,
+                            (Err(_), Ok(_)) => Ordering::Greater,
+                            (Err(_), Err(_)) => x.cmp(y),
+                        };
+                        if order != Ordering::Equal {
+                            return order;
+                        }
+                    }
+                }
+            }
+        }
+    }))
+}
+
+/// The page's query string with `v` set to `version`, other parameters
+/// (such as `guest`) kept
+pub fn reload_search(search: &str, version: &str) -> String {
+    let mut params: Vec<&str> = search
+        .trim_start_matches('?')
+        .split('&')
+        .filter(|p| !p.is_empty() && *p != "v" && !p.starts_with("v="))
+        .collect();
+    let v = format!("v={}", version);
+    params.push(&v);
+    format!("?{}", params.join("&"))
+}
+
+/// What the last check found, in [`STATE_PATH`]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UpdateState {
+    /// Wall-clock time of the last successful check
+    pub checked: Option<f64>,
+    /// The release it found
+    pub latest: Option<Release>,
+    /// Newest version the user was told about
+    pub notified: Option<String>,
+}
+
+/// The settings, or the defaults if there are none yet
+pub fn load_config() -> Result<UpdateConfig, String> {
+    match syscall::read_file(CONFIG_PATH) {
+        Ok(text) => UpdateConfig::parse(&text).map_err(|e| format!("{}: {}", CONFIG_PATH, e)),
+        Err(_) => Ok(UpdateConfig::default()),
+    }
+}
+
+pub fn save_config(config: &UpdateConfig) -> Result<(), String> {
+    syscall::atomic_write(CONFIG_PATH, config.to_text().as_bytes())
+        .map_err(|e| format!("{}: {}", CONFIG_PATH, e))
+}
+
+pub fn read_state() -> UpdateState {
+    syscall::read_file(STATE_PATH)
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+fn write_state(state: &UpdateState) -> Result<(), String> {
+    let json = serde_json::to_string(state).map_err(|e| e.to_string())?;
+    as_root(|| {
+        for dir in ["/var", "/var/lib", "/var/lib/update"] {
+            if !syscall::exists(dir)? {
+                syscall::mkdir(dir)?;
+            }
+        }
+        syscall::atomic_write(STATE_PATH, json.as_bytes())
+    })
+    .map_err(|e| format!("{}: {}", STATE_PATH, e))
+}
+
+/// Record a release found at `now`, queueing a notice the first time a
+/// newer one turns up; returns the message for the kernel log
+pub fn record(release: Release, now: f64) -> Result<String, String> {
+    let mut state = read_state();
+    let message = if release.is_newer() {
+        format!(
+            "axeberg {} is available (running {})",
+            release.version, VERSION
+        )
+    } else {
+        format!("axeberg {} is up to date", VERSION)
+    };
+    if release.is_newer() && state.notified.as_deref() != Some(release.version.as_str()) {
+        state.notified = Some(release.version.clone());
+        let notice = format!(
+            "{}: 'update notes' shows what's new, 'update apply' installs it",
+            message
+        );
+        STATUS.with(|s| s.borrow_mut().notice = Some(notice));
+    }
+    state.checked = Some(now);
+    state.latest = Some(release);
+    write_state(&state)?;
+    Ok(message)
+}
+
+/// Check if a scheduled check is due at `now`
+fn is_due(config: &UpdateConfig, last: Option<f64>, now: f64) -> bool {
+    !config.url.is_empty()
+        && config.interval > 0
+        && last.is_none_or(|last| now - last >= config.interval as f64 * 60_000.0)
+}
+
+/// What the service is doing, for `update status`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Status {
+    /// A check is in flight
+    pub busy: bool,
+    /// Wall-clock time of the last attempt this session
+    pub last_attempt: Option<f64>,
+    pub last_error: Option<String>,
+    /// Waiting to be shown at the terminal
+    notice: Option<String>,
+    /// When the schedule was last looked at
+    checked: Option<f64>,
+}
+
+thread_local! {
+    static STATUS: RefCell<Status> = RefCell::new(Status::default());
+}
+
+pub fn status() -> Status {
+    STATUS.with(|s| s.borrow().clone())
+}
+
+/// The notice about a newer release, once
+pub fn take_notice() -> Option<String> {
+    STATUS.with(|s| s.borrow_mut().notice.take())
+}
+
+/// Mark the service idle again, logging how it went
+fn end(result: Result<String, String>) {
+    use crate::kernel::LogLevel;
+    let (level, message) = match &result {
+        Ok(message) => (LogLevel::Notice, message.clone()),
+        Err(e) => (LogLevel::Err, e.clone()),
+    };
+    syscall::klog(level, "update", &message);
+    STATUS.with(|s| {
+        let mut s = s.borrow_mut();
+        s.busy = false;
+        s.last_error = result.err();
+    });
+}
+
+/// Start checking for a newer release now; the result goes to the kernel
+/// log
+pub fn start_check(config: UpdateConfig, now: f64) -> Result<(), String> {
+    if config.url.is_empty() {
+        return Err(format!("no url set in {}", CONFIG_PATH));
+    }
+    let started = STATUS.with(|s| {
+        let mut s = s.borrow_mut();
+        if s.busy {
+            return false;
+        }
+        s.busy = true;
+        s.last_attempt = Some(now);
+        true
+    });
+    if !started {
+        return Err("a check is already running".to_string());
+    }
+    #[cfg(target_arch = "wasm32")]
+    wasm_bindgen_futures::spawn_local(async move {
+        let result = match remote::fetch_release(&config.url).await {
+            Ok(release) => record(release, now),
+            Err(e) => Err(e),
+        };
+        end(result);
+    });
+    #[cfg(not(target_arch = "wasm32"))]
+    end(Err("checking for updates needs a browser".to_string()));
+    Ok(())
+}
+
+/// Save the filesystem and reload the page into the newer release
+pub fn apply() -> Result<String, String> {
+    let state = read_state();
+    let Some(release) = state.latest.filter(Release::is_newer) else {
+        return Err(format!("no newer release known, running {}", VERSION));
+    };
+    if !release.reload {
+        return Err(format!(
+            "{} can't be installed by reloading; see 'update notes'",
+            release.version
+        ));
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        let version = release.version.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Err(e) = remote::reload(&version).await {
+                syscall::klog(crate::kernel::LogLevel::Err, "update", &e);
+            }
+        });
+        Ok(release.version)
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    Err("updating needs a browser".to_string())
+}
+
+/// Start a scheduled check if one is due at `now_ms` (wall-clock
+/// milliseconds); returns whether one started
+///
+/// Cheap to call on every executor tick: the schedule is only looked at
+/// once a minute.
+pub fn tick(now_ms: f64) -> bool {
+    let look = STATUS.with(|s| {
+        let mut s = s.borrow_mut();
+        if s.busy || s.checked.is_some_and(|t| now_ms - t < CHECK_INTERVAL) {
+            return false;
+        }
+        s.checked = Some(now_ms);
+        true
+    });
+    if !look {
+        return false;
+    }
+    let Ok(config) = load_config() else {
+        return false;
+    };
+    // After a failure, wait a full interval before trying again
+    let last = match (read_state().checked, status().last_attempt) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        (a, b) => a.or(b),
+    };
+    is_due(&config, last, now_ms) && start_check(config, now_ms).is_ok()
+}
+
+/// Talking to the release server
+#[cfg(target_arch = "wasm32")]
+mod remote {
+    use super::*;
+    use crate::kernel::network;
+
+    /// Fetch the manifest, and the notes if they aren't inline
+    pub async fn fetch_release(url: &str) -> Result<Release, String> {
+        let response = network::fetch(url).await?;
+        if !(200..300).contains(&response.status) {
+            return Err(format!(
+                "{}: HTTP {} {}",
+                url, response.status, response.status_text
+            ));
+        }
+        let mut release = Release::parse(&response.body).map_err(|e| format!("{}: {}", url, e))?;
+        if release.notes.is_none()
+            && let Some(notes_url) = &release.notes_url
+        {
+            match network::fetch(notes_url).await {
+                Ok(response) if (200..300).contains(&response.status) => {
+                    release.notes = response.text().ok();
+                }
+                Ok(response) => syscall::klog(
+                    crate::kernel::LogLevel::Warn,
+                    "update",
+                    &format!("{}: HTTP {}", notes_url, response.status),
+                ),
+                Err(e) => syscall::klog(crate::kernel::LogLevel::Warn, "update", &e),
+            }
+        }
+        Ok(release)
+    }
+
+    /// Save the filesystem, then reload with the version in the URL
+    pub async fn reload(version: &str) -> Result<(), String> {
+        use crate::vfs::{MemoryFs, Persistence};
+        let data = syscall::vfs_snapshot().map_err(|e| format!("snapshot failed: {}", e))?;
+        let fs = MemoryFs::from_json(&data).map_err(|e| format!("snapshot failed: {}", e))?;
+        Persistence::save(
+            &fs,
+            syscall::persist_journaled(),
+            syscall::persist_compressed(),
+        )
+        .await
+        .map_err(|e| format!("save failed, not reloading: {}", e))?;
+        let location = web_sys::window().ok_or("no window")?.location();
+        let search = location.search().unwrap_or_default();
+        location
+            .set_search(&reload_search(&search, version))
+            .map_err(|e| format!("reload failed: {:?}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::syscall::{KERNEL, Kernel};
+
+    fn setup() {
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        STATUS.with(|s| *s.borrow_mut() = Status::default());
+    }
+
+    #[test]
+    fn test_config() {
+        let config =
+            UpdateConfig::parse("# x\nurl = https://example.com/m.json\ninterval = 0\n").unwrap();
+        assert_eq!(config.url, "https://example.com/m.json");
+        assert_eq!(config.interval, 0);
+        assert_eq!(UpdateConfig::parse(&config.to_text()).unwrap(), config);
+        assert!(
+            UpdateConfig::parse("interval = soon\n")
+                .unwrap_err()
+                .starts_with("line 1")
+        );
+        assert!(UpdateConfig::parse("color = red\n").is_err());
+    }
+
+    #[test]
+    fn test_compare_versions() {
+        use Ordering::*;
+        for (a, b, order) in [
+            ("0.2.0", "0.1.0", Greater),
+            ("0.1.10", "0.1.9", Greater),
+            ("1.0", "1.0.0", Equal),
+            ("v1.2.3", "1.2.3", Equal),
+            ("1.0.0-rc.1", "1.0.0", Less),
+            ("1.0.0-rc.2", "1.0.0-rc.10", Less),
+            ("1.0.0-beta", "1.0.0-alpha", Greater),
+            ("1.0.0-alpha", "1.0.0-alpha.1", Less),
+        ] {
+            assert_eq!(compare_versions(a, b), Some(order), "{} vs {}", a, b);
+        }
+        assert_eq!(compare_versions("latest", "1.0"), None);
+        assert_eq!(compare_versions("1.0-rc&x=1", "1.0"), None);
+    }
+
+    #[test]
+    fn test_release() {
+        let release = Release::parse(br#"{"version": "999.0.0", "notes": "Faster"}"#).unwrap();
+        assert!(release.is_newer());
+        assert!(release.reload);
+        let release =
+            Release::parse(format!(r#"{{"version": "{}", "reload": false}}"#, VERSION).as_bytes())
+                .unwrap();
+        assert!(!release.is_newer());
+        assert!(!release.reload);
+        assert!(Release::parse(br#"{"version": "next"}"#).is_err());
+        assert!(Release::parse(b"<html>").is_err());
+    }
+
+    #[test]
+    fn test_reload_search() {
+        assert_eq!(reload_search("", "0.2.0"), "?v=0.2.0");
+        assert_eq!(reload_search("?guest", "0.2.0"), "?guest&v=0.2.0");
+        assert_eq!(reload_search("?v=0.1.0&guest", "0.2.0"), "?guest&v=0.2.0");
+    }
+
+    #[test]
+    fn test_record_and_notice() {
+        setup();
+        let newer = Release::parse(br#"{"version": "999.0.0"}"#).unwrap();
+        assert!(
+            record(newer.clone(), 1000.0)
+                .unwrap()
+                .contains("999.0.0 is available")
+        );
+        assert!(take_notice().unwrap().contains("update apply"));
+        assert_eq!(take_notice(), None);
+        let state = read_state();
+        assert_eq!(state.checked, Some(1000.0));
+        assert_eq!(state.latest, Some(newer.clone()));
+
+        // Told once per release
+        record(newer, 2000.0).unwrap();
+        assert_eq!(take_notice(), None);
+        let current =
+            Release::parse(format!(r#"{{"version": "{}"}}"#, VERSION).as_bytes()).unwrap();
+        assert!(record(current, 3000.0).unwrap().ends_with("is up to date"));
+        assert_eq!(take_notice(), None);
+        assert!(apply().unwrap_err().starts_with("no newer release"));
+    }
+
+    #[test]
+    fn test_is_due() {
+        let config = UpdateConfig {
+            url: "https://example.com/m.json".to_string(),
+            interval: 10,
+        };
+        assert!(is_due(&config, None, 0.0));
+        assert!(!is_due(&config, Some(0.0), 599_999.0));
+        assert!(is_due(&config, Some(0.0), 600_000.0));
+        let manual = UpdateConfig {
+            interval: 0,
+            ..config.clone()
+        };
+        assert!(!is_due(&manual, None, 0.0));
+        assert!(!is_due(&UpdateConfig::default(), None, 0.0));
+    }
+}
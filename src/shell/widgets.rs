@@ -0,0 +1,736 @@
+//! Status bar widgets
+//!
+//! Small providers whose values the terminal shows right-aligned in its
+//! status bar, set up in [`CONFIG_PATH`] with one table per widget, in the
+//! order they are shown:
+//!
+//! ```toml
+//! [clock]
+//! format = "%a %d %b %H:%M"
+//!
+//! [weather]
+//! url = "https://api.example.com/now?city=Oslo"
+//! template = "{current.temperature}°C {current.summary}"
+//! interval = 900
+//! ```
+//!
+//! A table's provider is its name unless `kind` says otherwise:
+//!
+//! - `clock`: the time in `format` (see [`format_clock`]), at `offset`
+//!   minutes east of UTC or else local time
+//! - `weather`: fields of the JSON fetched from `url`, put into `template`
+//!   (see [`render_template`])
+//! - `storage`: bytes held by the filesystem
+//! - `network`: whether the browser is online
+//!
+//! Every widget takes `interval` (seconds between refreshes), `label` (put
+//! before the value) and `fallback` (shown until a refresh succeeds). A
+//! failed refresh keeps the last value; the error is shown by `widgets`.
+//!
+//! The platform calls [`tick`] with the wall-clock time and redraws the
+//! status bar with [`line`] when it returns true. Changes to the file are
+//! picked up within a few seconds.
+
+use crate::kernel::syscall;
+use crate::shell::cron::CivilTime;
+use crate::shell::profile::{parse_value, strip_comment};
+use std::cell::RefCell;
+
+/// Widget settings
+pub const CONFIG_PATH: &str = "/etc/widgets.toml";
+
+/// Written to [`CONFIG_PATH`] on first boot
+pub const DEFAULT_CONFIG: &str = "\
+# Status bar widgets, see 'man widgets'
+
+[storage]
+label = \"disk \"
+
+[network]
+
+[clock]
+format = \"%a %d %b %H:%M\"
+";
+
+/// Between widgets in the status bar
+pub const SEPARATOR: &str = "  |  ";
+
+/// How often [`tick`] looks for changes to [`CONFIG_PATH`], in milliseconds
+const CONFIG_CHECK: f64 = 5_000.0;
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Where a widget's value comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Provider {
+    Clock {
+        format: String,
+        /// Minutes east of UTC, or local time
+        offset: Option<i64>,
+    },
+    Weather {
+        url: String,
+        template: String,
+    },
+    Storage,
+    Network,
+}
+
+impl Provider {
+    /// The provider called `kind`, with default settings
+    fn named(kind: &str) -> Option<Self> {
+        Some(match kind {
+            "clock" => Provider::Clock {
+                format: "%H:%M".to_string(),
+                offset: None,
+            },
+            "weather" => Provider::Weather {
+                url: String::new(),
+                template: String::new(),
+            },
+            "storage" => Provider::Storage,
+            "network" => Provider::Network,
+            _ => return None,
+        })
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Provider::Clock { .. } => "clock",
+            Provider::Weather { .. } => "weather",
+            Provider::Storage => "storage",
+            Provider::Network => "network",
+        }
+    }
+
+    /// Seconds between refreshes unless `interval` is set
+    fn default_interval(&self) -> u64 {
+        match self {
+            Provider::Clock { .. } => 1,
+            Provider::Weather { .. } => 900,
+            Provider::Storage => 30,
+            Provider::Network => 5,
+        }
+    }
+
+    /// Apply a provider-specific setting
+    fn set(&mut self, key: &str, value: String) -> Result<(), String> {
+        match (self, key) {
+            (Provider::Clock { format, .. }, "format") => {
+                format_clock(&value, 0.0, 0)?;
+                *format = value;
+            }
+            (Provider::Clock { offset, .. }, "offset") => {
+                let minutes: i64 = value
+                    .parse()
+                    .map_err(|_| format!("offset: not a number: {}", value))?;
+                if minutes.abs() > 24 * 60 {
+                    return Err(format!("offset: out of range: {}", value));
+                }
+                *offset = Some(minutes);
+            }
+            (Provider::Weather { url, .. }, "url") => *url = value,
+            (Provider::Weather { template, .. }, "template") => *template = value,
+            (provider, key) => {
+                return Err(format!(
+                    "unknown key '{}' for a {} widget",
+                    key,
+                    provider.kind()
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A table in [`CONFIG_PATH`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Widget {
+    pub name: String,
+    pub provider: Provider,
+    /// Seconds between refreshes
+    pub interval: u64,
+    pub label: String,
+    pub fallback: String,
+}
+
+/// A setting's line number, key and value
+type Setting = (usize, String, String);
+
+/// Parse [`CONFIG_PATH`]
+pub fn parse(content: &str) -> Result<Vec<Widget>, String> {
+    // Each table's header line and name, and its settings
+    let mut tables: Vec<(usize, String, Vec<Setting>)> = Vec::new();
+
+    for (i, raw) in content.lines().enumerate() {
+        let line = strip_comment(raw).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let err = |msg: String| format!("line {}: {}", i + 1, msg);
+
+        if let Some(header) = line.strip_prefix('[') {
+            let name = header
+                .strip_suffix(']')
+                .ok_or_else(|| err("unterminated table header".to_string()))?
+                .trim();
+            if name.is_empty()
+                || !name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                return Err(err(format!("invalid widget name '{}'", name)));
+            }
+            if tables.iter().any(|(_, n, _)| n == name) {
+                return Err(err(format!("duplicate widget '{}'", name)));
+            }
+            tables.push((i + 1, name.to_string(), Vec::new()));
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| err(format!("expected key = value, got '{}'", line)))?;
+        let value = value.trim();
+        // Offsets may be negative, which basic TOML values here can't be
+        let value = if value.parse::<i64>().is_ok() {
+            value.to_string()
+        } else {
+            parse_value(value).map_err(&err)?
+        };
+        let Some((_, _, settings)) = tables.last_mut() else {
+            return Err(err(format!("'{}' outside a widget table", key.trim())));
+        };
+        settings.push((i + 1, key.trim().to_string(), value));
+    }
+
+    tables
+        .into_iter()
+        .map(|(line, name, settings)| {
+            let kind = settings
+                .iter()
+                .find(|(_, key, _)| key == "kind")
+                .map_or(name.as_str(), |(_, _, value)| value.as_str());
+            let provider = Provider::named(kind)
+                .ok_or_else(|| format!("line {}: unknown widget kind '{}'", line, kind))?;
+            let mut widget = Widget {
+                interval: provider.default_interval(),
+                provider,
+                name: name.clone(),
+                label: String::new(),
+                fallback: "--".to_string(),
+            };
+            for (n, key, value) in settings {
+                let err = |msg: String| format!("line {}: {}", n, msg);
+                match key.as_str() {
+                    "kind" => {}
+                    "interval" => {
+                        widget.interval =
+                            value.parse().ok().filter(|&secs| secs > 0).ok_or_else(|| {
+                                err(format!("interval: not a positive number: {}", value))
+                            })?
+                    }
+                    "label" => widget.label = value,
+                    "fallback" => widget.fallback = value,
+                    _ => widget.provider.set(&key, value).map_err(err)?,
+                }
+            }
+            if let Provider::Weather { url, template } = &widget.provider
+                && (url.is_empty() || template.is_empty())
+            {
+                return Err(format!(
+                    "line {}: widget '{}' needs a url and a template",
+                    line, name
+                ));
+            }
+            Ok(widget)
+        })
+        .collect()
+}
+
+/// Format `time_ms` (wall-clock milliseconds) at `offset` minutes east of
+/// UTC
+///
+/// `%H` hour, `%M` minute, `%S` second, `%d` day, `%m` month (all two
+/// digits), `%a` weekday name, `%b` month name and `%%` a percent sign.
+pub fn format_clock(format: &str, time_ms: f64, offset: i64) -> Result<String, String> {
+    let secs = (time_ms / 1000.0).floor() as i64 + offset * 60;
+    let civil = CivilTime::from_epoch_minute(secs.div_euclid(60));
+    let mut out = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('H') => out.push_str(&format!("{:02}", civil.hour)),
+            Some('M') => out.push_str(&format!("{:02}", civil.minute)),
+            Some('S') => out.push_str(&format!("{:02}", secs.rem_euclid(60))),
+            Some('d') => out.push_str(&format!("{:02}", civil.day)),
+            Some('m') => out.push_str(&format!("{:02}", civil.month)),
+            Some('a') => out.push_str(WEEKDAYS[civil.weekday as usize % 7]),
+            Some('b') => out.push_str(MONTHS[(civil.month as usize + 11) % 12]),
+            Some('%') => out.push('%'),
+            Some(other) => return Err(format!("format: unknown field '%{}'", other)),
+            None => return Err("format: trailing '%'".to_string()),
+        }
+    }
+    Ok(out)
+}
+
+/// Fill `{path}` placeholders in `template` from `json`
+///
+/// A path is keys and array indexes separated by dots, e.g.
+/// `{list.0.temp}`; strings go in without quotes. `{{` and `}}` are
+/// literal braces.
+pub fn render_template(template: &str, json: &serde_json::Value) -> Result<String, String> {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(i) = rest.find(['{', '}']) {
+        out.push_str(&rest[..i]);
+        let tail = &rest[i..];
+        if let Some(after) = tail.strip_prefix("{{").or_else(|| tail.strip_prefix("}}")) {
+            out.push_str(&tail[..1]);
+            rest = after;
+            continue;
+        }
+        let Some(inner) = tail.strip_prefix('{') else {
+            return Err("template: unmatched '}'".to_string());
+        };
+        let end = inner
+            .find('}')
+            .ok_or_else(|| "template: unterminated '{'".to_string())?;
+        let path = &inner[..end];
+        let value = path
+            .split('.')
+            .try_fold(json, |value, key| match value {
+                serde_json::Value::Array(items) => {
+                    key.parse::<usize>().ok().and_then(|n| items.get(n))
+                }
+                _ => value.get(key),
+            })
+            .ok_or_else(|| format!("no '{}' in the response", path))?;
+        match value {
+            serde_json::Value::String(s) => out.push_str(s),
+            other => out.push_str(&other.to_string()),
+        }
+        rest = &inner[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// A byte count in K, M or G
+fn human_size(bytes: usize) -> String {
+    let bytes = bytes as f64;
+    if bytes >= 1024.0 * 1024.0 * 1024.0 {
+        format!("{:.1}G", bytes / (1024.0 * 1024.0 * 1024.0))
+    } else if bytes >= 1024.0 * 1024.0 {
+        format!("{:.1}M", bytes / (1024.0 * 1024.0))
+    } else if bytes >= 1024.0 {
+        format!("{:.1}K", bytes / 1024.0)
+    } else {
+        format!("{}B", bytes)
+    }
+}
+
+/// Minutes the local time zone is east of UTC at `time_ms`
+fn local_offset(time_ms: f64) -> i64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        -(js_sys::Date::new(&time_ms.into()).get_timezone_offset() as i64)
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let _ = time_ms;
+        0
+    }
+}
+
+fn online() -> Result<bool, String> {
+    #[cfg(target_arch = "wasm32")]
+    {
+        let window = web_sys::window().ok_or("no window")?;
+        Ok(window.navigator().on_line())
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    Err("network status needs a browser".to_string())
+}
+
+/// A widget and how its last refresh went
+#[derive(Debug, Clone, PartialEq)]
+pub struct WidgetStatus {
+    pub widget: Widget,
+    /// Last value a refresh produced
+    pub value: Option<String>,
+    pub last_error: Option<String>,
+    /// Wall-clock time of the last refresh started
+    pub refreshed: Option<f64>,
+    /// A fetch is in flight
+    pub pending: bool,
+}
+
+impl WidgetStatus {
+    fn new(widget: Widget) -> Self {
+        Self {
+            widget,
+            value: None,
+            last_error: None,
+            refreshed: None,
+            pending: false,
+        }
+    }
+
+    /// What the status bar shows
+    pub fn text(&self) -> String {
+        let value = self.value.as_deref().unwrap_or(&self.widget.fallback);
+        format!("{}{}", self.widget.label, value)
+    }
+
+    fn is_due(&self, now_ms: f64) -> bool {
+        !self.pending
+            && self
+                .refreshed
+                .is_none_or(|t| now_ms - t >= self.widget.interval as f64 * 1000.0)
+    }
+}
+
+#[derive(Default)]
+struct State {
+    widgets: Vec<WidgetStatus>,
+    /// Why [`CONFIG_PATH`] couldn't be loaded
+    config_error: Option<String>,
+    /// Fingerprint of [`CONFIG_PATH`] when last loaded
+    stamp: Option<u64>,
+    /// When [`CONFIG_PATH`] was last looked at
+    checked: Option<f64>,
+    /// Bumped on every reload, so fetches started before one are dropped
+    generation: u64,
+    /// Last line returned by [`tick`]
+    line: String,
+}
+
+thread_local! {
+    static STATE: RefCell<State> = RefCell::new(State::default());
+}
+
+/// The configured widgets and how each is doing
+pub fn status() -> Vec<WidgetStatus> {
+    STATE.with(|s| s.borrow().widgets.clone())
+}
+
+/// Why [`CONFIG_PATH`] couldn't be loaded, if it couldn't
+pub fn config_error() -> Option<String> {
+    STATE.with(|s| s.borrow().config_error.clone())
+}
+
+/// The status bar text: every widget, separated by [`SEPARATOR`]
+pub fn line() -> String {
+    STATE.with(|s| {
+        s.borrow()
+            .widgets
+            .iter()
+            .map(WidgetStatus::text)
+            .collect::<Vec<_>>()
+            .join(SEPARATOR)
+    })
+}
+
+/// Read [`CONFIG_PATH`] again and refresh every widget on the next tick
+///
+/// A missing file means no widgets.
+pub fn reload() -> Result<(), String> {
+    let stamp = syscall::vfs_fingerprint(CONFIG_PATH);
+    let widgets = match stamp {
+        Some(_) => syscall::read_file(CONFIG_PATH)
+            .map_err(|e| format!("{}: {}", CONFIG_PATH, e))
+            .and_then(|text| parse(&text).map_err(|e| format!("{}: {}", CONFIG_PATH, e))),
+        None => Ok(Vec::new()),
+    };
+    STATE.with(|s| {
+        let mut s = s.borrow_mut();
+        s.stamp = stamp;
+        s.generation += 1;
+        match widgets {
+            Ok(widgets) => {
+                s.widgets = widgets.into_iter().map(WidgetStatus::new).collect();
+                s.config_error = None;
+                Ok(())
+            }
+            Err(e) => {
+                // Keep showing the widgets from before
+                s.config_error = Some(e.clone());
+                Err(e)
+            }
+        }
+    })
+}
+
+/// Reload [`CONFIG_PATH`] if it changed since it was last loaded
+fn check_config(now_ms: f64) {
+    let look = STATE.with(|s| {
+        let mut s = s.borrow_mut();
+        if s.checked.is_some_and(|t| now_ms - t < CONFIG_CHECK) {
+            return None;
+        }
+        s.checked = Some(now_ms);
+        Some(s.stamp)
+    });
+    let Some(loaded) = look else {
+        return;
+    };
+    let stamp = syscall::vfs_fingerprint(CONFIG_PATH);
+    let first = STATE.with(|s| s.borrow().generation == 0);
+    if (first || stamp != loaded)
+        && let Err(e) = reload()
+    {
+        syscall::klog(crate::kernel::LogLevel::Warn, "widgets", &e);
+    }
+}
+
+/// Record how a refresh of widget `index` went, unless the configuration
+/// was reloaded since it started
+fn finish(generation: u64, index: usize, result: Result<String, String>) {
+    STATE.with(|s| {
+        let mut s = s.borrow_mut();
+        if s.generation != generation {
+            return;
+        }
+        let Some(status) = s.widgets.get_mut(index) else {
+            return;
+        };
+        status.pending = false;
+        match result {
+            Ok(value) => {
+                status.value = Some(value);
+                status.last_error = None;
+            }
+            Err(e) => status.last_error = Some(e),
+        }
+    });
+}
+
+/// Start refreshing widget `index`; the value comes in through [`finish`]
+fn refresh(generation: u64, index: usize, provider: Provider, now_ms: f64) {
+    let result = match provider {
+        Provider::Clock { format, offset } => {
+            let offset = offset.unwrap_or_else(|| local_offset(now_ms));
+            format_clock(&format, now_ms, offset)
+        }
+        Provider::Storage => Ok(human_size(syscall::vfs_size())),
+        Provider::Network => online().map(|up| if up { "online" } else { "offline" }.to_string()),
+        Provider::Weather { url, template } => {
+            #[cfg(target_arch = "wasm32")]
+            wasm_bindgen_futures::spawn_local(async move {
+                finish(generation, index, fetch_json(&url, &template).await);
+            });
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                let _ = template;
+                finish(
+                    generation,
+                    index,
+                    Err(format!("{}: fetching needs a browser", url)),
+                );
+            }
+            return;
+        }
+    };
+    finish(generation, index, result);
+}
+
+/// Fetch `url` and fill `template` from the JSON it returns
+#[cfg(target_arch = "wasm32")]
+async fn fetch_json(url: &str, template: &str) -> Result<String, String> {
+    let response = crate::kernel::network::fetch(url).await?;
+    if !(200..300).contains(&response.status) {
+        return Err(format!(
+            "{}: HTTP {} {}",
+            url, response.status, response.status_text
+        ));
+    }
+    let json: serde_json::Value =
+        serde_json::from_slice(&response.body).map_err(|e| format!("{}: {}", url, e))?;
+    render_template(template, &json)
+}
+
+/// Refresh the widgets that are due at `now_ms` (wall-clock milliseconds);
+/// returns whether [`line`] changed since the last call
+///
+/// Cheap to call on every executor tick: each widget refreshes at most
+/// once per interval.
+pub fn tick(now_ms: f64) -> bool {
+    check_config(now_ms);
+
+    let (generation, due) = STATE.with(|s| {
+        let mut s = s.borrow_mut();
+        let generation = s.generation;
+        let due: Vec<(usize, Provider)> = s
+            .widgets
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, w)| w.is_due(now_ms))
+            .map(|(i, w)| {
+                w.refreshed = Some(now_ms);
+                w.pending = true;
+                (i, w.widget.provider.clone())
+            })
+            .collect();
+        (generation, due)
+    });
+    for (index, provider) in due {
+        refresh(generation, index, provider, now_ms);
+    }
+
+    let line = line();
+    STATE.with(|s| {
+        let mut s = s.borrow_mut();
+        let changed = s.line != line;
+        s.line = line;
+        changed
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::syscall::{KERNEL, Kernel};
+    use crate::shell::setup::as_root;
+
+    fn setup() {
+        KERNEL.with(|k| {
+            let mut k = k.borrow_mut();
+            *k = Kernel::new();
+            let pid = k.spawn_process("test", None);
+            k.set_current(pid);
+        });
+        STATE.with(|s| *s.borrow_mut() = State::default());
+    }
+
+    #[test]
+    fn test_parse() {
+        let widgets = parse(
+            "# bar\n[clock]\nformat = \"%H:%M:%S\"\noffset = -300\n\n[temp]\nkind = \"weather\"\nurl = \"https://example.com/w\"\ntemplate = \"{t}C\"\ninterval = 60\nlabel = \"out \"\nfallback = \"?\"\n[storage]\n",
+        )
+        .unwrap();
+        assert_eq!(widgets.len(), 3);
+        assert_eq!(
+            widgets[0].provider,
+            Provider::Clock {
+                format: "%H:%M:%S".to_string(),
+                offset: Some(-300)
+            }
+        );
+        assert_eq!(widgets[0].interval, 1);
+        assert_eq!(widgets[1].name, "temp");
+        assert_eq!(widgets[1].provider.kind(), "weather");
+        assert_eq!(widgets[1].interval, 60);
+        assert_eq!(widgets[1].label, "out ");
+        assert_eq!(widgets[1].fallback, "?");
+        assert_eq!(widgets[2].provider, Provider::Storage);
+        assert_eq!(widgets[2].fallback, "--");
+        assert_eq!(parse(DEFAULT_CONFIG).unwrap().len(), 3);
+
+        for (text, error) in [
+            ("[disk]\n", "line 1: unknown widget kind 'disk'"),
+            (
+                "interval = 5\n",
+                "line 1: 'interval' outside a widget table",
+            ),
+            ("[clock]\n[clock]\n", "line 2: duplicate widget 'clock'"),
+            (
+                "[clock]\nurl = \"x\"\n",
+                "line 2: unknown key 'url' for a clock widget",
+            ),
+            (
+                "[clock]\nformat = \"%Q\"\n",
+                "line 2: format: unknown field '%Q'",
+            ),
+            (
+                "[network]\ninterval = 0\n",
+                "line 2: interval: not a positive number: 0",
+            ),
+            (
+                "[weather]\nurl = \"x\"\n",
+                "line 1: widget 'weather' needs a url and a template",
+            ),
+            ("[clock\n", "line 1: unterminated table header"),
+        ] {
+            assert_eq!(parse(text).unwrap_err(), error, "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_format_clock() {
+        // 2024-03-05 14:07:09 UTC, a Tuesday
+        let time = 1_709_647_629_000.0;
+        assert_eq!(
+            format_clock("%a %d %b %H:%M:%S 100%%", time, 0).unwrap(),
+            "Tue 05 Mar 14:07:09 100%"
+        );
+        assert_eq!(
+            format_clock("%d/%m %H:%M", time, 600).unwrap(),
+            "06/03 00:07"
+        );
+        assert_eq!(format_clock("%H:%M", time, -15 * 60).unwrap(), "23:07");
+        assert!(format_clock("%", time, 0).is_err());
+    }
+
+    #[test]
+    fn test_render_template() {
+        let json = serde_json::json!({
+            "current": {"temp": 4.5, "summary": "Light rain"},
+            "days": [{"high": 7}, {"high": 9}]
+        });
+        assert_eq!(
+            render_template(
+                "{current.temp}°C {current.summary}, {days.1.high} {{x}}",
+                &json
+            )
+            .unwrap(),
+            "4.5°C Light rain, 9 {x}"
+        );
+        assert_eq!(
+            render_template("{current.wind}", &json).unwrap_err(),
+            "no 'current.wind' in the response"
+        );
+        assert!(render_template("{days.x}", &json).is_err());
+        assert!(render_template("{current", &json).is_err());
+        assert!(render_template("a}b", &json).is_err());
+    }
+
+    #[test]
+    fn test_tick() {
+        setup();
+        let write = |text: &str| as_root(|| syscall::write_file(CONFIG_PATH, text)).unwrap();
+        write(
+            "[clock]\nformat = \"%H:%M:%S\"\noffset = 0\n\n[net]\nkind = \"network\"\nfallback = \"n/a\"\n\n[weather]\nurl = \"https://example.com\"\ntemplate = \"{t}\"\nlabel = \"w:\"\n",
+        );
+
+        assert!(tick(1_709_647_629_000.0));
+        assert_eq!(line(), ["14:07:09", "n/a", "w:--"].join(SEPARATOR));
+        let widgets = status();
+        assert!(widgets[1].last_error.is_some());
+        assert!(widgets.iter().all(|w| !w.pending));
+
+        // Nothing due yet within the same second
+        assert!(!tick(1_709_647_629_500.0));
+        assert!(tick(1_709_647_630_000.0));
+        assert!(line().starts_with("14:07:10"));
+
+        // A broken file keeps the widgets there were
+        write("[clock]\nformat = \"%Q\"\n");
+        tick(1_709_647_640_000.0);
+        assert!(config_error().unwrap().contains("unknown field"));
+        assert_eq!(status().len(), 3);
+
+        write("[storage]\nlabel = \"disk \"\n");
+        assert!(tick(1_709_647_650_000.0));
+        assert!(config_error().is_none());
+        assert!(line().starts_with("disk "));
+    }
+}
@@ -13,16 +13,37 @@
 //! - Alt+D: delete word forward
 //! - Ctrl+R: reverse history search
 //! - Tab: file/command completion
+//! - Alt+U: pick a character to insert (see [`crate::shell::unicode`])
+//! - Alt+F1..F4: switch virtual console (see [`crate::kernel::tty`])
+//!
+//! Each virtual console has its own shell and scrollback; switching
+//! redraws the screen from the console's scrollback, or brings back the
+//! editor if it is open there.
+//!
+//! OSC 0/2 in command output set the page title and OSC 52 copies to the
+//! clipboard through /dev/clipboard (see [`crate::shell::osc`]). Progress
+//! a background task reports with OSC 9;4 through [`report`] is pinned as
+//! a bar above the prompt until it is cleared; a command still reporting
+//! progress when it finishes gets its bar printed after its output.
 
 #![cfg(target_arch = "wasm32")]
 
 use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::rc::Rc;
 use wasm_bindgen::JsCast;
 use wasm_bindgen::prelude::*;
 
+use crate::compositor::terminal::PRESSURE_SCROLLBACK;
+use crate::kernel::mount::{FstabEntry, MountOptions};
+use crate::kernel::pressure::PressureLevel;
 use crate::kernel::syscall;
 use crate::shell;
+use crate::shell::osc::{self, OscCommand};
+use crate::shell::paste::{self, Input, MultiLinePaste, PasteDecoder};
+use crate::shell::profile::{self, DEFAULT_PROFILE, TerminalProfile};
+use crate::shell::setup::SetupWizard;
+use crate::shell::unicode::{self, Picker};
 
 // Direct bindings to xterm.js globals (loaded via script tag)
 #[wasm_bindgen]
@@ -64,6 +85,9 @@ extern "C" {
     #[wasm_bindgen(method, getter)]
     fn rows(this: &XTerm) -> u32;
 
+    #[wasm_bindgen(method, getter)]
+    fn options(this: &XTerm) -> JsValue;
+
     /// The xterm-addon-fit FitAddon class (global `FitAddon`)
     #[wasm_bindgen(js_name = FitAddon)]
     type XTermFitAddon;
@@ -95,6 +119,21 @@ thread_local! {
     static SEARCH_MODE: RefCell<bool> = RefCell::new(false);
     static SEARCH_QUERY: RefCell<String> = RefCell::new(String::new());
     static SEARCH_RESULT_IDX: RefCell<Option<usize>> = RefCell::new(None);
+    // Lock screen state while the session is locked
+    static LOCK_SCREEN: RefCell<Option<LockScreen>> = RefCell::new(None);
+    // First-boot setup, until it is finished or skipped
+    static SETUP: RefCell<Option<SetupScreen>> = RefCell::new(None);
+    // Passphrase prompts for the vaults in /etc/fstab, at boot
+    static UNLOCK: RefCell<Option<UnlockScreen>> = RefCell::new(None);
+    // Character picker while it is open
+    static CHARS: RefCell<Option<Picker>> = RefCell::new(None);
+    // Pastes coming in, and one with lines to run until it is confirmed
+    static PASTE: RefCell<PasteDecoder> = RefCell::new(PasteDecoder::new());
+    static PENDING_PASTE: RefCell<Option<MultiLinePaste>> = RefCell::new(None);
+    // Progress a background task reports, and whether its bar is the line
+    // above the prompt
+    static PROGRESS: RefCell<Option<osc::Progress>> = const { RefCell::new(None) };
+    static PROGRESS_SHOWN: RefCell<bool> = const { RefCell::new(false) };
 }
 
 const PROMPT: &str = "$ ";
@@ -102,47 +141,50 @@ const SEARCH_PROMPT: &str = "(reverse-i-search)`";
 /// Maximum number of commands to keep in history
 const MAX_HISTORY_SIZE: usize = 1000;
 
+/// How often the idle monitor is polled, in milliseconds
+const IDLE_POLL_MS: i32 = 1000;
+
+/// Height of the status bar, in pixels
+const STATUS_BAR_HEIGHT: u32 = 20;
+
+/// Lock screen shown while the session is locked
+#[derive(Default)]
+struct LockScreen {
+    /// A key was pressed and the password prompt is showing
+    prompting: bool,
+    /// Password typed so far (never echoed)
+    password: String,
+    /// Write a prompt on unlock (locked by a command, before the prompt)
+    needs_prompt: bool,
+}
+
+/// First-boot setup prompt
+struct SetupScreen {
+    wizard: SetupWizard,
+    /// Answer typed so far
+    input: String,
+}
+
+/// Boot prompt for the passphrases of encrypted mounts
+struct UnlockScreen {
+    /// Vaults still to ask about, the current one first
+    vaults: VecDeque<FstabEntry>,
+    /// Passphrase typed so far (never echoed)
+    passphrase: String,
+}
+
 /// Initialize the xterm.js terminal
 pub fn init() -> Result<(), JsValue> {
-    // Create terminal options
+    // Create terminal options, with the built-in profile until the
+    // filesystem (and /etc/terminal/profiles.toml) is ready
+    let profile = TerminalProfile::new(DEFAULT_PROFILE);
     let options = js_sys::Object::new();
     js_sys::Reflect::set(&options, &"cursorBlink".into(), &true.into())?;
     js_sys::Reflect::set(&options, &"cursorWidth".into(), &2.into())?;
-    js_sys::Reflect::set(&options, &"fontSize".into(), &14.into())?;
-    js_sys::Reflect::set(
-        &options,
-        &"fontFamily".into(),
-        &"'JetBrains Mono', 'Fira Code', 'Cascadia Code', monospace".into(),
-    )?;
+    set_profile_options(&options, &profile)?;
     js_sys::Reflect::set(&options, &"drawBoldTextInBrightColors".into(), &true.into())?;
     js_sys::Reflect::set(&options, &"rightClickSelectsWord".into(), &true.into())?;
 
-    // Theme - Tokyo Night
-    let theme = js_sys::Object::new();
-    js_sys::Reflect::set(&theme, &"foreground".into(), &"#c0caf5".into())?;
-    js_sys::Reflect::set(&theme, &"background".into(), &"#1a1b26".into())?;
-    js_sys::Reflect::set(&theme, &"cursor".into(), &"#7aa2f7".into())?;
-    js_sys::Reflect::set(&theme, &"cursorAccent".into(), &"#1a1b26".into())?;
-    js_sys::Reflect::set(&theme, &"selectionBackground".into(), &"#33467c".into())?;
-    // ANSI colors
-    js_sys::Reflect::set(&theme, &"black".into(), &"#15161e".into())?;
-    js_sys::Reflect::set(&theme, &"red".into(), &"#f7768e".into())?;
-    js_sys::Reflect::set(&theme, &"green".into(), &"#9ece6a".into())?;
-    js_sys::Reflect::set(&theme, &"yellow".into(), &"#e0af68".into())?;
-    js_sys::Reflect::set(&theme, &"blue".into(), &"#7aa2f7".into())?;
-    js_sys::Reflect::set(&theme, &"magenta".into(), &"#bb9af7".into())?;
-    js_sys::Reflect::set(&theme, &"cyan".into(), &"#7dcfff".into())?;
-    js_sys::Reflect::set(&theme, &"white".into(), &"#a9b1d6".into())?;
-    js_sys::Reflect::set(&theme, &"brightBlack".into(), &"#414868".into())?;
-    js_sys::Reflect::set(&theme, &"brightRed".into(), &"#f7768e".into())?;
-    js_sys::Reflect::set(&theme, &"brightGreen".into(), &"#9ece6a".into())?;
-    js_sys::Reflect::set(&theme, &"brightYellow".into(), &"#e0af68".into())?;
-    js_sys::Reflect::set(&theme, &"brightBlue".into(), &"#7aa2f7".into())?;
-    js_sys::Reflect::set(&theme, &"brightMagenta".into(), &"#bb9af7".into())?;
-    js_sys::Reflect::set(&theme, &"brightCyan".into(), &"#7dcfff".into())?;
-    js_sys::Reflect::set(&theme, &"brightWhite".into(), &"#c0caf5".into())?;
-    js_sys::Reflect::set(&options, &"theme".into(), &theme)?;
-
     // Create terminal
     let terminal = XTerm::new(&options.into());
 
@@ -161,15 +203,42 @@ pub fn init() -> Result<(), JsValue> {
     style.set_property("left", "0")?;
     style.set_property("width", "100%")?;
     style.set_property("height", "100%")?;
-    style.set_property("background", "#1a1b26")?;
+    style.set_property(
+        "background",
+        profile.color("background").unwrap_or_default(),
+    )?;
 
     document
         .body()
         .ok_or("no body")?
         .append_child(&html_container)?;
 
-    // Open terminal in container
+    // Status bar for the widgets (see crate::shell::widgets), hidden
+    // until there is something to show
+    let status_bar: web_sys::HtmlElement = document.create_element("div")?.dyn_into()?;
+    status_bar.set_id("status-bar");
+    let style = status_bar.style();
+    style.set_property("position", "fixed")?;
+    style.set_property("bottom", "0")?;
+    style.set_property("left", "0")?;
+    style.set_property("right", "0")?;
+    style.set_property("height", &format!("{}px", STATUS_BAR_HEIGHT))?;
+    style.set_property("line-height", &format!("{}px", STATUS_BAR_HEIGHT))?;
+    style.set_property("padding", "0 8px")?;
+    style.set_property("font-size", "12px")?;
+    style.set_property("text-align", "right")?;
+    style.set_property("white-space", "pre")?;
+    style.set_property("overflow", "hidden")?;
+    style.set_property("display", "none")?;
+    style_status_bar(&status_bar, &profile)?;
+    document
+        .body()
+        .ok_or("no body")?
+        .append_child(&status_bar)?;
+
+    // Open terminal in container, with pastes bracketed
     terminal.open(&html_container);
+    terminal.write(paste::ENABLE);
 
     // Add fit addon to auto-resize
     let fit_addon = XTermFitAddon::new_fit();
@@ -193,7 +262,7 @@ pub fn init() -> Result<(), JsValue> {
         *t.borrow_mut() = Some(term_rc.clone());
     });
     FIT_ADDON.with(|f| {
-        *f.borrow_mut() = Some(fit_rc.clone());
+        *f.borrow_mut() = Some(fit_rc);
     });
 
     // Set up keyboard handler (for special keys like Ctrl+, arrows)
@@ -203,7 +272,10 @@ pub fn init() -> Result<(), JsValue> {
     setup_data_handler(term_rc.clone());
 
     // Set up resize handler
-    setup_resize_handler(fit_rc);
+    setup_resize_handler();
+
+    // Lock the session after the idle timeout
+    setup_idle_handler(term_rc.clone());
 
     // Focus terminal
     term_rc.focus();
@@ -211,14 +283,215 @@ pub fn init() -> Result<(), JsValue> {
     Ok(())
 }
 
+/// Set font and theme options from a terminal profile
+fn set_profile_options(options: &JsValue, profile: &TerminalProfile) -> Result<(), JsValue> {
+    js_sys::Reflect::set(options, &"fontSize".into(), &profile.font_size.into())?;
+    js_sys::Reflect::set(
+        options,
+        &"fontFamily".into(),
+        &profile.font_family.as_str().into(),
+    )?;
+    let theme = js_sys::Object::new();
+    for (key, value) in profile.theme() {
+        js_sys::Reflect::set(&theme, &key.into(), &value.into())?;
+    }
+    js_sys::Reflect::set(options, &"theme".into(), &theme)?;
+    Ok(())
+}
+
+/// Cut the console's scrollback, for a memory pressure hook: xterm.js
+/// drops lines beyond a lowered `scrollback`, and the old limit is put back
+/// for new output
+pub fn trim_scrollback(level: PressureLevel) -> Option<String> {
+    let term = TERMINAL.with(|t| t.borrow().clone())?;
+    let keep = match level {
+        PressureLevel::Normal => return None,
+        PressureLevel::Moderate => PRESSURE_SCROLLBACK,
+        PressureLevel::Critical => 0,
+    };
+    let options = term.options();
+    let key = JsValue::from_str("scrollback");
+    let limit = js_sys::Reflect::get(&options, &key).ok()?;
+    if limit.as_f64().is_some_and(|limit| limit <= keep as f64) {
+        return None;
+    }
+    js_sys::Reflect::set(&options, &key, &(keep as u32).into()).ok()?;
+    js_sys::Reflect::set(&options, &key, &limit).ok()?;
+    Some(format!("console scrollback beyond {} lines", keep))
+}
+
+/// Apply a terminal profile to the running terminal: font, colors, working
+/// directory and startup command
+pub fn apply_profile(profile: &TerminalProfile) -> Result<(), JsValue> {
+    let Some(term) = TERMINAL.with(|t| t.borrow().clone()) else {
+        return Ok(());
+    };
+    set_profile_options(&term.options(), profile)?;
+
+    let document = web_sys::window()
+        .and_then(|w| w.document())
+        .ok_or("no document")?;
+    if let Some(container) = document.get_element_by_id("terminal") {
+        let container: web_sys::HtmlElement = container.dyn_into()?;
+        container.style().set_property(
+            "background",
+            profile.color("background").unwrap_or_default(),
+        )?;
+    }
+    if let Some(status_bar) = document.get_element_by_id("status-bar") {
+        style_status_bar(&status_bar.dyn_into()?, profile)?;
+    }
+    FIT_ADDON.with(|f| {
+        if let Some(fit) = f.borrow().as_ref() {
+            fit.fit();
+        }
+    });
+
+    let (output, _) = take_osc(&shell::start_profile(profile));
+    if profile.cwd.is_some() || profile.startup.is_some() {
+        erase_bottom(&term);
+        if let Some(startup) = &profile.startup {
+            term.writeln(&format!("{}{}", prompt(), startup));
+        }
+        for line in output.lines() {
+            term.writeln(line);
+        }
+        write_prompt(&term);
+    }
+    Ok(())
+}
+
+/// Set the status bar's font and colors from a terminal profile
+fn style_status_bar(
+    status_bar: &web_sys::HtmlElement,
+    profile: &TerminalProfile,
+) -> Result<(), JsValue> {
+    let style = status_bar.style();
+    style.set_property("font-family", &profile.font_family)?;
+    style.set_property("color", profile.color("foreground").unwrap_or_default())?;
+    style.set_property(
+        "background",
+        profile.color("background").unwrap_or_default(),
+    )?;
+    style.set_property(
+        "border-top",
+        &format!(
+            "1px solid {}",
+            profile.color("brightBlack").unwrap_or_default()
+        ),
+    )?;
+    Ok(())
+}
+
+/// Show `text` right-aligned in the status bar, or hide the bar if it is
+/// empty, giving the terminal its rows back
+pub fn set_status(text: &str) {
+    let Some(document) = web_sys::window().and_then(|w| w.document()) else {
+        return;
+    };
+    let (Some(status_bar), Some(container)) = (
+        document.get_element_by_id("status-bar"),
+        document.get_element_by_id("terminal"),
+    ) else {
+        return;
+    };
+    let (Ok(status_bar), Ok(container)) = (
+        status_bar.dyn_into::<web_sys::HtmlElement>(),
+        container.dyn_into::<web_sys::HtmlElement>(),
+    ) else {
+        return;
+    };
+    status_bar.set_inner_text(text);
+
+    let show = !text.is_empty();
+    let shown = status_bar
+        .style()
+        .get_property_value("display")
+        .is_ok_and(|display| display != "none");
+    if show == shown {
+        return;
+    }
+    let _ = status_bar
+        .style()
+        .set_property("display", if show { "block" } else { "none" });
+    let height = if show {
+        format!("calc(100% - {}px)", STATUS_BAR_HEIGHT + 1)
+    } else {
+        "100%".to_string()
+    };
+    let _ = container.style().set_property("height", &height);
+    refit();
+}
+
+/// Carry out the OSC requests in command output, returning the rest and
+/// the last progress report it made, if any (`Some(None)` if it cleared
+/// it)
+fn take_osc(output: &str) -> (String, Option<Option<osc::Progress>>) {
+    let (output, requests) = osc::extract(output);
+    let mut progress = None;
+    for request in requests {
+        match request {
+            OscCommand::Progress(report) => progress = Some(report),
+            OscCommand::Title(title) => {
+                if let Some(document) = web_sys::window().and_then(|w| w.document()) {
+                    document.set_title(if title.is_empty() { "axeberg" } else { &title });
+                }
+            }
+            OscCommand::Copy(data) => {
+                if let Err(e) = osc::copy(&data) {
+                    syscall::klog(
+                        crate::kernel::LogLevel::Info,
+                        "terminal",
+                        &format!("clipboard copy refused: {}", e),
+                    );
+                }
+            }
+        }
+    }
+    (output, progress)
+}
+
+/// The shell prompt, or the script REPL's while it is active
+fn prompt() -> &'static str {
+    shell::script_prompt().unwrap_or(PROMPT)
+}
+
+/// Write the prompt, with the progress bar above it if a task is
+/// reporting
 fn write_prompt(term: &XTerm) {
-    term.write(PROMPT);
+    let bar = PROGRESS.with(|p| p.borrow().map(|p| p.render()));
+    if let Some(bar) = &bar {
+        term.writeln(bar);
+    }
+    PROGRESS_SHOWN.with(|s| *s.borrow_mut() = bar.is_some());
+    term.write(prompt());
+}
+
+/// Clear the prompt line, and the progress bar above it, leaving the
+/// cursor where the bar started
+fn erase_bottom(term: &XTerm) {
+    term.write("\x1b[2K\r");
+    if PROGRESS_SHOWN.with(|s| *s.borrow()) {
+        term.write("\x1b[1A\x1b[2K");
+    }
+}
+
+/// Write the progress bar and prompt line back after [`erase_bottom`]
+fn restore_bottom(term: &XTerm) {
+    write_prompt(term);
+    INPUT_BUFFER.with(|buf| {
+        CURSOR_POS.with(|pos| {
+            let buffer = buf.borrow();
+            term.write(&buffer);
+            move_cursor(term, &buffer, buffer.len(), *pos.borrow());
+        })
+    });
 }
 
 /// Replace the current input line with new text
 fn replace_line(term: &XTerm, buffer: &mut String, cursor: &mut usize, new_text: &str) {
     term.write("\x1b[2K\r"); // Clear line, move to start
-    term.write(PROMPT);
+    term.write(prompt());
     term.write(new_text);
     *buffer = new_text.to_string();
     *cursor = buffer.len();
@@ -227,11 +500,36 @@ fn replace_line(term: &XTerm, buffer: &mut String, cursor: &mut usize, new_text:
 /// Redraw the current line (used after buffer modifications)
 fn redraw_line(term: &XTerm, buffer: &str, cursor: usize) {
     term.write("\x1b[2K\r");
-    term.write(PROMPT);
+    term.write(prompt());
     term.write(buffer);
-    let move_back = buffer.len() - cursor;
-    if move_back > 0 {
-        term.write(&format!("\x1b[{}D", move_back));
+    move_cursor(term, buffer, buffer.len(), cursor);
+}
+
+/// Start of the character before byte `pos`
+fn prev_char(buffer: &str, pos: usize) -> usize {
+    buffer[..pos]
+        .chars()
+        .next_back()
+        .map_or(0, |c| pos - c.len_utf8())
+}
+
+/// End of the character at byte `pos`
+fn next_char(buffer: &str, pos: usize) -> usize {
+    buffer[pos..]
+        .chars()
+        .next()
+        .map_or(pos, |c| pos + c.len_utf8())
+}
+
+/// Move the terminal cursor from byte `from` of the line to byte `to`,
+/// by the columns the text between them takes
+fn move_cursor(term: &XTerm, buffer: &str, from: usize, to: usize) {
+    let (columns, dir) = match to < from {
+        true => (unicode::str_width(&buffer[to..from]), 'D'),
+        false => (unicode::str_width(&buffer[from..to]), 'C'),
+    };
+    if columns > 0 {
+        term.write(&format!("\x1b[{}{}", columns, dir));
     }
 }
 
@@ -515,6 +813,8 @@ fn load_history() {
                     history.push(line.to_string());
                 }
             }
+            // So `!!` and `fc` reach back into earlier sessions too
+            shell::remember_history(&history);
             HISTORY_POS.with(|p| {
                 *p.borrow_mut() = history.len();
             });
@@ -549,8 +849,11 @@ fn trigger_autosave() {
 
             AUTOSAVE_INTERVAL.with(|interval| {
                 let interval = *interval.borrow();
-                if interval > 0 && *c % interval == 0 {
-                    // Trigger async save
+                // While saving power, writes are batched (see flush_autosave)
+                if interval > 0
+                    && *c % interval == 0
+                    && syscall::power_request_persist(platform_now())
+                {
                     do_autosave();
                 }
             });
@@ -558,6 +861,24 @@ fn trigger_autosave() {
     });
 }
 
+/// Auto-save soon for something other than a command, like the editor's
+/// swap files, when auto-save is on
+pub(crate) fn request_autosave() {
+    if AUTOSAVE_ENABLED.with(|enabled| *enabled.borrow())
+        && syscall::power_request_persist(platform_now())
+    {
+        do_autosave();
+    }
+}
+
+/// Write an auto-save the power governor held back, once it is due, or
+/// the save an `fsync` asked for, whatever the auto-save settings
+pub(crate) fn flush_autosave(now: f64) {
+    if syscall::take_persist_request() || syscall::power_persist_due(now) {
+        do_autosave();
+    }
+}
+
 /// Perform the actual auto-save operation
 fn do_autosave() {
     use crate::vfs::Persistence;
@@ -578,10 +899,15 @@ fn do_autosave() {
             }
         };
 
-        if let Err(e) = Persistence::save(&fs).await {
-            crate::console_log!("[autosave] Save failed: {}", e);
-        } else {
-            crate::console_log!("[autosave] Filesystem saved to OPFS");
+        match Persistence::save(
+            &fs,
+            syscall::persist_journaled(),
+            syscall::persist_compressed(),
+        )
+        .await
+        {
+            Ok(backend) => crate::console_log!("[autosave] Filesystem saved to {}", backend),
+            Err(e) => crate::console_log!("[autosave] Save failed: {}", e),
         }
     });
 }
@@ -624,6 +950,48 @@ fn setup_keyboard_handler(term: Rc<XTerm>) {
         let alt = dom_event.alt_key();
         let shift = dom_event.shift_key();
 
+        // Any key counts as activity; while locked, keys only reach the
+        // password prompt
+        syscall::session_input(platform_now());
+        if syscall::session_locked() {
+            handle_lock_key(&term_for_closure, &key, key_code, ctrl);
+            return;
+        }
+        if SETUP.with(|s| s.borrow().is_some()) {
+            handle_setup_key(&term_for_closure, &key, key_code, ctrl);
+            return;
+        }
+        if UNLOCK.with(|u| u.borrow().is_some()) {
+            handle_unlock_key(&term_for_closure, &key, key_code, ctrl);
+            return;
+        }
+        // Alt+F1..F4 - switch virtual console, whatever has this one
+        if alt && (112..=115).contains(&key_code) {
+            dom_event
+                .unchecked_ref::<web_sys::Event>()
+                .prevent_default();
+            switch_console(&term_for_closure, (key_code - 111) as usize);
+            return;
+        }
+        if CHARS.with(|c| c.borrow().is_some()) {
+            handle_chars_key(&term_for_closure, &key, key_code, ctrl, shift);
+            return;
+        }
+        // Characters answer a multi-line paste through onData; Enter,
+        // Escape and the like say no
+        if PENDING_PASTE.with(|p| p.borrow().is_some()) {
+            if ctrl || alt || key.chars().count() != 1 || key.chars().all(char::is_control) {
+                answer_paste(&term_for_closure, false);
+            }
+            return;
+        }
+
+        // Alt+U - pick a character to insert, at the prompt or in the editor
+        if alt && !ctrl && key_code == 85 && !SEARCH_MODE.with(|m| *m.borrow()) {
+            open_chars("");
+            return;
+        }
+
         // Check if editor is active - route special keys to editor
         // Regular characters are handled by on_data via handle_paste
         if crate::editor::is_active() {
@@ -737,42 +1105,11 @@ fn setup_keyboard_handler(term: Rc<XTerm>) {
                     13 => {
                         term_for_closure.writeln("");
                         if !buffer.is_empty() {
-                            let input = buffer.clone();
-
-                            // Add to history (avoid duplicates of last command)
-                            HISTORY.with(|h| {
-                                let mut history = h.borrow_mut();
-                                if history.last() != Some(&input) {
-                                    // Enforce history size limit
-                                    if history.len() >= MAX_HISTORY_SIZE {
-                                        history.remove(0);
-                                    }
-                                    history.push(input.clone());
-                                }
-                                HISTORY_POS.with(|p| {
-                                    *p.borrow_mut() = history.len();
-                                });
-                            });
-                            SAVED_BUFFER.with(|s| s.borrow_mut().clear());
-
-                            // Save history periodically
-                            HISTORY.with(|h| {
-                                if h.borrow().len() % 10 == 0 {
-                                    save_history();
-                                }
-                            });
-
-                            buffer.clear();
+                            let input = std::mem::take(&mut *buffer);
                             *cursor = 0;
-
-                            // Execute command through shell
-                            let output = shell::execute_command(&input);
-                            for line in output.lines() {
-                                term_for_closure.writeln(line);
+                            if !run_command(&term_for_closure, input) {
+                                return;
                             }
-
-                            // Auto-save filesystem periodically
-                            trigger_autosave();
                         }
                         write_prompt(&term_for_closure);
                     }
@@ -787,8 +1124,8 @@ fn setup_keyboard_handler(term: Rc<XTerm>) {
                     // Backspace
                     8 => {
                         if *cursor > 0 {
-                            buffer.remove(*cursor - 1);
-                            *cursor -= 1;
+                            *cursor = prev_char(&buffer, *cursor);
+                            buffer.remove(*cursor);
                             redraw_line(&term_for_closure, &buffer, *cursor);
                         }
                     }
@@ -801,31 +1138,25 @@ fn setup_keyboard_handler(term: Rc<XTerm>) {
                     }
                     // Home
                     36 => {
-                        if *cursor > 0 {
-                            term_for_closure.write(&format!("\x1b[{}D", *cursor));
-                            *cursor = 0;
-                        }
+                        move_cursor(&term_for_closure, &buffer, *cursor, 0);
+                        *cursor = 0;
                     }
                     // End
                     35 => {
-                        let move_right = buffer.len() - *cursor;
-                        if move_right > 0 {
-                            term_for_closure.write(&format!("\x1b[{}C", move_right));
-                            *cursor = buffer.len();
-                        }
+                        move_cursor(&term_for_closure, &buffer, *cursor, buffer.len());
+                        *cursor = buffer.len();
                     }
                     // Left arrow
                     37 => {
                         if alt {
                             // Alt+Left = word backward
                             let new_pos = word_start(&buffer, *cursor);
-                            if new_pos < *cursor {
-                                term_for_closure.write(&format!("\x1b[{}D", *cursor - new_pos));
-                                *cursor = new_pos;
-                            }
+                            move_cursor(&term_for_closure, &buffer, *cursor, new_pos);
+                            *cursor = new_pos;
                         } else if *cursor > 0 {
-                            term_for_closure.write("\x1b[D");
-                            *cursor -= 1;
+                            let new_pos = prev_char(&buffer, *cursor);
+                            move_cursor(&term_for_closure, &buffer, *cursor, new_pos);
+                            *cursor = new_pos;
                         }
                     }
                     // Right arrow
@@ -833,13 +1164,12 @@ fn setup_keyboard_handler(term: Rc<XTerm>) {
                         if alt {
                             // Alt+Right = word forward
                             let new_pos = word_end(&buffer, *cursor);
-                            if new_pos > *cursor {
-                                term_for_closure.write(&format!("\x1b[{}C", new_pos - *cursor));
-                                *cursor = new_pos;
-                            }
+                            move_cursor(&term_for_closure, &buffer, *cursor, new_pos);
+                            *cursor = new_pos;
                         } else if *cursor < buffer.len() {
-                            term_for_closure.write("\x1b[C");
-                            *cursor += 1;
+                            let new_pos = next_char(&buffer, *cursor);
+                            move_cursor(&term_for_closure, &buffer, *cursor, new_pos);
+                            *cursor = new_pos;
                         }
                     }
                     // Up arrow - previous history
@@ -906,16 +1236,15 @@ fn setup_keyboard_handler(term: Rc<XTerm>) {
                     }
                     // Ctrl+A - start of line
                     65 if ctrl => {
-                        if *cursor > 0 {
-                            term_for_closure.write(&format!("\x1b[{}D", *cursor));
-                            *cursor = 0;
-                        }
+                        move_cursor(&term_for_closure, &buffer, *cursor, 0);
+                        *cursor = 0;
                     }
                     // Ctrl+B - back one char (same as left arrow)
                     66 if ctrl => {
                         if *cursor > 0 {
-                            term_for_closure.write("\x1b[D");
-                            *cursor -= 1;
+                            let new_pos = prev_char(&buffer, *cursor);
+                            move_cursor(&term_for_closure, &buffer, *cursor, new_pos);
+                            *cursor = new_pos;
                         }
                     }
                     // Ctrl+C - cancel
@@ -940,17 +1269,15 @@ fn setup_keyboard_handler(term: Rc<XTerm>) {
                     }
                     // Ctrl+E - end of line
                     69 if ctrl => {
-                        let move_right = buffer.len() - *cursor;
-                        if move_right > 0 {
-                            term_for_closure.write(&format!("\x1b[{}C", move_right));
-                            *cursor = buffer.len();
-                        }
+                        move_cursor(&term_for_closure, &buffer, *cursor, buffer.len());
+                        *cursor = buffer.len();
                     }
                     // Ctrl+F - forward one char (same as right arrow)
                     70 if ctrl => {
                         if *cursor < buffer.len() {
-                            term_for_closure.write("\x1b[C");
-                            *cursor += 1;
+                            let new_pos = next_char(&buffer, *cursor);
+                            move_cursor(&term_for_closure, &buffer, *cursor, new_pos);
+                            *cursor = new_pos;
                         }
                     }
                     // Ctrl+K - kill to end of line
@@ -969,10 +1296,7 @@ fn setup_keyboard_handler(term: Rc<XTerm>) {
                         term_for_closure.write("\x1b[H");
                         write_prompt(&term_for_closure);
                         term_for_closure.write(&buffer);
-                        let move_back = buffer.len() - *cursor;
-                        if move_back > 0 {
-                            term_for_closure.write(&format!("\x1b[{}D", move_back));
-                        }
+                        move_cursor(&term_for_closure, &buffer, buffer.len(), *cursor);
                     }
                     // Ctrl+N - next history (same as down arrow)
                     78 if ctrl => {
@@ -1045,22 +1369,19 @@ fn setup_keyboard_handler(term: Rc<XTerm>) {
                     }
                     // Ctrl+T - transpose characters
                     84 if ctrl => {
-                        if *cursor > 0 && buffer.len() >= 2 {
-                            let swap_pos = if *cursor == buffer.len() {
-                                *cursor - 1
-                            } else {
-                                *cursor
-                            };
-                            if swap_pos > 0 {
-                                let chars: Vec<char> = buffer.chars().collect();
-                                let mut new_chars = chars.clone();
-                                new_chars.swap(swap_pos - 1, swap_pos);
-                                *buffer = new_chars.into_iter().collect();
-                                if *cursor < buffer.len() {
-                                    *cursor += 1;
-                                }
-                                redraw_line(&term_for_closure, &buffer, *cursor);
-                            }
+                        // Swap the characters either side of the cursor, or
+                        // the last two at the end of the line
+                        let end = match *cursor == buffer.len() {
+                            true => *cursor,
+                            false => next_char(&buffer, *cursor),
+                        };
+                        let mid = prev_char(&buffer, end);
+                        let start = prev_char(&buffer, mid);
+                        if *cursor > 0 && start < mid {
+                            let swapped = format!("{}{}", &buffer[mid..end], &buffer[start..mid]);
+                            buffer.replace_range(start..end, &swapped);
+                            *cursor = end;
+                            redraw_line(&term_for_closure, &buffer, *cursor);
                         }
                     }
                     // Ctrl+U - kill to start of line
@@ -1098,10 +1419,8 @@ fn setup_keyboard_handler(term: Rc<XTerm>) {
                     // Alt+B - word backward
                     66 if alt => {
                         let new_pos = word_start(&buffer, *cursor);
-                        if new_pos < *cursor {
-                            term_for_closure.write(&format!("\x1b[{}D", *cursor - new_pos));
-                            *cursor = new_pos;
-                        }
+                        move_cursor(&term_for_closure, &buffer, *cursor, new_pos);
+                        *cursor = new_pos;
                     }
                     // Alt+D - delete word forward
                     68 if alt => {
@@ -1116,10 +1435,8 @@ fn setup_keyboard_handler(term: Rc<XTerm>) {
                     // Alt+F - word forward
                     70 if alt => {
                         let new_pos = word_end(&buffer, *cursor);
-                        if new_pos > *cursor {
-                            term_for_closure.write(&format!("\x1b[{}C", new_pos - *cursor));
-                            *cursor = new_pos;
-                        }
+                        move_cursor(&term_for_closure, &buffer, *cursor, new_pos);
+                        *cursor = new_pos;
                     }
                     // Regular printable characters are handled by onData handler
                     // This allows proper paste support and handles all keyboard layouts
@@ -1133,87 +1450,309 @@ fn setup_keyboard_handler(term: Rc<XTerm>) {
     callback.forget();
 }
 
+/// Add a command to the history and run it, writing its output
+///
+/// Returns false if the command took the screen (`lock`, `chars`) and
+/// the prompt shouldn't be written.
+fn run_command(term: &XTerm, input: String) -> bool {
+    // Add to history (avoid duplicates of last command)
+    HISTORY.with(|h| {
+        let mut history = h.borrow_mut();
+        if history.last() != Some(&input) {
+            // Enforce history size limit
+            if history.len() >= MAX_HISTORY_SIZE {
+                history.remove(0);
+            }
+            history.push(input.clone());
+        }
+        HISTORY_POS.with(|p| {
+            *p.borrow_mut() = history.len();
+        });
+    });
+    SAVED_BUFFER.with(|s| s.borrow_mut().clear());
+
+    // Save history periodically
+    HISTORY.with(|h| {
+        if h.borrow().len() % 10 == 0 {
+            save_history();
+        }
+    });
+
+    // Execute command through shell
+    let vt = syscall::active_vt();
+    syscall::vt_record(vt, &format!("{}{}", prompt(), input));
+    let (output, progress) = take_osc(&shell::execute_command(&input));
+    for line in output.lines() {
+        term.writeln(line);
+    }
+    syscall::vt_record(vt, &output);
+    // It ran to the end before anything was drawn, so only its last
+    // report is left to show
+    if let Some(Some(progress)) = progress {
+        term.writeln(&progress.render());
+    }
+
+    // Auto-save filesystem periodically
+    trigger_autosave();
+
+    // `lock` ran: the prompt comes back on unlock
+    if syscall::session_locked() {
+        show_lock_screen(term, true);
+        return false;
+    }
+    // `chvt` ran: the other console is drawn with its own prompt
+    if syscall::active_vt() != vt {
+        show_console(term);
+        return false;
+    }
+    // `chars` ran: the prompt comes back with the character picked
+    CHARS.with(|c| c.borrow().is_none())
+}
+
+/// Put virtual console `n` on screen, dropping the line being typed
+fn switch_console(term: &XTerm, n: usize) {
+    if n == syscall::active_vt() {
+        return;
+    }
+    if let Err(e) = syscall::chvt(n) {
+        notify(&format!("chvt: {}", e));
+        return;
+    }
+    INPUT_BUFFER.with(|b| b.borrow_mut().clear());
+    CURSOR_POS.with(|p| *p.borrow_mut() = 0);
+    SEARCH_MODE.with(|m| *m.borrow_mut() = false);
+    show_console(term);
+}
+
+/// Redraw the console on screen: the editor if it is open there, or else
+/// the console's scrollback and the prompt
+fn show_console(term: &XTerm) {
+    let vt = shell::follow_console();
+    term.clear();
+    term.write("\x1b[2J\x1b[H");
+    if crate::editor::is_active() {
+        crate::editor::refresh();
+        return;
+    }
+    for line in syscall::vt_scrollback(vt) {
+        term.writeln(&line);
+    }
+    write_prompt(term);
+}
+
 /// Handle text data input (typed characters and paste)
 fn setup_data_handler(term: Rc<XTerm>) {
     let term_for_closure = term.clone();
 
     let callback = Closure::wrap(Box::new(move |data: String| {
-        // Skip control characters (handled by onKey)
-        // onData receives the raw character/string
-        if data.is_empty() {
-            return;
+        // Pastes come bracketed (see crate::shell::paste)
+        for input in PASTE.with(|p| p.borrow_mut().feed(&data)) {
+            match input {
+                Input::Typed(data) => handle_typed(&term_for_closure, &data),
+                Input::Pasted(text) => handle_paste(&term_for_closure, &text),
+            }
         }
+    }) as Box<dyn FnMut(_)>);
 
-        // Check for control characters that should be handled by onKey
-        let first_byte = data.as_bytes()[0];
-        if first_byte < 32 && first_byte != 9 {
-            // Control character (except tab which we handle in onKey)
-            return;
-        }
+    term.on_data(callback.as_ref().unchecked_ref());
+    callback.forget();
+}
 
-        // Check if editor is active - route to editor
-        if crate::editor::is_active() {
-            crate::editor::handle_paste(&data);
-            return;
-        }
+/// Handle typed text; keys with more to them than a character are
+/// handled by onKey
+fn handle_typed(term: &XTerm, data: &str) {
+    // Skip control characters (handled by onKey)
+    // onData receives the raw character/string
+    if data.is_empty() {
+        return;
+    }
 
-        // Check if in search mode
-        let in_search = SEARCH_MODE.with(|m| *m.borrow());
-        if in_search {
-            // Let onKey handle search mode
-            return;
-        }
+    // Mouse reports xterm.js makes for a program that turned mouse
+    // reporting on are that program's input
+    if data.starts_with("\x1b[<") || data.starts_with("\x1b[M") {
+        let tty = syscall::getpid()
+            .ok()
+            .and_then(syscall::process_tty)
+            .unwrap_or_else(|| "console".to_string());
+        let _ = syscall::tty_input(&tty, data.as_bytes());
+        return;
+    }
 
-        INPUT_BUFFER.with(|buf| {
-            CURSOR_POS.with(|pos| {
-                let mut buffer = buf.borrow_mut();
-                let mut cursor = pos.borrow_mut();
+    // Check for control characters that should be handled by onKey
+    let first_byte = data.as_bytes()[0];
+    if first_byte < 32 && first_byte != 9 {
+        // Control character (except tab which we handle in onKey)
+        return;
+    }
 
-                // Filter to only printable characters
-                let printable: String = data
-                    .chars()
-                    .filter(|c| c.is_ascii_graphic() || *c == ' ')
-                    .collect();
+    // Typing at the lock screen does nothing
+    if syscall::session_locked() {
+        return;
+    }
+    // A key answers the question about a multi-line paste
+    if PENDING_PASTE.with(|p| p.borrow().is_some()) {
+        answer_paste(term, data.eq_ignore_ascii_case("y"));
+        return;
+    }
+    // Single keys reach the setup through onKey; pastes land here
+    if setup_paste(term, data) || unlock_paste(data) {
+        return;
+    }
+    // The picker takes its keys through onKey
+    if CHARS.with(|c| c.borrow().is_some()) {
+        return;
+    }
 
-                if printable.is_empty() {
-                    return;
-                }
+    // Check if editor is active - route to editor
+    if crate::editor::is_active() {
+        crate::editor::handle_paste(data);
+        return;
+    }
 
-                // Insert at cursor position
-                buffer.insert_str(*cursor, &printable);
-                *cursor += printable.len();
-
-                if printable.len() == 1 {
-                    // Single character: efficient update without full redraw
-                    // Write from inserted position to end of buffer
-                    term_for_closure.write(&buffer[*cursor - 1..]);
-                    // Move cursor back to correct position
-                    let move_back = buffer.len() - *cursor;
-                    if move_back > 0 {
-                        term_for_closure.write(&format!("\x1b[{}D", move_back));
-                    }
+    // Check if in search mode
+    let in_search = SEARCH_MODE.with(|m| *m.borrow());
+    if in_search {
+        // Let onKey handle search mode
+        return;
+    }
+
+    INPUT_BUFFER.with(|buf| {
+        CURSOR_POS.with(|pos| {
+            let mut buffer = buf.borrow_mut();
+            let mut cursor = pos.borrow_mut();
+
+            // Filter to only printable characters
+            let printable: String = data.chars().filter(|c| !c.is_control()).collect();
+
+            if printable.is_empty() {
+                return;
+            }
+
+            // Insert at cursor position
+            buffer.insert_str(*cursor, &printable);
+            *cursor += printable.len();
+
+            if printable.chars().count() == 1 {
+                // Single character: efficient update without full redraw
+                // Write from inserted position to end of buffer
+                term.write(&buffer[*cursor - printable.len()..]);
+                // Move cursor back to correct position
+                move_cursor(term, &buffer, buffer.len(), *cursor);
+            } else {
+                // Multi-character paste: full redraw
+                redraw_line(term, &buffer, *cursor);
+            }
+        });
+    });
+}
+
+/// Handle a paste: the editor takes it whole, the input line takes it
+/// at the cursor, asking before running any complete lines in it
+fn handle_paste(term: &XTerm, text: &str) {
+    // Pasting at the lock screen does nothing
+    if syscall::session_locked() {
+        return;
+    }
+    if setup_paste(term, text) || unlock_paste(text) {
+        return;
+    }
+    if CHARS.with(|c| c.borrow().is_some()) || PENDING_PASTE.with(|p| p.borrow().is_some()) {
+        return;
+    }
+    if crate::editor::is_active() {
+        crate::editor::handle_paste(text);
+        return;
+    }
+    if SEARCH_MODE.with(|m| *m.borrow()) {
+        return;
+    }
+
+    // Tabs would throw off the cursor arithmetic
+    let text = text.replace('\t', " ");
+    INPUT_BUFFER.with(|buf| {
+        CURSOR_POS.with(|pos| {
+            let mut buffer = buf.borrow_mut();
+            let mut cursor = pos.borrow_mut();
+            let mut line = buffer.clone();
+            line.insert_str(*cursor, &text);
+
+            let Some(paste) = MultiLinePaste::split(&line) else {
+                // Nothing to run: blank lines go
+                let rest = line.rsplit('\n').next().unwrap_or_default();
+                *cursor = if line.contains('\n') {
+                    rest.len()
                 } else {
-                    // Multi-character paste: full redraw
-                    redraw_line(&term_for_closure, &buffer, *cursor);
+                    *cursor + text.len()
+                };
+                *buffer = rest.to_string();
+                redraw_line(term, &buffer, *cursor);
+                return;
+            };
+            term.writeln("");
+            let question = paste.question();
+            if let Some((ask, listing)) = question.split_last() {
+                for (i, line) in listing.iter().enumerate() {
+                    if i == 0 {
+                        term.writeln(&format!("\x1b[33m{}\x1b[0m", line));
+                    } else {
+                        term.writeln(&format!("\x1b[2m{}\x1b[0m", line));
+                    }
                 }
-            });
+                term.write(ask);
+            }
+            PENDING_PASTE.with(|p| *p.borrow_mut() = Some(paste));
         });
-    }) as Box<dyn FnMut(_)>);
+    });
+}
 
-    term.on_data(callback.as_ref().unchecked_ref());
-    callback.forget();
+/// Answer the question about a multi-line paste: yes runs its commands
+/// and leaves the unfinished line for editing, no drops it
+fn answer_paste(term: &XTerm, yes: bool) {
+    let Some(paste) = PENDING_PASTE.with(|p| p.borrow_mut().take()) else {
+        return;
+    };
+    if !yes {
+        term.writeln("no");
+        let (buffer, cursor) =
+            INPUT_BUFFER.with(|b| (b.borrow().clone(), CURSOR_POS.with(|p| *p.borrow())));
+        redraw_line(term, &buffer, cursor);
+        return;
+    }
+    term.writeln("yes");
+    for command in paste.commands {
+        term.writeln(&format!("{}{}", prompt(), command));
+        if !run_command(term, command) {
+            // The rest of the paste doesn't run behind a lock screen
+            INPUT_BUFFER.with(|b| b.borrow_mut().clear());
+            CURSOR_POS.with(|p| *p.borrow_mut() = 0);
+            return;
+        }
+    }
+    INPUT_BUFFER.with(|b| *b.borrow_mut() = paste.rest.clone());
+    CURSOR_POS.with(|p| *p.borrow_mut() = paste.rest.len());
+    redraw_line(term, &paste.rest, paste.rest.len());
 }
 
-fn setup_resize_handler(fit_addon: Rc<XTermFitAddon>) {
-    let callback = Closure::wrap(Box::new(move || {
-        fit_addon.fit();
-        // Update editor size if active
-        if crate::editor::is_active() {
-            let (cols, rows) = get_size();
-            crate::editor::set_screen_size(cols, rows);
+/// Fit the terminal to its container, resizing the editor if it's open
+fn refit() {
+    FIT_ADDON.with(|f| {
+        if let Some(fit) = f.borrow().as_ref() {
+            fit.fit();
+        }
+    });
+    // Update editor size if active
+    if crate::editor::is_active() {
+        let (cols, rows) = get_size();
+        crate::editor::set_screen_size(cols, rows);
+        if CHARS.with(|c| c.borrow().is_none()) {
             crate::editor::refresh();
         }
-    }) as Box<dyn FnMut()>);
+    }
+    redraw_chars();
+}
+
+fn setup_resize_handler() {
+    let callback = Closure::wrap(Box::new(refit) as Box<dyn FnMut()>);
 
     if let Some(window) = web_sys::window() {
         let _ =
@@ -1222,6 +1761,409 @@ fn setup_resize_handler(fit_addon: Rc<XTermFitAddon>) {
     callback.forget();
 }
 
+/// Current time from `performance.now()`, for the idle monitor and the
+/// power governor
+pub(crate) fn platform_now() -> f64 {
+    web_sys::window()
+        .and_then(|w| w.performance())
+        .map(|p| p.now())
+        .unwrap_or(0.0)
+}
+
+/// Poll the idle monitor and show the lock screen when it locks
+fn setup_idle_handler(term: Rc<XTerm>) {
+    syscall::session_input(platform_now());
+    let callback = Closure::wrap(Box::new(move || {
+        if syscall::session_poll(platform_now()) {
+            show_lock_screen(&term, false);
+        }
+    }) as Box<dyn FnMut()>);
+
+    if let Some(window) = web_sys::window() {
+        let _ = window.set_interval_with_callback_and_timeout_and_arguments_0(
+            callback.as_ref().unchecked_ref(),
+            IDLE_POLL_MS,
+        );
+    }
+    callback.forget();
+}
+
+/// Blank the screen (alternate buffer) and the compositor
+fn show_lock_screen(term: &XTerm, needs_prompt: bool) {
+    LOCK_SCREEN.with(|l| {
+        *l.borrow_mut() = Some(LockScreen {
+            needs_prompt,
+            ..LockScreen::default()
+        })
+    });
+    crate::compositor::set_locked(true);
+    term.write("\x1b[?1049h\x1b[2J\x1b[H");
+    term.writeln("\x1b[2m🔒 Session locked - press any key to unlock\x1b[0m");
+}
+
+/// Route a key to the lock screen's password prompt
+fn handle_lock_key(term: &XTerm, key: &str, key_code: u32, ctrl: bool) {
+    // Locked from another path (e.g. a script): show the screen first
+    if LOCK_SCREEN.with(|l| l.borrow().is_none()) {
+        show_lock_screen(term, false);
+    }
+
+    let unlocked = LOCK_SCREEN.with(|l| {
+        let mut lock = l.borrow_mut();
+        let Some(screen) = lock.as_mut() else {
+            return None;
+        };
+        if !screen.prompting {
+            screen.prompting = true;
+            term.write("\r\nPassword: ");
+            return None;
+        }
+        match key_code {
+            // Enter
+            13 => {
+                let password = std::mem::take(&mut screen.password);
+                match syscall::session_unlock(&password) {
+                    Ok(()) => return lock.take(),
+                    Err(_) => term.write("\r\n\x1b[31mIncorrect password\x1b[0m\r\nPassword: "),
+                }
+            }
+            // Backspace
+            8 => {
+                screen.password.pop();
+            }
+            // Escape - back to the blank screen
+            27 => {
+                screen.password.clear();
+                screen.prompting = false;
+                term.write("\x1b[2J\x1b[H");
+                term.writeln("\x1b[2m🔒 Session locked - press any key to unlock\x1b[0m");
+            }
+            _ if !ctrl && key.chars().count() == 1 => screen.password.push_str(key),
+            _ => {}
+        }
+        None
+    });
+
+    if let Some(screen) = unlocked {
+        crate::compositor::set_locked(false);
+        // Back to the main buffer, with the screen as it was before locking
+        term.write("\x1b[?1049l");
+        if screen.needs_prompt {
+            write_prompt(term);
+        }
+        if crate::editor::is_active() {
+            crate::editor::refresh();
+        }
+        // Back over the picker if it was open
+        if CHARS.with(|c| c.borrow().is_some()) {
+            term.write("\x1b[?1049h");
+            redraw_chars();
+        }
+    }
+}
+
+/// Open the character picker (alternate buffer), searching for `query`
+/// if not empty; the character picked goes in at the cursor of the
+/// command line or the editor
+pub fn open_chars(query: &str) {
+    let Some(term) = TERMINAL.with(|t| t.borrow().clone()) else {
+        return;
+    };
+    let (cols, _) = get_size();
+    CHARS.with(|c| *c.borrow_mut() = Some(Picker::new(query, cols)));
+    term.write("\x1b[?1049h");
+    redraw_chars();
+}
+
+/// Draw the picker again, at the terminal's current size
+fn redraw_chars() {
+    let (cols, rows) = get_size();
+    let screen = CHARS.with(|c| {
+        let mut chars = c.borrow_mut();
+        let picker = chars.as_mut()?;
+        picker.resize(cols);
+        Some(picker.render(rows))
+    });
+    if let Some(screen) = screen {
+        write(&screen);
+    }
+}
+
+/// Route a key to the character picker
+fn handle_chars_key(term: &XTerm, key: &str, key_code: u32, ctrl: bool, shift: bool) {
+    let (_, rows) = get_size();
+    let page = rows.saturating_sub(5).max(1) as isize;
+    let picked = CHARS.with(|c| {
+        let mut chars = c.borrow_mut();
+        let picker = chars.as_mut()?;
+        match key_code {
+            // Enter
+            13 => return Some(picker.selected()),
+            // Escape drops the search first; Ctrl+C and Ctrl+G close
+            27 => {
+                if !picker.clear() {
+                    return Some(None);
+                }
+            }
+            67 | 71 if ctrl => return Some(None),
+            8 => picker.backspace(),
+            9 => picker.next_group(!shift),
+            33 => picker.move_by(0, -page),
+            34 => picker.move_by(0, page),
+            37 => picker.move_by(-1, 0),
+            38 => picker.move_by(0, -1),
+            39 => picker.move_by(1, 0),
+            40 => picker.move_by(0, 1),
+            _ if !ctrl && key.chars().count() == 1 => {
+                if let Some(ch) = key.chars().next().filter(|c| !c.is_control()) {
+                    picker.type_char(ch);
+                }
+            }
+            _ => {}
+        }
+        None
+    });
+
+    match picked {
+        Some(picked) => close_chars(term, picked),
+        None => redraw_chars(),
+    }
+}
+
+/// Close the picker and insert what was picked
+fn close_chars(term: &XTerm, picked: Option<char>) {
+    CHARS.with(|c| c.borrow_mut().take());
+    term.write("\x1b[?1049l");
+    if crate::editor::is_active() {
+        match picked {
+            Some(ch) => crate::editor::handle_paste(&ch.to_string()),
+            None => crate::editor::refresh(),
+        }
+        return;
+    }
+    INPUT_BUFFER.with(|buf| {
+        CURSOR_POS.with(|pos| {
+            let mut buffer = buf.borrow_mut();
+            let mut cursor = pos.borrow_mut();
+            if let Some(ch) = picked {
+                buffer.insert(*cursor, ch);
+                *cursor += ch.len_utf8();
+            }
+            redraw_line(term, &buffer, *cursor);
+        });
+    });
+}
+
+/// Start the first-boot setup in place of the shell prompt
+pub fn start_setup() {
+    let Some(term) = TERMINAL.with(|t| t.borrow().clone()) else {
+        return;
+    };
+    let wizard = SetupWizard::new();
+    term.write("\x1b[2J\x1b[H");
+    term.writeln("\x1b[1mWelcome to axeberg\x1b[0m - let's set up your system.");
+    term.writeln("\x1b[2mPress Enter to keep a default, Esc to skip the rest.\x1b[0m");
+    term.writeln("");
+    term.write(&wizard.prompt());
+    SETUP.with(|s| {
+        *s.borrow_mut() = Some(SetupScreen {
+            wizard,
+            input: String::new(),
+        })
+    });
+}
+
+/// Route a key to the setup prompt
+fn handle_setup_key(term: &XTerm, key: &str, key_code: u32, ctrl: bool) {
+    let finished = SETUP.with(|s| {
+        let mut setup = s.borrow_mut();
+        let screen = setup.as_mut()?;
+        match key_code {
+            // Enter
+            13 => {
+                let input = std::mem::take(&mut screen.input);
+                term.write("\r\n");
+                if let Err(e) = screen.wizard.answer(&input) {
+                    term.writeln(&format!("\x1b[31m{}\x1b[0m", e));
+                }
+            }
+            // Backspace
+            8 => {
+                if screen.input.pop().is_some() && !screen.wizard.is_secret() {
+                    term.write("\x08 \x08");
+                }
+            }
+            // Escape - keep the defaults for everything left
+            27 => {
+                screen.input.clear();
+                screen.wizard.skip();
+                term.write("\r\n");
+            }
+            _ if !ctrl && key.chars().count() == 1 => {
+                screen.input.push_str(key);
+                if !screen.wizard.is_secret() {
+                    term.write(key);
+                }
+            }
+            _ => return None,
+        }
+        if screen.wizard.is_done() {
+            return setup.take();
+        }
+        term.write(&screen.wizard.prompt());
+        None
+    });
+
+    if let Some(screen) = finished {
+        finish_setup(term, &screen.wizard);
+    }
+}
+
+/// Add pasted text to the setup answer; false when setup isn't running
+fn setup_paste(term: &XTerm, data: &str) -> bool {
+    SETUP.with(|s| {
+        let mut setup = s.borrow_mut();
+        let Some(screen) = setup.as_mut() else {
+            return false;
+        };
+        if data.chars().count() == 1 {
+            return true;
+        }
+        let printable: String = data
+            .chars()
+            .filter(|c| c.is_ascii_graphic() || *c == ' ')
+            .collect();
+        screen.input.push_str(&printable);
+        if !screen.wizard.is_secret() {
+            term.write(&printable);
+        }
+        true
+    })
+}
+
+/// Apply the setup answers and hand over to the shell
+fn finish_setup(term: &XTerm, wizard: &SetupWizard) {
+    match wizard.apply() {
+        Ok(home) => {
+            term.writeln(&format!(
+                "\x1b[32mSetup complete.\x1b[0m Logged in as {}, home is {}.",
+                wizard.username(),
+                home
+            ));
+            term.writeln("Type 'help' for available commands.");
+            term.writeln("");
+        }
+        Err(e) => term.writeln(&format!("\x1b[31mSetup failed: {}\x1b[0m", e)),
+    }
+    let profile = match profile::load() {
+        Ok(profiles) => profiles.default_profile(),
+        Err(_) => TerminalProfile::new(DEFAULT_PROFILE),
+    };
+    // The profile starts in the new home directory and writes the prompt
+    // itself; otherwise write it here
+    if apply_profile(&profile).is_err() || (profile.cwd.is_none() && profile.startup.is_none()) {
+        write_prompt(term);
+    }
+    do_autosave();
+}
+
+/// Ask for the passphrase of each vault in `vaults` in turn, mounting the
+/// ones that are given, before handing over to the shell
+pub fn start_unlock(vaults: Vec<FstabEntry>) {
+    let Some(term) = TERMINAL.with(|t| t.borrow().clone()) else {
+        return;
+    };
+    let Some(first) = vaults.first() else {
+        return;
+    };
+    // In place of the prompt written at startup
+    term.write("\x1b[2K\r");
+    term.writeln("\x1b[2mEncrypted mounts - Esc leaves one locked.\x1b[0m");
+    term.write(&unlock_prompt(first));
+    UNLOCK.with(|u| {
+        *u.borrow_mut() = Some(UnlockScreen {
+            vaults: vaults.into(),
+            passphrase: String::new(),
+        })
+    });
+}
+
+fn unlock_prompt(vault: &FstabEntry) -> String {
+    format!("Passphrase for {}: ", vault.target)
+}
+
+/// Route a key to the passphrase prompt
+fn handle_unlock_key(term: &XTerm, key: &str, key_code: u32, ctrl: bool) {
+    let finished = UNLOCK.with(|u| {
+        let mut unlock = u.borrow_mut();
+        let screen = unlock.as_mut()?;
+        let vault = screen.vaults.front()?.clone();
+        match key_code {
+            // Enter
+            13 => {
+                let passphrase = std::mem::take(&mut screen.passphrase);
+                term.write("\r\n");
+                let options = MountOptions::parse(&vault.options);
+                match syscall::mount_encrypted(
+                    &vault.source,
+                    &vault.target,
+                    &passphrase,
+                    false,
+                    options,
+                ) {
+                    Ok(()) => {
+                        screen.vaults.pop_front();
+                    }
+                    Err(e) => term.writeln(&format!("\x1b[31m{}\x1b[0m", e)),
+                }
+            }
+            // Backspace
+            8 => {
+                screen.passphrase.pop();
+                return None;
+            }
+            // Escape - leave this one locked
+            27 => {
+                screen.passphrase.clear();
+                screen.vaults.pop_front();
+                term.writeln(&format!("\r\n{} left locked", vault.target));
+            }
+            _ if !ctrl && key.chars().count() == 1 => {
+                screen.passphrase.push_str(key);
+                return None;
+            }
+            _ => return None,
+        }
+        match screen.vaults.front() {
+            Some(next) => {
+                term.write(&unlock_prompt(next));
+                None
+            }
+            None => unlock.take(),
+        }
+    });
+
+    if finished.is_some() {
+        write_prompt(term);
+    }
+}
+
+/// Add pasted text to the passphrase; false when not asking for one
+fn unlock_paste(data: &str) -> bool {
+    UNLOCK.with(|u| {
+        let mut unlock = u.borrow_mut();
+        let Some(screen) = unlock.as_mut() else {
+            return false;
+        };
+        if data.chars().count() > 1 {
+            screen
+                .passphrase
+                .extend(data.chars().filter(|c| !c.is_control()));
+        }
+        true
+    })
+}
+
 /// Write a line to the terminal
 pub fn writeln(text: &str) {
     TERMINAL.with(|t| {
@@ -1229,6 +2171,78 @@ pub fn writeln(text: &str) {
             term.writeln(text);
         }
     });
+    syscall::vt_record(syscall::active_vt(), text);
+}
+
+/// Check if a message can be shown at the prompt now: not while the
+/// session is locked, during setup, or in the editor, the character picker
+/// or reverse search
+pub fn can_notify() -> bool {
+    TERMINAL.with(|t| t.borrow().is_some())
+        && !syscall::session_locked()
+        && SETUP.with(|s| s.borrow().is_none())
+        && UNLOCK.with(|u| u.borrow().is_none())
+        && CHARS.with(|c| c.borrow().is_none())
+        && !crate::editor::is_active()
+        && !SEARCH_MODE.with(|m| *m.borrow())
+}
+
+/// Show a message above the prompt, keeping what has been typed
+pub fn notify(message: &str) {
+    let Some(term) = TERMINAL.with(|t| t.borrow().clone()) else {
+        return;
+    };
+    erase_bottom(&term);
+    term.writeln(message);
+    syscall::vt_record(syscall::active_vt(), message);
+    restore_bottom(&term);
+}
+
+/// Replace the `previous` lines last shown with [`notify`] or here by
+/// `lines`, keeping what has been typed
+///
+/// Used for progress that updates in place, such as `pkg install`.
+pub fn renotify(previous: usize, lines: &[String]) {
+    let Some(term) = TERMINAL.with(|t| t.borrow().clone()) else {
+        return;
+    };
+    erase_bottom(&term);
+    if previous > 0 {
+        term.write(&format!("\x1b[{}A", previous));
+    }
+    for line in lines {
+        term.write("\x1b[2K");
+        term.writeln(line);
+    }
+    restore_bottom(&term);
+}
+
+/// Show output from outside a command, such as a background task's
+///
+/// Progress it reports (OSC 9;4) is pinned as a bar above the prompt until
+/// it is cleared; any text is shown as with [`notify`], and other OSC
+/// requests are carried out as for a command. While the screen is taken
+/// (see [`can_notify`]) the text is dropped and the bar waits for the
+/// prompt.
+pub fn report(output: &str) {
+    let (text, progress) = take_osc(output);
+    let Some(term) = TERMINAL.with(|t| t.borrow().clone()) else {
+        return;
+    };
+    if !can_notify() {
+        if let Some(progress) = progress {
+            PROGRESS.with(|p| *p.borrow_mut() = progress);
+        }
+        return;
+    }
+    erase_bottom(&term);
+    for line in text.lines() {
+        term.writeln(line);
+    }
+    if let Some(progress) = progress {
+        PROGRESS.with(|p| *p.borrow_mut() = progress);
+    }
+    restore_bottom(&term);
 }
 
 /// Write text to the terminal (no newline)
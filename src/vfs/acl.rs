@@ -0,0 +1,209 @@
+//! POSIX access control lists
+//!
+//! An ACL gives named users and groups their own permissions on a file, on
+//! top of the owner/group/other mode bits. It is kept, as on Linux, in the
+//! `system.posix_acl_access` extended attribute, one `user:UID:rwx` or
+//! `group:GID:rwx` entry per line, and shows up in [`Metadata::acl`].
+//!
+//! As in POSIX, a `mask::rwx` entry caps what the named entries and the
+//! owning group get. `setfacl` sets it to cover every entry, and `chmod`
+//! sets it to the new group bits, so a `chmod g-w` or `chmod 600` still
+//! takes access away from users the list names.
+//!
+//! [`Metadata::acl`]: super::Metadata::acl
+
+/// Extended attribute an ACL is kept in
+pub const ACL_XATTR: &str = "system.posix_acl_access";
+
+/// Read permission bit of an entry
+pub const ACL_READ: u8 = 4;
+/// Write permission bit of an entry
+pub const ACL_WRITE: u8 = 2;
+/// Execute permission bit of an entry
+pub const ACL_EXECUTE: u8 = 1;
+
+/// Who an ACL entry is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AclTag {
+    User(u32),
+    Group(u32),
+}
+
+/// One named user's or group's permissions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AclEntry {
+    pub tag: AclTag,
+    /// `ACL_READ | ACL_WRITE | ACL_EXECUTE` bits
+    pub perms: u8,
+}
+
+/// A file's access control list: its named user and group entries, users
+/// first, each sorted by ID, and the mask
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Acl {
+    entries: Vec<AclEntry>,
+    /// Most the named entries and the owning group are granted; none caps
+    /// nothing
+    mask: Option<u8>,
+}
+
+impl Acl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn entries(&self) -> &[AclEntry] {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Permissions of the entry for `tag`, if there is one
+    pub fn get(&self, tag: AclTag) -> Option<u8> {
+        self.entries.iter().find(|e| e.tag == tag).map(|e| e.perms)
+    }
+
+    /// Add the entry for `tag`, or replace its permissions
+    pub fn set(&mut self, tag: AclTag, perms: u8) {
+        let perms = perms & (ACL_READ | ACL_WRITE | ACL_EXECUTE);
+        match self.entries.binary_search_by(|e| e.tag.cmp(&tag)) {
+            Ok(i) => self.entries[i].perms = perms,
+            Err(i) => self.entries.insert(i, AclEntry { tag, perms }),
+        }
+    }
+
+    pub fn mask(&self) -> Option<u8> {
+        self.mask
+    }
+
+    pub fn set_mask(&mut self, perms: u8) {
+        self.mask = Some(perms & (ACL_READ | ACL_WRITE | ACL_EXECUTE));
+    }
+
+    /// Set the mask to cover every named entry and `group`, the owning
+    /// group's bits, as `setfacl` does after a change
+    pub fn recalculate_mask(&mut self, group: u8) {
+        let mask = self.entries.iter().fold(group, |mask, e| mask | e.perms);
+        self.set_mask(mask);
+    }
+
+    /// What `perms`, of a named entry or the owning group, grant under the
+    /// mask
+    pub fn effective(&self, perms: u8) -> u8 {
+        perms & self.mask.unwrap_or(ACL_READ | ACL_WRITE | ACL_EXECUTE)
+    }
+
+    /// Remove the entry for `tag`; returns whether there was one
+    pub fn remove(&mut self, tag: AclTag) -> bool {
+        let before = self.entries.len();
+        self.entries.retain(|e| e.tag != tag);
+        self.entries.len() != before
+    }
+
+    /// The attribute value the ACL is stored as
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = String::new();
+        for entry in &self.entries {
+            let (kind, id) = match entry.tag {
+                AclTag::User(uid) => ("user", uid),
+                AclTag::Group(gid) => ("group", gid),
+            };
+            out.push_str(&format!("{}:{}:{}\n", kind, id, perms_string(entry.perms)));
+        }
+        if let Some(mask) = self.mask {
+            out.push_str(&format!("mask::{}\n", perms_string(mask)));
+        }
+        out.into_bytes()
+    }
+
+    /// Read an ACL back from its attribute value; `None` if it is malformed
+    pub fn decode(value: &[u8]) -> Option<Self> {
+        let mut acl = Self::new();
+        for line in std::str::from_utf8(value).ok()?.lines() {
+            let mut fields = line.split(':');
+            let (kind, id, perms) = (fields.next()?, fields.next()?, fields.next()?);
+            if fields.next().is_some() {
+                return None;
+            }
+            if (kind, id) == ("mask", "") {
+                acl.set_mask(parse_perms(perms)?);
+                continue;
+            }
+            let id = id.parse().ok()?;
+            let tag = match kind {
+                "user" => AclTag::User(id),
+                "group" => AclTag::Group(id),
+                _ => return None,
+            };
+            acl.set(tag, parse_perms(perms)?);
+        }
+        Some(acl)
+    }
+}
+
+/// Permission bits as `rwx`, with `-` for each one missing
+pub fn perms_string(perms: u8) -> String {
+    [(ACL_READ, 'r'), (ACL_WRITE, 'w'), (ACL_EXECUTE, 'x')]
+        .iter()
+        .map(|&(bit, c)| if perms & bit != 0 { c } else { '-' })
+        .collect()
+}
+
+/// Parse permissions written like `rw-`, `rx` or `7`
+pub fn parse_perms(text: &str) -> Option<u8> {
+    if let Ok(n) = text.parse::<u8>() {
+        return (n <= 7).then_some(n);
+    }
+    let mut perms = 0;
+    for c in text.chars() {
+        perms |= match c {
+            'r' => ACL_READ,
+            'w' => ACL_WRITE,
+            'x' => ACL_EXECUTE,
+            '-' => 0,
+            _ => return None,
+        };
+    }
+    Some(perms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_acl_round_trip() {
+        let mut acl = Acl::new();
+        acl.set(AclTag::Group(50), ACL_READ);
+        acl.set(AclTag::User(1002), ACL_READ | ACL_WRITE);
+        acl.set(AclTag::User(1001), 0o7);
+        acl.set(AclTag::User(1002), ACL_READ);
+        assert_eq!(
+            acl.encode(),
+            b"user:1001:rwx\nuser:1002:r--\ngroup:50:r--\n"
+        );
+        assert_eq!(Acl::decode(&acl.encode()), Some(acl.clone()));
+        assert_eq!(acl.get(AclTag::User(1002)), Some(ACL_READ));
+
+        assert!(acl.remove(AclTag::User(1001)));
+        assert!(!acl.remove(AclTag::User(1001)));
+        assert_eq!(acl.entries().len(), 2);
+
+        // The mask covers the entries and the owning group, and caps them
+        acl.recalculate_mask(ACL_EXECUTE);
+        assert_eq!(acl.mask(), Some(ACL_READ | ACL_EXECUTE));
+        acl.set_mask(ACL_READ);
+        assert_eq!(acl.effective(0o7), ACL_READ);
+        assert!(acl.encode().ends_with(b"group:50:r--\nmask::r--\n"));
+        assert_eq!(Acl::decode(&acl.encode()), Some(acl.clone()));
+        assert_eq!(Acl::new().effective(0o6), 0o6);
+
+        assert_eq!(Acl::decode(b"other:1:r--\n"), None);
+        assert_eq!(Acl::decode(b"user:bob:r--\n"), None);
+        assert_eq!(parse_perms("rx"), Some(ACL_READ | ACL_EXECUTE));
+        assert_eq!(parse_perms("6"), Some(ACL_READ | ACL_WRITE));
+        assert_eq!(parse_perms("rq"), None);
+    }
+}
@@ -0,0 +1,145 @@
+//! File-change feed
+//!
+//! [`MemoryFs`](super::MemoryFs) records the path of every change it makes
+//! (content, creation, removal, rename, owner and mode) in a bounded
+//! [`ChangeLog`]. A watcher keeps a [`ChangeCursor`] and asks for the paths
+//! changed after it, so it only has to look at those instead of walking the
+//! whole tree.
+//!
+//! A recorded path stands for itself and everything under it: renaming a
+//! directory records the old and the new directory, not each file. When a
+//! watcher falls so far behind that the entries it needs were dropped, or
+//! the filesystem was replaced (a restore or an import), it is told to
+//! start over from a full scan.
+
+use std::collections::{BTreeSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Entries kept before the oldest are dropped
+pub const CAPACITY: usize = 4096;
+
+/// Tells logs apart, so a cursor from a replaced filesystem isn't reused
+static NEXT_LOG: AtomicU64 = AtomicU64::new(1);
+
+/// Position in a [`ChangeLog`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChangeCursor {
+    log: u64,
+    seq: u64,
+}
+
+/// The last [`CAPACITY`] changed paths, numbered in order
+#[derive(Debug)]
+pub struct ChangeLog {
+    id: u64,
+    /// Number of the newest entry
+    seq: u64,
+    entries: VecDeque<(u64, String)>,
+    /// Number of the newest entry dropped to make room
+    dropped: u64,
+}
+
+impl ChangeLog {
+    pub fn new() -> Self {
+        Self {
+            id: NEXT_LOG.fetch_add(1, Ordering::Relaxed),
+            seq: 0,
+            entries: VecDeque::new(),
+            dropped: 0,
+        }
+    }
+
+    /// Record a change to `path`
+    pub fn record(&mut self, path: &str) {
+        self.seq += 1;
+        // Writes come in pieces; one entry per run of them is enough, as
+        // long as it moves up to the newest
+        if let Some(last) = self.entries.back_mut()
+            && last.1 == path
+        {
+            last.0 = self.seq;
+            return;
+        }
+        self.entries.push_back((self.seq, path.to_string()));
+        if self.entries.len() > CAPACITY
+            && let Some((seq, _)) = self.entries.pop_front()
+        {
+            self.dropped = seq;
+        }
+    }
+
+    /// Where the log is now
+    pub fn cursor(&self) -> ChangeCursor {
+        ChangeCursor {
+            log: self.id,
+            seq: self.seq,
+        }
+    }
+
+    /// Paths changed after `since`, sorted and without repeats, or `None` if
+    /// they are no longer all known and the caller has to rescan
+    pub fn since(&self, since: ChangeCursor) -> Option<BTreeSet<String>> {
+        if since.log != self.id || since.seq > self.seq || since.seq < self.dropped {
+            return None;
+        }
+        Some(
+            self.entries
+                .iter()
+                .filter(|(seq, _)| *seq > since.seq)
+                .map(|(_, path)| path.clone())
+                .collect(),
+        )
+    }
+}
+
+impl Default for ChangeLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Check if `path` is `changed` or lies under it
+pub fn covers(changed: &str, path: &str) -> bool {
+    changed == "/"
+        || path
+            .strip_prefix(changed)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_since() {
+        let mut log = ChangeLog::new();
+        let start = log.cursor();
+        assert_eq!(log.since(start), Some(BTreeSet::new()));
+
+        log.record("/b");
+        log.record("/b");
+        log.record("/a");
+        log.record("/b");
+        let paths: Vec<String> = log.since(start).unwrap().into_iter().collect();
+        assert_eq!(paths, ["/a", "/b"]);
+        assert_eq!(log.since(log.cursor()), Some(BTreeSet::new()));
+
+        // Repeating the newest path still counts as a change after a cursor
+        let cursor = log.cursor();
+        log.record("/b");
+        let paths: Vec<String> = log.since(cursor).unwrap().into_iter().collect();
+        assert_eq!(paths, ["/b"]);
+
+        // Another log's cursor, or one that fell behind, means a rescan
+        assert_eq!(ChangeLog::new().since(start), None);
+        for n in 0..CAPACITY {
+            log.record(&format!("/f{}", n));
+        }
+        assert_eq!(log.since(start), None);
+
+        assert!(covers("/home", "/home/user/notes.txt"));
+        assert!(covers("/home", "/home"));
+        assert!(!covers("/home", "/homework"));
+        assert!(covers("/", "/etc"));
+    }
+}
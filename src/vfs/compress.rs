@@ -0,0 +1,87 @@
+//! Compressed snapshots
+//!
+//! With the `compress` option on the root mount (the default), a snapshot
+//! is deflated (see [`deflate`]) before it is sealed and stored:
+//!
+//! ```text
+//! #axeberg-deflate v1
+//! <raw deflate stream of {"nodes":...}>
+//! #axeberg-sha256:...
+//! ```
+//!
+//! The checksum footer covers the compressed bytes, so a damaged copy is
+//! caught before anything is inflated. Snapshots are JSON with file
+//! contents as arrays of numbers, which deflate shrinks several times over.
+//! Loading takes either kind: data without the header is plain JSON, as
+//! written with `nocompress` or before compression existed.
+
+use super::deflate;
+use std::borrow::Cow;
+use std::io;
+
+/// First line of a compressed snapshot
+const HEADER: &[u8] = b"#axeberg-deflate v1\n";
+
+/// Largest snapshot inflated, in bytes
+pub const MAX_UNPACKED: usize = 1 << 30;
+
+/// Compress a serialized snapshot for storage
+pub fn pack(data: &[u8]) -> Vec<u8> {
+    let mut packed = HEADER.to_vec();
+    packed.extend_from_slice(&deflate::compress(data));
+    packed
+}
+
+/// Check if stored data is a compressed snapshot
+pub fn is_packed(data: &[u8]) -> bool {
+    data.starts_with(HEADER)
+}
+
+/// The serialized snapshot in stored data, inflated if it was compressed
+pub fn unpack(data: &[u8]) -> io::Result<Cow<'_, [u8]>> {
+    match data.strip_prefix(HEADER) {
+        Some(stream) => deflate::decompress(stream, MAX_UNPACKED).map(Cow::Owned),
+        None => Ok(Cow::Borrowed(data)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::{FileSystem, MemoryFs, write_string};
+
+    #[test]
+    fn test_pack_round_trip() {
+        let mut fs = MemoryFs::new();
+        fs.create_dir("/docs").unwrap();
+        for i in 0..20 {
+            let text = format!("Chapter {}\n", i) + &"All work and no play. ".repeat(50);
+            write_string(&mut fs, &format!("/docs/{}.txt", i), &text).unwrap();
+        }
+        let json = fs.to_json().unwrap();
+
+        let packed = pack(&json);
+        assert!(is_packed(&packed));
+        assert!(
+            packed.len() * 5 < json.len(),
+            "{} of {}",
+            packed.len(),
+            json.len()
+        );
+        assert_eq!(unpack(&packed).unwrap(), json.as_slice());
+
+        let restored = MemoryFs::from_json(&unpack(&packed).unwrap()).unwrap();
+        assert_eq!(restored.fingerprints_under("/"), fs.fingerprints_under("/"));
+    }
+
+    #[test]
+    fn test_unpack_plain_and_damaged() {
+        // Uncompressed snapshots load as they are
+        assert!(matches!(unpack(b"{\"nodes\":{}}"), Ok(Cow::Borrowed(_))));
+        assert!(!is_packed(b"{\"nodes\":{}}"));
+
+        let mut packed = pack(b"{\"nodes\":{}}");
+        packed.truncate(HEADER.len() + 2);
+        assert!(unpack(&packed).is_err());
+    }
+}
@@ -0,0 +1,501 @@
+//! Deflate compression (RFC 1951)
+//!
+//! [`decompress`] reads any deflate stream: stored, fixed and dynamic
+//! Huffman blocks. [`compress`] finds repeats with hash chains over the
+//! 32 KiB window and codes them with the fixed Huffman tables, which keeps
+//! it short while still shrinking text to well under half; data that
+//! doesn't shrink is stored. Zip archives use both, and [`crc32`] is the
+//! checksum they carry.
+
+use std::io;
+
+/// Length symbols 257..=285: base length and extra bits
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+/// Distance symbols 0..=29: base distance and extra bits
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// Order the code length code lengths are sent in
+const CLEN_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+const WINDOW: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+/// Candidates looked at per position
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: u32 = 15;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("deflate: {}", msg))
+}
+
+/// CRC-32 (IEEE), as zip, gzip and PNG use
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    acc: u32,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            acc: 0,
+            count: 0,
+        }
+    }
+
+    /// The next `n` (at most 16) bits, least significant first
+    fn bits(&mut self, n: u32) -> io::Result<u32> {
+        while self.count < n {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or_else(|| invalid("unexpected end of data"))?;
+            self.pos += 1;
+            self.acc |= u32::from(byte) << self.count;
+            self.count += 8;
+        }
+        let value = self.acc & ((1 << n) - 1);
+        self.acc >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    /// Skip to the next byte boundary
+    fn align(&mut self) {
+        self.acc = 0;
+        self.count = 0;
+    }
+}
+
+/// A canonical Huffman code: how many codes of each length, and the
+/// symbols in code order
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> io::Result<Self> {
+        let mut counts = [0u16; 16];
+        for &len in lengths {
+            counts[usize::from(len)] += 1;
+        }
+        counts[0] = 0;
+        // An over-subscribed code can't be decoded
+        let mut left: i32 = 1;
+        for &count in &counts[1..] {
+            left = left * 2 - i32::from(count);
+            if left < 0 {
+                return Err(invalid("bad Huffman code"));
+            }
+        }
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[usize::from(offsets[usize::from(len)])] = symbol as u16;
+                offsets[usize::from(len)] += 1;
+            }
+        }
+        Ok(Self { counts, symbols })
+    }
+
+    fn decode(&self, bits: &mut BitReader) -> io::Result<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..16 {
+            code |= bits.bits(1)? as i32;
+            let count = i32::from(self.counts[len]);
+            if code - count < first {
+                return self
+                    .symbols
+                    .get((index + code - first) as usize)
+                    .copied()
+                    .ok_or_else(|| invalid("bad Huffman code"));
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("bad Huffman code"))
+    }
+}
+
+fn fixed_tables() -> io::Result<(Huffman, Huffman)> {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+fn dynamic_tables(bits: &mut BitReader) -> io::Result<(Huffman, Huffman)> {
+    let nlen = bits.bits(5)? as usize + 257;
+    let ndist = bits.bits(5)? as usize + 1;
+    let ncode = bits.bits(4)? as usize + 4;
+    if nlen > 286 || ndist > 30 {
+        return Err(invalid("bad code counts"));
+    }
+    let mut clens = [0u8; 19];
+    for &i in &CLEN_ORDER[..ncode] {
+        clens[i] = bits.bits(3)? as u8;
+    }
+    let clen = Huffman::new(&clens)?;
+
+    let mut lengths = vec![0u8; nlen + ndist];
+    let mut i = 0;
+    while i < lengths.len() {
+        let symbol = clen.decode(bits)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *i
+                    .checked_sub(1)
+                    .and_then(|p| lengths.get(p))
+                    .ok_or_else(|| invalid("repeat with no length"))?;
+                (previous, 3 + bits.bits(2)? as usize)
+            }
+            17 => (0, 3 + bits.bits(3)? as usize),
+            _ => (0, 11 + bits.bits(7)? as usize),
+        };
+        if i + repeat > lengths.len() {
+            return Err(invalid("too many lengths"));
+        }
+        lengths[i..i + repeat].fill(value);
+        i += repeat;
+    }
+    if lengths[256] == 0 {
+        return Err(invalid("no end of block code"));
+    }
+    Ok((
+        Huffman::new(&lengths[..nlen])?,
+        Huffman::new(&lengths[nlen..])?,
+    ))
+}
+
+/// Decompress a raw deflate stream, refusing to produce more than `limit`
+/// bytes
+pub fn decompress(data: &[u8], limit: usize) -> io::Result<Vec<u8>> {
+    let mut bits = BitReader::new(data);
+    let mut out = Vec::new();
+    loop {
+        let last = bits.bits(1)? == 1;
+        match bits.bits(2)? {
+            0 => {
+                bits.align();
+                let header = data
+                    .get(bits.pos..bits.pos + 4)
+                    .ok_or_else(|| invalid("unexpected end of data"))?;
+                let len = usize::from(u16::from_le_bytes([header[0], header[1]]));
+                let nlen = u16::from_le_bytes([header[2], header[3]]);
+                if len as u16 != !nlen {
+                    return Err(invalid("bad stored block length"));
+                }
+                let start = bits.pos + 4;
+                let block = data
+                    .get(start..start + len)
+                    .ok_or_else(|| invalid("unexpected end of data"))?;
+                if out.len() + len > limit {
+                    return Err(invalid("data too large"));
+                }
+                out.extend_from_slice(block);
+                bits.pos = start + len;
+            }
+            kind @ (1 | 2) => {
+                let (lit, dist) = if kind == 1 {
+                    fixed_tables()?
+                } else {
+                    dynamic_tables(&mut bits)?
+                };
+                loop {
+                    let symbol = usize::from(lit.decode(&mut bits)?);
+                    if symbol < 256 {
+                        if out.len() >= limit {
+                            return Err(invalid("data too large"));
+                        }
+                        out.push(symbol as u8);
+                        continue;
+                    }
+                    if symbol == 256 {
+                        break;
+                    }
+                    let index = symbol - 257;
+                    let (Some(&base), Some(&extra)) =
+                        (LENGTH_BASE.get(index), LENGTH_EXTRA.get(index))
+                    else {
+                        return Err(invalid("bad length code"));
+                    };
+                    let len = usize::from(base) + bits.bits(u32::from(extra))? as usize;
+                    let index = usize::from(dist.decode(&mut bits)?);
+                    let (Some(&base), Some(&extra)) = (DIST_BASE.get(index), DIST_EXTRA.get(index))
+                    else {
+                        return Err(invalid("bad distance code"));
+                    };
+                    let distance = usize::from(base) + bits.bits(u32::from(extra))? as usize;
+                    if distance > out.len() {
+                        return Err(invalid("distance too far back"));
+                    }
+                    if out.len() + len > limit {
+                        return Err(invalid("data too large"));
+                    }
+                    let start = out.len() - distance;
+                    // The copy may overlap what it produces
+                    for i in 0..len {
+                        out.push(out[start + i]);
+                    }
+                }
+            }
+            _ => return Err(invalid("bad block type")),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+struct BitWriter {
+    out: Vec<u8>,
+    acc: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self {
+            out: Vec::new(),
+            acc: 0,
+            count: 0,
+        }
+    }
+
+    /// Write `n` bits of `value`, least significant first
+    fn bits(&mut self, value: u32, n: u32) {
+        self.acc |= u64::from(value) << self.count;
+        self.count += n;
+        while self.count >= 8 {
+            self.out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Write a Huffman code, which goes most significant bit first
+    fn code(&mut self, code: u32, len: u32) {
+        self.bits(code.reverse_bits() >> (32 - len), len);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.acc as u8);
+        }
+        self.out
+    }
+}
+
+/// The fixed Huffman code for a literal/length symbol
+fn fixed_literal(symbol: usize) -> (u32, u32) {
+    let symbol = symbol as u32;
+    match symbol {
+        0..=143 => (0x30 + symbol, 8),
+        144..=255 => (0x190 + symbol - 144, 9),
+        256..=279 => (symbol - 256, 7),
+        _ => (0xC0 + symbol - 280, 8),
+    }
+}
+
+/// Index of the largest base in `bases` not above `value`
+fn bucket(bases: &[u16], value: usize) -> usize {
+    bases.partition_point(|&base| usize::from(base) <= value) - 1
+}
+
+/// Record that the three bytes at `pos` start there, returning the last
+/// position they started at before. `head` holds the most recent position
+/// for each hash and `prev` links each position to the one before it.
+fn insert(data: &[u8], head: &mut [usize], prev: &mut [usize], pos: usize) -> usize {
+    let v = u32::from(data[pos]) << 16 | u32::from(data[pos + 1]) << 8 | u32::from(data[pos + 2]);
+    let h = (v.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize;
+    prev[pos] = head[h];
+    head[h] = pos;
+    prev[pos]
+}
+
+/// Compress `data` into a raw deflate stream
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut w = BitWriter::new();
+    w.bits(1, 1);
+    w.bits(1, 2);
+
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; data.len()];
+    let mut pos = 0;
+    while pos < data.len() {
+        let mut best = (0, 0);
+        if pos + MIN_MATCH <= data.len() {
+            let max = MAX_MATCH.min(data.len() - pos);
+            let mut candidate = insert(data, &mut head, &mut prev, pos);
+            let mut chain = 0;
+            while candidate != usize::MAX && pos - candidate <= WINDOW && chain < MAX_CHAIN {
+                let len = data[candidate..]
+                    .iter()
+                    .zip(&data[pos..pos + max])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if len > best.0 {
+                    best = (len, pos - candidate);
+                    if len == max {
+                        break;
+                    }
+                }
+                candidate = prev[candidate];
+                chain += 1;
+            }
+        }
+
+        let (len, distance) = best;
+        if len < MIN_MATCH {
+            let (code, bits) = fixed_literal(usize::from(data[pos]));
+            w.code(code, bits);
+            pos += 1;
+            continue;
+        }
+        let index = bucket(&LENGTH_BASE, len);
+        let (code, bits) = fixed_literal(257 + index);
+        w.code(code, bits);
+        w.bits(
+            (len - usize::from(LENGTH_BASE[index])) as u32,
+            u32::from(LENGTH_EXTRA[index]),
+        );
+        let index = bucket(&DIST_BASE, distance);
+        w.code(index as u32, 5);
+        w.bits(
+            (distance - usize::from(DIST_BASE[index])) as u32,
+            u32::from(DIST_EXTRA[index]),
+        );
+        // Index the positions the match covers, for later matches
+        for p in pos + 1..pos + len {
+            if p + MIN_MATCH <= data.len() {
+                insert(data, &mut head, &mut prev, p);
+            }
+        }
+        pos += len;
+    }
+    let (code, bits) = fixed_literal(256);
+    w.code(code, bits);
+    let compressed = w.finish();
+
+    let stored_len = data.len() + 5 * data.len().div_ceil(0xFFFF).max(1);
+    if compressed.len() < stored_len {
+        compressed
+    } else {
+        store(data)
+    }
+}
+
+/// `data` in stored blocks
+fn store(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + 5);
+    let mut chunks = data.chunks(0xFFFF).peekable();
+    if chunks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(chunk) = chunks.next() {
+        out.push(u8::from(chunks.peek().is_none()));
+        let len = chunk.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(chunk);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let text = "the quick brown fox jumps over the lazy dog\n".repeat(200);
+        let mut noise = Vec::new();
+        let mut x = 12345u32;
+        for _ in 0..70_000 {
+            x = x.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            noise.push((x >> 16) as u8);
+        }
+        for data in [
+            &b""[..],
+            b"a",
+            b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+            text.as_bytes(),
+            &noise,
+        ] {
+            let packed = compress(data);
+            assert_eq!(decompress(&packed, usize::MAX).unwrap(), data);
+        }
+        assert!(compress(text.as_bytes()).len() < text.len() / 10);
+        // Noise is stored, in two blocks
+        assert_eq!(compress(&noise).len(), noise.len() + 10);
+        assert!(decompress(&compress(text.as_bytes()), 100).is_err());
+    }
+
+    #[test]
+    fn test_decompress_dynamic() {
+        // A raw deflate stream from zlib at level 9: one dynamic Huffman
+        // block
+        let stream = [
+            0x0d, 0xcb, 0xd1, 0x09, 0xc0, 0x20, 0x0c, 0x05, 0xc0, 0x55, 0xde, 0x00, 0xa5, 0x93,
+            0x74, 0x09, 0x31, 0x0f, 0x09, 0x18, 0x23, 0x49, 0xdc, 0xbf, 0x7e, 0x1f, 0xf7, 0x79,
+            0xd0, 0xa0, 0x3b, 0x8f, 0x41, 0x7c, 0x7a, 0x20, 0xb5, 0xd0, 0x8c, 0xf5, 0xa0, 0xfb,
+            0x4a, 0xf6, 0x62, 0x9d, 0x40, 0x13, 0xdd, 0x9a, 0x5d, 0xd7, 0x00, 0xa7, 0x5e, 0x4c,
+            0xca, 0x0d, 0xa0, 0x9e, 0x34, 0x17, 0x14, 0x6d, 0x7b, 0xbc, 0x3f,
+        ];
+        assert_eq!(
+            decompress(&stream, 1024).unwrap(),
+            b"Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor."
+        );
+        assert!(decompress(&stream[..10], 1024).is_err());
+        assert!(decompress(&[0x07], 1024).is_err());
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+}
@@ -0,0 +1,699 @@
+//! Encrypted filesystem
+//!
+//! [`EncryptedFs`] wraps another filesystem and keeps it encrypted: file
+//! contents, the names of files and directories, symbolic link targets and
+//! extended attribute values are all sealed with AES-256-GCM before they
+//! reach the inner filesystem. Whatever can read the inner filesystem (or
+//! the browser storage it is saved to) sees the shape of the tree, sizes,
+//! owners and times, and nothing else.
+//!
+//! The keys come from a passphrase, stretched with PBKDF2 and a random
+//! salt. The salt, the round count and a value sealed with the key are
+//! kept in `/.vault` in the inner filesystem, so a wrong passphrase is
+//! caught when the vault is opened rather than as garbage later.
+//!
+//! Names are sealed with a nonce made from the name itself (an HMAC of
+//! it), so the same name always seals the same way and can be looked up;
+//! they are stored base64url-encoded. That leaves room for names of up to
+//! 163 bytes. Contents get a fresh random nonce each time a file is saved:
+//!
+//! ```text
+//! nonce (12) | ciphertext | tag (16)
+//! ```
+//!
+//! An open file is decrypted whole into memory and sealed again when it is
+//! closed; until then its size shows through [`FileSystem::metadata`], but
+//! the inner filesystem (and so [`FileSystem::image`]) has the old
+//! contents.
+
+use super::{DirEntry, FileHandle, FileSystem, Metadata, OpenOptions, atomic_write};
+use crate::kernel::aes::AesGcm;
+use crate::kernel::crypto::{self, KDF_ITERATIONS};
+use crate::kernel::syscall::generate_random_bytes;
+use std::io::{self, SeekFrom};
+
+/// Where the inner filesystem keeps the salt and the passphrase check
+pub const HEADER_PATH: &str = "/.vault";
+
+/// Start of the header
+const MAGIC: &[u8] = b"axeberg-vault v1\n";
+
+/// Sealed into the header to check the passphrase
+const CHECK: &[u8] = b"axeberg-vault";
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+/// Bytes a sealed value takes over its plaintext
+const OVERHEAD: usize = NONCE_LEN + TAG_LEN;
+
+/// base64url alphabet, for sealed names and link targets
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+fn wrong_passphrase() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "wrong passphrase or damaged vault",
+    )
+}
+
+fn damaged(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("damaged {}", what))
+}
+
+fn bad_handle() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "Invalid file handle")
+}
+
+/// Encode bytes as unpadded base64url
+fn encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..=chunk.len() {
+            out.push(BASE64[(n >> (18 - 6 * i) & 63) as usize] as char);
+        }
+    }
+    out
+}
+
+/// Decode unpadded base64url; `None` if it isn't
+fn decode(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    for chunk in text.as_bytes().chunks(4) {
+        if chunk.len() == 1 {
+            return None;
+        }
+        let mut n = 0u32;
+        for (i, c) in chunk.iter().enumerate() {
+            let v = BASE64.iter().position(|b| b == c)? as u32;
+            n |= v << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            out.push((n >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(out)
+}
+
+/// Read a whole file of the inner filesystem
+fn read_all<F: FileSystem + ?Sized>(fs: &mut F, path: &str) -> io::Result<Vec<u8>> {
+    let handle = fs.open(path, OpenOptions::new().read(true))?;
+    let mut data = Vec::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    let read = loop {
+        match fs.read(handle, &mut buf) {
+            Ok(0) => break Ok(()),
+            Ok(n) => data.extend_from_slice(&buf[..n]),
+            Err(e) => break Err(e),
+        }
+    };
+    fs.close(handle)?;
+    read.map(|()| data)
+}
+
+/// Keys derived from the passphrase
+struct Keys {
+    /// Seals contents, link targets and attribute values
+    data: AesGcm,
+    /// Seals names
+    names: AesGcm,
+    /// Makes the nonce a name is sealed with
+    name_nonce: [u8; 32],
+}
+
+impl Keys {
+    fn derive(passphrase: &str, salt: &[u8], iterations: u32) -> io::Result<Self> {
+        crypto::check_iterations(iterations).map_err(|_| damaged("vault header"))?;
+        let master = crypto::pbkdf2_sha256(passphrase.as_bytes(), salt, iterations);
+        let cipher = |label: &[u8]| {
+            AesGcm::new(&crypto::hmac_sha256(&master, label))
+                .ok_or_else(|| io::Error::other("bad key length"))
+        };
+        Ok(Self {
+            data: cipher(b"data")?,
+            names: cipher(b"names")?,
+            name_nonce: crypto::hmac_sha256(&master, b"name-nonce"),
+        })
+    }
+
+    /// Seal with a random nonce
+    fn seal(&self, plaintext: &[u8]) -> Vec<u8> {
+        let mut nonce = [0u8; NONCE_LEN];
+        nonce.copy_from_slice(&generate_random_bytes(NONCE_LEN));
+        let mut sealed = nonce.to_vec();
+        sealed.extend(self.data.seal(&nonce, b"", plaintext));
+        sealed
+    }
+
+    /// Open the output of [`Keys::seal`]
+    fn open(&self, sealed: &[u8]) -> Option<Vec<u8>> {
+        let (nonce, rest) = sealed.split_first_chunk::<NONCE_LEN>()?;
+        self.data.open(nonce, b"", rest).ok()
+    }
+
+    /// Seal a name, the same way every time
+    fn seal_name(&self, name: &str) -> String {
+        let mac = crypto::hmac_sha256(&self.name_nonce, name.as_bytes());
+        let mut nonce = [0u8; NONCE_LEN];
+        nonce.copy_from_slice(&mac[..NONCE_LEN]);
+        let mut sealed = nonce.to_vec();
+        sealed.extend(self.names.seal(&nonce, b"", name.as_bytes()));
+        encode(&sealed)
+    }
+
+    /// Open a sealed name; `None` for one that isn't ours
+    fn open_name(&self, sealed: &str) -> Option<String> {
+        let sealed = decode(sealed)?;
+        let (nonce, rest) = sealed.split_first_chunk::<NONCE_LEN>()?;
+        String::from_utf8(self.names.open(nonce, b"", rest).ok()?).ok()
+    }
+}
+
+/// A file open through the wrapper
+struct OpenFile {
+    /// Path in the inner filesystem
+    inner: String,
+    /// Path as the caller sees it
+    path: String,
+    /// Decrypted contents
+    data: Vec<u8>,
+    position: u64,
+    readable: bool,
+    writable: bool,
+    /// Changed since it was opened, so it is sealed again on close
+    dirty: bool,
+}
+
+/// A filesystem kept encrypted inside another
+pub struct EncryptedFs<F: FileSystem> {
+    inner: F,
+    keys: Keys,
+    handles: slab::Slab<OpenFile>,
+}
+
+impl<F: FileSystem> EncryptedFs<F> {
+    /// Make a new vault in `inner`, which should be empty
+    pub fn create(inner: F, passphrase: &str) -> io::Result<Self> {
+        Self::create_with(inner, passphrase, KDF_ITERATIONS)
+    }
+
+    /// Make a new vault with a given number of PBKDF2 rounds
+    pub fn create_with(mut inner: F, passphrase: &str, iterations: u32) -> io::Result<Self> {
+        if inner.exists(HEADER_PATH) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "already a vault",
+            ));
+        }
+        let salt = generate_random_bytes(SALT_LEN);
+        let keys = Keys::derive(passphrase, &salt, iterations)?;
+        let mut header = MAGIC.to_vec();
+        header.extend_from_slice(&salt);
+        header.extend_from_slice(&iterations.to_le_bytes());
+        header.extend(keys.seal(CHECK));
+        atomic_write(&mut inner, HEADER_PATH, &header)?;
+        inner.chmod(HEADER_PATH, 0o600)?;
+        Ok(Self {
+            inner,
+            keys,
+            handles: slab::Slab::new(),
+        })
+    }
+
+    /// Open the vault in `inner` with its passphrase
+    pub fn open(mut inner: F, passphrase: &str) -> io::Result<Self> {
+        let header = read_all(&mut inner, HEADER_PATH)?;
+        let header = header
+            .strip_prefix(MAGIC)
+            .ok_or_else(|| damaged("vault header"))?;
+        if header.len() < SALT_LEN + 4 {
+            return Err(damaged("vault header"));
+        }
+        let (salt, rest) = header.split_at(SALT_LEN);
+        let (iterations, check) = rest.split_at(4);
+        let iterations =
+            u32::from_le_bytes([iterations[0], iterations[1], iterations[2], iterations[3]]);
+        let keys = Keys::derive(passphrase, salt, iterations)?;
+        if keys.open(check).as_deref() != Some(CHECK) {
+            return Err(wrong_passphrase());
+        }
+        Ok(Self {
+            inner,
+            keys,
+            handles: slab::Slab::new(),
+        })
+    }
+
+    /// The inner filesystem, holding only sealed data
+    pub fn inner(&self) -> &F {
+        &self.inner
+    }
+
+    /// Give up the keys and return the inner filesystem
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+
+    /// The inner path for `path`, every name in it sealed
+    fn inner_path(&self, path: &str) -> String {
+        let mut parts: Vec<String> = Vec::new();
+        for part in path.split('/') {
+            match part {
+                "" | "." => {}
+                ".." => {
+                    parts.pop();
+                }
+                name => parts.push(self.keys.seal_name(name)),
+            }
+        }
+        format!("/{}", parts.join("/"))
+    }
+
+    /// The path for an inner path
+    fn outer_path(&self, inner: &str) -> io::Result<String> {
+        let mut parts = Vec::new();
+        for part in inner.split('/').filter(|p| !p.is_empty()) {
+            parts.push(self.keys.open_name(part).ok_or_else(|| damaged("name"))?);
+        }
+        Ok(format!("/{}", parts.join("/")))
+    }
+
+    fn open_target(&self, sealed: &str) -> io::Result<String> {
+        decode(sealed)
+            .and_then(|sealed| self.keys.open(&sealed))
+            .and_then(|target| String::from_utf8(target).ok())
+            .ok_or_else(|| damaged("link target"))
+    }
+
+    /// Metadata as the caller sees it
+    fn outer_metadata(&self, inner: &str, mut meta: Metadata) -> io::Result<Metadata> {
+        if let Some(target) = meta.symlink_target.take() {
+            let target = self.open_target(&target)?;
+            meta.size = target.len() as u64;
+            meta.symlink_target = Some(target);
+        } else if meta.is_file {
+            meta.size = meta.size.saturating_sub(OVERHEAD as u64);
+            // Changes not yet sealed
+            if let Some((_, file)) = self
+                .handles
+                .iter()
+                .find(|(_, f)| f.dirty && f.inner == inner)
+            {
+                meta.size = file.data.len() as u64;
+            }
+        }
+        Ok(meta)
+    }
+
+    /// Seal `data` into the inner file at `inner`
+    fn store(&mut self, inner: &str, data: &[u8]) -> io::Result<()> {
+        let sealed = self.keys.seal(data);
+        let handle = self
+            .inner
+            .open(inner, OpenOptions::new().write(true).truncate(true))?;
+        let written = self.inner.write(handle, &sealed);
+        self.inner.close(handle)?;
+        if written? != sealed.len() {
+            return Err(io::Error::new(io::ErrorKind::WriteZero, "Short write"));
+        }
+        Ok(())
+    }
+
+    fn file(&self, handle: FileHandle) -> io::Result<&OpenFile> {
+        self.handles.get(handle).ok_or_else(bad_handle)
+    }
+
+    fn file_mut(&mut self, handle: FileHandle) -> io::Result<&mut OpenFile> {
+        self.handles.get_mut(handle).ok_or_else(bad_handle)
+    }
+}
+
+impl<F: FileSystem> FileSystem for EncryptedFs<F> {
+    fn open(&mut self, path: &str, options: OpenOptions) -> io::Result<FileHandle> {
+        let inner = self.inner_path(path);
+        let handle = self.inner.open(
+            &inner,
+            OpenOptions::new()
+                .read(true)
+                .write(options.write)
+                .create(options.create),
+        )?;
+        let canonical = self.inner.handle_path(handle);
+        self.inner.close(handle)?;
+        let canonical = canonical?;
+
+        let sealed = read_all(&mut self.inner, &canonical)?;
+        // A file just made has nothing sealed in it yet
+        let data = if options.truncate || sealed.is_empty() {
+            Vec::new()
+        } else {
+            self.keys.open(&sealed).ok_or_else(|| damaged("file"))?
+        };
+        let file = OpenFile {
+            path: self.outer_path(&canonical)?,
+            inner: canonical,
+            data,
+            position: 0,
+            readable: options.read,
+            writable: options.write,
+            dirty: options.write && (options.truncate || sealed.is_empty()),
+        };
+        Ok(self.handles.insert(file))
+    }
+
+    fn close(&mut self, handle: FileHandle) -> io::Result<()> {
+        if !self.handles.contains(handle) {
+            return Err(bad_handle());
+        }
+        let file = self.handles.remove(handle);
+        if !file.dirty {
+            return Ok(());
+        }
+        match self.store(&file.inner, &file.data) {
+            // Removed while open: nothing to keep
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+
+    fn read(&mut self, handle: FileHandle, buf: &mut [u8]) -> io::Result<usize> {
+        let file = self.file_mut(handle)?;
+        if !file.readable {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "File not opened for reading",
+            ));
+        }
+        let start = (file.position as usize).min(file.data.len());
+        let n = buf.len().min(file.data.len() - start);
+        buf[..n].copy_from_slice(&file.data[start..start + n]);
+        file.position += n as u64;
+        Ok(n)
+    }
+
+    fn write(&mut self, handle: FileHandle, buf: &[u8]) -> io::Result<usize> {
+        let file = self.file_mut(handle)?;
+        if !file.writable {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "File not opened for writing",
+            ));
+        }
+        let start = usize::try_from(file.position)
+            .ok()
+            .filter(|p| p.checked_add(buf.len()).is_some())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "write past the largest file size",
+                )
+            })?;
+        let end = start + buf.len();
+        if file.data.len() < end {
+            file.data.resize(end, 0);
+        }
+        file.data[start..end].copy_from_slice(buf);
+        file.position = end as u64;
+        file.dirty = true;
+        Ok(buf.len())
+    }
+
+    fn seek(&mut self, handle: FileHandle, pos: SeekFrom) -> io::Result<u64> {
+        let file = self.file_mut(handle)?;
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => (file.data.len() as u64).checked_add_signed(offset),
+            SeekFrom::Current(offset) => file.position.checked_add_signed(offset),
+        };
+        file.position = position
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid seek position"))?;
+        Ok(file.position)
+    }
+
+    fn metadata(&self, path: &str) -> io::Result<Metadata> {
+        let inner = self.inner_path(path);
+        let meta = self.inner.metadata(&inner)?;
+        self.outer_metadata(&inner, meta)
+    }
+
+    fn create_dir(&mut self, path: &str) -> io::Result<()> {
+        let inner = self.inner_path(path);
+        self.inner.create_dir(&inner)
+    }
+
+    fn read_dir(&self, path: &str) -> io::Result<Vec<DirEntry>> {
+        let inner = self.inner_path(path);
+        let mut entries: Vec<DirEntry> = self
+            .inner
+            .read_dir(&inner)?
+            .into_iter()
+            .filter_map(|entry| {
+                // The header, and anything else not sealed with our key
+                let name = self.keys.open_name(&entry.name)?;
+                Some(DirEntry { name, ..entry })
+            })
+            .collect();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(entries)
+    }
+
+    fn remove_file(&mut self, path: &str) -> io::Result<()> {
+        let inner = self.inner_path(path);
+        self.inner.remove_file(&inner)
+    }
+
+    fn remove_dir(&mut self, path: &str) -> io::Result<()> {
+        let inner = self.inner_path(path);
+        self.inner.remove_dir(&inner)
+    }
+
+    fn rename(&mut self, from: &str, to: &str) -> io::Result<()> {
+        let (from, to) = (self.inner_path(from), self.inner_path(to));
+        self.inner.rename(&from, &to)?;
+        for (_, file) in self.handles.iter_mut() {
+            if let Some(rest) = file.inner.strip_prefix(&from)
+                && (rest.is_empty() || rest.starts_with('/'))
+            {
+                file.inner = format!("{}{}", to, rest);
+            }
+        }
+        Ok(())
+    }
+
+    fn copy_file(&mut self, from: &str, to: &str) -> io::Result<u64> {
+        let (from, to) = (self.inner_path(from), self.inner_path(to));
+        let copied = self.inner.copy_file(&from, &to)?;
+        Ok(copied.saturating_sub(OVERHEAD as u64))
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.inner.exists(&self.inner_path(path))
+    }
+
+    fn symlink(&mut self, target: &str, link_path: &str) -> io::Result<()> {
+        let sealed = encode(&self.keys.seal(target.as_bytes()));
+        let inner = self.inner_path(link_path);
+        self.inner.symlink(&sealed, &inner)
+    }
+
+    fn read_link(&self, path: &str) -> io::Result<String> {
+        let sealed = self.inner.read_link(&self.inner_path(path))?;
+        self.open_target(&sealed)
+    }
+
+    fn link(&mut self, source: &str, dest: &str) -> io::Result<()> {
+        let (source, dest) = (self.inner_path(source), self.inner_path(dest));
+        self.inner.link(&source, &dest)
+    }
+
+    fn chmod(&mut self, path: &str, mode: u16) -> io::Result<()> {
+        let inner = self.inner_path(path);
+        self.inner.chmod(&inner, mode)
+    }
+
+    fn chown(&mut self, path: &str, uid: Option<u32>, gid: Option<u32>) -> io::Result<()> {
+        let inner = self.inner_path(path);
+        self.inner.chown(&inner, uid, gid)
+    }
+
+    fn setxattr(&mut self, path: &str, name: &str, value: &[u8]) -> io::Result<()> {
+        super::check_xattr(name, value)?;
+        let inner = self.inner_path(path);
+        let sealed = self.keys.seal(value);
+        self.inner.setxattr(&inner, name, &sealed)
+    }
+
+    fn getxattr(&self, path: &str, name: &str) -> io::Result<Vec<u8>> {
+        let sealed = self.inner.getxattr(&self.inner_path(path), name)?;
+        self.keys.open(&sealed).ok_or_else(|| damaged("attribute"))
+    }
+
+    fn listxattr(&self, path: &str) -> io::Result<Vec<String>> {
+        self.inner.listxattr(&self.inner_path(path))
+    }
+
+    fn removexattr(&mut self, path: &str, name: &str) -> io::Result<()> {
+        let inner = self.inner_path(path);
+        self.inner.removexattr(&inner, name)
+    }
+
+    fn fstat(&self, handle: FileHandle) -> io::Result<Metadata> {
+        let file = self.file(handle)?;
+        let mut meta = self.inner.metadata(&file.inner)?;
+        meta.size = file.data.len() as u64;
+        Ok(meta)
+    }
+
+    fn handle_path(&self, handle: FileHandle) -> io::Result<String> {
+        Ok(self.file(handle)?.path.clone())
+    }
+
+    fn set_clock(&mut self, now: f64) {
+        self.inner.set_clock(now);
+    }
+
+    fn utimes(&mut self, path: &str, atime: Option<f64>, mtime: Option<f64>) -> io::Result<()> {
+        let inner = self.inner_path(path);
+        self.inner.utimes(&inner, atime, mtime)
+    }
+
+    fn image(&self) -> Option<io::Result<Vec<u8>>> {
+        self.inner.image()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::{MemoryFs, read_to_string, write_string};
+
+    fn vault() -> EncryptedFs<MemoryFs> {
+        EncryptedFs::create_with(MemoryFs::new(), "correct horse", 10).unwrap()
+    }
+
+    fn names(fs: &dyn FileSystem, dir: &str) -> Vec<String> {
+        fs.read_dir(dir)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.name)
+            .collect()
+    }
+
+    #[test]
+    fn test_base64() {
+        for len in 0..8 {
+            let data: Vec<u8> = (0..len).map(|i| (i * 77 + 200) as u8).collect();
+            assert_eq!(decode(&encode(&data)).unwrap(), data);
+        }
+        assert_eq!(encode(b"\xfb\xff"), "-_8");
+        assert!(decode("a").is_none());
+        assert!(decode("a.b=").is_none());
+    }
+
+    #[test]
+    fn test_nothing_readable_inside() {
+        let mut fs = vault();
+        fs.create_dir("/diary").unwrap();
+        write_string(
+            &mut fs,
+            "/diary/monday.txt",
+            "Dear diary, the secret is out",
+        )
+        .unwrap();
+        fs.symlink("/diary/monday.txt", "/latest").unwrap();
+        fs.setxattr("/latest", "user.origin", b"https://example.com")
+            .unwrap();
+
+        assert_eq!(names(&fs, "/"), vec!["diary", "latest"]);
+        assert_eq!(
+            read_to_string(&mut fs, "/diary/monday.txt").unwrap(),
+            "Dear diary, the secret is out"
+        );
+        assert_eq!(fs.read_link("/latest").unwrap(), "/diary/monday.txt");
+        let meta = fs.metadata("/diary/monday.txt").unwrap();
+        assert_eq!(meta.size, 29);
+
+        let image = String::from_utf8_lossy(&fs.image().unwrap().unwrap()).into_owned();
+        for plain in ["diary", "monday", "secret", "latest", "example"] {
+            assert!(!image.contains(plain), "{} in {}", plain, image);
+        }
+        assert!(image.contains("user.origin"));
+    }
+
+    #[test]
+    fn test_reopen() {
+        let mut fs = vault();
+        write_string(&mut fs, "/notes.txt", "buy milk").unwrap();
+        let image = fs.into_inner().to_json().unwrap();
+        let inner = || MemoryFs::from_json(&image).unwrap();
+
+        let err = EncryptedFs::open(inner(), "wrong horse").err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(EncryptedFs::open(MemoryFs::new(), "correct horse").is_err());
+        assert_eq!(
+            EncryptedFs::create_with(inner(), "x", 10)
+                .err()
+                .unwrap()
+                .kind(),
+            io::ErrorKind::AlreadyExists
+        );
+
+        // A planted round count fails at once rather than hanging the mount
+        let mut planted = inner();
+        let mut header = read_all(&mut planted, HEADER_PATH).unwrap();
+        let at = MAGIC.len() + SALT_LEN;
+        header[at..at + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        atomic_write(&mut planted, HEADER_PATH, &header).unwrap();
+        let err = EncryptedFs::open(planted, "correct horse").err().unwrap();
+        assert_eq!(err.to_string(), "damaged vault header");
+
+        let mut fs = EncryptedFs::open(inner(), "correct horse").unwrap();
+        assert_eq!(read_to_string(&mut fs, "/notes.txt").unwrap(), "buy milk");
+        assert_eq!(names(&fs, "/"), vec!["notes.txt"]);
+    }
+
+    #[test]
+    fn test_handles() {
+        let mut fs = vault();
+        let handle = fs
+            .open("/log", OpenOptions::new().write(true).create(true))
+            .unwrap();
+        fs.write(handle, b"hello world").unwrap();
+        // Visible before it is sealed on close
+        assert_eq!(fs.metadata("/log").unwrap().size, 11);
+        assert_eq!(fs.fstat(handle).unwrap().size, 11);
+        assert_eq!(fs.handle_path(handle).unwrap(), "/log");
+        fs.seek(handle, SeekFrom::Start(6)).unwrap();
+        fs.write(handle, b"there").unwrap();
+        fs.seek(handle, SeekFrom::End(2)).unwrap();
+        fs.write(handle, b"!").unwrap();
+        fs.close(handle).unwrap();
+        assert_eq!(read_to_string(&mut fs, "/log").unwrap(), "hello there\0\0!");
+
+        // Renamed while open, sealed in its new place
+        let handle = fs.open("/log", OpenOptions::new().write(true)).unwrap();
+        fs.rename("/log", "/old.log").unwrap();
+        fs.write(handle, b"H").unwrap();
+        fs.close(handle).unwrap();
+        assert!(!fs.exists("/log"));
+        assert!(
+            read_to_string(&mut fs, "/old.log")
+                .unwrap()
+                .starts_with("Hello")
+        );
+
+        // A new empty file is still a valid sealed file
+        write_string(&mut fs, "/empty", "").unwrap();
+        assert_eq!(fs.metadata("/empty").unwrap().size, 0);
+        assert_eq!(read_to_string(&mut fs, "/empty").unwrap(), "");
+
+        let read_only = fs.open("/empty", OpenOptions::new().read(true)).unwrap();
+        assert!(fs.write(read_only, b"x").is_err());
+        fs.close(read_only).unwrap();
+    }
+}
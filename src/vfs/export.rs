@@ -0,0 +1,249 @@
+//! Whole filesystems as tar streams
+//!
+//! [`export`] writes every file, directory and symbolic link of a
+//! filesystem into one tar archive (see [`super::tar`]), with its mode,
+//! owner, modification time and extended attributes, so it can be
+//! downloaded and kept, or unpacked with any tar. [`import`] builds a new
+//! [`MemoryFs`] from such an archive, or from one made elsewhere.
+//!
+//! Sparse files are written out in full; runs of zeros come back as holes
+//! on import. Devices and FIFOs in a foreign archive are skipped.
+
+use super::memory::MemoryFs;
+use super::sparse::write_sparse;
+use super::tar::{self, TarAttrs, TarKind, TarWriter};
+use super::{FileSystem, OpenOptions};
+use std::collections::BTreeMap;
+use std::io;
+
+/// What `export` and `import` did
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ArchiveStats {
+    /// Entries, the root directory included
+    pub entries: usize,
+    /// Bytes of file content
+    pub bytes: u64,
+}
+
+/// Write the whole of `fs` as a tar archive
+pub fn export<F: FileSystem + ?Sized>(fs: &mut F) -> io::Result<(Vec<u8>, ArchiveStats)> {
+    let mut writer = TarWriter::new();
+    let mut stats = ArchiveStats::default();
+    let mut pending = vec!["/".to_string()];
+    while let Some(path) = pending.pop() {
+        let meta = fs.metadata(&path)?;
+        let mut xattrs = BTreeMap::new();
+        for name in fs.listxattr(&path)? {
+            let value = fs.getxattr(&path, &name)?;
+            xattrs.insert(name, value);
+        }
+        let attrs = TarAttrs {
+            mode: meta.mode,
+            uid: meta.uid,
+            gid: meta.gid,
+            mtime: meta.mtime.max(0.0) as u64,
+            xattrs,
+        };
+
+        if meta.is_symlink {
+            writer.add_symlink(&path, &fs.read_link(&path)?, &attrs);
+        } else if meta.is_dir {
+            writer.add_dir(&path, &attrs);
+            let mut names: Vec<String> = fs.read_dir(&path)?.into_iter().map(|e| e.name).collect();
+            // Popped in name order
+            names.sort_unstable_by(|a, b| b.cmp(a));
+            let base = path.trim_end_matches('/');
+            pending.extend(names.into_iter().map(|name| format!("{}/{}", base, name)));
+        } else {
+            let handle = fs.open(&path, OpenOptions::new().read(true))?;
+            let mut data = vec![0u8; meta.size as usize];
+            let read = fs.read(handle, &mut data);
+            fs.close(handle)?;
+            read?;
+            stats.bytes += data.len() as u64;
+            writer.add_file(&path, &data, &attrs);
+        }
+        stats.entries += 1;
+    }
+    Ok((writer.finish(), stats))
+}
+
+/// Build a filesystem from a tar archive
+///
+/// Later entries replace earlier ones with the same path, as they do when
+/// an archive is extracted, and directories the archive doesn't list are
+/// made with mode 755.
+pub fn import(data: &[u8]) -> io::Result<(MemoryFs, ArchiveStats)> {
+    let entries = tar::read_entries(data)?;
+    let mut fs = MemoryFs::new();
+    let mut stats = ArchiveStats::default();
+    for entry in &entries {
+        let path = entry.path.as_str();
+        make_parents(&mut fs, path)?;
+        match fs.metadata(path) {
+            Ok(meta) if meta.is_dir && entry.kind == TarKind::Dir => {}
+            Ok(meta) if meta.is_dir => remove_tree(&mut fs, path)?,
+            Ok(_) => fs.remove_file(path)?,
+            Err(_) => {}
+        }
+
+        match &entry.kind {
+            TarKind::File { offset, len } => {
+                let handle = fs.open(
+                    path,
+                    OpenOptions::new().write(true).create(true).truncate(true),
+                )?;
+                let written = write_sparse(&mut fs, handle, &data[*offset..offset + len]);
+                fs.close(handle)?;
+                written?;
+                stats.bytes += *len as u64;
+            }
+            TarKind::Dir if path == "/" => {}
+            TarKind::Dir => {
+                if !fs.exists(path) {
+                    fs.create_dir(path)?;
+                }
+            }
+            TarKind::Symlink(target) => fs.symlink(target, path)?,
+            TarKind::HardLink(source) => {
+                fs.link(source, path)?;
+                stats.bytes += fs.metadata(path)?.size;
+            }
+        }
+        fs.chown(path, Some(entry.uid), Some(entry.gid))?;
+        fs.chmod(path, entry.mode)?;
+        for (name, value) in &entry.xattrs {
+            fs.setxattr(path, name, value)?;
+        }
+        stats.entries += 1;
+    }
+
+    // Last, as adding to a directory changes its time
+    for entry in &entries {
+        let mtime = Some(entry.mtime as f64);
+        fs.utimes(&entry.path, mtime, mtime)?;
+    }
+    if !entries.iter().any(|e| e.path == "/") {
+        stats.entries += 1;
+    }
+    Ok((fs, stats))
+}
+
+/// Create the missing directories above `path`
+fn make_parents(fs: &mut MemoryFs, path: &str) -> io::Result<()> {
+    let mut dir = String::new();
+    let parts: Vec<&str> = path.split('/').filter(|p| !p.is_empty()).collect();
+    for part in parts.iter().take(parts.len().saturating_sub(1)) {
+        dir.push('/');
+        dir.push_str(part);
+        match fs.metadata(&dir) {
+            Ok(meta) if meta.is_dir => {}
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: parent is not a directory", path),
+                ));
+            }
+            Err(_) => {
+                fs.create_dir(&dir)?;
+                fs.chmod(&dir, 0o755)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Remove `dir` and everything in it
+fn remove_tree(fs: &mut MemoryFs, dir: &str) -> io::Result<()> {
+    for entry in fs.read_dir(dir)? {
+        let path = format!("{}/{}", dir, entry.name);
+        if entry.is_dir && !entry.is_symlink {
+            remove_tree(fs, &path)?;
+        } else {
+            fs.remove_file(&path)?;
+        }
+    }
+    fs.remove_dir(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::{read_to_string, write_string};
+
+    fn sample() -> MemoryFs {
+        let mut fs = MemoryFs::new();
+        fs.create_dir("/etc").unwrap();
+        write_string(&mut fs, "/etc/shadow", "root:x").unwrap();
+        fs.chown("/etc/shadow", Some(0), Some(42)).unwrap();
+        fs.chmod("/etc/shadow", 0o640).unwrap();
+        fs.setxattr("/etc/shadow", "user.origin", b"\0\nbinary")
+            .unwrap();
+
+        let deep = format!("/home/{}", "d".repeat(150));
+        fs.create_dir("/home").unwrap();
+        fs.create_dir(&deep).unwrap();
+        write_string(&mut fs, &format!("{}/notes", deep), "deep").unwrap();
+        fs.symlink("/etc/shadow", "/home/link").unwrap();
+        fs.create_dir("/empty").unwrap();
+
+        let handle = fs
+            .open("/sparse", OpenOptions::new().write(true).create(true))
+            .unwrap();
+        fs.seek(handle, io::SeekFrom::Start(1 << 20)).unwrap();
+        fs.write(handle, b"end").unwrap();
+        fs.close(handle).unwrap();
+
+        fs.utimes("/etc/shadow", None, Some(1_700_000_000_123.0))
+            .unwrap();
+        fs.chmod("/", 0o755).unwrap();
+        fs
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut fs = sample();
+        let (archive, stats) = export(&mut fs).unwrap();
+        assert_eq!(stats.entries, 9);
+        assert_eq!(stats.bytes, 6 + 4 + (1 << 20) + 3);
+
+        let (mut copy, imported) = import(&archive).unwrap();
+        assert_eq!(imported, stats);
+        assert!(copy.changes_since(&fs.snapshot()).is_empty());
+
+        let meta = copy.metadata("/etc/shadow").unwrap();
+        assert_eq!((meta.uid, meta.gid, meta.mode), (0, 42, 0o640));
+        assert_eq!(meta.mtime, 1_700_000_000_123.0);
+        assert_eq!(
+            copy.getxattr("/etc/shadow", "user.origin").unwrap(),
+            b"\0\nbinary"
+        );
+        assert_eq!(copy.read_link("/home/link").unwrap(), "/etc/shadow");
+        assert!(copy.metadata("/empty").unwrap().is_dir);
+        // Holes come back as holes
+        assert!(copy.metadata("/sparse").unwrap().allocated < 4096);
+        let deep = format!("/home/{}/notes", "d".repeat(150));
+        assert_eq!(read_to_string(&mut copy, &deep).unwrap(), "deep");
+    }
+
+    #[test]
+    fn test_import_foreign() {
+        // Parents made up, later entries win, paths kept inside
+        let mut writer = TarWriter::new();
+        let attrs = TarAttrs {
+            mode: 0o644,
+            ..TarAttrs::default()
+        };
+        writer.add_file("a/b/c", b"one", &attrs);
+        writer.add_file("a/b/c", b"two", &attrs);
+        writer.add_file("../../escape", b"x", &attrs);
+        let (mut fs, stats) = import(&writer.finish()).unwrap();
+        assert_eq!(stats.entries, 4);
+        assert_eq!(read_to_string(&mut fs, "/a/b/c").unwrap(), "two");
+        assert_eq!(fs.metadata("/a/b").unwrap().mode, 0o755);
+        assert!(fs.exists("/escape"));
+
+        let err = import(&[1u8; 1024]).map(|_| ()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,316 @@
+//! Checksummed snapshots and backup rotation
+//!
+//! A persisted snapshot ends in a footer holding the SHA-256 of everything
+//! before it:
+//!
+//! ```text
+//! {"nodes":...}
+//! #axeberg-sha256:9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08
+//! ```
+//!
+//! Every save first moves the previous snapshot into a backup slot, keeping
+//! the last [`BACKUPS`] (`name.1` is the newest). Loading tries the snapshot
+//! and then each backup in turn, and takes the first one whose checksum
+//! matches and which parses; the ones skipped are reported so the caller
+//! can tell the user instead of silently starting empty.
+//!
+//! Snapshots written before checksums existed have no footer. They are
+//! accepted as they are: parsing them is the only check they get.
+
+use crate::kernel::pkg::Checksum;
+use std::fmt;
+
+/// Rotated backups kept next to the snapshot
+pub const BACKUPS: usize = 3;
+
+/// Start of the footer, before the hex digest
+const FOOTER_PREFIX: &[u8] = b"\n#axeberg-sha256:";
+
+/// Footer length: prefix, 64 hex digits and a newline
+const FOOTER_LEN: usize = FOOTER_PREFIX.len() + 64 + 1;
+
+/// Why stored data can't be used
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IntegrityError {
+    /// Nothing was stored
+    Empty,
+    /// The data doesn't match its footer
+    ChecksumMismatch { expected: String, actual: String },
+    /// The checksum matches but the content doesn't parse
+    Invalid(String),
+}
+
+impl fmt::Display for IntegrityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntegrityError::Empty => write!(f, "empty"),
+            IntegrityError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "checksum mismatch (expected {:.12}, got {:.12})",
+                expected, actual
+            ),
+            IntegrityError::Invalid(e) => write!(f, "unreadable: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for IntegrityError {}
+
+/// Which copy a load came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Primary,
+    /// Backup slot, 1 being the newest
+    Backup(usize),
+    /// The journal of a save that was interrupted (see [`super::wal`])
+    Journal,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Primary => write!(f, "current snapshot"),
+            Source::Backup(n) => write!(f, "backup {}", n),
+            Source::Journal => write!(f, "save journal"),
+        }
+    }
+}
+
+/// A successful load, and what was wrong with the copies skipped for it
+#[derive(Debug)]
+pub struct Recovered<T> {
+    pub value: T,
+    pub source: Source,
+    /// One line per copy that was corrupt, e.g. `axeberg_fs.json: empty`
+    pub problems: Vec<String>,
+}
+
+impl<T> Recovered<T> {
+    /// Check if the current snapshot loaded without trouble
+    pub fn is_clean(&self) -> bool {
+        self.source == Source::Primary && self.problems.is_empty()
+    }
+}
+
+/// Append the checksum footer to `data`
+pub fn seal(data: &[u8]) -> Vec<u8> {
+    let mut sealed = Vec::with_capacity(data.len() + FOOTER_LEN);
+    sealed.extend_from_slice(data);
+    sealed.extend_from_slice(FOOTER_PREFIX);
+    sealed.extend_from_slice(Checksum::compute(data).to_hex().as_bytes());
+    sealed.push(b'\n');
+    sealed
+}
+
+/// Check the footer of sealed data and return what it covers
+///
+/// Data without a footer is returned as is.
+pub fn unseal(sealed: &[u8]) -> Result<&[u8], IntegrityError> {
+    if sealed.is_empty() {
+        return Err(IntegrityError::Empty);
+    }
+    let Some(split) = sealed.len().checked_sub(FOOTER_LEN) else {
+        return Ok(sealed);
+    };
+    let (data, footer) = sealed.split_at(split);
+    let Some(digest) = footer.strip_prefix(FOOTER_PREFIX) else {
+        return Ok(sealed);
+    };
+
+    let expected = String::from_utf8_lossy(&digest[..64]).into_owned();
+    let actual = Checksum::compute(data).to_hex();
+    if expected != actual {
+        return Err(IntegrityError::ChecksumMismatch { expected, actual });
+    }
+    Ok(data)
+}
+
+/// Unseal and parse one stored copy
+pub fn open<T, E: fmt::Display>(
+    sealed: &[u8],
+    parse: impl FnOnce(&[u8]) -> Result<T, E>,
+) -> Result<T, IntegrityError> {
+    let data = unseal(sealed)?;
+    parse(data).map_err(|e| IntegrityError::Invalid(e.to_string()))
+}
+
+/// File name of backup slot `n` of `name`
+pub fn backup_name(name: &str, n: usize) -> String {
+    format!("{}.{}", name, n)
+}
+
+/// The copies to try when loading, in order: the snapshot, then the
+/// backups from newest to oldest
+pub fn candidates(name: &str) -> Vec<(Source, String)> {
+    std::iter::once((Source::Primary, name.to_string()))
+        .chain((1..=BACKUPS).map(|n| (Source::Backup(n), backup_name(name, n))))
+        .collect()
+}
+
+/// The copies to make before saving, in order: each backup moves one slot
+/// down (the oldest falls off) and the snapshot becomes backup 1
+pub fn rotation(name: &str) -> Vec<(String, String)> {
+    let mut moves: Vec<(String, String)> = (1..BACKUPS)
+        .rev()
+        .map(|n| (backup_name(name, n), backup_name(name, n + 1)))
+        .collect();
+    moves.push((name.to_string(), backup_name(name, 1)));
+    moves
+}
+
+/// Goes through the copies of a snapshot in [`candidates`] order,
+/// remembering what was wrong with each one skipped
+#[derive(Debug, Default)]
+pub struct Recovery {
+    problems: Vec<String>,
+}
+
+impl Recovery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Try one copy; returns it if it is usable
+    pub fn attempt<T, E: fmt::Display>(
+        &mut self,
+        source: Source,
+        name: &str,
+        sealed: &[u8],
+        parse: impl FnOnce(&[u8]) -> Result<T, E>,
+    ) -> Option<Recovered<T>> {
+        match open(sealed, parse) {
+            Ok(value) => Some(Recovered {
+                value,
+                source,
+                problems: std::mem::take(&mut self.problems),
+            }),
+            Err(e) => {
+                self.problems.push(format!("{}: {}", name, e));
+                None
+            }
+        }
+    }
+
+    /// Record a copy that exists but couldn't be read back whole
+    pub fn unreadable(&mut self, name: &str, problem: impl fmt::Display) {
+        self.problems.push(format!("{}: {}", name, problem));
+    }
+
+    /// After every copy failed or was missing: the problems, if there were
+    /// any copies at all
+    pub fn finish(self) -> Result<(), Vec<String>> {
+        if self.problems.is_empty() {
+            Ok(())
+        } else {
+            Err(self.problems)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn parse(data: &[u8]) -> Result<String, serde_json::Error> {
+        serde_json::from_slice(data)
+    }
+
+    /// Load from `store` the way the persistence layer loads from OPFS
+    fn recover(store: &HashMap<String, Vec<u8>>) -> Result<Option<Recovered<String>>, Vec<String>> {
+        let mut recovery = Recovery::new();
+        for (source, name) in candidates("fs") {
+            if let Some(sealed) = store.get(&name)
+                && let Some(loaded) = recovery.attempt(source, &name, sealed, parse)
+            {
+                return Ok(Some(loaded));
+            }
+        }
+        recovery.finish().map(|()| None)
+    }
+
+    #[test]
+    fn test_seal_and_unseal() {
+        let sealed = seal(b"{\"a\":1}");
+        assert!(sealed.ends_with(b"\n"));
+        assert_eq!(unseal(&sealed), Ok(&b"{\"a\":1}"[..]));
+
+        // A flipped byte is caught
+        let mut corrupt = sealed.clone();
+        corrupt[2] = b'b';
+        assert!(matches!(
+            unseal(&corrupt),
+            Err(IntegrityError::ChecksumMismatch { .. })
+        ));
+
+        // Old snapshots without a footer pass through
+        assert_eq!(unseal(b"{}"), Ok(&b"{}"[..]));
+        assert_eq!(unseal(b""), Err(IntegrityError::Empty));
+        assert!(matches!(
+            open(&seal(b"{"), parse),
+            Err(IntegrityError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_rotation_names() {
+        assert_eq!(
+            rotation("fs.json"),
+            [
+                ("fs.json.2".to_string(), "fs.json.3".to_string()),
+                ("fs.json.1".to_string(), "fs.json.2".to_string()),
+                ("fs.json".to_string(), "fs.json.1".to_string()),
+            ]
+        );
+        let names: Vec<String> = candidates("fs.json").into_iter().map(|c| c.1).collect();
+        assert_eq!(names, ["fs.json", "fs.json.1", "fs.json.2", "fs.json.3"]);
+    }
+
+    #[test]
+    fn test_recover_falls_back() {
+        let mut store: HashMap<String, Vec<u8>> = HashMap::new();
+        assert!(matches!(recover(&store), Ok(None)));
+
+        // Save three times, rotating like the persistence layer does
+        for content in ["one", "two", "three"] {
+            for (from, to) in rotation("fs") {
+                if let Some(data) = store.get(&from).cloned() {
+                    store.insert(to, data);
+                }
+            }
+            let json = serde_json::to_vec(content).unwrap();
+            store.insert("fs".to_string(), seal(&json));
+        }
+        let loaded = recover(&store).unwrap();
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.value, "three");
+        assert!(loaded.is_clean());
+
+        // A torn write of the snapshot falls back to the newest backup
+        let torn = store["fs"][..4].to_vec();
+        store.insert("fs".to_string(), torn);
+        let loaded = recover(&store).unwrap().unwrap();
+        assert_eq!(loaded.value, "two");
+        assert_eq!(loaded.source, Source::Backup(1));
+        assert!(!loaded.is_clean());
+        assert_eq!(loaded.problems.len(), 1);
+        assert!(loaded.problems[0].starts_with("fs: unreadable:"));
+
+        // So does one with a damaged byte
+        let mut bad = seal(b"\"three\"");
+        bad[1] = b'T';
+        store.insert("fs".to_string(), bad);
+        store.insert("fs.1".to_string(), Vec::new());
+        let loaded = recover(&store).unwrap().unwrap();
+        assert_eq!(loaded.value, "one");
+        assert_eq!(loaded.source, Source::Backup(2));
+        assert_eq!(loaded.problems.len(), 2);
+        assert!(loaded.problems[0].starts_with("fs: checksum mismatch"));
+        assert_eq!(loaded.problems[1], "fs.1: empty");
+
+        // Nothing usable at all
+        store.insert("fs.2".to_string(), Vec::new());
+        store.remove("fs.3");
+        assert_eq!(recover(&store).unwrap_err().len(), 3);
+    }
+}
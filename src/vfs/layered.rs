@@ -5,6 +5,7 @@
 //! All writes go to the upper layer (copy-on-write semantics).
 //! Deletions are tracked via whiteout markers in the upper layer.
 
+use super::sparse::write_sparse;
 use super::{DirEntry, FileHandle, FileSystem, MemoryFs, Metadata, OpenOptions};
 use std::collections::HashSet;
 use std::io::{self, SeekFrom};
@@ -12,11 +13,11 @@ use std::io::{self, SeekFrom};
 /// Whiteout prefix for marking deleted files
 /// A whiteout is a special marker that indicates a file was deleted in the upper layer,
 /// hiding it from the lower layer.
-const WHITEOUT_PREFIX: &str = ".wh.";
+pub(super) const WHITEOUT_PREFIX: &str = ".wh.";
 
 /// Opaque directory marker
 /// When a directory is marked opaque, its contents from the lower layer are completely hidden.
-const OPAQUE_MARKER: &str = ".wh..wh..opq";
+pub(super) const OPAQUE_MARKER: &str = ".wh..wh..opq";
 
 /// A handle tracking which layer owns a file operation
 #[derive(Debug, Clone)]
@@ -228,7 +229,7 @@ impl LayeredFs {
             let handle = self
                 .upper
                 .open(&path, OpenOptions::new().write(true).create(true))?;
-            self.upper.write(handle, &data)?;
+            write_sparse(&mut self.upper, handle, &data)?;
             self.upper.close(handle)?;
         }
 
@@ -236,6 +237,12 @@ impl LayeredFs {
         self.upper.chmod(&path, meta.mode)?;
         self.upper.chown(&path, Some(meta.uid), Some(meta.gid))?;
 
+        // Copy extended attributes
+        for name in self.lower.listxattr(&path)? {
+            let value = self.lower.getxattr(&path, &name)?;
+            self.upper.setxattr(&path, &name, &value)?;
+        }
+
         Ok(())
     }
 
@@ -416,6 +423,23 @@ impl FileSystem for LayeredFs {
         }
     }
 
+    fn read_with(
+        &mut self,
+        handle: FileHandle,
+        len: usize,
+        sink: &mut dyn FnMut(&[u8]),
+    ) -> io::Result<usize> {
+        let layer_handle = self
+            .handles
+            .get(handle)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid file handle"))?;
+
+        match layer_handle.layer {
+            Layer::Upper => self.upper.read_with(layer_handle.inner_handle, len, sink),
+            Layer::Lower => self.lower.read_with(layer_handle.inner_handle, len, sink),
+        }
+    }
+
     fn write(&mut self, handle: FileHandle, buf: &[u8]) -> io::Result<usize> {
         let layer_handle = self
             .handles
@@ -917,6 +941,73 @@ impl FileSystem for LayeredFs {
             Err(io::Error::new(io::ErrorKind::NotFound, "Path not found"))
         }
     }
+
+    fn setxattr(&mut self, path: &str, name: &str, value: &[u8]) -> io::Result<()> {
+        let path = Self::normalize_path(path);
+
+        // Check if whited out
+        if self.is_whiteout(&path) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "Path not found"));
+        }
+
+        // Need to copy up before modifying
+        if !self.upper.exists(&path) && self.lower.exists(&path) {
+            self.copy_up(&path)?;
+        }
+
+        if self.upper.exists(&path) {
+            self.upper.setxattr(&path, name, value)
+        } else {
+            Err(io::Error::new(io::ErrorKind::NotFound, "Path not found"))
+        }
+    }
+
+    fn getxattr(&self, path: &str, name: &str) -> io::Result<Vec<u8>> {
+        let path = Self::normalize_path(path);
+
+        // Check for whiteout
+        if self.is_whiteout(&path) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "Path not found"));
+        }
+
+        // Check upper first, then lower
+        if self.upper.exists(&path) {
+            self.upper.getxattr(&path, name)
+        } else if self.lower.exists(&path) {
+            self.lower.getxattr(&path, name)
+        } else {
+            Err(io::Error::new(io::ErrorKind::NotFound, "Path not found"))
+        }
+    }
+
+    fn listxattr(&self, path: &str) -> io::Result<Vec<String>> {
+        let path = Self::normalize_path(path);
+
+        // Check for whiteout
+        if self.is_whiteout(&path) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "Path not found"));
+        }
+
+        // Check upper first, then lower
+        if self.upper.exists(&path) {
+            self.upper.listxattr(&path)
+        } else if self.lower.exists(&path) {
+            self.lower.listxattr(&path)
+        } else {
+            Err(io::Error::new(io::ErrorKind::NotFound, "Path not found"))
+        }
+    }
+
+    fn removexattr(&mut self, path: &str, name: &str) -> io::Result<()> {
+        let path = Self::normalize_path(path);
+
+        // Nothing to copy up if there's nothing to remove
+        self.getxattr(&path, name)?;
+        if !self.upper.exists(&path) {
+            self.copy_up(&path)?;
+        }
+        self.upper.removexattr(&path, name)
+    }
 }
 
 #[cfg(test)]
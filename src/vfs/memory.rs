@@ -3,10 +3,20 @@
 //! Simple, fast, ephemeral. Good for development and as a cache layer.
 //! Supports serialization for persistence to OPFS.
 
+mod fsck;
+
+pub use fsck::{Finding, FsckProblem, FsckReport, LOST_FOUND, Repair};
+
+use super::acl::{ACL_XATTR, Acl};
+use super::changelog::{ChangeCursor, ChangeLog, covers};
+use super::sparse::{self, BlockStore, FileData};
 use super::{DirEntry, FileHandle, FileSystem, Metadata, OpenOptions};
 use serde::{Deserialize, Serialize};
 use slab::Slab;
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
 use std::io::{self, SeekFrom};
 
 /// A file's contents and position
@@ -18,9 +28,9 @@ struct OpenFile {
 }
 
 /// A stored file or directory
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 enum Node {
-    File(Vec<u8>),
+    File(FileData),
     Directory,
     Symlink(String),
 }
@@ -43,6 +53,9 @@ struct NodeMeta {
     /// Change time (last metadata change) in milliseconds since epoch
     #[serde(default)]
     ctime: f64,
+    /// Extended attributes, by name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    xattrs: BTreeMap<String, Vec<u8>>,
 }
 
 impl Default for NodeMeta {
@@ -54,6 +67,7 @@ impl Default for NodeMeta {
             atime: 0.0,
             mtime: 0.0,
             ctime: 0.0,
+            xattrs: BTreeMap::new(),
         }
     }
 }
@@ -67,6 +81,7 @@ impl NodeMeta {
             atime: now,
             mtime: now,
             ctime: now,
+            xattrs: BTreeMap::new(),
         }
     }
 
@@ -82,6 +97,7 @@ impl NodeMeta {
             atime: 0.0,
             mtime: 0.0,
             ctime: 0.0,
+            xattrs: BTreeMap::new(),
         }
     }
 
@@ -97,6 +113,7 @@ impl NodeMeta {
             atime: 0.0,
             mtime: 0.0,
             ctime: 0.0,
+            xattrs: BTreeMap::new(),
         }
     }
 
@@ -106,17 +123,125 @@ impl NodeMeta {
 }
 
 /// Serializable snapshot of the filesystem
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct FsSnapshot {
     /// All files and directories
     nodes: HashMap<String, Node>,
     /// Permission metadata for each path
     #[serde(default)]
     meta: HashMap<String, NodeMeta>,
+    /// Per-user limits on bytes owned, by uid
+    #[serde(default)]
+    quotas: BTreeMap<u32, u64>,
     /// Format version for future compatibility
     version: u32,
 }
 
+impl FsSnapshot {
+    /// A snapshot holding only `paths` (those that exist in this one)
+    pub fn subset<'a>(&self, paths: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut nodes = HashMap::new();
+        let mut meta = HashMap::new();
+        for path in paths {
+            if let Some(node) = self.nodes.get(path) {
+                nodes.insert(path.to_string(), node.clone());
+            }
+            if let Some(m) = self.meta.get(path) {
+                meta.insert(path.to_string(), m.clone());
+            }
+        }
+        Self {
+            nodes,
+            meta,
+            quotas: BTreeMap::new(),
+            version: self.version,
+        }
+    }
+
+    /// Check if the snapshot holds `path`
+    pub fn contains(&self, path: &str) -> bool {
+        self.nodes.contains_key(path)
+    }
+
+    /// Content of `path`, if the snapshot holds it as a regular file
+    pub fn file(&self, path: &str) -> Option<Vec<u8>> {
+        match self.nodes.get(path)? {
+            Node::File(data) => Some(data.to_vec()),
+            _ => None,
+        }
+    }
+
+    /// Number of paths in the snapshot
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Check if the snapshot holds no paths
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// [`MemoryFs::fingerprint`] of every path, by path
+    pub fn fingerprints(&self) -> BTreeMap<String, u64> {
+        self.nodes
+            .iter()
+            .map(|(path, node)| (path.clone(), fingerprint(node, self.meta.get(path))))
+            .collect()
+    }
+
+    /// Total bytes of file content
+    pub fn content_size(&self) -> usize {
+        content_size(&self.nodes)
+    }
+
+    /// Paths that differ from `before`, as [`MemoryFs::changes_since`]
+    pub fn changes_since(&self, before: &FsSnapshot) -> Vec<FsChange> {
+        changes_between(&self.nodes, &self.meta, before)
+    }
+
+    /// Drop `dir` and everything under it
+    pub fn remove_under(&mut self, dir: &str) {
+        self.nodes.retain(|path, _| !covers(dir, path));
+        self.meta.retain(|path, _| !covers(dir, path));
+    }
+}
+
+/// How a path changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsChangeKind {
+    Created,
+    Modified,
+    Deleted,
+}
+
+impl FsChangeKind {
+    /// One-character marker: `+` created, `~` modified, `-` deleted
+    pub fn marker(self) -> char {
+        match self {
+            FsChangeKind::Created => '+',
+            FsChangeKind::Modified => '~',
+            FsChangeKind::Deleted => '-',
+        }
+    }
+
+    /// Parse a marker written by [`FsChangeKind::marker`]
+    pub fn from_marker(c: char) -> Option<Self> {
+        match c {
+            '+' => Some(FsChangeKind::Created),
+            '~' => Some(FsChangeKind::Modified),
+            '-' => Some(FsChangeKind::Deleted),
+            _ => None,
+        }
+    }
+}
+
+/// A path that differs between a snapshot and the live filesystem
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FsChange {
+    pub path: String,
+    pub kind: FsChangeKind,
+}
+
 /// In-memory filesystem
 pub struct MemoryFs {
     /// All files and directories, keyed by path
@@ -127,6 +252,16 @@ pub struct MemoryFs {
     handles: Slab<OpenFile>,
     /// Current clock time (set by kernel before operations)
     clock: f64,
+    /// Paths changed, for watchers
+    changes: ChangeLog,
+    /// Bytes owned by each uid, kept up to date as files change
+    usage: HashMap<u32, u64>,
+    /// Per-user limits on bytes owned, by uid
+    quotas: BTreeMap<u32, u64>,
+    /// Directories under which paths are looked up regardless of case
+    casefold: BTreeSet<String>,
+    /// Blocks of file contents, shared between files holding the same bytes
+    blocks: BlockStore,
 }
 
 impl MemoryFs {
@@ -136,6 +271,11 @@ impl MemoryFs {
             meta: HashMap::new(),
             handles: Slab::new(),
             clock: 0.0,
+            changes: ChangeLog::new(),
+            usage: HashMap::new(),
+            quotas: BTreeMap::new(),
+            casefold: BTreeSet::new(),
+            blocks: BlockStore::new(),
         };
         // Root directory always exists
         fs.nodes.insert("/".to_string(), Node::Directory);
@@ -190,7 +330,14 @@ impl MemoryFs {
     }
 
     /// Normalize a path (ensure leading slash, no trailing slash except root, resolve . and ..)
-    fn normalize_path(path: &str) -> String {
+    ///
+    /// Almost every path reaching the filesystem is already normal, so that
+    /// case borrows instead of allocating.
+    fn normalize_path(path: &str) -> Cow<'_, str> {
+        if Self::is_normal(path) {
+            return Cow::Borrowed(path);
+        }
+
         let path = if path.starts_with('/') {
             path.to_string()
         } else {
@@ -210,15 +357,71 @@ impl MemoryFs {
         }
 
         if result.is_empty() {
-            "/".to_string()
+            Cow::Borrowed("/")
         } else {
-            format!("/{}", result.join("/"))
+            Cow::Owned(format!("/{}", result.join("/")))
+        }
+    }
+
+    /// Whether `normalize_path` would return `path` unchanged.
+    fn is_normal(path: &str) -> bool {
+        path == "/"
+            || path
+                .strip_prefix('/')
+                .is_some_and(|rest| rest.split('/').all(|c| !matches!(c, "" | "." | "..")))
+    }
+
+    /// Whether paths under `dir` are looked up regardless of case
+    pub fn is_casefold(&self, dir: &str) -> bool {
+        self.casefold.contains(Self::normalize_path(dir).as_ref())
+    }
+
+    /// Normalize `path` and, under a case-insensitive directory, spell it
+    /// the way the existing file is named
+    fn lookup_path<'p>(&self, path: &'p str) -> Cow<'p, str> {
+        let path = Self::normalize_path(path);
+        if self.casefold.is_empty() || self.nodes.contains_key(path.as_ref()) {
+            return path;
+        }
+        match self.fold_case(&path) {
+            Some(folded) => Cow::Owned(folded),
+            None => path,
         }
     }
 
+    /// The existing spelling of normal `path` below a case-insensitive
+    /// directory, keeping the case of the names that don't exist yet;
+    /// `None` if it isn't below one
+    fn fold_case(&self, path: &str) -> Option<String> {
+        if self.nodes.contains_key(path) {
+            return Some(path.to_string());
+        }
+        let root = self
+            .casefold
+            .iter()
+            .find(|root| *root != path && covers(root, path))?;
+        let fold = |s: &str| s.chars().flat_map(char::to_lowercase).collect::<Vec<_>>();
+        let wanted = fold(path);
+        let found = self
+            .nodes
+            .keys()
+            .filter(|key| covers(root, key) && fold(key) == wanted)
+            .min();
+        if let Some(found) = found {
+            return Some(found.clone());
+        }
+        let (parent, name) = path.rsplit_once('/')?;
+        let parent = if parent.is_empty() { "/" } else { parent };
+        let parent = self.fold_case(parent).unwrap_or_else(|| parent.to_string());
+        Some(match parent.as_str() {
+            "/" => format!("/{}", name),
+            _ => format!("{}/{}", parent, name),
+        })
+    }
+
     /// Get parent directory of a path
     fn parent_path(path: &str) -> Option<String> {
-        let path = Self::normalize_path(path);
+        let path = Self::normalize_path(path).into_owned();
         if path == "/" {
             return None;
         }
@@ -275,9 +478,9 @@ impl MemoryFs {
             ));
         }
 
-        let normalized = Self::normalize_path(path);
+        let normalized = self.lookup_path(path);
 
-        match self.nodes.get(&normalized) {
+        match self.nodes.get(normalized.as_ref()) {
             Some(Node::Symlink(target)) => {
                 // Resolve the symlink target
                 let resolved_target = if target.starts_with('/') {
@@ -296,7 +499,7 @@ impl MemoryFs {
             }
             Some(_) => {
                 // Not a symlink, return the normalized path
-                Ok(normalized)
+                Ok(normalized.into_owned())
             }
             None => {
                 // Path doesn't exist - could be a path with symlinks in parent
@@ -314,7 +517,7 @@ impl MemoryFs {
             ));
         }
 
-        let normalized = Self::normalize_path(path);
+        let normalized = self.lookup_path(path);
         if normalized == "/" {
             return Ok("/".to_string());
         }
@@ -325,6 +528,7 @@ impl MemoryFs {
 
         for component in components {
             let current_path = format!("{}/{}", resolved, component);
+            let current_path = self.lookup_path(&current_path).into_owned();
 
             match self.nodes.get(&current_path) {
                 Some(Node::Symlink(target)) => {
@@ -363,8 +567,8 @@ impl MemoryFs {
 
     /// Check if a path is a symlink (without following it)
     pub fn is_symlink(&self, path: &str) -> bool {
-        let normalized = Self::normalize_path(path);
-        matches!(self.nodes.get(&normalized), Some(Node::Symlink(_)))
+        let normalized = self.lookup_path(path);
+        matches!(self.nodes.get(normalized.as_ref()), Some(Node::Symlink(_)))
     }
 }
 
@@ -377,12 +581,128 @@ impl Default for MemoryFs {
 /// Snapshot version - increment when format changes
 const SNAPSHOT_VERSION: u32 = 2;
 
+/// Hash of a node and its owner, mode and extended attributes
+fn fingerprint(node: &Node, meta: Option<&NodeMeta>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    node.hash(&mut hasher);
+    if let Some(meta) = meta {
+        (meta.uid, meta.gid, meta.mode).hash(&mut hasher);
+        // Only hashed when present, so older fingerprints still match
+        if !meta.xattrs.is_empty() {
+            meta.xattrs.hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+/// Bytes a node holds: a file's allocated contents or a symlink's target
+fn node_size(node: &Node) -> usize {
+    match node {
+        Node::File(data) => data.allocated() as usize,
+        Node::Symlink(target) => target.len(),
+        Node::Directory => 0,
+    }
+}
+
+/// Bytes held by files and symlink targets
+fn content_size(nodes: &HashMap<String, Node>) -> usize {
+    nodes.values().map(node_size).sum()
+}
+
+/// Bytes held by each owner, leaving out those with none
+fn usage_by_owner(
+    nodes: &HashMap<String, Node>,
+    meta: &HashMap<String, NodeMeta>,
+) -> HashMap<u32, u64> {
+    let mut usage = HashMap::new();
+    for (path, node) in nodes {
+        let size = node_size(node) as u64;
+        if size > 0 {
+            let uid = meta.get(path).map_or(NodeMeta::default().uid, |m| m.uid);
+            *usage.entry(uid).or_insert(0) += size;
+        }
+    }
+    usage
+}
+
+/// Paths in `nodes` that differ from `before`, sorted by path (see
+/// [`MemoryFs::changes_since`])
+fn changes_between(
+    nodes: &HashMap<String, Node>,
+    meta: &HashMap<String, NodeMeta>,
+    before: &FsSnapshot,
+) -> Vec<FsChange> {
+    let same_meta = |path: &str| match (meta.get(path), before.meta.get(path)) {
+        (Some(a), Some(b)) => {
+            a.uid == b.uid && a.gid == b.gid && a.mode == b.mode && a.xattrs == b.xattrs
+        }
+        (a, b) => a.is_none() && b.is_none(),
+    };
+
+    let mut changes: Vec<FsChange> = nodes
+        .iter()
+        .filter_map(|(path, node)| {
+            let kind = match before.nodes.get(path) {
+                None => FsChangeKind::Created,
+                Some(old) if old != node || !same_meta(path) => FsChangeKind::Modified,
+                Some(_) => return None,
+            };
+            Some(FsChange {
+                path: path.clone(),
+                kind,
+            })
+        })
+        .chain(
+            before
+                .nodes
+                .keys()
+                .filter(|path| !nodes.contains_key(*path))
+                .map(|path| FsChange {
+                    path: path.clone(),
+                    kind: FsChangeKind::Deleted,
+                }),
+        )
+        .collect();
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+    changes
+}
+
 impl MemoryFs {
+    /// Total bytes of file content, as [`FsSnapshot::content_size`]
+    pub fn content_size(&self) -> usize {
+        content_size(&self.nodes)
+    }
+
+    /// Bytes file contents and symlink targets take in memory, where
+    /// blocks shared between files count once; at most
+    /// [`MemoryFs::content_size`]
+    pub fn stored_size(&self) -> usize {
+        let files = self.nodes.values().filter_map(|node| match node {
+            Node::File(data) => Some(data),
+            _ => None,
+        });
+        let links: usize = self
+            .nodes
+            .values()
+            .filter_map(|node| match node {
+                Node::Symlink(target) => Some(target.len()),
+                _ => None,
+            })
+            .sum();
+        sparse::stored_size(files) as usize + links
+    }
+
+    /// Drop shared blocks that no file holds any more
+    pub fn sweep_blocks(&mut self) {
+        self.blocks.sweep();
+    }
+
     /// Create a snapshot of the filesystem for persistence
     pub fn snapshot(&self) -> FsSnapshot {
         FsSnapshot {
             nodes: self.nodes.clone(),
             meta: self.meta.clone(),
+            quotas: self.quotas.clone(),
             version: SNAPSHOT_VERSION,
         }
     }
@@ -422,14 +742,150 @@ impl MemoryFs {
             snapshot.meta
         };
 
+        let mut nodes = snapshot.nodes;
+        let mut blocks = BlockStore::new();
+        for node in nodes.values_mut() {
+            if let Node::File(data) = node {
+                blocks.share(data);
+            }
+        }
+
         Ok(Self {
-            nodes: snapshot.nodes,
+            usage: usage_by_owner(&nodes, &meta),
+            nodes,
             meta,
             handles: Slab::new(),
             clock: 0.0,
+            changes: ChangeLog::new(),
+            quotas: snapshot.quotas,
+            casefold: BTreeSet::new(),
+            blocks,
         })
     }
 
+    /// Paths that differ from `before`, sorted by path
+    ///
+    /// A path counts as modified when its content, kind, owner, mode or
+    /// extended attributes changed. Timestamps are ignored, so reading a
+    /// file is not a change.
+    pub fn changes_since(&self, before: &FsSnapshot) -> Vec<FsChange> {
+        changes_between(&self.nodes, &self.meta, before)
+    }
+
+    /// Hash of a path's content, kind, owner and mode (None if it doesn't exist)
+    ///
+    /// Used to tell whether a path changed since it was last looked at.
+    pub fn fingerprint(&self, path: &str) -> Option<u64> {
+        let node = self.nodes.get(path)?;
+        Some(fingerprint(node, self.meta.get(path)))
+    }
+
+    /// [`MemoryFs::fingerprint`] of `dir` and every path under it
+    pub fn fingerprints_under(&self, dir: &str) -> BTreeMap<String, u64> {
+        self.nodes
+            .iter()
+            .filter(|(path, _)| covers(dir, path))
+            .map(|(path, node)| (path.clone(), fingerprint(node, self.meta.get(path))))
+            .collect()
+    }
+
+    /// Where the change log is now, to pass to [`MemoryFs::changed_since`]
+    pub fn change_cursor(&self) -> ChangeCursor {
+        self.changes.cursor()
+    }
+
+    /// Paths changed after `cursor`; `None` if the log no longer reaches
+    /// back that far and the caller has to rescan (see [`super::changelog`])
+    pub fn changed_since(&self, cursor: ChangeCursor) -> Option<BTreeSet<String>> {
+        self.changes.since(cursor)
+    }
+
+    /// Bring paths forward to a later state: remove `removed`, then make
+    /// every path in `image` as it is there
+    ///
+    /// The counterpart of [`MemoryFs::changes_since`]: with `image` holding
+    /// the created and modified paths of a later snapshot and `removed` its
+    /// deleted ones, this turns the earlier snapshot into the later one.
+    pub fn apply(&mut self, image: &FsSnapshot, removed: &[String]) {
+        for path in removed {
+            self.nodes.remove(path);
+            self.meta.remove(path);
+            self.changes.record(path);
+        }
+        for (path, node) in &image.nodes {
+            self.changes.record(path);
+            self.nodes.insert(path.clone(), node.clone());
+            match image.meta.get(path) {
+                Some(meta) => self.meta.insert(path.clone(), meta.clone()),
+                None => self.meta.remove(path),
+            };
+        }
+        self.usage = usage_by_owner(&self.nodes, &self.meta);
+    }
+
+    /// Undo `changes`, restoring modified and deleted paths from `image`
+    ///
+    /// `image` must hold the before-state of every modified or deleted path
+    /// (see [`FsSnapshot::subset`]). Nothing is changed on error: a created
+    /// directory that has gained entries not in `changes` is left alone
+    /// and reported as not empty.
+    pub fn revert(&mut self, image: &FsSnapshot, changes: &[FsChange]) -> io::Result<()> {
+        let created: HashSet<&str> = changes
+            .iter()
+            .filter(|c| c.kind == FsChangeKind::Created)
+            .map(|c| c.path.as_str())
+            .collect();
+
+        for change in changes {
+            match change.kind {
+                FsChangeKind::Created => {
+                    let prefix = format!("{}/", change.path.trim_end_matches('/'));
+                    if let Some(extra) = self
+                        .nodes
+                        .keys()
+                        .find(|p| p.starts_with(&prefix) && !created.contains(p.as_str()))
+                    {
+                        return Err(io::Error::new(
+                            io::ErrorKind::DirectoryNotEmpty,
+                            format!("{} was created since", extra),
+                        ));
+                    }
+                }
+                FsChangeKind::Modified | FsChangeKind::Deleted => {
+                    if !image.nodes.contains_key(&change.path) {
+                        return Err(io::Error::new(
+                            io::ErrorKind::NotFound,
+                            format!("no before-image for {}", change.path),
+                        ));
+                    }
+                }
+            }
+        }
+
+        for path in &created {
+            self.nodes.remove(*path);
+            self.meta.remove(*path);
+        }
+        for change in changes {
+            self.changes.record(&change.path);
+        }
+
+        // Parents sort before their children
+        let mut restore: Vec<&String> = image.nodes.keys().collect();
+        restore.sort();
+        for path in restore {
+            if let Some(node) = image.nodes.get(path) {
+                self.nodes.insert(path.clone(), node.clone());
+            }
+            match image.meta.get(path) {
+                Some(meta) => self.meta.insert(path.clone(), meta.clone()),
+                None => self.meta.remove(path),
+            };
+        }
+        self.usage = usage_by_owner(&self.nodes, &self.meta);
+        Ok(())
+    }
+
     /// Serialize to JSON bytes
     pub fn to_json(&self) -> io::Result<Vec<u8>> {
         serde_json::to_vec(&self.snapshot())
@@ -442,13 +898,112 @@ impl MemoryFs {
             .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
         Self::restore(snapshot)
     }
+
+    /// Bytes of file contents and symlink targets owned by `uid`
+    pub fn usage(&self, uid: u32) -> u64 {
+        self.usage.get(&uid).copied().unwrap_or(0)
+    }
+
+    /// Bytes owned by every uid that owns any, by uid
+    pub fn usage_by_owner(&self) -> BTreeMap<u32, u64> {
+        self.usage.iter().map(|(&uid, &used)| (uid, used)).collect()
+    }
+
+    /// The most bytes `uid` may own, if limited
+    pub fn quota(&self, uid: u32) -> Option<u64> {
+        self.quotas.get(&uid).copied()
+    }
+
+    /// Every limit set, by uid
+    pub fn quotas(&self) -> &BTreeMap<u32, u64> {
+        &self.quotas
+    }
+
+    /// Limit the bytes `uid` may own, or lift the limit with `None`
+    ///
+    /// A limit below what the user already owns is allowed: they can't
+    /// grow their files until they are back under it.
+    pub fn set_quota(&mut self, uid: u32, limit: Option<u64>) {
+        match limit {
+            Some(limit) => self.quotas.insert(uid, limit),
+            None => self.quotas.remove(&uid),
+        };
+    }
+
+    /// Check that `uid` may own `extra` more bytes
+    pub fn check_quota(&self, uid: u32, extra: u64) -> io::Result<()> {
+        match self.quota(uid) {
+            Some(limit) if extra > 0 && self.usage(uid).saturating_add(extra) > limit => Err(
+                io::Error::new(io::ErrorKind::QuotaExceeded, "Disk quota exceeded"),
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Move the bytes charged to `uid` from `old` to `new`
+    fn charge(&mut self, uid: u32, old: usize, new: usize) {
+        let used = self.usage.entry(uid).or_insert(0);
+        *used = used.saturating_sub(old as u64) + new as u64;
+        if *used == 0 {
+            self.usage.remove(&uid);
+        }
+    }
+
+    /// The uid whose quota `path` counts against
+    fn owner(&self, path: &str) -> u32 {
+        self.meta
+            .get(path)
+            .map_or(NodeMeta::default().uid, |m| m.uid)
+    }
+
+    /// Read from the file behind `handle` at its position with `read`,
+    /// which returns the bytes it read, then move the position past them
+    fn read_file(
+        &mut self,
+        handle: FileHandle,
+        read: impl FnOnce(&FileData, u64) -> usize,
+    ) -> io::Result<usize> {
+        let file = self
+            .handles
+            .get_mut(handle)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid file handle"))?;
+
+        if !file.readable {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "File not opened for reading",
+            ));
+        }
+
+        let path = file.path.clone();
+        let position = file.position;
+
+        let to_read = match self.nodes.get(&path) {
+            Some(Node::File(data)) => read(data, position),
+            _ => return Err(io::Error::new(io::ErrorKind::NotFound, "File not found")),
+        };
+
+        // Update position
+        if let Some(file) = self.handles.get_mut(handle) {
+            file.position += to_read as u64;
+        }
+
+        // Update access time (atime) on read
+        if to_read > 0
+            && let Some(meta) = self.meta.get_mut(&path)
+        {
+            meta.atime = self.clock;
+        }
+
+        Ok(to_read)
+    }
 }
 
 impl FileSystem for MemoryFs {
     fn open(&mut self, path: &str, options: OpenOptions) -> io::Result<FileHandle> {
         // Validate path before processing
         Self::validate_path(path)?;
-        let path = Self::normalize_path(path);
+        let path = self.lookup_path(path).into_owned();
 
         // Check if file exists
         let exists = self.nodes.contains_key(&path);
@@ -463,19 +1018,24 @@ impl FileSystem for MemoryFs {
         if !exists {
             // Create new file with current timestamp
             self.ensure_parent(&path)?;
-            self.nodes.insert(path.clone(), Node::File(Vec::new()));
+            self.nodes.insert(path.clone(), Node::File(FileData::new()));
             self.meta
                 .insert(path.clone(), NodeMeta::file_default(self.clock));
+            self.changes.record(&path);
         } else if options.truncate {
             // Truncate existing file and update mtime/ctime
+            let owner = self.owner(&path);
             if let Some(Node::File(data)) = self.nodes.get_mut(&path) {
+                let len = data.allocated() as usize;
                 data.clear();
+                self.charge(owner, len, 0);
             }
             // Update modification time
             if let Some(meta) = self.meta.get_mut(&path) {
                 meta.mtime = self.clock;
                 meta.ctime = self.clock;
             }
+            self.changes.record(&path);
         }
 
         // Verify it's a file, not directory
@@ -503,7 +1063,13 @@ impl FileSystem for MemoryFs {
 
     fn close(&mut self, handle: FileHandle) -> io::Result<()> {
         if self.handles.contains(handle) {
-            self.handles.remove(handle);
+            let file = self.handles.remove(handle);
+            // What was written shares blocks with files that hold the same
+            if file.writable
+                && let Some(Node::File(data)) = self.nodes.get_mut(&file.path)
+            {
+                self.blocks.share(data);
+            }
             Ok(())
         } else {
             Err(io::Error::new(
@@ -514,44 +1080,16 @@ impl FileSystem for MemoryFs {
     }
 
     fn read(&mut self, handle: FileHandle, buf: &mut [u8]) -> io::Result<usize> {
-        let file = self
-            .handles
-            .get_mut(handle)
-            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid file handle"))?;
-
-        if !file.readable {
-            return Err(io::Error::new(
-                io::ErrorKind::PermissionDenied,
-                "File not opened for reading",
-            ));
-        }
-
-        let path = file.path.clone();
-        let position = file.position as usize;
-
-        let data = match self.nodes.get(&path) {
-            Some(Node::File(data)) => data,
-            _ => return Err(io::Error::new(io::ErrorKind::NotFound, "File not found")),
-        };
-
-        let available = data.len().saturating_sub(position);
-        let to_read = buf.len().min(available);
-
-        buf[..to_read].copy_from_slice(&data[position..position + to_read]);
-
-        // Update position
-        if let Some(file) = self.handles.get_mut(handle) {
-            file.position += to_read as u64;
-        }
-
-        // Update access time (atime) on read
-        if to_read > 0
-            && let Some(meta) = self.meta.get_mut(&path)
-        {
-            meta.atime = self.clock;
-        }
+        self.read_file(handle, |data, position| data.read_at(position, buf))
+    }
 
-        Ok(to_read)
+    fn read_with(
+        &mut self,
+        handle: FileHandle,
+        len: usize,
+        sink: &mut dyn FnMut(&[u8]),
+    ) -> io::Result<usize> {
+        self.read_file(handle, |data, position| data.visit_at(position, len, sink))
     }
 
     fn write(&mut self, handle: FileHandle, buf: &[u8]) -> io::Result<usize> {
@@ -568,19 +1106,30 @@ impl FileSystem for MemoryFs {
         }
 
         let path = file.path.clone();
-        let position = file.position as usize;
-
-        let data = match self.nodes.get_mut(&path) {
-            Some(Node::File(data)) => data,
+        let position = file.position;
+        let owner = self.owner(&path);
+        let end = position.checked_add(buf.len() as u64).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "write past the largest file size",
+            )
+        })?;
+
+        // Only bytes that land in a hole or past the end take more space
+        let (allocated, added) = match self.nodes.get(&path) {
+            Some(Node::File(data)) => (
+                data.allocated(),
+                buf.len() as u64 - data.allocated_in(position, end),
+            ),
             _ => return Err(io::Error::new(io::ErrorKind::NotFound, "File not found")),
         };
+        self.check_quota(owner, added)?;
+        self.charge(owner, allocated as usize, (allocated + added) as usize);
 
-        // Extend file if necessary
-        if position + buf.len() > data.len() {
-            data.resize(position + buf.len(), 0);
-        }
-
-        data[position..position + buf.len()].copy_from_slice(buf);
+        let Some(Node::File(data)) = self.nodes.get_mut(&path) else {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "File not found"));
+        };
+        data.write_at(position, buf);
 
         // Update position
         if let Some(file) = self.handles.get_mut(handle) {
@@ -588,11 +1137,12 @@ impl FileSystem for MemoryFs {
         }
 
         // Update modification time (mtime) and change time (ctime) on write
-        if !buf.is_empty()
-            && let Some(meta) = self.meta.get_mut(&path)
-        {
-            meta.mtime = self.clock;
-            meta.ctime = self.clock;
+        if !buf.is_empty() {
+            if let Some(meta) = self.meta.get_mut(&path) {
+                meta.mtime = self.clock;
+                meta.ctime = self.clock;
+            }
+            self.changes.record(&path);
         }
 
         Ok(buf.len())
@@ -608,7 +1158,7 @@ impl FileSystem for MemoryFs {
         let current = file.position;
 
         let size = match self.nodes.get(&path) {
-            Some(Node::File(data)) => data.len() as u64,
+            Some(Node::File(data)) => data.len(),
             _ => return Err(io::Error::new(io::ErrorKind::NotFound, "File not found")),
         };
 
@@ -642,13 +1192,18 @@ impl FileSystem for MemoryFs {
     }
 
     fn metadata(&self, path: &str) -> io::Result<Metadata> {
-        let path = Self::normalize_path(path);
+        let path = self.lookup_path(path);
 
-        let meta = self.meta.get(&path).cloned().unwrap_or_default();
+        let meta = self.meta.get(path.as_ref()).cloned().unwrap_or_default();
+        let acl = meta
+            .xattrs
+            .get(ACL_XATTR)
+            .and_then(|value| Acl::decode(value));
 
-        match self.nodes.get(&path) {
+        match self.nodes.get(path.as_ref()) {
             Some(Node::File(data)) => Ok(Metadata {
-                size: data.len() as u64,
+                size: data.len(),
+                allocated: data.allocated(),
                 is_dir: false,
                 is_file: true,
                 is_symlink: false,
@@ -660,9 +1215,11 @@ impl FileSystem for MemoryFs {
                 mtime: meta.mtime,
                 ctime: meta.ctime,
                 nlink: 1,
+                acl,
             }),
             Some(Node::Directory) => Ok(Metadata {
                 size: 0,
+                allocated: 0,
                 is_dir: true,
                 is_file: false,
                 is_symlink: false,
@@ -674,9 +1231,11 @@ impl FileSystem for MemoryFs {
                 mtime: meta.mtime,
                 ctime: meta.ctime,
                 nlink: 2, // Directories have at least 2 (. and parent)
+                acl,
             }),
             Some(Node::Symlink(target)) => Ok(Metadata {
                 size: target.len() as u64,
+                allocated: target.len() as u64,
                 is_dir: false,
                 is_file: false,
                 is_symlink: true,
@@ -688,6 +1247,7 @@ impl FileSystem for MemoryFs {
                 mtime: meta.mtime,
                 ctime: meta.ctime,
                 nlink: 1,
+                acl: None,
             }),
             None => Err(io::Error::new(io::ErrorKind::NotFound, "Path not found")),
         }
@@ -695,7 +1255,7 @@ impl FileSystem for MemoryFs {
 
     fn create_dir(&mut self, path: &str) -> io::Result<()> {
         Self::validate_path(path)?;
-        let path = Self::normalize_path(path);
+        let path = self.lookup_path(path).into_owned();
 
         if self.nodes.contains_key(&path) {
             return Err(io::Error::new(
@@ -707,14 +1267,15 @@ impl FileSystem for MemoryFs {
         self.ensure_parent(&path)?;
         self.nodes.insert(path.clone(), Node::Directory);
         self.meta
-            .insert(path, NodeMeta::dir_default_with_time(self.clock));
+            .insert(path.clone(), NodeMeta::dir_default_with_time(self.clock));
+        self.changes.record(&path);
         Ok(())
     }
 
     fn read_dir(&self, path: &str) -> io::Result<Vec<DirEntry>> {
-        let path = Self::normalize_path(path);
+        let path = self.lookup_path(path);
 
-        match self.nodes.get(&path) {
+        match self.nodes.get(path.as_ref()) {
             Some(Node::Directory) => {}
             Some(Node::File(_)) | Some(Node::Symlink(_)) => {
                 return Err(io::Error::new(
@@ -730,28 +1291,17 @@ impl FileSystem for MemoryFs {
             }
         }
 
-        let prefix = if path == "/" {
-            "/".to_string()
-        } else {
-            format!("{}/", path)
-        };
+        // Children are `<path>/<name>`; the root's are `/<name>`
+        let base = if path == "/" { "" } else { path.as_ref() };
 
         let entries: Vec<DirEntry> = self
             .nodes
             .iter()
             .filter_map(|(p, node)| {
-                if p == &path {
-                    return None; // Skip self
-                }
-
                 // Check if this is a direct child
-                if !p.starts_with(&prefix) {
-                    return None;
-                }
-
-                let relative = &p[prefix.len()..];
-                if relative.contains('/') {
-                    return None; // Not a direct child
+                let relative = p.strip_prefix(base)?.strip_prefix('/')?;
+                if relative.is_empty() || relative.contains('/') {
+                    return None; // Self or not a direct child
                 }
 
                 Some(DirEntry {
@@ -767,12 +1317,16 @@ impl FileSystem for MemoryFs {
 
     fn remove_file(&mut self, path: &str) -> io::Result<()> {
         Self::validate_path(path)?;
-        let path = Self::normalize_path(path);
+        let path = self.lookup_path(path).into_owned();
 
         match self.nodes.get(&path) {
             Some(Node::File(_)) | Some(Node::Symlink(_)) => {
-                self.nodes.remove(&path);
+                let owner = self.owner(&path);
+                if let Some(node) = self.nodes.remove(&path) {
+                    self.charge(owner, node_size(&node), 0);
+                }
                 self.meta.remove(&path);
+                self.changes.record(&path);
                 Ok(())
             }
             Some(Node::Directory) => Err(io::Error::new(
@@ -785,7 +1339,7 @@ impl FileSystem for MemoryFs {
 
     fn remove_dir(&mut self, path: &str) -> io::Result<()> {
         Self::validate_path(path)?;
-        let path = Self::normalize_path(path);
+        let path = self.lookup_path(path).into_owned();
 
         if path == "/" {
             return Err(io::Error::new(
@@ -807,6 +1361,7 @@ impl FileSystem for MemoryFs {
                 }
                 self.nodes.remove(&path);
                 self.meta.remove(&path);
+                self.changes.record(&path);
                 Ok(())
             }
             Some(Node::File(_)) | Some(Node::Symlink(_)) => Err(io::Error::new(
@@ -821,8 +1376,20 @@ impl FileSystem for MemoryFs {
     }
 
     fn rename(&mut self, from: &str, to: &str) -> io::Result<()> {
-        let from = Self::normalize_path(from);
-        let to = Self::normalize_path(to);
+        let from = self.lookup_path(from).into_owned();
+        let to = match self.lookup_path(to) {
+            // A change of case only respells the name
+            folded if folded == from => {
+                let given = Self::normalize_path(to);
+                let name = given.rsplit('/').next().unwrap_or_default();
+                match folded.rsplit_once('/') {
+                    Some(("", _)) => format!("/{}", name),
+                    Some((parent, _)) => format!("{}/{}", parent, name),
+                    None => given.into_owned(),
+                }
+            }
+            folded => folded.into_owned(),
+        };
 
         if from == "/" {
             return Err(io::Error::new(
@@ -882,15 +1449,17 @@ impl FileSystem for MemoryFs {
             self.nodes.insert(to.clone(), node);
         }
         if let Some(meta) = self.meta.remove(&from) {
-            self.meta.insert(to, meta);
+            self.meta.insert(to.clone(), meta);
         }
+        self.changes.record(&from);
+        self.changes.record(&to);
 
         Ok(())
     }
 
     fn copy_file(&mut self, from: &str, to: &str) -> io::Result<u64> {
-        let from = Self::normalize_path(from);
-        let to = Self::normalize_path(to);
+        let from = self.lookup_path(from).into_owned();
+        let to = self.lookup_path(to).into_owned();
 
         // Get source data (for symlinks, copy the link itself)
         let node_to_copy = match self.nodes.get(&from) {
@@ -906,10 +1475,12 @@ impl FileSystem for MemoryFs {
         };
 
         let size = match &node_to_copy {
-            Node::File(data) => data.len() as u64,
+            Node::File(data) => data.len(),
             Node::Symlink(target) => target.len() as u64,
             Node::Directory => 0,
         };
+        // Holes stay holes in the copy, and cost nothing
+        let charged = node_size(&node_to_copy);
 
         // Ensure parent exists
         self.ensure_parent(&to)?;
@@ -917,20 +1488,33 @@ impl FileSystem for MemoryFs {
         // Copy metadata (but set new owner to current user would require context)
         let meta = self.meta.get(&from).cloned().unwrap_or_default();
 
+        // The copy counts against the source's owner, less any file it replaces
+        let replaced = self.nodes.get(&to).map_or(0, node_size);
+        let replaced_owner = self.owner(&to);
+        let freed = if replaced_owner == meta.uid {
+            replaced
+        } else {
+            0
+        };
+        self.check_quota(meta.uid, charged.saturating_sub(freed) as u64)?;
+        self.charge(replaced_owner, replaced, 0);
+        self.charge(meta.uid, 0, charged);
+
         // Insert copy at destination
         self.nodes.insert(to.clone(), node_to_copy);
-        self.meta.insert(to, meta);
+        self.meta.insert(to.clone(), meta);
+        self.changes.record(&to);
 
         Ok(size)
     }
 
     fn exists(&self, path: &str) -> bool {
-        let path = Self::normalize_path(path);
-        self.nodes.contains_key(&path)
+        let path = self.lookup_path(path);
+        self.nodes.contains_key(path.as_ref())
     }
 
     fn symlink(&mut self, target: &str, link_path: &str) -> io::Result<()> {
-        let link_path = Self::normalize_path(link_path);
+        let link_path = self.lookup_path(link_path).into_owned();
 
         // Check if link path already exists
         if self.nodes.contains_key(&link_path) {
@@ -943,17 +1527,21 @@ impl FileSystem for MemoryFs {
         // Ensure parent directory exists
         self.ensure_parent(&link_path)?;
 
+        // Symlinks have mode 0o777 by convention (permissions are on target)
+        let meta = NodeMeta::symlink_default(self.clock);
+        self.check_quota(meta.uid, target.len() as u64)?;
+        self.charge(meta.uid, 0, target.len());
+
         // Create the symlink (target is stored as-is, can be relative or absolute)
         self.nodes
             .insert(link_path.clone(), Node::Symlink(target.to_string()));
-        // Symlinks have mode 0o777 by convention (permissions are on target)
-        self.meta
-            .insert(link_path, NodeMeta::symlink_default(self.clock));
+        self.meta.insert(link_path.clone(), meta);
+        self.changes.record(&link_path);
         Ok(())
     }
 
     fn read_link(&self, path: &str) -> io::Result<String> {
-        let path = Self::normalize_path(path);
+        let path = self.lookup_path(path).into_owned();
 
         match self.nodes.get(&path) {
             Some(Node::Symlink(target)) => Ok(target.clone()),
@@ -969,8 +1557,8 @@ impl FileSystem for MemoryFs {
         // Note: True hard links require inode-based storage.
         // For now, we copy the file content to simulate a hard link.
         // This is a simplified implementation that doesn't share inode state.
-        let source = Self::normalize_path(source);
-        let dest = Self::normalize_path(dest);
+        let source = self.lookup_path(source).into_owned();
+        let dest = self.lookup_path(dest).into_owned();
 
         // Check source exists and is a file
         let content = match self.nodes.get(&source) {
@@ -1008,6 +1596,11 @@ impl FileSystem for MemoryFs {
             ));
         }
 
+        // The copy counts against the owner like any other file
+        let owner = self.owner(&source);
+        self.check_quota(owner, content.allocated())?;
+        self.charge(owner, 0, content.allocated() as usize);
+
         // Create the link (copy content)
         self.nodes.insert(dest.clone(), Node::File(content));
 
@@ -1015,16 +1608,18 @@ impl FileSystem for MemoryFs {
         if let Some(source_meta) = self.meta.get(&source).cloned() {
             let mut dest_meta = source_meta;
             dest_meta.ctime = self.clock; // Update ctime for new entry
-            self.meta.insert(dest, dest_meta);
+            self.meta.insert(dest.clone(), dest_meta);
         } else {
-            self.meta.insert(dest, NodeMeta::file_default(self.clock));
+            self.meta
+                .insert(dest.clone(), NodeMeta::file_default(self.clock));
         }
+        self.changes.record(&dest);
 
         Ok(())
     }
 
     fn chmod(&mut self, path: &str, mode: u16) -> io::Result<()> {
-        let path = Self::normalize_path(path);
+        let path = self.lookup_path(path).into_owned();
 
         if !self.nodes.contains_key(&path) {
             return Err(io::Error::new(io::ErrorKind::NotFound, "Path not found"));
@@ -1037,20 +1632,33 @@ impl FileSystem for MemoryFs {
             // Create default meta with the new mode and current timestamp
             let mut node_meta = NodeMeta::file_default(self.clock);
             node_meta.mode = mode & 0o7777;
-            self.meta.insert(path, node_meta);
+            self.meta.insert(path.clone(), node_meta);
         }
+        self.changes.record(&path);
 
         Ok(())
     }
 
     fn chown(&mut self, path: &str, uid: Option<u32>, gid: Option<u32>) -> io::Result<()> {
-        let path = Self::normalize_path(path);
+        let path = self.lookup_path(path).into_owned();
 
         if !self.nodes.contains_key(&path) {
             return Err(io::Error::new(io::ErrorKind::NotFound, "Path not found"));
         }
 
+        // The new owner takes over the bytes
+        let owner = self.owner(&path);
+        let size = self.nodes.get(&path).map_or(0, node_size);
+        if let Some(new_uid) = uid
+            && new_uid != owner
+        {
+            self.check_quota(new_uid, size as u64)?;
+            self.charge(owner, size, 0);
+            self.charge(new_uid, 0, size);
+        }
+
         let clock = self.clock;
+        self.changes.record(&path);
         let meta = self.meta.entry(path).or_default();
 
         if let Some(new_uid) = uid {
@@ -1090,7 +1698,7 @@ impl FileSystem for MemoryFs {
     }
 
     fn utimes(&mut self, path: &str, atime: Option<f64>, mtime: Option<f64>) -> io::Result<()> {
-        let path = Self::normalize_path(path);
+        let path = self.lookup_path(path).into_owned();
 
         if !self.nodes.contains_key(&path) {
             return Err(io::Error::new(io::ErrorKind::NotFound, "Path not found"));
@@ -1104,12 +1712,273 @@ impl FileSystem for MemoryFs {
 
         Ok(())
     }
+
+    fn setxattr(&mut self, path: &str, name: &str, value: &[u8]) -> io::Result<()> {
+        let path = self.lookup_path(path).into_owned();
+        super::check_xattr(name, value)?;
+
+        if !self.nodes.contains_key(&path) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "Path not found"));
+        }
+
+        let clock = self.clock;
+        self.changes.record(&path);
+        let meta = self.meta.entry(path).or_default();
+        meta.xattrs.insert(name.to_string(), value.to_vec());
+        meta.ctime = clock;
+        Ok(())
+    }
+
+    fn getxattr(&self, path: &str, name: &str) -> io::Result<Vec<u8>> {
+        let path = self.lookup_path(path).into_owned();
+
+        if !self.nodes.contains_key(&path) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "Path not found"));
+        }
+
+        self.meta
+            .get(&path)
+            .and_then(|meta| meta.xattrs.get(name))
+            .cloned()
+            .ok_or_else(super::no_such_attribute)
+    }
+
+    fn listxattr(&self, path: &str) -> io::Result<Vec<String>> {
+        let path = self.lookup_path(path).into_owned();
+
+        if !self.nodes.contains_key(&path) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "Path not found"));
+        }
+
+        Ok(self
+            .meta
+            .get(&path)
+            .map(|meta| meta.xattrs.keys().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    fn removexattr(&mut self, path: &str, name: &str) -> io::Result<()> {
+        let path = self.lookup_path(path).into_owned();
+
+        if !self.nodes.contains_key(&path) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "Path not found"));
+        }
+
+        let clock = self.clock;
+        let meta = self
+            .meta
+            .get_mut(&path)
+            .filter(|meta| meta.xattrs.contains_key(name))
+            .ok_or_else(super::no_such_attribute)?;
+        meta.xattrs.remove(name);
+        meta.ctime = clock;
+        self.changes.record(&path);
+        Ok(())
+    }
+
+    /// Names keep the case they were created with: a path that differs
+    /// from an existing one only in case refers to it, instead of making
+    /// another file.
+    fn set_casefold(&mut self, dir: &str, on: bool) {
+        let dir = Self::normalize_path(dir).into_owned();
+        if on {
+            self.casefold.insert(dir);
+        } else {
+            self.casefold.remove(&dir);
+        }
+    }
+
+    fn image(&self) -> Option<io::Result<Vec<u8>>> {
+        Some(self.to_json())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_changes_since_and_revert() {
+        use crate::vfs::{read_to_string, write_string};
+
+        let mut fs = MemoryFs::new();
+        write_string(&mut fs, "/keep.txt", "same").unwrap();
+        write_string(&mut fs, "/edit.txt", "old").unwrap();
+        write_string(&mut fs, "/gone.txt", "bye").unwrap();
+        let before = fs.snapshot();
+
+        // Reads only touch atime and are not changes
+        read_to_string(&mut fs, "/keep.txt").unwrap();
+        write_string(&mut fs, "/edit.txt", "new").unwrap();
+        fs.remove_file("/gone.txt").unwrap();
+        fs.create_dir("/dir").unwrap();
+        write_string(&mut fs, "/dir/new.txt", "hi").unwrap();
+        let edited = fs.fingerprint("/edit.txt");
+
+        let changes = fs.changes_since(&before);
+        let summary: Vec<String> = changes
+            .iter()
+            .map(|c| format!("{}{}", c.kind.marker(), c.path))
+            .collect();
+        assert_eq!(
+            summary,
+            vec!["+/dir", "+/dir/new.txt", "~/edit.txt", "-/gone.txt"]
+        );
+
+        let image = before.subset(
+            changes
+                .iter()
+                .filter(|c| c.kind != FsChangeKind::Created)
+                .map(|c| c.path.as_str()),
+        );
+        assert_eq!(image.len(), 2);
+        assert_eq!(image.content_size(), 6);
+
+        // A file added to a created directory since blocks the revert
+        write_string(&mut fs, "/dir/later.txt", "x").unwrap();
+        let err = fs.revert(&image, &changes).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::DirectoryNotEmpty);
+        assert_eq!(fs.fingerprint("/edit.txt"), edited);
+        fs.remove_file("/dir/later.txt").unwrap();
+
+        fs.revert(&image, &changes).unwrap();
+        assert!(fs.changes_since(&before).is_empty());
+        assert_eq!(read_to_string(&mut fs, "/gone.txt").unwrap(), "bye");
+        assert_ne!(fs.fingerprint("/edit.txt"), edited);
+        assert_eq!(fs.fingerprint("/dir"), None);
+    }
+
+    #[test]
+    fn test_fingerprints_and_apply() {
+        use crate::vfs::{read_to_string, write_string};
+
+        let mut fs = MemoryFs::new();
+        write_string(&mut fs, "/a.txt", "one").unwrap();
+        write_string(&mut fs, "/b.txt", "two").unwrap();
+        let base = fs.snapshot();
+        let mut earlier = MemoryFs::restore(base.clone()).unwrap();
+
+        write_string(&mut fs, "/a.txt", "uno").unwrap();
+        fs.remove_file("/b.txt").unwrap();
+        fs.create_dir("/c").unwrap();
+        let later = fs.snapshot();
+        let fingerprints = later.fingerprints();
+        assert_eq!(
+            fingerprints.get("/a.txt").copied(),
+            fs.fingerprint("/a.txt")
+        );
+        assert!(!fingerprints.contains_key("/b.txt"));
+
+        let changes = fs.changes_since(&base);
+        let image = later.subset(
+            changes
+                .iter()
+                .filter(|c| c.kind != FsChangeKind::Deleted)
+                .map(|c| c.path.as_str()),
+        );
+        let removed: Vec<String> = changes
+            .iter()
+            .filter(|c| c.kind == FsChangeKind::Deleted)
+            .map(|c| c.path.clone())
+            .collect();
+        earlier.apply(&image, &removed);
+        assert!(earlier.changes_since(&later).is_empty());
+        assert_eq!(read_to_string(&mut earlier, "/a.txt").unwrap(), "uno");
+    }
+
+    #[test]
+    fn test_change_feed() {
+        use crate::vfs::write_string;
+
+        let mut fs = MemoryFs::new();
+        fs.create_dir("/notes").unwrap();
+        let cursor = fs.change_cursor();
+        write_string(&mut fs, "/notes/a.txt", "one").unwrap();
+        fs.rename("/notes", "/docs").unwrap();
+        fs.chmod("/docs/a.txt", 0o600).unwrap();
+        let changed: Vec<String> = fs.changed_since(cursor).unwrap().into_iter().collect();
+        assert_eq!(changed, ["/docs", "/docs/a.txt", "/notes", "/notes/a.txt"]);
+
+        let under: Vec<String> = fs.fingerprints_under("/docs").into_keys().collect();
+        assert_eq!(under, ["/docs", "/docs/a.txt"]);
+
+        // Reading changes nothing; a restored filesystem starts a new log
+        let cursor = fs.change_cursor();
+        crate::vfs::read_to_string(&mut fs, "/docs/a.txt").unwrap();
+        assert!(fs.changed_since(cursor).unwrap().is_empty());
+        let restored = MemoryFs::restore(fs.snapshot()).unwrap();
+        assert_eq!(restored.changed_since(cursor), None);
+    }
+
+    #[test]
+    fn test_dedup() {
+        use crate::vfs::{read_to_string, write_string};
+
+        let mut fs = MemoryFs::new();
+        let text = "all work and no play\n".repeat(1000);
+        write_string(&mut fs, "/a.txt", &text).unwrap();
+        assert_eq!(fs.stored_size(), text.len());
+
+        // Writing the same bytes again, or copying, takes no more room
+        write_string(&mut fs, "/b.txt", &text).unwrap();
+        fs.copy_file("/a.txt", "/c.txt").unwrap();
+        assert_eq!(fs.content_size(), 3 * text.len());
+        assert_eq!(fs.stored_size(), text.len());
+
+        // Nor does a snapshot, and changing a file leaves it alone
+        let image = fs.snapshot();
+        write_string(&mut fs, "/c.txt", "changed").unwrap();
+        assert_eq!(fs.stored_size(), text.len() + 7);
+        let mut restored = MemoryFs::restore(image).unwrap();
+        assert_eq!(read_to_string(&mut restored, "/c.txt").unwrap(), text);
+        assert_eq!(restored.stored_size(), text.len());
+    }
+
+    #[test]
+    fn test_casefold() {
+        use crate::vfs::{read_to_string, write_string};
+
+        let mut fs = MemoryFs::new();
+        fs.create_dir("/share").unwrap();
+        fs.create_dir("/other").unwrap();
+        fs.set_casefold("/share", true);
+        assert!(fs.is_casefold("/share"));
+
+        fs.create_dir("/share/Docs").unwrap();
+        write_string(&mut fs, "/share/Docs/ReadMe.md", "hi").unwrap();
+        assert_eq!(
+            read_to_string(&mut fs, "/share/DOCS/readme.MD").unwrap(),
+            "hi"
+        );
+        assert!(fs.metadata("/share/docs").unwrap().is_dir);
+
+        // New names keep the case given, in the existing directory
+        write_string(&mut fs, "/share/docs/New.txt", "new").unwrap();
+        assert!(fs.nodes.contains_key("/share/Docs/New.txt"));
+        assert_eq!(
+            fs.create_dir("/share/DOCS").unwrap_err().kind(),
+            io::ErrorKind::AlreadyExists
+        );
+
+        // Renaming to another case of the same name respells it
+        fs.rename("/share/docs", "/share/DOCS").unwrap();
+        let names: Vec<String> = fs
+            .read_dir("/share")
+            .unwrap()
+            .into_iter()
+            .map(|e| e.name)
+            .collect();
+        assert_eq!(names, vec!["DOCS"]);
+        assert!(fs.exists("/share/docs/readme.md"));
+
+        // Only below the directory, and only while it is on
+        fs.create_dir("/other/Dir").unwrap();
+        assert!(!fs.exists("/other/dir"));
+        assert!(!fs.exists("/SHARE"));
+        fs.set_casefold("/share", false);
+        assert!(!fs.exists("/share/docs"));
+    }
+
     #[test]
     fn test_basic_file_ops() {
         let mut fs = MemoryFs::new();
@@ -1208,6 +2077,50 @@ mod tests {
         assert_eq!(fs.metadata("/test.txt").unwrap().size, 2);
     }
 
+    #[test]
+    fn test_sparse_file() {
+        let mut fs = MemoryFs::new();
+        fs.set_quota(1000, Some(64));
+
+        // Seeking far past the end and writing stores only what was written
+        let handle = fs
+            .open("/disk.img", OpenOptions::new().write(true).create(true))
+            .unwrap();
+        fs.seek(handle, SeekFrom::Start(1 << 30)).unwrap();
+        fs.write(handle, b"tail").unwrap();
+        fs.close(handle).unwrap();
+
+        let meta = fs.metadata("/disk.img").unwrap();
+        assert_eq!((meta.size, meta.allocated), ((1 << 30) + 4, 4));
+        assert_eq!(fs.usage(1000), 4);
+
+        let handle = fs.open("/disk.img", OpenOptions::new()).unwrap();
+        fs.seek(handle, SeekFrom::Start((1 << 30) - 2)).unwrap();
+        let mut buf = [9u8; 8];
+        assert_eq!(fs.read(handle, &mut buf).unwrap(), 6);
+        assert_eq!(&buf[..6], b"\0\0tail");
+        fs.close(handle).unwrap();
+
+        // Overwriting stored bytes is free; filling a hole is charged
+        let handle = fs
+            .open("/disk.img", OpenOptions::new().write(true))
+            .unwrap();
+        fs.seek(handle, SeekFrom::Start(1 << 30)).unwrap();
+        fs.write(handle, b"TA").unwrap();
+        fs.write(handle, &[1; 100]).unwrap_err();
+        fs.close(handle).unwrap();
+        assert_eq!(fs.usage(1000), 4);
+
+        // Copies and snapshots keep the holes
+        fs.set_quota(1000, None);
+        fs.copy_file("/disk.img", "/copy.img").unwrap();
+        assert_eq!(fs.metadata("/copy.img").unwrap().allocated, 4);
+        let restored = MemoryFs::from_json(&fs.to_json().unwrap()).unwrap();
+        let meta = restored.metadata("/disk.img").unwrap();
+        assert_eq!((meta.size, meta.allocated), ((1 << 30) + 4, 4));
+        assert_eq!(restored.usage(1000), 8);
+    }
+
     #[test]
     fn test_seek() {
         let mut fs = MemoryFs::new();
@@ -1959,4 +2872,122 @@ mod tests {
         assert_eq!(meta.mtime, 2000.0);
         assert_eq!(meta.ctime, 2000.0);
     }
+
+    #[test]
+    fn test_atomic_write() {
+        use crate::vfs::{atomic_write, read_to_string};
+
+        let mut fs = MemoryFs::new();
+        fs.create_dir("/etc").unwrap();
+        atomic_write(&mut fs, "/etc/conf", b"one").unwrap();
+        assert_eq!(read_to_string(&mut fs, "/etc/conf").unwrap(), "one");
+
+        fs.chmod("/etc/conf", 0o600).unwrap();
+        fs.chown("/etc/conf", Some(0), Some(0)).unwrap();
+        fs.symlink("conf", "/etc/link").unwrap();
+        atomic_write(&mut fs, "/etc/link", b"two").unwrap();
+
+        // The link still points at the file, which kept its mode and owner
+        assert!(fs.metadata("/etc/link").unwrap().is_symlink);
+        assert_eq!(read_to_string(&mut fs, "/etc/conf").unwrap(), "two");
+        let meta = fs.metadata("/etc/conf").unwrap();
+        assert_eq!((meta.mode, meta.uid, meta.gid), (0o600, 0, 0));
+        let names: Vec<String> = fs
+            .read_dir("/etc")
+            .unwrap()
+            .into_iter()
+            .map(|e| e.name)
+            .collect();
+        assert_eq!(names.len(), 2);
+        assert!(!names.iter().any(|n| n.ends_with(".tmp")));
+
+        assert!(atomic_write(&mut fs, "/etc", b"x").is_err());
+        assert!(atomic_write(&mut fs, "/missing/conf", b"x").is_err());
+        assert!(!fs.exists("/missing"));
+    }
+
+    #[test]
+    fn test_quota() {
+        use crate::vfs::write_string;
+
+        let mut fs = MemoryFs::new();
+        write_string(&mut fs, "/a", "12345").unwrap();
+        fs.symlink("/a", "/link").unwrap();
+        assert_eq!(fs.usage(1000), 7);
+
+        fs.set_quota(1000, Some(10));
+        assert_eq!(
+            write_string(&mut fs, "/b", "four").unwrap_err().kind(),
+            io::ErrorKind::QuotaExceeded
+        );
+        assert!(fs.copy_file("/a", "/c").is_err());
+        assert!(fs.link("/a", "/d").is_err());
+        // Rewriting in place only charges the growth
+        write_string(&mut fs, "/a", "123456").unwrap();
+        assert_eq!(fs.usage(1000), 8);
+
+        // Handing a file to another user moves its bytes
+        fs.chown("/a", Some(0), None).unwrap();
+        assert_eq!((fs.usage(1000), fs.usage(0)), (2, 6));
+        fs.set_quota(0, Some(8));
+        assert!(fs.chown("/link", Some(0), None).is_ok());
+        write_string(&mut fs, "/b", "four").unwrap();
+        assert!(fs.chown("/b", Some(0), None).is_err());
+
+        // Over a lowered limit, shrinking still works
+        fs.set_quota(1000, Some(1));
+        fs.remove_file("/b").unwrap();
+        assert_eq!(fs.usage(1000), 0);
+
+        let restored = MemoryFs::restore(fs.snapshot()).unwrap();
+        assert_eq!(restored.usage_by_owner(), fs.usage_by_owner());
+        assert_eq!(restored.quota(1000), Some(1));
+        fs.set_quota(1000, None);
+        assert_eq!(fs.quotas().len(), 1);
+    }
+
+    #[test]
+    fn test_xattr() {
+        use crate::vfs::{XATTR_NAME_MAX, XATTR_SIZE_MAX, atomic_write, write_string};
+
+        let mut fs = MemoryFs::new();
+        write_string(&mut fs, "/a", "data").unwrap();
+        let before = fs.snapshot();
+        fs.setxattr("/a", "user.mime", b"text/plain").unwrap();
+        fs.setxattr("/a", "user.origin", b"").unwrap();
+        assert_eq!(fs.getxattr("/a", "user.mime").unwrap(), b"text/plain");
+        assert_eq!(fs.listxattr("/a").unwrap(), ["user.mime", "user.origin"]);
+        assert_eq!(fs.listxattr("/").unwrap(), Vec::<String>::new());
+        assert_eq!(
+            fs.getxattr("/a", "user.none").unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        assert!(fs.setxattr("/missing", "user.x", b"").is_err());
+        assert!(fs.setxattr("/a", "", b"").is_err());
+        assert!(
+            fs.setxattr("/a", &"n".repeat(XATTR_NAME_MAX + 1), b"")
+                .is_err()
+        );
+        assert!(
+            fs.setxattr("/a", "user.big", &[0; XATTR_SIZE_MAX + 1])
+                .is_err()
+        );
+
+        // Attributes are part of the file's state
+        assert_eq!(fs.changes_since(&before).len(), 1);
+        assert_ne!(
+            fs.fingerprint("/a"),
+            Some(fingerprint(&before.nodes["/a"], None))
+        );
+
+        // They survive a rewrite, a rename and a save
+        atomic_write(&mut fs, "/a", b"new").unwrap();
+        fs.rename("/a", "/b").unwrap();
+        let mut fs = MemoryFs::from_json(&fs.to_json().unwrap()).unwrap();
+        assert_eq!(fs.getxattr("/b", "user.mime").unwrap(), b"text/plain");
+
+        fs.removexattr("/b", "user.mime").unwrap();
+        assert_eq!(fs.listxattr("/b").unwrap(), ["user.origin"]);
+        assert!(fs.removexattr("/b", "user.mime").is_err());
+    }
 }
@@ -0,0 +1,553 @@
+//! Consistency check and repair for [`MemoryFs`]
+//!
+//! The filesystem is a map from path to node, with a second map for each
+//! node's metadata and a running count of the bytes each owner holds.
+//! Nothing stops a hand-edited or damaged snapshot from breaking the
+//! invariants the rest of the code relies on:
+//!
+//! - `/` exists and is a directory
+//! - every path is absolute and normalized (no `//`, `.` or `..`)
+//! - every path's parent is a directory; a path whose parent is missing is
+//!   an orphan that no directory listing reaches
+//! - every node has metadata, and there is none for paths that don't exist
+//! - the bytes charged to each owner match what they own
+//!
+//! Breaking these is an error. Symlinks that point nowhere, or that loop,
+//! are legal but usually stale, so they are only warnings.
+//!
+//! Repairs keep data where they can: missing parent directories are
+//! recreated, and paths that can't stay where they are (a parent that is a
+//! file, a name that can't be normalized) move to `/lost+found`.
+
+use super::{MemoryFs, Node, NodeMeta, usage_by_owner};
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// Where paths that can't be reconnected are moved
+pub const LOST_FOUND: &str = "/lost+found";
+
+/// What [`MemoryFs::fsck`] may change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Repair {
+    /// Report only
+    Nothing,
+    /// Fix errors, leaving warnings alone (what runs at boot)
+    Errors,
+    /// Fix errors, and remove dangling and looping symlinks too
+    All,
+}
+
+/// One inconsistency
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FsckProblem {
+    /// `/` is missing or isn't a directory
+    BadRoot,
+    /// A path that isn't absolute and normalized, or is too long
+    BadPath(String),
+    /// A path whose parent doesn't exist
+    Orphan(String),
+    /// A path whose nearest existing ancestor is a file or symlink
+    NotInDirectory(String),
+    /// A node without metadata
+    MissingMeta(String),
+    /// Metadata for a path that doesn't exist
+    StrayMeta(String),
+    /// A symlink whose target doesn't exist
+    DanglingSymlink { path: String, target: String },
+    /// A symlink that leads back to itself
+    SymlinkLoop(String),
+    /// The bytes charged to an owner differ from what they own
+    WrongUsage {
+        uid: u32,
+        recorded: u64,
+        actual: u64,
+    },
+}
+
+impl FsckProblem {
+    /// Check if the problem breaks an invariant, rather than being a warning
+    pub fn is_error(&self) -> bool {
+        !matches!(
+            self,
+            FsckProblem::DanglingSymlink { .. } | FsckProblem::SymlinkLoop(_)
+        )
+    }
+}
+
+impl fmt::Display for FsckProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FsckProblem::BadRoot => write!(f, "/: root is not a directory"),
+            FsckProblem::BadPath(path) => write!(f, "{:?}: malformed path", path),
+            FsckProblem::Orphan(path) => write!(f, "{}: parent directory missing", path),
+            FsckProblem::NotInDirectory(path) => write!(f, "{}: parent is not a directory", path),
+            FsckProblem::MissingMeta(path) => write!(f, "{}: no owner or mode", path),
+            FsckProblem::StrayMeta(path) => write!(f, "{}: metadata for a missing path", path),
+            FsckProblem::DanglingSymlink { path, target } => {
+                write!(f, "{}: dangling symlink to {}", path, target)
+            }
+            FsckProblem::SymlinkLoop(path) => write!(f, "{}: symlink loop", path),
+            FsckProblem::WrongUsage {
+                uid,
+                recorded,
+                actual,
+            } => write!(
+                f,
+                "uid {}: usage recorded as {} bytes, owns {}",
+                uid, recorded, actual
+            ),
+        }
+    }
+}
+
+/// A problem found, and whether it was fixed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub problem: FsckProblem,
+    pub fixed: bool,
+}
+
+/// The result of a check
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FsckReport {
+    /// Paths checked
+    pub paths: usize,
+    pub findings: Vec<Finding>,
+}
+
+impl FsckReport {
+    /// Errors found, fixed or not
+    pub fn errors(&self) -> usize {
+        self.findings
+            .iter()
+            .filter(|f| f.problem.is_error())
+            .count()
+    }
+
+    /// Warnings found, fixed or not
+    pub fn warnings(&self) -> usize {
+        self.findings.len() - self.errors()
+    }
+
+    /// Problems fixed
+    pub fn fixed(&self) -> usize {
+        self.findings.iter().filter(|f| f.fixed).count()
+    }
+
+    /// Check if no errors are left unfixed
+    pub fn is_consistent(&self) -> bool {
+        self.findings
+            .iter()
+            .all(|f| f.fixed || !f.problem.is_error())
+    }
+}
+
+impl MemoryFs {
+    /// Check the filesystem's invariants, fixing what `repair` allows
+    ///
+    /// Checks run in order, each on the result of the repairs before it,
+    /// so a path moved out of the way is then checked where it landed.
+    pub fn fsck(&mut self, repair: Repair) -> FsckReport {
+        let mut report = FsckReport {
+            paths: self.nodes.len(),
+            findings: Vec::new(),
+        };
+        let fix_errors = repair != Repair::Nothing;
+
+        self.check_root(fix_errors, &mut report);
+        self.check_paths(fix_errors, &mut report);
+        self.check_parents(fix_errors, &mut report);
+        self.check_meta(fix_errors, &mut report);
+        self.check_symlinks(repair == Repair::All, &mut report);
+        self.check_usage(fix_errors, &mut report);
+        report
+    }
+
+    fn check_root(&mut self, fix: bool, report: &mut FsckReport) {
+        if matches!(self.nodes.get("/"), Some(Node::Directory)) {
+            return;
+        }
+        if fix {
+            // Whatever was there is kept, under lost+found
+            if let Some(node) = self.nodes.remove("/") {
+                let meta = self.meta.remove("/");
+                let name = self.lost_name("root");
+                self.insert_lost(name, node, meta);
+            }
+            self.nodes.insert("/".to_string(), Node::Directory);
+            self.meta.insert("/".to_string(), NodeMeta::root_dir());
+            self.changes.record("/");
+        }
+        report.findings.push(Finding {
+            problem: FsckProblem::BadRoot,
+            fixed: fix,
+        });
+    }
+
+    fn check_paths(&mut self, fix: bool, report: &mut FsckReport) {
+        let mut bad: Vec<String> = self
+            .nodes
+            .keys()
+            .filter(|path| {
+                Self::normalize_path(path) != **path || Self::validate_path(path).is_err()
+            })
+            .cloned()
+            .collect();
+        bad.sort();
+
+        for path in bad {
+            if fix {
+                let node = self.nodes.remove(&path);
+                let meta = self.meta.remove(&path);
+                let normal = Self::normalize_path(&path).into_owned();
+                if let Some(node) = node {
+                    if Self::validate_path(&normal).is_ok() && !self.nodes.contains_key(&normal) {
+                        self.nodes.insert(normal.clone(), node);
+                        if let Some(meta) = meta {
+                            self.meta.insert(normal.clone(), meta);
+                        }
+                        self.changes.record(&normal);
+                    } else {
+                        let name = self.lost_name(&normal);
+                        self.insert_lost(name, node, meta);
+                    }
+                }
+            }
+            report.findings.push(Finding {
+                problem: FsckProblem::BadPath(path),
+                fixed: fix,
+            });
+        }
+    }
+
+    fn check_parents(&mut self, fix: bool, report: &mut FsckReport) {
+        // Shallowest first, so a recreated directory is there for its children
+        let mut paths: Vec<String> = self.nodes.keys().filter(|p| *p != "/").cloned().collect();
+        paths.sort_by_key(|p| (p.matches('/').count(), p.clone()));
+
+        for path in paths {
+            // Moved out of the way along with an earlier problem
+            if !self.nodes.contains_key(&path) {
+                continue;
+            }
+            let mut missing = Vec::new();
+            let mut ancestor = Self::parent_path(&path);
+            while let Some(dir) = ancestor.take_if(|dir| !self.nodes.contains_key(dir.as_str())) {
+                ancestor = Self::parent_path(&dir);
+                missing.push(dir);
+            }
+            let in_directory = ancestor
+                .as_ref()
+                .is_none_or(|dir| matches!(self.nodes.get(dir), Some(Node::Directory)));
+
+            let problem = if !in_directory {
+                FsckProblem::NotInDirectory(path.clone())
+            } else if !missing.is_empty() {
+                FsckProblem::Orphan(path.clone())
+            } else {
+                continue;
+            };
+
+            if fix {
+                if in_directory {
+                    for dir in missing.into_iter().rev() {
+                        self.nodes.insert(dir.clone(), Node::Directory);
+                        self.meta
+                            .insert(dir.clone(), NodeMeta::dir_default_with_time(self.clock));
+                        self.changes.record(&dir);
+                    }
+                } else if let Some(node) = self.nodes.remove(&path) {
+                    let meta = self.meta.remove(&path);
+                    self.changes.record(&path);
+                    let name = self.lost_name(&path);
+                    self.insert_lost(name, node, meta);
+                }
+            }
+            report.findings.push(Finding {
+                problem,
+                fixed: fix,
+            });
+        }
+    }
+
+    fn check_meta(&mut self, fix: bool, report: &mut FsckReport) {
+        let mut missing: Vec<String> = self
+            .nodes
+            .keys()
+            .filter(|p| !self.meta.contains_key(*p))
+            .cloned()
+            .collect();
+        missing.sort();
+        let mut stray: Vec<String> = self
+            .meta
+            .keys()
+            .filter(|p| !self.nodes.contains_key(*p))
+            .cloned()
+            .collect();
+        stray.sort();
+
+        for path in missing {
+            if fix {
+                let meta = match self.nodes.get(&path) {
+                    Some(Node::Directory) => NodeMeta::dir_default_with_time(self.clock),
+                    Some(Node::Symlink(_)) => NodeMeta::symlink_default(self.clock),
+                    _ => NodeMeta::file_default(self.clock),
+                };
+                self.meta.insert(path.clone(), meta);
+            }
+            report.findings.push(Finding {
+                problem: FsckProblem::MissingMeta(path),
+                fixed: fix,
+            });
+        }
+        for path in stray {
+            if fix {
+                self.meta.remove(&path);
+            }
+            report.findings.push(Finding {
+                problem: FsckProblem::StrayMeta(path),
+                fixed: fix,
+            });
+        }
+    }
+
+    fn check_symlinks(&mut self, fix: bool, report: &mut FsckReport) {
+        let mut links: Vec<(String, String)> = self
+            .nodes
+            .iter()
+            .filter_map(|(path, node)| match node {
+                Node::Symlink(target) => Some((path.clone(), target.clone())),
+                _ => None,
+            })
+            .collect();
+        links.sort();
+
+        for (path, target) in links {
+            let problem = match self.resolve_symlinks(&path) {
+                Ok(_) => continue,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    FsckProblem::DanglingSymlink {
+                        path: path.clone(),
+                        target,
+                    }
+                }
+                Err(_) => FsckProblem::SymlinkLoop(path.clone()),
+            };
+            if fix && let Some(node) = self.nodes.remove(&path) {
+                let owner = self.owner(&path);
+                self.charge(owner, super::node_size(&node), 0);
+                self.meta.remove(&path);
+                self.changes.record(&path);
+            }
+            report.findings.push(Finding {
+                problem,
+                fixed: fix,
+            });
+        }
+    }
+
+    fn check_usage(&mut self, fix: bool, report: &mut FsckReport) {
+        let actual = usage_by_owner(&self.nodes, &self.meta);
+        let uids: BTreeSet<u32> = actual.keys().chain(self.usage.keys()).copied().collect();
+        let mut wrong = false;
+        for uid in uids {
+            let recorded = self.usage.get(&uid).copied().unwrap_or(0);
+            let owns = actual.get(&uid).copied().unwrap_or(0);
+            if recorded != owns {
+                wrong = true;
+                report.findings.push(Finding {
+                    problem: FsckProblem::WrongUsage {
+                        uid,
+                        recorded,
+                        actual: owns,
+                    },
+                    fixed: fix,
+                });
+            }
+        }
+        if wrong && fix {
+            self.usage = actual;
+        }
+    }
+
+    /// A free name in lost+found for what was at `path`, creating the
+    /// directory if need be
+    fn lost_name(&mut self, path: &str) -> String {
+        if !matches!(self.nodes.get(LOST_FOUND), Some(Node::Directory)) {
+            if let Some(node) = self.nodes.remove(LOST_FOUND) {
+                // Something else by that name: it is lost too
+                let meta = self.meta.remove(LOST_FOUND);
+                self.nodes.insert(LOST_FOUND.to_string(), Node::Directory);
+                let name = self.lost_name(LOST_FOUND);
+                self.insert_lost(name, node, meta);
+            }
+            self.nodes.insert(LOST_FOUND.to_string(), Node::Directory);
+            self.meta.insert(
+                LOST_FOUND.to_string(),
+                NodeMeta::with_time(0, 0, 0o700, self.clock),
+            );
+            self.changes.record(LOST_FOUND);
+        }
+
+        // The old path, flattened into one name that fits
+        let mut base: String = path
+            .trim_start_matches('/')
+            .chars()
+            .map(|c| if c == '/' || c == '\0' { '#' } else { c })
+            .collect();
+        if base.is_empty() {
+            base.push('#');
+        }
+        while base.len() > Self::MAX_NAME_LEN - 8 {
+            base.pop();
+        }
+
+        let mut name = format!("{}/{}", LOST_FOUND, base);
+        let mut n = 1;
+        while self.nodes.contains_key(&name) {
+            name = format!("{}/{}.{}", LOST_FOUND, base, n);
+            n += 1;
+        }
+        name
+    }
+
+    fn insert_lost(&mut self, name: String, node: Node, meta: Option<NodeMeta>) {
+        if let Some(meta) = meta {
+            self.meta.insert(name.clone(), meta);
+        }
+        self.nodes.insert(name.clone(), node);
+        self.changes.record(&name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::{FileSystem, OpenOptions};
+
+    fn healthy() -> MemoryFs {
+        let mut fs = MemoryFs::new();
+        fs.create_dir("/home").unwrap();
+        let handle = fs
+            .open(
+                "/home/notes.txt",
+                OpenOptions::new().write(true).create(true),
+            )
+            .unwrap();
+        fs.write(handle, b"hello").unwrap();
+        fs.close(handle).unwrap();
+        fs.symlink("/home/notes.txt", "/home/link").unwrap();
+        fs
+    }
+
+    fn problems(report: &FsckReport) -> Vec<FsckProblem> {
+        report.findings.iter().map(|f| f.problem.clone()).collect()
+    }
+
+    #[test]
+    fn test_healthy_fs_is_clean() {
+        let mut fs = healthy();
+        let report = fs.fsck(Repair::All);
+        assert!(report.findings.is_empty());
+        assert!(report.is_consistent());
+        assert_eq!(report.paths, 4);
+    }
+
+    #[test]
+    fn test_orphans_are_reconnected() {
+        let mut fs = healthy();
+        fs.nodes.insert(
+            "/var/log/boot.log".into(),
+            Node::File(b"ok".to_vec().into()),
+        );
+        fs.meta
+            .insert("/var/log/boot.log".into(), NodeMeta::file_default(0.0));
+
+        let report = fs.fsck(Repair::Nothing);
+        assert_eq!(
+            problems(&report),
+            [
+                FsckProblem::Orphan("/var/log/boot.log".into()),
+                FsckProblem::WrongUsage {
+                    uid: 1000,
+                    recorded: 20,
+                    actual: 22
+                }
+            ]
+        );
+        assert!(!report.is_consistent());
+
+        let report = fs.fsck(Repair::Errors);
+        assert!(report.is_consistent());
+        assert!(fs.metadata("/var/log").unwrap().is_dir);
+        assert_eq!(fs.read_dir("/var/log").unwrap()[0].name, "boot.log");
+        assert!(fs.fsck(Repair::Nothing).findings.is_empty());
+    }
+
+    #[test]
+    fn test_misplaced_paths_go_to_lost_found() {
+        let mut fs = healthy();
+        // A child of a file, and a path that isn't normalized
+        fs.nodes
+            .insert("/home/notes.txt/x".into(), Node::File(Vec::new().into()));
+        fs.meta
+            .insert("/home/notes.txt/x".into(), NodeMeta::file_default(0.0));
+        fs.nodes.insert("/home//b".into(), Node::Directory);
+        fs.nodes
+            .insert("/".into(), Node::File(b"?".to_vec().into()));
+
+        let report = fs.fsck(Repair::Errors);
+        assert_eq!(
+            problems(&report),
+            [
+                FsckProblem::BadRoot,
+                FsckProblem::BadPath("/home//b".into()),
+                FsckProblem::NotInDirectory("/home/notes.txt/x".into()),
+                FsckProblem::MissingMeta("/home/b".into()),
+                FsckProblem::WrongUsage {
+                    uid: 0,
+                    recorded: 0,
+                    actual: 1
+                }
+            ]
+        );
+        assert!(report.is_consistent());
+        assert!(fs.metadata("/home/b").unwrap().is_dir);
+        assert!(fs.exists("/lost+found/root"));
+        assert!(fs.exists("/lost+found/home#notes.txt#x"));
+        assert_eq!(fs.metadata("/lost+found").unwrap().mode, 0o700);
+        assert!(fs.fsck(Repair::Nothing).findings.is_empty());
+    }
+
+    #[test]
+    fn test_bad_symlinks_are_warnings() {
+        let mut fs = healthy();
+        fs.symlink("/nowhere", "/home/stale").unwrap();
+        fs.symlink("/home/b", "/home/a").unwrap();
+        fs.symlink("/home/a", "/home/b").unwrap();
+        fs.meta.insert("/gone".into(), NodeMeta::default());
+
+        let report = fs.fsck(Repair::Errors);
+        assert_eq!(
+            problems(&report),
+            [
+                FsckProblem::StrayMeta("/gone".into()),
+                FsckProblem::SymlinkLoop("/home/a".into()),
+                FsckProblem::SymlinkLoop("/home/b".into()),
+                FsckProblem::DanglingSymlink {
+                    path: "/home/stale".into(),
+                    target: "/nowhere".into()
+                },
+            ]
+        );
+        assert_eq!((report.errors(), report.warnings()), (1, 3));
+        assert!(report.is_consistent());
+        assert!(fs.is_symlink("/home/stale"));
+
+        let report = fs.fsck(Repair::All);
+        assert_eq!(report.fixed(), 3);
+        assert!(!fs.is_symlink("/home/stale"));
+        assert!(fs.is_symlink("/home/link"));
+        assert!(fs.fsck(Repair::Nothing).findings.is_empty());
+    }
+}
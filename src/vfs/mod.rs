@@ -5,13 +5,33 @@
 //!
 //! Design: trait-based abstraction, keeping it simple.
 
+pub mod acl;
+pub mod changelog;
+pub mod compress;
+pub mod deflate;
+pub mod encrypted;
+pub mod export;
+pub mod integrity;
 pub mod layered;
 pub mod memory;
+pub mod overlay;
 pub mod persist;
-
+pub mod snapshots;
+pub mod sparse;
+pub mod tar;
+pub mod wal;
+pub mod webdav;
+pub mod zip;
+
+pub use acl::{Acl, AclEntry, AclTag};
+pub use changelog::ChangeCursor;
+pub use encrypted::EncryptedFs;
 pub use layered::LayeredFs;
-pub use memory::{FsSnapshot, MemoryFs};
+pub use memory::{FsChange, FsChangeKind, FsSnapshot, FsckReport, MemoryFs};
+pub use overlay::OverlayFs;
 pub use persist::Persistence;
+pub use tar::TarFs;
+pub use webdav::WebDavFs;
 
 use std::io;
 
@@ -67,7 +87,10 @@ impl OpenOptions {
 /// File metadata
 #[derive(Debug, Clone)]
 pub struct Metadata {
+    /// Length in bytes (the apparent size)
     pub size: u64,
+    /// Bytes actually stored; less than `size` for a file with holes
+    pub allocated: u64,
     pub is_dir: bool,
     pub is_file: bool,
     pub is_symlink: bool,
@@ -86,12 +109,15 @@ pub struct Metadata {
     pub ctime: f64,
     /// Number of hard links to this inode
     pub nlink: u32,
+    /// Access control list, if the file has one (see [`acl`])
+    pub acl: Option<Acl>,
 }
 
 impl Default for Metadata {
     fn default() -> Self {
         Self {
             size: 0,
+            allocated: 0,
             is_dir: false,
             is_file: true,
             is_symlink: false,
@@ -103,6 +129,7 @@ impl Default for Metadata {
             mtime: 0.0,
             ctime: 0.0,
             nlink: 1, // New files have 1 link
+            acl: None,
         }
     }
 }
@@ -126,6 +153,23 @@ pub trait FileSystem {
     /// Read from a file
     fn read(&mut self, handle: FileHandle, buf: &mut [u8]) -> io::Result<usize>;
 
+    /// Read up to `len` bytes from a file, handing them to `sink` in order
+    ///
+    /// Backends that keep file contents in memory hand over slices of
+    /// them, so the caller copies each byte once, straight to where it is
+    /// going; the default reads into a buffer first.
+    fn read_with(
+        &mut self,
+        handle: FileHandle,
+        len: usize,
+        sink: &mut dyn FnMut(&[u8]),
+    ) -> io::Result<usize> {
+        let mut buf = vec![0u8; len];
+        let n = self.read(handle, &mut buf)?;
+        sink(&buf[..n]);
+        Ok(n)
+    }
+
     /// Write to a file
     fn write(&mut self, handle: FileHandle, buf: &[u8]) -> io::Result<usize>;
 
@@ -194,10 +238,66 @@ pub trait FileSystem {
     ///
     /// If atime or mtime is None, the current clock time is used.
     fn utimes(&mut self, path: &str, atime: Option<f64>, mtime: Option<f64>) -> io::Result<()>;
+
+    /// Set extended attribute `name` of `path` to `value`
+    ///
+    /// Extended attributes are small named values kept with a file (a mime
+    /// type, where it was downloaded from). Names are at most
+    /// [`XATTR_NAME_MAX`] bytes and values at most [`XATTR_SIZE_MAX`].
+    fn setxattr(&mut self, path: &str, name: &str, value: &[u8]) -> io::Result<()>;
+
+    /// Get extended attribute `name` of `path`
+    ///
+    /// Fails with [`io::ErrorKind::NotFound`] if `path` has no such attribute.
+    fn getxattr(&self, path: &str, name: &str) -> io::Result<Vec<u8>>;
+
+    /// Names of the extended attributes of `path`, sorted
+    fn listxattr(&self, path: &str) -> io::Result<Vec<String>>;
+
+    /// Remove extended attribute `name` of `path`
+    fn removexattr(&mut self, path: &str, name: &str) -> io::Result<()>;
+
+    /// Look up paths under `dir` regardless of case, or stop doing so;
+    /// filesystems that only match names exactly ignore this
+    fn set_casefold(&mut self, _dir: &str, _on: bool) {}
+
+    /// The whole filesystem as bytes it can be loaded back from, for one
+    /// kept in a file (see [`encrypted`]); `None` if it has no such form
+    fn image(&self) -> Option<io::Result<Vec<u8>>> {
+        None
+    }
+}
+
+/// Longest extended attribute name, in bytes
+pub const XATTR_NAME_MAX: usize = 255;
+
+/// Largest extended attribute value, in bytes
+pub const XATTR_SIZE_MAX: usize = 64 * 1024;
+
+/// Check an extended attribute name and value against the limits
+pub(crate) fn check_xattr(name: &str, value: &[u8]) -> io::Result<()> {
+    if name.is_empty() || name.len() > XATTR_NAME_MAX {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Invalid attribute name",
+        ));
+    }
+    if value.len() > XATTR_SIZE_MAX {
+        return Err(io::Error::new(
+            io::ErrorKind::FileTooLarge,
+            "Attribute value too large",
+        ));
+    }
+    Ok(())
+}
+
+/// The error for an extended attribute a file doesn't have
+pub(crate) fn no_such_attribute() -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, "No such attribute")
 }
 
 /// Convenience wrapper for reading entire file to string
-pub fn read_to_string<F: FileSystem>(fs: &mut F, path: &str) -> io::Result<String> {
+pub fn read_to_string<F: FileSystem + ?Sized>(fs: &mut F, path: &str) -> io::Result<String> {
     let handle = fs.open(path, OpenOptions::new().read(true))?;
     let meta = fs.metadata(path)?;
     let mut buf = vec![0u8; meta.size as usize];
@@ -207,7 +307,11 @@ pub fn read_to_string<F: FileSystem>(fs: &mut F, path: &str) -> io::Result<Strin
 }
 
 /// Convenience wrapper for writing string to file
-pub fn write_string<F: FileSystem>(fs: &mut F, path: &str, content: &str) -> io::Result<()> {
+pub fn write_string<F: FileSystem + ?Sized>(
+    fs: &mut F,
+    path: &str,
+    content: &str,
+) -> io::Result<()> {
     let handle = fs.open(
         path,
         OpenOptions::new().write(true).create(true).truncate(true),
@@ -216,3 +320,160 @@ pub fn write_string<F: FileSystem>(fs: &mut F, path: &str, content: &str) -> io:
     fs.close(handle)?;
     Ok(())
 }
+
+/// Symbolic links [`atomic_write`] follows before giving up
+pub(crate) const MAX_LINK_DEPTH: usize = 40;
+
+/// Replace the contents of `path` with `data` in one step
+///
+/// The data goes to a temporary file beside `path`, which is closed and
+/// then renamed over it, so a reader, a snapshot or a reload never sees a
+/// half-written file: `path` holds either the old contents or the new.
+/// An existing file keeps its mode, owner and extended attributes, and a
+/// symbolic link is followed so the file it points at is replaced, not the
+/// link. On failure the temporary file is removed and `path` is left alone.
+pub fn atomic_write<F: FileSystem + ?Sized>(fs: &mut F, path: &str, data: &[u8]) -> io::Result<()> {
+    let mut path = path.to_string();
+    let mut previous = None;
+    for _ in 0..MAX_LINK_DEPTH {
+        match fs.metadata(&path) {
+            Ok(meta) if meta.is_dir => {
+                return Err(io::Error::new(
+                    io::ErrorKind::IsADirectory,
+                    "Is a directory",
+                ));
+            }
+            Ok(meta) if meta.is_symlink => {
+                let target = fs.read_link(&path)?;
+                path = link_target(&path, &target);
+            }
+            Ok(meta) => {
+                previous = Some(meta);
+                break;
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => break,
+            Err(e) => return Err(e),
+        }
+    }
+
+    let (dir, name) = path.rsplit_once('/').unwrap_or(("", &path));
+    let temp = format!("{}/.{}.tmp", dir, name);
+    let result = replace_with(fs, &path, &temp, data, previous.as_ref());
+    if result.is_err() {
+        let _ = fs.remove_file(&temp);
+    }
+    result
+}
+
+fn replace_with<F: FileSystem + ?Sized>(
+    fs: &mut F,
+    path: &str,
+    temp: &str,
+    data: &[u8],
+    previous: Option<&Metadata>,
+) -> io::Result<()> {
+    let handle = fs.open(
+        temp,
+        OpenOptions::new().write(true).create(true).truncate(true),
+    )?;
+    // Owned from the start by whoever the contents will count against
+    let written = match previous {
+        Some(meta) => fs
+            .chmod(temp, meta.mode)
+            .and_then(|()| fs.chown(temp, Some(meta.uid), Some(meta.gid)))
+            .and_then(|()| copy_xattrs(fs, path, temp))
+            .and_then(|()| fs.write(handle, data)),
+        None => fs.write(handle, data),
+    };
+    // Closing is what makes the contents durable
+    fs.close(handle)?;
+    if written? != data.len() {
+        return Err(io::Error::new(io::ErrorKind::WriteZero, "Short write"));
+    }
+    if previous.is_some() {
+        fs.remove_file(path)?;
+    }
+    fs.rename(temp, path)
+}
+
+/// Give `to` the extended attributes of `from`
+fn copy_xattrs<F: FileSystem + ?Sized>(fs: &mut F, from: &str, to: &str) -> io::Result<()> {
+    for name in fs.listxattr(from)? {
+        let value = fs.getxattr(from, &name)?;
+        fs.setxattr(to, &name, &value)?;
+    }
+    Ok(())
+}
+
+/// Where a link at `link` pointing to `target` leads
+pub(crate) fn link_target(link: &str, target: &str) -> String {
+    let joined = if target.starts_with('/') {
+        target.to_string()
+    } else {
+        let dir = link.rsplit_once('/').map_or("", |(dir, _)| dir);
+        format!("{}/{}", dir, target)
+    };
+    let mut parts: Vec<&str> = Vec::new();
+    for part in joined.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            name => parts.push(name),
+        }
+    }
+    format!("/{}", parts.join("/"))
+}
+
+/// Copy the tree under `dir` into a new `MemoryFs`, with `dir` as its root
+///
+/// Modes, owners and times come along; hard links are copied as separate
+/// files.
+pub fn copy_tree<F: FileSystem + ?Sized>(fs: &mut F, dir: &str) -> io::Result<MemoryFs> {
+    let meta = fs.metadata(dir)?;
+    if !meta.is_dir {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Not a directory",
+        ));
+    }
+    let mut copy = MemoryFs::new();
+    copy_tree_into(fs, dir, &mut copy, "/", &meta)?;
+    Ok(copy)
+}
+
+fn copy_tree_into<F: FileSystem + ?Sized>(
+    fs: &mut F,
+    from: &str,
+    copy: &mut MemoryFs,
+    to: &str,
+    meta: &Metadata,
+) -> io::Result<()> {
+    if meta.is_dir {
+        if to != "/" {
+            copy.create_dir(to)?;
+        }
+        for entry in fs.read_dir(from)? {
+            let from = format!("{}/{}", from.trim_end_matches('/'), entry.name);
+            let to = format!("{}/{}", to.trim_end_matches('/'), entry.name);
+            let meta = fs.metadata(&from)?;
+            copy_tree_into(fs, &from, copy, &to, &meta)?;
+        }
+    } else if meta.is_symlink {
+        copy.symlink(&fs.read_link(from)?, to)?;
+        return Ok(());
+    } else {
+        let handle = fs.open(from, OpenOptions::new().read(true))?;
+        let mut data = vec![0u8; meta.size as usize];
+        let read = fs.read(handle, &mut data);
+        fs.close(handle)?;
+        read?;
+        let handle = copy.open(to, OpenOptions::new().write(true).create(true))?;
+        copy.write(handle, &data)?;
+        copy.close(handle)?;
+    }
+    copy.chmod(to, meta.mode)?;
+    copy.chown(to, Some(meta.uid), Some(meta.gid))?;
+    copy.utimes(to, Some(meta.atime), Some(meta.mtime))
+}
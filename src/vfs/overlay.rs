@@ -0,0 +1,802 @@
+//! Overlay (union) filesystem
+//!
+//! Stacks a writable upper [`MemoryFs`] over any number of read-only lower
+//! filesystems, like Linux's overlayfs. A path is looked up in the upper
+//! layer and then in each lower layer in turn, and a directory found in
+//! several layers shows the entries of all of them. Writes only ever touch
+//! the upper layer: a file held by a lower layer is copied up before it is
+//! changed, and removing something a lower layer holds leaves a whiteout
+//! (`.wh.NAME`) in the upper layer to hide it. A directory made where one
+//! was removed is marked opaque, so the old contents don't show through.
+//! The markers are the ones [`LayeredFs`](super::LayeredFs) uses.
+//!
+//! Lower layers may carry whiteouts and opaque markers of their own (an
+//! old upper layer reused as a lower one), which hide what's below them in
+//! the same way.
+
+use super::layered::{OPAQUE_MARKER, WHITEOUT_PREFIX};
+use super::sparse::write_sparse;
+use super::{DirEntry, FileHandle, FileSystem, MemoryFs, Metadata, OpenOptions};
+use std::collections::HashSet;
+use std::io::{self, SeekFrom};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Layer {
+    Upper,
+    /// Index into `lowers`
+    Lower(usize),
+}
+
+/// Writable upper layer over read-only lower layers
+pub struct OverlayFs {
+    upper: MemoryFs,
+    /// Lower layers, topmost first
+    lowers: Vec<Box<dyn FileSystem>>,
+    /// Our handles, mapped to the layer and handle they were opened with
+    handles: slab::Slab<(Layer, FileHandle)>,
+}
+
+fn not_found() -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, "Path not found")
+}
+
+fn not_a_directory() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "Not a directory")
+}
+
+fn already_exists() -> io::Error {
+    io::Error::new(io::ErrorKind::AlreadyExists, "Path already exists")
+}
+
+/// Normalize a path (leading slash, `.` and `..` resolved)
+fn normalize(path: &str) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            name => parts.push(name),
+        }
+    }
+    format!("/{}", parts.join("/"))
+}
+
+/// `name` in the directory `dir`
+fn child(dir: &str, name: &str) -> String {
+    if dir == "/" {
+        format!("/{}", name)
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+/// Parent directory and name of a normalized path; `None` for the root
+fn split(path: &str) -> Option<(&str, &str)> {
+    let idx = path.rfind('/')?;
+    let name = &path[idx + 1..];
+    if name.is_empty() {
+        return None;
+    }
+    Some((if idx == 0 { "/" } else { &path[..idx] }, name))
+}
+
+/// The whiteout that hides a normalized path
+fn whiteout(path: &str) -> Option<String> {
+    let (dir, name) = split(path)?;
+    Some(child(dir, &format!("{}{}", WHITEOUT_PREFIX, name)))
+}
+
+impl OverlayFs {
+    /// Create an overlay of `upper` over `lowers`, topmost first
+    ///
+    /// The root directory takes its mode, owner and times from the topmost
+    /// lower layer, as if it had been copied up.
+    pub fn new(upper: MemoryFs, lowers: Vec<Box<dyn FileSystem>>) -> Self {
+        let mut fs = Self {
+            upper,
+            lowers,
+            handles: slab::Slab::new(),
+        };
+        if let Some(meta) = fs.lowers.first().and_then(|lower| lower.metadata("/").ok()) {
+            // The upper root always exists, so this can't fail
+            let _ = fs.copy_attrs(Layer::Lower(0), "/", &meta);
+        }
+        fs
+    }
+
+    /// The upper layer, holding every change made through the overlay
+    pub fn upper(&self) -> &MemoryFs {
+        &self.upper
+    }
+
+    fn layer(&self, layer: Layer) -> &dyn FileSystem {
+        match layer {
+            Layer::Upper => &self.upper,
+            Layer::Lower(i) => self.lowers[i].as_ref(),
+        }
+    }
+
+    fn layer_mut(&mut self, layer: Layer) -> &mut dyn FileSystem {
+        match layer {
+            Layer::Upper => &mut self.upper,
+            Layer::Lower(i) => self.lowers[i].as_mut(),
+        }
+    }
+
+    /// The layers holding `path`, topmost first
+    ///
+    /// The path is read from the first of them; the rest are the layers a
+    /// merged directory also lists. Empty if the path doesn't exist.
+    fn layers_of(&self, path: &str) -> Vec<Layer> {
+        let mut layers: Vec<Layer> = std::iter::once(Layer::Upper)
+            .chain((0..self.lowers.len()).map(Layer::Lower))
+            .collect();
+        let mut dir = "/".to_string();
+
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            // The markers themselves are never visible
+            if name.starts_with(WHITEOUT_PREFIX) {
+                return Vec::new();
+            }
+            let path = child(&dir, name);
+            let hidden = child(&dir, &format!("{}{}", WHITEOUT_PREFIX, name));
+            let mut found = Vec::new();
+            for layer in layers {
+                let fs = self.layer(layer);
+                if fs.exists(&hidden) {
+                    break;
+                }
+                let Ok(meta) = fs.metadata(&path) else {
+                    continue;
+                };
+                // Only directories merge, and only with directories
+                if !meta.is_dir {
+                    if found.is_empty() {
+                        found.push(layer);
+                    }
+                    break;
+                }
+                found.push(layer);
+                if fs.exists(&child(&path, OPAQUE_MARKER)) {
+                    break;
+                }
+            }
+            if found.is_empty() {
+                return found;
+            }
+            layers = found;
+            dir = path;
+        }
+        layers
+    }
+
+    /// The layer `path` is read from
+    fn top(&self, path: &str) -> io::Result<Layer> {
+        self.layers_of(path).first().copied().ok_or_else(not_found)
+    }
+
+    fn read_all(&mut self, layer: Layer, path: &str) -> io::Result<Vec<u8>> {
+        let fs = self.layer_mut(layer);
+        let handle = fs.open(path, OpenOptions::new().read(true))?;
+        let mut data = vec![0u8; fs.fstat(handle)?.size as usize];
+        let read = fs.read(handle, &mut data);
+        fs.close(handle)?;
+        read?;
+        Ok(data)
+    }
+
+    /// Give the upper copy of `path` the mode, owner, times and extended
+    /// attributes it has in `from`
+    fn copy_attrs(&mut self, from: Layer, path: &str, meta: &Metadata) -> io::Result<()> {
+        self.upper.chmod(path, meta.mode)?;
+        self.upper.chown(path, Some(meta.uid), Some(meta.gid))?;
+        self.upper
+            .utimes(path, Some(meta.atime), Some(meta.mtime))?;
+        let source = self.layer(from);
+        let xattrs = source
+            .listxattr(path)?
+            .into_iter()
+            .map(|name| source.getxattr(path, &name).map(|value| (name, value)))
+            .collect::<io::Result<Vec<_>>>()?;
+        for (name, value) in xattrs {
+            self.upper.setxattr(path, &name, &value)?;
+        }
+        Ok(())
+    }
+
+    /// Make sure the visible directory `dir` exists in the upper layer
+    fn copy_up_dir(&mut self, dir: &str) -> io::Result<()> {
+        if self.upper.exists(dir) {
+            return Ok(());
+        }
+        let layer = self.top(dir)?;
+        let meta = self.layer(layer).metadata(dir)?;
+        if !meta.is_dir {
+            return Err(not_a_directory());
+        }
+        if let Some((parent, _)) = split(dir) {
+            self.copy_up_dir(parent)?;
+        }
+        self.upper.create_dir(dir)?;
+        self.copy_attrs(layer, dir, &meta)
+    }
+
+    /// Copy `path` into the upper layer if only a lower layer holds it
+    ///
+    /// A directory is copied up empty; what's in it stays where it is.
+    fn copy_up(&mut self, path: &str) -> io::Result<()> {
+        let layer = self.top(path)?;
+        if layer == Layer::Upper {
+            return Ok(());
+        }
+        let meta = self.layer(layer).metadata(path)?;
+        if meta.is_dir {
+            return self.copy_up_dir(path);
+        }
+        if let Some((parent, _)) = split(path) {
+            self.copy_up_dir(parent)?;
+        }
+        if meta.is_symlink {
+            let target = self.layer(layer).read_link(path)?;
+            return self.upper.symlink(&target, path);
+        }
+        let data = self.read_all(layer, path)?;
+        let handle = self
+            .upper
+            .open(path, OpenOptions::new().write(true).create(true))?;
+        // Holes in the lower file stay holes
+        let written = write_sparse(&mut self.upper, handle, &data);
+        self.upper.close(handle)?;
+        written?;
+        self.copy_attrs(layer, path, &meta)
+    }
+
+    /// Copy `path` and everything under it into the upper layer
+    fn copy_up_tree(&mut self, path: &str) -> io::Result<()> {
+        self.copy_up(path)?;
+        if self.upper.metadata(path)?.is_dir {
+            for entry in self.read_dir(path)? {
+                self.copy_up_tree(&child(path, &entry.name))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn add_marker(&mut self, marker: &str) -> io::Result<()> {
+        let handle = self
+            .upper
+            .open(marker, OpenOptions::new().write(true).create(true))?;
+        self.upper.close(handle)
+    }
+
+    /// Hide `path` in the lower layers
+    fn add_whiteout(&mut self, path: &str) -> io::Result<()> {
+        let (Some((parent, _)), Some(marker)) = (split(path), whiteout(path)) else {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "Cannot hide root directory",
+            ));
+        };
+        self.copy_up_dir(parent)?;
+        self.add_marker(&marker)
+    }
+
+    /// Get ready to create `path` in the upper layer
+    ///
+    /// Its parent has to be a visible directory, which is copied up. Any
+    /// whiteout for `path` is removed; returns true if there was one.
+    fn prepare_create(&mut self, path: &str) -> io::Result<bool> {
+        let Some((parent, _)) = split(path) else {
+            return Err(already_exists());
+        };
+        if !self.metadata(parent)?.is_dir {
+            return Err(not_a_directory());
+        }
+        self.copy_up_dir(parent)?;
+        match whiteout(path) {
+            Some(marker) if self.upper.exists(&marker) => {
+                self.upper.remove_file(&marker)?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn handle(&self, handle: FileHandle) -> io::Result<(Layer, FileHandle)> {
+        self.handles
+            .get(handle)
+            .copied()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid file handle"))
+    }
+}
+
+impl FileSystem for OverlayFs {
+    fn open(&mut self, path: &str, options: OpenOptions) -> io::Result<FileHandle> {
+        let path = normalize(path);
+        let layer = match self.layers_of(&path).first() {
+            Some(_) if options.write || options.truncate => {
+                self.copy_up(&path)?;
+                Layer::Upper
+            }
+            Some(&layer) => layer,
+            None if options.create => {
+                self.prepare_create(&path)?;
+                Layer::Upper
+            }
+            None => return Err(not_found()),
+        };
+        let inner = self.layer_mut(layer).open(&path, options)?;
+        Ok(self.handles.insert((layer, inner)))
+    }
+
+    fn close(&mut self, handle: FileHandle) -> io::Result<()> {
+        let (layer, inner) = self.handle(handle)?;
+        self.handles.remove(handle);
+        self.layer_mut(layer).close(inner)
+    }
+
+    fn read(&mut self, handle: FileHandle, buf: &mut [u8]) -> io::Result<usize> {
+        let (layer, inner) = self.handle(handle)?;
+        self.layer_mut(layer).read(inner, buf)
+    }
+
+    fn read_with(
+        &mut self,
+        handle: FileHandle,
+        len: usize,
+        sink: &mut dyn FnMut(&[u8]),
+    ) -> io::Result<usize> {
+        let (layer, inner) = self.handle(handle)?;
+        self.layer_mut(layer).read_with(inner, len, sink)
+    }
+
+    fn write(&mut self, handle: FileHandle, buf: &[u8]) -> io::Result<usize> {
+        match self.handle(handle)? {
+            (Layer::Upper, inner) => self.upper.write(inner, buf),
+            (Layer::Lower(_), _) => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "Cannot write to lower layer",
+            )),
+        }
+    }
+
+    fn seek(&mut self, handle: FileHandle, pos: SeekFrom) -> io::Result<u64> {
+        let (layer, inner) = self.handle(handle)?;
+        self.layer_mut(layer).seek(inner, pos)
+    }
+
+    fn metadata(&self, path: &str) -> io::Result<Metadata> {
+        let path = normalize(path);
+        self.layer(self.top(&path)?).metadata(&path)
+    }
+
+    fn create_dir(&mut self, path: &str) -> io::Result<()> {
+        let path = normalize(path);
+        if self.exists(&path) {
+            return Err(already_exists());
+        }
+        let replaces = self.prepare_create(&path)?;
+        self.upper.create_dir(&path)?;
+        if replaces {
+            // Don't let the removed directory's contents show through
+            self.add_marker(&child(&path, OPAQUE_MARKER))?;
+        }
+        Ok(())
+    }
+
+    fn read_dir(&self, path: &str) -> io::Result<Vec<DirEntry>> {
+        let path = normalize(path);
+        let layers = self.layers_of(&path);
+        let top = layers.first().copied().ok_or_else(not_found)?;
+        if !self.layer(top).metadata(&path)?.is_dir {
+            return Err(not_a_directory());
+        }
+
+        let mut entries = Vec::new();
+        let mut seen: HashSet<String> = HashSet::new();
+        for layer in layers {
+            let listing = self.layer(layer).read_dir(&path)?;
+            // Whiteouts hide names in the layers below, not their own
+            let hidden: Vec<String> = listing
+                .iter()
+                .filter_map(|e| e.name.strip_prefix(WHITEOUT_PREFIX))
+                .map(str::to_string)
+                .collect();
+            for entry in listing {
+                if !entry.name.starts_with(WHITEOUT_PREFIX) && seen.insert(entry.name.clone()) {
+                    entries.push(entry);
+                }
+            }
+            seen.extend(hidden);
+        }
+        Ok(entries)
+    }
+
+    fn remove_file(&mut self, path: &str) -> io::Result<()> {
+        let path = normalize(path);
+        let layer = self.top(&path)?;
+        if self.layer(layer).metadata(&path)?.is_dir {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Cannot remove directory with remove_file",
+            ));
+        }
+        if layer == Layer::Upper {
+            self.upper.remove_file(&path)?;
+        }
+        if self.exists(&path) {
+            self.add_whiteout(&path)?;
+        }
+        Ok(())
+    }
+
+    fn remove_dir(&mut self, path: &str) -> io::Result<()> {
+        let path = normalize(path);
+        if path == "/" {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "Cannot remove root directory",
+            ));
+        }
+        if !self.metadata(&path)?.is_dir {
+            return Err(not_a_directory());
+        }
+        if !self.read_dir(&path)?.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Directory not empty",
+            ));
+        }
+        if self.upper.exists(&path) {
+            // Nothing is visible, so only markers are left in it
+            for entry in self.upper.read_dir(&path)? {
+                self.upper.remove_file(&child(&path, &entry.name))?;
+            }
+            self.upper.remove_dir(&path)?;
+        }
+        if self.exists(&path) {
+            self.add_whiteout(&path)?;
+        }
+        Ok(())
+    }
+
+    fn rename(&mut self, from: &str, to: &str) -> io::Result<()> {
+        let from = normalize(from);
+        let to = normalize(to);
+        if from == "/" {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "Cannot rename root directory",
+            ));
+        }
+        let meta = self.metadata(&from)?;
+        if self.exists(&to) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "Destination already exists",
+            ));
+        }
+
+        // A directory takes everything in it along
+        if meta.is_dir {
+            self.copy_up_tree(&from)?;
+        } else {
+            self.copy_up(&from)?;
+        }
+        let replaces = self.prepare_create(&to)?;
+        self.upper.rename(&from, &to)?;
+        if meta.is_dir && replaces {
+            self.add_marker(&child(&to, OPAQUE_MARKER))?;
+        }
+        if self.exists(&from) {
+            self.add_whiteout(&from)?;
+        }
+        Ok(())
+    }
+
+    fn copy_file(&mut self, from: &str, to: &str) -> io::Result<u64> {
+        let from = normalize(from);
+        let to = normalize(to);
+        let layer = self.top(&from)?;
+        let meta = self.layer(layer).metadata(&from)?;
+        if meta.is_dir {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Cannot copy directory with copy_file",
+            ));
+        }
+        if meta.is_symlink {
+            let target = self.layer(layer).read_link(&from)?;
+            self.symlink(&target, &to)?;
+            return Ok(target.len() as u64);
+        }
+
+        let data = self.read_all(layer, &from)?;
+        let handle = self.open(
+            &to,
+            OpenOptions::new().write(true).create(true).truncate(true),
+        )?;
+        let written = self.write(handle, &data);
+        self.close(handle)?;
+        written?;
+        self.chmod(&to, meta.mode)?;
+        Ok(data.len() as u64)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        !self.layers_of(&normalize(path)).is_empty()
+    }
+
+    fn symlink(&mut self, target: &str, link_path: &str) -> io::Result<()> {
+        let link_path = normalize(link_path);
+        if self.exists(&link_path) {
+            return Err(already_exists());
+        }
+        self.prepare_create(&link_path)?;
+        self.upper.symlink(target, &link_path)
+    }
+
+    fn read_link(&self, path: &str) -> io::Result<String> {
+        let path = normalize(path);
+        self.layer(self.top(&path)?).read_link(&path)
+    }
+
+    fn link(&mut self, source: &str, dest: &str) -> io::Result<()> {
+        let source = normalize(source);
+        let dest = normalize(dest);
+        if !self.exists(&source) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "Source not found"));
+        }
+        if self.exists(&dest) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "Destination already exists",
+            ));
+        }
+        self.copy_up(&source)?;
+        self.prepare_create(&dest)?;
+        self.upper.link(&source, &dest)
+    }
+
+    fn chmod(&mut self, path: &str, mode: u16) -> io::Result<()> {
+        let path = normalize(path);
+        self.copy_up(&path)?;
+        self.upper.chmod(&path, mode)
+    }
+
+    fn chown(&mut self, path: &str, uid: Option<u32>, gid: Option<u32>) -> io::Result<()> {
+        let path = normalize(path);
+        self.copy_up(&path)?;
+        self.upper.chown(&path, uid, gid)
+    }
+
+    fn setxattr(&mut self, path: &str, name: &str, value: &[u8]) -> io::Result<()> {
+        let path = normalize(path);
+        self.copy_up(&path)?;
+        self.upper.setxattr(&path, name, value)
+    }
+
+    fn getxattr(&self, path: &str, name: &str) -> io::Result<Vec<u8>> {
+        let path = normalize(path);
+        self.layer(self.top(&path)?).getxattr(&path, name)
+    }
+
+    fn listxattr(&self, path: &str) -> io::Result<Vec<String>> {
+        let path = normalize(path);
+        self.layer(self.top(&path)?).listxattr(&path)
+    }
+
+    fn removexattr(&mut self, path: &str, name: &str) -> io::Result<()> {
+        let path = normalize(path);
+        self.getxattr(&path, name)?;
+        self.copy_up(&path)?;
+        self.upper.removexattr(&path, name)
+    }
+
+    fn fstat(&self, handle: FileHandle) -> io::Result<Metadata> {
+        let (layer, inner) = self.handle(handle)?;
+        self.layer(layer).fstat(inner)
+    }
+
+    fn handle_path(&self, handle: FileHandle) -> io::Result<String> {
+        let (layer, inner) = self.handle(handle)?;
+        self.layer(layer).handle_path(inner)
+    }
+
+    fn set_clock(&mut self, now: f64) {
+        self.upper.set_clock(now);
+        for lower in &mut self.lowers {
+            lower.set_clock(now);
+        }
+    }
+
+    fn utimes(&mut self, path: &str, atime: Option<f64>, mtime: Option<f64>) -> io::Result<()> {
+        let path = normalize(path);
+        self.copy_up(&path)?;
+        self.upper.utimes(&path, atime, mtime)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::{read_to_string, write_string};
+
+    fn fs_with(files: &[(&str, &str)]) -> MemoryFs {
+        let mut fs = MemoryFs::new();
+        for (path, content) in files {
+            if let Some((dir, _)) = split(path) {
+                let mut at = String::new();
+                for part in dir.split('/').filter(|p| !p.is_empty()) {
+                    at = format!("{}/{}", at, part);
+                    if !fs.exists(&at) {
+                        fs.create_dir(&at).unwrap();
+                    }
+                }
+            }
+            write_string(&mut fs, path, content).unwrap();
+        }
+        fs
+    }
+
+    /// Two lowers: `/etc/motd` in both (the top one wins), `/usr/bin/ls`
+    /// only in the bottom one
+    fn overlay() -> OverlayFs {
+        let top = fs_with(&[("/etc/motd", "top"), ("/etc/hosts", "127.0.0.1")]);
+        let bottom = fs_with(&[("/etc/motd", "bottom"), ("/usr/bin/ls", "ls")]);
+        OverlayFs::new(MemoryFs::new(), vec![Box::new(top), Box::new(bottom)])
+    }
+
+    fn names(fs: &OverlayFs, dir: &str) -> Vec<String> {
+        let mut names: Vec<String> = fs
+            .read_dir(dir)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.name)
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_lookup_through_layers() {
+        let mut fs = overlay();
+        assert_eq!(read_to_string(&mut fs, "/etc/motd").unwrap(), "top");
+        assert_eq!(read_to_string(&mut fs, "/usr/bin/ls").unwrap(), "ls");
+        assert_eq!(names(&fs, "/"), ["etc", "usr"]);
+        assert_eq!(names(&fs, "/etc"), ["hosts", "motd"]);
+        assert!(!fs.exists("/nope"));
+        // The root looks like the top lower layer's
+        assert_eq!(
+            fs.metadata("/").unwrap().uid,
+            fs.lowers[0].metadata("/").unwrap().uid
+        );
+        // Reading leaves the upper layer alone
+        assert!(!fs.upper().exists("/etc"));
+    }
+
+    #[test]
+    fn test_copy_up_on_write() {
+        let mut fs = overlay();
+        fs.chmod("/usr/bin/ls", 0o755).unwrap();
+        let handle = fs
+            .open("/usr/bin/ls", OpenOptions::new().write(true).truncate(true))
+            .unwrap();
+        fs.write(handle, b"new ls").unwrap();
+        fs.close(handle).unwrap();
+
+        assert_eq!(read_to_string(&mut fs, "/usr/bin/ls").unwrap(), "new ls");
+        assert_eq!(fs.metadata("/usr/bin/ls").unwrap().mode, 0o755);
+        assert!(fs.upper().exists("/usr/bin/ls"));
+        // The lower layer still has the original
+        assert_eq!(
+            read_to_string(fs.lowers[1].as_mut(), "/usr/bin/ls").unwrap(),
+            "ls"
+        );
+
+        write_string(&mut fs, "/etc/new", "n").unwrap();
+        assert_eq!(names(&fs, "/etc"), ["hosts", "motd", "new"]);
+    }
+
+    #[test]
+    fn test_xattr_copy_up() {
+        let mut fs = overlay();
+        fs.lowers[1]
+            .setxattr("/usr/bin/ls", "user.origin", b"base")
+            .unwrap();
+        assert_eq!(fs.getxattr("/usr/bin/ls", "user.origin").unwrap(), b"base");
+
+        // Copying up keeps the lower layer's attributes
+        fs.setxattr("/usr/bin/ls", "user.mime", b"app").unwrap();
+        assert!(fs.upper().exists("/usr/bin/ls"));
+        assert_eq!(
+            fs.listxattr("/usr/bin/ls").unwrap(),
+            ["user.mime", "user.origin"]
+        );
+        fs.removexattr("/usr/bin/ls", "user.origin").unwrap();
+        assert_eq!(fs.listxattr("/usr/bin/ls").unwrap(), ["user.mime"]);
+        assert_eq!(
+            fs.lowers[1].listxattr("/usr/bin/ls").unwrap(),
+            ["user.origin"]
+        );
+        assert!(fs.removexattr("/etc/motd", "user.none").is_err());
+        assert!(!fs.upper().exists("/etc/motd"));
+    }
+
+    #[test]
+    fn test_whiteouts() {
+        let mut fs = overlay();
+        fs.remove_file("/etc/motd").unwrap();
+        // Hidden in every lower, not just the top one
+        assert!(!fs.exists("/etc/motd"));
+        assert_eq!(names(&fs, "/etc"), ["hosts"]);
+        assert!(fs.upper().exists("/etc/.wh.motd"));
+        assert!(!fs.exists("/etc/.wh.motd"));
+
+        // Making it again starts from nothing
+        write_string(&mut fs, "/etc/motd", "mine").unwrap();
+        assert_eq!(read_to_string(&mut fs, "/etc/motd").unwrap(), "mine");
+        assert!(!fs.upper().exists("/etc/.wh.motd"));
+
+        // A removed file made again in the upper layer gets a whiteout too
+        fs.remove_file("/etc/motd").unwrap();
+        assert!(!fs.exists("/etc/motd"));
+    }
+
+    #[test]
+    fn test_directory_whiteouts() {
+        let mut fs = overlay();
+        // Not empty in the merged view
+        assert!(fs.remove_dir("/usr/bin").is_err());
+        fs.remove_file("/usr/bin/ls").unwrap();
+        fs.remove_dir("/usr/bin").unwrap();
+        assert!(!fs.exists("/usr/bin"));
+        assert!(!fs.exists("/usr/bin/ls"));
+        assert!(names(&fs, "/usr").is_empty());
+
+        // A new directory in its place is opaque
+        fs.create_dir("/usr/bin").unwrap();
+        assert!(names(&fs, "/usr/bin").is_empty());
+        assert!(!fs.exists("/usr/bin/ls"));
+        assert!(fs.create_dir("/usr/bin").is_err());
+    }
+
+    #[test]
+    fn test_rename() {
+        let mut fs = overlay();
+        fs.rename("/usr", "/opt").unwrap();
+        assert!(!fs.exists("/usr"));
+        assert_eq!(read_to_string(&mut fs, "/opt/bin/ls").unwrap(), "ls");
+
+        fs.rename("/etc/hosts", "/hosts").unwrap();
+        assert_eq!(names(&fs, "/etc"), ["motd"]);
+        assert_eq!(read_to_string(&mut fs, "/hosts").unwrap(), "127.0.0.1");
+        assert!(fs.rename("/hosts", "/etc/motd").is_err());
+
+        assert_eq!(fs.copy_file("/etc/motd", "/motd").unwrap(), 3);
+        assert_eq!(read_to_string(&mut fs, "/motd").unwrap(), "top");
+    }
+
+    #[test]
+    fn test_lower_markers() {
+        // An old upper layer reused as a lower one keeps hiding things
+        let mut old = fs_with(&[("/etc/.wh.motd", ""), ("/usr/bin/.wh..wh..opq", "")]);
+        old.create_dir("/usr/bin/extra").unwrap();
+        let bottom = fs_with(&[("/etc/motd", "bottom"), ("/usr/bin/ls", "ls")]);
+        let fs = OverlayFs::new(MemoryFs::new(), vec![Box::new(old), Box::new(bottom)]);
+        assert!(!fs.exists("/etc/motd"));
+        assert_eq!(names(&fs, "/usr/bin"), ["extra"]);
+        assert!(!fs.exists("/usr/bin/ls"));
+    }
+
+    #[test]
+    fn test_lower_handles_are_read_only() {
+        let mut fs = overlay();
+        let handle = fs.open("/etc/hosts", OpenOptions::new()).unwrap();
+        assert!(fs.write(handle, b"x").is_err());
+        fs.close(handle).unwrap();
+        assert!(fs.close(handle).is_err());
+    }
+}
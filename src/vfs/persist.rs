@@ -1,116 +1,291 @@
-//! OPFS Persistence Layer
+//! Snapshot Persistence Layer
 //!
-//! Uses the Origin Private File System (OPFS) API to persist the VFS state.
-//! OPFS provides a fast, sandboxed filesystem accessible from the browser.
+//! Persists the VFS state in the browser, using the Origin Private File
+//! System (OPFS) where there is one and IndexedDB otherwise (see [`idb`]).
+//! Both hold far more than the few megabytes localStorage allows.
 //!
 //! Key design decisions:
 //! - Single JSON file for entire filesystem (simple, atomic)
+//! - Checksummed, with rotated backups to fall back on (see [`integrity`])
+//! - Optionally journaled, so an interrupted save is replayed (see [`wal`])
+//! - Optionally deflated, several times smaller (see [`compress`])
 //! - Async operations via wasm-bindgen-futures
-//! - Graceful fallback if OPFS unavailable
+//! - Graceful fallback if neither backend is available
+
+mod idb;
 
 use super::MemoryFs;
+use super::compress;
+use super::integrity::{self, Recovered, Recovery, Source};
+use super::wal;
+use std::fmt;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen_futures::JsFuture;
 
 /// Persistence manager for VFS
 pub struct Persistence;
 
-/// The filename we use in OPFS
+/// The filename we use in OPFS, and the blob name in IndexedDB
 const FS_FILENAME: &str = "axeberg_fs.json";
 
-impl Persistence {
-    /// Save filesystem to OPFS
-    pub async fn save(fs: &MemoryFs) -> Result<(), String> {
-        let data = fs
-            .to_json()
-            .map_err(|e| format!("Serialize error: {}", e))?;
+/// Where snapshots are kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Opfs,
+    IndexedDb,
+}
 
-        // Get OPFS root
-        let root = Self::get_opfs_root().await?;
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Backend::Opfs => write!(f, "OPFS"),
+            Backend::IndexedDb => write!(f, "IndexedDB"),
+        }
+    }
+}
 
-        // Create/open the file
-        let file_opts = web_sys::FileSystemGetFileOptions::new();
-        file_opts.set_create(true);
+/// Why a stored copy couldn't be read
+#[derive(Debug)]
+enum Unreadable {
+    /// The storage itself failed
+    Failed(String),
+    /// The copy is there but its pieces don't fit together
+    Damaged(String),
+}
 
-        let file_handle: web_sys::FileSystemFileHandle =
-            JsFuture::from(root.get_file_handle_with_options(FS_FILENAME, &file_opts))
-                .await
-                .map_err(|e| format!("Failed to get file handle: {:?}", e))?
-                .dyn_into()
-                .map_err(|_| "Failed to cast to FileSystemFileHandle")?;
+impl fmt::Display for Unreadable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Unreadable::Failed(e) | Unreadable::Damaged(e) => write!(f, "{}", e),
+        }
+    }
+}
 
-        // Create writable stream
-        let writable: web_sys::FileSystemWritableFileStream =
-            JsFuture::from(file_handle.create_writable())
+/// An open backend, read and written by name
+enum Store {
+    Opfs(web_sys::FileSystemDirectoryHandle),
+    IndexedDb(idb::Blobs),
+}
+
+impl Store {
+    /// OPFS if the browser has it, IndexedDB if not
+    async fn open() -> Result<Self, String> {
+        match Persistence::get_opfs_root().await {
+            Ok(root) => Ok(Store::Opfs(root)),
+            Err(opfs) => idb::Blobs::open()
                 .await
-                .map_err(|e| format!("Failed to create writable: {:?}", e))?
-                .dyn_into()
-                .map_err(|_| "Failed to cast to FileSystemWritableFileStream")?;
+                .map(Store::IndexedDb)
+                .map_err(|idb| format!("{}; {}", opfs, idb)),
+        }
+    }
 
-        // Write data
-        let uint8_array = js_sys::Uint8Array::from(data.as_slice());
-        let write_promise = writable
-            .write_with_buffer_source(&uint8_array)
-            .map_err(|e| format!("Failed to get write promise: {:?}", e))?;
-        JsFuture::from(write_promise)
-            .await
-            .map_err(|e| format!("Failed to write: {:?}", e))?;
+    fn backend(&self) -> Backend {
+        match self {
+            Store::Opfs(_) => Backend::Opfs,
+            Store::IndexedDb(_) => Backend::IndexedDb,
+        }
+    }
 
-        // Close the stream
-        JsFuture::from(writable.close())
-            .await
-            .map_err(|e| format!("Failed to close: {:?}", e))?;
+    async fn read(&self, name: &str) -> Result<Option<Vec<u8>>, Unreadable> {
+        match self {
+            Store::Opfs(root) => Persistence::read_file(root, name)
+                .await
+                .map_err(Unreadable::Failed),
+            Store::IndexedDb(blobs) => blobs.read(name).await,
+        }
+    }
 
-        Ok(())
+    async fn write(&self, name: &str, data: &[u8]) -> Result<(), String> {
+        match self {
+            Store::Opfs(root) => Persistence::write_file(root, name, data).await,
+            Store::IndexedDb(blobs) => blobs.write(name, data).await,
+        }
     }
 
-    /// Load filesystem from OPFS
-    pub async fn load() -> Result<Option<MemoryFs>, String> {
-        // Get OPFS root
-        let root = match Self::get_opfs_root().await {
-            Ok(r) => r,
-            Err(_) => return Ok(None), // OPFS not available
-        };
+    /// Remove an entry, ignoring errors (it might not exist)
+    async fn remove(&self, name: &str) {
+        match self {
+            Store::Opfs(root) => {
+                JsFuture::from(root.remove_entry(name)).await.ok();
+            }
+            Store::IndexedDb(blobs) => {
+                blobs.remove(name).await.ok();
+            }
+        }
+    }
+}
 
-        // Try to get the file
-        let file_opts = web_sys::FileSystemGetFileOptions::new();
-        file_opts.set_create(false);
+/// Parse a stored snapshot, compressed or not
+fn parse_snapshot(data: &[u8]) -> std::io::Result<MemoryFs> {
+    MemoryFs::from_json(&compress::unpack(data)?)
+}
 
-        let file_handle: web_sys::FileSystemFileHandle = match JsFuture::from(
-            root.get_file_handle_with_options(FS_FILENAME, &file_opts),
-        )
-        .await
-        {
-            Ok(handle) => handle
-                .dyn_into()
-                .map_err(|_| "Failed to cast to FileSystemFileHandle")?,
-            Err(_) => return Ok(None), // File doesn't exist yet
+impl Persistence {
+    /// Save filesystem to OPFS, or IndexedDB without it, returning the
+    /// backend used
+    ///
+    /// With `journal`, the snapshot goes to the write-ahead journal first
+    /// and the journal is removed once the snapshot is written, so a save
+    /// cut short is finished at the next load instead of leaving a torn
+    /// snapshot. With `compress`, the snapshot is deflated before it is
+    /// sealed.
+    pub async fn save(fs: &MemoryFs, journal: bool, compress: bool) -> Result<Backend, String> {
+        let data = fs
+            .to_json()
+            .map_err(|e| format!("Serialize error: {}", e))?;
+        let sealed = if compress {
+            integrity::seal(&compress::pack(&data))
+        } else {
+            integrity::seal(&data)
         };
 
-        // Get the file
-        let file: web_sys::File = JsFuture::from(file_handle.get_file())
-            .await
-            .map_err(|e| format!("Failed to get file: {:?}", e))?
-            .dyn_into()
-            .map_err(|_| "Failed to cast to File")?;
+        let store = Store::open().await?;
+        let journal_name = wal::journal_name(FS_FILENAME);
+        if journal {
+            store
+                .write(&journal_name, &wal::record(FS_FILENAME, &sealed))
+                .await?;
+        }
+        Self::commit(&store, &sealed).await?;
+        if journal {
+            store.remove(&journal_name).await;
+        }
+        Ok(store.backend())
+    }
 
-        // Read the file contents
-        let array_buffer = JsFuture::from(file.array_buffer())
-            .await
-            .map_err(|e| format!("Failed to read file: {:?}", e))?;
+    /// Write a sealed snapshot in place
+    ///
+    /// The previous snapshot becomes the newest backup first, unless it is
+    /// corrupt (a bad copy must not push out a good backup) or already is
+    /// the newest backup (a replayed save that got that far).
+    async fn commit(store: &Store, sealed: &[u8]) -> Result<(), String> {
+        let current = match store.read(FS_FILENAME).await {
+            Ok(current) => current.filter(|current| integrity::unseal(current).is_ok()),
+            Err(Unreadable::Damaged(_)) => None,
+            Err(Unreadable::Failed(e)) => return Err(e),
+        };
+        let newest_backup = integrity::backup_name(FS_FILENAME, 1);
+        let rotated = match (&current, store.read(&newest_backup).await) {
+            (Some(current), Ok(Some(backup))) => *current == backup,
+            _ => false,
+        };
+        if !rotated {
+            for (from, to) in integrity::rotation(FS_FILENAME) {
+                if from == FS_FILENAME && current.is_none() {
+                    continue;
+                }
+                match store.read(&from).await {
+                    Ok(Some(backup)) => store.write(&to, &backup).await?,
+                    Ok(None) | Err(Unreadable::Damaged(_)) => {}
+                    Err(Unreadable::Failed(e)) => return Err(e),
+                }
+            }
+        }
+
+        store.write(FS_FILENAME, sealed).await
+    }
 
-        let uint8_array = js_sys::Uint8Array::new(&array_buffer);
-        let data = uint8_array.to_vec();
+    /// Load filesystem from OPFS, or IndexedDB without it
+    ///
+    /// A complete journal entry left by an interrupted save is replayed
+    /// first: its snapshot is written in place and loaded. An incomplete
+    /// one is discarded. Otherwise falls back to the newest backup that is
+    /// intact when the snapshot is corrupt; the result says which copy was
+    /// used and what was wrong with the others. Fails only if there are
+    /// copies and none is usable.
+    pub async fn load() -> Result<Option<Recovered<MemoryFs>>, String> {
+        let store = match Store::open().await {
+            Ok(store) => store,
+            Err(_) => return Ok(None), // No storage available
+        };
 
-        // Deserialize
-        let fs = MemoryFs::from_json(&data).map_err(|e| format!("Deserialize error: {}", e))?;
+        let mut recovery = Recovery::new();
+        let journal_name = wal::journal_name(FS_FILENAME);
+        match store.read(&journal_name).await {
+            Ok(Some(entry)) => match wal::replay(&entry) {
+                Ok(entry) if entry.target != FS_FILENAME => {
+                    let problem = format!("journal for {}, discarded", entry.target);
+                    recovery.unreadable(&journal_name, problem);
+                    store.remove(&journal_name).await;
+                }
+                Ok(entry) => {
+                    if let Some(loaded) = recovery.attempt(
+                        Source::Journal,
+                        &journal_name,
+                        entry.sealed,
+                        parse_snapshot,
+                    ) {
+                        // Finish the save; if that fails the journal stays
+                        // and is replayed again next time
+                        if Self::commit(&store, entry.sealed).await.is_ok() {
+                            store.remove(&journal_name).await;
+                        }
+                        return Ok(Some(loaded));
+                    }
+                    store.remove(&journal_name).await;
+                }
+                Err(e) => {
+                    recovery.unreadable(&journal_name, format!("{}, discarded", e));
+                    store.remove(&journal_name).await;
+                }
+            },
+            Ok(None) => {}
+            Err(Unreadable::Damaged(e)) => {
+                recovery.unreadable(&journal_name, format!("{}, discarded", e));
+                store.remove(&journal_name).await;
+            }
+            Err(Unreadable::Failed(e)) => return Err(e),
+        }
+
+        for (source, name) in integrity::candidates(FS_FILENAME) {
+            let sealed = match store.read(&name).await {
+                Ok(Some(sealed)) => sealed,
+                Ok(None) => continue,
+                // Chunks that don't add up are a damaged copy, like a bad checksum
+                Err(Unreadable::Damaged(e)) => {
+                    recovery.unreadable(&name, e);
+                    continue;
+                }
+                Err(Unreadable::Failed(e)) => return Err(e),
+            };
+            if let Some(loaded) = recovery.attempt(source, &name, &sealed, parse_snapshot) {
+                return Ok(Some(loaded));
+            }
+        }
+
+        // Nothing saved yet, or nothing usable
+        recovery
+            .finish()
+            .map(|()| None)
+            .map_err(|problems| format!("no intact snapshot or backup ({})", problems.join("; ")))
+    }
 
-        Ok(Some(fs))
+    /// Describe a load that didn't go cleanly, for boot warnings and dmesg
+    pub fn describe(recovered: &Recovered<MemoryFs>) -> Vec<String> {
+        let mut lines: Vec<String> = recovered
+            .problems
+            .iter()
+            .map(|p| format!("corrupt {}", p))
+            .collect();
+        match recovered.source {
+            Source::Primary => {}
+            Source::Backup(n) => lines.push(format!(
+                "restored from {}",
+                integrity::backup_name(FS_FILENAME, n)
+            )),
+            Source::Journal => lines.push("replayed the journal of an interrupted save".into()),
+        }
+        lines
     }
 
-    /// Check if OPFS is available
+    /// Check if OPFS or IndexedDB is available
     pub async fn is_available() -> bool {
-        Self::get_opfs_root().await.is_ok()
+        Self::backend().await.is_some()
+    }
+
+    /// The backend saves go to, if there is one
+    pub async fn backend() -> Option<Backend> {
+        Store::open().await.ok().map(|store| store.backend())
     }
 
     /// Get the OPFS root directory handle
@@ -131,15 +306,93 @@ impl Persistence {
         Ok(root)
     }
 
-    /// Clear persisted data
-    pub async fn clear() -> Result<(), String> {
-        let root = Self::get_opfs_root().await?;
+    /// Read a file from the OPFS root, `None` if it doesn't exist
+    async fn read_file(
+        root: &web_sys::FileSystemDirectoryHandle,
+        name: &str,
+    ) -> Result<Option<Vec<u8>>, String> {
+        // Try to get the file
+        let file_opts = web_sys::FileSystemGetFileOptions::new();
+        file_opts.set_create(false);
 
-        // Remove the file if it exists
-        JsFuture::from(root.remove_entry(FS_FILENAME)).await.ok(); // Ignore errors (file might not exist)
+        let file_handle: web_sys::FileSystemFileHandle =
+            match JsFuture::from(root.get_file_handle_with_options(name, &file_opts)).await {
+                Ok(handle) => handle
+                    .dyn_into()
+                    .map_err(|_| "Failed to cast to FileSystemFileHandle")?,
+                Err(_) => return Ok(None), // File doesn't exist yet
+            };
+
+        // Get the file
+        let file: web_sys::File = JsFuture::from(file_handle.get_file())
+            .await
+            .map_err(|e| format!("Failed to get file: {:?}", e))?
+            .dyn_into()
+            .map_err(|_| "Failed to cast to File")?;
+
+        // Read the file contents
+        let array_buffer = JsFuture::from(file.array_buffer())
+            .await
+            .map_err(|e| format!("Failed to read file: {:?}", e))?;
+
+        let uint8_array = js_sys::Uint8Array::new(&array_buffer);
+        Ok(Some(uint8_array.to_vec()))
+    }
+
+    /// Replace a file in the OPFS root
+    ///
+    /// A writable stream only replaces the file when it is closed, so a
+    /// failed write leaves the old contents.
+    async fn write_file(
+        root: &web_sys::FileSystemDirectoryHandle,
+        name: &str,
+        data: &[u8],
+    ) -> Result<(), String> {
+        // Create/open the file
+        let file_opts = web_sys::FileSystemGetFileOptions::new();
+        file_opts.set_create(true);
+
+        let file_handle: web_sys::FileSystemFileHandle =
+            JsFuture::from(root.get_file_handle_with_options(name, &file_opts))
+                .await
+                .map_err(|e| format!("Failed to get file handle: {:?}", e))?
+                .dyn_into()
+                .map_err(|_| "Failed to cast to FileSystemFileHandle")?;
+
+        // Create writable stream
+        let writable: web_sys::FileSystemWritableFileStream =
+            JsFuture::from(file_handle.create_writable())
+                .await
+                .map_err(|e| format!("Failed to create writable: {:?}", e))?
+                .dyn_into()
+                .map_err(|_| "Failed to cast to FileSystemWritableFileStream")?;
+
+        // Write data
+        let uint8_array = js_sys::Uint8Array::from(data);
+        let write_promise = writable
+            .write_with_buffer_source(&uint8_array)
+            .map_err(|e| format!("Failed to get write promise: {:?}", e))?;
+        JsFuture::from(write_promise)
+            .await
+            .map_err(|e| format!("Failed to write: {:?}", e))?;
+
+        // Close the stream
+        JsFuture::from(writable.close())
+            .await
+            .map_err(|e| format!("Failed to close: {:?}", e))?;
 
         Ok(())
     }
+
+    /// Clear persisted data, backups and journal included
+    pub async fn clear() -> Result<(), String> {
+        let store = Store::open().await?;
+        for (_, name) in integrity::candidates(FS_FILENAME) {
+            store.remove(&name).await;
+        }
+        store.remove(&wal::journal_name(FS_FILENAME)).await;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -183,4 +436,38 @@ mod tests {
         let restored = MemoryFs::from_json(&json).unwrap();
         assert!(restored.exists("/"));
     }
+
+    #[test]
+    fn test_describe_recovery() {
+        let clean = Recovered {
+            value: MemoryFs::new(),
+            source: Source::Primary,
+            problems: Vec::new(),
+        };
+        assert!(Persistence::describe(&clean).is_empty());
+
+        let fallback = Recovered {
+            value: MemoryFs::new(),
+            source: Source::Backup(2),
+            problems: vec!["axeberg_fs.json: empty".to_string()],
+        };
+        assert_eq!(
+            Persistence::describe(&fallback),
+            [
+                "corrupt axeberg_fs.json: empty",
+                "restored from axeberg_fs.json.2"
+            ]
+        );
+
+        let replayed = Recovered {
+            value: MemoryFs::new(),
+            source: Source::Journal,
+            problems: Vec::new(),
+        };
+        assert!(!replayed.is_clean());
+        assert_eq!(
+            Persistence::describe(&replayed),
+            ["replayed the journal of an interrupted save"]
+        );
+    }
 }
@@ -0,0 +1,273 @@
+//! IndexedDB snapshot storage
+//!
+//! The fallback for browsers without OPFS. Each named blob is split into
+//! chunks of [`CHUNK_SIZE`] stored under `name#000000`, `name#000001`, ...
+//! beside a [`Manifest`] under `name` itself, so no single value gets big
+//! enough to trip the per-value limits some browsers have. A blob's old
+//! chunks, new chunks and manifest are all written in one readwrite
+//! transaction, which IndexedDB commits or aborts as a whole.
+
+use super::Unreadable;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+
+/// Database holding the snapshots
+const DB_NAME: &str = "axeberg";
+
+/// Object store for blobs and their chunks
+const STORE: &str = "blobs";
+
+/// Bytes per chunk
+pub const CHUNK_SIZE: usize = 1 << 20;
+
+/// What a blob is made of, stored as JSON under its name
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Total length in bytes
+    pub len: usize,
+    /// Number of chunks
+    pub chunks: usize,
+}
+
+/// The key of chunk `index` of `name`
+///
+/// Indexes are zero-padded so the keys sort in order.
+pub fn chunk_key(name: &str, index: usize) -> String {
+    format!("{}#{:06}", name, index)
+}
+
+/// Keys from `name#` up to the end of its chunks
+fn chunk_range(name: &str) -> (String, String) {
+    (format!("{}#", name), format!("{}#\u{ffff}", name))
+}
+
+/// Split `data` into chunks, with the manifest that describes them
+///
+/// Empty data still gets a manifest, with no chunks.
+pub fn split(data: &[u8]) -> (Manifest, Vec<&[u8]>) {
+    let chunks: Vec<&[u8]> = data.chunks(CHUNK_SIZE).collect();
+    let manifest = Manifest {
+        len: data.len(),
+        chunks: chunks.len(),
+    };
+    (manifest, chunks)
+}
+
+/// Put chunks back together, checking them against the manifest
+pub fn join(manifest: &Manifest, chunks: Vec<Vec<u8>>) -> Result<Vec<u8>, String> {
+    if chunks.len() != manifest.chunks {
+        return Err(format!(
+            "expected {} chunks, found {}",
+            manifest.chunks,
+            chunks.len()
+        ));
+    }
+    let data = chunks.concat();
+    if data.len() != manifest.len {
+        return Err(format!(
+            "expected {} bytes, found {}",
+            manifest.len,
+            data.len()
+        ));
+    }
+    Ok(data)
+}
+
+/// Wait for a request to succeed or fail
+async fn finished(request: &web_sys::IdbRequest) -> Result<JsValue, String> {
+    let done = js_sys::Promise::new(&mut |resolve, reject| {
+        request.set_onsuccess(Some(&resolve));
+        request.set_onerror(Some(&reject));
+    });
+    JsFuture::from(done)
+        .await
+        .map_err(|e| format!("IndexedDB request failed: {:?}", e))?;
+    request
+        .result()
+        .map_err(|e| format!("IndexedDB request failed: {:?}", e))
+}
+
+/// Wait for a transaction to commit
+async fn committed(tx: &web_sys::IdbTransaction) -> Result<(), String> {
+    let done = js_sys::Promise::new(&mut |resolve, reject| {
+        tx.set_oncomplete(Some(&resolve));
+        tx.set_onerror(Some(&reject));
+        tx.set_onabort(Some(&reject));
+    });
+    JsFuture::from(done)
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("IndexedDB transaction failed: {:?}", e))
+}
+
+/// Blob storage in an IndexedDB object store
+pub struct Blobs {
+    db: web_sys::IdbDatabase,
+}
+
+impl Blobs {
+    /// Open the database, creating it on first use
+    pub async fn open() -> Result<Self, String> {
+        let window = web_sys::window().ok_or_else(|| "No window object".to_string())?;
+        let factory = window
+            .indexed_db()
+            .ok()
+            .flatten()
+            .ok_or_else(|| "IndexedDB not available".to_string())?;
+        let open = factory
+            .open_with_u32(DB_NAME, 1)
+            .map_err(|e| format!("Failed to open IndexedDB: {:?}", e))?;
+
+        let request = open.clone();
+        let upgrade = Closure::<dyn FnMut()>::new(move || {
+            if let Ok(db) = request.result()
+                && let Ok(db) = db.dyn_into::<web_sys::IdbDatabase>()
+            {
+                // Failure shows up as an error on the first transaction
+                db.create_object_store(STORE).ok();
+            }
+        });
+        open.set_onupgradeneeded(Some(upgrade.as_ref().unchecked_ref()));
+
+        let db = finished(&open)
+            .await?
+            .dyn_into()
+            .map_err(|_| "Failed to cast to IdbDatabase")?;
+        open.set_onupgradeneeded(None);
+        Ok(Self { db })
+    }
+
+    fn store(
+        &self,
+        mode: web_sys::IdbTransactionMode,
+    ) -> Result<(web_sys::IdbTransaction, web_sys::IdbObjectStore), String> {
+        let tx = self
+            .db
+            .transaction_with_str_and_mode(STORE, mode)
+            .map_err(|e| format!("Failed to start transaction: {:?}", e))?;
+        let store = tx
+            .object_store(STORE)
+            .map_err(|e| format!("Failed to open object store: {:?}", e))?;
+        Ok((tx, store))
+    }
+
+    /// Read a blob, `None` if it doesn't exist
+    pub async fn read(&self, name: &str) -> Result<Option<Vec<u8>>, Unreadable> {
+        let (_tx, store) = self
+            .store(web_sys::IdbTransactionMode::Readonly)
+            .map_err(Unreadable::Failed)?;
+        let (from, to) = chunk_range(name);
+        let fail = |e: JsValue| Unreadable::Failed(format!("Failed to read {}: {:?}", name, e));
+        let range = web_sys::IdbKeyRange::bound(&from.into(), &to.into()).map_err(fail)?;
+        // Both requests go out before either is awaited, so they see the
+        // same state of the store
+        let manifest = store.get(&name.into()).map_err(fail)?;
+        let chunks = store.get_all_with_key(&range).map_err(fail)?;
+
+        let manifest = finished(&manifest).await.map_err(Unreadable::Failed)?;
+        if manifest.is_undefined() {
+            return Ok(None);
+        }
+        let manifest: Manifest = manifest
+            .as_string()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .ok_or_else(|| Unreadable::Damaged("bad manifest".to_string()))?;
+        let chunks: Vec<Vec<u8>> =
+            js_sys::Array::from(&finished(&chunks).await.map_err(Unreadable::Failed)?)
+                .iter()
+                .map(|chunk| js_sys::Uint8Array::new(&chunk).to_vec())
+                .collect();
+        join(&manifest, chunks)
+            .map(Some)
+            .map_err(Unreadable::Damaged)
+    }
+
+    /// Replace a blob
+    pub async fn write(&self, name: &str, data: &[u8]) -> Result<(), String> {
+        let (tx, store) = self.store(web_sys::IdbTransactionMode::Readwrite)?;
+        let (manifest, chunks) = split(data);
+        let manifest = serde_json::to_string(&manifest).map_err(|e| e.to_string())?;
+        let fail = |e: JsValue| format!("Failed to write {}: {:?}", name, e);
+
+        Self::delete_chunks(&store, name)?;
+        for (index, chunk) in chunks.into_iter().enumerate() {
+            let chunk = js_sys::Uint8Array::from(chunk);
+            store
+                .put_with_key(&chunk, &chunk_key(name, index).into())
+                .map_err(fail)?;
+        }
+        store
+            .put_with_key(&manifest.into(), &name.into())
+            .map_err(fail)?;
+        committed(&tx).await
+    }
+
+    /// Remove a blob if it exists
+    pub async fn remove(&self, name: &str) -> Result<(), String> {
+        let (tx, store) = self.store(web_sys::IdbTransactionMode::Readwrite)?;
+        Self::delete_chunks(&store, name)?;
+        store
+            .delete(&name.into())
+            .map_err(|e| format!("Failed to remove {}: {:?}", name, e))?;
+        committed(&tx).await
+    }
+
+    fn delete_chunks(store: &web_sys::IdbObjectStore, name: &str) -> Result<(), String> {
+        let (from, to) = chunk_range(name);
+        let range = web_sys::IdbKeyRange::bound(&from.into(), &to.into())
+            .map_err(|e| format!("Bad key range: {:?}", e))?;
+        store
+            .delete(&range)
+            .map(|_| ())
+            .map_err(|e| format!("Failed to remove {}: {:?}", name, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunking() {
+        let data: Vec<u8> = (0..CHUNK_SIZE * 2 + 10).map(|i| i as u8).collect();
+        let (manifest, chunks) = split(&data);
+        assert_eq!(
+            manifest,
+            Manifest {
+                len: CHUNK_SIZE * 2 + 10,
+                chunks: 3
+            }
+        );
+        assert_eq!(chunks[2].len(), 10);
+
+        let owned: Vec<Vec<u8>> = chunks.iter().map(|c| c.to_vec()).collect();
+        assert_eq!(join(&manifest, owned.clone()).unwrap(), data);
+        // A chunk lost or cut short is caught
+        assert!(join(&manifest, owned[..2].to_vec()).is_err());
+        let mut short = owned;
+        short[2].pop();
+        assert!(join(&manifest, short).is_err());
+
+        let (empty, chunks) = split(b"");
+        assert_eq!(empty.chunks, 0);
+        assert_eq!(
+            join(&empty, chunks.iter().map(|c| c.to_vec()).collect()).unwrap(),
+            b""
+        );
+    }
+
+    #[test]
+    fn test_chunk_keys() {
+        assert_eq!(chunk_key("fs.json", 3), "fs.json#000003");
+        // Chunk keys sort in order, and between the range bounds
+        let (from, to) = chunk_range("fs.json");
+        let keys: Vec<String> = (0..12).map(|i| chunk_key("fs.json", i)).collect();
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+        assert!(keys.iter().all(|k| from < *k && *k < to));
+        // Neither the manifest nor the backups fall in the range
+        for other in ["fs.json", "fs.json.1", "fs.json.10"] {
+            assert!(!(from.as_str() <= other && other <= to.as_str()));
+        }
+    }
+}
@@ -0,0 +1,367 @@
+//! Named filesystem snapshots
+//!
+//! A snapshot is the whole filesystem as it was when it was taken, kept
+//! under a name in [`SNAPSHOT_DIR`] so it is saved and reloaded along with
+//! everything else. Each file holds a header line (a [`SnapshotInfo`] as
+//! JSON) and then the image, deflated (see [`compress`]); listing reads
+//! only the headers.
+//!
+//! Snapshots are left out of every image, and rolling back leaves them
+//! alone: going back to an old snapshot keeps the ones taken since, so a
+//! rollback can itself be rolled back.
+
+use super::changelog::covers;
+use super::compress;
+use super::memory::{FsChange, FsChangeKind, FsSnapshot, MemoryFs};
+use super::{FileSystem, OpenOptions, atomic_write};
+use serde::{Deserialize, Serialize};
+use std::io;
+
+/// Where snapshots are kept, readable by root only
+pub const SNAPSHOT_DIR: &str = "/var/lib/snapshots";
+
+/// Longest snapshot name, in bytes
+pub const MAX_NAME_LEN: usize = 64;
+
+/// What a snapshot's header says about it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotInfo {
+    pub name: String,
+    /// When it was taken, in milliseconds since the epoch
+    pub time: f64,
+    /// Paths in it
+    pub paths: usize,
+    /// Bytes of file content in it
+    pub bytes: usize,
+}
+
+/// A file as one snapshot holds it
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileVersion {
+    /// The snapshot it comes from
+    pub snapshot: String,
+    /// When that snapshot was taken, in milliseconds since the epoch
+    pub time: f64,
+    pub content: Vec<u8>,
+}
+
+/// Check a snapshot name: letters, digits, `.`, `_` and `-`, not starting
+/// with a `.`
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+}
+
+fn path_of(name: &str) -> io::Result<String> {
+    if !is_valid_name(name) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid snapshot name: {}", name),
+        ));
+    }
+    Ok(format!("{}/{}", SNAPSHOT_DIR, name))
+}
+
+fn invalid(name: &str, e: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("snapshot {}: {}", name, e),
+    )
+}
+
+/// The filesystem as it is now, leaving the snapshots out
+fn capture(fs: &MemoryFs) -> FsSnapshot {
+    let mut image = fs.snapshot();
+    image.remove_under(SNAPSHOT_DIR);
+    image
+}
+
+/// Take a snapshot of `fs` called `name`, at wall-clock `time`
+pub fn create(fs: &mut MemoryFs, name: &str, time: f64) -> io::Result<SnapshotInfo> {
+    let path = path_of(name)?;
+    if fs.exists(&path) {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("snapshot {} already exists", name),
+        ));
+    }
+    for dir in ["/var", "/var/lib", SNAPSHOT_DIR] {
+        if !fs.exists(dir) {
+            fs.create_dir(dir)?;
+            fs.chown(dir, Some(0), Some(0))?;
+        }
+    }
+    fs.chmod(SNAPSHOT_DIR, 0o700)?;
+
+    let image = capture(fs);
+    let info = SnapshotInfo {
+        name: name.to_string(),
+        time,
+        paths: image.len(),
+        bytes: image.content_size(),
+    };
+    let mut data = serde_json::to_vec(&info).map_err(|e| invalid(name, e))?;
+    data.push(b'\n');
+    let json = serde_json::to_vec(&image).map_err(|e| invalid(name, e))?;
+    data.extend_from_slice(&compress::pack(&json));
+
+    atomic_write(fs, &path, &data)?;
+    fs.chown(&path, Some(0), Some(0))?;
+    fs.chmod(&path, 0o600)?;
+    Ok(info)
+}
+
+/// The stored snapshot `name`, header and all
+fn read(fs: &mut MemoryFs, name: &str) -> io::Result<Vec<u8>> {
+    let path = path_of(name)?;
+    let size = fs.metadata(&path).map_err(|e| {
+        if e.kind() == io::ErrorKind::NotFound {
+            io::Error::new(e.kind(), format!("no snapshot called {}", name))
+        } else {
+            e
+        }
+    })?;
+    let handle = fs.open(&path, OpenOptions::new().read(true))?;
+    let mut data = vec![0u8; size.size as usize];
+    let read = fs.read(handle, &mut data);
+    fs.close(handle)?;
+    read?;
+    Ok(data)
+}
+
+/// Split a stored snapshot into its header and the packed image
+fn split<'a>(name: &str, data: &'a [u8]) -> io::Result<(SnapshotInfo, &'a [u8])> {
+    let at = data
+        .iter()
+        .position(|&b| b == b'\n')
+        .ok_or_else(|| invalid(name, "no header"))?;
+    let info = serde_json::from_slice(&data[..at]).map_err(|e| invalid(name, e))?;
+    Ok((info, &data[at + 1..]))
+}
+
+/// The header of snapshot `name`
+pub fn info(fs: &mut MemoryFs, name: &str) -> io::Result<SnapshotInfo> {
+    let data = read(fs, name)?;
+    Ok(split(name, &data)?.0)
+}
+
+/// The image held by snapshot `name`
+pub fn load(fs: &mut MemoryFs, name: &str) -> io::Result<FsSnapshot> {
+    let data = read(fs, name)?;
+    let (_, packed) = split(name, &data)?;
+    let json = compress::unpack(packed)?;
+    serde_json::from_slice(&json).map_err(|e| invalid(name, e))
+}
+
+/// Every snapshot whose header can be read, oldest first
+pub fn list(fs: &mut MemoryFs) -> Vec<SnapshotInfo> {
+    let Ok(entries) = fs.read_dir(SNAPSHOT_DIR) else {
+        return Vec::new();
+    };
+    let mut snapshots: Vec<SnapshotInfo> = entries
+        .iter()
+        .filter(|e| !e.is_dir && is_valid_name(&e.name))
+        .filter_map(|e| info(fs, &e.name).ok())
+        .collect();
+    snapshots.sort_by(|a, b| a.time.total_cmp(&b.time).then(a.name.cmp(&b.name)));
+    snapshots
+}
+
+/// The versions of file `path` the snapshots hold, oldest first
+///
+/// A snapshot whose copy is the same as the version before it adds
+/// nothing, so each version is a different content. Snapshots that can't
+/// be read, or don't hold `path` as a regular file, are skipped.
+pub fn versions(fs: &mut MemoryFs, path: &str) -> Vec<FileVersion> {
+    let mut versions: Vec<FileVersion> = Vec::new();
+    for snapshot in list(fs) {
+        let Some(content) = load(fs, &snapshot.name)
+            .ok()
+            .and_then(|image| image.file(path))
+        else {
+            continue;
+        };
+        if versions.last().is_some_and(|v| v.content == content) {
+            continue;
+        }
+        versions.push(FileVersion {
+            snapshot: snapshot.name,
+            time: snapshot.time,
+            content,
+        });
+    }
+    versions
+}
+
+/// Delete snapshot `name`
+pub fn remove(fs: &mut MemoryFs, name: &str) -> io::Result<()> {
+    let path = path_of(name)?;
+    if !fs.exists(&path) {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no snapshot called {}", name),
+        ));
+    }
+    fs.remove_file(&path)
+}
+
+/// What changed from snapshot `from` to snapshot `to`, or to the live
+/// filesystem if `to` is `None`, sorted by path
+pub fn diff(fs: &mut MemoryFs, from: &str, to: Option<&str>) -> io::Result<Vec<FsChange>> {
+    let before = load(fs, from)?;
+    match to {
+        Some(to) => Ok(load(fs, to)?.changes_since(&before)),
+        None => Ok(live_changes(fs, &before)),
+    }
+}
+
+/// Paths of the live filesystem that differ from `image`, apart from the
+/// snapshots themselves
+fn live_changes(fs: &MemoryFs, image: &FsSnapshot) -> Vec<FsChange> {
+    fs.changes_since(image)
+        .into_iter()
+        .filter(|c| !covers(SNAPSHOT_DIR, &c.path))
+        .collect()
+}
+
+/// Roll `fs` back to snapshot `name`, returning the paths that changed
+///
+/// Only paths that differ are touched, so open files elsewhere and the
+/// change log see the rollback as a set of ordinary changes. Nothing is
+/// changed on error.
+pub fn restore(fs: &mut MemoryFs, name: &str) -> io::Result<Vec<FsChange>> {
+    let image = load(fs, name)?;
+    let changes = live_changes(fs, &image);
+    let before = image.subset(
+        changes
+            .iter()
+            .filter(|c| c.kind != FsChangeKind::Created)
+            .map(|c| c.path.as_str()),
+    );
+    fs.revert(&before, &changes)?;
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::{read_to_string, write_string};
+
+    fn fs() -> MemoryFs {
+        let mut fs = MemoryFs::new();
+        fs.create_dir("/home").unwrap();
+        write_string(&mut fs, "/home/notes", "one").unwrap();
+        fs
+    }
+
+    #[test]
+    fn test_names() {
+        assert!(is_valid_name("before-upgrade"));
+        assert!(is_valid_name("v1.2_rc"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name(".hidden"));
+        assert!(!is_valid_name("a/b"));
+        assert!(!is_valid_name(&"x".repeat(MAX_NAME_LEN + 1)));
+
+        let mut fs = fs();
+        let err = create(&mut fs, "../etc", 0.0).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_create_and_list() {
+        let mut fs = fs();
+        let first = create(&mut fs, "first", 2000.0).unwrap();
+        assert_eq!(first.bytes, 3);
+        create(&mut fs, "early", 1000.0).unwrap();
+
+        let names: Vec<String> = list(&mut fs).into_iter().map(|s| s.name).collect();
+        assert_eq!(names, ["early", "first"]);
+        assert_eq!(info(&mut fs, "first").unwrap(), first);
+
+        // Root's, and one snapshot never holds another
+        let meta = fs.metadata("/var/lib/snapshots/first").unwrap();
+        assert_eq!((meta.uid, meta.mode), (0, 0o600));
+        assert!(!load(&mut fs, "early").unwrap().contains(SNAPSHOT_DIR));
+
+        let err = create(&mut fs, "first", 3000.0).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+
+        remove(&mut fs, "early").unwrap();
+        assert_eq!(list(&mut fs).len(), 1);
+        let err = remove(&mut fs, "early").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut fs = fs();
+        create(&mut fs, "a", 1.0).unwrap();
+        write_string(&mut fs, "/home/notes", "two").unwrap();
+        write_string(&mut fs, "/home/todo", "x").unwrap();
+        create(&mut fs, "b", 2.0).unwrap();
+        fs.remove_file("/home/todo").unwrap();
+
+        let changes = |changes: Vec<FsChange>| -> Vec<String> {
+            changes
+                .iter()
+                .map(|c| format!("{}{}", c.kind.marker(), c.path))
+                .collect()
+        };
+        assert_eq!(
+            changes(diff(&mut fs, "a", Some("b")).unwrap()),
+            ["~/home/notes", "+/home/todo"]
+        );
+        assert_eq!(changes(diff(&mut fs, "b", None).unwrap()), ["-/home/todo"]);
+        assert!(diff(&mut fs, "a", Some("missing")).is_err());
+    }
+
+    #[test]
+    fn test_versions() {
+        let mut fs = fs();
+        create(&mut fs, "a", 1.0).unwrap();
+        create(&mut fs, "b", 2.0).unwrap();
+        write_string(&mut fs, "/home/notes", "two").unwrap();
+        create(&mut fs, "c", 3.0).unwrap();
+        fs.remove_file("/home/notes").unwrap();
+        create(&mut fs, "d", 4.0).unwrap();
+
+        // "b" holds the same as "a", and "d" no file at all
+        let found: Vec<(String, Vec<u8>)> = versions(&mut fs, "/home/notes")
+            .into_iter()
+            .map(|v| (v.snapshot, v.content))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("a".to_string(), b"one".to_vec()),
+                ("c".into(), b"two".to_vec())
+            ]
+        );
+        assert!(versions(&mut fs, "/home").is_empty());
+    }
+
+    #[test]
+    fn test_restore() {
+        let mut fs = fs();
+        create(&mut fs, "clean", 1.0).unwrap();
+        write_string(&mut fs, "/home/notes", "changed").unwrap();
+        fs.create_dir("/home/new").unwrap();
+        write_string(&mut fs, "/home/new/file", "x").unwrap();
+        create(&mut fs, "dirty", 2.0).unwrap();
+
+        let changes = restore(&mut fs, "clean").unwrap();
+        assert_eq!(changes.len(), 3);
+        assert_eq!(read_to_string(&mut fs, "/home/notes").unwrap(), "one");
+        assert!(!fs.exists("/home/new"));
+
+        // The snapshot taken since is still there to go forward again
+        restore(&mut fs, "dirty").unwrap();
+        assert_eq!(read_to_string(&mut fs, "/home/new/file").unwrap(), "x");
+        assert!(restore(&mut fs, "dirty").unwrap().is_empty());
+    }
+}
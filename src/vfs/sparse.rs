@@ -0,0 +1,662 @@
+//! Sparse file contents
+//!
+//! A file is stored as an extent map: runs of bytes keyed by their offset,
+//! plus the file's length. The gaps between runs (holes) read as zeros but
+//! take no memory, so seeking far past the end and writing a few bytes
+//! stores just those bytes.
+//!
+//! A file without holes is one run from offset 0, and serializes as a plain
+//! byte array, the same as before files could be sparse. Only a file with
+//! holes uses the map form:
+//!
+//! ```text
+//! [104, 105]                                    "hi"
+//! {"len": 1048576, "extents": [[1048574, [104, 105]]]}
+//! ```
+//!
+//! In memory, runs are cut at every multiple of [`BLOCK`] and reference
+//! counted, so a [`BlockStore`] can point files holding the same block of
+//! bytes at one copy. Cloning a file shares its blocks, and writing to a
+//! shared block copies just that block first.
+
+use super::{FileHandle, FileSystem};
+use serde::de::value::MapAccessDeserializer;
+use serde::de::{MapAccess, SeqAccess, Visitor};
+use serde::ser::SerializeSeq;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io::{self, SeekFrom};
+use std::rc::Rc;
+
+/// Runs of zeros at least this long are left as holes by [`write_sparse`]
+pub const HOLE_MIN: usize = 4096;
+
+/// Runs are cut at multiples of this, the unit files share contents in
+pub const BLOCK: u64 = 4096;
+
+/// The contents of a file, with holes
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileData {
+    /// Length of the file, holes included
+    len: u64,
+    /// Runs of data by offset; they never overlap, never cross a multiple
+    /// of [`BLOCK`], never touch another in the same block, and all end by
+    /// `len`. Since the cuts only depend on which bytes are stored, equal
+    /// files have equal maps.
+    extents: BTreeMap<u64, Rc<Vec<u8>>>,
+}
+
+impl FileData {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Length of the file, holes included (the apparent size)
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Check if the file is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Bytes actually stored (the allocated size)
+    pub fn allocated(&self) -> u64 {
+        self.extents.values().map(|run| run.len() as u64).sum()
+    }
+
+    /// Bytes stored between `start` and `end`
+    pub fn allocated_in(&self, start: u64, end: u64) -> u64 {
+        self.extents
+            .range(..end)
+            .rev()
+            .take_while(|(run_start, run)| **run_start + run.len() as u64 > start)
+            .map(|(&run_start, run)| (run_start + run.len() as u64).min(end) - run_start.max(start))
+            .sum()
+    }
+
+    /// Check if the file has holes
+    pub fn is_sparse(&self) -> bool {
+        self.allocated() < self.len
+    }
+
+    /// Read from `offset` into `buf`, returning the bytes read; holes read
+    /// as zeros
+    pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> usize {
+        if offset >= self.len {
+            return 0;
+        }
+        let n = buf.len().min((self.len - offset) as usize);
+        let end = offset + n as u64;
+        let buf = &mut buf[..n];
+        buf.fill(0);
+        for (&start, run) in self.extents.range(..end).rev() {
+            let run_end = start + run.len() as u64;
+            if run_end <= offset {
+                break;
+            }
+            let from = start.max(offset);
+            let to = run_end.min(end);
+            buf[(from - offset) as usize..(to - offset) as usize]
+                .copy_from_slice(&run[(from - start) as usize..(to - start) as usize]);
+        }
+        n
+    }
+
+    /// Hand up to `len` bytes from `offset` to `sink` in order, as slices
+    /// of the stored runs rather than copies; holes come as slices of
+    /// zeros. Returns the bytes handed over.
+    pub fn visit_at(&self, offset: u64, len: usize, sink: &mut dyn FnMut(&[u8])) -> usize {
+        if offset >= self.len {
+            return 0;
+        }
+        let n = len.min((self.len - offset) as usize);
+        let end = offset + n as u64;
+
+        // The run holding `offset`, if any, and then every later one
+        let first = self
+            .extents
+            .range(..=offset)
+            .next_back()
+            .filter(|(start, run)| **start + run.len() as u64 > offset)
+            .map_or(offset, |(start, _)| *start);
+        let mut at = offset;
+        for (&start, run) in self.extents.range(first..end) {
+            zeros(start.saturating_sub(at), sink);
+            let from = start.max(at);
+            let to = (start + run.len() as u64).min(end);
+            sink(&run[(from - start) as usize..(to - start) as usize]);
+            at = to;
+        }
+        zeros(end - at, sink);
+        n
+    }
+
+    /// Write `data` at `offset`, growing the file if it ends past the end;
+    /// a gap between the old end and `offset` becomes a hole
+    pub fn write_at(&mut self, offset: u64, data: &[u8]) {
+        let mut at = offset;
+        for piece in data.chunks(BLOCK as usize) {
+            // Up to the end of the block `at` is in
+            let room = (BLOCK - at % BLOCK) as usize;
+            let (first, rest) = piece.split_at(room.min(piece.len()));
+            self.write_in_block(at, first);
+            at += first.len() as u64;
+            if !rest.is_empty() {
+                self.write_in_block(at, rest);
+                at += rest.len() as u64;
+            }
+        }
+    }
+
+    /// Write `data`, which doesn't cross a block boundary, at `offset`
+    fn write_in_block(&mut self, offset: u64, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        let end = offset + data.len() as u64;
+        let block = offset - offset % BLOCK;
+
+        // Runs in the block that overlap or touch the write merge with it
+        // into one
+        let touching: Vec<u64> = self
+            .extents
+            .range(block..(end + 1).min(block + BLOCK))
+            .rev()
+            .take_while(|(start, run)| **start + run.len() as u64 >= offset)
+            .map(|(start, _)| *start)
+            .collect();
+        let start = touching.last().map_or(offset, |&first| first.min(offset));
+
+        let mut merged = Vec::new();
+        for run_start in touching.into_iter().rev() {
+            let Some(run) = self.extents.remove(&run_start) else {
+                continue;
+            };
+            if run_start == start && merged.is_empty() {
+                // Grow the first run in place rather than copying it,
+                // unless another file shares it
+                merged = Rc::unwrap_or_clone(run);
+                continue;
+            }
+            let at = (run_start - start) as usize;
+            if merged.len() < at + run.len() {
+                merged.resize(at + run.len(), 0);
+            }
+            merged[at..at + run.len()].copy_from_slice(&run);
+        }
+
+        let at = (offset - start) as usize;
+        if merged.len() < at + data.len() {
+            merged.resize(at + data.len(), 0);
+        }
+        merged[at..at + data.len()].copy_from_slice(data);
+        self.extents.insert(start, Rc::new(merged));
+        self.len = self.len.max(end);
+    }
+
+    /// Cut the file to `len` bytes, or grow it with a hole
+    pub fn set_len(&mut self, len: u64) {
+        if len < self.len {
+            self.extents.retain(|&start, _| start < len);
+            if let Some((&start, run)) = self.extents.iter_mut().next_back()
+                && start + run.len() as u64 > len
+            {
+                Rc::make_mut(run).truncate((len - start) as usize);
+            }
+        }
+        self.len = len;
+    }
+
+    /// Empty the file
+    pub fn clear(&mut self) {
+        self.extents.clear();
+        self.len = 0;
+    }
+
+    /// The whole file, holes filled with zeros
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut data = vec![0; self.len as usize];
+        self.read_at(0, &mut data);
+        data
+    }
+
+    /// The stored runs as `(offset, bytes)`, in order; a run longer than a
+    /// block comes in pieces, one per block
+    pub fn extents(&self) -> impl Iterator<Item = (u64, &[u8])> {
+        self.extents
+            .iter()
+            .map(|(&start, run)| (start, run.as_slice()))
+    }
+
+    /// Check if every byte is stored, as in a plain file
+    fn is_dense(&self) -> bool {
+        self.allocated() == self.len
+    }
+
+    /// The stored runs joined where they touch, as `(offset, pieces)`:
+    /// the runs a file had before they were cut into blocks
+    fn joined_runs(&self) -> Vec<(u64, Vec<&[u8]>)> {
+        let mut runs: Vec<(u64, Vec<&[u8]>)> = Vec::new();
+        let mut end = None;
+        for (&start, run) in &self.extents {
+            match runs.last_mut() {
+                Some((_, pieces)) if end == Some(start) => pieces.push(run),
+                _ => runs.push((start, vec![run])),
+            }
+            end = Some(start + run.len() as u64);
+        }
+        runs
+    }
+}
+
+impl From<Vec<u8>> for FileData {
+    fn from(data: Vec<u8>) -> Self {
+        if data.len() as u64 <= BLOCK {
+            let len = data.len() as u64;
+            let mut extents = BTreeMap::new();
+            if !data.is_empty() {
+                extents.insert(0, Rc::new(data));
+            }
+            return Self { len, extents };
+        }
+        let mut file = Self::new();
+        file.write_at(0, &data);
+        file
+    }
+}
+
+impl Hash for FileData {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // A plain file hashes as its bytes did before files had holes, and
+        // runs as they did before they were cut into blocks, so
+        // fingerprints taken then still match. `[u8]` hashes as its length
+        // and then its bytes, which can be fed in pieces.
+        if self.is_dense() {
+            state.write_usize(self.len as usize);
+            for run in self.extents.values() {
+                state.write(run);
+            }
+            return;
+        }
+        self.len.hash(state);
+        for (start, pieces) in self.joined_runs() {
+            start.hash(state);
+            state.write_usize(pieces.iter().map(|piece| piece.len()).sum());
+            for piece in pieces {
+                state.write(piece);
+            }
+        }
+    }
+}
+
+/// The map form of a sparse file
+#[derive(Serialize, Deserialize)]
+struct Sparse {
+    len: u64,
+    extents: Vec<(u64, Vec<u8>)>,
+}
+
+impl Serialize for FileData {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.is_dense() {
+            let mut seq = serializer.serialize_seq(Some(self.len as usize))?;
+            for byte in self.extents.values().flat_map(|run| run.iter()) {
+                seq.serialize_element(byte)?;
+            }
+            return seq.end();
+        }
+        Sparse {
+            len: self.len,
+            extents: self
+                .joined_runs()
+                .into_iter()
+                .map(|(start, pieces)| (start, pieces.concat()))
+                .collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for FileData {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(FileDataVisitor)
+    }
+}
+
+/// Reads either form, without buffering a plain file's bytes twice
+struct FileDataVisitor;
+
+impl<'de> Visitor<'de> for FileDataVisitor {
+    type Value = FileData;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a byte array or a sparse file map")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<FileData, A::Error> {
+        let mut data = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(byte) = seq.next_element::<u8>()? {
+            data.push(byte);
+        }
+        Ok(data.into())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<FileData, A::Error> {
+        let sparse = Sparse::deserialize(MapAccessDeserializer::new(map))?;
+        // Rebuilt run by run, so overlapping or unsorted runs from a
+        // damaged snapshot still make a consistent map
+        let mut file = FileData::new();
+        for (start, run) in sparse.extents {
+            file.write_at(start, &run);
+        }
+        file.set_len(sparse.len);
+        Ok(file)
+    }
+}
+
+/// Blocks of file contents by their bytes, so that files holding the same
+/// bytes share one copy
+///
+/// A block stays in the store while any file holds it. Writing to a shared
+/// block copies it first, so the blocks in the store never change.
+#[derive(Debug, Default)]
+pub struct BlockStore {
+    blocks: HashSet<Rc<Vec<u8>>>,
+    /// How many blocks were left after the last sweep
+    swept: usize,
+}
+
+impl BlockStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Point `file`'s blocks at the stored copies of the same bytes, and
+    /// store those there are no copies of yet
+    pub fn share(&mut self, file: &mut FileData) {
+        for run in file.extents.values_mut() {
+            match self.blocks.get(run.as_ref()) {
+                Some(stored) => {
+                    if !Rc::ptr_eq(stored, run) {
+                        *run = Rc::clone(stored);
+                    }
+                }
+                None => {
+                    self.blocks.insert(Rc::clone(run));
+                }
+            }
+        }
+        // Sweep as often as the store doubles, so it costs nothing extra
+        // per block over time
+        if self.blocks.len() > 2 * self.swept.max(64) {
+            self.sweep();
+        }
+    }
+
+    /// Drop the blocks no file holds any more
+    pub fn sweep(&mut self) {
+        self.blocks.retain(|block| Rc::strong_count(block) > 1);
+        self.swept = self.blocks.len();
+    }
+
+    /// Number of blocks stored
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Check if no blocks are stored
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+}
+
+/// Bytes `files` take in memory, counting each block they share once
+pub fn stored_size<'a>(files: impl IntoIterator<Item = &'a FileData>) -> u64 {
+    let mut seen = HashSet::new();
+    files
+        .into_iter()
+        .flat_map(|file| file.extents.values())
+        .filter(|run| seen.insert(Rc::as_ptr(run)))
+        .map(|run| run.len() as u64)
+        .sum()
+}
+
+/// Hand `len` zeros to `sink`, a page at a time
+fn zeros(len: u64, sink: &mut dyn FnMut(&[u8])) {
+    static ZEROS: [u8; HOLE_MIN] = [0; HOLE_MIN];
+    let mut left = len;
+    while left > 0 {
+        let n = left.min(HOLE_MIN as u64);
+        sink(&ZEROS[..n as usize]);
+        left -= n;
+    }
+}
+
+/// Write `data` to `handle` from the start, seeking over runs of at least
+/// [`HOLE_MIN`] zeros instead of writing them
+///
+/// The file should be empty, as after opening it with truncate. Backends
+/// that support holes leave the runs unallocated; others fill them with
+/// zeros, so the result reads back the same either way.
+pub fn write_sparse<F: FileSystem + ?Sized>(
+    fs: &mut F,
+    handle: FileHandle,
+    data: &[u8],
+) -> io::Result<()> {
+    let mut pos = 0;
+    while pos < data.len() {
+        let zeros = data[pos..].iter().take_while(|&&b| b == 0).count();
+        // Keep the last byte, so a trailing hole still sets the length
+        let zeros = zeros.min(data.len() - pos - 1);
+        if zeros >= HOLE_MIN {
+            pos += zeros;
+            fs.seek(handle, SeekFrom::Start(pos as u64))?;
+            continue;
+        }
+        // Data up to the next run of zeros long enough to skip
+        let mut zero_run = 0;
+        let run = data[pos + zeros..]
+            .iter()
+            .position(|&b| {
+                zero_run = if b == 0 { zero_run + 1 } else { 0 };
+                zero_run == HOLE_MIN
+            })
+            .map_or(data.len(), |i| pos + zeros + i + 1 - HOLE_MIN);
+        fs.write(handle, &data[pos..run])?;
+        pos = run;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_holes_read_as_zeros() {
+        let mut file = FileData::new();
+        file.write_at(1 << 20, b"end");
+        assert_eq!(file.len(), (1 << 20) + 3);
+        assert_eq!(file.allocated(), 3);
+        assert!(file.is_sparse());
+
+        let mut buf = [9u8; 6];
+        assert_eq!(file.read_at((1 << 20) - 3, &mut buf), 6);
+        assert_eq!(&buf, b"\0\0\0end");
+        assert_eq!(file.read_at(file.len(), &mut buf), 0);
+
+        file.write_at(0, b"start");
+        assert_eq!(file.allocated(), 8);
+        assert_eq!(&file.to_vec()[..6], b"start\0");
+    }
+
+    #[test]
+    fn test_visit_at() {
+        let mut file = FileData::new();
+        file.write_at(2, b"ab");
+        file.write_at(HOLE_MIN as u64 * 3, b"cd");
+
+        let visit = |offset: u64, len: usize| {
+            let mut chunks = 0;
+            let mut seen = Vec::new();
+            let n = file.visit_at(offset, len, &mut |chunk| {
+                chunks += 1;
+                seen.extend_from_slice(chunk);
+            });
+            let mut buf = vec![0u8; len];
+            assert_eq!(file.read_at(offset, &mut buf), n);
+            assert_eq!(seen, &buf[..n]);
+            chunks
+        };
+        // Slices of the run itself, no copy
+        assert_eq!(visit(3, 1), 1);
+        assert_eq!(visit(0, 6), 3);
+        // Holes a page of zeros at a time
+        assert_eq!(visit(0, file.len() as usize + 10), 6);
+        assert_eq!(visit(HOLE_MIN as u64 * 3 + 1, 10), 1);
+        assert_eq!(visit(file.len(), 10), 0);
+    }
+
+    #[test]
+    fn test_writes_merge_runs() {
+        let mut file = FileData::new();
+        file.write_at(10, b"cd");
+        file.write_at(14, b"gh");
+        file.write_at(12, b"ef");
+        file.write_at(8, b"ab");
+        assert_eq!(file.extents().collect::<Vec<_>>(), [(8, &b"abcdefgh"[..])]);
+
+        // Overwriting inside a run, and across its end
+        file.write_at(9, b"X");
+        file.write_at(15, b"YZ");
+        assert_eq!(file.extents().collect::<Vec<_>>(), [(8, &b"aXcdefgYZ"[..])]);
+        assert_eq!(file.len(), 17);
+    }
+
+    #[test]
+    fn test_runs_cut_at_blocks() {
+        let data: Vec<u8> = (0..3 * BLOCK + 10).map(|i| i as u8).collect();
+        let file = FileData::from(data.clone());
+        let starts: Vec<u64> = file.extents().map(|(start, _)| start).collect();
+        assert_eq!(starts, [0, BLOCK, 2 * BLOCK, 3 * BLOCK]);
+        assert_eq!(file.to_vec(), data);
+
+        // Written piece by piece across the boundaries, it comes out the same
+        let mut pieces = FileData::new();
+        for chunk in data.chunks(1000) {
+            pieces.write_at(pieces.len(), chunk);
+        }
+        assert_eq!(pieces, file);
+
+        // Still a plain file to serde and to hashing
+        let hash = |value: &dyn Fn(&mut std::collections::hash_map::DefaultHasher)| {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            value(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(hash(&|h| file.hash(h)), hash(&|h| data.hash(h)));
+        assert_eq!(
+            serde_json::to_string(&file).unwrap(),
+            serde_json::to_string(&data).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_block_store_shares() {
+        let data = vec![7u8; 2 * BLOCK as usize];
+        let mut store = BlockStore::new();
+        let mut a = FileData::from(data.clone());
+        let mut b = FileData::from(data.clone());
+        store.share(&mut a);
+        store.share(&mut b);
+        // Both blocks of both files are one copy
+        assert_eq!(store.len(), 1);
+        assert_eq!(stored_size([&a, &b]), BLOCK);
+
+        // Writing to a shared block copies it
+        b.write_at(1, b"x");
+        assert_eq!(a.to_vec(), data);
+        assert_eq!(stored_size([&a, &b]), 2 * BLOCK);
+        store.share(&mut b);
+        assert_eq!(store.len(), 2);
+
+        drop(b);
+        store.sweep();
+        assert_eq!(store.len(), 1);
+        drop(a);
+        store.sweep();
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn test_set_len() {
+        let mut file = FileData::from(b"hello world".to_vec());
+        assert!(!file.is_sparse());
+        file.set_len(5);
+        assert_eq!(file.to_vec(), b"hello");
+        file.set_len(8);
+        assert_eq!(file.to_vec(), b"hello\0\0\0");
+        assert_eq!(file.allocated(), 5);
+        file.set_len(0);
+        assert!(file.is_empty());
+        assert_eq!(file.extents().count(), 0);
+    }
+
+    #[test]
+    fn test_write_sparse() {
+        let mut fs = crate::vfs::MemoryFs::new();
+        let mut data = vec![0u8; 3 * HOLE_MIN];
+        data[10] = 1;
+        data[2 * HOLE_MIN] = 2;
+        data.extend_from_slice(&[0; HOLE_MIN + 10]);
+
+        let options = crate::vfs::OpenOptions::new().write(true).create(true);
+        let handle = fs.open("/f", options).unwrap();
+        write_sparse(&mut fs, handle, &data).unwrap();
+        fs.close(handle).unwrap();
+
+        let meta = fs.metadata("/f").unwrap();
+        assert_eq!(meta.size, data.len() as u64);
+        // The short run of zeros before byte 10 is written, the long ones
+        // are skipped, and the trailing hole costs its last byte
+        assert_eq!(meta.allocated, 11 + 1 + 1);
+
+        let handle = fs.open("/f", crate::vfs::OpenOptions::new()).unwrap();
+        let mut back = vec![9u8; data.len()];
+        assert_eq!(fs.read(handle, &mut back).unwrap(), data.len());
+        assert_eq!(back, data);
+    }
+
+    #[test]
+    fn test_serialization() {
+        // Plain files keep the old form, and their old hash
+        let plain = FileData::from(b"hi".to_vec());
+        assert_eq!(serde_json::to_string(&plain).unwrap(), "[104,105]");
+        let hash = |value: &dyn Fn(&mut std::collections::hash_map::DefaultHasher)| {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            value(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(hash(&|h| plain.hash(h)), hash(&|h| b"hi".to_vec().hash(h)));
+
+        let mut sparse = FileData::new();
+        sparse.write_at(6, b"hi");
+        sparse.set_len(10);
+        let json = serde_json::to_string(&sparse).unwrap();
+        assert_eq!(json, r#"{"len":10,"extents":[[6,[104,105]]]}"#);
+        assert_eq!(serde_json::from_str::<FileData>(&json).unwrap(), sparse);
+        assert_eq!(
+            serde_json::from_str::<FileData>("[104,105]").unwrap(),
+            plain
+        );
+
+        // Overlapping runs are merged, and runs past the length cut
+        let damaged: FileData =
+            serde_json::from_str(r#"{"len":4,"extents":[[2,[3,4,5]],[0,[1,2,3]]]}"#).unwrap();
+        assert_eq!(damaged.to_vec(), [1, 2, 3, 4]);
+    }
+}
@@ -0,0 +1,967 @@
+//! Tar archives as a read-only filesystem
+//!
+//! [`TarFs`] serves the entries of a tar archive held in memory, so a
+//! package or dataset shipped as one `.tar` file can be mounted and browsed
+//! in place (`mount -t tar archive.tar /mnt/dir`). File contents are read
+//! straight out of the archive; nothing is unpacked.
+//!
+//! The reader understands ustar headers, with the GNU long name records
+//! (`L`/`K`) and pax extended headers (`x`) that tools use for long paths.
+//! Regular files, directories, symbolic links and hard links are served;
+//! devices and FIFOs are skipped. Directories the archive doesn't list
+//! itself are made up with mode 755. Paths can't climb out of the root.
+//!
+//! [`TarWriter`] builds archives the reader (and other tools) can read
+//! back, with pax headers for what ustar can't hold; [`super::export`]
+//! uses it to write out a whole filesystem.
+
+use super::{DirEntry, FileHandle, FileSystem, Metadata, OpenOptions};
+use std::collections::BTreeMap;
+use std::io::{self, SeekFrom};
+
+/// Size of a header or data block
+const BLOCK: usize = 512;
+
+/// Symbolic links followed before giving up
+const MAX_SYMLINK_DEPTH: usize = 40;
+
+/// Pax record key prefix for an extended attribute, as GNU tar and star
+/// write them
+const XATTR_PREFIX: &str = "SCHILY.xattr.";
+
+/// What an archive entry is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TarKind {
+    /// A regular file, its contents at `offset..offset + len`
+    File {
+        offset: usize,
+        len: usize,
+    },
+    Dir,
+    /// A symbolic link to the given target
+    Symlink(String),
+    /// Another name for an earlier entry, given by its normalized path
+    HardLink(String),
+}
+
+/// An entry read from an archive header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TarEntry {
+    /// Normalized absolute path
+    pub path: String,
+    pub kind: TarKind,
+    pub mode: u16,
+    pub uid: u32,
+    pub gid: u32,
+    /// Modification time in milliseconds since epoch
+    pub mtime: u64,
+    /// Extended attributes, from `SCHILY.xattr.` pax records
+    pub xattrs: BTreeMap<String, Vec<u8>>,
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn not_found() -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, "Path not found")
+}
+
+fn read_only() -> io::Error {
+    io::Error::new(io::ErrorKind::ReadOnlyFilesystem, "Read-only file system")
+}
+
+/// Normalize a path (leading slash, `.` and `..` resolved, never above the
+/// root)
+fn normalize(path: &str) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            name => parts.push(name),
+        }
+    }
+    format!("/{}", parts.join("/"))
+}
+
+/// Parent directory of a normalized path; `None` for the root
+fn parent(path: &str) -> Option<&str> {
+    match path.rfind('/')? {
+        0 if path.len() > 1 => Some("/"),
+        0 => None,
+        idx => Some(&path[..idx]),
+    }
+}
+
+/// A NUL-terminated header field
+fn field(bytes: &[u8]) -> &[u8] {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    &bytes[..end]
+}
+
+fn text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(field(bytes)).into_owned()
+}
+
+/// A numeric field: octal digits, or big-endian base-256 when the high bit
+/// of the first byte is set (GNU, for values octal can't hold)
+fn number(bytes: &[u8]) -> Option<u64> {
+    if bytes.first().is_some_and(|&b| b & 0x80 != 0) {
+        return bytes.iter().enumerate().try_fold(0u64, |n, (i, &b)| {
+            let b = if i == 0 { b & 0x7F } else { b };
+            n.checked_mul(256)?.checked_add(u64::from(b))
+        });
+    }
+    let digits = std::str::from_utf8(field(bytes)).ok()?.trim();
+    if digits.is_empty() {
+        return Some(0);
+    }
+    u64::from_str_radix(digits, 8).ok()
+}
+
+/// Check a header's checksum: the sum of its bytes, with the checksum
+/// field itself counted as spaces
+fn checksum_ok(header: &[u8]) -> bool {
+    let sum: u64 = header
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| {
+            if (148..156).contains(&i) {
+                32
+            } else {
+                u64::from(b)
+            }
+        })
+        .sum();
+    number(&header[148..156]) == Some(sum)
+}
+
+/// Apply the `path`, `linkpath`, `size`, `mtime` and `SCHILY.xattr.*`
+/// records of a pax extended header
+fn pax_records(data: &[u8], overrides: &mut Overrides) {
+    let mut rest = data;
+    while !rest.is_empty() {
+        let Some(space) = rest.iter().position(|&b| b == b' ') else {
+            return;
+        };
+        let Some(len) = std::str::from_utf8(&rest[..space])
+            .ok()
+            .and_then(|n| n.parse::<usize>().ok())
+            .filter(|&len| len > space && len <= rest.len())
+        else {
+            return;
+        };
+        let record = &rest[space + 1..len];
+        let record = record.strip_suffix(b"\n").unwrap_or(record);
+        if let Some(eq) = record.iter().position(|&b| b == b'=') {
+            let key = String::from_utf8_lossy(&record[..eq]);
+            // Attribute values are bytes; the rest are text
+            let raw = &record[eq + 1..];
+            let value = String::from_utf8_lossy(raw);
+            match key.as_ref() {
+                "path" => overrides.path = Some(value.into_owned()),
+                "linkpath" => overrides.link = Some(value.into_owned()),
+                "size" => overrides.size = value.parse().ok(),
+                "mtime" => overrides.mtime = pax_time(&value),
+                key => {
+                    if let Some(name) = key.strip_prefix(XATTR_PREFIX) {
+                        overrides.xattrs.insert(name.to_string(), raw.to_vec());
+                    }
+                }
+            }
+        }
+        rest = &rest[len..];
+    }
+}
+
+/// A pax time, seconds with an optional fraction, in milliseconds
+fn pax_time(value: &str) -> Option<u64> {
+    let (secs, fraction) = value.split_once('.').unwrap_or((value, ""));
+    let millis: String = fraction.chars().chain("000".chars()).take(3).collect();
+    secs.parse::<u64>()
+        .ok()?
+        .checked_mul(1000)?
+        .checked_add(millis.parse().ok()?)
+}
+
+/// Values from GNU long name records and pax headers, for the next entry
+#[derive(Default)]
+struct Overrides {
+    path: Option<String>,
+    link: Option<String>,
+    size: Option<u64>,
+    /// Milliseconds
+    mtime: Option<u64>,
+    xattrs: BTreeMap<String, Vec<u8>>,
+}
+
+/// Read the entries of a tar archive, in archive order
+pub fn read_entries(data: &[u8]) -> io::Result<Vec<TarEntry>> {
+    let mut entries = Vec::new();
+    let mut overrides = Overrides::default();
+    let mut pos = 0;
+    while pos + BLOCK <= data.len() {
+        let header = &data[pos..pos + BLOCK];
+        // A zero block ends the archive
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+        if !checksum_ok(header) {
+            return Err(invalid(format!("bad tar header checksum at byte {}", pos)));
+        }
+
+        let size = match overrides.size.take() {
+            Some(size) => size,
+            None => number(&header[124..136])
+                .ok_or_else(|| invalid(format!("bad size in tar header at byte {}", pos)))?,
+        };
+        let start = pos + BLOCK;
+        let len = usize::try_from(size)
+            .ok()
+            .filter(|&len| len <= data.len() - start)
+            .ok_or_else(|| invalid(format!("truncated tar entry at byte {}", pos)))?;
+        pos = start + len.div_ceil(BLOCK) * BLOCK;
+        let body = &data[start..start + len];
+
+        let typeflag = header[156];
+        match typeflag {
+            b'L' => {
+                overrides.path = Some(text(body));
+                continue;
+            }
+            b'K' => {
+                overrides.link = Some(text(body));
+                continue;
+            }
+            b'x' => {
+                pax_records(body, &mut overrides);
+                continue;
+            }
+            // Global pax headers and vendor records carry nothing we serve
+            b'g' | b'A'..=b'Z' => continue,
+            _ => {}
+        }
+
+        let name = overrides.path.take().unwrap_or_else(|| {
+            let name = text(&header[0..100]);
+            let prefix = text(&header[345..500]);
+            if &header[257..262] == b"ustar" && !prefix.is_empty() {
+                format!("{}/{}", prefix, name)
+            } else {
+                name
+            }
+        });
+        let link = overrides
+            .link
+            .take()
+            .unwrap_or_else(|| text(&header[157..257]));
+        let mtime = overrides
+            .mtime
+            .take()
+            .or_else(|| number(&header[136..148]).map(|secs| secs.saturating_mul(1000)))
+            .unwrap_or(0);
+        let xattrs = std::mem::take(&mut overrides.xattrs);
+
+        let kind = match typeflag {
+            b'0' | 0 | b'7' if name.ends_with('/') => TarKind::Dir,
+            b'0' | 0 | b'7' => TarKind::File { offset: start, len },
+            b'1' => TarKind::HardLink(normalize(&link)),
+            b'2' => TarKind::Symlink(link),
+            b'5' => TarKind::Dir,
+            // Devices and FIFOs
+            _ => continue,
+        };
+        let path = normalize(&name);
+        if path == "/" && kind != TarKind::Dir {
+            continue;
+        }
+        entries.push(TarEntry {
+            path,
+            kind,
+            mode: (number(&header[100..108]).unwrap_or(0o644) & 0o7777) as u16,
+            uid: number(&header[108..116]).unwrap_or(0) as u32,
+            gid: number(&header[116..124]).unwrap_or(0) as u32,
+            mtime,
+            xattrs,
+        });
+    }
+    Ok(entries)
+}
+
+/// Ownership, mode, time and extended attributes of an entry being written
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TarAttrs {
+    pub mode: u16,
+    pub uid: u32,
+    pub gid: u32,
+    /// Modification time in milliseconds since epoch
+    pub mtime: u64,
+    pub xattrs: BTreeMap<String, Vec<u8>>,
+}
+
+/// Builds an archive in memory
+///
+/// Entries get ustar headers. What a header can't hold exactly goes in a
+/// pax extended header before it: a path or link target over 100 bytes,
+/// a time with milliseconds, and extended attributes (as `SCHILY.xattr.`
+/// records). Numbers too big for octal are written in base 256.
+#[derive(Debug, Default)]
+pub struct TarWriter {
+    out: Vec<u8>,
+    count: usize,
+}
+
+impl TarWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a directory; `/` is written as `./`
+    pub fn add_dir(&mut self, path: &str, attrs: &TarAttrs) {
+        let name = match normalize(path).trim_start_matches('/') {
+            "" => "./".to_string(),
+            name => format!("{}/", name),
+        };
+        self.add(&name, b'5', b"", "", attrs);
+    }
+
+    /// Add a regular file
+    pub fn add_file(&mut self, path: &str, data: &[u8], attrs: &TarAttrs) {
+        let name = normalize(path).trim_start_matches('/').to_string();
+        self.add(&name, b'0', data, "", attrs);
+    }
+
+    /// Add a symbolic link
+    pub fn add_symlink(&mut self, path: &str, target: &str, attrs: &TarAttrs) {
+        let name = normalize(path).trim_start_matches('/').to_string();
+        self.add(&name, b'2', b"", target, attrs);
+    }
+
+    fn add(&mut self, name: &str, typeflag: u8, data: &[u8], link: &str, attrs: &TarAttrs) {
+        let mut pax = Vec::new();
+        if name.len() > 100 {
+            pax_record(&mut pax, "path", name.as_bytes());
+        }
+        if link.len() > 100 {
+            pax_record(&mut pax, "linkpath", link.as_bytes());
+        }
+        if !attrs.mtime.is_multiple_of(1000) {
+            let time = format!("{}.{:03}", attrs.mtime / 1000, attrs.mtime % 1000);
+            pax_record(&mut pax, "mtime", time.as_bytes());
+        }
+        for (key, value) in &attrs.xattrs {
+            pax_record(&mut pax, &format!("{}{}", XATTR_PREFIX, key), value);
+        }
+        if !pax.is_empty() {
+            let header = write_header("././@PaxHeader", b'x', pax.len() as u64, "", attrs);
+            self.out.extend_from_slice(&header);
+            self.append(&pax);
+        }
+
+        let header = write_header(name, typeflag, data.len() as u64, link, attrs);
+        self.out.extend_from_slice(&header);
+        self.append(data);
+        self.count += 1;
+    }
+
+    /// Append `data` padded to a whole block
+    fn append(&mut self, data: &[u8]) {
+        self.out.extend_from_slice(data);
+        self.out.resize(self.out.len().div_ceil(BLOCK) * BLOCK, 0);
+    }
+
+    /// Number of entries added so far
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Append the two zero blocks that end an archive and return it
+    pub fn finish(mut self) -> Vec<u8> {
+        self.out.resize(self.out.len() + BLOCK * 2, 0);
+        self.out
+    }
+}
+
+/// Append a pax record, `LEN key=value\n`, where LEN counts itself
+fn pax_record(out: &mut Vec<u8>, key: &str, value: &[u8]) {
+    let base = key.len() + value.len() + 3;
+    let mut len = base + 1;
+    while base + len.to_string().len() != len {
+        len = base + len.to_string().len();
+    }
+    out.extend_from_slice(format!("{} {}=", len, key).as_bytes());
+    out.extend_from_slice(value);
+    out.push(b'\n');
+}
+
+/// Write `value` into a numeric field: octal digits and a NUL, or base
+/// 256 when octal won't fit
+fn put_number(field: &mut [u8], value: u64) {
+    let digits = field.len() - 1;
+    let octal = format!("{:0width$o}", value, width = digits);
+    if octal.len() == digits {
+        field[..digits].copy_from_slice(octal.as_bytes());
+        field[digits] = 0;
+        return;
+    }
+    let bytes = value.to_be_bytes();
+    field.fill(0);
+    let at = field.len() - bytes.len();
+    field[at..].copy_from_slice(&bytes);
+    field[0] |= 0x80;
+}
+
+/// A ustar header; names and link targets longer than their fields are
+/// cut short, the full ones going in a pax header before it
+fn write_header(name: &str, typeflag: u8, size: u64, link: &str, attrs: &TarAttrs) -> Vec<u8> {
+    let mut h = vec![0u8; BLOCK];
+    let name = &name.as_bytes()[..name.len().min(100)];
+    h[..name.len()].copy_from_slice(name);
+    put_number(&mut h[100..108], u64::from(attrs.mode & 0o7777));
+    put_number(&mut h[108..116], u64::from(attrs.uid));
+    put_number(&mut h[116..124], u64::from(attrs.gid));
+    put_number(&mut h[124..136], size);
+    put_number(&mut h[136..148], attrs.mtime / 1000);
+    h[156] = typeflag;
+    let link = &link.as_bytes()[..link.len().min(100)];
+    h[157..157 + link.len()].copy_from_slice(link);
+    h[257..263].copy_from_slice(b"ustar\0");
+    h[263..265].copy_from_slice(b"00");
+    h[148..156].copy_from_slice(b"        ");
+    let sum: u64 = h.iter().map(|&b| u64::from(b)).sum();
+    h[148..155].copy_from_slice(format!("{:06o}\0", sum).as_bytes());
+    h
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    File { offset: usize, len: usize },
+    Dir,
+    Symlink(String),
+}
+
+#[derive(Debug, Clone)]
+struct Inode {
+    node: Node,
+    mode: u16,
+    uid: u32,
+    gid: u32,
+    mtime: f64,
+}
+
+struct OpenFile {
+    path: String,
+    offset: usize,
+    len: usize,
+    pos: u64,
+}
+
+/// Read-only filesystem over a tar archive
+pub struct TarFs {
+    data: Vec<u8>,
+    nodes: BTreeMap<String, Inode>,
+    handles: slab::Slab<OpenFile>,
+}
+
+impl TarFs {
+    /// Parse `data` as a tar archive and serve its entries
+    ///
+    /// Later entries replace earlier ones with the same path, as they do
+    /// when the archive is extracted.
+    pub fn new(data: Vec<u8>) -> io::Result<Self> {
+        let entries = read_entries(&data)?;
+        let mut nodes = BTreeMap::new();
+        nodes.insert(
+            "/".to_string(),
+            Inode {
+                node: Node::Dir,
+                mode: 0o755,
+                uid: 0,
+                gid: 0,
+                mtime: 0.0,
+            },
+        );
+
+        for entry in entries {
+            let node = match entry.kind {
+                TarKind::File { offset, len } => Node::File { offset, len },
+                TarKind::Dir => Node::Dir,
+                TarKind::Symlink(target) => Node::Symlink(target),
+                TarKind::HardLink(target) => match nodes.get(&target) {
+                    Some(Inode {
+                        node: node @ Node::File { .. },
+                        ..
+                    }) => node.clone(),
+                    _ => {
+                        return Err(invalid(format!(
+                            "{}: hard link to missing file {}",
+                            entry.path, target
+                        )));
+                    }
+                },
+            };
+
+            let mut dir = parent(&entry.path);
+            while let Some(path) = dir {
+                match nodes.get(path) {
+                    Some(Inode {
+                        node: Node::Dir, ..
+                    }) => break,
+                    Some(_) => {
+                        return Err(invalid(format!(
+                            "{}: parent is not a directory",
+                            entry.path
+                        )));
+                    }
+                    None => {
+                        nodes.insert(
+                            path.to_string(),
+                            Inode {
+                                node: Node::Dir,
+                                mode: 0o755,
+                                uid: entry.uid,
+                                gid: entry.gid,
+                                mtime: entry.mtime as f64,
+                            },
+                        );
+                    }
+                }
+                dir = parent(path);
+            }
+
+            nodes.insert(
+                entry.path,
+                Inode {
+                    node,
+                    mode: entry.mode,
+                    uid: entry.uid,
+                    gid: entry.gid,
+                    mtime: entry.mtime as f64,
+                },
+            );
+        }
+
+        Ok(Self {
+            data,
+            nodes,
+            handles: slab::Slab::new(),
+        })
+    }
+
+    /// Size of the archive in bytes
+    pub fn archive_len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Follow symbolic links in every component of `path`
+    ///
+    /// Absolute targets are taken from the root of the archive.
+    fn resolve(&self, path: &str) -> io::Result<String> {
+        let mut pending: Vec<String> = normalize(path)
+            .split('/')
+            .filter(|p| !p.is_empty())
+            .rev()
+            .map(str::to_string)
+            .collect();
+        let mut resolved = String::from("/");
+        let mut links = 0;
+        while let Some(part) = pending.pop() {
+            let next = match part.as_str() {
+                "." => continue,
+                ".." => parent(&resolved).unwrap_or("/").to_string(),
+                name if resolved == "/" => format!("/{}", name),
+                name => format!("{}/{}", resolved, name),
+            };
+            match self.nodes.get(&next).map(|inode| &inode.node) {
+                Some(Node::Symlink(target)) => {
+                    links += 1;
+                    if links > MAX_SYMLINK_DEPTH {
+                        return Err(io::Error::other(
+                            "too many levels of symbolic links (possible loop)",
+                        ));
+                    }
+                    if target.starts_with('/') {
+                        resolved = "/".to_string();
+                    }
+                    pending.extend(
+                        target
+                            .split('/')
+                            .filter(|p| !p.is_empty())
+                            .rev()
+                            .map(str::to_string),
+                    );
+                }
+                Some(_) => resolved = next,
+                None => return Err(not_found()),
+            }
+        }
+        Ok(resolved)
+    }
+
+    fn inode(&self, path: &str) -> io::Result<&Inode> {
+        self.nodes.get(&normalize(path)).ok_or_else(not_found)
+    }
+}
+
+impl FileSystem for TarFs {
+    fn open(&mut self, path: &str, options: OpenOptions) -> io::Result<FileHandle> {
+        if options.write || options.create || options.truncate {
+            return Err(read_only());
+        }
+        let path = self.resolve(path)?;
+        match self.nodes.get(&path).map(|inode| &inode.node) {
+            Some(&Node::File { offset, len }) => Ok(self.handles.insert(OpenFile {
+                path,
+                offset,
+                len,
+                pos: 0,
+            })),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Is a directory",
+            )),
+        }
+    }
+
+    fn close(&mut self, handle: FileHandle) -> io::Result<()> {
+        self.handles
+            .try_remove(handle)
+            .map(|_| ())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid handle"))
+    }
+
+    fn read(&mut self, handle: FileHandle, buf: &mut [u8]) -> io::Result<usize> {
+        let file = self
+            .handles
+            .get_mut(handle)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid handle"))?;
+        let pos = usize::try_from(file.pos)
+            .unwrap_or(usize::MAX)
+            .min(file.len);
+        let n = buf.len().min(file.len - pos);
+        let start = file.offset + pos;
+        buf[..n].copy_from_slice(&self.data[start..start + n]);
+        file.pos += n as u64;
+        Ok(n)
+    }
+
+    fn write(&mut self, _handle: FileHandle, _buf: &[u8]) -> io::Result<usize> {
+        Err(read_only())
+    }
+
+    fn seek(&mut self, handle: FileHandle, pos: SeekFrom) -> io::Result<u64> {
+        let file = self
+            .handles
+            .get_mut(handle)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid handle"))?;
+        let new_pos = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::End(n) => (file.len as u64).checked_add_signed(n),
+            SeekFrom::Current(n) => file.pos.checked_add_signed(n),
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid seek"))?;
+        file.pos = new_pos;
+        Ok(new_pos)
+    }
+
+    fn metadata(&self, path: &str) -> io::Result<Metadata> {
+        let inode = self.inode(path)?;
+        let (size, symlink_target) = match &inode.node {
+            Node::File { len, .. } => (*len as u64, None),
+            Node::Dir => (0, None),
+            Node::Symlink(target) => (target.len() as u64, Some(target.clone())),
+        };
+        Ok(Metadata {
+            size,
+            allocated: size,
+            is_dir: matches!(inode.node, Node::Dir),
+            is_file: matches!(inode.node, Node::File { .. }),
+            is_symlink: symlink_target.is_some(),
+            symlink_target,
+            uid: inode.uid,
+            gid: inode.gid,
+            mode: inode.mode,
+            atime: inode.mtime,
+            mtime: inode.mtime,
+            ctime: inode.mtime,
+            nlink: 1,
+            acl: None,
+        })
+    }
+
+    fn create_dir(&mut self, _path: &str) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn read_dir(&self, path: &str) -> io::Result<Vec<DirEntry>> {
+        let dir = self.resolve(path)?;
+        if !matches!(self.inode(&dir)?.node, Node::Dir) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Not a directory",
+            ));
+        }
+        let prefix = if dir == "/" { dir } else { format!("{}/", dir) };
+        Ok(self
+            .nodes
+            .range(prefix.clone()..)
+            .take_while(|(p, _)| p.starts_with(&prefix))
+            .filter_map(|(p, inode)| {
+                let name = &p[prefix.len()..];
+                (!name.is_empty() && !name.contains('/')).then(|| DirEntry {
+                    name: name.to_string(),
+                    is_dir: matches!(inode.node, Node::Dir),
+                    is_symlink: matches!(inode.node, Node::Symlink(_)),
+                })
+            })
+            .collect())
+    }
+
+    fn remove_file(&mut self, _path: &str) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn remove_dir(&mut self, _path: &str) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn rename(&mut self, _from: &str, _to: &str) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn copy_file(&mut self, _from: &str, _to: &str) -> io::Result<u64> {
+        Err(read_only())
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.nodes.contains_key(&normalize(path))
+    }
+
+    fn symlink(&mut self, _target: &str, _link_path: &str) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn read_link(&self, path: &str) -> io::Result<String> {
+        match &self.inode(path)?.node {
+            Node::Symlink(target) => Ok(target.clone()),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Not a symbolic link",
+            )),
+        }
+    }
+
+    fn link(&mut self, _source: &str, _dest: &str) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn chmod(&mut self, _path: &str, _mode: u16) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn chown(&mut self, _path: &str, _uid: Option<u32>, _gid: Option<u32>) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn fstat(&self, handle: FileHandle) -> io::Result<Metadata> {
+        let file = self
+            .handles
+            .get(handle)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid handle"))?;
+        self.metadata(&file.path)
+    }
+
+    fn handle_path(&self, handle: FileHandle) -> io::Result<String> {
+        self.handles
+            .get(handle)
+            .map(|file| file.path.clone())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid handle"))
+    }
+
+    fn set_clock(&mut self, _now: f64) {}
+
+    fn utimes(&mut self, _path: &str, _atime: Option<f64>, _mtime: Option<f64>) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn setxattr(&mut self, _path: &str, _name: &str, _value: &[u8]) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn getxattr(&self, path: &str, _name: &str) -> io::Result<Vec<u8>> {
+        self.inode(path)?;
+        Err(super::no_such_attribute())
+    }
+
+    fn listxattr(&self, path: &str) -> io::Result<Vec<String>> {
+        self.inode(path)?;
+        Ok(Vec::new())
+    }
+
+    fn removexattr(&mut self, _path: &str, _name: &str) -> io::Result<()> {
+        Err(read_only())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::read_to_string;
+
+    /// A ustar header for `name`, with a correct checksum
+    fn header(name: &str, typeflag: u8, size: usize, link: &str) -> Vec<u8> {
+        let mut h = vec![0u8; BLOCK];
+        h[..name.len()].copy_from_slice(name.as_bytes());
+        h[100..107].copy_from_slice(b"0000644");
+        h[108..115].copy_from_slice(b"0001750");
+        h[116..123].copy_from_slice(b"0001750");
+        h[124..135].copy_from_slice(format!("{:011o}", size).as_bytes());
+        h[136..147].copy_from_slice(b"14540000000");
+        h[156] = typeflag;
+        h[157..157 + link.len()].copy_from_slice(link.as_bytes());
+        h[257..263].copy_from_slice(b"ustar\0");
+        h[263..265].copy_from_slice(b"00");
+        h[148..156].copy_from_slice(b"        ");
+        let sum: u32 = h.iter().map(|&b| u32::from(b)).sum();
+        h[148..155].copy_from_slice(format!("{:06o}\0", sum).as_bytes());
+        h
+    }
+
+    fn entry(archive: &mut Vec<u8>, name: &str, typeflag: u8, body: &[u8], link: &str) {
+        archive.extend(header(name, typeflag, body.len(), link));
+        archive.extend_from_slice(body);
+        archive.resize(archive.len().div_ceil(BLOCK) * BLOCK, 0);
+    }
+
+    fn sample() -> Vec<u8> {
+        let mut tar = Vec::new();
+        entry(&mut tar, "./pkg/", b'5', b"", "");
+        entry(&mut tar, "./pkg/README", b'0', b"hello from tar\n", "");
+        entry(&mut tar, "pkg/bin/tool", b'0', &[7u8; 700], "");
+        entry(&mut tar, "pkg/latest", b'2', b"", "bin/tool");
+        entry(&mut tar, "pkg/copy", b'1', b"", "pkg/README");
+        let long = format!("data/{}/deep.txt", "d".repeat(120));
+        entry(&mut tar, "././@LongLink", b'L', long.as_bytes(), "");
+        entry(&mut tar, "truncated-name", b'0', b"long", "");
+        entry(&mut tar, "../../escape", b'0', b"x", "");
+        tar.extend(vec![0u8; BLOCK * 2]);
+        tar
+    }
+
+    #[test]
+    fn test_read_entries() {
+        let entries = read_entries(&sample()).unwrap();
+        let paths: Vec<&str> = entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(
+            paths[..5],
+            [
+                "/pkg",
+                "/pkg/README",
+                "/pkg/bin/tool",
+                "/pkg/latest",
+                "/pkg/copy"
+            ]
+        );
+        assert!(paths[5].ends_with("/deep.txt") && paths[5].len() > 100);
+        // `..` can't leave the archive
+        assert_eq!(paths[6], "/escape");
+
+        let readme = &entries[1];
+        assert_eq!((readme.mode, readme.uid, readme.gid), (0o644, 1000, 1000));
+        assert_eq!(readme.mtime, 0o14540000000 * 1000);
+        assert_eq!(entries[3].kind, TarKind::Symlink("bin/tool".to_string()));
+        assert_eq!(
+            entries[4].kind,
+            TarKind::HardLink("/pkg/README".to_string())
+        );
+
+        let mut damaged = sample();
+        damaged[10] ^= 1;
+        assert!(read_entries(&damaged).is_err());
+        let mut short = sample();
+        short.truncate(BLOCK * 4 + 100);
+        assert!(read_entries(&short).is_err());
+    }
+
+    #[test]
+    fn test_pax_and_base256() {
+        let mut tar = Vec::new();
+        let record = "31 path=pax/very/long/name.txt\n";
+        entry(&mut tar, "PaxHeader", b'x', record.as_bytes(), "");
+        entry(&mut tar, "short", b'0', b"pax", "");
+        let mut big = header("big", b'0', 0, "");
+        // Base-256 size of 3
+        big[124..136].copy_from_slice(&[0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 3]);
+        big[148..156].copy_from_slice(b"        ");
+        let sum: u32 = big.iter().map(|&b| u32::from(b)).sum();
+        big[148..155].copy_from_slice(format!("{:06o}\0", sum).as_bytes());
+        tar.extend(big);
+        tar.extend_from_slice(b"abc");
+        tar.resize(tar.len().div_ceil(BLOCK) * BLOCK, 0);
+
+        let entries = read_entries(&tar).unwrap();
+        assert_eq!(entries[0].path, "/pax/very/long/name.txt");
+        assert_eq!(entries[1].path, "/big");
+        assert!(matches!(entries[1].kind, TarKind::File { len: 3, .. }));
+    }
+
+    #[test]
+    fn test_tarfs() {
+        let mut fs = TarFs::new(sample()).unwrap();
+        assert_eq!(
+            read_to_string(&mut fs, "/pkg/README").unwrap(),
+            "hello from tar\n"
+        );
+        // Hard links share the data; symlinks are followed on open
+        assert_eq!(
+            read_to_string(&mut fs, "/pkg/copy").unwrap(),
+            "hello from tar\n"
+        );
+        let meta = fs.metadata("/pkg/latest").unwrap();
+        assert!(meta.is_symlink);
+        assert_eq!(fs.read_link("/pkg/latest").unwrap(), "bin/tool");
+        let handle = fs.open("/pkg/latest", OpenOptions::new()).unwrap();
+        assert_eq!(fs.fstat(handle).unwrap().size, 700);
+        fs.seek(handle, SeekFrom::Start(690)).unwrap();
+        let mut buf = [0u8; 32];
+        assert_eq!(fs.read(handle, &mut buf).unwrap(), 10);
+        assert_eq!(fs.read(handle, &mut buf).unwrap(), 0);
+        fs.close(handle).unwrap();
+
+        // Parents that aren't in the archive are filled in
+        assert!(fs.metadata("/data").unwrap().is_dir);
+        let mut names: Vec<String> = fs
+            .read_dir("/pkg")
+            .unwrap()
+            .into_iter()
+            .map(|e| e.name)
+            .collect();
+        names.sort();
+        assert_eq!(names, ["README", "bin", "copy", "latest"]);
+        let root: Vec<String> = fs
+            .read_dir("/")
+            .unwrap()
+            .into_iter()
+            .map(|e| e.name)
+            .collect();
+        assert_eq!(root, ["data", "escape", "pkg"]);
+
+        let denied = |r: io::Result<()>| r.unwrap_err().kind() == io::ErrorKind::ReadOnlyFilesystem;
+        assert!(denied(fs.create_dir("/new")));
+        assert!(denied(fs.remove_file("/pkg/README")));
+        assert!(denied(fs.chmod("/pkg/README", 0o600)));
+        assert!(
+            fs.open("/pkg/README", OpenOptions::new().write(true))
+                .is_err()
+        );
+        assert!(fs.open("/pkg", OpenOptions::new()).is_err());
+        assert!(fs.open("/missing", OpenOptions::new()).is_err());
+    }
+}
@@ -0,0 +1,146 @@
+//! Write-ahead journal for snapshot saves
+//!
+//! A save is several writes: the backups rotate, then the snapshot is
+//! replaced in place. A tab that dies in the middle can leave the snapshot
+//! torn, or lose the new state after the old one was moved away. With
+//! journaling on, a save first writes the whole sealed snapshot to a
+//! journal entry next to it, then rotates the backups and writes the
+//! snapshot, and only then removes the journal:
+//!
+//! ```text
+//! #axeberg-journal v1 axeberg_fs.json
+//! {"nodes":...}
+//! #axeberg-sha256:9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08
+//! ```
+//!
+//! A journal still there at boot means a save was interrupted. If the
+//! entry is complete (its header is intact and the checksum footer
+//! matches) it holds the newest state, so it is replayed: written as the
+//! snapshot and loaded. If not, the tab died while writing the journal
+//! itself, before the snapshot was touched, so the entry is discarded and
+//! the snapshot loads as usual.
+
+use super::integrity::{self, IntegrityError};
+use std::fmt;
+
+/// First line of every journal entry, followed by the snapshot's name
+const HEADER: &str = "#axeberg-journal v1 ";
+
+/// Name of the journal entry for the snapshot `name`
+pub fn journal_name(name: &str) -> String {
+    format!("{}.journal", name)
+}
+
+/// Why a journal entry can't be replayed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JournalError {
+    /// The header is missing or damaged
+    BadHeader,
+    /// The entry ends before its checksum footer: the write was cut short
+    Incomplete,
+    /// The footer is there but doesn't match
+    Corrupt(IntegrityError),
+}
+
+impl fmt::Display for JournalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JournalError::BadHeader => write!(f, "bad journal header"),
+            JournalError::Incomplete => write!(f, "incomplete journal entry"),
+            JournalError::Corrupt(e) => write!(f, "corrupt journal entry: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for JournalError {}
+
+/// A complete journal entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry<'a> {
+    /// Name of the snapshot the entry is for
+    pub target: &'a str,
+    /// The sealed snapshot, ready to be written under `target`
+    pub sealed: &'a [u8],
+}
+
+/// Journal entry that will write `sealed` (from [`integrity::seal`]) as
+/// the snapshot `target`
+pub fn record(target: &str, sealed: &[u8]) -> Vec<u8> {
+    let mut entry = Vec::with_capacity(HEADER.len() + target.len() + 1 + sealed.len());
+    entry.extend_from_slice(HEADER.as_bytes());
+    entry.extend_from_slice(target.as_bytes());
+    entry.push(b'\n');
+    entry.extend_from_slice(sealed);
+    entry
+}
+
+/// Check a journal entry and return what it would write
+///
+/// Unlike a snapshot, an entry must end in a checksum footer: one without
+/// is a write that was cut short.
+pub fn replay(entry: &[u8]) -> Result<Entry<'_>, JournalError> {
+    let rest = entry
+        .strip_prefix(HEADER.as_bytes())
+        .ok_or(JournalError::BadHeader)?;
+    let newline = rest
+        .iter()
+        .position(|&b| b == b'\n')
+        .ok_or(JournalError::Incomplete)?;
+    let target = std::str::from_utf8(&rest[..newline]).map_err(|_| JournalError::BadHeader)?;
+    if target.is_empty() {
+        return Err(JournalError::BadHeader);
+    }
+    let sealed = &rest[newline + 1..];
+    match integrity::unseal(sealed) {
+        Ok(data) if data.len() < sealed.len() => Ok(Entry { target, sealed }),
+        Ok(_) | Err(IntegrityError::Empty) => Err(JournalError::Incomplete),
+        Err(e) => Err(JournalError::Corrupt(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_replay() {
+        let sealed = integrity::seal(b"{\"nodes\":{}}");
+        let entry = record("fs.json", &sealed);
+        assert!(entry.starts_with(b"#axeberg-journal v1 fs.json\n"));
+        assert_eq!(
+            replay(&entry),
+            Ok(Entry {
+                target: "fs.json",
+                sealed: &sealed
+            })
+        );
+        assert_eq!(journal_name("fs.json"), "fs.json.journal");
+    }
+
+    #[test]
+    fn test_torn_entries_are_not_replayed() {
+        let sealed = integrity::seal(b"{\"nodes\":{}}");
+        let entry = record("fs.json", &sealed);
+
+        // Cut short anywhere, an entry is never replayed
+        for len in 0..entry.len() {
+            assert!(replay(&entry[..len]).is_err(), "replayed {} bytes", len);
+        }
+        assert_eq!(replay(&entry[..10]), Err(JournalError::BadHeader));
+        assert_eq!(replay(&entry[..30]), Err(JournalError::Incomplete));
+        assert_eq!(
+            replay(&entry[..entry.len() - 20]),
+            Err(JournalError::Incomplete)
+        );
+
+        let mut flipped = entry.clone();
+        flipped[30] ^= 1;
+        assert!(matches!(
+            replay(&flipped),
+            Err(JournalError::Corrupt(
+                IntegrityError::ChecksumMismatch { .. }
+            ))
+        ));
+        assert_eq!(replay(&record("", &sealed)), Err(JournalError::BadHeader));
+    }
+}
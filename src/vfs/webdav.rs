@@ -0,0 +1,815 @@
+//! Remote WebDAV shares as a filesystem
+//!
+//! [`WebDavFs`] mounts a directory served over WebDAV (RFC 4918), so
+//! `mount -t webdav https://dav.example.com/share /mnt/remote` makes the
+//! share browsable with `ls`, `cat` and the rest. In the browser the
+//! requests go through the Fetch API, so the server has to allow the page's
+//! origin with CORS, including the `PROPFIND`, `MKCOL`, `MOVE` and `COPY`
+//! methods and the `Depth`, `Destination`, `Overwrite` and `Authorization`
+//! headers.
+//!
+//! [`FileSystem`] calls can't wait on the network, so the share is read
+//! whole when it is mounted ([`connect`], walking it one `PROPFIND` of
+//! depth 1 at a time and `GET`ting each file) and served from a
+//! [`MemoryFs`] copy. Changes are made to the copy at once and queued as
+//! requests that are sent in order in the background: a file opened for
+//! writing goes up with a `PUT` when it is closed, new directories with
+//! `MKCOL`, and removals, renames and copies as `DELETE`, `MOVE` and
+//! `COPY`. A request the server refuses is logged to `dmesg`; the copy keeps
+//! the change. Changes made on the server after mounting don't show up until
+//! the share is mounted again.
+//!
+//! WebDAV has no modes, owners or links: the tree belongs to whoever
+//! mounted it, `chmod`, `chown` and times only change the local copy, and
+//! symbolic links and extended attributes can't be made.
+
+use super::{DirEntry, FileHandle, FileSystem, MemoryFs, Metadata, OpenOptions};
+use std::cell::RefCell;
+use std::collections::{HashSet, VecDeque};
+use std::io::{self, SeekFrom};
+use std::rc::Rc;
+
+/// The `PROPFIND` body: just the properties a listing needs
+pub const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<D:propfind xmlns:D="DAV:"><D:prop><D:resourcetype/><D:getcontentlength/><D:getlastmodified/></D:prop></D:propfind>"#;
+
+fn unsupported(what: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("{} not supported over WebDAV", what),
+    )
+}
+
+/// A file or directory on the share, from a `PROPFIND` response
+#[derive(Debug, Clone, PartialEq)]
+pub struct DavEntry {
+    /// Path within the share, like `/docs/a.txt`
+    pub path: String,
+    pub is_dir: bool,
+    pub size: u64,
+    /// Modification time in milliseconds since epoch, if the server said
+    pub mtime: Option<f64>,
+}
+
+/// A change to make on the server
+#[derive(Debug, Clone, PartialEq)]
+pub enum DavRequest {
+    Put { path: String, data: Vec<u8> },
+    Mkcol { path: String },
+    Delete { path: String },
+    Move { from: String, to: String },
+    Copy { from: String, to: String },
+}
+
+impl DavRequest {
+    /// The HTTP method
+    pub fn method(&self) -> &'static str {
+        match self {
+            DavRequest::Put { .. } => "PUT",
+            DavRequest::Mkcol { .. } => "MKCOL",
+            DavRequest::Delete { .. } => "DELETE",
+            DavRequest::Move { .. } => "MOVE",
+            DavRequest::Copy { .. } => "COPY",
+        }
+    }
+
+    /// The path the request is made on
+    pub fn path(&self) -> &str {
+        match self {
+            DavRequest::Put { path, .. }
+            | DavRequest::Mkcol { path }
+            | DavRequest::Delete { path }
+            | DavRequest::Move { from: path, .. }
+            | DavRequest::Copy { from: path, .. } => path,
+        }
+    }
+}
+
+/// Where the share is and how to get in
+#[derive(Debug, Clone, PartialEq)]
+pub struct Remote {
+    /// Scheme, host and port, as in `https://dav.example.com`
+    origin: String,
+    /// Path of the share on the server, without a trailing slash
+    root: String,
+    /// Value of the `Authorization` header
+    auth: Option<String>,
+}
+
+impl Remote {
+    /// Parse an `http://` or `https://` share URL
+    pub fn new(url: &str, auth: Option<String>) -> Result<Self, String> {
+        let rest = url
+            .strip_prefix("https://")
+            .or_else(|| url.strip_prefix("http://"))
+            .ok_or_else(|| format!("{}: not an http(s) URL", url))?;
+        let host_len = rest.find('/').unwrap_or(rest.len());
+        if host_len == 0 {
+            return Err(format!("{}: no host", url));
+        }
+        let scheme_len = url.len() - rest.len();
+        let root = percent_decode(rest[host_len..].trim_end_matches('/'));
+        Ok(Self {
+            origin: url[..scheme_len + host_len].to_string(),
+            root,
+            auth,
+        })
+    }
+
+    /// Log in with HTTP basic authentication
+    pub fn with_login(mut self, user: &str, password: &str) -> Self {
+        let login = format!("{}:{}", user, password);
+        self.auth = Some(format!("Basic {}", base64(login.as_bytes())));
+        self
+    }
+
+    /// The URL of `path` within the share; directories end in `/`
+    pub fn url(&self, path: &str, is_dir: bool) -> String {
+        let mut full = format!("{}{}", self.root, path);
+        if is_dir && !full.ends_with('/') {
+            full.push('/');
+        }
+        format!("{}{}", self.origin, percent_encode(&full))
+    }
+
+    /// The path within the share that an `href` from the server names,
+    /// unless it is outside the share
+    fn share_path(&self, href: &str) -> Option<String> {
+        let href = href.strip_prefix(&self.origin).unwrap_or(href);
+        let path = percent_decode(href);
+        let rest = path.strip_prefix(&self.root)?;
+        if !rest.is_empty() && !rest.starts_with('/') {
+            return None;
+        }
+        Some(normalize(rest))
+    }
+}
+
+/// Parse a `207 Multi-Status` response to a `PROPFIND`
+///
+/// Namespace prefixes are ignored, and only properties the server found
+/// (a `200` propstat) are used. Entries outside the share are left out.
+pub fn parse_multistatus(xml: &str, remote: &Remote) -> Vec<DavEntry> {
+    let mut entries = Vec::new();
+    for response in elements(xml, "response") {
+        let Some(href) = elements(response, "href").first().copied() else {
+            continue;
+        };
+        let Some(path) = remote.share_path(unescape(href.trim()).as_str()) else {
+            continue;
+        };
+        let mut found: Vec<&str> = elements(response, "propstat")
+            .into_iter()
+            .filter(|propstat| {
+                elements(propstat, "status")
+                    .first()
+                    .is_none_or(|status| status.contains(" 200 "))
+            })
+            .collect();
+        if found.is_empty() {
+            found.push(response);
+        }
+        let prop = |name: &str| {
+            found
+                .iter()
+                .find_map(|propstat| elements(propstat, name).first().copied())
+        };
+        let is_dir = prop("resourcetype")
+            .is_some_and(|resourcetype| !elements(resourcetype, "collection").is_empty());
+        entries.push(DavEntry {
+            path,
+            is_dir,
+            size: prop("getcontentlength")
+                .and_then(|len| len.trim().parse().ok())
+                .unwrap_or(0),
+            mtime: prop("getlastmodified").and_then(|date| parse_http_date(date.trim())),
+        });
+    }
+    entries
+}
+
+/// The contents of every element called `name` in `xml`, whatever its
+/// namespace prefix; empty for `<name/>`
+fn elements<'a>(xml: &'a str, name: &str) -> Vec<&'a str> {
+    let mut found = Vec::new();
+    let mut at = 0;
+    while let Some(open) = xml[at..].find('<').map(|i| at + i) {
+        let tag_end = match xml[open..].find('>') {
+            Some(i) => open + i,
+            None => break,
+        };
+        let tag = &xml[open + 1..tag_end];
+        at = tag_end + 1;
+        let qualified = tag.split([' ', '\t', '\r', '\n', '/']).next().unwrap_or("");
+        let local = qualified.rsplit(':').next().unwrap_or(qualified);
+        if tag.starts_with(['/', '?', '!']) || local != name {
+            continue;
+        }
+        if tag.ends_with('/') {
+            found.push("");
+            continue;
+        }
+        let close = format!("</{}>", qualified);
+        if let Some(end) = xml[at..].find(&close) {
+            found.push(&xml[at..at + end]);
+            at += end + close.len();
+        }
+    }
+    found
+}
+
+/// Replace XML's predefined entities
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Parse an HTTP date (`Sun, 06 Nov 1994 08:49:37 GMT`) into milliseconds
+/// since epoch
+pub fn parse_http_date(date: &str) -> Option<f64> {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let fields: Vec<&str> = date.split_whitespace().collect();
+    let [_, day, month, year, time, "GMT"] = fields.as_slice() else {
+        return None;
+    };
+    let month = MONTHS.iter().position(|m| m == month)? as i64 + 1;
+    let mut clock = time.split(':').map(|n| n.parse::<i64>().ok());
+    let (hours, minutes, seconds) = (clock.next()??, clock.next()??, clock.next()??);
+    let days =
+        crate::kernel::calendar::days_from_civil(year.parse().ok()?, month, day.parse().ok()?);
+    let seconds = days * 86_400 + hours * 3600 + minutes * 60 + seconds;
+    Some(seconds as f64 * 1000.0)
+}
+
+/// Percent-encode a path for a URL, leaving `/` and unreserved characters
+fn percent_encode(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    for b in path.bytes() {
+        if b.is_ascii_alphanumeric() || b"/-._~".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+/// Decode `%XX` escapes; malformed ones are kept as they are
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                out.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Standard, padded base64, for basic authentication
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Normalize a path (leading slash, `.` and `..` resolved)
+fn normalize(path: &str) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            name => parts.push(name),
+        }
+    }
+    format!("/{}", parts.join("/"))
+}
+
+/// Requests waiting to go to the server
+#[derive(Default)]
+struct Outbox {
+    requests: VecDeque<DavRequest>,
+    /// Whether a task is sending them
+    #[cfg(target_arch = "wasm32")]
+    sending: bool,
+}
+
+/// A WebDAV share, served from a copy in memory (see the module docs)
+pub struct WebDavFs {
+    cache: MemoryFs,
+    remote: Remote,
+    /// Files opened for writing, which go up when they are closed
+    written: HashSet<FileHandle>,
+    outbox: Rc<RefCell<Outbox>>,
+}
+
+impl WebDavFs {
+    /// An empty copy of the share at `remote`; [`connect`] fills it in
+    pub fn new(remote: Remote) -> Self {
+        let mut cache = MemoryFs::new();
+        let _ = cache.chmod("/", 0o755);
+        Self {
+            cache,
+            remote,
+            written: HashSet::new(),
+            outbox: Rc::default(),
+        }
+    }
+
+    /// Where the share is
+    pub fn remote(&self) -> &Remote {
+        &self.remote
+    }
+
+    /// Add `entry`, with contents `data` if it is a file, to the copy
+    /// without sending anything; its parent must be there already
+    pub fn load(&mut self, entry: &DavEntry, data: &[u8]) -> io::Result<()> {
+        if entry.path == "/" {
+            return Ok(());
+        }
+        if entry.is_dir {
+            self.cache.create_dir(&entry.path)?;
+            self.cache.chmod(&entry.path, 0o755)?;
+        } else {
+            let handle = self.cache.open(
+                &entry.path,
+                OpenOptions::new().write(true).create(true).truncate(true),
+            )?;
+            let written = self.cache.write(handle, data);
+            self.cache.close(handle)?;
+            written?;
+            self.cache.chmod(&entry.path, 0o644)?;
+        }
+        if let Some(mtime) = entry.mtime {
+            self.cache.utimes(&entry.path, Some(mtime), Some(mtime))?;
+        }
+        Ok(())
+    }
+
+    /// Give the whole copy to `uid`:`gid`, the user who mounted it
+    pub fn set_owner(&mut self, uid: u32, gid: u32) -> io::Result<()> {
+        let mut dirs = vec!["/".to_string()];
+        self.cache.chown("/", Some(uid), Some(gid))?;
+        while let Some(dir) = dirs.pop() {
+            for entry in self.cache.read_dir(&dir)? {
+                let path = normalize(&format!("{}/{}", dir, entry.name));
+                self.cache.chown(&path, Some(uid), Some(gid))?;
+                if entry.is_dir {
+                    dirs.push(path);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Requests not yet sent, oldest first
+    pub fn pending(&self) -> Vec<DavRequest> {
+        self.outbox.borrow().requests.iter().cloned().collect()
+    }
+
+    /// Queue `request`, and in the browser make sure something is sending
+    fn send(&self, request: DavRequest) {
+        self.outbox.borrow_mut().requests.push_back(request);
+        #[cfg(target_arch = "wasm32")]
+        self.flush();
+    }
+
+    /// Send queued requests in order on a background task, unless one is
+    /// already at it
+    #[cfg(target_arch = "wasm32")]
+    fn flush(&self) {
+        if std::mem::replace(&mut self.outbox.borrow_mut().sending, true) {
+            return;
+        }
+        let outbox = Rc::clone(&self.outbox);
+        let remote = self.remote.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            loop {
+                let next = outbox.borrow_mut().requests.pop_front();
+                let Some(request) = next else {
+                    break;
+                };
+                if let Err(e) = http::send(&remote, &request).await {
+                    crate::kernel::syscall::klog(crate::kernel::klog::LogLevel::Warn, "webdav", &e);
+                }
+            }
+            outbox.borrow_mut().sending = false;
+        });
+    }
+
+    /// The whole of the file at `path` in the copy
+    fn contents(&mut self, path: &str) -> io::Result<Vec<u8>> {
+        let size = self.cache.metadata(path)?.size as usize;
+        let handle = self.cache.open(path, OpenOptions::new().read(true))?;
+        let mut data = vec![0; size];
+        let read = self.cache.read(handle, &mut data);
+        self.cache.close(handle)?;
+        data.truncate(read?);
+        Ok(data)
+    }
+}
+
+impl FileSystem for WebDavFs {
+    fn open(&mut self, path: &str, options: OpenOptions) -> io::Result<FileHandle> {
+        let handle = self.cache.open(path, options)?;
+        if options.write || options.create || options.truncate {
+            self.written.insert(handle);
+        }
+        Ok(handle)
+    }
+
+    fn close(&mut self, handle: FileHandle) -> io::Result<()> {
+        let path = match self.written.remove(&handle) {
+            true => Some(self.cache.handle_path(handle)?),
+            false => None,
+        };
+        self.cache.close(handle)?;
+        if let Some(path) = path {
+            let data = self.contents(&path)?;
+            self.send(DavRequest::Put { path, data });
+        }
+        Ok(())
+    }
+
+    fn read(&mut self, handle: FileHandle, buf: &mut [u8]) -> io::Result<usize> {
+        self.cache.read(handle, buf)
+    }
+
+    fn write(&mut self, handle: FileHandle, buf: &[u8]) -> io::Result<usize> {
+        self.cache.write(handle, buf)
+    }
+
+    fn seek(&mut self, handle: FileHandle, pos: SeekFrom) -> io::Result<u64> {
+        self.cache.seek(handle, pos)
+    }
+
+    fn metadata(&self, path: &str) -> io::Result<Metadata> {
+        self.cache.metadata(path)
+    }
+
+    fn create_dir(&mut self, path: &str) -> io::Result<()> {
+        self.cache.create_dir(path)?;
+        self.send(DavRequest::Mkcol {
+            path: normalize(path),
+        });
+        Ok(())
+    }
+
+    fn read_dir(&self, path: &str) -> io::Result<Vec<DirEntry>> {
+        self.cache.read_dir(path)
+    }
+
+    fn remove_file(&mut self, path: &str) -> io::Result<()> {
+        self.cache.remove_file(path)?;
+        self.send(DavRequest::Delete {
+            path: normalize(path),
+        });
+        Ok(())
+    }
+
+    fn remove_dir(&mut self, path: &str) -> io::Result<()> {
+        self.cache.remove_dir(path)?;
+        self.send(DavRequest::Delete {
+            path: normalize(path),
+        });
+        Ok(())
+    }
+
+    fn rename(&mut self, from: &str, to: &str) -> io::Result<()> {
+        self.cache.rename(from, to)?;
+        self.send(DavRequest::Move {
+            from: normalize(from),
+            to: normalize(to),
+        });
+        Ok(())
+    }
+
+    fn copy_file(&mut self, from: &str, to: &str) -> io::Result<u64> {
+        let size = self.cache.copy_file(from, to)?;
+        self.send(DavRequest::Copy {
+            from: normalize(from),
+            to: normalize(to),
+        });
+        Ok(size)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.cache.exists(path)
+    }
+
+    fn symlink(&mut self, _target: &str, _link_path: &str) -> io::Result<()> {
+        Err(unsupported("symbolic links"))
+    }
+
+    fn read_link(&self, path: &str) -> io::Result<String> {
+        self.cache.read_link(path)
+    }
+
+    fn link(&mut self, _source: &str, _dest: &str) -> io::Result<()> {
+        Err(unsupported("hard links"))
+    }
+
+    fn chmod(&mut self, path: &str, mode: u16) -> io::Result<()> {
+        self.cache.chmod(path, mode)
+    }
+
+    fn chown(&mut self, path: &str, uid: Option<u32>, gid: Option<u32>) -> io::Result<()> {
+        self.cache.chown(path, uid, gid)
+    }
+
+    fn fstat(&self, handle: FileHandle) -> io::Result<Metadata> {
+        self.cache.fstat(handle)
+    }
+
+    fn handle_path(&self, handle: FileHandle) -> io::Result<String> {
+        self.cache.handle_path(handle)
+    }
+
+    fn set_clock(&mut self, now: f64) {
+        self.cache.set_clock(now);
+    }
+
+    fn utimes(&mut self, path: &str, atime: Option<f64>, mtime: Option<f64>) -> io::Result<()> {
+        self.cache.utimes(path, atime, mtime)
+    }
+
+    fn setxattr(&mut self, _path: &str, _name: &str, _value: &[u8]) -> io::Result<()> {
+        Err(unsupported("extended attributes"))
+    }
+
+    fn getxattr(&self, path: &str, name: &str) -> io::Result<Vec<u8>> {
+        self.cache.getxattr(path, name)
+    }
+
+    fn listxattr(&self, path: &str) -> io::Result<Vec<String>> {
+        self.cache.listxattr(path)
+    }
+
+    fn removexattr(&mut self, _path: &str, _name: &str) -> io::Result<()> {
+        Err(unsupported("extended attributes"))
+    }
+}
+
+/// Read the whole share at `remote` into a [`WebDavFs`]
+#[cfg(target_arch = "wasm32")]
+pub async fn connect(remote: Remote) -> Result<WebDavFs, String> {
+    let mut fs = WebDavFs::new(remote);
+    let mut dirs = vec!["/".to_string()];
+    while let Some(dir) = dirs.pop() {
+        let listing = http::propfind(&fs.remote, &dir).await?;
+        for entry in parse_multistatus(&listing, &fs.remote) {
+            if entry.path == dir {
+                continue;
+            }
+            let data = if entry.is_dir {
+                dirs.push(entry.path.clone());
+                Vec::new()
+            } else {
+                http::get(&fs.remote, &entry.path).await?
+            };
+            fs.load(&entry, &data)
+                .map_err(|e| format!("{}: {}", entry.path, e))?;
+        }
+    }
+    Ok(fs)
+}
+
+/// The requests themselves, through [`crate::kernel::network`]
+#[cfg(target_arch = "wasm32")]
+mod http {
+    use super::{DavRequest, PROPFIND_BODY, Remote};
+    use crate::kernel::network::{HttpMethod, HttpRequest, HttpResponse};
+
+    /// A request for `path`, logged in
+    fn request(remote: &Remote, method: HttpMethod, path: &str, is_dir: bool) -> HttpRequest {
+        let request = HttpRequest::new(method, &remote.url(path, is_dir));
+        match &remote.auth {
+            Some(auth) => request.header("Authorization", auth),
+            None => request,
+        }
+    }
+
+    /// Fail unless the response is a success
+    fn check(response: HttpResponse, method: &str, path: &str) -> Result<HttpResponse, String> {
+        if (200..300).contains(&response.status) {
+            Ok(response)
+        } else {
+            Err(format!(
+                "{} {}: HTTP {} {}",
+                method, path, response.status, response.status_text
+            ))
+        }
+    }
+
+    /// List the directory at `path` and its entries
+    pub async fn propfind(remote: &Remote, path: &str) -> Result<String, String> {
+        let response = request(remote, HttpMethod::Propfind, path, true)
+            .header("Depth", "1")
+            .header("Content-Type", "application/xml; charset=utf-8")
+            .body(PROPFIND_BODY.as_bytes().to_vec())
+            .send()
+            .await?;
+        let response = check(response, "PROPFIND", path)?;
+        Ok(String::from_utf8_lossy(&response.body).into_owned())
+    }
+
+    /// The contents of the file at `path`
+    pub async fn get(remote: &Remote, path: &str) -> Result<Vec<u8>, String> {
+        let response = request(remote, HttpMethod::Get, path, false).send().await?;
+        Ok(check(response, "GET", path)?.body)
+    }
+
+    /// Make `change` on the server
+    pub async fn send(remote: &Remote, change: &DavRequest) -> Result<(), String> {
+        let path = change.path();
+        let request = match change {
+            DavRequest::Put { data, .. } => {
+                request(remote, HttpMethod::Put, path, false).body(data.clone())
+            }
+            DavRequest::Mkcol { .. } => request(remote, HttpMethod::Mkcol, path, true),
+            DavRequest::Delete { .. } => request(remote, HttpMethod::Delete, path, false),
+            DavRequest::Move { to, .. } | DavRequest::Copy { to, .. } => {
+                let method = match change {
+                    DavRequest::Move { .. } => HttpMethod::Move,
+                    _ => HttpMethod::Copy,
+                };
+                request(remote, method, path, false)
+                    .header("Destination", &remote.url(to, false))
+                    .header("Overwrite", "T")
+            }
+        };
+        check(request.send().await?, change.method(), path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LISTING: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:multistatus xmlns:d="DAV:">
+  <d:response>
+    <d:href>/dav/share/</d:href>
+    <d:propstat>
+      <d:prop><d:resourcetype><d:collection/></d:resourcetype></d:prop>
+      <d:status>HTTP/1.1 200 OK</d:status>
+    </d:propstat>
+  </d:response>
+  <d:response>
+    <d:href>https://dav.test/dav/share/My%20Notes.txt</d:href>
+    <d:propstat>
+      <d:prop>
+        <d:resourcetype/>
+        <d:getcontentlength>12</d:getcontentlength>
+        <d:getlastmodified>Sun, 06 Nov 1994 08:49:37 GMT</d:getlastmodified>
+      </d:prop>
+      <d:status>HTTP/1.1 200 OK</d:status>
+    </d:propstat>
+    <d:propstat>
+      <d:prop><d:getcontentlength/></d:prop>
+      <d:status>HTTP/1.1 404 Not Found</d:status>
+    </d:propstat>
+  </d:response>
+  <D:response xmlns:D="DAV:">
+    <D:href>/dav/share/sub%26dir/</D:href>
+    <D:propstat><D:prop><D:resourcetype><D:collection /></D:resourcetype></D:prop></D:propstat>
+  </D:response>
+  <d:response><d:href>/elsewhere/x</d:href></d:response>
+</d:multistatus>"#;
+
+    fn remote() -> Remote {
+        Remote::new("https://dav.test/dav/share/", None).unwrap()
+    }
+
+    #[test]
+    fn test_remote() {
+        let remote = remote();
+        assert_eq!(remote.url("/", true), "https://dav.test/dav/share/");
+        assert_eq!(
+            remote.url("/a b/ü", false),
+            "https://dav.test/dav/share/a%20b/%C3%BC"
+        );
+        assert_eq!(remote.share_path("/dav/share"), Some("/".into()));
+        assert_eq!(remote.share_path("/dav/shared/x"), None);
+
+        let login = remote.with_login("Aladdin", "open sesame");
+        assert_eq!(
+            login.auth.as_deref(),
+            Some("Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ==")
+        );
+        assert!(Remote::new("ftp://dav.test/", None).is_err());
+        assert!(Remote::new("http:///share", None).is_err());
+    }
+
+    #[test]
+    fn test_parse_multistatus() {
+        let entries = parse_multistatus(LISTING, &remote());
+        assert_eq!(
+            entries,
+            [
+                DavEntry {
+                    path: "/".into(),
+                    is_dir: true,
+                    size: 0,
+                    mtime: None,
+                },
+                DavEntry {
+                    path: "/My Notes.txt".into(),
+                    is_dir: false,
+                    size: 12,
+                    mtime: Some(784_111_777_000.0),
+                },
+                DavEntry {
+                    path: "/sub&dir".into(),
+                    is_dir: true,
+                    size: 0,
+                    mtime: None,
+                },
+            ]
+        );
+        assert_eq!(parse_http_date("06 Nov 1994 08:49:37 GMT"), None);
+    }
+
+    #[test]
+    fn test_changes_are_queued() {
+        let mut fs = WebDavFs::new(remote());
+        for entry in parse_multistatus(LISTING, &remote()) {
+            fs.load(&entry, b"hello, world").unwrap();
+        }
+        fs.set_owner(1000, 1000).unwrap();
+        assert!(fs.pending().is_empty());
+        let meta = fs.metadata("/My Notes.txt").unwrap();
+        assert_eq!(
+            (meta.size, meta.uid, meta.mtime),
+            (12, 1000, 784_111_777_000.0)
+        );
+
+        let handle = fs
+            .open("/sub&dir/new", OpenOptions::new().write(true).create(true))
+            .unwrap();
+        fs.write(handle, b"data").unwrap();
+        fs.close(handle).unwrap();
+        fs.create_dir("/sub&dir/inner").unwrap();
+        fs.rename("/My Notes.txt", "/notes").unwrap();
+        fs.remove_dir("/sub&dir/inner").unwrap();
+        assert_eq!(
+            fs.pending(),
+            [
+                DavRequest::Put {
+                    path: "/sub&dir/new".into(),
+                    data: b"data".to_vec(),
+                },
+                DavRequest::Mkcol {
+                    path: "/sub&dir/inner".into(),
+                },
+                DavRequest::Move {
+                    from: "/My Notes.txt".into(),
+                    to: "/notes".into(),
+                },
+                DavRequest::Delete {
+                    path: "/sub&dir/inner".into(),
+                },
+            ]
+        );
+
+        // Reading sends nothing, and links can't be made
+        let handle = fs.open("/notes", OpenOptions::new().read(true)).unwrap();
+        fs.close(handle).unwrap();
+        assert_eq!(fs.pending().len(), 4);
+        assert!(fs.symlink("/notes", "/link").is_err());
+    }
+}
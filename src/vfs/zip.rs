@@ -0,0 +1,518 @@
+//! Zip archives
+//!
+//! [`read_entries`] lists an archive from its central directory and
+//! [`read_file`] pulls one member out, stored or deflated, checking its CRC.
+//! [`ZipWriter`] builds archives that keep Unix modes, symbolic links and
+//! modification times (in the extended timestamp field, with the DOS time
+//! as a fallback), deflating each file unless that doesn't make it smaller.
+//!
+//! Zip64 archives and encrypted members aren't supported. Member paths are
+//! normalized like tar's, so nothing can name a path above the root.
+
+use super::deflate;
+use crate::kernel::calendar::{civil_from_days, days_from_civil};
+use std::io;
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const END_OF_DIRECTORY: u32 = 0x0605_4b50;
+/// Extended timestamp extra field (Unix mtime)
+const EXTRA_TIMESTAMP: u16 = 0x5455;
+
+pub const METHOD_STORED: u16 = 0;
+pub const METHOD_DEFLATED: u16 = 8;
+
+/// "Version made by": Unix, spec 2.0
+const MADE_BY_UNIX: u16 = 3 << 8 | 20;
+const VERSION_NEEDED: u16 = 20;
+/// General purpose flag: names are UTF-8
+const FLAG_UTF8: u16 = 1 << 11;
+const FLAG_ENCRYPTED: u16 = 1;
+
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFLNK: u32 = 0o120000;
+const S_IFREG: u32 = 0o100000;
+
+/// What an archive member is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZipKind {
+    File,
+    Dir,
+    /// A symbolic link; its data is the target
+    Symlink,
+}
+
+/// A member listed in the central directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZipEntry {
+    /// Normalized absolute path
+    pub path: String,
+    pub kind: ZipKind,
+    pub mode: u16,
+    /// Modification time in milliseconds since epoch
+    pub mtime: u64,
+    pub method: u16,
+    pub encrypted: bool,
+    pub crc: u32,
+    pub compressed_size: usize,
+    pub size: usize,
+    header_offset: usize,
+}
+
+impl ZipEntry {
+    /// Modification time as `YYYY-MM-DD HH:MM` (UTC)
+    pub fn modified(&self) -> String {
+        let secs = (self.mtime / 1000) as i64;
+        let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+        let of_day = secs.rem_euclid(86_400);
+        format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}",
+            year,
+            month,
+            day,
+            of_day / 3600,
+            of_day / 60 % 60
+        )
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("zip: {}", msg))
+}
+
+fn unsupported(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, format!("zip: {}", msg))
+}
+
+fn u16_at(data: &[u8], at: usize) -> io::Result<u16> {
+    data.get(at..at + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or_else(|| invalid("truncated archive"))
+}
+
+fn u32_at(data: &[u8], at: usize) -> io::Result<u32> {
+    data.get(at..at + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| invalid("truncated archive"))
+}
+
+/// Normalize a member path (leading slash, `.` and `..` resolved, never
+/// above the root)
+fn normalize(path: &str) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split(['/', '\\']) {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            name => parts.push(name),
+        }
+    }
+    format!("/{}", parts.join("/"))
+}
+
+/// DOS time and date fields, which hold 1980 to 2107 in two second steps
+fn to_dos(mtime: u64) -> (u16, u16) {
+    let secs = (mtime / 1000) as i64;
+    let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+    if !(1980..=2107).contains(&year) {
+        // 1980-01-01 00:00
+        return (0, 1 << 5 | 1);
+    }
+    let of_day = secs.rem_euclid(86_400);
+    let time = (of_day / 3600) << 11 | (of_day / 60 % 60) << 5 | (of_day % 60 / 2);
+    let date = (year - 1980) << 9 | month << 5 | day;
+    (time as u16, date as u16)
+}
+
+fn from_dos(time: u16, date: u16) -> u64 {
+    let (time, date) = (i64::from(time), i64::from(date));
+    let days = days_from_civil(
+        1980 + (date >> 9),
+        ((date >> 5) & 0xF).max(1),
+        (date & 0x1F).max(1),
+    );
+    let secs = days * 86_400 + (time >> 11) * 3600 + ((time >> 5) & 0x3F) * 60 + (time & 0x1F) * 2;
+    secs.max(0) as u64 * 1000
+}
+
+/// The Unix mtime from an extended timestamp field, if `extra` has one
+fn extra_mtime(extra: &[u8]) -> Option<u64> {
+    let mut rest = extra;
+    while rest.len() >= 4 {
+        let id = u16::from_le_bytes([rest[0], rest[1]]);
+        let len = usize::from(u16::from_le_bytes([rest[2], rest[3]]));
+        let body = rest.get(4..4 + len)?;
+        if id == EXTRA_TIMESTAMP && body.len() >= 5 && body[0] & 1 != 0 {
+            let secs = u32::from_le_bytes([body[1], body[2], body[3], body[4]]);
+            return Some(u64::from(secs) * 1000);
+        }
+        rest = &rest[4 + len..];
+    }
+    None
+}
+
+/// Find the end of central directory record, which sits behind at most a
+/// 64 KiB comment
+fn end_of_directory(data: &[u8]) -> io::Result<usize> {
+    let last = data
+        .len()
+        .checked_sub(22)
+        .ok_or_else(|| invalid("not a zip archive"))?;
+    let first = last.saturating_sub(0xFFFF);
+    (first..=last)
+        .rev()
+        .find(|&at| u32_at(data, at).ok() == Some(END_OF_DIRECTORY))
+        .ok_or_else(|| invalid("not a zip archive"))
+}
+
+/// List the members of an archive, in central directory order
+pub fn read_entries(data: &[u8]) -> io::Result<Vec<ZipEntry>> {
+    let end = end_of_directory(data)?;
+    let count = u16_at(data, end + 10)?;
+    let offset = u32_at(data, end + 16)?;
+    if count == 0xFFFF || offset == 0xFFFF_FFFF {
+        return Err(unsupported("zip64 archives are not supported"));
+    }
+
+    let mut entries = Vec::with_capacity(usize::from(count));
+    let mut at = offset as usize;
+    for _ in 0..count {
+        if u32_at(data, at)? != CENTRAL_HEADER {
+            return Err(invalid("bad central directory"));
+        }
+        let made_by = u16_at(data, at + 4)?;
+        let flags = u16_at(data, at + 8)?;
+        let method = u16_at(data, at + 10)?;
+        let dos_time = u16_at(data, at + 12)?;
+        let dos_date = u16_at(data, at + 14)?;
+        let crc = u32_at(data, at + 16)?;
+        let compressed_size = u32_at(data, at + 20)?;
+        let size = u32_at(data, at + 24)?;
+        let name_len = usize::from(u16_at(data, at + 28)?);
+        let extra_len = usize::from(u16_at(data, at + 30)?);
+        let comment_len = usize::from(u16_at(data, at + 32)?);
+        let external = u32_at(data, at + 38)?;
+        let header_offset = u32_at(data, at + 42)?;
+        if [compressed_size, size, header_offset].contains(&0xFFFF_FFFF) {
+            return Err(unsupported("zip64 archives are not supported"));
+        }
+        let name = data
+            .get(at + 46..at + 46 + name_len)
+            .ok_or_else(|| invalid("truncated archive"))?;
+        let extra = data
+            .get(at + 46 + name_len..at + 46 + name_len + extra_len)
+            .ok_or_else(|| invalid("truncated archive"))?;
+        let name = String::from_utf8_lossy(name);
+
+        let unix = external >> 16;
+        let (kind, mode) = if made_by >> 8 == 3 && unix != 0 {
+            let kind = match unix & S_IFMT {
+                S_IFDIR => ZipKind::Dir,
+                S_IFLNK => ZipKind::Symlink,
+                _ => ZipKind::File,
+            };
+            (kind, (unix & 0o7777) as u16)
+        } else if name.ends_with('/') || external & 0x10 != 0 {
+            (ZipKind::Dir, 0o755)
+        } else {
+            (ZipKind::File, 0o644)
+        };
+        let kind = if name.ends_with('/') {
+            ZipKind::Dir
+        } else {
+            kind
+        };
+
+        entries.push(ZipEntry {
+            path: normalize(&name),
+            kind,
+            mode,
+            mtime: extra_mtime(extra).unwrap_or_else(|| from_dos(dos_time, dos_date)),
+            method,
+            encrypted: flags & FLAG_ENCRYPTED != 0,
+            crc,
+            compressed_size: compressed_size as usize,
+            size: size as usize,
+            header_offset: header_offset as usize,
+        });
+        at += 46 + name_len + extra_len + comment_len;
+    }
+    Ok(entries)
+}
+
+/// The contents of a member (the target, for a symbolic link)
+pub fn read_file(data: &[u8], entry: &ZipEntry) -> io::Result<Vec<u8>> {
+    if entry.encrypted {
+        return Err(unsupported("encrypted members are not supported"));
+    }
+    let at = entry.header_offset;
+    if u32_at(data, at)? != LOCAL_HEADER {
+        return Err(invalid("bad local header"));
+    }
+    let name_len = usize::from(u16_at(data, at + 26)?);
+    let extra_len = usize::from(u16_at(data, at + 28)?);
+    let start = at + 30 + name_len + extra_len;
+    let raw = data
+        .get(start..start + entry.compressed_size)
+        .ok_or_else(|| invalid("truncated archive"))?;
+    let contents = match entry.method {
+        METHOD_STORED => raw.to_vec(),
+        METHOD_DEFLATED => deflate::decompress(raw, entry.size)?,
+        method => {
+            return Err(unsupported(&format!(
+                "compression method {} is not supported",
+                method
+            )));
+        }
+    };
+    if contents.len() != entry.size || deflate::crc32(&contents) != entry.crc {
+        return Err(invalid(&format!("{}: bad CRC", entry.path)));
+    }
+    Ok(contents)
+}
+
+/// Builds an archive in memory
+#[derive(Debug, Default)]
+pub struct ZipWriter {
+    out: Vec<u8>,
+    central: Vec<u8>,
+    count: usize,
+}
+
+impl ZipWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a directory
+    pub fn add_dir(&mut self, path: &str, mode: u16, mtime: u64) -> io::Result<()> {
+        let name = format!("{}/", normalize(path).trim_start_matches('/'));
+        let external = (S_IFDIR | u32::from(mode & 0o7777)) << 16 | 0x10;
+        self.add(&name, b"", false, external, mtime).map(|_| ())
+    }
+
+    /// Add a file, deflated when `compress` is set and that makes it
+    /// smaller. Returns the size stored in the archive.
+    pub fn add_file(
+        &mut self,
+        path: &str,
+        data: &[u8],
+        mode: u16,
+        mtime: u64,
+        compress: bool,
+    ) -> io::Result<usize> {
+        let name = normalize(path).trim_start_matches('/').to_string();
+        let external = (S_IFREG | u32::from(mode & 0o7777)) << 16;
+        self.add(&name, data, compress, external, mtime)
+    }
+
+    /// Add a symbolic link
+    pub fn add_symlink(&mut self, path: &str, target: &str, mtime: u64) -> io::Result<()> {
+        let name = normalize(path).trim_start_matches('/').to_string();
+        let external = (S_IFLNK | 0o777) << 16;
+        self.add(&name, target.as_bytes(), false, external, mtime)
+            .map(|_| ())
+    }
+
+    fn add(
+        &mut self,
+        name: &str,
+        data: &[u8],
+        compress: bool,
+        external: u32,
+        mtime: u64,
+    ) -> io::Result<usize> {
+        if name.is_empty() || name == "/" {
+            return Err(invalid("empty member name"));
+        }
+        if self.count >= 0xFFFF {
+            return Err(unsupported("too many members"));
+        }
+        let packed = if compress {
+            Some(deflate::compress(data)).filter(|packed| packed.len() < data.len())
+        } else {
+            None
+        };
+        let (method, stored) = match &packed {
+            Some(packed) => (METHOD_DEFLATED, packed.as_slice()),
+            None => (METHOD_STORED, data),
+        };
+        let offset = self.out.len();
+        if offset + stored.len() + name.len() + 64 > 0xFFFF_FFFF {
+            return Err(unsupported("archive too large"));
+        }
+        let (time, date) = to_dos(mtime);
+        let crc = deflate::crc32(data);
+        let mut extra = Vec::with_capacity(9);
+        extra.extend_from_slice(&EXTRA_TIMESTAMP.to_le_bytes());
+        extra.extend_from_slice(&5u16.to_le_bytes());
+        extra.push(1);
+        extra.extend_from_slice(&((mtime / 1000).min(u64::from(u32::MAX)) as u32).to_le_bytes());
+
+        // Fields the local and central headers share, from "version needed"
+        let mut common = Vec::with_capacity(26);
+        common.extend_from_slice(&VERSION_NEEDED.to_le_bytes());
+        common.extend_from_slice(&FLAG_UTF8.to_le_bytes());
+        common.extend_from_slice(&method.to_le_bytes());
+        common.extend_from_slice(&time.to_le_bytes());
+        common.extend_from_slice(&date.to_le_bytes());
+        common.extend_from_slice(&crc.to_le_bytes());
+        common.extend_from_slice(&(stored.len() as u32).to_le_bytes());
+        common.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        common.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        common.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+
+        self.out.extend_from_slice(&LOCAL_HEADER.to_le_bytes());
+        self.out.extend_from_slice(&common);
+        self.out.extend_from_slice(name.as_bytes());
+        self.out.extend_from_slice(&extra);
+        self.out.extend_from_slice(stored);
+
+        self.central
+            .extend_from_slice(&CENTRAL_HEADER.to_le_bytes());
+        self.central.extend_from_slice(&MADE_BY_UNIX.to_le_bytes());
+        self.central.extend_from_slice(&common);
+        // Comment length, disk number, internal attributes
+        self.central.extend_from_slice(&[0; 6]);
+        self.central.extend_from_slice(&external.to_le_bytes());
+        self.central
+            .extend_from_slice(&(offset as u32).to_le_bytes());
+        self.central.extend_from_slice(name.as_bytes());
+        self.central.extend_from_slice(&extra);
+        self.count += 1;
+        Ok(stored.len())
+    }
+
+    /// Number of members added so far
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Append the central directory and return the archive
+    pub fn finish(mut self) -> Vec<u8> {
+        let offset = self.out.len() as u32;
+        let count = self.count as u16;
+        self.out.extend_from_slice(&self.central);
+        self.out.extend_from_slice(&END_OF_DIRECTORY.to_le_bytes());
+        // This disk, the disk the directory starts on
+        self.out.extend_from_slice(&[0; 4]);
+        self.out.extend_from_slice(&count.to_le_bytes());
+        self.out.extend_from_slice(&count.to_le_bytes());
+        self.out
+            .extend_from_slice(&(self.central.len() as u32).to_le_bytes());
+        self.out.extend_from_slice(&offset.to_le_bytes());
+        // Comment length
+        self.out.extend_from_slice(&[0; 2]);
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let text = "zip me up\n".repeat(100);
+        let mtime = 1_700_000_000_000;
+        let mut zip = ZipWriter::new();
+        zip.add_dir("/docs", 0o750, mtime).unwrap();
+        let stored = zip
+            .add_file("/docs/readme.txt", text.as_bytes(), 0o600, mtime, true)
+            .unwrap();
+        assert!(stored < text.len() / 5);
+        zip.add_file("docs/tiny", b"x", 0o755, mtime, true).unwrap();
+        zip.add_symlink("docs/link", "readme.txt", mtime).unwrap();
+        assert_eq!(zip.len(), 4);
+        let archive = zip.finish();
+
+        let entries = read_entries(&archive).unwrap();
+        let paths: Vec<&str> = entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            ["/docs", "/docs/readme.txt", "/docs/tiny", "/docs/link"]
+        );
+        assert_eq!(entries[0].kind, ZipKind::Dir);
+        assert_eq!(entries[0].mode, 0o750);
+        assert_eq!(entries[1].method, METHOD_DEFLATED);
+        assert_eq!(entries[1].mode, 0o600);
+        assert_eq!(entries[1].mtime, mtime);
+        assert_eq!(entries[1].modified(), "2023-11-14 22:13");
+        // One byte doesn't shrink, so it is stored
+        assert_eq!(entries[2].method, METHOD_STORED);
+        assert_eq!(entries[3].kind, ZipKind::Symlink);
+        assert_eq!(read_file(&archive, &entries[1]).unwrap(), text.as_bytes());
+        assert_eq!(read_file(&archive, &entries[2]).unwrap(), b"x");
+        assert_eq!(read_file(&archive, &entries[3]).unwrap(), b"readme.txt");
+
+        let mut corrupt = archive.clone();
+        corrupt[entries[3].header_offset - 1] = b'y';
+        assert!(read_file(&corrupt, &entries[2]).is_err());
+        assert!(read_entries(b"not a zip").is_err());
+        assert!(read_entries(&ZipWriter::new().finish()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_foreign_archive() {
+        // "../../etc/evil", stored, made on MS-DOS: no Unix attributes and
+        // no timestamp field
+        let mut archive = Vec::new();
+        let name = b"../../etc/evil";
+        let body = b"boo";
+        let crc = deflate::crc32(body);
+        let (time, date) = (0x6000u16, 0x5821u16);
+        archive.extend_from_slice(&LOCAL_HEADER.to_le_bytes());
+        for field in [20u16, 0, 0, time, date] {
+            archive.extend_from_slice(&field.to_le_bytes());
+        }
+        archive.extend_from_slice(&crc.to_le_bytes());
+        archive.extend_from_slice(&3u32.to_le_bytes());
+        archive.extend_from_slice(&3u32.to_le_bytes());
+        archive.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        archive.extend_from_slice(&0u16.to_le_bytes());
+        archive.extend_from_slice(name);
+        archive.extend_from_slice(body);
+        let directory = archive.len() as u32;
+        archive.extend_from_slice(&CENTRAL_HEADER.to_le_bytes());
+        for field in [20u16, 20, 0, 0, time, date] {
+            archive.extend_from_slice(&field.to_le_bytes());
+        }
+        archive.extend_from_slice(&crc.to_le_bytes());
+        archive.extend_from_slice(&3u32.to_le_bytes());
+        archive.extend_from_slice(&3u32.to_le_bytes());
+        archive.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        archive.extend_from_slice(&[0; 8]);
+        archive.extend_from_slice(&0u32.to_le_bytes());
+        archive.extend_from_slice(&0u32.to_le_bytes());
+        archive.extend_from_slice(name);
+        let size = archive.len() as u32 - directory;
+        archive.extend_from_slice(&END_OF_DIRECTORY.to_le_bytes());
+        archive.extend_from_slice(&[0, 0, 0, 0, 1, 0, 1, 0]);
+        archive.extend_from_slice(&size.to_le_bytes());
+        archive.extend_from_slice(&directory.to_le_bytes());
+        archive.extend_from_slice(&0u16.to_le_bytes());
+
+        let entries = read_entries(&archive).unwrap();
+        assert_eq!(entries[0].path, "/etc/evil");
+        assert_eq!(entries[0].kind, ZipKind::File);
+        assert_eq!(entries[0].mode, 0o644);
+        // 2024-01-01 12:00:00
+        assert_eq!(entries[0].mtime, 1_704_110_400_000);
+        assert_eq!(read_file(&archive, &entries[0]).unwrap(), b"boo");
+    }
+
+    #[test]
+    fn test_dos_time() {
+        assert_eq!(to_dos(1_704_110_400_000), (0x6000, 0x5821));
+        assert_eq!(from_dos(0x6000, 0x5821), 1_704_110_400_000);
+        // Before 1980 clamps
+        assert_eq!(to_dos(0), (0, 0x21));
+    }
+}